@@ -29,6 +29,9 @@
 #include <base/logging.h>
 #include <base/strings/stringprintf.h>
 
+#include <map>
+#include <vector>
+
 #include "bt_target.h"  // Must be first to define build configuration
 #include "bta/gatt/bta_gattc_int.h"
 #include "bta/hh/bta_hh_int.h"
@@ -107,6 +110,11 @@ static const char* bta_gattc_op_code_name[] = {
     "Indication"    /* GATTC_OPTYPE_INDICATION */
 };
 
+/* Values of the multiple handle value notifications being received, by
+ * connection, held until the last value of the notification */
+static std::map<uint16_t, std::vector<tBTA_GATTC_NOTIFY>>
+    bta_gattc_multi_notifications;
+
 /*****************************************************************************
  *  Action Functions
  ****************************************************************************/
@@ -1111,6 +1119,7 @@ void bta_gattc_op_cmpl(tBTA_GATTC_CLCB* p_clcb, const tBTA_GATTC_DATA* p_data) {
     case GATTC_OPTYPE_DISCOVERY:
     case GATTC_OPTYPE_NOTIFICATION:
     case GATTC_OPTYPE_INDICATION:
+    case GATTC_OPTYPE_MULTI_NOTIFICATION:
     default:
       LOG(ERROR) << "unexpected operation, ignored";
       return;
@@ -1402,6 +1411,17 @@ static void bta_gattc_proc_other_indication(tBTA_GATTC_CLCB* p_clcb, uint8_t op,
   p_notify->bda = p_clcb->bda;
   memcpy(p_notify->value, p_data->att_value.value, p_data->att_value.len);
   p_notify->conn_id = p_clcb->bta_conn_id;
+  p_notify->multi_count = 0;
+
+  /* the values of a multiple handle value notification are reported together
+   * once its last value is received, see bta_gattc_flush_multi_notification */
+  auto pending = bta_gattc_multi_notifications.find(p_clcb->bta_conn_id);
+  if (op == GATTC_OPTYPE_MULTI_NOTIFICATION ||
+      (op == GATTC_OPTYPE_NOTIFICATION &&
+       pending != bta_gattc_multi_notifications.end())) {
+    bta_gattc_multi_notifications[p_clcb->bta_conn_id].push_back(*p_notify);
+    return;
+  }
 
   if (p_clcb->p_rcb->p_cback) {
     tBTA_GATTC bta_gattc;
@@ -1479,6 +1499,26 @@ static void bta_gattc_process_indicate(uint16_t conn_id, tGATTC_OPTYPE op,
   }
 }
 
+/** report the values of the multiple handle value notification received on
+ * |conn_id|, the values of the handles not registered being left out */
+static void bta_gattc_flush_multi_notification(uint16_t conn_id) {
+  auto pending = bta_gattc_multi_notifications.find(conn_id);
+  if (pending == bta_gattc_multi_notifications.end()) return;
+
+  std::vector<tBTA_GATTC_NOTIFY> notifications = std::move(pending->second);
+  bta_gattc_multi_notifications.erase(pending);
+
+  tBTA_GATTC_CLCB* p_clcb = bta_gattc_find_clcb_by_conn_id(conn_id);
+  if (p_clcb == NULL || !p_clcb->p_rcb->p_cback) return;
+
+  for (const tBTA_GATTC_NOTIFY& notify : notifications) {
+    tBTA_GATTC bta_gattc;
+    bta_gattc.notify = notify;
+    bta_gattc.notify.multi_count = notifications.size();
+    (*p_clcb->p_rcb->p_cback)(BTA_GATTC_NOTIF_EVT, &bta_gattc);
+  }
+}
+
 /** client operation complete callback register with BTE GATT */
 static void bta_gattc_cmpl_cback(uint16_t conn_id, tGATTC_OPTYPE op,
                                  tGATT_STATUS status,
@@ -1487,8 +1527,13 @@ static void bta_gattc_cmpl_cback(uint16_t conn_id, tGATTC_OPTYPE op,
           << " status:" << +status;
 
   /* notification and indication processed right away */
-  if (op == GATTC_OPTYPE_NOTIFICATION || op == GATTC_OPTYPE_INDICATION) {
+  if (op == GATTC_OPTYPE_NOTIFICATION || op == GATTC_OPTYPE_INDICATION ||
+      op == GATTC_OPTYPE_MULTI_NOTIFICATION) {
     bta_gattc_process_indicate(conn_id, op, p_data);
+    /* the last value of a multiple handle value notification is reported as
+     * a notification, whether its handle is registered or not */
+    if (op == GATTC_OPTYPE_NOTIFICATION)
+      bta_gattc_flush_multi_notification(conn_id);
     return;
   }
   /* for all other operation, not expected if w/o connection */
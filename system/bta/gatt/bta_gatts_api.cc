@@ -257,6 +257,49 @@ void BTA_GATTS_HandleValueIndication(uint16_t conn_id, uint16_t attr_id,
   bta_sys_sendmsg(p_buf);
 }
 
+static void bta_gatts_multiple_notify_impl(uint16_t conn_id,
+                                           std::vector<tGATT_VALUE> values) {
+  tGATT_IF gatt_if;
+  RawAddress remote_bda;
+  tBT_TRANSPORT transport;
+
+  if (!GATT_GetConnectionInfor(conn_id, &gatt_if, remote_bda, &transport)) {
+    LOG(ERROR) << "Unknown connection_id=" << loghex(conn_id)
+               << " fail sending notifications";
+    return;
+  }
+
+  tGATT_STATUS status = GATTS_HandleMultipleValueNotification(conn_id, values);
+
+  tBTA_GATTS_RCB* p_rcb = bta_gatts_find_app_rcb_by_app_if(gatt_if);
+  if (p_rcb && p_rcb->p_cback) {
+    tBTA_GATTS cb_data;
+    cb_data.req_data.status = status;
+    cb_data.req_data.conn_id = conn_id;
+    (*p_rcb->p_cback)(BTA_GATTS_CONF_EVT, &cb_data);
+  }
+}
+
+/*******************************************************************************
+ *
+ * Function         BTA_GATTS_HandleMultipleValueNotification
+ *
+ * Description      This function is called to send the values of several
+ *                  attributes in a single notification. A BTA_GATTS_CONF_EVT
+ *                  is reported with the status once sent.
+ *
+ * Parameters       conn_id - connection identifier.
+ *                  values - handles and values to notify.
+ *
+ * Returns          None
+ *
+ ******************************************************************************/
+void BTA_GATTS_HandleMultipleValueNotification(
+    uint16_t conn_id, std::vector<tGATT_VALUE> values) {
+  do_in_main_thread(FROM_HERE, base::Bind(&bta_gatts_multiple_notify_impl,
+                                          conn_id, std::move(values)));
+}
+
 /*******************************************************************************
  *
  * Function         BTA_GATTS_SendRsp
@@ -193,6 +193,9 @@ typedef struct {
   uint8_t value[GATT_MAX_ATTR_LEN];
   bool is_notify;
   uint16_t cid;
+  /* number of values reported together for a multiple handle value
+   * notification, 0 for a single value */
+  uint16_t multi_count;
 } tBTA_GATTC_NOTIFY;
 
 typedef struct {
@@ -935,6 +938,23 @@ extern void BTA_GATTS_HandleValueIndication(uint16_t conn_id, uint16_t attr_id,
                                             std::vector<uint8_t> value,
                                             bool need_confirm);
 
+/*******************************************************************************
+ *
+ * Function         BTA_GATTS_HandleMultipleValueNotification
+ *
+ * Description      This function is called to send the values of several
+ *                  attributes in a single notification. A BTA_GATTS_CONF_EVT
+ *                  is reported with the status once sent.
+ *
+ * Parameters       conn_id - connection identifier.
+ *                  values - handles and values to notify.
+ *
+ * Returns          None
+ *
+ ******************************************************************************/
+extern void BTA_GATTS_HandleMultipleValueNotification(
+    uint16_t conn_id, std::vector<tGATT_VALUE> values);
+
 /*******************************************************************************
  *
  * Function         BTA_GATTS_SendRsp
@@ -300,6 +300,12 @@ class BleScannerInterfaceImpl : public BleScannerInterface {
                               jni_thread_wrapper(FROM_HERE, std::move(cb))));
   }
 
+  void SetScanPhys(int scanner_id, uint8_t scan_phys, Callback cb) override {
+    // Only the LE 1M PHY is scanned on without the GD scanning manager.
+    uint8_t status = scan_phys == 0x01 ? BTM_SUCCESS : BTM_MODE_UNSUPPORTED;
+    do_in_jni_thread(Bind(cb, status));
+  }
+
   void BatchscanConfigStorage(int client_if, int batch_scan_full_max,
                               int batch_scan_trunc_max,
                               int batch_scan_notify_threshold,
@@ -171,6 +171,7 @@ static void btif_gattc_upstreams_evt(uint16_t event, char* p_param) {
       data.handle = p_data->notify.handle;
       data.is_notify = p_data->notify.is_notify;
       data.len = p_data->notify.len;
+      data.multi_count = p_data->notify.multi_count;
 
       HAL_CBACK(bt_gatt_callbacks, client->notify_cb, p_data->notify.conn_id,
                 data);
@@ -406,6 +406,29 @@ static bt_status_t btif_gatts_send_indication(int server_if,
   //       invoked without need for confirmation.
 }
 
+static bt_status_t btif_gatts_send_multiple_notifications(
+    int server_if, int conn_id, const uint16_t* handles,
+    const uint16_t* lengths, size_t count, const uint8_t* values) {
+  CHECK_BTGATT_INIT();
+
+  std::vector<tGATT_VALUE> notifications(count);
+  for (size_t i = 0; i < count; i++) {
+    if (lengths[i] > GATT_MAX_ATTR_LEN) return BT_STATUS_PARM_INVALID;
+
+    tGATT_VALUE& notification = notifications[i];
+    memset(&notification, 0, sizeof(notification));
+    notification.conn_id = conn_id;
+    notification.handle = handles[i];
+    notification.len = lengths[i];
+    notification.auth_req = GATT_AUTH_REQ_NONE;
+    memcpy(notification.value, values, lengths[i]);
+    values += lengths[i];
+  }
+
+  return do_in_jni_thread(Bind(&BTA_GATTS_HandleMultipleValueNotification,
+                               conn_id, std::move(notifications)));
+}
+
 static void btif_gatts_send_response_impl(int conn_id, int trans_id, int status,
                                           btgatt_response_t response) {
   tGATTS_RSP rsp_struct;
@@ -450,4 +473,4 @@ const btgatt_server_interface_t btgattServerInterface = {
     btif_gatts_add_service,    btif_gatts_stop_service,
     btif_gatts_delete_service, btif_gatts_send_indication,
     btif_gatts_send_response,  btif_gatts_set_preferred_phy,
-    btif_gatts_read_phy};
+    btif_gatts_read_phy,       btif_gatts_send_multiple_notifications};
@@ -40,6 +40,7 @@ flatbuffers::Offset<bluetooth::common::InitFlagsData> bluetooth::dumpsys::InitFl
   builder.add_finite_att_timeout_is_enabled(initFlags::finite_att_timeout_is_enabled());
   builder.add_gatt_robust_caching_client_is_enabled(initFlags::gatt_robust_caching_client_is_enabled());
   builder.add_gatt_robust_caching_server_is_enabled(initFlags::gatt_robust_caching_server_is_enabled());
+  builder.add_gatt_service_changed_is_enabled(initFlags::gatt_service_changed_is_enabled());
   builder.add_gd_core_is_enabled(initFlags::gd_core_is_enabled());
   builder.add_gd_l2cap_is_enabled(initFlags::gd_l2cap_is_enabled());
   builder.add_gd_link_policy_is_enabled(initFlags::gd_link_policy_is_enabled());
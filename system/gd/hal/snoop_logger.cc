@@ -190,6 +190,59 @@ size_t get_btsnooz_packet_length_to_write(
   return std::min(included_length, kDefaultBtSnoozMaxPayloadBytesPerPacket);
 }
 
+// Returns the length of |packet| kept in the filtered btsnoop log. The headers of the packets
+// carrying user data, such as the audio or the values of the GATT attributes, are kept but not
+// their payload, so that the log shows the flow of the protocols without the data of the user.
+size_t get_filtered_packet_length(const HciPacket& packet, SnoopLogger::PacketType type) {
+  static const size_t kAclHeaderSize = 4;
+  static const size_t kL2capHeaderSize = 4;
+  static const size_t kL2capCidOffset = (kAclHeaderSize + 2);
+  static const uint16_t kL2capSignalingCid = 0x0001;
+  static const uint16_t kL2capLeSignalingCid = 0x0005;
+  static const uint16_t kL2capAttCid = 0x0004;
+  // Opcode and attribute handle.
+  static const size_t kAttHeaderSize = 3;
+  static const size_t kScoHeaderSize = 3;
+  static const size_t kIsoHeaderSize = 4;
+
+  static const size_t kAclPacketBoundaryOffset = 1;
+  static const uint8_t kAclContinuingFragment = 0x01;
+
+  switch (type) {
+    case SnoopLogger::PacketType::CMD:
+    case SnoopLogger::PacketType::EVT:
+      return packet.size();
+
+    case SnoopLogger::PacketType::ACL: {
+      size_t len_hci_acl = kAclHeaderSize + kL2capHeaderSize;
+      if (packet.size() < len_hci_acl) {
+        return std::min(kAclHeaderSize, packet.size());
+      }
+      // The fragments continuing an L2CAP packet start with payload instead of a header.
+      if (((packet[kAclPacketBoundaryOffset] >> 4) & 0x03) == kAclContinuingFragment) {
+        return kAclHeaderSize;
+      }
+
+      uint16_t l2cap_cid =
+          static_cast<uint16_t>(packet[kL2capCidOffset]) |
+          static_cast<uint16_t>((static_cast<uint16_t>(packet[kL2capCidOffset + 1]) << static_cast<uint16_t>(8)));
+      if (l2cap_cid == kL2capSignalingCid || l2cap_cid == kL2capLeSignalingCid) {
+        return packet.size();
+      } else if (l2cap_cid == kL2capAttCid) {
+        return std::min(len_hci_acl + kAttHeaderSize, packet.size());
+      }
+      return len_hci_acl;
+    }
+
+    case SnoopLogger::PacketType::SCO:
+      return std::min(kScoHeaderSize, packet.size());
+
+    case SnoopLogger::PacketType::ISO:
+      return std::min(kIsoHeaderSize, packet.size());
+  }
+  return 0;
+}
+
 }  // namespace
 
 const std::string SnoopLogger::kBtSnoopLogModeDisabled = "disabled";
@@ -219,13 +272,17 @@ SnoopLogger::SnoopLogger(
       qualcomm_debug_log_enabled_(qualcomm_debug_log_enabled),
       snooz_log_life_time_(snooz_log_life_time),
       snooz_log_delete_alarm_interval_(snooz_log_delete_alarm_interval) {
-  if (false && btsnoop_mode == kBtSnoopLogModeFiltered) {
-    // TODO(b/163733538): implement filtered snoop log in GD, currently filtered == disabled
+  snoop_log_base_path_ = snoop_log_path_;
+  ApplyMode(btsnoop_mode);
+}
+
+void SnoopLogger::ApplyMode(const std::string& btsnoop_mode) {
+  if (btsnoop_mode == kBtSnoopLogModeFiltered) {
     LOG_INFO("Filtered Snoop Logs enabled");
     is_enabled_ = true;
     is_filtered_ = true;
     // delete unfiltered logs
-    delete_btsnoop_files(get_btsnoop_log_path(snoop_log_path_, false));
+    delete_btsnoop_files(get_btsnoop_log_path(snoop_log_base_path_, false));
     // delete snooz logs
     delete_btsnoop_files(snooz_log_path_);
   } else if (btsnoop_mode == kBtSnoopLogModeFull) {
@@ -233,7 +290,7 @@ SnoopLogger::SnoopLogger(
     is_enabled_ = true;
     is_filtered_ = false;
     // delete filtered logs
-    delete_btsnoop_files(get_btsnoop_log_path(snoop_log_path_, true));
+    delete_btsnoop_files(get_btsnoop_log_path(snoop_log_base_path_, true));
     // delete snooz logs
     delete_btsnoop_files(snooz_log_path_);
   } else {
@@ -241,11 +298,33 @@ SnoopLogger::SnoopLogger(
     is_enabled_ = false;
     is_filtered_ = false;
     // delete both filtered and unfiltered logs
-    delete_btsnoop_files(get_btsnoop_log_path(snoop_log_path_, true));
-    delete_btsnoop_files(get_btsnoop_log_path(snoop_log_path_, false));
+    delete_btsnoop_files(get_btsnoop_log_path(snoop_log_base_path_, true));
+    delete_btsnoop_files(get_btsnoop_log_path(snoop_log_base_path_, false));
+  }
+  if (!is_enabled_) {
+    btsnoop_mode_ = kBtSnoopLogModeDisabled;
+  } else if (is_filtered_) {
+    btsnoop_mode_ = kBtSnoopLogModeFiltered;
+  } else {
+    btsnoop_mode_ = kBtSnoopLogModeFull;
   }
   // Add ".filtered" extension if necessary
-  snoop_log_path_ = get_btsnoop_log_path(snoop_log_path_, is_filtered_);
+  snoop_log_path_ = get_btsnoop_log_path(snoop_log_base_path_, is_filtered_);
+}
+
+void SnoopLogger::SetMode(const std::string& btsnoop_mode) {
+  std::lock_guard<std::recursive_mutex> lock(file_mutex_);
+  CloseCurrentSnoopLogFile();
+  ApplyMode(btsnoop_mode);
+  // The log file is only open while the module is started.
+  if (is_enabled_ && alarm_) {
+    OpenNextSnoopLogFile();
+  }
+}
+
+std::string SnoopLogger::GetMode() const {
+  std::lock_guard<std::recursive_mutex> lock(file_mutex_);
+  return btsnoop_mode_;
 }
 
 void SnoopLogger::CloseCurrentSnoopLogFile() {
@@ -340,10 +419,15 @@ void SnoopLogger::Capture(const HciPacket& packet, Direction direction, PacketTy
     if (packet_counter_ > max_packets_per_file_) {
       OpenNextSnoopLogFile();
     }
+    size_t included_length = packet.size();
+    if (is_filtered_) {
+      included_length = get_filtered_packet_length(packet, type);
+      header.length_captured = htonl(included_length + /* type byte */ 1);
+    }
     if (!btsnoop_ostream_.write(reinterpret_cast<const char*>(&header), sizeof(PacketHeaderType))) {
       LOG_ERROR("Failed to write packet header for btsnoop, error: \"%s\"", strerror(errno));
     }
-    if (!btsnoop_ostream_.write(reinterpret_cast<const char*>(packet.data()), packet.size())) {
+    if (!btsnoop_ostream_.write(reinterpret_cast<const char*>(packet.data()), included_length)) {
       LOG_ERROR("Failed to write packet payload for btsnoop, error: \"%s\"", strerror(errno));
     }
     // std::ofstream::flush() pushes user data into kernel memory. The data will be written even if this process
@@ -95,6 +95,13 @@ class SnoopLogger : public ::bluetooth::Module {
 
   void Capture(const HciPacket& packet, Direction direction, PacketType type);
 
+  // Switches the btsnoop log to |btsnoop_mode| while running: the current log file is closed, the
+  // log files are deleted as they are at startup, and the log file of the new mode is opened.
+  void SetMode(const std::string& btsnoop_mode);
+
+  // Returns the mode of the btsnoop log, disabled if it is not known.
+  std::string GetMode() const;
+
  protected:
   void ListDependencies(ModuleList* list) const override;
   void Start() override;
@@ -119,7 +126,14 @@ class SnoopLogger : public ::bluetooth::Module {
   void DumpSnoozLogToFile(const std::vector<std::string>& data) const;
 
  private:
+  // Sets the flags and the log file path of |btsnoop_mode|, and deletes the log files it does
+  // not keep.
+  void ApplyMode(const std::string& btsnoop_mode);
+
   std::string snoop_log_path_;
+  // Log file path without the extension of the filtered mode.
+  std::string snoop_log_base_path_;
+  std::string btsnoop_mode_;
   std::string snooz_log_path_;
   std::ofstream btsnoop_ostream_;
   bool is_enabled_ = false;
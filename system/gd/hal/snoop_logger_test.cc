@@ -52,6 +52,9 @@ std::vector<uint8_t> kAvdtpSuspend = {0x02, 0x02, 0x00, 0x07, 0x00, 0x03, 0x00,
 std::vector<uint8_t> kHfpAtNrec0 = {0x02, 0x02, 0x20, 0x13, 0x00, 0x0f, 0x00, 0x41, 0x00, 0x09, 0xff, 0x15,
                                     0x01, 0x41, 0x54, 0x2b, 0x4e, 0x52, 0x45, 0x43, 0x3d, 0x30, 0x0d, 0x5c};
 
+// ATT Write Request of 2 bytes on an LE connection.
+std::vector<uint8_t> kAttWriteRequest = {0x40, 0x20, 0x09, 0x00, 0x05, 0x00, 0x04, 0x00, 0x12, 0x03, 0x00, 0xaa, 0xbb};
+
 std::vector<uint8_t> kQualcommConnectionRequest = {0xdc, 0x2e, 0x54, 0x00, 0x50, 0x00, 0xff, 0x00, 0x00, 0x0a,
                                                    0x0f, 0x09, 0x01, 0x00, 0x5c, 0x93, 0x01, 0x00, 0x42, 0x00};
 
@@ -422,4 +425,36 @@ TEST_F(SnoopLoggerModuleTest, qualcomm_debug_log_regression_test) {
   ASSERT_FALSE(std::filesystem::exists(temp_snooz_log_));
 }
 
+TEST_F(SnoopLoggerModuleTest, set_mode_filtered_test) {
+  auto filtered_snoop_log = temp_snoop_log_.string() + ".filtered";
+  auto* snoop_logger = new TestSnoopLoggerModule(
+      temp_snoop_log_.string(), temp_snooz_log_.string(), 10, SnoopLogger::kBtSnoopLogModeFull, false);
+  TestModuleRegistry test_registry;
+  test_registry.InjectTestModule(&SnoopLogger::Factory, snoop_logger);
+  ASSERT_TRUE(std::filesystem::exists(temp_snoop_log_));
+
+  // The mode takes effect without restarting the module.
+  snoop_logger->SetMode(SnoopLogger::kBtSnoopLogModeFiltered);
+  ASSERT_EQ(snoop_logger->GetMode(), SnoopLogger::kBtSnoopLogModeFiltered);
+  ASSERT_FALSE(std::filesystem::exists(temp_snoop_log_));
+  ASSERT_TRUE(std::filesystem::exists(filtered_snoop_log));
+
+  // The value written is left out, but not the ATT header.
+  snoop_logger->Capture(kAttWriteRequest, SnoopLogger::Direction::OUTGOING, SnoopLogger::PacketType::ACL);
+  snoop_logger->Capture(kInformationRequest, SnoopLogger::Direction::OUTGOING, SnoopLogger::PacketType::CMD);
+  ASSERT_EQ(
+      std::filesystem::file_size(filtered_snoop_log),
+      sizeof(SnoopLogger::FileHeaderType) + 2 * sizeof(SnoopLogger::PacketHeaderType) + 11 +
+          kInformationRequest.size());
+
+  snoop_logger->SetMode(SnoopLogger::kBtSnoopLogModeDisabled);
+  ASSERT_EQ(snoop_logger->GetMode(), SnoopLogger::kBtSnoopLogModeDisabled);
+  ASSERT_FALSE(std::filesystem::exists(filtered_snoop_log));
+  test_registry.StopAll();
+
+  // Verify states after test
+  ASSERT_FALSE(std::filesystem::exists(temp_snoop_log_));
+  ASSERT_FALSE(std::filesystem::exists(filtered_snoop_log));
+}
+
 }  // namespace testing
@@ -263,6 +263,10 @@ void AclManager::ClearResolvingList() {
   CallOn(pimpl_->le_impl_, &le_impl::clear_resolving_list);
 }
 
+void AclManager::SetPrivacyMode(AddressWithType address_with_type, PrivacyMode privacy_mode) {
+  CallOn(pimpl_->le_impl_, &le_impl::set_privacy_mode, address_with_type, privacy_mode);
+}
+
 void AclManager::CentralLinkKey(KeyFlag key_flag) {
   CallOn(pimpl_->classic_impl_, &classic_impl::central_link_key, key_flag);
 }
@@ -119,6 +119,7 @@ public:
      const std::array<uint8_t, 16>& local_irk);
  virtual void RemoveDeviceFromResolvingList(AddressWithType address_with_type);
  virtual void ClearResolvingList();
+ virtual void SetPrivacyMode(AddressWithType address_with_type, PrivacyMode privacy_mode);
 
  virtual void CentralLinkKey(KeyFlag key_flag);
  virtual void SwitchRole(Address address, Role role);
@@ -944,6 +944,11 @@ struct le_impl : public bluetooth::hci::LeAddressManagerCallback {
     le_address_manager_->ClearResolvingList();
   }
 
+  void set_privacy_mode(AddressWithType address_with_type, PrivacyMode privacy_mode) {
+    le_address_manager_->SetPrivacyMode(
+        address_with_type.ToPeerAddressType(), address_with_type.GetAddress(), privacy_mode);
+  }
+
   void set_privacy_policy_for_initiator_address(
       LeAddressManager::AddressPolicy address_policy,
       AddressWithType fixed_address,
@@ -102,6 +102,11 @@ class FuzzHciLayer : public HciLayer {
     }
   }
 
+  void RegisterCommandLatencyHandler(
+      common::ContextualCallback<void(hci::OpCode, std::chrono::microseconds)> latency_handler) override {}
+
+  void UnregisterCommandLatencyHandler() override {}
+
   hci::SecurityInterface* GetSecurityInterface(common::ContextualCallback<void(hci::EventView)> event_handler) override;
 
   hci::LeSecurityInterface* GetLeSecurityInterface(
@@ -182,6 +182,9 @@ struct HciLayer::impl {
     }
     ASSERT_LOG(waiting_command_ == op_code, "Waiting for 0x%02hx (%s), got 0x%02hx (%s)", waiting_command_,
                OpCodeText(waiting_command_).c_str(), op_code, OpCodeText(op_code).c_str());
+    command_latency_handler_.InvokeIfNotEmpty(
+        op_code,
+        std::chrono::duration_cast<std::chrono::microseconds>(std::chrono::steady_clock::now() - command_sent_time_));
 
     bool is_vendor_specific = static_cast<int>(op_code) & (0x3f << 10);
     CommandStatusView status_view = CommandStatusView::Create(event);
@@ -260,6 +263,7 @@ struct HciLayer::impl {
     log_link_layer_connection_command(command_queue_.front().command_view);
     log_classic_pairing_command_status(command_queue_.front().command_view, ErrorCode::STATUS_UNKNOWN);
     waiting_command_ = op_code;
+    command_sent_time_ = std::chrono::steady_clock::now();
     command_credits_ = 0;  // Only allow one outstanding command
     if (hci_timeout_alarm_ != nullptr) {
       hci_timeout_alarm_->Schedule(BindOnce(&impl::on_hci_timeout, common::Unretained(this), op_code), kHciTimeoutMs);
@@ -306,6 +310,14 @@ struct HciLayer::impl {
     subevent_handlers_.erase(subevent_handlers_.find(event));
   }
 
+  void register_command_latency(ContextualCallback<void(OpCode, std::chrono::microseconds)> handler) {
+    command_latency_handler_ = handler;
+  }
+
+  void unregister_command_latency() {
+    command_latency_handler_ = {};
+  }
+
   static void abort_after_root_inflammation(uint8_t vse_error) {
     ASSERT_LOG(false, "Root inflammation with reason 0x%02hhx", vse_error);
   }
@@ -398,6 +410,8 @@ struct HciLayer::impl {
   std::map<EventCode, ContextualCallback<void(EventView)>> event_handlers_;
   std::map<SubeventCode, ContextualCallback<void(LeMetaEventView)>> subevent_handlers_;
   OpCode waiting_command_{OpCode::NONE};
+  std::chrono::steady_clock::time_point command_sent_time_;
+  ContextualCallback<void(OpCode, std::chrono::microseconds)> command_latency_handler_{};
   uint8_t command_credits_{1};  // Send reset first
   Alarm* hci_timeout_alarm_{nullptr};
   Alarm* hci_abort_alarm_{nullptr};
@@ -493,6 +507,15 @@ void HciLayer::UnregisterLeEventHandler(SubeventCode event) {
   CallOn(impl_, &impl::unregister_le_event, event);
 }
 
+void HciLayer::RegisterCommandLatencyHandler(
+    ContextualCallback<void(OpCode, std::chrono::microseconds)> latency_handler) {
+  CallOn(impl_, &impl::register_command_latency, latency_handler);
+}
+
+void HciLayer::UnregisterCommandLatencyHandler() {
+  CallOn(impl_, &impl::unregister_command_latency);
+}
+
 void HciLayer::on_disconnection_complete(EventView event_view) {
   auto disconnection_view = DisconnectionCompleteView::Create(event_view);
   if (!disconnection_view.IsValid()) {
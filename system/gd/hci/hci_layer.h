@@ -71,6 +71,13 @@ class HciLayer : public Module, public CommandInterface<CommandBuilder> {
 
   virtual void UnregisterLeEventHandler(SubeventCode subevent_code);
 
+  // Registers a handler of the latency of each command, from the command sent to the controller to the
+  // Command Complete or Command Status event completing it.
+  virtual void RegisterCommandLatencyHandler(
+      common::ContextualCallback<void(OpCode, std::chrono::microseconds)> latency_handler);
+
+  virtual void UnregisterCommandLatencyHandler();
+
   virtual SecurityInterface* GetSecurityInterface(common::ContextualCallback<void(EventView)> event_handler);
 
   virtual LeSecurityInterface* GetLeSecurityInterface(common::ContextualCallback<void(LeMetaEventView)> event_handler);
@@ -476,8 +476,11 @@ void LeAddressManager::AddDeviceToResolvingList(
   cached_commands_.push(std::move(command));
 
   if (supports_ble_privacy_) {
-    auto packet_builder =
-        hci::LeSetPrivacyModeBuilder::Create(peer_identity_address_type, peer_identity_address, PrivacyMode::DEVICE);
+    auto privacy_mode = privacy_modes_.find(peer_identity_address);
+    auto packet_builder = hci::LeSetPrivacyModeBuilder::Create(
+        peer_identity_address_type,
+        peer_identity_address,
+        privacy_mode == privacy_modes_.end() ? PrivacyMode::DEVICE : privacy_mode->second);
     Command command = {CommandType::LE_SET_PRIVACY_MODE, HCICommand{std::move(packet_builder)}};
     cached_commands_.push(std::move(command));
   }
@@ -503,6 +506,8 @@ void LeAddressManager::RemoveDeviceFromFilterAcceptList(
 
 void LeAddressManager::RemoveDeviceFromResolvingList(
     PeerAddressType peer_identity_address_type, Address peer_identity_address) {
+  privacy_modes_.erase(peer_identity_address);
+
   // Disable Address resolution
   auto disable_builder = hci::LeSetAddressResolutionEnableBuilder::Create(hci::Enable::DISABLED);
   Command disable = {CommandType::SET_ADDRESS_RESOLUTION_ENABLE, HCICommand{std::move(disable_builder)}};
@@ -525,6 +530,38 @@ void LeAddressManager::RemoveDeviceFromResolvingList(
   }
 }
 
+void LeAddressManager::SetPrivacyMode(
+    PeerAddressType peer_identity_address_type, Address peer_identity_address, PrivacyMode privacy_mode) {
+  if (privacy_mode == PrivacyMode::DEVICE) {
+    privacy_modes_.erase(peer_identity_address);
+  } else {
+    privacy_modes_[peer_identity_address] = privacy_mode;
+  }
+  if (!supports_ble_privacy_) {
+    return;
+  }
+
+  // The privacy mode cannot be changed while the address resolution is enabled.
+  auto disable_builder = hci::LeSetAddressResolutionEnableBuilder::Create(hci::Enable::DISABLED);
+  Command disable = {CommandType::SET_ADDRESS_RESOLUTION_ENABLE, HCICommand{std::move(disable_builder)}};
+  cached_commands_.push(std::move(disable));
+
+  auto packet_builder =
+      hci::LeSetPrivacyModeBuilder::Create(peer_identity_address_type, peer_identity_address, privacy_mode);
+  Command command = {CommandType::LE_SET_PRIVACY_MODE, HCICommand{std::move(packet_builder)}};
+  cached_commands_.push(std::move(command));
+
+  auto enable_builder = hci::LeSetAddressResolutionEnableBuilder::Create(hci::Enable::ENABLED);
+  Command enable = {CommandType::SET_ADDRESS_RESOLUTION_ENABLE, HCICommand{std::move(enable_builder)}};
+  cached_commands_.push(std::move(enable));
+
+  if (registered_clients_.empty()) {
+    handler_->BindOnceOn(this, &LeAddressManager::handle_next_command).Invoke();
+  } else {
+    handler_->BindOnceOn(this, &LeAddressManager::pause_registered_clients).Invoke();
+  }
+}
+
 void LeAddressManager::ClearFilterAcceptList() {
   auto packet_builder = hci::LeClearFilterAcceptListBuilder::Create();
   Command command = {CommandType::CLEAR_CONNECT_LIST, HCICommand{std::move(packet_builder)}};
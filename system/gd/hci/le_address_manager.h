@@ -91,6 +91,9 @@ class LeAddressManager {
       const std::array<uint8_t, 16>& local_irk);
   void RemoveDeviceFromFilterAcceptList(FilterAcceptListAddressType connect_list_address_type, Address address);
   void RemoveDeviceFromResolvingList(PeerAddressType peer_identity_address_type, Address peer_identity_address);
+  // Sets the privacy mode of a device of the resolving list, kept while it stays in the list.
+  void SetPrivacyMode(
+      PeerAddressType peer_identity_address_type, Address peer_identity_address, PrivacyMode privacy_mode);
   void ClearFilterAcceptList();
   void ClearResolvingList();
   void OnCommandComplete(CommandCompleteView view);
@@ -175,6 +178,8 @@ class LeAddressManager {
   uint8_t resolving_list_size_;
   std::queue<Command> cached_commands_;
   bool supports_ble_privacy_{false};
+  // Privacy modes other than the device privacy of the devices of the resolving list.
+  std::map<Address, PrivacyMode> privacy_modes_;
 };
 
 }  // namespace hci
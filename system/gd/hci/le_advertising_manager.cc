@@ -66,6 +66,8 @@ struct Advertiser {
   bool directed = false;
   bool in_use = false;
   std::unique_ptr<os::Alarm> address_rotation_alarm;
+  // Zero for the randomized interval of the stack.
+  std::chrono::milliseconds address_rotation_interval{0};
 };
 
 ExtendedAdvertisingConfig::ExtendedAdvertisingConfig(const AdvertisingConfig& config) : AdvertisingConfig(config) {
@@ -253,7 +255,7 @@ struct LeAdvertisingManager::impl : public bluetooth::hci::LeAddressManagerCallb
           advertising_sets_[advertiser_id].address_rotation_alarm->Schedule(
               common::BindOnce(
                   &impl::set_advertising_set_random_address_on_timer, common::Unretained(this), advertiser_id),
-              le_address_manager_->GetNextPrivateAddressIntervalMs());
+              next_address_rotation_interval(advertiser_id));
         }
         enable_advertiser(advertiser_id, true, 0, 0);
       }
@@ -450,7 +452,7 @@ struct LeAdvertisingManager::impl : public bluetooth::hci::LeAddressManagerCallb
           advertising_sets_[id].address_rotation_alarm = std::make_unique<os::Alarm>(module_handler_);
           advertising_sets_[id].address_rotation_alarm->Schedule(
               common::BindOnce(&impl::set_advertising_set_random_address_on_timer, common::Unretained(this), id),
-              le_address_manager_->GetNextPrivateAddressIntervalMs());
+              next_address_rotation_interval(id));
         } else {
           advertising_sets_[id].current_address = le_address_manager_->GetCurrentAddress();
           le_advertising_interface_->EnqueueCommand(
@@ -580,7 +582,32 @@ struct LeAdvertisingManager::impl : public bluetooth::hci::LeAddressManagerCallb
 
     advertising_sets_[advertiser_id].address_rotation_alarm->Schedule(
         common::BindOnce(&impl::set_advertising_set_random_address_on_timer, common::Unretained(this), advertiser_id),
-        le_address_manager_->GetNextPrivateAddressIntervalMs());
+        next_address_rotation_interval(advertiser_id));
+  }
+
+  std::chrono::milliseconds next_address_rotation_interval(AdvertiserId advertiser_id) {
+    auto interval = advertising_sets_[advertiser_id].address_rotation_interval;
+    if (interval.count() == 0) {
+      return le_address_manager_->GetNextPrivateAddressIntervalMs();
+    }
+    return interval;
+  }
+
+  void set_address_rotation_interval(AdvertiserId advertiser_id, std::chrono::milliseconds interval) {
+    if (advertising_sets_.find(advertiser_id) == advertising_sets_.end()) {
+      LOG_INFO("Unknown advertising id %u", advertiser_id);
+      return;
+    }
+    advertising_sets_[advertiser_id].address_rotation_interval = interval;
+
+    // Only a set that rotates its address has an alarm, which is rescheduled with the new interval.
+    if (advertising_sets_[advertiser_id].address_rotation_alarm != nullptr) {
+      advertising_sets_[advertiser_id].address_rotation_alarm->Cancel();
+      advertising_sets_[advertiser_id].address_rotation_alarm->Schedule(
+          common::BindOnce(
+              &impl::set_advertising_set_random_address_on_timer, common::Unretained(this), advertiser_id),
+          next_address_rotation_interval(advertiser_id));
+    }
   }
 
   void get_own_address(AdvertiserId advertiser_id) {
@@ -1366,7 +1393,16 @@ struct LeAdvertisingManager::impl : public bluetooth::hci::LeAddressManagerCallb
           "update random address for advertising set %d : %s",
           advertiser_id,
           address_with_type.GetAddress().ToString().c_str());
+      // The first address of the set is not a rotation.
+      bool rotated = advertising_sets_[advertiser_id].current_address.GetAddress() != Address::kEmpty &&
+                     advertising_sets_[advertiser_id].current_address != address_with_type;
       advertising_sets_[advertiser_id].current_address = address_with_type;
+      if (rotated && advertising_callbacks_ != nullptr) {
+        advertising_callbacks_->OnOwnAddressChanged(
+            advertiser_id,
+            static_cast<uint8_t>(address_with_type.GetAddressType()),
+            address_with_type.GetAddress());
+      }
     }
   }
 
@@ -1637,6 +1673,11 @@ void LeAdvertisingManager::GetOwnAddress(uint8_t advertiser_id) {
   CallOn(pimpl_.get(), &impl::get_own_address, advertiser_id);
 }
 
+void LeAdvertisingManager::SetAddressRotationInterval(
+    AdvertiserId advertiser_id, std::chrono::milliseconds interval) {
+  CallOn(pimpl_.get(), &impl::set_address_rotation_interval, advertiser_id, interval);
+}
+
 void LeAdvertisingManager::SetParameters(AdvertiserId advertiser_id, ExtendedAdvertisingConfig config) {
   CallOn(pimpl_.get(), &impl::set_parameters, advertiser_id, config);
 }
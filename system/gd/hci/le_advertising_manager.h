@@ -15,6 +15,7 @@
  */
 #pragma once
 
+#include <chrono>
 #include <memory>
 
 #include "common/callback.h"
@@ -92,6 +93,8 @@ class AdvertisingCallback {
   virtual void OnPeriodicAdvertisingDataSet(uint8_t advertiser_id, uint8_t status) = 0;
   virtual void OnPeriodicAdvertisingEnabled(uint8_t advertiser_id, bool enable, uint8_t status) = 0;
   virtual void OnOwnAddressRead(uint8_t advertiser_id, uint8_t address_type, Address address) = 0;
+  // Called when the random address of an advertising set is rotated.
+  virtual void OnOwnAddressChanged(uint8_t advertiser_id, uint8_t address_type, Address address) {}
 };
 
 class LeAdvertisingManager : public bluetooth::Module {
@@ -128,6 +131,10 @@ class LeAdvertisingManager : public bluetooth::Module {
 
   void GetOwnAddress(uint8_t advertiser_id);
 
+  // Sets the interval between the rotations of the random address of an advertising set. An interval of 0 restores
+  // the randomized interval of the stack.
+  void SetAddressRotationInterval(AdvertiserId advertiser_id, std::chrono::milliseconds interval);
+
   void RegisterAdvertiser(base::OnceCallback<void(uint8_t /* inst_id */, uint8_t /* status */)> callback);
 
   void SetParameters(AdvertiserId advertiser_id, ExtendedAdvertisingConfig config);
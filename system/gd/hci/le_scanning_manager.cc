@@ -41,6 +41,8 @@ constexpr uint16_t kLeScanIntervalMin = 0x0004;
 constexpr uint16_t kLeScanIntervalMax = 0x4000;
 constexpr uint16_t kDefaultLeExtendedScanInterval = 4800;
 constexpr uint16_t kLeExtendedScanIntervalMax = 0xFFFF;
+constexpr uint8_t kScanPhyLe1m = 0x01;
+constexpr uint8_t kScanPhyLeCoded = 0x04;
 
 constexpr uint8_t kScannableBit = 1;
 constexpr uint8_t kDirectedBit = 2;
@@ -541,8 +543,13 @@ struct LeScanningManager::impl : public LeAddressManagerCallback {
     phy_scan_parameters.le_scan_window_ = window_ms_;
     phy_scan_parameters.le_scan_interval_ = interval_ms_;
     phy_scan_parameters.le_scan_type_ = le_scan_type_;
-    parameter_vector.push_back(phy_scan_parameters);
-    uint8_t phys_in_use = 1;
+    // One set of parameters per PHY in use, the same for all of them.
+    for (uint8_t phy : {kScanPhyLe1m, kScanPhyLeCoded}) {
+      if (scan_phys_ & phy) {
+        parameter_vector.push_back(phy_scan_parameters);
+      }
+    }
+    uint8_t phys_in_use = scan_phys_;
 
     // The Host shall not issue set scan parameter command when scanning is enabled
     stop_scan();
@@ -709,6 +716,24 @@ struct LeScanningManager::impl : public LeAddressManagerCallback {
     scanning_callbacks_->OnSetScannerParameterComplete(scanner_id, ScanningCallback::SUCCESS);
   }
 
+  void set_scan_phys(ScannerId scanner_id, uint8_t scan_phys) {
+    if (scan_phys == 0 || (scan_phys & ~(kScanPhyLe1m | kScanPhyLeCoded)) != 0) {
+      LOG_ERROR("Invalid scan_phys 0x%02x", scan_phys);
+      scanning_callbacks_->OnSetScannerParameterComplete(
+          scanner_id, ScanningCallback::ScanningStatus::ILLEGAL_PARAMETER);
+      return;
+    }
+    // Only the extended scanning commands scan on other PHYs than LE 1M.
+    if (scan_phys != kScanPhyLe1m && api_type_ != ScanApiType::EXTENDED) {
+      LOG_ERROR("Scanning on the PHYs 0x%02x requires extended scanning", scan_phys);
+      scanning_callbacks_->OnSetScannerParameterComplete(
+          scanner_id, ScanningCallback::ScanningStatus::ILLEGAL_PARAMETER);
+      return;
+    }
+    scan_phys_ = scan_phys;
+    scanning_callbacks_->OnSetScannerParameterComplete(scanner_id, ScanningCallback::SUCCESS);
+  }
+
   void scan_filter_enable(bool enable) {
     if (!is_filter_supported_) {
       LOG_WARN("Advertising filter is not supported");
@@ -1606,6 +1631,7 @@ struct LeScanningManager::impl : public LeAddressManagerCallback {
   LeScanType le_scan_type_ = LeScanType::ACTIVE;
   uint32_t interval_ms_{1000};
   uint16_t window_ms_{1000};
+  uint8_t scan_phys_{kScanPhyLe1m};
   OwnAddressType own_address_type_{OwnAddressType::PUBLIC_DEVICE_ADDRESS};
   LeScanningFilterPolicy filter_policy_{LeScanningFilterPolicy::ACCEPT_ALL};
   BatchScanConfig batch_scan_config_;
@@ -1685,6 +1711,10 @@ void LeScanningManager::SetScanParameters(
   CallOn(pimpl_.get(), &impl::set_scan_parameters, scanner_id, scan_type, scan_interval, scan_window);
 }
 
+void LeScanningManager::SetScanPhys(ScannerId scanner_id, uint8_t scan_phys) {
+  CallOn(pimpl_.get(), &impl::set_scan_phys, scanner_id, scan_phys);
+}
+
 void LeScanningManager::ScanFilterEnable(bool enable) {
   CallOn(pimpl_.get(), &impl::scan_filter_enable, enable);
 }
@@ -54,6 +54,8 @@ class LeScanningManager : public bluetooth::Module {
   virtual void SetScanParameters(
       ScannerId scanner_id, LeScanType scan_type, uint16_t scan_interval, uint16_t scan_window);
 
+  virtual void SetScanPhys(ScannerId scanner_id, uint8_t scan_phys);
+
   /* Scan filter */
   virtual void ScanFilterEnable(bool enable);
 
@@ -211,6 +211,7 @@ init_flags!(
         finite_att_timeout = true,
         gatt_robust_caching_client = true,
         gatt_robust_caching_server,
+        gatt_service_changed = true,
         gd_core,
         gd_l2cap,
         gd_link_policy,
@@ -274,9 +275,11 @@ mod tests {
         test_load(vec![
             "INIT_btaa_hci=false", //override a default flag
             "INIT_gatt_robust_caching_server=true",
+            "INIT_gatt_service_changed=false",
         ]);
         assert!(!btaa_hci_is_enabled());
         assert!(gatt_robust_caching_server_is_enabled());
+        assert!(!gatt_service_changed_is_enabled());
     }
     #[test]
     fn parsing_failure() {
@@ -1,21 +1,29 @@
 use crate::dbus_iface::{
     export_bluetooth_callback_dbus_obj, export_bluetooth_connection_callback_dbus_obj,
-    export_bluetooth_gatt_callback_dbus_obj, export_bluetooth_manager_callback_dbus_obj,
-    export_suspend_callback_dbus_obj,
+    export_bluetooth_gatt_callback_dbus_obj, export_bluetooth_gatt_server_callback_dbus_obj,
+    export_bluetooth_manager_callback_dbus_obj, export_periodic_advertising_callback_dbus_obj,
+    export_scanner_callback_dbus_obj, export_suspend_callback_dbus_obj,
 };
 use crate::ClientContext;
 use crate::{console_yellow, print_info};
-use bt_topshim::btif::{BtBondState, BtSspVariant};
+use bt_topshim::btif::{BtBondState, BtSspVariant, Uuid128Bit};
 use bt_topshim::profiles::gatt::GattStatus;
+use btstack::address::BtAddress;
 use btstack::bluetooth::{
-    BluetoothDevice, IBluetooth, IBluetoothCallback, IBluetoothConnectionCallback,
+    BluetoothDevice, IBluetooth, IBluetoothCallback, IBluetoothConnectionCallback, RadioActivity,
 };
-use btstack::bluetooth_gatt::{BluetoothGattService, IBluetoothGattCallback, LePhy};
+use btstack::bluetooth_gatt::{
+    BatchScanResult, BluetoothGattService, CharacteristicReadResult, GattHandleValue,
+    IBluetoothGattCallback, IBluetoothGattServerCallback, IPeriodicAdvertisingCallback,
+    IScannerCallback, LePhy, ScanResult,
+};
+use btstack::gatt_conformance::ConformanceIssue;
 use btstack::suspend::ISuspendCallback;
+use btstack::uuid::UuidHelper;
 use btstack::RPCProxy;
 use dbus::nonblock::SyncConnection;
 use dbus_crossroads::Crossroads;
-use dbus_projection::DisconnectWatcher;
+use dbus_projection::{DisconnectWatcher, InterfacePolicy};
 use manager_service::iface_bluetooth_manager::IBluetoothManagerCallback;
 use std::sync::{Arc, Mutex};
 
@@ -76,6 +84,7 @@ impl manager_service::RPCProxy for BtManagerCallback {
             &mut cr.lock().unwrap(),
             Arc::new(Mutex::new(self)),
             Arc::new(Mutex::new(DisconnectWatcher::new())),
+            &InterfacePolicy::default(),
         );
     }
 }
@@ -213,6 +222,43 @@ impl IBluetoothCallback for BtCallback {
             });
         }
     }
+
+    fn on_ready(&self) {
+        print_info!("Adapter is ready");
+    }
+
+    fn on_pairing_locked_out(&self, device_address: String, global: bool, lockout_secs: u32) {
+        if global {
+            print_info!("Pairing from all devices locked out for {}s", lockout_secs);
+        } else {
+            print_info!("Pairing from [{}] locked out for {}s", device_address, lockout_secs);
+        }
+    }
+
+    fn on_stack_restart_started(&self, reason: String) {
+        print_info!("Stack restarting: {}", reason);
+    }
+
+    fn on_stack_restart_completed(&self, reason: String, success: bool) {
+        print_info!(
+            "Stack restart for '{}' {}",
+            reason,
+            if success { "succeeded" } else { "failed" }
+        );
+    }
+
+    fn on_radio_activity_changed(&self, activity: RadioActivity) {
+        print_info!(
+            "Radio activity: scanning = {}, advertising = {}, connections = {}",
+            activity.scanning,
+            activity.advertising,
+            activity.connections
+        );
+    }
+
+    fn on_device_forgotten(&self, device_address: String) {
+        print_info!("Device [{}] forgotten", device_address);
+    }
 }
 
 impl RPCProxy for BtCallback {
@@ -236,6 +282,7 @@ impl RPCProxy for BtCallback {
             &mut cr.lock().unwrap(),
             Arc::new(Mutex::new(self)),
             Arc::new(Mutex::new(DisconnectWatcher::new())),
+            &InterfacePolicy::default(),
         );
     }
 }
@@ -290,6 +337,7 @@ impl RPCProxy for BtConnectionCallback {
             &mut cr.lock().unwrap(),
             Arc::new(Mutex::new(self)),
             Arc::new(Mutex::new(DisconnectWatcher::new())),
+            &InterfacePolicy::default(),
         );
     }
 }
@@ -324,7 +372,7 @@ impl IBluetoothGattCallback for BtGattCallback {
         status: i32,
         client_id: i32,
         connected: bool,
-        addr: String,
+        addr: BtAddress,
     ) {
         print_info!(
             "GATT Client connection state = {}, client_id = {}, connected = {}, addr = {}",
@@ -335,7 +383,7 @@ impl IBluetoothGattCallback for BtGattCallback {
         );
     }
 
-    fn on_phy_update(&self, addr: String, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
+    fn on_phy_update(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
         print_info!(
             "Phy updated: addr = {}, tx_phy = {:?}, rx_phy = {:?}, status = {:?}",
             addr,
@@ -345,7 +393,7 @@ impl IBluetoothGattCallback for BtGattCallback {
         );
     }
 
-    fn on_phy_read(&self, addr: String, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
+    fn on_phy_read(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
         print_info!(
             "Phy read: addr = {}, tx_phy = {:?}, rx_phy = {:?}, status = {:?}",
             addr,
@@ -355,7 +403,12 @@ impl IBluetoothGattCallback for BtGattCallback {
         );
     }
 
-    fn on_search_complete(&self, addr: String, services: Vec<BluetoothGattService>, status: i32) {
+    fn on_search_complete(
+        &self,
+        addr: BtAddress,
+        services: Vec<BluetoothGattService>,
+        status: i32,
+    ) {
         print_info!(
             "GATT DB Search complete: addr = {}, services = {:?}, status = {}",
             addr,
@@ -364,7 +417,7 @@ impl IBluetoothGattCallback for BtGattCallback {
         );
     }
 
-    fn on_characteristic_read(&self, addr: String, status: i32, handle: i32, value: Vec<u8>) {
+    fn on_characteristic_read(&self, addr: BtAddress, status: i32, handle: i32, value: Vec<u8>) {
         print_info!(
             "GATT Characteristic read: addr = {}, status = {}, handle = {}, value = {:?}",
             addr,
@@ -374,7 +427,32 @@ impl IBluetoothGattCallback for BtGattCallback {
         );
     }
 
-    fn on_characteristic_write(&self, addr: String, status: i32, handle: i32) {
+    fn on_get_gatt_db(&self, addr: BtAddress, services: Vec<BluetoothGattService>) {
+        print_info!("GATT DB: addr = {}, services = {:?}", addr, services);
+    }
+
+    fn on_service_read(
+        &self,
+        addr: BtAddress,
+        service_uuid: Uuid128Bit,
+        results: Vec<CharacteristicReadResult>,
+    ) {
+        print_info!(
+            "GATT Service read: addr = {}, service = {}, results = {:?}",
+            addr,
+            UuidHelper::to_string(&service_uuid),
+            results
+        );
+    }
+
+    fn on_conformance_report(&self, addr: BtAddress, issues: Vec<ConformanceIssue>) {
+        print_info!("GATT conformance report: addr = {}, {} issue(s)", addr, issues.len());
+        for issue in issues {
+            print_info!("  {}", issue);
+        }
+    }
+
+    fn on_characteristic_write(&self, addr: BtAddress, status: i32, handle: i32) {
         print_info!(
             "GATT Characteristic write: addr = {}, status = {}, handle = {}",
             addr,
@@ -383,11 +461,27 @@ impl IBluetoothGattCallback for BtGattCallback {
         );
     }
 
-    fn on_execute_write(&self, addr: String, status: i32) {
+    fn on_characteristic_write_progress(
+        &self,
+        addr: BtAddress,
+        handle: i32,
+        bytes_written: i32,
+        total_bytes: i32,
+    ) {
+        print_info!(
+            "GATT Characteristic write progress: addr = {}, handle = {}, {}/{} bytes",
+            addr,
+            handle,
+            bytes_written,
+            total_bytes
+        );
+    }
+
+    fn on_execute_write(&self, addr: BtAddress, status: i32) {
         print_info!("GATT execute write addr = {}, status = {}", addr, status);
     }
 
-    fn on_descriptor_read(&self, addr: String, status: i32, handle: i32, value: Vec<u8>) {
+    fn on_descriptor_read(&self, addr: BtAddress, status: i32, handle: i32, value: Vec<u8>) {
         print_info!(
             "GATT Descriptor read: addr = {}, status = {}, handle = {}, value = {:?}",
             addr,
@@ -397,7 +491,7 @@ impl IBluetoothGattCallback for BtGattCallback {
         );
     }
 
-    fn on_descriptor_write(&self, addr: String, status: i32, handle: i32) {
+    fn on_descriptor_write(&self, addr: BtAddress, status: i32, handle: i32) {
         print_info!(
             "GATT Descriptor write: addr = {}, status = {}, handle = {}",
             addr,
@@ -406,21 +500,50 @@ impl IBluetoothGattCallback for BtGattCallback {
         );
     }
 
-    fn on_notify(&self, addr: String, handle: i32, value: Vec<u8>) {
+    fn on_notify(&self, addr: BtAddress, handle: i32, value: Vec<u8>) {
         print_info!("GATT Notification: addr = {}, handle = {}, value = {:?}", addr, handle, value);
     }
 
-    fn on_read_remote_rssi(&self, addr: String, rssi: i32, status: i32) {
+    fn on_notify_multiple(&self, addr: BtAddress, values: Vec<GattHandleValue>) {
+        for v in values {
+            print_info!(
+                "GATT Multiple Notification: addr = {}, handle = {}, value = {:?}",
+                addr,
+                v.handle,
+                v.value
+            );
+        }
+    }
+
+    fn on_notification_queue_overflow(&self, addr: BtAddress, handle: i32, dropped: u32) {
+        print_info!(
+            "GATT Notification queue overflow: addr = {}, handle = {}, dropped = {}",
+            addr,
+            handle,
+            dropped
+        );
+    }
+
+    fn on_read_remote_rssi(&self, addr: BtAddress, rssi: i32, status: i32) {
         print_info!("Remote RSSI read: addr = {}, rssi = {}, status = {}", addr, rssi, status);
     }
 
-    fn on_configure_mtu(&self, addr: String, mtu: i32, status: i32) {
+    fn on_rssi_threshold_crossed(&self, addr: BtAddress, rssi: i32, threshold: i32) {
+        print_info!(
+            "Remote RSSI threshold crossed: addr = {}, rssi = {}, threshold = {}",
+            addr,
+            rssi,
+            threshold
+        );
+    }
+
+    fn on_configure_mtu(&self, addr: BtAddress, mtu: i32, status: i32) {
         print_info!("MTU configured: addr = {}, mtu = {}, status = {}", addr, mtu, status);
     }
 
     fn on_connection_updated(
         &self,
-        addr: String,
+        addr: BtAddress,
         interval: i32,
         latency: i32,
         timeout: i32,
@@ -436,9 +559,17 @@ impl IBluetoothGattCallback for BtGattCallback {
         );
     }
 
-    fn on_service_changed(&self, addr: String) {
+    fn on_service_changed(&self, addr: BtAddress) {
         print_info!("Service changed for {}", addr,);
     }
+
+    fn on_notification_pipe_active(&self, addr: BtAddress, handle: i32) {
+        print_info!("Notification pipe active for {} handle {}", addr, handle);
+    }
+
+    fn on_eatt_state_changed(&self, addr: BtAddress, bearer_count: i32, bearer_mtus: Vec<i32>) {
+        print_info!("EATT bearers of {}: {} with MTUs {:?}", addr, bearer_count, bearer_mtus);
+    }
 }
 
 impl RPCProxy for BtGattCallback {
@@ -462,6 +593,448 @@ impl RPCProxy for BtGattCallback {
             &mut cr.lock().unwrap(),
             Arc::new(Mutex::new(self)),
             Arc::new(Mutex::new(DisconnectWatcher::new())),
+            &InterfacePolicy::default(),
+        );
+    }
+}
+
+pub(crate) struct BtGattServerCallback {
+    objpath: String,
+    context: Arc<Mutex<ClientContext>>,
+
+    dbus_connection: Arc<SyncConnection>,
+    dbus_crossroads: Arc<Mutex<Crossroads>>,
+}
+
+impl BtGattServerCallback {
+    pub(crate) fn new(
+        objpath: String,
+        context: Arc<Mutex<ClientContext>>,
+        dbus_connection: Arc<SyncConnection>,
+        dbus_crossroads: Arc<Mutex<Crossroads>>,
+    ) -> Self {
+        Self { objpath, context, dbus_connection, dbus_crossroads }
+    }
+}
+
+impl IBluetoothGattServerCallback for BtGattServerCallback {
+    fn on_server_registered(&self, status: i32, server_id: i32) {
+        print_info!("GATT Server registered status = {}, server_id = {}", status, server_id);
+        self.context.lock().unwrap().gatt_server_id = Some(server_id);
+    }
+
+    fn on_server_connection_state(&self, server_id: i32, connected: bool, addr: BtAddress) {
+        print_info!(
+            "GATT Server connection state: server_id = {}, connected = {}, addr = {}",
+            server_id,
+            connected,
+            addr
+        );
+    }
+
+    fn on_service_added(&self, status: i32, service: BluetoothGattService) {
+        print_info!("GATT Service added: status = {}, service = {:?}", status, service);
+    }
+
+    fn on_service_removed(&self, status: i32, handle: i32) {
+        print_info!("GATT Service removed: status = {}, handle = {}", status, handle);
+    }
+
+    fn on_characteristic_read_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        is_long: bool,
+        handle: i32,
+    ) {
+        print_info!(
+            "GATT Characteristic read request: addr = {}, request_id = {}, offset = {}, \
+            is_long = {}, handle = {}",
+            addr,
+            request_id,
+            offset,
+            is_long,
+            handle
+        );
+    }
+
+    fn on_descriptor_read_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        is_long: bool,
+        handle: i32,
+    ) {
+        print_info!(
+            "GATT Descriptor read request: addr = {}, request_id = {}, offset = {}, \
+            is_long = {}, handle = {}",
+            addr,
+            request_id,
+            offset,
+            is_long,
+            handle
+        );
+    }
+
+    fn on_characteristic_write_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        len: i32,
+        is_prep: bool,
+        need_response: bool,
+        handle: i32,
+        value: Vec<u8>,
+    ) {
+        print_info!(
+            "GATT Characteristic write request: addr = {}, request_id = {}, offset = {}, \
+            len = {}, is_prep = {}, need_response = {}, handle = {}, value = {:?}",
+            addr,
+            request_id,
+            offset,
+            len,
+            is_prep,
+            need_response,
+            handle,
+            value
+        );
+    }
+
+    fn on_descriptor_write_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        len: i32,
+        is_prep: bool,
+        need_response: bool,
+        handle: i32,
+        value: Vec<u8>,
+    ) {
+        print_info!(
+            "GATT Descriptor write request: addr = {}, request_id = {}, offset = {}, \
+            len = {}, is_prep = {}, need_response = {}, handle = {}, value = {:?}",
+            addr,
+            request_id,
+            offset,
+            len,
+            is_prep,
+            need_response,
+            handle,
+            value
+        );
+    }
+
+    fn on_execute_write(&self, addr: BtAddress, request_id: i32, execute_write: bool) {
+        print_info!(
+            "GATT Execute write request: addr = {}, request_id = {}, execute_write = {}",
+            addr,
+            request_id,
+            execute_write
+        );
+    }
+
+    fn on_notification_sent(&self, addr: BtAddress, status: i32) {
+        print_info!("GATT Notification sent: addr = {}, status = {}", addr, status);
+    }
+
+    fn on_mtu_changed(&self, addr: BtAddress, mtu: i32) {
+        print_info!("GATT Server MTU changed: addr = {}, mtu = {}", addr, mtu);
+    }
+
+    fn on_user_description_changed(&self, addr: BtAddress, handle: i32, description: String) {
+        print_info!(
+            "GATT Server user description changed: addr = {}, handle = {}, description = {}",
+            addr,
+            handle,
+            description
+        );
+    }
+
+    fn on_server_configuration_changed(&self, addr: BtAddress, handle: i32, broadcast: bool) {
+        print_info!(
+            "GATT Server configuration changed: addr = {}, handle = {}, broadcast = {}",
+            addr,
+            handle,
+            broadcast
+        );
+    }
+
+    fn on_phy_update(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
+        print_info!(
+            "GATT Server PHY updated: addr = {}, tx_phy = {:?}, rx_phy = {:?}, status = {:?}",
+            addr,
+            tx_phy,
+            rx_phy,
+            status
+        );
+    }
+
+    fn on_connection_updated(
+        &self,
+        addr: BtAddress,
+        interval: i32,
+        latency: i32,
+        timeout: i32,
+        status: i32,
+    ) {
+        print_info!(
+            "GATT Server connection updated: addr = {}, interval = {}, latency = {}, \
+            timeout = {}, status = {}",
+            addr,
+            interval,
+            latency,
+            timeout,
+            status
+        );
+    }
+}
+
+impl RPCProxy for BtGattServerCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        self.objpath.clone()
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {
+        let cr = self.dbus_crossroads.clone();
+        export_bluetooth_gatt_server_callback_dbus_obj(
+            self.get_object_id(),
+            self.dbus_connection.clone(),
+            &mut cr.lock().unwrap(),
+            Arc::new(Mutex::new(self)),
+            Arc::new(Mutex::new(DisconnectWatcher::new())),
+            &InterfacePolicy::default(),
+        );
+    }
+}
+
+/// Callback container for LE scanner callbacks.
+pub(crate) struct BtScannerCallback {
+    objpath: String,
+    context: Arc<Mutex<ClientContext>>,
+
+    dbus_connection: Arc<SyncConnection>,
+    dbus_crossroads: Arc<Mutex<Crossroads>>,
+}
+
+impl BtScannerCallback {
+    pub(crate) fn new(
+        objpath: String,
+        context: Arc<Mutex<ClientContext>>,
+        dbus_connection: Arc<SyncConnection>,
+        dbus_crossroads: Arc<Mutex<Crossroads>>,
+    ) -> Self {
+        Self { objpath, context, dbus_connection, dbus_crossroads }
+    }
+}
+
+impl IScannerCallback for BtScannerCallback {
+    fn on_scanner_registered(&self, status: i32, scanner_id: i32) {
+        print_info!("Scanner registered status = {}, scanner_id = {}", status, scanner_id);
+        if status == 0 {
+            self.context.lock().unwrap().scanner_id = Some(scanner_id);
+        }
+    }
+
+    fn on_scan_result(&self, scan_result: ScanResult) {
+        print_info!(
+            "Scan result: address = {}, rssi = {}, name = {}",
+            scan_result.address,
+            scan_result.rssi,
+            scan_result.scan_record.name
+        );
+    }
+
+    fn on_scan_result_batch(&self, scan_results: Vec<ScanResult>) {
+        for scan_result in scan_results {
+            self.on_scan_result(scan_result);
+        }
+    }
+
+    fn on_scan_result_lost(&self, scan_result: ScanResult) {
+        print_info!("Scan result lost: address = {}", scan_result.address);
+    }
+
+    fn on_batch_scan_reports(&self, scanner_id: i32, status: i32, results: Vec<BatchScanResult>) {
+        print_info!(
+            "Batch scan reports: scanner_id = {}, status = {}, results = {}",
+            scanner_id,
+            status,
+            results.len()
+        );
+    }
+
+    fn on_batch_scan_threshold_crossed(&self, scanner_id: i32) {
+        print_info!("Batch scan threshold crossed: scanner_id = {}", scanner_id);
+    }
+
+    fn on_scan_parameters_changed(&self, scanner_id: i32, interval: i32, window: i32) {
+        print_info!(
+            "Scan parameters changed: scanner_id = {}, interval = {}, window = {}",
+            scanner_id,
+            interval,
+            window
+        );
+    }
+
+    fn on_scan_duty_cycle_changed(&self, scanner_id: i32, requested: i32, effective: i32) {
+        print_info!(
+            "Scan duty cycle changed: scanner_id = {}, requested = {}, effective = {}",
+            scanner_id,
+            requested,
+            effective
+        );
+    }
+
+    fn on_manufacturer_data_found(
+        &self,
+        scanner_id: i32,
+        subscription_id: u32,
+        addr: BtAddress,
+        rssi: i32,
+        data: Vec<u8>,
+    ) {
+        print_info!(
+            "Manufacturer data found: scanner_id = {}, subscription_id = {}, addr = {}, \
+             rssi = {}, data = {:?}",
+            scanner_id,
+            subscription_id,
+            addr,
+            rssi,
+            data
+        );
+    }
+}
+
+impl RPCProxy for BtScannerCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        self.objpath.clone()
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {
+        let cr = self.dbus_crossroads.clone();
+        export_scanner_callback_dbus_obj(
+            self.get_object_id(),
+            self.dbus_connection.clone(),
+            &mut cr.lock().unwrap(),
+            Arc::new(Mutex::new(self)),
+            Arc::new(Mutex::new(DisconnectWatcher::new())),
+            &InterfacePolicy::default(),
+        );
+    }
+}
+
+/// Callback container for periodic advertising sync callbacks.
+pub(crate) struct BtPeriodicAdvertisingCallback {
+    objpath: String,
+
+    dbus_connection: Arc<SyncConnection>,
+    dbus_crossroads: Arc<Mutex<Crossroads>>,
+}
+
+impl BtPeriodicAdvertisingCallback {
+    pub(crate) fn new(
+        objpath: String,
+        dbus_connection: Arc<SyncConnection>,
+        dbus_crossroads: Arc<Mutex<Crossroads>>,
+    ) -> Self {
+        Self { objpath, dbus_connection, dbus_crossroads }
+    }
+}
+
+impl IPeriodicAdvertisingCallback for BtPeriodicAdvertisingCallback {
+    fn on_sync_started(
+        &self,
+        sync_handle: i32,
+        status: i32,
+        sid: i32,
+        addr_type: i32,
+        address: BtAddress,
+        phy: i32,
+        interval: i32,
+    ) {
+        print_info!(
+            "Periodic sync started: sync_handle = {}, status = {}, sid = {}, addr_type = {}, \
+             address = {}, phy = {}, interval = {}",
+            sync_handle,
+            status,
+            sid,
+            addr_type,
+            address,
+            phy,
+            interval
+        );
+    }
+
+    fn on_sync_report(
+        &self,
+        sync_handle: i32,
+        tx_power: i32,
+        rssi: i32,
+        status: i32,
+        data: Vec<u8>,
+    ) {
+        print_info!(
+            "Periodic sync report: sync_handle = {}, tx_power = {}, rssi = {}, status = {}, \
+             data = {:?}",
+            sync_handle,
+            tx_power,
+            rssi,
+            status,
+            data
+        );
+    }
+
+    fn on_sync_lost(&self, sync_handle: i32) {
+        print_info!("Periodic sync lost: sync_handle = {}", sync_handle);
+    }
+
+    fn on_sync_transferred(&self, address: BtAddress, status: i32) {
+        print_info!("Periodic sync transferred to {}, status = {}", address, status);
+    }
+}
+
+impl RPCProxy for BtPeriodicAdvertisingCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        self.objpath.clone()
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {
+        let cr = self.dbus_crossroads.clone();
+        export_periodic_advertising_callback_dbus_obj(
+            self.get_object_id(),
+            self.dbus_connection.clone(),
+            &mut cr.lock().unwrap(),
+            Arc::new(Mutex::new(self)),
+            Arc::new(Mutex::new(DisconnectWatcher::new())),
+            &InterfacePolicy::default(),
         );
     }
 }
@@ -512,6 +1085,7 @@ impl RPCProxy for SuspendCallback {
             &mut cr.lock().unwrap(),
             Arc::new(Mutex::new(self)),
             Arc::new(Mutex::new(DisconnectWatcher::new())),
+            &InterfacePolicy::default(),
         );
     }
 }
@@ -2,13 +2,18 @@ use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Result};
 use std::sync::{Arc, Mutex};
 
-use crate::callbacks::BtGattCallback;
+use crate::callbacks::{
+    BtGattCallback, BtGattServerCallback, BtPeriodicAdvertisingCallback, BtScannerCallback,
+};
 use crate::ClientContext;
 use crate::{console_red, console_yellow, print_error, print_info};
 use bt_topshim::btif::BtTransport;
+use btstack::address::BtAddress;
 use btstack::bluetooth::{BluetoothDevice, IBluetooth};
-use btstack::bluetooth_gatt::IBluetoothGatt;
-use btstack::uuid::{Profile, UuidHelper};
+use btstack::bluetooth_gatt::{
+    IBluetoothGatt, ScanSettings, OPERATION_TOKEN_ALL, OPERATION_TOKEN_DISCOVERY,
+};
+use btstack::uuid::{BtUuid, Profile, UuidHelper};
 use manager_service::iface_bluetooth_manager::IBluetoothManager;
 
 const INDENT_CHAR: &str = " ";
@@ -16,6 +21,7 @@ const BAR1_CHAR: &str = "=";
 const BAR2_CHAR: &str = "-";
 const MAX_MENU_CHAR_WIDTH: usize = 72;
 const GATT_CLIENT_APP_UUID: &str = "12345678123456781234567812345678";
+const GATT_SERVER_APP_UUID: &str = "12345678123456781234567812345679";
 
 type CommandFunction = fn(&mut CommandHandler, &Vec<String>);
 
@@ -273,6 +279,7 @@ impl CommandHandler {
                     let cod = adapter_dbus.get_bluetooth_class();
                     let multi_adv_supported = adapter_dbus.is_multi_advertisement_supported();
                     let le_ext_adv_supported = adapter_dbus.is_le_extended_advertising_supported();
+                    let activity = adapter_dbus.get_radio_activity();
                     let uuid_helper = UuidHelper::new();
                     let enabled_profiles = uuid_helper.get_enabled_profiles();
                     let connected_profiles: Vec<Profile> = enabled_profiles
@@ -289,6 +296,7 @@ impl CommandHandler {
                     print_info!("IsMultiAdvertisementSupported: {}", multi_adv_supported);
                     print_info!("IsLeExtendedAdvertisingSupported: {}", le_ext_adv_supported);
                     print_info!("Connected profiles: {:?}", connected_profiles);
+                    print_info!("RadioActivity: {:?}", activity);
                     print_info!(
                         "Uuids: {}",
                         DisplayList(
@@ -301,7 +309,7 @@ impl CommandHandler {
                 }
                 "discoverable" => match &args[1][0..] {
                     "on" => {
-                        let discoverable = self
+                        let result = self
                             .context
                             .lock()
                             .unwrap()
@@ -309,13 +317,13 @@ impl CommandHandler {
                             .as_ref()
                             .unwrap()
                             .set_discoverable(true, 60);
-                        print_info!(
-                            "Set discoverable for 60s: {}",
-                            if discoverable { "succeeded" } else { "failed" }
-                        );
+                        match result {
+                            Ok(()) => print_info!("Set discoverable for 60s: succeeded"),
+                            Err(e) => print_error!("Failed to set discoverable: {}", e),
+                        }
                     }
                     "off" => {
-                        let discoverable = self
+                        let result = self
                             .context
                             .lock()
                             .unwrap()
@@ -323,10 +331,10 @@ impl CommandHandler {
                             .as_ref()
                             .unwrap()
                             .set_discoverable(false, 60);
-                        print_info!(
-                            "Turn discoverable off: {}",
-                            if discoverable { "succeeded" } else { "failed" }
-                        );
+                        match result {
+                            Ok(()) => print_info!("Turn discoverable off: succeeded"),
+                            Err(e) => print_error!("Failed to turn discoverable off: {}", e),
+                        }
                     }
                     _ => println!("Invalid argument for adapter discoverable '{}'", args[1]),
                 },
@@ -355,10 +363,18 @@ impl CommandHandler {
 
         enforce_arg_len(args, 1, "discovery <start|stop>", || match &args[0][0..] {
             "start" => {
-                self.context.lock().unwrap().adapter_dbus.as_ref().unwrap().start_discovery();
+                let result =
+                    self.context.lock().unwrap().adapter_dbus.as_ref().unwrap().start_discovery();
+                if let Err(e) = result {
+                    print_error!("Failed to start discovery: {}", e);
+                }
             }
             "stop" => {
-                self.context.lock().unwrap().adapter_dbus.as_ref().unwrap().cancel_discovery();
+                let result =
+                    self.context.lock().unwrap().adapter_dbus.as_ref().unwrap().cancel_discovery();
+                if let Err(e) = result {
+                    print_error!("Failed to stop discovery: {}", e);
+                }
             }
             _ => {
                 println!("Invalid argument '{}'", args[0]);
@@ -372,61 +388,102 @@ impl CommandHandler {
             return;
         }
 
-        enforce_arg_len(args, 2, "bond <add|remove|cancel> <address>", || match &args[0][0..] {
-            "add" => {
-                let device = BluetoothDevice {
-                    address: String::from(&args[1]),
-                    name: String::from("Classic Device"),
-                };
+        enforce_arg_len(args, 2, "bond <add|remove|forget|cancel> <address>", || {
+            match &args[0][0..] {
+                "add" => {
+                    let device = BluetoothDevice {
+                        address: String::from(&args[1]),
+                        name: String::from("Classic Device"),
+                    };
 
-                let bonding_attempt =
-                    &self.context.lock().unwrap().bonding_attempt.as_ref().cloned();
+                    let bonding_attempt =
+                        &self.context.lock().unwrap().bonding_attempt.as_ref().cloned();
 
-                if bonding_attempt.is_some() {
-                    print_info!(
-                        "Already bonding [{}]. Cancel bonding first.",
-                        bonding_attempt.as_ref().unwrap().address,
-                    );
-                    return;
+                    if bonding_attempt.is_some() {
+                        print_info!(
+                            "Already bonding [{}]. Cancel bonding first.",
+                            bonding_attempt.as_ref().unwrap().address,
+                        );
+                        return;
+                    }
+
+                    let result = self
+                        .context
+                        .lock()
+                        .unwrap()
+                        .adapter_dbus
+                        .as_mut()
+                        .unwrap()
+                        .create_bond(device.clone(), BtTransport::Auto);
+
+                    match result {
+                        Ok(()) => self.context.lock().unwrap().bonding_attempt = Some(device),
+                        Err(e) => print_error!("Failed to bond [{}]: {}", device.address, e),
+                    }
                 }
+                "remove" => {
+                    let device = BluetoothDevice {
+                        address: String::from(&args[1]),
+                        name: String::from("Classic Device"),
+                    };
 
-                let success = self
-                    .context
-                    .lock()
-                    .unwrap()
-                    .adapter_dbus
-                    .as_ref()
-                    .unwrap()
-                    .create_bond(device.clone(), BtTransport::Auto);
+                    let address = device.address.clone();
+                    let result = self
+                        .context
+                        .lock()
+                        .unwrap()
+                        .adapter_dbus
+                        .as_ref()
+                        .unwrap()
+                        .remove_bond(device);
 
-                if success {
-                    self.context.lock().unwrap().bonding_attempt = Some(device);
+                    if let Err(e) = result {
+                        print_error!("Failed to remove bond [{}]: {}", address, e);
+                    }
                 }
-            }
-            "remove" => {
-                let device = BluetoothDevice {
-                    address: String::from(&args[1]),
-                    name: String::from("Classic Device"),
-                };
+                "forget" => {
+                    let device = BluetoothDevice {
+                        address: String::from(&args[1]),
+                        name: String::from("Classic Device"),
+                    };
 
-                self.context.lock().unwrap().adapter_dbus.as_ref().unwrap().remove_bond(device);
-            }
-            "cancel" => {
-                let device = BluetoothDevice {
-                    address: String::from(&args[1]),
-                    name: String::from("Classic Device"),
-                };
+                    let address = device.address.clone();
+                    let result = self
+                        .context
+                        .lock()
+                        .unwrap()
+                        .adapter_dbus
+                        .as_mut()
+                        .unwrap()
+                        .remove_bond_cascade(device);
 
-                self.context
-                    .lock()
-                    .unwrap()
-                    .adapter_dbus
-                    .as_ref()
-                    .unwrap()
-                    .cancel_bond_process(device);
-            }
-            _ => {
-                println!("Invalid argument '{}'", args[0]);
+                    if let Err(e) = result {
+                        print_error!("Failed to forget [{}]: {}", address, e);
+                    }
+                }
+                "cancel" => {
+                    let device = BluetoothDevice {
+                        address: String::from(&args[1]),
+                        name: String::from("Classic Device"),
+                    };
+
+                    let address = device.address.clone();
+                    let result = self
+                        .context
+                        .lock()
+                        .unwrap()
+                        .adapter_dbus
+                        .as_ref()
+                        .unwrap()
+                        .cancel_bond_process(device);
+
+                    if let Err(e) = result {
+                        print_error!("Failed to cancel bonding [{}]: {}", address, e);
+                    }
+                }
+                _ => {
+                    println!("Invalid argument '{}'", args[0]);
+                }
             }
         });
     }
@@ -502,6 +559,15 @@ impl CommandHandler {
                         (name, alias, device_type, class, bonded, connected, uuids)
                     };
 
+                    let version_info = self
+                        .context
+                        .lock()
+                        .unwrap()
+                        .adapter_dbus
+                        .as_mut()
+                        .unwrap()
+                        .get_remote_version_info(device.clone());
+
                     print_info!("Address: {}", &device.address);
                     print_info!("Name: {}", name);
                     print_info!("Alias: {}", alias);
@@ -518,6 +584,24 @@ impl CommandHandler {
                                 .collect::<Vec<String>>()
                         )
                     );
+                    match version_info {
+                        Ok(info) => {
+                            print_info!(
+                                "Version: {:#04x}, subversion: {:#06x}, manufacturer: {:#06x}",
+                                info.lmp_version,
+                                info.lmp_subversion,
+                                info.manufacturer
+                            );
+                            print_info!(
+                                "Features: {}",
+                                info.features
+                                    .iter()
+                                    .map(|b| format!("{:02x}", b))
+                                    .collect::<String>()
+                            );
+                        }
+                        Err(e) => print_info!("Version: {}", e),
+                    }
                 }
                 "set-alias" => {
                     if args.len() < 3 {
@@ -583,7 +667,7 @@ impl CommandHandler {
                 let dbus_crossroads = self.context.lock().unwrap().dbus_crossroads.clone();
 
                 self.context.lock().unwrap().gatt_dbus.as_mut().unwrap().register_client(
-                    String::from(GATT_CLIENT_APP_UUID),
+                    BtUuid::from_string(GATT_CLIENT_APP_UUID).unwrap(),
                     Box::new(BtGattCallback::new(
                         String::from("/org/chromium/bluetooth/client/bluetooth_gatt_callback"),
                         self.context.clone(),
@@ -593,6 +677,23 @@ impl CommandHandler {
                     false,
                 );
             }
+            "register-server" => {
+                let dbus_connection = self.context.lock().unwrap().dbus_connection.clone();
+                let dbus_crossroads = self.context.lock().unwrap().dbus_crossroads.clone();
+
+                self.context.lock().unwrap().gatt_dbus.as_mut().unwrap().register_server(
+                    BtUuid::from_string(GATT_SERVER_APP_UUID).unwrap(),
+                    Box::new(BtGattServerCallback::new(
+                        String::from(
+                            "/org/chromium/bluetooth/client/bluetooth_gatt_server_callback",
+                        ),
+                        self.context.clone(),
+                        dbus_connection,
+                        dbus_crossroads,
+                    )),
+                    false,
+                );
+            }
             "client-connect" => {
                 if args.len() < 2 {
                     println!("usage: gatt client-connect <addr>");
@@ -605,15 +706,56 @@ impl CommandHandler {
                     return;
                 }
 
-                let addr = String::from(&args[1]);
-                self.context.lock().unwrap().gatt_dbus.as_ref().unwrap().client_connect(
-                    client_id.unwrap(),
-                    addr,
-                    false,
-                    2,
-                    false,
-                    1,
-                );
+                let addr = match BtAddress::from_string(&args[1]) {
+                    Some(addr) => addr,
+                    None => {
+                        println!("Invalid address {}", args[1]);
+                        return;
+                    }
+                };
+                let result = self
+                    .context
+                    .lock()
+                    .unwrap()
+                    .gatt_dbus
+                    .as_mut()
+                    .unwrap()
+                    .client_connect(client_id.unwrap(), addr, false, 2, false, 1);
+
+                if let Err(e) = result {
+                    print_error!("Failed to connect: {}", e);
+                }
+            }
+            "client-background-connect" | "client-background-remove" => {
+                if args.len() < 2 {
+                    println!("usage: gatt {} <addr>", args[0]);
+                    return;
+                }
+
+                let client_id = self.context.lock().unwrap().gatt_client_id;
+                if client_id.is_none() {
+                    println!("GATT client is not yet registered.");
+                    return;
+                }
+
+                let addr = match BtAddress::from_string(&args[1]) {
+                    Some(addr) => addr,
+                    None => {
+                        println!("Invalid address {}", args[1]);
+                        return;
+                    }
+                };
+                let mut context = self.context.lock().unwrap();
+                let gatt_dbus = context.gatt_dbus.as_mut().unwrap();
+                let result = if args[0] == "client-background-connect" {
+                    gatt_dbus.add_device_to_background_connect(client_id.unwrap(), addr)
+                } else {
+                    gatt_dbus.remove_device_from_background_connect(client_id.unwrap(), addr)
+                };
+
+                if let Err(e) = result {
+                    print_error!("Failed to update the background connection list: {}", e);
+                }
             }
             "client-read-phy" => {
                 if args.len() < 2 {
@@ -627,14 +769,94 @@ impl CommandHandler {
                     return;
                 }
 
-                let addr = String::from(&args[1]);
-                self.context
+                let addr = match BtAddress::from_string(&args[1]) {
+                    Some(addr) => addr,
+                    None => {
+                        println!("Invalid address {}", args[1]);
+                        return;
+                    }
+                };
+                let result = self
+                    .context
                     .lock()
                     .unwrap()
                     .gatt_dbus
                     .as_mut()
                     .unwrap()
                     .client_read_phy(client_id.unwrap(), addr);
+
+                if let Err(e) = result {
+                    print_error!("Failed to read PHY: {}", e);
+                }
+            }
+            "client-cancel" => {
+                if args.len() < 3 {
+                    println!("usage: gatt client-cancel <addr> <handle|discovery|all>");
+                    return;
+                }
+
+                let client_id = self.context.lock().unwrap().gatt_client_id;
+                if client_id.is_none() {
+                    println!("GATT client is not yet registered.");
+                    return;
+                }
+
+                let addr = match BtAddress::from_string(&args[1]) {
+                    Some(addr) => addr,
+                    None => {
+                        println!("Invalid address {}", args[1]);
+                        return;
+                    }
+                };
+                let token = match &args[2][..] {
+                    "discovery" => OPERATION_TOKEN_DISCOVERY,
+                    "all" => OPERATION_TOKEN_ALL,
+                    handle => match handle.parse::<i32>() {
+                        Ok(h) if h > 0 => h,
+                        _ => {
+                            println!("Invalid handle {}", handle);
+                            return;
+                        }
+                    },
+                };
+
+                let result =
+                    self.context.lock().unwrap().gatt_dbus.as_mut().unwrap().cancel_operation(
+                        client_id.unwrap(),
+                        addr,
+                        token,
+                    );
+
+                if let Err(e) = result {
+                    print_error!("Failed to cancel: {}", e);
+                }
+            }
+            "client-get-preferred-phy" => {
+                if args.len() < 2 {
+                    println!("usage: gatt client-get-preferred-phy <addr>");
+                    return;
+                }
+
+                let addr = match BtAddress::from_string(&args[1]) {
+                    Some(addr) => addr,
+                    None => {
+                        println!("Invalid address {}", args[1]);
+                        return;
+                    }
+                };
+                let result = self
+                    .context
+                    .lock()
+                    .unwrap()
+                    .gatt_dbus
+                    .as_ref()
+                    .unwrap()
+                    .get_preferred_phy(addr);
+
+                match result {
+                    Ok(preference) => print_info!("Preferred PHY: {:?}", preference),
+                    Err(e) => print_error!("Failed to get the preferred PHY: {}", e),
+                }
             }
             "client-discover-services" => {
                 if args.len() < 2 {
@@ -648,14 +870,259 @@ impl CommandHandler {
                     return;
                 }
 
-                let addr = String::from(&args[1]);
-                self.context
+                let addr = match BtAddress::from_string(&args[1]) {
+                    Some(addr) => addr,
+                    None => {
+                        println!("Invalid address {}", args[1]);
+                        return;
+                    }
+                };
+                let result = self
+                    .context
                     .lock()
                     .unwrap()
                     .gatt_dbus
-                    .as_ref()
+                    .as_mut()
                     .unwrap()
                     .discover_services(client_id.unwrap(), addr);
+
+                if let Err(e) = result {
+                    print_error!("Failed to discover services: {}", e);
+                }
+            }
+            "client-get-gatt-db" => {
+                if args.len() < 2 {
+                    println!("usage: gatt client-get-gatt-db <addr>");
+                    return;
+                }
+
+                let client_id = self.context.lock().unwrap().gatt_client_id;
+                if client_id.is_none() {
+                    println!("GATT client is not yet registered.");
+                    return;
+                }
+
+                let addr = match BtAddress::from_string(&args[1]) {
+                    Some(addr) => addr,
+                    None => {
+                        println!("Invalid address {}", args[1]);
+                        return;
+                    }
+                };
+                let result = self
+                    .context
+                    .lock()
+                    .unwrap()
+                    .gatt_dbus
+                    .as_mut()
+                    .unwrap()
+                    .get_gatt_db(client_id.unwrap(), addr);
+
+                match result {
+                    Ok(services) => print_info!("Cached GATT DB: {:?}", services),
+                    Err(e) => print_error!("Failed to get GATT DB: {}", e),
+                }
+            }
+            "client-read-service" => {
+                if args.len() < 3 {
+                    println!("usage: gatt client-read-service <addr> <service_uuid>");
+                    return;
+                }
+
+                let client_id = self.context.lock().unwrap().gatt_client_id;
+                if client_id.is_none() {
+                    println!("GATT client is not yet registered.");
+                    return;
+                }
+
+                let addr = match BtAddress::from_string(&args[1]) {
+                    Some(addr) => addr,
+                    None => {
+                        println!("Invalid address {}", args[1]);
+                        return;
+                    }
+                };
+                let uuid = match BtUuid::from_string(&args[2]) {
+                    Some(uuid) => uuid,
+                    None => {
+                        println!("Invalid UUID {}", args[2]);
+                        return;
+                    }
+                };
+                let result = self.context.lock().unwrap().gatt_dbus.as_mut().unwrap().read_service(
+                    client_id.unwrap(),
+                    addr,
+                    uuid,
+                );
+
+                if let Err(e) = result {
+                    print_error!("Failed to read service: {}", e);
+                }
+            }
+            "client-check-conformance" => {
+                if args.len() < 2 {
+                    println!("usage: gatt client-check-conformance <addr>");
+                    return;
+                }
+
+                let client_id = self.context.lock().unwrap().gatt_client_id;
+                if client_id.is_none() {
+                    println!("GATT client is not yet registered.");
+                    return;
+                }
+
+                let addr = match BtAddress::from_string(&args[1]) {
+                    Some(addr) => addr,
+                    None => {
+                        println!("Invalid address {}", args[1]);
+                        return;
+                    }
+                };
+                let result = self
+                    .context
+                    .lock()
+                    .unwrap()
+                    .gatt_dbus
+                    .as_mut()
+                    .unwrap()
+                    .check_conformance(client_id.unwrap(), addr);
+
+                if let Err(e) = result {
+                    print_error!("Failed to check conformance: {}", e);
+                }
+            }
+            "att-trace" => {
+                if args.len() < 2 || (args[1] != "stop" && args.len() < 3) {
+                    println!("usage: gatt att-trace <start|dump> <addr> | gatt att-trace stop");
+                    return;
+                }
+
+                let mut context = self.context.lock().unwrap();
+                let gatt = context.gatt_dbus.as_mut().unwrap();
+                match (&args[1][0..], args.get(2).and_then(BtAddress::from_string)) {
+                    ("start", Some(addr)) => {
+                        if let Err(e) = gatt.start_att_trace(addr) {
+                            print_error!("Failed to start ATT trace: {}", e);
+                        }
+                    }
+                    ("stop", _) => gatt.stop_att_trace(),
+                    ("dump", Some(addr)) => match gatt.get_att_trace(addr) {
+                        Ok(records) => {
+                            for r in records {
+                                print_info!(
+                                    "+{}us {:?} opcode=0x{:02x} handle={} len={} status={}",
+                                    r.delta_us,
+                                    r.direction,
+                                    r.opcode,
+                                    r.handle,
+                                    r.length,
+                                    r.status
+                                );
+                            }
+                        }
+                        Err(e) => print_error!("Failed to get ATT trace: {}", e),
+                    },
+                    ("start", None) | ("dump", None) => println!("Invalid address {}", args[2]),
+                    _ => println!("Invalid argument '{}'", args[1]),
+                }
+            }
+            "register-scanner" => {
+                let dbus_connection = self.context.lock().unwrap().dbus_connection.clone();
+                let dbus_crossroads = self.context.lock().unwrap().dbus_crossroads.clone();
+
+                self.context.lock().unwrap().gatt_dbus.as_mut().unwrap().register_scanner(
+                    Box::new(BtScannerCallback::new(
+                        String::from("/org/chromium/bluetooth/client/scanner_callback"),
+                        self.context.clone(),
+                        dbus_connection,
+                        dbus_crossroads,
+                    )),
+                );
+            }
+            "start-scan" | "stop-scan" => {
+                let scanner_id = match self.context.lock().unwrap().scanner_id {
+                    Some(id) => id,
+                    None => {
+                        println!("Scanner is not yet registered.");
+                        return;
+                    }
+                };
+
+                let mut context = self.context.lock().unwrap();
+                let gatt_dbus = context.gatt_dbus.as_mut().unwrap();
+                if args[0] == "start-scan" {
+                    if let Err(e) =
+                        gatt_dbus.start_scan(scanner_id, ScanSettings::default(), vec![])
+                    {
+                        print_error!("Failed to start scan: {}", e);
+                    }
+                } else {
+                    gatt_dbus.stop_scan(scanner_id);
+                }
+            }
+            "start-sync" => {
+                if args.len() < 3 {
+                    println!("usage: gatt start-sync <sid> <addr>");
+                    return;
+                }
+
+                let sid = match args[1].parse::<i32>() {
+                    Ok(sid) => sid,
+                    Err(_) => {
+                        println!("Invalid sid {}", args[1]);
+                        return;
+                    }
+                };
+                let addr = match BtAddress::from_string(&args[2]) {
+                    Some(addr) => addr,
+                    None => {
+                        println!("Invalid address {}", args[2]);
+                        return;
+                    }
+                };
+
+                let dbus_connection = self.context.lock().unwrap().dbus_connection.clone();
+                let dbus_crossroads = self.context.lock().unwrap().dbus_crossroads.clone();
+
+                // No skipped reports, and a sync timeout of 10 seconds.
+                let result = self.context.lock().unwrap().gatt_dbus.as_mut().unwrap().start_sync(
+                    sid,
+                    addr,
+                    0,
+                    1000,
+                    Box::new(BtPeriodicAdvertisingCallback::new(
+                        String::from(
+                            "/org/chromium/bluetooth/client/periodic_advertising_callback",
+                        ),
+                        dbus_connection,
+                        dbus_crossroads,
+                    )),
+                );
+
+                if let Err(e) = result {
+                    print_error!("Failed to start sync: {}", e);
+                }
+            }
+            "stop-sync" => {
+                if args.len() < 2 {
+                    println!("usage: gatt stop-sync <sync_handle>");
+                    return;
+                }
+
+                let sync_handle = match args[1].parse::<i32>() {
+                    Ok(sync_handle) => sync_handle,
+                    Err(_) => {
+                        println!("Invalid sync handle {}", args[1]);
+                        return;
+                    }
+                };
+
+                let result =
+                    self.context.lock().unwrap().gatt_dbus.as_mut().unwrap().stop_sync(sync_handle);
+
+                if let Err(e) = result {
+                    print_error!("Failed to stop sync: {}", e);
+                }
             }
             _ => {
                 println!("Invalid argument '{}'", args[0]);
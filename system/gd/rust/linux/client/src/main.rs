@@ -56,6 +56,12 @@ pub(crate) struct ClientContext {
     /// If set, the registered GATT client id. None otherwise.
     pub(crate) gatt_client_id: Option<i32>,
 
+    /// If set, the registered GATT server id. None otherwise.
+    pub(crate) gatt_server_id: Option<i32>,
+
+    /// If set, the registered LE scanner id. None otherwise.
+    pub(crate) scanner_id: Option<i32>,
+
     /// Proxy for manager interface.
     pub(crate) manager_dbus: BluetoothManagerDBus,
 
@@ -98,6 +104,8 @@ impl ClientContext {
             discovering_state: false,
             found_devices: HashMap::new(),
             gatt_client_id: None,
+            gatt_server_id: None,
+            scanner_id: None,
             manager_dbus,
             adapter_dbus: None,
             gatt_dbus: None,
@@ -0,0 +1,88 @@
+//! Compares building a fresh proxy for every callback invocation with reusing a pooled proxy.
+//!
+//! Each iteration simulates 1000 callback invocations to the same remote object, which is about
+//! one second of traffic for a busy scanner client. The number of heap allocations per iteration
+//! is printed before the timings.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use dbus::nonblock::Proxy;
+use dbus::strings::{BusName, Path};
+use dbus_projection::ProxyPool;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const CALLBACKS_PER_ITERATION: usize = 1000;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Stands in for the D-Bus connection, the proxies never send anything in this benchmark.
+type FakeConnection = Arc<()>;
+
+fn fresh_proxies(conn: &FakeConnection, remote: &BusName<'static>, path: &Path<'static>) {
+    for _ in 0..CALLBACKS_PER_ITERATION {
+        let proxy = Proxy::new(remote.clone(), path.clone(), Duration::from_secs(2), conn.clone());
+        black_box(proxy);
+    }
+}
+
+fn pooled_proxies(
+    pool: &mut ProxyPool<FakeConnection>,
+    conn: &FakeConnection,
+    remote: &BusName<'static>,
+    path: &Path<'static>,
+) {
+    for _ in 0..CALLBACKS_PER_ITERATION {
+        black_box(pool.get(conn, remote, path));
+    }
+}
+
+fn count_allocations<F: FnMut()>(mut f: F) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn callback_proxy_benchmark(c: &mut Criterion) {
+    let conn: FakeConnection = Arc::new(());
+    let remote = BusName::new(":1.42").unwrap().into_static();
+    let path =
+        Path::new("/org/chromium/bluetooth/client/1/scanner_callback").unwrap().into_static();
+    let mut pool = ProxyPool::new(Duration::from_secs(2));
+
+    println!(
+        "allocations per {} callbacks: fresh = {}, pooled = {}",
+        CALLBACKS_PER_ITERATION,
+        count_allocations(|| fresh_proxies(&conn, &remote, &path)),
+        count_allocations(|| pooled_proxies(&mut pool, &conn, &remote, &path)),
+    );
+
+    c.bench_function("fresh_proxy_1k_callbacks", |b| {
+        b.iter(|| fresh_proxies(&conn, &remote, &path))
+    });
+    c.bench_function("pooled_proxy_1k_callbacks", |b| {
+        b.iter(|| pooled_proxies(&mut pool, &conn, &remote, &path))
+    });
+}
+
+criterion_group!(benches, callback_proxy_benchmark);
+criterion_main!(benches);
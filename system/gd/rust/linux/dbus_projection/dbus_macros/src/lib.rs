@@ -9,7 +9,10 @@ use std::path::Path;
 use syn::parse::Parser;
 use syn::punctuated::Punctuated;
 use syn::token::Comma;
-use syn::{Expr, FnArg, ImplItem, ItemImpl, ItemStruct, Meta, Pat, ReturnType, Type};
+use syn::{
+    Expr, FnArg, GenericArgument, ImplItem, ItemImpl, ItemStruct, Meta, Pat, PathArguments,
+    ReturnType, Type,
+};
 
 use crate::proc_macro::TokenStream;
 
@@ -31,6 +34,40 @@ fn debug_output_to_file(gen: &proc_macro2::TokenStream, filename: String) {
     file.write_all(gen.to_string().as_bytes()).unwrap();
 }
 
+/// Returns the `T` and `E` types if `ty` is a `Result<T, E>`.
+fn get_result_types(ty: &Type) -> Option<(Type, Type)> {
+    let type_path = if let Type::Path(type_path) = ty { type_path } else { return None };
+    let segment = type_path.path.segments.last()?;
+    if !segment.ident.to_string().eq("Result") {
+        return None;
+    }
+
+    let args = if let PathArguments::AngleBracketed(args) = &segment.arguments {
+        args
+    } else {
+        return None;
+    };
+
+    let types: Vec<Type> = args
+        .args
+        .iter()
+        .filter_map(|arg| if let GenericArgument::Type(t) = arg { Some(t.clone()) } else { None })
+        .collect();
+
+    match types.len() {
+        2 => Some((types[0].clone(), types[1].clone())),
+        _ => None,
+    }
+}
+
+fn is_unit_type(ty: &Type) -> bool {
+    if let Type::Tuple(tuple) = ty {
+        tuple.elems.is_empty()
+    } else {
+        false
+    }
+}
+
 /// Marks a method to be projected to a D-Bus method and specifies the D-Bus method name.
 #[proc_macro_attribute]
 pub fn dbus_method(_attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -48,7 +85,8 @@ pub fn dbus_method(_attr: TokenStream, item: TokenStream) -> TokenStream {
 ///   `#[generate_dbus_exporter(export_foo_dbus_obj, "org.example.FooInterface")]`
 ///
 /// This generates a method called `export_foo_dbus_obj` that will export a Rust object into a
-/// D-Bus object having interface `org.example.FooInterface`.
+/// D-Bus object having interface `org.example.FooInterface`. Nothing is exported if the interface
+/// is disabled by the `InterfacePolicy` given to the method.
 #[proc_macro_attribute]
 pub fn generate_dbus_exporter(attr: TokenStream, item: TokenStream) -> TokenStream {
     let ori_item: proc_macro2::TokenStream = item.clone().into();
@@ -136,17 +174,20 @@ pub fn generate_dbus_exporter(attr: TokenStream, item: TokenStream) -> TokenStre
                             let #ident = <#arg_type as DBusArg>::from_dbus(
                                 #dbus_input_arg,
                                 Some(conn_clone.clone()),
-                                Some(ctx.message().sender().unwrap().into_static()),
+                                ctx.message().sender().map(|sender| sender.into_static()),
                                 Some(dc_watcher_clone.clone()),
                             );
 
-                            if let Result::Err(e) = #ident {
-                                return Err(dbus_crossroads::MethodErr::invalid_arg(
-                                    e.to_string().as_str()
-                                ));
-                            }
-
-                            let #ident = #ident.unwrap();
+                            let #ident = match #ident {
+                                Ok(arg) => arg,
+                                Err(e) => {
+                                    let prefix = dbus_projection::DBUS_ERROR_PREFIX;
+                                    return Err(dbus_crossroads::MethodErr::from((
+                                        format!("{}InvalidArgument", prefix),
+                                        format!("{}: {}", #ident_string, e),
+                                    )));
+                                }
+                            };
                         };
                     }
                 }
@@ -160,9 +201,44 @@ pub fn generate_dbus_exporter(attr: TokenStream, item: TokenStream) -> TokenStre
             let mut output_type = quote! {};
             let mut ret = quote! {Ok(())};
             if let ReturnType::Type(_, t) = method.sig.output {
-                output_type = quote! {<#t as DBusArg>::DBusType,};
-                ret = quote! {Ok((<#t as DBusArg>::to_dbus(ret).unwrap(),))};
-                output_names = quote! { "out", };
+                if let Some((ok_type, err_type)) = get_result_types(&t) {
+                    // A `Result` return is projected as the `Ok` value or as a D-Bus error.
+                    let ok_ret = if is_unit_type(&ok_type) {
+                        quote! {Ok(())}
+                    } else {
+                        output_type = quote! {<#ok_type as DBusArg>::DBusType,};
+                        output_names = quote! { "out", };
+                        quote! {
+                            <#ok_type as DBusArg>::to_dbus(ret)
+                                .map(|ret| (ret,))
+                                .map_err(|e| dbus_crossroads::MethodErr::from((
+                                    format!("{}Failed", dbus_projection::DBUS_ERROR_PREFIX),
+                                    format!("return value: {}", e),
+                                )))
+                        }
+                    };
+
+                    ret = quote! {
+                        match ret {
+                            Ok(ret) => #ok_ret,
+                            Err(e) => {
+                                let (name, message) = <#err_type as DBusErrorArg>::to_dbus_error(&e);
+                                Err(dbus_crossroads::MethodErr::from((name, message)))
+                            }
+                        }
+                    };
+                } else {
+                    output_type = quote! {<#t as DBusArg>::DBusType,};
+                    ret = quote! {
+                        <#t as DBusArg>::to_dbus(ret)
+                            .map(|ret| (ret,))
+                            .map_err(|e| dbus_crossroads::MethodErr::from((
+                                format!("{}Failed", dbus_projection::DBUS_ERROR_PREFIX),
+                                format!("return value: {}", e),
+                            )))
+                    };
+                    output_names = quote! { "out", };
+                }
             }
 
             register_methods = quote! {
@@ -175,7 +251,17 @@ pub fn generate_dbus_exporter(attr: TokenStream, item: TokenStream) -> TokenStre
                                           #dbus_input_args |
                       -> Result<(#output_type), dbus_crossroads::MethodErr> {
                     #make_args
-                    let ret = obj.lock().unwrap().#method_name(#method_args);
+                    #[allow(unused_mut)]
+                    let mut obj = match obj.lock() {
+                        Ok(obj) => obj,
+                        Err(_) => {
+                            return Err(dbus_crossroads::MethodErr::from((
+                                format!("{}Failed", dbus_projection::DBUS_ERROR_PREFIX),
+                                String::from("the object is poisoned"),
+                            )));
+                        }
+                    };
+                    let ret = obj.#method_name(#method_args);
                     #ret
                 };
                 ibuilder.method(
@@ -197,7 +283,12 @@ pub fn generate_dbus_exporter(attr: TokenStream, item: TokenStream) -> TokenStre
             cr: &mut dbus_crossroads::Crossroads,
             obj: #obj_type,
             disconnect_watcher: std::sync::Arc<std::sync::Mutex<dbus_projection::DisconnectWatcher>>,
+            policy: &dbus_projection::InterfacePolicy,
         ) {
+            if !policy.is_enabled(#dbus_iface_name) {
+                return;
+            }
+
             fn get_iface_token<T: #api_iface_ident + Send + ?Sized>(
                 conn: std::sync::Arc<dbus::nonblock::SyncConnection>,
                 cr: &mut dbus_crossroads::Crossroads,
@@ -325,6 +416,11 @@ pub fn generate_dbus_interface_client(_attr: TokenStream, item: TokenStream) ->
                 (#input_list)
             };
 
+            let result_types = match &method.sig.output {
+                ReturnType::Type(_, t) => get_result_types(t),
+                ReturnType::Default => None,
+            };
+
             let body = match &method.sig.output {
                 // Build the method call to `self.client_proxy`. `method` or `method_noreturn`
                 // depends on whether there is a return from the function.
@@ -333,6 +429,49 @@ pub fn generate_dbus_interface_client(_attr: TokenStream, item: TokenStream) ->
                         self.client_proxy.method_noreturn(#dbus_method_name, #input_tuple)
                     }
                 }
+                // A `Result` return is built from either the reply or the D-Bus error.
+                _ if result_types.is_some() => {
+                    let (ok_type, err_type) = result_types.unwrap();
+                    let to_err = quote! {
+                        Err(<#err_type as DBusErrorArg>::from_dbus_error(
+                            e.name().unwrap_or(""),
+                            e.message().unwrap_or(""),
+                        ))
+                    };
+
+                    if is_unit_type(&ok_type) {
+                        quote! {
+                            match self.client_proxy.method_withresult_noreturn(
+                                #dbus_method_name,
+                                #input_tuple,
+                            ) {
+                                Ok(()) => Ok(()),
+                                Err(e) => #to_err,
+                            }
+                        }
+                    } else {
+                        quote! {
+                            let ret: Result<(<#ok_type as DBusArg>::DBusType,), dbus::Error> =
+                                self.client_proxy.method_withresult(
+                                    #dbus_method_name,
+                                    #input_tuple,
+                                );
+                            match ret {
+                                Ok((ret,)) => {
+                                    <#ok_type as DBusArg>::from_dbus(ret, None, None, None)
+                                        .map_err(|e| {
+                                            let prefix = dbus_projection::DBUS_ERROR_PREFIX;
+                                            <#err_type as DBusErrorArg>::from_dbus_error(
+                                                &format!("{}Failed", prefix),
+                                                &format!("return value: {}", e),
+                                            )
+                                        })
+                                }
+                                Err(e) => #to_err,
+                            }
+                        }
+                    }
+                }
                 _ => {
                     quote! {
                         let ret: #output_as_dbus_arg::DBusType = self.client_proxy.method(
@@ -399,7 +538,28 @@ fn copy_without_attributes(item: &TokenStream) -> TokenStream {
     gen.into()
 }
 
+/// Returns `T` if `ty` is `Option<T>`.
+fn option_inner_type(ty: &syn::TypePath) -> Option<Type> {
+    let segment = ty.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(inner) => Some(inner.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Generates a DBusArg implementation to transform Rust plain structs to a D-Bus data structure.
+///
+/// The fields marked with `#[dbus_optional]` may be missing from the dictionary, in which case
+/// they take their value from the `Default` of the struct. New fields are added this way so that
+/// the clients written before them keep working. An optional field of type `Option<T>` is left
+/// out of the dictionary when it is `None`, and is otherwise sent as a `T`.
 // TODO: Support more data types of struct fields (currently only supports integers and enums).
 #[proc_macro_attribute]
 pub fn dbus_propmap(attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -415,10 +575,12 @@ pub fn dbus_propmap(attr: TokenStream, item: TokenStream) -> TokenStream {
 
     let mut make_fields = quote! {};
     let mut field_idents = quote! {};
+    let mut set_optional_fields = quote! {};
 
     let mut insert_map_fields = quote! {};
     for field in ast.fields {
         let field_ident = field.ident;
+        let optional = field.attrs.iter().any(|attr| attr.path.is_ident("dbus_optional"));
 
         if field_ident.is_none() {
             continue;
@@ -431,9 +593,11 @@ pub fn dbus_propmap(attr: TokenStream, item: TokenStream) -> TokenStream {
         } else {
             continue;
         };
-
-        field_idents = quote! {
-            #field_idents #field_ident,
+        let option_type = if optional { option_inner_type(&field_type) } else { None };
+        let is_option = option_type.is_some();
+        let field_type = match option_type {
+            Some(inner) => quote! { #inner },
+            None => quote! { #field_type },
         };
 
         let field_type_name = format_ident! {"{}_type_", field_str};
@@ -447,38 +611,98 @@ pub fn dbus_propmap(attr: TokenStream, item: TokenStream) -> TokenStream {
                     )))));
                 }
             };
+            let #field_ident = match #field_ident.as_static_inner(0) {
+                Some(inner) => inner,
+                None => {
+                    return Err(Box::new(DBusArgError::new(String::from(format!(
+                        "{}.{} is an empty variant",
+                        #struct_str, #field_str
+                    )))));
+                }
+            };
             let #field_ident = <<#field_type as DBusArg>::DBusType as RefArgToRust>::ref_arg_to_rust(
-                #field_ident.as_static_inner(0).unwrap(),
+                #field_ident,
                 format!("{}.{}", #struct_str, #field_str),
             )?;
             type #field_type_name = #field_type;
-            let #field_ident = #field_type_name::from_dbus(
+            let #field_ident = match #field_type_name::from_dbus(
                 #field_ident,
                 conn__.clone(),
                 remote__.clone(),
                 disconnect_watcher__.clone(),
-            )?;
-        };
-
-        make_fields = quote! {
-            #make_fields
-
-            let #field_ident = match data__.get(#field_str) {
-                Some(data) => data,
-                None => {
+            ) {
+                Ok(field) => field,
+                Err(e) => {
                     return Err(Box::new(DBusArgError::new(String::from(format!(
-                        "{}.{} is required",
-                        #struct_str, #field_str
+                        "{}.{}: {}",
+                        #struct_str, #field_str, e
                     )))));
                 }
             };
-            #make_field
         };
 
-        insert_map_fields = quote! {
-            #insert_map_fields
-            let field_data__ = DBusArg::to_dbus(data__.#field_ident)?;
-            map__.insert(String::from(#field_str), dbus::arg::Variant(Box::new(field_data__)));
+        if optional {
+            make_fields = quote! {
+                #make_fields
+
+                let #field_ident = match data__.get(#field_str) {
+                    Some(#field_ident) => {
+                        #make_field
+                        Some(#field_ident)
+                    }
+                    None => None,
+                };
+            };
+
+            let field_value = if is_option {
+                quote! { Some(field__) }
+            } else {
+                quote! { field__ }
+            };
+            set_optional_fields = quote! {
+                #set_optional_fields
+                if let Some(field__) = #field_ident {
+                    result__.#field_ident = #field_value;
+                }
+            };
+        } else {
+            make_fields = quote! {
+                #make_fields
+
+                let #field_ident = match data__.get(#field_str) {
+                    Some(data) => data,
+                    None => {
+                        return Err(Box::new(DBusArgError::new(String::from(format!(
+                            "{}.{} is required",
+                            #struct_str, #field_str
+                        )))));
+                    }
+                };
+                #make_field
+            };
+
+            field_idents = quote! {
+                #field_idents #field_ident,
+            };
+        }
+
+        insert_map_fields = if is_option {
+            quote! {
+                #insert_map_fields
+                if let Some(field__) = data__.#field_ident {
+                    let field_data__ = DBusArg::to_dbus(field__)?;
+                    map__.insert(
+                        String::from(#field_str),
+                        dbus::arg::Variant(Box::new(field_data__)),
+                    );
+                }
+            }
+        } else {
+            quote! {
+                #insert_map_fields
+                let field_data__ = DBusArg::to_dbus(data__.#field_ident)?;
+                map__.insert(String::from(#field_str), dbus::arg::Variant(Box::new(field_data__)));
+            }
         };
     }
 
@@ -497,10 +721,13 @@ pub fn dbus_propmap(attr: TokenStream, item: TokenStream) -> TokenStream {
             ) -> Result<#struct_ident, Box<dyn std::error::Error>> {
                 #make_fields
 
-                return Ok(#struct_ident {
+                #[allow(unused_mut)]
+                let mut result__ = #struct_ident {
                     #field_idents
                     ..Default::default()
-                });
+                };
+                #set_optional_fields
+                return Ok(result__);
             }
 
             fn to_dbus(data__: #struct_ident) -> Result<dbus::arg::PropMap, Box<dyn std::error::Error>> {
@@ -579,7 +806,7 @@ pub fn dbus_proxy_obj(attr: TokenStream, item: TokenStream) -> TokenStream {
                         let ident = pat_ident.ident.clone();
 
                         method_args = quote! {
-                            #method_args DBusArg::to_dbus(#ident).unwrap(),
+                            #method_args DBusArg::to_dbus(#ident)?,
                         };
                     }
                 }
@@ -589,20 +816,22 @@ pub fn dbus_proxy_obj(attr: TokenStream, item: TokenStream) -> TokenStream {
                 #method_impls
                 #[allow(unused_variables)]
                 #method_sig {
-                    let remote__ = self.remote.clone();
-                    let objpath__ = self.objpath.clone();
-                    let conn__ = self.conn.clone();
+                    let args = (|| -> Result<_, Box<dyn std::error::Error>> {
+                        Ok((#method_args))
+                    })();
+                    let args = match args {
+                        Ok(args) => args,
+                        Err(e) => {
+                            log::warn!("Not calling {}: {}", #dbus_method_name, e);
+                            return;
+                        }
+                    };
+                    let proxy = self.proxy.clone();
                     tokio::spawn(async move {
-                        let proxy = dbus::nonblock::Proxy::new(
-                            remote__,
-                            objpath__,
-                            std::time::Duration::from_secs(2),
-                            conn__,
-                        );
                         let future: dbus::nonblock::MethodReply<()> = proxy.method_call(
                             #dbus_iface_name,
                             #dbus_method_name,
-                            (#method_args),
+                            args,
                         );
                         let _result = future.await;
                     });
@@ -624,9 +853,9 @@ pub fn dbus_proxy_obj(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
 
         struct #struct_ident {
-            conn: std::sync::Arc<dbus::nonblock::SyncConnection>,
             remote: dbus::strings::BusName<'static>,
             objpath: Path<'static>,
+            proxy: std::sync::Arc<dbus::nonblock::Proxy<'static, std::sync::Arc<dbus::nonblock::SyncConnection>>>,
             disconnect_watcher: std::sync::Arc<std::sync::Mutex<DisconnectWatcher>>,
         }
 
@@ -643,6 +872,10 @@ pub fn dbus_proxy_obj(attr: TokenStream, item: TokenStream) -> TokenStream {
                 self.objpath.to_string().clone()
             }
 
+            fn get_remote_id(&self) -> String {
+                self.remote.to_string()
+            }
+
             fn unregister(&mut self, id: u32) -> bool {
                 self.disconnect_watcher.lock().unwrap().remove(self.remote.clone(), id)
             }
@@ -658,11 +891,28 @@ pub fn dbus_proxy_obj(attr: TokenStream, item: TokenStream) -> TokenStream {
                 remote__: Option<dbus::strings::BusName<'static>>,
                 disconnect_watcher__: Option<std::sync::Arc<std::sync::Mutex<DisconnectWatcher>>>,
             ) -> Result<Box<dyn #trait_ + Send>, Box<dyn std::error::Error>> {
+                let (conn__, remote__, disconnect_watcher__) =
+                    match (conn__, remote__, disconnect_watcher__) {
+                        (Some(conn), Some(remote), Some(watcher)) => (conn, remote, watcher),
+                        _ => {
+                            return Err(Box::new(DBusArgError::new(String::from(
+                                "the callback object has no sender",
+                            ))));
+                        }
+                    };
+                let proxy = match disconnect_watcher__.lock() {
+                    Ok(mut watcher) => watcher.get_proxy(&conn__, &remote__, &objpath__),
+                    Err(_) => {
+                        return Err(Box::new(DBusArgError::new(String::from(
+                            "the disconnect watcher is poisoned",
+                        ))));
+                    }
+                };
                 Ok(Box::new(#struct_ident {
-                    conn: conn__.unwrap(),
-                    remote: remote__.unwrap(),
+                    remote: remote__,
                     objpath: objpath__,
-                    disconnect_watcher: disconnect_watcher__.unwrap(),
+                    proxy,
+                    disconnect_watcher: disconnect_watcher__,
                 }))
             }
 
@@ -738,8 +988,10 @@ pub fn generate_dbus_arg(_item: TokenStream) -> TokenStream {
                         arg.arg_type().as_str(),
                     )))));
                 }
-                let arg = (*any.downcast_ref::<<Self as DBusArg>::DBusType>().unwrap()).clone();
-                return Ok(arg);
+                match any.downcast_ref::<<Self as DBusArg>::DBusType>() {
+                    Some(arg) => Ok(arg.clone()),
+                    None => Err(Box::new(DBusArgError::new(format!("{} cannot be read", name)))),
+                }
             }
         }
 
@@ -759,14 +1011,18 @@ pub fn generate_dbus_arg(_item: TokenStream) -> TokenStream {
                     }
                     Some(item) => item,
                 };
-                let mut key = iter.next();
-                let mut val = iter.next();
-                while !key.is_none() && !val.is_none() {
-                    let k = key.unwrap().as_str().unwrap().to_string();
-                    let v = dbus::arg::Variant(val.unwrap().box_clone());
+                while let (Some(key), Some(val)) = (iter.next(), iter.next()) {
+                    let k = match key.as_str() {
+                        Some(k) => k.to_string(),
+                        None => {
+                            return Err(Box::new(DBusArgError::new(String::from(format!(
+                                "{} key is not a string",
+                                name,
+                            )))))
+                        }
+                    };
+                    let v = dbus::arg::Variant(val.box_clone());
                     map.insert(k, v);
-                    key = iter.next();
-                    val = iter.next();
                 }
                 return Ok(map);
             }
@@ -778,21 +1034,73 @@ pub fn generate_dbus_arg(_item: TokenStream) -> TokenStream {
             type RustType = Vec<T>;
             fn ref_arg_to_rust(
                 arg: &(dyn dbus::arg::RefArg + 'static),
-                _name: String,
+                name: String,
             ) -> Result<Self::RustType, Box<dyn Error>> {
                 let mut vec: Vec<T> = vec![];
-                let mut iter = arg.as_iter().unwrap();
-                let mut val = iter.next();
-                while !val.is_none() {
-                    let arg = val.unwrap().box_clone();
-                    let arg = <T as RefArgToRust>::ref_arg_to_rust(&arg, _name.clone() + " element")?;
+                let iter = match arg.as_iter() {
+                    None => {
+                        return Err(Box::new(DBusArgError::new(String::from(format!(
+                            "{} is not iterable",
+                            name,
+                        )))))
+                    }
+                    Some(item) => item,
+                };
+                for val in iter {
+                    let arg = val.box_clone();
+                    let arg = <T as RefArgToRust>::ref_arg_to_rust(&arg, name.clone() + " element")?;
                     vec.push(arg);
-                    val = iter.next();
                 }
                 return Ok(vec);
             }
         }
 
+        // A dictionary is convertible from RefArg to Rust's HashMap, if its keys and values are
+        // also convertible themselves recursively.
+        impl<K, V> RefArgToRust for std::collections::HashMap<K, V>
+        where
+            K: 'static + Eq + std::hash::Hash + RefArgToRust<RustType = K>,
+            V: 'static + RefArgToRust<RustType = V>,
+        {
+            type RustType = std::collections::HashMap<K, V>;
+            fn ref_arg_to_rust(
+                arg: &(dyn dbus::arg::RefArg + 'static),
+                name: String,
+            ) -> Result<Self::RustType, Box<dyn Error>> {
+                let mut map: std::collections::HashMap<K, V> = std::collections::HashMap::new();
+                let mut iter = match arg.as_iter() {
+                    None => {
+                        return Err(Box::new(DBusArgError::new(String::from(format!(
+                            "{} is not iterable",
+                            name,
+                        )))))
+                    }
+                    Some(item) => item,
+                };
+                while let (Some(key), Some(val)) = (iter.next(), iter.next()) {
+                    let k = <K as RefArgToRust>::ref_arg_to_rust(
+                        &key.box_clone(),
+                        name.clone() + " key",
+                    )?;
+                    let v = <V as RefArgToRust>::ref_arg_to_rust(
+                        &val.box_clone(),
+                        name.clone() + " value",
+                    )?;
+                    map.insert(k, v);
+                }
+                return Ok(map);
+            }
+        }
+
+        /// Converts between an error type returned by projected methods and a D-Bus error,
+        /// represented by its name and message.
+        #[allow(dead_code)]
+        pub(crate) trait DBusErrorArg: Sized {
+            fn to_dbus_error(err: &Self) -> (String, String);
+
+            fn from_dbus_error(name: &str, message: &str) -> Self;
+        }
+
         pub(crate) trait DBusArg {
             type DBusType;
 
@@ -866,6 +1174,56 @@ pub fn generate_dbus_arg(_item: TokenStream) -> TokenStream {
                 Ok(list)
             }
         }
+
+        // Keys are basic D-Bus types, or types represented by one such as UUIDs. Keys which are
+        // different forms of the same value are rejected, rather than one of them being dropped.
+        impl<K, V> DBusArg for std::collections::HashMap<K, V>
+        where
+            K: DBusArg + Eq + std::hash::Hash,
+            K::DBusType: Eq + std::hash::Hash,
+            V: DBusArg,
+        {
+            type DBusType = std::collections::HashMap<K::DBusType, V::DBusType>;
+
+            fn from_dbus(
+                data: std::collections::HashMap<K::DBusType, V::DBusType>,
+                conn: Option<Arc<dbus::nonblock::SyncConnection>>,
+                remote: Option<BusName<'static>>,
+                disconnect_watcher: Option<Arc<Mutex<DisconnectWatcher>>>,
+            ) -> Result<std::collections::HashMap<K, V>, Box<dyn Error>> {
+                let mut map = std::collections::HashMap::new();
+                for (key, value) in data {
+                    let k = K::from_dbus(
+                        key,
+                        conn.clone(),
+                        remote.clone(),
+                        disconnect_watcher.clone(),
+                    )?;
+                    let v = V::from_dbus(
+                        value,
+                        conn.clone(),
+                        remote.clone(),
+                        disconnect_watcher.clone(),
+                    )?;
+                    if map.insert(k, v).is_some() {
+                        return Err(Box::new(DBusArgError::new(String::from(
+                            "Dictionary has several forms of the same key",
+                        ))));
+                    }
+                }
+                Ok(map)
+            }
+
+            fn to_dbus(
+                data: std::collections::HashMap<K, V>,
+            ) -> Result<std::collections::HashMap<K::DBusType, V::DBusType>, Box<dyn Error>> {
+                let mut map = std::collections::HashMap::new();
+                for (key, value) in data {
+                    map.insert(K::to_dbus(key)?, V::to_dbus(value)?);
+                }
+                Ok(map)
+            }
+        }
     };
 
     debug_output_to_file(&gen, format!("out-generate_dbus_arg.rs"));
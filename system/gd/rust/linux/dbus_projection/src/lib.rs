@@ -43,15 +43,101 @@
 //!   [`generate_dbus_exporter`](dbus_macros::generate_dbus_exporter) like in
 //!   [here](https://android.googlesource.com/platform/packages/modules/Bluetooth/+/refs/heads/master/system/gd/rust/linux/mgmt/src/bin/btmanagerd/main.rs)
 //!   passing in the object path, D-Bus connection, Crossroads object, the Rust object to be
-//!   projected, and a [`DisconnectWatcher`](DisconnectWatcher) object.
+//!   projected, a [`DisconnectWatcher`](DisconnectWatcher) object, and the
+//!   [`InterfacePolicy`](InterfacePolicy) deciding whether the interface is exported at all.
 
 use dbus::channel::MatchingReceiver;
 use dbus::message::MatchRule;
-use dbus::nonblock::SyncConnection;
-use dbus::strings::BusName;
+use dbus::nonblock::{Proxy, SyncConnection};
+use dbus::strings::{BusName, Path};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Prefix of the names of the D-Bus errors replied by projected methods. The name is completed
+/// with the error category, `InvalidArgument` when an argument cannot be converted and `Failed`
+/// when the method cannot be carried out otherwise.
+pub const DBUS_ERROR_PREFIX: &str = "org.chromium.bluetooth.Error.";
+
+/// Timeout of the method calls made on remote callback objects.
+const CALLBACK_PROXY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A cache of proxies to remote D-Bus objects, keyed by bus name and object path.
+///
+/// Callback objects are invoked very often, so building a new `Proxy` (and cloning its bus name
+/// and object path) for each invocation is wasteful. The pool hands out shared proxies instead.
+/// Entries are only dropped when the owning client disconnects, see `DisconnectWatcher`.
+pub struct ProxyPool<C> {
+    timeout: Duration,
+    proxies: HashMap<BusName<'static>, HashMap<Path<'static>, Arc<Proxy<'static, C>>>>,
+}
+
+impl<C: Clone> ProxyPool<C> {
+    /// Creates an empty pool whose proxies use the given method call timeout.
+    pub fn new(timeout: Duration) -> ProxyPool<C> {
+        ProxyPool { timeout, proxies: HashMap::new() }
+    }
+
+    /// Returns the proxy to `path` at `remote`, creating it if it is not in the pool yet.
+    pub fn get(
+        &mut self,
+        conn: &C,
+        remote: &BusName<'static>,
+        path: &Path<'static>,
+    ) -> Arc<Proxy<'static, C>> {
+        // Look up with borrowed keys first so that a hit does not allocate.
+        if let Some(proxy) = self.proxies.get(remote).and_then(|paths| paths.get(path)) {
+            return proxy.clone();
+        }
+
+        let proxy = Arc::new(Proxy::new(remote.clone(), path.clone(), self.timeout, conn.clone()));
+        self.proxies
+            .entry(remote.clone())
+            .or_insert_with(HashMap::new)
+            .insert(path.clone(), proxy.clone());
+        proxy
+    }
+
+    /// Drops all the proxies to objects owned by `remote`.
+    pub fn remove_remote(&mut self, remote: &BusName<'static>) {
+        self.proxies.remove(remote);
+    }
+
+    /// Returns the number of proxies in the pool.
+    pub fn len(&self) -> usize {
+        self.proxies.values().map(|paths| paths.len()).sum()
+    }
+}
+
+/// Decides which interfaces the functions generated by
+/// [`generate_dbus_exporter`](dbus_macros::generate_dbus_exporter) export, so that a daemon can hide
+/// whole interfaces depending on its configuration rather than on how it is built.
+///
+/// All the interfaces are exported by default.
+#[derive(Clone, Debug, Default)]
+pub struct InterfacePolicy {
+    disabled: HashSet<String>,
+}
+
+impl InterfacePolicy {
+    /// Stops the interface `name` from being exported.
+    pub fn disable<S: Into<String>>(&mut self, name: S) {
+        self.disabled.insert(name.into());
+    }
+
+    /// Returns whether the interface `name` is exported.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.disabled.contains(name)
+    }
+
+    /// Returns the names of the interfaces which are not exported.
+    pub fn disabled(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.disabled.iter().cloned().collect();
+        names.sort();
+        names
+    }
+}
 
 /// A D-Bus "NameOwnerChanged" handler that continuously monitors client disconnects.
 ///
@@ -63,12 +149,19 @@ pub struct DisconnectWatcher {
 
     /// Map of disconnect callbacks by bus address and callback id.
     callbacks: Arc<Mutex<HashMap<BusName<'static>, HashMap<u32, Box<dyn Fn(u32) + Send>>>>>,
+
+    /// Proxies to the callback objects of the watched clients, invalidated on disconnect.
+    proxies: Arc<Mutex<ProxyPool<Arc<SyncConnection>>>>,
 }
 
 impl DisconnectWatcher {
     /// Creates a new DisconnectWatcher with empty callbacks.
     pub fn new() -> DisconnectWatcher {
-        DisconnectWatcher { next_id: 0, callbacks: Arc::new(Mutex::new(HashMap::new())) }
+        DisconnectWatcher {
+            next_id: 0,
+            callbacks: Arc::new(Mutex::new(HashMap::new())),
+            proxies: Arc::new(Mutex::new(ProxyPool::new(CALLBACK_PROXY_TIMEOUT))),
+        }
     }
 
     /// Get the next unique id for this watcher.
@@ -91,12 +184,25 @@ impl DisconnectWatcher {
         return id;
     }
 
+    /// Returns a shared proxy to the object `path` of the client `remote`.
+    ///
+    /// The proxy is kept until the client disconnects.
+    pub fn get_proxy(
+        &self,
+        conn: &Arc<SyncConnection>,
+        remote: &BusName<'static>,
+        path: &Path<'static>,
+    ) -> Arc<Proxy<'static, Arc<SyncConnection>>> {
+        self.proxies.lock().unwrap().get(conn, remote, path)
+    }
+
     /// Sets up the D-Bus handler that monitors client disconnects.
     pub async fn setup_watch(&mut self, conn: Arc<SyncConnection>) {
         let mr = MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged");
 
         conn.add_match_no_cb(&mr.match_str()).await.unwrap();
         let callbacks_map = self.callbacks.clone();
+        let proxies = self.proxies.clone();
         conn.start_receive(
             mr,
             Box::new(move |msg, _conn| {
@@ -116,6 +222,8 @@ impl DisconnectWatcher {
                 // disconnected. So call the registered callbacks to be notified of this client
                 // disconnect.
                 let addr = BusName::new(addr.unwrap()).unwrap().into_static();
+                proxies.lock().unwrap().remove_remote(&addr);
+
                 if !callbacks_map.lock().unwrap().contains_key(&addr) {
                     return true;
                 }
@@ -225,6 +333,38 @@ macro_rules! impl_dbus_arg_from_into {
         }
     };
 }
+/// Implements `DBusArg` for a type represented as a string in D-Bus, which is parsed by the
+/// `from_string` of the type and formatted by its `Display`.
+#[macro_export]
+macro_rules! impl_dbus_arg_string {
+    ($rust_type:ty) => {
+        impl DBusArg for $rust_type {
+            type DBusType = String;
+            fn from_dbus(
+                data: String,
+                _conn: Option<std::sync::Arc<dbus::nonblock::SyncConnection>>,
+                _remote: Option<dbus::strings::BusName<'static>>,
+                _disconnect_watcher: Option<
+                    std::sync::Arc<std::sync::Mutex<dbus_projection::DisconnectWatcher>>,
+                >,
+            ) -> Result<$rust_type, Box<dyn std::error::Error>> {
+                match <$rust_type>::from_string(&data) {
+                    Some(x) => Ok(x),
+                    None => Err(Box::new(DBusArgError::new(String::from(format!(
+                        "error converting '{}' to {}",
+                        data,
+                        stringify!($rust_type)
+                    ))))),
+                }
+            }
+
+            fn to_dbus(data: $rust_type) -> Result<String, Box<dyn std::error::Error>> {
+                return Ok(data.to_string());
+            }
+        }
+    };
+}
+
 /// Marks a function to be implemented by dbus_projection macros.
 #[macro_export]
 macro_rules! dbus_generated {
@@ -1,12 +1,32 @@
 use core::any::Any;
 
 use dbus_macros::{dbus_propmap, generate_dbus_arg};
+use dbus_projection::impl_dbus_arg_string;
 
 use dbus::arg::{Arg, ArgType, IterAppend, RefArg};
 use dbus::Signature;
 
 generate_dbus_arg!();
 
+#[derive(Debug, PartialEq)]
+struct Handle {
+    value: u16,
+}
+
+impl Handle {
+    fn from_string(raw: &str) -> Option<Handle> {
+        Some(Handle { value: u16::from_str_radix(raw.strip_prefix("0x")?, 16).ok()? })
+    }
+}
+
+impl fmt::Display for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#06x}", self.value)
+    }
+}
+
+impl_dbus_arg_string!(Handle);
+
 #[derive(Debug, Default, Clone, PartialEq)]
 struct OtherStruct {
     address: String,
@@ -37,6 +57,28 @@ struct SomeStructDBus {
     recursive: Vec<SomeStruct>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+struct ExtendedStruct {
+    name: String,
+    retries: i32,
+    backoff: Option<u32>,
+}
+
+impl Default for ExtendedStruct {
+    fn default() -> Self {
+        ExtendedStruct { name: String::from(""), retries: 3, backoff: None }
+    }
+}
+
+#[dbus_propmap(ExtendedStruct)]
+struct ExtendedStructDBus {
+    name: String,
+    #[dbus_optional]
+    retries: i32,
+    #[dbus_optional]
+    backoff: Option<u32>,
+}
+
 // Pretends to be a D-Bus dictionary.
 #[derive(Debug)]
 struct FakeDictionary {
@@ -96,6 +138,29 @@ mod tests {
         assert_eq!("Some Variable is not iterable", result.unwrap_err().to_string());
     }
 
+    #[test]
+    fn test_dbus_propmap_malformed() {
+        let data_dbus = FakeDictionary {
+            items: vec![(String::from("address"), Box::new(vec![1 as u8, 2, 3]))],
+        };
+        let result = <dbus::arg::PropMap as RefArgToRust>::ref_arg_to_rust(
+            &data_dbus,
+            String::from("Some Variable"),
+        )
+        .unwrap();
+        let result = <OtherStruct as DBusArg>::from_dbus(result, None, None, None);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("OtherStruct.address type does not match"));
+
+        let result = <Vec<String> as RefArgToRust>::ref_arg_to_rust(
+            &String::from("some data"),
+            String::from("Some Vector"),
+        );
+        assert_eq!("Some Vector is not iterable", result.unwrap_err().to_string());
+    }
+
     #[test]
     fn test_dbus_propmap_success() {
         let data_dbus = FakeDictionary {
@@ -173,4 +238,64 @@ mod tests {
         };
         assert_eq!(expected_struct, result_struct);
     }
+
+    #[test]
+    fn test_dbus_propmap_optional_field() {
+        let from_items = |items: Vec<(String, Box<dyn RefArg>)>| {
+            let data = <dbus::arg::PropMap as RefArgToRust>::ref_arg_to_rust(
+                &FakeDictionary { items },
+                String::from("Some Variable"),
+            )
+            .unwrap();
+            <ExtendedStruct as DBusArg>::from_dbus(data, None, None, None)
+        };
+
+        // A missing optional field takes the default of the struct.
+        let result = from_items(vec![(String::from("name"), Box::new(String::from("foo")))]);
+        assert_eq!(
+            ExtendedStruct { name: String::from("foo"), retries: 3, backoff: None },
+            result.unwrap()
+        );
+
+        let result = from_items(vec![
+            (String::from("name"), Box::new(String::from("foo"))),
+            (String::from("retries"), Box::new(5)),
+            (String::from("backoff"), Box::new(7u32)),
+        ]);
+        assert_eq!(
+            ExtendedStruct { name: String::from("foo"), retries: 5, backoff: Some(7) },
+            result.unwrap()
+        );
+
+        // The optional fields are still checked when present.
+        let result = from_items(vec![
+            (String::from("name"), Box::new(String::from("foo"))),
+            (String::from("retries"), Box::new(String::from("5"))),
+        ]);
+        assert!(result.unwrap_err().to_string().starts_with("ExtendedStruct.retries"));
+
+        let result = from_items(vec![(String::from("retries"), Box::new(5))]);
+        assert_eq!("ExtendedStruct.name is required", result.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_dbus_propmap_option_field() {
+        let data = ExtendedStruct { name: String::from("foo"), retries: 3, backoff: None };
+        let map = <ExtendedStruct as DBusArg>::to_dbus(data).unwrap();
+        assert!(!map.contains_key("backoff"));
+
+        let data = ExtendedStruct { name: String::from("foo"), retries: 3, backoff: Some(7) };
+        let map = <ExtendedStruct as DBusArg>::to_dbus(data).unwrap();
+        assert_eq!(Some(7), map["backoff"].0.as_u64());
+    }
+
+    #[test]
+    fn test_dbus_arg_string() {
+        let handle = <Handle as DBusArg>::from_dbus(String::from("0x002a"), None, None, None);
+        assert_eq!(Handle { value: 42 }, handle.unwrap());
+        assert_eq!("0x002a", <Handle as DBusArg>::to_dbus(Handle { value: 42 }).unwrap());
+
+        let result = <Handle as DBusArg>::from_dbus(String::from("42"), None, None, None);
+        assert_eq!("error converting '42' to Handle", result.unwrap_err().to_string());
+    }
 }
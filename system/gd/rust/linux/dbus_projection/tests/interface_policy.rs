@@ -0,0 +1,14 @@
+use dbus_projection::InterfacePolicy;
+
+#[test]
+fn test_interface_policy() {
+    let mut policy = InterfacePolicy::default();
+    assert!(policy.is_enabled("org.example.Foo"));
+
+    policy.disable("org.example.Foo");
+    policy.disable("org.example.Bar");
+    policy.disable("org.example.Foo");
+    assert!(!policy.is_enabled("org.example.Foo"));
+    assert!(policy.is_enabled("org.example.Baz"));
+    assert_eq!(policy.disabled(), vec!["org.example.Bar", "org.example.Foo"]);
+}
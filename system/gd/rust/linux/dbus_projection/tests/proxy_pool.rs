@@ -0,0 +1,25 @@
+use dbus::strings::{BusName, Path};
+use dbus_projection::ProxyPool;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn test_proxy_pool_reuse_and_invalidation() {
+    let conn = Arc::new(());
+    let client1 = BusName::new(":1.1").unwrap().into_static();
+    let client2 = BusName::new(":1.2").unwrap().into_static();
+    let path1 = Path::new("/callback/1").unwrap().into_static();
+    let path2 = Path::new("/callback/2").unwrap().into_static();
+    let mut pool = ProxyPool::new(Duration::from_secs(2));
+
+    let proxy = pool.get(&conn, &client1, &path1);
+    assert!(Arc::ptr_eq(&proxy, &pool.get(&conn, &client1, &path1)));
+    assert!(!Arc::ptr_eq(&proxy, &pool.get(&conn, &client1, &path2)));
+    assert!(!Arc::ptr_eq(&proxy, &pool.get(&conn, &client2, &path1)));
+    assert_eq!(3, pool.len());
+
+    pool.remove_remote(&client1);
+    assert_eq!(1, pool.len());
+    assert!(!Arc::ptr_eq(&proxy, &pool.get(&conn, &client1, &path1)));
+}
@@ -8,7 +8,7 @@ use crate::bluetooth_manager::BluetoothManager;
 use dbus::channel::MatchingReceiver;
 use dbus::message::MatchRule;
 use dbus_crossroads::Crossroads;
-use dbus_projection::DisconnectWatcher;
+use dbus_projection::{DisconnectWatcher, InterfacePolicy};
 use dbus_tokio::connection;
 use log::LevelFilter;
 use std::sync::atomic::AtomicBool;
@@ -101,6 +101,7 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
         &mut cr,
         bluetooth_manager.clone(),
         disconnect_watcher.clone(),
+        &InterfacePolicy::default(),
     );
 
     // We add the Crossroads instance to the connection so that incoming method calls will be handled.
@@ -1,6 +1,33 @@
 use pkg_config::Config;
 
+/// Generates the protobuf messages of the unix domain socket frontend, and a mod.rs declaring
+/// their module.
+#[cfg(feature = "uds")]
+fn generate_uds_protos() {
+    use std::io::Write;
+
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("proto_out");
+    if let Err(e) = std::fs::create_dir(&out_dir) {
+        println!("Proto dir failed to be created: {}", e);
+    }
+
+    protoc_rust::Codegen::new()
+        .out_dir(out_dir.to_str().unwrap())
+        .inputs(&["proto/bluetooth_ipc.proto"])
+        .includes(&["proto"])
+        .run()
+        .expect("protoc");
+
+    let mut f = std::fs::File::create(out_dir.join("mod.rs")).unwrap();
+    f.write_all(b"// Generated by build.rs\n\npub mod bluetooth_ipc;\n").unwrap();
+
+    println!("cargo:rerun-if-changed=proto/bluetooth_ipc.proto");
+}
+
 fn main() {
+    #[cfg(feature = "uds")]
+    generate_uds_protos();
+
     let target_dir = std::env::var_os("CARGO_TARGET_DIR").unwrap();
 
     // The main linking point with c++ code is the libbluetooth-static.a
@@ -0,0 +1,77 @@
+//! Callbacks of the advertising sets saved by the previous daemon, found again on the bus for the
+//! clients still connected to it.
+
+use btstack::bluetooth_adv::{IAdvertisingCallbackRestorer, IAdvertisingSetCallback};
+use dbus::blocking::SyncConnection as BlockingConnection;
+use dbus::nonblock::SyncConnection;
+use dbus::strings::{BusName, Path};
+use dbus_projection::DisconnectWatcher;
+use log::warn;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::dbus_arg::DBusArg;
+
+/// Time given to the bus to tell whether a client is still connected.
+const BUS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Finds the callbacks of the saved advertising sets on the bus.
+pub struct DBusAdvertisingCallbackRestorer {
+    bus: BlockingConnection,
+    conn: Arc<SyncConnection>,
+    disconnect_watcher: Arc<Mutex<DisconnectWatcher>>,
+}
+
+impl DBusAdvertisingCallbackRestorer {
+    pub fn new(
+        conn: Arc<SyncConnection>,
+        disconnect_watcher: Arc<Mutex<DisconnectWatcher>>,
+    ) -> Result<DBusAdvertisingCallbackRestorer, dbus::Error> {
+        Ok(DBusAdvertisingCallbackRestorer {
+            bus: BlockingConnection::new_system()?,
+            conn,
+            disconnect_watcher,
+        })
+    }
+
+    fn is_connected(&self, remote_id: &str) -> bool {
+        let proxy =
+            self.bus.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", BUS_TIMEOUT);
+        match proxy.method_call("org.freedesktop.DBus", "NameHasOwner", (remote_id,)) {
+            Ok((connected,)) => connected,
+            Err(e) => {
+                warn!("Failed to tell whether {} is connected: {}", remote_id, e);
+                false
+            }
+        }
+    }
+}
+
+impl IAdvertisingCallbackRestorer for DBusAdvertisingCallbackRestorer {
+    fn restore_callback(
+        &self,
+        remote_id: &String,
+        object_id: &String,
+    ) -> Option<Box<dyn IAdvertisingSetCallback + Send>> {
+        // A unique bus name is never reused, so a client found connected is the one which saved
+        // the set.
+        if !self.is_connected(remote_id) {
+            return None;
+        }
+
+        let remote = BusName::new(remote_id.clone()).ok()?;
+        let path = Path::new(object_id.clone()).ok()?;
+        match <Box<dyn IAdvertisingSetCallback + Send>>::from_dbus(
+            path,
+            Some(self.conn.clone()),
+            Some(remote),
+            Some(self.disconnect_watcher.clone()),
+        ) {
+            Ok(callback) => Some(callback),
+            Err(e) => {
+                warn!("Failed to restore the advertising callback of {}: {}", remote_id, e);
+                None
+            }
+        }
+    }
+}
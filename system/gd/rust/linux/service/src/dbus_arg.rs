@@ -1,3 +1,21 @@
+use btstack::address::BtAddress;
+use btstack::error::BtError;
+use btstack::uuid::BtUuid;
 use dbus_macros::generate_dbus_arg;
+use dbus_projection::{impl_dbus_arg_string, DBUS_ERROR_PREFIX};
 
 generate_dbus_arg!();
+
+impl_dbus_arg_string!(BtAddress);
+impl_dbus_arg_string!(BtUuid);
+
+// Represents BtError as a D-Bus error named after its category. The message carries the sub-code.
+impl DBusErrorArg for BtError {
+    fn to_dbus_error(err: &BtError) -> (String, String) {
+        err.to_named_error(DBUS_ERROR_PREFIX)
+    }
+
+    fn from_dbus_error(name: &str, message: &str) -> BtError {
+        BtError::from_named_error(DBUS_ERROR_PREFIX, name, message)
+    }
+}
@@ -0,0 +1,64 @@
+use btstack::battery_manager::{
+    BatteryInfo, BatterySource, IBatteryManager, IBatteryManagerCallback,
+};
+use btstack::error::BtError;
+use btstack::RPCProxy;
+
+use dbus::arg::RefArg;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_propmap, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, impl_dbus_arg_enum, DisconnectWatcher};
+
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg, RefArgToRust};
+
+impl_dbus_arg_enum!(BatterySource);
+
+#[dbus_propmap(BatteryInfo)]
+pub struct BatteryInfoDBus {
+    address: String,
+    percentage: u32,
+    source: BatterySource,
+}
+
+#[allow(dead_code)]
+struct IBatteryManagerDBus {}
+
+#[generate_dbus_exporter(export_battery_manager_dbus_obj, "org.chromium.bluetooth.BatteryManager")]
+impl IBatteryManager for IBatteryManagerDBus {
+    #[dbus_method("RegisterBatteryCallback")]
+    fn register_battery_callback(
+        &mut self,
+        callback: Box<dyn IBatteryManagerCallback + Send>,
+    ) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterBatteryCallback")]
+    fn unregister_battery_callback(&mut self, callback_id: u32) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetBatteryInformation")]
+    fn get_battery_information(&self, addr: String) -> Result<BatteryInfo, BtError> {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct BatteryManagerCallbackDBus {}
+
+#[dbus_proxy_obj(BatteryManagerCallback, "org.chromium.bluetooth.BatteryManagerCallback")]
+impl IBatteryManagerCallback for BatteryManagerCallbackDBus {
+    #[dbus_method("OnBatteryInfoUpdated")]
+    fn on_battery_info_updated(&self, addr: String, battery_info: BatteryInfo) {
+        dbus_generated!()
+    }
+}
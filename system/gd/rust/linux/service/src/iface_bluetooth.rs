@@ -3,8 +3,11 @@ extern crate bt_shim;
 use bt_topshim::btif::{BtDeviceType, BtSspVariant, BtTransport, Uuid128Bit};
 
 use btstack::bluetooth::{
-    BluetoothDevice, IBluetooth, IBluetoothCallback, IBluetoothConnectionCallback,
+    BluetoothDevice, ClassicScanParameters, ClassicScanPreset, IBluetooth, IBluetoothCallback,
+    IBluetoothConnectionCallback, RadioActivity, RemoteVersionInfo,
 };
+use btstack::error::BtError;
+use btstack::privacy::{IdentityExposure, LocalIdentity, OwnAddressType, PrivacyMode};
 use btstack::uuid::Profile;
 use btstack::RPCProxy;
 
@@ -22,7 +25,7 @@ use num_traits::cast::{FromPrimitive, ToPrimitive};
 
 use std::sync::Arc;
 
-use crate::dbus_arg::{DBusArg, DBusArgError, RefArgToRust};
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg, RefArgToRust};
 
 #[dbus_propmap(BluetoothDevice)]
 pub struct BluetoothDeviceDBus {
@@ -30,6 +33,40 @@ pub struct BluetoothDeviceDBus {
     name: String,
 }
 
+#[dbus_propmap(ClassicScanParameters)]
+pub struct ClassicScanParametersDBus {
+    page_scan_interval: u16,
+    page_scan_window: u16,
+    inquiry_scan_interval: u16,
+    inquiry_scan_window: u16,
+    interlaced_scan: bool,
+}
+
+#[dbus_propmap(IdentityExposure)]
+pub struct IdentityExposureDBus {
+    peer_address: String,
+    identity: LocalIdentity,
+    own_address_type: OwnAddressType,
+    first_exposed_millis: u64,
+    last_exposed_millis: u64,
+    count: u32,
+}
+
+#[dbus_propmap(RadioActivity)]
+pub struct RadioActivityDBus {
+    scanning: bool,
+    advertising: bool,
+    connections: u32,
+}
+
+#[dbus_propmap(RemoteVersionInfo)]
+pub struct RemoteVersionInfoDBus {
+    lmp_version: u8,
+    lmp_subversion: u16,
+    manufacturer: u16,
+    features: Vec<u8>,
+}
+
 #[allow(dead_code)]
 struct BluetoothCallbackDBus {}
 
@@ -73,11 +110,45 @@ impl IBluetoothCallback for BluetoothCallbackDBus {
     fn on_bond_state_changed(&self, status: u32, address: String, state: u32) {
         dbus_generated!()
     }
+
+    #[dbus_method("OnReady")]
+    fn on_ready(&self) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnPairingLockedOut")]
+    fn on_pairing_locked_out(&self, device_address: String, global: bool, lockout_secs: u32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnStackRestartStarted")]
+    fn on_stack_restart_started(&self, reason: String) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnStackRestartCompleted")]
+    fn on_stack_restart_completed(&self, reason: String, success: bool) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnRadioActivityChanged")]
+    fn on_radio_activity_changed(&self, activity: RadioActivity) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnDeviceForgotten")]
+    fn on_device_forgotten(&self, device_address: String) {
+        dbus_generated!()
+    }
 }
 
 impl_dbus_arg_enum!(BtDeviceType);
 impl_dbus_arg_enum!(BtSspVariant);
 impl_dbus_arg_enum!(BtTransport);
+impl_dbus_arg_enum!(ClassicScanPreset);
+impl_dbus_arg_enum!(LocalIdentity);
+impl_dbus_arg_enum!(OwnAddressType);
+impl_dbus_arg_enum!(PrivacyMode);
 impl_dbus_arg_enum!(Profile);
 
 #[allow(dead_code)]
@@ -146,7 +217,7 @@ impl IBluetooth for IBluetoothDBus {
     }
 
     #[dbus_method("SetName")]
-    fn set_name(&self, name: String) -> bool {
+    fn set_name(&self, name: String) -> Result<(), BtError> {
         dbus_generated!()
     }
 
@@ -156,7 +227,7 @@ impl IBluetooth for IBluetoothDBus {
     }
 
     #[dbus_method("SetBluetoothClass")]
-    fn set_bluetooth_class(&self, cod: u32) -> bool {
+    fn set_bluetooth_class(&self, cod: u32) -> Result<(), BtError> {
         dbus_generated!()
     }
 
@@ -171,7 +242,7 @@ impl IBluetooth for IBluetoothDBus {
     }
 
     #[dbus_method("SetDiscoverable")]
-    fn set_discoverable(&self, mode: bool, duration: u32) -> bool {
+    fn set_discoverable(&self, mode: bool, duration: u32) -> Result<(), BtError> {
         dbus_generated!()
     }
 
@@ -186,12 +257,12 @@ impl IBluetooth for IBluetoothDBus {
     }
 
     #[dbus_method("StartDiscovery")]
-    fn start_discovery(&self) -> bool {
+    fn start_discovery(&self) -> Result<(), BtError> {
         dbus_generated!()
     }
 
     #[dbus_method("CancelDiscovery")]
-    fn cancel_discovery(&self) -> bool {
+    fn cancel_discovery(&self) -> Result<(), BtError> {
         dbus_generated!()
     }
 
@@ -205,18 +276,32 @@ impl IBluetooth for IBluetoothDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("IsReady")]
+    fn is_ready(&self) -> bool {
+        dbus_generated!()
+    }
+
     #[dbus_method("CreateBond")]
-    fn create_bond(&self, device: BluetoothDevice, transport: BtTransport) -> bool {
+    fn create_bond(
+        &mut self,
+        device: BluetoothDevice,
+        transport: BtTransport,
+    ) -> Result<(), BtError> {
         dbus_generated!()
     }
 
     #[dbus_method("CancelBondProcess")]
-    fn cancel_bond_process(&self, device: BluetoothDevice) -> bool {
+    fn cancel_bond_process(&self, device: BluetoothDevice) -> Result<(), BtError> {
         dbus_generated!()
     }
 
     #[dbus_method("RemoveBond")]
-    fn remove_bond(&self, device: BluetoothDevice) -> bool {
+    fn remove_bond(&self, device: BluetoothDevice) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RemoveBondCascade")]
+    fn remove_bond_cascade(&mut self, device: BluetoothDevice) -> Result<(), BtError> {
         dbus_generated!()
     }
 
@@ -304,4 +389,54 @@ impl IBluetooth for IBluetoothDBus {
     fn disconnect_all_enabled_profiles(&mut self, device: BluetoothDevice) -> bool {
         dbus_generated!()
     }
+
+    #[dbus_method("GetIdentityExposureReport")]
+    fn get_identity_exposure_report(&self) -> Vec<IdentityExposure> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetClassicScanParameters")]
+    fn get_classic_scan_parameters(&self) -> ClassicScanParameters {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetClassicScanParameters")]
+    fn set_classic_scan_parameters(
+        &mut self,
+        parameters: ClassicScanParameters,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetClassicScanPreset")]
+    fn set_classic_scan_preset(&mut self, preset: ClassicScanPreset) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RestartStack")]
+    fn restart_stack(&mut self, reason: String) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetRadioActivity")]
+    fn get_radio_activity(&self) -> RadioActivity {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetRemoteVersionInfo")]
+    fn get_remote_version_info(
+        &mut self,
+        device: BluetoothDevice,
+    ) -> Result<RemoteVersionInfo, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetDevicePrivacyMode")]
+    fn set_device_privacy_mode(
+        &mut self,
+        device: BluetoothDevice,
+        mode: PrivacyMode,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
 }
@@ -0,0 +1,79 @@
+use bt_topshim::btif::Uuid128Bit;
+
+use btstack::bluetooth::BluetoothDevice;
+use btstack::bluetooth_admin::{IBluetoothAdmin, IBluetoothAdminPolicyCallback, PolicyEffect};
+use btstack::RPCProxy;
+
+use dbus::arg::RefArg;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_propmap, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, DisconnectWatcher};
+
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError, RefArgToRust};
+
+#[dbus_propmap(PolicyEffect)]
+pub struct PolicyEffectDBus {
+    service_blocked: Vec<Uuid128Bit>,
+    affected: bool,
+}
+
+#[allow(dead_code)]
+struct IBluetoothAdminDBus {}
+
+#[generate_dbus_exporter(export_bluetooth_admin_dbus_obj, "org.chromium.bluetooth.BluetoothAdmin")]
+impl IBluetoothAdmin for IBluetoothAdminDBus {
+    #[dbus_method("IsServiceAllowed")]
+    fn is_service_allowed(&self, service: Uuid128Bit) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetAllowedServices")]
+    fn set_allowed_services(&mut self, services: Vec<Uuid128Bit>) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetAllowedServices")]
+    fn get_allowed_services(&self) -> Vec<Uuid128Bit> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetDevicePolicyEffect")]
+    fn get_device_policy_effect(&self, device: BluetoothDevice) -> PolicyEffect {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RegisterAdminPolicyCallback")]
+    fn register_admin_policy_callback(
+        &mut self,
+        callback: Box<dyn IBluetoothAdminPolicyCallback + Send>,
+    ) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterAdminPolicyCallback")]
+    fn unregister_admin_policy_callback(&mut self, callback_id: u32) -> bool {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct AdminPolicyCallbackDBus {}
+
+#[dbus_proxy_obj(AdminPolicyCallback, "org.chromium.bluetooth.AdminPolicyCallback")]
+impl IBluetoothAdminPolicyCallback for AdminPolicyCallbackDBus {
+    #[dbus_method("OnServiceAllowlistChanged")]
+    fn on_service_allowlist_changed(&self, allowlist: Vec<Uuid128Bit>) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnDevicePolicyEffectChanged")]
+    fn on_device_policy_effect_changed(&self, device: BluetoothDevice, effect: PolicyEffect) {
+        dbus_generated!()
+    }
+}
@@ -0,0 +1,79 @@
+use bt_topshim::snoop::SnoopLogMode;
+
+use btstack::bluetooth_debug::{IBluetoothDebug, IBluetoothDebugCallback};
+use btstack::RPCProxy;
+
+use dbus::arg::RefArg;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, impl_dbus_arg_enum, DisconnectWatcher};
+
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError};
+
+impl_dbus_arg_enum!(SnoopLogMode);
+
+#[allow(dead_code)]
+struct IBluetoothDebugDBus {}
+
+#[generate_dbus_exporter(export_bluetooth_debug_dbus_obj, "org.chromium.bluetooth.BluetoothDebug")]
+impl IBluetoothDebug for IBluetoothDebugDBus {
+    #[dbus_method("GetStateSnapshot")]
+    fn get_state_snapshot(&self, redact: bool) -> String {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetCounters")]
+    fn get_counters(&self) -> HashMap<String, u64> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RegisterCallback")]
+    fn register_callback(&mut self, callback: Box<dyn IBluetoothDebugCallback + Send>) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterCallback")]
+    fn unregister_callback(&mut self, callback_id: u32) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetHciLatencyThreshold")]
+    fn set_hci_latency_threshold(&mut self, threshold_ms: u32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetSnoopLogMode")]
+    fn set_snoop_log_mode(&mut self, mode: SnoopLogMode) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetSnoopLogMode")]
+    fn get_snoop_log_mode(&self) -> SnoopLogMode {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct BluetoothDebugCallbackDBus {}
+
+#[dbus_proxy_obj(BluetoothDebugCallback, "org.chromium.bluetooth.BluetoothDebugCallback")]
+impl IBluetoothDebugCallback for BluetoothDebugCallbackDBus {
+    #[dbus_method("OnControllerSluggish")]
+    fn on_controller_sluggish(&self, opcode: u16, latency_ms: u32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnControllerRecovered")]
+    fn on_controller_recovered(&self, opcode: u16, latency_ms: u32) {
+        dbus_generated!()
+    }
+}
@@ -1,13 +1,34 @@
 use bt_topshim::{btif::Uuid128Bit, profiles::gatt::GattStatus};
 
+use btstack::address::BtAddress;
+use btstack::att_retry::AttRetryPolicy;
+use btstack::att_trace::{AttPduDirection, AttPduRecord};
+use btstack::bluetooth_adv::{
+    AdStructureCost, AdvertiseData, AdvertiseDataBreakdown, AdvertisingSetParameters,
+    AdvertisingStatus, AdvertisingTerminationReason, IAdvertisingSetCallback, TdsRole,
+    TdsTransportState, TransportBlock, TransportDiscoveryData,
+};
 use btstack::bluetooth_gatt::{
-    BluetoothGattCharacteristic, BluetoothGattDescriptor, BluetoothGattService,
-    GattWriteRequestStatus, GattWriteType, IBluetoothGatt, IBluetoothGattCallback,
-    IScannerCallback, LePhy, RSSISettings, ScanFilter, ScanSettings, ScanType,
+    BatchScanDiscardRule, BatchScanMode, BatchScanResult, BluetoothGattCharacteristic,
+    BluetoothGattDescriptor, BluetoothGattService, CharacteristicReadResult, GattConnectionInfo,
+    GattHandleValue, GattWriteRequestStatus, GattWriteType, IBluetoothGatt, IBluetoothGattCallback,
+    IBluetoothGattServerCallback, IPeriodicAdvertisingCallback, IPeripheralConnectionAgent,
+    IScannerCallback, LePhy, NotificationDropPolicy, PeripheralConnectionPolicy, RSSISettings,
+    ScanCallbackType, ScanFilter, ScanMatchInstruction, ScanMatchOpcode, ScanMatchProgram,
+    ScanPriority, ScanRecord, ScanRecordDelivery, ScanResult, ScanSettings, ScanType,
 };
+use btstack::connection_priority::ConnectionPriority;
+use btstack::error::BtError;
+use btstack::gatt_conformance::{ConformanceIssue, ConformanceProblem};
+use btstack::gatt_service_builder::{ServiceValidationError, ServiceValidationProblem};
+use btstack::link_tuning::LinkTuningProfile;
+use btstack::notification_queue::NotificationQueueConfig;
+use btstack::phy_preferences::PhyPreference;
+use btstack::uuid::BtUuid;
+use btstack::write_journal::{JournalConflictPolicy, JournalEntry};
 use btstack::RPCProxy;
 
-use dbus::arg::RefArg;
+use dbus::arg::{OwnedFd, RefArg};
 
 use dbus::nonblock::SyncConnection;
 use dbus::strings::Path;
@@ -19,10 +40,13 @@ use dbus_projection::{dbus_generated, impl_dbus_arg_enum};
 
 use num_traits::cast::{FromPrimitive, ToPrimitive};
 
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::fs::File;
+use std::os::unix::io::{FromRawFd, IntoRawFd};
 use std::sync::Arc;
 
-use crate::dbus_arg::{DBusArg, DBusArgError, RefArgToRust};
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg, RefArgToRust};
 
 #[allow(dead_code)]
 struct BluetoothGattCallbackDBus {}
@@ -40,70 +64,121 @@ impl IBluetoothGattCallback for BluetoothGattCallbackDBus {
         status: i32,
         client_id: i32,
         connected: bool,
-        addr: String,
+        addr: BtAddress,
     ) {
         dbus_generated!()
     }
 
     #[dbus_method("OnPhyUpdate")]
-    fn on_phy_update(&self, addr: String, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
+    fn on_phy_update(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
         dbus_generated!()
     }
 
     #[dbus_method("OnPhyRead")]
-    fn on_phy_read(&self, addr: String, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
+    fn on_phy_read(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
         dbus_generated!()
     }
 
     #[dbus_method("OnSearchComplete")]
-    fn on_search_complete(&self, addr: String, services: Vec<BluetoothGattService>, status: i32) {
+    fn on_search_complete(
+        &self,
+        addr: BtAddress,
+        services: Vec<BluetoothGattService>,
+        status: i32,
+    ) {
         dbus_generated!()
     }
 
     #[dbus_method("OnCharacteristicRead")]
-    fn on_characteristic_read(&self, addr: String, status: i32, handle: i32, value: Vec<u8>) {
+    fn on_characteristic_read(&self, addr: BtAddress, status: i32, handle: i32, value: Vec<u8>) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnGetGattDb")]
+    fn on_get_gatt_db(&self, addr: BtAddress, services: Vec<BluetoothGattService>) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnServiceRead")]
+    fn on_service_read(
+        &self,
+        addr: BtAddress,
+        service_uuid: Uuid128Bit,
+        results: Vec<CharacteristicReadResult>,
+    ) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnConformanceReport")]
+    fn on_conformance_report(&self, addr: BtAddress, issues: Vec<ConformanceIssue>) {
         dbus_generated!()
     }
 
     #[dbus_method("OnCharacteristicWrite")]
-    fn on_characteristic_write(&self, addr: String, status: i32, handle: i32) {
+    fn on_characteristic_write(&self, addr: BtAddress, status: i32, handle: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnCharacteristicWriteProgress")]
+    fn on_characteristic_write_progress(
+        &self,
+        addr: BtAddress,
+        handle: i32,
+        bytes_written: i32,
+        total_bytes: i32,
+    ) {
         dbus_generated!()
     }
 
     #[dbus_method("OnExecuteWrite")]
-    fn on_execute_write(&self, addr: String, status: i32) {
+    fn on_execute_write(&self, addr: BtAddress, status: i32) {
         dbus_generated!()
     }
 
     #[dbus_method("OnDescriptorRead")]
-    fn on_descriptor_read(&self, addr: String, status: i32, handle: i32, value: Vec<u8>) {
+    fn on_descriptor_read(&self, addr: BtAddress, status: i32, handle: i32, value: Vec<u8>) {
         dbus_generated!()
     }
 
     #[dbus_method("OnDescriptorWrite")]
-    fn on_descriptor_write(&self, addr: String, status: i32, handle: i32) {
+    fn on_descriptor_write(&self, addr: BtAddress, status: i32, handle: i32) {
         dbus_generated!()
     }
 
     #[dbus_method("OnNotify")]
-    fn on_notify(&self, addr: String, handle: i32, value: Vec<u8>) {
+    fn on_notify(&self, addr: BtAddress, handle: i32, value: Vec<u8>) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnNotifyMultiple")]
+    fn on_notify_multiple(&self, addr: BtAddress, values: Vec<GattHandleValue>) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnNotificationQueueOverflow")]
+    fn on_notification_queue_overflow(&self, addr: BtAddress, handle: i32, dropped: u32) {
         dbus_generated!()
     }
 
     #[dbus_method("OnReadRemoteRssi")]
-    fn on_read_remote_rssi(&self, addr: String, rssi: i32, status: i32) {
+    fn on_read_remote_rssi(&self, addr: BtAddress, rssi: i32, status: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnRssiThresholdCrossed")]
+    fn on_rssi_threshold_crossed(&self, addr: BtAddress, rssi: i32, threshold: i32) {
         dbus_generated!()
     }
 
     #[dbus_method("OnConfigureMtu")]
-    fn on_configure_mtu(&self, addr: String, mtu: i32, status: i32) {
+    fn on_configure_mtu(&self, addr: BtAddress, mtu: i32, status: i32) {
         dbus_generated!()
     }
 
     #[dbus_method("OnConnectionUpdated")]
     fn on_connection_updated(
         &self,
-        addr: String,
+        addr: BtAddress,
         interval: i32,
         latency: i32,
         timeout: i32,
@@ -113,7 +188,17 @@ impl IBluetoothGattCallback for BluetoothGattCallbackDBus {
     }
 
     #[dbus_method("OnServiceChanged")]
-    fn on_service_changed(&self, addr: String) {
+    fn on_service_changed(&self, addr: BtAddress) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnNotificationPipeActive")]
+    fn on_notification_pipe_active(&self, addr: BtAddress, handle: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnEattStateChanged")]
+    fn on_eatt_state_changed(&self, addr: BtAddress, bearer_count: i32, bearer_mtus: Vec<i32>) {
         dbus_generated!()
     }
 }
@@ -128,7 +213,11 @@ impl DBusArg for Uuid128Bit {
         _remote: Option<dbus::strings::BusName<'static>>,
         _disconnect_watcher: Option<Arc<std::sync::Mutex<DisconnectWatcher>>>,
     ) -> Result<[u8; 16], Box<dyn std::error::Error>> {
-        return Ok(data.try_into().unwrap());
+        let len = data.len();
+        match data.try_into() {
+            Ok(uuid) => Ok(uuid),
+            Err(_) => Err(Box::new(DBusArgError::new(format!("UUID has {} bytes, not 16", len)))),
+        }
     }
 
     fn to_dbus(data: [u8; 16]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
@@ -136,242 +225,1489 @@ impl DBusArg for Uuid128Bit {
     }
 }
 
-#[allow(dead_code)]
-struct ScannerCallbackDBus {}
+// Represents a file, such as a pipe, as a file descriptor in D-Bus.
+impl DBusArg for File {
+    type DBusType = OwnedFd;
 
-#[dbus_proxy_obj(ScannerCallback, "org.chromium.bluetooth.ScannerCallback")]
-impl IScannerCallback for ScannerCallbackDBus {
-    #[dbus_method("OnScannerRegistered")]
-    fn on_scanner_registered(&self, status: i32, scanner_id: i32) {
-        dbus_generated!()
+    fn from_dbus(
+        data: OwnedFd,
+        _conn: Option<Arc<SyncConnection>>,
+        _remote: Option<dbus::strings::BusName<'static>>,
+        _disconnect_watcher: Option<Arc<std::sync::Mutex<DisconnectWatcher>>>,
+    ) -> Result<File, Box<dyn std::error::Error>> {
+        return Ok(unsafe { File::from_raw_fd(data.into_fd()) });
     }
-}
 
-#[dbus_propmap(BluetoothGattDescriptor)]
-pub struct BluetoothGattDescriptorDBus {
-    uuid: Uuid128Bit,
-    instance_id: i32,
-    permissions: i32,
+    fn to_dbus(data: File) -> Result<OwnedFd, Box<dyn std::error::Error>> {
+        return Ok(unsafe { OwnedFd::new(data.into_raw_fd()) });
+    }
 }
 
-#[dbus_propmap(BluetoothGattCharacteristic)]
-pub struct BluetoothGattCharacteristicDBus {
-    uuid: Uuid128Bit,
-    instance_id: i32,
-    properties: i32,
-    permissions: i32,
-    key_size: i32,
-    write_type: GattWriteType,
-    descriptors: Vec<BluetoothGattDescriptor>,
-}
+#[allow(dead_code)]
+struct BluetoothGattServerCallbackDBus {}
 
-#[dbus_propmap(BluetoothGattService)]
-pub struct BluetoothGattServiceDBus {
-    uuid: Uuid128Bit,
-    instance_id: i32,
-    service_type: i32,
-    characteristics: Vec<BluetoothGattCharacteristic>,
-    included_services: Vec<BluetoothGattService>,
-}
+#[dbus_proxy_obj(BluetoothGattServerCallback, "org.chromium.bluetooth.BluetoothGattServerCallback")]
+impl IBluetoothGattServerCallback for BluetoothGattServerCallbackDBus {
+    #[dbus_method("OnServerRegistered")]
+    fn on_server_registered(&self, status: i32, server_id: i32) {
+        dbus_generated!()
+    }
 
-#[dbus_propmap(RSSISettings)]
-pub struct RSSISettingsDBus {
-    low_threshold: i32,
-    high_threshold: i32,
-}
+    #[dbus_method("OnServerConnectionState")]
+    fn on_server_connection_state(&self, server_id: i32, connected: bool, addr: BtAddress) {
+        dbus_generated!()
+    }
 
-#[dbus_propmap(ScanSettings)]
-struct ScanSettingsDBus {
-    interval: i32,
-    window: i32,
-    scan_type: ScanType,
-    rssi_settings: RSSISettings,
-}
+    #[dbus_method("OnServiceAdded")]
+    fn on_service_added(&self, status: i32, service: BluetoothGattService) {
+        dbus_generated!()
+    }
 
-impl_dbus_arg_enum!(GattStatus);
-impl_dbus_arg_enum!(GattWriteRequestStatus);
-impl_dbus_arg_enum!(GattWriteType);
-impl_dbus_arg_enum!(LePhy);
-impl_dbus_arg_enum!(ScanType);
+    #[dbus_method("OnServiceRemoved")]
+    fn on_service_removed(&self, status: i32, handle: i32) {
+        dbus_generated!()
+    }
 
-#[dbus_propmap(ScanFilter)]
-struct ScanFilterDBus {}
+    #[dbus_method("OnCharacteristicReadRequest")]
+    fn on_characteristic_read_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        is_long: bool,
+        handle: i32,
+    ) {
+        dbus_generated!()
+    }
 
-#[allow(dead_code)]
-struct IBluetoothGattDBus {}
+    #[dbus_method("OnDescriptorReadRequest")]
+    fn on_descriptor_read_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        is_long: bool,
+        handle: i32,
+    ) {
+        dbus_generated!()
+    }
 
-#[generate_dbus_exporter(export_bluetooth_gatt_dbus_obj, "org.chromium.bluetooth.BluetoothGatt")]
-impl IBluetoothGatt for IBluetoothGattDBus {
-    #[dbus_method("RegisterScanner")]
-    fn register_scanner(&self, callback: Box<dyn IScannerCallback + Send>) {
+    #[dbus_method("OnCharacteristicWriteRequest")]
+    fn on_characteristic_write_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        len: i32,
+        is_prep: bool,
+        need_response: bool,
+        handle: i32,
+        value: Vec<u8>,
+    ) {
         dbus_generated!()
     }
 
-    #[dbus_method("UnregisterScanner")]
-    fn unregister_scanner(&self, scanner_id: i32) {
+    #[dbus_method("OnDescriptorWriteRequest")]
+    fn on_descriptor_write_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        len: i32,
+        is_prep: bool,
+        need_response: bool,
+        handle: i32,
+        value: Vec<u8>,
+    ) {
         dbus_generated!()
     }
 
-    #[dbus_method("StartScan")]
-    fn start_scan(&self, scanner_id: i32, settings: ScanSettings, filters: Vec<ScanFilter>) {
+    #[dbus_method("OnExecuteWrite")]
+    fn on_execute_write(&self, addr: BtAddress, request_id: i32, execute_write: bool) {
         dbus_generated!()
     }
 
-    #[dbus_method("StopScan")]
-    fn stop_scan(&self, scanner_id: i32) {
+    #[dbus_method("OnNotificationSent")]
+    fn on_notification_sent(&self, addr: BtAddress, status: i32) {
         dbus_generated!()
     }
 
-    #[dbus_method("RegisterClient")]
-    fn register_client(
-        &mut self,
-        app_uuid: String,
-        callback: Box<dyn IBluetoothGattCallback + Send>,
-        eatt_support: bool,
-    ) {
+    #[dbus_method("OnMtuChanged")]
+    fn on_mtu_changed(&self, addr: BtAddress, mtu: i32) {
         dbus_generated!()
     }
 
-    #[dbus_method("UnregisterClient")]
-    fn unregister_client(&mut self, client_id: i32) {
+    #[dbus_method("OnUserDescriptionChanged")]
+    fn on_user_description_changed(&self, addr: BtAddress, handle: i32, description: String) {
         dbus_generated!()
     }
 
-    #[dbus_method("ClientConnect")]
-    fn client_connect(
-        &self,
-        client_id: i32,
-        addr: String,
-        is_direct: bool,
-        transport: i32,
-        opportunistic: bool,
-        phy: i32,
-    ) {
+    #[dbus_method("OnServerConfigurationChanged")]
+    fn on_server_configuration_changed(&self, addr: BtAddress, handle: i32, broadcast: bool) {
         dbus_generated!()
     }
 
-    #[dbus_method("ClientDisconnect")]
-    fn client_disconnect(&self, client_id: i32, addr: String) {
+    #[dbus_method("OnPhyUpdate")]
+    fn on_phy_update(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
         dbus_generated!()
     }
 
-    #[dbus_method("ClientSetPreferredPhy")]
-    fn client_set_preferred_phy(
+    #[dbus_method("OnConnectionUpdated")]
+    fn on_connection_updated(
         &self,
-        client_id: i32,
-        addr: String,
-        tx_phy: LePhy,
-        rx_phy: LePhy,
-        phy_options: i32,
+        addr: BtAddress,
+        interval: i32,
+        latency: i32,
+        timeout: i32,
+        status: i32,
     ) {
         dbus_generated!()
     }
+}
 
-    #[dbus_method("ClientReadPhy")]
-    fn client_read_phy(&mut self, client_id: i32, addr: String) {
+#[allow(dead_code)]
+struct ScannerCallbackDBus {}
+
+#[dbus_proxy_obj(ScannerCallback, "org.chromium.bluetooth.ScannerCallback")]
+impl IScannerCallback for ScannerCallbackDBus {
+    #[dbus_method("OnScannerRegistered")]
+    fn on_scanner_registered(&self, status: i32, scanner_id: i32) {
         dbus_generated!()
     }
 
-    #[dbus_method("RefreshDevice")]
-    fn refresh_device(&self, client_id: i32, addr: String) {
+    #[dbus_method("OnScanResult")]
+    fn on_scan_result(&self, scan_result: ScanResult) {
         dbus_generated!()
     }
 
-    #[dbus_method("DiscoverServices")]
-    fn discover_services(&self, client_id: i32, addr: String) {
+    #[dbus_method("OnScanResultBatch")]
+    fn on_scan_result_batch(&self, scan_results: Vec<ScanResult>) {
         dbus_generated!()
     }
 
-    #[dbus_method("DiscoverServiceByUuid")]
-    fn discover_service_by_uuid(&self, client_id: i32, addr: String, uuid: String) {
+    #[dbus_method("OnScanResultLost")]
+    fn on_scan_result_lost(&self, scan_result: ScanResult) {
         dbus_generated!()
     }
 
-    #[dbus_method("ReadCharacteristic")]
-    fn read_characteristic(&self, client_id: i32, addr: String, handle: i32, auth_req: i32) {
+    #[dbus_method("OnBatchScanReports")]
+    fn on_batch_scan_reports(&self, scanner_id: i32, status: i32, results: Vec<BatchScanResult>) {
         dbus_generated!()
     }
 
-    #[dbus_method("ReadUsingCharacteristicUuid")]
-    fn read_using_characteristic_uuid(
+    #[dbus_method("OnBatchScanThresholdCrossed")]
+    fn on_batch_scan_threshold_crossed(&self, scanner_id: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnScanParametersChanged")]
+    fn on_scan_parameters_changed(&self, scanner_id: i32, interval: i32, window: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnScanDutyCycleChanged")]
+    fn on_scan_duty_cycle_changed(&self, scanner_id: i32, requested: i32, effective: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnManufacturerDataFound")]
+    fn on_manufacturer_data_found(
         &self,
-        client_id: i32,
-        addr: String,
-        uuid: String,
-        start_handle: i32,
-        end_handle: i32,
-        auth_req: i32,
+        scanner_id: i32,
+        subscription_id: u32,
+        addr: BtAddress,
+        rssi: i32,
+        data: Vec<u8>,
     ) {
         dbus_generated!()
     }
+}
 
-    #[dbus_method("WriteCharacteristic")]
-    fn write_characteristic(
+#[allow(dead_code)]
+struct PeripheralConnectionAgentDBus {}
+
+#[dbus_proxy_obj(PeripheralConnectionAgent, "org.chromium.bluetooth.PeripheralConnectionAgent")]
+impl IPeripheralConnectionAgent for PeripheralConnectionAgentDBus {
+    #[dbus_method("OnPeripheralConnectionRequest")]
+    fn on_peripheral_connection_request(&self, addr: BtAddress) {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct PeriodicAdvertisingCallbackDBus {}
+
+#[dbus_proxy_obj(PeriodicAdvertisingCallback, "org.chromium.bluetooth.PeriodicAdvertisingCallback")]
+impl IPeriodicAdvertisingCallback for PeriodicAdvertisingCallbackDBus {
+    #[dbus_method("OnSyncStarted")]
+    fn on_sync_started(
         &self,
-        client_id: i32,
-        addr: String,
-        handle: i32,
-        write_type: GattWriteType,
-        auth_req: i32,
-        value: Vec<u8>,
-    ) -> GattWriteRequestStatus {
+        sync_handle: i32,
+        status: i32,
+        sid: i32,
+        addr_type: i32,
+        address: BtAddress,
+        phy: i32,
+        interval: i32,
+    ) {
         dbus_generated!()
     }
 
-    #[dbus_method("ReadDescriptor")]
-    fn read_descriptor(&self, client_id: i32, addr: String, handle: i32, auth_req: i32) {
+    #[dbus_method("OnSyncReport")]
+    fn on_sync_report(
+        &self,
+        sync_handle: i32,
+        tx_power: i32,
+        rssi: i32,
+        status: i32,
+        data: Vec<u8>,
+    ) {
         dbus_generated!()
     }
 
-    #[dbus_method("WriteDescriptor")]
-    fn write_descriptor(
+    #[dbus_method("OnSyncLost")]
+    fn on_sync_lost(&self, sync_handle: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnSyncTransferred")]
+    fn on_sync_transferred(&self, address: BtAddress, status: i32) {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct AdvertisingSetCallbackDBus {}
+
+#[dbus_proxy_obj(AdvertisingSetCallback, "org.chromium.bluetooth.AdvertisingSetCallback")]
+impl IAdvertisingSetCallback for AdvertisingSetCallbackDBus {
+    #[dbus_method("OnAdvertisingSetStarted")]
+    fn on_advertising_set_started(
         &self,
-        client_id: i32,
-        addr: String,
-        handle: i32,
-        auth_req: i32,
-        value: Vec<u8>,
+        reg_id: i32,
+        advertiser_id: i32,
+        tx_power: i32,
+        status: AdvertisingStatus,
     ) {
         dbus_generated!()
     }
 
-    #[dbus_method("RegisterForNotification")]
-    fn register_for_notification(&self, client_id: i32, addr: String, handle: i32, enable: bool) {
+    #[dbus_method("OnOwnAddressRead")]
+    fn on_own_address_read(&self, advertiser_id: i32, address_type: i32, address: String) {
         dbus_generated!()
     }
 
-    #[dbus_method("BeginReliableWrite")]
-    fn begin_reliable_write(&mut self, client_id: i32, addr: String) {
+    #[dbus_method("OnOwnAddressChanged")]
+    fn on_own_address_changed(&self, advertiser_id: i32, address_type: i32, address: String) {
         dbus_generated!()
     }
 
-    #[dbus_method("EndReliableWrite")]
-    fn end_reliable_write(&mut self, client_id: i32, addr: String, execute: bool) {
+    #[dbus_method("OnAdvertisingSetStopped")]
+    fn on_advertising_set_stopped(&self, advertiser_id: i32) {
         dbus_generated!()
     }
 
-    #[dbus_method("ReadRemoteRssi")]
-    fn read_remote_rssi(&self, client_id: i32, addr: String) {
+    #[dbus_method("OnAdvertisingEnabled")]
+    fn on_advertising_enabled(&self, advertiser_id: i32, enable: bool, status: AdvertisingStatus) {
         dbus_generated!()
     }
 
-    #[dbus_method("ConfigureMtu")]
-    fn configure_mtu(&self, client_id: i32, addr: String, mtu: i32) {
+    #[dbus_method("OnAdvertisingSetTerminated")]
+    fn on_advertising_set_terminated(
+        &self,
+        advertiser_id: i32,
+        reason: AdvertisingTerminationReason,
+    ) {
         dbus_generated!()
     }
 
-    #[dbus_method("ConnectionParameterUpdate")]
-    fn connection_parameter_update(
+    #[dbus_method("OnAdvertisingDataSet")]
+    fn on_advertising_data_set(&self, advertiser_id: i32, status: AdvertisingStatus) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnScanResponseDataSet")]
+    fn on_scan_response_data_set(&self, advertiser_id: i32, status: AdvertisingStatus) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnAdvertisingParametersUpdated")]
+    fn on_advertising_parameters_updated(
         &self,
-        client_id: i32,
-        addr: String,
-        min_interval: i32,
-        max_interval: i32,
-        latency: i32,
-        timeout: i32,
-        min_ce_len: u16,
-        max_ce_len: u16,
+        advertiser_id: i32,
+        tx_power: i32,
+        status: AdvertisingStatus,
     ) {
         dbus_generated!()
     }
+
+    #[dbus_method("OnAdvertisingSetSuspended")]
+    fn on_advertising_set_suspended(&self, advertiser_id: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnAdvertisingSetResumed")]
+    fn on_advertising_set_resumed(&self, advertiser_id: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnAdvertisingSetRestored")]
+    fn on_advertising_set_restored(
+        &self,
+        advertiser_id: i32,
+        tx_power: i32,
+        status: AdvertisingStatus,
+    ) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnTxPowerChanged")]
+    fn on_tx_power_changed(&self, advertiser_id: i32, tx_power: i32) {
+        dbus_generated!()
+    }
+}
+
+#[dbus_propmap(BluetoothGattDescriptor)]
+pub struct BluetoothGattDescriptorDBus {
+    uuid: Uuid128Bit,
+    instance_id: i32,
+    permissions: i32,
+}
+
+#[dbus_propmap(BluetoothGattCharacteristic)]
+pub struct BluetoothGattCharacteristicDBus {
+    uuid: Uuid128Bit,
+    instance_id: i32,
+    properties: i32,
+    permissions: i32,
+    key_size: i32,
+    write_type: GattWriteType,
+    descriptors: Vec<BluetoothGattDescriptor>,
+}
+
+#[dbus_propmap(AttPduRecord)]
+pub struct AttPduRecordDBus {
+    direction: AttPduDirection,
+    opcode: u8,
+    handle: i32,
+    length: i32,
+    status: i32,
+    delta_us: u64,
+}
+
+#[dbus_propmap(CharacteristicReadResult)]
+pub struct CharacteristicReadResultDBus {
+    uuid: Uuid128Bit,
+    handle: i32,
+    status: i32,
+    value: Vec<u8>,
+}
+
+#[dbus_propmap(GattHandleValue)]
+pub struct GattHandleValueDBus {
+    handle: i32,
+    value: Vec<u8>,
+}
+
+#[dbus_propmap(PhyPreference)]
+pub struct PhyPreferenceDBus {
+    tx_phy: LePhy,
+    rx_phy: LePhy,
+    phy_options: i32,
+}
+
+#[dbus_propmap(AttRetryPolicy)]
+pub struct AttRetryPolicyDBus {
+    max_attempts: u32,
+    backoff_ms: u32,
+}
+
+#[dbus_propmap(NotificationQueueConfig)]
+pub struct NotificationQueueConfigDBus {
+    depth: u32,
+    coalesce: bool,
+}
+
+#[dbus_propmap(JournalEntry)]
+pub struct JournalEntryDBus {
+    id: i32,
+    address: String,
+    handle: i32,
+    write_type: GattWriteType,
+    auth_req: i32,
+    value: Vec<u8>,
+    queued_at_ms: u64,
+}
+
+#[dbus_propmap(GattConnectionInfo)]
+pub struct GattConnectionInfoDBus {
+    address: String,
+    conn_id: i32,
+    mtu: i32,
+    link_tuning_profile: LinkTuningProfile,
+    link_tuning_overridden: bool,
+}
+
+#[dbus_propmap(ConformanceIssue)]
+pub struct ConformanceIssueDBus {
+    problem: ConformanceProblem,
+    service_uuid: Uuid128Bit,
+    characteristic_uuid: Uuid128Bit,
+    descriptor_uuid: Uuid128Bit,
+    handle: i32,
+}
+
+#[dbus_propmap(BluetoothGattService)]
+pub struct BluetoothGattServiceDBus {
+    uuid: Uuid128Bit,
+    instance_id: i32,
+    service_type: i32,
+    characteristics: Vec<BluetoothGattCharacteristic>,
+    included_services: Vec<BluetoothGattService>,
+}
+
+#[dbus_propmap(ServiceValidationError)]
+pub struct ServiceValidationErrorDBus {
+    problem: ServiceValidationProblem,
+    characteristic_uuid: Uuid128Bit,
+    #[dbus_optional]
+    descriptor_uuid: Option<Uuid128Bit>,
+}
+
+#[dbus_propmap(RSSISettings)]
+pub struct RSSISettingsDBus {
+    low_threshold: i32,
+    high_threshold: i32,
+}
+
+#[dbus_propmap(ScanSettings)]
+struct ScanSettingsDBus {
+    interval: i32,
+    window: i32,
+    scan_type: ScanType,
+    rssi_settings: RSSISettings,
+    #[dbus_optional]
+    rssi_smoothing_window: i32,
+    #[dbus_optional]
+    allowed_addresses: Vec<BtAddress>,
+    #[dbus_optional]
+    denied_addresses: Vec<BtAddress>,
+    #[dbus_optional]
+    callback_type: ScanCallbackType,
+    #[dbus_optional]
+    match_lost_timeout_ms: i32,
+    #[dbus_optional]
+    match_sightings: i32,
+    #[dbus_optional]
+    match_sightings_window_ms: i32,
+    #[dbus_optional]
+    priority: ScanPriority,
+    #[dbus_optional]
+    record_delivery: ScanRecordDelivery,
+    #[dbus_optional]
+    phys: u8,
+    #[dbus_optional]
+    report_delay_ms: i32,
+}
+
+#[dbus_propmap(ScanResult)]
+struct ScanResultDBus {
+    address: BtAddress,
+    addr_type: u8,
+    event_type: u16,
+    primary_phy: u8,
+    secondary_phy: u8,
+    primary_le_phy: LePhy,
+    secondary_le_phy: LePhy,
+    advertising_sid: u8,
+    identity_address: String,
+    is_bonded: bool,
+    tx_power: i32,
+    rssi: i32,
+    smoothed_rssi: i32,
+    periodic_adv_int: u16,
+    adv_data: Vec<u8>,
+    scan_record: ScanRecord,
+}
+
+#[dbus_propmap(ScanRecord)]
+struct ScanRecordDBus {
+    name: String,
+    service_uuids: Vec<Uuid128Bit>,
+    service_data: HashMap<BtUuid, Vec<u8>>,
+    manufacturer_data: HashMap<u16, Vec<u8>>,
+    tx_power_level: i32,
+    flags: u8,
+}
+
+impl_dbus_arg_enum!(AdvertisingStatus);
+impl_dbus_arg_enum!(AdvertisingTerminationReason);
+impl_dbus_arg_enum!(AttPduDirection);
+impl_dbus_arg_enum!(BatchScanDiscardRule);
+impl_dbus_arg_enum!(BatchScanMode);
+impl_dbus_arg_enum!(ConformanceProblem);
+impl_dbus_arg_enum!(GattStatus);
+impl_dbus_arg_enum!(GattWriteRequestStatus);
+impl_dbus_arg_enum!(GattWriteType);
+impl_dbus_arg_enum!(JournalConflictPolicy);
+impl_dbus_arg_enum!(LePhy);
+impl_dbus_arg_enum!(LinkTuningProfile);
+impl_dbus_arg_enum!(ConnectionPriority);
+impl_dbus_arg_enum!(NotificationDropPolicy);
+impl_dbus_arg_enum!(PeripheralConnectionPolicy);
+impl_dbus_arg_enum!(ScanCallbackType);
+impl_dbus_arg_enum!(ScanType);
+impl_dbus_arg_enum!(ScanMatchOpcode);
+impl_dbus_arg_enum!(ScanPriority);
+impl_dbus_arg_enum!(ScanRecordDelivery);
+impl_dbus_arg_enum!(ServiceValidationProblem);
+impl_dbus_arg_enum!(TdsRole);
+impl_dbus_arg_enum!(TdsTransportState);
+
+#[dbus_propmap(AdvertisingSetParameters)]
+struct AdvertisingSetParametersDBus {
+    connectable: bool,
+    scannable: bool,
+    is_legacy: bool,
+    is_anonymous: bool,
+    include_tx_power: bool,
+    primary_phy: LePhy,
+    secondary_phy: LePhy,
+    interval: i32,
+    tx_power_level: i32,
+    own_address_type: i32,
+}
+
+#[dbus_propmap(AdvertiseData)]
+struct AdvertiseDataDBus {
+    service_uuids: Vec<BtUuid>,
+    solicit_uuids: Vec<BtUuid>,
+    manufacturer_data: HashMap<u16, Vec<u8>>,
+    service_data: HashMap<BtUuid, Vec<u8>>,
+    transport_discovery_data: Vec<TransportDiscoveryData>,
+    include_tx_power_level: bool,
+    include_device_name: bool,
+}
+
+#[dbus_propmap(TransportBlock)]
+struct TransportBlockDBus {
+    organization_id: u8,
+    role: TdsRole,
+    transport_data_incomplete: bool,
+    transport_state: TdsTransportState,
+    transport_data: Vec<u8>,
+}
+
+#[dbus_propmap(TransportDiscoveryData)]
+struct TransportDiscoveryDataDBus {
+    transport_blocks: Vec<TransportBlock>,
+}
+
+#[dbus_propmap(AdStructureCost)]
+struct AdStructureCostDBus {
+    ad_type: u8,
+    description: String,
+    length: i32,
+}
+
+#[dbus_propmap(AdvertiseDataBreakdown)]
+struct AdvertiseDataBreakdownDBus {
+    structures: Vec<AdStructureCost>,
+    length: i32,
+    legacy_remaining: i32,
+    extended_remaining: i32,
+}
+
+#[dbus_propmap(BatchScanResult)]
+struct BatchScanResultDBus {
+    address: String,
+    addr_type: u8,
+    tx_power: i32,
+    rssi: i32,
+    timestamp_millis: i32,
+    adv_data: Vec<u8>,
+}
+
+#[dbus_propmap(ScanFilter)]
+struct ScanFilterDBus {
+    address: String,
+    addr_type: u8,
+    service_uuid: String,
+    name: String,
+    manufacturer_id: u16,
+    manufacturer_data: Vec<u8>,
+    manufacturer_data_mask: Vec<u8>,
+    rssi_high_threshold: i32,
+    rssi_low_threshold: i32,
+}
+
+#[dbus_propmap(ScanMatchInstruction)]
+struct ScanMatchInstructionDBus {
+    opcode: ScanMatchOpcode,
+    ad_type: u8,
+    offset: i32,
+    mask: Vec<u8>,
+    value: Vec<u8>,
+    threshold: i32,
+}
+
+#[dbus_propmap(ScanMatchProgram)]
+struct ScanMatchProgramDBus {
+    instructions: Vec<ScanMatchInstruction>,
+}
+
+#[allow(dead_code)]
+struct IBluetoothGattDBus {}
+
+#[generate_dbus_exporter(export_bluetooth_gatt_dbus_obj, "org.chromium.bluetooth.BluetoothGatt")]
+impl IBluetoothGatt for IBluetoothGattDBus {
+    #[dbus_method("RegisterScanner")]
+    fn register_scanner(&mut self, callback: Box<dyn IScannerCallback + Send>) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterScanner")]
+    fn unregister_scanner(&mut self, scanner_id: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetScannerLivenessInterval")]
+    fn set_scanner_liveness_interval(
+        &mut self,
+        scanner_id: i32,
+        interval_ms: u32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("PingScanner")]
+    fn ping_scanner(&mut self, scanner_id: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StartScan")]
+    fn start_scan(
+        &mut self,
+        scanner_id: i32,
+        settings: ScanSettings,
+        filters: Vec<ScanFilter>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StopScan")]
+    fn stop_scan(&mut self, scanner_id: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetScanMatchProgram")]
+    fn set_scan_match_program(
+        &mut self,
+        scanner_id: i32,
+        program: ScanMatchProgram,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetScanAddressLists")]
+    fn set_scan_address_lists(
+        &mut self,
+        scanner_id: i32,
+        allowed_addresses: Vec<BtAddress>,
+        denied_addresses: Vec<BtAddress>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetScanParameters")]
+    fn set_scan_parameters(
+        &mut self,
+        scanner_id: i32,
+        interval: i32,
+        window: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SubscribeManufacturerData")]
+    fn subscribe_manufacturer_data(
+        &mut self,
+        scanner_id: i32,
+        manufacturer_id: u16,
+        prefix: Vec<u8>,
+        mask: Vec<u8>,
+    ) -> Result<u32, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnsubscribeManufacturerData")]
+    fn unsubscribe_manufacturer_data(
+        &mut self,
+        scanner_id: i32,
+        subscription_id: u32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("BatchScanConfigStorage")]
+    fn batch_scan_config_storage(
+        &mut self,
+        scanner_id: i32,
+        full_max: i32,
+        truncated_max: i32,
+        notify_threshold: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("BatchScanEnable")]
+    fn batch_scan_enable(
+        &mut self,
+        scanner_id: i32,
+        mode: BatchScanMode,
+        interval: i32,
+        window: i32,
+        discard_rule: BatchScanDiscardRule,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("BatchScanDisable")]
+    fn batch_scan_disable(&mut self, scanner_id: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("BatchScanReadReports")]
+    fn batch_scan_read_reports(
+        &mut self,
+        scanner_id: i32,
+        mode: BatchScanMode,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StartSync")]
+    fn start_sync(
+        &mut self,
+        sid: i32,
+        address: BtAddress,
+        skip: i32,
+        timeout: i32,
+        callback: Box<dyn IPeriodicAdvertisingCallback + Send>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StopSync")]
+    fn stop_sync(&mut self, sync_handle: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("CancelCreateSync")]
+    fn cancel_create_sync(&mut self, sid: i32, address: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("TransferSync")]
+    fn transfer_sync(
+        &mut self,
+        address: BtAddress,
+        service_data: i32,
+        sync_handle: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("TransferSetInfo")]
+    fn transfer_set_info(
+        &mut self,
+        address: BtAddress,
+        service_data: i32,
+        adv_handle: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SyncTxParameters")]
+    fn sync_tx_parameters(
+        &mut self,
+        address: BtAddress,
+        mode: i32,
+        skip: i32,
+        timeout: i32,
+        callback: Box<dyn IPeriodicAdvertisingCallback + Send>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StartAdvertisingSet")]
+    fn start_advertising_set(
+        &mut self,
+        parameters: AdvertisingSetParameters,
+        advertise_data: AdvertiseData,
+        scan_response: AdvertiseData,
+        duration: i32,
+        max_ext_adv_events: i32,
+        callback: Box<dyn IAdvertisingSetCallback + Send>,
+    ) -> Result<i32, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StopAdvertisingSet")]
+    fn stop_advertising_set(&mut self, advertiser_id: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("EnableAdvertisingSet")]
+    fn enable_advertising_set(
+        &mut self,
+        advertiser_id: i32,
+        enable: bool,
+        duration: i32,
+        max_ext_adv_events: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetAdvertisingData")]
+    fn set_advertising_data(
+        &mut self,
+        advertiser_id: i32,
+        data: AdvertiseData,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetScanResponseData")]
+    fn set_scan_response_data(
+        &mut self,
+        advertiser_id: i32,
+        data: AdvertiseData,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetAdvertisingParameters")]
+    fn set_advertising_parameters(
+        &mut self,
+        advertiser_id: i32,
+        parameters: AdvertisingSetParameters,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetOwnAddress")]
+    fn get_own_address(&mut self, advertiser_id: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetAdvertisingAddressRotationInterval")]
+    fn set_advertising_address_rotation_interval(
+        &mut self,
+        advertiser_id: i32,
+        interval_ms: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetAdvertisingSetPersistent")]
+    fn set_advertising_set_persistent(
+        &mut self,
+        advertiser_id: i32,
+        persistent: bool,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetAdvertisingSetRestartPersistent")]
+    fn set_advertising_set_restart_persistent(
+        &mut self,
+        advertiser_id: i32,
+        restart_persistent: bool,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetAdvertisingTxPower")]
+    fn get_advertising_tx_power(&mut self, advertiser_id: i32) -> Result<i32, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetTxPowerLevel")]
+    fn set_advertising_tx_power_level(
+        &mut self,
+        advertiser_id: i32,
+        tx_power_level: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetMaxAdvertisingDataLength")]
+    fn get_max_advertising_data_length(&self, parameters: AdvertisingSetParameters) -> i32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetAdvertiseDataBreakdown")]
+    fn get_advertise_data_breakdown(
+        &self,
+        data: AdvertiseData,
+        connectable: bool,
+    ) -> Result<AdvertiseDataBreakdown, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetMaxAdvertisingSetsPerApp")]
+    fn set_max_advertising_sets_per_app(&mut self, max: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RegisterClient")]
+    fn register_client(
+        &mut self,
+        app_uuid: BtUuid,
+        callback: Box<dyn IBluetoothGattCallback + Send>,
+        eatt_support: bool,
+    ) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterClient")]
+    fn unregister_client(&mut self, client_id: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClientConnect")]
+    fn client_connect(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        is_direct: bool,
+        transport: i32,
+        opportunistic: bool,
+        phy: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClientDisconnect")]
+    fn client_disconnect(&mut self, client_id: i32, addr: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("AddDeviceToBackgroundConnect")]
+    fn add_device_to_background_connect(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RemoveDeviceFromBackgroundConnect")]
+    fn remove_device_from_background_connect(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClientSetPreferredPhy")]
+    fn client_set_preferred_phy(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        tx_phy: LePhy,
+        rx_phy: LePhy,
+        phy_options: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClientReadPhy")]
+    fn client_read_phy(&mut self, client_id: i32, addr: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetPreferredPhy")]
+    fn get_preferred_phy(&self, addr: BtAddress) -> Result<PhyPreference, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetDefaultPhyPreference")]
+    fn set_default_phy_preference(&mut self, preference: PhyPreference) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetDefaultPhyPreference")]
+    fn get_default_phy_preference(&self) -> PhyPreference {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetLinkTuningProfile")]
+    fn set_link_tuning_profile(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        profile: LinkTuningProfile,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClearLinkTuningProfile")]
+    fn clear_link_tuning_profile(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetConnectionInfo")]
+    fn get_connection_info(
+        &self,
+        client_id: i32,
+        addr: BtAddress,
+    ) -> Result<GattConnectionInfo, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RefreshDevice")]
+    fn refresh_device(&self, client_id: i32, addr: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("DiscoverServices")]
+    fn discover_services(&mut self, client_id: i32, addr: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("DiscoverServiceByUuid")]
+    fn discover_service_by_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        uuid: BtUuid,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ReadCharacteristic")]
+    fn read_characteristic(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        auth_req: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ReadUsingCharacteristicUuid")]
+    fn read_using_characteristic_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        uuid: BtUuid,
+        start_handle: i32,
+        end_handle: i32,
+        auth_req: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetGattDb")]
+    fn get_gatt_db(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+    ) -> Result<Vec<BluetoothGattService>, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ReadService")]
+    fn read_service(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        service_uuid: BtUuid,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("CheckConformance")]
+    fn check_conformance(&mut self, client_id: i32, addr: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("WriteCharacteristic")]
+    fn write_characteristic(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        write_type: GattWriteType,
+        auth_req: i32,
+        value: Vec<u8>,
+    ) -> GattWriteRequestStatus {
+        dbus_generated!()
+    }
+
+    #[dbus_method("WriteUsingCharacteristicUuid")]
+    fn write_using_characteristic_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        uuid: BtUuid,
+        start_handle: i32,
+        end_handle: i32,
+        write_type: GattWriteType,
+        auth_req: i32,
+        value: Vec<u8>,
+    ) -> GattWriteRequestStatus {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ReadDescriptorByUuid")]
+    fn read_descriptor_by_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        characteristic_uuid: BtUuid,
+        descriptor_uuid: BtUuid,
+        auth_req: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ReadDescriptor")]
+    fn read_descriptor(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        auth_req: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("WriteDescriptor")]
+    fn write_descriptor(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        auth_req: i32,
+        value: Vec<u8>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("CancelOperation")]
+    fn cancel_operation(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        token: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("EnableWriteJournal")]
+    fn enable_write_journal(
+        &mut self,
+        client_id: i32,
+        policy: JournalConflictPolicy,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("DisableWriteJournal")]
+    fn disable_write_journal(&mut self, client_id: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetWriteJournal")]
+    fn get_write_journal(&self, client_id: i32, addr: BtAddress) -> Vec<JournalEntry> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("CancelJournaledWrite")]
+    fn cancel_journaled_write(&mut self, client_id: i32, entry_id: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClearWriteJournal")]
+    fn clear_write_journal(&mut self, client_id: i32, addr: BtAddress) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetAttRetryPolicy")]
+    fn set_att_retry_policy(
+        &mut self,
+        client_id: i32,
+        policy: AttRetryPolicy,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetNotificationQueue")]
+    fn set_notification_queue(
+        &mut self,
+        client_id: i32,
+        config: NotificationQueueConfig,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RegisterForNotification")]
+    fn register_for_notification(
+        &self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        enable: bool,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetNotificationPipe")]
+    fn set_notification_pipe(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        pipe: File,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClearNotificationPipe")]
+    fn clear_notification_pipe(&mut self, client_id: i32, addr: BtAddress, handle: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("BeginReliableWrite")]
+    fn begin_reliable_write(&mut self, client_id: i32, addr: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("EndReliableWrite")]
+    fn end_reliable_write(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        execute: bool,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ReadRemoteRssi")]
+    fn read_remote_rssi(&self, client_id: i32, addr: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StartRssiMonitor")]
+    fn start_rssi_monitor(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        low: i32,
+        high: i32,
+        sampling_period_ms: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StopRssiMonitor")]
+    fn stop_rssi_monitor(&mut self, client_id: i32, addr: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ConfigureMtu")]
+    fn configure_mtu(&mut self, client_id: i32, addr: BtAddress, mtu: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ConnectionParameterUpdate")]
+    fn connection_parameter_update(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        min_interval: i32,
+        max_interval: i32,
+        latency: i32,
+        timeout: i32,
+        min_ce_len: u16,
+        max_ce_len: u16,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetConnectionPriority")]
+    fn set_connection_priority(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        priority: ConnectionPriority,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RegisterServer")]
+    fn register_server(
+        &mut self,
+        app_uuid: BtUuid,
+        callback: Box<dyn IBluetoothGattServerCallback + Send>,
+        eatt_support: bool,
+    ) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterServer")]
+    fn unregister_server(&mut self, server_id: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ServerConnect")]
+    fn server_connect(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        is_direct: bool,
+        transport: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ServerDisconnect")]
+    fn server_disconnect(&self, server_id: i32, addr: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("AddService")]
+    fn add_service(&self, server_id: i32, service: BluetoothGattService) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ValidateService")]
+    fn validate_service(&self, service: BluetoothGattService) -> Vec<ServiceValidationError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RemoveService")]
+    fn remove_service(&self, server_id: i32, handle: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetUserDescription")]
+    fn set_user_description(
+        &mut self,
+        server_id: i32,
+        handle: i32,
+        description: String,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SendResponse")]
+    fn send_response(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        request_id: i32,
+        status: GattStatus,
+        offset: i32,
+        value: Vec<u8>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SendNotification")]
+    fn send_notification(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        confirm: bool,
+        value: Vec<u8>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SendMultipleNotifications")]
+    fn send_multiple_notifications(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        values: Vec<GattHandleValue>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetNotificationDropPolicy")]
+    fn set_notification_drop_policy(
+        &mut self,
+        server_id: i32,
+        handle: i32,
+        policy: NotificationDropPolicy,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetNotificationQueueDepth")]
+    fn get_notification_queue_depth(
+        &self,
+        server_id: i32,
+        addr: BtAddress,
+    ) -> Result<u32, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetPeripheralConnectionPolicy")]
+    fn set_peripheral_connection_policy(&mut self, policy: PeripheralConnectionPolicy) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetPeripheralConnectionPolicy")]
+    fn get_peripheral_connection_policy(&self) -> PeripheralConnectionPolicy {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetPeripheralAllowList")]
+    fn set_peripheral_allow_list(&mut self, addresses: Vec<BtAddress>) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RegisterPeripheralConnectionAgent")]
+    fn register_peripheral_connection_agent(
+        &mut self,
+        agent: Box<dyn IPeripheralConnectionAgent + Send>,
+    ) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RespondPeripheralConnection")]
+    fn respond_peripheral_connection(
+        &mut self,
+        addr: BtAddress,
+        accept: bool,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StartAttTrace")]
+    fn start_att_trace(&mut self, addr: BtAddress) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StopAttTrace")]
+    fn stop_att_trace(&mut self) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetAttTrace")]
+    fn get_att_trace(&self, addr: BtAddress) -> Result<Vec<AttPduRecord>, BtError> {
+        dbus_generated!()
+    }
 }
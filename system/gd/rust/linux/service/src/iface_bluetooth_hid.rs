@@ -0,0 +1,131 @@
+use btstack::bluetooth::BluetoothDevice;
+use btstack::bluetooth_hid::{
+    HidConnectionState, HidProtocolMode, HidReportType, IBluetoothHid, IBluetoothHidCallback,
+};
+use btstack::error::BtError;
+use btstack::RPCProxy;
+
+use dbus::arg::RefArg;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, impl_dbus_arg_enum, DisconnectWatcher};
+
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg, RefArgToRust};
+
+impl_dbus_arg_enum!(HidConnectionState);
+impl_dbus_arg_enum!(HidProtocolMode);
+impl_dbus_arg_enum!(HidReportType);
+
+#[allow(dead_code)]
+struct IBluetoothHidDBus {}
+
+#[generate_dbus_exporter(export_bluetooth_hid_dbus_obj, "org.chromium.bluetooth.BluetoothHid")]
+impl IBluetoothHid for IBluetoothHidDBus {
+    #[dbus_method("RegisterCallback")]
+    fn register_callback(&mut self, callback: Box<dyn IBluetoothHidCallback + Send>) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterCallback")]
+    fn unregister_callback(&mut self, callback_id: u32) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("Connect")]
+    fn connect(&mut self, device: BluetoothDevice) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("Disconnect")]
+    fn disconnect(&mut self, device: BluetoothDevice) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetConnectionState")]
+    fn get_connection_state(&self, device: BluetoothDevice) -> HidConnectionState {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetConnectedDevices")]
+    fn get_connected_devices(&self) -> Vec<BluetoothDevice> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetReport")]
+    fn get_report(
+        &mut self,
+        device: BluetoothDevice,
+        report_type: HidReportType,
+        report_id: u8,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetReport")]
+    fn set_report(
+        &mut self,
+        device: BluetoothDevice,
+        report_type: HidReportType,
+        report: Vec<u8>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetProtocolMode")]
+    fn get_protocol_mode(&mut self, device: BluetoothDevice) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetProtocolMode")]
+    fn set_protocol_mode(
+        &mut self,
+        device: BluetoothDevice,
+        mode: HidProtocolMode,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("VirtualUnplug")]
+    fn virtual_unplug(&mut self, device: BluetoothDevice) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct BluetoothHidCallbackDBus {}
+
+#[dbus_proxy_obj(BluetoothHidCallback, "org.chromium.bluetooth.BluetoothHidCallback")]
+impl IBluetoothHidCallback for BluetoothHidCallbackDBus {
+    #[dbus_method("OnConnectionStateChanged")]
+    fn on_connection_state_changed(&self, device: BluetoothDevice, state: HidConnectionState) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnReport")]
+    fn on_report(&self, device: BluetoothDevice, status: u32, report: Vec<u8>) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnProtocolMode")]
+    fn on_protocol_mode(&self, device: BluetoothDevice, status: u32, mode: HidProtocolMode) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnHandshake")]
+    fn on_handshake(&self, device: BluetoothDevice, status: u32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnVirtualUnplug")]
+    fn on_virtual_unplug(&self, device: BluetoothDevice, status: u32) {
+        dbus_generated!()
+    }
+}
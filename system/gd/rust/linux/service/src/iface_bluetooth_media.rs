@@ -65,6 +65,11 @@ impl IBluetoothMediaCallback for BluetoothMediaCallbackDBus {
     fn on_absolute_volume_changed(&self, volume: i32) {
         dbus_generated!()
     }
+
+    #[dbus_method("OnA2dpAudioConfigChanged")]
+    fn on_a2dp_audio_config_changed(&self, addr: String, config: A2dpCodecConfig) {
+        dbus_generated!()
+    }
 }
 
 #[allow(dead_code)]
@@ -125,6 +130,21 @@ impl IBluetoothMedia for IBluetoothMediaDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("SetCodecPreference")]
+    fn set_codec_preference(&mut self, device: String, codec_type: i32) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetA2dpAudioConfig")]
+    fn get_a2dp_audio_config(&self, device: String) -> A2dpCodecConfig {
+        dbus_generated!()
+    }
+
+    #[dbus_method("IsAbsoluteVolumeSupported")]
+    fn is_absolute_volume_supported(&self) -> bool {
+        dbus_generated!()
+    }
+
     #[dbus_method("StartAudioRequest")]
     fn start_audio_request(&mut self) {
         dbus_generated!()
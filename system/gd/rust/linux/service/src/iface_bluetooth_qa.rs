@@ -0,0 +1,188 @@
+use bt_topshim::btif::Uuid128Bit;
+
+use btstack::bluetooth_qa::{
+    ControllerInfo, ControllerListKind, ControllerLists, GattTestCommand, IBluetoothQA,
+    IBluetoothQACallback, LeTestMode, LeTestPayload, LeTestResult, QAScanMode,
+};
+use btstack::error::BtError;
+use btstack::RPCProxy;
+
+use dbus::arg::RefArg;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_propmap, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, impl_dbus_arg_enum, DisconnectWatcher};
+
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg, RefArgToRust};
+
+impl_dbus_arg_enum!(LeTestMode);
+impl_dbus_arg_enum!(LeTestPayload);
+impl_dbus_arg_enum!(GattTestCommand);
+impl_dbus_arg_enum!(QAScanMode);
+impl_dbus_arg_enum!(ControllerListKind);
+
+#[dbus_propmap(LeTestResult)]
+pub struct LeTestResultDBus {
+    mode: LeTestMode,
+    channel: u8,
+    in_progress: bool,
+    status: u32,
+    num_packets: u16,
+    rssi: i32,
+}
+
+#[dbus_propmap(ControllerInfo)]
+pub struct ControllerInfoDBus {
+    hci_version: u8,
+    hci_revision: u16,
+    lmp_version: u8,
+    lmp_subversion: u16,
+    manufacturer: u16,
+    le_features: u64,
+    secure_connections: bool,
+    simultaneous_le_bredr: bool,
+}
+
+#[dbus_propmap(ControllerLists)]
+pub struct ControllerListsDBus {
+    filter_accept_list: Vec<String>,
+    resolving_list: Vec<String>,
+    periodic_advertiser_list: Vec<String>,
+}
+
+#[allow(dead_code)]
+struct IBluetoothQADBus {}
+
+#[generate_dbus_exporter(export_bluetooth_qa_dbus_obj, "org.chromium.bluetooth.BluetoothQA")]
+impl IBluetoothQA for IBluetoothQADBus {
+    #[dbus_method("RegisterCallback")]
+    fn register_callback(&mut self, callback: Box<dyn IBluetoothQACallback + Send>) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterCallback")]
+    fn unregister_callback(&mut self, callback_id: u32) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("EnableDutMode")]
+    fn enable_dut_mode(&mut self, enable: bool) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("IsDutModeEnabled")]
+    fn is_dut_mode_enabled(&self) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SendDutCommand")]
+    fn send_dut_command(&mut self, opcode: u16, params: Vec<u8>) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StartLeTransmitterTest")]
+    fn start_le_transmitter_test(
+        &mut self,
+        channel: u8,
+        data_length: u8,
+        payload: LeTestPayload,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StartLeReceiverTest")]
+    fn start_le_receiver_test(&mut self, channel: u8) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("EndLeTest")]
+    fn end_le_test(&mut self) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetLeTestResult")]
+    fn get_le_test_result(&self) -> LeTestResult {
+        dbus_generated!()
+    }
+
+    #[dbus_method("IsQAEnabled")]
+    fn is_qa_enabled(&self) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SendGattTestCommand")]
+    fn send_gatt_test_command(
+        &mut self,
+        command: GattTestCommand,
+        address: String,
+        uuid: Uuid128Bit,
+        params: Vec<u16>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetScanMode")]
+    fn set_scan_mode(&mut self, mode: QAScanMode, duration: u32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetControllerInfo")]
+    fn get_controller_info(&mut self) -> Result<ControllerInfo, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetControllerLists")]
+    fn get_controller_lists(&mut self) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClearControllerLists")]
+    fn clear_controller_lists(&mut self, kind: ControllerListKind) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StartTxPowerSweep")]
+    fn start_tx_power_sweep(
+        &mut self,
+        advertiser_id: i32,
+        min_level: i32,
+        max_level: i32,
+        step: i32,
+        dwell_ms: i32,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StopTxPowerSweep")]
+    fn stop_tx_power_sweep(&mut self, advertiser_id: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct BluetoothQACallbackDBus {}
+
+#[dbus_proxy_obj(BluetoothQACallback, "org.chromium.bluetooth.BluetoothQACallback")]
+impl IBluetoothQACallback for BluetoothQACallbackDBus {
+    #[dbus_method("OnDutEvent")]
+    fn on_dut_event(&self, opcode: u16, data: Vec<u8>) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnLeTestStatus")]
+    fn on_le_test_status(&self, result: LeTestResult) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnControllerLists")]
+    fn on_controller_lists(&self, lists: ControllerLists) {
+        dbus_generated!()
+    }
+}
@@ -0,0 +1,160 @@
+use bt_topshim::btif::{BtStatus, Uuid128Bit};
+
+use btstack::error::BtError;
+use btstack::socket_manager::{
+    BluetoothSocketConnection, IBluetoothSocketCallback, IBluetoothSocketManager, SocketId,
+};
+use btstack::RPCProxy;
+
+use dbus::arg::RefArg;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_propmap, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, impl_dbus_arg_enum, DisconnectWatcher};
+
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+
+use std::fs::File;
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg, RefArgToRust};
+
+impl_dbus_arg_enum!(BtStatus);
+
+#[dbus_propmap(BluetoothSocketConnection)]
+pub struct BluetoothSocketConnectionDBus {
+    addr: String,
+    channel: i32,
+    max_tx_packet_size: u16,
+    max_rx_packet_size: u16,
+    max_tx_pdu_size: u16,
+    max_rx_pdu_size: u16,
+}
+
+#[allow(dead_code)]
+struct IBluetoothSocketManagerDBus {}
+
+#[generate_dbus_exporter(
+    export_bluetooth_socket_manager_dbus_obj,
+    "org.chromium.bluetooth.SocketManager"
+)]
+impl IBluetoothSocketManager for IBluetoothSocketManagerDBus {
+    #[dbus_method("RegisterCallback")]
+    fn register_callback(&mut self, callback: Box<dyn IBluetoothSocketCallback + Send>) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterCallback")]
+    fn unregister_callback(&mut self, callback_id: u32) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ListenUsingRfcomm")]
+    fn listen_using_rfcomm(
+        &mut self,
+        callback_id: u32,
+        name: String,
+        uuid: Uuid128Bit,
+        secure: bool,
+    ) -> Result<SocketId, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ListenUsingL2capChannel")]
+    fn listen_using_l2cap_channel(
+        &mut self,
+        callback_id: u32,
+        secure: bool,
+    ) -> Result<SocketId, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ConnectRfcomm")]
+    fn connect_rfcomm(
+        &mut self,
+        callback_id: u32,
+        addr: String,
+        uuid: Uuid128Bit,
+        secure: bool,
+    ) -> Result<SocketId, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ConnectL2capChannel")]
+    fn connect_l2cap_channel(
+        &mut self,
+        callback_id: u32,
+        addr: String,
+        psm: i32,
+        secure: bool,
+    ) -> Result<SocketId, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ListenUsingL2capEcfc")]
+    fn listen_using_l2cap_ecfc(
+        &mut self,
+        callback_id: u32,
+        mtu: i32,
+        secure: bool,
+    ) -> Result<SocketId, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ConnectL2capEcfc")]
+    fn connect_l2cap_ecfc(
+        &mut self,
+        callback_id: u32,
+        addr: String,
+        psm: i32,
+        channel_count: i32,
+        mtu: i32,
+        secure: bool,
+    ) -> Result<SocketId, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("Close")]
+    fn close(&mut self, socket_id: SocketId) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct BluetoothSocketCallbackDBus {}
+
+#[dbus_proxy_obj(BluetoothSocketCallback, "org.chromium.bluetooth.SocketCallback")]
+impl IBluetoothSocketCallback for BluetoothSocketCallbackDBus {
+    #[dbus_method("OnListening")]
+    fn on_listening(&self, socket_id: SocketId, channel: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnIncomingConnection")]
+    fn on_incoming_connection(
+        &self,
+        socket_id: SocketId,
+        connection: BluetoothSocketConnection,
+        fd: File,
+    ) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnOutgoingConnection")]
+    fn on_outgoing_connection(
+        &self,
+        socket_id: SocketId,
+        connection: BluetoothSocketConnection,
+        fd: File,
+    ) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnSocketClosed")]
+    fn on_socket_closed(&self, socket_id: SocketId, status: BtStatus) {
+        dbus_generated!()
+    }
+}
@@ -0,0 +1,91 @@
+use btstack::bluetooth_media::{CallState, IBluetoothTelephony, IBluetoothTelephonyCallback};
+use btstack::error::BtError;
+use btstack::RPCProxy;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, impl_dbus_arg_enum, DisconnectWatcher};
+
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg};
+
+impl_dbus_arg_enum!(CallState);
+
+#[allow(dead_code)]
+struct IBluetoothTelephonyDBus {}
+
+#[generate_dbus_exporter(
+    export_bluetooth_telephony_dbus_obj,
+    "org.chromium.bluetooth.BluetoothTelephony"
+)]
+impl IBluetoothTelephony for IBluetoothTelephonyDBus {
+    #[dbus_method("RegisterTelephonyCallback")]
+    fn register_telephony_callback(
+        &mut self,
+        callback: Box<dyn IBluetoothTelephonyCallback + Send>,
+    ) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterTelephonyCallback")]
+    fn unregister_telephony_callback(&mut self, callback_id: u32) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ConnectSco")]
+    fn connect_sco(&mut self, device: String) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("DisconnectSco")]
+    fn disconnect_sco(&mut self, device: String) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("IncomingCall")]
+    fn incoming_call(&mut self, device: String, number: String) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("AnswerCall")]
+    fn answer_call(&mut self, device: String) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("HangupCall")]
+    fn hangup_call(&mut self, device: String) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetCallState")]
+    fn get_call_state(&self, device: String) -> CallState {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetSpeakerVolume")]
+    fn set_speaker_volume(&mut self, device: String, volume: u8) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct BluetoothTelephonyCallbackDBus {}
+
+#[dbus_proxy_obj(BluetoothTelephonyCallback, "org.chromium.bluetooth.BluetoothTelephonyCallback")]
+impl IBluetoothTelephonyCallback for BluetoothTelephonyCallbackDBus {
+    #[dbus_method("OnCallStateChanged")]
+    fn on_call_state_changed(&self, addr: String, state: CallState) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnSpeakerVolumeChanged")]
+    fn on_speaker_volume_changed(&self, addr: String, volume: u8) {
+        dbus_generated!()
+    }
+}
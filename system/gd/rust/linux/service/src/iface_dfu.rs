@@ -0,0 +1,59 @@
+use btstack::address::BtAddress;
+use btstack::dfu::{DfuStatus, IDfu, IDfuCallback};
+use btstack::error::BtError;
+use btstack::RPCProxy;
+
+use dbus::arg::RefArg;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, impl_dbus_arg_enum, DisconnectWatcher};
+
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg, RefArgToRust};
+
+impl_dbus_arg_enum!(DfuStatus);
+
+#[allow(dead_code)]
+struct IDfuDBus {}
+
+#[generate_dbus_exporter(export_dfu_dbus_obj, "org.chromium.bluetooth.Dfu")]
+impl IDfu for IDfuDBus {
+    #[dbus_method("StartDfu")]
+    fn start_dfu(
+        &mut self,
+        addr: BtAddress,
+        init_packet: Vec<u8>,
+        firmware: Vec<u8>,
+        callback: Box<dyn IDfuCallback + Send>,
+    ) -> Result<i32, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("CancelDfu")]
+    fn cancel_dfu(&mut self, dfu_id: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct DfuCallbackDBus {}
+
+#[dbus_proxy_obj(DfuCallback, "org.chromium.bluetooth.DfuCallback")]
+impl IDfuCallback for DfuCallbackDBus {
+    #[dbus_method("OnDfuProgress")]
+    fn on_dfu_progress(&self, dfu_id: i32, bytes_sent: i32, total_bytes: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnDfuFinished")]
+    fn on_dfu_finished(&self, dfu_id: i32, addr: String, status: DfuStatus, message: String) {
+        dbus_generated!()
+    }
+}
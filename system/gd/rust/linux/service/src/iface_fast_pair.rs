@@ -0,0 +1,66 @@
+use btstack::error::BtError;
+use btstack::fast_pair::{IFastPair, IFastPairCallback};
+use btstack::RPCProxy;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, DisconnectWatcher};
+
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg};
+
+#[allow(dead_code)]
+struct IFastPairDBus {}
+
+#[generate_dbus_exporter(export_fast_pair_dbus_obj, "org.chromium.bluetooth.FastPair")]
+impl IFastPair for IFastPairDBus {
+    #[dbus_method("RegisterFastPairCallback")]
+    fn register_fast_pair_callback(&mut self, callback: Box<dyn IFastPairCallback + Send>) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterFastPairCallback")]
+    fn unregister_fast_pair_callback(&mut self, callback_id: u32) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetProviderEnabled")]
+    fn set_provider_enabled(&mut self, enabled: bool) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("IsProviderEnabled")]
+    fn is_provider_enabled(&self) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetAccountKeyCount")]
+    fn get_account_key_count(&self) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClearAccountKeys")]
+    fn clear_account_keys(&mut self) {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct FastPairCallbackDBus {}
+
+#[dbus_proxy_obj(FastPairCallback, "org.chromium.bluetooth.FastPairCallback")]
+impl IFastPairCallback for FastPairCallbackDBus {
+    #[dbus_method("OnKeyBasedPairingRequest")]
+    fn on_key_based_pairing_request(&self, addr: String, accepted: bool) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnAccountKeyAdded")]
+    fn on_account_key_added(&self, addr: String, account_key_count: u32) {
+        dbus_generated!()
+    }
+}
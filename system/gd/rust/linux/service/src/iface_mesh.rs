@@ -0,0 +1,109 @@
+use bt_topshim::btif::Uuid128Bit;
+
+use btstack::address::BtAddress;
+use btstack::error::BtError;
+use btstack::mesh::{
+    IBluetoothMesh, IBluetoothMeshCallback, MeshGattBearer, MeshLinkCloseReason, MeshPduType,
+};
+use btstack::RPCProxy;
+
+use dbus::arg::RefArg;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, impl_dbus_arg_enum, DisconnectWatcher};
+
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg, RefArgToRust};
+
+impl_dbus_arg_enum!(MeshPduType);
+impl_dbus_arg_enum!(MeshGattBearer);
+impl_dbus_arg_enum!(MeshLinkCloseReason);
+
+#[allow(dead_code)]
+struct IBluetoothMeshDBus {}
+
+#[generate_dbus_exporter(export_mesh_dbus_obj, "org.chromium.bluetooth.Mesh")]
+impl IBluetoothMesh for IBluetoothMeshDBus {
+    #[dbus_method("RegisterMeshCallback")]
+    fn register_mesh_callback(&mut self, callback: Box<dyn IBluetoothMeshCallback + Send>) -> u32 {
+        dbus_generated!()
+    }
+
+    #[dbus_method("UnregisterMeshCallback")]
+    fn unregister_mesh_callback(&mut self, callback_id: u32) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StartAdvertisingBearer")]
+    fn start_advertising_bearer(&mut self) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("StopAdvertisingBearer")]
+    fn stop_advertising_bearer(&mut self) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SendAdvertisingPdu")]
+    fn send_advertising_pdu(&mut self, pdu_type: MeshPduType, pdu: Vec<u8>) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OpenAdvertisingLink")]
+    fn open_advertising_link(&mut self, device_uuid: Uuid128Bit) -> Result<i32, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OpenGattLink")]
+    fn open_gatt_link(&mut self, addr: BtAddress, bearer: MeshGattBearer) -> Result<i32, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("CloseLink")]
+    fn close_link(&mut self, link_id: i32, reason: MeshLinkCloseReason) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SendLinkPdu")]
+    fn send_link_pdu(
+        &mut self,
+        link_id: i32,
+        pdu_type: MeshPduType,
+        pdu: Vec<u8>,
+    ) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct BluetoothMeshCallbackDBus {}
+
+#[dbus_proxy_obj(BluetoothMeshCallback, "org.chromium.bluetooth.BluetoothMeshCallback")]
+impl IBluetoothMeshCallback for BluetoothMeshCallbackDBus {
+    #[dbus_method("OnAdvertisingPdu")]
+    fn on_advertising_pdu(&self, addr: String, rssi: i32, pdu_type: MeshPduType, pdu: Vec<u8>) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnLinkOpened")]
+    fn on_link_opened(&self, link_id: i32) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnLinkClosed")]
+    fn on_link_closed(&self, link_id: i32, reason: MeshLinkCloseReason) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnLinkPdu")]
+    fn on_link_pdu(&self, link_id: i32, pdu_type: MeshPduType, pdu: Vec<u8>) {
+        dbus_generated!()
+    }
+}
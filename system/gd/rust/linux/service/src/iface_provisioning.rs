@@ -0,0 +1,68 @@
+use btstack::error::BtError;
+use btstack::provisioning::{IProvisioning, IProvisioningCallback, ProvisioningStatus};
+use btstack::RPCProxy;
+
+use dbus::arg::RefArg;
+
+use dbus::nonblock::SyncConnection;
+use dbus::strings::Path;
+
+use dbus_macros::{dbus_method, dbus_proxy_obj, generate_dbus_exporter};
+
+use dbus_projection::{dbus_generated, impl_dbus_arg_enum, DisconnectWatcher};
+
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+
+use std::sync::Arc;
+
+use crate::dbus_arg::{DBusArg, DBusArgError, DBusErrorArg, RefArgToRust};
+
+impl_dbus_arg_enum!(ProvisioningStatus);
+
+#[allow(dead_code)]
+struct IProvisioningDBus {}
+
+#[generate_dbus_exporter(export_provisioning_dbus_obj, "org.chromium.bluetooth.Provisioning")]
+impl IProvisioning for IProvisioningDBus {
+    #[dbus_method("StartProvisioning")]
+    fn start_provisioning(
+        &mut self,
+        descriptor: String,
+        callback: Box<dyn IProvisioningCallback + Send>,
+    ) -> Result<i32, BtError> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("CancelProvisioning")]
+    fn cancel_provisioning(&mut self, session_id: i32) -> Result<(), BtError> {
+        dbus_generated!()
+    }
+}
+
+#[allow(dead_code)]
+struct ProvisioningCallbackDBus {}
+
+#[dbus_proxy_obj(ProvisioningCallback, "org.chromium.bluetooth.ProvisioningCallback")]
+impl IProvisioningCallback for ProvisioningCallbackDBus {
+    #[dbus_method("OnProvisioningProgress")]
+    fn on_provisioning_progress(
+        &self,
+        session_id: i32,
+        step: i32,
+        total_steps: i32,
+        description: String,
+    ) {
+        dbus_generated!()
+    }
+
+    #[dbus_method("OnProvisioningFinished")]
+    fn on_provisioning_finished(
+        &self,
+        session_id: i32,
+        addr: String,
+        status: ProvisioningStatus,
+        message: String,
+    ) {
+        dbus_generated!()
+    }
+}
@@ -0,0 +1,48 @@
+//! Policy of the D-Bus interfaces exported by the daemon, set with the `disabled_interface`
+//! settings of the policy file of `btstack::policy`.
+
+use btstack::policy::Policy;
+use dbus_projection::InterfacePolicy;
+
+use log::{info, warn};
+
+/// Interface the clients and the manager can't do without, which is always exported.
+const ADAPTER_INTERFACE: &str = "org.chromium.bluetooth.Bluetooth";
+
+/// Returns the policy of the D-Bus interfaces set by `policy`.
+pub fn interface_policy(policy: &Policy) -> InterfacePolicy {
+    let mut interface_policy = InterfacePolicy::default();
+
+    for name in policy.disabled_interfaces() {
+        if name == ADAPTER_INTERFACE {
+            warn!("The D-Bus interface {} can't be disabled", name);
+            continue;
+        }
+        info!("Not exporting the D-Bus interface {}, disabled by policy", name);
+        interface_policy.disable(name);
+    }
+
+    interface_policy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interface_policy() {
+        let path = std::env::temp_dir().join(format!("btadapterd-policy-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "disabled_interface = org.chromium.bluetooth.BluetoothQA\n\
+             disabled_interface = org.chromium.bluetooth.Bluetooth\n",
+        )
+        .unwrap();
+        let policy = interface_policy(&Policy::load(&path));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!policy.is_enabled("org.chromium.bluetooth.BluetoothQA"));
+        assert!(policy.is_enabled(ADAPTER_INTERFACE));
+        assert_eq!(policy.disabled().len(), 1);
+    }
+}
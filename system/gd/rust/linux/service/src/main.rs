@@ -2,26 +2,61 @@ use dbus::{channel::MatchingReceiver, message::MatchRule};
 use dbus_crossroads::Crossroads;
 use dbus_tokio::connection;
 use futures::future;
-use log::LevelFilter;
+use log::{warn, LevelFilter};
+use std::collections::HashSet;
 use std::error::Error;
 use std::sync::{Arc, Mutex};
 use syslog::{BasicLogger, Facility, Formatter3164};
 
 use bt_topshim::{btif::get_btinterface, topstack};
 use btstack::{
+    battery_manager::BatteryManager,
     bluetooth::{get_bt_dispatcher, Bluetooth, IBluetooth},
+    bluetooth_admin::BluetoothAdmin,
+    bluetooth_debug::BluetoothDebug,
     bluetooth_gatt::BluetoothGatt,
+    bluetooth_hid::BluetoothHid,
+    bluetooth_le_audio::BluetoothLeAudio,
     bluetooth_media::BluetoothMedia,
+    bluetooth_qa::BluetoothQA,
+    dfu::DfuManager,
+    fast_pair::FastPairManager,
+    mesh::MeshManager,
+    policy::{Policy, POLICY_FILE},
+    provisioning::ProvisioningManager,
+    socket_manager::BluetoothSocketManager,
     suspend::Suspend,
     Stack,
 };
 use dbus_projection::DisconnectWatcher;
 
+mod advertising_restore;
 mod dbus_arg;
+mod iface_battery_manager;
 mod iface_bluetooth;
+mod iface_bluetooth_admin;
+mod iface_bluetooth_debug;
 mod iface_bluetooth_gatt;
+mod iface_bluetooth_hid;
+mod iface_bluetooth_le_audio;
 mod iface_bluetooth_media;
+mod iface_bluetooth_qa;
+mod iface_bluetooth_socket_manager;
+mod iface_bluetooth_telephony;
+mod iface_dfu;
+mod iface_fast_pair;
+mod iface_mesh;
+mod iface_provisioning;
 mod iface_suspend;
+mod interface_policy;
+mod scan_permission;
+mod sd_notify;
+#[cfg(feature = "uds")]
+mod uds;
+
+// The protobuf messages of the UDS frontend, in the `bluetooth_ipc` module.
+#[cfg(feature = "uds")]
+include!(concat!(env!("OUT_DIR"), "/proto_out/mod.rs"));
 
 const DBUS_SERVICE_NAME: &str = "org.chromium.bluetooth";
 
@@ -40,11 +75,74 @@ fn get_adapter_index(args: &Vec<String>) -> i32 {
     0
 }
 
+/// Check command line arguments for the RSSI calibration offset of the platform
+/// (--rssi-calibration-offset=N). If no offset is set, default to 0.
+fn get_rssi_calibration_offset(args: &Vec<String>) -> i32 {
+    for arg in args {
+        if arg.starts_with("--rssi-calibration-offset=") {
+            let num = (&arg[26..]).parse::<i32>();
+            if num.is_ok() {
+                return num.unwrap();
+            }
+        }
+    }
+
+    0
+}
+
+/// Check command line arguments for the built-in Current Time Service (--enable-time-service).
+/// The service is disabled by default.
+fn get_time_service_enabled(args: &Vec<String>) -> bool {
+    args.iter().any(|arg| arg == "--enable-time-service")
+}
+
+/// Check command line arguments for the QA commands of the manual and certification tests
+/// (--enable-qa-commands). The commands are disabled by default.
+fn get_qa_commands_enabled(args: &Vec<String>) -> bool {
+    args.iter().any(|arg| arg == "--enable-qa-commands")
+}
+
+/// Check command line arguments for the path of the unix domain socket serving the clients
+/// without D-Bus (--uds-socket=PATH). The socket is not served by default.
+fn get_uds_socket_path(args: &Vec<String>) -> Option<String> {
+    args.iter().find_map(|arg| arg.strip_prefix("--uds-socket=")).map(String::from)
+}
+
+/// Check command line arguments for running without D-Bus (--no-dbus), in which case the
+/// clients are only served by the UDS frontend.
+fn get_dbus_disabled(args: &Vec<String>) -> bool {
+    args.iter().any(|arg| arg == "--no-dbus")
+}
+
+/// Checks that the clients can be served by the frontends asked for on the command line.
+fn check_frontends(
+    dbus_disabled: bool,
+    uds_socket_path: &Option<String>,
+    uds_built: bool,
+) -> Result<(), &'static str> {
+    if uds_socket_path.is_some() && !uds_built {
+        return Err("--uds-socket needs the UDS frontend, which is not built");
+    }
+    if dbus_disabled && uds_socket_path.is_none() {
+        return Err("--no-dbus needs --uds-socket");
+    }
+    Ok(())
+}
+
+/// Check command line arguments for the users whose clients may scan
+/// (--scan-permitted-users=UID[,UID...]), on the platforms tying LE scanning to the location
+/// permission. Every client may scan if not set.
+fn get_scan_permitted_users(args: &Vec<String>) -> Option<HashSet<u32>> {
+    args.iter()
+        .find_map(|arg| arg.strip_prefix("--scan-permitted-users="))
+        .map(|users| users.split(',').filter_map(|uid| uid.trim().parse().ok()).collect())
+}
+
 fn make_object_name(idx: i32, name: &str) -> String {
     String::from(format!("/org/chromium/bluetooth/hci{}/{}", idx, name))
 }
 
-/// Runs the Bluetooth daemon serving D-Bus IPC.
+/// Runs the Bluetooth daemon serving D-Bus IPC, and the UDS frontend if enabled.
 fn main() -> Result<(), Box<dyn Error>> {
     let formatter = Formatter3164 {
         facility: Facility::LOG_USER,
@@ -59,46 +157,56 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let (tx, rx) = Stack::create_channel();
 
+    // Args don't include arg[0] which is the binary name
+    let all_args = std::env::args().collect::<Vec<String>>();
+    let args = all_args[1..].to_vec();
+
+    let adapter_index = get_adapter_index(&args);
+
     let intf = Arc::new(Mutex::new(get_btinterface().unwrap()));
     let suspend = Arc::new(Mutex::new(Box::new(Suspend::new(tx.clone()))));
+    let bluetooth_qa = Arc::new(Mutex::new(Box::new(BluetoothQA::new(tx.clone(), intf.clone()))));
     let bluetooth_gatt = Arc::new(Mutex::new(Box::new(BluetoothGatt::new(intf.clone()))));
     let bluetooth_media =
         Arc::new(Mutex::new(Box::new(BluetoothMedia::new(tx.clone(), intf.clone()))));
+    let bluetooth_le_audio =
+        Arc::new(Mutex::new(Box::new(BluetoothLeAudio::new(tx.clone(), intf.clone()))));
+    let bluetooth_socket_manager =
+        Arc::new(Mutex::new(Box::new(BluetoothSocketManager::new(tx.clone(), intf.clone()))));
     let bluetooth = Arc::new(Mutex::new(Box::new(Bluetooth::new(
         tx.clone(),
         intf.clone(),
         bluetooth_media.clone(),
+        adapter_index,
     ))));
+    let bluetooth_admin = Arc::new(Mutex::new(Box::new(BluetoothAdmin::new(tx.clone()))));
+    let battery_manager = Arc::new(Mutex::new(Box::new(BatteryManager::new(tx.clone()))));
+    let bluetooth_hid = Arc::new(Mutex::new(Box::new(BluetoothHid::new(tx.clone()))));
+    let bluetooth_debug = Arc::new(Mutex::new(Box::new(BluetoothDebug::new(tx.clone()))));
+    let provisioning = Arc::new(Mutex::new(Box::new(ProvisioningManager::new(tx.clone()))));
+    let fast_pair = Arc::new(Mutex::new(Box::new(FastPairManager::new(tx.clone()))));
+    let mesh = Arc::new(Mutex::new(Box::new(MeshManager::new(tx.clone()))));
+    let dfu = Arc::new(Mutex::new(Box::new(DfuManager::new(tx.clone()))));
 
-    // Args don't include arg[0] which is the binary name
-    let all_args = std::env::args().collect::<Vec<String>>();
-    let args = all_args[1..].to_vec();
-
-    let adapter_index = get_adapter_index(&args);
+    bluetooth_gatt.lock().unwrap().set_rssi_calibration_offset(get_rssi_calibration_offset(&args));
+    bluetooth_gatt.lock().unwrap().set_time_service_enabled(get_time_service_enabled(&args));
+    bluetooth_qa.lock().unwrap().set_commands_enabled(get_qa_commands_enabled(&args));
+    let dbus_disabled = get_dbus_disabled(&args);
+    if let Some(users) = get_scan_permitted_users(&args) {
+        // The users are told by the bus, failing closed rather than letting every client scan.
+        if dbus_disabled {
+            return Err("--scan-permitted-users needs D-Bus".into());
+        }
+        let checker = scan_permission::UserScanPermissionChecker::new(users)?;
+        bluetooth_gatt.lock().unwrap().set_scan_permission_checker(Box::new(checker));
+    }
+    let policy = Policy::load(POLICY_FILE);
+    let interface_policy = interface_policy::interface_policy(&policy);
+    bluetooth_gatt.lock().unwrap().set_policy(policy);
+    let uds_socket_path = get_uds_socket_path(&args);
+    check_frontends(dbus_disabled, &uds_socket_path, cfg!(feature = "uds"))?;
 
     topstack::get_runtime().block_on(async {
-        // Connect to D-Bus system bus.
-        let (resource, conn) = connection::new_system_sync()?;
-
-        // The `resource` is a task that should be spawned onto a tokio compatible
-        // reactor ASAP. If the resource ever finishes, we lost connection to D-Bus.
-        tokio::spawn(async {
-            let err = resource.await;
-            panic!("Lost connection to D-Bus: {}", err);
-        });
-
-        // Request a service name and quit if not able to.
-        conn.request_name(DBUS_SERVICE_NAME, false, true, false).await?;
-
-        // Prepare D-Bus interfaces.
-        let mut cr = Crossroads::new();
-        cr.set_async_support(Some((
-            conn.clone(),
-            Box::new(|x| {
-                tokio::spawn(x);
-            }),
-        )));
-
         // Run the stack main dispatch loop.
         topstack::get_runtime().spawn(Stack::dispatch(
             rx,
@@ -106,68 +214,263 @@ fn main() -> Result<(), Box<dyn Error>> {
             bluetooth_gatt.clone(),
             bluetooth_media.clone(),
             suspend.clone(),
+            bluetooth_qa.clone(),
+            bluetooth_le_audio.clone(),
+            bluetooth_socket_manager.clone(),
+            bluetooth_admin.clone(),
+            battery_manager.clone(),
+            bluetooth_hid.clone(),
+            bluetooth_debug.clone(),
+            provisioning.clone(),
+            fast_pair.clone(),
+            mesh.clone(),
+            dfu.clone(),
         ));
 
-        // Set up the disconnect watcher to monitor client disconnects.
-        let disconnect_watcher = Arc::new(Mutex::new(DisconnectWatcher::new()));
-        disconnect_watcher.lock().unwrap().setup_watch(conn.clone()).await;
+        // Connect to D-Bus and export the interfaces, unless only the UDS frontend is served.
+        let dbus = if dbus_disabled {
+            None
+        } else {
+            // Connect to D-Bus system bus.
+            let (resource, conn) = connection::new_system_sync()?;
 
-        // Register D-Bus method handlers of IBluetooth.
-        iface_bluetooth::export_bluetooth_dbus_obj(
-            make_object_name(adapter_index, "adapter"),
-            conn.clone(),
-            &mut cr,
-            bluetooth.clone(),
-            disconnect_watcher.clone(),
-        );
-        // Register D-Bus method handlers of IBluetoothGatt.
-        iface_bluetooth_gatt::export_bluetooth_gatt_dbus_obj(
-            make_object_name(adapter_index, "gatt"),
-            conn.clone(),
-            &mut cr,
-            bluetooth_gatt.clone(),
-            disconnect_watcher.clone(),
-        );
+            // The `resource` is a task that should be spawned onto a tokio compatible
+            // reactor ASAP. If the resource ever finishes, we lost connection to D-Bus.
+            tokio::spawn(async {
+                let err = resource.await;
+                panic!("Lost connection to D-Bus: {}", err);
+            });
 
-        iface_bluetooth_media::export_bluetooth_media_dbus_obj(
-            make_object_name(adapter_index, "media"),
-            conn.clone(),
-            &mut cr,
-            bluetooth_media.clone(),
-            disconnect_watcher.clone(),
-        );
+            // Prepare D-Bus interfaces.
+            let mut cr = Crossroads::new();
+            cr.set_async_support(Some((
+                conn.clone(),
+                Box::new(|x| {
+                    tokio::spawn(x);
+                }),
+            )));
 
-        iface_suspend::export_suspend_dbus_obj(
-            make_object_name(adapter_index, "suspend"),
-            conn.clone(),
-            &mut cr,
-            suspend,
-            disconnect_watcher.clone(),
-        );
+            // Set up the disconnect watcher to monitor client disconnects.
+            let disconnect_watcher = Arc::new(Mutex::new(DisconnectWatcher::new()));
+            disconnect_watcher.lock().unwrap().setup_watch(conn.clone()).await;
+
+            // The advertising sets saved by the previous daemon are re-created for the clients
+            // still on the bus.
+            let restorer = advertising_restore::DBusAdvertisingCallbackRestorer::new(
+                conn.clone(),
+                disconnect_watcher.clone(),
+            )?;
+            bluetooth_gatt.lock().unwrap().set_advertising_callback_restorer(Box::new(restorer));
+
+            // Register D-Bus method handlers of IBluetooth.
+            iface_bluetooth::export_bluetooth_dbus_obj(
+                make_object_name(adapter_index, "adapter"),
+                conn.clone(),
+                &mut cr,
+                bluetooth.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+            // Register D-Bus method handlers of IBluetoothGatt.
+            iface_bluetooth_gatt::export_bluetooth_gatt_dbus_obj(
+                make_object_name(adapter_index, "gatt"),
+                conn.clone(),
+                &mut cr,
+                bluetooth_gatt.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_bluetooth_media::export_bluetooth_media_dbus_obj(
+                make_object_name(adapter_index, "media"),
+                conn.clone(),
+                &mut cr,
+                bluetooth_media.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_bluetooth_telephony::export_bluetooth_telephony_dbus_obj(
+                make_object_name(adapter_index, "telephony"),
+                conn.clone(),
+                &mut cr,
+                bluetooth_media.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_bluetooth_le_audio::export_bluetooth_le_audio_dbus_obj(
+                make_object_name(adapter_index, "le_audio"),
+                conn.clone(),
+                &mut cr,
+                bluetooth_le_audio.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_bluetooth_socket_manager::export_bluetooth_socket_manager_dbus_obj(
+                make_object_name(adapter_index, "socket_manager"),
+                conn.clone(),
+                &mut cr,
+                bluetooth_socket_manager.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_bluetooth_admin::export_bluetooth_admin_dbus_obj(
+                make_object_name(adapter_index, "admin"),
+                conn.clone(),
+                &mut cr,
+                bluetooth_admin.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_battery_manager::export_battery_manager_dbus_obj(
+                make_object_name(adapter_index, "battery_manager"),
+                conn.clone(),
+                &mut cr,
+                battery_manager.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_bluetooth_hid::export_bluetooth_hid_dbus_obj(
+                make_object_name(adapter_index, "hid"),
+                conn.clone(),
+                &mut cr,
+                bluetooth_hid.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_bluetooth_debug::export_bluetooth_debug_dbus_obj(
+                make_object_name(adapter_index, "debug"),
+                conn.clone(),
+                &mut cr,
+                bluetooth_debug.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_suspend::export_suspend_dbus_obj(
+                make_object_name(adapter_index, "suspend"),
+                conn.clone(),
+                &mut cr,
+                suspend.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_provisioning::export_provisioning_dbus_obj(
+                make_object_name(adapter_index, "provisioning"),
+                conn.clone(),
+                &mut cr,
+                provisioning.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_fast_pair::export_fast_pair_dbus_obj(
+                make_object_name(adapter_index, "fast_pair"),
+                conn.clone(),
+                &mut cr,
+                fast_pair.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_mesh::export_mesh_dbus_obj(
+                make_object_name(adapter_index, "mesh"),
+                conn.clone(),
+                &mut cr,
+                mesh.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_dfu::export_dfu_dbus_obj(
+                make_object_name(adapter_index, "dfu"),
+                conn.clone(),
+                &mut cr,
+                dfu.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            iface_bluetooth_qa::export_bluetooth_qa_dbus_obj(
+                make_object_name(adapter_index, "qa"),
+                conn.clone(),
+                &mut cr,
+                bluetooth_qa.clone(),
+                disconnect_watcher.clone(),
+                &interface_policy,
+            );
+
+            Some((conn, cr))
+        };
 
         // Hold locks and initialize all interfaces. This must be done AFTER DBus is
         // initialized so DBus can properly enforce user policies.
         {
+            bluetooth_debug.lock().unwrap().restore_snoop_log_mode();
             intf.lock().unwrap().initialize(get_bt_dispatcher(tx.clone()), args);
 
             bluetooth_media.lock().unwrap().set_adapter(bluetooth.clone());
+            bluetooth_gatt.lock().unwrap().set_adapter(bluetooth.clone());
+            bluetooth_admin.lock().unwrap().set_adapter(bluetooth.clone());
+            bluetooth_hid.lock().unwrap().set_adapter(bluetooth.clone());
+            bluetooth_qa.lock().unwrap().set_adapter(bluetooth.clone());
+            bluetooth_qa.lock().unwrap().set_gatt(bluetooth_gatt.clone());
+            bluetooth_debug.lock().unwrap().set_adapter(bluetooth.clone());
+            bluetooth_debug.lock().unwrap().set_gatt(bluetooth_gatt.clone());
+            suspend.lock().unwrap().set_adapter(bluetooth.clone());
+            suspend.lock().unwrap().set_gatt(bluetooth_gatt.clone());
 
             let mut bluetooth = bluetooth.lock().unwrap();
             bluetooth.init_profiles();
             bluetooth.enable();
 
             bluetooth_gatt.lock().unwrap().init_profiles(tx.clone());
+            bluetooth_le_audio.lock().unwrap().init_profiles();
+            bluetooth_socket_manager.lock().unwrap().initialize();
         }
 
-        // Start listening on DBus after exporting interfaces and initializing
-        // all bluetooth objects.
-        conn.start_receive(
-            MatchRule::new_method_call(),
-            Box::new(move |msg, conn| {
-                cr.handle_message(msg, conn).unwrap();
-                true
-            }),
-        );
+        // Registers GATT clients, servers and adapter callbacks, so the locks above must be
+        // released.
+        battery_manager.lock().unwrap().init(bluetooth.clone(), bluetooth_gatt.clone());
+        provisioning.lock().unwrap().init(bluetooth_gatt.clone());
+        fast_pair.lock().unwrap().init(bluetooth.clone(), bluetooth_gatt.clone());
+        mesh.lock().unwrap().init(bluetooth_gatt.clone());
+        dfu.lock().unwrap().init(bluetooth_gatt.clone());
+
+        // Serve the clients without D-Bus on a unix domain socket.
+        #[cfg(feature = "uds")]
+        if let Some(path) = uds_socket_path {
+            uds::start_uds_frontend(path, bluetooth.clone(), bluetooth_gatt.clone())?;
+        }
+
+        if let Some((conn, mut cr)) = dbus {
+            // Start listening on DBus after exporting interfaces and initializing
+            // all bluetooth objects.
+            conn.start_receive(
+                MatchRule::new_method_call(),
+                Box::new(move |msg, conn| {
+                    cr.handle_message(msg, conn).unwrap();
+                    true
+                }),
+            );
+
+            // Request a service name and quit if not able to. This is done last so that the calls
+            // made by clients while the daemon is starting are held by the bus (with D-Bus
+            // activation) instead of reaching a daemon which can't serve them yet.
+            conn.request_name(DBUS_SERVICE_NAME, false, true, false).await?;
+        }
+
+        // Tell the service manager that the daemon is ready.
+        if let Err(e) = sd_notify::notify("READY=1") {
+            warn!("Failed to notify readiness: {}", e);
+        }
 
         // Serve clients forever.
         future::pending::<()>().await;
@@ -177,7 +480,12 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 #[cfg(test)]
 mod tests {
-    use crate::get_adapter_index;
+    use crate::{
+        check_frontends, get_adapter_index, get_dbus_disabled, get_qa_commands_enabled,
+        get_rssi_calibration_offset, get_scan_permitted_users, get_time_service_enabled,
+        get_uds_socket_path,
+    };
+    use std::collections::HashSet;
 
     #[test]
     fn device_index_parsed() {
@@ -191,4 +499,76 @@ mod tests {
         assert_eq!(get_adapter_index(&vec! {"--hci=12".to_string()}), 12);
         assert_eq!(get_adapter_index(&vec! {"--hci=1".to_string(), "--hci=2".to_string()}), 1);
     }
+
+    #[test]
+    fn rssi_calibration_offset_parsed() {
+        assert_eq!(get_rssi_calibration_offset(&vec! {}), 0);
+        assert_eq!(
+            get_rssi_calibration_offset(&vec! {"--rssi-calibration-offset=x".to_string()}),
+            0
+        );
+        assert_eq!(
+            get_rssi_calibration_offset(&vec! {"--rssi-calibration-offset=-3".to_string()}),
+            -3
+        );
+        assert_eq!(
+            get_rssi_calibration_offset(&vec! {
+                "--hci=1".to_string(),
+                "--rssi-calibration-offset=5".to_string()
+            }),
+            5
+        );
+    }
+
+    #[test]
+    fn time_service_enabled_parsed() {
+        assert!(!get_time_service_enabled(&vec! {}));
+        assert!(!get_time_service_enabled(&vec! {"--enable-time-service=0".to_string()}));
+        assert!(get_time_service_enabled(&vec! {
+            "--hci=1".to_string(),
+            "--enable-time-service".to_string()
+        }));
+    }
+
+    #[test]
+    fn uds_frontend_parsed() {
+        assert_eq!(get_uds_socket_path(&vec! {}), None);
+        assert_eq!(
+            get_uds_socket_path(&vec! {"--uds-socket=/run/bluetooth/ipc".to_string()}),
+            Some("/run/bluetooth/ipc".to_string())
+        );
+        assert!(!get_dbus_disabled(&vec! {"--uds-socket=/run/bluetooth/ipc".to_string()}));
+        assert!(get_dbus_disabled(&vec! {"--no-dbus".to_string()}));
+    }
+
+    #[test]
+    fn frontends_checked() {
+        let path = Some("/run/bluetooth/ipc".to_string());
+        assert!(check_frontends(false, &None, false).is_ok());
+        assert!(check_frontends(false, &path, true).is_ok());
+        assert!(check_frontends(true, &path, true).is_ok());
+        assert!(check_frontends(true, &None, true).is_err());
+        assert!(check_frontends(true, &path, false).is_err());
+        assert!(check_frontends(false, &path, false).is_err());
+    }
+
+    #[test]
+    fn qa_commands_enabled_parsed() {
+        assert!(!get_qa_commands_enabled(&vec! {}));
+        assert!(!get_qa_commands_enabled(&vec! {"--enable-qa-commands=1".to_string()}));
+        assert!(get_qa_commands_enabled(&vec! {"--enable-qa-commands".to_string()}));
+    }
+
+    #[test]
+    fn scan_permitted_users_parsed() {
+        assert_eq!(get_scan_permitted_users(&vec! {}), None);
+        assert_eq!(
+            get_scan_permitted_users(&vec! {"--scan-permitted-users=1000, 20104,x".to_string()}),
+            Some(vec![1000, 20104].into_iter().collect())
+        );
+        assert_eq!(
+            get_scan_permitted_users(&vec! {"--scan-permitted-users=".to_string()}),
+            Some(HashSet::new())
+        );
+    }
 }
@@ -0,0 +1,72 @@
+//! Scan permission of the D-Bus clients, for the platforms tying LE scanning to the location
+//! permission. Only the clients running as one of the users given with
+//! `--scan-permitted-users=UID[,UID...]` may scan, the users being told by the bus.
+
+use btstack::bluetooth_gatt::IScanPermissionChecker;
+use dbus::blocking::SyncConnection;
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Time given to the bus to tell the user of a client.
+const BUS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Decisions kept before starting over, most clients of the oldest ones being gone by then.
+const MAX_DECISIONS: usize = 256;
+
+/// Permits the clients running as one of the permitted users.
+pub struct UserScanPermissionChecker {
+    conn: SyncConnection,
+    permitted_users: HashSet<u32>,
+    // Decisions by unique bus name, which are never reused, so that the bus is only asked once
+    // per client.
+    decisions: Mutex<HashMap<String, bool>>,
+}
+
+impl UserScanPermissionChecker {
+    pub fn new(permitted_users: HashSet<u32>) -> Result<UserScanPermissionChecker, dbus::Error> {
+        Ok(UserScanPermissionChecker {
+            conn: SyncConnection::new_system()?,
+            permitted_users,
+            decisions: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn user_of(&self, sender: &str) -> Option<u32> {
+        let proxy =
+            self.conn.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", BUS_TIMEOUT);
+        match proxy.method_call("org.freedesktop.DBus", "GetConnectionUnixUser", (sender,)) {
+            Ok((uid,)) => Some(uid),
+            Err(e) => {
+                warn!("Failed to get the user of {}: {}", sender, e);
+                None
+            }
+        }
+    }
+}
+
+impl IScanPermissionChecker for UserScanPermissionChecker {
+    fn is_scan_permitted(&self, sender: &String) -> bool {
+        // The scanners of the daemon itself have no sender.
+        if sender.is_empty() {
+            return true;
+        }
+
+        let mut decisions = self.decisions.lock().unwrap();
+        if let Some(permitted) = decisions.get(sender) {
+            return *permitted;
+        }
+
+        // A client whose user is unknown is denied, and the bus asked again the next time.
+        let permitted = match self.user_of(sender) {
+            Some(uid) => self.permitted_users.contains(&uid),
+            None => return false,
+        };
+        if decisions.len() >= MAX_DECISIONS {
+            decisions.clear();
+        }
+        decisions.insert(sender.clone(), permitted);
+        permitted
+    }
+}
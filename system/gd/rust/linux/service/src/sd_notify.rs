@@ -0,0 +1,53 @@
+//! Minimal implementation of the systemd service notification protocol (sd_notify).
+
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+
+/// Sends `state` (e.g. "READY=1") to the service manager.
+///
+/// Returns false without error if the daemon is not run by a service manager expecting
+/// notifications.
+pub fn notify(state: &str) -> io::Result<bool> {
+    match std::env::var_os(NOTIFY_SOCKET_ENV) {
+        Some(path) => notify_socket(Path::new(&path), state),
+        None => Ok(false),
+    }
+}
+
+fn notify_socket(path: &Path, state: &str) -> io::Result<bool> {
+    // Sockets in the abstract namespace (starting with '@') can't be addressed by std.
+    if path.to_str().map_or(false, |p| p.starts_with('@')) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "abstract notification sockets are not supported",
+        ));
+    }
+
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notify_socket_sends_state() {
+        let path = std::env::temp_dir().join(format!("btadapterd-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        assert!(notify_socket(&path, "READY=1").unwrap());
+
+        let mut buf = [0u8; 32];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(b"READY=1", &buf[..len]);
+
+        let _ = std::fs::remove_file(&path);
+        assert!(notify_socket(Path::new("@abstract"), "READY=1").is_err());
+    }
+}
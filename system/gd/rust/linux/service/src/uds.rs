@@ -0,0 +1,2261 @@
+//! Unix domain socket frontend, serving the adapter, GATT client, GATT server, scanner and
+//! advertiser APIs to the clients of the environments without D-Bus, such as containers and
+//! minimal images.
+//!
+//! The requests call the same btstack objects as the D-Bus frontend, and the callbacks of the
+//! clients are registered with the stack like D-Bus proxies, their events being relayed to the
+//! connection that registered them. See proto/bluetooth_ipc.proto for the protocol.
+//!
+//! Only the root user and the clients running as the user or the group of the daemon are
+//! served, as the permissions of the socket say.
+
+use bt_topshim::btif::{BtSspVariant, BtTransport, Uuid128Bit};
+use bt_topshim::profiles::gatt::GattStatus;
+
+use btstack::address::BtAddress;
+use btstack::bluetooth::{
+    Bluetooth, BluetoothDevice, IBluetooth, IBluetoothCallback, IBluetoothConnectionCallback,
+    RadioActivity,
+};
+use btstack::bluetooth_adv::{
+    AdvertiseData, AdvertisingSetParameters, AdvertisingStatus, AdvertisingTerminationReason,
+    IAdvertisingSetCallback,
+};
+use btstack::bluetooth_gatt::{
+    BatchScanResult, BluetoothGatt, BluetoothGattCharacteristic, BluetoothGattDescriptor,
+    BluetoothGattService, CharacteristicReadResult, GattHandleValue, GattWriteType, IBluetoothGatt,
+    IBluetoothGattCallback, IBluetoothGattServerCallback, IScannerCallback, LePhy, ScanFilter,
+    ScanResult, ScanSettings, ScanType,
+};
+use btstack::error::{BtError, BtResult};
+use btstack::gatt_conformance::ConformanceIssue;
+use btstack::uuid::BtUuid;
+use btstack::RPCProxy;
+
+use log::{debug, info, warn};
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+use protobuf::{Message, RepeatedField};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::ErrorKind;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::unix::OwnedReadHalf;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::sync::Notify;
+
+use crate::bluetooth_ipc::{self, *};
+
+/// Largest message accepted from a client.
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Messages queued for a client before it is disconnected for not reading them.
+const MAX_QUEUED_MESSAGES: usize = 1024;
+
+/// The callback ids are allocated above the ids of the D-Bus disconnect watcher, as the stack
+/// stores the callbacks of both frontends together.
+const CALLBACK_ID_BASE: u32 = 1 << 31;
+
+static NEXT_CALLBACK_ID: AtomicU32 = AtomicU32::new(CALLBACK_ID_BASE);
+
+/// State of a client connection, shared with the callbacks registered over it.
+struct Connection {
+    // Encoded messages to write to the client, None once the client is being disconnected.
+    writer: Option<Sender<Vec<u8>>>,
+    // Notified when the messages cannot be queued anymore, to disconnect the client.
+    overflow: Arc<Notify>,
+    // Observers of the disconnection of the callbacks, by callback id.
+    disconnect_observers: HashMap<u32, Box<dyn Fn(u32) + Send>>,
+    // GATT clients registered over the connection by callback id, with their client id once
+    // the stack has registered them. They are unregistered once the connection is closed.
+    gatt_clients: HashMap<u32, Option<i32>>,
+    // GATT servers registered over the connection, as the GATT clients.
+    gatt_servers: HashMap<u32, Option<i32>>,
+    // Scanners registered over the connection, as the GATT clients. The stack unregisters them
+    // along with their callback.
+    scanners: HashMap<u32, Option<i32>>,
+    // Advertising sets started over the connection, stopped by the stack along with their
+    // callback.
+    advertising_sets: HashSet<i32>,
+    bluetooth_gatt: Arc<Mutex<Box<BluetoothGatt>>>,
+}
+
+impl Connection {
+    /// Queues a message, or disconnects the client rather than dropping it.
+    fn send(&mut self, message: ServerMessage) {
+        let frame = match encode_frame(&message) {
+            Some(frame) => frame,
+            None => return,
+        };
+        let writer = match &self.writer {
+            Some(writer) => writer,
+            None => return,
+        };
+
+        match writer.try_send(frame) {
+            Ok(()) => return,
+            Err(TrySendError::Full(_)) => {
+                warn!("Disconnecting a UDS client not reading its messages");
+            }
+            Err(TrySendError::Closed(_)) => (),
+        }
+        self.writer = None;
+        self.overflow.notify_one();
+    }
+}
+
+/// Whether one of `apps`, by callback id, was registered by the stack with `id`.
+fn is_registered(apps: &HashMap<u32, Option<i32>>, id: i32) -> bool {
+    apps.values().any(|app_id| *app_id == Some(id))
+}
+
+/// Records the id given by the stack to the app of `apps` registered with `callback_id`. False if
+/// the app was unregistered, or its connection closed, while the stack was registering it.
+fn record_registration(
+    apps: &mut HashMap<u32, Option<i32>>,
+    callback_id: u32,
+    status: i32,
+    id: i32,
+) -> bool {
+    if status != 0 {
+        apps.remove(&callback_id);
+        return true;
+    }
+
+    match apps.get_mut(&callback_id) {
+        Some(registration) => {
+            *registration = Some(id);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Frames a message by its length, None if it cannot be encoded.
+fn encode_frame(message: &ServerMessage) -> Option<Vec<u8>> {
+    match message.write_to_bytes() {
+        Ok(bytes) => {
+            let mut frame = (bytes.len() as u32).to_le_bytes().to_vec();
+            frame.extend(bytes);
+            Some(frame)
+        }
+        Err(e) => {
+            warn!("Failed to encode a message: {}", e);
+            None
+        }
+    }
+}
+
+/// Callback of the stack relaying its events to a client.
+struct UdsCallback {
+    id: u32,
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl UdsCallback {
+    fn new(connection: Arc<Mutex<Connection>>) -> UdsCallback {
+        UdsCallback { id: NEXT_CALLBACK_ID.fetch_add(1, Ordering::Relaxed), connection }
+    }
+
+    fn send_event<F: FnOnce(&mut Event)>(&self, f: F) {
+        let mut event = Event::new();
+        event.set_callback_id(self.id);
+        f(&mut event);
+
+        let mut message = ServerMessage::new();
+        message.set_event(event);
+        self.connection.lock().unwrap().send(message);
+    }
+}
+
+impl RPCProxy for UdsCallback {
+    fn register_disconnect(&mut self, f: Box<dyn Fn(u32) + Send>) -> u32 {
+        self.connection.lock().unwrap().disconnect_observers.insert(self.id, f);
+        self.id
+    }
+
+    fn get_object_id(&self) -> String {
+        format!("uds/{}", self.id)
+    }
+
+    fn unregister(&mut self, id: u32) -> bool {
+        self.connection.lock().unwrap().disconnect_observers.remove(&id).is_some()
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+fn device_to_proto(device: BluetoothDevice) -> Device {
+    let mut proto = Device::new();
+    proto.set_address(device.address);
+    proto.set_name(device.name);
+    proto
+}
+
+fn device_from_proto(proto: &Device) -> BluetoothDevice {
+    BluetoothDevice { address: proto.get_address().to_string(), name: proto.get_name().to_string() }
+}
+
+// Rejects malformed addresses the same way as the D-Bus API.
+fn address_from_proto(address: &str) -> BtResult<BtAddress> {
+    BtAddress::from_string(address)
+        .ok_or_else(|| BtError::invalid_argument(format!("Invalid address {}", address)))
+}
+
+fn service_to_proto(service: BluetoothGattService) -> GattService {
+    let characteristics = service
+        .characteristics
+        .into_iter()
+        .map(|characteristic| {
+            let descriptors = characteristic
+                .descriptors
+                .into_iter()
+                .map(|descriptor| {
+                    let mut proto = GattDescriptor::new();
+                    proto.set_uuid(descriptor.uuid.to_vec());
+                    proto.set_instance_id(descriptor.instance_id);
+                    proto.set_permissions(descriptor.permissions);
+                    proto
+                })
+                .collect();
+
+            let mut proto = GattCharacteristic::new();
+            proto.set_uuid(characteristic.uuid.to_vec());
+            proto.set_instance_id(characteristic.instance_id);
+            proto.set_properties(characteristic.properties);
+            proto.set_descriptors(RepeatedField::from_vec(descriptors));
+            proto.set_permissions(characteristic.permissions);
+            proto.set_key_size(characteristic.key_size);
+            proto.set_write_type(characteristic.write_type.to_i32().unwrap_or_default());
+            proto
+        })
+        .collect();
+
+    let mut proto = GattService::new();
+    proto.set_uuid(service.uuid.to_vec());
+    proto.set_instance_id(service.instance_id);
+    proto.set_service_type(service.service_type);
+    proto.set_characteristics(RepeatedField::from_vec(characteristics));
+    proto.set_included_services(RepeatedField::from_vec(
+        service.included_services.into_iter().map(service_to_proto).collect(),
+    ));
+    proto
+}
+
+fn uuid_from_proto(uuid: &[u8]) -> BtResult<Uuid128Bit> {
+    Uuid128Bit::try_from(uuid).map_err(|_| BtError::invalid_argument("A UUID must be 16 bytes"))
+}
+
+fn service_from_proto(proto: &GattService) -> BtResult<BluetoothGattService> {
+    let characteristics = proto
+        .get_characteristics()
+        .iter()
+        .map(|characteristic| {
+            let descriptors = characteristic
+                .get_descriptors()
+                .iter()
+                .map(|descriptor| {
+                    Ok(BluetoothGattDescriptor {
+                        uuid: uuid_from_proto(descriptor.get_uuid())?,
+                        instance_id: descriptor.get_instance_id(),
+                        permissions: descriptor.get_permissions(),
+                    })
+                })
+                .collect::<BtResult<_>>()?;
+
+            Ok(BluetoothGattCharacteristic {
+                uuid: uuid_from_proto(characteristic.get_uuid())?,
+                instance_id: characteristic.get_instance_id(),
+                properties: characteristic.get_properties(),
+                permissions: characteristic.get_permissions(),
+                key_size: characteristic.get_key_size(),
+                write_type: GattWriteType::from_i32(characteristic.get_write_type())
+                    .ok_or_else(|| BtError::invalid_argument("Invalid write type"))?,
+                descriptors,
+            })
+        })
+        .collect::<BtResult<_>>()?;
+
+    Ok(BluetoothGattService {
+        uuid: uuid_from_proto(proto.get_uuid())?,
+        instance_id: proto.get_instance_id(),
+        service_type: proto.get_service_type(),
+        characteristics,
+        included_services: proto
+            .get_included_services()
+            .iter()
+            .map(service_from_proto)
+            .collect::<BtResult<_>>()?,
+    })
+}
+
+fn scan_result_to_proto(result: ScanResult) -> bluetooth_ipc::ScanResult {
+    let mut proto = bluetooth_ipc::ScanResult::new();
+    proto.set_address(result.address.to_string());
+    proto.set_addr_type(result.addr_type.into());
+    proto.set_event_type(result.event_type.into());
+    proto.set_primary_phy(result.primary_phy.into());
+    proto.set_secondary_phy(result.secondary_phy.into());
+    proto.set_advertising_sid(result.advertising_sid.into());
+    proto.set_identity_address(result.identity_address);
+    proto.set_is_bonded(result.is_bonded);
+    proto.set_tx_power(result.tx_power);
+    proto.set_rssi(result.rssi);
+    proto.set_smoothed_rssi(result.smoothed_rssi);
+    proto.set_periodic_adv_int(result.periodic_adv_int.into());
+    proto.set_adv_data(result.adv_data);
+    proto
+}
+
+fn batch_scan_result_to_proto(result: BatchScanResult) -> bluetooth_ipc::BatchScanResult {
+    let mut proto = bluetooth_ipc::BatchScanResult::new();
+    proto.set_address(result.address);
+    proto.set_addr_type(result.addr_type.into());
+    proto.set_tx_power(result.tx_power);
+    proto.set_rssi(result.rssi);
+    proto.set_timestamp_millis(result.timestamp_millis);
+    proto.set_adv_data(result.adv_data);
+    proto
+}
+
+fn addresses_from_proto(addresses: &[String]) -> BtResult<Vec<BtAddress>> {
+    addresses.iter().map(|address| address_from_proto(address)).collect()
+}
+
+fn scan_settings_from_proto(proto: &bluetooth_ipc::ScanSettings) -> BtResult<ScanSettings> {
+    Ok(ScanSettings {
+        interval: proto.get_interval(),
+        window: proto.get_window(),
+        scan_type: ScanType::from_u32(proto.get_scan_type())
+            .ok_or_else(|| BtError::invalid_argument("Invalid scan type"))?,
+        allowed_addresses: addresses_from_proto(proto.get_allowed_addresses())?,
+        denied_addresses: addresses_from_proto(proto.get_denied_addresses())?,
+        ..Default::default()
+    })
+}
+
+// 0 leaves the RSSI threshold of a filter unset.
+fn rssi_threshold_from_proto(threshold: i32) -> i32 {
+    match threshold {
+        0 => ScanFilter::default().rssi_high_threshold,
+        threshold => threshold,
+    }
+}
+
+fn scan_filter_from_proto(proto: &bluetooth_ipc::ScanFilter) -> BtResult<ScanFilter> {
+    Ok(ScanFilter {
+        address: proto.get_address().to_string(),
+        addr_type: u8::try_from(proto.get_addr_type())
+            .map_err(|_| BtError::invalid_argument("Invalid address type"))?,
+        service_uuid: proto.get_service_uuid().to_string(),
+        name: proto.get_name().to_string(),
+        manufacturer_id: u16::try_from(proto.get_manufacturer_id())
+            .map_err(|_| BtError::invalid_argument("Invalid manufacturer id"))?,
+        manufacturer_data: proto.get_manufacturer_data().to_vec(),
+        manufacturer_data_mask: proto.get_manufacturer_data_mask().to_vec(),
+        rssi_high_threshold: rssi_threshold_from_proto(proto.get_rssi_high_threshold()),
+        rssi_low_threshold: rssi_threshold_from_proto(proto.get_rssi_low_threshold()),
+    })
+}
+
+fn advertising_parameters_from_proto(
+    proto: &bluetooth_ipc::AdvertisingSetParameters,
+) -> BtResult<AdvertisingSetParameters> {
+    Ok(AdvertisingSetParameters {
+        connectable: proto.get_connectable(),
+        scannable: proto.get_scannable(),
+        is_legacy: proto.get_is_legacy(),
+        is_anonymous: proto.get_is_anonymous(),
+        include_tx_power: proto.get_include_tx_power(),
+        primary_phy: le_phy_from_proto(proto.get_primary_phy())?,
+        secondary_phy: le_phy_from_proto(proto.get_secondary_phy())?,
+        interval: proto.get_interval(),
+        tx_power_level: proto.get_tx_power_level(),
+        own_address_type: proto.get_own_address_type(),
+    })
+}
+
+fn advertise_data_from_proto(proto: &bluetooth_ipc::AdvertiseData) -> BtResult<AdvertiseData> {
+    let uuids = |uuids: &[Vec<u8>]| {
+        uuids.iter().map(|uuid| Ok(BtUuid::from(uuid_from_proto(uuid)?))).collect::<BtResult<_>>()
+    };
+
+    Ok(AdvertiseData {
+        service_uuids: uuids(proto.get_service_uuids())?,
+        solicit_uuids: uuids(proto.get_solicit_uuids())?,
+        manufacturer_data: proto
+            .get_manufacturer_data()
+            .iter()
+            .map(|data| {
+                let id = u16::try_from(data.get_manufacturer_id())
+                    .map_err(|_| BtError::invalid_argument("Invalid manufacturer id"))?;
+                Ok((id, data.get_data().to_vec()))
+            })
+            .collect::<BtResult<_>>()?,
+        service_data: proto
+            .get_service_data()
+            .iter()
+            .map(|data| {
+                Ok((BtUuid::from(uuid_from_proto(data.get_uuid())?), data.get_data().to_vec()))
+            })
+            .collect::<BtResult<_>>()?,
+        include_tx_power_level: proto.get_include_tx_power_level(),
+        include_device_name: proto.get_include_device_name(),
+        ..Default::default()
+    })
+}
+
+impl IBluetoothCallback for UdsCallback {
+    fn on_address_changed(&self, addr: String) {
+        self.send_event(|event| {
+            let mut proto = TextEvent::new();
+            proto.set_text(addr);
+            event.set_address_changed(proto);
+        });
+    }
+
+    fn on_name_changed(&self, name: String) {
+        self.send_event(|event| {
+            let mut proto = TextEvent::new();
+            proto.set_text(name);
+            event.set_name_changed(proto);
+        });
+    }
+
+    fn on_discoverable_changed(&self, discoverable: bool) {
+        self.send_event(|event| {
+            let mut proto = FlagEvent::new();
+            proto.set_flag(discoverable);
+            event.set_discoverable_changed(proto);
+        });
+    }
+
+    fn on_device_found(&self, remote_device: BluetoothDevice) {
+        self.send_event(|event| {
+            let mut proto = DeviceFoundEvent::new();
+            proto.set_device(device_to_proto(remote_device));
+            event.set_device_found(proto);
+        });
+    }
+
+    fn on_device_cleared(&self, remote_device: BluetoothDevice) {
+        self.send_event(|event| {
+            let mut proto = DeviceFoundEvent::new();
+            proto.set_device(device_to_proto(remote_device));
+            event.set_device_cleared(proto);
+        });
+    }
+
+    fn on_discovering_changed(&self, discovering: bool) {
+        self.send_event(|event| {
+            let mut proto = DiscoveringChangedEvent::new();
+            proto.set_discovering(discovering);
+            event.set_discovering_changed(proto);
+        });
+    }
+
+    fn on_ssp_request(
+        &self,
+        remote_device: BluetoothDevice,
+        cod: u32,
+        variant: BtSspVariant,
+        passkey: u32,
+    ) {
+        self.send_event(|event| {
+            let mut proto = SspRequestEvent::new();
+            proto.set_device(device_to_proto(remote_device));
+            proto.set_cod(cod);
+            proto.set_variant(variant.to_u32().unwrap_or_default());
+            proto.set_passkey(passkey);
+            event.set_ssp_request(proto);
+        });
+    }
+
+    fn on_bond_state_changed(&self, status: u32, device_address: String, state: u32) {
+        self.send_event(|event| {
+            let mut proto = BondStateChangedEvent::new();
+            proto.set_status(status);
+            proto.set_address(device_address);
+            proto.set_state(state);
+            event.set_bond_state_changed(proto);
+        });
+    }
+
+    fn on_ready(&self) {
+        self.send_event(|event| event.set_ready(Empty::new()));
+    }
+
+    fn on_pairing_locked_out(&self, device_address: String, global: bool, lockout_secs: u32) {
+        self.send_event(|event| {
+            let mut proto = PairingLockedOutEvent::new();
+            proto.set_address(device_address);
+            proto.set_global(global);
+            proto.set_lockout_secs(lockout_secs);
+            event.set_pairing_locked_out(proto);
+        });
+    }
+
+    fn on_stack_restart_started(&self, reason: String) {
+        self.send_event(|event| {
+            let mut proto = TextEvent::new();
+            proto.set_text(reason);
+            event.set_stack_restart_started(proto);
+        });
+    }
+
+    fn on_stack_restart_completed(&self, reason: String, success: bool) {
+        self.send_event(|event| {
+            let mut proto = StackRestartCompletedEvent::new();
+            proto.set_reason(reason);
+            proto.set_success(success);
+            event.set_stack_restart_completed(proto);
+        });
+    }
+
+    fn on_radio_activity_changed(&self, activity: RadioActivity) {
+        self.send_event(|event| {
+            let mut proto = RadioActivityChangedEvent::new();
+            proto.set_scanning(activity.scanning);
+            proto.set_advertising(activity.advertising);
+            proto.set_connections(activity.connections);
+            event.set_radio_activity_changed(proto);
+        });
+    }
+
+    fn on_device_forgotten(&self, device_address: String) {
+        self.send_event(|event| {
+            let mut proto = TextEvent::new();
+            proto.set_text(device_address);
+            event.set_device_forgotten(proto);
+        });
+    }
+}
+
+impl IBluetoothConnectionCallback for UdsCallback {
+    fn on_device_connected(&self, remote_device: BluetoothDevice) {
+        self.send_event(|event| {
+            let mut proto = DeviceConnectionEvent::new();
+            proto.set_device(device_to_proto(remote_device));
+            proto.set_connected(true);
+            event.set_device_connection(proto);
+        });
+    }
+
+    fn on_device_disconnected(&self, remote_device: BluetoothDevice) {
+        self.send_event(|event| {
+            let mut proto = DeviceConnectionEvent::new();
+            proto.set_device(device_to_proto(remote_device));
+            proto.set_connected(false);
+            event.set_device_connection(proto);
+        });
+    }
+}
+
+fn phy_event(addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) -> GattPhyEvent {
+    let mut proto = GattPhyEvent::new();
+    proto.set_address(addr.to_string());
+    proto.set_tx_phy(tx_phy.to_i32().unwrap_or_default());
+    proto.set_rx_phy(rx_phy.to_i32().unwrap_or_default());
+    proto.set_status(status.to_i32().unwrap_or_default());
+    proto
+}
+
+fn connection_updated_event(
+    addr: BtAddress,
+    interval: i32,
+    latency: i32,
+    timeout: i32,
+    status: i32,
+) -> GattConnectionUpdatedEvent {
+    let mut proto = GattConnectionUpdatedEvent::new();
+    proto.set_address(addr.to_string());
+    proto.set_interval(interval);
+    proto.set_latency(latency);
+    proto.set_timeout(timeout);
+    proto.set_status(status);
+    proto
+}
+
+impl IBluetoothGattCallback for UdsCallback {
+    fn on_client_registered(&self, status: i32, client_id: i32) {
+        {
+            let mut connection = self.connection.lock().unwrap();
+            if !record_registration(&mut connection.gatt_clients, self.id, status, client_id) {
+                // The stack is locked by the caller, hence the task.
+                let bluetooth_gatt = connection.bluetooth_gatt.clone();
+                tokio::spawn(async move {
+                    bluetooth_gatt.lock().unwrap().unregister_client(client_id);
+                });
+                return;
+            }
+        }
+
+        self.send_event(|event| {
+            let mut proto = GattClientRegisteredEvent::new();
+            proto.set_status(status);
+            proto.set_client_id(client_id);
+            event.set_gatt_client_registered(proto);
+        });
+    }
+
+    fn on_client_connection_state(
+        &self,
+        status: i32,
+        client_id: i32,
+        connected: bool,
+        addr: BtAddress,
+    ) {
+        self.send_event(|event| {
+            let mut proto = GattClientConnectionStateEvent::new();
+            proto.set_status(status);
+            proto.set_client_id(client_id);
+            proto.set_connected(connected);
+            proto.set_address(addr.to_string());
+            event.set_gatt_client_connection_state(proto);
+        });
+    }
+
+    fn on_phy_update(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
+        self.send_event(|event| event.set_gatt_phy_update(phy_event(addr, tx_phy, rx_phy, status)));
+    }
+
+    fn on_phy_read(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
+        self.send_event(|event| event.set_gatt_phy_read(phy_event(addr, tx_phy, rx_phy, status)));
+    }
+
+    fn on_search_complete(
+        &self,
+        addr: BtAddress,
+        services: Vec<BluetoothGattService>,
+        status: i32,
+    ) {
+        self.send_event(|event| {
+            let mut proto = GattSearchCompleteEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_services(RepeatedField::from_vec(
+                services.into_iter().map(service_to_proto).collect(),
+            ));
+            proto.set_status(status);
+            event.set_gatt_search_complete(proto);
+        });
+    }
+
+    fn on_get_gatt_db(&self, addr: BtAddress, services: Vec<BluetoothGattService>) {
+        self.send_event(|event| {
+            let mut proto = GattDatabaseEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_services(RepeatedField::from_vec(
+                services.into_iter().map(service_to_proto).collect(),
+            ));
+            event.set_gatt_database(proto);
+        });
+    }
+
+    fn on_characteristic_read(&self, addr: BtAddress, status: i32, handle: i32, value: Vec<u8>) {
+        self.send_event(|event| {
+            let mut proto = GattCharacteristicReadEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_status(status);
+            proto.set_handle(handle);
+            proto.set_value(value);
+            event.set_gatt_characteristic_read(proto);
+        });
+    }
+
+    fn on_service_read(
+        &self,
+        addr: BtAddress,
+        service_uuid: Uuid128Bit,
+        results: Vec<CharacteristicReadResult>,
+    ) {
+        let results = results
+            .into_iter()
+            .map(|result| {
+                let mut proto = GattCharacteristicReadResult::new();
+                proto.set_uuid(result.uuid.to_vec());
+                proto.set_handle(result.handle);
+                proto.set_status(result.status);
+                proto.set_value(result.value);
+                proto
+            })
+            .collect();
+
+        self.send_event(|event| {
+            let mut proto = GattServiceReadEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_service_uuid(service_uuid.to_vec());
+            proto.set_results(RepeatedField::from_vec(results));
+            event.set_gatt_service_read(proto);
+        });
+    }
+
+    fn on_conformance_report(&self, addr: BtAddress, issues: Vec<ConformanceIssue>) {
+        let issues = issues
+            .into_iter()
+            .map(|issue| {
+                let mut proto = GattConformanceIssue::new();
+                proto.set_problem(issue.problem.to_u32().unwrap_or_default());
+                proto.set_service_uuid(issue.service_uuid.to_vec());
+                proto.set_characteristic_uuid(issue.characteristic_uuid.to_vec());
+                proto.set_descriptor_uuid(issue.descriptor_uuid.to_vec());
+                proto.set_handle(issue.handle);
+                proto
+            })
+            .collect();
+
+        self.send_event(|event| {
+            let mut proto = GattConformanceReportEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_issues(RepeatedField::from_vec(issues));
+            event.set_gatt_conformance_report(proto);
+        });
+    }
+
+    fn on_characteristic_write(&self, addr: BtAddress, status: i32, handle: i32) {
+        self.send_event(|event| {
+            let mut proto = GattCharacteristicWriteEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_status(status);
+            proto.set_handle(handle);
+            event.set_gatt_characteristic_write(proto);
+        });
+    }
+
+    fn on_characteristic_write_progress(
+        &self,
+        addr: BtAddress,
+        handle: i32,
+        bytes_written: i32,
+        total_bytes: i32,
+    ) {
+        self.send_event(|event| {
+            let mut proto = GattWriteProgressEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_handle(handle);
+            proto.set_bytes_written(bytes_written);
+            proto.set_total_bytes(total_bytes);
+            event.set_gatt_characteristic_write_progress(proto);
+        });
+    }
+
+    fn on_execute_write(&self, addr: BtAddress, status: i32) {
+        self.send_event(|event| {
+            let mut proto = GattStatusEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_status(status);
+            event.set_gatt_execute_write(proto);
+        });
+    }
+
+    fn on_descriptor_read(&self, addr: BtAddress, status: i32, handle: i32, value: Vec<u8>) {
+        self.send_event(|event| {
+            let mut proto = GattCharacteristicReadEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_status(status);
+            proto.set_handle(handle);
+            proto.set_value(value);
+            event.set_gatt_descriptor_read(proto);
+        });
+    }
+
+    fn on_notification_queue_overflow(&self, addr: BtAddress, handle: i32, dropped: u32) {
+        self.send_event(|event| {
+            let mut proto = GattNotificationQueueOverflowEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_handle(handle);
+            proto.set_dropped(dropped);
+            event.set_gatt_notification_queue_overflow(proto);
+        });
+    }
+
+    fn on_descriptor_write(&self, addr: BtAddress, status: i32, handle: i32) {
+        self.send_event(|event| {
+            let mut proto = GattCharacteristicWriteEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_status(status);
+            proto.set_handle(handle);
+            event.set_gatt_descriptor_write(proto);
+        });
+    }
+
+    fn on_notify(&self, addr: BtAddress, handle: i32, value: Vec<u8>) {
+        self.send_event(|event| {
+            let mut proto = GattNotifyEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_handle(handle);
+            proto.set_value(value);
+            event.set_gatt_notify(proto);
+        });
+    }
+
+    fn on_notify_multiple(&self, addr: BtAddress, values: Vec<GattHandleValue>) {
+        for v in values {
+            self.on_notify(addr, v.handle, v.value);
+        }
+    }
+
+    fn on_read_remote_rssi(&self, addr: BtAddress, rssi: i32, status: i32) {
+        self.send_event(|event| {
+            let mut proto = GattRssiEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_rssi(rssi);
+            proto.set_status(status);
+            event.set_gatt_read_remote_rssi(proto);
+        });
+    }
+
+    fn on_rssi_threshold_crossed(&self, addr: BtAddress, rssi: i32, threshold: i32) {
+        self.send_event(|event| {
+            let mut proto = GattRssiThresholdEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_rssi(rssi);
+            proto.set_threshold(threshold);
+            event.set_gatt_rssi_threshold_crossed(proto);
+        });
+    }
+
+    fn on_configure_mtu(&self, addr: BtAddress, mtu: i32, status: i32) {
+        self.send_event(|event| {
+            let mut proto = GattMtuEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_mtu(mtu);
+            proto.set_status(status);
+            event.set_gatt_configure_mtu(proto);
+        });
+    }
+
+    fn on_connection_updated(
+        &self,
+        addr: BtAddress,
+        interval: i32,
+        latency: i32,
+        timeout: i32,
+        status: i32,
+    ) {
+        self.send_event(|event| {
+            event.set_gatt_connection_updated(connection_updated_event(
+                addr, interval, latency, timeout, status,
+            ))
+        });
+    }
+
+    fn on_service_changed(&self, addr: BtAddress) {
+        self.send_event(|event| {
+            let mut proto = TextEvent::new();
+            proto.set_text(addr.to_string());
+            event.set_gatt_service_changed(proto);
+        });
+    }
+
+    fn on_notification_pipe_active(&self, addr: BtAddress, handle: i32) {
+        self.send_event(|event| {
+            let mut proto = GattHandleEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_handle(handle);
+            event.set_gatt_notification_pipe_active(proto);
+        });
+    }
+
+    fn on_eatt_state_changed(&self, addr: BtAddress, _bearer_count: i32, bearer_mtus: Vec<i32>) {
+        self.send_event(|event| {
+            let mut proto = GattEattStateEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_bearer_mtus(bearer_mtus);
+            event.set_gatt_eatt_state_changed(proto);
+        });
+    }
+}
+
+impl IScannerCallback for UdsCallback {
+    fn on_scanner_registered(&self, status: i32, scanner_id: i32) {
+        {
+            let mut connection = self.connection.lock().unwrap();
+            // Unregistered by the stack along with its callback otherwise.
+            if !record_registration(&mut connection.scanners, self.id, status, scanner_id) {
+                return;
+            }
+        }
+
+        self.send_event(|event| {
+            let mut proto = ScannerRegisteredEvent::new();
+            proto.set_status(status);
+            proto.set_scanner_id(scanner_id);
+            event.set_scanner_registered(proto);
+        });
+    }
+
+    fn on_scan_result(&self, scan_result: ScanResult) {
+        self.send_event(|event| {
+            let mut proto = ScanResultEvent::new();
+            proto.set_result(scan_result_to_proto(scan_result));
+            event.set_scan_result(proto);
+        });
+    }
+
+    fn on_scan_result_batch(&self, scan_results: Vec<ScanResult>) {
+        self.send_event(|event| {
+            let mut proto = ScanResultBatchEvent::new();
+            proto.set_results(RepeatedField::from_vec(
+                scan_results.into_iter().map(scan_result_to_proto).collect(),
+            ));
+            event.set_scan_result_batch(proto);
+        });
+    }
+
+    fn on_scan_result_lost(&self, scan_result: ScanResult) {
+        self.send_event(|event| {
+            let mut proto = ScanResultEvent::new();
+            proto.set_result(scan_result_to_proto(scan_result));
+            event.set_scan_result_lost(proto);
+        });
+    }
+
+    fn on_batch_scan_reports(&self, scanner_id: i32, status: i32, results: Vec<BatchScanResult>) {
+        self.send_event(|event| {
+            let mut proto = BatchScanReportsEvent::new();
+            proto.set_scanner_id(scanner_id);
+            proto.set_status(status);
+            proto.set_results(RepeatedField::from_vec(
+                results.into_iter().map(batch_scan_result_to_proto).collect(),
+            ));
+            event.set_batch_scan_reports(proto);
+        });
+    }
+
+    fn on_batch_scan_threshold_crossed(&self, scanner_id: i32) {
+        self.send_event(|event| {
+            let mut proto = ScannerEvent::new();
+            proto.set_scanner_id(scanner_id);
+            event.set_batch_scan_threshold_crossed(proto);
+        });
+    }
+
+    fn on_scan_parameters_changed(&self, scanner_id: i32, interval: i32, window: i32) {
+        self.send_event(|event| {
+            let mut proto = ScanParametersChangedEvent::new();
+            proto.set_scanner_id(scanner_id);
+            proto.set_interval(interval);
+            proto.set_window(window);
+            event.set_scan_parameters_changed(proto);
+        });
+    }
+
+    fn on_scan_duty_cycle_changed(&self, scanner_id: i32, requested: i32, effective: i32) {
+        self.send_event(|event| {
+            let mut proto = ScanDutyCycleChangedEvent::new();
+            proto.set_scanner_id(scanner_id);
+            proto.set_requested(requested);
+            proto.set_effective(effective);
+            event.set_scan_duty_cycle_changed(proto);
+        });
+    }
+
+    fn on_manufacturer_data_found(
+        &self,
+        scanner_id: i32,
+        subscription_id: u32,
+        addr: BtAddress,
+        rssi: i32,
+        data: Vec<u8>,
+    ) {
+        self.send_event(|event| {
+            let mut proto = ManufacturerDataFoundEvent::new();
+            proto.set_scanner_id(scanner_id);
+            proto.set_subscription_id(subscription_id);
+            proto.set_address(addr.to_string());
+            proto.set_rssi(rssi);
+            proto.set_data(data);
+            event.set_manufacturer_data_found(proto);
+        });
+    }
+}
+
+fn advertiser_event(advertiser_id: i32) -> AdvertiserEvent {
+    let mut proto = AdvertiserEvent::new();
+    proto.set_advertiser_id(advertiser_id);
+    proto
+}
+
+fn advertiser_status_event(advertiser_id: i32, status: AdvertisingStatus) -> AdvertiserStatusEvent {
+    let mut proto = AdvertiserStatusEvent::new();
+    proto.set_advertiser_id(advertiser_id);
+    proto.set_status(status.to_u32().unwrap_or_default());
+    proto
+}
+
+fn advertiser_address_event(
+    advertiser_id: i32,
+    address_type: i32,
+    address: String,
+) -> AdvertiserAddressEvent {
+    let mut proto = AdvertiserAddressEvent::new();
+    proto.set_advertiser_id(advertiser_id);
+    proto.set_address_type(address_type);
+    proto.set_address(address);
+    proto
+}
+
+fn advertiser_tx_power_event(
+    advertiser_id: i32,
+    tx_power: i32,
+    status: AdvertisingStatus,
+) -> AdvertiserTxPowerEvent {
+    let mut proto = AdvertiserTxPowerEvent::new();
+    proto.set_advertiser_id(advertiser_id);
+    proto.set_tx_power(tx_power);
+    proto.set_status(status.to_u32().unwrap_or_default());
+    proto
+}
+
+impl IAdvertisingSetCallback for UdsCallback {
+    fn on_advertising_set_started(
+        &self,
+        reg_id: i32,
+        advertiser_id: i32,
+        tx_power: i32,
+        status: AdvertisingStatus,
+    ) {
+        // A set that failed to start is not kept by the stack.
+        if status != AdvertisingStatus::Success {
+            self.connection.lock().unwrap().advertising_sets.remove(&advertiser_id);
+        }
+
+        self.send_event(|event| {
+            let mut proto = AdvertisingSetStartedEvent::new();
+            proto.set_reg_id(reg_id);
+            proto.set_advertiser_id(advertiser_id);
+            proto.set_tx_power(tx_power);
+            proto.set_status(status.to_u32().unwrap_or_default());
+            event.set_advertising_set_started(proto);
+        });
+    }
+
+    fn on_own_address_read(&self, advertiser_id: i32, address_type: i32, address: String) {
+        self.send_event(|event| {
+            event.set_own_address_read(advertiser_address_event(
+                advertiser_id,
+                address_type,
+                address,
+            ))
+        });
+    }
+
+    fn on_own_address_changed(&self, advertiser_id: i32, address_type: i32, address: String) {
+        self.send_event(|event| {
+            event.set_own_address_changed(advertiser_address_event(
+                advertiser_id,
+                address_type,
+                address,
+            ))
+        });
+    }
+
+    fn on_advertising_set_stopped(&self, advertiser_id: i32) {
+        self.connection.lock().unwrap().advertising_sets.remove(&advertiser_id);
+        self.send_event(|event| event.set_advertising_set_stopped(advertiser_event(advertiser_id)));
+    }
+
+    fn on_advertising_enabled(&self, advertiser_id: i32, enable: bool, status: AdvertisingStatus) {
+        self.send_event(|event| {
+            let mut proto = AdvertisingEnabledEvent::new();
+            proto.set_advertiser_id(advertiser_id);
+            proto.set_enable(enable);
+            proto.set_status(status.to_u32().unwrap_or_default());
+            event.set_advertising_enabled(proto);
+        });
+    }
+
+    fn on_advertising_set_terminated(
+        &self,
+        advertiser_id: i32,
+        reason: AdvertisingTerminationReason,
+    ) {
+        self.send_event(|event| {
+            let mut proto = AdvertisingTerminatedEvent::new();
+            proto.set_advertiser_id(advertiser_id);
+            proto.set_reason(reason.to_u32().unwrap_or_default());
+            event.set_advertising_set_terminated(proto);
+        });
+    }
+
+    fn on_advertising_data_set(&self, advertiser_id: i32, status: AdvertisingStatus) {
+        self.send_event(|event| {
+            event.set_advertising_data_set(advertiser_status_event(advertiser_id, status))
+        });
+    }
+
+    fn on_scan_response_data_set(&self, advertiser_id: i32, status: AdvertisingStatus) {
+        self.send_event(|event| {
+            event.set_scan_response_data_set(advertiser_status_event(advertiser_id, status))
+        });
+    }
+
+    fn on_advertising_parameters_updated(
+        &self,
+        advertiser_id: i32,
+        tx_power: i32,
+        status: AdvertisingStatus,
+    ) {
+        self.send_event(|event| {
+            event.set_advertising_parameters_updated(advertiser_tx_power_event(
+                advertiser_id,
+                tx_power,
+                status,
+            ))
+        });
+    }
+
+    fn on_advertising_set_suspended(&self, advertiser_id: i32) {
+        self.send_event(|event| {
+            event.set_advertising_set_suspended(advertiser_event(advertiser_id))
+        });
+    }
+
+    fn on_advertising_set_resumed(&self, advertiser_id: i32) {
+        self.send_event(|event| event.set_advertising_set_resumed(advertiser_event(advertiser_id)));
+    }
+
+    fn on_advertising_set_restored(
+        &self,
+        advertiser_id: i32,
+        tx_power: i32,
+        status: AdvertisingStatus,
+    ) {
+        self.send_event(|event| {
+            event.set_advertising_set_restored(advertiser_tx_power_event(
+                advertiser_id,
+                tx_power,
+                status,
+            ))
+        });
+    }
+
+    fn on_tx_power_changed(&self, advertiser_id: i32, tx_power: i32) {
+        self.send_event(|event| {
+            event.set_advertising_tx_power_changed(advertiser_tx_power_event(
+                advertiser_id,
+                tx_power,
+                AdvertisingStatus::Success,
+            ))
+        });
+    }
+}
+
+fn read_request_event(
+    addr: BtAddress,
+    request_id: i32,
+    offset: i32,
+    is_long: bool,
+    handle: i32,
+) -> GattReadRequestEvent {
+    let mut proto = GattReadRequestEvent::new();
+    proto.set_address(addr.to_string());
+    proto.set_request_id(request_id);
+    proto.set_offset(offset);
+    proto.set_is_long(is_long);
+    proto.set_handle(handle);
+    proto
+}
+
+impl IBluetoothGattServerCallback for UdsCallback {
+    fn on_server_registered(&self, status: i32, server_id: i32) {
+        {
+            let mut connection = self.connection.lock().unwrap();
+            if !record_registration(&mut connection.gatt_servers, self.id, status, server_id) {
+                // The stack is locked by the caller, hence the task.
+                let bluetooth_gatt = connection.bluetooth_gatt.clone();
+                tokio::spawn(async move {
+                    bluetooth_gatt.lock().unwrap().unregister_server(server_id);
+                });
+                return;
+            }
+        }
+
+        self.send_event(|event| {
+            let mut proto = GattServerRegisteredEvent::new();
+            proto.set_status(status);
+            proto.set_server_id(server_id);
+            event.set_gatt_server_registered(proto);
+        });
+    }
+
+    fn on_server_connection_state(&self, server_id: i32, connected: bool, addr: BtAddress) {
+        self.send_event(|event| {
+            let mut proto = GattServerConnectionStateEvent::new();
+            proto.set_server_id(server_id);
+            proto.set_connected(connected);
+            proto.set_address(addr.to_string());
+            event.set_gatt_server_connection_state(proto);
+        });
+    }
+
+    fn on_service_added(&self, status: i32, service: BluetoothGattService) {
+        self.send_event(|event| {
+            let mut proto = GattServiceAddedEvent::new();
+            proto.set_status(status);
+            proto.set_service(service_to_proto(service));
+            event.set_gatt_service_added(proto);
+        });
+    }
+
+    fn on_service_removed(&self, status: i32, handle: i32) {
+        self.send_event(|event| {
+            let mut proto = GattServiceRemovedEvent::new();
+            proto.set_status(status);
+            proto.set_handle(handle);
+            event.set_gatt_service_removed(proto);
+        });
+    }
+
+    fn on_characteristic_read_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        is_long: bool,
+        handle: i32,
+    ) {
+        self.send_event(|event| {
+            event.set_gatt_characteristic_read_request(read_request_event(
+                addr, request_id, offset, is_long, handle,
+            ))
+        });
+    }
+
+    fn on_descriptor_read_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        is_long: bool,
+        handle: i32,
+    ) {
+        self.send_event(|event| {
+            event.set_gatt_descriptor_read_request(read_request_event(
+                addr, request_id, offset, is_long, handle,
+            ))
+        });
+    }
+
+    fn on_characteristic_write_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        len: i32,
+        is_prep: bool,
+        need_response: bool,
+        handle: i32,
+        value: Vec<u8>,
+    ) {
+        self.send_event(|event| {
+            let mut proto = GattWriteRequestEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_request_id(request_id);
+            proto.set_offset(offset);
+            proto.set_len(len);
+            proto.set_is_prep(is_prep);
+            proto.set_need_response(need_response);
+            proto.set_handle(handle);
+            proto.set_value(value);
+            event.set_gatt_characteristic_write_request(proto);
+        });
+    }
+
+    fn on_descriptor_write_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        len: i32,
+        is_prep: bool,
+        need_response: bool,
+        handle: i32,
+        value: Vec<u8>,
+    ) {
+        self.send_event(|event| {
+            let mut proto = GattWriteRequestEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_request_id(request_id);
+            proto.set_offset(offset);
+            proto.set_len(len);
+            proto.set_is_prep(is_prep);
+            proto.set_need_response(need_response);
+            proto.set_handle(handle);
+            proto.set_value(value);
+            event.set_gatt_descriptor_write_request(proto);
+        });
+    }
+
+    fn on_execute_write(&self, addr: BtAddress, request_id: i32, execute_write: bool) {
+        self.send_event(|event| {
+            let mut proto = GattExecuteWriteRequestEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_request_id(request_id);
+            proto.set_execute_write(execute_write);
+            event.set_gatt_execute_write_request(proto);
+        });
+    }
+
+    fn on_notification_sent(&self, addr: BtAddress, status: i32) {
+        self.send_event(|event| {
+            let mut proto = GattStatusEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_status(status);
+            event.set_gatt_notification_sent(proto);
+        });
+    }
+
+    fn on_mtu_changed(&self, addr: BtAddress, mtu: i32) {
+        self.send_event(|event| {
+            let mut proto = GattMtuEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_mtu(mtu);
+            event.set_gatt_server_mtu_changed(proto);
+        });
+    }
+
+    fn on_user_description_changed(&self, addr: BtAddress, handle: i32, description: String) {
+        self.send_event(|event| {
+            let mut proto = GattUserDescriptionEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_handle(handle);
+            proto.set_description(description);
+            event.set_gatt_user_description_changed(proto);
+        });
+    }
+
+    fn on_server_configuration_changed(&self, addr: BtAddress, handle: i32, broadcast: bool) {
+        self.send_event(|event| {
+            let mut proto = GattServerConfigurationEvent::new();
+            proto.set_address(addr.to_string());
+            proto.set_handle(handle);
+            proto.set_broadcast(broadcast);
+            event.set_gatt_server_configuration_changed(proto);
+        });
+    }
+
+    fn on_phy_update(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus) {
+        self.send_event(|event| {
+            event.set_gatt_server_phy_update(phy_event(addr, tx_phy, rx_phy, status))
+        });
+    }
+
+    fn on_connection_updated(
+        &self,
+        addr: BtAddress,
+        interval: i32,
+        latency: i32,
+        timeout: i32,
+        status: i32,
+    ) {
+        self.send_event(|event| {
+            event.set_gatt_server_connection_updated(connection_updated_event(
+                addr, interval, latency, timeout, status,
+            ))
+        });
+    }
+}
+
+/// Result of a request, turned into its reply.
+enum Outcome {
+    Done,
+    Flag(bool),
+    Number(u32),
+    SignedNumber(i32),
+    Text(String),
+    Devices(Vec<BluetoothDevice>),
+    Uuids(Vec<Uuid128Bit>),
+}
+
+fn make_reply(id: u32, result: BtResult<Outcome>) -> Reply {
+    let mut reply = Reply::new();
+    reply.set_id(id);
+    match result {
+        Ok(Outcome::Done) => reply.set_done(Empty::new()),
+        Ok(Outcome::Flag(flag)) => reply.set_flag(flag),
+        Ok(Outcome::Number(number)) => reply.set_number(number),
+        Ok(Outcome::SignedNumber(number)) => reply.set_signed_number(number),
+        Ok(Outcome::Text(text)) => reply.set_text(text),
+        Ok(Outcome::Devices(devices)) => {
+            let mut proto = DeviceList::new();
+            proto.set_devices(RepeatedField::from_vec(
+                devices.into_iter().map(device_to_proto).collect(),
+            ));
+            reply.set_devices(proto);
+        }
+        Ok(Outcome::Uuids(uuids)) => {
+            let mut proto = UuidList::new();
+            proto.set_uuids(RepeatedField::from_vec(
+                uuids.into_iter().map(|uuid| uuid.to_vec()).collect(),
+            ));
+            reply.set_uuids(proto);
+        }
+        Err(e) => {
+            let mut proto = Error::new();
+            proto.set_category(e.category.to_u32().unwrap_or_default());
+            proto.set_sub_code(e.sub_code);
+            proto.set_message(e.message);
+            reply.set_error(proto);
+        }
+    }
+    reply
+}
+
+fn le_phy_from_proto(phy: i32) -> BtResult<LePhy> {
+    LePhy::from_i32(phy).ok_or_else(|| BtError::invalid_argument(format!("Invalid PHY {}", phy)))
+}
+
+fn ce_len_from_proto(len: u32) -> BtResult<u16> {
+    u16::try_from(len).map_err(|_| BtError::invalid_argument("Invalid connection event length"))
+}
+
+/// Serves the requests of a client connection.
+struct Session {
+    connection: Arc<Mutex<Connection>>,
+    bluetooth: Arc<Mutex<Box<Bluetooth>>>,
+    bluetooth_gatt: Arc<Mutex<Box<BluetoothGatt>>>,
+}
+
+impl Session {
+    // The GATT clients of the other connections and of the D-Bus clients are not served, nor
+    // are their GATT servers, scanners and advertising sets.
+    fn gatt_client(&self, client_id: i32) -> BtResult<i32> {
+        if !is_registered(&self.connection.lock().unwrap().gatt_clients, client_id) {
+            return Err(BtError::invalid_argument(format!("Unknown GATT client {}", client_id)));
+        }
+        Ok(client_id)
+    }
+
+    fn gatt_server(&self, server_id: i32) -> BtResult<i32> {
+        if !is_registered(&self.connection.lock().unwrap().gatt_servers, server_id) {
+            return Err(BtError::invalid_argument(format!("Unknown GATT server {}", server_id)));
+        }
+        Ok(server_id)
+    }
+
+    fn scanner(&self, scanner_id: i32) -> BtResult<i32> {
+        if !is_registered(&self.connection.lock().unwrap().scanners, scanner_id) {
+            return Err(BtError::invalid_argument(format!("Unknown scanner {}", scanner_id)));
+        }
+        Ok(scanner_id)
+    }
+
+    fn advertiser(&self, advertiser_id: i32) -> BtResult<i32> {
+        if !self.connection.lock().unwrap().advertising_sets.contains(&advertiser_id) {
+            return Err(BtError::invalid_argument(format!(
+                "Unknown advertising set {}",
+                advertiser_id
+            )));
+        }
+        Ok(advertiser_id)
+    }
+
+    fn unregister_callback(&self, callback_id: u32) -> BtResult<Outcome> {
+        let (observer, gatt_client, gatt_server) = {
+            let mut connection = self.connection.lock().unwrap();
+            connection.scanners.remove(&callback_id);
+            (
+                connection.disconnect_observers.remove(&callback_id),
+                connection.gatt_clients.remove(&callback_id),
+                connection.gatt_servers.remove(&callback_id),
+            )
+        };
+
+        match (observer, gatt_client, gatt_server) {
+            (Some(observer), _, _) => observer(callback_id),
+            (None, Some(Some(client_id)), _) => {
+                self.bluetooth_gatt.lock().unwrap().unregister_client(client_id)
+            }
+            (None, _, Some(Some(server_id))) => {
+                self.bluetooth_gatt.lock().unwrap().unregister_server(server_id)
+            }
+            // Unregistered by `on_client_registered` or `on_server_registered` once the stack has
+            // registered it.
+            (None, Some(None), _) | (None, _, Some(None)) => (),
+            (None, None, None) => {
+                return Err(BtError::invalid_argument(format!("Unknown callback {}", callback_id)))
+            }
+        }
+        Ok(Outcome::Done)
+    }
+
+    fn handle_request(&self, request: Request) -> BtResult<Outcome> {
+        let request = match request.request {
+            Some(request) => request,
+            None => return Err(BtError::invalid_argument("Unknown request")),
+        };
+
+        match request {
+            Request_oneof_request::register_adapter_callback(_) => {
+                let callback = Box::new(UdsCallback::new(self.connection.clone()));
+                let id = callback.id;
+                self.bluetooth.lock().unwrap().register_callback(callback);
+                Ok(Outcome::Number(id))
+            }
+            Request_oneof_request::register_connection_callback(_) => {
+                let callback = Box::new(UdsCallback::new(self.connection.clone()));
+                let id = callback.id;
+                self.bluetooth.lock().unwrap().register_connection_callback(callback);
+                Ok(Outcome::Number(id))
+            }
+            Request_oneof_request::unregister_callback(r) => {
+                self.unregister_callback(r.get_callback_id())
+            }
+            Request_oneof_request::is_ready(_) => {
+                Ok(Outcome::Flag(self.bluetooth.lock().unwrap().is_ready()))
+            }
+            Request_oneof_request::get_address(_) => {
+                Ok(Outcome::Text(self.bluetooth.lock().unwrap().get_address()))
+            }
+            Request_oneof_request::get_uuids(_) => {
+                Ok(Outcome::Uuids(self.bluetooth.lock().unwrap().get_uuids()))
+            }
+            Request_oneof_request::get_name(_) => {
+                Ok(Outcome::Text(self.bluetooth.lock().unwrap().get_name()))
+            }
+            Request_oneof_request::set_name(r) => {
+                self.bluetooth.lock().unwrap().set_name(r.get_text().to_string())?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::get_discoverable(_) => {
+                Ok(Outcome::Flag(self.bluetooth.lock().unwrap().get_discoverable()))
+            }
+            Request_oneof_request::set_discoverable(r) => {
+                self.bluetooth.lock().unwrap().set_discoverable(r.get_mode(), r.get_duration())?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::start_discovery(_) => {
+                self.bluetooth.lock().unwrap().start_discovery()?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::cancel_discovery(_) => {
+                self.bluetooth.lock().unwrap().cancel_discovery()?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::is_discovering(_) => {
+                Ok(Outcome::Flag(self.bluetooth.lock().unwrap().is_discovering()))
+            }
+            Request_oneof_request::get_bonded_devices(_) => {
+                Ok(Outcome::Devices(self.bluetooth.lock().unwrap().get_bonded_devices()))
+            }
+            Request_oneof_request::connect_all_enabled_profiles(r) => {
+                let device = device_from_proto(r.get_device());
+                Ok(Outcome::Flag(
+                    self.bluetooth.lock().unwrap().connect_all_enabled_profiles(device),
+                ))
+            }
+            Request_oneof_request::disconnect_all_enabled_profiles(r) => {
+                let device = device_from_proto(r.get_device());
+                Ok(Outcome::Flag(
+                    self.bluetooth.lock().unwrap().disconnect_all_enabled_profiles(device),
+                ))
+            }
+            Request_oneof_request::create_bond(r) => {
+                let transport = BtTransport::from_u32(r.get_transport())
+                    .ok_or_else(|| BtError::invalid_argument("Invalid transport"))?;
+                let device = device_from_proto(r.get_device());
+                self.bluetooth.lock().unwrap().create_bond(device, transport)?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::cancel_bond_process(r) => {
+                let device = device_from_proto(r.get_device());
+                self.bluetooth.lock().unwrap().cancel_bond_process(device)?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::remove_bond(r) => {
+                let device = device_from_proto(r.get_device());
+                self.bluetooth.lock().unwrap().remove_bond(device)?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::get_bond_state(r) => {
+                let device = device_from_proto(r.get_device());
+                Ok(Outcome::Number(self.bluetooth.lock().unwrap().get_bond_state(device)))
+            }
+            Request_oneof_request::set_pin(r) => {
+                let device = device_from_proto(r.get_device());
+                Ok(Outcome::Flag(self.bluetooth.lock().unwrap().set_pin(
+                    device,
+                    r.get_accept(),
+                    r.get_secret().to_vec(),
+                )))
+            }
+            Request_oneof_request::set_passkey(r) => {
+                let device = device_from_proto(r.get_device());
+                Ok(Outcome::Flag(self.bluetooth.lock().unwrap().set_passkey(
+                    device,
+                    r.get_accept(),
+                    r.get_secret().to_vec(),
+                )))
+            }
+            Request_oneof_request::set_pairing_confirmation(r) => {
+                let device = device_from_proto(r.get_device());
+                Ok(Outcome::Flag(
+                    self.bluetooth.lock().unwrap().set_pairing_confirmation(device, r.get_accept()),
+                ))
+            }
+            Request_oneof_request::get_remote_name(r) => {
+                let device = device_from_proto(r.get_device());
+                Ok(Outcome::Text(self.bluetooth.lock().unwrap().get_remote_name(device)))
+            }
+            Request_oneof_request::get_remote_uuids(r) => {
+                let device = device_from_proto(r.get_device());
+                Ok(Outcome::Uuids(self.bluetooth.lock().unwrap().get_remote_uuids(device)))
+            }
+            Request_oneof_request::fetch_remote_uuids(r) => {
+                let device = device_from_proto(r.get_device());
+                Ok(Outcome::Flag(self.bluetooth.lock().unwrap().fetch_remote_uuids(device)))
+            }
+            Request_oneof_request::get_connection_state(r) => {
+                let device = device_from_proto(r.get_device());
+                Ok(Outcome::Number(self.bluetooth.lock().unwrap().get_connection_state(device)))
+            }
+            Request_oneof_request::register_gatt_client(r) => {
+                let uuid = Uuid128Bit::try_from(r.get_app_uuid())
+                    .map_err(|_| BtError::invalid_argument("The app UUID must be 16 bytes"))?;
+                let callback = Box::new(UdsCallback::new(self.connection.clone()));
+                let id = callback.id;
+                self.connection.lock().unwrap().gatt_clients.insert(id, None);
+                self.bluetooth_gatt.lock().unwrap().register_client(
+                    BtUuid::from(uuid),
+                    callback,
+                    r.get_eatt_support(),
+                );
+                Ok(Outcome::Number(id))
+            }
+            Request_oneof_request::unregister_gatt_client(r) => {
+                let client_id = self.gatt_client(r.get_client_id())?;
+                self.connection.lock().unwrap().gatt_clients.retain(|_, id| *id != Some(client_id));
+                self.bluetooth_gatt.lock().unwrap().unregister_client(client_id);
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_client_connect(r) => {
+                self.bluetooth_gatt.lock().unwrap().client_connect(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_is_direct(),
+                    r.get_transport(),
+                    r.get_opportunistic(),
+                    r.get_phy(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_client_disconnect(r) => {
+                self.bluetooth_gatt.lock().unwrap().client_disconnect(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_add_device_to_background_connect(r) => {
+                self.bluetooth_gatt.lock().unwrap().add_device_to_background_connect(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_remove_device_from_background_connect(r) => {
+                self.bluetooth_gatt.lock().unwrap().remove_device_from_background_connect(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_discover_services(r) => {
+                self.bluetooth_gatt.lock().unwrap().discover_services(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_refresh_device(r) => {
+                self.bluetooth_gatt.lock().unwrap().refresh_device(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_read_characteristic(r) => {
+                self.bluetooth_gatt.lock().unwrap().read_characteristic(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_handle(),
+                    r.get_auth_req(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_write_characteristic(r) => {
+                let write_type = GattWriteType::from_i32(r.get_write_type())
+                    .ok_or_else(|| BtError::invalid_argument("Invalid write type"))?;
+                let status = self.bluetooth_gatt.lock().unwrap().write_characteristic(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_handle(),
+                    write_type,
+                    r.get_auth_req(),
+                    r.get_value().to_vec(),
+                );
+                Ok(Outcome::Number(status.to_u32().unwrap_or_default()))
+            }
+            Request_oneof_request::gatt_read_descriptor(r) => {
+                self.bluetooth_gatt.lock().unwrap().read_descriptor(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_handle(),
+                    r.get_auth_req(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_write_descriptor(r) => {
+                self.bluetooth_gatt.lock().unwrap().write_descriptor(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_handle(),
+                    r.get_auth_req(),
+                    r.get_value().to_vec(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_register_for_notification(r) => {
+                self.bluetooth_gatt.lock().unwrap().register_for_notification(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_handle(),
+                    r.get_enable(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_begin_reliable_write(r) => {
+                self.bluetooth_gatt.lock().unwrap().begin_reliable_write(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_end_reliable_write(r) => {
+                self.bluetooth_gatt.lock().unwrap().end_reliable_write(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_execute(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_cancel_operation(r) => {
+                self.bluetooth_gatt.lock().unwrap().cancel_operation(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_token(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_configure_mtu(r) => {
+                self.bluetooth_gatt.lock().unwrap().configure_mtu(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_mtu(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_read_remote_rssi(r) => {
+                self.bluetooth_gatt.lock().unwrap().read_remote_rssi(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_read_phy(r) => {
+                self.bluetooth_gatt.lock().unwrap().client_read_phy(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_set_preferred_phy(r) => {
+                self.bluetooth_gatt.lock().unwrap().client_set_preferred_phy(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    le_phy_from_proto(r.get_tx_phy())?,
+                    le_phy_from_proto(r.get_rx_phy())?,
+                    r.get_phy_options(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_connection_parameter_update(r) => {
+                self.bluetooth_gatt.lock().unwrap().connection_parameter_update(
+                    self.gatt_client(r.get_client_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_min_interval(),
+                    r.get_max_interval(),
+                    r.get_latency(),
+                    r.get_timeout(),
+                    ce_len_from_proto(r.get_min_ce_len())?,
+                    ce_len_from_proto(r.get_max_ce_len())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::register_scanner(_) => {
+                let callback = Box::new(UdsCallback::new(self.connection.clone()));
+                let id = callback.id;
+                self.connection.lock().unwrap().scanners.insert(id, None);
+                self.bluetooth_gatt.lock().unwrap().register_scanner(callback);
+                Ok(Outcome::Number(id))
+            }
+            Request_oneof_request::unregister_scanner(r) => {
+                let scanner_id = self.scanner(r.get_scanner_id())?;
+                self.connection.lock().unwrap().scanners.retain(|_, id| *id != Some(scanner_id));
+                self.bluetooth_gatt.lock().unwrap().unregister_scanner(scanner_id);
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::start_scan(r) => {
+                let settings = scan_settings_from_proto(r.get_settings())?;
+                let filters =
+                    r.get_filters().iter().map(scan_filter_from_proto).collect::<BtResult<_>>()?;
+                self.bluetooth_gatt.lock().unwrap().start_scan(
+                    self.scanner(r.get_scanner_id())?,
+                    settings,
+                    filters,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::stop_scan(r) => {
+                self.bluetooth_gatt.lock().unwrap().stop_scan(self.scanner(r.get_scanner_id())?);
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::start_advertising_set(r) => {
+                let callback = Box::new(UdsCallback::new(self.connection.clone()));
+                let advertiser_id = self.bluetooth_gatt.lock().unwrap().start_advertising_set(
+                    advertising_parameters_from_proto(r.get_parameters())?,
+                    advertise_data_from_proto(r.get_advertise_data())?,
+                    advertise_data_from_proto(r.get_scan_response())?,
+                    r.get_duration(),
+                    r.get_max_ext_adv_events(),
+                    callback,
+                )?;
+                self.connection.lock().unwrap().advertising_sets.insert(advertiser_id);
+                Ok(Outcome::SignedNumber(advertiser_id))
+            }
+            Request_oneof_request::stop_advertising_set(r) => {
+                let advertiser_id = self.advertiser(r.get_advertiser_id())?;
+                self.bluetooth_gatt.lock().unwrap().stop_advertising_set(advertiser_id)?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::enable_advertising_set(r) => {
+                self.bluetooth_gatt.lock().unwrap().enable_advertising_set(
+                    self.advertiser(r.get_advertiser_id())?,
+                    r.get_enable(),
+                    r.get_duration(),
+                    r.get_max_ext_adv_events(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::set_advertising_data(r) => {
+                self.bluetooth_gatt.lock().unwrap().set_advertising_data(
+                    self.advertiser(r.get_advertiser_id())?,
+                    advertise_data_from_proto(r.get_data())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::set_scan_response_data(r) => {
+                self.bluetooth_gatt.lock().unwrap().set_scan_response_data(
+                    self.advertiser(r.get_advertiser_id())?,
+                    advertise_data_from_proto(r.get_data())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::get_own_address(r) => {
+                self.bluetooth_gatt
+                    .lock()
+                    .unwrap()
+                    .get_own_address(self.advertiser(r.get_advertiser_id())?)?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::register_gatt_server(r) => {
+                let uuid = Uuid128Bit::try_from(r.get_app_uuid())
+                    .map_err(|_| BtError::invalid_argument("The app UUID must be 16 bytes"))?;
+                let callback = Box::new(UdsCallback::new(self.connection.clone()));
+                let id = callback.id;
+                self.connection.lock().unwrap().gatt_servers.insert(id, None);
+                self.bluetooth_gatt.lock().unwrap().register_server(
+                    BtUuid::from(uuid),
+                    callback,
+                    r.get_eatt_support(),
+                );
+                Ok(Outcome::Number(id))
+            }
+            Request_oneof_request::unregister_gatt_server(r) => {
+                let server_id = self.gatt_server(r.get_server_id())?;
+                self.connection.lock().unwrap().gatt_servers.retain(|_, id| *id != Some(server_id));
+                self.bluetooth_gatt.lock().unwrap().unregister_server(server_id);
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_server_connect(r) => {
+                self.bluetooth_gatt.lock().unwrap().server_connect(
+                    self.gatt_server(r.get_server_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_is_direct(),
+                    r.get_transport(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_server_disconnect(r) => {
+                self.bluetooth_gatt.lock().unwrap().server_disconnect(
+                    self.gatt_server(r.get_server_id())?,
+                    address_from_proto(r.get_address())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_add_service(r) => {
+                self.bluetooth_gatt.lock().unwrap().add_service(
+                    self.gatt_server(r.get_server_id())?,
+                    service_from_proto(r.get_service())?,
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_remove_service(r) => {
+                self.bluetooth_gatt
+                    .lock()
+                    .unwrap()
+                    .remove_service(self.gatt_server(r.get_server_id())?, r.get_handle())?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_send_response(r) => {
+                let status = GattStatus::from_i32(r.get_status())
+                    .ok_or_else(|| BtError::invalid_argument("Invalid status"))?;
+                self.bluetooth_gatt.lock().unwrap().send_response(
+                    self.gatt_server(r.get_server_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_request_id(),
+                    status,
+                    r.get_offset(),
+                    r.get_value().to_vec(),
+                )?;
+                Ok(Outcome::Done)
+            }
+            Request_oneof_request::gatt_send_notification(r) => {
+                self.bluetooth_gatt.lock().unwrap().send_notification(
+                    self.gatt_server(r.get_server_id())?,
+                    address_from_proto(r.get_address())?,
+                    r.get_handle(),
+                    r.get_confirm(),
+                    r.get_value().to_vec(),
+                )?;
+                Ok(Outcome::Done)
+            }
+        }
+    }
+
+    /// Removes the callbacks, GATT clients and GATT servers registered over the connection, the
+    /// stack removing the scanners and advertising sets of the callbacks. The GATT clients and
+    /// servers still being registered are unregistered by `on_client_registered` and
+    /// `on_server_registered`.
+    fn close(&self) {
+        let (observers, gatt_clients, gatt_servers) = {
+            let mut connection = self.connection.lock().unwrap();
+            connection.writer = None;
+            connection.scanners.clear();
+            connection.advertising_sets.clear();
+            (
+                std::mem::take(&mut connection.disconnect_observers),
+                std::mem::take(&mut connection.gatt_clients),
+                std::mem::take(&mut connection.gatt_servers),
+            )
+        };
+
+        for (id, observer) in observers {
+            observer(id);
+        }
+
+        let mut bluetooth_gatt = self.bluetooth_gatt.lock().unwrap();
+        for client_id in gatt_clients.into_values().flatten() {
+            bluetooth_gatt.unregister_client(client_id);
+        }
+        for server_id in gatt_servers.into_values().flatten() {
+            bluetooth_gatt.unregister_server(server_id);
+        }
+    }
+}
+
+/// Reads a message from a client, None once the connection is closed or broken.
+async fn read_message(reader: &mut OwnedReadHalf) -> Option<Vec<u8>> {
+    let len = reader.read_u32_le().await.ok()? as usize;
+    if len > MAX_MESSAGE_LEN {
+        warn!("Dropping a client sending a {} bytes message", len);
+        return None;
+    }
+
+    let mut buffer = vec![0; len];
+    reader.read_exact(&mut buffer).await.ok()?;
+    Some(buffer)
+}
+
+async fn serve_client(
+    stream: UnixStream,
+    bluetooth: Arc<Mutex<Box<Bluetooth>>>,
+    bluetooth_gatt: Arc<Mutex<Box<BluetoothGatt>>>,
+) {
+    let (mut reader, mut writer) = stream.into_split();
+    let (tx, mut rx) = channel::<Vec<u8>>(MAX_QUEUED_MESSAGES);
+    tokio::spawn(async move {
+        while let Some(frame) = rx.recv().await {
+            if writer.write_all(&frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let overflow = Arc::new(Notify::new());
+    let connection = Arc::new(Mutex::new(Connection {
+        writer: Some(tx),
+        overflow: overflow.clone(),
+        disconnect_observers: HashMap::new(),
+        gatt_clients: HashMap::new(),
+        gatt_servers: HashMap::new(),
+        scanners: HashMap::new(),
+        advertising_sets: HashSet::new(),
+        bluetooth_gatt: bluetooth_gatt.clone(),
+    }));
+    let session = Session { connection, bluetooth, bluetooth_gatt };
+
+    loop {
+        let bytes = tokio::select! {
+            bytes = read_message(&mut reader) => match bytes {
+                Some(bytes) => bytes,
+                None => break,
+            },
+            _ = overflow.notified() => break,
+        };
+        let request = match Request::parse_from_bytes(&bytes) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Dropping a client sending an invalid message: {}", e);
+                break;
+            }
+        };
+
+        let id = request.get_id();
+        let mut message = ServerMessage::new();
+        message.set_reply(make_reply(id, session.handle_request(request)));
+        session.connection.lock().unwrap().send(message);
+    }
+
+    debug!("UDS client disconnected");
+    session.close();
+}
+
+/// Removes the socket left at `path` by a previous instance of the daemon. Anything else at
+/// `path`, including a socket still being served, is an error.
+fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(std::io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} is not a socket", path.display()),
+        ));
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err(std::io::Error::new(
+            ErrorKind::AddrInUse,
+            format!("{} is served by another process", path.display()),
+        ));
+    }
+    std::fs::remove_file(path)
+}
+
+/// Whether a client may be served, given the user and group of the daemon.
+fn is_peer_allowed(peer_uid: u32, peer_gid: u32, uid: u32, gid: u32) -> bool {
+    peer_uid == 0 || peer_uid == uid || peer_gid == gid
+}
+
+/// Listens for clients on the unix domain socket at `path`, replacing any stale socket.
+pub fn start_uds_frontend(
+    path: String,
+    bluetooth: Arc<Mutex<Box<Bluetooth>>>,
+    bluetooth_gatt: Arc<Mutex<Box<BluetoothGatt>>>,
+) -> std::io::Result<()> {
+    remove_stale_socket(Path::new(&path))?;
+    let listener = UnixListener::bind(&path)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o660))?;
+    // The socket is owned by the user and the group of the daemon.
+    let metadata = std::fs::metadata(&path)?;
+    let (uid, gid) = (metadata.uid(), metadata.gid());
+    info!("Serving the UDS frontend on {}", path);
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept a UDS client: {}", e);
+                    continue;
+                }
+            };
+
+            // The permissions of the socket may not be enforced by the platform, and the socket
+            // was briefly open to any user before being restricted.
+            match stream.peer_cred() {
+                Ok(cred) if is_peer_allowed(cred.uid(), cred.gid(), uid, gid) => {
+                    tokio::spawn(serve_client(stream, bluetooth.clone(), bluetooth_gatt.clone()));
+                }
+                Ok(cred) => warn!("Refusing a UDS client of user {}", cred.uid()),
+                Err(e) => warn!("Failed to get the credentials of a UDS client: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener as StdUnixListener;
+    use std::path::PathBuf;
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("btadapterd-uds-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_encode_frame() {
+        let mut reply = Reply::new();
+        reply.set_id(7);
+        reply.set_flag(true);
+        let mut message = ServerMessage::new();
+        message.set_reply(reply);
+
+        let frame = encode_frame(&message).unwrap();
+        let len = u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize;
+        assert_eq!(len, frame.len() - 4);
+        assert_eq!(ServerMessage::parse_from_bytes(&frame[4..]).unwrap(), message);
+    }
+
+    #[test]
+    fn test_make_reply() {
+        let reply = make_reply(3, Ok(Outcome::Uuids(vec![[1; 16]])));
+        assert_eq!(reply.get_id(), 3);
+        assert_eq!(reply.get_uuids().get_uuids(), &[vec![1; 16]]);
+
+        let reply = make_reply(4, Err(BtError::invalid_argument("Invalid transport")));
+        assert_eq!(reply.get_id(), 4);
+        assert!(reply.has_error());
+        assert_eq!(reply.get_error().get_message(), "Invalid transport");
+    }
+
+    #[test]
+    fn test_request_conversions() {
+        assert_eq!(le_phy_from_proto(2).unwrap(), LePhy::Phy2m);
+        assert!(le_phy_from_proto(9).is_err());
+        assert_eq!(ce_len_from_proto(12).unwrap(), 12);
+        assert!(ce_len_from_proto(0x10000).is_err());
+        assert!(address_from_proto("00:11:22:33:44:55").is_ok());
+        assert!(address_from_proto("00:11:22:33:44").is_err());
+    }
+
+    #[test]
+    fn test_record_registration() {
+        let mut apps: HashMap<u32, Option<i32>> = vec![(1, None), (2, None)].into_iter().collect();
+
+        assert!(record_registration(&mut apps, 1, 0, 7));
+        assert!(is_registered(&apps, 7));
+        // A failed registration is forgotten but still reported.
+        assert!(record_registration(&mut apps, 2, 1, 0));
+        assert!(!apps.contains_key(&2));
+        // An app unregistered while the stack was registering it.
+        assert!(!record_registration(&mut apps, 3, 0, 8));
+        assert!(!is_registered(&apps, 8));
+    }
+
+    #[test]
+    fn test_service_conversions() {
+        let service = BluetoothGattService {
+            uuid: [1; 16],
+            instance_id: 0,
+            service_type: 0,
+            characteristics: vec![BluetoothGattCharacteristic {
+                uuid: [2; 16],
+                instance_id: 0,
+                properties: 0x12,
+                permissions: 0x01,
+                key_size: 16,
+                write_type: GattWriteType::Write,
+                descriptors: vec![BluetoothGattDescriptor {
+                    uuid: [3; 16],
+                    instance_id: 0,
+                    permissions: 0x11,
+                }],
+            }],
+            included_services: vec![],
+        };
+
+        let proto = service_to_proto(service.clone());
+        let converted = service_from_proto(&proto).unwrap();
+        assert_eq!(converted.uuid, service.uuid);
+        assert_eq!(converted.characteristics[0].permissions, 0x01);
+        assert_eq!(converted.characteristics[0].key_size, 16);
+        assert_eq!(converted.characteristics[0].descriptors[0].uuid, [3; 16]);
+        assert_eq!(converted.characteristics[0].descriptors[0].permissions, 0x11);
+
+        let mut proto = proto;
+        proto.set_uuid(vec![1; 4]);
+        assert!(service_from_proto(&proto).is_err());
+    }
+
+    #[test]
+    fn test_scan_filter_from_proto() {
+        let mut proto = bluetooth_ipc::ScanFilter::new();
+        proto.set_manufacturer_id(0x00e0);
+        proto.set_rssi_low_threshold(-80);
+        let filter = scan_filter_from_proto(&proto).unwrap();
+        assert_eq!(filter.manufacturer_id, 0x00e0);
+        assert_eq!(filter.rssi_low_threshold, -80);
+        assert_eq!(filter.rssi_high_threshold, ScanFilter::default().rssi_high_threshold);
+
+        proto.set_manufacturer_id(0x10000);
+        assert!(scan_filter_from_proto(&proto).is_err());
+    }
+
+    #[test]
+    fn test_is_peer_allowed() {
+        assert!(is_peer_allowed(0, 0, 1000, 1000));
+        assert!(is_peer_allowed(1000, 5, 1000, 1000));
+        assert!(is_peer_allowed(2000, 1000, 1000, 1000));
+        assert!(!is_peer_allowed(2000, 2000, 1000, 1000));
+    }
+
+    #[test]
+    fn test_remove_stale_socket() {
+        let path = test_path("stale");
+        let _ = std::fs::remove_file(&path);
+        assert!(remove_stale_socket(&path).is_ok());
+
+        // A socket no longer served is removed.
+        drop(StdUnixListener::bind(&path).unwrap());
+        assert!(remove_stale_socket(&path).is_ok());
+        assert!(!path.exists());
+
+        // A socket still served is kept.
+        let listener = StdUnixListener::bind(&path).unwrap();
+        assert_eq!(remove_stale_socket(&path).unwrap_err().kind(), ErrorKind::AddrInUse);
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_remove_stale_socket_keeps_other_files() {
+        let path = test_path("file");
+        std::fs::write(&path, b"data").unwrap();
+
+        assert_eq!(remove_stale_socket(&path).unwrap_err().kind(), ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&path).unwrap(), b"data");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+//! Addresses of remote devices as passed through the APIs.
+
+use std::fmt;
+
+use bt_topshim::btif::RawAddress;
+
+/// The address of a remote device passed to the GATT APIs.
+///
+/// It is represented in D-Bus as a string of six colon separated hexadecimal bytes
+/// (`AA:BB:CC:DD:EE:FF`), in upper or lower case, so that malformed addresses are rejected before
+/// reaching the stack instead of failing to match any device.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct BtAddress {
+    pub raw: RawAddress,
+}
+
+impl BtAddress {
+    /// Parses an address in the form accepted in D-Bus.
+    pub fn from_string<S: AsRef<str>>(addr: S) -> Option<BtAddress> {
+        let bytes: Vec<&str> = addr.as_ref().split(':').collect();
+        if bytes.len() != 6
+            || bytes.iter().any(|b| b.len() != 2 || !b.bytes().all(|c| c.is_ascii_hexdigit()))
+        {
+            return None;
+        }
+
+        let mut raw = RawAddress::default();
+        for (i, byte) in bytes.iter().enumerate() {
+            raw.val[i] = u8::from_str_radix(byte, 16).ok()?;
+        }
+        Some(BtAddress { raw })
+    }
+}
+
+impl From<RawAddress> for BtAddress {
+    fn from(raw: RawAddress) -> BtAddress {
+        BtAddress { raw }
+    }
+}
+
+impl From<BtAddress> for RawAddress {
+    fn from(addr: BtAddress) -> RawAddress {
+        addr.raw
+    }
+}
+
+/// Formats the address in upper case, the form of the addresses reported by the stack.
+impl fmt::Display for BtAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.raw.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_string() {
+        let addr = BtAddress::from_string("0a:1B:2c:3D:4e:5F").unwrap();
+        assert_eq!(addr.raw.val, [0x0a, 0x1b, 0x2c, 0x3d, 0x4e, 0x5f]);
+        assert_eq!(addr.to_string(), "0A:1B:2C:3D:4E:5F");
+        assert_eq!(BtAddress::from_string(addr.to_string()), Some(addr));
+
+        assert_eq!(BtAddress::from_string(""), None);
+        assert_eq!(BtAddress::from_string("0A:1B:2C:3D:4E"), None);
+        assert_eq!(BtAddress::from_string("0A:1B:2C:3D:4E:5F:60"), None);
+        assert_eq!(BtAddress::from_string("A:1B:2C:3D:4E:5F"), None);
+        assert_eq!(BtAddress::from_string("+A:1B:2C:3D:4E:5F"), None);
+        assert_eq!(BtAddress::from_string("0A:1B:2C:3D:4E:5G"), None);
+        assert_eq!(BtAddress::from_string("0A-1B-2C-3D-4E-5F"), None);
+    }
+
+    #[test]
+    fn test_raw_address() {
+        let raw = RawAddress { val: [0x0a, 0x1b, 0x2c, 0x3d, 0x4e, 0x5f] };
+        assert_eq!(RawAddress::from(BtAddress::from(raw)), raw);
+        assert_eq!(BtAddress::from(raw).to_string(), raw.to_string());
+    }
+}
@@ -0,0 +1,174 @@
+//! Resolution of the resolvable private addresses (RPA) found by the scans, see
+//! `ScanResult::identity_address`.
+//!
+//! A device advertising with an RPA changes its address every few minutes, so that the clients
+//! cannot tell its advertisements apart from those of unknown devices. The stack resolves the RPAs
+//! with the Identity Resolving Keys (IRK) distributed by the bonded devices, the same keys the
+//! native stack loads into the resolving list, so that the clients match the advertisements to the
+//! bonded devices without access to the keys.
+
+use bt_topshim::btif::RawAddress;
+use bt_topshim::controller::IdentityKey as BondedIdentityKey;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::crypto::aes128_encrypt;
+
+/// Shortest time between two updates of the keys, which change as devices are bonded or their
+/// bond removed.
+const KEYS_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most resolutions remembered, after which they are all forgotten.
+const MAX_CACHED_RESOLUTIONS: usize = 256;
+
+/// Identity key of a bonded device.
+#[derive(PartialEq)]
+struct IdentityKey {
+    /// IRK, most significant byte first.
+    irk: [u8; 16],
+    identity_address: String,
+}
+
+impl IdentityKey {
+    fn new(bonded_address: &RawAddress, key: &BondedIdentityKey) -> Self {
+        let mut irk = key.irk;
+        irk.reverse();
+
+        // The bonded address is the identity of the devices which distributed none.
+        let identity_address = match RawAddress::from_bytes(&key.identity_address) {
+            Some(addr) if addr.val != [0; 6] => addr.to_string(),
+            _ => bonded_address.to_string(),
+        };
+        IdentityKey { irk, identity_address }
+    }
+}
+
+/// Resolves the RPAs with the IRKs of the bonded devices.
+pub(crate) struct IdentityResolver {
+    keys: Vec<IdentityKey>,
+    last_update: Option<Instant>,
+    // Identity of the RPAs already resolved, None for the RPAs that do not resolve.
+    resolutions: HashMap<String, Option<String>>,
+}
+
+impl IdentityResolver {
+    pub(crate) fn new() -> Self {
+        IdentityResolver { keys: vec![], last_update: None, resolutions: HashMap::new() }
+    }
+
+    /// Returns the identity address of a device. An RPA is resolved with the keys of the bonded
+    /// devices, giving an empty address if none resolves it. The other addresses are their own
+    /// identity.
+    ///
+    /// `bonded_keys` returns the identity keys of the bonded devices by bonded address. It is
+    /// only called for the RPAs, at most once every `KEYS_UPDATE_INTERVAL`.
+    pub(crate) fn identity_of<F>(&mut self, address: &String, bonded_keys: F) -> String
+    where
+        F: FnOnce() -> Vec<(RawAddress, BondedIdentityKey)>,
+    {
+        let addr = match RawAddress::from_string(address.clone()) {
+            Some(addr) => addr,
+            None => return String::new(),
+        };
+        if !is_resolvable_private_address(&addr.val) {
+            return address.clone();
+        }
+
+        let now = Instant::now();
+        if self.last_update.map_or(true, |last| now.duration_since(last) >= KEYS_UPDATE_INTERVAL) {
+            self.last_update = Some(now);
+            self.update_keys(bonded_keys());
+        }
+        if let Some(identity) = self.resolutions.get(address) {
+            return identity.clone().unwrap_or_default();
+        }
+
+        let identity = self
+            .keys
+            .iter()
+            .find(|key| rpa_matches_irk(&addr.val, &key.irk))
+            .map(|key| key.identity_address.clone());
+        if self.resolutions.len() >= MAX_CACHED_RESOLUTIONS {
+            self.resolutions.clear();
+        }
+        self.resolutions.insert(address.clone(), identity.clone());
+        identity.unwrap_or_default()
+    }
+
+    /// Replaces the keys, forgetting the resolutions if they changed.
+    fn update_keys(&mut self, bonded_keys: Vec<(RawAddress, BondedIdentityKey)>) {
+        let keys: Vec<IdentityKey> =
+            bonded_keys.iter().map(|(address, key)| IdentityKey::new(address, key)).collect();
+        if keys != self.keys {
+            self.keys = keys;
+            self.resolutions.clear();
+        }
+    }
+}
+
+/// Returns whether an address is an RPA, whose two most significant bits are 0b01.
+pub(crate) fn is_resolvable_private_address(address: &[u8; 6]) -> bool {
+    address[0] & 0xC0 == 0x40
+}
+
+/// Checks the hash of an RPA against an IRK, with the random address hash function `ah` of the
+/// core specification (Vol 3, Part H, 2.2.2).
+fn rpa_matches_irk(rpa: &[u8; 6], irk: &[u8; 16]) -> bool {
+    // The upper half of the address is the random part, the lower half its hash.
+    let mut block = [0u8; 16];
+    block[13..16].copy_from_slice(&rpa[0..3]);
+    aes128_encrypt(irk, &block)[13..16] == rpa[3..6]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sample data of the core specification (Vol 3, Part H, D.7): the IRK
+    // ec0234a357c8ad05341010a60a397d9b hashes the random part 708194 into 0dfbaa.
+    const IRK: [u8; 16] = [
+        0x9b, 0x7d, 0x39, 0x0a, 0xa6, 0x10, 0x10, 0x34, 0x05, 0xad, 0xc8, 0x57, 0xa3, 0x34, 0x02,
+        0xec,
+    ];
+    const RPA: &str = "70:81:94:0D:FB:AA";
+
+    fn bonded_key(
+        bonded_address: &str,
+        identity_address: [u8; 6],
+    ) -> (RawAddress, BondedIdentityKey) {
+        (
+            RawAddress::from_string(bonded_address).unwrap(),
+            BondedIdentityKey { irk: IRK, identity_address },
+        )
+    }
+
+    #[test]
+    fn test_identity_of() {
+        let mut resolver = IdentityResolver::new();
+        let keys = || vec![bonded_key("11:22:33:44:55:66", [0x11, 0x22, 0x33, 0x44, 0x55, 0x77])];
+        assert_eq!("11:22:33:44:55:77", resolver.identity_of(&String::from(RPA), keys));
+        // Another hash of the same random part does not resolve.
+        assert_eq!("", resolver.identity_of(&String::from("70:81:94:0D:FB:AB"), keys));
+        // Public and static random addresses are their own identity.
+        assert_eq!(
+            "AA:BB:CC:DD:EE:FF",
+            resolver.identity_of(&String::from("AA:BB:CC:DD:EE:FF"), || unreachable!())
+        );
+    }
+
+    #[test]
+    fn test_keys_update() {
+        let mut resolver = IdentityResolver::new();
+        // The bonded address is the identity of a device which distributed none.
+        let keys = || vec![bonded_key("11:22:33:44:55:66", [0; 6])];
+        assert_eq!("11:22:33:44:55:66", resolver.identity_of(&String::from(RPA), keys));
+
+        // The keys are not asked for again right away.
+        assert_eq!("11:22:33:44:55:66", resolver.identity_of(&String::from(RPA), || vec![]));
+
+        // The resolutions are forgotten with the bond.
+        resolver.last_update = None;
+        assert_eq!("", resolver.identity_of(&String::from(RPA), || vec![]));
+    }
+}
@@ -0,0 +1,302 @@
+//! Regulatory and product limits on LE advertising, set in the policy file of `crate::policy`:
+//!
+//! ```text
+//! # Shortest interval of each set, in milliseconds.
+//! advertising_min_interval_ms = 200
+//! # Highest share of the time spent advertising by all the sets together, in percent.
+//! advertising_max_duty_cycle = 2.5
+//! # What to do with the sets out of policy: reject (the default) or adjust their interval.
+//! advertising_out_of_policy = adjust
+//! ```
+//!
+//! The duty cycle of a set is the air time of an advertising event over its interval. The air
+//! time is estimated from the PHYs and the length of the advertising data, the scan responses and
+//! connection requests being left out. All the enabled sets are counted, including the ones
+//! suspended to share the controller slots, so that the limit holds whichever sets advertise.
+
+use crate::bluetooth_adv::{AdvertisingSetParameters, FLAGS_LEN, INTERVAL_MAX, INTERVAL_MIN};
+use crate::bluetooth_gatt::LePhy;
+use crate::error::{BtError, BtErrorCategory, BtResult};
+
+/// Prefix of the settings of the policy file read by `AdvertisingPolicy::parse_setting`.
+pub(crate) const ADVERTISING_SETTING_PREFIX: &str = "advertising_";
+
+// Duration of the advertising interval units, in microseconds.
+const INTERVAL_UNIT_US: f64 = 625.0;
+
+// Advertising channels on which each primary advertising PDU is sent.
+const PRIMARY_CHANNELS: u64 = 3;
+
+// Longest payload of an advertising PDU.
+const PDU_PAYLOAD_MAX: usize = 255;
+
+// Payload of a legacy advertising PDU, besides the data: the advertiser address.
+const LEGACY_PDU_OVERHEAD: usize = 6;
+
+// Extended header of ADV_EXT_IND: length and mode, flags, ADI and AuxPtr.
+const EXT_IND_PDU_OVERHEAD: usize = 7;
+
+// Extended header of AUX_ADV_IND and AUX_CHAIN_IND, besides the advertiser address: length and
+// mode, flags, ADI and AuxPtr.
+const AUX_PDU_OVERHEAD: usize = 7;
+
+/// What is done with a set out of policy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OutOfPolicyAction {
+    /// The request is rejected with an error telling the limit.
+    Reject,
+    /// The interval of the set is lengthened to meet the policy.
+    Adjust,
+}
+
+impl Default for OutOfPolicyAction {
+    fn default() -> Self {
+        OutOfPolicyAction::Reject
+    }
+}
+
+/// Limits on the advertising sets. The sets are not limited by default.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AdvertisingPolicy {
+    /// Shortest interval of a set, in 0.625 ms units.
+    min_interval: Option<i32>,
+    /// Highest aggregate duty cycle of the sets, from 0 to 1.
+    max_duty_cycle: Option<f64>,
+    action: OutOfPolicyAction,
+}
+
+impl AdvertisingPolicy {
+    /// Reads a setting of the policy file starting with `ADVERTISING_SETTING_PREFIX`. Returns
+    /// why the setting is ignored if it is unknown or invalid.
+    pub(crate) fn parse_setting(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "advertising_min_interval_ms" => match value.parse::<f64>() {
+                Ok(ms) if ms > 0.0 => {
+                    self.min_interval = Some((ms * 1000.0 / INTERVAL_UNIT_US).ceil() as i32);
+                    Ok(())
+                }
+                _ => Err(format!("Invalid minimum advertising interval: {}", value)),
+            },
+            "advertising_max_duty_cycle" => match value.parse::<f64>() {
+                Ok(percent) if percent > 0.0 && percent <= 100.0 => {
+                    self.max_duty_cycle = Some(percent / 100.0);
+                    Ok(())
+                }
+                _ => Err(format!("Invalid maximum advertising duty cycle: {}", value)),
+            },
+            "advertising_out_of_policy" => {
+                self.action = match value {
+                    "reject" => OutOfPolicyAction::Reject,
+                    "adjust" => OutOfPolicyAction::Adjust,
+                    _ => return Err(format!("Invalid out of policy action: {}", value)),
+                };
+                Ok(())
+            }
+            _ => Err(format!("Unknown policy setting: {}", key)),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.min_interval.is_some() || self.max_duty_cycle.is_some()
+    }
+
+    /// Checks a set advertising `adv_data_len` bytes with `parameters`, while the other sets
+    /// take `others_duty_cycle` of the time. Returns the interval the set may use, the one of
+    /// `parameters` or, if the policy adjusts the sets, a longer one. Fails with the limit
+    /// exceeded otherwise.
+    pub(crate) fn check(
+        &self,
+        parameters: &AdvertisingSetParameters,
+        adv_data_len: usize,
+        others_duty_cycle: f64,
+    ) -> BtResult<i32> {
+        let interval = parameters.interval.clamp(INTERVAL_MIN, INTERVAL_MAX - 1);
+        let mut required = interval;
+
+        if let Some(min_interval) = self.min_interval {
+            if interval < min_interval {
+                if self.action == OutOfPolicyAction::Reject {
+                    return Err(out_of_policy(format!(
+                        "Advertising interval of {:.1} ms is shorter than the {:.1} ms allowed",
+                        interval_ms(interval),
+                        interval_ms(min_interval)
+                    )));
+                }
+                required = min_interval;
+            }
+        }
+
+        if let Some(max_duty_cycle) = self.max_duty_cycle {
+            let budget = max_duty_cycle - others_duty_cycle;
+            if budget <= 0.0 {
+                return Err(out_of_policy(format!(
+                    "The other advertising sets already take the {:.2}% duty cycle allowed",
+                    max_duty_cycle * 100.0
+                )));
+            }
+
+            let airtime = event_airtime_us(parameters, adv_data_len) as f64;
+            if airtime / (required as f64 * INTERVAL_UNIT_US) > budget {
+                let shortest = (airtime / (budget * INTERVAL_UNIT_US)).ceil() as i32;
+                if self.action == OutOfPolicyAction::Reject || shortest >= INTERVAL_MAX {
+                    return Err(out_of_policy(format!(
+                        "Advertising duty cycle of {:.2}% exceeds the {:.2}% left of the {:.2}% \
+                         allowed, the interval must be at least {:.1} ms",
+                        duty_cycle(parameters, adv_data_len) * 100.0,
+                        budget * 100.0,
+                        max_duty_cycle * 100.0,
+                        interval_ms(shortest)
+                    )));
+                }
+                required = shortest;
+            }
+        }
+
+        Ok(if required == interval { parameters.interval } else { required })
+    }
+}
+
+fn out_of_policy(message: String) -> BtError {
+    BtError::new(BtErrorCategory::PermissionDenied, message)
+}
+
+pub(crate) fn interval_ms(interval: i32) -> f64 {
+    interval as f64 * INTERVAL_UNIT_US / 1000.0
+}
+
+/// Returns the share of the time, from 0 to 1, a set advertising `adv_data_len` bytes with
+/// `parameters` spends on air.
+pub(crate) fn duty_cycle(parameters: &AdvertisingSetParameters, adv_data_len: usize) -> f64 {
+    let interval = parameters.interval.clamp(INTERVAL_MIN, INTERVAL_MAX - 1);
+    event_airtime_us(parameters, adv_data_len) as f64 / (interval as f64 * INTERVAL_UNIT_US)
+}
+
+/// Returns the air time of an advertising PDU with a payload of `payload_len` bytes, in
+/// microseconds. The LE Coded PHY is counted with the S=8 coding, the slowest.
+fn pdu_airtime_us(phy: LePhy, payload_len: usize) -> u64 {
+    // PDU header and CRC.
+    let len = (2 + payload_len + 3) as u64;
+    match phy {
+        // Preamble of 1 byte and access address.
+        LePhy::Phy1m | LePhy::Invalid => 8 * (1 + 4 + len),
+        // Preamble of 2 bytes and access address.
+        LePhy::Phy2m => 4 * (2 + 4 + len),
+        // Preamble, access address, coding indicator and terminators.
+        LePhy::PhyCoded => 80 + 256 + 16 + 24 + 64 * len + 24,
+    }
+}
+
+/// Returns the air time of an advertising event of a set, in microseconds.
+pub(crate) fn event_airtime_us(parameters: &AdvertisingSetParameters, adv_data_len: usize) -> u64 {
+    // The Flags are added by the stack to the advertising data of connectable sets.
+    let data_len = if parameters.connectable { adv_data_len + FLAGS_LEN } else { adv_data_len };
+
+    if parameters.is_legacy {
+        return PRIMARY_CHANNELS * pdu_airtime_us(LePhy::Phy1m, LEGACY_PDU_OVERHEAD + data_len);
+    }
+
+    let tx_power_len = if parameters.include_tx_power { 1 } else { 0 };
+    let primary = PRIMARY_CHANNELS
+        * pdu_airtime_us(parameters.primary_phy, EXT_IND_PDU_OVERHEAD + tx_power_len);
+
+    // The data is sent in AUX_ADV_IND, followed by AUX_CHAIN_IND for what does not fit.
+    let address_len = if parameters.is_anonymous { 0 } else { 6 };
+    let mut auxiliary = 0;
+    let mut remaining = data_len;
+    let mut overhead = AUX_PDU_OVERHEAD + address_len + tx_power_len;
+    loop {
+        let chunk = remaining.min(PDU_PAYLOAD_MAX - overhead);
+        auxiliary += pdu_airtime_us(parameters.secondary_phy, overhead + chunk);
+        remaining -= chunk;
+        if remaining == 0 {
+            break;
+        }
+        overhead = AUX_PDU_OVERHEAD;
+    }
+
+    primary + auxiliary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_params(interval: i32) -> AdvertisingSetParameters {
+        AdvertisingSetParameters {
+            connectable: false,
+            scannable: false,
+            is_legacy: true,
+            interval,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_airtime() {
+        // 3 ADV_NONCONN_IND of 31 bytes of data on the LE 1M PHY.
+        assert_eq!(event_airtime_us(&legacy_params(160), 31), 3 * 8 * (1 + 4 + 2 + 6 + 31 + 3));
+
+        let mut extended = AdvertisingSetParameters {
+            primary_phy: LePhy::Phy1m,
+            secondary_phy: LePhy::Phy2m,
+            interval: 160,
+            ..Default::default()
+        };
+        let short = event_airtime_us(&extended, 20);
+        // Data longer than a PDU is chained.
+        assert!(event_airtime_us(&extended, 600) > short + 4 * 2 * AUX_PDU_OVERHEAD as u64);
+        extended.secondary_phy = LePhy::PhyCoded;
+        assert!(event_airtime_us(&extended, 20) > short);
+    }
+
+    #[test]
+    fn test_min_interval() {
+        let mut policy = AdvertisingPolicy::default();
+        assert!(!policy.is_enabled());
+        assert_eq!(policy.check(&legacy_params(160), 31, 0.0), Ok(160));
+
+        assert!(policy.parse_setting("advertising_min_interval_ms", "200").is_ok());
+        assert!(policy.is_enabled());
+        let err = policy.check(&legacy_params(160), 31, 0.0).unwrap_err();
+        assert_eq!(err.category, BtErrorCategory::PermissionDenied);
+        assert_eq!(policy.check(&legacy_params(320), 31, 0.0), Ok(320));
+
+        assert!(policy.parse_setting("advertising_out_of_policy", "adjust").is_ok());
+        assert_eq!(policy.check(&legacy_params(160), 31, 0.0), Ok(320));
+    }
+
+    #[test]
+    fn test_duty_cycle() {
+        let mut policy = AdvertisingPolicy::default();
+        assert!(policy.parse_setting("advertising_max_duty_cycle", "1").is_ok());
+
+        // About 1.1 ms of air time every 100 ms.
+        let params = legacy_params(160);
+        assert!(duty_cycle(&params, 31) > 0.01);
+        assert!(policy.check(&params, 31, 0.0).is_err());
+        assert!(policy.check(&legacy_params(320), 31, 0.0).is_ok());
+        assert!(policy.check(&legacy_params(320), 31, 0.005).is_err());
+        assert!(policy.check(&legacy_params(320), 31, 0.01).is_err());
+
+        assert!(policy.parse_setting("advertising_out_of_policy", "adjust").is_ok());
+        let interval = policy.check(&params, 31, 0.005).unwrap();
+        assert!(duty_cycle(&legacy_params(interval), 31) <= 0.005);
+        assert!(duty_cycle(&legacy_params(interval - 1), 31) > 0.005);
+        // No interval fits a budget already taken.
+        assert!(policy.check(&params, 31, 0.01).is_err());
+    }
+
+    #[test]
+    fn test_parse_setting() {
+        let mut policy = AdvertisingPolicy::default();
+        assert!(policy.parse_setting("advertising_min_interval_ms", "-1").is_err());
+        assert!(policy.parse_setting("advertising_max_duty_cycle", "101").is_err());
+        assert!(policy.parse_setting("advertising_out_of_policy", "ignore").is_err());
+        assert!(policy.parse_setting("advertising_unknown", "1").is_err());
+        assert!(!policy.is_enabled());
+        assert_eq!(policy.action, OutOfPolicyAction::Reject);
+
+        assert!(policy.parse_setting("advertising_min_interval_ms", "100.5").is_ok());
+        assert_eq!(policy.min_interval, Some(161));
+    }
+}
@@ -0,0 +1,220 @@
+//! Retries of the idempotent ATT operations failing with a transient error, such as the device
+//! lacking resources or the stack being busy, see `IBluetoothGatt::set_att_retry_policy`.
+
+use bt_topshim::profiles::gatt::GattStatus;
+
+use crate::bluetooth_gatt::GattOperation;
+
+use num_traits::cast::FromPrimitive;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Most attempts of an operation a policy may allow, the first one included.
+pub(crate) const MAX_ATT_ATTEMPTS: u32 = 8;
+
+/// Longest wait before an attempt, however many attempts failed before.
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Shift of the number of retries in the status of a retried operation, above the `GattStatus`
+/// of its last attempt.
+pub const ATT_RETRIES_SHIFT: u32 = 8;
+
+/// Retry policy of a client.
+#[derive(Clone, Debug, Default)]
+pub struct AttRetryPolicy {
+    /// Most attempts of an operation, the first one included. 0 and 1 disable the retries.
+    pub max_attempts: u32,
+    /// Wait before the first retry, in milliseconds, doubled at each following retry.
+    pub backoff_ms: u32,
+}
+
+impl AttRetryPolicy {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_attempts > 1
+    }
+
+    /// Returns the wait before the retry `retry`, counted from 1, or None if the policy does not
+    /// allow that many attempts.
+    pub(crate) fn backoff(&self, retry: u32) -> Option<Duration> {
+        if retry == 0 || retry >= self.max_attempts {
+            return None;
+        }
+
+        let factor = 1u64.checked_shl(retry - 1).unwrap_or(u64::MAX);
+        let backoff = Duration::from_millis((self.backoff_ms as u64).saturating_mul(factor));
+        Some(backoff.min(MAX_BACKOFF))
+    }
+}
+
+/// Read of a client with a retry policy, until its result is delivered.
+#[derive(Clone, Copy)]
+pub(crate) struct AttRetry {
+    pub operation: GattOperation,
+    pub auth_req: i32,
+    pub retries: u32,
+    // Status of the last failed attempt.
+    pub status: i32,
+}
+
+/// Retry policies of the clients and the reads they are retrying.
+#[derive(Default)]
+pub(crate) struct AttRetries {
+    // Policies of the clients, by client ID.
+    policies: HashMap<i32, AttRetryPolicy>,
+    // Reads of the clients with a policy, by connection ID and attribute handle.
+    reads: HashMap<(i32, i32), AttRetry>,
+}
+
+impl AttRetries {
+    /// Sets the policy of a client. A disabled policy stops the retries of its next reads.
+    pub(crate) fn set_policy(&mut self, client_id: i32, policy: AttRetryPolicy) {
+        if policy.is_enabled() {
+            self.policies.insert(client_id, policy);
+        } else {
+            self.policies.remove(&client_id);
+        }
+    }
+
+    pub(crate) fn remove_client(&mut self, client_id: i32) {
+        self.policies.remove(&client_id);
+    }
+
+    /// Remembers a read of a client, to send it again if it fails with a transient error. Does
+    /// nothing if the client has no policy.
+    pub(crate) fn track(
+        &mut self,
+        client_id: i32,
+        conn_id: i32,
+        operation: GattOperation,
+        handle: i32,
+        auth_req: i32,
+    ) {
+        if self.policies.contains_key(&client_id) {
+            let retry = AttRetry { operation, auth_req, retries: 0, status: 0 };
+            self.reads.insert((conn_id, handle), retry);
+        }
+    }
+
+    pub(crate) fn get(&self, conn_id: i32, handle: i32) -> Option<AttRetry> {
+        self.reads.get(&(conn_id, handle)).copied()
+    }
+
+    /// Records an attempt of a read failing with `status`. Returns the wait before the next
+    /// attempt, or None if the read is not tracked or the policy of the client does not allow
+    /// another attempt.
+    pub(crate) fn retry(
+        &mut self,
+        client_id: i32,
+        conn_id: i32,
+        handle: i32,
+        status: i32,
+    ) -> Option<Duration> {
+        let policy = self.policies.get(&client_id)?;
+        let retry = self.reads.get_mut(&(conn_id, handle))?;
+        let backoff = policy.backoff(retry.retries + 1)?;
+
+        retry.retries += 1;
+        retry.status = status;
+        Some(backoff)
+    }
+
+    /// Forgets a read once its result is delivered. Returns how many times it was retried.
+    pub(crate) fn take(&mut self, conn_id: i32, handle: i32) -> u32 {
+        self.reads.remove(&(conn_id, handle)).map_or(0, |retry| retry.retries)
+    }
+
+    /// Forgets the reads of a closed connection.
+    pub(crate) fn remove_connection(&mut self, conn_id: i32) {
+        self.reads.retain(|(id, _), _| *id != conn_id);
+    }
+}
+
+/// Returns the status reported for an operation which ended with `status` after `retries`
+/// retries. The `GattStatus` of the last attempt stays in the low byte, so a status with no
+/// retries is left as it is.
+pub(crate) fn status_with_retries(status: i32, retries: u32) -> i32 {
+    ((retries.min(MAX_ATT_ATTEMPTS) as i32) << ATT_RETRIES_SHIFT) | (status & 0xff)
+}
+
+/// Returns whether an operation failing with `status` may succeed when attempted again.
+pub(crate) fn is_transient_error(status: i32) -> bool {
+    match GattStatus::from_i32(status) {
+        Some(GattStatus::InsufResource)
+        | Some(GattStatus::NoResources)
+        | Some(GattStatus::Busy) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::cast::ToPrimitive;
+
+    #[test]
+    fn test_backoff() {
+        let policy = AttRetryPolicy { max_attempts: 4, backoff_ms: 100 };
+        assert!(policy.is_enabled());
+        assert_eq!(policy.backoff(0), None);
+        assert_eq!(policy.backoff(1), Some(Duration::from_millis(100)));
+        assert_eq!(policy.backoff(2), Some(Duration::from_millis(200)));
+        assert_eq!(policy.backoff(3), Some(Duration::from_millis(400)));
+        assert_eq!(policy.backoff(4), None);
+
+        let policy = AttRetryPolicy { max_attempts: MAX_ATT_ATTEMPTS, backoff_ms: 2000 };
+        assert_eq!(policy.backoff(3), Some(MAX_BACKOFF));
+
+        let policy = AttRetryPolicy { max_attempts: 1, backoff_ms: 100 };
+        assert!(!policy.is_enabled());
+        assert_eq!(policy.backoff(1), None);
+    }
+
+    #[test]
+    fn test_att_retries() {
+        let busy = GattStatus::Busy.to_i32().unwrap();
+        let mut retries = AttRetries::default();
+
+        // The reads of the clients without a policy are not retried.
+        retries.track(1, 10, GattOperation::ReadCharacteristic, 3, 0);
+        assert!(retries.get(10, 3).is_none());
+
+        retries.set_policy(1, AttRetryPolicy { max_attempts: 3, backoff_ms: 100 });
+        retries.track(1, 10, GattOperation::ReadCharacteristic, 3, 0);
+        assert_eq!(retries.retry(1, 10, 3, busy), Some(Duration::from_millis(100)));
+        assert_eq!(retries.retry(1, 10, 3, busy), Some(Duration::from_millis(200)));
+        assert_eq!(retries.retry(1, 10, 3, busy), None);
+        assert_eq!(retries.get(10, 3).map(|r| r.status), Some(busy));
+        assert_eq!(retries.take(10, 3), 2);
+        assert_eq!(retries.take(10, 3), 0);
+
+        // The reads of a closed connection are forgotten.
+        retries.track(1, 10, GattOperation::ReadDescriptor, 4, 0);
+        retries.remove_connection(10);
+        assert!(retries.get(10, 4).is_none());
+
+        // A disabled policy stops the retries.
+        retries.track(1, 11, GattOperation::ReadCharacteristic, 3, 0);
+        retries.set_policy(1, AttRetryPolicy::default());
+        assert_eq!(retries.retry(1, 11, 3, busy), None);
+    }
+
+    #[test]
+    fn test_status_with_retries() {
+        let busy = GattStatus::Busy.to_i32().unwrap();
+        assert_eq!(status_with_retries(busy, 0), busy);
+        assert_eq!(status_with_retries(0, 0), 0);
+
+        let status = status_with_retries(busy, 3);
+        assert_eq!(status & 0xff, busy);
+        assert_eq!(status >> ATT_RETRIES_SHIFT, 3);
+        assert_eq!(status_with_retries(0, 2) >> ATT_RETRIES_SHIFT, 2);
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(is_transient_error(GattStatus::InsufResource.to_i32().unwrap()));
+        assert!(is_transient_error(GattStatus::Busy.to_i32().unwrap()));
+        assert!(!is_transient_error(GattStatus::Success.to_i32().unwrap()));
+        assert!(!is_transient_error(GattStatus::InsufAuthentication.to_i32().unwrap()));
+    }
+}
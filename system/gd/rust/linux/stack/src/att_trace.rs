@@ -0,0 +1,257 @@
+//! Lightweight tracing of the ATT PDUs exchanged with a single remote device, for debugging
+//! without a full snoop capture.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+/// Maximum number of PDUs kept by a trace. The oldest ones are dropped first.
+pub const ATT_TRACE_CAPACITY: usize = 256;
+
+// ATT opcodes, see Core Specification Vol 3, Part F, 3.4.
+pub const ATT_ERROR_RSP: u8 = 0x01;
+pub const ATT_EXCHANGE_MTU_REQ: u8 = 0x02;
+pub const ATT_EXCHANGE_MTU_RSP: u8 = 0x03;
+pub const ATT_READ_BY_TYPE_REQ: u8 = 0x08;
+pub const ATT_READ_BY_TYPE_RSP: u8 = 0x09;
+pub const ATT_READ_REQ: u8 = 0x0A;
+pub const ATT_READ_RSP: u8 = 0x0B;
+pub const ATT_READ_BLOB_REQ: u8 = 0x0C;
+pub const ATT_READ_BLOB_RSP: u8 = 0x0D;
+pub const ATT_WRITE_REQ: u8 = 0x12;
+pub const ATT_WRITE_RSP: u8 = 0x13;
+pub const ATT_PREPARE_WRITE_REQ: u8 = 0x16;
+pub const ATT_PREPARE_WRITE_RSP: u8 = 0x17;
+pub const ATT_EXECUTE_WRITE_REQ: u8 = 0x18;
+pub const ATT_EXECUTE_WRITE_RSP: u8 = 0x19;
+pub const ATT_HANDLE_VALUE_NTF: u8 = 0x1B;
+pub const ATT_HANDLE_VALUE_IND: u8 = 0x1D;
+pub const ATT_MULTIPLE_HANDLE_VALUE_NTF: u8 = 0x23;
+pub const ATT_WRITE_CMD: u8 = 0x52;
+
+/// Returns the opcode of the response to a request, which is an Error Response if the request
+/// failed with `status`.
+pub(crate) fn response_opcode(opcode: u8, status: i32) -> u8 {
+    if status == 0 {
+        opcode
+    } else {
+        ATT_ERROR_RSP
+    }
+}
+
+/// Returns the opcode of a write request received by the server.
+pub(crate) fn write_request_opcode(need_rsp: bool, is_prep: bool) -> u8 {
+    if !need_rsp {
+        ATT_WRITE_CMD
+    } else if is_prep {
+        ATT_PREPARE_WRITE_REQ
+    } else {
+        ATT_WRITE_REQ
+    }
+}
+
+/// Returns the opcode of the response expected for a request, if any.
+fn expected_response(request: u8) -> Option<u8> {
+    match request {
+        ATT_EXCHANGE_MTU_REQ => Some(ATT_EXCHANGE_MTU_RSP),
+        ATT_READ_BY_TYPE_REQ => Some(ATT_READ_BY_TYPE_RSP),
+        ATT_READ_REQ => Some(ATT_READ_RSP),
+        ATT_READ_BLOB_REQ => Some(ATT_READ_BLOB_RSP),
+        ATT_WRITE_REQ => Some(ATT_WRITE_RSP),
+        ATT_PREPARE_WRITE_REQ => Some(ATT_PREPARE_WRITE_RSP),
+        ATT_EXECUTE_WRITE_REQ => Some(ATT_EXECUTE_WRITE_RSP),
+        _ => None,
+    }
+}
+
+/// Whether a PDU was sent to or received from the remote device.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, Eq, Hash, PartialEq)]
+#[repr(u32)]
+pub enum AttPduDirection {
+    Sent = 0,
+    Received = 1,
+}
+
+impl Default for AttPduDirection {
+    fn default() -> Self {
+        AttPduDirection::Sent
+    }
+}
+
+impl AttPduDirection {
+    fn reverse(self) -> AttPduDirection {
+        match self {
+            AttPduDirection::Sent => AttPduDirection::Received,
+            AttPduDirection::Received => AttPduDirection::Sent,
+        }
+    }
+}
+
+/// A decoded ATT PDU.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AttPduRecord {
+    pub direction: AttPduDirection,
+    pub opcode: u8,
+    /// Attribute handle, or 0 if the PDU does not refer to one.
+    pub handle: i32,
+    /// Length of the attribute value carried by the PDU.
+    pub length: i32,
+    /// GATT status, or 0 if the PDU does not carry one.
+    pub status: i32,
+    /// Time elapsed since the previous PDU of the trace, in microseconds.
+    pub delta_us: u64,
+}
+
+/// Bounded ring of the ATT PDUs exchanged with `address`.
+pub(crate) struct AttTrace {
+    pub(crate) address: String,
+    /// Whether PDUs are still being recorded. A stopped trace can still be retrieved.
+    pub(crate) active: bool,
+    records: VecDeque<AttPduRecord>,
+    last_pdu: Option<Instant>,
+    // Opcodes of the expected responses, keyed by direction and key of the request.
+    pending_responses: HashMap<(AttPduDirection, i32), u8>,
+}
+
+impl AttTrace {
+    pub(crate) fn new(address: String) -> AttTrace {
+        AttTrace {
+            address,
+            active: true,
+            records: VecDeque::new(),
+            last_pdu: None,
+            pending_responses: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        now: Instant,
+        direction: AttPduDirection,
+        opcode: u8,
+        handle: i32,
+        length: usize,
+        status: i32,
+    ) {
+        let delta_us =
+            self.last_pdu.map_or(0, |last| now.saturating_duration_since(last).as_micros() as u64);
+        self.last_pdu = Some(now);
+
+        if self.records.len() >= ATT_TRACE_CAPACITY {
+            self.records.pop_front();
+        }
+        self.records.push_back(AttPduRecord {
+            direction,
+            opcode,
+            handle,
+            length: length as i32,
+            status,
+            delta_us,
+        });
+    }
+
+    /// Records a request, which is answered by `record_response` with the same `key`: the
+    /// handle for the requests of the local client, the transaction ID for the local server.
+    pub(crate) fn record_request(
+        &mut self,
+        now: Instant,
+        direction: AttPduDirection,
+        key: i32,
+        opcode: u8,
+        handle: i32,
+        length: usize,
+    ) {
+        self.record(now, direction, opcode, handle, length, 0);
+
+        if let Some(response) = expected_response(opcode) {
+            // Some requests may never be answered, e.g. if the link drops.
+            if self.pending_responses.len() >= ATT_TRACE_CAPACITY {
+                self.pending_responses.clear();
+            }
+            self.pending_responses.insert((direction, key), response);
+        }
+    }
+
+    /// Records the response to the request with `key`. Responses to requests that were not
+    /// traced, such as write commands or requests made before the trace started, are ignored.
+    pub(crate) fn record_response(
+        &mut self,
+        now: Instant,
+        direction: AttPduDirection,
+        key: i32,
+        handle: i32,
+        length: usize,
+        status: i32,
+    ) {
+        if let Some(response) = self.pending_responses.remove(&(direction.reverse(), key)) {
+            let opcode = response_opcode(response, status);
+            self.record(now, direction, opcode, handle, length, status);
+        }
+    }
+
+    pub(crate) fn records(&self) -> Vec<AttPduRecord> {
+        self.records.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_att_trace_ring() {
+        let mut trace = AttTrace::new(String::from("AA:BB:CC:DD:EE:FF"));
+        let start = Instant::now();
+
+        trace.record(start, AttPduDirection::Sent, ATT_READ_REQ, 3, 0, 0);
+        trace.record(
+            start + Duration::from_millis(5),
+            AttPduDirection::Received,
+            response_opcode(ATT_READ_RSP, 0),
+            3,
+            20,
+            0,
+        );
+
+        let records = trace.records();
+        assert_eq!(2, records.len());
+        assert_eq!(0, records[0].delta_us);
+        assert_eq!(ATT_READ_RSP, records[1].opcode);
+        assert_eq!(20, records[1].length);
+        assert_eq!(5000, records[1].delta_us);
+
+        // The oldest PDUs are dropped once the trace is full.
+        for i in 0..ATT_TRACE_CAPACITY {
+            trace.record(start, AttPduDirection::Sent, ATT_WRITE_CMD, i as i32, 1, 0);
+        }
+
+        let records = trace.records();
+        assert_eq!(ATT_TRACE_CAPACITY, records.len());
+        assert_eq!(0, records[0].handle);
+        assert_eq!(ATT_TRACE_CAPACITY as i32 - 1, records[ATT_TRACE_CAPACITY - 1].handle);
+    }
+
+    #[test]
+    fn test_att_trace_responses() {
+        let mut trace = AttTrace::new(String::from("AA:BB:CC:DD:EE:FF"));
+        let now = Instant::now();
+        let (sent, received) = (AttPduDirection::Sent, AttPduDirection::Received);
+
+        trace.record_request(now, received, 1, ATT_WRITE_REQ, 5, 2);
+        trace.record_request(now, received, 2, ATT_READ_BLOB_REQ, 7, 0);
+        trace.record_request(now, sent, 9, ATT_WRITE_CMD, 9, 4);
+        trace.record_response(now, sent, 2, 7, 10, 0);
+        trace.record_response(now, sent, 1, 5, 0, 0x03);
+        // Write commands are not answered.
+        trace.record_response(now, received, 9, 9, 0, 0);
+
+        let records = trace.records();
+        let opcodes: Vec<u8> = records.iter().map(|r| r.opcode).collect();
+        assert_eq!(
+            vec![ATT_WRITE_REQ, ATT_READ_BLOB_REQ, ATT_WRITE_CMD, ATT_READ_BLOB_RSP, ATT_ERROR_RSP],
+            opcodes
+        );
+        assert_eq!(sent, records[4].direction);
+        assert_eq!(0x03, records[4].status);
+    }
+}
@@ -0,0 +1,505 @@
+//! Battery levels of the connected devices, collected from the Battery Service of the bonded LE
+//! devices and from the battery indicator of the hands-free units, so that the clients do not run
+//! their own GATT clients just for the battery levels.
+
+use bt_topshim::btif::{BtBondState, BtTransport, Uuid128Bit};
+use bt_topshim::profiles::gatt::GattStatus;
+use bt_topshim::profiles::hfp::HfpCallbacks;
+use bt_topshim::topstack;
+
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::Sender;
+
+use crate::address::BtAddress;
+use crate::bluetooth::{Bluetooth, BluetoothDevice, IBluetooth, IBluetoothConnectionCallback};
+use crate::bluetooth_gatt::{
+    BluetoothGatt, BluetoothGattCharacteristic, BluetoothGattService, CharacteristicReadResult,
+    GattHandleValue, IBluetoothGatt, IBluetoothGattCallback, LePhy, BASE_UUID,
+};
+use crate::error::{BtError, BtResult};
+use crate::gatt_conformance::ConformanceIssue;
+use crate::gatt_service_builder::CCCD_UUID;
+use crate::{Message, RPCProxy};
+
+/// Application UUID of the GATT client reading the Battery Service.
+const BATTERY_CLIENT_UUID: Uuid128Bit = [
+    0x3B, 0x5E, 0x91, 0x0D, 0x7A, 0x24, 0x4C, 0x61, 0x8F, 0x02, 0xD6, 0x4B, 0x1E, 0x93, 0x18, 0x0F,
+];
+
+const BATTERY_SERVICE_UUID16: [u8; 2] = [0x18, 0x0F];
+const BATTERY_LEVEL_UUID16: [u8; 2] = [0x2A, 0x19];
+
+// Value of the CCCD enabling the notifications.
+const CCCD_ENABLE_NOTIFICATION: [u8; 2] = [0x01, 0x00];
+
+fn uuid16(short: [u8; 2]) -> Uuid128Bit {
+    let mut uuid = BASE_UUID;
+    uuid[2..4].copy_from_slice(&short);
+    uuid
+}
+
+/// Defines the battery manager API.
+pub trait IBatteryManager {
+    /// Adds an observer of the battery levels.
+    ///
+    /// Returns the id of the callback.
+    fn register_battery_callback(
+        &mut self,
+        callback: Box<dyn IBatteryManagerCallback + Send>,
+    ) -> u32;
+
+    /// Removes an observer of the battery levels.
+    ///
+    /// Returns false if `callback_id` is not recognized.
+    fn unregister_battery_callback(&mut self, callback_id: u32) -> bool;
+
+    /// Returns the last battery level reported by a connected device.
+    fn get_battery_information(&self, addr: String) -> BtResult<BatteryInfo>;
+}
+
+/// Battery level events.
+pub trait IBatteryManagerCallback: RPCProxy {
+    /// When a connected device reports its battery level.
+    fn on_battery_info_updated(&self, addr: String, battery_info: BatteryInfo);
+}
+
+/// Source of a battery level.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum BatterySource {
+    /// Battery Level characteristic of the Battery Service.
+    Gatt = 0,
+    /// Battery level indicator of a hands-free unit.
+    Hfp = 1,
+}
+
+impl Default for BatterySource {
+    fn default() -> Self {
+        BatterySource::Gatt
+    }
+}
+
+/// Battery level of a device, as last reported by any of its sources.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BatteryInfo {
+    pub address: String,
+    /// Battery level in percent.
+    pub percentage: u32,
+    pub source: BatterySource,
+}
+
+/// Actions of the battery manager dispatched from the event loop, as the events of the GATT
+/// client and of the adapter are delivered while their objects are locked.
+pub enum BatteryActions {
+    /// Params: Status, Client ID
+    GattClientRegistered(i32, i32),
+    DeviceConnected(BluetoothDevice),
+    DeviceDisconnected(BluetoothDevice),
+    /// Params: Address, Connected
+    GattConnectionState(String, bool),
+    /// Params: Address, Services
+    GattSearchComplete(String, Vec<BluetoothGattService>),
+    /// Params: Address, Handle, Value
+    GattValue(String, i32, Vec<u8>),
+}
+
+/// Implementation of the battery manager API.
+pub struct BatteryManager {
+    tx: Sender<Message>,
+    adapter: Option<Arc<Mutex<Box<Bluetooth>>>>,
+    gatt: Option<Arc<Mutex<Box<BluetoothGatt>>>>,
+    client_id: Option<i32>,
+    callbacks: HashMap<u32, Box<dyn IBatteryManagerCallback + Send>>,
+    // Handle of the Battery Level characteristic of the connected devices, by address.
+    battery_level_handles: HashMap<String, i32>,
+    batteries: HashMap<String, BatteryInfo>,
+}
+
+impl BatteryManager {
+    pub fn new(tx: Sender<Message>) -> BatteryManager {
+        BatteryManager {
+            tx,
+            adapter: None,
+            gatt: None,
+            client_id: None,
+            callbacks: HashMap::new(),
+            battery_level_handles: HashMap::new(),
+            batteries: HashMap::new(),
+        }
+    }
+
+    /// Registers the GATT client reading the Battery Service and starts watching the connections
+    /// of the devices. Must be called once the profiles are initialized.
+    pub fn init(
+        &mut self,
+        adapter: Arc<Mutex<Box<Bluetooth>>>,
+        gatt: Arc<Mutex<Box<BluetoothGatt>>>,
+    ) {
+        adapter.lock().unwrap().register_connection_callback(Box::new(BatteryConnectionCallback {
+            tx: self.tx.clone(),
+        }));
+        gatt.lock().unwrap().register_client(
+            crate::uuid::BtUuid { uu: BATTERY_CLIENT_UUID },
+            Box::new(BatteryGattCallback { tx: self.tx.clone() }),
+            false,
+        );
+
+        self.adapter = Some(adapter);
+        self.gatt = Some(gatt);
+    }
+
+    pub(crate) fn remove_callback(&mut self, id: u32) -> bool {
+        match self.callbacks.get_mut(&id) {
+            Some(callback) => {
+                callback.unregister(id);
+                self.callbacks.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn dispatch_battery_actions(&mut self, action: BatteryActions) {
+        match action {
+            BatteryActions::GattClientRegistered(status, client_id) => {
+                if status != GattStatus::Success as i32 {
+                    warn!("Failed to register the battery GATT client: {}", status);
+                    return;
+                }
+                self.client_id = Some(client_id);
+            }
+            BatteryActions::DeviceConnected(device) => self.connect_battery_service(device),
+            BatteryActions::DeviceDisconnected(device) => {
+                self.battery_level_handles.remove(&device.address);
+                self.batteries.remove(&device.address);
+            }
+            BatteryActions::GattConnectionState(address, connected) => {
+                if !connected {
+                    self.battery_level_handles.remove(&address);
+                    return;
+                }
+                let addr = BtAddress::from_string(&address);
+                if let (Some(gatt), Some(client_id), Some(addr)) =
+                    (&self.gatt, self.client_id, addr)
+                {
+                    let _ = gatt.lock().unwrap().discover_services(client_id, addr);
+                }
+            }
+            BatteryActions::GattSearchComplete(address, services) => {
+                self.subscribe_battery_level(address, services)
+            }
+            BatteryActions::GattValue(address, handle, value) => {
+                if self.battery_level_handles.get(&address) != Some(&handle) {
+                    return;
+                }
+                match value.first() {
+                    Some(level) if *level <= 100 => {
+                        self.update_battery(address, *level as u32, BatterySource::Gatt)
+                    }
+                    _ => warn!("[{}]: Invalid battery level {:?}", address, value),
+                }
+            }
+        }
+    }
+
+    pub fn dispatch_hfp_callbacks(&mut self, cb: HfpCallbacks) {
+        match cb {
+            HfpCallbacks::BatteryLevel(level, addr) => {
+                self.update_battery(addr.to_string(), level as u32, BatterySource::Hfp)
+            }
+            _ => (),
+        }
+    }
+
+    /// Joins the LE link of a bonded device exposing the Battery Service.
+    fn connect_battery_service(&self, device: BluetoothDevice) {
+        let (adapter, gatt, client_id) = match (&self.adapter, &self.gatt, self.client_id) {
+            (Some(adapter), Some(gatt), Some(client_id)) => (adapter, gatt, client_id),
+            _ => return,
+        };
+
+        {
+            let adapter = adapter.lock().unwrap();
+            let has_battery_service =
+                adapter.get_remote_uuids(device.clone()).contains(&uuid16(BATTERY_SERVICE_UUID16));
+            if adapter.get_bond_state(device.clone()) != BtBondState::Bonded as u32
+                || !has_battery_service
+            {
+                return;
+            }
+        }
+
+        let addr = match BtAddress::from_string(&device.address) {
+            Some(addr) => addr,
+            None => return,
+        };
+
+        debug!("[{}]: Connecting to the Battery Service", device.address);
+        // Opportunistic, so that the link is left to the other clients.
+        let result = gatt.lock().unwrap().client_connect(
+            client_id,
+            addr,
+            false,
+            BtTransport::Le as i32,
+            true,
+            LePhy::Phy1m as i32,
+        );
+        if let Err(e) = result {
+            warn!("[{}]: Failed to connect to the Battery Service: {}", device.address, e);
+        }
+    }
+
+    /// Reads the Battery Level of a device and subscribes to its notifications.
+    fn subscribe_battery_level(&mut self, address: String, services: Vec<BluetoothGattService>) {
+        let (gatt, client_id, addr) =
+            match (self.gatt.clone(), self.client_id, BtAddress::from_string(&address)) {
+                (Some(gatt), Some(client_id), Some(addr)) => (gatt, client_id, addr),
+                _ => return,
+            };
+
+        let battery_level = services
+            .iter()
+            .filter(|s| s.uuid == uuid16(BATTERY_SERVICE_UUID16))
+            .flat_map(|s| s.characteristics.iter())
+            .find(|c| c.uuid == uuid16(BATTERY_LEVEL_UUID16));
+        let characteristic = match battery_level {
+            Some(c) => c,
+            None => return,
+        };
+
+        self.battery_level_handles.insert(address, characteristic.instance_id);
+
+        let mut gatt = gatt.lock().unwrap();
+        let handle = characteristic.instance_id;
+        let _ = gatt.read_characteristic(client_id, addr, handle, 0);
+
+        if characteristic.properties & BluetoothGattCharacteristic::PROPERTY_NOTIFY == 0 {
+            return;
+        }
+        let _ = gatt.register_for_notification(client_id, addr, handle, true);
+        if let Some(cccd) = characteristic.descriptors.iter().find(|d| d.uuid == CCCD_UUID) {
+            let _ = gatt.write_descriptor(
+                client_id,
+                addr,
+                cccd.instance_id,
+                0,
+                CCCD_ENABLE_NOTIFICATION.to_vec(),
+            );
+        }
+    }
+
+    fn update_battery(&mut self, address: String, percentage: u32, source: BatterySource) {
+        let info = BatteryInfo { address: address.clone(), percentage, source };
+        if self.batteries.get(&address) == Some(&info) {
+            return;
+        }
+
+        self.batteries.insert(address.clone(), info.clone());
+        for callback in self.callbacks.values() {
+            callback.on_battery_info_updated(address.clone(), info.clone());
+        }
+    }
+}
+
+impl IBatteryManager for BatteryManager {
+    fn register_battery_callback(
+        &mut self,
+        mut callback: Box<dyn IBatteryManagerCallback + Send>,
+    ) -> u32 {
+        let tx = self.tx.clone();
+
+        let id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _result = tx.send(Message::BatteryManagerCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.callbacks.insert(id, callback);
+        id
+    }
+
+    fn unregister_battery_callback(&mut self, callback_id: u32) -> bool {
+        self.remove_callback(callback_id)
+    }
+
+    fn get_battery_information(&self, addr: String) -> BtResult<BatteryInfo> {
+        self.batteries
+            .get(&addr)
+            .cloned()
+            .ok_or_else(|| BtError::not_found(format!("No battery level for {}", addr)))
+    }
+}
+
+fn send_battery_action(tx: &Sender<Message>, action: BatteryActions) {
+    let tx = tx.clone();
+    topstack::get_runtime().spawn(async move {
+        let _ = tx.send(Message::BatteryManager(action)).await;
+    });
+}
+
+/// Relays the connections of the devices to the battery manager.
+struct BatteryConnectionCallback {
+    tx: Sender<Message>,
+}
+
+impl IBluetoothConnectionCallback for BatteryConnectionCallback {
+    fn on_device_connected(&self, remote_device: BluetoothDevice) {
+        send_battery_action(&self.tx, BatteryActions::DeviceConnected(remote_device));
+    }
+
+    fn on_device_disconnected(&self, remote_device: BluetoothDevice) {
+        send_battery_action(&self.tx, BatteryActions::DeviceDisconnected(remote_device));
+    }
+}
+
+impl RPCProxy for BatteryConnectionCallback {
+    // Never disconnects, and is never unregistered.
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("BatteryManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+/// Relays the events of the GATT client of the battery manager.
+struct BatteryGattCallback {
+    tx: Sender<Message>,
+}
+
+impl IBluetoothGattCallback for BatteryGattCallback {
+    fn on_client_registered(&self, status: i32, client_id: i32) {
+        send_battery_action(&self.tx, BatteryActions::GattClientRegistered(status, client_id));
+    }
+
+    fn on_client_connection_state(
+        &self,
+        status: i32,
+        _client_id: i32,
+        connected: bool,
+        addr: BtAddress,
+    ) {
+        let connected = connected && status == GattStatus::Success as i32;
+        send_battery_action(
+            &self.tx,
+            BatteryActions::GattConnectionState(addr.to_string(), connected),
+        );
+    }
+
+    fn on_phy_update(&self, _addr: BtAddress, _tx_phy: LePhy, _rx_phy: LePhy, _status: GattStatus) {
+    }
+
+    fn on_phy_read(&self, _addr: BtAddress, _tx_phy: LePhy, _rx_phy: LePhy, _status: GattStatus) {}
+
+    fn on_search_complete(
+        &self,
+        addr: BtAddress,
+        services: Vec<BluetoothGattService>,
+        status: i32,
+    ) {
+        if status == GattStatus::Success as i32 {
+            send_battery_action(
+                &self.tx,
+                BatteryActions::GattSearchComplete(addr.to_string(), services),
+            );
+        }
+    }
+
+    fn on_service_read(
+        &self,
+        _addr: BtAddress,
+        _service_uuid: Uuid128Bit,
+        _results: Vec<CharacteristicReadResult>,
+    ) {
+    }
+
+    fn on_conformance_report(&self, _addr: BtAddress, _issues: Vec<ConformanceIssue>) {}
+
+    fn on_get_gatt_db(&self, _addr: BtAddress, _services: Vec<BluetoothGattService>) {}
+
+    fn on_characteristic_read(&self, addr: BtAddress, status: i32, handle: i32, value: Vec<u8>) {
+        if status == GattStatus::Success as i32 {
+            send_battery_action(
+                &self.tx,
+                BatteryActions::GattValue(addr.to_string(), handle, value),
+            );
+        }
+    }
+
+    fn on_characteristic_write(&self, _addr: BtAddress, _status: i32, _handle: i32) {}
+
+    fn on_characteristic_write_progress(
+        &self,
+        _addr: BtAddress,
+        _handle: i32,
+        _bytes_written: i32,
+        _total_bytes: i32,
+    ) {
+    }
+
+    fn on_execute_write(&self, _addr: BtAddress, _status: i32) {}
+
+    fn on_descriptor_read(&self, _addr: BtAddress, _status: i32, _handle: i32, _value: Vec<u8>) {}
+
+    fn on_descriptor_write(&self, _addr: BtAddress, _status: i32, _handle: i32) {}
+
+    fn on_notify(&self, addr: BtAddress, handle: i32, value: Vec<u8>) {
+        send_battery_action(&self.tx, BatteryActions::GattValue(addr.to_string(), handle, value));
+    }
+
+    fn on_notify_multiple(&self, addr: BtAddress, values: Vec<GattHandleValue>) {
+        for v in values {
+            self.on_notify(addr, v.handle, v.value);
+        }
+    }
+
+    fn on_notification_queue_overflow(&self, _addr: BtAddress, _handle: i32, _dropped: u32) {}
+
+    fn on_read_remote_rssi(&self, _addr: BtAddress, _rssi: i32, _status: i32) {}
+
+    fn on_rssi_threshold_crossed(&self, _addr: BtAddress, _rssi: i32, _threshold: i32) {}
+
+    fn on_configure_mtu(&self, _addr: BtAddress, _mtu: i32, _status: i32) {}
+
+    fn on_connection_updated(
+        &self,
+        _addr: BtAddress,
+        _interval: i32,
+        _latency: i32,
+        _timeout: i32,
+        _status: i32,
+    ) {
+    }
+
+    fn on_service_changed(&self, _addr: BtAddress) {}
+
+    fn on_notification_pipe_active(&self, _addr: BtAddress, _handle: i32) {}
+
+    fn on_eatt_state_changed(&self, _addr: BtAddress, _count: i32, _mtus: Vec<i32>) {}
+}
+
+impl RPCProxy for BatteryGattCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("BatteryManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
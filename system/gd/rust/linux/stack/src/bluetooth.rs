@@ -2,10 +2,12 @@
 
 use bt_topshim::btif::{
     BaseCallbacks, BaseCallbacksDispatcher, BluetoothInterface, BluetoothProperty, BtAclState,
-    BtBondState, BtDeviceType, BtDiscoveryState, BtHciErrorCode, BtPinCode, BtPropertyType,
-    BtScanMode, BtSspVariant, BtState, BtStatus, BtTransport, RawAddress, Uuid, Uuid128Bit,
+    BtBondState, BtDeviceType, BtDiscoveryState, BtHciErrorCode, BtLocalLeFeatures, BtPinCode,
+    BtPropertyType, BtScanMode, BtSspVariant, BtState, BtStatus, BtTransport, RawAddress, Uuid,
+    Uuid128Bit,
 };
 use bt_topshim::{
+    controller::{Controller, ControllerCallbacksDispatcher, IdentityKey},
     profiles::hid_host::{HHCallbacksDispatcher, HidHost},
     profiles::sdp::{BtSdpRecord, Sdp, SdpCallbacks, SdpCallbacksDispatcher},
     topstack,
@@ -13,9 +15,10 @@ use bt_topshim::{
 
 use btif_macros::{btif_callback, btif_callbacks_dispatcher};
 
-use log::{debug, warn};
+use log::{debug, info, warn};
 use num_traits::cast::ToPrimitive;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
@@ -24,7 +27,16 @@ use tokio::sync::mpsc::Sender;
 use tokio::task::JoinHandle;
 use tokio::time;
 
+use crate::bluetooth_admin::is_service_in_allowlist;
 use crate::bluetooth_media::{BluetoothMedia, IBluetoothMedia, MediaActions};
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::pairing_guard::{PairingDecision, PairingRateLimiter};
+use crate::privacy::{
+    IdentityExposure, IdentityExposureLog, LocalIdentity, OwnAddressType, PrivacyMode,
+};
+use crate::state_snapshot::{AdapterSnapshot, DeviceSnapshot};
+use crate::storage::{save_lines, PUBLIC_FILE_MODE};
+use crate::suspend::{SuspendPreparation, SuspendType};
 use crate::uuid::{Profile, UuidHelper};
 use crate::{BluetoothCallbackType, Message, RPCProxy};
 
@@ -36,6 +48,10 @@ const MIN_ADV_INSTANCES_FOR_MULTI_ADV: u8 = 5;
 /// clear event should be sent to clients.
 const FOUND_DEVICE_FRESHNESS: Duration = Duration::from_secs(30);
 
+/// Time given to `IBluetooth::restart_stack` to enable the adapter again, after which the
+/// restart is reported as failed.
+const STACK_RESTART_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Defines the adapter API.
 pub trait IBluetooth {
     /// Adds a callback from a client who wishes to observe adapter events.
@@ -70,13 +86,13 @@ pub trait IBluetooth {
     fn get_name(&self) -> String;
 
     /// Sets the local adapter name.
-    fn set_name(&self, name: String) -> bool;
+    fn set_name(&self, name: String) -> BtResult<()>;
 
     /// Gets the bluetooth class.
     fn get_bluetooth_class(&self) -> u32;
 
     /// Sets the bluetooth class.
-    fn set_bluetooth_class(&self, cod: u32) -> bool;
+    fn set_bluetooth_class(&self, cod: u32) -> BtResult<()>;
 
     /// Returns whether the adapter is discoverable.
     fn get_discoverable(&self) -> bool;
@@ -85,7 +101,7 @@ pub trait IBluetooth {
     fn get_discoverable_timeout(&self) -> u32;
 
     /// Sets discoverability. If discoverable, limits the duration with given value.
-    fn set_discoverable(&self, mode: bool, duration: u32) -> bool;
+    fn set_discoverable(&self, mode: bool, duration: u32) -> BtResult<()>;
 
     /// Returns whether multi-advertisement is supported.
     /// A minimum number of 5 advertising instances is required for multi-advertisment support.
@@ -95,10 +111,10 @@ pub trait IBluetooth {
     fn is_le_extended_advertising_supported(&self) -> bool;
 
     /// Starts BREDR Inquiry.
-    fn start_discovery(&self) -> bool;
+    fn start_discovery(&self) -> BtResult<()>;
 
     /// Cancels BREDR Inquiry.
-    fn cancel_discovery(&self) -> bool;
+    fn cancel_discovery(&self) -> BtResult<()>;
 
     /// Checks if discovery is started.
     fn is_discovering(&self) -> bool;
@@ -106,14 +122,25 @@ pub trait IBluetooth {
     /// Checks when discovery ends in milliseconds from now.
     fn get_discovery_end_millis(&self) -> u64;
 
+    /// Returns whether the adapter is enabled and its properties are loaded, so that requests
+    /// can be served. `IBluetoothCallback::on_ready` is invoked when this becomes true.
+    fn is_ready(&self) -> bool;
+
     /// Initiates pairing to a remote device. Triggers connection if not already started.
-    fn create_bond(&self, device: BluetoothDevice, transport: BtTransport) -> bool;
+    fn create_bond(&mut self, device: BluetoothDevice, transport: BtTransport) -> BtResult<()>;
 
     /// Cancels any pending bond attempt on given device.
-    fn cancel_bond_process(&self, device: BluetoothDevice) -> bool;
+    fn cancel_bond_process(&self, device: BluetoothDevice) -> BtResult<()>;
 
     /// Removes pairing for given device.
-    fn remove_bond(&self, device: BluetoothDevice) -> bool;
+    fn remove_bond(&self, device: BluetoothDevice) -> BtResult<()>;
+
+    /// Removes the pairing like `remove_bond`, then forgets everything the stack keeps about the
+    /// device: its alias and other properties, GATT cache, background connection, PHY
+    /// preference, peripheral allow-list entry, media codec state and HID report map. All the
+    /// subsystems forget the device at once, reported with
+    /// `IBluetoothCallback::on_device_forgotten`.
+    fn remove_bond_cascade(&mut self, device: BluetoothDevice) -> BtResult<()>;
 
     /// Returns a list of known bonded devices.
     fn get_bonded_devices(&self) -> Vec<BluetoothDevice>;
@@ -165,6 +192,214 @@ pub trait IBluetooth {
 
     /// Disconnect all profiles supported by device and enabled on adapter.
     fn disconnect_all_enabled_profiles(&mut self, device: BluetoothDevice) -> bool;
+
+    /// Returns which local identities have been exposed to which remote devices, and when.
+    fn get_identity_exposure_report(&self) -> Vec<IdentityExposure>;
+
+    /// Returns the page scan and inquiry scan parameters used by the adapter.
+    fn get_classic_scan_parameters(&self) -> ClassicScanParameters;
+
+    /// Sets the page scan and inquiry scan parameters used by the adapter. They are saved, so they
+    /// are kept across adapter restarts. This is a privileged operation.
+    fn set_classic_scan_parameters(&mut self, parameters: ClassicScanParameters) -> BtResult<()>;
+
+    /// Sets the page scan and inquiry scan parameters to one of the presets. This is a privileged
+    /// operation.
+    fn set_classic_scan_preset(&mut self, preset: ClassicScanPreset) -> BtResult<()>;
+
+    /// Restarts the stack without restarting the daemon, to recover from a wedged controller.
+    /// The adapter is disabled, which resets the controller, then enabled again. Bonded devices
+    /// and registered callbacks are kept. The GATT clients, servers and scanners are registered
+    /// again once the adapter is enabled, under new IDs, and have to start over their connections,
+    /// services and scans. The restart is reported with
+    /// `IBluetoothCallback::on_stack_restart_started` and `on_stack_restart_completed`, which
+    /// reports a failure if the adapter is not enabled again within 30 seconds. This is a
+    /// privileged operation.
+    fn restart_stack(&mut self, reason: String) -> BtResult<()>;
+
+    /// Returns the current radio activity, then reported with
+    /// `IBluetoothCallback::on_radio_activity_changed`.
+    fn get_radio_activity(&self) -> RadioActivity;
+
+    /// Returns the version and supported features of a remote device. They are read from the
+    /// connection if the device is connected, otherwise the values cached from its last
+    /// connection are returned. Fails with NotFound if they were never read.
+    fn get_remote_version_info(&mut self, device: BluetoothDevice) -> BtResult<RemoteVersionInfo>;
+
+    /// Sets the LE privacy mode of a bonded device using resolvable private addresses, whether it
+    /// may also connect and be found with its identity address. The mode is applied again when
+    /// the adapter is enabled. Fails with NotFound if the device is not bonded, and with
+    /// Unsupported if it did not distribute an identity resolving key.
+    fn set_device_privacy_mode(
+        &mut self,
+        device: BluetoothDevice,
+        mode: PrivacyMode,
+    ) -> BtResult<()>;
+}
+
+/// Presets of `ClassicScanParameters`.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum ClassicScanPreset {
+    /// The parameters used by the stack by default.
+    Default = 0,
+    /// Scans often, for the lowest reconnection latency.
+    FastConnect = 1,
+    /// Scans rarely, for the lowest power consumption.
+    LowPower = 2,
+}
+
+/// Prefix of the files holding the classic scan parameters of each adapter, so that they are kept
+/// across restarts of the daemon. The files are suffixed with the hci index of the adapter.
+pub const CLASSIC_SCAN_PARAMETERS_FILE: &str = "/var/lib/bluetooth/classic_scan_parameters";
+
+/// Page scan and inquiry scan parameters. Intervals and windows are in units of 0.625 ms.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassicScanParameters {
+    pub page_scan_interval: u16,
+    pub page_scan_window: u16,
+    pub inquiry_scan_interval: u16,
+    pub inquiry_scan_window: u16,
+    /// Whether interlaced page and inquiry scans are used, which shortens the connection and
+    /// discovery latency at the cost of doubling the scan duty cycle.
+    pub interlaced_scan: bool,
+}
+
+impl Default for ClassicScanParameters {
+    fn default() -> Self {
+        ClassicScanParameters::from_preset(ClassicScanPreset::Default)
+    }
+}
+
+impl ClassicScanParameters {
+    // Range of the intervals and windows allowed by the specification.
+    const MIN_INTERVAL: u16 = 0x12;
+    const MIN_WINDOW: u16 = 0x11;
+    const MAX_INTERVAL: u16 = 0x1000;
+
+    // Parameters the stack sets up the controller with, see BTM_DEFAULT_CONN_* and
+    // BTM_DEFAULT_DISC_*. Interlaced scans are enabled whenever the controller supports them.
+    const DEFAULT_PAGE_SCAN_INTERVAL: u16 = 0x400;
+    const DEFAULT_PAGE_SCAN_WINDOW: u16 = 0x12;
+    const DEFAULT_INQUIRY_SCAN_INTERVAL: u16 = 0x800;
+    const DEFAULT_INQUIRY_SCAN_WINDOW: u16 = 0x12;
+
+    pub fn from_preset(preset: ClassicScanPreset) -> ClassicScanParameters {
+        let (page_scan_interval, inquiry_scan_interval, interlaced_scan) = match preset {
+            ClassicScanPreset::Default => {
+                (Self::DEFAULT_PAGE_SCAN_INTERVAL, Self::DEFAULT_INQUIRY_SCAN_INTERVAL, true)
+            }
+            ClassicScanPreset::FastConnect => (0x100, 0x100, true),
+            ClassicScanPreset::LowPower => (Self::MAX_INTERVAL, Self::MAX_INTERVAL, false),
+        };
+
+        ClassicScanParameters {
+            page_scan_interval,
+            page_scan_window: Self::DEFAULT_PAGE_SCAN_WINDOW,
+            inquiry_scan_interval,
+            inquiry_scan_window: Self::DEFAULT_INQUIRY_SCAN_WINDOW,
+            interlaced_scan,
+        }
+    }
+
+    /// Path of the file holding the parameters of the adapter `hci_index`.
+    fn path(hci_index: i32) -> PathBuf {
+        PathBuf::from(format!("{}.hci{}", CLASSIC_SCAN_PARAMETERS_FILE, hci_index))
+    }
+
+    /// Loads the parameters saved in `path`, or the default ones if none were saved or the file is
+    /// malformed.
+    fn load(path: &Path) -> ClassicScanParameters {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| ClassicScanParameters::parse_line(contents.trim()))
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) {
+        let line = format!(
+            "{} {} {} {} {}",
+            self.page_scan_interval,
+            self.page_scan_window,
+            self.inquiry_scan_interval,
+            self.inquiry_scan_window,
+            self.interlaced_scan as u8
+        );
+
+        if let Err(e) = save_lines(path, &[line], PUBLIC_FILE_MODE) {
+            warn!("Failed to save the classic scan parameters to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Parses a line made of the page scan interval and window, the inquiry scan interval and
+    /// window, and whether interlaced scans are used.
+    fn parse_line(line: &str) -> Option<ClassicScanParameters> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 5 {
+            return None;
+        }
+
+        let parameters = ClassicScanParameters {
+            page_scan_interval: fields[0].parse().ok()?,
+            page_scan_window: fields[1].parse().ok()?,
+            inquiry_scan_interval: fields[2].parse().ok()?,
+            inquiry_scan_window: fields[3].parse().ok()?,
+            interlaced_scan: match fields[4] {
+                "0" => false,
+                "1" => true,
+                _ => return None,
+            },
+        };
+        parameters.validate().ok()?;
+        Some(parameters)
+    }
+
+    fn validate(&self) -> BtResult<()> {
+        for (name, interval, window) in [
+            ("page", self.page_scan_interval, self.page_scan_window),
+            ("inquiry", self.inquiry_scan_interval, self.inquiry_scan_window),
+        ] {
+            if !(Self::MIN_INTERVAL..=Self::MAX_INTERVAL).contains(&interval) || interval % 2 != 0 {
+                return Err(BtError::invalid_argument(format!(
+                    "Invalid {} scan interval {:#x}",
+                    name, interval
+                )));
+            }
+
+            if !(Self::MIN_WINDOW..=interval).contains(&window) {
+                return Err(BtError::invalid_argument(format!(
+                    "Invalid {} scan window {:#x}",
+                    name, window
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What the radio is busy with, for activity indicators in the system UI.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RadioActivity {
+    /// BR/EDR inquiry or LE scanning is ongoing.
+    pub scanning: bool,
+    /// An LE advertising set is advertising.
+    pub advertising: bool,
+    /// Number of connected remote devices, on any transport.
+    pub connections: u32,
+}
+
+/// Version and supported features of a remote device, for interoperability triage.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RemoteVersionInfo {
+    /// LMP version for BR/EDR or LL version for LE, which share their numbering.
+    pub lmp_version: u8,
+    pub lmp_subversion: u16,
+    /// Company identifier of the manufacturer of the controller of the device.
+    pub manufacturer: u16,
+    /// LMP features, the pages read from the device one after the other, empty if the device was
+    /// only connected over LE.
+    pub features: Vec<u8>,
 }
 
 /// Serializable device used in various apis.
@@ -206,6 +441,10 @@ struct BluetoothDeviceContext {
     pub info: BluetoothDevice,
     pub last_seen: Instant,
     pub properties: HashMap<BtPropertyType, BluetoothProperty>,
+    /// Version information read from the last connection.
+    pub version_info: Option<RemoteVersionInfo>,
+    /// LE privacy mode set with `IBluetooth::set_device_privacy_mode`.
+    pub privacy_mode: PrivacyMode,
 }
 
 impl BluetoothDeviceContext {
@@ -222,6 +461,8 @@ impl BluetoothDeviceContext {
             info,
             last_seen,
             properties: HashMap::new(),
+            version_info: None,
+            privacy_mode: PrivacyMode::default(),
         };
         device.update_properties(properties);
         device
@@ -280,6 +521,27 @@ pub trait IBluetoothCallback: RPCProxy {
 
     /// When a bonding attempt has completed.
     fn on_bond_state_changed(&self, status: u32, device_address: String, state: u32);
+
+    /// When the adapter becomes ready to serve requests, see `IBluetooth::is_ready`.
+    fn on_ready(&self);
+
+    /// When pairing attempts initiated by remote devices are rejected for `lockout_secs` seconds
+    /// because of too many attempts, either from `device_address` or from all devices if `global`.
+    fn on_pairing_locked_out(&self, device_address: String, global: bool, lockout_secs: u32);
+
+    /// When the adapter is disabled for a restart of the stack, see `IBluetooth::restart_stack`.
+    fn on_stack_restart_started(&self, reason: String);
+
+    /// When a restart of the stack is done. `success` is false if the adapter could not be
+    /// enabled again, otherwise `on_ready` was invoked right before.
+    fn on_stack_restart_completed(&self, reason: String, success: bool);
+
+    /// When scanning or advertising starts or stops, or the number of connected devices changes.
+    fn on_radio_activity_changed(&self, activity: RadioActivity);
+
+    /// When the device passed to `IBluetooth::remove_bond_cascade` is forgotten by every
+    /// subsystem.
+    fn on_device_forgotten(&self, device_address: String);
 }
 
 pub trait IBluetoothConnectionCallback: RPCProxy {
@@ -290,25 +552,80 @@ pub trait IBluetoothConnectionCallback: RPCProxy {
     fn on_device_disconnected(&self, remote_device: BluetoothDevice);
 }
 
+/// Ongoing `IBluetooth::restart_stack`.
+#[derive(Debug)]
+struct StackRestart {
+    reason: String,
+    deadline: Instant,
+}
+
+impl StackRestart {
+    /// Starts a restart, unless one is ongoing already or the adapter is not enabled.
+    fn start(
+        ongoing: Option<&StackRestart>,
+        state: &BtState,
+        reason: String,
+        now: Instant,
+    ) -> BtResult<StackRestart> {
+        if ongoing.is_some() {
+            return Err(BtError::new(BtErrorCategory::Busy, "The stack is already restarting"));
+        }
+
+        if *state != BtState::On {
+            return Err(BtError::new(BtErrorCategory::NotReady, "The adapter is not enabled"));
+        }
+
+        Ok(StackRestart { reason, deadline: now + STACK_RESTART_TIMEOUT })
+    }
+
+    fn has_timed_out(&self, now: Instant) -> bool {
+        now >= self.deadline
+    }
+}
+
 /// Implementation of the adapter API.
 pub struct Bluetooth {
     intf: Arc<Mutex<BluetoothInterface>>,
 
+    // Index of the adapter, as in hci{N}.
+    adapter_index: i32,
+    // Services the devices may be connected to, set by `BluetoothAdmin`. Empty if not restricted.
+    allowed_services: HashSet<Uuid128Bit>,
     bonded_devices: HashMap<String, BluetoothDeviceContext>,
     bluetooth_media: Arc<Mutex<Box<BluetoothMedia>>>,
     callbacks: HashMap<u32, Box<dyn IBluetoothCallback + Send>>,
+    classic_scan_parameters: ClassicScanParameters,
     connection_callbacks: HashMap<u32, Box<dyn IBluetoothConnectionCallback + Send>>,
+    controller: Option<Controller>,
     discovering_started: Instant,
     hh: Option<HidHost>,
+    identity_exposures: IdentityExposureLog,
     is_connectable: bool,
     is_discovering: bool,
+    is_ready: bool,
     local_address: Option<RawAddress>,
+    // Devices we have initiated bonding with, which are not subject to pairing rate limiting.
+    outgoing_bonds: HashSet<String>,
+    // Devices to forget once unbonded, see `IBluetooth::remove_bond_cascade`.
+    pending_forgets: HashSet<String>,
+    pairing_limiter: PairingRateLimiter,
     properties: HashMap<BtPropertyType, BluetoothProperty>,
     profiles_ready: bool,
+    // Whether LE scanning and advertising are ongoing, as reported by `BluetoothGatt`.
+    le_scanning: bool,
+    le_advertising: bool,
+    // Last activity reported to the callbacks.
+    radio_activity: RadioActivity,
+    // Ongoing `IBluetooth::restart_stack`, if any.
+    stack_restart: Option<StackRestart>,
     found_devices: HashMap<String, BluetoothDeviceContext>,
     freshness_check: Option<JoinHandle<()>>,
     sdp: Option<Sdp>,
     state: BtState,
+    // Connectable and discoverable states to restore on resume, while the system is suspended,
+    // and the scan mode the suspend waits for.
+    suspended_scan_mode: Option<(bool, bool)>,
+    pending_scan_mode: Option<BtScanMode>,
     tx: Sender<Message>,
     uuid_helper: UuidHelper,
     /// Used to delay connection until we have SDP results.
@@ -321,24 +638,42 @@ impl Bluetooth {
         tx: Sender<Message>,
         intf: Arc<Mutex<BluetoothInterface>>,
         bluetooth_media: Arc<Mutex<Box<BluetoothMedia>>>,
+        adapter_index: i32,
     ) -> Bluetooth {
         Bluetooth {
+            adapter_index,
+            allowed_services: HashSet::new(),
             bonded_devices: HashMap::new(),
             callbacks: HashMap::new(),
+            classic_scan_parameters: ClassicScanParameters::load(&ClassicScanParameters::path(
+                adapter_index,
+            )),
             connection_callbacks: HashMap::new(),
+            controller: None,
             hh: None,
+            identity_exposures: IdentityExposureLog::new(),
             bluetooth_media,
             discovering_started: Instant::now(),
             intf,
             is_connectable: false,
             is_discovering: false,
+            is_ready: false,
             local_address: None,
+            outgoing_bonds: HashSet::new(),
+            pending_forgets: HashSet::new(),
+            pairing_limiter: PairingRateLimiter::new(),
             properties: HashMap::new(),
             profiles_ready: false,
+            le_scanning: false,
+            le_advertising: false,
+            radio_activity: RadioActivity::default(),
+            stack_restart: None,
             found_devices: HashMap::new(),
             freshness_check: None,
             sdp: None,
             state: BtState::Off,
+            suspended_scan_mode: None,
+            pending_scan_mode: None,
             tx,
             uuid_helper: UuidHelper::new(),
             wait_to_connect: false,
@@ -368,10 +703,113 @@ impl Bluetooth {
             }),
         });
 
+        let controllertx = self.tx.clone();
+        self.controller = Some(Controller::new());
+        self.controller.as_mut().unwrap().initialize(ControllerCallbacksDispatcher {
+            dispatch: Box::new(move |cb| {
+                let txl = controllertx.clone();
+                topstack::get_runtime().spawn(async move {
+                    let _ = txl.send(Message::Controller(cb)).await;
+                });
+            }),
+        });
+
         // Mark profiles as ready
         self.profiles_ready = true;
     }
 
+    /// Returns the HID host profile, once the profiles are initialized.
+    pub(crate) fn hid_host(&self) -> Option<&HidHost> {
+        self.hh.as_ref()
+    }
+
+    /// Returns whether the admin policy allows connecting to `service`.
+    pub(crate) fn is_service_allowed(&self, service: &Uuid128Bit) -> bool {
+        is_service_in_allowlist(&self.allowed_services, service)
+    }
+
+    /// Applies the services allowed by the admin policy, disconnecting the profiles of the
+    /// connected devices that are no longer allowed.
+    pub(crate) fn set_allowed_services(&mut self, allowed_services: HashSet<Uuid128Bit>) {
+        self.allowed_services = allowed_services;
+        if !self.profiles_ready {
+            return;
+        }
+
+        let devices: Vec<BluetoothDevice> =
+            self.bonded_devices.values().map(|d| d.info.clone()).collect();
+        for device in devices {
+            if self.get_connection_state(device.clone()) == 0 {
+                continue;
+            }
+
+            let addr = match RawAddress::from_string(device.address.clone()) {
+                Some(addr) => addr,
+                None => continue,
+            };
+
+            let mut disconnect_media = false;
+            for uuid in self.get_remote_uuids(device.clone()).iter() {
+                if self.is_service_allowed(uuid) {
+                    continue;
+                }
+
+                match self.uuid_helper.is_known_profile(uuid) {
+                    Some(Profile::Hid) | Some(Profile::Hogp) => {
+                        info!("[{}]: Disconnecting HID blocked by policy.", device.address);
+                        self.hh.as_ref().unwrap().disconnect(&mut addr.clone());
+                    }
+                    Some(Profile::A2dpSink) | Some(Profile::Hfp) => disconnect_media = true,
+                    _ => (),
+                }
+            }
+
+            if disconnect_media {
+                info!("[{}]: Disconnecting media blocked by policy.", device.address);
+                let txl = self.tx.clone();
+                let address = device.address.clone();
+                topstack::get_runtime().spawn(async move {
+                    let _ = txl.send(Message::Media(MediaActions::Disconnect(address))).await;
+                });
+            }
+        }
+    }
+
+    /// Sets the LE privacy modes of the bonded devices again, once the adapter is enabled.
+    fn apply_privacy_modes(&mut self) {
+        let controller = match self.controller.as_mut() {
+            Some(controller) => controller,
+            None => return,
+        };
+
+        for device in self.bonded_devices.values() {
+            if device.privacy_mode == PrivacyMode::default() {
+                continue;
+            }
+            let applied = RawAddress::from_string(device.info.address.clone()).map_or(false, |a| {
+                controller.set_privacy_mode(a.val, device.privacy_mode == PrivacyMode::Device)
+            });
+            if !applied {
+                warn!("Failed to set the privacy mode of {}", device.info.address);
+            }
+        }
+    }
+
+    /// Writes the classic scan parameters to the controller.
+    fn apply_classic_scan_parameters(&mut self) {
+        let params = &self.classic_scan_parameters;
+        let controller = match self.controller.as_mut() {
+            Some(controller) => controller,
+            None => return,
+        };
+
+        controller.write_page_scan_activity(params.page_scan_interval, params.page_scan_window);
+        controller
+            .write_inquiry_scan_activity(params.inquiry_scan_interval, params.inquiry_scan_window);
+        controller.write_page_scan_type(params.interlaced_scan);
+        controller.write_inquiry_scan_type(params.interlaced_scan);
+    }
+
     fn update_local_address(&mut self, addr: &RawAddress) {
         self.local_address = Some(*addr);
 
@@ -380,6 +818,78 @@ impl Bluetooth {
         });
     }
 
+    /// Updates the readiness of the adapter and notifies the clients when it becomes ready.
+    fn update_ready(&mut self) {
+        let is_ready = self.state == BtState::On && self.local_address.is_some();
+        if is_ready == self.is_ready {
+            return;
+        }
+
+        self.is_ready = is_ready;
+        if is_ready {
+            self.for_all_callbacks(|callback| {
+                callback.on_ready();
+            });
+
+            self.complete_stack_restart(true);
+        }
+    }
+
+    /// Reports the end of the ongoing `IBluetooth::restart_stack`, if any.
+    fn complete_stack_restart(&mut self, success: bool) {
+        let restart = match self.stack_restart.take() {
+            Some(restart) => restart,
+            None => return,
+        };
+
+        if !success {
+            warn!("Failed to restart the stack for: {}", restart.reason);
+        }
+        self.for_all_callbacks(|callback| {
+            callback.on_stack_restart_completed(restart.reason.clone(), success);
+        });
+    }
+
+    /// Disables the adapter for the ongoing `IBluetooth::restart_stack`, once the GATT
+    /// registrations are released.
+    pub(crate) fn disable_for_restart(&mut self) {
+        if self.stack_restart.is_some() && !self.disable() {
+            self.complete_stack_restart(false);
+        }
+    }
+
+    /// Reports the ongoing `IBluetooth::restart_stack` as failed if it is not over in time.
+    pub(crate) fn check_stack_restart_timeout(&mut self) {
+        if self.stack_restart.as_ref().map_or(false, |r| r.has_timed_out(Instant::now())) {
+            self.complete_stack_restart(false);
+        }
+    }
+
+    /// Asks every subsystem to forget a device, from the dispatch loop so that no other event is
+    /// handled in between.
+    fn request_forget_device(&self, address: String) {
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(Message::ForgetDevice(address)).await;
+        });
+    }
+
+    /// Forgets the properties of a device, including its alias, and its HID report map, then
+    /// reports the device forgotten. The other subsystems have forgotten it already.
+    pub(crate) fn forget_device(&mut self, address: &String) {
+        self.found_devices.remove(address);
+        self.bonded_devices.remove(address);
+
+        // The bond removal already unplugs the bonded HID devices, which fails harmlessly then.
+        if let (Some(hh), Some(mut addr)) =
+            (self.hh.as_ref(), RawAddress::from_string(address.clone()))
+        {
+            let _ = hh.virtual_unplug(&mut addr);
+        }
+
+        self.for_all_callbacks(|callback| callback.on_device_forgotten(address.clone()));
+    }
+
     fn for_all_callbacks<F: Fn(&Box<dyn IBluetoothCallback + Send>)>(&self, f: F) {
         for (_, callback) in self.callbacks.iter() {
             f(&callback);
@@ -408,6 +918,117 @@ impl Bluetooth {
         }
     }
 
+    /// Records whether LE scanning and advertising are ongoing, see `RadioActivity`.
+    pub(crate) fn set_le_activity(&mut self, scanning: bool, advertising: bool) {
+        self.le_scanning = scanning;
+        self.le_advertising = advertising;
+        self.update_radio_activity();
+    }
+
+    /// Reports the radio activity to the callbacks if it changed.
+    fn update_radio_activity(&mut self) {
+        let activity = self.get_radio_activity();
+        if activity == self.radio_activity {
+            return;
+        }
+
+        self.radio_activity = activity.clone();
+        self.for_all_callbacks(|callback| {
+            callback.on_radio_activity_changed(activity.clone());
+        });
+    }
+
+    /// Returns the LE features of the controller, once read while enabling the adapter.
+    pub fn get_local_le_features(&self) -> Option<BtLocalLeFeatures> {
+        match self.properties.get(&BtPropertyType::LocalLeFeatures) {
+            Some(BluetoothProperty::LocalLeFeatures(llf)) => Some(llf.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns the company identifier of the manufacturer of the controller, once enabled.
+    pub(crate) fn get_controller_manufacturer(&mut self) -> Option<u16> {
+        if self.state != BtState::On {
+            return None;
+        }
+
+        self.controller.as_mut().map(|controller| controller.read_manufacturer())
+    }
+
+    /// Returns the state of the adapter and of the bonded and connected devices, see
+    /// `IBluetoothDebug::get_state_snapshot`.
+    pub(crate) fn adapter_snapshot(&self) -> AdapterSnapshot {
+        let mut devices: Vec<DeviceSnapshot> = self
+            .bonded_devices
+            .values()
+            .map(|d| DeviceSnapshot {
+                address: d.info.address.clone(),
+                name: d.info.name.clone(),
+                bonded: true,
+                connected: d.acl_state == BtAclState::Connected,
+            })
+            .collect();
+        // A bonded device can also be in the found devices.
+        devices.extend(
+            self.found_devices
+                .values()
+                .filter(|d| d.acl_state == BtAclState::Connected)
+                .filter(|d| !self.bonded_devices.contains_key(&d.info.address))
+                .map(|d| DeviceSnapshot {
+                    address: d.info.address.clone(),
+                    name: d.info.name.clone(),
+                    bonded: false,
+                    connected: true,
+                }),
+        );
+
+        AdapterSnapshot {
+            enabled: self.state == BtState::On,
+            address: self.get_address(),
+            name: self.get_name(),
+            discoverable: self.get_discoverable(),
+            connectable: self.is_connectable,
+            discovering: self.is_discovering,
+            le_scanning: self.le_scanning,
+            le_advertising: self.le_advertising,
+            devices,
+        }
+    }
+
+    /// Returns the identity keys of the bonded devices which distributed one, by bonded address.
+    pub(crate) fn get_identity_keys(&mut self) -> Vec<(RawAddress, IdentityKey)> {
+        let controller = match (self.state == BtState::On, self.controller.as_mut()) {
+            (true, Some(controller)) => controller,
+            _ => return vec![],
+        };
+
+        self.bonded_devices
+            .keys()
+            .filter_map(|address| RawAddress::from_string(address.clone()))
+            .filter_map(|addr| controller.read_identity_key(addr.val).map(|key| (addr, key)))
+            .collect()
+    }
+
+    /// Records that a local identity has been exposed, to a peer or to all devices around when
+    /// `peer_address` is empty.
+    pub(crate) fn record_identity_exposure(
+        &mut self,
+        peer_address: &String,
+        identity: LocalIdentity,
+        own_address_type: OwnAddressType,
+    ) {
+        self.identity_exposures.record(peer_address, identity, own_address_type);
+    }
+
+    /// Returns the controller, once the adapter is enabled.
+    pub(crate) fn get_controller(&mut self) -> Option<&mut Controller> {
+        if self.state != BtState::On {
+            return None;
+        }
+
+        self.controller.as_mut()
+    }
+
     pub fn set_connectable(&mut self, mode: bool) -> bool {
         self.is_connectable = mode;
         if mode && self.get_discoverable() {
@@ -418,6 +1039,91 @@ impl Bluetooth {
         )) == 0
     }
 
+    fn get_scan_mode(&self) -> BtScanMode {
+        match self.properties.get(&BtPropertyType::AdapterScanMode) {
+            Some(BluetoothProperty::AdapterScanMode(mode)) => mode.clone(),
+            _ => BtScanMode::None_,
+        }
+    }
+
+    /// Returns the addresses of the bonded devices allowed to wake the system by `suspend_type`,
+    /// `hid_profile` being the HID profile of the transport.
+    fn get_wake_devices(&self, suspend_type: SuspendType, hid_profile: Profile) -> Vec<RawAddress> {
+        self.bonded_devices
+            .values()
+            .filter(|device| match suspend_type {
+                SuspendType::NoWakesAllowed => false,
+                SuspendType::AllowWakeFromHid => {
+                    match device.properties.get(&BtPropertyType::Uuids) {
+                        Some(BluetoothProperty::Uuids(uuids)) => uuids.iter().any(|uuid| {
+                            self.uuid_helper.is_known_profile(&uuid.uu) == Some(&hid_profile)
+                        }),
+                        _ => false,
+                    }
+                }
+                SuspendType::Other => true,
+            })
+            .filter_map(|device| RawAddress::from_string(device.info.address.clone()))
+            .collect()
+    }
+
+    /// Prepares the radio for suspend. The adapter stops being discoverable, and only the bonded
+    /// devices allowed to wake the system by `suspend_type` can connect, over BR/EDR with HID and
+    /// over LE with HOGP. Returns whether the suspend waits for the scan mode to change, which is
+    /// reported with `Message::SuspendPreparationDone`.
+    pub(crate) fn suspend_classic(&mut self, suspend_type: SuspendType) -> bool {
+        let wake_devices = self.get_wake_devices(suspend_type, Profile::Hid);
+        let le_wake_devices = self.get_wake_devices(suspend_type, Profile::Hogp);
+
+        if self.suspended_scan_mode.is_none() {
+            self.suspended_scan_mode = Some((self.is_connectable, self.get_discoverable()));
+        }
+
+        self.intf.lock().unwrap().clear_event_filter();
+        if let Some(controller) = self.get_controller() {
+            for address in wake_devices.iter() {
+                controller.add_connection_setup_filter(address.val);
+            }
+            controller.suspend_le_connections(le_wake_devices.iter().map(|a| a.val).collect());
+        }
+
+        info!(
+            "Suspending with {} BR/EDR and {} LE wake devices",
+            wake_devices.len(),
+            le_wake_devices.len()
+        );
+        let mode =
+            if wake_devices.is_empty() { BtScanMode::None_ } else { BtScanMode::Connectable };
+        self.set_connectable(!wake_devices.is_empty());
+        let _ = self.set_discoverable(false, self.get_discoverable_timeout());
+
+        if self.get_scan_mode() == mode {
+            return false;
+        }
+        self.pending_scan_mode = Some(mode);
+        true
+    }
+
+    /// Clears the wake filters and restores the connectable and discoverable states of the adapter
+    /// before suspend.
+    pub(crate) fn resume_classic(&mut self) {
+        let (connectable, discoverable) = match self.suspended_scan_mode.take() {
+            Some(states) => states,
+            None => return,
+        };
+        self.pending_scan_mode = None;
+
+        self.intf.lock().unwrap().clear_event_filter();
+        if let Some(controller) = self.get_controller() {
+            controller.resume_le_connections();
+        }
+
+        self.set_connectable(connectable);
+        if discoverable {
+            let _ = self.set_discoverable(true, self.get_discoverable_timeout());
+        }
+    }
+
     pub(crate) fn callback_disconnected(&mut self, id: u32, cb_type: BluetoothCallbackType) {
         match cb_type {
             BluetoothCallbackType::Adapter => {
@@ -477,6 +1183,34 @@ impl Bluetooth {
         Ok(())
     }
 
+    /// Reads the version information of a connected device into its cache. Returns the cached
+    /// information, if any.
+    fn refresh_remote_version_info(&mut self, address: &str) -> Option<RemoteVersionInfo> {
+        let raw_address = RawAddress::from_string(address)?;
+        let version = match (self.state == BtState::On, self.controller.as_mut()) {
+            (true, Some(controller)) => controller
+                .read_remote_version(raw_address.val)
+                .map(|v| (v, controller.read_remote_features(raw_address.val))),
+            _ => None,
+        };
+
+        let device = self.get_remote_device_if_found_mut(address)?;
+        if let Some((version, features)) = version {
+            // The features are only known over BR/EDR, keep the ones of a previous connection.
+            let features = match (&device.version_info, features.is_empty()) {
+                (Some(cached), true) => cached.features.clone(),
+                _ => features,
+            };
+            device.version_info = Some(RemoteVersionInfo {
+                lmp_version: version.lmp_version,
+                lmp_subversion: version.lmp_subversion,
+                manufacturer: version.manufacturer,
+                features,
+            });
+        }
+        device.version_info.clone()
+    }
+
     /// Check whether found devices are still fresh. If they're outside the
     /// freshness window, send a notification to clear the device from clients.
     pub(crate) fn trigger_freshness_check(&mut self) {
@@ -614,10 +1348,28 @@ impl BtifBluetoothCallbacks for Bluetooth {
 
         if self.state == BtState::On {
             self.bluetooth_media.lock().unwrap().initialize();
+
+            let tx = self.tx.clone();
+            tokio::spawn(async move {
+                let _ = tx.send(Message::TimeServiceStart).await;
+                let _ = tx.send(Message::AdvertisingSetsRestore).await;
+                let _ = tx.send(Message::GattRegistrationsRestore).await;
+            });
         }
 
         if self.state == BtState::Off {
             self.properties.clear();
+            // The bond removals in progress are not completed anymore.
+            self.pending_forgets.clear();
+
+            let tx = self.tx.clone();
+            tokio::spawn(async move {
+                let _ = tx.send(Message::AdvertisingSetsLost).await;
+            });
+
+            if self.stack_restart.is_some() && !self.enable() {
+                self.complete_stack_restart(false);
+            }
         } else {
             // Trigger properties update
             self.intf.lock().unwrap().get_adapter_properties();
@@ -627,7 +1379,20 @@ impl BtifBluetoothCallbacks for Bluetooth {
 
             // Ensure device is connectable so that disconnected device can reconnect
             self.set_connectable(true);
+
+            // The controller is reset with the stack defaults.
+            if self.classic_scan_parameters != ClassicScanParameters::default() {
+                self.apply_classic_scan_parameters();
+            }
+            self.apply_privacy_modes();
+
+            // The HCI layer is started once the adapter is on.
+            if let Some(controller) = self.controller.as_mut() {
+                controller.start_command_latency_reports();
+            }
         }
+
+        self.update_ready();
     }
 
     #[allow(unused_variables)]
@@ -675,12 +1440,23 @@ impl BtifBluetoothCallbacks for Bluetooth {
                         callback
                             .on_discoverable_changed(*mode == BtScanMode::ConnectableDiscoverable);
                     });
+
+                    if self.pending_scan_mode.as_ref() == Some(mode) {
+                        self.pending_scan_mode = None;
+                        let tx = self.tx.clone();
+                        tokio::spawn(async move {
+                            let preparation = SuspendPreparation::ScanMode;
+                            let _ = tx.send(Message::SuspendPreparationDone(preparation)).await;
+                        });
+                    }
                 }
                 _ => {}
             }
 
             self.properties.insert(prop.get_type(), prop);
         }
+
+        self.update_ready();
     }
 
     fn device_found(&mut self, _n: i32, properties: Vec<BluetoothProperty>) {
@@ -725,6 +1501,7 @@ impl BtifBluetoothCallbacks for Bluetooth {
         self.for_all_callbacks(|callback| {
             callback.on_discovering_changed(state == BtDiscoveryState::Started);
         });
+        self.update_radio_activity();
 
         // Stopped discovering and no freshness check is active. Immediately do
         // freshness check which will schedule a recurring future until all
@@ -742,6 +1519,35 @@ impl BtifBluetoothCallbacks for Bluetooth {
         variant: BtSspVariant,
         passkey: u32,
     ) {
+        let address = remote_addr.to_string();
+        if !self.outgoing_bonds.contains(&address) {
+            match self.pairing_limiter.check(&address) {
+                PairingDecision::Allowed => {
+                    debug!("Incoming pairing attempt from {}", address);
+                }
+                PairingDecision::Rejected { lockout, new_lockout, global } => {
+                    warn!(
+                        "Rejecting pairing attempt from {}: {} pairing locked out for {}s",
+                        address,
+                        if global { "all" } else { "device" },
+                        lockout.as_secs()
+                    );
+                    self.intf.lock().unwrap().ssp_reply(&remote_addr, variant, 0, 0);
+
+                    if new_lockout {
+                        self.for_all_callbacks(|callback| {
+                            callback.on_pairing_locked_out(
+                                address.clone(),
+                                global,
+                                lockout.as_secs() as u32,
+                            );
+                        });
+                    }
+                    return;
+                }
+            }
+        }
+
         // Currently this supports many agent because we accept many callbacks.
         // TODO: We need a way to select the default agent.
         self.for_all_callbacks(|callback| {
@@ -763,6 +1569,18 @@ impl BtifBluetoothCallbacks for Bluetooth {
     ) {
         let address = addr.to_string();
 
+        if &bond_state != &BtBondState::Bonding {
+            self.outgoing_bonds.remove(&address);
+        }
+
+        // The device stays bonded if its bond removal failed, and is not forgotten either.
+        if status != BtStatus::Success
+            && &bond_state == &BtBondState::Bonded
+            && self.pending_forgets.remove(&address)
+        {
+            warn!("Failed to remove the bond of {}, not forgetting it", address);
+        }
+
         // Easy case of not bonded -- we remove the device from the bonded list and change the bond
         // state in the found list (in case it was previously bonding).
         if &bond_state == &BtBondState::NotBonded {
@@ -770,6 +1588,10 @@ impl BtifBluetoothCallbacks for Bluetooth {
             self.found_devices
                 .entry(address.clone())
                 .and_modify(|d| d.bond_state = bond_state.clone());
+
+            if self.pending_forgets.remove(&address) {
+                self.request_forget_device(address.clone());
+            }
         }
         // We will only insert into the bonded list after bonding is complete
         else if &bond_state == &BtBondState::Bonded && !self.bonded_devices.contains_key(&address)
@@ -836,10 +1658,21 @@ impl BtifBluetoothCallbacks for Bluetooth {
 
         match device {
             Some(d) => {
+                let uuids_changed =
+                    properties.iter().any(|p| p.get_type() == BtPropertyType::Uuids);
                 d.update_properties(properties);
                 d.seen();
 
                 let info = d.info.clone();
+                // The admin policy reports its effects on the bonded devices only.
+                if uuids_changed && self.bonded_devices.contains_key(&address) {
+                    let txl = self.tx.clone();
+                    let device = info.clone();
+                    topstack::get_runtime().spawn(async move {
+                        let _ = txl.send(Message::AdminRemoteUuidsChanged(device)).await;
+                    });
+                }
+
                 let uuids = self.get_remote_uuids(info.clone());
                 if self.wait_to_connect && uuids.len() > 0 {
                     self.connect_all_enabled_profiles(info);
@@ -854,7 +1687,7 @@ impl BtifBluetoothCallbacks for Bluetooth {
         status: BtStatus,
         addr: RawAddress,
         state: BtAclState,
-        _link_type: BtTransport,
+        link_type: BtTransport,
         _hci_reason: BtHciErrorCode,
     ) {
         if status != BtStatus::Success {
@@ -863,6 +1696,29 @@ impl BtifBluetoothCallbacks for Bluetooth {
         }
 
         let address = addr.to_string();
+        if state == BtAclState::Connected {
+            let own_address_type = match (&link_type, self.controller.as_mut()) {
+                (BtTransport::Le, Some(controller)) => controller
+                    .read_connection_address(addr.val)
+                    .map(|a| OwnAddressType::from_le_address(a.address_type, &a.address))
+                    .unwrap_or_default(),
+                _ => OwnAddressType::Public,
+            };
+            self.record_identity_exposure(
+                &address,
+                LocalIdentity::from_transport(&link_type),
+                own_address_type,
+            );
+        }
+        if link_type == BtTransport::Le {
+            let txl = self.tx.clone();
+            let message =
+                Message::GattLeLinkStateChanged(address.clone(), state == BtAclState::Connected);
+            topstack::get_runtime().spawn(async move {
+                let _ = txl.send(message).await;
+            });
+        }
+
         let device = match self.get_remote_device_if_found_mut(&address) {
             None => {
                 self.found_devices.insert(
@@ -901,6 +1757,7 @@ impl BtifBluetoothCallbacks for Bluetooth {
                             });
                         }
                     };
+                    self.update_radio_activity();
                 }
             }
             None => (),
@@ -995,8 +1852,10 @@ impl IBluetooth for Bluetooth {
         }
     }
 
-    fn set_name(&self, name: String) -> bool {
-        self.intf.lock().unwrap().set_adapter_property(BluetoothProperty::BdName(name)) == 0
+    fn set_name(&self, name: String) -> BtResult<()> {
+        BtError::from_status(
+            self.intf.lock().unwrap().set_adapter_property(BluetoothProperty::BdName(name)),
+        )
     }
 
     fn get_bluetooth_class(&self) -> u32 {
@@ -1009,8 +1868,10 @@ impl IBluetooth for Bluetooth {
         }
     }
 
-    fn set_bluetooth_class(&self, cod: u32) -> bool {
-        self.intf.lock().unwrap().set_adapter_property(BluetoothProperty::ClassOfDevice(cod)) == 0
+    fn set_bluetooth_class(&self, cod: u32) -> BtResult<()> {
+        BtError::from_status(
+            self.intf.lock().unwrap().set_adapter_property(BluetoothProperty::ClassOfDevice(cod)),
+        )
     }
 
     fn get_discoverable(&self) -> bool {
@@ -1036,13 +1897,13 @@ impl IBluetooth for Bluetooth {
         }
     }
 
-    fn set_discoverable(&self, mode: bool, duration: u32) -> bool {
+    fn set_discoverable(&self, mode: bool, duration: u32) -> BtResult<()> {
         self.intf
             .lock()
             .unwrap()
             .set_adapter_property(BluetoothProperty::AdapterDiscoverableTimeout(duration));
-        self.intf.lock().unwrap().set_adapter_property(BluetoothProperty::AdapterScanMode(
-            if mode {
+        BtError::from_status(self.intf.lock().unwrap().set_adapter_property(
+            BluetoothProperty::AdapterScanMode(if mode {
                 BtScanMode::ConnectableDiscoverable
             } else {
                 if self.is_connectable {
@@ -1050,8 +1911,8 @@ impl IBluetooth for Bluetooth {
                 } else {
                     BtScanMode::None_
                 }
-            },
-        )) == 0
+            }),
+        ))
     }
 
     fn is_multi_advertisement_supported(&self) -> bool {
@@ -1076,18 +1937,22 @@ impl IBluetooth for Bluetooth {
         }
     }
 
-    fn start_discovery(&self) -> bool {
-        self.intf.lock().unwrap().start_discovery() == 0
+    fn start_discovery(&self) -> BtResult<()> {
+        BtError::from_status(self.intf.lock().unwrap().start_discovery())
     }
 
-    fn cancel_discovery(&self) -> bool {
-        self.intf.lock().unwrap().cancel_discovery() == 0
+    fn cancel_discovery(&self) -> BtResult<()> {
+        BtError::from_status(self.intf.lock().unwrap().cancel_discovery())
     }
 
     fn is_discovering(&self) -> bool {
         self.is_discovering
     }
 
+    fn is_ready(&self) -> bool {
+        self.is_ready
+    }
+
     fn get_discovery_end_millis(&self) -> u64 {
         if !self.is_discovering {
             return 0;
@@ -1101,44 +1966,62 @@ impl IBluetooth for Bluetooth {
         }
     }
 
-    fn create_bond(&self, device: BluetoothDevice, transport: BtTransport) -> bool {
+    fn create_bond(&mut self, device: BluetoothDevice, transport: BtTransport) -> BtResult<()> {
         let addr = RawAddress::from_string(device.address.clone());
 
         if addr.is_none() {
             warn!("Can't create bond. Address {} is not valid", device.address);
-            return false;
+            return Err(BtError::invalid_argument(format!("invalid address {}", device.address)));
         }
 
         let address = addr.unwrap();
 
         // BREDR connection won't work when Inquiry is in progress.
-        self.cancel_discovery();
+        let _ = self.cancel_discovery();
 
-        self.intf.lock().unwrap().create_bond(&address, transport) == 0
+        BtError::from_status(self.intf.lock().unwrap().create_bond(&address, transport))?;
+        self.outgoing_bonds.insert(address.to_string());
+        Ok(())
     }
 
-    fn cancel_bond_process(&self, device: BluetoothDevice) -> bool {
+    fn cancel_bond_process(&self, device: BluetoothDevice) -> BtResult<()> {
         let addr = RawAddress::from_string(device.address.clone());
 
         if addr.is_none() {
             warn!("Can't cancel bond. Address {} is not valid.", device.address);
-            return false;
+            return Err(BtError::invalid_argument(format!("invalid address {}", device.address)));
         }
 
         let address = addr.unwrap();
-        self.intf.lock().unwrap().cancel_bond(&address) == 0
+        BtError::from_status(self.intf.lock().unwrap().cancel_bond(&address))
     }
 
-    fn remove_bond(&self, device: BluetoothDevice) -> bool {
+    fn remove_bond(&self, device: BluetoothDevice) -> BtResult<()> {
         let addr = RawAddress::from_string(device.address.clone());
 
         if addr.is_none() {
             warn!("Can't remove bond. Address {} is not valid.", device.address);
-            return false;
+            return Err(BtError::invalid_argument(format!("invalid address {}", device.address)));
         }
 
         let address = addr.unwrap();
-        self.intf.lock().unwrap().remove_bond(&address) == 0
+        BtError::from_status(self.intf.lock().unwrap().remove_bond(&address))
+    }
+
+    fn remove_bond_cascade(&mut self, device: BluetoothDevice) -> BtResult<()> {
+        let address = RawAddress::from_string(device.address.clone()).ok_or_else(|| {
+            BtError::invalid_argument(format!("invalid address {}", device.address))
+        })?;
+        let addr = address.to_string();
+
+        // The device is forgotten once the bond removal completes.
+        if self.bonded_devices.contains_key(&addr) {
+            BtError::from_status(self.intf.lock().unwrap().remove_bond(&address))?;
+            self.pending_forgets.insert(addr);
+        } else {
+            self.request_forget_device(addr);
+        }
+        Ok(())
     }
 
     fn get_bonded_devices(&self) -> Vec<BluetoothDevice> {
@@ -1360,7 +2243,9 @@ impl IBluetooth for Bluetooth {
         for uuid in uuids.iter() {
             match self.uuid_helper.is_known_profile(uuid) {
                 Some(p) => {
-                    if self.uuid_helper.is_profile_enabled(&p) {
+                    if !self.is_service_allowed(uuid) {
+                        debug!("[{}]: Not connecting {:?} blocked by policy.", device.address, p);
+                    } else if self.uuid_helper.is_profile_enabled(&p) {
                         match p {
                             Profile::Hid | Profile::Hogp => {
                                 self.hh.as_ref().unwrap().connect(&mut addr.unwrap());
@@ -1436,6 +2321,122 @@ impl IBluetooth for Bluetooth {
 
         return true;
     }
+
+    fn get_identity_exposure_report(&self) -> Vec<IdentityExposure> {
+        self.identity_exposures.report()
+    }
+
+    fn get_classic_scan_parameters(&self) -> ClassicScanParameters {
+        self.classic_scan_parameters.clone()
+    }
+
+    fn set_classic_scan_parameters(&mut self, parameters: ClassicScanParameters) -> BtResult<()> {
+        parameters.validate()?;
+
+        debug!("Setting classic scan parameters {:?}", parameters);
+        if parameters != self.classic_scan_parameters {
+            parameters.save(&ClassicScanParameters::path(self.adapter_index));
+        }
+        self.classic_scan_parameters = parameters;
+        if self.state == BtState::On {
+            self.apply_classic_scan_parameters();
+        }
+        Ok(())
+    }
+
+    fn set_classic_scan_preset(&mut self, preset: ClassicScanPreset) -> BtResult<()> {
+        self.set_classic_scan_parameters(ClassicScanParameters::from_preset(preset))
+    }
+
+    fn restart_stack(&mut self, reason: String) -> BtResult<()> {
+        let restart =
+            StackRestart::start(self.stack_restart.as_ref(), &self.state, reason, Instant::now())?;
+
+        warn!("Restarting the stack for: {}", restart.reason);
+        self.for_all_callbacks(|callback| {
+            callback.on_stack_restart_started(restart.reason.clone());
+        });
+        self.stack_restart = Some(restart);
+
+        // The adapter is disabled from the dispatch loop, once the GATT registrations are
+        // released.
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(Message::StackRestart).await;
+            time::sleep(STACK_RESTART_TIMEOUT).await;
+            let _ = tx.send(Message::StackRestartTimeout).await;
+        });
+        Ok(())
+    }
+
+    fn get_radio_activity(&self) -> RadioActivity {
+        // A bonded device can also be in the found devices.
+        let connections = self
+            .bonded_devices
+            .values()
+            .chain(self.found_devices.values())
+            .filter(|d| d.acl_state == BtAclState::Connected)
+            .map(|d| &d.info.address)
+            .collect::<HashSet<_>>()
+            .len();
+
+        RadioActivity {
+            scanning: self.is_discovering || self.le_scanning,
+            advertising: self.le_advertising,
+            connections: connections as u32,
+        }
+    }
+
+    fn get_remote_version_info(&mut self, device: BluetoothDevice) -> BtResult<RemoteVersionInfo> {
+        if let Some(info) = self.refresh_remote_version_info(&device.address) {
+            return Ok(info);
+        }
+
+        // The version of bonded devices is also kept in the storage of the stack.
+        match self.get_remote_device_property(&device, &BtPropertyType::RemoteVersionInfo) {
+            Some(BluetoothProperty::RemoteVersionInfo(version)) => Ok(RemoteVersionInfo {
+                lmp_version: version.version as u8,
+                lmp_subversion: version.sub_ver as u16,
+                manufacturer: version.manufacturer as u16,
+                features: vec![],
+            }),
+            _ => Err(BtError::not_found(format!(
+                "No version information for device {}",
+                device.address
+            ))),
+        }
+    }
+
+    fn set_device_privacy_mode(
+        &mut self,
+        device: BluetoothDevice,
+        mode: PrivacyMode,
+    ) -> BtResult<()> {
+        let address = RawAddress::from_string(device.address.clone()).ok_or_else(|| {
+            BtError::invalid_argument(format!("invalid address {}", device.address))
+        })?;
+        let addr = address.to_string();
+        if !self.bonded_devices.contains_key(&addr) {
+            return Err(BtError::not_found(format!("Device {} is not bonded", addr)));
+        }
+
+        let controller = match (self.state == BtState::On, self.controller.as_mut()) {
+            (true, Some(controller)) => controller,
+            _ => return Err(BtError::new(BtErrorCategory::NotReady, "The adapter is not enabled")),
+        };
+        // Only the devices in the resolving list use resolvable private addresses.
+        if !controller.set_privacy_mode(address.val, mode == PrivacyMode::Device) {
+            return Err(BtError::new(
+                BtErrorCategory::Unsupported,
+                format!("Device {} has no identity resolving key", addr),
+            ));
+        }
+
+        if let Some(device) = self.bonded_devices.get_mut(&addr) {
+            device.privacy_mode = mode;
+        }
+        Ok(())
+    }
 }
 
 impl BtifSdpCallbacks for Bluetooth {
@@ -1453,3 +2454,139 @@ impl BtifSdpCallbacks for Bluetooth {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classic_scan_presets() {
+        for preset in [
+            ClassicScanPreset::Default,
+            ClassicScanPreset::FastConnect,
+            ClassicScanPreset::LowPower,
+        ] {
+            assert_eq!(Ok(()), ClassicScanParameters::from_preset(preset).validate());
+        }
+        assert!(ClassicScanParameters::from_preset(ClassicScanPreset::FastConnect).interlaced_scan);
+        assert_eq!(
+            ClassicScanParameters {
+                page_scan_interval: 0x400,
+                page_scan_window: 0x12,
+                inquiry_scan_interval: 0x800,
+                inquiry_scan_window: 0x12,
+                interlaced_scan: true,
+            },
+            ClassicScanParameters::default()
+        );
+    }
+
+    #[test]
+    fn test_classic_scan_parameters_validate() {
+        let valid = ClassicScanParameters::default();
+
+        // Intervals must be even and in range.
+        for interval in [0x10, 0x801, 0x1002] {
+            let parameters =
+                ClassicScanParameters { page_scan_interval: interval, ..valid.clone() };
+            assert!(parameters.validate().is_err());
+        }
+
+        // Windows must not be longer than their interval.
+        let parameters = ClassicScanParameters {
+            inquiry_scan_interval: 0x100,
+            inquiry_scan_window: 0x102,
+            ..valid.clone()
+        };
+        assert!(parameters.validate().is_err());
+        let parameters = ClassicScanParameters { page_scan_window: 0x10, ..valid.clone() };
+        assert!(parameters.validate().is_err());
+
+        let parameters = ClassicScanParameters {
+            page_scan_interval: 0x12,
+            page_scan_window: 0x12,
+            ..valid.clone()
+        };
+        assert_eq!(Ok(()), parameters.validate());
+    }
+
+    #[test]
+    fn test_classic_scan_parameters_parse_line() {
+        assert_eq!(
+            Some(ClassicScanParameters {
+                page_scan_interval: 0x100,
+                page_scan_window: 0x12,
+                inquiry_scan_interval: 0x800,
+                inquiry_scan_window: 0x24,
+                interlaced_scan: true,
+            }),
+            ClassicScanParameters::parse_line("256 18 2048 36 1")
+        );
+        assert_eq!(None, ClassicScanParameters::parse_line("256 18 2048 36"));
+        assert_eq!(None, ClassicScanParameters::parse_line("256 18 2048 36 2"));
+        // Parameters which would be refused by `set_classic_scan_parameters`.
+        assert_eq!(None, ClassicScanParameters::parse_line("257 18 2048 36 0"));
+    }
+
+    #[test]
+    fn test_classic_scan_parameters_path() {
+        assert_eq!(
+            PathBuf::from("/var/lib/bluetooth/classic_scan_parameters.hci1"),
+            ClassicScanParameters::path(1)
+        );
+    }
+
+    /// Removes the file at its path when dropped, even if the test fails.
+    struct TempFile(PathBuf);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_classic_scan_parameters_round_trip() {
+        let file = TempFile(
+            std::env::temp_dir()
+                .join(format!("classic_scan_parameters_test_{}", std::process::id())),
+        );
+        let path = &file.0;
+        assert_eq!(ClassicScanParameters::default(), ClassicScanParameters::load(path));
+
+        let parameters = ClassicScanParameters::from_preset(ClassicScanPreset::FastConnect);
+        parameters.save(path);
+        assert_eq!(parameters, ClassicScanParameters::load(path));
+
+        std::fs::write(path, "garbage\n").unwrap();
+        assert_eq!(ClassicScanParameters::default(), ClassicScanParameters::load(path));
+    }
+
+    #[test]
+    fn test_stack_restart_start() {
+        let now = Instant::now();
+        let restart = StackRestart::start(None, &BtState::On, String::from("wedged"), now).unwrap();
+        assert_eq!("wedged", restart.reason);
+
+        // Only one restart at a time, and only from an enabled adapter.
+        let err = StackRestart::start(Some(&restart), &BtState::On, String::new(), now);
+        assert_eq!(BtErrorCategory::Busy, err.unwrap_err().category);
+        let err = StackRestart::start(None, &BtState::Off, String::new(), now);
+        assert_eq!(BtErrorCategory::NotReady, err.unwrap_err().category);
+    }
+
+    #[test]
+    fn test_stack_restart_timeout() {
+        let now = Instant::now();
+        let restart = StackRestart::start(None, &BtState::On, String::new(), now).unwrap();
+        assert!(!restart.has_timed_out(now));
+        assert!(!restart.has_timed_out(now + STACK_RESTART_TIMEOUT - Duration::from_millis(1)));
+        assert!(restart.has_timed_out(now + STACK_RESTART_TIMEOUT));
+
+        // The timer of an earlier restart does not end a later one.
+        let later =
+            StackRestart::start(None, &BtState::On, String::new(), now + STACK_RESTART_TIMEOUT)
+                .unwrap();
+        assert!(!later.has_timed_out(restart.deadline));
+    }
+}
@@ -0,0 +1,291 @@
+//! Admin policy API, with which an enterprise policy daemon restricts the services that the
+//! devices may be connected to.
+//!
+//! The policy is enforced by `Bluetooth`, `BluetoothHid` and `BluetoothMedia`, which refuse to
+//! connect the profiles of services outside of the allowlist, disconnect the ones that the devices
+//! connect, and disconnect them once blocked. The policy is kept across restarts of the daemon.
+
+use bt_topshim::btif::Uuid128Bit;
+
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::Sender;
+
+use crate::bluetooth::{Bluetooth, BluetoothDevice, IBluetooth};
+use crate::storage::{parse_uuid, save_lines, to_hex, PUBLIC_FILE_MODE};
+use crate::uuid::UuidHelper;
+use crate::{Message, RPCProxy};
+
+/// File holding the services allowed by the policy, one per line.
+pub const ADMIN_POLICY_FILE: &str = "/var/lib/bluetooth/admin_policy";
+
+/// Defines the Admin API. The whole API is privileged, as it lifts the policy as well.
+pub trait IBluetoothAdmin {
+    /// Returns whether the policy allows connecting to `service`.
+    fn is_service_allowed(&self, service: Uuid128Bit) -> bool;
+
+    /// Restricts the services that the devices may be connected to, all of them being allowed if
+    /// `services` is empty. The connected profiles of the services that are no longer allowed are
+    /// disconnected.
+    fn set_allowed_services(&mut self, services: Vec<Uuid128Bit>) -> bool;
+
+    /// Returns the services allowed by the policy, empty if all of them are.
+    fn get_allowed_services(&self) -> Vec<Uuid128Bit>;
+
+    /// Returns the services of `device` blocked by the policy.
+    fn get_device_policy_effect(&self, device: BluetoothDevice) -> PolicyEffect;
+
+    /// Adds an observer of the policy and of its effects.
+    ///
+    /// Returns the id of the callback.
+    fn register_admin_policy_callback(
+        &mut self,
+        callback: Box<dyn IBluetoothAdminPolicyCallback + Send>,
+    ) -> u32;
+
+    /// Removes an observer of the policy.
+    ///
+    /// Returns false if `callback_id` is not recognized.
+    fn unregister_admin_policy_callback(&mut self, callback_id: u32) -> bool;
+}
+
+/// Admin policy events.
+pub trait IBluetoothAdminPolicyCallback: RPCProxy {
+    /// When the services allowed by the policy change.
+    fn on_service_allowlist_changed(&self, allowlist: Vec<Uuid128Bit>);
+
+    /// When the services of a bonded device blocked by the policy change, because of the policy
+    /// or of the services of the device.
+    fn on_device_policy_effect_changed(&self, device: BluetoothDevice, effect: PolicyEffect);
+}
+
+/// Effect of the policy on a device.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PolicyEffect {
+    /// Services of the device blocked by the policy.
+    pub service_blocked: Vec<Uuid128Bit>,
+    /// Whether the policy blocks any service of the device.
+    pub affected: bool,
+}
+
+/// Returns whether `allowlist` allows `service`, an empty list allowing all of them.
+pub(crate) fn is_service_in_allowlist(
+    allowlist: &HashSet<Uuid128Bit>,
+    service: &Uuid128Bit,
+) -> bool {
+    allowlist.is_empty() || allowlist.contains(service)
+}
+
+/// Returns the effect of `allowlist` on a device with the services `uuids`.
+fn policy_effect(allowlist: &HashSet<Uuid128Bit>, uuids: &[Uuid128Bit]) -> PolicyEffect {
+    let service_blocked: Vec<Uuid128Bit> =
+        uuids.iter().filter(|uuid| !is_service_in_allowlist(allowlist, uuid)).cloned().collect();
+    let affected = !service_blocked.is_empty();
+    PolicyEffect { service_blocked, affected }
+}
+
+/// Loads the services allowed by the policy saved in `path`. Malformed lines are skipped.
+fn load_allowed_services(path: &Path) -> HashSet<Uuid128Bit> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => contents.lines().filter_map(|line| parse_uuid(line.trim())).collect(),
+        Err(_) => HashSet::new(),
+    }
+}
+
+/// Implementation of the Admin API.
+pub struct BluetoothAdmin {
+    tx: Sender<Message>,
+    adapter: Option<Arc<Mutex<Box<Bluetooth>>>>,
+    policy_file: PathBuf,
+    allowed_services: HashSet<Uuid128Bit>,
+    callbacks: HashMap<u32, Box<dyn IBluetoothAdminPolicyCallback + Send>>,
+    // Last effect reported for the affected devices, by address.
+    device_policy_effects: HashMap<String, PolicyEffect>,
+}
+
+impl BluetoothAdmin {
+    pub fn new(tx: Sender<Message>) -> BluetoothAdmin {
+        BluetoothAdmin::with_policy_file(tx, ADMIN_POLICY_FILE)
+    }
+
+    /// Creates the API with the policy saved in `policy_file`.
+    fn with_policy_file<P: Into<PathBuf>>(tx: Sender<Message>, policy_file: P) -> BluetoothAdmin {
+        let policy_file = policy_file.into();
+        let allowed_services = load_allowed_services(&policy_file);
+        BluetoothAdmin {
+            tx,
+            adapter: None,
+            policy_file,
+            allowed_services,
+            callbacks: HashMap::new(),
+            device_policy_effects: HashMap::new(),
+        }
+    }
+
+    pub fn set_adapter(&mut self, adapter: Arc<Mutex<Box<Bluetooth>>>) {
+        adapter.lock().unwrap().set_allowed_services(self.allowed_services.clone());
+        self.adapter = Some(adapter);
+    }
+
+    fn save_allowed_services(&self) {
+        let lines: Vec<String> =
+            self.get_allowed_services().iter().map(|uuid| to_hex(uuid)).collect();
+        if let Err(e) = save_lines(&self.policy_file, &lines, PUBLIC_FILE_MODE) {
+            warn!("Failed to save the admin policy to {}: {}", self.policy_file.display(), e);
+        }
+    }
+
+    pub(crate) fn remove_callback(&mut self, id: u32) -> bool {
+        match self.callbacks.get_mut(&id) {
+            Some(callback) => {
+                callback.unregister(id);
+                self.callbacks.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reports the new effect of the policy on `device` if it changed.
+    pub(crate) fn update_device_policy_effect(&mut self, device: BluetoothDevice) {
+        let effect = self.get_device_policy_effect(device.clone());
+        let previous = self.device_policy_effects.get(&device.address).cloned().unwrap_or_default();
+        if effect == previous {
+            return;
+        }
+
+        if effect.affected {
+            self.device_policy_effects.insert(device.address.clone(), effect.clone());
+        } else {
+            self.device_policy_effects.remove(&device.address);
+        }
+
+        for callback in self.callbacks.values() {
+            callback.on_device_policy_effect_changed(device.clone(), effect.clone());
+        }
+    }
+}
+
+impl IBluetoothAdmin for BluetoothAdmin {
+    fn is_service_allowed(&self, service: Uuid128Bit) -> bool {
+        is_service_in_allowlist(&self.allowed_services, &service)
+    }
+
+    fn set_allowed_services(&mut self, services: Vec<Uuid128Bit>) -> bool {
+        let allowed_services: HashSet<Uuid128Bit> = services.into_iter().collect();
+        if allowed_services == self.allowed_services {
+            return true;
+        }
+
+        info!(
+            "Allowed services: [{}]",
+            allowed_services.iter().map(UuidHelper::to_string).collect::<Vec<_>>().join(", ")
+        );
+        self.allowed_services = allowed_services;
+        self.save_allowed_services();
+
+        let devices = match self.adapter.as_ref() {
+            Some(adapter) => {
+                let mut adapter = adapter.lock().unwrap();
+                adapter.set_allowed_services(self.allowed_services.clone());
+                adapter.get_bonded_devices()
+            }
+            None => vec![],
+        };
+
+        let allowlist = self.get_allowed_services();
+        for callback in self.callbacks.values() {
+            callback.on_service_allowlist_changed(allowlist.clone());
+        }
+
+        for device in devices {
+            self.update_device_policy_effect(device);
+        }
+        true
+    }
+
+    fn get_allowed_services(&self) -> Vec<Uuid128Bit> {
+        let mut services: Vec<Uuid128Bit> = self.allowed_services.iter().cloned().collect();
+        services.sort();
+        services
+    }
+
+    fn get_device_policy_effect(&self, device: BluetoothDevice) -> PolicyEffect {
+        let uuids = match self.adapter.as_ref() {
+            Some(adapter) => adapter.lock().unwrap().get_remote_uuids(device),
+            None => vec![],
+        };
+        policy_effect(&self.allowed_services, &uuids)
+    }
+
+    fn register_admin_policy_callback(
+        &mut self,
+        mut callback: Box<dyn IBluetoothAdminPolicyCallback + Send>,
+    ) -> u32 {
+        let tx = self.tx.clone();
+
+        let id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _result = tx.send(Message::AdminCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.callbacks.insert(id, callback);
+        id
+    }
+
+    fn unregister_admin_policy_callback(&mut self, callback_id: u32) -> bool {
+        self.remove_callback(callback_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_effect() {
+        let hid = UuidHelper::from_string(crate::uuid::HID).unwrap();
+        let a2dp = UuidHelper::from_string(crate::uuid::A2DP_SINK).unwrap();
+
+        let mut allowlist = HashSet::new();
+        assert!(is_service_in_allowlist(&allowlist, &hid));
+        assert_eq!(PolicyEffect::default(), policy_effect(&allowlist, &[hid, a2dp]));
+
+        allowlist.insert(hid);
+        assert!(is_service_in_allowlist(&allowlist, &hid));
+        assert!(!is_service_in_allowlist(&allowlist, &a2dp));
+        assert_eq!(
+            PolicyEffect { service_blocked: vec![a2dp], affected: true },
+            policy_effect(&allowlist, &[hid, a2dp])
+        );
+        assert_eq!(PolicyEffect::default(), policy_effect(&allowlist, &[hid]));
+    }
+
+    #[test]
+    fn test_policy_is_saved() {
+        let path = std::env::temp_dir().join(format!("admin_policy_test_{}", std::process::id()));
+        let hid = UuidHelper::from_string(crate::uuid::HID).unwrap();
+        let hogp = UuidHelper::from_string(crate::uuid::HOGP).unwrap();
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+
+        let mut admin = BluetoothAdmin::with_policy_file(tx.clone(), &path);
+        assert!(admin.get_allowed_services().is_empty());
+        assert!(admin.set_allowed_services(vec![hogp, hid]));
+
+        let mut admin = BluetoothAdmin::with_policy_file(tx.clone(), &path);
+        let mut expected = vec![hid, hogp];
+        expected.sort();
+        assert_eq!(expected, admin.get_allowed_services());
+        assert!(!admin.is_service_allowed(UuidHelper::from_string(crate::uuid::HFP).unwrap()));
+
+        // Lifting the restriction is saved too.
+        assert!(admin.set_allowed_services(vec![]));
+        assert!(BluetoothAdmin::with_policy_file(tx, &path).get_allowed_services().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
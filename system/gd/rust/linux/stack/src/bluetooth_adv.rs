@@ -0,0 +1,1729 @@
+//! LE advertising sets: the parameters and data of the sets, and the checks done before they reach
+//! the controller.
+//!
+//! The advertise data is encoded into AD structures here so that data too large for the set is
+//! rejected when the request is made, with the allowed size, rather than failing in the
+//! controller.
+//!
+//! The controller has a limited number of advertiser slots. When they are all taken, a set is
+//! suspended to make room for a new one, and the sets then take turns in the slots every
+//! `ADVERTISING_ROTATION_PERIOD`.
+//!
+//! For range testing, the TX power of a set can also be swept across a range of levels with
+//! `TxPowerSweep`.
+//!
+//! For payload budgeting, `AdvertiseDataBreakdown` tells the bytes taken by each AD structure of
+//! advertise data and the bytes left in legacy and extended advertisements.
+//!
+//! Advertise data is checked field by field before it is encoded, and rejected with an
+//! `InvalidAdvertiseData` error whose sub-code is the `AdvertiseDataProblem` found.
+//!
+//! The sets persistent across restarts are saved in `SAVED_ADVERTISING_SETS_FILE`, one
+//! `SavedAdvertisingSet` per line, for the next daemon to re-create them.
+
+use bt_topshim::btif::{BtLocalLeFeatures, Uuid128Bit};
+use bt_topshim::profiles::gatt::AdvertiseParameters;
+
+use log::warn;
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+use crate::bluetooth_gatt::{LePhy, BASE_UUID};
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::storage::{from_hex, save_lines, to_hex, PUBLIC_FILE_MODE};
+use crate::uuid::BtUuid;
+use crate::RPCProxy;
+
+/// Longest advertising data or scan response of a legacy advertisement.
+pub const LEGACY_ADV_DATA_LEN_MAX: usize = 31;
+
+/// Longest AD structure, including its length and type. The stack does not fragment AD
+/// structures across the advertising PDUs of an extended advertisement.
+pub const AD_STRUCTURE_LEN_MAX: usize = 251;
+
+// Size of the Flags AD structure added by the stack to connectable advertisements.
+pub(crate) const FLAGS_LEN: usize = 3;
+
+/// Advertising sets a client may hold until changed with
+/// `IBluetoothGatt::set_max_advertising_sets_per_app`.
+pub const DEFAULT_MAX_ADVERTISING_SETS_PER_APP: usize = 4;
+
+/// Time a set keeps its controller slot while other sets are suspended.
+pub const ADVERTISING_ROTATION_PERIOD: Duration = Duration::from_secs(10);
+
+/// Range of the TX power requested for an advertising set, in dBm.
+pub const TX_POWER_MIN: i32 = -127;
+pub const TX_POWER_MAX: i32 = 20;
+
+/// Shortest time a TX power sweep stays at each level.
+pub const TX_POWER_SWEEP_DWELL_MIN: Duration = Duration::from_millis(100);
+
+/// Range of the interval between the rotations of the address of an advertising set, as allowed
+/// for the timeout of resolvable private addresses.
+pub const ADDRESS_ROTATION_INTERVAL_MIN: Duration = Duration::from_secs(1);
+pub const ADDRESS_ROTATION_INTERVAL_MAX: Duration = Duration::from_secs(3600);
+
+/// Shortest advertising interval, in 0.625 ms units (20 ms).
+pub const INTERVAL_MIN: i32 = 0x20;
+/// Longest advertising interval, in 0.625 ms units, excluded since the controller is given a
+/// range ending one unit after the interval.
+pub const INTERVAL_MAX: i32 = 0xFF_FFFF;
+
+// Advertising event properties of HCI LE Set Extended Advertising Parameters.
+const ADV_PROP_CONNECTABLE: u16 = 0x01;
+const ADV_PROP_SCANNABLE: u16 = 0x02;
+const ADV_PROP_LEGACY: u16 = 0x10;
+const ADV_PROP_ANONYMOUS: u16 = 0x20;
+const ADV_PROP_INCLUDE_TX_POWER: u16 = 0x40;
+
+// Advertise on the 3 primary advertising channels.
+const CHANNEL_MAP_ALL: u8 = 0x07;
+
+// AD type of the Flags added by the stack to connectable advertisements.
+const AD_TYPE_FLAGS: u8 = 0x01;
+
+// AD types written by `AdvertiseData::encode`, see the Assigned Numbers.
+const AD_TYPE_SERVICE_UUIDS_16: u8 = 0x03;
+const AD_TYPE_SERVICE_UUIDS_32: u8 = 0x05;
+const AD_TYPE_SERVICE_UUIDS_128: u8 = 0x07;
+const AD_TYPE_COMPLETE_LOCAL_NAME: u8 = 0x09;
+const AD_TYPE_TX_POWER_LEVEL: u8 = 0x0A;
+const AD_TYPE_SOLICIT_UUIDS_16: u8 = 0x14;
+const AD_TYPE_SOLICIT_UUIDS_128: u8 = 0x15;
+const AD_TYPE_SERVICE_DATA_16: u8 = 0x16;
+const AD_TYPE_SOLICIT_UUIDS_32: u8 = 0x1F;
+const AD_TYPE_SERVICE_DATA_32: u8 = 0x20;
+const AD_TYPE_SERVICE_DATA_128: u8 = 0x21;
+const AD_TYPE_TRANSPORT_DISCOVERY_DATA: u8 = 0x26;
+const AD_TYPE_MANUFACTURER_DATA: u8 = 0xFF;
+
+// Organization ID of the transport blocks reserved by the Assigned Numbers.
+const TDS_ORGANIZATION_ID_RESERVED: u8 = 0x00;
+// Transport Data Incomplete bit of the TDS Flags, between the role and the transport state.
+const TDS_FLAG_TRANSPORT_DATA_INCOMPLETE: u8 = 0x04;
+const TDS_FLAGS_TRANSPORT_STATE_SHIFT: u8 = 3;
+
+// Statuses of HCI LE Advertising Set Terminated for the sets the controller stops on its own.
+const HCI_ADVERTISING_TIMEOUT: u8 = 0x3C;
+const HCI_LIMIT_REACHED: u8 = 0x43;
+
+/// Status of the advertising operations, as reported by the advertising manager.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum AdvertisingStatus {
+    Success = 0,
+    DataTooLarge,
+    TooManyAdvertisers,
+    AlreadyStarted,
+    InternalError,
+    FeatureUnsupported,
+}
+
+impl Default for AdvertisingStatus {
+    fn default() -> Self {
+        AdvertisingStatus::Success
+    }
+}
+
+/// Why the controller stopped advertising a set, see
+/// `IAdvertisingSetCallback::on_advertising_set_terminated`.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum AdvertisingTerminationReason {
+    /// The duration the set was enabled for is over.
+    DurationExpired = 0,
+    /// The set sent the maximum number of extended advertising events it was enabled for.
+    MaxEventsReached = 1,
+}
+
+impl AdvertisingTerminationReason {
+    /// Returns the reason of the termination of a set reported as disabled with `status`, if the
+    /// controller stopped it on its own.
+    pub(crate) fn from_status(status: u8) -> Option<AdvertisingTerminationReason> {
+        match status {
+            HCI_ADVERTISING_TIMEOUT => Some(AdvertisingTerminationReason::DurationExpired),
+            HCI_LIMIT_REACHED => Some(AdvertisingTerminationReason::MaxEventsReached),
+            _ => None,
+        }
+    }
+}
+
+/// Parameters of an advertising set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdvertisingSetParameters {
+    pub connectable: bool,
+    pub scannable: bool,
+    /// Legacy advertisements are seen by all scanners but carry at most 31 bytes.
+    pub is_legacy: bool,
+    /// Omits the address from the advertisements (extended advertising only).
+    pub is_anonymous: bool,
+    /// Includes the TX power in the extended advertising header.
+    pub include_tx_power: bool,
+    pub primary_phy: LePhy,
+    /// PHY of the auxiliary packets of an extended advertisement.
+    pub secondary_phy: LePhy,
+    /// Advertising interval, in 0.625 ms units, from `INTERVAL_MIN` to below `INTERVAL_MAX`.
+    pub interval: i32,
+    /// Requested TX power, in dBm.
+    pub tx_power_level: i32,
+    /// -1 for the address policy of the stack, 0 for the public address and 1 for a random one.
+    pub own_address_type: i32,
+}
+
+impl From<AdvertisingSetParameters> for AdvertiseParameters {
+    fn from(params: AdvertisingSetParameters) -> Self {
+        let mut props: u16 = 0;
+        if params.connectable {
+            props |= ADV_PROP_CONNECTABLE;
+        }
+        if params.scannable {
+            props |= ADV_PROP_SCANNABLE;
+        }
+        if params.is_legacy {
+            props |= ADV_PROP_LEGACY;
+        }
+        if params.is_anonymous {
+            props |= ADV_PROP_ANONYMOUS;
+        }
+        if params.include_tx_power {
+            props |= ADV_PROP_INCLUDE_TX_POWER;
+        }
+
+        // Checked by `AdvertisingSetParameters::validate`, only clamped to keep the conversion
+        // total.
+        let interval = params.interval.clamp(INTERVAL_MIN, INTERVAL_MAX - 1) as u32;
+
+        AdvertiseParameters {
+            advertising_event_properties: props,
+            min_interval: interval,
+            max_interval: interval + 1,
+            channel_map: CHANNEL_MAP_ALL,
+            tx_power: params.tx_power_level.clamp(i8::MIN.into(), i8::MAX.into()) as i8,
+            primary_advertising_phy: params.primary_phy as u8,
+            secondary_advertising_phy: params.secondary_phy as u8,
+            scan_request_notification_enable: 0,
+            own_address_type: params.own_address_type as i8,
+        }
+    }
+}
+
+/// Problem found in advertise data, the sub-code of the `BtErrorCategory::InvalidAdvertiseData`
+/// errors.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum AdvertiseDataProblem {
+    /// An AD structure is longer than `AD_STRUCTURE_LEN_MAX`.
+    AdStructureTooLong = 0,
+    /// A UUID is listed twice in the service UUIDs, or in the solicit UUIDs.
+    DuplicateUuid,
+    /// A Transport Discovery Data has no transport block.
+    EmptyTransportDiscoveryData,
+    /// A transport block has the reserved organization ID 0.
+    ReservedOrganizationId,
+    /// The encoded data is longer than the set allows.
+    DataTooLong,
+    /// The encoded data is longer than the set allows only because of the device name.
+    DeviceNameDoesNotFit,
+    /// The set is given a scan response but is not scannable.
+    ScanResponseNotScannable,
+}
+
+impl AdvertiseDataProblem {
+    fn error<T: Into<String>>(self, message: T) -> BtError {
+        BtError {
+            category: BtErrorCategory::InvalidAdvertiseData,
+            sub_code: self as u32,
+            message: message.into(),
+        }
+    }
+}
+
+/// Advertising capabilities of the controller.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdvertisingCapabilities {
+    pub extended_advertising: bool,
+    pub le_2m_phy: bool,
+    pub le_coded_phy: bool,
+    /// Longest advertising data of an extended advertising set.
+    pub max_extended_data_len: usize,
+}
+
+impl From<BtLocalLeFeatures> for AdvertisingCapabilities {
+    fn from(features: BtLocalLeFeatures) -> Self {
+        AdvertisingCapabilities {
+            extended_advertising: features.le_extended_advertising_supported,
+            le_2m_phy: features.le_2m_phy_supported,
+            le_coded_phy: features.le_coded_phy_supported,
+            max_extended_data_len: features.le_maximum_advertising_data_length.into(),
+        }
+    }
+}
+
+impl AdvertisingSetParameters {
+    /// Checks that the parameters are consistent and supported by the controller.
+    pub fn validate(&self, caps: &AdvertisingCapabilities) -> BtResult<()> {
+        if self.interval < INTERVAL_MIN || self.interval >= INTERVAL_MAX {
+            return Err(BtError::invalid_argument(format!(
+                "Advertising interval {} out of the range {} to {} in 0.625 ms units",
+                self.interval,
+                INTERVAL_MIN,
+                INTERVAL_MAX - 1
+            )));
+        }
+
+        if self.is_legacy {
+            if self.primary_phy != LePhy::Phy1m {
+                return Err(BtError::invalid_argument(
+                    "Legacy advertising only uses the LE 1M PHY",
+                ));
+            }
+            if self.is_anonymous {
+                return Err(BtError::invalid_argument("Legacy advertising cannot be anonymous"));
+            }
+            return Ok(());
+        }
+
+        if !caps.extended_advertising {
+            return Err(BtError::new(
+                BtErrorCategory::Unsupported,
+                "Extended advertising is not supported by the controller",
+            ));
+        }
+
+        if self.connectable && self.scannable {
+            return Err(BtError::invalid_argument(
+                "Extended advertising cannot be both connectable and scannable",
+            ));
+        }
+
+        let is_supported = |phy: LePhy, is_primary: bool| match phy {
+            LePhy::Phy1m => true,
+            LePhy::Phy2m => !is_primary && caps.le_2m_phy,
+            LePhy::PhyCoded => caps.le_coded_phy,
+            LePhy::Invalid => false,
+        };
+
+        if !is_supported(self.primary_phy, true) {
+            return Err(BtError::new(
+                BtErrorCategory::Unsupported,
+                format!("Primary PHY {:?} is not supported", self.primary_phy),
+            ));
+        }
+        if !is_supported(self.secondary_phy, false) {
+            return Err(BtError::new(
+                BtErrorCategory::Unsupported,
+                format!("Secondary PHY {:?} is not supported", self.secondary_phy),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Longest encoded advertising data, or scan response, the set can send. The Flags the stack
+    /// adds to the advertising data of connectable sets are not counted.
+    pub fn max_data_len(&self, caps: &AdvertisingCapabilities, scan_response: bool) -> usize {
+        let max = if self.is_legacy { LEGACY_ADV_DATA_LEN_MAX } else { caps.max_extended_data_len };
+
+        if self.connectable && !scan_response {
+            max.saturating_sub(FLAGS_LEN)
+        } else {
+            max
+        }
+    }
+
+    /// Checks that encoded advertising data, or scan response, fits in the set.
+    pub fn validate_data_len(
+        &self,
+        caps: &AdvertisingCapabilities,
+        len: usize,
+        scan_response: bool,
+    ) -> BtResult<()> {
+        if scan_response && len > 0 && !self.scannable {
+            return Err(AdvertiseDataProblem::ScanResponseNotScannable
+                .error("Scan response of a non-scannable advertising set"));
+        }
+
+        let max = self.max_data_len(caps, scan_response);
+        if len > max {
+            return Err(AdvertiseDataProblem::DataTooLong.error(format!(
+                "{} is {} bytes long, more than the {} bytes allowed for {} advertising",
+                if scan_response { "Scan response" } else { "Advertising data" },
+                len,
+                max,
+                if self.is_legacy { "legacy" } else { "extended" },
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Role of a device in a transport block.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum TdsRole {
+    NotSpecified = 0,
+    SeekerOnly,
+    ProviderOnly,
+    SeekerAndProvider,
+}
+
+impl Default for TdsRole {
+    fn default() -> Self {
+        TdsRole::NotSpecified
+    }
+}
+
+/// State of the transport of a transport block.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum TdsTransportState {
+    Off = 0,
+    On,
+    TemporarilyUnavailable,
+}
+
+impl Default for TdsTransportState {
+    fn default() -> Self {
+        TdsTransportState::Off
+    }
+}
+
+/// Transport block of Transport Discovery Data, describing a transport the device seeks or
+/// provides, see the Transport Discovery Service specification.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransportBlock {
+    /// Organization defining the transport data, from the Assigned Numbers.
+    pub organization_id: u8,
+    pub role: TdsRole,
+    /// The transport data continues in the Transport Discovery Service of the device.
+    pub transport_data_incomplete: bool,
+    pub transport_state: TdsTransportState,
+    pub transport_data: Vec<u8>,
+}
+
+impl TransportBlock {
+    fn tds_flags(&self) -> u8 {
+        let incomplete =
+            if self.transport_data_incomplete { TDS_FLAG_TRANSPORT_DATA_INCOMPLETE } else { 0 };
+        self.role as u8
+            | incomplete
+            | (self.transport_state as u8) << TDS_FLAGS_TRANSPORT_STATE_SHIFT
+    }
+}
+
+/// Transport Discovery Data AD structure, made of one or more transport blocks.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransportDiscoveryData {
+    pub transport_blocks: Vec<TransportBlock>,
+}
+
+impl TransportDiscoveryData {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for block in &self.transport_blocks {
+            bytes.push(block.organization_id);
+            bytes.push(block.tds_flags());
+            // Longer data is rejected with its AD structure.
+            bytes.push(block.transport_data.len().min(u8::MAX as usize) as u8);
+            bytes.extend_from_slice(&block.transport_data);
+        }
+        bytes
+    }
+}
+
+/// Data of an advertisement or scan response, encoded into AD structures by the stack.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdvertiseData {
+    pub service_uuids: Vec<BtUuid>,
+    pub solicit_uuids: Vec<BtUuid>,
+    /// Keyed by company identifier.
+    pub manufacturer_data: HashMap<u16, Vec<u8>>,
+    /// Keyed by service UUID.
+    pub service_data: HashMap<BtUuid, Vec<u8>>,
+    pub transport_discovery_data: Vec<TransportDiscoveryData>,
+    pub include_tx_power_level: bool,
+    pub include_device_name: bool,
+}
+
+impl AdvertiseData {
+    /// Checks the fields of the data which do not depend on the advertising set.
+    pub fn validate(&self) -> BtResult<()> {
+        for (uuids, field) in &[(&self.service_uuids, "service"), (&self.solicit_uuids, "solicit")]
+        {
+            let mut seen = HashSet::new();
+            if let Some(uuid) = uuids.iter().find(|uuid| !seen.insert(uuid.uu)) {
+                return Err(AdvertiseDataProblem::DuplicateUuid
+                    .error(format!("{} is listed twice in the {} UUIDs", uuid, field)));
+            }
+        }
+
+        for tds in &self.transport_discovery_data {
+            if tds.transport_blocks.is_empty() {
+                return Err(AdvertiseDataProblem::EmptyTransportDiscoveryData
+                    .error("Transport discovery data without transport block"));
+            }
+            if tds
+                .transport_blocks
+                .iter()
+                .any(|b| b.organization_id == TDS_ORGANIZATION_ID_RESERVED)
+            {
+                return Err(AdvertiseDataProblem::ReservedOrganizationId
+                    .error("Transport block of the reserved organization ID 0"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the data into AD structures, with the shortest form of each UUID. `device_name`
+    /// and `tx_power_level` are only written if the data includes them.
+    pub fn encode(&self, device_name: &str, tx_power_level: i32) -> BtResult<Vec<u8>> {
+        self.validate()?;
+        let mut bytes = vec![];
+
+        append_uuids(
+            &mut bytes,
+            &self.service_uuids,
+            [AD_TYPE_SERVICE_UUIDS_16, AD_TYPE_SERVICE_UUIDS_32, AD_TYPE_SERVICE_UUIDS_128],
+        )?;
+        append_uuids(
+            &mut bytes,
+            &self.solicit_uuids,
+            [AD_TYPE_SOLICIT_UUIDS_16, AD_TYPE_SOLICIT_UUIDS_32, AD_TYPE_SOLICIT_UUIDS_128],
+        )?;
+
+        // Sorted so that the same data is always encoded the same way.
+        let mut service_data: Vec<_> = self.service_data.iter().collect();
+        service_data.sort();
+        for (uuid, data) in service_data {
+            let mut payload = uuid_to_le_bytes(&uuid.uu);
+            let ad_type = match payload.len() {
+                2 => AD_TYPE_SERVICE_DATA_16,
+                4 => AD_TYPE_SERVICE_DATA_32,
+                _ => AD_TYPE_SERVICE_DATA_128,
+            };
+            payload.extend_from_slice(data);
+            append_ad_structure(&mut bytes, ad_type, &payload)?;
+        }
+
+        let mut manufacturer_data: Vec<_> = self.manufacturer_data.iter().collect();
+        manufacturer_data.sort();
+        for (company_id, data) in manufacturer_data {
+            let mut payload = company_id.to_le_bytes().to_vec();
+            payload.extend_from_slice(data);
+            append_ad_structure(&mut bytes, AD_TYPE_MANUFACTURER_DATA, &payload)?;
+        }
+
+        for tds in &self.transport_discovery_data {
+            append_ad_structure(&mut bytes, AD_TYPE_TRANSPORT_DISCOVERY_DATA, &tds.encode())?;
+        }
+
+        if self.include_tx_power_level {
+            let tx_power = tx_power_level.clamp(i8::MIN.into(), i8::MAX.into()) as i8;
+            append_ad_structure(&mut bytes, AD_TYPE_TX_POWER_LEVEL, &[tx_power as u8])?;
+        }
+
+        if self.include_device_name {
+            append_ad_structure(&mut bytes, AD_TYPE_COMPLETE_LOCAL_NAME, device_name.as_bytes())?;
+        }
+
+        Ok(bytes)
+    }
+
+    /// Encodes the data for an advertising set, failing if it does not fit in the set.
+    pub fn encode_for_set(
+        &self,
+        parameters: &AdvertisingSetParameters,
+        caps: &AdvertisingCapabilities,
+        device_name: &str,
+        scan_response: bool,
+    ) -> BtResult<Vec<u8>> {
+        let bytes = self.encode(device_name, parameters.tx_power_level)?;
+        parameters.validate_data_len(caps, bytes.len(), scan_response).map_err(|e| {
+            // The name is the last AD structure.
+            let max = parameters.max_data_len(caps, scan_response);
+            let name_len = device_name.len() + 2;
+            if e.sub_code == AdvertiseDataProblem::DataTooLong as u32
+                && self.include_device_name
+                && bytes.len() - name_len <= max
+            {
+                AdvertiseDataProblem::DeviceNameDoesNotFit.error(format!(
+                    "The device name takes {} bytes, only {} are left for it",
+                    name_len,
+                    max - (bytes.len() - name_len)
+                ))
+            } else {
+                e
+            }
+        })?;
+        Ok(bytes)
+    }
+}
+
+/// Declares advertise data, checked with `AdvertiseData::validate`.
+///
+/// Example:
+///     AdvertiseDataBuilder::new()
+///         .service_uuid(uuid)
+///         .manufacturer_data(company_id, data)
+///         .include_device_name()
+///         .build()
+#[derive(Default)]
+pub struct AdvertiseDataBuilder {
+    data: AdvertiseData,
+}
+
+impl AdvertiseDataBuilder {
+    pub fn new() -> AdvertiseDataBuilder {
+        AdvertiseDataBuilder::default()
+    }
+
+    pub fn service_uuid(mut self, uuid: BtUuid) -> AdvertiseDataBuilder {
+        self.data.service_uuids.push(uuid);
+        self
+    }
+
+    pub fn solicit_uuid(mut self, uuid: BtUuid) -> AdvertiseDataBuilder {
+        self.data.solicit_uuids.push(uuid);
+        self
+    }
+
+    pub fn manufacturer_data(mut self, company_id: u16, data: Vec<u8>) -> AdvertiseDataBuilder {
+        self.data.manufacturer_data.insert(company_id, data);
+        self
+    }
+
+    pub fn service_data(mut self, uuid: BtUuid, data: Vec<u8>) -> AdvertiseDataBuilder {
+        self.data.service_data.insert(uuid, data);
+        self
+    }
+
+    pub fn transport_discovery_data(mut self, tds: TransportDiscoveryData) -> AdvertiseDataBuilder {
+        self.data.transport_discovery_data.push(tds);
+        self
+    }
+
+    pub fn include_tx_power_level(mut self) -> AdvertiseDataBuilder {
+        self.data.include_tx_power_level = true;
+        self
+    }
+
+    pub fn include_device_name(mut self) -> AdvertiseDataBuilder {
+        self.data.include_device_name = true;
+        self
+    }
+
+    /// Returns the data, or the first problem found in it.
+    pub fn build(self) -> BtResult<AdvertiseData> {
+        self.data.validate()?;
+        Ok(self.data)
+    }
+}
+
+/// Bytes taken by an AD structure of encoded advertise data.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdStructureCost {
+    pub ad_type: u8,
+    /// What the structure carries, such as `Manufacturer data 0x00e0`.
+    pub description: String,
+    /// Length of the structure, its length and AD type included.
+    pub length: i32,
+}
+
+/// Advertise data broken down into its AD structures, see
+/// `IBluetoothGatt::get_advertise_data_breakdown`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdvertiseDataBreakdown {
+    /// AD structures in the order they are sent.
+    pub structures: Vec<AdStructureCost>,
+    /// Length of the encoded data.
+    pub length: i32,
+    /// Bytes left in a legacy advertisement, negative if the data does not fit.
+    pub legacy_remaining: i32,
+    /// Bytes left in an extended advertisement, negative if the data does not fit or the
+    /// controller does not support extended advertising.
+    pub extended_remaining: i32,
+}
+
+impl AdvertiseDataBreakdown {
+    /// Breaks down advertise data encoded by `AdvertiseData::encode`. The Flags the stack adds to
+    /// the advertising data of connectable sets are counted if `connectable` is set.
+    pub(crate) fn new(bytes: &[u8], connectable: bool, caps: &AdvertisingCapabilities) -> Self {
+        let mut structures = vec![];
+        if connectable {
+            structures.push(AdStructureCost {
+                ad_type: AD_TYPE_FLAGS,
+                description: describe_ad_structure(AD_TYPE_FLAGS, &[]),
+                length: FLAGS_LEN as i32,
+            });
+        }
+
+        let mut rest = bytes;
+        while let Some((&len, tail)) = rest.split_first() {
+            let len = len as usize;
+            if len == 0 || len > tail.len() {
+                break;
+            }
+            let (structure, tail) = tail.split_at(len);
+            structures.push(AdStructureCost {
+                ad_type: structure[0],
+                description: describe_ad_structure(structure[0], &structure[1..]),
+                length: len as i32 + 1,
+            });
+            rest = tail;
+        }
+
+        let length: i32 = structures.iter().map(|s| s.length).sum();
+        let extended_max =
+            if caps.extended_advertising { caps.max_extended_data_len as i32 } else { 0 };
+        AdvertiseDataBreakdown {
+            structures,
+            length,
+            legacy_remaining: LEGACY_ADV_DATA_LEN_MAX as i32 - length,
+            extended_remaining: extended_max - length,
+        }
+    }
+}
+
+/// Describes an AD structure written by the stack from its type and payload.
+fn describe_ad_structure(ad_type: u8, payload: &[u8]) -> String {
+    // Hexadecimal form of the little-endian UUID or company identifier starting the payload.
+    let le_hex = |len: usize| -> String {
+        payload.iter().take(len).rev().map(|b| format!("{:02x}", b)).collect()
+    };
+
+    match ad_type {
+        AD_TYPE_FLAGS => String::from("Flags, added by the stack"),
+        AD_TYPE_SERVICE_UUIDS_16 => format!("Service UUIDs, {} of 16 bits", payload.len() / 2),
+        AD_TYPE_SERVICE_UUIDS_32 => format!("Service UUIDs, {} of 32 bits", payload.len() / 4),
+        AD_TYPE_SERVICE_UUIDS_128 => format!("Service UUIDs, {} of 128 bits", payload.len() / 16),
+        AD_TYPE_SOLICIT_UUIDS_16 => format!("Solicit UUIDs, {} of 16 bits", payload.len() / 2),
+        AD_TYPE_SOLICIT_UUIDS_32 => format!("Solicit UUIDs, {} of 32 bits", payload.len() / 4),
+        AD_TYPE_SOLICIT_UUIDS_128 => format!("Solicit UUIDs, {} of 128 bits", payload.len() / 16),
+        AD_TYPE_SERVICE_DATA_16 => format!("Service data 0x{}", le_hex(2)),
+        AD_TYPE_SERVICE_DATA_32 => format!("Service data 0x{}", le_hex(4)),
+        AD_TYPE_SERVICE_DATA_128 => format!("Service data 0x{}", le_hex(16)),
+        AD_TYPE_TRANSPORT_DISCOVERY_DATA => String::from("Transport discovery data"),
+        AD_TYPE_MANUFACTURER_DATA => format!("Manufacturer data 0x{}", le_hex(2)),
+        AD_TYPE_TX_POWER_LEVEL => String::from("TX power level"),
+        AD_TYPE_COMPLETE_LOCAL_NAME => String::from("Complete local name"),
+        _ => format!("AD type {:#04x}", ad_type),
+    }
+}
+
+/// Returns the shortest little-endian form of a UUID: 2, 4 or 16 bytes.
+pub(crate) fn uuid_to_le_bytes(uuid: &Uuid128Bit) -> Vec<u8> {
+    let len = if uuid[4..] != BASE_UUID[4..] {
+        16
+    } else if uuid[0..2] == [0, 0] {
+        2
+    } else {
+        4
+    };
+
+    if len == 16 {
+        uuid.iter().rev().cloned().collect()
+    } else {
+        uuid[4 - len..4].iter().rev().cloned().collect()
+    }
+}
+
+/// Appends one AD structure per UUID length, with the AD types of 16, 32 and 128-bit UUIDs.
+fn append_uuids(bytes: &mut Vec<u8>, uuids: &[BtUuid], ad_types: [u8; 3]) -> BtResult<()> {
+    for &(len, ad_type) in &[(2, ad_types[0]), (4, ad_types[1]), (16, ad_types[2])] {
+        let payload: Vec<u8> = uuids
+            .iter()
+            .map(|uuid| uuid_to_le_bytes(&uuid.uu))
+            .filter(|uuid| uuid.len() == len)
+            .flatten()
+            .collect();
+
+        if !payload.is_empty() {
+            append_ad_structure(bytes, ad_type, &payload)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn append_ad_structure(bytes: &mut Vec<u8>, ad_type: u8, payload: &[u8]) -> BtResult<()> {
+    if payload.len() + 2 > AD_STRUCTURE_LEN_MAX {
+        return Err(AdvertiseDataProblem::AdStructureTooLong.error(format!(
+            "AD structure of type {:#04x} is {} bytes long, more than the {} bytes allowed",
+            ad_type,
+            payload.len() + 2,
+            AD_STRUCTURE_LEN_MAX
+        )));
+    }
+
+    // The length covers the AD type and the payload.
+    bytes.push(payload.len() as u8 + 1);
+    bytes.push(ad_type);
+    bytes.extend_from_slice(payload);
+    Ok(())
+}
+
+/// Converts the duration, in 10 ms units, and the maximum number of extended advertising events of
+/// an advertising set to their HCI sizes.
+pub(crate) fn advertising_duration(duration: i32, max_ext_adv_events: i32) -> BtResult<(u16, u8)> {
+    let duration = u16::try_from(duration).map_err(|_| {
+        BtError::invalid_argument(format!("Invalid advertising duration {}", duration))
+    })?;
+    let max_ext_adv_events = u8::try_from(max_ext_adv_events).map_err(|_| {
+        BtError::invalid_argument(format!(
+            "Invalid maximum number of advertising events {}",
+            max_ext_adv_events
+        ))
+    })?;
+
+    Ok((duration, max_ext_adv_events))
+}
+
+/// Checks the interval between the address rotations of an advertising set, in milliseconds. 0
+/// stands for the default interval of the stack.
+pub(crate) fn address_rotation_interval(interval_ms: i32) -> BtResult<u32> {
+    let interval = Duration::from_millis(interval_ms.max(0) as u64);
+    if interval_ms != 0
+        && (interval_ms < 0
+            || interval < ADDRESS_ROTATION_INTERVAL_MIN
+            || interval > ADDRESS_ROTATION_INTERVAL_MAX)
+    {
+        return Err(BtError::invalid_argument(format!(
+            "Invalid address rotation interval {} ms",
+            interval_ms
+        )));
+    }
+
+    Ok(interval_ms as u32)
+}
+
+/// Interface for advertising set callbacks to clients, passed to
+/// `IBluetoothGatt::start_advertising_set`. The sets are counted against the limit of the peer
+/// owning the callback object, and stopped when it disconnects.
+pub trait IAdvertisingSetCallback: RPCProxy {
+    /// When the `start_advertising_set` request is done. `advertiser_id` identifies the set in
+    /// the other calls if `status` is `Success`. It stays the same while the set is suspended.
+    ///
+    /// Also called with the `reg_id` of the first start and a new `advertiser_id` each time a set
+    /// is re-created by a new daemon, see `IBluetoothGatt::set_advertising_set_restart_persistent`.
+    fn on_advertising_set_started(
+        &self,
+        reg_id: i32,
+        advertiser_id: i32,
+        tx_power: i32,
+        status: AdvertisingStatus,
+    );
+
+    /// The completion of `IBluetoothGatt::get_own_address`.
+    fn on_own_address_read(&self, advertiser_id: i32, address_type: i32, address: String);
+
+    /// When the random address of the set is rotated, see
+    /// `IBluetoothGatt::set_advertising_address_rotation_interval`.
+    fn on_own_address_changed(&self, advertiser_id: i32, address_type: i32, address: String);
+
+    /// When the set is stopped by `IBluetoothGatt::stop_advertising_set`.
+    fn on_advertising_set_stopped(&self, advertiser_id: i32);
+
+    /// The completion of `IBluetoothGatt::enable_advertising_set`.
+    fn on_advertising_enabled(&self, advertiser_id: i32, enable: bool, status: AdvertisingStatus);
+
+    /// When the controller stops advertising the set at the end of the `duration` or of the
+    /// `max_ext_adv_events` it was started or enabled with. The set stays started and can be
+    /// enabled again with `IBluetoothGatt::enable_advertising_set`.
+    fn on_advertising_set_terminated(
+        &self,
+        advertiser_id: i32,
+        reason: AdvertisingTerminationReason,
+    );
+
+    /// The completion of `IBluetoothGatt::set_advertising_data`.
+    fn on_advertising_data_set(&self, advertiser_id: i32, status: AdvertisingStatus);
+
+    /// The completion of `IBluetoothGatt::set_scan_response_data`.
+    fn on_scan_response_data_set(&self, advertiser_id: i32, status: AdvertisingStatus);
+
+    /// The completion of `IBluetoothGatt::set_advertising_parameters`.
+    fn on_advertising_parameters_updated(
+        &self,
+        advertiser_id: i32,
+        tx_power: i32,
+        status: AdvertisingStatus,
+    );
+
+    /// When the set gives its controller slot to another set. The set keeps its ID and accepts
+    /// the other calls, which take effect once it is resumed.
+    fn on_advertising_set_suspended(&self, advertiser_id: i32);
+
+    /// When a suspended set advertises again.
+    fn on_advertising_set_resumed(&self, advertiser_id: i32);
+
+    /// When a persistent set is started again after the controller was reset, see
+    /// `IBluetoothGatt::set_advertising_set_persistent` and
+    /// `IBluetoothGatt::set_advertising_set_restart_persistent`. The set keeps its advertiser ID
+    /// if `status` is `Success`, and is stopped otherwise.
+    fn on_advertising_set_restored(
+        &self,
+        advertiser_id: i32,
+        tx_power: i32,
+        status: AdvertisingStatus,
+    );
+
+    /// When the controller selects another TX power for the set, such as when the set is resumed
+    /// or its parameters are updated under a regulatory limit, see
+    /// `IBluetoothGatt::get_advertising_tx_power`.
+    fn on_tx_power_changed(&self, advertiser_id: i32, tx_power: i32);
+}
+
+/// Where an advertising set stands with the controller.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum AdvertisingSetState {
+    /// Waiting for the controller to start the set for the first time.
+    Starting,
+    /// Advertising from the controller slot with the given advertiser ID.
+    Active(u8),
+    /// Swapped out of the controller for another set.
+    Suspended,
+    /// Waiting for the controller to start the set again.
+    Resuming,
+    /// Lost by a reset of the controller, waiting for the adapter to be enabled again.
+    Restoring,
+}
+
+/// Advertising set started by a client.
+pub(crate) struct AdvertisingSet {
+    /// Also the ID of the set given to the client, since the advertiser ID of the controller
+    /// changes when the set is suspended and resumed.
+    pub reg_id: i32,
+    pub state: AdvertisingSetState,
+    /// Peer owning the callback, or the object ID of a local callback, which the limit of sets
+    /// applies to.
+    pub app_id: String,
+    pub parameters: AdvertisingSetParameters,
+    // Encoded data, kept to start the set again when it is resumed.
+    pub adv_data: Vec<u8>,
+    pub scan_rsp: Vec<u8>,
+    pub enabled: bool,
+    /// Duration, in 10 ms units, and maximum number of extended advertising events the set was
+    /// last enabled with, 0 for no limit. Given again to the controller each time the set gets a
+    /// slot.
+    pub duration: u16,
+    pub max_ext_adv_events: u8,
+    /// When the set last got or lost a controller slot.
+    pub since: Instant,
+    /// When the set was started by the client.
+    pub started: Instant,
+    pub callback: Box<dyn IAdvertisingSetCallback + Send>,
+    /// ID of the disconnect observer of the callback.
+    pub callback_id: u32,
+    /// TX power selected by the controller, in dBm. None until the set is started.
+    pub tx_power: Option<i32>,
+    pub tx_power_sweep: Option<TxPowerSweep>,
+    /// Interval between the rotations of the random address, in milliseconds, 0 for the default
+    /// of the stack. Applied again when the set is resumed.
+    pub address_rotation_interval_ms: u32,
+    /// Whether the set keeps advertising while the system is suspended and is restored after a
+    /// reset of the controller.
+    pub persistent: bool,
+    /// Whether the set is restored after a reset of the controller and saved for the next daemon
+    /// to re-create it.
+    pub restart_persistent: bool,
+    /// ID returned by `IBluetoothGatt::start_advertising_set`, reported with the new ID of the
+    /// set each time it is re-created.
+    pub client_reg_id: i32,
+}
+
+impl AdvertisingSet {
+    /// Returns the advertiser ID of the controller slot of the set, if it has one.
+    pub fn handle(&self) -> Option<u8> {
+        match self.state {
+            AdvertisingSetState::Active(handle) => Some(handle),
+            _ => None,
+        }
+    }
+
+    /// Records the TX power selected by the controller. Returns whether it differs from the one
+    /// selected before, if any.
+    pub fn update_tx_power(&mut self, tx_power: i32) -> bool {
+        let changed = self.tx_power.map_or(false, |previous| previous != tx_power);
+        self.tx_power = Some(tx_power);
+        changed
+    }
+}
+
+/// TX power sweep of an advertising set, started with `IBluetoothQA::start_tx_power_sweep`.
+pub(crate) struct TxPowerSweep {
+    levels: Vec<i32>,
+    next: usize,
+    pub dwell: Duration,
+    /// Pending step to the next level, cancelled when the sweep is dropped.
+    pub step: Option<JoinHandle<()>>,
+}
+
+impl TxPowerSweep {
+    /// Sweeps from `min_level` to `max_level` dBm by `step` dB, staying `dwell_ms` at each level.
+    pub fn new(min_level: i32, max_level: i32, step: i32, dwell_ms: i32) -> BtResult<Self> {
+        if min_level < TX_POWER_MIN || max_level > TX_POWER_MAX || min_level > max_level {
+            return Err(BtError::invalid_argument(format!(
+                "Invalid TX power range {} to {} dBm",
+                min_level, max_level
+            )));
+        }
+        if step <= 0 {
+            return Err(BtError::invalid_argument(format!("Invalid TX power step {}", step)));
+        }
+
+        let dwell = Duration::from_millis(dwell_ms.max(0) as u64);
+        if dwell < TX_POWER_SWEEP_DWELL_MIN {
+            return Err(BtError::invalid_argument(format!(
+                "TX power sweep dwell of {} ms is shorter than {} ms",
+                dwell_ms,
+                TX_POWER_SWEEP_DWELL_MIN.as_millis()
+            )));
+        }
+
+        // The highest level is always part of the sweep, even if the step overshoots it.
+        let mut levels: Vec<i32> = (min_level..=max_level).step_by(step as usize).collect();
+        if levels.last() != Some(&max_level) {
+            levels.push(max_level);
+        }
+
+        Ok(TxPowerSweep { levels, next: 0, dwell, step: None })
+    }
+
+    /// Returns the level to apply next, starting over from the lowest one after the highest.
+    pub fn next_level(&mut self) -> i32 {
+        let level = self.levels[self.next];
+        self.next = (self.next + 1) % self.levels.len();
+        level
+    }
+
+    /// Returns the level applied last.
+    pub fn level(&self) -> i32 {
+        self.levels[(self.next + self.levels.len() - 1) % self.levels.len()]
+    }
+}
+
+impl Drop for TxPowerSweep {
+    fn drop(&mut self) {
+        if let Some(step) = self.step.take() {
+            step.abort();
+        }
+    }
+}
+
+/// File in which the advertising sets persistent across restarts are saved.
+pub(crate) const SAVED_ADVERTISING_SETS_FILE: &str = "/var/lib/bluetooth/advertising_sets";
+
+/// Hook finding the callbacks of the advertising sets saved by a previous daemon, which only the
+/// D-Bus frontend can reach again. Set with `BluetoothGatt::set_advertising_callback_restorer`,
+/// the saved sets are dropped otherwise.
+pub trait IAdvertisingCallbackRestorer {
+    /// Returns the callback object `object_id` of the D-Bus peer `remote_id`, the unique bus name
+    /// of its connection, or None if the peer is gone.
+    fn restore_callback(
+        &self,
+        remote_id: &String,
+        object_id: &String,
+    ) -> Option<Box<dyn IAdvertisingSetCallback + Send>>;
+}
+
+/// Advertising set persistent across restarts, as saved for the next daemon.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SavedAdvertisingSet {
+    /// Peer and object path of the callback of the set.
+    pub remote_id: String,
+    pub object_id: String,
+    pub client_reg_id: i32,
+    pub parameters: AdvertisingSetParameters,
+    pub adv_data: Vec<u8>,
+    pub scan_rsp: Vec<u8>,
+    pub enabled: bool,
+    pub duration: u16,
+    pub max_ext_adv_events: u8,
+    pub address_rotation_interval_ms: u32,
+    pub persistent: bool,
+}
+
+impl SavedAdvertisingSet {
+    /// Returns the record of `set`, None if its callback is local to this daemon.
+    pub fn from_set(set: &AdvertisingSet) -> Option<Self> {
+        let remote_id = set.callback.get_remote_id();
+        if remote_id.is_empty() {
+            return None;
+        }
+
+        Some(SavedAdvertisingSet {
+            remote_id,
+            object_id: set.callback.get_object_id(),
+            client_reg_id: set.client_reg_id,
+            parameters: set.parameters.clone(),
+            adv_data: set.adv_data.clone(),
+            scan_rsp: set.scan_rsp.clone(),
+            enabled: set.enabled,
+            duration: set.duration,
+            max_ext_adv_events: set.max_ext_adv_events,
+            address_rotation_interval_ms: set.address_rotation_interval_ms,
+            persistent: set.persistent,
+        })
+    }
+
+    /// Encodes the record as a line of JSON.
+    pub fn to_line(&self) -> String {
+        let p = &self.parameters;
+        json!({
+            "remote_id": self.remote_id,
+            "object_id": self.object_id,
+            "client_reg_id": self.client_reg_id,
+            "connectable": p.connectable,
+            "scannable": p.scannable,
+            "is_legacy": p.is_legacy,
+            "is_anonymous": p.is_anonymous,
+            "include_tx_power": p.include_tx_power,
+            "primary_phy": p.primary_phy.to_u8(),
+            "secondary_phy": p.secondary_phy.to_u8(),
+            "interval": p.interval,
+            "tx_power_level": p.tx_power_level,
+            "own_address_type": p.own_address_type,
+            "adv_data": to_hex(&self.adv_data),
+            "scan_rsp": to_hex(&self.scan_rsp),
+            "enabled": self.enabled,
+            "duration": self.duration,
+            "max_ext_adv_events": self.max_ext_adv_events,
+            "address_rotation_interval_ms": self.address_rotation_interval_ms,
+            "persistent": self.persistent,
+        })
+        .to_string()
+    }
+
+    /// Decodes a line written by `to_line`, None if it is malformed.
+    pub fn from_line(line: &str) -> Option<Self> {
+        let value: Value = serde_json::from_str(line).ok()?;
+        let string = |key: &str| value.get(key)?.as_str().map(String::from);
+        let flag = |key: &str| value.get(key)?.as_bool();
+        let number = |key: &str| value.get(key)?.as_i64();
+        let phy = |key: &str| LePhy::from_i64(number(key)?);
+
+        Some(SavedAdvertisingSet {
+            remote_id: string("remote_id")?,
+            object_id: string("object_id")?,
+            client_reg_id: i32::try_from(number("client_reg_id")?).ok()?,
+            parameters: AdvertisingSetParameters {
+                connectable: flag("connectable")?,
+                scannable: flag("scannable")?,
+                is_legacy: flag("is_legacy")?,
+                is_anonymous: flag("is_anonymous")?,
+                include_tx_power: flag("include_tx_power")?,
+                primary_phy: phy("primary_phy")?,
+                secondary_phy: phy("secondary_phy")?,
+                interval: i32::try_from(number("interval")?).ok()?,
+                tx_power_level: i32::try_from(number("tx_power_level")?).ok()?,
+                own_address_type: i32::try_from(number("own_address_type")?).ok()?,
+            },
+            adv_data: from_hex(&string("adv_data")?)?,
+            scan_rsp: from_hex(&string("scan_rsp")?)?,
+            enabled: flag("enabled")?,
+            duration: u16::try_from(number("duration")?).ok()?,
+            max_ext_adv_events: u8::try_from(number("max_ext_adv_events")?).ok()?,
+            address_rotation_interval_ms: u32::try_from(number("address_rotation_interval_ms")?)
+                .ok()?,
+            persistent: flag("persistent")?,
+        })
+    }
+}
+
+/// Loads the advertising sets saved in `path`, skipping the malformed lines.
+pub(crate) fn load_saved_advertising_sets(path: &Path) -> Vec<SavedAdvertisingSet> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return vec![],
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| {
+            let set = SavedAdvertisingSet::from_line(line);
+            if set.is_none() {
+                warn!("Skipping malformed saved advertising set in {}", path.display());
+            }
+            set
+        })
+        .collect()
+}
+
+/// Saves `sets` in `path`, replacing the sets saved before.
+pub(crate) fn save_advertising_sets(path: &Path, sets: &[SavedAdvertisingSet]) {
+    let lines: Vec<String> = sets.iter().map(SavedAdvertisingSet::to_line).collect();
+    if let Err(e) = save_lines(path, &lines, PUBLIC_FILE_MODE) {
+        warn!("Failed to save the advertising sets to {}: {}", path.display(), e);
+    }
+}
+
+/// Returns the index of the set holding a controller slot for the longest time.
+pub(crate) fn longest_active_set(sets: &[AdvertisingSet]) -> Option<usize> {
+    sets.iter()
+        .enumerate()
+        .filter(|(_, s)| s.handle().is_some())
+        .min_by_key(|(_, s)| s.since)
+        .map(|(i, _)| i)
+}
+
+/// Returns the index of the set suspended for the longest time.
+pub(crate) fn longest_suspended_set(sets: &[AdvertisingSet]) -> Option<usize> {
+    sets.iter()
+        .enumerate()
+        .filter(|(_, s)| s.state == AdvertisingSetState::Suspended)
+        .min_by_key(|(_, s)| s.since)
+        .map(|(i, _)| i)
+}
+
+/// Keeps the sets restored after a reset of the controller, the persistent ones already started,
+/// and marks them `Restoring`. Returns the other sets, which are lost.
+pub(crate) fn keep_restorable_sets(sets: &mut Vec<AdvertisingSet>) -> Vec<AdvertisingSet> {
+    let (restorable, lost): (Vec<AdvertisingSet>, Vec<AdvertisingSet>) =
+        sets.drain(..).partition(|s| {
+            (s.persistent || s.restart_persistent) && s.state != AdvertisingSetState::Starting
+        });
+    *sets = restorable;
+    for set in sets.iter_mut() {
+        set.state = AdvertisingSetState::Restoring;
+    }
+    lost
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use num_traits::cast::FromPrimitive;
+
+    fn extended_caps() -> AdvertisingCapabilities {
+        AdvertisingCapabilities {
+            extended_advertising: true,
+            le_2m_phy: true,
+            le_coded_phy: false,
+            max_extended_data_len: 1650,
+        }
+    }
+
+    struct TestAdvertisingSetCallback {}
+
+    impl IAdvertisingSetCallback for TestAdvertisingSetCallback {
+        fn on_advertising_set_started(
+            &self,
+            _reg_id: i32,
+            _advertiser_id: i32,
+            _tx_power: i32,
+            _status: AdvertisingStatus,
+        ) {
+        }
+        fn on_own_address_read(&self, _advertiser_id: i32, _address_type: i32, _address: String) {}
+        fn on_own_address_changed(
+            &self,
+            _advertiser_id: i32,
+            _address_type: i32,
+            _address: String,
+        ) {
+        }
+        fn on_advertising_set_stopped(&self, _advertiser_id: i32) {}
+        fn on_advertising_enabled(
+            &self,
+            _advertiser_id: i32,
+            _enable: bool,
+            _status: AdvertisingStatus,
+        ) {
+        }
+        fn on_advertising_set_terminated(
+            &self,
+            _advertiser_id: i32,
+            _reason: AdvertisingTerminationReason,
+        ) {
+        }
+        fn on_advertising_data_set(&self, _advertiser_id: i32, _status: AdvertisingStatus) {}
+        fn on_scan_response_data_set(&self, _advertiser_id: i32, _status: AdvertisingStatus) {}
+        fn on_advertising_parameters_updated(
+            &self,
+            _advertiser_id: i32,
+            _tx_power: i32,
+            _status: AdvertisingStatus,
+        ) {
+        }
+        fn on_advertising_set_suspended(&self, _advertiser_id: i32) {}
+        fn on_advertising_set_resumed(&self, _advertiser_id: i32) {}
+        fn on_advertising_set_restored(
+            &self,
+            _advertiser_id: i32,
+            _tx_power: i32,
+            _status: AdvertisingStatus,
+        ) {
+        }
+        fn on_tx_power_changed(&self, _advertiser_id: i32, _tx_power: i32) {}
+    }
+
+    impl RPCProxy for TestAdvertisingSetCallback {
+        fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+            0
+        }
+
+        fn get_object_id(&self) -> String {
+            String::from("")
+        }
+
+        fn unregister(&mut self, _id: u32) -> bool {
+            false
+        }
+
+        fn export_for_rpc(self: Box<Self>) {}
+    }
+
+    fn test_set(reg_id: i32, state: AdvertisingSetState, since: Instant) -> AdvertisingSet {
+        AdvertisingSet {
+            reg_id,
+            state,
+            app_id: String::from(""),
+            parameters: legacy_params(),
+            adv_data: vec![],
+            scan_rsp: vec![],
+            enabled: true,
+            duration: 0,
+            max_ext_adv_events: 0,
+            since,
+            started: since,
+            callback: Box::new(TestAdvertisingSetCallback {}),
+            callback_id: 0,
+            tx_power: None,
+            tx_power_sweep: None,
+            address_rotation_interval_ms: 0,
+            persistent: false,
+            restart_persistent: false,
+            client_reg_id: reg_id,
+        }
+    }
+
+    fn legacy_params() -> AdvertisingSetParameters {
+        AdvertisingSetParameters {
+            connectable: true,
+            scannable: true,
+            is_legacy: true,
+            primary_phy: LePhy::Phy1m,
+            secondary_phy: LePhy::Phy1m,
+            interval: INTERVAL_MIN,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_encode_advertise_data() {
+        let mut data = AdvertiseData {
+            service_uuids: vec![
+                BtUuid::from_string("180f").unwrap(),
+                BtUuid::from_string("12345678").unwrap(),
+            ],
+            include_tx_power_level: true,
+            include_device_name: true,
+            ..Default::default()
+        };
+        data.manufacturer_data.insert(0x00E0, vec![1, 2]);
+        data.service_data.insert(BtUuid::from_string("fe2c").unwrap(), vec![3]);
+
+        assert_eq!(
+            vec![
+                3, 0x03, 0x0F, 0x18, // 16-bit service UUIDs
+                5, 0x05, 0x78, 0x56, 0x34, 0x12, // 32-bit service UUIDs
+                4, 0x16, 0x2C, 0xFE, 3, // Service data
+                5, 0xFF, 0xE0, 0x00, 1, 2, // Manufacturer data
+                2, 0x0A, 0xF6, // TX power level
+                3, 0x09, b'a', b'b', // Complete local name
+            ],
+            data.encode("ab", -10).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encode_transport_discovery_data() {
+        let tds = TransportDiscoveryData {
+            transport_blocks: vec![
+                TransportBlock {
+                    organization_id: 0x01,
+                    role: TdsRole::ProviderOnly,
+                    transport_data_incomplete: true,
+                    transport_state: TdsTransportState::On,
+                    transport_data: vec![0xAA, 0xBB],
+                },
+                TransportBlock { organization_id: 0x02, ..Default::default() },
+            ],
+        };
+        let data = AdvertiseDataBuilder::new().transport_discovery_data(tds).build().unwrap();
+
+        assert_eq!(
+            vec![9, 0x26, 0x01, 0x0E, 2, 0xAA, 0xBB, 0x02, 0x00, 0],
+            data.encode("", 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_validate_advertise_data() {
+        let problem = |result: BtResult<AdvertiseData>| {
+            let e = result.unwrap_err();
+            assert_eq!(BtErrorCategory::InvalidAdvertiseData, e.category);
+            AdvertiseDataProblem::from_u32(e.sub_code).unwrap()
+        };
+        let uuid = BtUuid::from_string("180f").unwrap();
+
+        assert!(AdvertiseDataBuilder::new()
+            .service_uuid(uuid)
+            .solicit_uuid(uuid)
+            .service_data(uuid, vec![1])
+            .build()
+            .is_ok());
+        assert_eq!(
+            AdvertiseDataProblem::DuplicateUuid,
+            problem(AdvertiseDataBuilder::new().service_uuid(uuid).service_uuid(uuid).build())
+        );
+
+        // The forms of a UUID are the same service data key.
+        let data = AdvertiseDataBuilder::new()
+            .service_data(uuid, vec![1])
+            .service_data(
+                BtUuid::from_string("0000180F-0000-1000-8000-00805F9B34FB").unwrap(),
+                vec![2],
+            )
+            .build()
+            .unwrap();
+        assert_eq!(Some(&vec![2]), data.service_data.get(&uuid));
+        assert_eq!(1, data.service_data.len());
+
+        assert_eq!(
+            AdvertiseDataProblem::EmptyTransportDiscoveryData,
+            problem(
+                AdvertiseDataBuilder::new()
+                    .transport_discovery_data(TransportDiscoveryData::default())
+                    .build()
+            )
+        );
+        let reserved = TransportDiscoveryData { transport_blocks: vec![TransportBlock::default()] };
+        assert_eq!(
+            AdvertiseDataProblem::ReservedOrganizationId,
+            problem(AdvertiseDataBuilder::new().transport_discovery_data(reserved).build())
+        );
+    }
+
+    #[test]
+    fn test_encode_for_set() {
+        let caps = extended_caps();
+        let legacy = legacy_params();
+        let problem = |result: BtResult<Vec<u8>>| {
+            AdvertiseDataProblem::from_u32(result.unwrap_err().sub_code).unwrap()
+        };
+
+        // 24 bytes of manufacturer data, 4 left of the 28 bytes of a connectable set.
+        let data = AdvertiseDataBuilder::new().manufacturer_data(1, vec![0; 20]).build().unwrap();
+        assert!(data.encode_for_set(&legacy, &caps, "", false).is_ok());
+
+        let named = AdvertiseData { include_device_name: true, ..data.clone() };
+        assert!(named.encode_for_set(&legacy, &caps, "ab", false).is_ok());
+        assert_eq!(
+            AdvertiseDataProblem::DeviceNameDoesNotFit,
+            problem(named.encode_for_set(&legacy, &caps, "abc", false))
+        );
+
+        let long = AdvertiseDataBuilder::new().manufacturer_data(1, vec![0; 25]).build().unwrap();
+        assert_eq!(
+            AdvertiseDataProblem::DataTooLong,
+            problem(long.encode_for_set(&legacy, &caps, "", false))
+        );
+        let long_named = AdvertiseData { include_device_name: true, ..long };
+        assert_eq!(
+            AdvertiseDataProblem::DataTooLong,
+            problem(long_named.encode_for_set(&legacy, &caps, "ab", false))
+        );
+
+        let non_scannable = AdvertisingSetParameters { scannable: false, ..legacy };
+        assert_eq!(
+            AdvertiseDataProblem::ScanResponseNotScannable,
+            problem(data.encode_for_set(&non_scannable, &caps, "", true))
+        );
+    }
+
+    #[test]
+    fn test_encode_rejects_long_ad_structure() {
+        let mut data = AdvertiseData::default();
+        data.manufacturer_data.insert(1, vec![0; AD_STRUCTURE_LEN_MAX - 4]);
+        assert_eq!(AD_STRUCTURE_LEN_MAX, data.encode("", 0).unwrap().len());
+
+        data.manufacturer_data.insert(1, vec![0; AD_STRUCTURE_LEN_MAX - 3]);
+        assert!(data.encode("", 0).is_err());
+    }
+
+    #[test]
+    fn test_max_data_len() {
+        let caps = extended_caps();
+        let legacy = legacy_params();
+        assert_eq!(28, legacy.max_data_len(&caps, false));
+        assert_eq!(31, legacy.max_data_len(&caps, true));
+        assert!(legacy.validate_data_len(&caps, 28, false).is_ok());
+        assert!(legacy.validate_data_len(&caps, 29, false).is_err());
+        assert!(legacy.validate_data_len(&caps, 31, true).is_ok());
+
+        let extended =
+            AdvertisingSetParameters { connectable: false, is_legacy: false, ..legacy_params() };
+        assert_eq!(1650, extended.max_data_len(&caps, false));
+        assert!(extended.validate_data_len(&caps, 1651, false).is_err());
+
+        let non_scannable = AdvertisingSetParameters { scannable: false, ..extended };
+        assert!(non_scannable.validate_data_len(&caps, 0, true).is_ok());
+        assert!(non_scannable.validate_data_len(&caps, 1, true).is_err());
+    }
+
+    #[test]
+    fn test_advertise_data_breakdown() {
+        let mut data = AdvertiseData {
+            service_uuids: vec![BtUuid::from_string("180f").unwrap()],
+            include_device_name: true,
+            ..Default::default()
+        };
+        data.manufacturer_data.insert(0x00E0, vec![1, 2]);
+
+        let bytes = data.encode("ab", 0).unwrap();
+        let breakdown = AdvertiseDataBreakdown::new(&bytes, true, &extended_caps());
+        assert_eq!(
+            vec![
+                (AD_TYPE_FLAGS, "Flags, added by the stack", 3),
+                (AD_TYPE_SERVICE_UUIDS_16, "Service UUIDs, 1 of 16 bits", 4),
+                (AD_TYPE_MANUFACTURER_DATA, "Manufacturer data 0x00e0", 6),
+                (AD_TYPE_COMPLETE_LOCAL_NAME, "Complete local name", 4),
+            ],
+            breakdown
+                .structures
+                .iter()
+                .map(|s| (s.ad_type, s.description.as_str(), s.length))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(17, breakdown.length);
+        assert_eq!(14, breakdown.legacy_remaining);
+        assert_eq!(1633, breakdown.extended_remaining);
+
+        data.manufacturer_data.insert(0x00E0, vec![0; 20]);
+        let bytes = data.encode("ab", 0).unwrap();
+        let breakdown =
+            AdvertiseDataBreakdown::new(&bytes, false, &AdvertisingCapabilities::default());
+        assert_eq!(32, breakdown.length);
+        assert_eq!(-1, breakdown.legacy_remaining);
+        assert_eq!(-32, breakdown.extended_remaining);
+    }
+
+    #[test]
+    fn test_validate_parameters() {
+        let caps = extended_caps();
+        assert!(legacy_params().validate(&caps).is_ok());
+        // The interval is refused rather than adjusted.
+        for interval in [0, INTERVAL_MIN - 1, INTERVAL_MAX] {
+            assert!(AdvertisingSetParameters { interval, ..legacy_params() }
+                .validate(&caps)
+                .is_err());
+        }
+        assert!(AdvertisingSetParameters { primary_phy: LePhy::Phy2m, ..legacy_params() }
+            .validate(&caps)
+            .is_err());
+
+        let extended = AdvertisingSetParameters {
+            is_legacy: false,
+            scannable: false,
+            secondary_phy: LePhy::Phy2m,
+            ..legacy_params()
+        };
+        assert!(extended.validate(&caps).is_ok());
+        assert!(extended.validate(&AdvertisingCapabilities::default()).is_err());
+        assert!(AdvertisingSetParameters { scannable: true, ..extended.clone() }
+            .validate(&caps)
+            .is_err());
+        assert!(AdvertisingSetParameters { primary_phy: LePhy::PhyCoded, ..extended }
+            .validate(&caps)
+            .is_err());
+    }
+
+    #[test]
+    fn test_advertise_parameters_conversion() {
+        let params: AdvertiseParameters =
+            AdvertisingSetParameters { interval: 0, tx_power_level: -200, ..legacy_params() }
+                .into();
+        assert_eq!(
+            ADV_PROP_CONNECTABLE | ADV_PROP_SCANNABLE | ADV_PROP_LEGACY,
+            params.advertising_event_properties
+        );
+        assert_eq!(INTERVAL_MIN as u32, params.min_interval);
+        assert_eq!(i8::MIN, params.tx_power);
+    }
+
+    #[test]
+    fn test_rotation_candidates() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(1);
+        let mut sets = vec![
+            test_set(0, AdvertisingSetState::Active(1), later),
+            test_set(1, AdvertisingSetState::Suspended, later),
+            test_set(2, AdvertisingSetState::Active(2), now),
+            test_set(3, AdvertisingSetState::Resuming, now),
+            test_set(4, AdvertisingSetState::Suspended, now),
+        ];
+        assert_eq!(Some(2), longest_active_set(&sets));
+        assert_eq!(Some(4), longest_suspended_set(&sets));
+
+        sets.retain(|s| s.reg_id < 2);
+        assert_eq!(Some(0), longest_active_set(&sets));
+        assert_eq!(Some(1), longest_suspended_set(&sets));
+
+        sets.clear();
+        assert_eq!(None, longest_active_set(&sets));
+        assert_eq!(None, longest_suspended_set(&sets));
+    }
+
+    #[test]
+    fn test_restorable_sets() {
+        let now = Instant::now();
+        let mut persistent = test_set(0, AdvertisingSetState::Active(1), now);
+        persistent.persistent = true;
+        let mut restart_persistent = test_set(1, AdvertisingSetState::Suspended, now);
+        restart_persistent.restart_persistent = true;
+        let mut starting = test_set(2, AdvertisingSetState::Starting, now);
+        starting.persistent = true;
+        let mut sets = vec![
+            persistent,
+            test_set(3, AdvertisingSetState::Active(2), now),
+            restart_persistent,
+            starting,
+        ];
+
+        // The persistent sets not started yet are lost, their start failing.
+        let lost = keep_restorable_sets(&mut sets);
+        assert_eq!(vec![3, 2], lost.iter().map(|s| s.reg_id).collect::<Vec<i32>>());
+        assert_eq!(vec![0, 1], sets.iter().map(|s| s.reg_id).collect::<Vec<i32>>());
+        assert!(sets.iter().all(|s| s.state == AdvertisingSetState::Restoring));
+
+        assert!(keep_restorable_sets(&mut sets).is_empty());
+        assert_eq!(2, sets.len());
+    }
+
+    #[test]
+    fn test_tx_power_sweep() {
+        let mut sweep = TxPowerSweep::new(-21, 1, 8, 500).unwrap();
+        let levels: Vec<i32> = (0..6).map(|_| sweep.next_level()).collect();
+        assert_eq!(vec![-21, -13, -5, 1, -21, -13], levels);
+        assert_eq!(-13, sweep.level());
+        assert_eq!(Duration::from_millis(500), sweep.dwell);
+
+        let mut sweep = TxPowerSweep::new(0, 0, 1, 100).unwrap();
+        assert_eq!(0, sweep.next_level());
+        assert_eq!(0, sweep.next_level());
+
+        assert!(TxPowerSweep::new(-200, 0, 1, 100).is_err());
+        assert!(TxPowerSweep::new(10, 0, 1, 100).is_err());
+        assert!(TxPowerSweep::new(-10, 0, 0, 100).is_err());
+        assert!(TxPowerSweep::new(-10, 0, 1, 50).is_err());
+    }
+
+    #[test]
+    fn test_update_tx_power() {
+        let mut set = test_set(1, AdvertisingSetState::Starting, Instant::now());
+        // The power selected when the set starts is not a change.
+        assert!(!set.update_tx_power(-7));
+        assert!(!set.update_tx_power(-7));
+        assert!(set.update_tx_power(-10));
+        assert_eq!(Some(-10), set.tx_power);
+    }
+
+    #[test]
+    fn test_address_rotation_interval() {
+        assert_eq!(0, address_rotation_interval(0).unwrap());
+        assert_eq!(1000, address_rotation_interval(1000).unwrap());
+        assert_eq!(3_600_000, address_rotation_interval(3_600_000).unwrap());
+        assert!(address_rotation_interval(999).is_err());
+        assert!(address_rotation_interval(3_600_001).is_err());
+        assert!(address_rotation_interval(-1).is_err());
+    }
+
+    #[test]
+    fn test_termination_reason() {
+        assert_eq!(
+            Some(AdvertisingTerminationReason::DurationExpired),
+            AdvertisingTerminationReason::from_status(0x3C)
+        );
+        assert_eq!(
+            Some(AdvertisingTerminationReason::MaxEventsReached),
+            AdvertisingTerminationReason::from_status(0x43)
+        );
+        assert_eq!(None, AdvertisingTerminationReason::from_status(0));
+        assert_eq!(
+            None,
+            AdvertisingTerminationReason::from_status(AdvertisingStatus::InternalError as u8)
+        );
+    }
+
+    fn saved_set() -> SavedAdvertisingSet {
+        SavedAdvertisingSet {
+            remote_id: String::from(":1.42"),
+            object_id: String::from("/org/example/adv0"),
+            client_reg_id: 3,
+            parameters: AdvertisingSetParameters {
+                is_legacy: false,
+                secondary_phy: LePhy::Phy2m,
+                tx_power_level: -7,
+                own_address_type: 1,
+                ..legacy_params()
+            },
+            adv_data: vec![0x02, 0x01, 0x06],
+            scan_rsp: vec![],
+            enabled: true,
+            duration: 500,
+            max_ext_adv_events: 20,
+            address_rotation_interval_ms: 60_000,
+            persistent: true,
+        }
+    }
+
+    #[test]
+    fn test_saved_advertising_set_line() {
+        let set = saved_set();
+        assert_eq!(Some(set.clone()), SavedAdvertisingSet::from_line(&set.to_line()));
+
+        assert_eq!(None, SavedAdvertisingSet::from_line("{}"));
+        assert_eq!(None, SavedAdvertisingSet::from_line("not json"));
+        let line = set.to_line().replace("\"duration\":500", "\"duration\":70000");
+        assert_eq!(None, SavedAdvertisingSet::from_line(&line));
+    }
+
+    #[test]
+    fn test_local_sets_not_saved() {
+        // The test callback has no remote ID, like the callbacks of the daemon itself.
+        let set = test_set(1, AdvertisingSetState::Active(0), Instant::now());
+        assert_eq!(None, SavedAdvertisingSet::from_set(&set));
+    }
+
+    #[test]
+    fn test_advertising_sets_saved() {
+        let path =
+            std::env::temp_dir().join(format!("btstack-advertising-sets-{}", std::process::id()));
+        assert!(load_saved_advertising_sets(&path).is_empty());
+
+        let mut other = saved_set();
+        other.client_reg_id = 4;
+        other.enabled = false;
+        save_advertising_sets(&path, &[saved_set(), other.clone()]);
+        assert_eq!(vec![saved_set(), other.clone()], load_saved_advertising_sets(&path));
+
+        // A malformed line does not lose the other sets.
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, format!("garbage\n{}", contents)).unwrap();
+        assert_eq!(vec![saved_set(), other], load_saved_advertising_sets(&path));
+
+        save_advertising_sets(&path, &[]);
+        assert!(load_saved_advertising_sets(&path).is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
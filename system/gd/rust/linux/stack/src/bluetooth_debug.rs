@@ -0,0 +1,237 @@
+//! Debug API, to collect the state of the stack for crash reports and support tickets, and to
+//! watch the responsiveness of the controller.
+
+use bt_topshim::snoop::{self, SnoopLogMode};
+
+use log::{info, warn};
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::Sender;
+
+use crate::bluetooth::Bluetooth;
+use crate::bluetooth_gatt::BluetoothGatt;
+use crate::state_snapshot::{Redaction, StateSnapshot};
+use crate::storage::{save_lines, PUBLIC_FILE_MODE};
+use crate::{Message, RPCProxy};
+
+/// File keeping the mode of the snoop log across restarts of the daemon, the system properties
+/// the snoop logger reads its mode from being kept in memory only on Linux.
+pub const SNOOP_LOG_MODE_FILE: &str = "/var/lib/bluetooth/snoop_log_mode";
+
+/// Defines the debug API. The whole API is privileged, as the snapshots and the snoop logs expose
+/// the devices and their traffic.
+pub trait IBluetoothDebug {
+    /// Returns a snapshot of the state of the stack as a JSON object: the adapter and its known
+    /// devices, the GATT connections with their parameters, the scanners, the advertising sets,
+    /// the recent errors and the depths of the queues.
+    ///
+    /// With `redact`, the addresses are replaced by hashes and the device names are left out.
+    /// The hashes are salted per daemon instance, so they only match within the snapshots taken
+    /// from the same instance.
+    fn get_state_snapshot(&self, redact: bool) -> String;
+
+    /// Returns the counters of the activity of the stack since the daemon started, by name:
+    /// `gatt.operation.<operation>` and `gatt.failure.<operation>` for the GATT operations,
+    /// `gatt.connection` and `gatt.connection_failure.<status>` for the connections,
+    /// `scan.session.<priority>` for the scans started, `adv.start_failure.<status>` for the
+    /// advertising sets failing to start and `adv.set_lifetime.{count,total_ms,max_ms}` for the
+    /// advertising sets stopped. The counters are also logged every 30 minutes.
+    ///
+    /// The latencies of the HCI commands, from the command to the event completing it, are
+    /// counted in histograms per opcode group: `hci.latency.<group>.le_<bound>ms` counts the
+    /// commands completed within `bound` milliseconds but not within the bound below, and
+    /// `hci.latency.<group>.gt_1000ms` the slower ones.
+    fn get_counters(&self) -> HashMap<String, u64>;
+
+    /// Adds an observer of the debug events.
+    fn register_callback(&mut self, callback: Box<dyn IBluetoothDebugCallback + Send>) -> u32;
+
+    /// Removes an observer of the debug events.
+    ///
+    /// Returns false if `callback_id` is not recognized.
+    fn unregister_callback(&mut self, callback_id: u32) -> bool;
+
+    /// Sets the latency of the HCI commands above which the controller is considered sluggish,
+    /// 500 milliseconds by default. 0 disables the alerts.
+    fn set_hci_latency_threshold(&mut self, threshold_ms: u32);
+
+    /// Sets the mode of the HCI snoop log. The mode takes effect right away, the log file being
+    /// re-opened, and is kept across restarts of the daemon. As when the stack starts, the log
+    /// files the new mode does not write are deleted, so that disabling the log removes the
+    /// captures.
+    fn set_snoop_log_mode(&mut self, mode: SnoopLogMode);
+
+    /// Returns the mode of the HCI snoop log.
+    fn get_snoop_log_mode(&self) -> SnoopLogMode;
+}
+
+/// Debug events.
+pub trait IBluetoothDebugCallback: RPCProxy {
+    /// When an HCI command took longer than the threshold to complete, while the controller was
+    /// responsive.
+    fn on_controller_sluggish(&self, opcode: u16, latency_ms: u32);
+
+    /// When the HCI commands complete within the threshold again after the controller was
+    /// sluggish.
+    fn on_controller_recovered(&self, opcode: u16, latency_ms: u32);
+}
+
+/// Implementation of the debug API.
+pub struct BluetoothDebug {
+    tx: Sender<Message>,
+    adapter: Option<Arc<Mutex<Box<Bluetooth>>>>,
+    gatt: Option<Arc<Mutex<Box<BluetoothGatt>>>>,
+    callbacks: HashMap<u32, Box<dyn IBluetoothDebugCallback + Send>>,
+    redaction_salt: u64,
+}
+
+impl BluetoothDebug {
+    pub fn new(tx: Sender<Message>) -> BluetoothDebug {
+        BluetoothDebug {
+            tx,
+            adapter: None,
+            gatt: None,
+            callbacks: HashMap::new(),
+            redaction_salt: RandomState::new().build_hasher().finish(),
+        }
+    }
+
+    pub fn set_adapter(&mut self, adapter: Arc<Mutex<Box<Bluetooth>>>) {
+        self.adapter = Some(adapter);
+    }
+
+    pub fn set_gatt(&mut self, gatt: Arc<Mutex<Box<BluetoothGatt>>>) {
+        self.gatt = Some(gatt);
+    }
+
+    /// Applies the mode of the snoop log saved by `set_snoop_log_mode`, if any. Called before the
+    /// stack starts, so that the snoop logger opens the log in that mode.
+    pub fn restore_snoop_log_mode(&self) {
+        if let Some(mode) = load_snoop_log_mode(Path::new(SNOOP_LOG_MODE_FILE)) {
+            info!("Restoring snoop log mode {:?}", mode);
+            snoop::set_snoop_log_mode(mode);
+        }
+    }
+
+    pub(crate) fn remove_callback(&mut self, id: u32) -> bool {
+        match self.callbacks.get_mut(&id) {
+            Some(callback) => {
+                callback.unregister(id);
+                self.callbacks.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn hci_latency_alert(&self, opcode: u16, latency_ms: u32, sluggish: bool) {
+        for (_, callback) in self.callbacks.iter() {
+            if sluggish {
+                callback.on_controller_sluggish(opcode, latency_ms);
+            } else {
+                callback.on_controller_recovered(opcode, latency_ms);
+            }
+        }
+    }
+}
+
+impl IBluetoothDebug for BluetoothDebug {
+    fn get_state_snapshot(&self, redact: bool) -> String {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let mut snapshot = StateSnapshot { time, ..Default::default() };
+
+        if let Some(adapter) = &self.adapter {
+            snapshot.adapter = adapter.lock().unwrap().adapter_snapshot();
+        }
+        if let Some(gatt) = &self.gatt {
+            gatt.lock().unwrap().fill_snapshot(&mut snapshot);
+        }
+
+        let redaction =
+            if redact { Redaction::hashed(self.redaction_salt) } else { Redaction::none() };
+        snapshot.to_json(&redaction)
+    }
+
+    fn get_counters(&self) -> HashMap<String, u64> {
+        self.gatt.as_ref().map_or(HashMap::new(), |gatt| gatt.lock().unwrap().get_counters())
+    }
+
+    fn register_callback(&mut self, mut callback: Box<dyn IBluetoothDebugCallback + Send>) -> u32 {
+        let tx = self.tx.clone();
+
+        let id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _result = tx.send(Message::DebugCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.callbacks.insert(id, callback);
+        id
+    }
+
+    fn unregister_callback(&mut self, callback_id: u32) -> bool {
+        self.remove_callback(callback_id)
+    }
+
+    fn set_hci_latency_threshold(&mut self, threshold_ms: u32) {
+        let threshold = match threshold_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms.into())),
+        };
+        if let Some(gatt) = &self.gatt {
+            gatt.lock().unwrap().set_hci_latency_threshold(threshold);
+        }
+    }
+
+    fn set_snoop_log_mode(&mut self, mode: SnoopLogMode) {
+        info!("Snoop log mode set to {:?}", mode);
+        snoop::set_snoop_log_mode(mode);
+        save_snoop_log_mode(Path::new(SNOOP_LOG_MODE_FILE), mode);
+    }
+
+    fn get_snoop_log_mode(&self) -> SnoopLogMode {
+        snoop::get_snoop_log_mode()
+    }
+}
+
+/// Loads the mode of the snoop log saved in `path`, None if none was saved or the file is
+/// malformed.
+fn load_snoop_log_mode(path: &Path) -> Option<SnoopLogMode> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    SnoopLogMode::from_u32(contents.trim().parse().ok()?)
+}
+
+fn save_snoop_log_mode(path: &Path, mode: SnoopLogMode) {
+    let line = mode.to_u32().unwrap_or_default().to_string();
+    if let Err(e) = save_lines(path, &[line], PUBLIC_FILE_MODE) {
+        warn!("Failed to save the snoop log mode to {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snoop_log_mode_saved() {
+        let path =
+            std::env::temp_dir().join(format!("btstack-snoop-log-mode-{}", std::process::id()));
+        assert_eq!(None, load_snoop_log_mode(&path));
+
+        save_snoop_log_mode(&path, SnoopLogMode::Filtered);
+        assert_eq!(Some(SnoopLogMode::Filtered), load_snoop_log_mode(&path));
+        save_snoop_log_mode(&path, SnoopLogMode::Disabled);
+        assert_eq!(Some(SnoopLogMode::Disabled), load_snoop_log_mode(&path));
+
+        std::fs::write(&path, "7\n").unwrap();
+        assert_eq!(None, load_snoop_log_mode(&path));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -3,20 +3,93 @@
 use btif_macros::{btif_callback, btif_callbacks_dispatcher};
 
 use bt_topshim::bindings::root::bluetooth::Uuid;
-use bt_topshim::btif::{BluetoothInterface, RawAddress, Uuid128Bit};
+use bt_topshim::btif::{
+    BluetoothInterface, BtBondState, BtStatus, BtTransport, RawAddress, Uuid128Bit,
+};
+use bt_topshim::controller::ControllerCallbacks;
+use bt_topshim::profiles::gatt::ffi::RustRawAddress;
 use bt_topshim::profiles::gatt::{
-    BtGattDbElement, BtGattNotifyParams, BtGattReadParams, Gatt, GattClientCallbacks,
-    GattClientCallbacksDispatcher, GattScannerCallbacksDispatcher, GattServerCallbacksDispatcher,
-    GattStatus,
+    AdvertisingTrackInfo, ApcfCommand, BtGattDbElement, BtGattNotifyParams, BtGattReadParams,
+    BtGattResponse, BtGattValue, Gatt, GattAdvCallbacks, GattAdvCallbacksDispatcher,
+    GattAdvInbandCallbacksDispatcher, GattClientCallbacks, GattClientCallbacksDispatcher,
+    GattFilterParam, GattScannerCallbacks, GattScannerCallbacksDispatcher,
+    GattScannerInbandCallbacks, GattScannerInbandCallbacksDispatcher, GattServerCallbacks,
+    GattServerCallbacksDispatcher, GattStatus, PeriodicAdvertisingParameters,
 };
 use bt_topshim::topstack;
 
-use log::{debug, warn};
+use log::{debug, info, warn};
 use num_traits::cast::{FromPrimitive, ToPrimitive};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::ops::RangeInclusive;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc::Sender;
-
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::address::BtAddress;
+use crate::address_resolution::IdentityResolver;
+use crate::advertising_policy::{duty_cycle, interval_ms};
+use crate::att_retry::{
+    is_transient_error, status_with_retries, AttRetries, AttRetryPolicy, MAX_ATT_ATTEMPTS,
+};
+use crate::att_trace::{
+    write_request_opcode, AttPduDirection, AttPduRecord, AttTrace, ATT_EXCHANGE_MTU_REQ,
+    ATT_EXECUTE_WRITE_REQ, ATT_HANDLE_VALUE_IND, ATT_HANDLE_VALUE_NTF,
+    ATT_MULTIPLE_HANDLE_VALUE_NTF, ATT_PREPARE_WRITE_REQ, ATT_READ_BLOB_REQ, ATT_READ_BY_TYPE_REQ,
+    ATT_READ_REQ, ATT_WRITE_CMD, ATT_WRITE_REQ,
+};
+use crate::bluetooth::{Bluetooth, BluetoothDevice, IBluetooth};
+use crate::bluetooth_adv::{
+    address_rotation_interval, advertising_duration, keep_restorable_sets,
+    load_saved_advertising_sets, longest_active_set, longest_suspended_set, save_advertising_sets,
+    uuid_to_le_bytes, AdvertiseData, AdvertiseDataBreakdown, AdvertisingCapabilities,
+    AdvertisingSet, AdvertisingSetParameters, AdvertisingSetState, AdvertisingStatus,
+    AdvertisingTerminationReason, IAdvertisingCallbackRestorer, IAdvertisingSetCallback,
+    SavedAdvertisingSet, TxPowerSweep, ADVERTISING_ROTATION_PERIOD,
+    DEFAULT_MAX_ADVERTISING_SETS_PER_APP, SAVED_ADVERTISING_SETS_FILE, TX_POWER_MAX, TX_POWER_MIN,
+};
+use crate::connection_priority::{
+    self, ConnectionParameters, ConnectionPriority, ParameterRequests,
+};
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::gatt_conformance::{ConformanceCheck, ConformanceIssue};
+use crate::gatt_server_descriptors::{
+    DescriptorKey, ManagedDescriptor, ManagedDescriptorKind, ServerDescriptorStore,
+    MAX_USER_DESCRIPTION_LEN, SERVER_DESCRIPTORS_FILE,
+};
+use crate::gatt_service_builder::{
+    invalid_service_error, validate_service, ServiceValidationError, CCCD_UUID, WRITE_PERMISSIONS,
+};
+use crate::hci_latency::{bucket_name, opcode_group_name, HciLatencyTracker, LatencyAlert};
+use crate::link_tuning::{self, LinkProfileOverrides, LinkTuningProfile};
+use crate::metrics::{Metrics, METRICS_LOG_PERIOD};
+use crate::msft::{self, MonitorCondition};
+use crate::notification_pacing::{PendingNotification, PendingNotifications};
+use crate::notification_queue::{
+    NotificationQueue, NotificationQueueConfig, MAX_NOTIFICATION_QUEUE_DEPTH,
+    NOTIFICATION_QUEUE_DELAY,
+};
+use crate::phy_preferences::{PhyPreference, PhyPreferenceStore, PHY_PREFERENCES_FILE};
+use crate::policy::Policy;
+use crate::privacy::{LocalIdentity, OwnAddressType};
+use crate::rssi_monitor::RssiMonitor;
+use crate::rssi_smoother::RssiSmoother;
+use crate::state_snapshot::{
+    push_recent_error, AdvertiserSnapshot, ConnectionSnapshot, QueueDepths, RecentError,
+    ScannerSnapshot, StateSnapshot,
+};
+use crate::suspend::{SuspendPreparation, SuspendType};
+use crate::time_service::{
+    self, ClockWatch, LocalTime, TimeServer, CLOCK_CHECK_PERIOD, TIME_SERVER_UUID,
+};
+use crate::write_journal::{JournalConflictPolicy, JournalEntry, JournalFlush, WriteJournal};
 use crate::{Message, RPCProxy};
 
 struct Client {
@@ -24,6 +97,7 @@ struct Client {
     uuid: Uuid128Bit,
     callback: Box<dyn IBluetoothGattCallback + Send>,
     is_congested: bool,
+    eatt_support: bool,
 
     // Queued on_characteristic_write callback.
     congestion_queue: Vec<(String, i32, i32)>,
@@ -84,7 +158,12 @@ impl ContextMap {
         self.get_by_client_id_mut(client_id)
     }
 
-    fn add(&mut self, uuid: &Uuid128Bit, callback: Box<dyn IBluetoothGattCallback + Send>) {
+    fn add(
+        &mut self,
+        uuid: &Uuid128Bit,
+        callback: Box<dyn IBluetoothGattCallback + Send>,
+        eatt_support: bool,
+    ) {
         if self.get_by_uuid(uuid).is_some() {
             return;
         }
@@ -94,6 +173,7 @@ impl ContextMap {
             uuid: uuid.clone(),
             callback,
             is_congested: false,
+            eatt_support,
             congestion_queue: vec![],
         });
     }
@@ -133,21 +213,569 @@ impl ContextMap {
             Some(conn) => Some(conn.conn_id),
         }
     }
+
+    /// Returns the connections to `address` of the clients with EATT support.
+    fn get_eatt_conn_ids(&self, address: &String) -> Vec<i32> {
+        self.connections
+            .iter()
+            .filter(|conn| conn.address == *address)
+            .filter(|conn| self.get_by_client_id(conn.client_id).map_or(false, |c| c.eatt_support))
+            .map(|conn| conn.conn_id)
+            .collect()
+    }
+}
+
+/// Parameters of a `IBluetoothGatt::client_connect` request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ConnectRequest {
+    client_id: i32,
+    is_direct: bool,
+    transport: i32,
+    opportunistic: bool,
+    phy: i32,
+}
+
+/// What to do with a connection request of a client, see `SharedConnection::request`.
+#[derive(Debug, PartialEq)]
+enum ConnectAction {
+    /// Pass the request to the native stack, which creates the link or joins the existing one.
+    Connect,
+    /// Wait for the connection attempt of another client to complete.
+    Wait,
+    /// Report the existing connection of the client again.
+    Connected,
+}
+
+/// Clients of the GATT connection to a device. The clients share the ACL and ATT bearer of the
+/// device, so only one direct connection attempt is made at a time and the other clients join
+/// the link once it is up.
+#[derive(Default)]
+struct SharedConnection {
+    // Client whose direct attempt creates the link.
+    connecting: Option<i32>,
+    // Requests of the clients waiting for `connecting`.
+    waiting: Vec<ConnectRequest>,
+    connected: HashSet<i32>,
+}
+
+impl SharedConnection {
+    fn request(&mut self, request: ConnectRequest) -> ConnectAction {
+        let client_id = request.client_id;
+        if self.connected.contains(&client_id) {
+            return ConnectAction::Connected;
+        }
+
+        if self.connecting == Some(client_id)
+            || self.waiting.iter().any(|r| r.client_id == client_id)
+        {
+            return ConnectAction::Wait;
+        }
+
+        // Background and opportunistic requests never create a link by themselves, so they do
+        // not wait for other clients.
+        let creates_link = request.is_direct && !request.opportunistic;
+        if self.connecting.is_some() && creates_link {
+            self.waiting.push(request);
+            return ConnectAction::Wait;
+        }
+
+        if creates_link && self.connected.is_empty() {
+            self.connecting = Some(client_id);
+        }
+        ConnectAction::Connect
+    }
+
+    /// Records the connection of a client and returns the requests of the clients that can now
+    /// join the link.
+    fn connected(&mut self, client_id: i32) -> Vec<ConnectRequest> {
+        self.connected.insert(client_id);
+        if self.connecting == Some(client_id) {
+            self.connecting = None;
+        }
+        self.waiting.drain(..).collect()
+    }
+
+    /// Records a failed attempt of a client and returns the requests of the clients that were
+    /// waiting for it, which fail as well.
+    fn failed(&mut self, client_id: i32) -> Vec<ConnectRequest> {
+        if self.connecting != Some(client_id) {
+            return vec![];
+        }
+
+        self.connecting = None;
+        self.waiting.drain(..).collect()
+    }
+
+    /// Withdraws the pending request of a client. Returns None if the client had none, or else
+    /// whether its attempt was in progress and the requests that were waiting for it.
+    fn cancel(&mut self, client_id: i32) -> Option<(bool, Vec<ConnectRequest>)> {
+        if self.connecting == Some(client_id) {
+            self.connecting = None;
+            return Some((true, self.waiting.drain(..).collect()));
+        }
+
+        let count = self.waiting.len();
+        self.waiting.retain(|r| r.client_id != client_id);
+        if self.waiting.len() != count {
+            Some((false, vec![]))
+        } else {
+            None
+        }
+    }
+
+    fn disconnected(&mut self, client_id: i32) {
+        self.connected.remove(&client_id);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.connecting.is_none() && self.waiting.is_empty() && self.connected.is_empty()
+    }
+}
+
+struct Server {
+    id: Option<i32>,
+    uuid: Uuid128Bit,
+    callback: Box<dyn IBluetoothGattServerCallback + Send>,
+    eatt_support: bool,
+
+    // Connection ID and attribute handle of the read and write requests waiting for
+    // `send_response`, keyed by request ID. Dropped along with their connection.
+    pending_requests: HashMap<i32, (i32, i32)>,
+
+    // Drop policies of the characteristics not using `NotificationDropPolicy::Queue`, keyed by
+    // attribute handle.
+    drop_policies: HashMap<i32, NotificationDropPolicy>,
+}
+
+struct ServerConnection {
+    conn_id: i32,
+    address: String,
+    server_id: i32,
+    is_congested: bool,
+
+    // Notifications waiting for the link to be uncongested, sent one at a time.
+    notification_queue: PendingNotifications,
+}
+
+impl ServerConnection {
+    fn new(conn_id: i32, address: String, server_id: i32) -> ServerConnection {
+        ServerConnection {
+            conn_id,
+            address,
+            server_id,
+            is_congested: false,
+            notification_queue: PendingNotifications::default(),
+        }
+    }
+}
+
+struct ServerContextMap {
+    servers: Vec<Server>,
+    connections: Vec<ServerConnection>,
+}
+
+impl ServerContextMap {
+    fn new() -> ServerContextMap {
+        ServerContextMap { servers: vec![], connections: vec![] }
+    }
+
+    fn get_by_uuid(&self, uuid: &Uuid128Bit) -> Option<&Server> {
+        self.servers.iter().find(|server| server.uuid == *uuid)
+    }
+
+    fn get_by_server_id(&self, server_id: i32) -> Option<&Server> {
+        self.servers.iter().find(|server| server.id == Some(server_id))
+    }
+
+    fn get_by_server_id_mut(&mut self, server_id: i32) -> Option<&mut Server> {
+        self.servers.iter_mut().find(|server| server.id == Some(server_id))
+    }
+
+    fn get_address_by_conn_id(&self, conn_id: i32) -> Option<String> {
+        self.connections
+            .iter()
+            .find(|conn| conn.conn_id == conn_id)
+            .map(|conn| conn.address.clone())
+    }
+
+    fn get_server_by_conn_id(&self, conn_id: i32) -> Option<&Server> {
+        match self.connections.iter().find(|conn| conn.conn_id == conn_id) {
+            None => None,
+            Some(conn) => self.get_by_server_id(conn.server_id),
+        }
+    }
+
+    fn get_server_by_conn_id_mut(&mut self, conn_id: i32) -> Option<&mut Server> {
+        let server_id = match self.connections.iter().find(|conn| conn.conn_id == conn_id) {
+            None => return None,
+            Some(conn) => conn.server_id,
+        };
+
+        self.get_by_server_id_mut(server_id)
+    }
+
+    fn add(
+        &mut self,
+        uuid: &Uuid128Bit,
+        callback: Box<dyn IBluetoothGattServerCallback + Send>,
+        eatt_support: bool,
+    ) {
+        if self.get_by_uuid(uuid).is_some() {
+            return;
+        }
+
+        self.servers.push(Server {
+            id: None,
+            uuid: uuid.clone(),
+            callback,
+            eatt_support,
+            pending_requests: HashMap::new(),
+            drop_policies: HashMap::new(),
+        });
+    }
+
+    fn remove(&mut self, id: i32) {
+        self.servers.retain(|server| server.id != Some(id));
+        self.connections.retain(|conn| conn.server_id != id);
+    }
+
+    fn set_server_id(&mut self, uuid: &Uuid128Bit, id: i32) {
+        if let Some(server) = self.servers.iter_mut().find(|server| server.uuid == *uuid) {
+            server.id = Some(id);
+        }
+    }
+
+    fn add_connection(&mut self, server_id: i32, conn_id: i32, address: &String) {
+        if self.get_conn_id_from_address(server_id, address).is_some() {
+            return;
+        }
+
+        self.connections.push(ServerConnection::new(conn_id, address.clone(), server_id));
+    }
+
+    fn get_connection_mut(&mut self, conn_id: i32) -> Option<&mut ServerConnection> {
+        self.connections.iter_mut().find(|conn| conn.conn_id == conn_id)
+    }
+
+    fn remove_connection(&mut self, conn_id: i32) {
+        self.connections.retain(|conn| conn.conn_id != conn_id);
+        // The requests of the connection can no longer be answered.
+        for server in self.servers.iter_mut() {
+            server.pending_requests.retain(|_, (id, _)| *id != conn_id);
+        }
+    }
+
+    fn get_conn_id_from_address(&self, server_id: i32, address: &String) -> Option<i32> {
+        self.connections
+            .iter()
+            .find(|conn| conn.server_id == server_id && conn.address == *address)
+            .map(|conn| conn.conn_id)
+    }
 }
 
 /// Defines the GATT API.
 pub trait IBluetoothGatt {
-    fn register_scanner(&self, callback: Box<dyn IScannerCallback + Send>);
+    /// Registers an LE scanner. The scanner id is delivered with
+    /// `IScannerCallback::on_scanner_registered`.
+    fn register_scanner(&mut self, callback: Box<dyn IScannerCallback + Send>);
+
+    /// Unregisters an LE scanner, stopping its scan if needed.
+    fn unregister_scanner(&mut self, scanner_id: i32);
+
+    /// Sets the liveness interval of a scanner, in milliseconds. The scanner must then call
+    /// `ping_scanner` at least once per interval, and is unregistered as with
+    /// `unregister_scanner` once it misses several pings in a row. This reclaims the scanners of
+    /// the processes which stopped consuming their results while keeping their D-Bus connection
+    /// open, the scanners of the disconnected processes being unregistered in any case. 0 stops
+    /// checking the liveness of the scanner.
+    fn set_scanner_liveness_interval(&mut self, scanner_id: i32, interval_ms: u32) -> BtResult<()>;
+
+    /// Tells that a scanner with a liveness interval is alive, see
+    /// `set_scanner_liveness_interval`.
+    fn ping_scanner(&mut self, scanner_id: i32) -> BtResult<()>;
+
+    /// Starts LE scanning for the given scanner.
+    ///
+    /// The scan results are only reported if they match any of `filters`, or all of them if
+    /// `filters` is empty.
+    fn start_scan(
+        &mut self,
+        scanner_id: i32,
+        settings: ScanSettings,
+        filters: Vec<ScanFilter>,
+    ) -> BtResult<()>;
+
+    /// Stops LE scanning for the given scanner.
+    fn stop_scan(&mut self, scanner_id: i32);
+
+    /// Attaches a match program to a scanner which decides whether each scan result is
+    /// delivered. An empty program detaches the current one. Fails if the program is rejected by
+    /// the verifier.
+    fn set_scan_match_program(
+        &mut self,
+        scanner_id: i32,
+        program: ScanMatchProgram,
+    ) -> BtResult<()>;
+
+    /// Replaces the address allow-list and deny-list of a scanner set by `start_scan`, taking
+    /// effect immediately if it is scanning.
+    fn set_scan_address_lists(
+        &mut self,
+        scanner_id: i32,
+        allowed_addresses: Vec<BtAddress>,
+        denied_addresses: Vec<BtAddress>,
+    ) -> BtResult<()>;
+
+    /// Replaces the scan interval and window of a scanner set by `start_scan`. The controller
+    /// scans with the parameters of the most aggressive scanning scanner, which are reported with
+    /// `IScannerCallback::on_scan_parameters_changed`. Scans above a duty cycle of 50% take turns
+    /// with each other, see `ScanSettings::priority`.
+    fn set_scan_parameters(&mut self, scanner_id: i32, interval: i32, window: i32) -> BtResult<()>;
+
+    /// Subscribes a scanner to the manufacturer specific data of `manufacturer_id` starting with
+    /// `prefix`, compared under `mask` (empty to compare all the bits). While a scanner has
+    /// subscriptions, it receives the matching payloads with
+    /// `IScannerCallback::on_manufacturer_data_found` instead of the whole scan results.
+    ///
+    /// Returns the id of the subscription.
+    fn subscribe_manufacturer_data(
+        &mut self,
+        scanner_id: i32,
+        manufacturer_id: u16,
+        prefix: Vec<u8>,
+        mask: Vec<u8>,
+    ) -> BtResult<u32>;
+
+    /// Removes a subscription added with `subscribe_manufacturer_data`.
+    fn unsubscribe_manufacturer_data(
+        &mut self,
+        scanner_id: i32,
+        subscription_id: u32,
+    ) -> BtResult<()>;
+
+    /// Splits the controller batch scan storage between the truncated and full results, in
+    /// percents of the storage. `IScannerCallback::on_batch_scan_threshold_crossed` is invoked
+    /// when the storage is filled above `notify_threshold` percents.
+    fn batch_scan_config_storage(
+        &mut self,
+        scanner_id: i32,
+        full_max: i32,
+        truncated_max: i32,
+        notify_threshold: i32,
+    ) -> BtResult<()>;
+
+    /// Starts batch scanning, with the scan results stored by the controller until they are
+    /// read with `batch_scan_read_reports`. Only one scanner can batch scan at a time.
+    fn batch_scan_enable(
+        &mut self,
+        scanner_id: i32,
+        mode: BatchScanMode,
+        interval: i32,
+        window: i32,
+        discard_rule: BatchScanDiscardRule,
+    ) -> BtResult<()>;
+
+    /// Stops batch scanning.
+    fn batch_scan_disable(&mut self, scanner_id: i32) -> BtResult<()>;
+
+    /// Reads the results stored in `mode` by the controller, which are delivered with
+    /// `IScannerCallback::on_batch_scan_reports`.
+    fn batch_scan_read_reports(&mut self, scanner_id: i32, mode: BatchScanMode) -> BtResult<()>;
+
+    /// Synchronizes to the periodic advertising of the advertising set `sid` of `address`.
+    /// The sync events are delivered to `callback`, starting with
+    /// `IPeriodicAdvertisingCallback::on_sync_started`. `skip` is the number of periodic
+    /// advertising packets that can be skipped and `timeout` the sync timeout, in 10 ms units.
+    fn start_sync(
+        &mut self,
+        sid: i32,
+        address: BtAddress,
+        skip: i32,
+        timeout: i32,
+        callback: Box<dyn IPeriodicAdvertisingCallback + Send>,
+    ) -> BtResult<()>;
+
+    /// Terminates an established sync.
+    fn stop_sync(&mut self, sync_handle: i32) -> BtResult<()>;
+
+    /// Cancels a `start_sync` that is not established yet.
+    fn cancel_create_sync(&mut self, sid: i32, address: BtAddress) -> BtResult<()>;
+
+    /// Transfers an established sync to a connected peer (PAST). The result is delivered with
+    /// `IPeriodicAdvertisingCallback::on_sync_transferred` to the callback of the sync.
+    fn transfer_sync(
+        &mut self,
+        address: BtAddress,
+        service_data: i32,
+        sync_handle: i32,
+    ) -> BtResult<()>;
+
+    /// Transfers the sync info of a local periodic advertising set to a connected peer (PAST).
+    fn transfer_set_info(
+        &mut self,
+        address: BtAddress,
+        service_data: i32,
+        adv_handle: i32,
+    ) -> BtResult<()>;
+
+    /// Sets how syncs transferred by `address` are received. The next sync transferred by
+    /// `address` is reported to `callback`.
+    fn sync_tx_parameters(
+        &mut self,
+        address: BtAddress,
+        mode: i32,
+        skip: i32,
+        timeout: i32,
+        callback: Box<dyn IPeriodicAdvertisingCallback + Send>,
+    ) -> BtResult<()>;
+
+    /// Starts an advertising set. The advertise data and scan response must fit in the set, see
+    /// `get_max_advertising_data_length`. `duration` is in 10 ms units, 0 to advertise until the
+    /// set is disabled, and `max_ext_adv_events` the number of extended advertising events to
+    /// send, 0 for no limit.
+    ///
+    /// Each client may hold up to `set_max_advertising_sets_per_app` sets, whichever callback
+    /// objects they are started with. A set is stopped when its callback disconnects. When the
+    /// controller has no free advertiser slot, the set that advertised the longest is suspended
+    /// to make room, see `IAdvertisingSetCallback::on_advertising_set_suspended`. A set resumed
+    /// advertises again for the whole duration and number of events it was last enabled with.
+    ///
+    /// The sets are held to the minimum interval and maximum aggregate duty cycle of the
+    /// advertising policy of the platform, if any. A set out of policy is rejected with a
+    /// `PermissionDenied` error telling the limit, or its interval lengthened to meet the policy,
+    /// as the policy decides. The same applies to `set_advertising_parameters`, while the other
+    /// changes putting a set out of policy are rejected.
+    ///
+    /// Returns the registration ID delivered with
+    /// `IAdvertisingSetCallback::on_advertising_set_started`, which is also the advertiser ID of
+    /// the set.
+    fn start_advertising_set(
+        &mut self,
+        parameters: AdvertisingSetParameters,
+        advertise_data: AdvertiseData,
+        scan_response: AdvertiseData,
+        duration: i32,
+        max_ext_adv_events: i32,
+        callback: Box<dyn IAdvertisingSetCallback + Send>,
+    ) -> BtResult<i32>;
+
+    /// Stops and releases an advertising set.
+    fn stop_advertising_set(&mut self, advertiser_id: i32) -> BtResult<()>;
+
+    /// Enables or disables an advertising set. See `start_advertising_set` for `duration` and
+    /// `max_ext_adv_events`.
+    fn enable_advertising_set(
+        &mut self,
+        advertiser_id: i32,
+        enable: bool,
+        duration: i32,
+        max_ext_adv_events: i32,
+    ) -> BtResult<()>;
+
+    /// Replaces the advertising data of a set.
+    fn set_advertising_data(&mut self, advertiser_id: i32, data: AdvertiseData) -> BtResult<()>;
 
-    fn unregister_scanner(&self, scanner_id: i32);
+    /// Replaces the scan response of a set.
+    fn set_scan_response_data(&mut self, advertiser_id: i32, data: AdvertiseData) -> BtResult<()>;
 
-    fn start_scan(&self, scanner_id: i32, settings: ScanSettings, filters: Vec<ScanFilter>);
-    fn stop_scan(&self, scanner_id: i32);
+    /// Replaces the parameters of a set. Fails if the current data does not fit in the set with
+    /// the new parameters.
+    fn set_advertising_parameters(
+        &mut self,
+        advertiser_id: i32,
+        parameters: AdvertisingSetParameters,
+    ) -> BtResult<()>;
+
+    /// Reads the address a set advertises with, delivered with
+    /// `IAdvertisingSetCallback::on_own_address_read`.
+    fn get_own_address(&mut self, advertiser_id: i32) -> BtResult<()>;
+
+    /// Sets the interval between the rotations of the random address of an advertising set,
+    /// from 1 second to 1 hour, without restarting the set. 0 restores the default interval of
+    /// the stack. Each rotation is reported with `IAdvertisingSetCallback::on_own_address_changed`.
+    /// Fails for a set advertising with the public address.
+    fn set_advertising_address_rotation_interval(
+        &mut self,
+        advertiser_id: i32,
+        interval_ms: i32,
+    ) -> BtResult<()>;
+
+    /// Marks an advertising set as persistent, for the system to be woken by the devices
+    /// scanning it. A persistent set keeps advertising while the system is suspended, and is
+    /// started again after a reset of the controller, reported with
+    /// `IAdvertisingSetCallback::on_advertising_set_restored`. The other sets are disarmed while
+    /// suspended and stopped when the controller resets.
+    fn set_advertising_set_persistent(
+        &mut self,
+        advertiser_id: i32,
+        persistent: bool,
+    ) -> BtResult<()>;
+
+    /// Marks an advertising set to outlive the adapter being disabled, which restarts the daemon.
+    /// The set is saved with its parameters, data, duration and maximum number of events, and
+    /// re-created by the next daemon once the adapter is enabled again if the client is still
+    /// connected to the bus. It is given a new advertiser ID delivered with
+    /// `IAdvertisingSetCallback::on_advertising_set_started` along with the registration ID
+    /// returned by `start_advertising_set`. Within the same daemon, the set is restored like a
+    /// set marked with `set_advertising_set_persistent`, keeping its advertiser ID, but it does
+    /// not advertise while the system is suspended.
+    fn set_advertising_set_restart_persistent(
+        &mut self,
+        advertiser_id: i32,
+        restart_persistent: bool,
+    ) -> BtResult<()>;
+
+    /// Returns the TX power selected by the controller for an advertising set, in dBm, which may
+    /// differ from the level requested by its parameters. Changes are reported with
+    /// `IAdvertisingSetCallback::on_tx_power_changed`. Fails if the set is not started yet.
+    fn get_advertising_tx_power(&mut self, advertiser_id: i32) -> BtResult<i32>;
+
+    /// Requests another TX power level for an advertising set, in dBm, keeping its other
+    /// parameters. The completion is reported with
+    /// `IAdvertisingSetCallback::on_advertising_parameters_updated`. Fails while a TX power sweep
+    /// runs on the set.
+    fn set_advertising_tx_power_level(
+        &mut self,
+        advertiser_id: i32,
+        tx_power_level: i32,
+    ) -> BtResult<()>;
+
+    /// Returns the longest advertising data, in bytes once encoded, that a set with `parameters`
+    /// can send. The scan response of connectable sets can be 3 bytes longer, as their
+    /// advertising data also carries the Flags.
+    fn get_max_advertising_data_length(&self, parameters: AdvertisingSetParameters) -> i32;
+
+    /// Developer utility for payload budgeting: encodes `data` as the advertising data of a set,
+    /// with the Flags of connectable sets if `connectable` is set, and returns the bytes taken by
+    /// each AD structure and the bytes left in legacy and extended advertisements. Nothing is
+    /// sent to the controller. Fails if the data cannot be encoded.
+    fn get_advertise_data_breakdown(
+        &self,
+        data: AdvertiseData,
+        connectable: bool,
+    ) -> BtResult<AdvertiseDataBreakdown>;
+
+    /// Sets how many advertising sets each callback object may hold. Sets already started are
+    /// kept.
+    fn set_max_advertising_sets_per_app(&mut self, max: i32) -> BtResult<()>;
+
+    // GATT Client
+    //
+    // The requests of the clients return an error right away if they cannot be sent, such as
+    // for an invalid parameter, an unknown client or a device the client is not connected to.
+    // The outcome of the requests sent is reported by their callback.
+    //
+    // The reads and writes of a client on a connection wait for a free ATT bearer, one being
+    // connected without EATT, and their outcomes are reported in request order.
 
     /// Registers a GATT Client.
+    ///
+    /// With `eatt_support`, the client uses the Enhanced ATT bearers the stack connects to the
+    /// devices supporting it, and `on_eatt_state_changed` reports them. Its reads and writes are
+    /// then spread over the bearers, their outcomes still being reported in request order.
     fn register_client(
         &mut self,
-        app_uuid: String,
+        app_uuid: crate::uuid::BtUuid,
         callback: Box<dyn IBluetoothGattCallback + Send>,
         eatt_support: bool,
     );
@@ -155,61 +783,157 @@ pub trait IBluetoothGatt {
     /// Unregisters a GATT Client.
     fn unregister_client(&mut self, client_id: i32);
 
-    /// Initiates a GATT connection to a peer device.
+    /// Initiates a GATT connection to a peer device. The clients connecting to the same device
+    /// share its link: while a direct attempt of a client is pending, the direct requests of the
+    /// other clients wait for it and then join the link, or fail along with it. An opportunistic
+    /// request never creates the link, it only joins the link once another client brings it up.
+    /// A `phy` of `LePhy::Phy2m` or `LePhy::PhyCoded` is also kept as the preferred PHY of the
+    /// client for the device, like `client_set_preferred_phy` does, and requested as soon as the
+    /// connection is up.
     fn client_connect(
-        &self,
+        &mut self,
         client_id: i32,
-        addr: String,
+        addr: BtAddress,
         is_direct: bool,
         transport: i32,
         opportunistic: bool,
         phy: i32,
-    );
+    ) -> BtResult<()>;
+
+    /// Disconnects a GATT connection, or withdraws the pending connection request of the client.
+    fn client_disconnect(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()>;
 
-    /// Disconnects a GATT connection.
-    fn client_disconnect(&self, client_id: i32, addr: String);
+    /// Adds a bonded device to the background connection list of a client. The client is
+    /// connected to the device whenever it advertises, with `on_client_connection_state`, and
+    /// again after each disconnection until the device is removed from the list.
+    fn add_device_to_background_connect(&mut self, client_id: i32, addr: BtAddress)
+        -> BtResult<()>;
 
-    /// Sets preferred PHY.
+    /// Removes a device from the background connection list of a client. An existing
+    /// connection is kept.
+    fn remove_device_from_background_connect(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+    ) -> BtResult<()>;
+
+    /// Sets preferred PHY. The preference of a bonded device is persisted and applied again each
+    /// time the device reconnects, so it may be set while the device is not connected. The
+    /// preference of the client is also applied each time the client connects to the device,
+    /// until it unregisters. The resulting PHY is reported with `on_phy_update`.
     fn client_set_preferred_phy(
-        &self,
+        &mut self,
         client_id: i32,
-        addr: String,
+        addr: BtAddress,
         tx_phy: LePhy,
         rx_phy: LePhy,
         phy_options: i32,
-    );
+    ) -> BtResult<()>;
 
     /// Reads the PHY used by a peer.
-    fn client_read_phy(&mut self, client_id: i32, addr: String);
+    fn client_read_phy(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()>;
+
+    /// Returns the PHY preference persisted for a bonded device.
+    fn get_preferred_phy(&self, addr: BtAddress) -> BtResult<PhyPreference>;
+
+    /// Sets the PHY preference applied to the LE links of the devices without a persisted
+    /// preference when they connect. The PHY preferences of the clients and the link tuning
+    /// profiles take precedence. `PhyPreference::default()` leaves the PHY of the links to the
+    /// controller.
+    fn set_default_phy_preference(&mut self, preference: PhyPreference) -> BtResult<()>;
+
+    /// Returns the preference set with `set_default_phy_preference`.
+    fn get_default_phy_preference(&self) -> PhyPreference;
+
+    /// Overrides the link tuning profile chosen for a device from its services. The profile is
+    /// applied to the current LE link of the device, if any, and to its next links until the
+    /// client clears it or unregisters. When several clients override the profile of a device,
+    /// the last override applies. `LinkTuningProfile::Default` leaves the parameters of the next
+    /// links untouched.
+    fn set_link_tuning_profile(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        profile: LinkTuningProfile,
+    ) -> BtResult<()>;
+
+    /// Removes the link tuning profile override of a client for a device, its links being tuned
+    /// with the override of another client or from its services again.
+    fn clear_link_tuning_profile(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()>;
+
+    /// Returns the state of the connection of a client to a device.
+    fn get_connection_info(&self, client_id: i32, addr: BtAddress) -> BtResult<GattConnectionInfo>;
 
     /// Clears the attribute cache of a device.
-    fn refresh_device(&self, client_id: i32, addr: String);
+    fn refresh_device(&self, client_id: i32, addr: BtAddress) -> BtResult<()>;
 
-    /// Enumerates all GATT services on a connected device.
-    fn discover_services(&self, client_id: i32, addr: String);
+    /// Enumerates all GATT services on a connected device. The databases of bonded devices are
+    /// cached by the native stack (`gatt_robust_caching_client`), which only re-runs the discovery
+    /// when the Database Hash of the device changed.
+    fn discover_services(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()>;
+
+    /// Returns the attribute database cached from the last discovery on a connected device, which
+    /// is empty if no discovery completed yet. A fresh copy is also requested from the stack and
+    /// delivered with `IBluetoothGattCallback::on_get_gatt_db`, without re-running discovery.
+    fn get_gatt_db(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+    ) -> BtResult<Vec<BluetoothGattService>>;
 
     /// Search a GATT service on a connected device based on a UUID.
-    fn discover_service_by_uuid(&self, client_id: i32, addr: String, uuid: String);
+    fn discover_service_by_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        uuid: crate::uuid::BtUuid,
+    ) -> BtResult<()>;
 
     /// Reads a characteristic on a remote device.
-    fn read_characteristic(&self, client_id: i32, addr: String, handle: i32, auth_req: i32);
+    fn read_characteristic(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        auth_req: i32,
+    ) -> BtResult<()>;
 
     /// Reads a characteristic on a remote device.
     fn read_using_characteristic_uuid(
-        &self,
+        &mut self,
         client_id: i32,
-        addr: String,
-        uuid: String,
+        addr: BtAddress,
+        uuid: crate::uuid::BtUuid,
         start_handle: i32,
         end_handle: i32,
         auth_req: i32,
-    );
+    ) -> BtResult<()>;
+
+    /// Reads every readable characteristic of a discovered service, one after the other. The
+    /// values are delivered together with `IBluetoothGattCallback::on_service_read`.
+    fn read_service(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        service_uuid: crate::uuid::BtUuid,
+    ) -> BtResult<()>;
+
+    /// Checks the attribute database of a connected device for conformance problems: missing
+    /// descriptors, attributes declared out of order, values too long and security requirements
+    /// the bond does not meet. The services are discovered first if needed, then every readable
+    /// characteristic is read. The problems are delivered with
+    /// `IBluetoothGattCallback::on_conformance_report`.
+    fn check_conformance(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()>;
 
     /// Writes a remote characteristic.
+    ///
+    /// Values not fitting in a single ATT PDU with the negotiated MTU are written with prepared
+    /// writes, whatever `write_type` is. The progress is reported with
+    /// `IBluetoothGattCallback::on_characteristic_write_progress` as each part is acknowledged.
     fn write_characteristic(
-        &self,
+        &mut self,
         client_id: i32,
-        addr: String,
+        addr: BtAddress,
         handle: i32,
         write_type: GattWriteType,
         auth_req: i32,
@@ -217,74 +941,459 @@ pub trait IBluetoothGatt {
     ) -> GattWriteRequestStatus;
 
     /// Reads the descriptor for a given characteristic.
-    fn read_descriptor(&self, client_id: i32, addr: String, handle: i32, auth_req: i32);
+    fn read_descriptor(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        auth_req: i32,
+    ) -> BtResult<()>;
+
+    /// Writes the first characteristic `uuid` between `start_handle` and `end_handle`, as found
+    /// in the services discovered on the device, like `write_characteristic` does with its
+    /// handle. Returns `GattWriteRequestStatus::Fail` if the services are not discovered or the
+    /// characteristic is not found.
+    fn write_using_characteristic_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        uuid: crate::uuid::BtUuid,
+        start_handle: i32,
+        end_handle: i32,
+        write_type: GattWriteType,
+        auth_req: i32,
+        value: Vec<u8>,
+    ) -> GattWriteRequestStatus;
+
+    /// Reads the descriptor `descriptor_uuid` of the first characteristic `characteristic_uuid`
+    /// found in the services discovered on the device. The value is delivered with
+    /// `IBluetoothGattCallback::on_descriptor_read` as for `read_descriptor`.
+    fn read_descriptor_by_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        characteristic_uuid: crate::uuid::BtUuid,
+        descriptor_uuid: crate::uuid::BtUuid,
+        auth_req: i32,
+    ) -> BtResult<()>;
 
     /// Writes a remote descriptor for a given characteristic.
+    ///
+    /// The writes of the clients to the same Client Characteristic Configuration descriptor are
+    /// merged: the device is written the union of their values, only when it changes, so that a
+    /// client disabling notifications does not disable them for the others. The writes which do
+    /// not change the union complete right away.
     fn write_descriptor(
-        &self,
+        &mut self,
         client_id: i32,
-        addr: String,
+        addr: BtAddress,
         handle: i32,
         auth_req: i32,
         value: Vec<u8>,
-    );
+    ) -> BtResult<()>;
+
+    /// Cancels the pending operations of a client on a connection. `token` is the attribute
+    /// handle of the reads and writes to cancel, `OPERATION_TOKEN_DISCOVERY` for the service
+    /// discovery or `OPERATION_TOKEN_ALL` for every operation.
+    ///
+    /// The callbacks of the cancelled operations are invoked right away with `GattStatus::Cancel`
+    /// and their results are dropped. Requests already sent cannot be recalled, so a cancelled
+    /// write may still be applied by the remote device, except for long writes whose prepared
+    /// parts are discarded.
+    fn cancel_operation(&mut self, client_id: i32, addr: BtAddress, token: i32) -> BtResult<()>;
+
+    /// Enables the write journal of a client, or changes its conflict policy. The
+    /// characteristic writes of the client to the devices it is not connected to are then kept
+    /// in the journal, returning `GattWriteRequestStatus::Journaled`, and are sent in order the
+    /// next time the client connects to the device. Their results are delivered with
+    /// `IBluetoothGattCallback::on_characteristic_write` as usual.
+    ///
+    /// `policy` decides what happens to a write for a characteristic which already has one in
+    /// the journal. The journal holds up to 64 writes, over all the devices.
+    fn enable_write_journal(
+        &mut self,
+        client_id: i32,
+        policy: JournalConflictPolicy,
+    ) -> BtResult<()>;
+
+    /// Disables the write journal of a client, dropping the writes it holds. Fails with
+    /// `BtErrorCategory::NotFound` if the journal is not enabled.
+    fn disable_write_journal(&mut self, client_id: i32) -> BtResult<()>;
+
+    /// Returns the writes in the journal of a client for `addr`, or for every device if `addr`
+    /// is empty, in the order they will be sent.
+    fn get_write_journal(&self, client_id: i32, addr: BtAddress) -> Vec<JournalEntry>;
+
+    /// Removes the write `entry_id` from the journal of a client, unless it is already sent.
+    /// Fails with `BtErrorCategory::NotFound` if the write is not found.
+    fn cancel_journaled_write(&mut self, client_id: i32, entry_id: i32) -> BtResult<()>;
+
+    /// Removes the writes in the journal of a client for `addr`, or for every device if `addr`
+    /// is empty. Returns the number of writes removed.
+    fn clear_write_journal(&mut self, client_id: i32, addr: BtAddress) -> u32;
+
+    /// Sets the retry policy of a client. The characteristic and descriptor reads of the client
+    /// failing with a transient error, such as `GattStatus::InsufResource` or `GattStatus::Busy`,
+    /// are then sent again up to `policy.max_attempts` times in all, waiting longer before each
+    /// attempt. The number of retries of a read is reported in the status of its result, shifted
+    /// by `att_retry::ATT_RETRIES_SHIFT` above the `GattStatus` of the last attempt.
+    ///
+    /// The operations not answered within the 30 seconds of the ATT transaction timeout fail with
+    /// `GattStatus::Error` and are not retried, the request being still outstanding in the stack.
+    ///
+    /// The writes are not retried since the remote device may have applied them. A policy
+    /// allowing a single attempt disables the retries.
+    fn set_att_retry_policy(&mut self, client_id: i32, policy: AttRetryPolicy) -> BtResult<()>;
+
+    /// Sets the notification queue of a client. The notifications and indications received for
+    /// the client are then queued and delivered in batches with `IBluetoothGattCallback::on_notify`
+    /// instead of as they are received. When the queue is full the oldest value is dropped, which
+    /// is reported with `IBluetoothGattCallback::on_notification_queue_overflow` before the
+    /// batch. A depth of 0 removes the queue, delivering the values still queued.
+    ///
+    /// The characteristics bridged to a pipe with `register_notification_pipe` are not queued.
+    fn set_notification_queue(
+        &mut self,
+        client_id: i32,
+        config: NotificationQueueConfig,
+    ) -> BtResult<()>;
 
     /// Registers to receive notifications or indications for a given characteristic.
-    fn register_for_notification(&self, client_id: i32, addr: String, handle: i32, enable: bool);
+    fn register_for_notification(
+        &self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        enable: bool,
+    ) -> BtResult<()>;
+
+    /// Bridges the notifications of a characteristic to a pipe instead of `on_notify`.
+    ///
+    /// Each notification is written to `pipe` as a 2-byte little-endian length followed by the
+    /// value. `on_notification_pipe_active` is only invoked when the subscription becomes active
+    /// after being idle, so clients can drain the pipe in batches. The notifications are written
+    /// from a task of their own; those arriving while the pipe is full are queued up to a limit,
+    /// beyond which they are dropped.
+    fn set_notification_pipe(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        pipe: File,
+    ) -> BtResult<()>;
+
+    /// Stops bridging the notifications of a characteristic to a pipe.
+    fn clear_notification_pipe(&mut self, client_id: i32, addr: BtAddress, handle: i32);
 
     /// Begins reliable write.
-    fn begin_reliable_write(&mut self, client_id: i32, addr: String);
+    fn begin_reliable_write(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()>;
 
     /// Ends reliable write.
-    fn end_reliable_write(&mut self, client_id: i32, addr: String, execute: bool);
+    fn end_reliable_write(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        execute: bool,
+    ) -> BtResult<()>;
 
     /// Requests RSSI for a given remote device.
-    fn read_remote_rssi(&self, client_id: i32, addr: String);
+    fn read_remote_rssi(&self, client_id: i32, addr: BtAddress) -> BtResult<()>;
+
+    /// Monitors the RSSI of the connection of a client with `addr`, read every
+    /// `sampling_period_ms`, at least 100 ms. `on_rssi_threshold_crossed` reports when the RSSI
+    /// reaches `high` dBm or more, then when it falls to `low` dBm or less, and so on, starting
+    /// with the first sample if it is already past a threshold. The monitor replaces the previous
+    /// one of the connection and is stopped when the client disconnects. The reads of the
+    /// monitor are not reported with `on_read_remote_rssi`.
+    fn start_rssi_monitor(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        low: i32,
+        high: i32,
+        sampling_period_ms: i32,
+    ) -> BtResult<()>;
+
+    /// Stops the monitor started with `start_rssi_monitor`.
+    fn stop_rssi_monitor(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()>;
 
     /// Configures the MTU of a given connection.
-    fn configure_mtu(&self, client_id: i32, addr: String, mtu: i32);
+    fn configure_mtu(&mut self, client_id: i32, addr: BtAddress, mtu: i32) -> BtResult<()>;
 
-    /// Requests a connection parameter update.
+    /// Requests connection parameters for the connection of a client to a device. They are
+    /// arbitrated with the priorities and the parameters requested by the other clients of the
+    /// device, and with its link tuning profile, the most demanding ones being applied. The
+    /// request replaces the earlier one of the client, set with `set_connection_priority` as
+    /// well, and is dropped once the client disconnects.
     fn connection_parameter_update(
-        &self,
+        &mut self,
         client_id: i32,
-        addr: String,
+        addr: BtAddress,
         min_interval: i32,
         max_interval: i32,
         latency: i32,
         timeout: i32,
         min_ce_len: u16,
         max_ce_len: u16,
-    );
-}
+    ) -> BtResult<()>;
 
-#[derive(Debug, Default)]
-/// Represents a GATT Descriptor.
-pub struct BluetoothGattDescriptor {
-    pub uuid: Uuid128Bit,
-    pub instance_id: i32,
-    pub permissions: i32,
-}
+    /// Requests a priority for the connection of a client to a device, which is applied with
+    /// the connection parameters of its preset. They are arbitrated as the parameters requested
+    /// with `connection_parameter_update`, so that the highest priority requested for the device
+    /// is applied. The request is dropped once the client disconnects.
+    fn set_connection_priority(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        priority: ConnectionPriority,
+    ) -> BtResult<()>;
 
-impl BluetoothGattDescriptor {
-    fn new(uuid: Uuid128Bit, instance_id: i32, permissions: i32) -> BluetoothGattDescriptor {
-        BluetoothGattDescriptor { uuid, instance_id, permissions }
-    }
-}
+    // GATT Server
 
-#[derive(Debug, Default)]
-/// Represents a GATT Characteristic.
-pub struct BluetoothGattCharacteristic {
-    pub uuid: Uuid128Bit,
-    pub instance_id: i32,
-    pub properties: i32,
-    pub permissions: i32,
-    pub key_size: i32,
-    pub write_type: GattWriteType,
-    pub descriptors: Vec<BluetoothGattDescriptor>,
-}
+    /// Registers a GATT Server.
+    fn register_server(
+        &mut self,
+        app_uuid: crate::uuid::BtUuid,
+        callback: Box<dyn IBluetoothGattServerCallback + Send>,
+        eatt_support: bool,
+    );
 
-impl BluetoothGattCharacteristic {
+    /// Unregisters a GATT Server.
+    fn unregister_server(&mut self, server_id: i32);
+
+    /// Initiates a GATT connection from the server to a peer device.
+    fn server_connect(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        is_direct: bool,
+        transport: i32,
+    ) -> BtResult<()>;
+
+    /// Disconnects a peer device from the server.
+    fn server_disconnect(&self, server_id: i32, addr: BtAddress) -> BtResult<()>;
+
+    /// Adds a service to the server. The handles assigned to the attributes are delivered with
+    /// `IBluetoothGattServerCallback::on_service_added`. Services which fail `validate_service`
+    /// are rejected with an `InvalidService` error.
+    fn add_service(&self, server_id: i32, service: BluetoothGattService) -> BtResult<()>;
+
+    /// Checks that the properties, permissions and descriptors of the characteristics of a
+    /// service are consistent. Returns every problem found, or nothing if the service is valid.
+    fn validate_service(&self, service: BluetoothGattService) -> Vec<ServiceValidationError>;
+
+    /// Removes the service with the given handle from the server.
+    fn remove_service(&self, server_id: i32, handle: i32) -> BtResult<()>;
+
+    /// Sets the Characteristic User Description of the characteristic with the given handle.
+    ///
+    /// The stack answers the Characteristic User Description and Server Characteristic
+    /// Configuration descriptors of the services itself. Their values are shared by all the
+    /// clients, and the clients may write them if the descriptors have a write permission,
+    /// which is reported with `IBluetoothGattServerCallback::on_user_description_changed` and
+    /// `IBluetoothGattServerCallback::on_server_configuration_changed`. The values written by
+    /// bonded clients are restored when the service is added again, until the bond is removed,
+    /// and take precedence over the description set by the server until it sets it again.
+    fn set_user_description(
+        &mut self,
+        server_id: i32,
+        handle: i32,
+        description: String,
+    ) -> BtResult<()>;
+
+    /// Responds to a read or write request received by the server.
+    fn send_response(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        request_id: i32,
+        status: GattStatus,
+        offset: i32,
+        value: Vec<u8>,
+    ) -> BtResult<()>;
+
+    /// Sends a notification, or an indication if `confirm` is set, to a connected peer. While the
+    /// link to the peer is congested, the notification is queued according to the drop policy of
+    /// the characteristic without holding back the other peers.
+    fn send_notification(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        confirm: bool,
+        value: Vec<u8>,
+    ) -> BtResult<()>;
+
+    /// Sends the values of several characteristics to a connected peer in a single multiple
+    /// handle value notification, over an enhanced ATT bearer if the server registered with EATT
+    /// support. The peer must support the multiple handle value notifications and the values
+    /// must fit its MTU. Unlike `send_notification`, nothing is queued while the link is
+    /// congested. The completion is reported with
+    /// `IBluetoothGattServerCallback::on_notification_sent`.
+    fn send_multiple_notifications(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        values: Vec<GattHandleValue>,
+    ) -> BtResult<()>;
+
+    /// Sets what happens to the notifications of the characteristic with the given handle while
+    /// the link to a peer is congested.
+    fn set_notification_drop_policy(
+        &mut self,
+        server_id: i32,
+        handle: i32,
+        policy: NotificationDropPolicy,
+    ) -> BtResult<()>;
+
+    /// Returns the number of notifications waiting for the link to a connected peer to clear.
+    fn get_notification_queue_depth(&self, server_id: i32, addr: BtAddress) -> BtResult<u32>;
+
+    /// Sets which centrals may stay connected to the servers. Centrals rejected by the policy are
+    /// disconnected as soon as the connection is established, and the connected centrals no
+    /// longer accepted are disconnected.
+    fn set_peripheral_connection_policy(&mut self, policy: PeripheralConnectionPolicy);
+
+    /// Returns the policy applied to the incoming connections.
+    fn get_peripheral_connection_policy(&self) -> PeripheralConnectionPolicy;
+
+    /// Sets the centrals accepted by `PeripheralConnectionPolicy::AllowList`.
+    fn set_peripheral_allow_list(&mut self, addresses: Vec<BtAddress>);
+
+    /// Registers the agent deciding on the incoming connections with
+    /// `PeripheralConnectionPolicy::AskAgent`, replacing the previous one. Centrals the agent
+    /// does not decide on within 10 seconds are rejected.
+    fn register_peripheral_connection_agent(
+        &mut self,
+        agent: Box<dyn IPeripheralConnectionAgent + Send>,
+    );
+
+    /// Accepts or rejects a central the agent was asked about with
+    /// `IPeripheralConnectionAgent::on_peripheral_connection_request`.
+    fn respond_peripheral_connection(&mut self, addr: BtAddress, accept: bool) -> BtResult<()>;
+
+    // Debugging
+
+    /// Starts recording the ATT PDUs exchanged with a remote device, by both the clients and the
+    /// servers, into a bounded ring. Replaces the previous trace, only one device is traced at a
+    /// time.
+    fn start_att_trace(&mut self, addr: BtAddress) -> BtResult<()>;
+
+    /// Stops recording the ATT PDUs. The trace can still be retrieved until the next one starts.
+    fn stop_att_trace(&mut self);
+
+    /// Returns the ATT PDUs recorded for a remote device, oldest first.
+    fn get_att_trace(&self, addr: BtAddress) -> BtResult<Vec<AttPduRecord>>;
+}
+
+/// Which centrals may stay connected to the local servers.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum PeripheralConnectionPolicy {
+    AllowAll = 0,
+    /// Only the bonded centrals are accepted.
+    BondedOnly = 1,
+    /// Only the centrals of the allow list are accepted.
+    AllowList = 2,
+    /// The registered `IPeripheralConnectionAgent` decides, the centrals are rejected if there is
+    /// none.
+    AskAgent = 3,
+}
+
+impl Default for PeripheralConnectionPolicy {
+    fn default() -> Self {
+        PeripheralConnectionPolicy::AllowAll
+    }
+}
+
+/// Outcome of the peripheral connection policy for a central.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PeripheralDecision {
+    // Connected by the local device, which is not subject to the policy.
+    Initiated,
+    Accepted,
+    // Rejected before the servers were told about the connection.
+    Rejected,
+    // Waiting for the agent.
+    Pending,
+    // Rejected by the agent or by a change of the policy after the servers were told about the
+    // connection.
+    Revoked,
+}
+
+/// Time the agent has to decide on a central, after which the central is rejected.
+const PERIPHERAL_AGENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Decides whether a central connecting to the local servers is accepted, per `policy`.
+fn decide_peripheral_connection(
+    policy: PeripheralConnectionPolicy,
+    is_bonded: bool,
+    is_allowed: bool,
+    has_agent: bool,
+) -> PeripheralDecision {
+    let accepted = match policy {
+        PeripheralConnectionPolicy::AllowAll => true,
+        PeripheralConnectionPolicy::BondedOnly => is_bonded,
+        PeripheralConnectionPolicy::AllowList => is_allowed,
+        PeripheralConnectionPolicy::AskAgent if has_agent => return PeripheralDecision::Pending,
+        PeripheralConnectionPolicy::AskAgent => false,
+    };
+
+    if accepted {
+        PeripheralDecision::Accepted
+    } else {
+        PeripheralDecision::Rejected
+    }
+}
+
+/// What happens to the notifications of a characteristic sent while the link is congested.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum NotificationDropPolicy {
+    /// Notifications are queued and all delivered in order, up to the queue depth limit.
+    Queue = 0,
+    /// Only the latest value of the characteristic is delivered: the values still queued are
+    /// dropped and the latest one is queued after the notifications of the other characteristics.
+    LatestWins = 1,
+}
+
+impl Default for NotificationDropPolicy {
+    fn default() -> Self {
+        NotificationDropPolicy::Queue
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Represents a GATT Descriptor.
+pub struct BluetoothGattDescriptor {
+    pub uuid: Uuid128Bit,
+    pub instance_id: i32,
+    pub permissions: i32,
+}
+
+impl BluetoothGattDescriptor {
+    pub(crate) fn new(
+        uuid: Uuid128Bit,
+        instance_id: i32,
+        permissions: i32,
+    ) -> BluetoothGattDescriptor {
+        BluetoothGattDescriptor { uuid, instance_id, permissions }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Represents a GATT Characteristic.
+pub struct BluetoothGattCharacteristic {
+    pub uuid: Uuid128Bit,
+    pub instance_id: i32,
+    pub properties: i32,
+    pub permissions: i32,
+    pub key_size: i32,
+    pub write_type: GattWriteType,
+    pub descriptors: Vec<BluetoothGattDescriptor>,
+}
+
+impl BluetoothGattCharacteristic {
     pub const PROPERTY_BROADCAST: i32 = 0x01;
     pub const PROPERTY_READ: i32 = 0x02;
     pub const PROPERTY_WRITE_NO_RESPONSE: i32 = 0x04;
@@ -294,7 +1403,16 @@ impl BluetoothGattCharacteristic {
     pub const PROPERTY_SIGNED_WRITE: i32 = 0x40;
     pub const PROPERTY_EXTENDED_PROPS: i32 = 0x80;
 
-    fn new(
+    pub const PERMISSION_READ: i32 = 0x01;
+    pub const PERMISSION_READ_ENCRYPTED: i32 = 0x02;
+    pub const PERMISSION_READ_ENCRYPTED_MITM: i32 = 0x04;
+    pub const PERMISSION_WRITE: i32 = 0x10;
+    pub const PERMISSION_WRITE_ENCRYPTED: i32 = 0x20;
+    pub const PERMISSION_WRITE_ENCRYPTED_MITM: i32 = 0x40;
+    pub const PERMISSION_WRITE_SIGNED: i32 = 0x80;
+    pub const PERMISSION_WRITE_SIGNED_MITM: i32 = 0x100;
+
+    pub(crate) fn new(
         uuid: Uuid128Bit,
         instance_id: i32,
         properties: i32,
@@ -317,7 +1435,7 @@ impl BluetoothGattCharacteristic {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 /// Represents a GATT Service.
 pub struct BluetoothGattService {
     pub uuid: Uuid128Bit,
@@ -328,7 +1446,14 @@ pub struct BluetoothGattService {
 }
 
 impl BluetoothGattService {
-    fn new(uuid: Uuid128Bit, instance_id: i32, service_type: i32) -> BluetoothGattService {
+    pub const SERVICE_TYPE_PRIMARY: i32 = 0;
+    pub const SERVICE_TYPE_SECONDARY: i32 = 1;
+
+    pub(crate) fn new(
+        uuid: Uuid128Bit,
+        instance_id: i32,
+        service_type: i32,
+    ) -> BluetoothGattService {
         BluetoothGattService {
             uuid,
             instance_id,
@@ -339,6 +1464,392 @@ impl BluetoothGattService {
     }
 }
 
+/// Result of reading one characteristic, delivered with
+/// `IBluetoothGattCallback::on_service_read`.
+#[derive(Clone, Debug, Default)]
+pub struct CharacteristicReadResult {
+    pub uuid: Uuid128Bit,
+    pub handle: i32,
+    pub status: i32,
+    pub value: Vec<u8>,
+}
+
+/// Value of an attribute carried by a multiple handle value notification.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GattHandleValue {
+    pub handle: i32,
+    pub value: Vec<u8>,
+}
+
+/// Token of `IBluetoothGatt::cancel_operation` selecting the service discovery.
+pub const OPERATION_TOKEN_DISCOVERY: i32 = 0;
+/// Token of `IBluetoothGatt::cancel_operation` selecting every operation.
+pub const OPERATION_TOKEN_ALL: i32 = -1;
+
+/// Client operations that can be cancelled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum GattOperation {
+    Discovery,
+    ReadCharacteristic,
+    WriteCharacteristic,
+    ReadDescriptor,
+    WriteDescriptor,
+}
+
+/// Time an ATT request may wait for its response, after which the bearer is unusable.
+const ATT_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends `Message::GattTransactionTimeout` for a connection after `ATT_TRANSACTION_TIMEOUT`.
+fn schedule_transaction_timeout(
+    tx: Option<Sender<Message>>,
+    conn_id: i32,
+) -> Option<JoinHandle<()>> {
+    let tx = tx?;
+    Some(tokio::spawn(async move {
+        time::sleep(ATT_TRANSACTION_TIMEOUT).await;
+        let _ = tx.send(Message::GattTransactionTimeout(conn_id)).await;
+    }))
+}
+
+/// Request of an operation waiting for a free ATT bearer.
+#[derive(Debug)]
+enum AttRequest {
+    Read { auth_req: i32 },
+    Write { write_type: GattWriteType, auth_req: i32, value: Vec<u8> },
+}
+
+/// Result of an operation, held until the results of the operations requested before it are
+/// delivered.
+#[derive(Debug, Default, PartialEq)]
+struct OperationResult {
+    status: i32,
+    value: Vec<u8>,
+    // Number of times a read was retried, see `IBluetoothGatt::set_att_retry_policy`.
+    retries: u32,
+}
+
+/// Operation of a connection, from its request until its result is delivered.
+struct PendingOperation {
+    operation: GattOperation,
+    handle: i32,
+    cancelled: bool,
+    // The request while it waits for a free bearer, None once sent.
+    request: Option<AttRequest>,
+    // The result while the results of the operations requested before are awaited.
+    result: Option<OperationResult>,
+}
+
+impl PendingOperation {
+    fn is_in_flight(&self) -> bool {
+        self.request.is_none() && self.result.is_none()
+    }
+
+    fn is_in_flight_as(&self, operation: GattOperation, handle: i32) -> bool {
+        self.is_in_flight() && self.operation == operation && self.handle == handle
+    }
+}
+
+/// Operations of a connection waiting for their result, in request order.
+///
+/// ATT allows a single request in flight per bearer, so the reads and writes are queued until a
+/// bearer is free, and their results delivered in request order whichever bearer answers first.
+/// The discoveries are queued by the stack itself.
+#[derive(Default)]
+struct PendingOperations {
+    operations: Vec<PendingOperation>,
+    // When the ATT transaction timer was last started, None while it is stopped.
+    transaction_started: Option<Instant>,
+    // Ends the transactions once they time out.
+    transaction_timer: Option<JoinHandle<()>>,
+}
+
+impl Drop for PendingOperations {
+    fn drop(&mut self) {
+        if let Some(timer) = self.transaction_timer.take() {
+            timer.abort();
+        }
+    }
+}
+
+impl PendingOperations {
+    /// Records an operation sent right away.
+    fn push(&mut self, operation: GattOperation, handle: i32) {
+        self.operations.push(PendingOperation {
+            operation,
+            handle,
+            cancelled: false,
+            request: None,
+            result: None,
+        });
+    }
+
+    /// Records an operation waiting for a free bearer, see `next_request`.
+    fn queue(&mut self, operation: GattOperation, handle: i32, request: AttRequest) {
+        self.operations.push(PendingOperation {
+            operation,
+            handle,
+            cancelled: false,
+            request: Some(request),
+            result: None,
+        });
+    }
+
+    /// Returns the number of requests sent on the bearers, the discoveries excluded.
+    fn in_flight(&self) -> usize {
+        self.operations
+            .iter()
+            .filter(|o| o.operation != GattOperation::Discovery && o.is_in_flight())
+            .count()
+    }
+
+    /// Returns whether an operation may be sent right away on one of `bearers`, without
+    /// overtaking the queued ones.
+    fn can_send(&self, bearers: usize) -> bool {
+        self.operations.iter().all(|o| o.request.is_none()) && self.in_flight() < bearers
+    }
+
+    /// Takes the request of the oldest queued operation if one of `bearers` is free. The
+    /// operation is then in flight.
+    fn next_request(&mut self, bearers: usize) -> Option<(GattOperation, i32, AttRequest)> {
+        if self.in_flight() >= bearers {
+            return None;
+        }
+        self.operations
+            .iter_mut()
+            .find(|o| o.request.is_some())
+            .map(|o| (o.operation, o.handle, o.request.take().unwrap()))
+    }
+
+    /// Starts the ATT transaction timer again while results are awaited. The discoveries are
+    /// made of many transactions the stack times out itself, so they do not need the timer.
+    fn restart_transaction_timer(&mut self, tx: Option<Sender<Message>>, conn_id: i32) {
+        if let Some(timer) = self.transaction_timer.take() {
+            timer.abort();
+        }
+        self.transaction_started = None;
+
+        if self.in_flight() > 0 {
+            self.transaction_started = Some(Instant::now());
+            self.transaction_timer = schedule_transaction_timeout(tx, conn_id);
+        }
+    }
+
+    /// Returns whether no result arrived for `ATT_TRANSACTION_TIMEOUT` while some are awaited.
+    fn has_timed_out(&self) -> bool {
+        self.transaction_started
+            .map_or(false, |started| started.elapsed() >= ATT_TRANSACTION_TIMEOUT)
+    }
+
+    /// Ends the transactions which timed out. The operations already cancelled are forgotten and
+    /// the other ones are cancelled, so that their results are dropped if they arrive after all.
+    /// The queued operations are cancelled too, since the bearers can no longer be used. Returns
+    /// the operations cancelled.
+    fn time_out(&mut self) -> Vec<(GattOperation, i32)> {
+        self.operations.retain(|o| o.operation == GattOperation::Discovery || !o.cancelled);
+
+        let mut timed_out = vec![];
+        for o in self.operations.iter_mut() {
+            if o.operation == GattOperation::Discovery || o.result.is_some() {
+                continue;
+            }
+            o.cancelled = true;
+            timed_out.push((o.operation, o.handle));
+        }
+        self.forget_answered();
+        timed_out
+    }
+
+    /// Removes the oldest matching operation in flight once its result arrives. Returns whether
+    /// it was cancelled, in which case the result is dropped.
+    fn complete(&mut self, operation: GattOperation, handle: i32) -> bool {
+        match self.operations.iter().position(|o| o.is_in_flight_as(operation, handle)) {
+            Some(i) => self.operations.remove(i).cancelled,
+            None => false,
+        }
+    }
+
+    /// Records the result of the oldest matching operation in flight. Returns the results to
+    /// deliver, in request order: the results of the operations not tracked are delivered right
+    /// away, and the results of the cancelled ones dropped.
+    fn finish(
+        &mut self,
+        operation: GattOperation,
+        handle: i32,
+        result: OperationResult,
+    ) -> Vec<(GattOperation, i32, OperationResult)> {
+        match self.operations.iter().position(|o| o.is_in_flight_as(operation, handle)) {
+            Some(i) if self.operations[i].cancelled => {
+                self.operations.remove(i);
+            }
+            Some(i) => self.operations[i].result = Some(result),
+            None => return vec![(operation, handle, result)],
+        }
+        self.take_results()
+    }
+
+    /// Removes the results no longer waiting for the results of the operations requested
+    /// before. The cancelled operations were answered already and the discoveries are reported
+    /// on their own, so they do not hold the results back.
+    fn take_results(&mut self) -> Vec<(GattOperation, i32, OperationResult)> {
+        let mut results = vec![];
+        let mut i = 0;
+        while i < self.operations.len() {
+            let o = &self.operations[i];
+            if o.operation == GattOperation::Discovery || o.cancelled {
+                i += 1;
+                continue;
+            }
+            if o.result.is_none() {
+                break;
+            }
+
+            let o = self.operations.remove(i);
+            results.push((o.operation, o.handle, o.result.unwrap()));
+        }
+        results
+    }
+
+    /// Returns whether the oldest matching operation in flight was cancelled.
+    fn is_cancelled(&self, operation: GattOperation, handle: i32) -> bool {
+        self.operations
+            .iter()
+            .find(|o| o.is_in_flight_as(operation, handle))
+            .map_or(false, |o| o.cancelled)
+    }
+
+    /// Cancels the operations selected by `token`, see `IBluetoothGatt::cancel_operation`.
+    fn cancel(&mut self, token: i32) -> Vec<(GattOperation, i32)> {
+        let mut cancelled = vec![];
+        for o in self.operations.iter_mut() {
+            let selected = match token {
+                OPERATION_TOKEN_ALL => true,
+                OPERATION_TOKEN_DISCOVERY => o.operation == GattOperation::Discovery,
+                _ => o.operation != GattOperation::Discovery && o.handle == token,
+            };
+
+            if selected && !o.cancelled {
+                o.cancelled = true;
+                cancelled.push((o.operation, o.handle));
+            }
+        }
+        self.forget_answered();
+        cancelled
+    }
+
+    /// Forgets the cancelled operations not in flight, whose result will not arrive.
+    fn forget_answered(&mut self) {
+        self.operations.retain(|o| !o.cancelled || o.is_in_flight());
+    }
+}
+
+/// Client Characteristic Configuration of a remote characteristic, shared by the local clients.
+///
+/// The device holds a single configuration for all of them, so the value written is the union of
+/// the values wanted by the clients: notifications stay enabled while any client wants them.
+#[derive(Default)]
+struct SharedCccd {
+    // Value wanted by each client, by client ID.
+    wanted: HashMap<i32, u16>,
+    // Value written to the device or being written, None if unknown.
+    written: Option<u16>,
+}
+
+impl SharedCccd {
+    /// Records the value wanted by a client. Returns the value to write to the device, or None if
+    /// the device already holds the union of the wanted values.
+    fn set(&mut self, client_id: i32, value: u16) -> Option<u16> {
+        if value == 0 {
+            self.wanted.remove(&client_id);
+        } else {
+            self.wanted.insert(client_id, value);
+        }
+
+        let union = self.wanted.values().fold(0, |union, value| union | value);
+        if self.written == Some(union) {
+            return None;
+        }
+
+        self.written = Some(union);
+        Some(union)
+    }
+}
+
+// Authentication requirement of the reads done by `IBluetoothGatt::read_service`.
+const AUTH_REQ_NONE: i32 = 0;
+
+/// Purpose of a request of the attribute database of a connection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GattDbRequest {
+    /// `IBluetoothGatt::get_gatt_db`, answered with `on_get_gatt_db`.
+    Client,
+    /// End of a discovery, answered with `on_search_complete`.
+    Discovery,
+}
+
+/// Ongoing `IBluetoothGatt::read_service` on a connection.
+struct ServiceRead {
+    service_uuid: Uuid128Bit,
+    // Readable characteristics left to read, as (UUID, handle).
+    pending: Vec<(Uuid128Bit, i32)>,
+    results: Vec<CharacteristicReadResult>,
+}
+
+impl ServiceRead {
+    fn current_handle(&self) -> Option<i32> {
+        self.pending.first().map(|(_, handle)| *handle)
+    }
+}
+
+// ATT MTU of LE connections until a larger one is negotiated.
+const ATT_DEFAULT_MTU: usize = 23;
+
+// ATT bearers of a connection without EATT, the unenhanced bearer only.
+const ATT_BEARERS_WITHOUT_EATT: usize = 1;
+
+// Sizes of the Write Request header (opcode and handle) and of the Prepare Write Request header
+// (opcode, handle and offset).
+const ATT_WRITE_HEADER_SIZE: usize = 3;
+const ATT_PREPARE_WRITE_HEADER_SIZE: usize = 5;
+
+// Longest attribute value allowed by the Core specification (Vol 3, Part F, 3.2.9).
+const ATT_MAX_VALUE_LEN: usize = 512;
+
+/// Ongoing write of a characteristic value too long for a single ATT PDU, split into prepared
+/// writes.
+struct LongWrite {
+    handle: i32,
+    auth_req: i32,
+    value: Vec<u8>,
+    chunk_size: usize,
+    // Bytes acknowledged by the remote device so far.
+    written: usize,
+    // Status of the part rejected by the remote device, if any.
+    failure: Option<i32>,
+}
+
+impl LongWrite {
+    fn new(handle: i32, auth_req: i32, value: Vec<u8>, mtu: usize) -> LongWrite {
+        LongWrite {
+            handle,
+            auth_req,
+            value,
+            chunk_size: mtu.saturating_sub(ATT_PREPARE_WRITE_HEADER_SIZE).max(1),
+            written: 0,
+            failure: None,
+        }
+    }
+
+    /// Offset and data of the next part to prepare, or `None` once the whole value is queued.
+    fn next_chunk(&self) -> Option<(u16, &[u8])> {
+        if self.written >= self.value.len() {
+            return None;
+        }
+
+        let end = std::cmp::min(self.written + self.chunk_size, self.value.len());
+        Some((self.written as u16, &self.value[self.written..end]))
+    }
+}
+
 /// Callback for GATT Client API.
 pub trait IBluetoothGattCallback: RPCProxy {
     /// When the `register_client` request is done.
@@ -350,46 +1861,84 @@ pub trait IBluetoothGattCallback: RPCProxy {
         status: i32,
         client_id: i32,
         connected: bool,
-        addr: String,
+        addr: BtAddress,
     );
 
     /// When there is a change of PHY.
-    fn on_phy_update(&self, addr: String, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus);
+    fn on_phy_update(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus);
 
     /// The completion of IBluetoothGatt::read_phy.
-    fn on_phy_read(&self, addr: String, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus);
+    fn on_phy_read(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus);
 
     /// When GATT db is available.
-    fn on_search_complete(&self, addr: String, services: Vec<BluetoothGattService>, status: i32);
+    fn on_search_complete(&self, addr: BtAddress, services: Vec<BluetoothGattService>, status: i32);
+
+    /// The completion of IBluetoothGatt::get_gatt_db.
+    fn on_get_gatt_db(&self, addr: BtAddress, services: Vec<BluetoothGattService>);
 
     /// The completion of IBluetoothGatt::read_characteristic.
-    fn on_characteristic_read(&self, addr: String, status: i32, handle: i32, value: Vec<u8>);
+    fn on_characteristic_read(&self, addr: BtAddress, status: i32, handle: i32, value: Vec<u8>);
+
+    /// The completion of IBluetoothGatt::read_service, with the result of each readable
+    /// characteristic of the service.
+    fn on_service_read(
+        &self,
+        addr: BtAddress,
+        service_uuid: Uuid128Bit,
+        results: Vec<CharacteristicReadResult>,
+    );
+
+    /// The completion of IBluetoothGatt::check_conformance, with every problem found.
+    fn on_conformance_report(&self, addr: BtAddress, issues: Vec<ConformanceIssue>);
 
     /// The completion of IBluetoothGatt::write_characteristic.
-    fn on_characteristic_write(&self, addr: String, status: i32, handle: i32);
+    fn on_characteristic_write(&self, addr: BtAddress, status: i32, handle: i32);
+
+    /// When a part of a long characteristic value has been queued by the remote device. The
+    /// write completes with `on_characteristic_write` once all parts have been executed.
+    fn on_characteristic_write_progress(
+        &self,
+        addr: BtAddress,
+        handle: i32,
+        bytes_written: i32,
+        total_bytes: i32,
+    );
 
     /// When a reliable write is completed.
-    fn on_execute_write(&self, addr: String, status: i32);
+    fn on_execute_write(&self, addr: BtAddress, status: i32);
 
     /// The completion of IBluetoothGatt::read_descriptor.
-    fn on_descriptor_read(&self, addr: String, status: i32, handle: i32, value: Vec<u8>);
+    fn on_descriptor_read(&self, addr: BtAddress, status: i32, handle: i32, value: Vec<u8>);
 
     /// The completion of IBluetoothGatt::write_descriptor.
-    fn on_descriptor_write(&self, addr: String, status: i32, handle: i32);
+    fn on_descriptor_write(&self, addr: BtAddress, status: i32, handle: i32);
 
     /// When notification or indication is received.
-    fn on_notify(&self, addr: String, handle: i32, value: Vec<u8>);
+    fn on_notify(&self, addr: BtAddress, handle: i32, value: Vec<u8>);
+
+    /// When a multiple handle value notification is received, with the values of the handles
+    /// the client registered for in the order they were received. These values are neither
+    /// written to the notification pipes nor queued.
+    fn on_notify_multiple(&self, addr: BtAddress, values: Vec<GattHandleValue>);
+
+    /// When values of `handle` were dropped from the notification queue of the client since the
+    /// last batch, see `IBluetoothGatt::set_notification_queue`.
+    fn on_notification_queue_overflow(&self, addr: BtAddress, handle: i32, dropped: u32);
 
     /// The completion of IBluetoothGatt::read_remote_rssi.
-    fn on_read_remote_rssi(&self, addr: String, rssi: i32, status: i32);
+    fn on_read_remote_rssi(&self, addr: BtAddress, rssi: i32, status: i32);
+
+    /// When the RSSI of a device monitored with `IBluetoothGatt::start_rssi_monitor` reaches
+    /// `threshold`: the high threshold if `rssi` is at or above it, the low one otherwise.
+    fn on_rssi_threshold_crossed(&self, addr: BtAddress, rssi: i32, threshold: i32);
 
     /// The completion of IBluetoothGatt::configure_mtu.
-    fn on_configure_mtu(&self, addr: String, mtu: i32, status: i32);
+    fn on_configure_mtu(&self, addr: BtAddress, mtu: i32, status: i32);
 
     /// When a connection parameter changes.
     fn on_connection_updated(
         &self,
-        addr: String,
+        addr: BtAddress,
         interval: i32,
         latency: i32,
         timeout: i32,
@@ -397,974 +1946,9390 @@ pub trait IBluetoothGattCallback: RPCProxy {
     );
 
     /// When there is an addition, removal, or change of a GATT service.
-    fn on_service_changed(&self, addr: String);
-}
+    fn on_service_changed(&self, addr: BtAddress);
 
-/// Interface for scanner callbacks to clients, passed to `IBluetoothGatt::register_scanner`.
-pub trait IScannerCallback {
-    /// When the `register_scanner` request is done.
-    fn on_scanner_registered(&self, status: i32, scanner_id: i32);
-}
+    /// When notifications start being written to a pipe set with
+    /// `IBluetoothGatt::set_notification_pipe` after it has been idle.
+    fn on_notification_pipe_active(&self, addr: BtAddress, handle: i32);
 
-#[derive(Debug, FromPrimitive, ToPrimitive)]
-#[repr(u8)]
-/// GATT write type.
-enum GattDbElementType {
-    PrimaryService = 0,
-    SecondaryService = 1,
-    IncludedService = 2,
-    Characteristic = 3,
-    Descriptor = 4,
+    /// When the Enhanced ATT bearers of a device connected by a client registered with
+    /// `eatt_support` are first known or change, with the MTU of each bearer. No bearer means
+    /// the connection only uses the unenhanced ATT bearer.
+    fn on_eatt_state_changed(&self, addr: BtAddress, bearer_count: i32, bearer_mtus: Vec<i32>);
 }
 
-#[derive(Debug, FromPrimitive, ToPrimitive)]
-#[repr(u8)]
-/// GATT write type.
-pub enum GattWriteType {
-    Invalid = 0,
-    WriteNoRsp = 1,
-    Write = 2,
-    WritePrepare = 3,
-}
+/// Callback for GATT Server API.
+pub trait IBluetoothGattServerCallback: RPCProxy {
+    /// When the `register_server` request is done.
+    fn on_server_registered(&self, status: i32, server_id: i32);
 
-impl Default for GattWriteType {
-    fn default() -> Self {
-        GattWriteType::Write
-    }
-}
+    /// When a peer device connects to or disconnects from the server.
+    fn on_server_connection_state(&self, server_id: i32, connected: bool, addr: BtAddress);
 
-#[derive(Debug, FromPrimitive, ToPrimitive)]
-#[repr(u8)]
-/// Represents LE PHY.
-pub enum LePhy {
-    Invalid = 0,
-    Phy1m = 1,
-    Phy2m = 2,
-    PhyCoded = 3,
-}
+    /// The completion of IBluetoothGatt::add_service, with the handles assigned to the service.
+    fn on_service_added(&self, status: i32, service: BluetoothGattService);
 
-#[derive(Debug, FromPrimitive, ToPrimitive)]
-#[repr(u32)]
-/// Scan type configuration.
-pub enum ScanType {
-    Active = 0,
-    Passive = 1,
-}
+    /// The completion of IBluetoothGatt::remove_service.
+    fn on_service_removed(&self, status: i32, handle: i32);
 
-impl Default for ScanType {
-    fn default() -> Self {
-        ScanType::Active
-    }
-}
+    /// When a peer reads a characteristic. Must be answered with IBluetoothGatt::send_response.
+    fn on_characteristic_read_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        is_long: bool,
+        handle: i32,
+    );
 
-/// Represents RSSI configurations for hardware offloaded scanning.
-// TODO(b/200066804): This is still a placeholder struct, not yet complete.
-#[derive(Debug, Default)]
-pub struct RSSISettings {
-    pub low_threshold: i32,
-    pub high_threshold: i32,
+    /// When a peer reads a descriptor. Must be answered with IBluetoothGatt::send_response.
+    fn on_descriptor_read_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        is_long: bool,
+        handle: i32,
+    );
+
+    /// When a peer writes a characteristic. Must be answered with IBluetoothGatt::send_response
+    /// if `need_response` is set.
+    fn on_characteristic_write_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        len: i32,
+        is_prep: bool,
+        need_response: bool,
+        handle: i32,
+        value: Vec<u8>,
+    );
+
+    /// When a peer writes a descriptor. Must be answered with IBluetoothGatt::send_response if
+    /// `need_response` is set.
+    fn on_descriptor_write_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        len: i32,
+        is_prep: bool,
+        need_response: bool,
+        handle: i32,
+        value: Vec<u8>,
+    );
+
+    /// When a peer executes or cancels its prepared writes.
+    fn on_execute_write(&self, addr: BtAddress, request_id: i32, execute_write: bool);
+
+    /// The completion of IBluetoothGatt::send_notification.
+    fn on_notification_sent(&self, addr: BtAddress, status: i32);
+
+    /// When the MTU of a connection to the server changes.
+    fn on_mtu_changed(&self, addr: BtAddress, mtu: i32);
+
+    /// When a peer writes the Characteristic User Description of the characteristic with the
+    /// given handle, see `IBluetoothGatt::set_user_description`.
+    fn on_user_description_changed(&self, addr: BtAddress, handle: i32, description: String);
+
+    /// When a peer enables or disables the broadcast of the characteristic with the given handle
+    /// in its Server Characteristic Configuration. The server is expected to include the value of
+    /// the characteristic in its advertising data while `broadcast` is set. `addr` is all zeroes
+    /// when the broadcast is restored as the service is added.
+    fn on_server_configuration_changed(&self, addr: BtAddress, handle: i32, broadcast: bool);
+
+    /// When the PHY of a connection to the server changes.
+    fn on_phy_update(&self, addr: BtAddress, tx_phy: LePhy, rx_phy: LePhy, status: GattStatus);
+
+    /// When the parameters of a connection to the server change.
+    fn on_connection_updated(
+        &self,
+        addr: BtAddress,
+        interval: i32,
+        latency: i32,
+        timeout: i32,
+        status: i32,
+    );
 }
 
-/// Represents scanning configurations to be passed to `IBluetoothGatt::start_scan`.
-#[derive(Debug, Default)]
-pub struct ScanSettings {
-    pub interval: i32,
-    pub window: i32,
-    pub scan_type: ScanType,
-    pub rssi_settings: RSSISettings,
+/// Hook deciding which clients may scan, for the platforms tying LE scanning to the location
+/// permission. Set with `BluetoothGatt::set_scan_permission_checker`, every client may scan
+/// otherwise.
+pub trait IScanPermissionChecker {
+    /// Returns whether the client identified by `sender`, the unique bus name of its D-Bus
+    /// connection, or empty for the scanners of the daemon itself, may scan. Consulted when the
+    /// client starts a scan and for each result delivered to it, so it must answer quickly,
+    /// keeping its decisions rather than asking again each time.
+    fn is_scan_permitted(&self, sender: &String) -> bool;
 }
 
-/// Represents a scan filter to be passed to `IBluetoothGatt::start_scan`.
-#[derive(Debug, Default)]
-pub struct ScanFilter {}
+/// Interface of the agent deciding on the incoming connections, passed to
+/// `IBluetoothGatt::register_peripheral_connection_agent`.
+pub trait IPeripheralConnectionAgent {
+    /// When a central connects while the policy is `PeripheralConnectionPolicy::AskAgent`. The
+    /// agent answers with `IBluetoothGatt::respond_peripheral_connection`.
+    fn on_peripheral_connection_request(&self, addr: BtAddress);
+}
 
-/// Implementation of the GATT API (IBluetoothGatt).
-pub struct BluetoothGatt {
-    intf: Arc<Mutex<BluetoothInterface>>,
-    gatt: Option<Gatt>,
+/// Interface for periodic advertising sync callbacks to clients, passed to
+/// `IBluetoothGatt::start_sync` and `IBluetoothGatt::sync_tx_parameters`.
+pub trait IPeriodicAdvertisingCallback {
+    /// When the sync is established, or failed to be if `status` is not 0.
+    fn on_sync_started(
+        &self,
+        sync_handle: i32,
+        status: i32,
+        sid: i32,
+        addr_type: i32,
+        address: BtAddress,
+        phy: i32,
+        interval: i32,
+    );
 
-    context_map: ContextMap,
-    reliable_queue: HashSet<String>,
+    /// When periodic advertising data is received on the sync.
+    fn on_sync_report(
+        &self,
+        sync_handle: i32,
+        tx_power: i32,
+        rssi: i32,
+        status: i32,
+        data: Vec<u8>,
+    );
+
+    /// When the sync is lost. The sync handle is no longer valid.
+    fn on_sync_lost(&self, sync_handle: i32);
+
+    /// The completion of `IBluetoothGatt::transfer_sync`.
+    fn on_sync_transferred(&self, address: BtAddress, status: i32);
 }
 
-impl BluetoothGatt {
-    /// Constructs a new IBluetoothGatt implementation.
-    pub fn new(intf: Arc<Mutex<BluetoothInterface>>) -> BluetoothGatt {
-        BluetoothGatt {
-            intf: intf,
-            gatt: None,
-            context_map: ContextMap::new(),
-            reliable_queue: HashSet::new(),
-        }
+// Ranges of the parameters of the periodic advertising syncs and of their transfers, as
+// accepted by the HCI commands.
+const PERIODIC_SID_RANGE: RangeInclusive<i32> = 0..=0x0F;
+const PERIODIC_SKIP_RANGE: RangeInclusive<i32> = 0..=0x01F3;
+const PERIODIC_SYNC_TIMEOUT_RANGE: RangeInclusive<i32> = 0x000A..=0x4000;
+const PERIODIC_SYNC_HANDLE_RANGE: RangeInclusive<i32> = 0..=0x0EFF;
+const PERIODIC_SERVICE_DATA_RANGE: RangeInclusive<i32> = 0..=0xFFFF;
+const ADVERTISING_HANDLE_RANGE: RangeInclusive<i32> = 0..=0xEF;
+const PAST_MODE_RANGE: RangeInclusive<i32> = 0..=0x03;
+
+/// Converts a parameter of a periodic advertising sync to the type of the HCI command, refusing
+/// the values out of `range` rather than truncating them.
+fn sync_parameter<T: TryFrom<i32>>(
+    name: &str,
+    value: i32,
+    range: RangeInclusive<i32>,
+) -> BtResult<T> {
+    match range.contains(&value) {
+        true => T::try_from(value).map_err(|_| BtError::invalid_argument(name)),
+        false => Err(BtError::invalid_argument(format!(
+            "{} {} is out of range {}..={}",
+            name,
+            value,
+            range.start(),
+            range.end()
+        ))),
     }
+}
 
-    pub fn init_profiles(&mut self, tx: Sender<Message>) {
-        self.gatt = Gatt::new(&self.intf.lock().unwrap());
-        self.gatt.as_mut().unwrap().initialize(
-            GattClientCallbacksDispatcher {
-                dispatch: Box::new(move |cb| {
-                    let tx_clone = tx.clone();
-                    topstack::get_runtime().spawn(async move {
-                        let _ = tx_clone.send(Message::GattClient(cb)).await;
-                    });
-                }),
-            },
-            GattServerCallbacksDispatcher {
-                dispatch: Box::new(move |cb| {
-                    // TODO(b/193685149): Implement the callbacks
-                    debug!("received Gatt server callback: {:?}", cb);
-                }),
-            },
-            GattScannerCallbacksDispatcher {
-                dispatch: Box::new(move |cb| {
-                    debug!("received Gatt scanner callback: {:?}", cb);
-                }),
-            },
-        );
+/// Periodic advertising sync requested by a client.
+struct PeriodicSync {
+    sid: u8,
+    address: BtAddress,
+    // None until the sync is established.
+    handle: Option<u16>,
+    callback: Box<dyn IPeriodicAdvertisingCallback + Send>,
+}
+
+/// Interface for scanner callbacks to clients, passed to `IBluetoothGatt::register_scanner`.
+pub trait IScannerCallback: RPCProxy {
+    /// When the `register_scanner` request is done.
+    fn on_scanner_registered(&self, status: i32, scanner_id: i32);
+
+    /// When an LE advertisement is found by an ongoing scan.
+    fn on_scan_result(&self, scan_result: ScanResult);
+
+    /// The results found during the report delay of the scan, in the order they were found, see
+    /// `ScanSettings::report_delay_ms`.
+    fn on_scan_result_batch(&self, scan_results: Vec<ScanResult>);
+
+    /// When a device reported with `on_scan_result` has not matched the scan for the timeout set
+    /// in `ScanSettings::match_lost_timeout_ms`, if `ScanSettings::callback_type` reports lost
+    /// matches. `scan_result` is the last result of the device.
+    fn on_scan_result_lost(&self, scan_result: ScanResult);
+
+    /// When the batch scan results requested by `batch_scan_read_reports` are read.
+    fn on_batch_scan_reports(&self, scanner_id: i32, status: i32, results: Vec<BatchScanResult>);
+
+    /// When the batch scan storage is filled above the threshold set with
+    /// `batch_scan_config_storage`. The stored results are read and delivered right after.
+    fn on_batch_scan_threshold_crossed(&self, scanner_id: i32);
+
+    /// When the scan interval and window used by the controller for the scanner change, which
+    /// may be more aggressive than the ones requested by the scanner.
+    fn on_scan_parameters_changed(&self, scanner_id: i32, interval: i32, window: i32);
+
+    /// When the duty cycle of the scanner changes, in permille of the time. `requested` is the
+    /// duty cycle of its scan parameters and `effective` the one it gets on average, which is
+    /// lower while aggressive scans of other scanners compete with it, see
+    /// `ScanSettings::priority`.
+    fn on_scan_duty_cycle_changed(&self, scanner_id: i32, requested: i32, effective: i32);
+
+    /// When an advertisement matches a subscription added with
+    /// `IBluetoothGatt::subscribe_manufacturer_data`. `data` is the manufacturer specific data
+    /// following the company identifier.
+    fn on_manufacturer_data_found(
+        &self,
+        scanner_id: i32,
+        subscription_id: u32,
+        addr: BtAddress,
+        rssi: i32,
+        data: Vec<u8>,
+    );
+}
+
+#[derive(Debug, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+/// GATT write type.
+enum GattDbElementType {
+    PrimaryService = 0,
+    SecondaryService = 1,
+    IncludedService = 2,
+    Characteristic = 3,
+    Descriptor = 4,
+}
+
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+/// GATT write type.
+pub enum GattWriteType {
+    Invalid = 0,
+    WriteNoRsp = 1,
+    Write = 2,
+    WritePrepare = 3,
+}
+
+impl Default for GattWriteType {
+    fn default() -> Self {
+        GattWriteType::Write
     }
 }
 
-// Temporary util that covers only basic string conversion.
-// TODO(b/193685325): Implement more UUID utils by using Uuid from gd/hci/uuid.h with cxx.
-fn parse_uuid_string<T: Into<String>>(uuid: T) -> Option<Uuid> {
-    let uuid = uuid.into();
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u8)]
+/// Represents LE PHY.
+pub enum LePhy {
+    Invalid = 0,
+    Phy1m = 1,
+    Phy2m = 2,
+    PhyCoded = 3,
+}
 
-    if uuid.len() != 32 {
-        return None;
+impl Default for LePhy {
+    fn default() -> Self {
+        LePhy::Phy1m
     }
+}
 
-    let mut raw = [0; 16];
+#[derive(Debug, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+/// Scan type configuration.
+pub enum ScanType {
+    Active = 0,
+    Passive = 1,
+}
 
-    for i in 0..16 {
-        let byte = u8::from_str_radix(&uuid[i * 2..i * 2 + 2], 16);
-        if byte.is_err() {
-            return None;
-        }
-        raw[i] = byte.unwrap();
+impl Default for ScanType {
+    fn default() -> Self {
+        ScanType::Active
     }
+}
 
-    Some(Uuid { uu: raw })
+/// Results delivered to a scanner, see `ScanSettings::callback_type`.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ScanCallbackType {
+    /// Every matching advertisement is delivered with `on_scan_result`.
+    AllMatches = 0,
+    /// Only the advertisement finding a device is delivered, until the device is lost. A device
+    /// is found by its first matching advertisement, or its `ScanSettings::match_sightings`-th
+    /// one within `ScanSettings::match_sightings_window_ms`.
+    FirstMatch = 1,
+    /// Only the lost devices are delivered, with `on_scan_result_lost`.
+    MatchLost = 2,
+    /// Both `FirstMatch` and `MatchLost`.
+    FirstMatchAndMatchLost = 3,
 }
 
-#[derive(Debug, FromPrimitive, ToPrimitive)]
-#[repr(u8)]
-/// Status of WriteCharacteristic methods.
-pub enum GattWriteRequestStatus {
-    Success = 0,
-    Fail = 1,
-    Busy = 2,
+impl Default for ScanCallbackType {
+    fn default() -> Self {
+        ScanCallbackType::AllMatches
+    }
 }
 
-impl IBluetoothGatt for BluetoothGatt {
-    fn register_scanner(&self, _callback: Box<dyn IScannerCallback + Send>) {
-        // TODO(b/200066804): implement
+/// Priority class of a scanner, see `ScanSettings::priority`.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ScanPriority {
+    /// Scans in the background, which yield to the aggressive scans of the other classes.
+    Background = 0,
+    Normal = 1,
+    /// Scans of the application in the foreground.
+    Foreground = 2,
+}
+
+impl Default for ScanPriority {
+    fn default() -> Self {
+        ScanPriority::Normal
     }
+}
 
-    fn unregister_scanner(&self, _scanner_id: i32) {
-        // TODO(b/200066804): implement
+/// Forms of the advertising data delivered in the scan results, see
+/// `ScanSettings::record_delivery`.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ScanRecordDelivery {
+    /// Both `ScanResult::adv_data` and `ScanResult::scan_record`.
+    RawAndParsed = 0,
+    /// Only `ScanResult::adv_data`, `ScanResult::scan_record` being left empty.
+    RawOnly = 1,
+    /// Only `ScanResult::scan_record`, `ScanResult::adv_data` being left empty.
+    ParsedOnly = 2,
+}
+
+impl Default for ScanRecordDelivery {
+    fn default() -> Self {
+        ScanRecordDelivery::RawAndParsed
     }
+}
 
-    fn start_scan(&self, _scanner_id: i32, _settings: ScanSettings, _filters: Vec<ScanFilter>) {
-        // TODO(b/200066804): implement
+impl ScanRecordDelivery {
+    fn includes_raw(&self) -> bool {
+        *self != ScanRecordDelivery::ParsedOnly
     }
 
-    fn stop_scan(&self, _scanner_id: i32) {
-        // TODO(b/200066804): implement
+    fn includes_parsed(&self) -> bool {
+        *self != ScanRecordDelivery::RawOnly
     }
+}
 
-    fn register_client(
-        &mut self,
-        app_uuid: String,
-        callback: Box<dyn IBluetoothGattCallback + Send>,
-        eatt_support: bool,
-    ) {
-        let uuid = parse_uuid_string(app_uuid).unwrap();
-        self.context_map.add(&uuid.uu, callback);
-        self.gatt.as_ref().unwrap().client.register_client(&uuid, eatt_support);
+impl ScanCallbackType {
+    fn reports_first_match(&self) -> bool {
+        matches!(self, ScanCallbackType::FirstMatch | ScanCallbackType::FirstMatchAndMatchLost)
     }
 
-    fn unregister_client(&mut self, client_id: i32) {
-        self.context_map.remove(client_id);
-        self.gatt.as_ref().unwrap().client.unregister_client(client_id);
+    fn reports_match_lost(&self) -> bool {
+        matches!(self, ScanCallbackType::MatchLost | ScanCallbackType::FirstMatchAndMatchLost)
+    }
+}
+
+/// Kind of results stored by the controller while batch scanning.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum BatchScanMode {
+    Disabled = 0,
+    /// Only the address, TX power, RSSI and timestamp of each advertisement are stored.
+    Truncated = 1,
+    /// The advertising data and scan response are stored as well.
+    Full = 2,
+    TruncatedAndFull = 3,
+}
+
+impl Default for BatchScanMode {
+    fn default() -> Self {
+        BatchScanMode::Disabled
+    }
+}
+
+/// Results discarded by the controller when the batch scan storage is full.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum BatchScanDiscardRule {
+    Oldest = 0,
+    WeakestRssi = 1,
+}
+
+impl Default for BatchScanDiscardRule {
+    fn default() -> Self {
+        BatchScanDiscardRule::Oldest
+    }
+}
+
+/// Represents an LE advertisement stored by the controller while batch scanning, delivered
+/// with `IScannerCallback::on_batch_scan_reports`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BatchScanResult {
+    pub address: String,
+    pub addr_type: u8,
+    pub tx_power: i32,
+    pub rssi: i32,
+    /// Timestamp of the result in milliseconds, as reported by the controller.
+    pub timestamp_millis: i32,
+    /// Advertising data followed by the scan response. Empty for truncated results.
+    pub adv_data: Vec<u8>,
+}
+
+// Size of a truncated batch scan record: address, address type, TX power, RSSI and timestamp.
+const BATCH_SCAN_TRUNCATED_RECORD_LEN: usize = 11;
+
+// The controller timestamps batch scan results in units of 50 ms.
+const BATCH_SCAN_TIMESTAMP_UNIT_MILLIS: i32 = 50;
+
+// Own address type used while batch scanning.
+const BATCH_SCAN_OWN_ADDR_TYPE_PUBLIC: i32 = 0;
+
+/// Parses the records of a batch scan report. Parsing stops at the first malformed record.
+fn parse_batch_scan_records(
+    report_format: i32,
+    num_records: i32,
+    data: &[u8],
+) -> Vec<BatchScanResult> {
+    let full = match BatchScanMode::from_i32(report_format) {
+        Some(BatchScanMode::Truncated) => false,
+        Some(BatchScanMode::Full) => true,
+        _ => return vec![],
+    };
+
+    let mut results = vec![];
+    let mut rest = data;
+    while results.len() < num_records.max(0) as usize {
+        if rest.len() < BATCH_SCAN_TRUNCATED_RECORD_LEN {
+            break;
+        }
+
+        // The address is little-endian.
+        let mut address = [0u8; 6];
+        address.copy_from_slice(&rest[..6]);
+        address.reverse();
+
+        let mut result = BatchScanResult {
+            address: RawAddress { val: address }.to_string(),
+            addr_type: rest[6],
+            tx_power: (rest[7] as i8).into(),
+            rssi: (rest[8] as i8).into(),
+            timestamp_millis: i32::from(u16::from_le_bytes([rest[9], rest[10]]))
+                * BATCH_SCAN_TIMESTAMP_UNIT_MILLIS,
+            adv_data: vec![],
+        };
+        rest = &rest[BATCH_SCAN_TRUNCATED_RECORD_LEN..];
+
+        if full {
+            // Length prefixed advertising data then scan response.
+            for _ in 0..2 {
+                let len = match rest.first() {
+                    Some(len) if rest.len() > *len as usize => *len as usize,
+                    _ => return results,
+                };
+                result.adv_data.extend_from_slice(&rest[1..len + 1]);
+                rest = &rest[len + 1..];
+            }
+        }
+
+        results.push(result);
     }
 
-    fn client_connect(
-        &self,
-        client_id: i32,
-        addr: String,
-        is_direct: bool,
-        transport: i32,
-        opportunistic: bool,
-        phy: i32,
-    ) {
-        let address = match RawAddress::from_string(addr.clone()) {
-            None => return,
-            Some(addr) => addr,
-        };
+    results
+}
+
+/// Represents RSSI configurations for hardware offloaded scanning.
+// TODO(b/200066804): This is still a placeholder struct, not yet complete.
+#[derive(Debug, Default)]
+pub struct RSSISettings {
+    pub low_threshold: i32,
+    pub high_threshold: i32,
+}
+
+/// State of a GATT connection, returned by `IBluetoothGatt::get_connection_info`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GattConnectionInfo {
+    pub address: String,
+    pub conn_id: i32,
+    /// Negotiated ATT MTU.
+    pub mtu: i32,
+    /// Profile applied to the link when the device connected, or later by an override.
+    pub link_tuning_profile: LinkTuningProfile,
+    /// Whether `link_tuning_profile` was set by a client rather than chosen from the services.
+    pub link_tuning_overridden: bool,
+}
+
+/// Bits of `ScanSettings::phys`, as in the Scanning_PHYs of the LE Set Extended Scan Parameters
+/// command.
+pub const SCAN_PHY_LE_1M: u8 = 0x01;
+pub const SCAN_PHY_LE_CODED: u8 = 0x04;
+
+/// Opcodes of the LE Set Scan Enable and LE Set Extended Scan Enable commands, starting or
+/// stopping the scan of the controller.
+const HCI_LE_SET_SCAN_ENABLE: u16 = 0x200C;
+const HCI_LE_SET_EXTENDED_SCAN_ENABLE: u16 = 0x2042;
+
+/// Represents scanning configurations to be passed to `IBluetoothGatt::start_scan`.
+#[derive(Debug, Default)]
+pub struct ScanSettings {
+    /// Scan interval in units of 0.625 ms. 0 to use the default parameters.
+    pub interval: i32,
+    /// Scan window in units of 0.625 ms, at most `interval`. 0 to use the default parameters.
+    pub window: i32,
+    pub scan_type: ScanType,
+    pub rssi_settings: RSSISettings,
+    /// Window of the exponentially weighted moving average applied to the RSSI of each found
+    /// device, capped at `MAX_RSSI_SMOOTHING_WINDOW`. Values of 0 or 1 disable smoothing.
+    pub rssi_smoothing_window: i32,
+    /// If not empty, only the results from these addresses are reported.
+    pub allowed_addresses: Vec<BtAddress>,
+    /// The results from these addresses are never reported.
+    pub denied_addresses: Vec<BtAddress>,
+    pub callback_type: ScanCallbackType,
+    /// Time in milliseconds without a matching advertisement after which a device is lost, for
+    /// the callback types other than `AllMatches`. 0 to use the default of 10 seconds.
+    pub match_lost_timeout_ms: i32,
+    /// Matching advertisements of a device required within `match_sightings_window_ms` for it
+    /// to be found, for the callback types other than `AllMatches`. 0 or 1 to find a device on
+    /// its first match, so that a device only passing by is not reported.
+    pub match_sightings: i32,
+    /// Time in milliseconds in which the sightings of a device must be matched. 0 to use the
+    /// default of 1 second.
+    pub match_sightings_window_ms: i32,
+    /// Priority class of the scanner. The scans with a duty cycle above 50% are only honored for
+    /// the scanners of the highest class requesting one, taking turns between them.
+    pub priority: ScanPriority,
+    /// Forms of the advertising data delivered in the results. Delivering a single form reduces
+    /// the size of the results of the scanners receiving many of them.
+    pub record_delivery: ScanRecordDelivery,
+    /// PHYs of the primary advertising channels scanned on, as a bitmask of `SCAN_PHY_LE_1M` and
+    /// `SCAN_PHY_LE_CODED`. 0 to scan on the LE 1M PHY only. The controller scans on the PHYs of
+    /// all the scanners, each scanner only receiving the advertisements found on its own PHYs.
+    pub phys: u8,
+    /// Time in milliseconds the results are held before they are delivered together with
+    /// `IScannerCallback::on_scan_result_batch`, waking the client up less often. 0 to deliver
+    /// each result with `on_scan_result` as it is found.
+    pub report_delay_ms: i32,
+}
+
+/// Represents an LE advertisement found by a scan, delivered with
+/// `IScannerCallback::on_scan_result`.
+#[derive(Clone, Debug, Default)]
+pub struct ScanResult {
+    pub address: BtAddress,
+    pub addr_type: u8,
+    pub event_type: u16,
+    pub primary_phy: u8,
+    pub secondary_phy: u8,
+    /// PHY the advertisement was received on, `primary_phy` as an `LePhy`.
+    pub primary_le_phy: LePhy,
+    /// PHY the advertising data of an extended advertisement was received on, `secondary_phy` as
+    /// an `LePhy`. `LePhy::Invalid` for the legacy advertisements.
+    pub secondary_le_phy: LePhy,
+    pub advertising_sid: u8,
+    /// Identity address of the advertiser. A resolvable private address is resolved with the
+    /// keys of the bonded devices, and the result is empty if none of them resolves it. The
+    /// other addresses are their own identity.
+    pub identity_address: String,
+    /// Whether `identity_address` is the address of a bonded device.
+    pub is_bonded: bool,
+    pub tx_power: i32,
+    /// RSSI as reported by the controller.
+    pub rssi: i32,
+    /// RSSI after applying the platform calibration offset and the smoothing configured in
+    /// `ScanSettings::rssi_smoothing_window`.
+    pub smoothed_rssi: i32,
+    pub periodic_adv_int: u16,
+    pub adv_data: Vec<u8>,
+    /// `adv_data` parsed into its AD structures.
+    pub scan_record: ScanRecord,
+}
+
+/// The AD structures of an advertisement commonly used by clients.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScanRecord {
+    /// Complete local name, or the shortened one if the complete name is not advertised.
+    pub name: String,
+    pub service_uuids: Vec<Uuid128Bit>,
+    /// Service data, keyed by service UUID.
+    pub service_data: HashMap<crate::uuid::BtUuid, Vec<u8>>,
+    /// Manufacturer specific data, keyed by company identifier.
+    pub manufacturer_data: HashMap<u16, Vec<u8>>,
+    /// TX power level in dBm, or `TX_POWER_NOT_PRESENT`.
+    pub tx_power_level: i32,
+    /// Value of the Flags AD type, 0 if not advertised.
+    pub flags: u8,
+}
+
+impl ScanRecord {
+    pub const TX_POWER_NOT_PRESENT: i32 = 127;
+
+    /// Parses the AD structures of `adv_data`, ignoring anything after the first malformed one.
+    pub fn from_adv_data(adv_data: &[u8]) -> ScanRecord {
+        let mut record = ScanRecord {
+            service_uuids: advertised_service_uuids(adv_data),
+            tx_power_level: ScanRecord::TX_POWER_NOT_PRESENT,
+            ..Default::default()
+        };
+
+        for (ad_type, data) in ad_structures(adv_data) {
+            match ad_type {
+                AD_TYPE_FLAGS => {
+                    record.flags = data.first().cloned().unwrap_or(0);
+                }
+                AD_TYPE_SHORTENED_LOCAL_NAME if record.name.is_empty() => {
+                    record.name = String::from_utf8_lossy(data).into_owned();
+                }
+                AD_TYPE_COMPLETE_LOCAL_NAME => {
+                    record.name = String::from_utf8_lossy(data).into_owned();
+                }
+                AD_TYPE_TX_POWER_LEVEL => {
+                    if let Some(level) = data.first() {
+                        record.tx_power_level = *level as i8 as i32;
+                    }
+                }
+                AD_TYPE_SERVICE_DATA_UUID16
+                | AD_TYPE_SERVICE_DATA_UUID32
+                | AD_TYPE_SERVICE_DATA_UUID128 => {
+                    let len = match ad_type {
+                        AD_TYPE_SERVICE_DATA_UUID16 => 2,
+                        AD_TYPE_SERVICE_DATA_UUID32 => 4,
+                        _ => 16,
+                    };
+                    if data.len() >= len {
+                        let uuid = uuid_from_le_bytes(&data[..len]);
+                        record
+                            .service_data
+                            .insert(crate::uuid::BtUuid::from(uuid), data[len..].to_vec());
+                    }
+                }
+                AD_TYPE_MANUFACTURER_DATA if data.len() >= 2 => {
+                    record
+                        .manufacturer_data
+                        .insert(u16::from_le_bytes([data[0], data[1]]), data[2..].to_vec());
+                }
+                _ => {}
+            }
+        }
+
+        record
+    }
+}
+
+/// Consults the scan permission checker, if any, for the scanner of `sender`. The changes of
+/// decision are logged for auditing, `last` holding the previous decision of the scanner.
+fn check_scan_permission(
+    checker: Option<&(dyn IScanPermissionChecker + Send)>,
+    sender: &String,
+    scanner_id: i32,
+    last: &mut Option<bool>,
+) -> bool {
+    let permitted = checker.map_or(true, |c| c.is_scan_permitted(sender));
+    if *last != Some(permitted) {
+        if permitted {
+            info!("Scan permission audit: scanner {} of {} is permitted", scanner_id, sender);
+        } else {
+            warn!("Scan permission audit: scanner {} of {} is denied", scanner_id, sender);
+        }
+        *last = Some(permitted);
+    }
+    permitted
+}
+
+/// Time without a matching advertisement after which a device is lost by default.
+const DEFAULT_MATCH_LOST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time in which the sightings of a device must be matched for it to be found by default.
+const DEFAULT_MATCH_SIGHTINGS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Period of the check for lost devices, while scanners track their matches.
+const MATCH_LOST_CHECK_PERIOD: Duration = Duration::from_secs(1);
+
+/// Shortest liveness interval of a scanner.
+const MIN_SCANNER_LIVENESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of pings in a row a scanner with a liveness interval may miss before it is reclaimed.
+const SCANNER_MISSED_PINGS: u32 = 3;
+
+/// Results a scanner with a report delay holds at most, delivered early once reached.
+const MAX_BATCHED_SCAN_RESULTS: usize = 256;
+
+/// Devices matched by a scanner, for the callback types other than `AllMatches`.
+///
+/// A device is found once it matched `sightings` times within `window`, and lost once it did not
+/// match for `timeout`. While the filters of the scanner are offloaded to APCF, the controller
+/// tracks the devices instead and reports them found and lost.
+struct MatchTracker {
+    timeout: Duration,
+    sightings: usize,
+    window: Duration,
+    // Whether the controller tracks the devices, see `BluetoothGatt::offload_apcf_filters`.
+    offloaded: bool,
+    // Time of the last matching advertisement and last result of each found device.
+    devices: HashMap<BtAddress, (Instant, ScanResult)>,
+    // Times of the matches within the window of the devices not found yet.
+    candidates: HashMap<BtAddress, Vec<Instant>>,
+}
+
+impl MatchTracker {
+    fn new(timeout: Duration) -> MatchTracker {
+        MatchTracker {
+            timeout,
+            sightings: 1,
+            window: DEFAULT_MATCH_SIGHTINGS_WINDOW,
+            offloaded: false,
+            devices: HashMap::new(),
+            candidates: HashMap::new(),
+        }
+    }
+
+    /// Requires `sightings` matches within `window` for a device to be found.
+    fn with_sightings(mut self, sightings: usize, window: Duration) -> MatchTracker {
+        self.sightings = sightings.max(1);
+        self.window = window;
+        self
+    }
+
+    /// Records a matching result received at `now`. Returns whether the device is found by this
+    /// match. The results are ignored while the controller tracks the devices.
+    fn update(&mut self, now: Instant, result: ScanResult) -> bool {
+        if self.offloaded {
+            return false;
+        }
+        if let Some(device) = self.devices.get_mut(&result.address) {
+            *device = (now, result);
+            return false;
+        }
+
+        let window = self.window;
+        let sightings = self.candidates.entry(result.address).or_default();
+        sightings.retain(|seen| now.saturating_duration_since(*seen) < window);
+        sightings.push(now);
+        if sightings.len() < self.sightings {
+            return false;
+        }
+
+        self.candidates.remove(&result.address);
+        self.devices.insert(result.address, (now, result));
+        true
+    }
+
+    /// Records a device found by the controller at `now`. Returns whether it was not found yet.
+    fn found(&mut self, now: Instant, result: ScanResult) -> bool {
+        self.candidates.remove(&result.address);
+        self.devices.insert(result.address, (now, result)).is_none()
+    }
+
+    /// Forgets a device lost by the controller and returns its last result, if it was found.
+    fn lose(&mut self, address: &BtAddress) -> Option<ScanResult> {
+        self.devices.remove(address).map(|(_, result)| result)
+    }
+
+    /// Returns the on found and on lost parameters of the APCF filters tracking the devices in
+    /// the controller, or None if they cannot hold the settings of the tracker.
+    fn apcf_tracking(&self) -> Option<ApcfTracking> {
+        Some(ApcfTracking {
+            found_timeout_ms: u16::try_from(self.window.as_millis()).ok()?,
+            found_count: u8::try_from(self.sightings).ok()?,
+            lost_timeout_ms: u16::try_from(self.timeout.as_millis()).ok()?,
+        })
+    }
+
+    /// Forgets the devices not matched for the timeout at `now` and returns their last results,
+    /// as well as the sightings out of the window. The devices tracked by the controller are only
+    /// lost once it reports them.
+    fn expire(&mut self, now: Instant) -> Vec<ScanResult> {
+        let window = self.window;
+        self.candidates.retain(|_, sightings| {
+            sightings.last().map_or(false, |seen| now.saturating_duration_since(*seen) < window)
+        });
+        if self.offloaded {
+            return vec![];
+        }
+
+        let timeout = self.timeout;
+        let lost: Vec<BtAddress> = self
+            .devices
+            .iter()
+            .filter(|(_, (seen, _))| now.saturating_duration_since(*seen) >= timeout)
+            .map(|(address, _)| *address)
+            .collect();
+
+        lost.iter().filter_map(|address| self.devices.remove(address)).map(|(_, r)| r).collect()
+    }
+
+    /// Returns whether `expire` may forget any device or sighting.
+    fn may_expire(&self) -> bool {
+        !self.candidates.is_empty() || (!self.offloaded && !self.devices.is_empty())
+    }
+}
+
+/// Allow-list and deny-list of advertiser addresses of a scanner, checked before any other
+/// processing of the scan results.
+#[derive(Default)]
+struct AddressFilter {
+    allowed: HashSet<BtAddress>,
+    denied: HashSet<BtAddress>,
+}
+
+impl AddressFilter {
+    fn new(allowed: Vec<BtAddress>, denied: Vec<BtAddress>) -> AddressFilter {
+        AddressFilter {
+            allowed: allowed.into_iter().collect(),
+            denied: denied.into_iter().collect(),
+        }
+    }
+
+    fn permits(&self, address: &BtAddress) -> bool {
+        !self.denied.contains(address)
+            && (self.allowed.is_empty() || self.allowed.contains(address))
+    }
+}
+
+/// A notification pipe is considered idle if nothing was written to it for this long.
+const NOTIFICATION_PIPE_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Notifications waiting to be written to a pipe, beyond which they are dropped.
+const NOTIFICATION_PIPE_BACKLOG: usize = 64;
+
+/// Writes the notifications of a characteristic to a pipe from a task of its own, so that a slow
+/// reader never holds the main loop up.
+struct NotificationPipe {
+    frames: Sender<Vec<u8>>,
+    last_write: Option<Instant>,
+}
+
+impl NotificationPipe {
+    fn new(pipe: File) -> NotificationPipe {
+        let (frames, mut rx) = channel::<Vec<u8>>(NOTIFICATION_PIPE_BACKLOG);
+        tokio::spawn(async move {
+            let mut pipe = tokio::fs::File::from_std(pipe);
+            while let Some(frame) = rx.recv().await {
+                if let Err(e) = async {
+                    pipe.write_all(&frame).await?;
+                    pipe.flush().await
+                }
+                .await
+                {
+                    // Dropping the receiver lets the next notification fall back to `on_notify`.
+                    warn!("Failed to write to notification pipe: {}", e);
+                    break;
+                }
+            }
+        });
+
+        NotificationPipe { frames, last_write: None }
+    }
+}
+
+/// Frames a notification value to be written to a notification pipe.
+fn frame_notification(value: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(value.len() + 2);
+    frame.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    frame.extend_from_slice(value);
+    frame
+}
+
+/// Returns the bit of `ScanSettings::phys` of the PHY an advertisement was received on.
+fn scan_phy_bit(primary_phy: u8) -> u8 {
+    match LePhy::from_u8(primary_phy) {
+        Some(LePhy::PhyCoded) => SCAN_PHY_LE_CODED,
+        _ => SCAN_PHY_LE_1M,
+    }
+}
+
+/// Sends `Message::ScanResultBatchFlush` for a scanner after `delay`.
+fn schedule_scan_result_flush(
+    tx: Option<Sender<Message>>,
+    scanner_id: i32,
+    delay: Duration,
+) -> Option<JoinHandle<()>> {
+    let tx = tx?;
+    Some(tokio::spawn(async move {
+        time::sleep(delay).await;
+        let _ = tx.send(Message::ScanResultBatchFlush(scanner_id)).await;
+    }))
+}
+
+/// Sends `Message::ScannerLivenessCheck` for a scanner after `delay`.
+fn schedule_liveness_check(
+    tx: Option<Sender<Message>>,
+    scanner_id: i32,
+    delay: Duration,
+) -> Option<JoinHandle<()>> {
+    let tx = tx?;
+    Some(tokio::spawn(async move {
+        time::sleep(delay).await;
+        let _ = tx.send(Message::ScannerLivenessCheck(scanner_id)).await;
+    }))
+}
+
+/// Scan interval and window used when the scanner does not set any, in units of 0.625 ms.
+const DEFAULT_SCAN_PARAMETERS: ScanParameters = ScanParameters { interval: 6553, window: 1638 };
+
+// Range of the scan interval and window allowed by the specification.
+const MIN_SCAN_PARAMETER: i32 = 0x4;
+const MAX_SCAN_PARAMETER: i32 = 0x4000;
+
+/// LE scan interval and window, in units of 0.625 ms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ScanParameters {
+    interval: u16,
+    window: u16,
+}
+
+impl ScanParameters {
+    /// Returns the parameters from the interval and window of `ScanSettings`, or None if they are
+    /// not valid.
+    fn new(interval: i32, window: i32) -> Option<ScanParameters> {
+        if interval == 0 && window == 0 {
+            return Some(DEFAULT_SCAN_PARAMETERS);
+        }
+
+        let range = MIN_SCAN_PARAMETER..=MAX_SCAN_PARAMETER;
+        if !range.contains(&interval) || !range.contains(&window) || window > interval {
+            return None;
+        }
+
+        Some(ScanParameters { interval: interval as u16, window: window as u16 })
+    }
+
+    /// Whether these parameters scan more than `other`: they have a higher duty cycle, or the
+    /// same with a shorter interval.
+    fn is_more_aggressive_than(&self, other: &ScanParameters) -> bool {
+        let duty = self.window as u32 * other.interval as u32;
+        let other_duty = other.window as u32 * self.interval as u32;
+        duty > other_duty || (duty == other_duty && self.interval < other.interval)
+    }
+
+    /// Part of the interval spent scanning, in permille.
+    fn duty_cycle(&self) -> i32 {
+        (self.window as u32 * 1000 / self.interval as u32) as i32
+    }
+
+    /// Whether these parameters scan above the duty cycle the governor grants to every scanner.
+    fn is_aggressive(&self) -> bool {
+        self.duty_cycle() > SCAN_GOVERNOR_DUTY_CAP
+    }
+
+    /// Returns these parameters with the window reduced to the duty cycle granted to every
+    /// scanner, if they are above it.
+    fn throttled(&self) -> ScanParameters {
+        if !self.is_aggressive() {
+            return *self;
+        }
+
+        let window = (self.interval as u32 * SCAN_GOVERNOR_DUTY_CAP as u32 / 1000) as u16;
+        ScanParameters { interval: self.interval, window: window.max(MIN_SCAN_PARAMETER as u16) }
+    }
+}
+
+/// Duty cycle (permille) above which scans are aggressive and take turns with the aggressive
+/// scans of the same priority class.
+const SCAN_GOVERNOR_DUTY_CAP: i32 = 500;
+
+/// Time an aggressive scanner keeps its scan parameters before the next competing one takes over.
+const SCAN_GOVERNOR_SLICE: Duration = Duration::from_secs(10);
+
+/// Returns the scanner following `holder` among the `competitors`, by ascending id, wrapping
+/// around to the first one.
+fn next_scan_holder(competitors: &[u8], holder: Option<u8>) -> Option<u8> {
+    let first = competitors.iter().min().copied();
+    match holder {
+        Some(holder) => competitors.iter().filter(|id| **id > holder).min().copied().or(first),
+        None => first,
+    }
+}
+
+/// Returns the duty cycle (permille) a scanner requesting `parameters` gets on average, while
+/// `competitors` scanners take turns scanning aggressively. `is_competitor` is whether the
+/// scanner is one of them.
+fn effective_duty_cycle(
+    parameters: &ScanParameters,
+    competitors: usize,
+    is_competitor: bool,
+) -> i32 {
+    if !parameters.is_aggressive() {
+        return parameters.duty_cycle();
+    }
+    if !is_competitor {
+        return SCAN_GOVERNOR_DUTY_CAP;
+    }
+
+    let n = competitors as i32;
+    (parameters.duty_cycle() + (n - 1) * SCAN_GOVERNOR_DUTY_CAP) / n
+}
+
+/// Returns the most aggressive of the scan parameters.
+fn arbitrate_scan_parameters<I: Iterator<Item = ScanParameters>>(
+    parameters: I,
+) -> Option<ScanParameters> {
+    parameters.fold(None, |winner, p| match winner {
+        Some(w) if !p.is_more_aggressive_than(&w) => Some(w),
+        _ => Some(p),
+    })
+}
+
+/// Subscription of a scanner to manufacturer specific data.
+struct ManufacturerDataSubscription {
+    id: u32,
+    manufacturer_id: u16,
+    prefix: Vec<u8>,
+    mask: Vec<u8>,
+}
+
+impl ManufacturerDataSubscription {
+    /// Returns the manufacturer specific data of the advertisement matching the subscription,
+    /// without the company identifier.
+    fn find_match<'a>(&self, adv_data: &'a [u8]) -> Option<&'a [u8]> {
+        let company = self.manufacturer_id.to_le_bytes();
+        ad_structures(adv_data).find_map(|(ad_type, data)| {
+            if ad_type != AD_TYPE_MANUFACTURER_DATA || data.len() < 2 || data[..2] != company {
+                return None;
+            }
+
+            let payload = &data[2..];
+            if masked_equals(payload, 0, &self.mask, &self.prefix) {
+                Some(payload)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// Support of the Microsoft HCI extension by the controller, with the vendor opcode to use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MsftSupport {
+    Unknown,
+    Detecting(u16),
+    Supported(u16),
+    Unsupported,
+}
+
+/// MSFT command waiting for completion. The controller completes them in order.
+#[derive(Debug)]
+enum MsftRequest {
+    ReadSupportedFeatures,
+    // Identifies the monitor in `Scanner::msft_pending`.
+    MonitorAdvertisement(u32),
+    CancelMonitorAdvertisement,
+    SetAdvertisementFilterEnable,
+}
+
+impl MsftRequest {
+    fn subcommand(&self) -> u8 {
+        match self {
+            MsftRequest::ReadSupportedFeatures => msft::MSFT_READ_SUPPORTED_FEATURES,
+            MsftRequest::MonitorAdvertisement(_) => msft::MSFT_LE_MONITOR_ADVERTISEMENT,
+            MsftRequest::CancelMonitorAdvertisement => msft::MSFT_LE_CANCEL_MONITOR_ADVERTISEMENT,
+            MsftRequest::SetAdvertisementFilterEnable => {
+                msft::MSFT_LE_SET_ADVERTISEMENT_FILTER_ENABLE
+            }
+        }
+    }
+}
+
+/// Liveness check of a scanner, see `IBluetoothGatt::set_scanner_liveness_interval`.
+struct ScannerLiveness {
+    interval: Duration,
+    last_ping: Instant,
+    check: Option<JoinHandle<()>>,
+}
+
+impl ScannerLiveness {
+    /// Time without a ping after which the scanner is reclaimed.
+    fn deadline(&self) -> Duration {
+        self.interval * SCANNER_MISSED_PINGS
+    }
+}
+
+impl Drop for ScannerLiveness {
+    fn drop(&mut self) {
+        if let Some(check) = self.check.take() {
+            check.abort();
+        }
+    }
+}
+
+/// Results of a scanner with a report delay, held until they are delivered together.
+struct ScanResultBatch {
+    delay: Duration,
+    results: Vec<ScanResult>,
+    // Delivers `results` once the delay expires, None while no result is held.
+    flush: Option<JoinHandle<()>>,
+}
+
+impl Drop for ScanResultBatch {
+    fn drop(&mut self) {
+        if let Some(flush) = self.flush.take() {
+            flush.abort();
+        }
+    }
+}
+
+struct Scanner {
+    callback: Box<dyn IScannerCallback + Send>,
+    // ID of the disconnect observer of `callback`.
+    callback_id: u32,
+    scanner_id: Option<u8>,
+    is_scanning: bool,
+    scan_parameters: ScanParameters,
+    // Parameters used by the controller last reported to the callback.
+    reported_scan_parameters: Option<ScanParameters>,
+    priority: ScanPriority,
+    // Requested and effective duty cycles last reported to the callback.
+    reported_duty_cycle: Option<(i32, i32)>,
+    rssi_smoother: RssiSmoother,
+    address_filter: AddressFilter,
+    filters: Vec<ScanFilter>,
+    callback_type: ScanCallbackType,
+    record_delivery: ScanRecordDelivery,
+    // Bitmask of `SCAN_PHY_LE_1M` and `SCAN_PHY_LE_CODED`.
+    scan_phys: u8,
+    // Last decision of the scan permission checker, logged when it changes.
+    scan_permitted: Option<bool>,
+    // None when every matching result is delivered.
+    match_tracker: Option<MatchTracker>,
+    // APCF filter indexes holding `filters`, empty if they are not offloaded.
+    filter_indexes: Vec<u8>,
+    // MSFT monitors holding `filters` and the monitors still being added, see `MsftRequest`.
+    msft_handles: Vec<u8>,
+    msft_pending: Vec<u32>,
+    manufacturer_data_subscriptions: Vec<ManufacturerDataSubscription>,
+    // None when the liveness of the scanner is not checked.
+    liveness: Option<ScannerLiveness>,
+    // None when the results are delivered as they are found.
+    result_batch: Option<ScanResultBatch>,
+}
+
+impl Scanner {
+    /// Delivers a result, or holds it until the report delay of the scanner expires.
+    fn deliver_result(&mut self, result: ScanResult, tx: Option<Sender<Message>>) {
+        let batch = match self.result_batch.as_mut() {
+            Some(batch) => batch,
+            None => return self.callback.on_scan_result(result),
+        };
+
+        batch.results.push(result);
+        if batch.results.len() >= MAX_BATCHED_SCAN_RESULTS {
+            self.flush_results();
+        } else if batch.flush.is_none() {
+            if let Some(scanner_id) = self.scanner_id {
+                batch.flush = schedule_scan_result_flush(tx, scanner_id.into(), batch.delay);
+            }
+        }
+    }
+
+    /// Delivers the results held for the report delay, if any.
+    fn flush_results(&mut self) {
+        let batch = match self.result_batch.as_mut() {
+            Some(batch) => batch,
+            None => return,
+        };
+
+        if let Some(flush) = batch.flush.take() {
+            flush.abort();
+        }
+        if !batch.results.is_empty() {
+            self.callback.on_scan_result_batch(std::mem::take(&mut batch.results));
+        }
+    }
+}
+
+/// Represents a scan filter to be passed to `IBluetoothGatt::start_scan`.
+///
+/// An advertisement matches the filter if it matches all the conditions that are set. The
+/// filters of a scanner are offloaded to the controller when possible, with the Microsoft HCI
+/// extension (MSFT) or else APCF, and are always applied to the scan results in software as well.
+#[derive(Clone, Debug)]
+pub struct ScanFilter {
+    /// Address of the advertiser. Empty to match any address.
+    pub address: String,
+    /// Type of `address`, 0 for a public and 1 for a random address.
+    pub addr_type: u8,
+    /// Advertised service UUID, as 32 hexadecimal digits. Empty to match any service.
+    pub service_uuid: String,
+    /// Complete or shortened local name. Empty to match any name.
+    pub name: String,
+    /// Company identifier of the manufacturer specific data. Zero together with an empty
+    /// `manufacturer_data` matches any advertisement.
+    pub manufacturer_id: u16,
+    /// Prefix of the manufacturer specific data following the company identifier.
+    pub manufacturer_data: Vec<u8>,
+    /// Bits of `manufacturer_data` to compare. Empty to compare all the bits.
+    pub manufacturer_data_mask: Vec<u8>,
+    /// Advertisements received with a lower RSSI (dBm) are not reported.
+    pub rssi_high_threshold: i32,
+    /// RSSI (dBm) under which the controller considers a tracked advertiser lost.
+    pub rssi_low_threshold: i32,
+}
+
+impl Default for ScanFilter {
+    fn default() -> Self {
+        ScanFilter {
+            address: String::from(""),
+            addr_type: 0,
+            service_uuid: String::from(""),
+            name: String::from(""),
+            manufacturer_id: 0,
+            manufacturer_data: vec![],
+            manufacturer_data_mask: vec![],
+            rssi_high_threshold: i8::MIN.into(),
+            rssi_low_threshold: i8::MIN.into(),
+        }
+    }
+}
+
+// APCF filter types and feature selection bits, see `btm_ble_adv_filter.h`.
+const APCF_TYPE_ADDRESS: u8 = 0;
+const APCF_TYPE_SERVICE_UUID: u8 = 2;
+const APCF_TYPE_LOCAL_NAME: u8 = 4;
+const APCF_TYPE_MANUFACTURER_DATA: u8 = 5;
+
+const APCF_ACTION_ADD: u8 = 0;
+const APCF_ACTION_DELETE: u8 = 1;
+
+// All the conditions of a filter must match.
+const APCF_FILTER_LOGIC_AND: u8 = 1;
+
+// Delivery mode of the filters reporting the found and lost advertisers instead of every
+// advertisement.
+const APCF_DELAY_MODE_ON_FOUND: u8 = 1;
+// Advertisers the controller tracks for each filter in the on found delivery mode.
+const APCF_TRACKING_ENTRIES: u16 = 8;
+
+// States of the advertisers in the tracking events.
+const APCF_ADVERTISER_FOUND: u8 = 0;
+const APCF_ADVERTISER_LOST: u8 = 1;
+
+/// On found and on lost parameters of the APCF filters of a scanner whose devices are tracked by
+/// the controller, see `MatchTracker`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ApcfTracking {
+    found_timeout_ms: u16,
+    found_count: u8,
+    lost_timeout_ms: u16,
+}
+
+/// Number of APCF filter indexes the stack hands out to scanners.
+const MAX_SCAN_FILTER_INDEXES: u8 = 16;
+
+// AD types holding service UUIDs, by UUID length.
+const AD_TYPES_UUID16: [u8; 2] = [0x02, 0x03];
+const AD_TYPES_UUID32: [u8; 2] = [0x04, 0x05];
+const AD_TYPES_UUID128: [u8; 2] = [0x06, 0x07];
+const AD_TYPES_LOCAL_NAME: [u8; 2] = [0x08, 0x09];
+const AD_TYPE_MANUFACTURER_DATA: u8 = 0xFF;
+const AD_TYPE_FLAGS: u8 = 0x01;
+const AD_TYPE_SHORTENED_LOCAL_NAME: u8 = 0x08;
+const AD_TYPE_COMPLETE_LOCAL_NAME: u8 = 0x09;
+const AD_TYPE_TX_POWER_LEVEL: u8 = 0x0A;
+const AD_TYPE_SERVICE_DATA_UUID16: u8 = 0x16;
+const AD_TYPE_SERVICE_DATA_UUID32: u8 = 0x20;
+const AD_TYPE_SERVICE_DATA_UUID128: u8 = 0x21;
+
+// Bluetooth Base UUID, 00000000-0000-1000-8000-00805F9B34FB.
+pub(crate) const BASE_UUID: Uuid128Bit =
+    [0, 0, 0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0x80, 0x5F, 0x9B, 0x34, 0xFB];
+
+/// Returns the service UUIDs listed in the advertising data.
+fn advertised_service_uuids(adv_data: &[u8]) -> Vec<Uuid128Bit> {
+    let mut uuids = vec![];
+
+    for (ad_type, data) in ad_structures(adv_data) {
+        let len = if AD_TYPES_UUID16.contains(&ad_type) {
+            2
+        } else if AD_TYPES_UUID32.contains(&ad_type) {
+            4
+        } else if AD_TYPES_UUID128.contains(&ad_type) {
+            16
+        } else {
+            continue;
+        };
+
+        for chunk in data.chunks_exact(len) {
+            uuids.push(uuid_from_le_bytes(chunk));
+        }
+    }
+
+    uuids
+}
+
+/// Expands a 16, 32 or 128-bit UUID, little-endian as in the advertising data.
+fn uuid_from_le_bytes(bytes: &[u8]) -> Uuid128Bit {
+    let mut uuid = BASE_UUID;
+    let be: Vec<u8> = bytes.iter().rev().cloned().collect();
+    if bytes.len() == 16 {
+        uuid.copy_from_slice(&be);
+    } else {
+        uuid[4 - bytes.len()..4].copy_from_slice(&be);
+    }
+    uuid
+}
+
+impl ScanFilter {
+    fn has_manufacturer_data(&self) -> bool {
+        self.manufacturer_id != 0 || !self.manufacturer_data.is_empty()
+    }
+
+    fn data_mask(&self) -> Vec<u8> {
+        if self.manufacturer_data_mask.is_empty() {
+            vec![0xFF; self.manufacturer_data.len()]
+        } else {
+            self.manufacturer_data_mask.clone()
+        }
+    }
+
+    /// Returns whether the filter is well formed.
+    fn is_valid(&self) -> bool {
+        (self.address.is_empty() || RawAddress::from_string(self.address.clone()).is_some())
+            && (self.service_uuid.is_empty()
+                || parse_uuid_string(self.service_uuid.clone()).is_some())
+            && (self.manufacturer_data_mask.is_empty()
+                || self.manufacturer_data_mask.len() == self.manufacturer_data.len())
+    }
+
+    /// Returns whether a scan result matches the filter.
+    fn matches(&self, address: &BtAddress, adv_data: &[u8], rssi: i32) -> bool {
+        if rssi < self.rssi_high_threshold {
+            return false;
+        }
+
+        if !self.address.is_empty() && BtAddress::from_string(&self.address) != Some(*address) {
+            return false;
+        }
+
+        if !self.service_uuid.is_empty() {
+            let uuid = match parse_uuid_string(self.service_uuid.clone()) {
+                Some(uuid) => uuid.uu,
+                None => return false,
+            };
+            if !advertised_service_uuids(adv_data).contains(&uuid) {
+                return false;
+            }
+        }
+
+        if !self.name.is_empty()
+            && !ad_structures(adv_data).any(|(ad_type, data)| {
+                AD_TYPES_LOCAL_NAME.contains(&ad_type) && data == self.name.as_bytes()
+            })
+        {
+            return false;
+        }
+
+        if self.has_manufacturer_data() {
+            let company = self.manufacturer_id.to_le_bytes();
+            let mask = self.data_mask();
+            let found = ad_structures(adv_data).any(|(ad_type, data)| {
+                ad_type == AD_TYPE_MANUFACTURER_DATA
+                    && data.len() >= 2
+                    && data[..2] == company
+                    && masked_equals(&data[2..], 0, &mask, &self.manufacturer_data)
+            });
+            if !found {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Returns the condition of the MSFT monitor matching the filter, None if the filter does not
+    /// set exactly one condition or the condition cannot be monitored.
+    fn to_msft_condition(&self) -> Option<MonitorCondition> {
+        let conditions = [
+            !self.address.is_empty(),
+            !self.service_uuid.is_empty(),
+            !self.name.is_empty(),
+            self.has_manufacturer_data(),
+        ];
+        if conditions.iter().filter(|&&set| set).count() != 1 {
+            return None;
+        }
+
+        if let Some(address) = RawAddress::from_string(self.address.clone()) {
+            return Some(MonitorCondition::Address(self.addr_type, address.val));
+        }
+
+        if let Some(uuid) = parse_uuid_string(self.service_uuid.clone()) {
+            return Some(MonitorCondition::Uuid(uuid_to_le_bytes(&uuid.uu)));
+        }
+
+        if !self.name.is_empty() {
+            let name = self.name.as_bytes().to_vec();
+            return Some(MonitorCondition::Patterns(
+                AD_TYPES_LOCAL_NAME.iter().map(|ad_type| (*ad_type, name.clone())).collect(),
+            ));
+        }
+
+        // Patterns have no mask, so the whole data must be compared.
+        if self.data_mask().iter().all(|&b| b == 0xFF) {
+            let mut data = self.manufacturer_id.to_le_bytes().to_vec();
+            data.extend(&self.manufacturer_data);
+            return Some(MonitorCondition::Patterns(vec![(AD_TYPE_MANUFACTURER_DATA, data)]));
+        }
+
+        None
+    }
+
+    /// Returns the APCF filter parameters selecting the conditions set in the filter, delivering
+    /// the found and lost advertisers if the controller does the `tracking`.
+    fn to_filter_param(&self, tracking: Option<ApcfTracking>) -> GattFilterParam {
+        let mut feat_seln = 0;
+        if !self.address.is_empty() {
+            feat_seln |= 1 << APCF_TYPE_ADDRESS;
+        }
+        if !self.service_uuid.is_empty() {
+            feat_seln |= 1 << APCF_TYPE_SERVICE_UUID;
+        }
+        if !self.name.is_empty() {
+            feat_seln |= 1 << APCF_TYPE_LOCAL_NAME;
+        }
+        if self.has_manufacturer_data() {
+            feat_seln |= 1 << APCF_TYPE_MANUFACTURER_DATA;
+        }
+
+        let clamp_rssi = |rssi: i32| rssi.max(i8::MIN.into()).min(i8::MAX.into()) as i8 as u8;
+
+        let mut param = GattFilterParam {
+            feat_seln,
+            list_logic_type: 0,
+            filt_logic_type: APCF_FILTER_LOGIC_AND,
+            rssi_high_thres: clamp_rssi(self.rssi_high_threshold),
+            rssi_low_thres: clamp_rssi(self.rssi_low_threshold),
+            delay_mode: 0,
+            found_timeout: 0,
+            lost_timeout: 0,
+            found_timeout_count: 0,
+            num_of_tracking_entries: 0,
+        };
+
+        if let Some(tracking) = tracking {
+            param.delay_mode = APCF_DELAY_MODE_ON_FOUND;
+            param.found_timeout = tracking.found_timeout_ms;
+            param.found_timeout_count = tracking.found_count;
+            param.lost_timeout = tracking.lost_timeout_ms;
+            param.num_of_tracking_entries = APCF_TRACKING_ENTRIES;
+        }
+        param
+    }
+
+    /// Returns the APCF conditions of the filter.
+    fn to_apcf_commands(&self) -> Vec<ApcfCommand> {
+        let empty = ApcfCommand {
+            type_: 0,
+            address: RustRawAddress { address: [0; 6] },
+            addr_type: 0,
+            uuid: Uuid { uu: [0; 16] }.into(),
+            uuid_mask: Uuid { uu: [0; 16] }.into(),
+            name: vec![],
+            company: 0,
+            company_mask: 0,
+            ad_type: 0,
+            data: vec![],
+            data_mask: vec![],
+            irk: [0; 16],
+        };
+        let mut commands = vec![];
+
+        if let Some(address) = RawAddress::from_string(self.address.clone()) {
+            commands.push(ApcfCommand {
+                type_: APCF_TYPE_ADDRESS,
+                address: RustRawAddress { address: address.val },
+                addr_type: self.addr_type,
+                ..empty.clone()
+            });
+        }
+
+        if let Some(uuid) = parse_uuid_string(self.service_uuid.clone()) {
+            commands.push(ApcfCommand {
+                type_: APCF_TYPE_SERVICE_UUID,
+                uuid: uuid.into(),
+                uuid_mask: Uuid { uu: [0xFF; 16] }.into(),
+                ..empty.clone()
+            });
+        }
+
+        if !self.name.is_empty() {
+            commands.push(ApcfCommand {
+                type_: APCF_TYPE_LOCAL_NAME,
+                name: self.name.as_bytes().to_vec(),
+                ..empty.clone()
+            });
+        }
+
+        if self.has_manufacturer_data() {
+            commands.push(ApcfCommand {
+                type_: APCF_TYPE_MANUFACTURER_DATA,
+                company: self.manufacturer_id,
+                company_mask: 0xFFFF,
+                data: self.manufacturer_data.clone(),
+                data_mask: self.data_mask(),
+                ..empty.clone()
+            });
+        }
+
+        commands
+    }
+}
+
+/// Operations understood by a scan match program.
+///
+/// A program is evaluated like a small stack machine: compare operations push a boolean and
+/// logical operations combine the booleans on top of the stack.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ScanMatchOpcode {
+    /// Pushes whether the advertising data at `offset`, masked by `mask`, equals `value`.
+    MatchBytes = 0,
+    /// Pushes whether an AD structure of type `ad_type` exists whose payload at `offset`, masked
+    /// by `mask`, equals `value`.
+    MatchAdType = 1,
+    /// Pushes whether the RSSI of the result is at least `threshold`.
+    RssiAtLeast = 2,
+    /// Pops two values and pushes their conjunction.
+    And = 3,
+    /// Pops two values and pushes their disjunction.
+    Or = 4,
+    /// Pops one value and pushes its negation.
+    Not = 5,
+}
+
+impl Default for ScanMatchOpcode {
+    fn default() -> Self {
+        ScanMatchOpcode::MatchBytes
+    }
+}
+
+/// A single instruction of a `ScanMatchProgram`. Fields not used by `opcode` are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct ScanMatchInstruction {
+    pub opcode: ScanMatchOpcode,
+    pub ad_type: u8,
+    pub offset: i32,
+    pub mask: Vec<u8>,
+    pub value: Vec<u8>,
+    pub threshold: i32,
+}
+
+/// A match program attached to a scanner with `IBluetoothGatt::set_scan_match_program`.
+///
+/// The program decides per scan result whether it is delivered to the scanner. It is verified
+/// and compiled by the daemon before being accepted, so it can never read outside of the
+/// advertising data nor leave the stack in an inconsistent state.
+#[derive(Clone, Debug, Default)]
+pub struct ScanMatchProgram {
+    pub instructions: Vec<ScanMatchInstruction>,
+}
+
+/// Maximum number of instructions accepted in a scan match program.
+const SCAN_MATCH_MAX_INSTRUCTIONS: usize = 64;
+
+/// Maximum evaluation stack depth of a scan match program.
+const SCAN_MATCH_MAX_STACK_DEPTH: usize = 16;
+
+/// Maximum length of LE extended advertising data.
+const SCAN_MATCH_MAX_DATA_LEN: usize = 1650;
+
+#[derive(Debug, PartialEq)]
+enum CompiledScanMatchOp {
+    Bytes { offset: usize, mask: Vec<u8>, value: Vec<u8> },
+    AdType { ad_type: u8, offset: usize, mask: Vec<u8>, value: Vec<u8> },
+    RssiAtLeast(i8),
+    And,
+    Or,
+    Not,
+}
+
+/// A verified scan match program, ready to be evaluated against scan results.
+#[derive(Debug, PartialEq)]
+pub struct CompiledScanMatchProgram {
+    ops: Vec<CompiledScanMatchOp>,
+}
+
+impl CompiledScanMatchProgram {
+    /// Verifies and compiles `program`. Returns None if the program is rejected.
+    pub fn compile(program: &ScanMatchProgram) -> Option<CompiledScanMatchProgram> {
+        if program.instructions.is_empty()
+            || program.instructions.len() > SCAN_MATCH_MAX_INSTRUCTIONS
+        {
+            return None;
+        }
+
+        let mut ops = vec![];
+        let mut depth: usize = 0;
+
+        for inst in program.instructions.iter() {
+            let (op, pops) = match inst.opcode {
+                ScanMatchOpcode::MatchBytes | ScanMatchOpcode::MatchAdType => {
+                    if inst.offset < 0 || inst.value.is_empty() {
+                        return None;
+                    }
+
+                    let offset = inst.offset as usize;
+                    if offset + inst.value.len() > SCAN_MATCH_MAX_DATA_LEN {
+                        return None;
+                    }
+
+                    // An empty mask compares all bits.
+                    let mask = if inst.mask.is_empty() {
+                        vec![0xff; inst.value.len()]
+                    } else if inst.mask.len() == inst.value.len() {
+                        inst.mask.clone()
+                    } else {
+                        return None;
+                    };
+
+                    let value = inst.value.iter().zip(mask.iter()).map(|(v, m)| v & m).collect();
+
+                    if inst.opcode == ScanMatchOpcode::MatchBytes {
+                        (CompiledScanMatchOp::Bytes { offset, mask, value }, 0)
+                    } else {
+                        let ad_type = inst.ad_type;
+                        (CompiledScanMatchOp::AdType { ad_type, offset, mask, value }, 0)
+                    }
+                }
+                ScanMatchOpcode::RssiAtLeast => {
+                    if inst.threshold < i8::MIN.into() || inst.threshold > i8::MAX.into() {
+                        return None;
+                    }
+                    (CompiledScanMatchOp::RssiAtLeast(inst.threshold as i8), 0)
+                }
+                ScanMatchOpcode::And => (CompiledScanMatchOp::And, 2),
+                ScanMatchOpcode::Or => (CompiledScanMatchOp::Or, 2),
+                ScanMatchOpcode::Not => (CompiledScanMatchOp::Not, 1),
+            };
+
+            if depth < pops {
+                return None;
+            }
+            depth = depth - pops + 1;
+            if depth > SCAN_MATCH_MAX_STACK_DEPTH {
+                return None;
+            }
+
+            ops.push(op);
+        }
+
+        // A well formed program leaves exactly one verdict on the stack.
+        if depth != 1 {
+            return None;
+        }
+
+        Some(CompiledScanMatchProgram { ops })
+    }
+
+    /// Returns whether a scan result with the given advertising data and RSSI should be
+    /// delivered.
+    pub fn matches(&self, adv_data: &[u8], rssi: i8) -> bool {
+        let mut stack: Vec<bool> = Vec::with_capacity(SCAN_MATCH_MAX_STACK_DEPTH);
+
+        for op in self.ops.iter() {
+            let result = match op {
+                CompiledScanMatchOp::Bytes { offset, mask, value } => {
+                    masked_equals(adv_data, *offset, mask, value)
+                }
+                CompiledScanMatchOp::AdType { ad_type, offset, mask, value } => {
+                    ad_structures(adv_data).any(|(t, payload)| {
+                        t == *ad_type && masked_equals(payload, *offset, mask, value)
+                    })
+                }
+                CompiledScanMatchOp::RssiAtLeast(threshold) => rssi >= *threshold,
+                // The stack depth was verified at compile time.
+                CompiledScanMatchOp::And => {
+                    let (a, b) = (stack.pop().unwrap(), stack.pop().unwrap());
+                    a && b
+                }
+                CompiledScanMatchOp::Or => {
+                    let (a, b) = (stack.pop().unwrap(), stack.pop().unwrap());
+                    a || b
+                }
+                CompiledScanMatchOp::Not => !stack.pop().unwrap(),
+            };
+            stack.push(result);
+        }
+
+        stack.pop().unwrap_or(false)
+    }
+}
+
+fn masked_equals(data: &[u8], offset: usize, mask: &[u8], value: &[u8]) -> bool {
+    match data.get(offset..offset + value.len()) {
+        Some(bytes) => bytes.iter().zip(mask.iter()).map(|(b, m)| b & m).eq(value.iter().cloned()),
+        None => false,
+    }
+}
+
+/// Iterates over the (type, payload) pairs of the AD structures in `adv_data`, stopping at the
+/// first malformed structure.
+fn ad_structures(adv_data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut rest = adv_data;
+    std::iter::from_fn(move || {
+        let len = *rest.first()? as usize;
+        if len == 0 || rest.len() < len + 1 {
+            return None;
+        }
+        let item = (rest[1], &rest[2..len + 1]);
+        rest = &rest[len + 1..];
+        Some(item)
+    })
+}
+
+/// Implementation of the GATT API (IBluetoothGatt).
+pub struct BluetoothGatt {
+    intf: Arc<Mutex<BluetoothInterface>>,
+    gatt: Option<Gatt>,
+
+    context_map: ContextMap,
+    server_context_map: ServerContextMap,
+    reliable_queue: HashSet<String>,
+    scan_match_programs: HashMap<i32, CompiledScanMatchProgram>,
+    scan_permission_checker: Option<Box<dyn IScanPermissionChecker + Send>>,
+    advertising_callback_restorer: Option<Box<dyn IAdvertisingCallbackRestorer + Send>>,
+    // Sets saved by the previous daemon, re-created once the adapter is enabled, and the sets
+    // last saved for the next one.
+    saved_advertising_sets: Vec<SavedAdvertisingSet>,
+    last_saved_advertising_sets: Vec<SavedAdvertisingSet>,
+    // Keyed by connection ID and characteristic handle.
+    notification_pipes: HashMap<(i32, i32), NotificationPipe>,
+    // Attribute databases discovered on each connection, keyed by connection ID.
+    gatt_dbs: HashMap<i32, Vec<BluetoothGattService>>,
+    // Requests of the attribute database of the connections, which the stack answers in order.
+    // Keyed by connection ID and request ID.
+    gatt_db_requests: BTreeMap<(i32, u64), GattDbRequest>,
+    next_gatt_db_request_id: u64,
+    // Keyed by connection ID.
+    pending_operations: HashMap<i32, PendingOperations>,
+    // Retry policies of the clients and the reads they are retrying.
+    att_retries: AttRetries,
+    // Notification queues of the clients which set one, by client ID.
+    notification_queues: HashMap<i32, NotificationQueue>,
+    // Values of the multiple handle value notifications being received, by connection ID.
+    multiple_notifications: HashMap<i32, Vec<GattHandleValue>>,
+    // RSSI monitors, by connection ID.
+    rssi_monitors: HashMap<i32, RssiMonitor>,
+    // Connections whose service discovery was cancelled, by connection ID.
+    cancelled_discoveries: HashSet<i32>,
+    // Keyed by connection ID.
+    service_reads: HashMap<i32, ServiceRead>,
+    // Keyed by address and descriptor handle.
+    shared_cccds: HashMap<(String, i32), SharedCccd>,
+    // Keyed by connection ID.
+    conformance_checks: HashMap<i32, ConformanceCheck>,
+    // Negotiated ATT MTUs, by connection ID. Connections missing use `ATT_DEFAULT_MTU`.
+    mtus: HashMap<i32, usize>,
+    // Keyed by connection ID.
+    long_writes: HashMap<i32, LongWrite>,
+    // MTU of each connected EATT bearer, by address of the devices having some.
+    eatt_bearers: HashMap<String, Vec<i32>>,
+    link_profile_overrides: LinkProfileOverrides,
+    // Link tuning profiles applied to the devices whose LE link is connected, by address.
+    link_profiles: HashMap<String, LinkTuningProfile>,
+    // Connection parameters requested for the connected devices, by address.
+    connection_parameters: HashMap<String, ParameterRequests>,
+    // Interval, latency and supervision timeout last reported, by connection ID.
+    conn_params: HashMap<i32, (u16, u16, u16)>,
+    // Failed connections and operations, oldest first, for the state snapshots.
+    recent_errors: VecDeque<RecentError>,
+    metrics: Metrics,
+    hci_latency: HciLatencyTracker,
+    policy: Policy,
+
+    scanners: HashMap<Uuid128Bit, Scanner>,
+    next_scanner_uuid: u32,
+    // Clients, servers and scanners to register again once the adapter is enabled, see
+    // `release_registrations`.
+    released_registrations: HashSet<Uuid128Bit>,
+    rssi_calibration_offset: i32,
+    free_filter_indexes: Vec<u8>,
+    scan_filters_enabled: bool,
+    // Cleared once the controller reports it cannot track the advertisers of APCF filters.
+    apcf_tracking_supported: bool,
+    msft_support: MsftSupport,
+    pending_msft_requests: VecDeque<MsftRequest>,
+    next_msft_monitor_id: u32,
+    msft_filter_enabled: bool,
+    // Scan parameters last pushed to the controller.
+    applied_scan_parameters: Option<ScanParameters>,
+    // Bitmask of `SCAN_PHY_LE_1M` and `SCAN_PHY_LE_CODED` last sent to the controller.
+    applied_scan_phys: Option<u8>,
+    // Aggressive scanner whose turn it is to scan with its own parameters, and the pending
+    // rotation to the next one while several compete.
+    scan_holder: Option<u8>,
+    scan_governor_rotation: Option<JoinHandle<()>>,
+    next_subscription_id: u32,
+    // Scanner doing a batch scan and the mode it uses.
+    batch_scan: Option<(i32, BatchScanMode)>,
+
+    periodic_syncs: Vec<PeriodicSync>,
+    // Callbacks for the next sync transferred by each address.
+    past_receivers: HashMap<BtAddress, Box<dyn IPeriodicAdvertisingCallback + Send>>,
+    // Address and sync handle of the transfers waiting for completion, in request order.
+    pending_sync_transfers: VecDeque<(BtAddress, u16)>,
+
+    advertising_sets: Vec<AdvertisingSet>,
+    next_advertising_reg_id: i32,
+    max_advertising_sets_per_app: usize,
+    // Pending rotation of the sets in the controller slots, while sets are suspended.
+    advertising_rotation: Option<JoinHandle<()>>,
+    // Pending check for the devices lost by the scanners tracking their matches.
+    match_lost_check: Option<JoinHandle<()>>,
+    // Type of the ongoing system suspend, if any, with the scanners paused and the advertising
+    // sets disarmed for it. The sets stay in the list until the controller enables them again.
+    suspend_type: Option<SuspendType>,
+    paused_scanners: Vec<u8>,
+    disarmed_advertising_sets: Vec<i32>,
+    // Advertiser IDs of the sets whose own address is read by the stack when they start, to
+    // record the identities they broadcast.
+    pending_own_address_reads: HashSet<u8>,
+    // Preparations of the suspend waiting for the controller: the sets not disabled yet, and
+    // whether the scan is being stopped.
+    disarming_advertising_sets: Vec<i32>,
+    stopping_scan: bool,
+    tx: Option<Sender<Message>>,
+
+    att_trace: Option<AttTrace>,
+
+    adapter: Option<Arc<Mutex<Box<Bluetooth>>>>,
+    peripheral_policy: PeripheralConnectionPolicy,
+    peripheral_allow_list: HashSet<BtAddress>,
+    peripheral_agent: Option<Box<dyn IPeripheralConnectionAgent + Send>>,
+    // Decisions on the connected centrals, by address.
+    peripheral_decisions: HashMap<String, PeripheralDecision>,
+    // Timers rejecting the centrals the agent was asked about, by address.
+    peripheral_agent_timeouts: HashMap<String, JoinHandle<()>>,
+    // Addresses the local device is connecting to, which are not subject to the policy.
+    outgoing_connections: HashSet<String>,
+    // Clients reconnecting to each device in the background, by address.
+    background_connections: HashMap<String, HashSet<i32>>,
+    // Clients of the connection to each device, by address.
+    shared_connections: HashMap<String, SharedConnection>,
+    phy_preferences: PhyPreferenceStore,
+    // PHY preferences of the clients, by client ID and address.
+    client_phy_preferences: HashMap<(i32, String), PhyPreference>,
+    default_phy_preference: PhyPreference,
+    // Characteristic User Descriptions and Server Characteristic Configurations of the services
+    // added by the servers, answered by the stack. Keyed by server ID and descriptor handle.
+    managed_descriptors: HashMap<(i32, i32), ManagedDescriptor>,
+    server_descriptors: ServerDescriptorStore,
+    identity_resolver: IdentityResolver,
+    // Journals of the clients which enabled them, by client ID.
+    write_journals: HashMap<i32, WriteJournal>,
+    // Journaled writes being sent on each connection, by connection ID.
+    journal_flushes: HashMap<i32, JournalFlush>,
+    time_service_enabled: bool,
+    // Built-in Current Time Service, while registered.
+    time_server: Option<TimeServer>,
+    // Pending check for the adjustments of the clock served by the time server.
+    clock_check: Option<JoinHandle<()>>,
+}
+
+impl BluetoothGatt {
+    /// Constructs a new IBluetoothGatt implementation.
+    pub fn new(intf: Arc<Mutex<BluetoothInterface>>) -> BluetoothGatt {
+        BluetoothGatt {
+            intf: intf,
+            gatt: None,
+            context_map: ContextMap::new(),
+            server_context_map: ServerContextMap::new(),
+            reliable_queue: HashSet::new(),
+            scan_match_programs: HashMap::new(),
+            scan_permission_checker: None,
+            advertising_callback_restorer: None,
+            saved_advertising_sets: vec![],
+            last_saved_advertising_sets: vec![],
+            notification_pipes: HashMap::new(),
+            gatt_dbs: HashMap::new(),
+            gatt_db_requests: BTreeMap::new(),
+            next_gatt_db_request_id: 0,
+            pending_operations: HashMap::new(),
+            att_retries: AttRetries::default(),
+            notification_queues: HashMap::new(),
+            multiple_notifications: HashMap::new(),
+            rssi_monitors: HashMap::new(),
+            cancelled_discoveries: HashSet::new(),
+            service_reads: HashMap::new(),
+            shared_cccds: HashMap::new(),
+            conformance_checks: HashMap::new(),
+            mtus: HashMap::new(),
+            long_writes: HashMap::new(),
+            eatt_bearers: HashMap::new(),
+            link_profile_overrides: LinkProfileOverrides::default(),
+            connection_parameters: HashMap::new(),
+            link_profiles: HashMap::new(),
+            conn_params: HashMap::new(),
+            recent_errors: VecDeque::new(),
+            metrics: Metrics::default(),
+            hci_latency: HciLatencyTracker::new(),
+            policy: Policy::default(),
+            scanners: HashMap::new(),
+            next_scanner_uuid: 0,
+            released_registrations: HashSet::new(),
+            rssi_calibration_offset: 0,
+            free_filter_indexes: (1..=MAX_SCAN_FILTER_INDEXES).collect(),
+            scan_filters_enabled: false,
+            apcf_tracking_supported: true,
+            msft_support: MsftSupport::Unknown,
+            pending_msft_requests: VecDeque::new(),
+            next_msft_monitor_id: 0,
+            msft_filter_enabled: false,
+            applied_scan_parameters: None,
+            applied_scan_phys: None,
+            scan_holder: None,
+            scan_governor_rotation: None,
+            next_subscription_id: 1,
+            batch_scan: None,
+            periodic_syncs: vec![],
+            past_receivers: HashMap::new(),
+            pending_sync_transfers: VecDeque::new(),
+            advertising_sets: vec![],
+            next_advertising_reg_id: 0,
+            max_advertising_sets_per_app: DEFAULT_MAX_ADVERTISING_SETS_PER_APP,
+            advertising_rotation: None,
+            suspend_type: None,
+            paused_scanners: vec![],
+            disarmed_advertising_sets: vec![],
+            pending_own_address_reads: HashSet::new(),
+            disarming_advertising_sets: vec![],
+            stopping_scan: false,
+            match_lost_check: None,
+            tx: None,
+            att_trace: None,
+            adapter: None,
+            peripheral_policy: PeripheralConnectionPolicy::default(),
+            peripheral_allow_list: HashSet::new(),
+            peripheral_agent: None,
+            peripheral_decisions: HashMap::new(),
+            peripheral_agent_timeouts: HashMap::new(),
+            outgoing_connections: HashSet::new(),
+            background_connections: HashMap::new(),
+            shared_connections: HashMap::new(),
+            phy_preferences: PhyPreferenceStore::load(PHY_PREFERENCES_FILE),
+            client_phy_preferences: HashMap::new(),
+            default_phy_preference: PhyPreference::default(),
+            managed_descriptors: HashMap::new(),
+            server_descriptors: ServerDescriptorStore::load(SERVER_DESCRIPTORS_FILE),
+            identity_resolver: IdentityResolver::new(),
+            write_journals: HashMap::new(),
+            journal_flushes: HashMap::new(),
+            time_service_enabled: false,
+            time_server: None,
+            clock_check: None,
+        }
+    }
+
+    pub fn set_adapter(&mut self, adapter: Arc<Mutex<Box<Bluetooth>>>) {
+        self.adapter = Some(adapter);
+    }
+
+    pub fn init_profiles(&mut self, tx: Sender<Message>) {
+        self.gatt = Gatt::new(&self.intf.lock().unwrap());
+        self.tx = Some(tx.clone());
+        self.saved_advertising_sets =
+            load_saved_advertising_sets(Path::new(SAVED_ADVERTISING_SETS_FILE));
+        self.last_saved_advertising_sets = self.saved_advertising_sets.clone();
+
+        let tx_clone = tx.clone();
+        let tx_server = tx.clone();
+        let tx_scanner = tx.clone();
+        let tx_scanner_inband = tx.clone();
+        self.gatt.as_mut().unwrap().initialize(
+            GattClientCallbacksDispatcher {
+                dispatch: Box::new(move |cb| {
+                    let tx_clone = tx_clone.clone();
+                    topstack::get_runtime().spawn(async move {
+                        let _ = tx_clone.send(Message::GattClient(cb)).await;
+                    });
+                }),
+            },
+            GattServerCallbacksDispatcher {
+                dispatch: Box::new(move |cb| {
+                    let tx_clone = tx_server.clone();
+                    topstack::get_runtime().spawn(async move {
+                        let _ = tx_clone.send(Message::GattServer(cb)).await;
+                    });
+                }),
+            },
+            GattScannerCallbacksDispatcher {
+                dispatch: Box::new(move |cb| {
+                    let tx_clone = tx_scanner.clone();
+                    topstack::get_runtime().spawn(async move {
+                        let _ = tx_clone.send(Message::LeScanner(cb)).await;
+                    });
+                }),
+            },
+            GattScannerInbandCallbacksDispatcher {
+                dispatch: Box::new(move |cb| {
+                    let tx_clone = tx_scanner_inband.clone();
+                    topstack::get_runtime().spawn(async move {
+                        let _ = tx_clone.send(Message::LeScannerInband(cb)).await;
+                    });
+                }),
+            },
+            GattAdvCallbacksDispatcher {
+                dispatch: Box::new(move |cb| {
+                    let tx_clone = tx.clone();
+                    topstack::get_runtime().spawn(async move {
+                        let _ = tx_clone.send(Message::LeAdvertiser(cb)).await;
+                    });
+                }),
+            },
+            // The registered advertising callbacks report the same events.
+            GattAdvInbandCallbacksDispatcher {
+                dispatch: Box::new(|cb| debug!("Advertiser inband callback {:?}", cb)),
+            },
+        );
+        self.gatt.as_mut().unwrap().client.register_eatt_callback();
+
+        let tx_metrics = self.tx.clone().unwrap();
+        tokio::spawn(async move {
+            loop {
+                time::sleep(METRICS_LOG_PERIOD).await;
+                if tx_metrics.send(Message::MetricsLog).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Returns the counters of the GATT activity, see `IBluetoothDebug::get_counters`.
+    pub(crate) fn get_counters(&self) -> HashMap<String, u64> {
+        self.metrics.counters()
+    }
+
+    /// Logs the counters of the GATT activity, every `METRICS_LOG_PERIOD`.
+    pub(crate) fn log_metrics(&self) {
+        info!("GATT metrics: {}", self.metrics.summary());
+    }
+
+    pub fn dispatch_controller_callbacks(&mut self, cb: ControllerCallbacks) {
+        match cb {
+            ControllerCallbacks::CommandLatency(opcode, latency) => {
+                self.hci_command_completed(opcode, latency);
+                let scan_enable =
+                    matches!(opcode, HCI_LE_SET_SCAN_ENABLE | HCI_LE_SET_EXTENDED_SCAN_ENABLE);
+                if self.stopping_scan && scan_enable {
+                    self.stopping_scan = false;
+                    self.suspend_preparation_done(SuspendPreparation::LeScan);
+                }
+            }
+            // Delivered to the QA API.
+            ControllerCallbacks::ControllerLists(..) | ControllerCallbacks::LeTestEnded(..) => (),
+        }
+    }
+
+    /// Counts the latency of a completed HCI command in the histogram of its opcode group, and
+    /// alerts the debug clients when the controller becomes sluggish or responsive again.
+    fn hci_command_completed(&mut self, opcode: u16, latency: Duration) {
+        self.metrics.increment(format!(
+            "hci.latency.{}.{}",
+            opcode_group_name(opcode),
+            bucket_name(latency)
+        ));
+
+        let alert = match self.hci_latency.check_threshold(latency) {
+            Some(alert) => alert,
+            None => return,
+        };
+        let latency_ms = latency.as_millis().min(u32::MAX as u128) as u32;
+        let sluggish = alert == LatencyAlert::Sluggish;
+        if sluggish {
+            warn!("Controller is sluggish: command {:#06x} took {} ms", opcode, latency_ms);
+        } else {
+            info!("Controller is responsive again: command {:#06x} took {} ms", opcode, latency_ms);
+        }
+
+        if let Some(tx) = self.tx.clone() {
+            tokio::spawn(async move {
+                let _ = tx.send(Message::HciLatencyAlert(opcode, latency_ms, sluggish)).await;
+            });
+        }
+    }
+
+    /// Sets the latency above which the controller is considered sluggish, None to disable the
+    /// alerts.
+    pub(crate) fn set_hci_latency_threshold(&mut self, threshold: Option<Duration>) {
+        self.hci_latency.set_threshold(threshold);
+    }
+
+    /// Sets the offset added to the RSSI of scan results to compensate for the platform's
+    /// antenna and front-end characteristics.
+    pub fn set_rssi_calibration_offset(&mut self, offset: i32) {
+        self.rssi_calibration_offset = offset;
+    }
+
+    /// Enables the built-in Current Time Service, registered whenever the adapter is enabled.
+    pub fn set_time_service_enabled(&mut self, enabled: bool) {
+        self.time_service_enabled = enabled;
+    }
+
+    /// Registers the server of the built-in Current Time Service if enabled. The previous
+    /// registration, if any, went away with the adapter.
+    pub(crate) fn start_time_service(&mut self) {
+        if let Some(check) = self.clock_check.take() {
+            check.abort();
+        }
+        self.time_server = None;
+        if !self.time_service_enabled {
+            return;
+        }
+
+        self.time_server = Some(TimeServer::default());
+        self.gatt.as_ref().unwrap().server.register_server(&Uuid { uu: TIME_SERVER_UUID }, false);
+    }
+
+    fn is_time_server_connection(&self, conn_id: i32) -> bool {
+        let server_id = match self.time_server.as_ref().and_then(|server| server.server_id) {
+            Some(id) => id,
+            None => return false,
+        };
+
+        self.server_context_map
+            .connections
+            .iter()
+            .any(|conn| conn.conn_id == conn_id && conn.server_id == server_id)
+    }
+
+    fn respond_server_request(
+        &self,
+        conn_id: i32,
+        trans_id: i32,
+        handle: i32,
+        status: GattStatus,
+        value: &[u8],
+    ) {
+        let mut attr_value = BtGattValue::default();
+        attr_value.value[..value.len()].copy_from_slice(value);
+        attr_value.handle = handle as u16;
+        attr_value.len = value.len() as u16;
+
+        self.gatt.as_ref().unwrap().server.send_response(
+            conn_id,
+            trans_id,
+            status.to_i32().unwrap(),
+            &BtGattResponse { attr_value },
+        );
+    }
+
+    /// Takes over the descriptors of a service added by a server which the stack answers,
+    /// restoring the values written by the bonded clients. Returns the handles of the
+    /// characteristics restored as broadcast.
+    fn manage_descriptors(
+        &mut self,
+        server_id: i32,
+        app_uuid: Uuid128Bit,
+        service: &BluetoothGattService,
+    ) -> Vec<i32> {
+        let mut broadcast = vec![];
+        for characteristic in &service.characteristics {
+            for descriptor in &characteristic.descriptors {
+                let kind = match ManagedDescriptorKind::from_uuid(&descriptor.uuid) {
+                    Some(kind) => kind,
+                    None => continue,
+                };
+                let key = DescriptorKey {
+                    app_uuid,
+                    characteristic_uuid: characteristic.uuid,
+                    descriptor_uuid: descriptor.uuid,
+                };
+
+                let mut managed = ManagedDescriptor::new(
+                    kind,
+                    key,
+                    service.instance_id,
+                    characteristic.instance_id,
+                    descriptor.permissions & WRITE_PERMISSIONS != 0,
+                );
+                if let Some(value) = self.server_descriptors.get(&managed.key) {
+                    managed.value = value.clone();
+                }
+                if managed.is_broadcast() {
+                    broadcast.push(characteristic.instance_id);
+                }
+                self.managed_descriptors.insert((server_id, descriptor.instance_id), managed);
+            }
+        }
+        broadcast
+    }
+
+    /// Returns the ID of the server of a connection, if it has a descriptor `handle` answered by
+    /// the stack.
+    fn managed_descriptor_server(&self, conn_id: i32, handle: i32) -> Option<i32> {
+        self.server_context_map
+            .get_server_by_conn_id(conn_id)
+            .and_then(|server| server.id)
+            .filter(|server_id| self.managed_descriptors.contains_key(&(*server_id, handle)))
+    }
+
+    fn read_managed_descriptor(
+        &self,
+        server_id: i32,
+        conn_id: i32,
+        trans_id: i32,
+        handle: i32,
+        offset: i32,
+    ) {
+        let descriptor = &self.managed_descriptors[&(server_id, handle)];
+        match descriptor.value.get(offset as usize..) {
+            Some(rest) => {
+                self.respond_server_request(conn_id, trans_id, handle, GattStatus::Success, rest)
+            }
+            None => self.respond_server_request(
+                conn_id,
+                trans_id,
+                handle,
+                GattStatus::InvalidOffset,
+                &[],
+            ),
+        }
+    }
+
+    fn write_managed_descriptor(
+        &mut self,
+        server_id: i32,
+        conn_id: i32,
+        trans_id: i32,
+        address: &String,
+        handle: i32,
+        offset: i32,
+        need_rsp: bool,
+        is_prep: bool,
+        value: &[u8],
+    ) {
+        let is_bonded = self.is_bonded(address);
+        let descriptor = self.managed_descriptors.get_mut(&(server_id, handle)).unwrap();
+        let (status, changed) = match descriptor.check_write(offset, is_prep, value) {
+            Ok(value) => {
+                let changed = descriptor.value != value;
+                descriptor.value = value;
+                // A value written by a client which is not bonded is not restored.
+                if is_bonded {
+                    self.server_descriptors.set(
+                        descriptor.key.clone(),
+                        address,
+                        descriptor.value.clone(),
+                    );
+                } else {
+                    self.server_descriptors.remove(&descriptor.key);
+                }
+                (GattStatus::Success, changed)
+            }
+            Err(status) => (status, false),
+        };
+
+        if need_rsp {
+            self.respond_server_request(conn_id, trans_id, handle, status, &[]);
+        }
+        if !changed {
+            return;
+        }
+
+        let descriptor = &self.managed_descriptors[&(server_id, handle)];
+        let server = match self.server_context_map.get_by_server_id(server_id) {
+            Some(server) => server,
+            None => return,
+        };
+        match descriptor.kind {
+            ManagedDescriptorKind::UserDescription => {
+                server.callback.on_user_description_changed(
+                    callback_address(&address),
+                    descriptor.characteristic_handle,
+                    String::from_utf8_lossy(&descriptor.value).into_owned(),
+                );
+            }
+            ManagedDescriptorKind::ServerConfiguration => {
+                server.callback.on_server_configuration_changed(
+                    callback_address(&address),
+                    descriptor.characteristic_handle,
+                    descriptor.is_broadcast(),
+                );
+            }
+        }
+    }
+
+    fn time_server_read(&self, conn_id: i32, trans_id: i32, handle: i32, offset: i32) {
+        let server = self.time_server.as_ref().unwrap();
+        // A time failing to convert is reported with all its fields unknown.
+        let time = LocalTime::from_system_time(SystemTime::now()).unwrap_or_default();
+        let value = if Some(handle) == server.current_time_handle {
+            time_service::current_time(&time, 0)
+        } else if Some(handle) == server.local_time_handle {
+            time_service::local_time_information(&time)
+        } else if Some(handle) == server.cccd_handle {
+            (server.subscribers.contains(&conn_id) as u16).to_le_bytes().to_vec()
+        } else {
+            self.respond_server_request(conn_id, trans_id, handle, GattStatus::ReadNotPermit, &[]);
+            return;
+        };
+
+        match value.get(offset as usize..) {
+            Some(rest) => {
+                self.respond_server_request(conn_id, trans_id, handle, GattStatus::Success, rest)
+            }
+            None => self.respond_server_request(
+                conn_id,
+                trans_id,
+                handle,
+                GattStatus::InvalidOffset,
+                &[],
+            ),
+        }
+    }
+
+    fn time_server_write(
+        &mut self,
+        conn_id: i32,
+        trans_id: i32,
+        handle: i32,
+        need_rsp: bool,
+        value: &[u8],
+    ) {
+        let server = self.time_server.as_mut().unwrap();
+        let status = if Some(handle) == server.cccd_handle {
+            // Indications are not supported, only the notification bit matters.
+            if value.first().map_or(false, |v| v & 0x01 != 0) {
+                server.subscribers.insert(conn_id);
+            } else {
+                server.subscribers.remove(&conn_id);
+            }
+            GattStatus::Success
+        } else {
+            GattStatus::WriteNotPermit
+        };
+
+        if need_rsp {
+            self.respond_server_request(conn_id, trans_id, handle, status, &[]);
+        }
+    }
+
+    fn schedule_clock_check(&mut self) {
+        if let Some(tx) = self.tx.clone() {
+            self.clock_check = Some(tokio::spawn(async move {
+                time::sleep(CLOCK_CHECK_PERIOD).await;
+                let _ = tx.send(Message::TimeServiceClockCheck).await;
+            }));
+        }
+    }
+
+    /// Notifies the subscribers of the time server if the clock was adjusted since the last
+    /// check.
+    pub(crate) fn check_clock(&mut self) {
+        self.clock_check = None;
+        let server = match self.time_server.as_mut() {
+            Some(server) => server,
+            None => return,
+        };
+
+        let (wall, monotonic) = (SystemTime::now(), Instant::now());
+        if let Some(time) = LocalTime::from_system_time(wall) {
+            let reason = match server.clock.as_mut() {
+                Some(clock) => clock.sample(wall, monotonic, time),
+                None => {
+                    server.clock = Some(ClockWatch::new(wall, monotonic, time));
+                    0
+                }
+            };
+
+            if let (Some(server_id), Some(handle), true) =
+                (server.server_id, server.current_time_handle, reason != 0)
+            {
+                debug!("Clock adjusted with reason {:#04x}, notifying the current time", reason);
+                let value = time_service::current_time(&time, reason);
+                for conn_id in server.subscribers.iter() {
+                    self.gatt
+                        .as_ref()
+                        .unwrap()
+                        .server
+                        .send_indication(server_id, handle, *conn_id, 0, &value);
+                }
+            }
+        }
+
+        self.schedule_clock_check();
+    }
+
+    /// Forgets the GATT cache, background connections, PHY preference and peripheral policy of a
+    /// device, see `IBluetooth::remove_bond_cascade`.
+    pub(crate) fn forget_device(&mut self, address: &String) {
+        let addr = match RawAddress::from_string(address.clone()) {
+            Some(addr) => addr,
+            None => return,
+        };
+
+        if let Some(clients) = self.background_connections.remove(address) {
+            for client_id in clients {
+                self.gatt.as_ref().unwrap().client.disconnect(client_id, &addr, 0);
+            }
+        }
+        // The client ID is not used to clear the cache.
+        self.gatt.as_ref().unwrap().client.refresh(0, &addr);
+        self.phy_preferences.remove(address);
+        self.server_descriptors.forget_device(address);
+        if let Some(addr) = BtAddress::from_string(address) {
+            self.peripheral_allow_list.remove(&addr);
+        }
+        self.peripheral_decisions.remove(address);
+    }
+
+    /// Runs a GATT test command of the stack, see `IBluetoothQA::send_gatt_test_command`.
+    pub(crate) fn run_test_command(
+        &mut self,
+        command: i32,
+        address: &String,
+        uuid: Uuid128Bit,
+        params: [u16; 5],
+    ) -> BtResult<()> {
+        let mut addr = match address.is_empty() {
+            true => RawAddress::default(),
+            false => RawAddress::from_string(address.clone())
+                .ok_or_else(|| BtError::invalid_argument(format!("Invalid address {}", address)))?,
+        };
+        let gatt = self
+            .gatt
+            .as_ref()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "GATT is not initialized"))?;
+
+        match gatt.client.run_test_command(command, &mut addr, &mut Uuid { uu: uuid }, params) {
+            BtStatus::Success => Ok(()),
+            status => Err(BtError::from(status)),
+        }
+    }
+
+    /// Adds the GATT connections, scanners, advertising sets, recent errors and queue depths to
+    /// `snapshot`, see `IBluetoothDebug::get_state_snapshot`.
+    pub(crate) fn fill_snapshot(&self, snapshot: &mut StateSnapshot) {
+        for conn in self.context_map.connections.iter() {
+            let params = self.conn_params.get(&conn.conn_id);
+            snapshot.connections.push(ConnectionSnapshot {
+                conn_id: conn.conn_id,
+                address: conn.address.clone(),
+                app_id: conn.client_id,
+                is_server: false,
+                mtu: self.mtus.get(&conn.conn_id).cloned().unwrap_or(ATT_DEFAULT_MTU),
+                interval: params.map(|p| p.0),
+                latency: params.map(|p| p.1),
+                timeout: params.map(|p| p.2),
+                congested: self
+                    .context_map
+                    .get_by_client_id(conn.client_id)
+                    .map_or(false, |c| c.is_congested),
+                queue_depth: self
+                    .pending_operations
+                    .get(&conn.conn_id)
+                    .map_or(0, |p| p.operations.len()),
+            });
+        }
+        for conn in self.server_context_map.connections.iter() {
+            let params = self.conn_params.get(&conn.conn_id);
+            snapshot.connections.push(ConnectionSnapshot {
+                conn_id: conn.conn_id,
+                address: conn.address.clone(),
+                app_id: conn.server_id,
+                is_server: true,
+                mtu: self.mtus.get(&conn.conn_id).cloned().unwrap_or(ATT_DEFAULT_MTU),
+                interval: params.map(|p| p.0),
+                latency: params.map(|p| p.1),
+                timeout: params.map(|p| p.2),
+                congested: conn.is_congested,
+                queue_depth: conn.notification_queue.len(),
+            });
+        }
+
+        snapshot.scanners.extend(self.scanners.values().map(|scanner| ScannerSnapshot {
+            scanner_id: scanner.scanner_id,
+            scanning: scanner.is_scanning,
+            priority: format!("{:?}", scanner.priority),
+            interval: scanner.scan_parameters.interval,
+            window: scanner.scan_parameters.window,
+            filters: scanner.filters.len(),
+        }));
+        snapshot.advertisers.extend(self.advertising_sets.iter().map(|set| AdvertiserSnapshot {
+            reg_id: set.reg_id,
+            state: format!("{:?}", set.state),
+            enabled: set.enabled,
+            adv_data_len: set.adv_data.len(),
+            scan_rsp_len: set.scan_rsp.len(),
+        }));
+        snapshot.recent_errors.extend(self.recent_errors.iter().cloned());
+        snapshot.queues = QueueDepths {
+            congestion_queue: self
+                .context_map
+                .clients
+                .iter()
+                .map(|c| c.congestion_queue.len())
+                .sum(),
+            msft_requests: self.pending_msft_requests.len(),
+            sync_transfers: self.pending_sync_transfers.len(),
+        };
+    }
+
+    /// Sets the hook deciding which clients may scan. Scanners already running are checked
+    /// again with their next result.
+    pub fn set_scan_permission_checker(&mut self, checker: Box<dyn IScanPermissionChecker + Send>) {
+        self.scan_permission_checker = Some(checker);
+    }
+
+    /// Sets the hook finding the callbacks of the advertising sets saved by the previous daemon.
+    pub fn set_advertising_callback_restorer(
+        &mut self,
+        restorer: Box<dyn IAdvertisingCallbackRestorer + Send>,
+    ) {
+        self.advertising_callback_restorer = Some(restorer);
+    }
+
+    /// Sets the policy of the platform, limiting the advertising sets started from then on.
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy;
+    }
+
+    fn find_sync_by_handle(&mut self, sync_handle: u16) -> Option<&mut PeriodicSync> {
+        self.periodic_syncs.iter_mut().find(|s| s.handle == Some(sync_handle))
+    }
+
+    /// Finds a started advertising set by the advertiser ID given to the client.
+    fn find_advertising_set(&mut self, advertiser_id: i32) -> Option<&mut AdvertisingSet> {
+        self.advertising_sets
+            .iter_mut()
+            .find(|s| s.reg_id == advertiser_id && s.state != AdvertisingSetState::Starting)
+    }
+
+    /// Replaces the advertising data of a set with AD structures encoded by the caller, for the
+    /// AD types `AdvertiseData` does not hold.
+    pub(crate) fn set_raw_advertising_data(
+        &mut self,
+        advertiser_id: i32,
+        bytes: Vec<u8>,
+    ) -> BtResult<()> {
+        let parameters = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set.parameters.clone(),
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+
+        self.check_advertising_change(advertiser_id, &parameters, bytes.len())?;
+        let set = self.find_advertising_set(advertiser_id).unwrap();
+        set.adv_data = bytes.clone();
+        match set.handle() {
+            Some(handle) => {
+                self.gatt.as_mut().unwrap().advertiser.set_data(handle, false, bytes);
+            }
+            None => set.callback.on_advertising_data_set(advertiser_id, AdvertisingStatus::Success),
+        }
+        self.save_advertising_sets();
+        Ok(())
+    }
+
+    /// Starts a TX power sweep on an advertising set, see
+    /// `IBluetoothQA::start_tx_power_sweep`. The configured TX power of the set is left as is.
+    pub(crate) fn start_tx_power_sweep(
+        &mut self,
+        advertiser_id: i32,
+        min_level: i32,
+        max_level: i32,
+        step: i32,
+        dwell_ms: i32,
+    ) -> BtResult<()> {
+        let sweep = TxPowerSweep::new(min_level, max_level, step, dwell_ms)?;
+        let set = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set,
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+
+        // Replacing a sweep cancels its pending step.
+        set.tx_power_sweep = Some(sweep);
+        self.step_tx_power_sweep(advertiser_id);
+        Ok(())
+    }
+
+    /// Stops the TX power sweep of an advertising set and applies its configured TX power again.
+    pub(crate) fn stop_tx_power_sweep(&mut self, advertiser_id: i32) -> BtResult<()> {
+        let set = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set,
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+
+        if set.tx_power_sweep.take().is_none() {
+            return Err(BtError::not_found(format!(
+                "No TX power sweep on advertising set {}",
+                advertiser_id
+            )));
+        }
+
+        info!(
+            "TX power sweep of advertising set {} stopped, back to {} dBm",
+            advertiser_id, set.parameters.tx_power_level
+        );
+        // A suspended set is resumed with its configured parameters.
+        if let Some(handle) = set.handle() {
+            let parameters = set.parameters.clone();
+            self.gatt.as_mut().unwrap().advertiser.set_parameters(handle, parameters.into());
+        }
+        Ok(())
+    }
+
+    /// Applies the next level of the TX power sweep of a set and schedules the following one.
+    /// The level only overrides the configured TX power of the set while the sweep lasts.
+    pub(crate) fn step_tx_power_sweep(&mut self, advertiser_id: i32) {
+        let tx = self.tx.clone();
+        let set = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set,
+            None => return,
+        };
+        let sweep = match set.tx_power_sweep.as_mut() {
+            Some(sweep) => sweep,
+            None => return,
+        };
+
+        let level = sweep.next_level();
+        let dwell = sweep.dwell;
+        sweep.step = tx.map(|tx| {
+            tokio::spawn(async move {
+                time::sleep(dwell).await;
+                let _ = tx.send(Message::AdvertisingTxPowerSweep(advertiser_id)).await;
+            })
+        });
+
+        let parameters =
+            AdvertisingSetParameters { tx_power_level: level, ..set.parameters.clone() };
+        match set.handle() {
+            Some(handle) => {
+                info!(
+                    "TX power sweep of advertising set {}: requesting {} dBm",
+                    advertiser_id, level
+                );
+                self.gatt.as_mut().unwrap().advertiser.set_parameters(handle, parameters.into());
+            }
+            // Applied when the set is resumed.
+            None => info!(
+                "TX power sweep of advertising set {}: skipping {} dBm while suspended",
+                advertiser_id, level
+            ),
+        }
+    }
+
+    /// Finds an advertising set by the advertiser ID of its controller slot.
+    fn find_advertising_set_by_handle(&mut self, handle: u8) -> Option<&mut AdvertisingSet> {
+        self.advertising_sets.iter_mut().find(|s| s.handle() == Some(handle))
+    }
+
+    /// Reports a set the controller stopped advertising on its own. The set stays started, so
+    /// that its client can enable it again.
+    fn on_advertising_set_terminated(
+        &mut self,
+        advertiser_id: u8,
+        reason: AdvertisingTerminationReason,
+    ) {
+        let set = match self.find_advertising_set_by_handle(advertiser_id) {
+            Some(set) => set,
+            None => return,
+        };
+        debug!("Advertising set {} terminated: {:?}", set.reg_id, reason);
+        set.enabled = false;
+        set.callback.on_advertising_set_terminated(set.reg_id, reason);
+
+        // A set disarmed for suspend as it terminated is not enabled again on resume.
+        let reg_id = set.reg_id;
+        self.disarmed_advertising_sets.retain(|id| *id != reg_id);
+        self.advertising_set_disarmed(reg_id);
+        self.update_le_activity();
+        self.save_advertising_sets();
+    }
+
+    /// Asks the controller to start the advertising set at `index` with its current parameters,
+    /// data, duration and maximum number of events.
+    fn start_advertising_in_controller(&mut self, index: usize) {
+        let set = &self.advertising_sets[index];
+
+        // Periodic advertising is not exposed.
+        let periodic_params = PeriodicAdvertisingParameters {
+            enable: 0,
+            min_interval: 0,
+            max_interval: 0,
+            periodic_advertising_properties: 0,
+        };
+
+        self.gatt.as_mut().unwrap().advertiser.start_advertising_set(
+            set.reg_id,
+            set.parameters.clone().into(),
+            set.adv_data.clone(),
+            set.scan_rsp.clone(),
+            periodic_params,
+            vec![],
+            set.duration,
+            set.max_ext_adv_events,
+        );
+    }
+
+    /// Frees the controller slot of the active advertising set at `index`.
+    fn suspend_advertising_set(&mut self, index: usize) {
+        let set = &mut self.advertising_sets[index];
+        let handle = match set.handle() {
+            Some(handle) => handle,
+            None => return,
+        };
+
+        set.state = AdvertisingSetState::Suspended;
+        set.since = Instant::now();
+        set.callback.on_advertising_set_suspended(set.reg_id);
+        self.gatt.as_mut().unwrap().advertiser.unregister(handle);
+        self.update_le_activity();
+    }
+
+    /// Starts again the advertising set suspended for the longest time, if any.
+    fn resume_next_advertising_set(&mut self) {
+        if let Some(index) = longest_suspended_set(&self.advertising_sets) {
+            self.advertising_sets[index].state = AdvertisingSetState::Resuming;
+            // The set is disabled again once started if the client disabled it.
+            self.start_advertising_in_controller(index);
+        }
+    }
+
+    /// Schedules the next rotation of the advertising sets if some are suspended.
+    fn schedule_advertising_rotation(&mut self) {
+        if self.advertising_rotation.is_some()
+            || longest_suspended_set(&self.advertising_sets).is_none()
+        {
+            return;
+        }
+
+        if let Some(tx) = self.tx.clone() {
+            self.advertising_rotation = Some(tokio::spawn(async move {
+                time::sleep(ADVERTISING_ROTATION_PERIOD).await;
+                let _ = tx.send(Message::AdvertisingSetRotation).await;
+            }));
+        }
+    }
+
+    /// Delivers the results a scanner held for its report delay.
+    pub(crate) fn flush_scan_results(&mut self, scanner_id: i32) {
+        if let Some(scanner) = self.find_scanner_by_id(scanner_id) {
+            scanner.flush_results();
+        }
+    }
+
+    /// Reclaims a scanner which missed too many pings, or checks it again once it may have.
+    pub(crate) fn check_scanner_liveness(&mut self, scanner_id: i32) {
+        let tx = self.tx.clone();
+        let liveness = match self.find_scanner_by_id(scanner_id).and_then(|s| s.liveness.as_mut()) {
+            Some(liveness) => liveness,
+            None => return,
+        };
+
+        let elapsed = liveness.last_ping.elapsed();
+        if elapsed < liveness.deadline() {
+            liveness.check = schedule_liveness_check(tx, scanner_id, liveness.deadline() - elapsed);
+            return;
+        }
+
+        warn!("Scanner {} was not pinged for {:?}, unregistering it", scanner_id, elapsed);
+        self.metrics.increment("scan.scanner_reclaimed");
+        self.unregister_scanner(scanner_id);
+    }
+
+    /// Unregisters the scanners whose callback disconnected.
+    pub(crate) fn remove_scanner_callback(&mut self, callback_id: u32) {
+        let (uuid, scanner_id) =
+            match self.scanners.iter().find(|(_, s)| s.callback_id == callback_id) {
+                Some((uuid, scanner)) => (*uuid, scanner.scanner_id),
+                None => return,
+            };
+
+        match scanner_id {
+            Some(scanner_id) => self.unregister_scanner(scanner_id.into()),
+            // The registration result is dropped once it comes.
+            None => {
+                self.scanners.remove(&uuid);
+            }
+        }
+    }
+
+    /// Stops the advertising sets whose callback disconnected, which gives their slots back to
+    /// the limit of sets of their client.
+    pub(crate) fn remove_advertiser_callback(&mut self, callback_id: u32) {
+        let (lost, kept): (Vec<AdvertisingSet>, Vec<AdvertisingSet>) =
+            self.advertising_sets.drain(..).partition(|s| s.callback_id == callback_id);
+        self.advertising_sets = kept;
+        if lost.is_empty() {
+            return;
+        }
+
+        // The sets starting or resuming are released when the controller reports them started.
+        for set in lost {
+            self.metrics.record_duration("adv.set_lifetime", set.started.elapsed());
+            if let Some(handle) = set.handle() {
+                self.gatt.as_mut().unwrap().advertiser.unregister(handle);
+            }
+        }
+        self.resume_next_advertising_set();
+        self.update_le_activity();
+        self.save_advertising_sets();
+    }
+
+    fn schedule_match_lost_check(&mut self) {
+        if self.match_lost_check.is_some()
+            || !self
+                .scanners
+                .values()
+                .any(|s| s.match_tracker.as_ref().map_or(false, |t| t.may_expire()))
+        {
+            return;
+        }
+
+        if let Some(tx) = self.tx.clone() {
+            self.match_lost_check = Some(tokio::spawn(async move {
+                time::sleep(MATCH_LOST_CHECK_PERIOD).await;
+                let _ = tx.send(Message::ScanMatchLostCheck).await;
+            }));
+        }
+    }
+
+    /// Forgets the devices not matched for the timeout of their scanners and reports them lost
+    /// to the scanners asking for it.
+    pub(crate) fn check_lost_matches(&mut self) {
+        self.match_lost_check = None;
+
+        let now = Instant::now();
+        for scanner in self.scanners.values_mut() {
+            let lost = match scanner.match_tracker.as_mut() {
+                Some(tracker) => tracker.expire(now),
+                None => continue,
+            };
+
+            if scanner.callback_type.reports_match_lost() && !lost.is_empty() {
+                // The devices are reported lost after the results found before.
+                scanner.flush_results();
+                for result in lost {
+                    scanner.callback.on_scan_result_lost(result);
+                }
+            }
+        }
+
+        self.schedule_match_lost_check();
+    }
+
+    /// Swaps the set holding a controller slot the longest for the set suspended the longest, so
+    /// that every set advertises in turn while there are more sets than slots.
+    pub(crate) fn rotate_advertising_sets(&mut self) {
+        self.advertising_rotation = None;
+
+        let active = longest_active_set(&self.advertising_sets);
+        let suspended = longest_suspended_set(&self.advertising_sets);
+        // The sets do not advertise while the system is suspended.
+        if let (Some(active), Some(_), None) = (active, suspended, &self.suspend_type) {
+            if self.advertising_sets[active].since.elapsed() >= ADVERTISING_ROTATION_PERIOD {
+                // The set suspended now waits behind the others.
+                self.suspend_advertising_set(active);
+                self.resume_next_advertising_set();
+            }
+        }
+
+        self.schedule_advertising_rotation();
+    }
+
+    /// Drops the state kept for a registered client and gives its ID back to the stack.
+    fn release_client(&mut self, client_id: i32) {
+        self.background_connections.retain(|_, clients| {
+            clients.remove(&client_id);
+            !clients.is_empty()
+        });
+        let addresses: Vec<String> = self.shared_connections.keys().cloned().collect();
+        for address in addresses {
+            if let Some(addr) = RawAddress::from_string(address.clone()) {
+                self.cancel_shared_connect(client_id, &addr);
+            }
+            self.drop_connection_parameters(client_id, &address);
+            let shared_connections = &mut self.shared_connections;
+            if let Some(connection) = shared_connections.get_mut(&address) {
+                connection.disconnected(client_id);
+                if connection.is_empty() {
+                    shared_connections.remove(&address);
+                }
+            }
+        }
+
+        for address in self.link_profile_overrides.remove_client(client_id) {
+            self.retune_link(&address);
+        }
+        self.client_phy_preferences.retain(|(id, _), _| *id != client_id);
+        self.write_journals.remove(&client_id);
+        self.att_retries.remove_client(client_id);
+        self.notification_queues.remove(&client_id);
+        self.context_map.connections.retain(|c| c.client_id != client_id);
+        self.gatt.as_ref().unwrap().client.unregister_client(client_id);
+    }
+
+    /// Drops the services and connections of a registered server and gives its ID back to the
+    /// stack.
+    fn release_server(&mut self, server_id: i32) {
+        self.managed_descriptors.retain(|(id, _), _| *id != server_id);
+        self.server_context_map.connections.retain(|c| c.server_id != server_id);
+        self.gatt.as_ref().unwrap().server.unregister_server(server_id);
+    }
+
+    /// Stops a registered scanner and gives its ID back to the stack.
+    fn release_scanner(&mut self, scanner_id: i32) {
+        self.stop_scan(scanner_id);
+        let _ = self.batch_scan_disable(scanner_id);
+        self.scan_match_programs.remove(&scanner_id);
+        self.gatt.as_mut().unwrap().scanner.unregister(scanner_id as u8);
+    }
+
+    /// Gives the IDs of the clients, servers and scanners back to the stack before it restarts,
+    /// as the registrations would not survive the restart. Their callbacks are kept, and they
+    /// are registered again by `restore_registrations` once the adapter is enabled. The new IDs
+    /// are reported again with `on_client_registered`, `on_server_registered` and
+    /// `on_scanner_registered`.
+    pub(crate) fn release_registrations(&mut self) {
+        let client_ids: Vec<i32> = self.context_map.clients.iter().filter_map(|c| c.id).collect();
+        for client_id in client_ids {
+            self.release_client(client_id);
+        }
+        for client in self.context_map.clients.iter_mut() {
+            client.id = None;
+            client.is_congested = false;
+            client.congestion_queue.clear();
+            self.released_registrations.insert(client.uuid);
+        }
+
+        let server_ids: Vec<i32> =
+            self.server_context_map.servers.iter().filter_map(|s| s.id).collect();
+        for server_id in server_ids {
+            self.release_server(server_id);
+        }
+        for server in self.server_context_map.servers.iter_mut() {
+            server.id = None;
+            server.pending_requests.clear();
+            server.drop_policies.clear();
+            self.released_registrations.insert(server.uuid);
+        }
+
+        let scanner_ids: Vec<u8> = self.scanners.values().filter_map(|s| s.scanner_id).collect();
+        for scanner_id in scanner_ids {
+            self.release_scanner(scanner_id.into());
+        }
+        for (uuid, scanner) in self.scanners.iter_mut() {
+            scanner.scanner_id = None;
+            scanner.liveness = None;
+            self.released_registrations.insert(*uuid);
+        }
+    }
+
+    /// Registers again the clients, servers and scanners released by `release_registrations`.
+    pub(crate) fn restore_registrations(&mut self) {
+        let gatt = self.gatt.as_mut().unwrap();
+        for uuid in self.released_registrations.drain() {
+            let app_uuid = Uuid { uu: uuid };
+            if let Some(client) = self.context_map.get_by_uuid(&uuid) {
+                gatt.client.register_client(&app_uuid, client.eatt_support);
+            } else if let Some(server) = self.server_context_map.get_by_uuid(&uuid) {
+                gatt.server.register_server(&app_uuid, server.eatt_support);
+            } else if self.scanners.contains_key(&uuid) {
+                gatt.scanner.register_scanner(app_uuid);
+            }
+        }
+    }
+
+    /// Forgets the controller slots of the advertising sets once the adapter is disabled, which
+    /// resets the controller. The persistent sets, including the ones persistent across restarts,
+    /// wait for `restore_advertising_sets`, the other ones are stopped.
+    pub(crate) fn forget_advertising_slots(&mut self) {
+        if let Some(rotation) = self.advertising_rotation.take() {
+            rotation.abort();
+        }
+        self.disarmed_advertising_sets.clear();
+        self.disarming_advertising_sets.clear();
+        self.stopping_scan = false;
+
+        for set in keep_restorable_sets(&mut self.advertising_sets) {
+            if set.state == AdvertisingSetState::Starting {
+                set.callback.on_advertising_set_started(
+                    set.client_reg_id,
+                    set.reg_id,
+                    0,
+                    AdvertisingStatus::InternalError,
+                );
+            } else {
+                self.metrics.record_duration("adv.set_lifetime", set.started.elapsed());
+                set.callback.on_advertising_set_stopped(set.reg_id);
+            }
+        }
+        self.update_le_activity();
+    }
+
+    /// Starts again the persistent advertising sets lost by a reset of the controller, keeping
+    /// their ID. The sets saved by the previous daemon are re-created with a new ID, for the
+    /// clients still there.
+    pub(crate) fn restore_advertising_sets(&mut self) {
+        for index in 0..self.advertising_sets.len() {
+            if self.advertising_sets[index].state != AdvertisingSetState::Restoring {
+                continue;
+            }
+
+            info!("Restoring advertising set {}", self.advertising_sets[index].reg_id);
+            // The set is disabled again once started if the client disabled it.
+            self.start_advertising_in_controller(index);
+        }
+
+        for saved in std::mem::take(&mut self.saved_advertising_sets) {
+            let callback = self
+                .advertising_callback_restorer
+                .as_ref()
+                .and_then(|restorer| restorer.restore_callback(&saved.remote_id, &saved.object_id));
+            let mut callback = match callback {
+                Some(callback) => callback,
+                None => {
+                    info!("Dropping the advertising set saved for {}", saved.remote_id);
+                    continue;
+                }
+            };
+
+            let callback_id = self.watch_advertising_callback(&mut callback);
+            let reg_id = self.next_advertising_reg_id;
+            self.next_advertising_reg_id += 1;
+            info!("Re-creating advertising set {} of {}", reg_id, saved.remote_id);
+            self.advertising_sets.push(AdvertisingSet {
+                reg_id,
+                state: AdvertisingSetState::Starting,
+                app_id: saved.remote_id,
+                parameters: saved.parameters,
+                adv_data: saved.adv_data,
+                scan_rsp: saved.scan_rsp,
+                enabled: saved.enabled,
+                duration: saved.duration,
+                max_ext_adv_events: saved.max_ext_adv_events,
+                since: Instant::now(),
+                started: Instant::now(),
+                callback,
+                callback_id,
+                tx_power: None,
+                tx_power_sweep: None,
+                address_rotation_interval_ms: saved.address_rotation_interval_ms,
+                persistent: saved.persistent,
+                restart_persistent: true,
+                client_reg_id: saved.client_reg_id,
+            });
+            self.start_advertising_in_controller(self.advertising_sets.len() - 1);
+        }
+        self.save_advertising_sets();
+    }
+
+    /// Stops the advertising sets of `callback` once its client disconnects. Returns the ID of
+    /// the disconnect observer.
+    fn watch_advertising_callback(
+        &self,
+        callback: &mut Box<dyn IAdvertisingSetCallback + Send>,
+    ) -> u32 {
+        let tx = self.tx.clone();
+        callback.register_disconnect(Box::new(move |cb_id| {
+            if let Some(tx) = tx.clone() {
+                tokio::spawn(async move {
+                    let _ = tx.send(Message::AdvertiserCallbackDisconnected(cb_id)).await;
+                });
+            }
+        }))
+    }
+
+    /// Saves the advertising sets persistent across restarts for the next daemon, along with the
+    /// sets of the previous daemon not re-created yet, if they changed since they were last saved.
+    fn save_advertising_sets(&mut self) {
+        let sets: Vec<SavedAdvertisingSet> = self
+            .saved_advertising_sets
+            .iter()
+            .cloned()
+            .chain(
+                self.advertising_sets
+                    .iter()
+                    .filter(|s| s.restart_persistent)
+                    .filter_map(SavedAdvertisingSet::from_set),
+            )
+            .collect();
+        if sets == self.last_saved_advertising_sets {
+            return;
+        }
+
+        save_advertising_sets(Path::new(SAVED_ADVERTISING_SETS_FILE), &sets);
+        self.last_saved_advertising_sets = sets;
+    }
+
+    /// Prepares LE for suspend. The advertising sets not persistent are disarmed, and the scanners
+    /// are paused unless their filters are offloaded to the controller and `suspend_type` allows
+    /// wakes. The clients are not told, as the scans and sets are restored by `resume_le`.
+    /// Returns the preparations to wait for, each reported with `Message::SuspendPreparationDone`
+    /// once the controller completes it.
+    pub(crate) fn suspend_le(&mut self, suspend_type: SuspendType) -> Vec<SuspendPreparation> {
+        self.suspend_type = Some(suspend_type);
+
+        for set in self.advertising_sets.iter().filter(|s| !s.persistent) {
+            if let (true, Some(handle)) = (set.enabled, set.handle()) {
+                if !self.disarmed_advertising_sets.contains(&set.reg_id) {
+                    self.disarmed_advertising_sets.push(set.reg_id);
+                    self.disarming_advertising_sets.push(set.reg_id);
+                    self.gatt.as_mut().unwrap().advertiser.enable(handle, false, 0, 0);
+                }
+            }
+        }
+
+        let was_scanning = self.scanners.values().any(|s| s.is_scanning);
+        self.pause_scanners();
+        info!(
+            "Suspending with {} scanners paused and {} advertising sets disarmed",
+            self.paused_scanners.len(),
+            self.disarmed_advertising_sets.len()
+        );
+        self.update_scan();
+
+        let mut preparations = vec![];
+        if was_scanning && !self.scanners.values().any(|s| s.is_scanning) {
+            self.stopping_scan = true;
+            preparations.push(SuspendPreparation::LeScan);
+        }
+        if !self.disarming_advertising_sets.is_empty() {
+            preparations.push(SuspendPreparation::Advertising);
+        }
+        preparations
+    }
+
+    /// Restores the scanners and the advertising sets as they were before `suspend_le`.
+    pub(crate) fn resume_le(&mut self) {
+        if self.suspend_type.take().is_none() {
+            return;
+        }
+        self.disarming_advertising_sets.clear();
+        self.stopping_scan = false;
+
+        for scanner_id in std::mem::take(&mut self.paused_scanners) {
+            if let Some(scanner) = self.find_scanner_by_id(scanner_id.into()) {
+                scanner.is_scanning = true;
+            }
+        }
+
+        let sets = &self.advertising_sets;
+        self.disarmed_advertising_sets.retain(|reg_id| sets.iter().any(|s| s.reg_id == *reg_id));
+        // The sets disabled by their clients meanwhile stay disabled.
+        for set in self.advertising_sets.iter().filter(|s| s.enabled) {
+            if let (true, Some(handle)) =
+                (self.disarmed_advertising_sets.contains(&set.reg_id), set.handle())
+            {
+                self.gatt.as_mut().unwrap().advertiser.enable(
+                    handle,
+                    true,
+                    set.duration,
+                    set.max_ext_adv_events,
+                );
+            }
+        }
+
+        self.update_scan();
+    }
+
+    /// Notes that the advertising set `reg_id` is no longer advertising for the ongoing suspend,
+    /// and reports the advertising disarmed once no set is left.
+    fn advertising_set_disarmed(&mut self, reg_id: i32) {
+        let count = self.disarming_advertising_sets.len();
+        self.disarming_advertising_sets.retain(|id| *id != reg_id);
+        if count != self.disarming_advertising_sets.len()
+            && self.disarming_advertising_sets.is_empty()
+        {
+            self.suspend_preparation_done(SuspendPreparation::Advertising);
+        }
+    }
+
+    fn suspend_preparation_done(&self, preparation: SuspendPreparation) {
+        if let Some(tx) = self.tx.clone() {
+            tokio::spawn(async move {
+                let _ = tx.send(Message::SuspendPreparationDone(preparation)).await;
+            });
+        }
+    }
+
+    /// Pauses the scanners not allowed to scan during the ongoing suspend.
+    fn pause_scanners(&mut self) {
+        let allow_wakes = match &self.suspend_type {
+            Some(SuspendType::NoWakesAllowed) => false,
+            Some(_) => true,
+            None => return,
+        };
+
+        for scanner in self.scanners.values_mut().filter(|s| s.is_scanning) {
+            let offloaded = !scanner.filters.is_empty()
+                && (!scanner.filter_indexes.is_empty()
+                    || scanner.msft_handles.len() == scanner.filters.len());
+            if allow_wakes && offloaded {
+                continue;
+            }
+            if let Some(scanner_id) = scanner.scanner_id {
+                scanner.is_scanning = false;
+                self.paused_scanners.push(scanner_id);
+            }
+        }
+    }
+
+    /// Returns the advertising capabilities of the controller, none until the adapter is enabled.
+    fn advertising_capabilities(&self) -> AdvertisingCapabilities {
+        self.adapter
+            .as_ref()
+            .and_then(|adapter| adapter.lock().unwrap().get_local_le_features())
+            .map(AdvertisingCapabilities::from)
+            .unwrap_or_default()
+    }
+
+    /// Encodes advertise data for a set with `parameters`, checking that it fits.
+    /// Checks an advertising set against the advertising policy, the other enabled sets taking
+    /// their share of the duty cycle. `reg_id` is the set changed, if it is already started.
+    /// Returns the interval the set may use.
+    fn check_advertising_policy(
+        &self,
+        reg_id: Option<i32>,
+        parameters: &AdvertisingSetParameters,
+        adv_data_len: usize,
+    ) -> BtResult<i32> {
+        let policy = self.policy.advertising();
+        if !policy.is_enabled() {
+            return Ok(parameters.interval);
+        }
+
+        let others = self
+            .advertising_sets
+            .iter()
+            .filter(|s| s.enabled && Some(s.reg_id) != reg_id)
+            .map(|s| duty_cycle(&s.parameters, s.adv_data.len()))
+            .sum();
+        policy.check(parameters, adv_data_len, others)
+    }
+
+    /// Lengthens the interval of an advertising set if the advertising policy requires it, see
+    /// `check_advertising_policy`.
+    fn apply_advertising_policy(
+        &self,
+        reg_id: Option<i32>,
+        parameters: &mut AdvertisingSetParameters,
+        adv_data_len: usize,
+    ) -> BtResult<()> {
+        let interval = self.check_advertising_policy(reg_id, parameters, adv_data_len)?;
+        if interval != parameters.interval {
+            info!(
+                "Advertising interval lengthened from {:.1} to {:.1} ms by the advertising policy",
+                interval_ms(parameters.interval),
+                interval_ms(interval)
+            );
+            parameters.interval = interval;
+        }
+        Ok(())
+    }
+
+    /// Fails if a change of the advertising set `reg_id` puts it out of the advertising policy.
+    fn check_advertising_change(
+        &self,
+        reg_id: i32,
+        parameters: &AdvertisingSetParameters,
+        adv_data_len: usize,
+    ) -> BtResult<()> {
+        let interval = self.check_advertising_policy(Some(reg_id), parameters, adv_data_len)?;
+        if interval != parameters.interval {
+            return Err(BtError::new(
+                BtErrorCategory::PermissionDenied,
+                format!(
+                    "The advertising policy requires an interval of at least {:.1} ms for the \
+                     change, set it first",
+                    interval_ms(interval)
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn encode_advertise_data(
+        &self,
+        parameters: &AdvertisingSetParameters,
+        data: &AdvertiseData,
+        scan_response: bool,
+    ) -> BtResult<Vec<u8>> {
+        let device_name = match &self.adapter {
+            Some(adapter) if data.include_device_name => adapter.lock().unwrap().get_name(),
+            _ => String::new(),
+        };
+
+        data.encode_for_set(
+            parameters,
+            &self.advertising_capabilities(),
+            &device_name,
+            scan_response,
+        )
+    }
+
+    fn find_scanner_by_id(&mut self, scanner_id: i32) -> Option<&mut Scanner> {
+        self.scanners.values_mut().find(|s| s.scanner_id.map(|id| id as i32) == Some(scanner_id))
+    }
+
+    /// Offloads the filters of a scanner to the controller, as MSFT monitors if the controller
+    /// supports them and every filter can be monitored, or else to APCF.
+    fn offload_scan_filters(&mut self, scanner_id: i32) {
+        if self.msft_support == MsftSupport::Unknown {
+            self.detect_msft();
+        }
+
+        if !self.offload_msft_monitors(scanner_id) {
+            self.offload_apcf_filters(scanner_id);
+        }
+    }
+
+    /// Offloads the filters of a scanner to APCF, if enough filter indexes are free. Otherwise
+    /// the filters are only applied in software. The found and lost devices of the scanner are
+    /// tracked by the controller as well if it supports it.
+    fn offload_apcf_filters(&mut self, scanner_id: i32) {
+        let free = self.free_filter_indexes.len();
+        let tracking_supported = self.apcf_tracking_supported;
+        let scanner = match self.find_scanner_by_id(scanner_id) {
+            Some(s) => s,
+            None => return,
+        };
+
+        if scanner.filters.is_empty() || scanner.filters.len() > free {
+            return;
+        }
+
+        let tracking = match scanner.match_tracker.as_mut() {
+            Some(tracker) if tracking_supported => {
+                let tracking = tracker.apcf_tracking();
+                tracker.offloaded = tracking.is_some();
+                tracking
+            }
+            _ => None,
+        };
+        let filters = scanner.filters.clone();
+        let indexes: Vec<u8> = self.free_filter_indexes.drain(..filters.len()).collect();
+        for (filter, index) in filters.iter().zip(indexes.iter()) {
+            let scanner = &mut self.gatt.as_mut().unwrap().scanner;
+            scanner.scan_filter_setup(
+                scanner_id as u8,
+                APCF_ACTION_ADD,
+                *index,
+                filter.to_filter_param(tracking),
+            );
+            scanner.scan_filter_add(*index, filter.to_apcf_commands());
+        }
+
+        if let Some(scanner) = self.find_scanner_by_id(scanner_id) {
+            scanner.filter_indexes = indexes;
+        }
+    }
+
+    /// Removes the filters of a scanner from the controller.
+    fn remove_offloaded_scan_filters(&mut self, scanner_id: i32) {
+        let (indexes, handles) = match self.find_scanner_by_id(scanner_id) {
+            Some(s) => {
+                // Monitors still being added are cancelled once their handle is known.
+                s.msft_pending.clear();
+                if let Some(tracker) = s.match_tracker.as_mut() {
+                    tracker.offloaded = false;
+                }
+                (std::mem::take(&mut s.filter_indexes), std::mem::take(&mut s.msft_handles))
+            }
+            None => return,
+        };
+
+        if let Some(opcode) = self.msft_opcode() {
+            for handle in handles {
+                self.send_msft_command(
+                    opcode,
+                    MsftRequest::CancelMonitorAdvertisement,
+                    msft::cancel_monitor_advertisement(handle),
+                );
+            }
+        }
+
+        for index in indexes.iter() {
+            let scanner = &mut self.gatt.as_mut().unwrap().scanner;
+            scanner.scan_filter_clear(*index);
+            scanner.scan_filter_setup(
+                scanner_id as u8,
+                APCF_ACTION_DELETE,
+                *index,
+                ScanFilter::default().to_filter_param(None),
+            );
+        }
+        self.free_filter_indexes.extend(indexes);
+    }
+
+    /// Returns the vendor opcode of the MSFT commands, if the controller supports the monitors.
+    fn msft_opcode(&self) -> Option<u16> {
+        match self.msft_support {
+            MsftSupport::Supported(opcode) => Some(opcode),
+            _ => None,
+        }
+    }
+
+    fn send_msft_command(&mut self, opcode: u16, request: MsftRequest, params: Vec<u8>) {
+        self.pending_msft_requests.push_back(request);
+        self.gatt.as_mut().unwrap().scanner.msft_command(opcode, params);
+    }
+
+    /// Reads the features of the controller if it implements the MSFT extension. Detection is
+    /// retried by the next scan while the adapter is not enabled.
+    fn detect_msft(&mut self) {
+        let manufacturer = match &self.adapter {
+            Some(adapter) => adapter.lock().unwrap().get_controller_manufacturer(),
+            None => None,
+        };
+        let manufacturer = match manufacturer {
+            Some(m) => m,
+            None => return,
+        };
+
+        match msft::opcode_for_manufacturer(manufacturer) {
+            Some(opcode) => {
+                self.msft_support = MsftSupport::Detecting(opcode);
+                self.send_msft_command(
+                    opcode,
+                    MsftRequest::ReadSupportedFeatures,
+                    msft::read_supported_features(),
+                );
+            }
+            None => self.msft_support = MsftSupport::Unsupported,
+        }
+    }
+
+    /// Adds a MSFT monitor for each filter of a scanner. Returns false, adding none, if the
+    /// monitors are not supported or some filter cannot be monitored.
+    fn offload_msft_monitors(&mut self, scanner_id: i32) -> bool {
+        let opcode = match self.msft_opcode() {
+            Some(opcode) => opcode,
+            None => return false,
+        };
+        let scanner = match self.find_scanner_by_id(scanner_id) {
+            Some(s) => s,
+            None => return false,
+        };
+
+        if scanner.filters.is_empty() {
+            return false;
+        }
+
+        let monitors: Option<Vec<Vec<u8>>> = scanner
+            .filters
+            .iter()
+            .map(|filter| {
+                msft::monitor_advertisement(
+                    filter.rssi_high_threshold,
+                    filter.rssi_low_threshold,
+                    &filter.to_msft_condition()?,
+                )
+            })
+            .collect();
+        let monitors = match monitors {
+            Some(m) => m,
+            None => return false,
+        };
+
+        let mut ids = vec![];
+        for params in monitors {
+            self.next_msft_monitor_id += 1;
+            ids.push(self.next_msft_monitor_id);
+            self.send_msft_command(
+                opcode,
+                MsftRequest::MonitorAdvertisement(self.next_msft_monitor_id),
+                params,
+            );
+        }
+
+        if let Some(scanner) = self.find_scanner_by_id(scanner_id) {
+            scanner.msft_pending = ids;
+        }
+        true
+    }
+
+    /// Moves the filters of the active scanners from APCF to MSFT monitors, once the controller
+    /// is known to support them.
+    fn reoffload_scan_filters(&mut self) {
+        let scanner_ids: Vec<i32> = self
+            .scanners
+            .values()
+            .filter(|s| s.is_scanning && !s.filters.is_empty())
+            .filter_map(|s| s.scanner_id.map(|id| id.into()))
+            .collect();
+
+        for scanner_id in scanner_ids {
+            self.remove_offloaded_scan_filters(scanner_id);
+            self.offload_scan_filters(scanner_id);
+        }
+        self.update_scan();
+    }
+
+    /// Reads a characteristic on behalf of a procedure of the stack, such as `read_service`.
+    fn read_characteristic_for_procedure(&mut self, conn_id: i32, handle: i32) {
+        if let Some(address) = self.context_map.get_address_by_conn_id(conn_id) {
+            self.trace_att(&address, |trace, now| {
+                trace.record_request(now, AttPduDirection::Sent, handle, ATT_READ_REQ, handle, 0)
+            });
+        }
+
+        self.gatt.as_ref().unwrap().client.read_characteristic(
+            conn_id,
+            handle as u16,
+            AUTH_REQ_NONE,
+        );
+    }
+
+    /// Reads the next characteristic of the ongoing `read_service` on a connection, or delivers
+    /// the results if there is none left.
+    fn continue_service_read(&mut self, conn_id: i32) {
+        let handle = match self.service_reads.get(&conn_id) {
+            Some(read) => read.current_handle(),
+            None => return,
+        };
+
+        if let Some(handle) = handle {
+            self.read_characteristic_for_procedure(conn_id, handle);
+            return;
+        }
+
+        let read = self.service_reads.remove(&conn_id).unwrap();
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+        let client = self.context_map.get_client_by_conn_id(conn_id);
+        if let (Some(address), Some(client)) = (address, client) {
+            client.callback.on_service_read(
+                callback_address(&address),
+                read.service_uuid,
+                read.results,
+            );
+        }
+    }
+
+    /// Reads the next characteristic of the ongoing `check_conformance` on a connection, or
+    /// delivers the report if there is none left.
+    fn continue_conformance_check(&mut self, conn_id: i32) {
+        let handle = match self.conformance_checks.get(&conn_id) {
+            Some(check) if !check.awaiting_database => check.current_handle(),
+            _ => return,
+        };
+
+        if let Some(handle) = handle {
+            self.read_characteristic_for_procedure(conn_id, handle);
+            return;
+        }
+
+        let check = self.conformance_checks.remove(&conn_id).unwrap();
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+        let client = self.context_map.get_client_by_conn_id(conn_id);
+        if let (Some(address), Some(client)) = (address, client) {
+            client.callback.on_conformance_report(callback_address(&address), check.issues);
+        }
+    }
+
+    /// Prepares the next part of the long write of a connection, or executes the write once the
+    /// whole value is queued on the remote device.
+    fn continue_long_write(&mut self, conn_id: i32) {
+        let (handle, auth_req, chunk) = match self.long_writes.get(&conn_id) {
+            Some(write) => (
+                write.handle,
+                write.auth_req,
+                write.next_chunk().map(|(offset, data)| (offset, data.to_vec())),
+            ),
+            None => return,
+        };
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+
+        match chunk {
+            Some((offset, data)) => {
+                if let Some(address) = &address {
+                    self.trace_att(address, |trace, now| {
+                        trace.record_request(
+                            now,
+                            AttPduDirection::Sent,
+                            handle,
+                            ATT_PREPARE_WRITE_REQ,
+                            handle,
+                            data.len(),
+                        )
+                    });
+                }
+
+                self.gatt.as_mut().unwrap().client.prepare_write(
+                    conn_id,
+                    handle as u16,
+                    offset,
+                    auth_req,
+                    &data,
+                );
+            }
+            None => {
+                if let Some(address) = &address {
+                    self.trace_att(address, |trace, now| {
+                        trace.record_request(
+                            now,
+                            AttPduDirection::Sent,
+                            0,
+                            ATT_EXECUTE_WRITE_REQ,
+                            0,
+                            0,
+                        )
+                    });
+                }
+
+                self.gatt.as_ref().unwrap().client.execute_write(conn_id, 1);
+            }
+        }
+    }
+
+    /// Pushes the most aggressive parameters of the scanning scanners to the controller, and
+    /// reports them to the scanners they are new to.
+    ///
+    /// The aggressive scans are governed so that no scanner holds the controller indefinitely:
+    /// only the aggressive scanners of the highest priority class requesting one compete, taking
+    /// turns of `SCAN_GOVERNOR_SLICE`, and the others are throttled to `SCAN_GOVERNOR_DUTY_CAP`.
+    fn update_scan_parameters(&mut self) {
+        let competitors = self.scan_competitors();
+        if !self.scan_holder.map_or(false, |id| competitors.contains(&id)) {
+            self.scan_holder = next_scan_holder(&competitors, self.scan_holder);
+        }
+        if competitors.len() > 1 {
+            if self.scan_governor_rotation.is_none() {
+                self.schedule_scan_governor_rotation();
+            }
+        } else if let Some(rotation) = self.scan_governor_rotation.take() {
+            rotation.abort();
+        }
+
+        let holder = self.scan_holder;
+        let scanning: Vec<(u8, ScanParameters)> = self
+            .scanners
+            .values()
+            .filter(|s| s.is_scanning)
+            .filter_map(|s| s.scanner_id.map(|id| (id, s.scan_parameters)))
+            .map(|(id, p)| if Some(id) == holder { (id, p) } else { (id, p.throttled()) })
+            .collect();
+        let parameters = match arbitrate_scan_parameters(scanning.iter().map(|(_, p)| *p)) {
+            Some(p) => p,
+            None => return,
+        };
+
+        if self.applied_scan_parameters != Some(parameters) {
+            let winner_id = scanning.iter().find(|(_, p)| *p == parameters).unwrap().0;
+            debug!("Scanning with parameters {:?} of scanner {}", parameters, winner_id);
+            self.gatt.as_mut().unwrap().scanner.set_scan_parameters(
+                winner_id,
+                parameters.interval,
+                parameters.window,
+            );
+            self.applied_scan_parameters = Some(parameters);
+        }
+
+        // The controller scans on the PHYs of every scanner, see `ScanSettings::phys`.
+        let scan_phys =
+            self.scanners.values().filter(|s| s.is_scanning).fold(0, |phys, s| phys | s.scan_phys);
+        if self.applied_scan_phys != Some(scan_phys) {
+            debug!("Scanning on the PHYs {:#04x}", scan_phys);
+            self.gatt.as_mut().unwrap().scanner.set_scan_phys(scanning[0].0, scan_phys);
+            self.applied_scan_phys = Some(scan_phys);
+        }
+
+        for scanner in self.scanners.values_mut().filter(|s| s.is_scanning) {
+            if scanner.reported_scan_parameters == Some(parameters) {
+                continue;
+            }
+
+            scanner.reported_scan_parameters = Some(parameters);
+            if let Some(id) = scanner.scanner_id {
+                scanner.callback.on_scan_parameters_changed(
+                    id.into(),
+                    parameters.interval.into(),
+                    parameters.window.into(),
+                );
+            }
+        }
+
+        for scanner in self.scanners.values_mut().filter(|s| s.is_scanning) {
+            let id = match scanner.scanner_id {
+                Some(id) => id,
+                None => continue,
+            };
+            let duty_cycle = (
+                scanner.scan_parameters.duty_cycle(),
+                effective_duty_cycle(
+                    &scanner.scan_parameters,
+                    competitors.len(),
+                    competitors.contains(&id),
+                ),
+            );
+            if scanner.reported_duty_cycle == Some(duty_cycle) {
+                continue;
+            }
+
+            scanner.reported_duty_cycle = Some(duty_cycle);
+            scanner.callback.on_scan_duty_cycle_changed(id.into(), duty_cycle.0, duty_cycle.1);
+        }
+    }
+
+    fn schedule_scan_governor_rotation(&mut self) {
+        if let Some(tx) = self.tx.clone() {
+            self.scan_governor_rotation = Some(tokio::spawn(async move {
+                time::sleep(SCAN_GOVERNOR_SLICE).await;
+                let _ = tx.send(Message::ScanGovernorRotate).await;
+            }));
+        }
+    }
+
+    /// Returns the ids of the aggressive scanners of the highest priority class requesting one,
+    /// which take turns scanning with their own parameters.
+    fn scan_competitors(&self) -> Vec<u8> {
+        let aggressive: Vec<&Scanner> = self
+            .scanners
+            .values()
+            .filter(|s| s.is_scanning && s.scan_parameters.is_aggressive())
+            .collect();
+        let top_priority = aggressive.iter().map(|s| s.priority).fold(None, |top, p| match top {
+            Some(top) if top >= p => Some(top),
+            _ => Some(p),
+        });
+
+        aggressive
+            .iter()
+            .filter(|s| Some(s.priority) == top_priority)
+            .filter_map(|s| s.scanner_id)
+            .collect()
+    }
+
+    /// Hands the aggressive scan parameters over to the next competing scanner.
+    pub(crate) fn rotate_scan_holder(&mut self) {
+        self.scan_governor_rotation = None;
+        if !self.scanners.values().any(|s| s.is_scanning) {
+            return;
+        }
+
+        self.scan_holder = next_scan_holder(&self.scan_competitors(), self.scan_holder);
+        self.update_scan_parameters();
+    }
+
+    fn update_scan(&mut self) {
+        let is_scanning = self.scanners.values().any(|s| s.is_scanning);
+
+        // The controller filters can only be used if every active scanner has offloaded filters
+        // of the same kind, otherwise they would drop results wanted by the other scanners.
+        let use_msft = is_scanning
+            && self
+                .scanners
+                .values()
+                .filter(|s| s.is_scanning)
+                .all(|s| !s.filters.is_empty() && s.msft_handles.len() == s.filters.len());
+        if use_msft != self.msft_filter_enabled {
+            if let Some(opcode) = self.msft_opcode() {
+                self.send_msft_command(
+                    opcode,
+                    MsftRequest::SetAdvertisementFilterEnable,
+                    msft::set_advertisement_filter_enable(use_msft),
+                );
+            }
+            self.msft_filter_enabled = use_msft;
+        }
+
+        let use_filters = is_scanning
+            && self
+                .scanners
+                .values()
+                .filter(|s| s.is_scanning)
+                .all(|s| !s.filter_indexes.is_empty());
+        if use_filters != self.scan_filters_enabled {
+            if use_filters {
+                self.gatt.as_mut().unwrap().scanner.scan_filter_enable();
+            } else {
+                self.gatt.as_mut().unwrap().scanner.scan_filter_disable();
+            }
+            self.scan_filters_enabled = use_filters;
+        }
+
+        if is_scanning {
+            self.update_scan_parameters();
+            self.gatt.as_mut().unwrap().scanner.start_scan();
+        } else {
+            if let Some(rotation) = self.scan_governor_rotation.take() {
+                rotation.abort();
+            }
+            self.scan_holder = None;
+            self.gatt.as_mut().unwrap().scanner.stop_scan();
+        }
+        self.update_le_activity();
+    }
+
+    /// Reports whether LE scanning and advertising are ongoing to the adapter, which tracks the
+    /// radio activity.
+    fn update_le_activity(&self) {
+        let scanning = self.scanners.values().any(|s| s.is_scanning);
+        let advertising = self.advertising_sets.iter().any(|s| s.enabled && s.handle().is_some());
+        if let Some(adapter) = &self.adapter {
+            adapter.lock().unwrap().set_le_activity(scanning, advertising);
+        }
+    }
+
+    /// Returns the value to write to a descriptor for a client. The writes to a CCCD are merged
+    /// with the values wanted by the other clients, see `SharedCccd`, and None is returned if the
+    /// device already holds the merged value.
+    fn merge_cccd_write(
+        &mut self,
+        conn_id: i32,
+        client_id: i32,
+        handle: i32,
+        value: Vec<u8>,
+    ) -> Option<Vec<u8>> {
+        let is_cccd = self.gatt_dbs.get(&conn_id).map_or(false, |db| {
+            db.iter()
+                .flat_map(|s| s.characteristics.iter())
+                .flat_map(|c| c.descriptors.iter())
+                .any(|d| d.instance_id == handle && d.uuid == CCCD_UUID)
+        });
+        let address = match self.context_map.get_address_by_conn_id(conn_id) {
+            Some(address) if is_cccd && value.len() == 2 => address,
+            _ => return Some(value),
+        };
+
+        let wanted = u16::from_le_bytes([value[0], value[1]]);
+        let shared_cccds = &mut self.shared_cccds;
+        let write = shared_cccds.entry((address, handle)).or_default().set(client_id, wanted);
+        write.map(|union| union.to_le_bytes().to_vec())
+    }
+
+    /// Marks the value of a shared CCCD as unknown after a failed write, so that the next write
+    /// goes out.
+    fn forget_shared_cccd_value(&mut self, conn_id: i32, handle: i32) {
+        let address = match self.context_map.get_address_by_conn_id(conn_id) {
+            Some(address) => address,
+            None => return,
+        };
+
+        if let Some(cccd) = self.shared_cccds.get_mut(&(address, handle)) {
+            cccd.written = None;
+        }
+    }
+
+    /// Returns the connection of a client to a device, or an error if the client is not
+    /// registered or not connected to the device.
+    fn get_client_conn_id(&self, client_id: i32, addr: &String) -> BtResult<i32> {
+        if self.context_map.get_by_client_id(client_id).is_none() {
+            return Err(BtError::not_found(format!("Client {} is not registered", client_id)));
+        }
+
+        self.context_map
+            .get_conn_id_from_address(client_id, addr)
+            .ok_or_else(|| BtError::not_found(format!("Client is not connected to {}", addr)))
+    }
+
+    /// Returns the first characteristic `uuid` discovered on a connection with its handle between
+    /// `start_handle` and `end_handle`.
+    fn find_characteristic(
+        &self,
+        conn_id: i32,
+        uuid: &Uuid128Bit,
+        start_handle: i32,
+        end_handle: i32,
+    ) -> BtResult<&BluetoothGattCharacteristic> {
+        let db = self
+            .gatt_dbs
+            .get(&conn_id)
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "Services not discovered"))?;
+
+        db.iter()
+            .flat_map(|s| s.characteristics.iter())
+            .find(|c| {
+                c.uuid == *uuid && c.instance_id >= start_handle && c.instance_id <= end_handle
+            })
+            .ok_or_else(|| BtError::not_found("Characteristic not found"))
+    }
+
+    /// Journals a write of a client to a device it is not connected to, if the client enabled
+    /// its journal.
+    fn journal_write(
+        &mut self,
+        client_id: i32,
+        addr: &String,
+        handle: i32,
+        write_type: GattWriteType,
+        auth_req: i32,
+        value: Vec<u8>,
+    ) -> GattWriteRequestStatus {
+        let journal = match self.write_journals.get_mut(&client_id) {
+            Some(journal) => journal,
+            None => return GattWriteRequestStatus::Fail,
+        };
+        if RawAddress::from_string(addr.clone()).is_none() {
+            return GattWriteRequestStatus::Fail;
+        }
+
+        match journal.push(addr, handle, write_type, auth_req, value) {
+            Some(id) => {
+                debug!("Journaled write {} of client {} to {} {}", id, client_id, addr, handle);
+                GattWriteRequestStatus::Journaled
+            }
+            None => GattWriteRequestStatus::Fail,
+        }
+    }
+
+    /// Starts sending the writes a client journaled for the device it just connected to.
+    fn start_journal_flush(&mut self, client_id: i32, conn_id: i32, address: &String) {
+        let entries = match self.write_journals.get_mut(&client_id) {
+            Some(journal) => journal.take(address),
+            None => return,
+        };
+        if entries.is_empty() {
+            return;
+        }
+
+        debug!("Sending {} journaled writes of client {} to {}", entries.len(), client_id, address);
+        self.journal_flushes.insert(conn_id, JournalFlush::new(entries));
+        self.continue_journal_flush(conn_id);
+    }
+
+    /// Records the result of a write on a connection, sending the next journaled write once the
+    /// one in flight completed.
+    fn journal_write_completed(&mut self, conn_id: i32, handle: i32, long: bool) {
+        if let Some(flush) = self.journal_flushes.get_mut(&conn_id) {
+            flush.on_result(handle, long);
+        }
+        self.continue_journal_flush(conn_id);
+    }
+
+    /// Returns the number of writes of `handle` sent on a connection whose result has not come
+    /// yet.
+    fn pending_writes(&self, conn_id: i32, handle: i32) -> usize {
+        self.pending_operations.get(&conn_id).map_or(0, |operations| {
+            operations
+                .operations
+                .iter()
+                .filter(|o| {
+                    o.operation == GattOperation::WriteCharacteristic
+                        && o.handle == handle
+                        && o.result.is_none()
+                })
+                .count()
+        })
+    }
+
+    /// Sends the next journaled write of a connection, unless one is in flight. The writes the
+    /// stack rejects fail with `GattStatus::Error` and the next one is sent, a write waiting for
+    /// a long write of the client being sent once the long write completes.
+    fn continue_journal_flush(&mut self, conn_id: i32) {
+        let client_id = match self.context_map.connections.iter().find(|c| c.conn_id == conn_id) {
+            Some(conn) => conn.client_id,
+            None => return,
+        };
+
+        loop {
+            let flush = match self.journal_flushes.get_mut(&conn_id) {
+                Some(flush) => flush,
+                None => return,
+            };
+            let entry = match flush.next() {
+                Some(entry) => entry,
+                None => {
+                    if flush.is_done() {
+                        self.journal_flushes.remove(&conn_id);
+                    }
+                    return;
+                }
+            };
+
+            let (address, handle) = (entry.address.clone(), entry.handle);
+            let ahead = self.pending_writes(conn_id, handle);
+            // The journal records the addresses as reported by the stack.
+            let status = match BtAddress::from_string(&address) {
+                Some(addr) => self.write_characteristic(
+                    client_id,
+                    addr,
+                    handle,
+                    entry.write_type,
+                    entry.auth_req,
+                    entry.value.clone(),
+                ),
+                None => GattWriteRequestStatus::Fail,
+            };
+            let long = self.long_writes.contains_key(&conn_id);
+            let flush = match self.journal_flushes.get_mut(&conn_id) {
+                Some(flush) => flush,
+                None => return,
+            };
+            match status {
+                GattWriteRequestStatus::Success => {
+                    flush.sent(entry, ahead, long);
+                    return;
+                }
+                GattWriteRequestStatus::Busy => {
+                    flush.defer(entry);
+                    return;
+                }
+                status => {
+                    warn!("Journaled write {} to {} failed: {:?}", entry.id, address, status);
+                    if let Some(client) = self.context_map.get_by_client_id(client_id) {
+                        client.callback.on_characteristic_write(
+                            callback_address(&address),
+                            GattStatus::Error.to_i32().unwrap(),
+                            handle,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the result of sending a tracked operation, which is no longer tracked if the
+    /// stack rejected it since no result will arrive.
+    fn untrack_failed_operation(
+        &mut self,
+        conn_id: i32,
+        operation: GattOperation,
+        handle: i32,
+        status: BtStatus,
+    ) -> BtResult<()> {
+        if status != BtStatus::Success {
+            self.complete_operation(conn_id, operation, handle);
+        }
+        BtError::from_status(status as i32)
+    }
+
+    fn log_value(&self, address: &str, operation: &str, handle: i32, value: &[u8]) {
+        // The values may be secrets, such as keys or tokens, so only their length is logged.
+        debug!("[{}]: {} of handle {}: {} bytes", address, operation, handle, value.len());
+    }
+
+    fn track_operation(&mut self, conn_id: i32, operation: GattOperation, handle: i32) {
+        let operations = self.pending_operations.entry(conn_id).or_default();
+        operations.push(operation, handle);
+        // The timer of the requests sent before keeps running.
+        if operations.transaction_started.is_none() {
+            operations.restart_transaction_timer(self.tx.clone(), conn_id);
+        }
+        self.metrics.increment(format!("gatt.operation.{:?}", operation));
+    }
+
+    /// Returns the number of ATT bearers the operations of a connection are spread over. Only the
+    /// clients with EATT support use the EATT bearers of the device.
+    fn att_bearer_count(&self, conn_id: i32) -> usize {
+        if !self.context_map.get_client_by_conn_id(conn_id).map_or(false, |c| c.eatt_support) {
+            return ATT_BEARERS_WITHOUT_EATT;
+        }
+
+        self.context_map
+            .get_address_by_conn_id(conn_id)
+            .and_then(|address| self.eatt_bearers.get(&address))
+            .map_or(ATT_BEARERS_WITHOUT_EATT, |mtus| mtus.len().max(ATT_BEARERS_WITHOUT_EATT))
+    }
+
+    /// Sends a read or write of a client, or queues it until a bearer of the connection is free.
+    fn send_operation(
+        &mut self,
+        conn_id: i32,
+        operation: GattOperation,
+        handle: i32,
+        request: AttRequest,
+    ) -> BtResult<()> {
+        let bearers = self.att_bearer_count(conn_id);
+        let operations = self.pending_operations.entry(conn_id).or_default();
+        if !operations.can_send(bearers) {
+            operations.queue(operation, handle, request);
+            return Ok(());
+        }
+
+        self.track_operation(conn_id, operation, handle);
+        let status = self.send_request(conn_id, operation, handle, &request);
+        self.untrack_failed_operation(conn_id, operation, handle, status)
+    }
+
+    fn send_request(
+        &self,
+        conn_id: i32,
+        operation: GattOperation,
+        handle: i32,
+        request: &AttRequest,
+    ) -> BtStatus {
+        let client = &self.gatt.as_ref().unwrap().client;
+        match (operation, request) {
+            (GattOperation::ReadDescriptor, AttRequest::Read { auth_req }) => {
+                client.read_descriptor(conn_id, handle as u16, *auth_req)
+            }
+            (_, AttRequest::Read { auth_req }) => {
+                client.read_characteristic(conn_id, handle as u16, *auth_req)
+            }
+            (GattOperation::WriteDescriptor, AttRequest::Write { auth_req, value, .. }) => {
+                client.write_descriptor(conn_id, handle as u16, *auth_req, value)
+            }
+            (_, AttRequest::Write { write_type, auth_req, value }) => {
+                let write_type = write_type.to_i32().unwrap();
+                client.write_characteristic(conn_id, handle as u16, write_type, *auth_req, value)
+            }
+        }
+    }
+
+    /// Sends the queued operations of a connection while its bearers are free. The operations
+    /// the stack rejects fail with `GattStatus::Error`.
+    fn send_queued_operations(&mut self, conn_id: i32) {
+        let bearers = self.att_bearer_count(conn_id);
+        loop {
+            let (operation, handle, request) = {
+                let pending_operations = &mut self.pending_operations;
+                let operations = match pending_operations.get_mut(&conn_id) {
+                    Some(operations) => operations,
+                    None => return,
+                };
+                let next = match operations.next_request(bearers) {
+                    Some(next) => next,
+                    None => return,
+                };
+                if operations.transaction_started.is_none() {
+                    operations.restart_transaction_timer(self.tx.clone(), conn_id);
+                }
+                next
+            };
+
+            self.metrics.increment(format!("gatt.operation.{:?}", operation));
+            let status = self.send_request(conn_id, operation, handle, &request);
+            if status != BtStatus::Success {
+                warn!(
+                    "Failed to send the queued {:?} of handle {}: {:?}",
+                    operation, handle, status
+                );
+                if operation == GattOperation::WriteDescriptor {
+                    self.forget_shared_cccd_value(conn_id, handle);
+                }
+                let result = OperationResult {
+                    status: GattStatus::Error.to_i32().unwrap(),
+                    ..Default::default()
+                };
+                // The queue is sent on once the failure is delivered.
+                return self.finish_operation(conn_id, operation, handle, result);
+            }
+        }
+    }
+
+    /// Records the result of a read or write, delivers the results now in request order and
+    /// sends the operations waiting for the bearer it freed.
+    fn finish_operation(
+        &mut self,
+        conn_id: i32,
+        operation: GattOperation,
+        handle: i32,
+        result: OperationResult,
+    ) {
+        let results = match self.pending_operations.get_mut(&conn_id) {
+            Some(operations) => {
+                let results = operations.finish(operation, handle, result);
+                operations.restart_transaction_timer(self.tx.clone(), conn_id);
+                results
+            }
+            None => vec![(operation, handle, result)],
+        };
+
+        self.deliver_operation_results(conn_id, results);
+        self.send_queued_operations(conn_id);
+    }
+
+    /// Delivers the results no longer held back by an operation which was cancelled or failed.
+    fn deliver_held_results(&mut self, conn_id: i32) {
+        let results = match self.pending_operations.get_mut(&conn_id) {
+            Some(operations) => operations.take_results(),
+            None => return,
+        };
+        self.deliver_operation_results(conn_id, results);
+    }
+
+    fn deliver_operation_results(
+        &mut self,
+        conn_id: i32,
+        results: Vec<(GattOperation, i32, OperationResult)>,
+    ) {
+        let address = match self.context_map.get_address_by_conn_id(conn_id) {
+            Some(address) => address,
+            None => return,
+        };
+        let client = match self.context_map.get_client_by_conn_id_mut(conn_id) {
+            Some(client) => client,
+            None => return,
+        };
+
+        for (operation, handle, result) in results {
+            let status = status_with_retries(result.status, result.retries);
+            match operation {
+                GattOperation::ReadCharacteristic => {
+                    client.callback.on_characteristic_read(
+                        callback_address(&address),
+                        status,
+                        handle,
+                        result.value,
+                    );
+                }
+                GattOperation::ReadDescriptor => {
+                    client.callback.on_descriptor_read(
+                        callback_address(&address),
+                        status,
+                        handle,
+                        result.value,
+                    );
+                }
+                GattOperation::WriteCharacteristic if client.is_congested => {
+                    let status = if status == GattStatus::Congested.to_i32().unwrap() {
+                        GattStatus::Success.to_i32().unwrap()
+                    } else {
+                        status
+                    };
+                    client.congestion_queue.push((address.clone(), status, handle));
+                }
+                GattOperation::WriteCharacteristic => {
+                    client.callback.on_characteristic_write(
+                        callback_address(&address),
+                        status,
+                        handle,
+                    );
+                }
+                GattOperation::WriteDescriptor => {
+                    client.callback.on_descriptor_write(callback_address(&address), status, handle);
+                }
+                // The discoveries are reported with `on_search_complete`.
+                GattOperation::Discovery => {}
+            }
+        }
+    }
+
+    /// Schedules another attempt of a read failing with a transient error, as allowed by the
+    /// retry policy of the client. Returns whether the read is retried, its result being dropped.
+    fn schedule_retry(&mut self, conn_id: i32, handle: i32, status: i32) -> bool {
+        if !is_transient_error(status) {
+            return false;
+        }
+        let tx = match self.tx.clone() {
+            Some(tx) => tx,
+            None => return false,
+        };
+        let client_id = match self.context_map.get_client_by_conn_id(conn_id).and_then(|c| c.id) {
+            Some(client_id) => client_id,
+            None => return false,
+        };
+
+        let operation = match self.att_retries.get(conn_id, handle) {
+            Some(retry) => retry.operation,
+            None => return false,
+        };
+        let cancelled = match self.pending_operations.get(&conn_id) {
+            Some(operations) => operations.is_cancelled(operation, handle),
+            None => true,
+        };
+        if cancelled {
+            return false;
+        }
+        let backoff = match self.att_retries.retry(client_id, conn_id, handle, status) {
+            Some(backoff) => backoff,
+            None => return false,
+        };
+
+        debug!(
+            "Retrying {:?} of handle {} in {:?} after status {}",
+            operation, handle, backoff, status
+        );
+        self.metrics.increment("gatt.att_retry");
+        tokio::spawn(async move {
+            time::sleep(backoff).await;
+            let _ = tx.send(Message::GattRetryRead(conn_id, handle)).await;
+        });
+        true
+    }
+
+    /// Sends again a read which failed with a transient error, see `schedule_retry`.
+    pub(crate) fn retry_read(&mut self, conn_id: i32, handle: i32) {
+        let retry = match self.att_retries.get(conn_id, handle) {
+            Some(retry) => retry,
+            None => return,
+        };
+        let address = match self.context_map.get_address_by_conn_id(conn_id) {
+            Some(address) => address,
+            None => return,
+        };
+
+        // The client was already answered if the read was cancelled while waiting.
+        let cancelled = match self.pending_operations.get(&conn_id) {
+            Some(operations) => operations.is_cancelled(retry.operation, handle),
+            None => true,
+        };
+        if cancelled {
+            self.att_retries.take(conn_id, handle);
+            self.complete_operation(conn_id, retry.operation, handle);
+            self.send_queued_operations(conn_id);
+            return;
+        }
+
+        self.trace_att(&address, |trace, now| {
+            trace.record_request(now, AttPduDirection::Sent, handle, ATT_READ_REQ, handle, 0)
+        });
+        let client = &self.gatt.as_ref().unwrap().client;
+        let status = match retry.operation {
+            GattOperation::ReadDescriptor => {
+                client.read_descriptor(conn_id, handle as u16, retry.auth_req)
+            }
+            _ => client.read_characteristic(conn_id, handle as u16, retry.auth_req),
+        };
+        if status == BtStatus::Success {
+            return;
+        }
+
+        // The read fails with the error of its last attempt.
+        warn!("[{}]: Failed to retry the read of handle {}: {:?}", address, handle, status);
+        let retries = self.att_retries.take(conn_id, handle);
+        let result = OperationResult { status: retry.status, value: vec![], retries };
+        self.finish_operation(conn_id, retry.operation, handle, result);
+    }
+
+    /// Delivers the notification queue of a client, see `set_notification_queue`.
+    pub(crate) fn deliver_notifications(&mut self, client_id: i32) {
+        let (dropped, queued) = match self.notification_queues.get_mut(&client_id) {
+            Some(queue) => queue.take(),
+            None => return,
+        };
+        let client = match self.context_map.get_by_client_id(client_id) {
+            Some(client) => client,
+            None => return,
+        };
+
+        for ((address, handle), count) in dropped {
+            warn!(
+                "[{}]: Dropped {} values of handle {} for client {}",
+                address, count, handle, client_id
+            );
+            self.metrics.add("gatt.notification_dropped", count as u64);
+            client.callback.on_notification_queue_overflow(
+                callback_address(&address),
+                handle,
+                count,
+            );
+        }
+        for notification in queued {
+            client.callback.on_notify(
+                callback_address(&notification.address),
+                notification.handle,
+                notification.value,
+            );
+        }
+    }
+
+    /// Records the result of an operation. Returns whether the result must be dropped, as the
+    /// operation was cancelled.
+    fn complete_operation(&mut self, conn_id: i32, operation: GattOperation, handle: i32) -> bool {
+        match self.pending_operations.get_mut(&conn_id) {
+            Some(operations) => {
+                let cancelled = operations.complete(operation, handle);
+                operations.restart_transaction_timer(self.tx.clone(), conn_id);
+                cancelled
+            }
+            None => false,
+        }
+    }
+
+    /// Fails the operations of a connection whose ATT transaction timed out with
+    /// `GattStatus::Error`.
+    pub(crate) fn time_out_transactions(&mut self, conn_id: i32) {
+        let timed_out = {
+            let pending_operations = &mut self.pending_operations;
+            let operations = match pending_operations.get_mut(&conn_id) {
+                Some(operations) if operations.has_timed_out() => operations,
+                _ => return,
+            };
+            let timed_out = operations.time_out();
+            // The results of the cancelled operations may still arrive, they are forgotten at
+            // the next timeout otherwise.
+            operations.restart_transaction_timer(self.tx.clone(), conn_id);
+            timed_out
+        };
+
+        let address = match self.context_map.get_address_by_conn_id(conn_id) {
+            Some(address) => address,
+            None => return,
+        };
+        warn!(
+            "[{}]: No response to {} ATT requests for {:?}",
+            address,
+            timed_out.len(),
+            ATT_TRANSACTION_TIMEOUT
+        );
+        self.metrics.add("gatt.att_timeout", timed_out.len() as u64);
+
+        let status = GattStatus::Error.to_i32().unwrap();
+        for (operation, handle) in timed_out {
+            let retries = self.att_retries.take(conn_id, handle);
+            let status = status_with_retries(status, retries);
+            self.fail_operations(conn_id, &address, vec![(operation, handle)], status);
+        }
+        self.deliver_held_results(conn_id);
+    }
+
+    /// Delivers the results of operations failing with `status` to the client of a connection.
+    fn fail_operations(
+        &mut self,
+        conn_id: i32,
+        address: &str,
+        operations: Vec<(GattOperation, i32)>,
+        status: i32,
+    ) {
+        let client = match self.context_map.get_client_by_conn_id(conn_id) {
+            Some(client) => client,
+            None => return,
+        };
+
+        for (operation, handle) in operations {
+            let callback = &client.callback;
+            let addr = callback_address(address);
+            match operation {
+                GattOperation::Discovery => {
+                    self.cancelled_discoveries.insert(conn_id);
+                    callback.on_search_complete(addr, vec![], status);
+                }
+                GattOperation::ReadCharacteristic => {
+                    callback.on_characteristic_read(addr, status, handle, vec![]);
+                }
+                GattOperation::WriteCharacteristic => {
+                    callback.on_characteristic_write(addr, status, handle);
+                }
+                GattOperation::ReadDescriptor => {
+                    callback.on_descriptor_read(addr, status, handle, vec![]);
+                }
+                GattOperation::WriteDescriptor => {
+                    callback.on_descriptor_write(addr, status, handle);
+                }
+            }
+        }
+    }
+
+    /// Reads the RSSI of a monitored connection, reported in `read_remote_rssi_cb`, and schedules
+    /// the next read.
+    pub(crate) fn poll_rssi(&mut self, conn_id: i32) {
+        let client_id = self.context_map.get_client_by_conn_id(conn_id).and_then(|c| c.id);
+        let addr =
+            self.context_map.get_address_by_conn_id(conn_id).and_then(RawAddress::from_string);
+        let (client_id, addr) = match (client_id, addr) {
+            (Some(client_id), Some(addr)) => (client_id, addr),
+            _ => {
+                self.rssi_monitors.remove(&conn_id);
+                return;
+            }
+        };
+
+        let tx = self.tx.clone();
+        let monitor = match self.rssi_monitors.get_mut(&conn_id) {
+            Some(monitor) => monitor,
+            None => return,
+        };
+
+        if monitor.polls_pending > 0 {
+            // The read is given up after a whole period without result.
+            monitor.polls_pending = 0;
+        } else {
+            let status = self.gatt.as_ref().unwrap().client.read_remote_rssi(client_id, &addr);
+            if status == BtStatus::Success {
+                monitor.polls_pending += 1;
+            }
+        }
+
+        let period = monitor.period;
+        monitor.poll = tx.map(|tx| {
+            tokio::spawn(async move {
+                time::sleep(period).await;
+                let _ = tx.send(Message::GattRssiPoll(conn_id)).await;
+            })
+        });
+    }
+
+    /// Asks the stack for the attribute database of a connection.
+    fn request_gatt_db(&mut self, conn_id: i32, request: GattDbRequest) {
+        let request_id = self.next_gatt_db_request_id;
+        self.next_gatt_db_request_id += 1;
+        self.gatt_db_requests.insert((conn_id, request_id), request);
+        self.gatt.as_ref().unwrap().client.get_gatt_db(conn_id);
+    }
+
+    /// Returns the oldest request of the database of a connection, which the database reported
+    /// by the stack answers.
+    fn take_gatt_db_request(&mut self, conn_id: i32) -> Option<GattDbRequest> {
+        let key = *self.gatt_db_requests.range((conn_id, 0)..=(conn_id, u64::MAX)).next()?.0;
+        self.gatt_db_requests.remove(&key)
+    }
+
+    fn apply_phy_preference(&self, address: &String, preference: PhyPreference) {
+        let address = match RawAddress::from_string(address.clone()) {
+            Some(addr) => addr,
+            None => return,
+        };
+
+        self.gatt.as_ref().unwrap().client.set_preferred_phy(
+            &address,
+            preference.tx_phy.to_u8().unwrap(),
+            preference.rx_phy.to_u8().unwrap(),
+            preference.phy_options as u16,
+        );
+    }
+
+    /// Applies the persisted PHY preference of a device that connected, or else the default PHY
+    /// preference. The persisted preference is dropped if the device is no longer bonded.
+    fn restore_phy_preference(&mut self, address: &String) {
+        match self.phy_preferences.get(address) {
+            Some(preference) if self.is_bonded(address) => {
+                return self.apply_phy_preference(address, preference);
+            }
+            Some(_) => self.phy_preferences.remove(address),
+            None => (),
+        }
+
+        if self.default_phy_preference != PhyPreference::default() {
+            self.apply_phy_preference(address, self.default_phy_preference);
+        }
+    }
+
+    /// Applies the PHY preference of a client that connected to a device, if it has one.
+    fn apply_client_phy_preference(&mut self, client_id: i32, address: &String) {
+        let preference = self.client_phy_preferences.get(&(client_id, address.clone())).cloned();
+        if let Some(preference) = preference {
+            self.apply_phy_preference(address, preference);
+        }
+    }
+
+    /// Tunes the LE link of a device that connected, whichever client or profile connected it,
+    /// with its overridden profile, or else with the profile of its class if bonded.
+    pub(crate) fn on_le_link_connected(&mut self, address: String) {
+        let overridden = self.link_profile_overrides.get(&address);
+        let bonded_uuids = match overridden {
+            None if self.is_bonded(&address) => self.adapter.as_ref().map(|adapter| {
+                let device = BluetoothDevice::new(address.clone(), String::from(""));
+                adapter.lock().unwrap().get_remote_uuids(device)
+            }),
+            _ => None,
+        };
+
+        let profile = link_tuning::select(overridden, bonded_uuids.as_deref());
+        self.link_profiles.insert(address.clone(), profile);
+        self.apply_link_profile(&address, profile);
+
+        let tuning = link_tuning::parameters(profile).map(ConnectionParameters::from);
+        let _ = self.update_connection_parameters(&address, |requests| requests.set_tuning(tuning));
+    }
+
+    pub(crate) fn on_le_link_disconnected(&mut self, address: String) {
+        self.link_profiles.remove(&address);
+        self.connection_parameters.remove(&address);
+        // Syncs are only transferred over a connection.
+        if let Some(addr) = BtAddress::from_string(&address) {
+            self.past_receivers.remove(&addr);
+        }
+        // The EATT bearers go down with the link.
+        self.eatt_bearers.remove(&address);
+    }
+
+    /// Tunes the link of a device again after its overrides changed, if it is connected.
+    fn retune_link(&mut self, address: &String) {
+        if self.link_profiles.contains_key(address) {
+            self.on_le_link_connected(address.clone());
+        }
+    }
+
+    /// Requests the MTU of the link tuning profile of a device on a connection of a client to
+    /// it, the MTU being exchanged over ATT.
+    fn apply_link_mtu(&self, address: &String, conn_id: i32) {
+        let mtu = self
+            .link_profiles
+            .get(address)
+            .and_then(|profile| link_tuning::parameters(*profile))
+            .and_then(|parameters| parameters.mtu);
+        if let Some(mtu) = mtu {
+            self.gatt.as_ref().unwrap().client.configure_mtu(conn_id, mtu);
+        }
+    }
+
+    /// Applies the MTU and the PHY of a link tuning profile, its connection parameters being
+    /// arbitrated with the requests of the clients.
+    fn apply_link_profile(&self, address: &String, profile: LinkTuningProfile) {
+        let (addr, parameters) =
+            match (RawAddress::from_string(address.clone()), link_tuning::parameters(profile)) {
+                (Some(addr), Some(parameters)) => (addr, parameters),
+                _ => return,
+            };
+
+        debug!("[{}]: Applying link tuning profile {:?}", address, profile);
+        let client = &self.gatt.as_ref().unwrap().client;
+        // Requested once a client connects otherwise.
+        if let Some(connection) =
+            self.context_map.connections.iter().find(|c| c.address == *address)
+        {
+            self.apply_link_mtu(address, connection.conn_id);
+        }
+        // The PHY preference of the device takes precedence.
+        if let Some(phy) = parameters.phy {
+            if self.phy_preferences.get(address).is_none() {
+                let phy = phy.to_u8().unwrap();
+                client.set_preferred_phy(&addr, phy, phy, 0);
+            }
+        }
+    }
+
+    /// Updates the connection parameters requested for a device, applying them to its link if
+    /// the arbitrated parameters changed.
+    fn update_connection_parameters(
+        &mut self,
+        address: &String,
+        update: impl FnOnce(&mut ParameterRequests) -> Option<ConnectionParameters>,
+    ) -> BtResult<()> {
+        let requests = self.connection_parameters.entry(address.clone()).or_default();
+        let parameters = update(requests);
+        if requests.is_empty() {
+            self.connection_parameters.remove(address);
+        }
+
+        let (addr, parameters) = match (RawAddress::from_string(address.clone()), parameters) {
+            (Some(addr), Some(parameters)) => (addr, parameters),
+            _ => return Ok(()),
+        };
+        debug!("[{}]: Applying connection parameters {:?}", address, parameters);
+        let status = self.gatt.as_ref().unwrap().client.conn_parameter_update(
+            &addr,
+            parameters.interval.0,
+            parameters.interval.1,
+            parameters.latency,
+            parameters.timeout,
+            parameters.ce_len.0,
+            parameters.ce_len.1,
+        );
+        BtError::from_status(status as i32)
+    }
+
+    /// Drops the connection parameters requested by a client for a device, applying the ones
+    /// requested for the device otherwise if they changed.
+    fn drop_connection_parameters(&mut self, client_id: i32, address: &String) {
+        if self.connection_parameters.contains_key(address) {
+            let _ =
+                self.update_connection_parameters(address, |requests| requests.remove(client_id));
+        }
+    }
+
+    /// Connects a client to a device, sharing the link of the other clients of the device.
+    fn connect_shared(&mut self, address: &RawAddress, request: ConnectRequest) {
+        let addr = address.to_string();
+        let action = self.shared_connections.entry(addr.clone()).or_default().request(request);
+
+        match action {
+            ConnectAction::Connect => {
+                self.outgoing_connections.insert(addr);
+                self.gatt.as_ref().unwrap().client.connect(
+                    request.client_id,
+                    address,
+                    request.is_direct,
+                    request.transport,
+                    request.opportunistic,
+                    request.phy,
+                );
+            }
+            ConnectAction::Wait => {
+                debug!("Client {} waits for the pending connection to {}", request.client_id, addr)
+            }
+            ConnectAction::Connected => {
+                if let Some(client) = self.context_map.get_by_client_id(request.client_id) {
+                    client.callback.on_client_connection_state(
+                        GattStatus::Success as i32,
+                        request.client_id,
+                        true,
+                        BtAddress::from(*address),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Starts a background connection of a client, which completes once the device advertises.
+    fn connect_in_background(&mut self, client_id: i32, address: &RawAddress) {
+        self.connect_shared(
+            address,
+            ConnectRequest {
+                client_id,
+                is_direct: false,
+                transport: BtTransport::Le as i32,
+                opportunistic: false,
+                phy: LePhy::Phy1m as i32,
+            },
+        );
+    }
+
+    /// Withdraws the pending connection request of a client. Returns false if it had none.
+    fn cancel_shared_connect(&mut self, client_id: i32, address: &RawAddress) -> bool {
+        let addr = address.to_string();
+        let cancelled = match self.shared_connections.get_mut(&addr) {
+            Some(connection) => connection.cancel(client_id),
+            None => None,
+        };
+
+        let (was_connecting, requests) = match cancelled {
+            Some(cancelled) => cancelled,
+            None => return false,
+        };
+
+        if was_connecting {
+            // A connection ID of 0 cancels the pending attempt.
+            self.gatt.as_ref().unwrap().client.disconnect(client_id, address, 0);
+        }
+        if let Some(client) = self.context_map.get_by_client_id(client_id) {
+            client.callback.on_client_connection_state(
+                GattStatus::Success as i32,
+                client_id,
+                false,
+                BtAddress::from(*address),
+            );
+        }
+
+        // The clients that were waiting for the cancelled attempt make their own.
+        for request in requests {
+            self.connect_shared(address, request);
+        }
+        true
+    }
+
+    /// Returns the identity address of a device found by a scan, see `IdentityResolver`.
+    fn identity_of(&mut self, address: &String) -> String {
+        let adapter = self.adapter.clone();
+        self.identity_resolver.identity_of(address, || match adapter {
+            Some(adapter) => adapter.lock().unwrap().get_identity_keys(),
+            None => vec![],
+        })
+    }
+
+    fn is_bonded(&self, address: &String) -> bool {
+        self.adapter.as_ref().map_or(false, |adapter| {
+            let device = BluetoothDevice::new(address.clone(), String::from(""));
+            adapter.lock().unwrap().get_bond_state(device) == BtBondState::Bonded as u32
+        })
+    }
+
+    /// Applies the peripheral connection policy to a server connection. Connections initiated by
+    /// the local device are always accepted.
+    fn check_peripheral_connection(&mut self, address: &String) -> PeripheralDecision {
+        if let Some(decision) = self.peripheral_decisions.get(address) {
+            return *decision;
+        }
+
+        let is_outgoing = self.outgoing_connections.remove(address)
+            || self.context_map.connections.iter().any(|conn| &conn.address == address);
+        let decision = if is_outgoing {
+            PeripheralDecision::Initiated
+        } else {
+            self.peripheral_decision_for(address)
+        };
+
+        if decision == PeripheralDecision::Rejected {
+            warn!("Rejecting the connection of central {}", address);
+        }
+        self.set_peripheral_decision(address, decision);
+        decision
+    }
+
+    fn peripheral_decision_for(&self, address: &String) -> PeripheralDecision {
+        decide_peripheral_connection(
+            self.peripheral_policy,
+            self.peripheral_policy == PeripheralConnectionPolicy::BondedOnly
+                && self.is_bonded(address),
+            BtAddress::from_string(address)
+                .map_or(false, |addr| self.peripheral_allow_list.contains(&addr)),
+            self.peripheral_agent.is_some(),
+        )
+    }
+
+    /// Records the decision on a central. The agent is asked about the pending centrals, which
+    /// are rejected if it does not answer within `PERIPHERAL_AGENT_TIMEOUT`.
+    fn set_peripheral_decision(&mut self, address: &String, decision: PeripheralDecision) {
+        if let Some(timeout) = self.peripheral_agent_timeouts.remove(address) {
+            timeout.abort();
+        }
+
+        if decision == PeripheralDecision::Pending {
+            // The addresses of the connections are reported by the stack, hence valid.
+            self.peripheral_agent.as_ref().unwrap().on_peripheral_connection_request(
+                BtAddress::from_string(address).unwrap_or_default(),
+            );
+
+            if let Some(tx) = self.tx.clone() {
+                let addr = address.clone();
+                let timeout = tokio::spawn(async move {
+                    time::sleep(PERIPHERAL_AGENT_TIMEOUT).await;
+                    let _ = tx.send(Message::GattPeripheralAgentTimeout(addr)).await;
+                });
+                self.peripheral_agent_timeouts.insert(address.clone(), timeout);
+            }
+        }
+
+        self.peripheral_decisions.insert(address.clone(), decision);
+    }
+
+    fn forget_peripheral_decision(&mut self, address: &String) {
+        if let Some(timeout) = self.peripheral_agent_timeouts.remove(address) {
+            timeout.abort();
+        }
+        self.peripheral_decisions.remove(address);
+    }
+
+    /// Rejects a central the agent did not decide on in time.
+    pub(crate) fn time_out_peripheral_agent(&mut self, address: String) {
+        self.peripheral_agent_timeouts.remove(&address);
+        if self.peripheral_decisions.get(&address) != Some(&PeripheralDecision::Pending) {
+            return;
+        }
+
+        warn!("The agent did not decide on central {} in time", address);
+        self.set_peripheral_decision(&address, PeripheralDecision::Revoked);
+        self.disconnect_central(&address);
+    }
+
+    /// Applies the peripheral connection policy again to the connected centrals, after it
+    /// changed. The centrals no longer accepted are disconnected.
+    fn reapply_peripheral_policy(&mut self) {
+        let addresses: Vec<String> = self
+            .peripheral_decisions
+            .iter()
+            .filter(|(_, decision)| {
+                matches!(decision, PeripheralDecision::Accepted | PeripheralDecision::Pending)
+            })
+            .map(|(address, _)| address.clone())
+            .collect();
+
+        for address in addresses {
+            let decision = self.peripheral_decision_for(&address);
+            if Some(&decision) == self.peripheral_decisions.get(&address) {
+                continue;
+            }
+
+            if decision == PeripheralDecision::Rejected {
+                warn!("Disconnecting central {} no longer accepted", address);
+                self.set_peripheral_decision(&address, PeripheralDecision::Revoked);
+                self.disconnect_central(&address);
+            } else {
+                self.set_peripheral_decision(&address, decision);
+            }
+        }
+    }
+
+    /// Disconnects all the server connections of a central.
+    fn disconnect_central(&self, address: &String) {
+        let raw_address = match RawAddress::from_string(address.clone()) {
+            Some(addr) => addr,
+            None => return,
+        };
+
+        for conn in self.server_context_map.connections.iter().filter(|c| &c.address == address) {
+            self.gatt.as_ref().unwrap().server.disconnect(
+                conn.server_id,
+                &raw_address,
+                conn.conn_id,
+            );
+        }
+    }
+
+    /// Records a failed connection or operation with `address` for the state snapshots.
+    fn record_error(&mut self, address: &str, operation: &str, status: i32) {
+        if status != GattStatus::Success.to_i32().unwrap() {
+            push_recent_error(
+                &mut self.recent_errors,
+                RecentError::new(address, operation, status),
+            );
+            self.metrics.increment(format!("gatt.failure.{}", operation));
+        }
+    }
+
+    /// Runs `f` on the ATT trace if the PDUs exchanged with `address` are being recorded.
+    fn trace_att<F: FnOnce(&mut AttTrace, Instant)>(&mut self, address: &str, f: F) {
+        if let Some(trace) = self.att_trace.as_mut() {
+            if trace.active && trace.address.eq_ignore_ascii_case(address) {
+                f(trace, Instant::now());
+            }
+        }
+    }
+
+    /// Sends the next notification queued for a server connection, unless the link is congested.
+    /// The following one is sent once this one completes. The notifications which fail to be sent
+    /// are reported and skipped, since no completion would follow them.
+    fn send_next_notification(&mut self, conn_id: i32) {
+        loop {
+            let conn = match self.server_context_map.get_connection_mut(conn_id) {
+                Some(conn) => conn,
+                None => return,
+            };
+
+            if conn.is_congested {
+                return;
+            }
+
+            let notification = match conn.notification_queue.pop() {
+                Some(n) => n,
+                None => return,
+            };
+            let (server_id, address) = (conn.server_id, conn.address.clone());
+
+            if self.send_queued_notification(conn_id, server_id, &address, notification) {
+                return;
+            }
+        }
+    }
+
+    /// Sends a notification taken from the queue of a server connection. Returns false if it
+    /// could not be sent, in which case the failure is reported to the server.
+    fn send_queued_notification(
+        &mut self,
+        conn_id: i32,
+        server_id: i32,
+        address: &str,
+        notification: PendingNotification,
+    ) -> bool {
+        let opcode = if notification.confirm { ATT_HANDLE_VALUE_IND } else { ATT_HANDLE_VALUE_NTF };
+        self.trace_att(address, |trace, now| {
+            trace.record_request(
+                now,
+                AttPduDirection::Sent,
+                notification.handle,
+                opcode,
+                notification.handle,
+                notification.value.len(),
+            )
+        });
+
+        let status = self.gatt.as_ref().unwrap().server.send_indication(
+            server_id,
+            notification.handle,
+            conn_id,
+            notification.confirm as i32,
+            &notification.value,
+        );
+        if status != BtStatus::Success {
+            warn!("Failed to send queued notification to {}: {:?}", address, status);
+            if let Some(server) = self.server_context_map.get_by_server_id(server_id) {
+                server.callback.on_notification_sent(callback_address(address), status as i32);
+            }
+            return false;
+        }
+        true
+    }
+}
+
+// Temporary util that covers only basic string conversion.
+/// Returns an address kept by the stack as passed to the callbacks. The addresses are kept as
+/// formatted from a `RawAddress`, so they always parse.
+fn callback_address(address: &str) -> BtAddress {
+    BtAddress::from_string(address).unwrap_or_default()
+}
+
+// TODO(b/193685325): Implement more UUID utils by using Uuid from gd/hci/uuid.h with cxx.
+fn parse_uuid_string<T: Into<String>>(uuid: T) -> Option<Uuid> {
+    let uuid = uuid.into();
+
+    if uuid.len() != 32 {
+        return None;
+    }
+
+    let mut raw = [0; 16];
+
+    for i in 0..16 {
+        let byte = u8::from_str_radix(&uuid[i * 2..i * 2 + 2], 16);
+        if byte.is_err() {
+            return None;
+        }
+        raw[i] = byte.unwrap();
+    }
+
+    Some(Uuid { uu: raw })
+}
+
+/// Flattens a service into the attribute list expected by the GATT server interface.
+fn service_to_db_elements(service: &BluetoothGattService) -> Vec<BtGattDbElement> {
+    let service_type = if service.service_type == GattDbElementType::SecondaryService as i32 {
+        GattDbElementType::SecondaryService
+    } else {
+        GattDbElementType::PrimaryService
+    };
+
+    let mut elements = vec![BtGattDbElement {
+        uuid: Uuid { uu: service.uuid },
+        type_: service_type as u32,
+        ..Default::default()
+    }];
+
+    for included in &service.included_services {
+        elements.push(BtGattDbElement {
+            uuid: Uuid { uu: included.uuid },
+            type_: GattDbElementType::IncludedService as u32,
+            attribute_handle: included.instance_id as u16,
+            ..Default::default()
+        });
+    }
+
+    for characteristic in &service.characteristics {
+        elements.push(BtGattDbElement {
+            uuid: Uuid { uu: characteristic.uuid },
+            type_: GattDbElementType::Characteristic as u32,
+            properties: characteristic.properties as u8,
+            permissions: characteristic.permissions as u16,
+            ..Default::default()
+        });
+
+        for descriptor in &characteristic.descriptors {
+            elements.push(BtGattDbElement {
+                uuid: Uuid { uu: descriptor.uuid },
+                type_: GattDbElementType::Descriptor as u32,
+                permissions: descriptor.permissions as u16,
+                ..Default::default()
+            });
+        }
+    }
+
+    elements
+}
+
+/// Rebuilds a service from the attribute list reported by the GATT server interface. The
+/// attribute handles are used as instance IDs.
+fn service_from_db_elements(elements: &[BtGattDbElement]) -> Option<BluetoothGattService> {
+    let mut service: Option<BluetoothGattService> = None;
+
+    for elem in elements {
+        let handle = elem.attribute_handle as i32;
+        match GattDbElementType::from_u32(elem.type_) {
+            Some(GattDbElementType::PrimaryService) | Some(GattDbElementType::SecondaryService) => {
+                if service.is_some() {
+                    warn!("Ignoring attributes after the end of the added service");
+                    break;
+                }
+                service = Some(BluetoothGattService::new(elem.uuid.uu, handle, elem.type_ as i32));
+            }
+
+            Some(GattDbElementType::IncludedService) => {
+                if let Some(s) = service.as_mut() {
+                    s.included_services.push(BluetoothGattService::new(
+                        elem.uuid.uu,
+                        handle,
+                        GattDbElementType::PrimaryService as i32,
+                    ));
+                }
+            }
+
+            Some(GattDbElementType::Characteristic) => {
+                if let Some(s) = service.as_mut() {
+                    s.characteristics.push(BluetoothGattCharacteristic::new(
+                        elem.uuid.uu,
+                        handle,
+                        elem.properties as i32,
+                        elem.permissions as i32,
+                    ));
+                }
+            }
+
+            Some(GattDbElementType::Descriptor) => {
+                if let Some(c) = service.as_mut().and_then(|s| s.characteristics.last_mut()) {
+                    c.descriptors.push(BluetoothGattDescriptor::new(
+                        elem.uuid.uu,
+                        handle,
+                        elem.permissions as i32,
+                    ));
+                }
+            }
+
+            None => warn!("Unknown GATT attribute type {}", elem.type_),
+        }
+    }
+
+    service
+}
+
+#[derive(Debug, FromPrimitive, ToPrimitive)]
+#[repr(u8)]
+/// Status of WriteCharacteristic methods.
+pub enum GattWriteRequestStatus {
+    Success = 0,
+    Fail = 1,
+    Busy = 2,
+    /// The client is not connected to the device and the write is kept in its journal, see
+    /// `IBluetoothGatt::enable_write_journal`.
+    Journaled = 3,
+}
+
+impl IBluetoothGatt for BluetoothGatt {
+    fn register_scanner(&mut self, mut callback: Box<dyn IScannerCallback + Send>) {
+        // Each scanner gets a distinct app UUID to match the registration result with.
+        self.next_scanner_uuid += 1;
+        let mut uuid = [0u8; 16];
+        uuid[12..16].copy_from_slice(&self.next_scanner_uuid.to_be_bytes());
+
+        let tx = self.tx.clone();
+        let callback_id = callback.register_disconnect(Box::new(move |cb_id| {
+            if let Some(tx) = tx.clone() {
+                tokio::spawn(async move {
+                    let _ = tx.send(Message::ScannerCallbackDisconnected(cb_id)).await;
+                });
+            }
+        }));
+
+        self.scanners.insert(
+            uuid,
+            Scanner {
+                callback,
+                callback_id,
+                scanner_id: None,
+                is_scanning: false,
+                scan_parameters: DEFAULT_SCAN_PARAMETERS,
+                reported_scan_parameters: None,
+                priority: ScanPriority::Normal,
+                reported_duty_cycle: None,
+                rssi_smoother: RssiSmoother::new(0),
+                address_filter: AddressFilter::default(),
+                filters: vec![],
+                callback_type: ScanCallbackType::AllMatches,
+                record_delivery: ScanRecordDelivery::default(),
+                scan_phys: SCAN_PHY_LE_1M,
+                scan_permitted: None,
+                match_tracker: None,
+                filter_indexes: vec![],
+                msft_handles: vec![],
+                msft_pending: vec![],
+                manufacturer_data_subscriptions: vec![],
+                liveness: None,
+                result_batch: None,
+            },
+        );
+        self.gatt.as_mut().unwrap().scanner.register_scanner(Uuid { uu: uuid });
+    }
+
+    fn unregister_scanner(&mut self, scanner_id: i32) {
+        self.release_scanner(scanner_id);
+        self.scanners.retain(|_, s| s.scanner_id.map(|id| id as i32) != Some(scanner_id));
+    }
+
+    fn set_scanner_liveness_interval(&mut self, scanner_id: i32, interval_ms: u32) -> BtResult<()> {
+        let interval = Duration::from_millis(interval_ms.into());
+        if interval_ms != 0 && interval < MIN_SCANNER_LIVENESS_INTERVAL {
+            return Err(BtError::invalid_argument(format!(
+                "The liveness interval is at least {:?}",
+                MIN_SCANNER_LIVENESS_INTERVAL
+            )));
+        }
+
+        let tx = self.tx.clone();
+        let scanner = self.find_scanner_by_id(scanner_id).ok_or_else(|| {
+            BtError::not_found(format!("Scanner {} is not registered", scanner_id))
+        })?;
+        if interval_ms == 0 {
+            scanner.liveness = None;
+            return Ok(());
+        }
+
+        let mut liveness = ScannerLiveness { interval, last_ping: Instant::now(), check: None };
+        liveness.check = schedule_liveness_check(tx, scanner_id, liveness.deadline());
+        scanner.liveness = Some(liveness);
+        Ok(())
+    }
+
+    fn ping_scanner(&mut self, scanner_id: i32) -> BtResult<()> {
+        let scanner = self.find_scanner_by_id(scanner_id).ok_or_else(|| {
+            BtError::not_found(format!("Scanner {} is not registered", scanner_id))
+        })?;
+        match scanner.liveness.as_mut() {
+            Some(liveness) => {
+                liveness.last_ping = Instant::now();
+                Ok(())
+            }
+            None => Err(BtError::invalid_argument(format!(
+                "Scanner {} has no liveness interval",
+                scanner_id
+            ))),
+        }
+    }
+
+    fn start_scan(
+        &mut self,
+        scanner_id: i32,
+        settings: ScanSettings,
+        filters: Vec<ScanFilter>,
+    ) -> BtResult<()> {
+        if self.find_scanner_by_id(scanner_id).is_none() {
+            return Err(BtError::not_found(format!("Scanner {} is not registered", scanner_id)));
+        }
+
+        if let Some(i) = filters.iter().position(|f| !f.is_valid()) {
+            return Err(BtError::invalid_argument(format!("Invalid scan filter {}", i)));
+        }
+
+        let address_filter = AddressFilter::new(
+            settings.allowed_addresses.clone(),
+            settings.denied_addresses.clone(),
+        );
+
+        let scan_parameters = match ScanParameters::new(settings.interval, settings.window) {
+            Some(p) => p,
+            None => return Err(BtError::invalid_argument("Invalid scan interval or window")),
+        };
+
+        let scan_phys = match settings.phys {
+            0 => SCAN_PHY_LE_1M,
+            phys if phys & !(SCAN_PHY_LE_1M | SCAN_PHY_LE_CODED) != 0 => {
+                return Err(BtError::invalid_argument(format!("Invalid scan PHYs {:#04x}", phys)));
+            }
+            phys => phys,
+        };
+        if scan_phys & SCAN_PHY_LE_CODED != 0 {
+            let caps = self.advertising_capabilities();
+            if !caps.extended_advertising || !caps.le_coded_phy {
+                return Err(BtError::new(
+                    BtErrorCategory::Unsupported,
+                    "Scanning on the LE Coded PHY is not supported by the controller",
+                ));
+            }
+        }
+
+        let checker = self.scan_permission_checker.as_deref();
+        let scanner = self
+            .scanners
+            .values_mut()
+            .find(|s| s.scanner_id.map(|id| id as i32) == Some(scanner_id))
+            .unwrap();
+        let sender = scanner.callback.get_remote_id();
+        scanner.scan_permitted = None;
+        if !check_scan_permission(checker, &sender, scanner_id, &mut scanner.scan_permitted) {
+            return Err(BtError::new(
+                BtErrorCategory::PermissionDenied,
+                format!("Scanning is not permitted for {}", sender),
+            ));
+        }
+
+        let match_tracker = match (settings.callback_type, settings.match_lost_timeout_ms) {
+            (_, t) if t < 0 => {
+                return Err(BtError::invalid_argument("Invalid match lost timeout"));
+            }
+            (ScanCallbackType::AllMatches, _) => None,
+            (_, 0) => Some(MatchTracker::new(DEFAULT_MATCH_LOST_TIMEOUT)),
+            (_, t) => Some(MatchTracker::new(Duration::from_millis(t as u64))),
+        };
+
+        if settings.match_sightings < 0 || settings.match_sightings_window_ms < 0 {
+            return Err(BtError::invalid_argument("Invalid match sightings"));
+        }
+        let sightings_window = match settings.match_sightings_window_ms {
+            0 => DEFAULT_MATCH_SIGHTINGS_WINDOW,
+            w => Duration::from_millis(w as u64),
+        };
+        let match_tracker = match_tracker
+            .map(|t| t.with_sightings(settings.match_sightings as usize, sightings_window));
+
+        let result_batch = match settings.report_delay_ms {
+            d if d < 0 => return Err(BtError::invalid_argument("Invalid report delay")),
+            0 => None,
+            d => Some(ScanResultBatch {
+                delay: Duration::from_millis(d as u64),
+                results: vec![],
+                flush: None,
+            }),
+        };
+
+        self.remove_offloaded_scan_filters(scanner_id);
+
+        let scanner = self.find_scanner_by_id(scanner_id).unwrap();
+        scanner.is_scanning = true;
+        scanner.rssi_smoother = RssiSmoother::new(settings.rssi_smoothing_window);
+        scanner.address_filter = address_filter;
+        scanner.filters = filters;
+        scanner.callback_type = settings.callback_type;
+        scanner.record_delivery = settings.record_delivery;
+        scanner.scan_phys = scan_phys;
+        scanner.match_tracker = match_tracker;
+        // The results held with the previous settings are delivered first.
+        scanner.flush_results();
+        scanner.result_batch = result_batch;
+        scanner.scan_parameters = scan_parameters;
+        scanner.priority = settings.priority;
+        self.offload_scan_filters(scanner_id);
+        self.metrics.increment(format!("scan.session.{:?}", settings.priority));
+
+        // A scan started while suspended waits for the resume like the other ones.
+        self.pause_scanners();
+        self.update_scan();
+        Ok(())
+    }
+
+    fn stop_scan(&mut self, scanner_id: i32) {
+        self.paused_scanners.retain(|id| i32::from(*id) != scanner_id);
+        let scanner = match self.find_scanner_by_id(scanner_id) {
+            Some(s) => s,
+            None => return,
+        };
+
+        if !scanner.is_scanning {
+            return;
+        }
+
+        scanner.is_scanning = false;
+        scanner.reported_scan_parameters = None;
+        scanner.reported_duty_cycle = None;
+        scanner.rssi_smoother = RssiSmoother::new(0);
+        scanner.filters.clear();
+        // The tracked devices are not reported lost once the scan is stopped.
+        scanner.match_tracker = None;
+        scanner.flush_results();
+        scanner.result_batch = None;
+        self.remove_offloaded_scan_filters(scanner_id);
+        self.update_scan();
+    }
+
+    fn set_scan_match_program(
+        &mut self,
+        scanner_id: i32,
+        program: ScanMatchProgram,
+    ) -> BtResult<()> {
+        if program.instructions.is_empty() {
+            self.scan_match_programs.remove(&scanner_id);
+            return Ok(());
+        }
+
+        match CompiledScanMatchProgram::compile(&program) {
+            Some(compiled) => {
+                self.scan_match_programs.insert(scanner_id, compiled);
+                Ok(())
+            }
+            None => {
+                warn!("Rejected scan match program for scanner {}", scanner_id);
+                Err(BtError::invalid_argument("scan match program rejected by the verifier"))
+            }
+        }
+    }
+
+    fn set_scan_address_lists(
+        &mut self,
+        scanner_id: i32,
+        allowed_addresses: Vec<BtAddress>,
+        denied_addresses: Vec<BtAddress>,
+    ) -> BtResult<()> {
+        let address_filter = AddressFilter::new(allowed_addresses, denied_addresses);
+
+        match self.find_scanner_by_id(scanner_id) {
+            Some(scanner) => {
+                scanner.address_filter = address_filter;
+                Ok(())
+            }
+            None => Err(BtError::not_found(format!("Scanner {} is not registered", scanner_id))),
+        }
+    }
+
+    fn set_scan_parameters(&mut self, scanner_id: i32, interval: i32, window: i32) -> BtResult<()> {
+        let scan_parameters = match ScanParameters::new(interval, window) {
+            Some(p) => p,
+            None => return Err(BtError::invalid_argument("Invalid scan interval or window")),
+        };
+
+        let scanner = match self.find_scanner_by_id(scanner_id) {
+            Some(s) => s,
+            None => {
+                return Err(BtError::not_found(format!("Scanner {} is not registered", scanner_id)))
+            }
+        };
+
+        scanner.scan_parameters = scan_parameters;
+        if scanner.is_scanning {
+            self.update_scan_parameters();
+        }
+        Ok(())
+    }
+
+    fn subscribe_manufacturer_data(
+        &mut self,
+        scanner_id: i32,
+        manufacturer_id: u16,
+        prefix: Vec<u8>,
+        mask: Vec<u8>,
+    ) -> BtResult<u32> {
+        if !mask.is_empty() && mask.len() != prefix.len() {
+            return Err(BtError::invalid_argument("Mask and prefix lengths differ"));
+        }
+
+        let id = self.next_subscription_id;
+        let scanner = match self.find_scanner_by_id(scanner_id) {
+            Some(s) => s,
+            None => {
+                return Err(BtError::not_found(format!("Scanner {} is not registered", scanner_id)))
+            }
+        };
+
+        let mask = if mask.is_empty() { vec![0xFF; prefix.len()] } else { mask };
+        scanner.manufacturer_data_subscriptions.push(ManufacturerDataSubscription {
+            id,
+            manufacturer_id,
+            prefix,
+            mask,
+        });
+        self.next_subscription_id = self.next_subscription_id.wrapping_add(1).max(1);
+        Ok(id)
+    }
+
+    fn unsubscribe_manufacturer_data(
+        &mut self,
+        scanner_id: i32,
+        subscription_id: u32,
+    ) -> BtResult<()> {
+        let scanner = match self.find_scanner_by_id(scanner_id) {
+            Some(s) => s,
+            None => {
+                return Err(BtError::not_found(format!("Scanner {} is not registered", scanner_id)))
+            }
+        };
+
+        let subscriptions = &mut scanner.manufacturer_data_subscriptions;
+        match subscriptions.iter().position(|s| s.id == subscription_id) {
+            Some(index) => {
+                subscriptions.remove(index);
+                Ok(())
+            }
+            None => Err(BtError::not_found(format!("No subscription {}", subscription_id))),
+        }
+    }
+
+    fn batch_scan_config_storage(
+        &mut self,
+        scanner_id: i32,
+        full_max: i32,
+        truncated_max: i32,
+        notify_threshold: i32,
+    ) -> BtResult<()> {
+        if self.find_scanner_by_id(scanner_id).is_none() {
+            return Err(BtError::not_found(format!("Scanner {} is not registered", scanner_id)));
+        }
+
+        let is_percent = |value: i32| (0..=100).contains(&value);
+        if !is_percent(full_max)
+            || !is_percent(truncated_max)
+            || !is_percent(notify_threshold)
+            || full_max + truncated_max > 100
+        {
+            return Err(BtError::invalid_argument("Invalid batch scan storage configuration"));
+        }
+
+        self.gatt.as_mut().unwrap().scanner.batchscan_config_storage(
+            scanner_id as u8,
+            full_max,
+            truncated_max,
+            notify_threshold,
+        );
+        Ok(())
+    }
+
+    fn batch_scan_enable(
+        &mut self,
+        scanner_id: i32,
+        mode: BatchScanMode,
+        interval: i32,
+        window: i32,
+        discard_rule: BatchScanDiscardRule,
+    ) -> BtResult<()> {
+        if self.find_scanner_by_id(scanner_id).is_none() {
+            return Err(BtError::not_found(format!("Scanner {} is not registered", scanner_id)));
+        }
+
+        if mode == BatchScanMode::Disabled {
+            return Err(BtError::invalid_argument("Batch scan mode is disabled"));
+        }
+
+        let interval = u16::try_from(interval).map_err(|_| {
+            BtError::invalid_argument(format!("Invalid batch scan interval {}", interval))
+        })?;
+        let window = u16::try_from(window).map_err(|_| {
+            BtError::invalid_argument(format!("Invalid batch scan window {}", window))
+        })?;
+
+        match self.batch_scan {
+            Some((owner, _)) if owner != scanner_id => {
+                return Err(BtError::new(
+                    BtErrorCategory::Busy,
+                    format!("Scanner {} is already batch scanning", owner),
+                ));
+            }
+            _ => (),
+        }
+
+        self.gatt.as_mut().unwrap().scanner.batchscan_enable(
+            mode as i32,
+            interval,
+            window,
+            BATCH_SCAN_OWN_ADDR_TYPE_PUBLIC,
+            discard_rule as i32,
+        );
+        self.batch_scan = Some((scanner_id, mode));
+        Ok(())
+    }
+
+    fn batch_scan_disable(&mut self, scanner_id: i32) -> BtResult<()> {
+        match self.batch_scan {
+            Some((owner, _)) if owner == scanner_id => {
+                self.gatt.as_mut().unwrap().scanner.batchscan_disable();
+                self.batch_scan = None;
+                Ok(())
+            }
+            _ => Err(BtError::not_found(format!("Scanner {} is not batch scanning", scanner_id))),
+        }
+    }
+
+    fn batch_scan_read_reports(&mut self, scanner_id: i32, mode: BatchScanMode) -> BtResult<()> {
+        if self.find_scanner_by_id(scanner_id).is_none() {
+            return Err(BtError::not_found(format!("Scanner {} is not registered", scanner_id)));
+        }
+
+        // Results are read one kind at a time.
+        if mode != BatchScanMode::Truncated && mode != BatchScanMode::Full {
+            return Err(BtError::invalid_argument("Batch scan reports are truncated or full"));
+        }
+
+        self.gatt.as_mut().unwrap().scanner.batchscan_read_reports(scanner_id as u8, mode as i32);
+        Ok(())
+    }
+
+    fn start_sync(
+        &mut self,
+        sid: i32,
+        address: BtAddress,
+        skip: i32,
+        timeout: i32,
+        callback: Box<dyn IPeriodicAdvertisingCallback + Send>,
+    ) -> BtResult<()> {
+        let addr = RawAddress::from(address);
+        let sid: u8 = sync_parameter("SID", sid, PERIODIC_SID_RANGE)?;
+        let skip = sync_parameter("Skip", skip, PERIODIC_SKIP_RANGE)?;
+        let timeout = sync_parameter("Sync timeout", timeout, PERIODIC_SYNC_TIMEOUT_RANGE)?;
+
+        if self.periodic_syncs.iter().any(|s| s.sid == sid && s.address == address) {
+            return Err(BtError::new(
+                BtErrorCategory::Busy,
+                format!("Already syncing to set {} of {}", sid, address),
+            ));
+        }
+
+        self.periodic_syncs.push(PeriodicSync { sid, address, handle: None, callback });
+        self.gatt.as_mut().unwrap().scanner.start_sync(sid, addr, skip, timeout);
+        Ok(())
+    }
+
+    fn stop_sync(&mut self, sync_handle: i32) -> BtResult<()> {
+        let handle = sync_parameter("Sync handle", sync_handle, PERIODIC_SYNC_HANDLE_RANGE)?;
+        if self.find_sync_by_handle(handle).is_none() {
+            return Err(BtError::not_found(format!("No sync with handle {}", sync_handle)));
+        }
+
+        self.periodic_syncs.retain(|s| s.handle != Some(handle));
+        self.pending_sync_transfers.retain(|(_, h)| *h != handle);
+        self.gatt.as_mut().unwrap().scanner.stop_sync(handle);
+        Ok(())
+    }
+
+    fn cancel_create_sync(&mut self, sid: i32, address: BtAddress) -> BtResult<()> {
+        let addr = RawAddress::from(address);
+        let sid: u8 = sync_parameter("SID", sid, PERIODIC_SID_RANGE)?;
+
+        let is_pending =
+            |s: &PeriodicSync| s.handle.is_none() && s.sid == sid && s.address == address;
+        if !self.periodic_syncs.iter().any(is_pending) {
+            return Err(BtError::not_found(format!(
+                "No pending sync to set {} of {}",
+                sid, address
+            )));
+        }
+
+        self.periodic_syncs.retain(|s| !is_pending(s));
+        self.gatt.as_mut().unwrap().scanner.cancel_create_sync(sid, addr);
+        Ok(())
+    }
+
+    fn transfer_sync(
+        &mut self,
+        address: BtAddress,
+        service_data: i32,
+        sync_handle: i32,
+    ) -> BtResult<()> {
+        let addr = RawAddress::from(address);
+        let service_data =
+            sync_parameter("Service data", service_data, PERIODIC_SERVICE_DATA_RANGE)?;
+
+        let handle = sync_parameter("Sync handle", sync_handle, PERIODIC_SYNC_HANDLE_RANGE)?;
+        if self.find_sync_by_handle(handle).is_none() {
+            return Err(BtError::not_found(format!("No sync with handle {}", sync_handle)));
+        }
+
+        self.pending_sync_transfers.push_back((address, handle));
+        self.gatt.as_mut().unwrap().scanner.transfer_sync(addr, service_data, handle);
+        Ok(())
+    }
+
+    fn transfer_set_info(
+        &mut self,
+        address: BtAddress,
+        service_data: i32,
+        adv_handle: i32,
+    ) -> BtResult<()> {
+        let addr = RawAddress::from(address);
+        let service_data =
+            sync_parameter("Service data", service_data, PERIODIC_SERVICE_DATA_RANGE)?;
+        let adv_handle =
+            sync_parameter("Advertising handle", adv_handle, ADVERTISING_HANDLE_RANGE)?;
+
+        self.gatt.as_mut().unwrap().scanner.transfer_set_info(addr, service_data, adv_handle);
+        Ok(())
+    }
+
+    fn sync_tx_parameters(
+        &mut self,
+        address: BtAddress,
+        mode: i32,
+        skip: i32,
+        timeout: i32,
+        callback: Box<dyn IPeriodicAdvertisingCallback + Send>,
+    ) -> BtResult<()> {
+        let addr = RawAddress::from(address);
+        let mode = sync_parameter("Mode", mode, PAST_MODE_RANGE)?;
+        let skip = sync_parameter("Skip", skip, PERIODIC_SKIP_RANGE)?;
+        let timeout = sync_parameter("Sync timeout", timeout, PERIODIC_SYNC_TIMEOUT_RANGE)?;
+
+        self.past_receivers.insert(address, callback);
+        self.gatt.as_mut().unwrap().scanner.sync_tx_parameters(addr, mode, skip, timeout);
+        Ok(())
+    }
+
+    fn start_advertising_set(
+        &mut self,
+        parameters: AdvertisingSetParameters,
+        advertise_data: AdvertiseData,
+        scan_response: AdvertiseData,
+        duration: i32,
+        max_ext_adv_events: i32,
+        mut callback: Box<dyn IAdvertisingSetCallback + Send>,
+    ) -> BtResult<i32> {
+        parameters.validate(&self.advertising_capabilities())?;
+        let (duration, max_ext_adv_events) = advertising_duration(duration, max_ext_adv_events)?;
+        let adv_data = self.encode_advertise_data(&parameters, &advertise_data, false)?;
+        let scan_rsp = self.encode_advertise_data(&parameters, &scan_response, true)?;
+        let mut parameters = parameters;
+        self.apply_advertising_policy(None, &mut parameters, adv_data.len())?;
+
+        let app_id = match callback.get_remote_id() {
+            remote_id if remote_id.is_empty() => callback.get_object_id(),
+            remote_id => remote_id,
+        };
+        let app_sets = self.advertising_sets.iter().filter(|s| s.app_id == app_id).count();
+        if app_sets >= self.max_advertising_sets_per_app {
+            return Err(BtError::new(
+                BtErrorCategory::LimitExceeded,
+                format!(
+                    "The client already holds {} advertising sets, the maximum",
+                    self.max_advertising_sets_per_app
+                ),
+            ));
+        }
+
+        let callback_id = self.watch_advertising_callback(&mut callback);
+
+        let reg_id = self.next_advertising_reg_id;
+        self.next_advertising_reg_id += 1;
+        self.advertising_sets.push(AdvertisingSet {
+            reg_id,
+            state: AdvertisingSetState::Starting,
+            app_id,
+            parameters,
+            adv_data,
+            scan_rsp,
+            enabled: true,
+            duration,
+            max_ext_adv_events,
+            since: Instant::now(),
+            started: Instant::now(),
+            callback,
+            callback_id,
+            tx_power: None,
+            tx_power_sweep: None,
+            address_rotation_interval_ms: 0,
+            persistent: false,
+            restart_persistent: false,
+            client_reg_id: reg_id,
+        });
+
+        self.start_advertising_in_controller(self.advertising_sets.len() - 1);
+        Ok(reg_id)
+    }
+
+    fn stop_advertising_set(&mut self, advertiser_id: i32) -> BtResult<()> {
+        let index = self
+            .advertising_sets
+            .iter()
+            .position(|s| s.reg_id == advertiser_id && s.state != AdvertisingSetState::Starting)
+            .ok_or_else(|| BtError::not_found(format!("No advertising set {}", advertiser_id)))?;
+
+        // A set resuming is released when the controller reports it started.
+        let set = self.advertising_sets.remove(index);
+        self.advertising_set_disarmed(advertiser_id);
+        self.metrics.record_duration("adv.set_lifetime", set.started.elapsed());
+        if let Some(handle) = set.handle() {
+            self.gatt.as_mut().unwrap().advertiser.unregister(handle);
+            self.resume_next_advertising_set();
+            self.update_le_activity();
+        }
+        set.callback.on_advertising_set_stopped(advertiser_id);
+        self.save_advertising_sets();
+        Ok(())
+    }
+
+    fn enable_advertising_set(
+        &mut self,
+        advertiser_id: i32,
+        enable: bool,
+        duration: i32,
+        max_ext_adv_events: i32,
+    ) -> BtResult<()> {
+        let (duration, max_ext_adv_events) = advertising_duration(duration, max_ext_adv_events)?;
+        let set = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set,
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+        if enable && !set.enabled {
+            let (parameters, adv_data_len) = (set.parameters.clone(), set.adv_data.len());
+            self.check_advertising_change(advertiser_id, &parameters, adv_data_len)?;
+        }
+
+        let set = self.find_advertising_set(advertiser_id).unwrap();
+        set.enabled = enable;
+        if enable {
+            set.duration = duration;
+            set.max_ext_adv_events = max_ext_adv_events;
+        }
+        match set.handle() {
+            Some(handle) => self.gatt.as_mut().unwrap().advertiser.enable(
+                handle,
+                enable,
+                duration,
+                max_ext_adv_events,
+            ),
+            // Applied when the set is resumed.
+            None => set.callback.on_advertising_enabled(
+                advertiser_id,
+                enable,
+                AdvertisingStatus::Success,
+            ),
+        }
+        self.save_advertising_sets();
+        Ok(())
+    }
+
+    fn set_advertising_data(&mut self, advertiser_id: i32, data: AdvertiseData) -> BtResult<()> {
+        let parameters = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set.parameters.clone(),
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+
+        let bytes = self.encode_advertise_data(&parameters, &data, false)?;
+        self.set_raw_advertising_data(advertiser_id, bytes)
+    }
+
+    fn set_scan_response_data(&mut self, advertiser_id: i32, data: AdvertiseData) -> BtResult<()> {
+        let parameters = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set.parameters.clone(),
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+
+        let bytes = self.encode_advertise_data(&parameters, &data, true)?;
+        let set = self.find_advertising_set(advertiser_id).unwrap();
+        set.scan_rsp = bytes.clone();
+        match set.handle() {
+            Some(handle) => {
+                self.gatt.as_mut().unwrap().advertiser.set_data(handle, true, bytes);
+            }
+            None => {
+                set.callback.on_scan_response_data_set(advertiser_id, AdvertisingStatus::Success)
+            }
+        }
+        self.save_advertising_sets();
+        Ok(())
+    }
+
+    fn set_advertising_parameters(
+        &mut self,
+        advertiser_id: i32,
+        parameters: AdvertisingSetParameters,
+    ) -> BtResult<()> {
+        let caps = self.advertising_capabilities();
+        parameters.validate(&caps)?;
+
+        let adv_data_len = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set.adv_data.len(),
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+        let mut parameters = parameters;
+        self.apply_advertising_policy(Some(advertiser_id), &mut parameters, adv_data_len)?;
+
+        let set = self.find_advertising_set(advertiser_id).unwrap();
+        parameters.validate_data_len(&caps, set.adv_data.len(), false)?;
+        parameters.validate_data_len(&caps, set.scan_rsp.len(), true)?;
+        set.parameters = parameters.clone();
+
+        match set.handle() {
+            Some(handle) => {
+                self.gatt.as_mut().unwrap().advertiser.set_parameters(handle, parameters.into());
+            }
+            None => set.callback.on_advertising_parameters_updated(
+                advertiser_id,
+                parameters.tx_power_level,
+                AdvertisingStatus::Success,
+            ),
+        }
+        self.save_advertising_sets();
+        Ok(())
+    }
+
+    fn get_advertising_tx_power(&mut self, advertiser_id: i32) -> BtResult<i32> {
+        let set = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set,
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+
+        set.tx_power.ok_or_else(|| {
+            BtError::new(
+                BtErrorCategory::NotReady,
+                format!("Advertising set {} is not started yet", advertiser_id),
+            )
+        })
+    }
+
+    fn set_advertising_tx_power_level(
+        &mut self,
+        advertiser_id: i32,
+        tx_power_level: i32,
+    ) -> BtResult<()> {
+        if tx_power_level < TX_POWER_MIN || tx_power_level > TX_POWER_MAX {
+            return Err(BtError::invalid_argument(format!(
+                "Invalid TX power level {} dBm",
+                tx_power_level
+            )));
+        }
+
+        let set = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set,
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+        if set.tx_power_sweep.is_some() {
+            return Err(BtError::new(
+                BtErrorCategory::Busy,
+                format!("A TX power sweep runs on advertising set {}", advertiser_id),
+            ));
+        }
+
+        set.parameters.tx_power_level = tx_power_level;
+        let parameters = set.parameters.clone();
+        match set.handle() {
+            Some(handle) => {
+                self.gatt.as_mut().unwrap().advertiser.set_parameters(handle, parameters.into());
+            }
+            // Applied when the set is resumed.
+            None => set.callback.on_advertising_parameters_updated(
+                advertiser_id,
+                tx_power_level,
+                AdvertisingStatus::Success,
+            ),
+        }
+        self.save_advertising_sets();
+        Ok(())
+    }
+
+    fn get_own_address(&mut self, advertiser_id: i32) -> BtResult<()> {
+        let set = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set,
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+
+        match set.handle() {
+            Some(handle) => {
+                // The read of the stack may be left pending by a set stopped before it completed.
+                self.pending_own_address_reads.remove(&handle);
+                self.gatt.as_mut().unwrap().advertiser.get_own_address(handle);
+                Ok(())
+            }
+            None => Err(BtError::new(
+                BtErrorCategory::NotReady,
+                format!("Advertising set {} is suspended", advertiser_id),
+            )),
+        }
+    }
+
+    fn set_advertising_address_rotation_interval(
+        &mut self,
+        advertiser_id: i32,
+        interval_ms: i32,
+    ) -> BtResult<()> {
+        let interval_ms = address_rotation_interval(interval_ms)?;
+        let set = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set,
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+
+        if set.parameters.own_address_type == 0 {
+            return Err(BtError::invalid_argument(format!(
+                "Advertising set {} uses the public address",
+                advertiser_id
+            )));
+        }
+
+        set.address_rotation_interval_ms = interval_ms;
+        // A suspended set gets the interval when it is resumed.
+        if let Some(handle) = set.handle() {
+            self.gatt
+                .as_mut()
+                .unwrap()
+                .advertiser
+                .set_address_rotation_interval(handle, interval_ms);
+        }
+        self.save_advertising_sets();
+        Ok(())
+    }
+
+    fn set_advertising_set_persistent(
+        &mut self,
+        advertiser_id: i32,
+        persistent: bool,
+    ) -> BtResult<()> {
+        let set = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set,
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+
+        set.persistent = persistent;
+        self.save_advertising_sets();
+        Ok(())
+    }
+
+    fn set_advertising_set_restart_persistent(
+        &mut self,
+        advertiser_id: i32,
+        restart_persistent: bool,
+    ) -> BtResult<()> {
+        let set = match self.find_advertising_set(advertiser_id) {
+            Some(set) => set,
+            None => {
+                return Err(BtError::not_found(format!("No advertising set {}", advertiser_id)))
+            }
+        };
+
+        set.restart_persistent = restart_persistent;
+        self.save_advertising_sets();
+        Ok(())
+    }
+
+    fn get_max_advertising_data_length(&self, parameters: AdvertisingSetParameters) -> i32 {
+        parameters.max_data_len(&self.advertising_capabilities(), false) as i32
+    }
+
+    fn get_advertise_data_breakdown(
+        &self,
+        data: AdvertiseData,
+        connectable: bool,
+    ) -> BtResult<AdvertiseDataBreakdown> {
+        let device_name = match &self.adapter {
+            Some(adapter) if data.include_device_name => adapter.lock().unwrap().get_name(),
+            _ => String::new(),
+        };
+
+        // The TX power level changes the value of its AD structure, not its length.
+        let bytes = data.encode(&device_name, 0)?;
+        Ok(AdvertiseDataBreakdown::new(&bytes, connectable, &self.advertising_capabilities()))
+    }
+
+    fn set_max_advertising_sets_per_app(&mut self, max: i32) -> BtResult<()> {
+        if max < 1 {
+            return Err(BtError::invalid_argument(format!(
+                "Invalid maximum number of advertising sets {}",
+                max
+            )));
+        }
+
+        self.max_advertising_sets_per_app = max as usize;
+        Ok(())
+    }
+
+    fn register_client(
+        &mut self,
+        app_uuid: crate::uuid::BtUuid,
+        callback: Box<dyn IBluetoothGattCallback + Send>,
+        eatt_support: bool,
+    ) {
+        let uuid = Uuid { uu: app_uuid.uu };
+        self.context_map.add(&uuid.uu, callback, eatt_support);
+        self.gatt.as_ref().unwrap().client.register_client(&uuid, eatt_support);
+    }
+
+    fn unregister_client(&mut self, client_id: i32) {
+        self.release_client(client_id);
+        self.context_map.remove(client_id);
+    }
+
+    fn client_connect(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        is_direct: bool,
+        transport: i32,
+        opportunistic: bool,
+        phy: i32,
+    ) -> BtResult<()> {
+        let address = RawAddress::from(addr);
+        if self.context_map.get_by_client_id(client_id).is_none() {
+            return Err(BtError::not_found(format!("Client {} is not registered", client_id)));
+        }
+
+        if let Some(phy) =
+            LePhy::from_i32(phy).filter(|p| *p == LePhy::Phy2m || *p == LePhy::PhyCoded)
+        {
+            let preference = PhyPreference { tx_phy: phy, rx_phy: phy, phy_options: 0 };
+            self.client_phy_preferences.insert((client_id, addr.to_string()), preference);
+        }
+
+        self.connect_shared(
+            &address,
+            ConnectRequest { client_id, is_direct, transport, opportunistic, phy },
+        );
+        Ok(())
+    }
+
+    fn client_disconnect(&mut self, client_id: i32, address: BtAddress) -> BtResult<()> {
+        let addr = RawAddress::from(address);
+        let address = address.to_string();
+        if self.cancel_shared_connect(client_id, &addr) {
+            return Ok(());
+        }
+
+        let conn_id = self.get_client_conn_id(client_id, &address)?;
+        let status = self.gatt.as_ref().unwrap().client.disconnect(client_id, &addr, conn_id);
+        BtError::from_status(status as i32)
+    }
+
+    fn add_device_to_background_connect(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+    ) -> BtResult<()> {
+        let address = RawAddress::from(addr);
+        let addr = addr.to_string();
+        if self.context_map.get_by_client_id(client_id).is_none() {
+            return Err(BtError::not_found(format!("Client {} is not registered", client_id)));
+        }
+        if !self.is_bonded(&addr) {
+            return Err(BtError::invalid_argument(format!("{} is not bonded", addr)));
+        }
+
+        if !self.background_connections.entry(addr.clone()).or_default().insert(client_id) {
+            return Ok(());
+        }
+
+        if self.context_map.get_conn_id_from_address(client_id, &addr).is_none() {
+            self.connect_in_background(client_id, &address);
+        }
+        Ok(())
+    }
+
+    fn remove_device_from_background_connect(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+    ) -> BtResult<()> {
+        let address = RawAddress::from(addr);
+        let addr = addr.to_string();
+
+        let removed = match self.background_connections.get_mut(&addr) {
+            Some(clients) => clients.remove(&client_id),
+            None => false,
+        };
+        if !removed {
+            return Err(BtError::not_found(format!(
+                "{} is not in the background connection list",
+                addr
+            )));
+        }
+        if self.background_connections.get(&addr).map_or(false, |c| c.is_empty()) {
+            self.background_connections.remove(&addr);
+        }
+
+        // Takes the device out of the controller accept list if it is not connected yet.
+        if self.context_map.get_conn_id_from_address(client_id, &addr).is_none() {
+            self.gatt.as_ref().unwrap().client.disconnect(client_id, &address, 0);
+        }
+        Ok(())
+    }
+
+    fn client_set_preferred_phy(
+        &mut self,
+        client_id: i32,
+        address: BtAddress,
+        tx_phy: LePhy,
+        rx_phy: LePhy,
+        phy_options: i32,
+    ) -> BtResult<()> {
+        let address = address.to_string();
+
+        // The preference of a bonded device is kept for its next connections.
+        let preference = PhyPreference { tx_phy, rx_phy, phy_options };
+        if self.is_bonded(&address) {
+            self.phy_preferences.set(&address, preference);
+        }
+        if self.context_map.get_by_client_id(client_id).is_some() {
+            self.client_phy_preferences.insert((client_id, address.clone()), preference);
+        }
+
+        match self.get_client_conn_id(client_id, &address) {
+            Ok(_) => {
+                self.apply_phy_preference(&address, preference);
+                Ok(())
+            }
+            Err(_) if self.is_bonded(&address) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn client_read_phy(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()> {
+        let address = RawAddress::from(addr);
+        let addr = addr.to_string();
+        self.get_client_conn_id(client_id, &addr)?;
+
+        let status = self.gatt.as_mut().unwrap().client.read_phy(client_id, &address);
+        BtError::from_status(status as i32)
+    }
+
+    fn get_preferred_phy(&self, addr: BtAddress) -> BtResult<PhyPreference> {
+        let addr = addr.to_string();
+        self.phy_preferences
+            .get(&addr)
+            .ok_or_else(|| BtError::not_found(format!("No PHY preference for {}", addr)))
+    }
+
+    fn set_default_phy_preference(&mut self, preference: PhyPreference) -> BtResult<()> {
+        if preference.tx_phy == LePhy::Invalid || preference.rx_phy == LePhy::Invalid {
+            return Err(BtError::invalid_argument("Invalid PHY"));
+        }
+
+        self.default_phy_preference = preference;
+        Ok(())
+    }
+
+    fn get_default_phy_preference(&self) -> PhyPreference {
+        self.default_phy_preference
+    }
+
+    fn set_link_tuning_profile(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        profile: LinkTuningProfile,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        if self.context_map.get_by_client_id(client_id).is_none() {
+            return Err(BtError::not_found(format!("Client {} is not registered", client_id)));
+        }
+
+        self.link_profile_overrides.set(client_id, &addr, profile);
+        self.retune_link(&addr);
+        Ok(())
+    }
+
+    fn clear_link_tuning_profile(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()> {
+        let addr = addr.to_string();
+        if !self.link_profile_overrides.clear(client_id, &addr) {
+            return Err(BtError::not_found(format!(
+                "No link tuning profile override for {}",
+                addr
+            )));
+        }
+        self.retune_link(&addr);
+        Ok(())
+    }
+
+    fn get_connection_info(&self, client_id: i32, addr: BtAddress) -> BtResult<GattConnectionInfo> {
+        let addr = addr.to_string();
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+        Ok(GattConnectionInfo {
+            address: addr.clone(),
+            conn_id,
+            mtu: self.mtus.get(&conn_id).cloned().unwrap_or(ATT_DEFAULT_MTU) as i32,
+            link_tuning_profile: self.link_profiles.get(&addr).cloned().unwrap_or_default(),
+            link_tuning_overridden: self.link_profile_overrides.get(&addr).is_some(),
+        })
+    }
+
+    fn refresh_device(&self, client_id: i32, addr: BtAddress) -> BtResult<()> {
+        let address = RawAddress::from(addr);
+        if self.context_map.get_by_client_id(client_id).is_none() {
+            return Err(BtError::not_found(format!("Client {} is not registered", client_id)));
+        }
+
+        let status = self.gatt.as_ref().unwrap().client.refresh(client_id, &address);
+        BtError::from_status(status as i32)
+    }
+
+    fn discover_services(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+
+        self.track_operation(conn_id, GattOperation::Discovery, 0);
+        let status = self.gatt.as_ref().unwrap().client.search_service(conn_id, None);
+        self.untrack_failed_operation(conn_id, GattOperation::Discovery, 0, status)
+    }
+
+    fn get_gatt_db(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+    ) -> BtResult<Vec<BluetoothGattService>> {
+        let addr = addr.to_string();
+        let conn_id = match self.context_map.get_conn_id_from_address(client_id, &addr) {
+            Some(id) => id,
+            None => return Err(BtError::not_found(format!("Client is not connected to {}", addr))),
+        };
+
+        self.request_gatt_db(conn_id, GattDbRequest::Client);
+        Ok(self.gatt_dbs.get(&conn_id).cloned().unwrap_or_default())
+    }
+
+    fn discover_service_by_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        uuid: crate::uuid::BtUuid,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+
+        let filter = Uuid { uu: uuid.uu };
+        self.track_operation(conn_id, GattOperation::Discovery, 0);
+        let status = self.gatt.as_ref().unwrap().client.search_service(conn_id, Some(filter));
+        self.untrack_failed_operation(conn_id, GattOperation::Discovery, 0, status)
+    }
+
+    fn read_characteristic(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        auth_req: i32,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+
+        // TODO(b/200065274): Perform check on restricted handles.
+
+        self.trace_att(&addr, |trace, now| {
+            trace.record_request(now, AttPduDirection::Sent, handle, ATT_READ_REQ, handle, 0)
+        });
+
+        self.att_retries.track(
+            client_id,
+            conn_id,
+            GattOperation::ReadCharacteristic,
+            handle,
+            auth_req,
+        );
+        let request = AttRequest::Read { auth_req };
+        self.send_operation(conn_id, GattOperation::ReadCharacteristic, handle, request)
+    }
+
+    fn read_using_characteristic_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        uuid: crate::uuid::BtUuid,
+        start_handle: i32,
+        end_handle: i32,
+        auth_req: i32,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+
+        // TODO(b/200065274): Perform check on restricted handles.
+
+        // The values are delivered as if read one by one.
+        self.trace_att(&addr, |trace, now| {
+            trace.record(now, AttPduDirection::Sent, ATT_READ_BY_TYPE_REQ, start_handle, 0, 0)
+        });
+
+        let status = self.gatt.as_ref().unwrap().client.read_using_characteristic_uuid(
+            conn_id,
+            &Uuid { uu: uuid.uu },
+            start_handle as u16,
+            end_handle as u16,
+            auth_req,
+        );
+        BtError::from_status(status as i32)
+    }
+
+    fn read_service(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        service_uuid: crate::uuid::BtUuid,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = match self.context_map.get_conn_id_from_address(client_id, &addr) {
+            Some(id) => id,
+            None => return Err(BtError::not_found(format!("Client is not connected to {}", addr))),
+        };
+
+        let uuid = service_uuid.uu;
+
+        if self.service_reads.contains_key(&conn_id) {
+            return Err(BtError::new(BtErrorCategory::Busy, "A service read is in progress"));
+        }
+        if self.conformance_checks.contains_key(&conn_id) {
+            return Err(BtError::new(BtErrorCategory::Busy, "A conformance check is in progress"));
+        }
+
+        let service = match self.gatt_dbs.get(&conn_id) {
+            Some(db) => match db.iter().find(|s| s.uuid == uuid) {
+                Some(s) => s,
+                None => return Err(BtError::not_found("Service not found")),
+            },
+            None => return Err(BtError::new(BtErrorCategory::NotReady, "Services not discovered")),
+        };
+
+        let pending = service
+            .characteristics
+            .iter()
+            .filter(|c| c.properties & BluetoothGattCharacteristic::PROPERTY_READ != 0)
+            .map(|c| (c.uuid, c.instance_id))
+            .collect();
+
+        self.service_reads
+            .insert(conn_id, ServiceRead { service_uuid: uuid, pending, results: vec![] });
+        self.continue_service_read(conn_id);
+        Ok(())
+    }
+
+    fn check_conformance(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = match self.context_map.get_conn_id_from_address(client_id, &addr) {
+            Some(id) => id,
+            None => return Err(BtError::not_found(format!("Client is not connected to {}", addr))),
+        };
+
+        if self.service_reads.contains_key(&conn_id) {
+            return Err(BtError::new(BtErrorCategory::Busy, "A service read is in progress"));
+        }
+        if self.conformance_checks.contains_key(&conn_id) {
+            return Err(BtError::new(BtErrorCategory::Busy, "A conformance check is in progress"));
+        }
+
+        let mut check = ConformanceCheck::new(self.is_bonded(&addr));
+        match self.gatt_dbs.get(&conn_id) {
+            Some(db) => check.start(db),
+            // The check starts once the database is discovered.
+            None => self.gatt.as_ref().unwrap().client.search_service(conn_id, None),
+        }
+
+        self.conformance_checks.insert(conn_id, check);
+        self.continue_conformance_check(conn_id);
+        Ok(())
+    }
+
+    fn write_characteristic(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        mut write_type: GattWriteType,
+        auth_req: i32,
+        value: Vec<u8>,
+    ) -> GattWriteRequestStatus {
+        let addr = addr.to_string();
+        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
+        if conn_id.is_none() {
+            return self.journal_write(client_id, &addr, handle, write_type, auth_req, value);
+        }
+
+        if self.long_writes.contains_key(&conn_id.unwrap()) {
+            return GattWriteRequestStatus::Busy;
+        }
+
+        if self.reliable_queue.contains(&addr) {
+            write_type = GattWriteType::WritePrepare;
+        } else {
+            let mtu = self.mtus.get(&conn_id.unwrap()).cloned().unwrap_or(ATT_DEFAULT_MTU);
+            if value.len() > mtu.saturating_sub(ATT_WRITE_HEADER_SIZE) {
+                if value.len() > ATT_MAX_VALUE_LEN {
+                    return GattWriteRequestStatus::Fail;
+                }
+
+                self.long_writes
+                    .insert(conn_id.unwrap(), LongWrite::new(handle, auth_req, value, mtu));
+                self.continue_long_write(conn_id.unwrap());
+                return GattWriteRequestStatus::Success;
+            }
+        }
+
+        // TODO(b/200065274): Perform check on restricted handles.
+
+        let opcode = match write_type {
+            GattWriteType::WriteNoRsp => ATT_WRITE_CMD,
+            GattWriteType::WritePrepare => ATT_PREPARE_WRITE_REQ,
+            _ => ATT_WRITE_REQ,
+        };
+        self.trace_att(&addr, |trace, now| {
+            trace.record_request(now, AttPduDirection::Sent, handle, opcode, handle, value.len())
+        });
+        self.log_value(&addr, "Write", handle, &value);
+
+        let request = AttRequest::Write { write_type, auth_req, value };
+        match self.send_operation(
+            conn_id.unwrap(),
+            GattOperation::WriteCharacteristic,
+            handle,
+            request,
+        ) {
+            Ok(()) => GattWriteRequestStatus::Success,
+            Err(_) => GattWriteRequestStatus::Fail,
+        }
+    }
+
+    fn read_descriptor(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        auth_req: i32,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+
+        // TODO(b/200065274): Perform check on restricted handles.
+
+        self.trace_att(&addr, |trace, now| {
+            trace.record_request(now, AttPduDirection::Sent, handle, ATT_READ_REQ, handle, 0)
+        });
+
+        self.att_retries.track(client_id, conn_id, GattOperation::ReadDescriptor, handle, auth_req);
+        let request = AttRequest::Read { auth_req };
+        self.send_operation(conn_id, GattOperation::ReadDescriptor, handle, request)
+    }
+
+    fn write_using_characteristic_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        uuid: crate::uuid::BtUuid,
+        start_handle: i32,
+        end_handle: i32,
+        write_type: GattWriteType,
+        auth_req: i32,
+        value: Vec<u8>,
+    ) -> GattWriteRequestStatus {
+        let address = addr.to_string();
+        let conn_id = match self.context_map.get_conn_id_from_address(client_id, &address) {
+            Some(id) => id,
+            None => return GattWriteRequestStatus::Fail,
+        };
+
+        let handle = match self.find_characteristic(conn_id, &uuid.uu, start_handle, end_handle) {
+            Ok(characteristic) => characteristic.instance_id,
+            Err(e) => {
+                warn!("[{}]: Cannot write characteristic {}: {}", addr, uuid, e);
+                return GattWriteRequestStatus::Fail;
+            }
+        };
+        self.write_characteristic(client_id, addr, handle, write_type, auth_req, value)
+    }
+
+    fn read_descriptor_by_uuid(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        characteristic_uuid: crate::uuid::BtUuid,
+        descriptor_uuid: crate::uuid::BtUuid,
+        auth_req: i32,
+    ) -> BtResult<()> {
+        let conn_id = self.get_client_conn_id(client_id, &addr.to_string())?;
+
+        let handle = self
+            .find_characteristic(conn_id, &characteristic_uuid.uu, 0, i32::MAX)?
+            .descriptors
+            .iter()
+            .find(|d| d.uuid == descriptor_uuid.uu)
+            .map(|d| d.instance_id)
+            .ok_or_else(|| BtError::not_found("Descriptor not found"))?;
+        self.read_descriptor(client_id, addr, handle, auth_req)
+    }
+
+    fn write_descriptor(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        auth_req: i32,
+        value: Vec<u8>,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+
+        // TODO(b/200065274): Perform check on restricted handles.
+
+        let value = match self.merge_cccd_write(conn_id, client_id, handle, value) {
+            Some(value) => value,
+            None => {
+                if let Some(client) = self.context_map.get_by_client_id(client_id) {
+                    client.callback.on_descriptor_write(
+                        callback_address(&addr),
+                        GattStatus::Success.to_i32().unwrap(),
+                        handle,
+                    );
+                }
+                return Ok(());
+            }
+        };
+
+        self.trace_att(&addr, |trace, now| {
+            trace.record_request(
+                now,
+                AttPduDirection::Sent,
+                handle,
+                ATT_WRITE_REQ,
+                handle,
+                value.len(),
+            )
+        });
+        self.log_value(&addr, "Descriptor write", handle, &value);
+
+        let request = AttRequest::Write { write_type: GattWriteType::Write, auth_req, value };
+        let result = self.send_operation(conn_id, GattOperation::WriteDescriptor, handle, request);
+        if result.is_err() {
+            self.forget_shared_cccd_value(conn_id, handle);
+        }
+        result
+    }
+
+    fn cancel_operation(&mut self, client_id: i32, addr: BtAddress, token: i32) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = match self.context_map.get_conn_id_from_address(client_id, &addr) {
+            Some(id) => id,
+            None => return Err(BtError::not_found(format!("Client is not connected to {}", addr))),
+        };
+
+        let cancelled = match self.pending_operations.get_mut(&conn_id) {
+            Some(operations) => operations.cancel(token),
+            None => vec![],
+        };
+
+        // The prepared parts are discarded once the part in flight is acknowledged, unless the
+        // write is already being executed.
+        let mut long_write_cancelled = false;
+        if let Some(write) = self.long_writes.get_mut(&conn_id) {
+            if (token == OPERATION_TOKEN_ALL || token == write.handle)
+                && write.failure.is_none()
+                && write.next_chunk().is_some()
+            {
+                write.failure = Some(GattStatus::Cancel.to_i32().unwrap());
+                long_write_cancelled = true;
+            }
+        }
+
+        if cancelled.is_empty() && !long_write_cancelled {
+            return Err(BtError::not_found(format!("No pending operation for token {}", token)));
+        }
+
+        self.fail_operations(conn_id, &addr, cancelled, GattStatus::Cancel.to_i32().unwrap());
+        self.deliver_held_results(conn_id);
+        Ok(())
+    }
+
+    fn enable_write_journal(
+        &mut self,
+        client_id: i32,
+        policy: JournalConflictPolicy,
+    ) -> BtResult<()> {
+        if self.context_map.get_by_client_id(client_id).is_none() {
+            return Err(BtError::not_found(format!("Client {} is not registered", client_id)));
+        }
+
+        self.write_journals
+            .entry(client_id)
+            .and_modify(|journal| journal.set_policy(policy))
+            .or_insert_with(|| WriteJournal::new(policy));
+        Ok(())
+    }
+
+    fn disable_write_journal(&mut self, client_id: i32) -> BtResult<()> {
+        let conn_ids: Vec<i32> = self
+            .context_map
+            .connections
+            .iter()
+            .filter(|conn| conn.client_id == client_id)
+            .map(|conn| conn.conn_id)
+            .collect();
+        for conn_id in conn_ids {
+            self.journal_flushes.remove(&conn_id);
+        }
+
+        match self.write_journals.remove(&client_id) {
+            Some(_) => Ok(()),
+            None => Err(BtError::not_found(format!("No write journal for client {}", client_id))),
+        }
+    }
+
+    fn get_write_journal(&self, client_id: i32, addr: BtAddress) -> Vec<JournalEntry> {
+        let addr = addr.to_string();
+        match self.write_journals.get(&client_id) {
+            Some(journal) => journal.entries(&addr),
+            None => vec![],
+        }
+    }
+
+    fn cancel_journaled_write(&mut self, client_id: i32, entry_id: i32) -> BtResult<()> {
+        let journal = match self.write_journals.get_mut(&client_id) {
+            Some(journal) => journal,
+            None => {
+                return Err(BtError::not_found(format!(
+                    "No write journal for client {}",
+                    client_id
+                )))
+            }
+        };
+        if journal.cancel(entry_id) {
+            return Ok(());
+        }
+
+        // The write may be waiting for the previous ones of the connection being flushed.
+        for conn in self.context_map.connections.iter().filter(|c| c.client_id == client_id) {
+            if let Some(flush) = self.journal_flushes.get_mut(&conn.conn_id) {
+                if flush.cancel(entry_id) {
+                    return Ok(());
+                }
+            }
+        }
+        Err(BtError::not_found(format!("No journaled write {}", entry_id)))
+    }
+
+    fn clear_write_journal(&mut self, client_id: i32, addr: BtAddress) -> u32 {
+        let addr = addr.to_string();
+        match self.write_journals.get_mut(&client_id) {
+            Some(journal) => journal.take(&addr).len() as u32,
+            None => 0,
+        }
+    }
+
+    fn set_att_retry_policy(&mut self, client_id: i32, policy: AttRetryPolicy) -> BtResult<()> {
+        if self.context_map.get_by_client_id(client_id).is_none() {
+            return Err(BtError::not_found(format!("Client {} is not registered", client_id)));
+        }
+        if policy.max_attempts > MAX_ATT_ATTEMPTS {
+            return Err(BtError::invalid_argument(format!(
+                "At most {} attempts are allowed",
+                MAX_ATT_ATTEMPTS
+            )));
+        }
+
+        self.att_retries.set_policy(client_id, policy);
+        Ok(())
+    }
+
+    fn set_notification_queue(
+        &mut self,
+        client_id: i32,
+        config: NotificationQueueConfig,
+    ) -> BtResult<()> {
+        if self.context_map.get_by_client_id(client_id).is_none() {
+            return Err(BtError::not_found(format!("Client {} is not registered", client_id)));
+        }
+        if config.depth > MAX_NOTIFICATION_QUEUE_DEPTH {
+            return Err(BtError::invalid_argument(format!(
+                "The queue holds at most {} values",
+                MAX_NOTIFICATION_QUEUE_DEPTH
+            )));
+        }
+
+        // The values queued under the previous settings are delivered first.
+        self.deliver_notifications(client_id);
+        if config.depth > 0 {
+            self.notification_queues.insert(client_id, NotificationQueue::new(config));
+        } else {
+            self.notification_queues.remove(&client_id);
+        }
+        Ok(())
+    }
+
+    fn register_for_notification(
+        &self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        enable: bool,
+    ) -> BtResult<()> {
+        self.get_client_conn_id(client_id, &addr.to_string())?;
+        let address = RawAddress::from(addr);
+
+        // TODO(b/200065274): Perform check on restricted handles.
+
+        let client = &self.gatt.as_ref().unwrap().client;
+        let status = if enable {
+            client.register_for_notification(client_id, &address, handle as u16)
+        } else {
+            client.deregister_for_notification(client_id, &address, handle as u16)
+        };
+        BtError::from_status(status as i32)
+    }
+
+    fn set_notification_pipe(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        pipe: File,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = match self.context_map.get_conn_id_from_address(client_id, &addr) {
+            Some(id) => id,
+            None => return Err(BtError::not_found(format!("{} is not connected", addr))),
+        };
+
+        self.notification_pipes.insert((conn_id, handle), NotificationPipe::new(pipe));
+        Ok(())
+    }
+
+    fn clear_notification_pipe(&mut self, client_id: i32, addr: BtAddress, handle: i32) {
+        let addr = addr.to_string();
+        if let Some(conn_id) = self.context_map.get_conn_id_from_address(client_id, &addr) {
+            self.notification_pipes.remove(&(conn_id, handle));
+        }
+    }
+
+    fn begin_reliable_write(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()> {
+        let addr = addr.to_string();
+        self.get_client_conn_id(client_id, &addr)?;
+        self.reliable_queue.insert(addr);
+        Ok(())
+    }
+
+    fn end_reliable_write(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        execute: bool,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        self.reliable_queue.remove(&addr);
+
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+
+        self.trace_att(&addr, |trace, now| {
+            trace.record_request(now, AttPduDirection::Sent, 0, ATT_EXECUTE_WRITE_REQ, 0, 0)
+        });
+
+        let status =
+            self.gatt.as_ref().unwrap().client.execute_write(conn_id, if execute { 1 } else { 0 });
+        BtError::from_status(status as i32)
+    }
+
+    fn read_remote_rssi(&self, client_id: i32, addr: BtAddress) -> BtResult<()> {
+        self.get_client_conn_id(client_id, &addr.to_string())?;
+
+        let address = RawAddress::from(addr);
+        let status = self.gatt.as_ref().unwrap().client.read_remote_rssi(client_id, &address);
+        BtError::from_status(status as i32)
+    }
+
+    fn start_rssi_monitor(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        low: i32,
+        high: i32,
+        sampling_period_ms: i32,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+        let monitor = RssiMonitor::new(low, high, sampling_period_ms)?;
+
+        self.rssi_monitors.insert(conn_id, monitor);
+        self.poll_rssi(conn_id);
+        Ok(())
+    }
+
+    fn stop_rssi_monitor(&mut self, client_id: i32, addr: BtAddress) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+
+        match self.rssi_monitors.remove(&conn_id) {
+            Some(_) => Ok(()),
+            None => Err(BtError::not_found(format!("The RSSI of {} is not monitored", addr))),
+        }
+    }
+
+    fn configure_mtu(&mut self, client_id: i32, addr: BtAddress, mtu: i32) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = self.get_client_conn_id(client_id, &addr)?;
+
+        self.trace_att(&addr, |trace, now| {
+            trace.record_request(now, AttPduDirection::Sent, 0, ATT_EXCHANGE_MTU_REQ, 0, 0)
+        });
+
+        let status = self.gatt.as_ref().unwrap().client.configure_mtu(conn_id, mtu);
+        BtError::from_status(status as i32)
+    }
+
+    fn connection_parameter_update(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        min_interval: i32,
+        max_interval: i32,
+        latency: i32,
+        timeout: i32,
+        min_ce_len: u16,
+        max_ce_len: u16,
+    ) -> BtResult<()> {
+        let address = addr.to_string();
+        self.get_client_conn_id(client_id, &address)?;
+
+        let parameters = ConnectionParameters {
+            interval: (min_interval, max_interval),
+            latency,
+            timeout,
+            ce_len: (min_ce_len, max_ce_len),
+        };
+        self.update_connection_parameters(&address, |requests| {
+            requests.request(client_id, parameters)
+        })
+    }
+
+    fn set_connection_priority(
+        &mut self,
+        client_id: i32,
+        addr: BtAddress,
+        priority: ConnectionPriority,
+    ) -> BtResult<()> {
+        let address = addr.to_string();
+        self.get_client_conn_id(client_id, &address)?;
+
+        let parameters = connection_priority::parameters(priority);
+        self.update_connection_parameters(&address, |requests| {
+            requests.request(client_id, parameters)
+        })
+    }
+
+    fn register_server(
+        &mut self,
+        app_uuid: crate::uuid::BtUuid,
+        callback: Box<dyn IBluetoothGattServerCallback + Send>,
+        eatt_support: bool,
+    ) {
+        let uuid = Uuid { uu: app_uuid.uu };
+        self.server_context_map.add(&uuid.uu, callback, eatt_support);
+        self.gatt.as_ref().unwrap().server.register_server(&uuid, eatt_support);
+    }
+
+    fn unregister_server(&mut self, server_id: i32) {
+        self.release_server(server_id);
+        self.server_context_map.remove(server_id);
+    }
+
+    fn server_connect(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        is_direct: bool,
+        transport: i32,
+    ) -> BtResult<()> {
+        let address = RawAddress::from(addr);
+        self.outgoing_connections.insert(addr.to_string());
+        let status =
+            self.gatt.as_ref().unwrap().server.connect(server_id, &address, is_direct, transport);
+        BtError::from_status(status as i32)
+    }
+
+    fn server_disconnect(&self, server_id: i32, address: BtAddress) -> BtResult<()> {
+        let addr = address.to_string();
+        let conn_id = match self.server_context_map.get_conn_id_from_address(server_id, &addr) {
+            Some(id) => id,
+            None => return Err(BtError::not_found(format!("{} is not connected", addr))),
+        };
+
+        let status = self.gatt.as_ref().unwrap().server.disconnect(
+            server_id,
+            &RawAddress::from(address),
+            conn_id,
+        );
+        BtError::from_status(status as i32)
+    }
+
+    fn add_service(&self, server_id: i32, service: BluetoothGattService) -> BtResult<()> {
+        if self.server_context_map.get_by_server_id(server_id).is_none() {
+            return Err(BtError::not_found(format!("no server {}", server_id)));
+        }
+
+        let errors = validate_service(&service);
+        if !errors.is_empty() {
+            return Err(invalid_service_error(&errors));
+        }
+
+        let elements = service_to_db_elements(&service);
+        let status = self.gatt.as_ref().unwrap().server.add_service(server_id, &elements);
+        BtError::from_status(status as i32)
+    }
+
+    fn validate_service(&self, service: BluetoothGattService) -> Vec<ServiceValidationError> {
+        validate_service(&service)
+    }
+
+    fn remove_service(&self, server_id: i32, handle: i32) -> BtResult<()> {
+        if self.server_context_map.get_by_server_id(server_id).is_none() {
+            return Err(BtError::not_found(format!("no server {}", server_id)));
+        }
+
+        let status = self.gatt.as_ref().unwrap().server.delete_service(server_id, handle);
+        BtError::from_status(status as i32)
+    }
+
+    fn set_user_description(
+        &mut self,
+        server_id: i32,
+        handle: i32,
+        description: String,
+    ) -> BtResult<()> {
+        if description.len() > MAX_USER_DESCRIPTION_LEN {
+            return Err(BtError::invalid_argument("User description is too long"));
+        }
+
+        let descriptor = self
+            .managed_descriptors
+            .iter_mut()
+            .find(|((id, _), d)| {
+                *id == server_id
+                    && d.kind == ManagedDescriptorKind::UserDescription
+                    && d.characteristic_handle == handle
+            })
+            .map(|(_, d)| d)
+            .ok_or_else(|| {
+                BtError::not_found(format!("No user description for handle {}", handle))
+            })?;
+
+        descriptor.value = description.into_bytes();
+        self.server_descriptors.remove(&descriptor.key);
+        Ok(())
+    }
+
+    fn send_response(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        request_id: i32,
+        status: GattStatus,
+        offset: i32,
+        value: Vec<u8>,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = match self.server_context_map.get_conn_id_from_address(server_id, &addr) {
+            Some(id) => id,
+            None => return Err(BtError::not_found(format!("{} is not connected", addr))),
+        };
+
+        let pending_requests = self
+            .server_context_map
+            .get_by_server_id_mut(server_id)
+            .map(|server| &mut server.pending_requests);
+        let handle = match pending_requests {
+            Some(requests) if requests.get(&request_id).map(|r| r.0) == Some(conn_id) => {
+                requests.remove(&request_id).unwrap().1
+            }
+            _ => return Err(BtError::not_found(format!("no pending request {}", request_id))),
+        };
+
+        let mut attr_value = BtGattValue::default();
+        let len = std::cmp::min(value.len(), attr_value.value.len());
+        attr_value.value[..len].copy_from_slice(&value[..len]);
+        attr_value.handle = handle as u16;
+        attr_value.offset = offset as u16;
+        attr_value.len = len as u16;
+
+        self.trace_att(&addr, |trace, now| {
+            trace.record_response(
+                now,
+                AttPduDirection::Sent,
+                request_id,
+                handle,
+                len,
+                status.to_i32().unwrap(),
+            )
+        });
+
+        let status = self.gatt.as_ref().unwrap().server.send_response(
+            conn_id,
+            request_id,
+            status.to_i32().unwrap(),
+            &BtGattResponse { attr_value },
+        );
+        BtError::from_status(status as i32)
+    }
+
+    fn send_notification(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        handle: i32,
+        confirm: bool,
+        value: Vec<u8>,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        let conn_id = match self.server_context_map.get_conn_id_from_address(server_id, &addr) {
+            Some(id) => id,
+            None => return Err(BtError::not_found(format!("{} is not connected", addr))),
+        };
+
+        let policy = self
+            .server_context_map
+            .get_by_server_id(server_id)
+            .and_then(|server| server.drop_policies.get(&handle).cloned())
+            .unwrap_or_default();
+
+        // Keep the order of the notifications by queueing behind the ones already waiting.
+        let conn = self.server_context_map.get_connection_mut(conn_id).unwrap();
+        if conn.is_congested || !conn.notification_queue.is_empty() {
+            if !conn.notification_queue.push(PendingNotification { handle, confirm, value }, policy)
+            {
+                return Err(BtError::new(
+                    BtErrorCategory::Busy,
+                    format!("Notification queue of {} is full", addr),
+                ));
+            }
+            return Ok(());
+        }
+
+        let opcode = if confirm { ATT_HANDLE_VALUE_IND } else { ATT_HANDLE_VALUE_NTF };
+        self.trace_att(&addr, |trace, now| {
+            trace.record_request(now, AttPduDirection::Sent, handle, opcode, handle, value.len())
+        });
+
+        let status = self.gatt.as_ref().unwrap().server.send_indication(
+            server_id,
+            handle,
+            conn_id,
+            confirm as i32,
+            &value,
+        );
+        BtError::from_status(status as i32)
+    }
+
+    fn send_multiple_notifications(
+        &mut self,
+        server_id: i32,
+        addr: BtAddress,
+        values: Vec<GattHandleValue>,
+    ) -> BtResult<()> {
+        let addr = addr.to_string();
+        if values.len() < 2 {
+            return Err(BtError::invalid_argument(
+                "A multiple handle value notification carries at least two values",
+            ));
+        }
+        if let Some(v) = values.iter().find(|v| v.handle <= 0 || v.handle > u16::MAX as i32) {
+            return Err(BtError::invalid_argument(format!("Invalid handle {}", v.handle)));
+        }
+        if let Some(v) = values.iter().find(|v| v.value.len() > ATT_MAX_VALUE_LEN) {
+            return Err(BtError::invalid_argument(format!(
+                "Value of handle {} is longer than {} bytes",
+                v.handle, ATT_MAX_VALUE_LEN
+            )));
+        }
+
+        let conn_id = match self.server_context_map.get_conn_id_from_address(server_id, &addr) {
+            Some(id) => id,
+            None => return Err(BtError::not_found(format!("{} is not connected", addr))),
+        };
+
+        let conn = self.server_context_map.get_connection_mut(conn_id).unwrap();
+        if conn.is_congested || !conn.notification_queue.is_empty() {
+            return Err(BtError::new(
+                BtErrorCategory::Busy,
+                format!("Link to {} is congested", addr),
+            ));
+        }
+
+        self.trace_att(&addr, |trace, now| {
+            for v in values.iter() {
+                trace.record(
+                    now,
+                    AttPduDirection::Sent,
+                    ATT_MULTIPLE_HANDLE_VALUE_NTF,
+                    v.handle,
+                    v.value.len(),
+                    0,
+                );
+            }
+        });
+
+        let values: Vec<(u16, Vec<u8>)> =
+            values.into_iter().map(|v| (v.handle as u16, v.value)).collect();
+        let status = self
+            .gatt
+            .as_ref()
+            .unwrap()
+            .server
+            .send_multiple_notifications(server_id, conn_id, &values);
+        BtError::from_status(status as i32)
+    }
+
+    fn set_notification_drop_policy(
+        &mut self,
+        server_id: i32,
+        handle: i32,
+        policy: NotificationDropPolicy,
+    ) -> BtResult<()> {
+        let server = match self.server_context_map.get_by_server_id_mut(server_id) {
+            Some(server) => server,
+            None => return Err(BtError::not_found(format!("no server {}", server_id))),
+        };
+
+        match policy {
+            NotificationDropPolicy::Queue => server.drop_policies.remove(&handle),
+            _ => server.drop_policies.insert(handle, policy),
+        };
+        Ok(())
+    }
+
+    fn get_notification_queue_depth(&self, server_id: i32, addr: BtAddress) -> BtResult<u32> {
+        let addr = addr.to_string();
+        self.server_context_map
+            .connections
+            .iter()
+            .find(|conn| conn.server_id == server_id && conn.address == addr)
+            .map(|conn| conn.notification_queue.len() as u32)
+            .ok_or_else(|| BtError::not_found(format!("{} is not connected", addr)))
+    }
+
+    fn set_peripheral_connection_policy(&mut self, policy: PeripheralConnectionPolicy) {
+        debug!("Peripheral connection policy set to {:?}", policy);
+        self.peripheral_policy = policy;
+        self.reapply_peripheral_policy();
+    }
+
+    fn get_peripheral_connection_policy(&self) -> PeripheralConnectionPolicy {
+        self.peripheral_policy
+    }
+
+    fn set_peripheral_allow_list(&mut self, addresses: Vec<BtAddress>) {
+        self.peripheral_allow_list = addresses.into_iter().collect();
+        if self.peripheral_policy == PeripheralConnectionPolicy::AllowList {
+            self.reapply_peripheral_policy();
+        }
+    }
+
+    fn register_peripheral_connection_agent(
+        &mut self,
+        agent: Box<dyn IPeripheralConnectionAgent + Send>,
+    ) {
+        self.peripheral_agent = Some(agent);
+    }
+
+    fn respond_peripheral_connection(&mut self, addr: BtAddress, accept: bool) -> BtResult<()> {
+        let addr = addr.to_string();
+        if self.peripheral_decisions.get(&addr) != Some(&PeripheralDecision::Pending) {
+            return Err(BtError::not_found(format!("No pending connection from {}", addr)));
+        }
+
+        let decision =
+            if accept { PeripheralDecision::Accepted } else { PeripheralDecision::Revoked };
+        self.set_peripheral_decision(&addr, decision);
+        if !accept {
+            self.disconnect_central(&addr);
+        }
+        Ok(())
+    }
+
+    fn start_att_trace(&mut self, addr: BtAddress) -> BtResult<()> {
+        let addr = addr.to_string();
+        debug!("Starting ATT trace of {}", addr);
+        *self.att_trace = Some(AttTrace::new(addr));
+        Ok(())
+    }
+
+    fn stop_att_trace(&mut self) {
+        if let Some(trace) = self.att_trace.as_mut() {
+            trace.active = false;
+        }
+    }
+
+    fn get_att_trace(&self, addr: BtAddress) -> BtResult<Vec<AttPduRecord>> {
+        let addr = addr.to_string();
+        match self.att_trace.as_ref() {
+            Some(trace) if trace.address.eq_ignore_ascii_case(&addr) => Ok(trace.records()),
+            _ => Err(BtError::not_found(format!("No ATT trace of {}", addr))),
+        }
+    }
+}
+
+#[btif_callbacks_dispatcher(BluetoothGatt, dispatch_gatt_client_callbacks, GattClientCallbacks)]
+pub(crate) trait BtifGattClientCallbacks {
+    #[btif_callback(RegisterClient)]
+    fn register_client_cb(&mut self, status: i32, client_id: i32, app_uuid: Uuid);
+
+    #[btif_callback(Connect)]
+    fn connect_cb(&mut self, conn_id: i32, status: i32, client_id: i32, addr: RawAddress);
+
+    #[btif_callback(Disconnect)]
+    fn disconnect_cb(&mut self, conn_id: i32, status: i32, client_id: i32, addr: RawAddress);
+
+    #[btif_callback(SearchComplete)]
+    fn search_complete_cb(&mut self, conn_id: i32, status: i32);
+
+    #[btif_callback(RegisterForNotification)]
+    fn register_for_notification_cb(
+        &mut self,
+        conn_id: i32,
+        registered: i32,
+        status: i32,
+        handle: u16,
+    );
+
+    #[btif_callback(Notify)]
+    fn notify_cb(&mut self, conn_id: i32, data: BtGattNotifyParams);
+
+    #[btif_callback(ReadCharacteristic)]
+    fn read_characteristic_cb(&mut self, conn_id: i32, status: i32, data: BtGattReadParams);
+
+    #[btif_callback(WriteCharacteristic)]
+    fn write_characteristic_cb(
+        &mut self,
+        conn_id: i32,
+        status: i32,
+        handle: u16,
+        len: u16,
+        value: *const u8,
+    );
+
+    #[btif_callback(ReadDescriptor)]
+    fn read_descriptor_cb(&mut self, conn_id: i32, status: i32, data: BtGattReadParams);
+
+    #[btif_callback(WriteDescriptor)]
+    fn write_descriptor_cb(
+        &mut self,
+        conn_id: i32,
+        status: i32,
+        handle: u16,
+        len: u16,
+        value: *const u8,
+    );
+
+    #[btif_callback(ExecuteWrite)]
+    fn execute_write_cb(&mut self, conn_id: i32, status: i32);
+
+    #[btif_callback(ReadRemoteRssi)]
+    fn read_remote_rssi_cb(&mut self, client_id: i32, addr: RawAddress, rssi: i32, status: i32);
+
+    #[btif_callback(ConfigureMtu)]
+    fn configure_mtu_cb(&mut self, conn_id: i32, status: i32, mtu: i32);
+
+    #[btif_callback(Congestion)]
+    fn congestion_cb(&mut self, conn_id: i32, congested: bool);
+
+    #[btif_callback(GetGattDb)]
+    fn get_gatt_db_cb(&mut self, conn_id: i32, elements: Vec<BtGattDbElement>, count: i32);
+
+    #[btif_callback(PhyUpdated)]
+    fn phy_updated_cb(&mut self, conn_id: i32, tx_phy: u8, rx_phy: u8, status: u8);
+
+    #[btif_callback(ConnUpdated)]
+    fn conn_updated_cb(
+        &mut self,
+        conn_id: i32,
+        interval: u16,
+        latency: u16,
+        timeout: u16,
+        status: u8,
+    );
+
+    #[btif_callback(ServiceChanged)]
+    fn service_changed_cb(&self, conn_id: i32);
+
+    #[btif_callback(ReadPhy)]
+    fn read_phy_cb(&mut self, client_id: i32, addr: RawAddress, tx_phy: u8, rx_phy: u8, status: u8);
+
+    #[btif_callback(PrepareWrite)]
+    fn prepare_write_cb(&mut self, conn_id: i32, status: i32, handle: u16, len: u16);
+
+    #[btif_callback(EattChannels)]
+    fn eatt_channels_cb(&mut self, addr: RawAddress, mtus: Vec<u16>);
+}
+
+impl BtifGattClientCallbacks for BluetoothGatt {
+    fn register_client_cb(&mut self, status: i32, client_id: i32, app_uuid: Uuid) {
+        self.context_map.set_client_id(&app_uuid.uu, client_id);
+
+        let client = self.context_map.get_by_uuid(&app_uuid.uu);
+        if client.is_none() {
+            warn!("Warning: Client not registered for UUID {:?}", app_uuid.uu);
+            return;
+        }
+
+        let callback = &client.unwrap().callback;
+        callback.on_client_registered(status, client_id);
+    }
+
+    fn connect_cb(&mut self, conn_id: i32, status: i32, client_id: i32, addr: RawAddress) {
+        let address = addr.to_string();
+        self.outgoing_connections.remove(&address);
+        self.record_error(&address, "Connect", status);
+        if status == 0 {
+            let reconnected = !self.context_map.connections.iter().any(|c| c.address == address);
+            self.context_map.add_connection(client_id, conn_id, &address);
+            if reconnected {
+                self.restore_phy_preference(&address);
+                self.apply_link_mtu(&address, conn_id);
+            }
+            // Requested last so that the preference of the client takes precedence.
+            self.apply_client_phy_preference(client_id, &address);
+        }
+
+        let is_connected = match GattStatus::from_i32(status) {
+            None => false,
+            Some(gatt_status) => gatt_status == GattStatus::Success,
+        };
+
+        // The clients waiting for this attempt join the link, or fail along with it.
+        let waiting = {
+            let shared_connections = &mut self.shared_connections;
+            let connection = shared_connections.entry(address.clone()).or_default();
+            let waiting = if is_connected {
+                connection.connected(client_id)
+            } else {
+                connection.failed(client_id)
+            };
+            if connection.is_empty() {
+                shared_connections.remove(&address);
+            }
+            waiting
+        };
+
+        if let Some(client) = self.context_map.get_by_client_id(client_id) {
+            client.callback.on_client_connection_state(
+                status,
+                client_id,
+                is_connected,
+                BtAddress::from(addr),
+            );
+            // The EATT bearers may have been connected for another client already.
+            match self.eatt_bearers.get(&address) {
+                Some(mtus) if is_connected && client.eatt_support => {
+                    client.callback.on_eatt_state_changed(
+                        BtAddress::from(addr),
+                        mtus.len() as i32,
+                        mtus.clone(),
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        for request in waiting {
+            if is_connected {
+                self.connect_shared(&addr, request);
+            } else if let Some(client) = self.context_map.get_by_client_id(request.client_id) {
+                client.callback.on_client_connection_state(
+                    status,
+                    request.client_id,
+                    false,
+                    BtAddress::from(addr),
+                );
+            }
+        }
+
+        if is_connected {
+            self.metrics.increment("gatt.connection");
+            self.start_journal_flush(client_id, conn_id, &address);
+        } else {
+            self.metrics.increment(format!("gatt.connection_failure.{:#04x}", status));
+        }
+    }
+
+    fn disconnect_cb(&mut self, conn_id: i32, status: i32, client_id: i32, addr: RawAddress) {
+        {
+            let shared_connections = &mut self.shared_connections;
+            if let Some(connection) = shared_connections.get_mut(&addr.to_string()) {
+                connection.disconnected(client_id);
+                if connection.is_empty() {
+                    shared_connections.remove(&addr.to_string());
+                }
+            }
+        }
+        self.context_map.remove_connection(client_id, conn_id);
+        // The link may stay connected for the other clients, or for its tuning profile.
+        self.drop_connection_parameters(client_id, &addr.to_string());
+        self.notification_pipes.retain(|(id, _), _| *id != conn_id);
+        self.multiple_notifications.remove(&conn_id);
+        self.rssi_monitors.remove(&conn_id);
+        // The CCCDs are left as they are, the next write of a remaining client updates them.
+        self.shared_cccds.retain(|(address, _), cccd| {
+            if *address == addr.to_string() {
+                cccd.wanted.remove(&client_id);
+            }
+            !cccd.wanted.is_empty()
+        });
+        self.gatt_dbs.remove(&conn_id);
+        self.gatt_db_requests.retain(|(id, _), _| *id != conn_id);
+        self.pending_operations.remove(&conn_id);
+        self.att_retries.remove_connection(conn_id);
+        self.conn_params.remove(&conn_id);
+        // The writes which have not completed are sent again at the next connection.
+        if let Some(flush) = self.journal_flushes.remove(&conn_id) {
+            if let Some(journal) = self.write_journals.get_mut(&client_id) {
+                journal.restore(flush.into_unsent());
+            }
+        }
+        self.cancelled_discoveries.remove(&conn_id);
+        self.service_reads.remove(&conn_id);
+        self.conformance_checks.remove(&conn_id);
+        self.mtus.remove(&conn_id);
+        self.long_writes.remove(&conn_id);
+        let client = self.context_map.get_by_client_id(client_id);
+        if client.is_none() {
+            return;
+        }
+
+        client.unwrap().callback.on_client_connection_state(
+            status,
+            client_id,
+            match GattStatus::from_i32(status) {
+                None => false,
+                Some(gatt_status) => gatt_status == GattStatus::Success,
+            },
+            BtAddress::from(addr),
+        );
+
+        // Devices in the background connection list are reconnected once they advertise again.
+        let address = addr.to_string();
+        if self.background_connections.get(&address).map_or(false, |c| c.contains(&client_id)) {
+            self.connect_in_background(client_id, &addr);
+        }
+    }
+
+    fn search_complete_cb(&mut self, conn_id: i32, _status: i32) {
+        // The database of a cancelled discovery is still cached, without notifying the client.
+        if !self.complete_operation(conn_id, GattOperation::Discovery, 0) {
+            self.cancelled_discoveries.remove(&conn_id);
+        }
+
+        // Gatt DB is ready!
+        self.request_gatt_db(conn_id, GattDbRequest::Discovery);
+    }
+
+    fn register_for_notification_cb(
+        &mut self,
+        _conn_id: i32,
+        _registered: i32,
+        _status: i32,
+        _handle: u16,
+    ) {
+        // No-op.
+    }
+
+    fn notify_cb(&mut self, conn_id: i32, data: BtGattNotifyParams) {
+        let client = self.context_map.get_client_by_conn_id(conn_id);
+        if client.is_none() {
+            return;
+        }
+
+        let address = RawAddress { val: data.bda.address }.to_string();
+        let handle = data.handle as i32;
+        let value = &data.value[0..data.len as usize];
+
+        let opcode = if data.multi_count > 0 {
+            ATT_MULTIPLE_HANDLE_VALUE_NTF
+        } else if data.is_notify != 0 {
+            ATT_HANDLE_VALUE_NTF
+        } else {
+            ATT_HANDLE_VALUE_IND
+        };
+        self.trace_att(&address, |trace, now| {
+            trace.record(now, AttPduDirection::Received, opcode, handle, value.len(), 0)
+        });
+        let operation = if data.is_notify != 0 { "Notification" } else { "Indication" };
+        self.log_value(&address, operation, handle, value);
+
+        // The values of a multiple handle value notification are delivered together once all of
+        // them are received.
+        if data.multi_count > 1 {
+            let values = self.multiple_notifications.entry(conn_id).or_default();
+            values.push(GattHandleValue { handle, value: value.to_vec() });
+            if values.len() >= data.multi_count as usize {
+                let values = self.multiple_notifications.remove(&conn_id).unwrap_or_default();
+                client.unwrap().callback.on_notify_multiple(callback_address(&address), values);
+            }
+            return;
+        }
+
+        if let Some(notification_pipe) = self.notification_pipes.get_mut(&(conn_id, handle)) {
+            match notification_pipe.frames.try_send(frame_notification(value)) {
+                Ok(()) => {
+                    let now = Instant::now();
+                    let was_idle = match notification_pipe.last_write {
+                        Some(last) => now.duration_since(last) >= NOTIFICATION_PIPE_IDLE_TIMEOUT,
+                        None => true,
+                    };
+                    notification_pipe.last_write = Some(now);
+
+                    if was_idle {
+                        client
+                            .unwrap()
+                            .callback
+                            .on_notification_pipe_active(callback_address(&address), handle);
+                    }
+                    return;
+                }
+                Err(TrySendError::Full(_)) => {
+                    warn!("Notification pipe for handle {} is full, dropping value", handle);
+                    return;
+                }
+                Err(TrySendError::Closed(_)) => {
+                    // The client closed its end, fall back to regular notifications.
+                    warn!("Notification pipe for handle {} is closed", handle);
+                    self.notification_pipes.remove(&(conn_id, handle));
+                }
+            }
+        }
+
+        let client = client.unwrap();
+        if let Some(client_id) = client.id {
+            if let Some(queue) = self.notification_queues.get_mut(&client_id) {
+                if queue.is_empty() {
+                    if let Some(tx) = self.tx.clone() {
+                        tokio::spawn(async move {
+                            time::sleep(NOTIFICATION_QUEUE_DELAY).await;
+                            let _ = tx.send(Message::GattDeliverNotifications(client_id)).await;
+                        });
+                    }
+                }
+                queue.push(address, handle, value.to_vec());
+                return;
+            }
+        }
+
+        client.callback.on_notify(callback_address(&address), handle, value.to_vec());
+    }
+
+    fn read_characteristic_cb(&mut self, conn_id: i32, status: i32, data: BtGattReadParams) {
+        if let Some(address) = self.context_map.get_address_by_conn_id(conn_id) {
+            self.record_error(&address, "ReadCharacteristic", status);
+            self.trace_att(&address, |trace, now| {
+                trace.record_response(
+                    now,
+                    AttPduDirection::Received,
+                    data.handle as i32,
+                    data.handle as i32,
+                    data.value.len as usize,
+                    status,
+                )
+            });
+            if status == GattStatus::Success as i32 {
+                let value = &data.value.value[0..data.value.len as usize];
+                self.log_value(&address, "Read", data.handle as i32, value);
+            }
+        }
+
+        if let Some(check) = self.conformance_checks.get_mut(&conn_id) {
+            if check.current_handle() == Some(data.handle as i32) {
+                check.on_read(status, data.value.value[0..data.value.len as usize].to_vec());
+                self.continue_conformance_check(conn_id);
+                return;
+            }
+        }
+
+        if let Some(read) = self.service_reads.get_mut(&conn_id) {
+            if read.current_handle() == Some(data.handle as i32) {
+                let (uuid, handle) = read.pending.remove(0);
+                read.results.push(CharacteristicReadResult {
+                    uuid,
+                    handle,
+                    status,
+                    value: data.value.value[0..data.value.len as usize].to_vec(),
+                });
+                self.continue_service_read(conn_id);
+                return;
+            }
+        }
+
+        if self.schedule_retry(conn_id, data.handle as i32, status) {
+            return;
+        }
+        let retries = self.att_retries.take(conn_id, data.handle as i32);
+
+        let value = data.value.value[0..data.value.len as usize].to_vec();
+        let result = OperationResult { status, value, retries };
+        self.finish_operation(
+            conn_id,
+            GattOperation::ReadCharacteristic,
+            data.handle as i32,
+            result,
+        );
+    }
+
+    fn write_characteristic_cb(
+        &mut self,
+        conn_id: i32,
+        status: i32,
+        handle: u16,
+        _len: u16,
+        _value: *const u8,
+    ) {
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+        if address.is_none() {
+            return;
+        }
+        // The result of a write sent on a congested link is not a failure.
+        if status != GattStatus::Congested.to_i32().unwrap() {
+            self.record_error(address.as_ref().unwrap(), "WriteCharacteristic", status);
+        }
+
+        self.trace_att(address.as_ref().unwrap(), |trace, now| {
+            trace.record_response(
+                now,
+                AttPduDirection::Received,
+                handle as i32,
+                handle as i32,
+                0,
+                status,
+            )
+        });
+
+        let result = OperationResult { status, ..Default::default() };
+        self.finish_operation(conn_id, GattOperation::WriteCharacteristic, handle as i32, result);
+
+        // After the write is no longer pending, for the next journaled write to be sent behind
+        // the pending ones only.
+        self.journal_write_completed(conn_id, handle as i32, false);
+    }
+
+    fn read_descriptor_cb(&mut self, conn_id: i32, status: i32, data: BtGattReadParams) {
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+        if address.is_none() {
+            return;
+        }
+        self.record_error(address.as_ref().unwrap(), "ReadDescriptor", status);
+
+        self.trace_att(address.as_ref().unwrap(), |trace, now| {
+            trace.record_response(
+                now,
+                AttPduDirection::Received,
+                data.handle as i32,
+                data.handle as i32,
+                data.value.len as usize,
+                status,
+            )
+        });
+        if status == GattStatus::Success as i32 {
+            let value = &data.value.value[0..data.value.len as usize];
+            let address = address.as_ref().unwrap();
+            self.log_value(address, "Descriptor read", data.handle as i32, value);
+        }
+
+        if self.schedule_retry(conn_id, data.handle as i32, status) {
+            return;
+        }
+        let retries = self.att_retries.take(conn_id, data.handle as i32);
+
+        let value = data.value.value[0..data.value.len as usize].to_vec();
+        let result = OperationResult { status, value, retries };
+        self.finish_operation(conn_id, GattOperation::ReadDescriptor, data.handle as i32, result);
+    }
+
+    fn write_descriptor_cb(
+        &mut self,
+        conn_id: i32,
+        status: i32,
+        handle: u16,
+        _len: u16,
+        _value: *const u8,
+    ) {
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+        if address.is_none() {
+            return;
+        }
+        self.record_error(address.as_ref().unwrap(), "WriteDescriptor", status);
+
+        self.trace_att(address.as_ref().unwrap(), |trace, now| {
+            trace.record_response(
+                now,
+                AttPduDirection::Received,
+                handle as i32,
+                handle as i32,
+                0,
+                status,
+            )
+        });
+
+        if status != GattStatus::Success.to_i32().unwrap() {
+            self.forget_shared_cccd_value(conn_id, handle as i32);
+        }
+
+        let result = OperationResult { status, ..Default::default() };
+        self.finish_operation(conn_id, GattOperation::WriteDescriptor, handle as i32, result);
+    }
+
+    fn execute_write_cb(&mut self, conn_id: i32, status: i32) {
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+        if address.is_none() {
+            return;
+        }
+
+        self.trace_att(address.as_ref().unwrap(), |trace, now| {
+            trace.record_response(now, AttPduDirection::Received, 0, 0, 0, status)
+        });
+
+        let client = self.context_map.get_client_by_conn_id(conn_id);
+        if client.is_none() {
+            return;
+        }
+
+        if let Some(write) = self.long_writes.remove(&conn_id) {
+            client.unwrap().callback.on_characteristic_write(
+                callback_address(&address.unwrap()),
+                write.failure.unwrap_or(status),
+                write.handle,
+            );
+            self.journal_write_completed(conn_id, write.handle, true);
+            return;
+        }
+
+        client.unwrap().callback.on_execute_write(callback_address(&address.unwrap()), status);
+    }
+
+    fn read_remote_rssi_cb(&mut self, client_id: i32, addr: RawAddress, rssi: i32, status: i32) {
+        let client = self.context_map.get_by_client_id(client_id);
+        if client.is_none() {
+            return;
+        }
+
+        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr.to_string());
+        if let Some(monitor) = conn_id.and_then(|conn_id| self.rssi_monitors.get_mut(&conn_id)) {
+            if monitor.polls_pending > 0 {
+                monitor.polls_pending -= 1;
+                if status != GattStatus::Success.to_i32().unwrap() {
+                    return;
+                }
+                if let Some(threshold) = monitor.sample(rssi) {
+                    debug!("RSSI of {} reached {} dBm: {} dBm", addr, threshold, rssi);
+                    client.unwrap().callback.on_rssi_threshold_crossed(
+                        BtAddress::from(addr),
+                        rssi,
+                        threshold,
+                    );
+                }
+                return;
+            }
+        }
+
+        client.unwrap().callback.on_read_remote_rssi(BtAddress::from(addr), rssi, status);
+    }
+
+    fn configure_mtu_cb(&mut self, conn_id: i32, status: i32, mtu: i32) {
+        if status == GattStatus::Success.to_i32().unwrap() {
+            self.mtus.insert(conn_id, mtu as usize);
+        }
+
+        let client = self.context_map.get_client_by_conn_id(conn_id);
+        if client.is_none() {
+            return;
+        }
+
+        let addr = self.context_map.get_address_by_conn_id(conn_id);
+        if addr.is_none() {
+            return;
+        }
+
+        self.trace_att(addr.as_ref().unwrap(), |trace, now| {
+            trace.record_response(now, AttPduDirection::Received, 0, 0, 0, status)
+        });
+
+        client.unwrap().callback.on_configure_mtu(callback_address(&addr.unwrap()), mtu, status);
+    }
+
+    fn congestion_cb(&mut self, conn_id: i32, congested: bool) {
+        let client = self.context_map.get_client_by_conn_id_mut(conn_id);
+        if client.is_none() {
+            return;
+        }
+
+        let client = client.unwrap();
+
+        client.is_congested = congested;
+        if !client.is_congested {
+            for callback in client.congestion_queue.iter() {
+                client.callback.on_characteristic_write(
+                    callback_address(&callback.0),
+                    callback.1,
+                    callback.2,
+                );
+            }
+            client.congestion_queue.clear();
+        }
+    }
+
+    fn get_gatt_db_cb(&mut self, conn_id: i32, elements: Vec<BtGattDbElement>, _count: i32) {
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+        if address.is_none() {
+            return;
+        }
+
+        let client = self.context_map.get_client_by_conn_id(conn_id);
+        if client.is_none() {
+            return;
+        }
+
+        let mut db_out: Vec<BluetoothGattService> = vec![];
+
+        for elem in elements {
+            match GattDbElementType::from_u32(elem.type_).unwrap() {
+                GattDbElementType::PrimaryService | GattDbElementType::SecondaryService => {
+                    db_out.push(BluetoothGattService::new(
+                        elem.uuid.uu,
+                        elem.id as i32,
+                        elem.type_ as i32,
+                    ));
+                    // TODO(b/200065274): Mark restricted services.
+                }
+
+                GattDbElementType::Characteristic => {
+                    match db_out.last_mut() {
+                        Some(s) => s.characteristics.push(BluetoothGattCharacteristic::new(
+                            elem.uuid.uu,
+                            elem.id as i32,
+                            elem.properties as i32,
+                            0,
+                        )),
+                        None => {
+                            // TODO(b/193685325): Log error.
+                        }
+                    }
+                    // TODO(b/200065274): Mark restricted characteristics.
+                }
+
+                GattDbElementType::Descriptor => {
+                    match db_out.last_mut() {
+                        Some(s) => match s.characteristics.last_mut() {
+                            Some(c) => c.descriptors.push(BluetoothGattDescriptor::new(
+                                elem.uuid.uu,
+                                elem.id as i32,
+                                0,
+                            )),
+                            None => {
+                                // TODO(b/193685325): Log error.
+                            }
+                        },
+                        None => {
+                            // TODO(b/193685325): Log error.
+                        }
+                    }
+                    // TODO(b/200065274): Mark restricted descriptors.
+                }
+
+                GattDbElementType::IncludedService => {
+                    match db_out.last_mut() {
+                        Some(s) => {
+                            s.included_services.push(BluetoothGattService::new(
+                                elem.uuid.uu,
+                                elem.id as i32,
+                                elem.type_ as i32,
+                            ));
+                        }
+                        None => {
+                            // TODO(b/193685325): Log error.
+                        }
+                    }
+                }
+            }
+        }
+
+        let address = address.unwrap();
+        self.gatt_dbs.insert(conn_id, db_out.clone());
+        if let Some(GattDbRequest::Client) = self.take_gatt_db_request(conn_id) {
+            client.unwrap().callback.on_get_gatt_db(callback_address(&address), db_out);
+        } else if self.cancelled_discoveries.remove(&conn_id) {
+            // The client was already notified of the cancellation.
+        } else {
+            client.unwrap().callback.on_search_complete(callback_address(&address), db_out, 0);
+        }
 
-        self.gatt.as_ref().unwrap().client.connect(
-            client_id,
-            &address,
-            is_direct,
-            transport,
-            opportunistic,
-            phy,
-        );
+        if let Some(check) = self.conformance_checks.get_mut(&conn_id) {
+            if check.awaiting_database {
+                check.start(&self.gatt_dbs[&conn_id]);
+                self.continue_conformance_check(conn_id);
+            }
+        }
     }
 
-    fn client_disconnect(&self, client_id: i32, address: String) {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &address);
-        if conn_id.is_none() {
+    fn phy_updated_cb(&mut self, conn_id: i32, tx_phy: u8, rx_phy: u8, status: u8) {
+        let client = self.context_map.get_client_by_conn_id(conn_id);
+        if client.is_none() {
             return;
         }
 
-        self.gatt.as_ref().unwrap().client.disconnect(
-            client_id,
-            &RawAddress::from_string(address).unwrap(),
-            conn_id.unwrap(),
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+        if address.is_none() {
+            return;
+        }
+
+        client.unwrap().callback.on_phy_update(
+            callback_address(&address.unwrap()),
+            LePhy::from_u8(tx_phy).unwrap(),
+            LePhy::from_u8(rx_phy).unwrap(),
+            GattStatus::from_u8(status).unwrap(),
         );
     }
 
-    fn client_set_preferred_phy(
-        &self,
+    fn read_phy_cb(
+        &mut self,
         client_id: i32,
-        address: String,
-        tx_phy: LePhy,
-        rx_phy: LePhy,
-        phy_options: i32,
+        addr: RawAddress,
+        tx_phy: u8,
+        rx_phy: u8,
+        status: u8,
     ) {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &address);
-        if conn_id.is_none() {
+        let client = self.context_map.get_by_client_id(client_id);
+        if client.is_none() {
             return;
         }
 
-        self.gatt.as_ref().unwrap().client.set_preferred_phy(
-            &RawAddress::from_string(address).unwrap(),
-            tx_phy.to_u8().unwrap(),
-            rx_phy.to_u8().unwrap(),
-            phy_options as u16,
+        client.unwrap().callback.on_phy_read(
+            BtAddress::from(addr),
+            LePhy::from_u8(tx_phy).unwrap(),
+            LePhy::from_u8(rx_phy).unwrap(),
+            GattStatus::from_u8(status).unwrap(),
         );
     }
 
-    fn client_read_phy(&mut self, client_id: i32, addr: String) {
-        let address = match RawAddress::from_string(addr.clone()) {
+    fn prepare_write_cb(&mut self, conn_id: i32, status: i32, handle: u16, len: u16) {
+        let address = match self.context_map.get_address_by_conn_id(conn_id) {
+            Some(address) => address,
             None => return,
-            Some(addr) => addr,
         };
 
-        self.gatt.as_mut().unwrap().client.read_phy(client_id, &address);
-    }
+        self.trace_att(&address, |trace, now| {
+            trace.record_response(
+                now,
+                AttPduDirection::Received,
+                handle as i32,
+                handle as i32,
+                len as usize,
+                status,
+            )
+        });
 
-    fn refresh_device(&self, client_id: i32, addr: String) {
-        self.gatt
-            .as_ref()
-            .unwrap()
-            .client
-            .refresh(client_id, &RawAddress::from_string(addr).unwrap());
-    }
+        let write = match self.long_writes.get_mut(&conn_id) {
+            Some(write) => write,
+            None => return,
+        };
 
-    fn discover_services(&self, client_id: i32, addr: String) {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
-        if conn_id.is_none() {
+        if status != GattStatus::Success.to_i32().unwrap() {
+            // Drop the parts already queued, the write completes with the execute write response.
+            write.failure = Some(status);
+            self.trace_att(&address, |trace, now| {
+                trace.record_request(now, AttPduDirection::Sent, 0, ATT_EXECUTE_WRITE_REQ, 0, 0)
+            });
+            self.gatt.as_ref().unwrap().client.execute_write(conn_id, 0);
             return;
         }
 
-        self.gatt.as_ref().unwrap().client.search_service(conn_id.unwrap(), None);
-    }
-
-    fn discover_service_by_uuid(&self, client_id: i32, addr: String, uuid: String) {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
-        if conn_id.is_none() {
+        if write.failure.is_some() {
+            // The write was cancelled while this part was in flight.
+            self.trace_att(&address, |trace, now| {
+                trace.record_request(now, AttPduDirection::Sent, 0, ATT_EXECUTE_WRITE_REQ, 0, 0)
+            });
+            self.gatt.as_ref().unwrap().client.execute_write(conn_id, 0);
             return;
         }
 
-        let uuid = parse_uuid_string(uuid);
-        if uuid.is_none() {
+        let acknowledged = write.next_chunk().map_or(0, |(_, data)| data.len());
+        write.written += acknowledged;
+        let (handle, written, total) = (write.handle, write.written, write.value.len());
+
+        if let Some(client) = self.context_map.get_client_by_conn_id(conn_id) {
+            client.callback.on_characteristic_write_progress(
+                callback_address(&address),
+                handle,
+                written as i32,
+                total as i32,
+            );
+        }
+
+        self.continue_long_write(conn_id);
+    }
+
+    fn eatt_channels_cb(&mut self, addr: RawAddress, mtus: Vec<u16>) {
+        let address = addr.to_string();
+        let mtus: Vec<i32> = mtus.into_iter().map(|mtu| mtu as i32).collect();
+        let previous = if mtus.is_empty() {
+            self.eatt_bearers.remove(&address)
+        } else {
+            self.eatt_bearers.insert(address.clone(), mtus.clone())
+        };
+        if previous.unwrap_or_default() == mtus {
             return;
         }
 
-        self.gatt.as_ref().unwrap().client.search_service(conn_id.unwrap(), uuid);
+        for conn_id in self.context_map.get_eatt_conn_ids(&address) {
+            if let Some(client) = self.context_map.get_client_by_conn_id(conn_id) {
+                client.callback.on_eatt_state_changed(
+                    BtAddress::from(addr),
+                    mtus.len() as i32,
+                    mtus.clone(),
+                );
+            }
+            // The queued operations may go on the new bearers.
+            self.send_queued_operations(conn_id);
+        }
     }
 
-    fn read_characteristic(&self, client_id: i32, addr: String, handle: i32, auth_req: i32) {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
-        if conn_id.is_none() {
+    fn conn_updated_cb(
+        &mut self,
+        conn_id: i32,
+        interval: u16,
+        latency: u16,
+        timeout: u16,
+        status: u8,
+    ) {
+        if status == 0 {
+            self.conn_params.insert(conn_id, (interval, latency, timeout));
+        }
+
+        let client = self.context_map.get_client_by_conn_id(conn_id);
+        if client.is_none() {
             return;
         }
 
-        // TODO(b/200065274): Perform check on restricted handles.
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+        if address.is_none() {
+            return;
+        }
 
-        self.gatt.as_ref().unwrap().client.read_characteristic(
-            conn_id.unwrap(),
-            handle as u16,
-            auth_req,
+        client.unwrap().callback.on_connection_updated(
+            callback_address(&address.unwrap()),
+            interval as i32,
+            latency as i32,
+            timeout as i32,
+            status as i32,
         );
     }
 
-    fn read_using_characteristic_uuid(
-        &self,
-        client_id: i32,
-        addr: String,
-        uuid: String,
-        start_handle: i32,
-        end_handle: i32,
-        auth_req: i32,
-    ) {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
-        if conn_id.is_none() {
+    fn service_changed_cb(&self, conn_id: i32) {
+        let address = self.context_map.get_address_by_conn_id(conn_id);
+        if address.is_none() {
             return;
         }
 
-        let uuid = parse_uuid_string(uuid);
-        if uuid.is_none() {
+        let client = self.context_map.get_client_by_conn_id(conn_id);
+        if client.is_none() {
             return;
         }
 
-        // TODO(b/200065274): Perform check on restricted handles.
-
-        self.gatt.as_ref().unwrap().client.read_using_characteristic_uuid(
-            conn_id.unwrap(),
-            &uuid.unwrap(),
-            start_handle as u16,
-            end_handle as u16,
-            auth_req,
-        );
+        client.unwrap().callback.on_service_changed(callback_address(&address.unwrap()));
     }
+}
 
-    fn write_characteristic(
-        &self,
-        client_id: i32,
-        addr: String,
+#[btif_callbacks_dispatcher(BluetoothGatt, dispatch_gatt_server_callbacks, GattServerCallbacks)]
+pub(crate) trait BtifGattServerCallbacks {
+    #[btif_callback(RegisterServer)]
+    fn register_server_cb(&mut self, status: i32, server_id: i32, app_uuid: Uuid);
+
+    #[btif_callback(Connection)]
+    fn connection_cb(&mut self, conn_id: i32, server_id: i32, connected: i32, addr: RawAddress);
+
+    #[btif_callback(ServiceAdded)]
+    fn service_added_cb(
+        &mut self,
+        status: i32,
+        server_id: i32,
+        elements: Vec<BtGattDbElement>,
+        count: usize,
+    );
+
+    #[btif_callback(ServiceDeleted)]
+    fn service_deleted_cb(&mut self, status: i32, server_id: i32, handle: i32);
+
+    #[btif_callback(RequestReadCharacteristic)]
+    fn request_read_characteristic_cb(
+        &mut self,
+        conn_id: i32,
+        trans_id: i32,
+        addr: RawAddress,
         handle: i32,
-        mut write_type: GattWriteType,
-        auth_req: i32,
+        offset: i32,
+        is_long: bool,
+    );
+
+    #[btif_callback(RequestReadDescriptor)]
+    fn request_read_descriptor_cb(
+        &mut self,
+        conn_id: i32,
+        trans_id: i32,
+        addr: RawAddress,
+        handle: i32,
+        offset: i32,
+        is_long: bool,
+    );
+
+    #[btif_callback(RequestWriteCharacteristic)]
+    fn request_write_characteristic_cb(
+        &mut self,
+        conn_id: i32,
+        trans_id: i32,
+        addr: RawAddress,
+        handle: i32,
+        offset: i32,
+        need_rsp: bool,
+        is_prep: bool,
         value: Vec<u8>,
-    ) -> GattWriteRequestStatus {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
-        if conn_id.is_none() {
-            return GattWriteRequestStatus::Fail;
-        }
+        len: usize,
+    );
 
-        if self.reliable_queue.contains(&addr) {
-            write_type = GattWriteType::WritePrepare;
-        }
+    #[btif_callback(RequestWriteDescriptor)]
+    fn request_write_descriptor_cb(
+        &mut self,
+        conn_id: i32,
+        trans_id: i32,
+        addr: RawAddress,
+        handle: i32,
+        offset: i32,
+        need_rsp: bool,
+        is_prep: bool,
+        value: Vec<u8>,
+        len: usize,
+    );
 
-        // TODO(b/200065274): Perform check on restricted handles.
+    #[btif_callback(RequestExecWrite)]
+    fn request_exec_write_cb(
+        &mut self,
+        conn_id: i32,
+        trans_id: i32,
+        addr: RawAddress,
+        exec_write: i32,
+    );
 
-        // TODO(b/200070162): Handle concurrent write characteristic.
+    #[btif_callback(IndicationSent)]
+    fn indication_sent_cb(&mut self, conn_id: i32, status: i32);
 
-        self.gatt.as_ref().unwrap().client.write_characteristic(
-            conn_id.unwrap(),
-            handle as u16,
-            write_type.to_i32().unwrap(),
-            auth_req,
-            &value,
-        );
+    #[btif_callback(Congestion)]
+    fn server_congestion_cb(&mut self, conn_id: i32, congested: bool);
 
-        return GattWriteRequestStatus::Success;
-    }
+    #[btif_callback(MtuChanged)]
+    fn mtu_changed_cb(&mut self, conn_id: i32, mtu: i32);
 
-    fn read_descriptor(&self, client_id: i32, addr: String, handle: i32, auth_req: i32) {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
-        if conn_id.is_none() {
+    #[btif_callback(PhyUpdated)]
+    fn server_phy_updated_cb(&mut self, conn_id: i32, tx_phy: u8, rx_phy: u8, status: u8);
+
+    #[btif_callback(ConnUpdated)]
+    fn server_conn_updated_cb(
+        &mut self,
+        conn_id: i32,
+        interval: u16,
+        latency: u16,
+        timeout: u16,
+        status: u8,
+    );
+}
+
+impl BtifGattServerCallbacks for BluetoothGatt {
+    fn register_server_cb(&mut self, status: i32, server_id: i32, app_uuid: Uuid) {
+        if app_uuid.uu == TIME_SERVER_UUID && self.time_server.is_some() {
+            if status != GattStatus::Success as i32 {
+                warn!("Failed to register the time server: {}", status);
+                self.time_server = None;
+                return;
+            }
+
+            self.time_server.as_mut().unwrap().server_id = Some(server_id);
+            let elements = service_to_db_elements(&time_service::build_service());
+            self.gatt.as_ref().unwrap().server.add_service(server_id, &elements);
             return;
         }
 
-        // TODO(b/200065274): Perform check on restricted handles.
+        self.server_context_map.set_server_id(&app_uuid.uu, server_id);
 
-        self.gatt.as_ref().unwrap().client.read_descriptor(
-            conn_id.unwrap(),
-            handle as u16,
-            auth_req,
-        );
+        let server = self.server_context_map.get_by_uuid(&app_uuid.uu);
+        if server.is_none() {
+            return;
+        }
+
+        server.unwrap().callback.on_server_registered(status, server_id);
     }
 
-    fn write_descriptor(
-        &self,
-        client_id: i32,
-        addr: String,
-        handle: i32,
-        auth_req: i32,
-        value: Vec<u8>,
-    ) {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
-        if conn_id.is_none() {
-            return;
+    fn connection_cb(&mut self, conn_id: i32, server_id: i32, connected: i32, addr: RawAddress) {
+        let address = addr.to_string();
+        if connected != 0 {
+            self.server_context_map.add_connection(server_id, conn_id, &address);
+            match self.check_peripheral_connection(&address) {
+                PeripheralDecision::Rejected | PeripheralDecision::Revoked => {
+                    self.disconnect_central(&address);
+                    return;
+                }
+                _ => {}
+            }
+        } else {
+            self.server_context_map.remove_connection(conn_id);
+            if let Some(time_server) = self.time_server.as_mut() {
+                time_server.subscribers.remove(&conn_id);
+            }
+            let decision = self.peripheral_decisions.get(&address).cloned();
+            if !self.server_context_map.connections.iter().any(|conn| conn.address == address) {
+                self.forget_peripheral_decision(&address);
+            }
+
+            // The servers never heard of the rejected centrals.
+            if decision == Some(PeripheralDecision::Rejected) {
+                return;
+            }
         }
 
-        // TODO(b/200065274): Perform check on restricted handles.
+        let server = self.server_context_map.get_by_server_id(server_id);
+        if server.is_none() {
+            return;
+        }
 
-        self.gatt.as_ref().unwrap().client.write_descriptor(
-            conn_id.unwrap(),
-            handle as u16,
-            auth_req,
-            &value,
+        server.unwrap().callback.on_server_connection_state(
+            server_id,
+            connected != 0,
+            BtAddress::from(addr),
         );
     }
 
-    fn register_for_notification(&self, client_id: i32, addr: String, handle: i32, enable: bool) {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
-        if conn_id.is_none() {
-            return;
+    fn service_added_cb(
+        &mut self,
+        status: i32,
+        server_id: i32,
+        elements: Vec<BtGattDbElement>,
+        _count: usize,
+    ) {
+        let service = service_from_db_elements(&elements);
+        if let Some(time_server) = self.time_server.as_mut() {
+            if time_server.server_id == Some(server_id) {
+                match service {
+                    Some(service) if status == GattStatus::Success as i32 => {
+                        time_server.set_handles(&service);
+                        self.check_clock();
+                    }
+                    _ => warn!("Failed to add the Current Time Service: {}", status),
+                }
+                return;
+            }
         }
 
-        // TODO(b/200065274): Perform check on restricted handles.
+        let app_uuid = match self.server_context_map.get_by_server_id(server_id) {
+            Some(server) => server.uuid,
+            None => return,
+        };
 
-        if enable {
-            self.gatt.as_ref().unwrap().client.register_for_notification(
-                client_id,
-                &RawAddress::from_string(addr).unwrap(),
-                handle as u16,
-            );
+        let service = match service {
+            Some(service) => service,
+            None => {
+                warn!("Server {} added a service without attributes", server_id);
+                return;
+            }
+        };
+        let broadcast = if status == GattStatus::Success as i32 {
+            self.manage_descriptors(server_id, app_uuid, &service)
         } else {
-            self.gatt.as_ref().unwrap().client.deregister_for_notification(
-                client_id,
-                &RawAddress::from_string(addr).unwrap(),
-                handle as u16,
-            );
-        }
-    }
+            vec![]
+        };
 
-    fn begin_reliable_write(&mut self, _client_id: i32, addr: String) {
-        self.reliable_queue.insert(addr);
+        let server = self.server_context_map.get_by_server_id(server_id).unwrap();
+        server.callback.on_service_added(status, service);
+        for handle in broadcast {
+            server.callback.on_server_configuration_changed(BtAddress::default(), handle, true);
+        }
     }
 
-    fn end_reliable_write(&mut self, client_id: i32, addr: String, execute: bool) {
-        self.reliable_queue.remove(&addr);
+    fn service_deleted_cb(&mut self, status: i32, server_id: i32, handle: i32) {
+        if status == GattStatus::Success as i32 {
+            self.managed_descriptors
+                .retain(|(id, _), d| *id != server_id || d.service_handle != handle);
+        }
 
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
-        if conn_id.is_none() {
+        let server = self.server_context_map.get_by_server_id(server_id);
+        if server.is_none() {
             return;
         }
 
-        self.gatt
-            .as_ref()
-            .unwrap()
-            .client
-            .execute_write(conn_id.unwrap(), if execute { 1 } else { 0 });
+        server.unwrap().callback.on_service_removed(status, handle);
     }
 
-    fn read_remote_rssi(&self, client_id: i32, addr: String) {
-        self.gatt
-            .as_ref()
-            .unwrap()
-            .client
-            .read_remote_rssi(client_id, &RawAddress::from_string(addr).unwrap());
-    }
+    fn request_read_characteristic_cb(
+        &mut self,
+        conn_id: i32,
+        trans_id: i32,
+        addr: RawAddress,
+        handle: i32,
+        offset: i32,
+        is_long: bool,
+    ) {
+        let opcode = if is_long { ATT_READ_BLOB_REQ } else { ATT_READ_REQ };
+        self.trace_att(&addr.to_string(), |trace, now| {
+            trace.record_request(now, AttPduDirection::Received, trans_id, opcode, handle, 0)
+        });
 
-    fn configure_mtu(&self, client_id: i32, addr: String, mtu: i32) {
-        let conn_id = self.context_map.get_conn_id_from_address(client_id, &addr);
-        if conn_id.is_none() {
+        if self.is_time_server_connection(conn_id) {
+            self.time_server_read(conn_id, trans_id, handle, offset);
             return;
         }
 
-        self.gatt.as_ref().unwrap().client.configure_mtu(conn_id.unwrap(), mtu);
-    }
+        let server = self.server_context_map.get_server_by_conn_id_mut(conn_id);
+        if server.is_none() {
+            return;
+        }
 
-    fn connection_parameter_update(
-        &self,
-        _client_id: i32,
-        addr: String,
-        min_interval: i32,
-        max_interval: i32,
-        latency: i32,
-        timeout: i32,
-        min_ce_len: u16,
-        max_ce_len: u16,
-    ) {
-        self.gatt.as_ref().unwrap().client.conn_parameter_update(
-            &RawAddress::from_string(addr).unwrap(),
-            min_interval,
-            max_interval,
-            latency,
-            timeout,
-            min_ce_len,
-            max_ce_len,
+        let server = server.unwrap();
+        server.pending_requests.insert(trans_id, (conn_id, handle));
+        server.callback.on_characteristic_read_request(
+            BtAddress::from(addr),
+            trans_id,
+            offset,
+            is_long,
+            handle,
         );
     }
-}
 
-#[btif_callbacks_dispatcher(BluetoothGatt, dispatch_gatt_client_callbacks, GattClientCallbacks)]
-pub(crate) trait BtifGattClientCallbacks {
-    #[btif_callback(RegisterClient)]
-    fn register_client_cb(&mut self, status: i32, client_id: i32, app_uuid: Uuid);
-
-    #[btif_callback(Connect)]
-    fn connect_cb(&mut self, conn_id: i32, status: i32, client_id: i32, addr: RawAddress);
-
-    #[btif_callback(Disconnect)]
-    fn disconnect_cb(&mut self, conn_id: i32, status: i32, client_id: i32, addr: RawAddress);
-
-    #[btif_callback(SearchComplete)]
-    fn search_complete_cb(&mut self, conn_id: i32, status: i32);
-
-    #[btif_callback(RegisterForNotification)]
-    fn register_for_notification_cb(
+    fn request_read_descriptor_cb(
         &mut self,
         conn_id: i32,
-        registered: i32,
-        status: i32,
-        handle: u16,
-    );
+        trans_id: i32,
+        addr: RawAddress,
+        handle: i32,
+        offset: i32,
+        is_long: bool,
+    ) {
+        let opcode = if is_long { ATT_READ_BLOB_REQ } else { ATT_READ_REQ };
+        self.trace_att(&addr.to_string(), |trace, now| {
+            trace.record_request(now, AttPduDirection::Received, trans_id, opcode, handle, 0)
+        });
 
-    #[btif_callback(Notify)]
-    fn notify_cb(&mut self, conn_id: i32, data: BtGattNotifyParams);
+        if self.is_time_server_connection(conn_id) {
+            self.time_server_read(conn_id, trans_id, handle, offset);
+            return;
+        }
 
-    #[btif_callback(ReadCharacteristic)]
-    fn read_characteristic_cb(&mut self, conn_id: i32, status: i32, data: BtGattReadParams);
+        if let Some(server_id) = self.managed_descriptor_server(conn_id, handle) {
+            self.read_managed_descriptor(server_id, conn_id, trans_id, handle, offset);
+            return;
+        }
 
-    #[btif_callback(WriteCharacteristic)]
-    fn write_characteristic_cb(
-        &mut self,
-        conn_id: i32,
-        status: i32,
-        handle: u16,
-        len: u16,
-        value: *const u8,
-    );
+        let server = self.server_context_map.get_server_by_conn_id_mut(conn_id);
+        if server.is_none() {
+            return;
+        }
 
-    #[btif_callback(ReadDescriptor)]
-    fn read_descriptor_cb(&mut self, conn_id: i32, status: i32, data: BtGattReadParams);
+        let server = server.unwrap();
+        server.pending_requests.insert(trans_id, (conn_id, handle));
+        server.callback.on_descriptor_read_request(
+            BtAddress::from(addr),
+            trans_id,
+            offset,
+            is_long,
+            handle,
+        );
+    }
 
-    #[btif_callback(WriteDescriptor)]
-    fn write_descriptor_cb(
+    fn request_write_characteristic_cb(
         &mut self,
         conn_id: i32,
-        status: i32,
-        handle: u16,
-        len: u16,
-        value: *const u8,
-    );
-
-    #[btif_callback(ExecuteWrite)]
-    fn execute_write_cb(&mut self, conn_id: i32, status: i32);
-
-    #[btif_callback(ReadRemoteRssi)]
-    fn read_remote_rssi_cb(&mut self, client_id: i32, addr: RawAddress, rssi: i32, status: i32);
-
-    #[btif_callback(ConfigureMtu)]
-    fn configure_mtu_cb(&mut self, conn_id: i32, status: i32, mtu: i32);
+        trans_id: i32,
+        addr: RawAddress,
+        handle: i32,
+        offset: i32,
+        need_rsp: bool,
+        is_prep: bool,
+        value: Vec<u8>,
+        len: usize,
+    ) {
+        let opcode = write_request_opcode(need_rsp, is_prep);
+        self.trace_att(&addr.to_string(), |trace, now| {
+            trace.record_request(now, AttPduDirection::Received, trans_id, opcode, handle, len)
+        });
 
-    #[btif_callback(Congestion)]
-    fn congestion_cb(&mut self, conn_id: i32, congested: bool);
+        if self.is_time_server_connection(conn_id) {
+            if need_rsp {
+                self.respond_server_request(
+                    conn_id,
+                    trans_id,
+                    handle,
+                    GattStatus::WriteNotPermit,
+                    &[],
+                );
+            }
+            return;
+        }
 
-    #[btif_callback(GetGattDb)]
-    fn get_gatt_db_cb(&mut self, conn_id: i32, elements: Vec<BtGattDbElement>, count: i32);
+        let server = self.server_context_map.get_server_by_conn_id_mut(conn_id);
+        if server.is_none() {
+            return;
+        }
 
-    #[btif_callback(PhyUpdated)]
-    fn phy_updated_cb(&mut self, conn_id: i32, tx_phy: u8, rx_phy: u8, status: u8);
+        let server = server.unwrap();
+        if need_rsp {
+            server.pending_requests.insert(trans_id, (conn_id, handle));
+        }
+        server.callback.on_characteristic_write_request(
+            BtAddress::from(addr),
+            trans_id,
+            offset,
+            len as i32,
+            is_prep,
+            need_rsp,
+            handle,
+            value,
+        );
+    }
 
-    #[btif_callback(ConnUpdated)]
-    fn conn_updated_cb(
+    fn request_write_descriptor_cb(
         &mut self,
         conn_id: i32,
-        interval: u16,
-        latency: u16,
-        timeout: u16,
-        status: u8,
-    );
-
-    #[btif_callback(ServiceChanged)]
-    fn service_changed_cb(&self, conn_id: i32);
+        trans_id: i32,
+        addr: RawAddress,
+        handle: i32,
+        offset: i32,
+        need_rsp: bool,
+        is_prep: bool,
+        value: Vec<u8>,
+        len: usize,
+    ) {
+        let opcode = write_request_opcode(need_rsp, is_prep);
+        self.trace_att(&addr.to_string(), |trace, now| {
+            trace.record_request(now, AttPduDirection::Received, trans_id, opcode, handle, len)
+        });
 
-    #[btif_callback(ReadPhy)]
-    fn read_phy_cb(&mut self, client_id: i32, addr: RawAddress, tx_phy: u8, rx_phy: u8, status: u8);
-}
+        if self.is_time_server_connection(conn_id) {
+            self.time_server_write(conn_id, trans_id, handle, need_rsp, &value);
+            return;
+        }
 
-impl BtifGattClientCallbacks for BluetoothGatt {
-    fn register_client_cb(&mut self, status: i32, client_id: i32, app_uuid: Uuid) {
-        self.context_map.set_client_id(&app_uuid.uu, client_id);
+        if let Some(server_id) = self.managed_descriptor_server(conn_id, handle) {
+            self.write_managed_descriptor(
+                server_id,
+                conn_id,
+                trans_id,
+                &addr.to_string(),
+                handle,
+                offset,
+                need_rsp,
+                is_prep,
+                &value,
+            );
+            return;
+        }
 
-        let client = self.context_map.get_by_uuid(&app_uuid.uu);
-        if client.is_none() {
-            warn!("Warning: Client not registered for UUID {:?}", app_uuid.uu);
+        let server = self.server_context_map.get_server_by_conn_id_mut(conn_id);
+        if server.is_none() {
             return;
         }
 
-        let callback = &client.unwrap().callback;
-        callback.on_client_registered(status, client_id);
+        let server = server.unwrap();
+        if need_rsp {
+            server.pending_requests.insert(trans_id, (conn_id, handle));
+        }
+        server.callback.on_descriptor_write_request(
+            BtAddress::from(addr),
+            trans_id,
+            offset,
+            len as i32,
+            is_prep,
+            need_rsp,
+            handle,
+            value,
+        );
     }
 
-    fn connect_cb(&mut self, conn_id: i32, status: i32, client_id: i32, addr: RawAddress) {
-        if status == 0 {
-            self.context_map.add_connection(client_id, conn_id, &addr.to_string());
-        }
+    fn request_exec_write_cb(
+        &mut self,
+        conn_id: i32,
+        trans_id: i32,
+        addr: RawAddress,
+        exec_write: i32,
+    ) {
+        self.trace_att(&addr.to_string(), |trace, now| {
+            trace.record_request(
+                now,
+                AttPduDirection::Received,
+                trans_id,
+                ATT_EXECUTE_WRITE_REQ,
+                0,
+                0,
+            )
+        });
 
-        let client = self.context_map.get_by_client_id(client_id);
-        if client.is_none() {
+        let server = self.server_context_map.get_server_by_conn_id_mut(conn_id);
+        if server.is_none() {
             return;
         }
 
-        client.unwrap().callback.on_client_connection_state(
-            status,
-            client_id,
-            match GattStatus::from_i32(status) {
-                None => false,
-                Some(gatt_status) => gatt_status == GattStatus::Success,
-            },
-            addr.to_string(),
-        );
+        // The execute write response does not carry an attribute value.
+        let server = server.unwrap();
+        server.pending_requests.insert(trans_id, (conn_id, 0));
+        server.callback.on_execute_write(BtAddress::from(addr), trans_id, exec_write != 0);
     }
 
-    fn disconnect_cb(&mut self, conn_id: i32, status: i32, client_id: i32, addr: RawAddress) {
-        self.context_map.remove_connection(client_id, conn_id);
-        let client = self.context_map.get_by_client_id(client_id);
-        if client.is_none() {
+    fn indication_sent_cb(&mut self, conn_id: i32, status: i32) {
+        let address = self.server_context_map.get_address_by_conn_id(conn_id);
+        if address.is_none() {
             return;
         }
 
-        client.unwrap().callback.on_client_connection_state(
-            status,
-            client_id,
-            match GattStatus::from_i32(status) {
-                None => false,
-                Some(gatt_status) => gatt_status == GattStatus::Success,
-            },
-            addr.to_string(),
-        );
-    }
+        let server = self.server_context_map.get_server_by_conn_id(conn_id);
+        if server.is_none() {
+            return;
+        }
 
-    fn search_complete_cb(&mut self, conn_id: i32, _status: i32) {
-        // Gatt DB is ready!
-        self.gatt.as_ref().unwrap().client.get_gatt_db(conn_id);
+        server.unwrap().callback.on_notification_sent(callback_address(&address.unwrap()), status);
+        self.send_next_notification(conn_id);
     }
 
-    fn register_for_notification_cb(
-        &mut self,
-        _conn_id: i32,
-        _registered: i32,
-        _status: i32,
-        _handle: u16,
-    ) {
-        // No-op.
+    fn server_congestion_cb(&mut self, conn_id: i32, congested: bool) {
+        let conn = match self.server_context_map.get_connection_mut(conn_id) {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        conn.is_congested = congested;
+        if !congested {
+            self.send_next_notification(conn_id);
+        }
     }
 
-    fn notify_cb(&mut self, conn_id: i32, data: BtGattNotifyParams) {
-        let client = self.context_map.get_client_by_conn_id(conn_id);
-        if client.is_none() {
+    fn mtu_changed_cb(&mut self, conn_id: i32, mtu: i32) {
+        let address = self.server_context_map.get_address_by_conn_id(conn_id);
+        if address.is_none() {
             return;
         }
 
-        client.unwrap().callback.on_notify(
-            RawAddress { val: data.bda.address }.to_string(),
-            data.handle as i32,
-            data.value[0..data.len as usize].to_vec(),
-        );
+        let server = self.server_context_map.get_server_by_conn_id(conn_id);
+        if server.is_none() {
+            return;
+        }
+
+        server.unwrap().callback.on_mtu_changed(callback_address(&address.unwrap()), mtu);
     }
 
-    fn read_characteristic_cb(&mut self, conn_id: i32, status: i32, data: BtGattReadParams) {
-        let address = self.context_map.get_address_by_conn_id(conn_id);
+    fn server_phy_updated_cb(&mut self, conn_id: i32, tx_phy: u8, rx_phy: u8, status: u8) {
+        let address = self.server_context_map.get_address_by_conn_id(conn_id);
         if address.is_none() {
             return;
         }
 
-        let client = self.context_map.get_client_by_conn_id(conn_id);
-        if client.is_none() {
+        let server = self.server_context_map.get_server_by_conn_id(conn_id);
+        if server.is_none() {
             return;
         }
 
-        client.unwrap().callback.on_characteristic_read(
-            address.unwrap().to_string(),
-            status,
-            data.handle as i32,
-            data.value.value[0..data.value.len as usize].to_vec(),
+        server.unwrap().callback.on_phy_update(
+            callback_address(&address.unwrap()),
+            LePhy::from_u8(tx_phy).unwrap(),
+            LePhy::from_u8(rx_phy).unwrap(),
+            GattStatus::from_u8(status).unwrap(),
         );
     }
 
-    fn write_characteristic_cb(
+    fn server_conn_updated_cb(
         &mut self,
         conn_id: i32,
-        mut status: i32,
-        handle: u16,
-        _len: u16,
-        _value: *const u8,
+        interval: u16,
+        latency: u16,
+        timeout: u16,
+        status: u8,
     ) {
-        let address = self.context_map.get_address_by_conn_id(conn_id);
+        let address = self.server_context_map.get_address_by_conn_id(conn_id);
         if address.is_none() {
             return;
         }
 
-        // TODO(b/200070162): Design how to handle concurrent write characteristic to the same
-        // peer.
+        let server = self.server_context_map.get_server_by_conn_id(conn_id);
+        if server.is_none() {
+            return;
+        }
+
+        server.unwrap().callback.on_connection_updated(
+            callback_address(&address.unwrap()),
+            interval as i32,
+            latency as i32,
+            timeout as i32,
+            status as i32,
+        );
+    }
+}
+
+#[btif_callbacks_dispatcher(BluetoothGatt, dispatch_le_scanner_callbacks, GattScannerCallbacks)]
+pub(crate) trait BtifGattScannerCallbacks {
+    #[btif_callback(OnScannerRegistered)]
+    fn on_scanner_registered(&mut self, uuid: Uuid, scanner_id: u8, status: u8);
+
+    #[btif_callback(OnScanResult)]
+    fn on_scan_result(
+        &mut self,
+        event_type: u16,
+        addr_type: u8,
+        address: RawAddress,
+        primary_phy: u8,
+        secondary_phy: u8,
+        advertising_sid: u8,
+        tx_power: i8,
+        rssi: i8,
+        periodic_adv_int: u16,
+        adv_data: Vec<u8>,
+    );
+
+    #[btif_callback(OnTrackAdvFoundLost)]
+    fn on_track_adv_found_lost(&mut self, track_info: AdvertisingTrackInfo);
+
+    #[btif_callback(OnBatchScanReports)]
+    fn on_batch_scan_reports(
+        &mut self,
+        client_if: i32,
+        status: i32,
+        report_format: i32,
+        num_records: i32,
+        data: Vec<u8>,
+    );
+
+    #[btif_callback(OnBatchScanThresholdCrossed)]
+    fn on_batch_scan_threshold_crossed(&mut self, client_if: i32);
+}
+
+impl BtifGattScannerCallbacks for BluetoothGatt {
+    fn on_scanner_registered(&mut self, uuid: Uuid, scanner_id: u8, status: u8) {
+        let scanner = match self.scanners.get_mut(&uuid.uu) {
+            Some(s) => s,
+            None => {
+                warn!("Warning: Scanner not registered for UUID {:?}", uuid.uu);
+                // The scanner was removed while registering, its ID is given back.
+                if status == 0 {
+                    self.gatt.as_mut().unwrap().scanner.unregister(scanner_id);
+                }
+                return;
+            }
+        };
+
+        if status == 0 {
+            scanner.scanner_id = Some(scanner_id);
+        }
+        scanner.callback.on_scanner_registered(status.into(), scanner_id.into());
+
+        if status != 0 {
+            self.scanners.remove(&uuid.uu);
+        }
+    }
+
+    fn on_scan_result(
+        &mut self,
+        event_type: u16,
+        addr_type: u8,
+        address: RawAddress,
+        primary_phy: u8,
+        secondary_phy: u8,
+        advertising_sid: u8,
+        tx_power: i8,
+        rssi: i8,
+        periodic_adv_int: u16,
+        adv_data: Vec<u8>,
+    ) {
+        let address = BtAddress::from(address);
+        let identity_address = self.identity_of(&address.to_string());
+        let is_bonded = !identity_address.is_empty() && self.is_bonded(&identity_address);
+        let calibrated_rssi = i32::from(rssi) + self.rssi_calibration_offset;
+        // Only parsed for the scanners receiving the parsed record.
+        let mut scan_record: Option<ScanRecord> = None;
+        let mut tracks_matches = false;
+
+        let checker = self.scan_permission_checker.as_deref();
+        for scanner in self.scanners.values_mut().filter(|s| s.is_scanning) {
+            let scanner_id = match scanner.scanner_id {
+                Some(id) => id as i32,
+                None => continue,
+            };
+
+            if !scanner.address_filter.permits(&address) {
+                continue;
+            }
+
+            // The controller also scans on the PHYs requested by the other scanners.
+            if scanner.scan_phys & scan_phy_bit(primary_phy) == 0 {
+                continue;
+            }
 
-        let client = self.context_map.get_client_by_conn_id_mut(conn_id);
-        if client.is_none() {
-            return;
-        }
+            let sender = scanner.callback.get_remote_id();
+            if !check_scan_permission(checker, &sender, scanner_id, &mut scanner.scan_permitted) {
+                continue;
+            }
 
-        let client = client.unwrap();
+            // Results are filtered in software even when the filters are offloaded, since the
+            // controller filters are shared by all the scanners.
+            if !scanner.filters.is_empty()
+                && !scanner.filters.iter().any(|f| f.matches(&address, &adv_data, rssi.into()))
+            {
+                continue;
+            }
 
-        if client.is_congested {
-            if status == GattStatus::Congested.to_i32().unwrap() {
-                status = GattStatus::Success.to_i32().unwrap();
+            // Scanners without a scan match program receive every result.
+            if let Some(program) = self.scan_match_programs.get(&scanner_id) {
+                if !program.matches(&adv_data, rssi) {
+                    continue;
+                }
             }
 
-            client.congestion_queue.push((address.unwrap().to_string(), status, handle as i32));
-            return;
+            // Subscribed scanners only receive the manufacturer data they asked for.
+            if !scanner.manufacturer_data_subscriptions.is_empty() {
+                for subscription in scanner.manufacturer_data_subscriptions.iter() {
+                    if let Some(data) = subscription.find_match(&adv_data) {
+                        scanner.callback.on_manufacturer_data_found(
+                            scanner_id,
+                            subscription.id,
+                            address,
+                            rssi.into(),
+                            data.to_vec(),
+                        );
+                    }
+                }
+                continue;
+            }
+
+            let result = ScanResult {
+                address,
+                addr_type,
+                event_type,
+                primary_phy,
+                secondary_phy,
+                primary_le_phy: LePhy::from_u8(primary_phy).unwrap_or(LePhy::Invalid),
+                secondary_le_phy: LePhy::from_u8(secondary_phy).unwrap_or(LePhy::Invalid),
+                advertising_sid,
+                identity_address: identity_address.clone(),
+                is_bonded,
+                tx_power: tx_power.into(),
+                rssi: rssi.into(),
+                smoothed_rssi: scanner.rssi_smoother.update(&address, calibrated_rssi),
+                periodic_adv_int,
+                adv_data: if scanner.record_delivery.includes_raw() {
+                    adv_data.clone()
+                } else {
+                    vec![]
+                },
+                scan_record: if scanner.record_delivery.includes_parsed() {
+                    scan_record.get_or_insert_with(|| ScanRecord::from_adv_data(&adv_data)).clone()
+                } else {
+                    ScanRecord::default()
+                },
+            };
+
+            match scanner.match_tracker.as_mut() {
+                Some(tracker) => {
+                    // The sightings of the devices not found yet expire as well.
+                    tracks_matches = true;
+                    if tracker.update(Instant::now(), result.clone())
+                        && scanner.callback_type.reports_first_match()
+                    {
+                        scanner.deliver_result(result, self.tx.clone());
+                    }
+                }
+                None => scanner.deliver_result(result, self.tx.clone()),
+            }
         }
 
-        client.callback.on_characteristic_write(
-            address.unwrap().to_string(),
-            status,
-            handle as i32,
-        );
+        if tracks_matches {
+            self.schedule_match_lost_check();
+        }
     }
 
-    fn read_descriptor_cb(&mut self, conn_id: i32, status: i32, data: BtGattReadParams) {
-        let address = self.context_map.get_address_by_conn_id(conn_id);
-        if address.is_none() {
+    fn on_track_adv_found_lost(&mut self, track_info: AdvertisingTrackInfo) {
+        let scanner_id: i32 = track_info.scanner_id.into();
+        // Controllers not tracking advertisers, or without tracking entries left, report it with
+        // an event without an advertiser.
+        if track_info.advertiser_address.address == [0; 6] {
+            warn!(
+                "Advertisers cannot be tracked for scanner {}, tracking them in software",
+                scanner_id
+            );
+            self.apcf_tracking_supported = false;
+            self.remove_offloaded_scan_filters(scanner_id);
+            self.offload_scan_filters(scanner_id);
             return;
         }
 
-        let client = self.context_map.get_client_by_conn_id(conn_id);
-        if client.is_none() {
+        let address = BtAddress::from(RawAddress { val: track_info.advertiser_address.address });
+        let identity_address = self.identity_of(&address.to_string());
+        let is_bonded = !identity_address.is_empty() && self.is_bonded(&identity_address);
+        let calibrated_rssi = i32::from(track_info.rssi) + self.rssi_calibration_offset;
+
+        let checker = self.scan_permission_checker.as_deref();
+        let scanner = match self.scanners.values_mut().find(|s| {
+            s.scanner_id == Some(track_info.scanner_id)
+                && s.filter_indexes.contains(&track_info.filter_index)
+        }) {
+            Some(s) => s,
+            None => return,
+        };
+
+        if !scanner.is_scanning
+            || !scanner.match_tracker.as_ref().map_or(false, |t| t.offloaded)
+            || !scanner.address_filter.permits(&address)
+        {
             return;
         }
 
-        client.unwrap().callback.on_descriptor_read(
-            address.unwrap().to_string(),
-            status,
-            data.handle as i32,
-            data.value.value[0..data.value.len as usize].to_vec(),
-        );
+        let sender = scanner.callback.get_remote_id();
+        if !check_scan_permission(checker, &sender, scanner_id, &mut scanner.scan_permitted) {
+            return;
+        }
+
+        match track_info.advertiser_state {
+            APCF_ADVERTISER_FOUND => {
+                let mut adv_data = track_info.adv_packet;
+                adv_data.extend(track_info.scan_response);
+                let result = ScanResult {
+                    address,
+                    addr_type: track_info.advertiser_address_type,
+                    identity_address,
+                    is_bonded,
+                    tx_power: (track_info.tx_power as i8).into(),
+                    rssi: track_info.rssi.into(),
+                    smoothed_rssi: scanner.rssi_smoother.update(&address, calibrated_rssi),
+                    scan_record: if scanner.record_delivery.includes_parsed() {
+                        ScanRecord::from_adv_data(&adv_data)
+                    } else {
+                        ScanRecord::default()
+                    },
+                    adv_data: if scanner.record_delivery.includes_raw() {
+                        adv_data
+                    } else {
+                        vec![]
+                    },
+                    ..Default::default()
+                };
+
+                let found = scanner
+                    .match_tracker
+                    .as_mut()
+                    .map_or(false, |t| t.found(Instant::now(), result.clone()));
+                if found && scanner.callback_type.reports_first_match() {
+                    scanner.deliver_result(result, self.tx.clone());
+                }
+            }
+            APCF_ADVERTISER_LOST => {
+                let lost = scanner.match_tracker.as_mut().and_then(|t| t.lose(&address));
+                match lost {
+                    Some(result) if scanner.callback_type.reports_match_lost() => {
+                        // The device is reported lost after the results found before.
+                        scanner.flush_results();
+                        scanner.callback.on_scan_result_lost(result);
+                    }
+                    _ => (),
+                }
+            }
+            state => warn!("Unknown state {} of tracked advertiser {}", state, address),
+        }
     }
 
-    fn write_descriptor_cb(
+    fn on_batch_scan_reports(
         &mut self,
-        conn_id: i32,
+        client_if: i32,
         status: i32,
-        handle: u16,
-        _len: u16,
-        _value: *const u8,
+        report_format: i32,
+        num_records: i32,
+        data: Vec<u8>,
     ) {
-        let address = self.context_map.get_address_by_conn_id(conn_id);
-        if address.is_none() {
-            return;
+        let results = parse_batch_scan_records(report_format, num_records, &data);
+        if results.len() != num_records.max(0) as usize {
+            warn!("Batch scan report has {} valid records of {}", results.len(), num_records);
         }
 
-        let client = self.context_map.get_client_by_conn_id(conn_id);
-        if client.is_none() {
-            return;
+        let checker = self.scan_permission_checker.as_deref();
+        let scanner = self
+            .scanners
+            .values_mut()
+            .find(|s| s.scanner_id.map(|id| id as i32) == Some(client_if));
+        if let Some(scanner) = scanner {
+            let sender = scanner.callback.get_remote_id();
+            if check_scan_permission(checker, &sender, client_if, &mut scanner.scan_permitted) {
+                scanner.callback.on_batch_scan_reports(client_if, status, results);
+            }
         }
-
-        client.unwrap().callback.on_descriptor_write(
-            address.unwrap().to_string(),
-            status,
-            handle as i32,
-        );
     }
 
-    fn execute_write_cb(&mut self, conn_id: i32, status: i32) {
-        let address = self.context_map.get_address_by_conn_id(conn_id);
-        if address.is_none() {
-            return;
-        }
+    fn on_batch_scan_threshold_crossed(&mut self, client_if: i32) {
+        let mode = match self.batch_scan {
+            Some((owner, mode)) if owner == client_if => mode,
+            _ => return,
+        };
 
-        let client = self.context_map.get_client_by_conn_id(conn_id);
-        if client.is_none() {
-            return;
+        if let Some(scanner) = self.find_scanner_by_id(client_if) {
+            scanner.callback.on_batch_scan_threshold_crossed(client_if);
         }
 
-        client.unwrap().callback.on_execute_write(address.unwrap().to_string(), status);
+        // Drain the storage so that the client gets the results without asking for them.
+        for read_mode in [BatchScanMode::Truncated, BatchScanMode::Full].iter() {
+            if mode == *read_mode || mode == BatchScanMode::TruncatedAndFull {
+                let _ = self.batch_scan_read_reports(client_if, *read_mode);
+            }
+        }
     }
+}
 
-    fn read_remote_rssi_cb(&mut self, client_id: i32, addr: RawAddress, rssi: i32, status: i32) {
-        let client = self.context_map.get_by_client_id(client_id);
-        if client.is_none() {
-            return;
-        }
+#[btif_callbacks_dispatcher(
+    BluetoothGatt,
+    dispatch_le_scanner_inband_callbacks,
+    GattScannerInbandCallbacks
+)]
+pub(crate) trait BtifGattScannerInbandCallbacks {
+    #[btif_callback(StartSyncCallback)]
+    fn start_sync_cb(
+        &mut self,
+        status: u8,
+        sync_handle: u16,
+        advertising_sid: u8,
+        addr_type: u8,
+        address: RawAddress,
+        phy: u8,
+        interval: u16,
+    );
 
-        client.unwrap().callback.on_read_remote_rssi(addr.to_string(), rssi, status);
-    }
+    #[btif_callback(SyncReportCallback)]
+    fn sync_report_cb(
+        &mut self,
+        sync_handle: u16,
+        tx_power: i8,
+        rssi: i8,
+        status: u8,
+        data: Vec<u8>,
+    );
 
-    fn configure_mtu_cb(&mut self, conn_id: i32, status: i32, mtu: i32) {
-        let client = self.context_map.get_client_by_conn_id(conn_id);
-        if client.is_none() {
-            return;
-        }
+    #[btif_callback(SyncLostCallback)]
+    fn sync_lost_cb(&mut self, sync_handle: u16);
 
-        let addr = self.context_map.get_address_by_conn_id(conn_id);
-        if addr.is_none() {
-            return;
-        }
+    #[btif_callback(SyncTransferCallback)]
+    fn sync_transfer_cb(&mut self, status: u8, address: RawAddress);
 
-        client.unwrap().callback.on_configure_mtu(addr.unwrap(), mtu, status);
-    }
+    #[btif_callback(MsftCommandCallback)]
+    fn msft_command_cb(&mut self, opcode: u16, return_params: Vec<u8>);
+}
 
-    fn congestion_cb(&mut self, conn_id: i32, congested: bool) {
-        let client = self.context_map.get_client_by_conn_id_mut(conn_id);
-        if client.is_none() {
-            return;
+impl BtifGattScannerInbandCallbacks for BluetoothGatt {
+    fn start_sync_cb(
+        &mut self,
+        status: u8,
+        sync_handle: u16,
+        advertising_sid: u8,
+        addr_type: u8,
+        address: RawAddress,
+        phy: u8,
+        interval: u16,
+    ) {
+        let address = BtAddress::from(address);
+        let index = self
+            .periodic_syncs
+            .iter()
+            .position(|s| s.handle.is_none() && s.sid == advertising_sid && s.address == address);
+
+        let index = match index {
+            Some(i) => i,
+            None => match self.past_receivers.remove(&address) {
+                // The sync was transferred by a peer.
+                Some(callback) if status == 0 => {
+                    self.periodic_syncs.push(PeriodicSync {
+                        sid: advertising_sid,
+                        address,
+                        handle: None,
+                        callback,
+                    });
+                    self.periodic_syncs.len() - 1
+                }
+                _ => {
+                    warn!("Unexpected sync to set {} of {}", advertising_sid, address);
+                    return;
+                }
+            },
+        };
+
+        let sync = &mut self.periodic_syncs[index];
+        sync.callback.on_sync_started(
+            sync_handle.into(),
+            status.into(),
+            advertising_sid.into(),
+            addr_type.into(),
+            address,
+            phy.into(),
+            interval.into(),
+        );
+
+        if status == 0 {
+            sync.handle = Some(sync_handle);
+        } else {
+            self.periodic_syncs.remove(index);
         }
+    }
 
-        let client = client.unwrap();
+    fn sync_report_cb(
+        &mut self,
+        sync_handle: u16,
+        tx_power: i8,
+        rssi: i8,
+        status: u8,
+        data: Vec<u8>,
+    ) {
+        if let Some(sync) = self.find_sync_by_handle(sync_handle) {
+            sync.callback.on_sync_report(
+                sync_handle.into(),
+                tx_power.into(),
+                rssi.into(),
+                status.into(),
+                data,
+            );
+        }
+    }
 
-        client.is_congested = congested;
-        if !client.is_congested {
-            for callback in client.congestion_queue.iter() {
-                client.callback.on_characteristic_write(callback.0.clone(), callback.1, callback.2);
+    fn sync_lost_cb(&mut self, sync_handle: u16) {
+        let address = match self.find_sync_by_handle(sync_handle) {
+            Some(sync) => {
+                sync.callback.on_sync_lost(sync_handle.into());
+                Some(sync.address)
             }
-            client.congestion_queue.clear();
+            None => None,
+        };
+
+        // A transfer of the lost sync is not awaited anymore.
+        if let Some(address) = address {
+            self.past_receivers.remove(&address);
         }
+
+        self.periodic_syncs.retain(|s| s.handle != Some(sync_handle));
+        self.pending_sync_transfers.retain(|(_, h)| *h != sync_handle);
     }
 
-    fn get_gatt_db_cb(&mut self, conn_id: i32, elements: Vec<BtGattDbElement>, _count: i32) {
-        let address = self.context_map.get_address_by_conn_id(conn_id);
-        if address.is_none() {
-            return;
-        }
+    fn sync_transfer_cb(&mut self, status: u8, address: RawAddress) {
+        let address = BtAddress::from(address);
+        let index = match self.pending_sync_transfers.iter().position(|(a, _)| *a == address) {
+            Some(i) => i,
+            None => {
+                // Transfers done by `transfer_set_info` are not tracked.
+                return;
+            }
+        };
 
-        let client = self.context_map.get_client_by_conn_id(conn_id);
-        if client.is_none() {
-            return;
+        let (_, handle) = self.pending_sync_transfers.remove(index).unwrap();
+        if let Some(sync) = self.find_sync_by_handle(handle) {
+            sync.callback.on_sync_transferred(address, status.into());
         }
+    }
 
-        let mut db_out: Vec<BluetoothGattService> = vec![];
+    fn msft_command_cb(&mut self, opcode: u16, return_params: Vec<u8>) {
+        let request = match self.pending_msft_requests.pop_front() {
+            Some(r) => r,
+            None => {
+                warn!("Unexpected MSFT command complete with opcode {:#06x}", opcode);
+                return;
+            }
+        };
 
-        for elem in elements {
-            match GattDbElementType::from_u32(elem.type_).unwrap() {
-                GattDbElementType::PrimaryService | GattDbElementType::SecondaryService => {
-                    db_out.push(BluetoothGattService::new(
-                        elem.uuid.uu,
-                        elem.id as i32,
-                        elem.type_ as i32,
-                    ));
-                    // TODO(b/200065274): Mark restricted services.
-                }
+        let succeeded = match msft::command_result(&return_params) {
+            Some((subcommand, succeeded)) if subcommand == request.subcommand() => succeeded,
+            _ => {
+                warn!("Unexpected MSFT result {:?} for {:?}", return_params, request);
+                false
+            }
+        };
 
-                GattDbElementType::Characteristic => {
-                    match db_out.last_mut() {
-                        Some(s) => s.characteristics.push(BluetoothGattCharacteristic::new(
-                            elem.uuid.uu,
-                            elem.id as i32,
-                            elem.properties as i32,
-                            0,
-                        )),
-                        None => {
-                            // TODO(b/193685325): Log error.
-                        }
-                    }
-                    // TODO(b/200065274): Mark restricted characteristics.
+        match request {
+            MsftRequest::ReadSupportedFeatures => {
+                let opcode = match self.msft_support {
+                    MsftSupport::Detecting(opcode) => opcode,
+                    _ => return,
+                };
+
+                if succeeded && msft::supports_le_monitor(&return_params) {
+                    debug!("Controller supports MSFT monitors with opcode {:#06x}", opcode);
+                    self.msft_support = MsftSupport::Supported(opcode);
+                    self.reoffload_scan_filters();
+                } else {
+                    self.msft_support = MsftSupport::Unsupported;
                 }
-
-                GattDbElementType::Descriptor => {
-                    match db_out.last_mut() {
-                        Some(s) => match s.characteristics.last_mut() {
-                            Some(c) => c.descriptors.push(BluetoothGattDescriptor::new(
-                                elem.uuid.uu,
-                                elem.id as i32,
-                                0,
-                            )),
-                            None => {
-                                // TODO(b/193685325): Log error.
-                            }
-                        },
-                        None => {
-                            // TODO(b/193685325): Log error.
-                        }
+            }
+            MsftRequest::MonitorAdvertisement(id) => {
+                let handle = if succeeded { msft::monitor_handle(&return_params) } else { None };
+                let scanner = self.scanners.values_mut().find(|s| s.msft_pending.contains(&id));
+
+                match (scanner, handle) {
+                    (Some(scanner), Some(handle)) => {
+                        scanner.msft_pending.retain(|pending| *pending != id);
+                        scanner.msft_handles.push(handle);
                     }
-                    // TODO(b/200065274): Mark restricted descriptors.
-                }
-
-                GattDbElementType::IncludedService => {
-                    match db_out.last_mut() {
-                        Some(s) => {
-                            s.included_services.push(BluetoothGattService::new(
-                                elem.uuid.uu,
-                                elem.id as i32,
-                                elem.type_ as i32,
-                            ));
-                        }
-                        None => {
-                            // TODO(b/193685325): Log error.
+                    (Some(scanner), None) => {
+                        // Filter in software until APCF takes over.
+                        let scanner_id: i32 = scanner.scanner_id.unwrap_or_default().into();
+                        warn!("Failed to add an MSFT monitor for scanner {}", scanner_id);
+                        self.remove_offloaded_scan_filters(scanner_id);
+                        self.offload_apcf_filters(scanner_id);
+                    }
+                    (None, Some(handle)) => {
+                        // The scanner stopped while the monitor was being added.
+                        if let Some(opcode) = self.msft_opcode() {
+                            self.send_msft_command(
+                                opcode,
+                                MsftRequest::CancelMonitorAdvertisement,
+                                msft::cancel_monitor_advertisement(handle),
+                            );
                         }
                     }
+                    (None, None) => {}
+                }
+                self.update_scan();
+            }
+            _ => {
+                if !succeeded {
+                    warn!("MSFT command {:?} failed", request);
                 }
             }
         }
-
-        client.unwrap().callback.on_search_complete(address.unwrap().to_string(), db_out, 0);
     }
+}
 
-    fn phy_updated_cb(&mut self, conn_id: i32, tx_phy: u8, rx_phy: u8, status: u8) {
-        let client = self.context_map.get_client_by_conn_id(conn_id);
-        if client.is_none() {
-            return;
-        }
+#[btif_callbacks_dispatcher(BluetoothGatt, dispatch_le_adv_callbacks, GattAdvCallbacks)]
+pub(crate) trait BtifGattAdvCallbacks {
+    #[btif_callback(OnAdvertisingSetStarted)]
+    fn on_advertising_set_started(
+        &mut self,
+        reg_id: i32,
+        advertiser_id: u8,
+        tx_power: i8,
+        status: u8,
+    );
 
-        let address = self.context_map.get_address_by_conn_id(conn_id);
-        if address.is_none() {
-            return;
-        }
+    #[btif_callback(OnAdvertisingEnabled)]
+    fn on_advertising_enabled(&mut self, advertiser_id: u8, enable: bool, status: u8);
 
-        client.unwrap().callback.on_phy_update(
-            address.unwrap(),
-            LePhy::from_u8(tx_phy).unwrap(),
-            LePhy::from_u8(rx_phy).unwrap(),
-            GattStatus::from_u8(status).unwrap(),
-        );
-    }
+    #[btif_callback(OnAdvertisingDataSet)]
+    fn on_advertising_data_set(&mut self, advertiser_id: u8, status: u8);
 
-    fn read_phy_cb(
+    #[btif_callback(OnScanResponseDataSet)]
+    fn on_scan_response_data_set(&mut self, advertiser_id: u8, status: u8);
+
+    #[btif_callback(OnAdvertisingParametersUpdated)]
+    fn on_advertising_parameters_updated(&mut self, advertiser_id: u8, tx_power: i8, status: u8);
+
+    #[btif_callback(OnOwnAddressRead)]
+    fn on_own_address_read(&mut self, advertiser_id: u8, address_type: u8, address: RawAddress);
+
+    #[btif_callback(OnOwnAddressChanged)]
+    fn on_own_address_changed(&mut self, advertiser_id: u8, address_type: u8, address: RawAddress);
+}
+
+fn advertising_status(status: u8) -> AdvertisingStatus {
+    AdvertisingStatus::from_u8(status).unwrap_or(AdvertisingStatus::InternalError)
+}
+
+impl BtifGattAdvCallbacks for BluetoothGatt {
+    fn on_advertising_set_started(
         &mut self,
-        client_id: i32,
-        addr: RawAddress,
-        tx_phy: u8,
-        rx_phy: u8,
+        reg_id: i32,
+        advertiser_id: u8,
+        tx_power: i8,
         status: u8,
     ) {
-        let client = self.context_map.get_by_client_id(client_id);
-        if client.is_none() {
-            return;
+        let status = advertising_status(status);
+        let index = match self.advertising_sets.iter().position(|s| s.reg_id == reg_id) {
+            Some(i) => i,
+            None => {
+                // The set was stopped while resuming.
+                if status == AdvertisingStatus::Success {
+                    self.gatt.as_mut().unwrap().advertiser.unregister(advertiser_id);
+                } else {
+                    warn!("Unknown advertising set registration {}", reg_id);
+                }
+                return;
+            }
+        };
+
+        let set = &mut self.advertising_sets[index];
+        let previous_state = set.state;
+        match (previous_state, status) {
+            (_, AdvertisingStatus::Success) => {
+                set.state = AdvertisingSetState::Active(advertiser_id);
+                set.since = Instant::now();
+                // The controller may select another power each time it starts the set again.
+                if set.update_tx_power(tx_power.into()) {
+                    set.callback.on_tx_power_changed(reg_id, tx_power.into());
+                }
+                if set.address_rotation_interval_ms != 0 {
+                    self.gatt.as_mut().unwrap().advertiser.set_address_rotation_interval(
+                        advertiser_id,
+                        set.address_rotation_interval_ms,
+                    );
+                }
+                if set.enabled && !set.parameters.is_anonymous {
+                    self.pending_own_address_reads.insert(advertiser_id);
+                    self.gatt.as_mut().unwrap().advertiser.get_own_address(advertiser_id);
+                }
+                if previous_state == AdvertisingSetState::Starting {
+                    if !set.enabled {
+                        self.gatt.as_mut().unwrap().advertiser.enable(advertiser_id, false, 0, 0);
+                    }
+                    set.callback.on_advertising_set_started(
+                        set.client_reg_id,
+                        reg_id,
+                        tx_power.into(),
+                        status,
+                    );
+                } else {
+                    if !set.enabled {
+                        self.gatt.as_mut().unwrap().advertiser.enable(advertiser_id, false, 0, 0);
+                    }
+                    if previous_state == AdvertisingSetState::Restoring {
+                        set.callback.on_advertising_set_restored(reg_id, tx_power.into(), status);
+                    } else {
+                        set.callback.on_advertising_set_resumed(reg_id);
+                    }
+                }
+            }
+            (AdvertisingSetState::Resuming, _) => {
+                // Waits for the next rotation.
+                set.state = AdvertisingSetState::Suspended;
+            }
+            (_, AdvertisingStatus::TooManyAdvertisers)
+                if longest_active_set(&self.advertising_sets).is_some() =>
+            {
+                // Makes room for the new set instead of failing.
+                self.suspend_advertising_set(longest_active_set(&self.advertising_sets).unwrap());
+                self.start_advertising_in_controller(index);
+            }
+            (AdvertisingSetState::Restoring, _) => {
+                let set = self.advertising_sets.remove(index);
+                warn!("Failed to restore advertising set {}: {:?}", reg_id, status);
+                set.callback.on_advertising_set_restored(reg_id, tx_power.into(), status);
+                self.save_advertising_sets();
+            }
+            _ => {
+                // A set that failed to start is not kept.
+                let set = self.advertising_sets.remove(index);
+                self.metrics.increment(format!("adv.start_failure.{:?}", status));
+                set.callback.on_advertising_set_started(
+                    set.client_reg_id,
+                    reg_id,
+                    tx_power.into(),
+                    status,
+                );
+                self.save_advertising_sets();
+            }
         }
 
-        client.unwrap().callback.on_phy_read(
-            addr.to_string(),
-            LePhy::from_u8(tx_phy).unwrap(),
-            LePhy::from_u8(rx_phy).unwrap(),
-            GattStatus::from_u8(status).unwrap(),
-        );
+        self.schedule_advertising_rotation();
+        self.update_le_activity();
     }
 
-    fn conn_updated_cb(
-        &mut self,
-        conn_id: i32,
-        interval: u16,
-        latency: u16,
-        timeout: u16,
-        status: u8,
-    ) {
-        let client = self.context_map.get_client_by_conn_id(conn_id);
-        if client.is_none() {
-            return;
+    fn on_advertising_enabled(&mut self, advertiser_id: u8, enable: bool, status: u8) {
+        // The sets the controller stops at the end of their duration or of their advertising
+        // events are reported as disabled with the status of the termination.
+        let terminated =
+            if enable { None } else { AdvertisingTerminationReason::from_status(status) };
+        if let Some(reason) = terminated {
+            return self.on_advertising_set_terminated(advertiser_id, reason);
         }
 
-        let address = self.context_map.get_address_by_conn_id(conn_id);
-        if address.is_none() {
-            return;
+        let status = advertising_status(status);
+        let reg_id = match self.find_advertising_set_by_handle(advertiser_id) {
+            Some(set) => set.reg_id,
+            None => return,
+        };
+        // The sets disarmed for suspend stay enabled for their clients, unless they fail to be
+        // enabled again.
+        if let Some(index) = self.disarmed_advertising_sets.iter().position(|id| *id == reg_id) {
+            if !enable {
+                return self.advertising_set_disarmed(reg_id);
+            }
+            self.disarmed_advertising_sets.remove(index);
+            if status == AdvertisingStatus::Success {
+                return;
+            }
+            if let Some(set) = self.find_advertising_set_by_handle(advertiser_id) {
+                set.enabled = false;
+            }
         }
 
-        client.unwrap().callback.on_connection_updated(
-            address.unwrap(),
-            interval as i32,
-            latency as i32,
-            timeout as i32,
-            status as i32,
-        );
+        if let Some(set) = self.find_advertising_set_by_handle(advertiser_id) {
+            if status == AdvertisingStatus::Success {
+                set.enabled = enable;
+            }
+            set.callback.on_advertising_enabled(set.reg_id, enable, status);
+            self.update_le_activity();
+        }
     }
 
-    fn service_changed_cb(&self, conn_id: i32) {
-        let address = self.context_map.get_address_by_conn_id(conn_id);
-        if address.is_none() {
-            return;
+    fn on_advertising_data_set(&mut self, advertiser_id: u8, status: u8) {
+        if let Some(set) = self.find_advertising_set_by_handle(advertiser_id) {
+            set.callback.on_advertising_data_set(set.reg_id, advertising_status(status));
         }
+    }
 
-        let client = self.context_map.get_client_by_conn_id(conn_id);
-        if client.is_none() {
+    fn on_scan_response_data_set(&mut self, advertiser_id: u8, status: u8) {
+        if let Some(set) = self.find_advertising_set_by_handle(advertiser_id) {
+            set.callback.on_scan_response_data_set(set.reg_id, advertising_status(status));
+        }
+    }
+
+    fn on_advertising_parameters_updated(&mut self, advertiser_id: u8, tx_power: i8, status: u8) {
+        if let Some(set) = self.find_advertising_set_by_handle(advertiser_id) {
+            if let Some(sweep) = set.tx_power_sweep.as_ref() {
+                info!(
+                    "TX power sweep of advertising set {}: requested {} dBm, applied {} dBm ({:?})",
+                    set.reg_id,
+                    sweep.level(),
+                    tx_power,
+                    advertising_status(status)
+                );
+            }
+            set.callback.on_advertising_parameters_updated(
+                set.reg_id,
+                tx_power.into(),
+                advertising_status(status),
+            );
+            if advertising_status(status) == AdvertisingStatus::Success
+                && set.update_tx_power(tx_power.into())
+            {
+                set.callback.on_tx_power_changed(set.reg_id, tx_power.into());
+            }
+        }
+    }
+
+    fn on_own_address_read(&mut self, advertiser_id: u8, address_type: u8, address: RawAddress) {
+        // The reads of the stack are not reported to the client of the set.
+        if self.pending_own_address_reads.remove(&advertiser_id) {
+            let own_address_type = OwnAddressType::from_le_address(address_type, &address.val);
+            if let (true, Some(tx)) = (own_address_type.is_identity(), self.tx.clone()) {
+                tokio::spawn(async move {
+                    let message = Message::IdentityExposed(
+                        String::new(),
+                        LocalIdentity::LeAddress,
+                        own_address_type,
+                    );
+                    let _ = tx.send(message).await;
+                });
+            }
             return;
         }
 
-        client.unwrap().callback.on_service_changed(address.unwrap());
+        if let Some(set) = self.find_advertising_set_by_handle(advertiser_id) {
+            set.callback.on_own_address_read(set.reg_id, address_type.into(), address.to_string());
+        }
+    }
+
+    fn on_own_address_changed(&mut self, advertiser_id: u8, address_type: u8, address: RawAddress) {
+        if let Some(set) = self.find_advertising_set_by_handle(advertiser_id) {
+            set.callback.on_own_address_changed(
+                set.reg_id,
+                address_type.into(),
+                address.to_string(),
+            );
+        }
     }
 }
 
@@ -1387,55 +11352,96 @@ mod tests {
             _status: i32,
             _client_id: i32,
             _connected: bool,
-            _addr: String,
+            _addr: BtAddress,
         ) {
         }
 
         fn on_phy_update(
             &self,
-            _addr: String,
+            _addr: BtAddress,
             _tx_phy: LePhy,
             _rx_phy: LePhy,
             _status: GattStatus,
         ) {
         }
 
-        fn on_phy_read(&self, _addr: String, _tx_phy: LePhy, _rx_phy: LePhy, _status: GattStatus) {}
+        fn on_phy_read(
+            &self,
+            _addr: BtAddress,
+            _tx_phy: LePhy,
+            _rx_phy: LePhy,
+            _status: GattStatus,
+        ) {
+        }
 
         fn on_search_complete(
             &self,
-            _addr: String,
+            _addr: BtAddress,
             _services: Vec<BluetoothGattService>,
             _status: i32,
         ) {
         }
 
+        fn on_service_read(
+            &self,
+            _addr: BtAddress,
+            _service_uuid: Uuid128Bit,
+            _results: Vec<CharacteristicReadResult>,
+        ) {
+        }
+
+        fn on_conformance_report(&self, _addr: BtAddress, _issues: Vec<ConformanceIssue>) {}
+
+        fn on_get_gatt_db(&self, _addr: BtAddress, _services: Vec<BluetoothGattService>) {}
+
         fn on_characteristic_read(
             &self,
-            _addr: String,
+            _addr: BtAddress,
+            _status: i32,
+            _handle: i32,
+            _value: Vec<u8>,
+        ) {
+        }
+
+        fn on_characteristic_write(&self, _addr: BtAddress, _status: i32, _handle: i32) {}
+
+        fn on_characteristic_write_progress(
+            &self,
+            _addr: BtAddress,
+            _handle: i32,
+            _bytes_written: i32,
+            _total_bytes: i32,
+        ) {
+        }
+
+        fn on_execute_write(&self, _addr: BtAddress, _status: i32) {}
+
+        fn on_descriptor_read(
+            &self,
+            _addr: BtAddress,
             _status: i32,
             _handle: i32,
             _value: Vec<u8>,
         ) {
         }
 
-        fn on_characteristic_write(&self, _addr: String, _status: i32, _handle: i32) {}
+        fn on_descriptor_write(&self, _addr: BtAddress, _status: i32, _handle: i32) {}
 
-        fn on_execute_write(&self, _addr: String, _status: i32) {}
+        fn on_notify(&self, _addr: BtAddress, _handle: i32, _value: Vec<u8>) {}
 
-        fn on_descriptor_read(&self, _addr: String, _status: i32, _handle: i32, _value: Vec<u8>) {}
+        fn on_notify_multiple(&self, _addr: BtAddress, _values: Vec<GattHandleValue>) {}
 
-        fn on_descriptor_write(&self, _addr: String, _status: i32, _handle: i32) {}
+        fn on_notification_queue_overflow(&self, _addr: BtAddress, _handle: i32, _dropped: u32) {}
 
-        fn on_notify(&self, _addr: String, _handle: i32, _value: Vec<u8>) {}
+        fn on_read_remote_rssi(&self, _addr: BtAddress, _rssi: i32, _status: i32) {}
 
-        fn on_read_remote_rssi(&self, _addr: String, _rssi: i32, _status: i32) {}
+        fn on_rssi_threshold_crossed(&self, _addr: BtAddress, _rssi: i32, _threshold: i32) {}
 
-        fn on_configure_mtu(&self, _addr: String, _mtu: i32, _status: i32) {}
+        fn on_configure_mtu(&self, _addr: BtAddress, _mtu: i32, _status: i32) {}
 
         fn on_connection_updated(
             &self,
-            _addr: String,
+            _addr: BtAddress,
             _interval: i32,
             _latency: i32,
             _timeout: i32,
@@ -1443,7 +11449,11 @@ mod tests {
         ) {
         }
 
-        fn on_service_changed(&self, _addr: String) {}
+        fn on_service_changed(&self, _addr: BtAddress) {}
+
+        fn on_notification_pipe_active(&self, _addr: BtAddress, _handle: i32) {}
+
+        fn on_eatt_state_changed(&self, _addr: BtAddress, _count: i32, _mtus: Vec<i32>) {}
     }
 
     impl RPCProxy for TestBluetoothGattCallback {
@@ -1464,6 +11474,10 @@ mod tests {
 
     use super::*;
 
+    fn bt_address(addr: &str) -> BtAddress {
+        BtAddress::from_string(addr).unwrap()
+    }
+
     #[test]
     fn test_uuid_from_string() {
         let uuid = parse_uuid_string("abcdef");
@@ -1485,7 +11499,7 @@ mod tests {
         // Add client 1.
         let callback1 = Box::new(TestBluetoothGattCallback::new(String::from("Callback 1")));
         let uuid1 = parse_uuid_string("00000000000000000000000000000001").unwrap().uu;
-        map.add(&uuid1, callback1);
+        map.add(&uuid1, callback1, false);
         let found = map.get_by_uuid(&uuid1);
         assert!(found.is_some());
         assert_eq!("Callback 1", found.unwrap().callback.get_object_id());
@@ -1493,7 +11507,7 @@ mod tests {
         // Add client 2.
         let callback2 = Box::new(TestBluetoothGattCallback::new(String::from("Callback 2")));
         let uuid2 = parse_uuid_string("00000000000000000000000000000002").unwrap().uu;
-        map.add(&uuid2, callback2);
+        map.add(&uuid2, callback2, false);
         let found = map.get_by_uuid(&uuid2);
         assert!(found.is_some());
         assert_eq!("Callback 2", found.unwrap().callback.get_object_id());
@@ -1525,4 +11539,774 @@ mod tests {
         assert!(found.is_some());
         assert_eq!(4, found.unwrap());
     }
+
+    #[test]
+    fn test_context_map_eatt_connections() {
+        let mut map = ContextMap::new();
+        let address = String::from("aa:bb:cc:dd:ee:ff");
+
+        let uuid1 = parse_uuid_string("00000000000000000000000000000001").unwrap().uu;
+        map.add(&uuid1, Box::new(TestBluetoothGattCallback::new(String::from("1"))), true);
+        map.set_client_id(&uuid1, 1);
+        let uuid2 = parse_uuid_string("00000000000000000000000000000002").unwrap().uu;
+        map.add(&uuid2, Box::new(TestBluetoothGattCallback::new(String::from("2"))), false);
+        map.set_client_id(&uuid2, 2);
+
+        map.add_connection(1, 3, &address);
+        map.add_connection(2, 4, &address);
+        map.add_connection(1, 5, &String::from("11:22:33:44:55:66"));
+
+        // Only the clients with EATT support use the EATT bearers of the device.
+        assert_eq!(vec![3], map.get_eatt_conn_ids(&address));
+        map.remove_connection(1, 3);
+        assert!(map.get_eatt_conn_ids(&address).is_empty());
+    }
+
+    #[test]
+    fn test_service_db_elements() {
+        let mut service = BluetoothGattService::new([1; 16], 0, 0);
+        service.included_services.push(BluetoothGattService::new([2; 16], 40, 0));
+        let mut characteristic = BluetoothGattCharacteristic::new(
+            [3; 16],
+            0,
+            BluetoothGattCharacteristic::PROPERTY_READ
+                | BluetoothGattCharacteristic::PROPERTY_NOTIFY,
+            0x01,
+        );
+        characteristic.descriptors.push(BluetoothGattDescriptor::new([4; 16], 0, 0x11));
+        service.characteristics.push(characteristic);
+
+        let mut elements = service_to_db_elements(&service);
+        assert_eq!(4, elements.len());
+        assert_eq!(40, elements[1].attribute_handle);
+        assert_eq!(0x11, elements[3].permissions);
+
+        // The stack reports the added service with the assigned handles.
+        for (i, elem) in elements.iter_mut().enumerate() {
+            if elem.type_ != GattDbElementType::IncludedService as u32 {
+                elem.attribute_handle = 10 + i as u16;
+            }
+        }
+
+        let added = service_from_db_elements(&elements).unwrap();
+        assert_eq!([1; 16], added.uuid);
+        assert_eq!(10, added.instance_id);
+        assert_eq!(40, added.included_services[0].instance_id);
+        assert_eq!(1, added.characteristics.len());
+        assert_eq!(12, added.characteristics[0].instance_id);
+        assert_eq!(0x01, added.characteristics[0].permissions);
+        assert_eq!(13, added.characteristics[0].descriptors[0].instance_id);
+        assert_eq!(0x11, added.characteristics[0].descriptors[0].permissions);
+    }
+
+    fn match_bytes(offset: i32, value: Vec<u8>) -> ScanMatchInstruction {
+        ScanMatchInstruction {
+            opcode: ScanMatchOpcode::MatchBytes,
+            offset,
+            value,
+            ..Default::default()
+        }
+    }
+
+    fn logic_op(opcode: ScanMatchOpcode) -> ScanMatchInstruction {
+        ScanMatchInstruction { opcode, ..Default::default() }
+    }
+
+    #[test]
+    fn test_scan_match_program_verifier() {
+        // Empty program.
+        let program = ScanMatchProgram { instructions: vec![] };
+        assert!(CompiledScanMatchProgram::compile(&program).is_none());
+
+        // Stack underflow.
+        let program = ScanMatchProgram {
+            instructions: vec![match_bytes(0, vec![1]), logic_op(ScanMatchOpcode::And)],
+        };
+        assert!(CompiledScanMatchProgram::compile(&program).is_none());
+
+        // More than one verdict left on the stack.
+        let program = ScanMatchProgram {
+            instructions: vec![match_bytes(0, vec![1]), match_bytes(1, vec![2])],
+        };
+        assert!(CompiledScanMatchProgram::compile(&program).is_none());
+
+        // Out of bounds offset and mismatched mask length.
+        let program = ScanMatchProgram { instructions: vec![match_bytes(1650, vec![1])] };
+        assert!(CompiledScanMatchProgram::compile(&program).is_none());
+        let mut inst = match_bytes(0, vec![1, 2]);
+        inst.mask = vec![0xff];
+        let program = ScanMatchProgram { instructions: vec![inst] };
+        assert!(CompiledScanMatchProgram::compile(&program).is_none());
+
+        // RSSI threshold out of range.
+        let program = ScanMatchProgram {
+            instructions: vec![ScanMatchInstruction {
+                opcode: ScanMatchOpcode::RssiAtLeast,
+                threshold: -200,
+                ..Default::default()
+            }],
+        };
+        assert!(CompiledScanMatchProgram::compile(&program).is_none());
+    }
+
+    #[test]
+    fn test_scan_match_program_evaluation() {
+        // Flags, then manufacturer specific data for company 0x00e0.
+        let adv_data = vec![0x02, 0x01, 0x06, 0x05, 0xff, 0xe0, 0x00, 0x12, 0x34];
+
+        // Manufacturer data of company 0x00e0 whose first payload byte has its high nibble set
+        // to 1, and RSSI at least -70.
+        let program = ScanMatchProgram {
+            instructions: vec![
+                ScanMatchInstruction {
+                    opcode: ScanMatchOpcode::MatchAdType,
+                    ad_type: 0xff,
+                    offset: 0,
+                    mask: vec![0xff, 0xff, 0xf0],
+                    value: vec![0xe0, 0x00, 0x10],
+                    ..Default::default()
+                },
+                ScanMatchInstruction {
+                    opcode: ScanMatchOpcode::RssiAtLeast,
+                    threshold: -70,
+                    ..Default::default()
+                },
+                logic_op(ScanMatchOpcode::And),
+            ],
+        };
+        let compiled = CompiledScanMatchProgram::compile(&program).unwrap();
+        assert!(compiled.matches(&adv_data, -50));
+        assert!(!compiled.matches(&adv_data, -80));
+        assert!(!compiled.matches(&adv_data[..3], -50));
+
+        // Either the flags byte differs or the data is longer than 20 bytes.
+        let program = ScanMatchProgram {
+            instructions: vec![
+                match_bytes(2, vec![0x06]),
+                logic_op(ScanMatchOpcode::Not),
+                match_bytes(20, vec![0x00]),
+                logic_op(ScanMatchOpcode::Or),
+            ],
+        };
+        let compiled = CompiledScanMatchProgram::compile(&program).unwrap();
+        assert!(!compiled.matches(&adv_data, 0));
+        assert!(compiled.matches(&[0x02, 0x01, 0x04], 0));
+    }
+
+    #[test]
+    fn test_sync_parameter() {
+        assert_eq!(Ok(0x0F), sync_parameter::<u8>("SID", 0x0F, PERIODIC_SID_RANGE));
+        assert_eq!(
+            Ok(0x4000),
+            sync_parameter::<u16>("Timeout", 0x4000, PERIODIC_SYNC_TIMEOUT_RANGE)
+        );
+
+        // Values which the casts to the HCI types used to truncate are refused.
+        assert!(sync_parameter::<u8>("SID", 0x10, PERIODIC_SID_RANGE).is_err());
+        assert!(sync_parameter::<u8>("SID", 0x101, PERIODIC_SID_RANGE).is_err());
+        assert!(sync_parameter::<u16>("Skip", -1, PERIODIC_SKIP_RANGE).is_err());
+        assert!(sync_parameter::<u16>("Timeout", 0x10000, PERIODIC_SYNC_TIMEOUT_RANGE).is_err());
+        assert!(sync_parameter::<u16>("Timeout", 9, PERIODIC_SYNC_TIMEOUT_RANGE).is_err());
+        assert!(sync_parameter::<u8>("Handle", 0xF0, ADVERTISING_HANDLE_RANGE).is_err());
+    }
+
+    #[test]
+    fn test_check_scan_permission() {
+        struct TestChecker {
+            permitted: HashSet<String>,
+        }
+
+        impl IScanPermissionChecker for TestChecker {
+            fn is_scan_permitted(&self, sender: &String) -> bool {
+                self.permitted.contains(sender)
+            }
+        }
+
+        let allowed = String::from(":1.10");
+        let denied = String::from(":1.11");
+        let mut last = None;
+        assert!(check_scan_permission(None, &denied, 1, &mut last));
+        assert_eq!(Some(true), last);
+
+        let checker: Box<dyn IScanPermissionChecker + Send> =
+            Box::new(TestChecker { permitted: [allowed.clone()].iter().cloned().collect() });
+        assert!(check_scan_permission(Some(checker.as_ref()), &allowed, 1, &mut last));
+        assert!(!check_scan_permission(Some(checker.as_ref()), &denied, 2, &mut last));
+        assert_eq!(Some(false), last);
+    }
+
+    #[test]
+    fn test_match_tracker() {
+        let result = |address: &str, rssi: i32| ScanResult {
+            address: bt_address(address),
+            rssi,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let mut tracker = MatchTracker::new(Duration::from_secs(5));
+
+        assert!(tracker.update(start, result("AA:BB:CC:DD:EE:FF", -60)));
+        assert!(!tracker.update(start + Duration::from_secs(2), result("AA:BB:CC:DD:EE:FF", -50)));
+        assert!(tracker.update(start + Duration::from_secs(3), result("11:22:33:44:55:66", -70)));
+
+        // Each device expires after the timeout since its last match, with its last result.
+        assert!(tracker.expire(start + Duration::from_secs(6)).is_empty());
+        let lost = tracker.expire(start + Duration::from_secs(7));
+        assert_eq!(1, lost.len());
+        assert_eq!(-50, lost[0].rssi);
+        assert_eq!(1, tracker.expire(start + Duration::from_secs(8)).len());
+        assert!(!tracker.may_expire());
+
+        // A lost device is a first match again.
+        assert!(tracker.update(start + Duration::from_secs(9), result("AA:BB:CC:DD:EE:FF", -60)));
+    }
+
+    #[test]
+    fn test_match_tracker_sightings() {
+        let result =
+            |address: &str| ScanResult { address: bt_address(address), ..Default::default() };
+        let start = Instant::now();
+        let mut tracker =
+            MatchTracker::new(Duration::from_secs(5)).with_sightings(3, Duration::from_secs(1));
+        let address = "AA:BB:CC:DD:EE:FF";
+
+        // The sightings out of the window do not count.
+        assert!(!tracker.update(start, result(address)));
+        assert!(!tracker.update(start + Duration::from_millis(600), result(address)));
+        assert!(!tracker.update(start + Duration::from_millis(1200), result(address)));
+        assert!(tracker.update(start + Duration::from_millis(1500), result(address)));
+        assert!(!tracker.update(start + Duration::from_millis(1600), result(address)));
+
+        // The devices seen too few times are forgotten with their sightings.
+        assert!(!tracker.update(start + Duration::from_secs(2), result("11:22:33:44:55:66")));
+        assert!(tracker.expire(start + Duration::from_millis(2500)).is_empty());
+        assert_eq!(1, tracker.candidates.len());
+        assert!(tracker.expire(start + Duration::from_secs(3)).is_empty());
+        assert!(tracker.candidates.is_empty());
+        assert_eq!(1, tracker.expire(start + Duration::from_secs(7)).len());
+
+        assert_eq!(
+            Some(ApcfTracking { found_timeout_ms: 1000, found_count: 3, lost_timeout_ms: 5000 }),
+            tracker.apcf_tracking()
+        );
+        let tracker = MatchTracker::new(Duration::from_secs(100));
+        assert_eq!(None, tracker.apcf_tracking());
+
+        // The controller reports the devices it tracks found and lost.
+        let mut tracker = MatchTracker::new(Duration::from_secs(5));
+        tracker.offloaded = true;
+        assert!(!tracker.update(start, result(address)));
+        assert!(tracker.found(start, result(address)));
+        assert!(!tracker.found(start, result(address)));
+        assert!(!tracker.may_expire());
+        assert!(tracker.expire(start + Duration::from_secs(10)).is_empty());
+        assert_eq!(bt_address(address), tracker.lose(&bt_address(address)).unwrap().address);
+        assert_eq!(None, tracker.lose(&bt_address(address)).map(|r| r.address));
+    }
+
+    #[test]
+    fn test_parse_batch_scan_records() {
+        let truncated = vec![
+            0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0x01, 0xf6, 0xc4, 0x02, 0x00, // Record 1
+            0xff, 0xee, 0xdd, 0xcc, 0xbb, 0xaa, 0x00, 0x00, 0xb0, 0x00, 0x01, // Record 2
+        ];
+        let results = parse_batch_scan_records(BatchScanMode::Truncated as i32, 2, &truncated);
+        assert_eq!(2, results.len());
+        assert_eq!(
+            BatchScanResult {
+                address: String::from("11:22:33:44:55:66"),
+                addr_type: 1,
+                tx_power: -10,
+                rssi: -60,
+                timestamp_millis: 100,
+                adv_data: vec![],
+            },
+            results[0]
+        );
+        assert_eq!(String::from("AA:BB:CC:DD:EE:FF"), results[1].address);
+        assert_eq!(256 * 50, results[1].timestamp_millis);
+
+        // Records are not read past the data.
+        assert_eq!(
+            1,
+            parse_batch_scan_records(BatchScanMode::Truncated as i32, 3, &truncated[..20]).len()
+        );
+
+        let mut full = truncated[..11].to_vec();
+        full.extend_from_slice(&[0x03, 0x02, 0x01, 0x06, 0x02, 0x01, 0x09]);
+        let results = parse_batch_scan_records(BatchScanMode::Full as i32, 1, &full);
+        assert_eq!(1, results.len());
+        assert_eq!(vec![0x02, 0x01, 0x06, 0x01, 0x09], results[0].adv_data);
+
+        // Truncated scan response.
+        assert!(parse_batch_scan_records(BatchScanMode::Full as i32, 1, &full[..17]).is_empty());
+    }
+
+    #[test]
+    fn test_arbitrate_scan_parameters() {
+        let low_power = ScanParameters { interval: 8192, window: 512 };
+        let balanced = ScanParameters { interval: 4096, window: 1024 };
+        let balanced_short = ScanParameters { interval: 2048, window: 512 };
+        let low_latency = ScanParameters { interval: 4096, window: 4096 };
+
+        assert_eq!(None, arbitrate_scan_parameters(vec![].into_iter()));
+        assert_eq!(
+            Some(balanced),
+            arbitrate_scan_parameters(vec![low_power, balanced].into_iter())
+        );
+        assert_eq!(
+            Some(balanced_short),
+            arbitrate_scan_parameters(vec![balanced, balanced_short, low_power].into_iter())
+        );
+        assert_eq!(
+            Some(low_latency),
+            arbitrate_scan_parameters(vec![low_power, low_latency, balanced].into_iter())
+        );
+
+        assert_eq!(Some(DEFAULT_SCAN_PARAMETERS), ScanParameters::new(0, 0));
+        assert_eq!(Some(balanced), ScanParameters::new(4096, 1024));
+        assert_eq!(None, ScanParameters::new(1024, 4096));
+        assert_eq!(None, ScanParameters::new(0x4001, 0x10));
+        assert_eq!(None, ScanParameters::new(0x10, 0));
+    }
+
+    #[test]
+    fn test_scan_phy_bit() {
+        assert_eq!(SCAN_PHY_LE_1M, scan_phy_bit(LePhy::Phy1m as u8));
+        assert_eq!(SCAN_PHY_LE_CODED, scan_phy_bit(LePhy::PhyCoded as u8));
+        // Advertisements of an unknown PHY are reported as found on the LE 1M PHY.
+        assert_eq!(SCAN_PHY_LE_1M, scan_phy_bit(0));
+    }
+
+    #[test]
+    fn test_scan_governor() {
+        let balanced = ScanParameters { interval: 4096, window: 1024 };
+        let low_latency = ScanParameters { interval: 4096, window: 4096 };
+
+        assert_eq!(250, balanced.duty_cycle());
+        assert!(!balanced.is_aggressive());
+        assert_eq!(balanced, balanced.throttled());
+        assert!(low_latency.is_aggressive());
+        assert_eq!(ScanParameters { interval: 4096, window: 2048 }, low_latency.throttled());
+
+        assert_eq!(None, next_scan_holder(&[], None));
+        assert_eq!(Some(2), next_scan_holder(&[5, 2, 7], None));
+        assert_eq!(Some(5), next_scan_holder(&[5, 2, 7], Some(2)));
+        assert_eq!(Some(2), next_scan_holder(&[5, 2, 7], Some(7)));
+        // The holder stopped competing.
+        assert_eq!(Some(7), next_scan_holder(&[2, 7], Some(5)));
+
+        assert_eq!(250, effective_duty_cycle(&balanced, 2, false));
+        assert_eq!(1000, effective_duty_cycle(&low_latency, 1, true));
+        assert_eq!(750, effective_duty_cycle(&low_latency, 2, true));
+        assert_eq!(500, effective_duty_cycle(&low_latency, 2, false));
+    }
+
+    #[test]
+    fn test_manufacturer_data_subscription() {
+        let subscription = ManufacturerDataSubscription {
+            id: 1,
+            manufacturer_id: 0x004C,
+            prefix: vec![0x02, 0x10],
+            mask: vec![0xFF, 0xF0],
+        };
+
+        // Flags, then manufacturer data of company 0x004C.
+        let adv_data = [0x02, 0x01, 0x06, 0x06, 0xFF, 0x4C, 0x00, 0x02, 0x15, 0xAA];
+        assert_eq!(Some(&[0x02, 0x15, 0xAA][..]), subscription.find_match(&adv_data));
+
+        // Another company.
+        let adv_data = [0x06, 0xFF, 0xE0, 0x00, 0x02, 0x15, 0xAA];
+        assert_eq!(None, subscription.find_match(&adv_data));
+
+        // Masked prefix mismatch, and payload shorter than the prefix.
+        let adv_data = [0x06, 0xFF, 0x4C, 0x00, 0x02, 0x25, 0xAA];
+        assert_eq!(None, subscription.find_match(&adv_data));
+        let adv_data = [0x04, 0xFF, 0x4C, 0x00, 0x02];
+        assert_eq!(None, subscription.find_match(&adv_data));
+    }
+
+    #[test]
+    fn test_address_filter() {
+        let addr1 = bt_address("AA:BB:CC:DD:EE:FF");
+        let addr2 = bt_address("11:22:33:44:55:66");
+
+        assert!(AddressFilter::default().permits(&addr1));
+
+        let filter = AddressFilter::new(vec![], vec![bt_address("aa:bb:cc:dd:ee:ff")]);
+        assert!(!filter.permits(&addr1));
+        assert!(filter.permits(&addr2));
+
+        // The deny-list takes precedence over the allow-list.
+        let filter = AddressFilter::new(vec![addr1, addr2], vec![addr2]);
+        assert!(filter.permits(&addr1));
+        assert!(!filter.permits(&addr2));
+        assert!(!filter.permits(&bt_address("00:00:00:00:00:01")));
+    }
+
+    #[test]
+    fn test_scan_filter_matching() {
+        let address = bt_address("AA:BB:CC:DD:EE:FF");
+        // Heart rate service UUID, complete local name "hrm", then manufacturer specific data
+        // for company 0x00e0.
+        let adv_data = vec![
+            0x03, 0x03, 0x0d, 0x18, 0x04, 0x09, b'h', b'r', b'm', 0x05, 0xff, 0xe0, 0x00, 0x12,
+            0x34,
+        ];
+
+        assert!(ScanFilter::default().matches(&address, &adv_data, -100));
+
+        let filter = ScanFilter {
+            address: String::from("aa:bb:cc:dd:ee:ff"),
+            service_uuid: String::from("0000180d00001000800000805f9b34fb"),
+            name: String::from("hrm"),
+            ..Default::default()
+        };
+        assert!(filter.is_valid());
+        assert!(filter.matches(&address, &adv_data, -100));
+        assert!(!filter.matches(&bt_address("11:22:33:44:55:66"), &adv_data, -100));
+
+        let filter = ScanFilter { name: String::from("hr"), ..Default::default() };
+        assert!(!filter.matches(&address, &adv_data, -100));
+
+        let filter = ScanFilter {
+            manufacturer_id: 0x00e0,
+            manufacturer_data: vec![0x10],
+            manufacturer_data_mask: vec![0xf0],
+            rssi_high_threshold: -70,
+            ..Default::default()
+        };
+        assert!(filter.matches(&address, &adv_data, -60));
+        assert!(!filter.matches(&address, &adv_data, -80));
+        assert_eq!(0x20, filter.to_filter_param(None).feat_seln);
+        assert_eq!(-70i8 as u8, filter.to_filter_param(None).rssi_high_thres);
+
+        let filter = ScanFilter { manufacturer_id: 0x004c, ..Default::default() };
+        assert!(!filter.matches(&address, &adv_data, -60));
+
+        let filter = ScanFilter { manufacturer_data_mask: vec![0xff], ..Default::default() };
+        assert!(!filter.is_valid());
+    }
+
+    #[test]
+    fn test_scan_filter_msft_condition() {
+        let filter = ScanFilter {
+            address: String::from("01:02:03:04:05:06"),
+            addr_type: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            Some(MonitorCondition::Address(1, [1, 2, 3, 4, 5, 6])),
+            filter.to_msft_condition()
+        );
+
+        let filter = ScanFilter {
+            service_uuid: String::from("0000180d00001000800000805f9b34fb"),
+            ..Default::default()
+        };
+        assert_eq!(Some(MonitorCondition::Uuid(vec![0x0d, 0x18])), filter.to_msft_condition());
+
+        let filter = ScanFilter { name: String::from("hrm"), ..Default::default() };
+        assert_eq!(
+            Some(MonitorCondition::Patterns(vec![
+                (0x08, b"hrm".to_vec()),
+                (0x09, b"hrm".to_vec())
+            ])),
+            filter.to_msft_condition()
+        );
+
+        let filter = ScanFilter {
+            manufacturer_id: 0x00e0,
+            manufacturer_data: vec![0x12],
+            ..Default::default()
+        };
+        assert_eq!(
+            Some(MonitorCondition::Patterns(vec![(0xff, vec![0xe0, 0x00, 0x12])])),
+            filter.to_msft_condition()
+        );
+
+        // Masks and combined conditions are left to APCF.
+        let filter = ScanFilter {
+            manufacturer_id: 0x00e0,
+            manufacturer_data: vec![0x10],
+            manufacturer_data_mask: vec![0xf0],
+            ..Default::default()
+        };
+        assert_eq!(None, filter.to_msft_condition());
+
+        let filter =
+            ScanFilter { name: String::from("hrm"), manufacturer_id: 0x00e0, ..Default::default() };
+        assert_eq!(None, filter.to_msft_condition());
+        assert_eq!(None, ScanFilter::default().to_msft_condition());
+    }
+
+    #[test]
+    fn test_shared_connection() {
+        let request = |client_id: i32, opportunistic: bool| ConnectRequest {
+            client_id,
+            is_direct: true,
+            transport: 2,
+            opportunistic,
+            phy: 1,
+        };
+        let mut connection = SharedConnection::default();
+
+        // Only the first direct request makes an attempt.
+        assert_eq!(ConnectAction::Connect, connection.request(request(1, false)));
+        assert_eq!(ConnectAction::Wait, connection.request(request(2, false)));
+        assert_eq!(ConnectAction::Wait, connection.request(request(1, false)));
+        assert_eq!(ConnectAction::Connect, connection.request(request(3, true)));
+
+        // The waiting clients join the link once it is up.
+        assert_eq!(vec![request(2, false)], connection.connected(1));
+        assert_eq!(ConnectAction::Connected, connection.request(request(1, false)));
+        assert_eq!(ConnectAction::Connect, connection.request(request(2, false)));
+        connection.connected(2);
+
+        connection.disconnected(1);
+        connection.disconnected(2);
+        assert!(connection.is_empty());
+
+        // The waiting clients fail along with the attempt.
+        connection.request(request(1, false));
+        connection.request(request(2, false));
+        assert!(connection.failed(2).is_empty());
+        assert_eq!(vec![request(2, false)], connection.failed(1));
+        assert!(connection.is_empty());
+
+        // Cancelling the attempt hands the waiting requests back.
+        connection.request(request(1, false));
+        connection.request(request(2, false));
+        connection.request(request(3, false));
+        assert_eq!(Some((false, vec![])), connection.cancel(3));
+        assert_eq!(Some((true, vec![request(2, false)])), connection.cancel(1));
+        assert_eq!(None, connection.cancel(1));
+        assert!(connection.is_empty());
+    }
+
+    #[test]
+    fn test_shared_cccd() {
+        let mut cccd = SharedCccd::default();
+        assert_eq!(Some(0x01), cccd.set(1, 0x01));
+        assert_eq!(None, cccd.set(2, 0x01));
+        assert_eq!(Some(0x03), cccd.set(3, 0x02));
+
+        // Notifications stay enabled while a client wants them.
+        assert_eq!(None, cccd.set(1, 0));
+        assert_eq!(Some(0x02), cccd.set(2, 0));
+        assert_eq!(Some(0), cccd.set(3, 0));
+        assert!(cccd.wanted.is_empty());
+
+        // A failed write is retried.
+        cccd.written = None;
+        assert_eq!(Some(0), cccd.set(1, 0));
+    }
+
+    #[test]
+    fn test_pending_operations() {
+        let mut operations = PendingOperations::default();
+        operations.push(GattOperation::Discovery, 0);
+        operations.push(GattOperation::ReadCharacteristic, 3);
+        operations.push(GattOperation::WriteDescriptor, 5);
+        operations.push(GattOperation::ReadCharacteristic, 3);
+
+        assert!(operations.cancel(7).is_empty());
+        assert_eq!(
+            vec![(GattOperation::ReadCharacteristic, 3), (GattOperation::ReadCharacteristic, 3)],
+            operations.cancel(3)
+        );
+        assert_eq!(
+            vec![(GattOperation::Discovery, 0)],
+            operations.cancel(OPERATION_TOKEN_DISCOVERY)
+        );
+
+        // The results of the cancelled operations are dropped.
+        assert!(operations.is_cancelled(GattOperation::ReadCharacteristic, 3));
+        assert!(!operations.is_cancelled(GattOperation::WriteDescriptor, 5));
+        assert!(operations.complete(GattOperation::ReadCharacteristic, 3));
+        assert!(operations.complete(GattOperation::Discovery, 0));
+        assert!(!operations.complete(GattOperation::Discovery, 0));
+
+        assert_eq!(
+            vec![(GattOperation::WriteDescriptor, 5)],
+            operations.cancel(OPERATION_TOKEN_ALL)
+        );
+        assert!(operations.cancel(OPERATION_TOKEN_ALL).is_empty());
+        assert!(operations.complete(GattOperation::WriteDescriptor, 5));
+    }
+
+    #[test]
+    fn test_operation_queue() {
+        let read = || AttRequest::Read { auth_req: 0 };
+        let result = |status: i32| OperationResult { status, ..Default::default() };
+        let next = |operations: &mut PendingOperations, bearers: usize| {
+            operations.next_request(bearers).map(|(operation, handle, _)| (operation, handle))
+        };
+
+        let mut operations = PendingOperations::default();
+        operations.push(GattOperation::Discovery, 0);
+        assert!(operations.can_send(1));
+        operations.push(GattOperation::ReadCharacteristic, 1);
+        assert!(!operations.can_send(1));
+        operations.queue(GattOperation::ReadCharacteristic, 2, read());
+        operations.queue(GattOperation::ReadDescriptor, 3, read());
+        operations.queue(GattOperation::ReadCharacteristic, 4, read());
+        // The queued operations are not overtaken.
+        assert!(!operations.can_send(2));
+        assert_eq!(None, next(&mut operations, 1));
+
+        // The discovery does not hold the results back.
+        assert_eq!(
+            vec![(GattOperation::ReadCharacteristic, 1, result(0))],
+            operations.finish(GattOperation::ReadCharacteristic, 1, result(0))
+        );
+        assert_eq!(Some((GattOperation::ReadCharacteristic, 2)), next(&mut operations, 2));
+        assert_eq!(Some((GattOperation::ReadDescriptor, 3)), next(&mut operations, 2));
+        assert_eq!(None, next(&mut operations, 2));
+
+        // The results are delivered in request order.
+        assert!(operations.finish(GattOperation::ReadDescriptor, 3, result(1)).is_empty());
+        assert_eq!(
+            vec![
+                (GattOperation::ReadCharacteristic, 2, result(2)),
+                (GattOperation::ReadDescriptor, 3, result(1))
+            ],
+            operations.finish(GattOperation::ReadCharacteristic, 2, result(2))
+        );
+
+        // The queued operations cancelled are not sent, and the results of the operations not
+        // tracked are delivered right away.
+        assert_eq!(vec![(GattOperation::ReadCharacteristic, 4)], operations.cancel(4));
+        assert_eq!(None, next(&mut operations, 2));
+        assert_eq!(
+            vec![(GattOperation::WriteCharacteristic, 5, result(0))],
+            operations.finish(GattOperation::WriteCharacteristic, 5, result(0))
+        );
+        assert!(!operations.complete(GattOperation::Discovery, 0));
+        assert!(operations.operations.is_empty());
+    }
+
+    #[test]
+    fn test_transaction_timeout() {
+        let mut operations = PendingOperations::default();
+        operations.push(GattOperation::Discovery, 0);
+        operations.restart_transaction_timer(None, 1);
+        assert!(operations.transaction_started.is_none());
+
+        operations.push(GattOperation::ReadCharacteristic, 3);
+        operations.push(GattOperation::WriteCharacteristic, 4);
+        operations.push(GattOperation::ReadDescriptor, 5);
+        operations.restart_transaction_timer(None, 1);
+        assert!(!operations.has_timed_out());
+        operations.transaction_started = Some(Instant::now() - ATT_TRANSACTION_TIMEOUT);
+        assert!(operations.has_timed_out());
+
+        // The discovery does not time out.
+        assert_eq!(
+            vec![
+                (GattOperation::ReadCharacteristic, 3),
+                (GattOperation::WriteCharacteristic, 4),
+                (GattOperation::ReadDescriptor, 5)
+            ],
+            operations.time_out()
+        );
+        assert!(operations.is_cancelled(GattOperation::ReadCharacteristic, 3));
+        assert!(operations.is_cancelled(GattOperation::WriteCharacteristic, 4));
+
+        // The operations timing out again are forgotten once cancelled.
+        assert!(operations.time_out().is_empty());
+        assert!(!operations.complete(GattOperation::Discovery, 0));
+        assert!(operations.operations.is_empty());
+    }
+
+    #[test]
+    fn test_frame_notification() {
+        assert_eq!(vec![0x00, 0x00], frame_notification(&[]));
+        assert_eq!(vec![0x03, 0x00, 0x01, 0x02, 0x03], frame_notification(&[0x01, 0x02, 0x03]));
+
+        let value = vec![0xaa; 0x1ff];
+        let frame = frame_notification(&value);
+        assert_eq!(0x201, frame.len());
+        assert_eq!([0xff, 0x01], frame[0..2]);
+    }
+
+    #[test]
+    fn test_decide_peripheral_connection() {
+        use PeripheralConnectionPolicy::*;
+
+        assert_eq!(
+            PeripheralDecision::Accepted,
+            decide_peripheral_connection(AllowAll, false, false, false)
+        );
+        assert_eq!(
+            PeripheralDecision::Rejected,
+            decide_peripheral_connection(BondedOnly, false, true, true)
+        );
+        assert_eq!(
+            PeripheralDecision::Accepted,
+            decide_peripheral_connection(BondedOnly, true, false, false)
+        );
+        assert_eq!(
+            PeripheralDecision::Rejected,
+            decide_peripheral_connection(AllowList, true, false, true)
+        );
+        assert_eq!(
+            PeripheralDecision::Accepted,
+            decide_peripheral_connection(AllowList, false, true, false)
+        );
+        assert_eq!(
+            PeripheralDecision::Pending,
+            decide_peripheral_connection(AskAgent, true, true, true)
+        );
+        assert_eq!(
+            PeripheralDecision::Rejected,
+            decide_peripheral_connection(AskAgent, true, true, false)
+        );
+    }
+
+    #[test]
+    fn test_long_write_chunks() {
+        let value: Vec<u8> = (0..50).collect();
+        let mut write = LongWrite::new(3, 0, value.clone(), ATT_DEFAULT_MTU);
+
+        let mut chunks = vec![];
+        while let Some((offset, data)) = write.next_chunk().map(|(o, d)| (o, d.to_vec())) {
+            write.written += data.len();
+            chunks.push((offset, data));
+        }
+
+        assert_eq!(
+            vec![
+                (0, value[0..18].to_vec()),
+                (18, value[18..36].to_vec()),
+                (36, value[36..50].to_vec())
+            ],
+            chunks
+        );
+    }
+
+    #[test]
+    fn test_scan_record_from_adv_data() {
+        let adv_data = vec![
+            0x02, 0x01, 0x06, // Flags
+            0x04, 0x08, b'a', b'b', b'c', // Shortened local name
+            0x03, 0x03, 0x0F, 0x18, // 16-bit service UUIDs
+            0x02, 0x0A, 0xF8, // TX power level
+            0x05, 0x16, 0x0F, 0x18, 0x64, 0x01, // 16-bit service data
+            0x05, 0xFF, 0xE0, 0x00, 0x01, 0x02, // Manufacturer data
+        ];
+
+        let record = ScanRecord::from_adv_data(&adv_data);
+        let battery_service = crate::uuid::BtUuid::from_string("180f").unwrap();
+        assert_eq!(0x06, record.flags);
+        assert_eq!("abc", record.name);
+        assert_eq!(vec![battery_service.uu], record.service_uuids);
+        assert_eq!(-8, record.tx_power_level);
+        assert_eq!(Some(&vec![0x64, 0x01]), record.service_data.get(&battery_service));
+        assert_eq!(Some(&vec![0x01, 0x02]), record.manufacturer_data.get(&0x00E0));
+
+        let record = ScanRecord::from_adv_data(&[0x05, 0x09, b'a']);
+        assert_eq!("", record.name);
+        assert_eq!(ScanRecord::TX_POWER_NOT_PRESENT, record.tx_power_level);
+    }
 }
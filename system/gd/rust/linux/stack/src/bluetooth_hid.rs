@@ -0,0 +1,462 @@
+//! HID host API, to control the connected keyboards, mice and other HID devices over classic HID
+//! and HOGP.
+//!
+//! The HID host profile is owned by `Bluetooth`, which connects the HID devices along with their
+//! other profiles. This API adds the control of the reports and of the protocol mode of the
+//! devices, the results of which are delivered asynchronously to the callbacks.
+
+use bt_topshim::btif::{BtStatus, RawAddress};
+use bt_topshim::profiles::hid_host::{
+    BthhConnectionState, BthhProtocolMode, BthhReportType, BthhStatus, HHCallbacks, HidHost,
+};
+
+use log::{debug, info, warn};
+use num_traits::cast::ToPrimitive;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::Sender;
+
+use crate::bluetooth::{Bluetooth, BluetoothDevice, IBluetooth};
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::uuid::{self, UuidHelper};
+use crate::{Message, RPCProxy};
+
+/// Defines the HID host API.
+pub trait IBluetoothHid {
+    /// Adds an observer of the HID devices.
+    ///
+    /// Returns the id of the callback.
+    fn register_callback(&mut self, callback: Box<dyn IBluetoothHidCallback + Send>) -> u32;
+
+    /// Removes an observer of the HID devices.
+    ///
+    /// Returns false if `callback_id` is not recognized.
+    fn unregister_callback(&mut self, callback_id: u32) -> bool;
+
+    /// Connects the HID profile of a bonded device.
+    fn connect(&mut self, device: BluetoothDevice) -> BtResult<()>;
+
+    /// Disconnects the HID profile of a device.
+    fn disconnect(&mut self, device: BluetoothDevice) -> BtResult<()>;
+
+    /// Returns the state of the HID connection of a device.
+    fn get_connection_state(&self, device: BluetoothDevice) -> HidConnectionState;
+
+    /// Returns the devices whose HID profile is connected.
+    fn get_connected_devices(&self) -> Vec<BluetoothDevice>;
+
+    /// Requests a report of a connected device. The report is delivered with
+    /// `IBluetoothHidCallback::on_report`.
+    fn get_report(
+        &mut self,
+        device: BluetoothDevice,
+        report_type: HidReportType,
+        report_id: u8,
+    ) -> BtResult<()>;
+
+    /// Sends a report to a connected device, the report id being its first byte if the device
+    /// numbers its reports. The answer is delivered with `IBluetoothHidCallback::on_handshake`.
+    fn set_report(
+        &mut self,
+        device: BluetoothDevice,
+        report_type: HidReportType,
+        report: Vec<u8>,
+    ) -> BtResult<()>;
+
+    /// Requests the protocol mode of a connected device. The mode is delivered with
+    /// `IBluetoothHidCallback::on_protocol_mode`.
+    fn get_protocol_mode(&mut self, device: BluetoothDevice) -> BtResult<()>;
+
+    /// Switches a connected device between the report and the boot protocols.
+    fn set_protocol_mode(&mut self, device: BluetoothDevice, mode: HidProtocolMode)
+        -> BtResult<()>;
+
+    /// Unplugs a device, which disconnects it and makes both sides forget the HID bond. The
+    /// result is delivered with `IBluetoothHidCallback::on_virtual_unplug`.
+    fn virtual_unplug(&mut self, device: BluetoothDevice) -> BtResult<()>;
+}
+
+/// HID host events.
+///
+/// The statuses are the HID host statuses of the answers of the devices, 0 on success.
+pub trait IBluetoothHidCallback: RPCProxy {
+    /// When the state of the HID connection of a device changes.
+    fn on_connection_state_changed(&self, device: BluetoothDevice, state: HidConnectionState);
+
+    /// When a device answers `IBluetoothHid::get_report`.
+    fn on_report(&self, device: BluetoothDevice, status: u32, report: Vec<u8>);
+
+    /// When a device answers `IBluetoothHid::get_protocol_mode`.
+    fn on_protocol_mode(&self, device: BluetoothDevice, status: u32, mode: HidProtocolMode);
+
+    /// When a device answers a report or a protocol mode it was sent.
+    fn on_handshake(&self, device: BluetoothDevice, status: u32);
+
+    /// When a device is unplugged, by either side.
+    fn on_virtual_unplug(&self, device: BluetoothDevice, status: u32);
+}
+
+/// State of the HID connection of a device.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum HidConnectionState {
+    Disconnected = 0,
+    Connecting = 1,
+    Connected = 2,
+    Disconnecting = 3,
+}
+
+impl Default for HidConnectionState {
+    fn default() -> Self {
+        HidConnectionState::Disconnected
+    }
+}
+
+impl From<BthhConnectionState> for HidConnectionState {
+    fn from(state: BthhConnectionState) -> Self {
+        match state {
+            BthhConnectionState::Connected => HidConnectionState::Connected,
+            BthhConnectionState::Connecting => HidConnectionState::Connecting,
+            BthhConnectionState::Disconnecting => HidConnectionState::Disconnecting,
+            _ => HidConnectionState::Disconnected,
+        }
+    }
+}
+
+/// Protocol of the reports of a device.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum HidProtocolMode {
+    /// Reports described by the report map of the device.
+    Report = 0,
+    /// Fixed reports of the keyboards and mice, understood without the report map.
+    Boot = 1,
+    Unsupported = 0xff,
+}
+
+impl From<BthhProtocolMode> for HidProtocolMode {
+    fn from(mode: BthhProtocolMode) -> Self {
+        match mode {
+            BthhProtocolMode::ReportMode => HidProtocolMode::Report,
+            BthhProtocolMode::BootMode => HidProtocolMode::Boot,
+            _ => HidProtocolMode::Unsupported,
+        }
+    }
+}
+
+/// Kind of report.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum HidReportType {
+    Input = 1,
+    Output = 2,
+    Feature = 3,
+}
+
+impl From<HidReportType> for BthhReportType {
+    fn from(report_type: HidReportType) -> Self {
+        match report_type {
+            HidReportType::Input => BthhReportType::InputReport,
+            HidReportType::Output => BthhReportType::OutputReport,
+            HidReportType::Feature => BthhReportType::FeatureReport,
+        }
+    }
+}
+
+/// Encodes a report as the hexadecimal string expected by the HID host profile.
+fn encode_report(report: &[u8]) -> Vec<u8> {
+    let mut encoded: Vec<u8> =
+        report.iter().map(|b| format!("{:02x}", b)).collect::<String>().into_bytes();
+    encoded.push(0);
+    encoded
+}
+
+/// Implementation of the HID host API.
+pub struct BluetoothHid {
+    tx: Sender<Message>,
+    adapter: Option<Arc<Mutex<Box<Bluetooth>>>>,
+    callbacks: HashMap<u32, Box<dyn IBluetoothHidCallback + Send>>,
+    connection_states: HashMap<String, HidConnectionState>,
+}
+
+impl BluetoothHid {
+    pub fn new(tx: Sender<Message>) -> BluetoothHid {
+        BluetoothHid {
+            tx,
+            adapter: None,
+            callbacks: HashMap::new(),
+            connection_states: HashMap::new(),
+        }
+    }
+
+    pub fn set_adapter(&mut self, adapter: Arc<Mutex<Box<Bluetooth>>>) {
+        self.adapter = Some(adapter);
+    }
+
+    pub(crate) fn remove_callback(&mut self, id: u32) -> bool {
+        match self.callbacks.get_mut(&id) {
+            Some(callback) => {
+                callback.unregister(id);
+                self.callbacks.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Calls `f` with the HID host profile and the address of `device`.
+    fn with_hid_host<F>(&self, device: &BluetoothDevice, f: F) -> BtResult<()>
+    where
+        F: FnOnce(&HidHost, &mut RawAddress) -> BtStatus,
+    {
+        let mut addr = RawAddress::from_string(device.address.clone()).ok_or_else(|| {
+            BtError::invalid_argument(format!("Invalid address {}", device.address))
+        })?;
+
+        let adapter = self
+            .adapter
+            .as_ref()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "The adapter is not ready"))?
+            .lock()
+            .unwrap();
+        let hh = adapter.hid_host().ok_or_else(|| {
+            BtError::new(BtErrorCategory::NotReady, "The HID host profile is not initialized")
+        })?;
+
+        match f(hh, &mut addr) {
+            BtStatus::Success => Ok(()),
+            status => Err(BtError::from(status)),
+        }
+    }
+
+    /// Fails unless the HID profile of `device` is connected.
+    fn check_connected(&self, device: &BluetoothDevice) -> BtResult<()> {
+        match self.get_connection_state(device.clone()) {
+            HidConnectionState::Connected => Ok(()),
+            _ => Err(BtError::new(
+                BtErrorCategory::NotReady,
+                format!("HID of {} is not connected", device.address),
+            )),
+        }
+    }
+
+    fn get_device(&self, addr: &RawAddress) -> BluetoothDevice {
+        let address = addr.to_string();
+        let name = match self.adapter.as_ref() {
+            Some(adapter) => adapter
+                .lock()
+                .unwrap()
+                .get_remote_name(BluetoothDevice::new(address.clone(), String::from(""))),
+            None => String::from(""),
+        };
+        BluetoothDevice::new(address, name)
+    }
+
+    fn for_all_callbacks<F: Fn(&Box<dyn IBluetoothHidCallback + Send>)>(&self, f: F) {
+        for (_, callback) in self.callbacks.iter() {
+            f(&callback);
+        }
+    }
+
+    /// Returns whether the admin policy allows connecting to HID over classic or LE.
+    fn is_hid_allowed(&self) -> bool {
+        let adapter = match self.adapter.as_ref() {
+            Some(adapter) => adapter.lock().unwrap(),
+            None => return true,
+        };
+        [uuid::HID, uuid::HOGP]
+            .iter()
+            .filter_map(|s| UuidHelper::from_string(*s))
+            .any(|s| adapter.is_service_allowed(&s))
+    }
+
+    pub fn dispatch_hid_host_callbacks(&mut self, cb: HHCallbacks) {
+        match cb {
+            HHCallbacks::ConnectionState(addr, state) => {
+                let device = self.get_device(&addr);
+                // The devices connecting on their own are refused too.
+                if matches!(state, BthhConnectionState::Connecting | BthhConnectionState::Connected)
+                    && !self.is_hid_allowed()
+                {
+                    info!("[{}]: HID blocked by policy.", device.address);
+                    if let Err(e) = self.with_hid_host(&device, |hh, addr| hh.disconnect(addr)) {
+                        warn!("[{}]: Failed to disconnect HID: {}", device.address, e);
+                    }
+                    return;
+                }
+
+                let state = HidConnectionState::from(state);
+                let previous = self.connection_states.get(&device.address).cloned();
+                if previous.unwrap_or_default() == state {
+                    return;
+                }
+
+                info!("[{}]: HID {:?}.", device.address, state);
+                if state == HidConnectionState::Disconnected {
+                    self.connection_states.remove(&device.address);
+                } else {
+                    self.connection_states.insert(device.address.clone(), state);
+                }
+
+                self.for_all_callbacks(|callback| {
+                    callback.on_connection_state_changed(device.clone(), state);
+                });
+            }
+            HHCallbacks::GetReport(addr, status, report, _size) => {
+                let device = self.get_device(&addr);
+                let status = status.to_u32().unwrap_or_default();
+                self.for_all_callbacks(|callback| {
+                    callback.on_report(device.clone(), status, report.clone());
+                });
+            }
+            HHCallbacks::ProtocolMode(addr, status, mode) => {
+                let device = self.get_device(&addr);
+                let status = status.to_u32().unwrap_or_default();
+                let mode = HidProtocolMode::from(mode);
+                self.for_all_callbacks(|callback| {
+                    callback.on_protocol_mode(device.clone(), status, mode);
+                });
+            }
+            HHCallbacks::Handshake(addr, status) => {
+                let device = self.get_device(&addr);
+                let status = status.to_u32().unwrap_or_default();
+                self.for_all_callbacks(|callback| {
+                    callback.on_handshake(device.clone(), status);
+                });
+            }
+            HHCallbacks::VirtualUnplug(addr, status) => {
+                let device = self.get_device(&addr);
+                if status != BthhStatus::Ok {
+                    warn!("[{}]: HID virtual unplug failed: {:?}", device.address, status);
+                }
+
+                let status = status.to_u32().unwrap_or_default();
+                self.for_all_callbacks(|callback| {
+                    callback.on_virtual_unplug(device.clone(), status);
+                });
+            }
+            HHCallbacks::HidInfo(..) | HHCallbacks::IdleTime(..) => {
+                debug!("Ignored HH callback");
+            }
+        }
+    }
+}
+
+impl IBluetoothHid for BluetoothHid {
+    fn register_callback(&mut self, mut callback: Box<dyn IBluetoothHidCallback + Send>) -> u32 {
+        let tx = self.tx.clone();
+
+        let id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _result = tx.send(Message::HidCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.callbacks.insert(id, callback);
+        id
+    }
+
+    fn unregister_callback(&mut self, callback_id: u32) -> bool {
+        self.remove_callback(callback_id)
+    }
+
+    fn connect(&mut self, device: BluetoothDevice) -> BtResult<()> {
+        if !self.is_hid_allowed() {
+            return Err(BtError::new(
+                BtErrorCategory::PermissionDenied,
+                "HID is blocked by the admin policy",
+            ));
+        }
+
+        self.with_hid_host(&device, |hh, addr| hh.connect(addr))
+    }
+
+    fn disconnect(&mut self, device: BluetoothDevice) -> BtResult<()> {
+        self.with_hid_host(&device, |hh, addr| hh.disconnect(addr))
+    }
+
+    fn get_connection_state(&self, device: BluetoothDevice) -> HidConnectionState {
+        self.connection_states.get(&device.address).cloned().unwrap_or_default()
+    }
+
+    fn get_connected_devices(&self) -> Vec<BluetoothDevice> {
+        self.connection_states
+            .iter()
+            .filter(|(_, state)| **state == HidConnectionState::Connected)
+            .filter_map(|(address, _)| RawAddress::from_string(address.clone()))
+            .map(|addr| self.get_device(&addr))
+            .collect()
+    }
+
+    fn get_report(
+        &mut self,
+        device: BluetoothDevice,
+        report_type: HidReportType,
+        report_id: u8,
+    ) -> BtResult<()> {
+        self.check_connected(&device)?;
+
+        // A buffer size of 0 requests the whole report.
+        self.with_hid_host(&device, |hh, addr| {
+            hh.get_report(addr, report_type.into(), report_id, 0)
+        })
+    }
+
+    fn set_report(
+        &mut self,
+        device: BluetoothDevice,
+        report_type: HidReportType,
+        report: Vec<u8>,
+    ) -> BtResult<()> {
+        self.check_connected(&device)?;
+
+        if report.is_empty() {
+            return Err(BtError::invalid_argument("Empty report"));
+        }
+
+        let mut encoded = encode_report(&report);
+        self.with_hid_host(&device, |hh, addr| {
+            hh.set_report(addr, report_type.into(), &mut encoded)
+        })
+    }
+
+    fn get_protocol_mode(&mut self, device: BluetoothDevice) -> BtResult<()> {
+        self.check_connected(&device)?;
+
+        // The mode is ignored by the profile, which only reads the mode of the device.
+        self.with_hid_host(&device, |hh, addr| hh.get_protocol(addr, BthhProtocolMode::ReportMode))
+    }
+
+    fn set_protocol_mode(
+        &mut self,
+        device: BluetoothDevice,
+        mode: HidProtocolMode,
+    ) -> BtResult<()> {
+        self.check_connected(&device)?;
+
+        let mode = match mode {
+            HidProtocolMode::Report => BthhProtocolMode::ReportMode,
+            HidProtocolMode::Boot => BthhProtocolMode::BootMode,
+            HidProtocolMode::Unsupported => {
+                return Err(BtError::invalid_argument("Unsupported protocol mode"));
+            }
+        };
+        self.with_hid_host(&device, |hh, addr| hh.set_protocol(addr, mode))
+    }
+
+    fn virtual_unplug(&mut self, device: BluetoothDevice) -> BtResult<()> {
+        self.with_hid_host(&device, |hh, addr| hh.virtual_unplug(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_report() {
+        assert_eq!(b"\0".to_vec(), encode_report(&[]));
+        assert_eq!(b"01ff0a\0".to_vec(), encode_report(&[0x01, 0xff, 0x0a]));
+    }
+}
@@ -0,0 +1,526 @@
+//! LE Audio API (IBluetoothLeAudio), managing the groups of LE Audio devices and their
+//! Connected Isochronous Streams.
+
+use bt_topshim::btif::{BluetoothInterface, RawAddress};
+use bt_topshim::profiles::le_audio::{
+    LeAudioCallbacks, LeAudioCallbacksDispatcher, LeAudioClient, LeAudioCodecType,
+    LeAudioConnectionState, LeAudioGroupNodeStatus, LeAudioGroupStatus,
+};
+use bt_topshim::topstack;
+
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::Sender;
+
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::{Message, RPCProxy};
+
+/// Group id of a device which is not part of any group, and to deactivate the active group with
+/// `IBluetoothLeAudio::group_set_active`.
+pub const LE_AUDIO_GROUP_ID_INVALID: i32 = -1;
+
+// Directions of the streams in `LeAudioCallbacks::AudioConf`.
+const AUDIO_DIRECTION_SINK: u8 = 0x01;
+const AUDIO_DIRECTION_SOURCE: u8 = 0x02;
+
+/// Defines the LE Audio API.
+pub trait IBluetoothLeAudio {
+    /// Adds an observer of the LE Audio devices and groups.
+    ///
+    /// Returns the id of the callback.
+    fn register_callback(&mut self, callback: Box<dyn IBluetoothLeAudioCallback + Send>) -> u32;
+
+    /// Removes an observer of the LE Audio devices and groups.
+    ///
+    /// Returns false if `callback_id` is not recognized.
+    fn unregister_callback(&mut self, callback_id: u32) -> bool;
+
+    /// Connects the LE Audio profile of a device. The device joins its group once connected.
+    fn connect(&mut self, addr: String) -> BtResult<()>;
+
+    /// Disconnects the LE Audio profile of a device.
+    fn disconnect(&mut self, addr: String) -> BtResult<()>;
+
+    /// Returns the LE Audio connection state of a device.
+    fn get_connection_state(&self, addr: String) -> LeAudioConnectionState;
+
+    /// Returns the group of a device, or `LE_AUDIO_GROUP_ID_INVALID` if it has none.
+    fn get_group_id(&self, addr: String) -> i32;
+
+    /// Returns the status of a group.
+    fn get_group_status(&self, group_id: i32) -> BtResult<LeAudioGroupStatus>;
+
+    /// Adds a device to a group.
+    fn group_add_node(&mut self, group_id: i32, addr: String) -> BtResult<()>;
+
+    /// Removes a device from its group.
+    fn group_remove_node(&mut self, group_id: i32, addr: String) -> BtResult<()>;
+
+    /// Makes the group the one used for audio, or deactivates the active group with
+    /// `LE_AUDIO_GROUP_ID_INVALID`.
+    fn group_set_active(&mut self, group_id: i32) -> BtResult<()>;
+
+    /// Returns the configuration of the streams of a group, once known.
+    fn get_group_stream_config(&self, group_id: i32) -> BtResult<LeAudioStreamConfig>;
+
+    /// Sets the codecs preferred for the input and output streams of a group. The resulting
+    /// configuration is delivered with `IBluetoothLeAudioCallback::on_group_stream_config_changed`.
+    fn set_codec_config_preference(
+        &mut self,
+        group_id: i32,
+        input_codec: LeAudioCodecType,
+        output_codec: LeAudioCodecType,
+    ) -> BtResult<()>;
+
+    /// Tells whether a call is ongoing, to configure the streams for conversational audio.
+    fn set_in_call(&mut self, in_call: bool) -> BtResult<()>;
+}
+
+/// LE Audio events.
+pub trait IBluetoothLeAudioCallback: RPCProxy {
+    /// When the LE Audio connection state of a device changes.
+    fn on_connection_state_changed(&self, addr: String, state: LeAudioConnectionState);
+
+    /// When a group becomes active or inactive.
+    fn on_group_status_changed(&self, group_id: i32, status: LeAudioGroupStatus);
+
+    /// When a device is added to or removed from a group.
+    fn on_group_node_status_changed(
+        &self,
+        addr: String,
+        group_id: i32,
+        status: LeAudioGroupNodeStatus,
+    );
+
+    /// When the configuration of the streams of a group is discovered or changes.
+    fn on_group_stream_config_changed(&self, config: LeAudioStreamConfig);
+}
+
+/// Configuration of the streams of an LE Audio group.
+#[derive(Clone, Debug)]
+pub struct LeAudioStreamConfig {
+    pub group_id: i32,
+    /// Whether the group can render audio.
+    pub has_sink: bool,
+    /// Whether the group can capture audio.
+    pub has_source: bool,
+    /// Bitmask of the audio locations (e.g. front left, front right) rendered by the group.
+    pub sink_audio_locations: u32,
+    /// Bitmask of the audio locations captured by the group.
+    pub source_audio_locations: u32,
+    /// Bitmask of the audio contexts (e.g. media, conversational) available on the group.
+    pub available_contexts: u16,
+    pub input_codec: LeAudioCodecType,
+    pub output_codec: LeAudioCodecType,
+}
+
+impl LeAudioStreamConfig {
+    fn new(group_id: i32) -> LeAudioStreamConfig {
+        LeAudioStreamConfig {
+            group_id,
+            has_sink: false,
+            has_source: false,
+            sink_audio_locations: 0,
+            source_audio_locations: 0,
+            available_contexts: 0,
+            input_codec: LeAudioCodecType::Lc3,
+            output_codec: LeAudioCodecType::Lc3,
+        }
+    }
+}
+
+struct LeAudioGroup {
+    status: LeAudioGroupStatus,
+    members: HashSet<RawAddress>,
+    stream_config: Option<LeAudioStreamConfig>,
+}
+
+impl LeAudioGroup {
+    fn new() -> LeAudioGroup {
+        LeAudioGroup {
+            status: LeAudioGroupStatus::Inactive,
+            members: HashSet::new(),
+            stream_config: None,
+        }
+    }
+}
+
+/// Groups of LE Audio devices, as reported by the profile.
+#[derive(Default)]
+struct LeAudioGroups {
+    groups: HashMap<i32, LeAudioGroup>,
+}
+
+impl LeAudioGroups {
+    fn get_or_insert(&mut self, group_id: i32) -> &mut LeAudioGroup {
+        self.groups.entry(group_id).or_insert_with(LeAudioGroup::new)
+    }
+
+    fn set_status(&mut self, group_id: i32, status: LeAudioGroupStatus) {
+        self.get_or_insert(group_id).status = status;
+    }
+
+    /// Adds or removes a member of a group. The group is dropped with its last member.
+    fn set_node_status(&mut self, addr: RawAddress, group_id: i32, status: LeAudioGroupNodeStatus) {
+        let group = self.get_or_insert(group_id);
+        match status {
+            LeAudioGroupNodeStatus::Added => {
+                group.members.insert(addr);
+            }
+            LeAudioGroupNodeStatus::Removed => {
+                group.members.remove(&addr);
+                if group.members.is_empty() {
+                    self.groups.remove(&group_id);
+                }
+            }
+        }
+    }
+
+    /// Updates the streams of a group from `LeAudioCallbacks::AudioConf` and returns their
+    /// configuration.
+    fn set_audio_conf(
+        &mut self,
+        group_id: i32,
+        direction: u8,
+        sink_audio_locations: u32,
+        source_audio_locations: u32,
+        available_contexts: u16,
+    ) -> LeAudioStreamConfig {
+        let config = self.stream_config_mut(group_id);
+        config.has_sink = direction & AUDIO_DIRECTION_SINK != 0;
+        config.has_source = direction & AUDIO_DIRECTION_SOURCE != 0;
+        config.sink_audio_locations = sink_audio_locations;
+        config.source_audio_locations = source_audio_locations;
+        config.available_contexts = available_contexts;
+        config.clone()
+    }
+
+    /// Updates the codecs of a group and returns the configuration of its streams.
+    fn set_codecs(
+        &mut self,
+        group_id: i32,
+        input_codec: LeAudioCodecType,
+        output_codec: LeAudioCodecType,
+    ) -> LeAudioStreamConfig {
+        let config = self.stream_config_mut(group_id);
+        config.input_codec = input_codec;
+        config.output_codec = output_codec;
+        config.clone()
+    }
+
+    fn stream_config_mut(&mut self, group_id: i32) -> &mut LeAudioStreamConfig {
+        self.get_or_insert(group_id)
+            .stream_config
+            .get_or_insert_with(|| LeAudioStreamConfig::new(group_id))
+    }
+
+    fn contains(&self, group_id: i32) -> bool {
+        self.groups.contains_key(&group_id)
+    }
+
+    fn is_member(&self, group_id: i32, addr: &RawAddress) -> bool {
+        self.groups.get(&group_id).map_or(false, |group| group.members.contains(addr))
+    }
+
+    /// Returns the group of a device, or `LE_AUDIO_GROUP_ID_INVALID` if it has none.
+    fn group_of(&self, addr: &RawAddress) -> i32 {
+        self.groups
+            .iter()
+            .find(|(_, group)| group.members.contains(addr))
+            .map_or(LE_AUDIO_GROUP_ID_INVALID, |(group_id, _)| *group_id)
+    }
+
+    fn status(&self, group_id: i32) -> Option<LeAudioGroupStatus> {
+        self.groups.get(&group_id).map(|group| group.status)
+    }
+
+    fn stream_config(&self, group_id: i32) -> Option<LeAudioStreamConfig> {
+        self.groups.get(&group_id).and_then(|group| group.stream_config.clone())
+    }
+}
+
+/// Implementation of the LE Audio API.
+pub struct BluetoothLeAudio {
+    intf: Arc<Mutex<BluetoothInterface>>,
+    tx: Sender<Message>,
+    le_audio: Option<LeAudioClient>,
+    callbacks: HashMap<u32, Box<dyn IBluetoothLeAudioCallback + Send>>,
+    connection_states: HashMap<RawAddress, LeAudioConnectionState>,
+    groups: LeAudioGroups,
+}
+
+impl BluetoothLeAudio {
+    pub fn new(tx: Sender<Message>, intf: Arc<Mutex<BluetoothInterface>>) -> BluetoothLeAudio {
+        BluetoothLeAudio {
+            intf,
+            tx,
+            le_audio: None,
+            callbacks: HashMap::new(),
+            connection_states: HashMap::new(),
+            groups: LeAudioGroups::default(),
+        }
+    }
+
+    pub fn init_profiles(&mut self) {
+        let tx = self.tx.clone();
+        let mut le_audio = LeAudioClient::new(&self.intf.lock().unwrap());
+        le_audio.initialize(LeAudioCallbacksDispatcher {
+            dispatch: Box::new(move |cb| {
+                let tx = tx.clone();
+                topstack::get_runtime().spawn(async move {
+                    let _ = tx.send(Message::LeAudio(cb)).await;
+                });
+            }),
+        });
+        self.le_audio = Some(le_audio);
+    }
+
+    pub(crate) fn remove_callback(&mut self, id: u32) -> bool {
+        match self.callbacks.get_mut(&id) {
+            Some(callback) => {
+                callback.unregister(id);
+                self.callbacks.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn dispatch_le_audio_callbacks(&mut self, cb: LeAudioCallbacks) {
+        match cb {
+            LeAudioCallbacks::ConnectionState(state, addr) => {
+                info!("[{}]: LE Audio connection state {:?}", addr.to_string(), state);
+                if state == LeAudioConnectionState::Disconnected {
+                    self.connection_states.remove(&addr);
+                } else {
+                    self.connection_states.insert(addr, state);
+                }
+
+                for (_, callback) in self.callbacks.iter() {
+                    callback.on_connection_state_changed(addr.to_string(), state);
+                }
+            }
+            LeAudioCallbacks::GroupStatus(group_id, status) => {
+                self.groups.set_status(group_id, status);
+
+                for (_, callback) in self.callbacks.iter() {
+                    callback.on_group_status_changed(group_id, status);
+                }
+            }
+            LeAudioCallbacks::GroupNodeStatus(addr, group_id, status) => {
+                self.groups.set_node_status(addr, group_id, status);
+
+                for (_, callback) in self.callbacks.iter() {
+                    callback.on_group_node_status_changed(addr.to_string(), group_id, status);
+                }
+            }
+            LeAudioCallbacks::AudioConf(
+                direction,
+                group_id,
+                sink_audio_locations,
+                source_audio_locations,
+                available_contexts,
+            ) => {
+                let config = self.groups.set_audio_conf(
+                    group_id,
+                    direction,
+                    sink_audio_locations,
+                    source_audio_locations,
+                    available_contexts,
+                );
+                for (_, callback) in self.callbacks.iter() {
+                    callback.on_group_stream_config_changed(config.clone());
+                }
+            }
+            LeAudioCallbacks::GroupCodecConf(group_id, input_codec, output_codec) => {
+                let config = self.groups.set_codecs(group_id, input_codec, output_codec);
+                for (_, callback) in self.callbacks.iter() {
+                    callback.on_group_stream_config_changed(config.clone());
+                }
+            }
+        }
+    }
+
+    fn get_le_audio(&mut self) -> BtResult<&mut LeAudioClient> {
+        self.le_audio
+            .as_mut()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "LE Audio is not initialized"))
+    }
+}
+
+fn parse_address(addr: &String) -> BtResult<RawAddress> {
+    RawAddress::from_string(addr.clone())
+        .ok_or_else(|| BtError::invalid_argument(format!("Invalid address {}", addr)))
+}
+
+impl IBluetoothLeAudio for BluetoothLeAudio {
+    fn register_callback(
+        &mut self,
+        mut callback: Box<dyn IBluetoothLeAudioCallback + Send>,
+    ) -> u32 {
+        let tx = self.tx.clone();
+
+        let id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _result = tx.send(Message::LeAudioCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.callbacks.insert(id, callback);
+        id
+    }
+
+    fn unregister_callback(&mut self, callback_id: u32) -> bool {
+        self.remove_callback(callback_id)
+    }
+
+    fn connect(&mut self, addr: String) -> BtResult<()> {
+        let address = parse_address(&addr)?;
+        self.get_le_audio()?.connect(address);
+        Ok(())
+    }
+
+    fn disconnect(&mut self, addr: String) -> BtResult<()> {
+        let address = parse_address(&addr)?;
+        self.get_le_audio()?.disconnect(address);
+        Ok(())
+    }
+
+    fn get_connection_state(&self, addr: String) -> LeAudioConnectionState {
+        RawAddress::from_string(addr)
+            .and_then(|address| self.connection_states.get(&address).cloned())
+            .unwrap_or(LeAudioConnectionState::Disconnected)
+    }
+
+    fn get_group_id(&self, addr: String) -> i32 {
+        let address = match RawAddress::from_string(addr) {
+            Some(a) => a,
+            None => return LE_AUDIO_GROUP_ID_INVALID,
+        };
+
+        self.groups.group_of(&address)
+    }
+
+    fn get_group_status(&self, group_id: i32) -> BtResult<LeAudioGroupStatus> {
+        self.groups
+            .status(group_id)
+            .ok_or_else(|| BtError::not_found(format!("No group {}", group_id)))
+    }
+
+    fn group_add_node(&mut self, group_id: i32, addr: String) -> BtResult<()> {
+        let address = parse_address(&addr)?;
+        self.get_le_audio()?.group_add_node(group_id, address);
+        Ok(())
+    }
+
+    fn group_remove_node(&mut self, group_id: i32, addr: String) -> BtResult<()> {
+        let address = parse_address(&addr)?;
+        if !self.groups.is_member(group_id, &address) {
+            return Err(BtError::not_found(format!("{} is not in group {}", addr, group_id)));
+        }
+
+        self.get_le_audio()?.group_remove_node(group_id, address);
+        Ok(())
+    }
+
+    fn group_set_active(&mut self, group_id: i32) -> BtResult<()> {
+        if group_id != LE_AUDIO_GROUP_ID_INVALID && !self.groups.contains(group_id) {
+            return Err(BtError::not_found(format!("No group {}", group_id)));
+        }
+
+        self.get_le_audio()?.group_set_active(group_id);
+        Ok(())
+    }
+
+    fn get_group_stream_config(&self, group_id: i32) -> BtResult<LeAudioStreamConfig> {
+        self.groups
+            .stream_config(group_id)
+            .ok_or_else(|| BtError::not_found(format!("No stream configuration for {}", group_id)))
+    }
+
+    fn set_codec_config_preference(
+        &mut self,
+        group_id: i32,
+        input_codec: LeAudioCodecType,
+        output_codec: LeAudioCodecType,
+    ) -> BtResult<()> {
+        if input_codec == LeAudioCodecType::Invalid || output_codec == LeAudioCodecType::Invalid {
+            return Err(BtError::invalid_argument("Invalid codec"));
+        }
+
+        if !self.groups.contains(group_id) {
+            return Err(BtError::not_found(format!("No group {}", group_id)));
+        }
+
+        self.get_le_audio()?.set_codec_config_preference(group_id, input_codec, output_codec);
+        Ok(())
+    }
+
+    fn set_in_call(&mut self, in_call: bool) -> BtResult<()> {
+        self.get_le_audio()?.set_in_call(in_call);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(addr: &str) -> RawAddress {
+        RawAddress::from_string(addr).unwrap()
+    }
+
+    #[test]
+    fn test_group_members() {
+        let left = address("11:22:33:44:55:66");
+        let right = address("11:22:33:44:55:77");
+        let mut groups = LeAudioGroups::default();
+        assert_eq!(LE_AUDIO_GROUP_ID_INVALID, groups.group_of(&left));
+
+        groups.set_node_status(left, 1, LeAudioGroupNodeStatus::Added);
+        groups.set_node_status(right, 1, LeAudioGroupNodeStatus::Added);
+        assert_eq!(1, groups.group_of(&left));
+        assert!(groups.is_member(1, &right));
+        assert!(!groups.is_member(2, &right));
+        assert_eq!(Some(LeAudioGroupStatus::Inactive), groups.status(1));
+
+        groups.set_status(1, LeAudioGroupStatus::Active);
+        assert_eq!(Some(LeAudioGroupStatus::Active), groups.status(1));
+
+        // The group is dropped with its last member.
+        groups.set_node_status(left, 1, LeAudioGroupNodeStatus::Removed);
+        assert!(groups.contains(1));
+        assert_eq!(LE_AUDIO_GROUP_ID_INVALID, groups.group_of(&left));
+        groups.set_node_status(right, 1, LeAudioGroupNodeStatus::Removed);
+        assert!(!groups.contains(1));
+        assert_eq!(None, groups.status(1));
+    }
+
+    #[test]
+    fn test_group_stream_config() {
+        let mut groups = LeAudioGroups::default();
+        assert!(groups.stream_config(3).is_none());
+
+        let config = groups.set_audio_conf(3, AUDIO_DIRECTION_SINK, 0x3, 0, 0x4);
+        assert_eq!(3, config.group_id);
+        assert!(config.has_sink);
+        assert!(!config.has_source);
+        assert_eq!(0x3, config.sink_audio_locations);
+        assert_eq!(0x4, config.available_contexts);
+        assert_eq!(LeAudioCodecType::Lc3, config.output_codec);
+
+        // The codecs are kept when the streams change, and the other way around.
+        groups.set_codecs(3, LeAudioCodecType::Invalid, LeAudioCodecType::Lc3);
+        let config =
+            groups.set_audio_conf(3, AUDIO_DIRECTION_SINK | AUDIO_DIRECTION_SOURCE, 0x3, 0x1, 0x6);
+        assert!(config.has_source);
+        assert_eq!(0x1, config.source_audio_locations);
+        assert_eq!(LeAudioCodecType::Invalid, config.input_codec);
+
+        let config = groups.set_codecs(3, LeAudioCodecType::Lc3, LeAudioCodecType::Lc3);
+        assert_eq!(0x6, config.available_contexts);
+        assert_eq!(LeAudioCodecType::Lc3, groups.stream_config(3).unwrap().input_codec);
+    }
+}
@@ -3,18 +3,19 @@
 use bt_topshim::btif::{BluetoothInterface, RawAddress};
 use bt_topshim::profiles::a2dp::{
     A2dp, A2dpCallbacks, A2dpCallbacksDispatcher, A2dpCodecBitsPerSample, A2dpCodecChannelMode,
-    A2dpCodecConfig, A2dpCodecSampleRate, BtavConnectionState, PresentationPosition,
+    A2dpCodecConfig, A2dpCodecIndex, A2dpCodecPriority, A2dpCodecSampleRate, BtavConnectionState,
+    PresentationPosition,
 };
 use bt_topshim::profiles::avrcp::{Avrcp, AvrcpCallbacks, AvrcpCallbacksDispatcher};
 use bt_topshim::profiles::hfp::{
-    BthfAudioState, BthfConnectionState, Hfp, HfpCallbacks, HfpCallbacksDispatcher,
+    BthfAudioState, BthfCallState, BthfConnectionState, Hfp, HfpCallbacks, HfpCallbacksDispatcher,
     HfpCodecCapability,
 };
 
 use bt_topshim::topstack;
 
 use log::{info, warn};
-use num_traits::cast::ToPrimitive;
+use num_traits::cast::{FromPrimitive, ToPrimitive};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::sync::Arc;
@@ -25,10 +26,15 @@ use tokio::task::JoinHandle;
 use tokio::time::{sleep, Duration};
 
 use crate::bluetooth::{Bluetooth, BluetoothDevice, IBluetooth};
-use crate::Message;
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::uuid::{self, UuidHelper};
+use crate::{Message, RPCProxy};
 
 const DEFAULT_PROFILE_DISCOVERY_TIMEOUT_SEC: u64 = 5;
 
+/// Highest speaker volume of a hands-free unit.
+const HFP_MAX_VOLUME: u8 = 15;
+
 pub trait IBluetoothMedia {
     ///
     fn register_callback(&mut self, callback: Box<dyn IBluetoothMediaCallback + Send>) -> bool;
@@ -49,6 +55,19 @@ pub trait IBluetoothMedia {
         channel_mode: i32,
     ) -> bool;
     fn set_volume(&mut self, volume: i32);
+
+    /// Selects the A2DP source codec (`A2dpCodecIndex`, e.g. SBC, AAC or aptX) used with a
+    /// device, among the ones it supports. The new configuration is delivered with
+    /// `IBluetoothMediaCallback::on_a2dp_audio_config_changed`.
+    fn set_codec_preference(&mut self, device: String, codec_type: i32) -> bool;
+
+    /// Returns the A2DP codec configuration currently used with a device, with a codec type of
+    /// `A2dpCodecIndex::Max` if the device is not connected.
+    fn get_a2dp_audio_config(&self, device: String) -> A2dpCodecConfig;
+
+    /// Returns whether the connected device supports AVRCP absolute volume.
+    fn is_absolute_volume_supported(&self) -> bool;
+
     fn start_audio_request(&mut self);
     fn stop_audio_request(&mut self);
     fn get_presentation_position(&mut self) -> PresentationPosition;
@@ -72,6 +91,71 @@ pub trait IBluetoothMediaCallback {
 
     ///
     fn on_absolute_volume_changed(&self, volume: i32);
+
+    /// When the A2DP codec configuration used with a device changes, e.g. after
+    /// `IBluetoothMedia::set_codec_preference`.
+    fn on_a2dp_audio_config_changed(&self, addr: String, config: A2dpCodecConfig);
+}
+
+/// Defines the HFP audio gateway API, for the app handling the calls.
+pub trait IBluetoothTelephony {
+    /// Adds an observer of the calls and of the volume of the hands-free units.
+    ///
+    /// Returns the id of the callback.
+    fn register_telephony_callback(
+        &mut self,
+        callback: Box<dyn IBluetoothTelephonyCallback + Send>,
+    ) -> u32;
+
+    /// Removes an observer of the calls.
+    ///
+    /// Returns false if `callback_id` is not recognized.
+    fn unregister_telephony_callback(&mut self, callback_id: u32) -> bool;
+
+    /// Opens the SCO audio link to a hands-free unit.
+    fn connect_sco(&mut self, device: String) -> BtResult<()>;
+
+    /// Closes the SCO audio link to a hands-free unit.
+    fn disconnect_sco(&mut self, device: String) -> BtResult<()>;
+
+    /// Rings a hands-free unit for an incoming call from `number`.
+    fn incoming_call(&mut self, device: String, number: String) -> BtResult<()>;
+
+    /// Answers the incoming call.
+    fn answer_call(&mut self, device: String) -> BtResult<()>;
+
+    /// Hangs up the incoming or active call.
+    fn hangup_call(&mut self, device: String) -> BtResult<()>;
+
+    /// Returns the state of the call on a hands-free unit.
+    fn get_call_state(&self, device: String) -> CallState;
+
+    /// Sets the speaker volume of a hands-free unit, from 0 to 15.
+    fn set_speaker_volume(&mut self, device: String, volume: u8) -> BtResult<()>;
+}
+
+/// Call events of the HFP audio gateway.
+pub trait IBluetoothTelephonyCallback: RPCProxy {
+    /// When the call state changes, including when the hands-free unit answers or hangs up.
+    fn on_call_state_changed(&self, addr: String, state: CallState);
+
+    /// When the hands-free unit changes its speaker volume.
+    fn on_speaker_volume_changed(&self, addr: String, volume: u8);
+}
+
+/// State of the call on a hands-free unit.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum CallState {
+    Idle = 0,
+    Incoming = 1,
+    Active = 2,
+}
+
+impl Default for CallState {
+    fn default() -> Self {
+        CallState::Idle
+    }
 }
 
 /// Serializable device used in.
@@ -114,9 +198,12 @@ pub struct BluetoothMedia {
     hfp: Option<Hfp>,
     hfp_states: HashMap<RawAddress, BthfConnectionState>,
     selectable_caps: HashMap<RawAddress, Vec<A2dpCodecConfig>>,
+    audio_configs: HashMap<RawAddress, A2dpCodecConfig>,
     hfp_caps: HashMap<RawAddress, HfpCodecCapability>,
     device_added_tasks: Arc<Mutex<HashMap<RawAddress, Option<JoinHandle<()>>>>>,
     absolute_volume: bool,
+    telephony_callbacks: HashMap<u32, Box<dyn IBluetoothTelephonyCallback + Send>>,
+    call_states: HashMap<RawAddress, CallState>,
 }
 
 impl BluetoothMedia {
@@ -134,9 +221,12 @@ impl BluetoothMedia {
             hfp: None,
             hfp_states: HashMap::new(),
             selectable_caps: HashMap::new(),
+            audio_configs: HashMap::new(),
             hfp_caps: HashMap::new(),
             device_added_tasks: Arc::new(Mutex::new(HashMap::new())),
             absolute_volume: false,
+            telephony_callbacks: HashMap::new(),
+            call_states: HashMap::new(),
         }
     }
 
@@ -144,6 +234,20 @@ impl BluetoothMedia {
         self.adapter = Some(adapter);
     }
 
+    /// Forgets the codec and call state remembered for a device, see
+    /// `IBluetooth::remove_bond_cascade`.
+    pub(crate) fn forget_device(&mut self, address: &String) {
+        let addr = match RawAddress::from_string(address.clone()) {
+            Some(addr) => addr,
+            None => return,
+        };
+
+        self.selectable_caps.remove(&addr);
+        self.audio_configs.remove(&addr);
+        self.hfp_caps.remove(&addr);
+        self.call_states.remove(&addr);
+    }
+
     pub fn dispatch_a2dp_callbacks(&mut self, cb: A2dpCallbacks) {
         match cb {
             A2dpCallbacks::ConnectionState(addr, state) => {
@@ -152,6 +256,14 @@ impl BluetoothMedia {
                 {
                     return;
                 }
+                // The devices connecting on their own are refused too.
+                if matches!(state, BtavConnectionState::Connecting | BtavConnectionState::Connected)
+                    && !self.is_service_allowed(uuid::A2DP_SINK)
+                {
+                    info!("[{}]: a2dp blocked by policy.", addr.to_string());
+                    self.a2dp.as_mut().unwrap().disconnect(addr);
+                    return;
+                }
                 match state {
                     BtavConnectionState::Connected => {
                         info!("[{}]: a2dp connected.", addr.to_string());
@@ -159,7 +271,10 @@ impl BluetoothMedia {
                         self.a2dp_states.insert(addr, state);
                     }
                     BtavConnectionState::Disconnected => match self.a2dp_states.remove(&addr) {
-                        Some(_) => self.notify_media_capability_removed(addr),
+                        Some(_) => {
+                            self.audio_configs.remove(&addr);
+                            self.notify_media_capability_removed(addr);
+                        }
                         None => {
                             warn!("[{}]: Unknown address a2dp disconnected.", addr.to_string());
                         }
@@ -170,8 +285,12 @@ impl BluetoothMedia {
                 }
             }
             A2dpCallbacks::AudioState(_addr, _state) => {}
-            A2dpCallbacks::AudioConfig(addr, _config, _local_caps, selectable_caps) => {
+            A2dpCallbacks::AudioConfig(addr, config, _local_caps, selectable_caps) => {
                 self.selectable_caps.insert(addr, selectable_caps);
+                self.audio_configs.insert(addr, config);
+                self.for_all_callbacks(|callback| {
+                    callback.on_a2dp_audio_config_changed(addr.to_string(), config);
+                });
             }
             A2dpCallbacks::MandatoryCodecPreferred(_addr) => {}
         }
@@ -208,6 +327,17 @@ impl BluetoothMedia {
                 {
                     return;
                 }
+                if matches!(
+                    state,
+                    BthfConnectionState::Connecting
+                        | BthfConnectionState::Connected
+                        | BthfConnectionState::SlcConnected
+                ) && !self.is_service_allowed(uuid::HFP)
+                {
+                    info!("[{}]: hfp blocked by policy.", addr.to_string());
+                    self.hfp.as_mut().unwrap().disconnect(addr);
+                    return;
+                }
                 match state {
                     BthfConnectionState::Connected => {
                         info!("[{}]: hfp connected.", addr.to_string());
@@ -220,6 +350,7 @@ impl BluetoothMedia {
                     }
                     BthfConnectionState::Disconnected => {
                         info!("[{}]: hfp disconnected.", addr.to_string());
+                        self.call_states.remove(&addr);
                         match self.hfp_states.remove(&addr) {
                             Some(_) => self.notify_media_capability_removed(addr),
                             None => {
@@ -260,9 +391,81 @@ impl BluetoothMedia {
                     }
                 }
             }
+            HfpCallbacks::VolumeUpdate(volume, addr) => {
+                for (_, callback) in self.telephony_callbacks.iter() {
+                    callback.on_speaker_volume_changed(addr.to_string(), volume);
+                }
+            }
+            // Handled by the battery manager.
+            HfpCallbacks::BatteryLevel(..) => {}
+            HfpCallbacks::AnswerCall(addr) => {
+                if self.call_states.get(&addr) != Some(&CallState::Incoming) {
+                    warn!("[{}]: No incoming call to answer.", addr.to_string());
+                    return;
+                }
+                if let Err(e) = self.set_call_state(addr, CallState::Active, "") {
+                    warn!("[{}]: Failed to answer the call: {}", addr.to_string(), e);
+                }
+            }
+            HfpCallbacks::HangupCall(addr) => {
+                if self.call_states.get(&addr).map_or(true, |s| *s == CallState::Idle) {
+                    warn!("[{}]: No call to hang up.", addr.to_string());
+                    return;
+                }
+                if let Err(e) = self.set_call_state(addr, CallState::Idle, "") {
+                    warn!("[{}]: Failed to hang up the call: {}", addr.to_string(), e);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn remove_telephony_callback(&mut self, id: u32) -> bool {
+        match self.telephony_callbacks.get_mut(&id) {
+            Some(callback) => {
+                callback.unregister(id);
+                self.telephony_callbacks.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the address of a hands-free unit with an established service level connection.
+    fn get_slc_connected_address(&self, device: &String) -> BtResult<RawAddress> {
+        let addr = RawAddress::from_string(device.clone())
+            .ok_or_else(|| BtError::invalid_argument(format!("Invalid address {}", device)))?;
+
+        match self.hfp_states.get(&addr) {
+            Some(BthfConnectionState::SlcConnected) => Ok(addr),
+            _ => Err(BtError::new(
+                BtErrorCategory::NotReady,
+                format!("{} has no HFP service level connection", device),
+            )),
         }
     }
 
+    /// Reports the call state to the hands-free unit, then to the telephony callbacks.
+    fn set_call_state(&mut self, addr: RawAddress, state: CallState, number: &str) -> BtResult<()> {
+        let (num_active, call_setup_state) = match state {
+            CallState::Idle => (0, BthfCallState::Idle),
+            CallState::Incoming => (0, BthfCallState::Incoming),
+            CallState::Active => (1, BthfCallState::Idle),
+        };
+        BtError::from_status(self.hfp.as_mut().unwrap().phone_state_change(
+            num_active,
+            0,
+            call_setup_state,
+            number,
+            addr,
+        ))?;
+
+        self.call_states.insert(addr, state);
+        for (_, callback) in self.telephony_callbacks.iter() {
+            callback.on_call_state_changed(addr.to_string(), state);
+        }
+        Ok(())
+    }
+
     fn notify_media_capability_added(&self, addr: RawAddress) {
         // Return true if the device added message is sent by the call.
         fn dedup_added_cb(
@@ -382,6 +585,14 @@ impl BluetoothMedia {
         }
     }
 
+    /// Returns whether the admin policy allows connecting to `service` of the devices.
+    fn is_service_allowed(&self, service: &str) -> bool {
+        match (&self.adapter, UuidHelper::from_string(service)) {
+            (Some(adapter), Some(uuid)) => adapter.lock().unwrap().is_service_allowed(&uuid),
+            _ => true,
+        }
+    }
+
     fn adapter_get_remote_name(&self, addr: RawAddress) -> String {
         let device = BluetoothDevice::new(
             addr.to_string(),
@@ -531,6 +742,57 @@ impl IBluetoothMedia for BluetoothMedia {
         };
     }
 
+    fn set_codec_preference(&mut self, device: String, codec_type: i32) -> bool {
+        let addr = match RawAddress::from_string(device.clone()) {
+            Some(addr) => addr,
+            None => {
+                warn!("Invalid device string {}", device);
+                return false;
+            }
+        };
+
+        match A2dpCodecIndex::from_i32(codec_type) {
+            Some(index) if index < A2dpCodecIndex::SRC_MAX => {}
+            _ => {
+                warn!("[{}]: Invalid source codec {}", device, codec_type);
+                return false;
+            }
+        }
+
+        let capability = self
+            .selectable_caps
+            .get(&addr)
+            .and_then(|caps| caps.iter().find(|cap| cap.codec_type == codec_type));
+        let mut config = match capability {
+            Some(cap) => *cap,
+            None => {
+                warn!("[{}]: Codec {} is not supported by the device", device, codec_type);
+                return false;
+            }
+        };
+
+        // Let the stack pick the best sample format the codec supports.
+        config.codec_priority = A2dpCodecPriority::Highest as i32;
+        config.sample_rate = A2dpCodecSampleRate::RATE_NONE.bits();
+        config.bits_per_sample = A2dpCodecBitsPerSample::SAMPLE_NONE.bits();
+        config.channel_mode = A2dpCodecChannelMode::MODE_NONE.bits();
+
+        self.a2dp.as_mut().unwrap().config_codec(addr, vec![config]) == 0
+    }
+
+    fn get_a2dp_audio_config(&self, device: String) -> A2dpCodecConfig {
+        RawAddress::from_string(device)
+            .and_then(|addr| self.audio_configs.get(&addr).cloned())
+            .unwrap_or(A2dpCodecConfig {
+                codec_type: A2dpCodecIndex::Max as i32,
+                ..Default::default()
+            })
+    }
+
+    fn is_absolute_volume_supported(&self) -> bool {
+        self.absolute_volume
+    }
+
     fn start_audio_request(&mut self) {
         self.a2dp.as_mut().unwrap().start_audio_request();
     }
@@ -574,3 +836,81 @@ impl IBluetoothMedia for BluetoothMedia {
         }
     }
 }
+
+impl IBluetoothTelephony for BluetoothMedia {
+    fn register_telephony_callback(
+        &mut self,
+        mut callback: Box<dyn IBluetoothTelephonyCallback + Send>,
+    ) -> u32 {
+        let tx = self.tx.clone();
+
+        let id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _result = tx.send(Message::TelephonyCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.telephony_callbacks.insert(id, callback);
+        id
+    }
+
+    fn unregister_telephony_callback(&mut self, callback_id: u32) -> bool {
+        self.remove_telephony_callback(callback_id)
+    }
+
+    fn connect_sco(&mut self, device: String) -> BtResult<()> {
+        let addr = self.get_slc_connected_address(&device)?;
+        BtError::from_status(self.hfp.as_mut().unwrap().connect_audio(addr))
+    }
+
+    fn disconnect_sco(&mut self, device: String) -> BtResult<()> {
+        let addr = self.get_slc_connected_address(&device)?;
+        BtError::from_status(self.hfp.as_mut().unwrap().disconnect_audio(addr))
+    }
+
+    fn incoming_call(&mut self, device: String, number: String) -> BtResult<()> {
+        let addr = self.get_slc_connected_address(&device)?;
+        if self.call_states.get(&addr).map_or(false, |s| *s != CallState::Idle) {
+            return Err(BtError::new(BtErrorCategory::Busy, format!("{} has a call", device)));
+        }
+
+        self.set_call_state(addr, CallState::Incoming, &number)
+    }
+
+    fn answer_call(&mut self, device: String) -> BtResult<()> {
+        let addr = self.get_slc_connected_address(&device)?;
+        if self.call_states.get(&addr) != Some(&CallState::Incoming) {
+            return Err(BtError::new(
+                BtErrorCategory::NotReady,
+                format!("{} has no incoming call", device),
+            ));
+        }
+
+        self.set_call_state(addr, CallState::Active, "")
+    }
+
+    fn hangup_call(&mut self, device: String) -> BtResult<()> {
+        let addr = self.get_slc_connected_address(&device)?;
+        if self.call_states.get(&addr).map_or(true, |s| *s == CallState::Idle) {
+            return Err(BtError::new(BtErrorCategory::NotReady, format!("{} has no call", device)));
+        }
+
+        self.set_call_state(addr, CallState::Idle, "")
+    }
+
+    fn get_call_state(&self, device: String) -> CallState {
+        RawAddress::from_string(device)
+            .and_then(|addr| self.call_states.get(&addr).cloned())
+            .unwrap_or_default()
+    }
+
+    fn set_speaker_volume(&mut self, device: String, volume: u8) -> BtResult<()> {
+        if volume > HFP_MAX_VOLUME {
+            return Err(BtError::invalid_argument(format!("Invalid volume {}", volume)));
+        }
+
+        let addr = self.get_slc_connected_address(&device)?;
+        BtError::from_status(self.hfp.as_mut().unwrap().set_volume(volume as i8, addr))
+    }
+}
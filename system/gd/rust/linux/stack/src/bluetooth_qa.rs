@@ -0,0 +1,818 @@
+//! Bluetooth QA API, for manufacturing and certification tests.
+
+use bt_topshim::btif::{BaseCallbacks, BluetoothInterface, BtStatus, Uuid128Bit};
+use bt_topshim::controller::{Controller, ControllerCallbacks};
+
+use btif_macros::{btif_callback, btif_callbacks_dispatcher};
+
+use log::{debug, warn};
+use num_traits::cast::ToPrimitive;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::Sender;
+
+use crate::bluetooth::{Bluetooth, IBluetooth};
+use crate::bluetooth_gatt::BluetoothGatt;
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::{Message, RPCProxy};
+
+const HCI_LE_RECEIVER_TEST: u16 = 0x201D;
+const HCI_LE_TRANSMITTER_TEST: u16 = 0x201E;
+const HCI_LE_TEST_END: u16 = 0x201F;
+
+/// RSSI of an LE test whose controller reports none, as for HCI_Read_RSSI.
+pub const LE_TEST_RSSI_UNAVAILABLE: i32 = 127;
+
+/// Highest LE RF channel. Channel N is at 2402 + 2 * N MHz.
+const MAX_LE_TEST_CHANNEL: u8 = 39;
+
+/// Number of numeric parameters of a GATT test command.
+const GATT_TEST_PARAMS: usize = 5;
+
+/// Time after which the controller lists are read again if they were not delivered, the stack
+/// having been stopped meanwhile.
+const CONTROLLER_LISTS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Defines the QA API, to run the tests of the factory line and of the certification through the
+/// daemon.
+///
+/// The commands of the manual and certification tests acting on the controller or on the stack
+/// fail with `PermissionDenied` unless the daemon is started with `--enable-qa-commands`. The DUT
+/// mode and the LE tests of the factory line are always available.
+pub trait IBluetoothQA {
+    /// Adds an observer of the test events.
+    ///
+    /// Returns the id of the callback.
+    fn register_callback(&mut self, callback: Box<dyn IBluetoothQACallback + Send>) -> u32;
+
+    /// Removes an observer of the test events.
+    ///
+    /// Returns false if `callback_id` is not recognized.
+    fn unregister_callback(&mut self, callback_id: u32) -> bool;
+
+    /// Enters or exits the Device Under Test mode, in which a tester can control the controller
+    /// over the air.
+    fn enable_dut_mode(&mut self, enable: bool) -> BtResult<()>;
+
+    /// Returns whether the Device Under Test mode is enabled.
+    fn is_dut_mode_enabled(&self) -> bool;
+
+    /// Sends an HCI command to the controller in Device Under Test mode, such as the vendor
+    /// command reading the RSSI of the received test packets. The events of the controller are
+    /// delivered with `IBluetoothQACallback::on_dut_event`.
+    fn send_dut_command(&mut self, opcode: u16, params: Vec<u8>) -> BtResult<()>;
+
+    /// Starts transmitting `data_length` bytes long test packets on the LE RF `channel`.
+    fn start_le_transmitter_test(
+        &mut self,
+        channel: u8,
+        data_length: u8,
+        payload: LeTestPayload,
+    ) -> BtResult<()>;
+
+    /// Starts receiving test packets on the LE RF `channel`.
+    fn start_le_receiver_test(&mut self, channel: u8) -> BtResult<()>;
+
+    /// Ends the running LE test. The result is delivered with
+    /// `IBluetoothQACallback::on_le_test_status`.
+    fn end_le_test(&mut self) -> BtResult<()>;
+
+    /// Returns the state of the running LE test, or the result of the last one.
+    fn get_le_test_result(&self) -> LeTestResult;
+
+    /// Returns whether the QA commands are enabled in the daemon.
+    fn is_qa_enabled(&self) -> bool;
+
+    /// Sends a GATT test command to the stack. `address` and `uuid` are only used by the
+    /// commands taking a device or a UUID, `address` may be empty otherwise. `params` are the
+    /// numeric parameters of the command, up to 5, missing ones being 0.
+    fn send_gatt_test_command(
+        &mut self,
+        command: GattTestCommand,
+        address: String,
+        uuid: Uuid128Bit,
+        params: Vec<u16>,
+    ) -> BtResult<()>;
+
+    /// Sets the scan mode of the adapter through its connectable and discoverable settings, as
+    /// `IBluetooth::set_discoverable` does. The adapter stays discoverable for `duration` seconds,
+    /// 0 meaning no limit.
+    fn set_scan_mode(&mut self, mode: QAScanMode, duration: u32) -> BtResult<()>;
+
+    /// Returns the version and the features of the controller, once the adapter is enabled.
+    fn get_controller_info(&mut self) -> BtResult<ControllerInfo>;
+
+    /// Reads the contents of the LE lists of the controller, to debug reconnections prevented by
+    /// stale entries after the bonds stored on the host changed. The lists are delivered with
+    /// `IBluetoothQACallback::on_controller_lists`.
+    fn get_controller_lists(&mut self) -> BtResult<()>;
+
+    /// Clears the LE list `kind` of the controller.
+    fn clear_controller_lists(&mut self, kind: ControllerListKind) -> BtResult<()>;
+
+    /// Steps the TX power of an advertising set from `min_level` to `max_level` dBm by `step` dB
+    /// for range testing, staying `dwell_ms` at each level, and starts over after the highest
+    /// level until `stop_tx_power_sweep` is called or the set is stopped. The power applied by
+    /// the controller at each level is logged and delivered with
+    /// `IAdvertisingSetCallback::on_advertising_parameters_updated`.
+    fn start_tx_power_sweep(
+        &mut self,
+        advertiser_id: i32,
+        min_level: i32,
+        max_level: i32,
+        step: i32,
+        dwell_ms: i32,
+    ) -> BtResult<()>;
+
+    /// Stops the sweep started with `start_tx_power_sweep`, setting the set back to its
+    /// configured TX power.
+    fn stop_tx_power_sweep(&mut self, advertiser_id: i32) -> BtResult<()>;
+}
+
+/// QA events.
+pub trait IBluetoothQACallback: RPCProxy {
+    /// When the controller sends an HCI event in Device Under Test mode.
+    fn on_dut_event(&self, opcode: u16, data: Vec<u8>);
+
+    /// When an LE test is started or ended, or failed to be.
+    fn on_le_test_status(&self, result: LeTestResult);
+
+    /// When the LE lists of the controller are read, see `IBluetoothQA::get_controller_lists`.
+    fn on_controller_lists(&self, lists: ControllerLists);
+}
+
+/// Packet payloads of the LE transmitter test.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum LeTestPayload {
+    Prbs9 = 0,
+    Pattern11110000 = 1,
+    Pattern10101010 = 2,
+    Prbs15 = 3,
+    AllOnes = 4,
+    AllZeros = 5,
+    Pattern00001111 = 6,
+    Pattern01010101 = 7,
+}
+
+/// Kind of LE test.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum LeTestMode {
+    None = 0,
+    Transmitter = 1,
+    Receiver = 2,
+}
+
+impl Default for LeTestMode {
+    fn default() -> Self {
+        LeTestMode::None
+    }
+}
+
+/// GATT test commands of the stack.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum GattTestCommand {
+    /// Enables (param 1 is 1) or disables the test GATT client.
+    Enable = 0x01,
+    /// Connects to `address`, with the device type in param 1 and the address type in param 2.
+    Connect = 0x02,
+    /// Disconnects from `address`.
+    Disconnect = 0x03,
+    /// Discovers on the connected device, with the discovery type in param 1, the UUID to
+    /// discover and the handle range in params 2 and 3.
+    Discover = 0x04,
+    /// Sets the pairing configuration: auth requirement, IO capabilities, initiator and
+    /// responder keys, and maximum key size, in params 1 to 5.
+    PairingConfig = 0xF0,
+}
+
+/// Scan modes of the adapter.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum QAScanMode {
+    None = 0,
+    Connectable = 1,
+    ConnectableDiscoverable = 2,
+}
+
+impl QAScanMode {
+    /// Connectable and discoverable settings of the adapter in this mode.
+    fn settings(&self) -> (bool, bool) {
+        match self {
+            QAScanMode::None => (false, false),
+            QAScanMode::Connectable => (true, false),
+            QAScanMode::ConnectableDiscoverable => (true, true),
+        }
+    }
+}
+
+/// Version and features of the controller.
+#[derive(Clone, Debug, Default)]
+pub struct ControllerInfo {
+    pub hci_version: u8,
+    pub hci_revision: u16,
+    pub lmp_version: u8,
+    pub lmp_subversion: u16,
+    /// Company identifier of the manufacturer.
+    pub manufacturer: u16,
+    /// Bit mask of the LE Read Local Supported Features command.
+    pub le_features: u64,
+    pub secure_connections: bool,
+    pub simultaneous_le_bredr: bool,
+}
+
+/// LE lists of the controller.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum ControllerListKind {
+    FilterAcceptList = 0,
+    ResolvingList = 1,
+    PeriodicAdvertiserList = 2,
+}
+
+/// Contents of the LE lists of the controller, as mirrored by the host since the controller
+/// cannot read them back. The entries are the addresses followed by their type.
+#[derive(Clone, Debug, Default)]
+pub struct ControllerLists {
+    pub filter_accept_list: Vec<String>,
+    pub resolving_list: Vec<String>,
+    pub periodic_advertiser_list: Vec<String>,
+}
+
+/// State of the running LE test, or result of the last one.
+#[derive(Clone, Debug, Default)]
+pub struct LeTestResult {
+    pub mode: LeTestMode,
+    pub channel: u8,
+    pub in_progress: bool,
+    /// `BtStatus` of the last test command.
+    pub status: u32,
+    /// Number of packets received by the receiver test, once ended.
+    pub num_packets: u16,
+    /// Average RSSI of the packets received by the receiver test in dBm, once ended, or
+    /// `LE_TEST_RSSI_UNAVAILABLE` if the controller does not append it to the report of the test.
+    pub rssi: i32,
+}
+
+/// Bookkeeping of the LE test, which runs on the controller until it is ended.
+#[derive(Default)]
+struct LeTest {
+    result: LeTestResult,
+    // Whether the pending LE test command ends the test.
+    ending: bool,
+}
+
+impl LeTest {
+    /// Checks that a test of `mode` can be started on `channel`, and returns the opcode of the
+    /// command starting it.
+    fn check_start(&self, mode: LeTestMode, channel: u8) -> BtResult<u16> {
+        if self.result.in_progress {
+            return Err(BtError::new(BtErrorCategory::Busy, "An LE test is running"));
+        }
+
+        if channel > MAX_LE_TEST_CHANNEL {
+            return Err(BtError::invalid_argument(format!("Invalid LE channel {}", channel)));
+        }
+
+        Ok(match mode {
+            LeTestMode::Transmitter => HCI_LE_TRANSMITTER_TEST,
+            _ => HCI_LE_RECEIVER_TEST,
+        })
+    }
+
+    fn started(&mut self, mode: LeTestMode, channel: u8) {
+        self.result = LeTestResult {
+            mode,
+            channel,
+            in_progress: true,
+            status: 0,
+            num_packets: 0,
+            rssi: LE_TEST_RSSI_UNAVAILABLE,
+        };
+        self.ending = false;
+    }
+
+    fn check_end(&self) -> BtResult<()> {
+        if !self.result.in_progress {
+            return Err(BtError::new(BtErrorCategory::NotReady, "No LE test is running"));
+        }
+        Ok(())
+    }
+
+    fn ended(&mut self) {
+        self.ending = true;
+    }
+
+    /// Records the status of the pending test command and returns its opcode, or None if no test
+    /// is running. The test is over once ended, or if it failed to start.
+    fn on_status(&mut self, status: &BtStatus, num_packets: u16) -> Option<u16> {
+        if !self.result.in_progress {
+            return None;
+        }
+
+        let opcode = match (self.ending, self.result.mode) {
+            (true, _) => HCI_LE_TEST_END,
+            (false, LeTestMode::Transmitter) => HCI_LE_TRANSMITTER_TEST,
+            (false, _) => HCI_LE_RECEIVER_TEST,
+        };
+
+        self.result.status = status.to_u32().unwrap_or_default();
+        if self.ending || *status != BtStatus::Success {
+            self.result.in_progress = false;
+            self.result.num_packets = num_packets;
+            self.ending = false;
+        }
+        Some(opcode)
+    }
+
+    /// Records the report of the test end, see `on_status`.
+    fn on_end(&mut self, status: &BtStatus, num_packets: u16, rssi: i8) -> Option<u16> {
+        let opcode = self.on_status(status, num_packets)?;
+        if self.result.mode == LeTestMode::Receiver && *status == BtStatus::Success {
+            self.result.rssi = rssi.into();
+        }
+        Some(opcode)
+    }
+
+    fn notify(&self, callbacks: &HashMap<u32, Box<dyn IBluetoothQACallback + Send>>) {
+        for (_, callback) in callbacks.iter() {
+            callback.on_le_test_status(self.result.clone());
+        }
+    }
+}
+
+/// Bookkeeping of the reads of the controller lists, which the stack gathers from its threads
+/// without blocking the daemon. The reads requested before the lists are delivered share them.
+#[derive(Default)]
+struct ControllerListsRead {
+    since: Option<Instant>,
+}
+
+impl ControllerListsRead {
+    /// Returns whether a read started less than `CONTROLLER_LISTS_TIMEOUT` before `now` is still
+    /// pending.
+    fn is_pending(&self, now: Instant) -> bool {
+        matches!(self.since, Some(since) if now.duration_since(since) < CONTROLLER_LISTS_TIMEOUT)
+    }
+
+    fn started(&mut self, now: Instant) {
+        self.since = Some(now);
+    }
+
+    /// Returns whether the lists delivered were read, the lists of a read already delivered
+    /// being dropped.
+    fn completed(&mut self) -> bool {
+        self.since.take().is_some()
+    }
+}
+
+/// Pads the numeric parameters of a GATT test command with zeros.
+fn gatt_test_params(params: &[u16]) -> BtResult<[u16; GATT_TEST_PARAMS]> {
+    if params.len() > GATT_TEST_PARAMS {
+        return Err(BtError::invalid_argument("Too many test command parameters"));
+    }
+
+    let mut test_params = [0u16; GATT_TEST_PARAMS];
+    test_params[..params.len()].copy_from_slice(params);
+    Ok(test_params)
+}
+
+/// Implementation of the QA API.
+pub struct BluetoothQA {
+    intf: Arc<Mutex<BluetoothInterface>>,
+    tx: Sender<Message>,
+    adapter: Option<Arc<Mutex<Box<Bluetooth>>>>,
+    gatt: Option<Arc<Mutex<Box<BluetoothGatt>>>>,
+    callbacks: HashMap<u32, Box<dyn IBluetoothQACallback + Send>>,
+    commands_enabled: bool,
+    dut_mode_enabled: bool,
+    le_test: LeTest,
+    controller_lists_read: ControllerListsRead,
+}
+
+impl BluetoothQA {
+    pub fn new(tx: Sender<Message>, intf: Arc<Mutex<BluetoothInterface>>) -> BluetoothQA {
+        BluetoothQA {
+            intf,
+            tx,
+            adapter: None,
+            gatt: None,
+            callbacks: HashMap::new(),
+            commands_enabled: false,
+            dut_mode_enabled: false,
+            le_test: LeTest::default(),
+            controller_lists_read: ControllerListsRead::default(),
+        }
+    }
+
+    pub fn set_adapter(&mut self, adapter: Arc<Mutex<Box<Bluetooth>>>) {
+        self.adapter = Some(adapter);
+    }
+
+    pub fn set_gatt(&mut self, gatt: Arc<Mutex<Box<BluetoothGatt>>>) {
+        self.gatt = Some(gatt);
+    }
+
+    /// Enables the commands of the manual and certification tests, disabled by default so that
+    /// they are not reachable on production devices.
+    pub fn set_commands_enabled(&mut self, enabled: bool) {
+        self.commands_enabled = enabled;
+    }
+
+    /// Runs `f` on the controller, once the adapter is enabled. The adapter stays locked while `f`
+    /// runs, so `f` must not wait for the stack.
+    fn with_controller<T, F: FnOnce(&mut Controller) -> T>(&self, f: F) -> BtResult<T> {
+        let adapter = self
+            .adapter
+            .as_ref()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "Adapter is not ready"))?;
+        let mut adapter = adapter.lock().unwrap();
+        let controller = adapter
+            .get_controller()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "Adapter is not enabled"))?;
+        Ok(f(controller))
+    }
+
+    fn check_commands_enabled(&self) -> BtResult<()> {
+        if !self.commands_enabled {
+            return Err(BtError::new(
+                BtErrorCategory::PermissionDenied,
+                "QA commands are not enabled",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn dispatch_controller_callbacks(&mut self, cb: ControllerCallbacks) {
+        match cb {
+            ControllerCallbacks::ControllerLists(
+                filter_accept_list,
+                resolving_list,
+                periodic_advertiser_list,
+            ) => {
+                if !self.controller_lists_read.completed() {
+                    return;
+                }
+
+                let lists = ControllerLists {
+                    filter_accept_list,
+                    resolving_list,
+                    periodic_advertiser_list,
+                };
+                for (_, callback) in self.callbacks.iter() {
+                    callback.on_controller_lists(lists.clone());
+                }
+            }
+            ControllerCallbacks::LeTestEnded(status, num_packets, rssi) => {
+                let status = if status == 0 { BtStatus::Success } else { BtStatus::Fail };
+                if self.le_test.on_end(&status, num_packets, rssi).is_none() {
+                    warn!("Unexpected LE test end {:?}", status);
+                    return;
+                }
+
+                self.le_test.notify(&self.callbacks);
+            }
+            // Delivered to GATT.
+            ControllerCallbacks::CommandLatency(..) => (),
+        }
+    }
+
+    pub(crate) fn remove_callback(&mut self, id: u32) -> bool {
+        match self.callbacks.get_mut(&id) {
+            Some(callback) => {
+                callback.unregister(id);
+                self.callbacks.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn start_le_test(
+        &mut self,
+        mode: LeTestMode,
+        channel: u8,
+        mut params: Vec<u8>,
+    ) -> BtResult<()> {
+        let opcode = self.le_test.check_start(mode, channel)?;
+        BtError::from_status(self.intf.lock().unwrap().le_test_mode(opcode, &mut params))?;
+
+        self.le_test.started(mode, channel);
+        Ok(())
+    }
+}
+
+impl IBluetoothQA for BluetoothQA {
+    fn register_callback(&mut self, mut callback: Box<dyn IBluetoothQACallback + Send>) -> u32 {
+        let tx = self.tx.clone();
+
+        let id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _result = tx.send(Message::QACallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.callbacks.insert(id, callback);
+        id
+    }
+
+    fn unregister_callback(&mut self, callback_id: u32) -> bool {
+        self.remove_callback(callback_id)
+    }
+
+    fn enable_dut_mode(&mut self, enable: bool) -> BtResult<()> {
+        BtError::from_status(self.intf.lock().unwrap().dut_mode_configure(enable))?;
+
+        debug!("DUT mode {}", if enable { "enabled" } else { "disabled" });
+        self.dut_mode_enabled = enable;
+        Ok(())
+    }
+
+    fn is_dut_mode_enabled(&self) -> bool {
+        self.dut_mode_enabled
+    }
+
+    fn send_dut_command(&mut self, opcode: u16, mut params: Vec<u8>) -> BtResult<()> {
+        if !self.dut_mode_enabled {
+            return Err(BtError::new(BtErrorCategory::NotReady, "DUT mode is not enabled"));
+        }
+
+        if params.len() > u8::MAX as usize {
+            return Err(BtError::invalid_argument("Command parameters are too long"));
+        }
+
+        BtError::from_status(self.intf.lock().unwrap().dut_mode_send(opcode, &mut params))
+    }
+
+    fn start_le_transmitter_test(
+        &mut self,
+        channel: u8,
+        data_length: u8,
+        payload: LeTestPayload,
+    ) -> BtResult<()> {
+        let params = vec![channel, data_length, payload.to_u8().unwrap()];
+        self.start_le_test(LeTestMode::Transmitter, channel, params)
+    }
+
+    fn start_le_receiver_test(&mut self, channel: u8) -> BtResult<()> {
+        self.start_le_test(LeTestMode::Receiver, channel, vec![channel])
+    }
+
+    fn end_le_test(&mut self) -> BtResult<()> {
+        self.le_test.check_end()?;
+
+        // The report of the test end is delivered with `ControllerCallbacks::LeTestEnded`.
+        self.with_controller(|controller| controller.end_le_test())?;
+        self.le_test.ended();
+        Ok(())
+    }
+
+    fn get_le_test_result(&self) -> LeTestResult {
+        self.le_test.result.clone()
+    }
+
+    fn is_qa_enabled(&self) -> bool {
+        self.commands_enabled
+    }
+
+    fn send_gatt_test_command(
+        &mut self,
+        command: GattTestCommand,
+        address: String,
+        uuid: Uuid128Bit,
+        params: Vec<u16>,
+    ) -> BtResult<()> {
+        self.check_commands_enabled()?;
+        let test_params = gatt_test_params(&params)?;
+
+        let gatt = self
+            .gatt
+            .as_ref()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "GATT is not ready"))?;
+        debug!("GATT test command {:?} {:?}", command, test_params);
+        gatt.lock().unwrap().run_test_command(
+            command.to_i32().unwrap(),
+            &address,
+            uuid,
+            test_params,
+        )
+    }
+
+    fn set_scan_mode(&mut self, mode: QAScanMode, duration: u32) -> BtResult<()> {
+        self.check_commands_enabled()?;
+
+        let adapter = self
+            .adapter
+            .as_ref()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "Adapter is not ready"))?;
+        let mut adapter = adapter.lock().unwrap();
+        // The adapter keeps the connectable setting, to fall back to once it is not discoverable
+        // anymore.
+        let (connectable, discoverable) = mode.settings();
+        if !adapter.set_connectable(connectable) {
+            return Err(BtError::new(BtErrorCategory::Failed, "Failed to set the scan mode"));
+        }
+        adapter.set_discoverable(discoverable, duration)
+    }
+
+    fn get_controller_info(&mut self) -> BtResult<ControllerInfo> {
+        self.with_controller(|controller| {
+            let version = controller.read_local_version();
+            ControllerInfo {
+                hci_version: version.hci_version,
+                hci_revision: version.hci_revision,
+                lmp_version: version.lmp_version,
+                lmp_subversion: version.lmp_subversion,
+                manufacturer: version.manufacturer,
+                le_features: controller.read_le_local_features(),
+                secure_connections: controller.supports_secure_connections(),
+                simultaneous_le_bredr: controller.supports_simultaneous_le_bredr(),
+            }
+        })
+    }
+
+    fn get_controller_lists(&mut self) -> BtResult<()> {
+        self.check_commands_enabled()?;
+
+        let now = Instant::now();
+        if self.controller_lists_read.is_pending(now) {
+            return Ok(());
+        }
+        self.with_controller(|controller| controller.read_controller_lists())?;
+        self.controller_lists_read.started(now);
+        Ok(())
+    }
+
+    fn clear_controller_lists(&mut self, kind: ControllerListKind) -> BtResult<()> {
+        self.check_commands_enabled()?;
+
+        debug!("Clearing the controller {:?}", kind);
+        self.with_controller(|controller| match kind {
+            ControllerListKind::FilterAcceptList => controller.clear_filter_accept_list(),
+            ControllerListKind::ResolvingList => controller.clear_resolving_list(),
+            ControllerListKind::PeriodicAdvertiserList => {
+                controller.clear_periodic_advertiser_list()
+            }
+        })
+    }
+
+    fn start_tx_power_sweep(
+        &mut self,
+        advertiser_id: i32,
+        min_level: i32,
+        max_level: i32,
+        step: i32,
+        dwell_ms: i32,
+    ) -> BtResult<()> {
+        self.check_commands_enabled()?;
+
+        let gatt = self
+            .gatt
+            .as_ref()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "GATT is not ready"))?;
+        gatt.lock().unwrap().start_tx_power_sweep(
+            advertiser_id,
+            min_level,
+            max_level,
+            step,
+            dwell_ms,
+        )
+    }
+
+    fn stop_tx_power_sweep(&mut self, advertiser_id: i32) -> BtResult<()> {
+        self.check_commands_enabled()?;
+
+        let gatt = self
+            .gatt
+            .as_ref()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "GATT is not ready"))?;
+        gatt.lock().unwrap().stop_tx_power_sweep(advertiser_id)
+    }
+}
+
+#[btif_callbacks_dispatcher(BluetoothQA, dispatch_base_callbacks, BaseCallbacks)]
+pub(crate) trait BtifBluetoothQACallbacks {
+    #[btif_callback(DutModeRecv)]
+    fn dut_mode_recv(&mut self, opcode: u16, data: Vec<u8>, len: u8);
+
+    #[btif_callback(LeTestMode)]
+    fn le_test_mode(&mut self, status: BtStatus, num_packets: u16);
+}
+
+impl BtifBluetoothQACallbacks for BluetoothQA {
+    fn dut_mode_recv(&mut self, opcode: u16, data: Vec<u8>, _len: u8) {
+        for (_, callback) in self.callbacks.iter() {
+            callback.on_dut_event(opcode, data.clone());
+        }
+    }
+
+    fn le_test_mode(&mut self, status: BtStatus, num_packets: u16) {
+        if self.le_test.on_status(&status, num_packets).is_none() {
+            warn!("Unexpected LE test status {:?}", status);
+            return;
+        }
+
+        self.le_test.notify(&self.callbacks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_le_test_receiver() {
+        let mut le_test = LeTest::default();
+        assert_eq!(BtErrorCategory::NotReady, le_test.check_end().unwrap_err().category);
+        assert_eq!(None, le_test.on_status(&BtStatus::Success, 0));
+
+        assert!(le_test.check_start(LeTestMode::Receiver, MAX_LE_TEST_CHANNEL + 1).is_err());
+        assert_eq!(Ok(HCI_LE_RECEIVER_TEST), le_test.check_start(LeTestMode::Receiver, 12));
+        le_test.started(LeTestMode::Receiver, 12);
+        assert_eq!(
+            BtErrorCategory::Busy,
+            le_test.check_start(LeTestMode::Transmitter, 12).unwrap_err().category
+        );
+
+        // The test keeps running once started.
+        assert_eq!(Some(HCI_LE_RECEIVER_TEST), le_test.on_status(&BtStatus::Success, 0));
+        assert!(le_test.result.in_progress);
+        assert_eq!(LE_TEST_RSSI_UNAVAILABLE, le_test.result.rssi);
+
+        // The received packets are counted when the test ends.
+        assert_eq!(Ok(()), le_test.check_end());
+        le_test.ended();
+        assert_eq!(Some(HCI_LE_TEST_END), le_test.on_end(&BtStatus::Success, 42, -67));
+        assert!(!le_test.result.in_progress);
+        assert_eq!(LeTestMode::Receiver, le_test.result.mode);
+        assert_eq!(12, le_test.result.channel);
+        assert_eq!(42, le_test.result.num_packets);
+        assert_eq!(-67, le_test.result.rssi);
+    }
+
+    #[test]
+    fn test_le_test_failed_start() {
+        let mut le_test = LeTest::default();
+        assert_eq!(Ok(HCI_LE_TRANSMITTER_TEST), le_test.check_start(LeTestMode::Transmitter, 0));
+        le_test.started(LeTestMode::Transmitter, 0);
+
+        assert_eq!(Some(HCI_LE_TRANSMITTER_TEST), le_test.on_status(&BtStatus::Fail, 0));
+        assert!(!le_test.result.in_progress);
+        assert_eq!(BtStatus::Fail.to_u32().unwrap(), le_test.result.status);
+
+        // Another test can be started.
+        assert!(le_test.check_start(LeTestMode::Transmitter, 0).is_ok());
+    }
+
+    #[test]
+    fn test_gatt_test_params() {
+        assert_eq!(Ok([0, 0, 0, 0, 0]), gatt_test_params(&[]));
+        assert_eq!(Ok([1, 2, 0, 0, 0]), gatt_test_params(&[1, 2]));
+        assert_eq!(Ok([1, 2, 3, 4, 5]), gatt_test_params(&[1, 2, 3, 4, 5]));
+        assert!(gatt_test_params(&[1, 2, 3, 4, 5, 6]).is_err());
+    }
+
+    #[test]
+    fn test_controller_lists_read() {
+        let mut read = ControllerListsRead::default();
+        let now = Instant::now();
+        assert!(!read.is_pending(now));
+        // Lists nobody asked for are dropped.
+        assert!(!read.completed());
+
+        // The reads requested meanwhile share the pending one.
+        read.started(now);
+        assert!(read.is_pending(now + Duration::from_millis(500)));
+        assert!(read.completed());
+        assert!(!read.is_pending(now + Duration::from_millis(500)));
+        assert!(!read.completed());
+    }
+
+    #[test]
+    fn test_controller_lists_read_timeout() {
+        let mut read = ControllerListsRead::default();
+        let now = Instant::now();
+        read.started(now);
+
+        // The lists are read again if the stack never delivered them.
+        assert!(!read.is_pending(now + CONTROLLER_LISTS_TIMEOUT));
+        let later = now + CONTROLLER_LISTS_TIMEOUT;
+        read.started(later);
+        assert!(read.is_pending(later));
+
+        // The lists of the first read complete the second one, and the late ones are dropped.
+        assert!(read.completed());
+        assert!(!read.completed());
+    }
+
+    #[test]
+    fn test_qa_scan_mode() {
+        assert_eq!((false, false), QAScanMode::None.settings());
+        assert_eq!((true, false), QAScanMode::Connectable.settings());
+        assert_eq!((true, true), QAScanMode::ConnectableDiscoverable.settings());
+    }
+}
@@ -0,0 +1,213 @@
+//! Arbitration of the connection parameters requested for the LE link of a device.
+//!
+//! The parameters of a link are requested by its link tuning profile, by the clients asking for a
+//! connection priority, whose presets are defined here, and by the clients updating the
+//! parameters directly. The most demanding request applies to the link, so that a client asking
+//! for a low power link does not slow down another one transferring data, nor an input device
+//! tuned for a low latency.
+
+use std::collections::HashMap;
+
+use crate::link_tuning::LinkParameters;
+
+/// Priority of a connection, see `IBluetoothGatt::set_connection_priority`. Ordered from the
+/// lowest to the highest priority.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ConnectionPriority {
+    /// Long connection intervals with peripheral latency, for links with little traffic.
+    LowPower = 0,
+    /// Parameters suitable for most links.
+    Balanced = 1,
+    /// Short connection intervals, for fast transfers or a low latency.
+    High = 2,
+}
+
+impl Default for ConnectionPriority {
+    fn default() -> Self {
+        ConnectionPriority::Balanced
+    }
+}
+
+/// Connection parameters of a link, as given to the LE Connection Update command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ConnectionParameters {
+    /// Minimum and maximum connection intervals in 1.25 ms units.
+    pub interval: (i32, i32),
+    pub latency: i32,
+    /// Supervision timeout in 10 ms units.
+    pub timeout: i32,
+    /// Minimum and maximum lengths of the connection events in 0.625 ms units.
+    pub ce_len: (u16, u16),
+}
+
+impl From<LinkParameters> for ConnectionParameters {
+    fn from(parameters: LinkParameters) -> Self {
+        ConnectionParameters {
+            interval: parameters.interval,
+            latency: parameters.latency,
+            timeout: parameters.timeout,
+            ce_len: (0, 0),
+        }
+    }
+}
+
+/// Returns the connection parameters applied for `priority`.
+pub(crate) fn parameters(priority: ConnectionPriority) -> ConnectionParameters {
+    let (interval, latency) = match priority {
+        // 100 to 125 ms, the peripheral skipping up to 2 connection events.
+        ConnectionPriority::LowPower => ((80, 100), 2),
+        // 30 to 50 ms.
+        ConnectionPriority::Balanced => ((24, 40), 0),
+        // 11.25 to 15 ms.
+        ConnectionPriority::High => ((9, 12), 0),
+    };
+    ConnectionParameters { interval, latency, timeout: 500, ce_len: (0, 0) }
+}
+
+/// Connection parameters requested for the link of a device.
+#[derive(Debug, Default)]
+pub(crate) struct ParameterRequests {
+    // Parameters of the link tuning profile of the device.
+    tuning: Option<ConnectionParameters>,
+    // Parameters requested by the clients connected to the device, by client ID, either as a
+    // priority or directly.
+    requests: HashMap<i32, ConnectionParameters>,
+}
+
+impl ParameterRequests {
+    /// Records the parameters of the link tuning profile of the device. Returns the parameters to
+    /// apply to the link if they changed.
+    pub fn set_tuning(
+        &mut self,
+        tuning: Option<ConnectionParameters>,
+    ) -> Option<ConnectionParameters> {
+        self.update(|requests| requests.tuning = tuning)
+    }
+
+    /// Records the parameters requested by a client, replacing its earlier request. Returns the
+    /// parameters to apply to the link if they changed.
+    pub fn request(
+        &mut self,
+        client_id: i32,
+        parameters: ConnectionParameters,
+    ) -> Option<ConnectionParameters> {
+        self.update(|requests| {
+            requests.requests.insert(client_id, parameters);
+        })
+    }
+
+    /// Drops the request of a client. Returns the parameters to apply to the link if they
+    /// changed. The link keeps its parameters once no request is left.
+    pub fn remove(&mut self, client_id: i32) -> Option<ConnectionParameters> {
+        self.update(|requests| {
+            requests.requests.remove(&client_id);
+        })
+    }
+
+    /// Returns the parameters applied to the link, the most demanding ones requested: those with
+    /// the shortest connection interval, then with the lowest latency.
+    pub fn effective(&self) -> Option<ConnectionParameters> {
+        self.requests.values().chain(self.tuning.iter()).cloned().min_by_key(|p| {
+            (p.interval.1, p.interval.0, p.latency, p.timeout, p.ce_len.0, p.ce_len.1)
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tuning.is_none() && self.requests.is_empty()
+    }
+
+    fn update(&mut self, f: impl FnOnce(&mut Self)) -> Option<ConnectionParameters> {
+        let before = self.effective();
+        f(self);
+        self.effective().filter(|p| before != Some(*p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(max_interval: i32, latency: i32) -> ConnectionParameters {
+        ConnectionParameters {
+            interval: (max_interval, max_interval),
+            latency,
+            timeout: 300,
+            ce_len: (2, 4),
+        }
+    }
+
+    #[test]
+    fn test_arbitration() {
+        let mut requests = ParameterRequests::default();
+        let low_power = parameters(ConnectionPriority::LowPower);
+        let high = parameters(ConnectionPriority::High);
+        assert_eq!(None, requests.effective());
+
+        assert_eq!(Some(low_power), requests.request(1, low_power));
+        assert_eq!(Some(high), requests.request(2, high));
+        // A lower priority of another client does not slow the link down.
+        assert_eq!(None, requests.request(1, parameters(ConnectionPriority::Balanced)));
+        assert_eq!(None, requests.request(2, high));
+        assert_eq!(Some(high), requests.effective());
+
+        assert_eq!(Some(parameters(ConnectionPriority::Balanced)), requests.remove(2));
+        assert_eq!(None, requests.remove(3));
+        assert_eq!(None, requests.remove(1));
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn test_raw_parameters() {
+        let mut requests = ParameterRequests::default();
+        let high = parameters(ConnectionPriority::High);
+        assert_eq!(Some(high), requests.request(1, high));
+
+        // Raw parameters slower than the priority of another client are not applied.
+        assert_eq!(None, requests.request(2, raw(40, 0)));
+        assert_eq!(Some(high), requests.effective());
+
+        // Faster ones are, and replace the earlier request of the client.
+        assert_eq!(Some(raw(6, 0)), requests.request(2, raw(6, 0)));
+        assert_eq!(None, requests.request(1, parameters(ConnectionPriority::LowPower)));
+
+        // Equal intervals are arbitrated by latency.
+        assert_eq!(None, requests.request(1, raw(6, 4)));
+        assert_eq!(Some(raw(6, 0)), requests.effective());
+
+        assert_eq!(Some(raw(6, 4)), requests.remove(2));
+        assert_eq!(None, requests.remove(1));
+    }
+
+    #[test]
+    fn test_tuning() {
+        let mut requests = ParameterRequests::default();
+        let tuning = raw(12, 0);
+        assert_eq!(Some(tuning), requests.set_tuning(Some(tuning)));
+        assert!(!requests.is_empty());
+
+        // A client asking for a low power link does not slow down a tuned link.
+        assert_eq!(None, requests.request(1, parameters(ConnectionPriority::LowPower)));
+        assert_eq!(Some(tuning), requests.effective());
+
+        let high = parameters(ConnectionPriority::High);
+        assert_eq!(Some(high), requests.request(2, high));
+        // The tuning applies again once the client is gone.
+        assert_eq!(Some(tuning), requests.remove(2));
+        assert_eq!(None, requests.set_tuning(Some(tuning)));
+
+        assert_eq!(Some(parameters(ConnectionPriority::LowPower)), requests.set_tuning(None));
+        assert_eq!(None, requests.remove(1));
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn test_parameters() {
+        let high = parameters(ConnectionPriority::High);
+        let low_power = parameters(ConnectionPriority::LowPower);
+        assert!(high.interval.1 < parameters(ConnectionPriority::Balanced).interval.0);
+        assert!(low_power.interval.0 > parameters(ConnectionPriority::Balanced).interval.1);
+        assert_eq!(0, high.latency);
+        assert_eq!((0, 0), high.ce_len);
+    }
+}
@@ -0,0 +1,121 @@
+//! Cryptographic primitives of the protocols run by the stack itself, such as the resolution of
+//! the private addresses and the Fast Pair key-based pairing: AES-128, SHA-256 and ECDH on the
+//! P-256 curve.
+//!
+//! They wrap the RustCrypto implementations and take their inputs most significant byte first as
+//! in the specifications which use them.
+
+use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use aes::Aes128;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+/// Encrypts a single block with AES-128.
+pub(crate) fn aes128_encrypt(key: &[u8; 16], block: &[u8; 16]) -> [u8; 16] {
+    let mut output = (*block).into();
+    Aes128::new(key.into()).encrypt_block(&mut output);
+    output.into()
+}
+
+/// Decrypts a single block with AES-128.
+pub(crate) fn aes128_decrypt(key: &[u8; 16], block: &[u8; 16]) -> [u8; 16] {
+    let mut output = (*block).into();
+    Aes128::new(key.into()).decrypt_block(&mut output);
+    output.into()
+}
+
+/// Returns the SHA-256 digest of a message.
+pub(crate) fn sha256(message: &[u8]) -> [u8; 32] {
+    Sha256::digest(message).into()
+}
+
+/// Fills `bytes` from the random number generator of the kernel.
+pub(crate) fn random_bytes(bytes: &mut [u8]) -> std::io::Result<()> {
+    std::fs::File::open("/dev/urandom")?.read_exact(bytes)
+}
+
+/// Returns the public key of a private key, as the concatenated X and Y coordinates. None if the
+/// private key is out of range.
+pub(crate) fn p256_public_key(private_key: &[u8; 32]) -> Option<[u8; 64]> {
+    let secret = SecretKey::from_slice(private_key).ok()?;
+    let point = secret.public_key().to_encoded_point(false);
+    let mut public_key = [0u8; 64];
+    public_key[..32].copy_from_slice(point.x()?);
+    public_key[32..].copy_from_slice(point.y()?);
+    Some(public_key)
+}
+
+/// Computes the ECDH shared secret, the X coordinate of the product of the peer public key, as
+/// the concatenated X and Y coordinates, with the private key. None if the public key is not on
+/// the curve or if the private key is out of range.
+pub(crate) fn p256_ecdh(private_key: &[u8; 32], public_key: &[u8; 64]) -> Option<[u8; 32]> {
+    let secret = SecretKey::from_slice(private_key).ok()?;
+    let mut encoded = [0u8; 65];
+    encoded[0] = 0x04;
+    encoded[1..].copy_from_slice(public_key);
+    let peer = PublicKey::from_sec1_bytes(&encoded).ok()?;
+    let shared = p256::ecdh::diffie_hellman(secret.to_nonzero_scalar(), peer.as_affine());
+    Some((*shared.raw_secret_bytes()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::from_hex;
+
+    fn array<const N: usize>(hex: &str) -> [u8; N] {
+        let mut array = [0u8; N];
+        array.copy_from_slice(&from_hex(hex).unwrap());
+        array
+    }
+
+    #[test]
+    fn test_aes128() {
+        // FIPS-197, Appendix C.1.
+        let key = array::<16>("000102030405060708090a0b0c0d0e0f");
+        let plaintext = array::<16>("00112233445566778899aabbccddeeff");
+        let ciphertext = array::<16>("69c4e0d86a7b0430d8cdb78070b4c55a");
+        assert_eq!(ciphertext, aes128_encrypt(&key, &plaintext));
+        assert_eq!(plaintext, aes128_decrypt(&key, &ciphertext));
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            array::<32>("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            sha256(b"abc")
+        );
+        // Two blocks once padded.
+        assert_eq!(
+            array::<32>("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"),
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")
+        );
+    }
+
+    #[test]
+    fn test_p256() {
+        let two = array::<32>("0000000000000000000000000000000000000000000000000000000000000002");
+        assert_eq!(
+            array::<64>(
+                "7cf27b188d034f7e8a52380304b51ac3c08969e277f21b35a60b48fc47669978\
+                 07775510db8ed040293d9ac69f7430dbba7dade63ce982299e04b79d227873d1"
+            )
+            .to_vec(),
+            p256_public_key(&two).unwrap().to_vec()
+        );
+
+        let alice = array::<32>("3f49f6d4a3c55f3874c9b3e3d2103f504aff607beb40b7995899b8a6cd3c1abd");
+        let bob = array::<32>("55188b3d32f6bb9a900afcfbeed4e72a59cb9ac2f19d7cfb6b4fdd49f47fc5fd");
+        let alice_public = p256_public_key(&alice).unwrap();
+        let bob_public = p256_public_key(&bob).unwrap();
+        assert_eq!(p256_ecdh(&alice, &bob_public), p256_ecdh(&bob, &alice_public));
+
+        // Points off the curve and out of range private keys are rejected.
+        let mut off_curve = alice_public;
+        off_curve[63] ^= 1;
+        assert_eq!(None, p256_ecdh(&bob, &off_curve));
+        assert_eq!(None, p256_public_key(&[0; 32]));
+    }
+}
@@ -0,0 +1,1424 @@
+//! Device firmware updates over GATT, see `IDfu::start_dfu`.
+//!
+//! The stack connects to the device, negotiates the MTU and subscribes to the control point of
+//! the update service, then transfers the firmware split to the MTU. Each chunk is written once
+//! the previous one is handed to the controller, and the image is verified with CRC-32 receipts
+//! before it is applied, so that the clients only provide the image.
+//!
+//! The devices running the bootloader of the nRF5 SDK are updated with Nordic Secure DFU (service
+//! `fe59`). The init packet is sent in a command object, then the firmware in data objects of the
+//! size reported by the device. The packets are written without response, the device reporting
+//! the CRC-32 of the bytes received every `NORDIC_PRN` packets, and each object is verified before
+//! it is executed.
+//!
+//! The applications of these devices expose the buttonless DFU characteristic in the same service
+//! instead. They are asked to reboot into their bootloader, which is connected before the
+//! transfer: the bootloader of the devices without bonds advertises with the address of the
+//! application incremented by one, while the bootloader of the bonded devices keeps it.
+//!
+//! A device runs one update at a time.
+
+use bt_topshim::btif::{BtTransport, Uuid128Bit};
+use bt_topshim::profiles::gatt::GattStatus;
+use bt_topshim::topstack;
+
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::address::BtAddress;
+use crate::bluetooth_gatt::{
+    BluetoothGatt, BluetoothGattCharacteristic, BluetoothGattService, CharacteristicReadResult,
+    GattHandleValue, GattWriteRequestStatus, GattWriteType, IBluetoothGatt, IBluetoothGattCallback,
+    LePhy,
+};
+use crate::connection_priority::ConnectionPriority;
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::gatt_conformance::ConformanceIssue;
+use crate::gatt_service_builder::CCCD_UUID;
+use crate::storage::to_hex;
+use crate::uuid::BtUuid;
+use crate::{Message, RPCProxy};
+
+/// Application UUID of the GATT client running the updates.
+const DFU_CLIENT_UUID: Uuid128Bit = [
+    0x93, 0x4B, 0x2D, 0x61, 0x0E, 0x7A, 0x4F, 0x18, 0xB6, 0x3C, 0x55, 0xD1, 0x08, 0xE2, 0x7F, 0x4A,
+];
+
+const NORDIC_DFU_SERVICE_UUID: Uuid128Bit = [
+    0x00, 0x00, 0xFE, 0x59, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0x80, 0x5F, 0x9B, 0x34, 0xFB,
+];
+const NORDIC_DFU_CONTROL_POINT_UUID: Uuid128Bit = [
+    0x8E, 0xC9, 0x00, 0x01, 0xF3, 0x15, 0x4F, 0x60, 0x9F, 0xB8, 0x83, 0x88, 0x30, 0xDA, 0xEA, 0x50,
+];
+const NORDIC_DFU_PACKET_UUID: Uuid128Bit = [
+    0x8E, 0xC9, 0x00, 0x02, 0xF3, 0x15, 0x4F, 0x60, 0x9F, 0xB8, 0x83, 0x88, 0x30, 0xDA, 0xEA, 0x50,
+];
+/// Buttonless DFU characteristics of the applications whose bootloader does not keep the bonds,
+/// and of those whose bootloader keeps them.
+const NORDIC_BUTTONLESS_UUID: Uuid128Bit = [
+    0x8E, 0xC9, 0x00, 0x03, 0xF3, 0x15, 0x4F, 0x60, 0x9F, 0xB8, 0x83, 0x88, 0x30, 0xDA, 0xEA, 0x50,
+];
+const NORDIC_BUTTONLESS_BONDED_UUID: Uuid128Bit = [
+    0x8E, 0xC9, 0x00, 0x04, 0xF3, 0x15, 0x4F, 0x60, 0x9F, 0xB8, 0x83, 0x88, 0x30, 0xDA, 0xEA, 0x50,
+];
+
+// Opcodes and results of the Nordic Secure DFU control point.
+const NORDIC_OP_CREATE: u8 = 0x01;
+const NORDIC_OP_SET_PRN: u8 = 0x02;
+const NORDIC_OP_CALCULATE_CHECKSUM: u8 = 0x03;
+const NORDIC_OP_EXECUTE: u8 = 0x04;
+const NORDIC_OP_SELECT: u8 = 0x06;
+const NORDIC_OP_RESPONSE: u8 = 0x60;
+const NORDIC_RESULT_SUCCESS: u8 = 0x01;
+const NORDIC_OBJECT_COMMAND: u8 = 0x01;
+const NORDIC_OBJECT_DATA: u8 = 0x02;
+
+/// Packets written between two receipts of the device.
+const NORDIC_PRN: u16 = 12;
+
+// Opcodes and results of the buttonless DFU characteristic.
+const BUTTONLESS_OP_ENTER_BOOTLOADER: u8 = 0x01;
+const BUTTONLESS_OP_RESPONSE: u8 = 0x20;
+const BUTTONLESS_RESULT_SUCCESS: u8 = 0x01;
+
+const CCCD_ENABLE_NOTIFICATION: [u8; 2] = [0x01, 0x00];
+const CCCD_ENABLE_INDICATION: [u8; 2] = [0x02, 0x00];
+
+/// MTU requested to the devices, fitting 244 bytes of firmware in each packet.
+const DFU_MTU: i32 = 247;
+const DEFAULT_ATT_MTU: usize = 23;
+const ATT_WRITE_HEADER_LEN: usize = 3;
+
+/// Time for each operation to complete, the devices taking a while to erase their flash before
+/// answering some commands.
+const OPERATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Defines the firmware update API.
+pub trait IDfu {
+    /// Starts updating the firmware of the device `addr` with Nordic Secure DFU, rebooting it
+    /// into its bootloader first if it runs its application. `init_packet` is the signed init
+    /// packet of the firmware. The progress and the outcome of the update are reported to
+    /// `callback`.
+    ///
+    /// Returns the id of the update. Fails if an update of the device is running.
+    fn start_dfu(
+        &mut self,
+        addr: BtAddress,
+        init_packet: Vec<u8>,
+        firmware: Vec<u8>,
+        callback: Box<dyn IDfuCallback + Send>,
+    ) -> BtResult<i32>;
+
+    /// Stops an update, disconnecting the device. The update finishes with `DfuStatus::Cancelled`.
+    fn cancel_dfu(&mut self, dfu_id: i32) -> BtResult<()>;
+}
+
+/// Firmware update events.
+pub trait IDfuCallback: RPCProxy {
+    /// When some more bytes of the firmware are written to the device, reported at most once per
+    /// percent of the firmware.
+    fn on_dfu_progress(&self, dfu_id: i32, bytes_sent: i32, total_bytes: i32);
+
+    /// When an update finishes. `addr` is the address the update was started for, and `message`
+    /// explains the failures.
+    fn on_dfu_finished(&self, dfu_id: i32, addr: String, status: DfuStatus, message: String);
+}
+
+/// Outcome of a firmware update.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum DfuStatus {
+    Success = 0,
+    /// The update was cancelled with `IDfu::cancel_dfu`.
+    Cancelled = 1,
+    ConnectionFailed = 2,
+    /// The device disconnected before the end of the update.
+    Disconnected = 3,
+    /// The device does not have the DFU service.
+    ServiceNotFound = 4,
+    /// A GATT operation failed.
+    OperationFailed = 5,
+    /// The device refused a command, such as an init packet whose signature does not match.
+    Rejected = 6,
+    /// The device answered a command with a malformed response.
+    InvalidResponse = 7,
+    /// The bytes received by the device do not match the firmware.
+    VerificationFailed = 8,
+    /// An operation did not complete before the timeout.
+    TimedOut = 9,
+}
+
+/// Updates the CRC-32 (IEEE 802.3) `crc` of some bytes with the bytes following them.
+pub(crate) fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes.get(offset..offset + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+/// Attributes of the DFU service of a device, by handle.
+#[derive(Debug, PartialEq)]
+enum DfuAttributes {
+    /// The device runs its bootloader.
+    Bootloader { control_point: i32, cccd: i32, packet: i32 },
+    /// The device runs its application, which reboots into its bootloader when asked.
+    Buttonless { characteristic: i32, cccd: i32, bonded: bool },
+}
+
+fn cccd_of(characteristic: &BluetoothGattCharacteristic) -> Option<i32> {
+    characteristic.descriptors.iter().find(|d| d.uuid == CCCD_UUID).map(|d| d.instance_id)
+}
+
+/// Finds the attributes of the DFU service among the services of a device.
+fn find_attributes(services: &[BluetoothGattService]) -> Option<DfuAttributes> {
+    let characteristics: Vec<&BluetoothGattCharacteristic> = services
+        .iter()
+        .filter(|s| s.uuid == NORDIC_DFU_SERVICE_UUID)
+        .flat_map(|s| s.characteristics.iter())
+        .collect();
+    let find = |uuid: Uuid128Bit| characteristics.iter().find(|c| c.uuid == uuid).cloned();
+
+    if let (Some(control_point), Some(packet)) =
+        (find(NORDIC_DFU_CONTROL_POINT_UUID), find(NORDIC_DFU_PACKET_UUID))
+    {
+        return Some(DfuAttributes::Bootloader {
+            control_point: control_point.instance_id,
+            cccd: cccd_of(control_point)?,
+            packet: packet.instance_id,
+        });
+    }
+    [(NORDIC_BUTTONLESS_UUID, false), (NORDIC_BUTTONLESS_BONDED_UUID, true)].iter().find_map(
+        |(uuid, bonded)| {
+            let characteristic = find(*uuid)?;
+            Some(DfuAttributes::Buttonless {
+                characteristic: characteristic.instance_id,
+                cccd: cccd_of(characteristic)?,
+                bonded: *bonded,
+            })
+        },
+    )
+}
+
+/// Returns the address the bootloader of a device advertises with once its application rebooted
+/// into it.
+fn bootloader_address(address: BtAddress, bonded: bool) -> BtAddress {
+    let mut address = address;
+    if !bonded {
+        // Only the least significant byte is incremented, wrapping around.
+        address.raw.val[5] = address.raw.val[5].wrapping_add(1);
+    }
+    address
+}
+
+/// Checks the response of an application asked to reboot into its bootloader.
+fn check_bootloader_entered(value: &[u8]) -> Result<(), (DfuStatus, String)> {
+    match value {
+        [BUTTONLESS_OP_RESPONSE, BUTTONLESS_OP_ENTER_BOOTLOADER, BUTTONLESS_RESULT_SUCCESS, ..] => {
+            Ok(())
+        }
+        [BUTTONLESS_OP_RESPONSE, BUTTONLESS_OP_ENTER_BOOTLOADER, result, ..] => Err((
+            DfuStatus::Rejected,
+            format!("Entering the bootloader failed with result {:#04x}", result),
+        )),
+        _ => Err((
+            DfuStatus::InvalidResponse,
+            format!("Unexpected response {} to entering the bootloader", to_hex(value)),
+        )),
+    }
+}
+
+/// Next GATT operation of a transfer.
+#[derive(Debug, PartialEq)]
+enum TransferStep {
+    /// Writes a command to the control point. Its response is passed to
+    /// `NordicTransfer::on_response`.
+    Command(Vec<u8>),
+    /// Writes a chunk of the firmware. Its completion is passed to
+    /// `NordicTransfer::on_packet_written`.
+    Packet(Vec<u8>),
+    /// Waits for a notification of the control point, passed to `NordicTransfer::on_response`.
+    AwaitResponse,
+    /// The firmware is transferred and verified.
+    Complete,
+}
+
+type TransferResult = Result<TransferStep, (DfuStatus, String)>;
+
+/// Stage of a Nordic Secure DFU transfer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NordicStage {
+    SetPrn,
+    Select,
+    Create,
+    /// Writing the packets of an object, or waiting for a receipt.
+    Send,
+    Checksum,
+    Execute,
+}
+
+/// Nordic Secure DFU transfer, once connected and subscribed to the control point.
+struct NordicTransfer {
+    init_packet: Vec<u8>,
+    firmware: Vec<u8>,
+    chunk_size: usize,
+    stage: NordicStage,
+    /// Type of the objects being transferred, the init packet then the firmware.
+    object_type: u8,
+    max_object_size: usize,
+    /// End of the object being transferred, in the payload of its type.
+    object_end: usize,
+    /// Bytes of the payload written, and their CRC-32.
+    offset: usize,
+    crc: u32,
+    /// Packets written since the object was created or since the last receipt.
+    packets: u16,
+}
+
+impl NordicTransfer {
+    fn new(init_packet: Vec<u8>, firmware: Vec<u8>) -> NordicTransfer {
+        NordicTransfer {
+            init_packet,
+            firmware,
+            chunk_size: DEFAULT_ATT_MTU - ATT_WRITE_HEADER_LEN,
+            stage: NordicStage::SetPrn,
+            object_type: NORDIC_OBJECT_COMMAND,
+            max_object_size: 0,
+            object_end: 0,
+            offset: 0,
+            crc: 0,
+            packets: 0,
+        }
+    }
+
+    fn payload(&self) -> &[u8] {
+        if self.object_type == NORDIC_OBJECT_COMMAND {
+            &self.init_packet
+        } else {
+            &self.firmware
+        }
+    }
+
+    fn select(&mut self, object_type: u8) -> TransferStep {
+        self.stage = NordicStage::Select;
+        self.object_type = object_type;
+        self.offset = 0;
+        self.crc = 0;
+        TransferStep::Command(vec![NORDIC_OP_SELECT, object_type])
+    }
+
+    fn create(&mut self) -> TransferStep {
+        self.stage = NordicStage::Create;
+        self.object_end = (self.offset + self.max_object_size).min(self.payload().len());
+        // The device counts the packets of the receipts from the creation of each object.
+        self.packets = 0;
+
+        let mut command = vec![NORDIC_OP_CREATE, self.object_type];
+        command.extend_from_slice(&((self.object_end - self.offset) as u32).to_le_bytes());
+        TransferStep::Command(command)
+    }
+
+    fn send_next(&mut self) -> TransferStep {
+        if self.packets == NORDIC_PRN {
+            return TransferStep::AwaitResponse;
+        }
+        if self.offset == self.object_end {
+            self.stage = NordicStage::Checksum;
+            return TransferStep::Command(vec![NORDIC_OP_CALCULATE_CHECKSUM]);
+        }
+
+        let end = (self.offset + self.chunk_size).min(self.object_end);
+        let packet = self.payload()[self.offset..end].to_vec();
+        self.crc = crc32_update(self.crc, &packet);
+        self.offset = end;
+        self.packets += 1;
+        TransferStep::Packet(packet)
+    }
+
+    /// Checks the offset and the CRC-32 of the bytes received by the device.
+    fn verify(&self, params: &[u8]) -> Result<(), (DfuStatus, String)> {
+        let (offset, crc) = match (read_u32(params, 0), read_u32(params, 4)) {
+            (Some(offset), Some(crc)) => (offset, crc),
+            _ => {
+                return Err((
+                    DfuStatus::InvalidResponse,
+                    format!("Malformed checksum {}", to_hex(params)),
+                ))
+            }
+        };
+        if offset as usize != self.offset || crc != self.crc {
+            return Err((
+                DfuStatus::VerificationFailed,
+                format!(
+                    "Device received {} bytes with CRC {:#010x} instead of {} with CRC {:#010x}",
+                    offset, crc, self.offset, self.crc
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Starts the transfer, writing packets of `chunk_size` bytes.
+    fn start(&mut self, chunk_size: usize) -> TransferStep {
+        self.chunk_size = chunk_size;
+        self.stage = NordicStage::SetPrn;
+        let mut command = vec![NORDIC_OP_SET_PRN];
+        command.extend_from_slice(&NORDIC_PRN.to_le_bytes());
+        TransferStep::Command(command)
+    }
+
+    fn on_packet_written(&mut self) -> TransferStep {
+        self.send_next()
+    }
+
+    fn on_response(&mut self, value: &[u8]) -> TransferResult {
+        let opcode = match self.stage {
+            NordicStage::SetPrn => NORDIC_OP_SET_PRN,
+            NordicStage::Select => NORDIC_OP_SELECT,
+            NordicStage::Create => NORDIC_OP_CREATE,
+            // The receipts are responses to a checksum the device did not wait for.
+            NordicStage::Send | NordicStage::Checksum => NORDIC_OP_CALCULATE_CHECKSUM,
+            NordicStage::Execute => NORDIC_OP_EXECUTE,
+        };
+        match value {
+            [NORDIC_OP_RESPONSE, op, NORDIC_RESULT_SUCCESS, ..] if *op == opcode => (),
+            [NORDIC_OP_RESPONSE, op, result, ..] if *op == opcode => {
+                return Err((
+                    DfuStatus::Rejected,
+                    format!("Command {:#04x} failed with result {:#04x}", opcode, result),
+                ))
+            }
+            _ => {
+                return Err((
+                    DfuStatus::InvalidResponse,
+                    format!("Unexpected response {} to command {:#04x}", to_hex(value), opcode),
+                ))
+            }
+        }
+        let params = &value[3..];
+
+        match self.stage {
+            NordicStage::SetPrn => Ok(self.select(NORDIC_OBJECT_COMMAND)),
+            NordicStage::Select => {
+                let max_object_size = read_u32(params, 0).unwrap_or(0) as usize;
+                if max_object_size == 0 {
+                    return Err((
+                        DfuStatus::InvalidResponse,
+                        format!("Malformed object selection {}", to_hex(params)),
+                    ));
+                }
+                if self.object_type == NORDIC_OBJECT_COMMAND
+                    && self.init_packet.len() > max_object_size
+                {
+                    return Err((
+                        DfuStatus::Rejected,
+                        format!(
+                            "Init packet of {} bytes exceeds the command objects of {} bytes",
+                            self.init_packet.len(),
+                            max_object_size
+                        ),
+                    ));
+                }
+                self.max_object_size = max_object_size;
+                Ok(self.create())
+            }
+            NordicStage::Create => {
+                self.stage = NordicStage::Send;
+                Ok(self.send_next())
+            }
+            NordicStage::Send => {
+                self.verify(params)?;
+                self.packets = 0;
+                Ok(self.send_next())
+            }
+            NordicStage::Checksum => {
+                self.verify(params)?;
+                self.stage = NordicStage::Execute;
+                Ok(TransferStep::Command(vec![NORDIC_OP_EXECUTE]))
+            }
+            NordicStage::Execute => {
+                if self.offset < self.payload().len() {
+                    Ok(self.create())
+                } else if self.object_type == NORDIC_OBJECT_COMMAND {
+                    Ok(self.select(NORDIC_OBJECT_DATA))
+                } else {
+                    Ok(TransferStep::Complete)
+                }
+            }
+        }
+    }
+
+    /// Returns the number of bytes of the firmware written.
+    fn bytes_sent(&self) -> usize {
+        if self.object_type == NORDIC_OBJECT_DATA {
+            self.offset
+        } else {
+            0
+        }
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.firmware.len()
+    }
+}
+
+/// Actions of the firmware update manager dispatched from the event loop, as the events of the
+/// GATT client are delivered while the GATT object is locked.
+pub enum DfuActions {
+    /// Params: Status, Client ID
+    GattClientRegistered(i32, i32),
+    /// Params: Address, Connected
+    GattConnectionState(String, bool),
+    /// Params: Address, MTU, Status
+    MtuConfigured(String, i32, i32),
+    /// Params: Address, Services, Status
+    GattSearchComplete(String, Vec<BluetoothGattService>, i32),
+    /// Params: Address, Status, Handle
+    DescriptorWritten(String, i32, i32),
+    /// Params: Address, Status, Handle
+    CharacteristicWritten(String, i32, i32),
+    /// Params: Address, Handle, Value
+    GattNotification(String, i32, Vec<u8>),
+    /// Params: Update ID, Operation
+    OperationTimeout(i32, u32),
+}
+
+/// Stage of an update.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SessionState {
+    Connecting,
+    ConfiguringMtu,
+    Discovering,
+    Subscribing,
+    /// Waiting for a notification of the control point.
+    AwaitingResponse,
+    WritingPacket,
+    /// Waiting for the application to accept rebooting into its bootloader.
+    EnteringBootloader,
+    /// Waiting for the application to disconnect.
+    Rebooting,
+}
+
+struct Session {
+    id: i32,
+    /// Address the update was started for.
+    device: BtAddress,
+    /// Address connected, the one of the bootloader once the application rebooted into it.
+    address: BtAddress,
+    transfer: NordicTransfer,
+    callback: Box<dyn IDfuCallback + Send>,
+    callback_id: u32,
+    state: SessionState,
+    mtu: usize,
+    control_point: Option<i32>,
+    packet: Option<i32>,
+    // Handle of the buttonless DFU characteristic of the application, and whether its bootloader
+    // keeps the bonds.
+    buttonless: Option<(i32, bool)>,
+    rebooted: bool,
+    // Operation last started, so that the timeout of a completed one is ignored.
+    operation: u32,
+    reported_percent: Option<usize>,
+    timeout: Option<JoinHandle<()>>,
+}
+
+/// Implementation of the firmware update API.
+pub struct DfuManager {
+    tx: Sender<Message>,
+    gatt: Option<Arc<Mutex<Box<BluetoothGatt>>>>,
+    client_id: Option<i32>,
+    next_dfu_id: i32,
+    sessions: HashMap<i32, Session>,
+}
+
+impl DfuManager {
+    pub fn new(tx: Sender<Message>) -> DfuManager {
+        DfuManager { tx, gatt: None, client_id: None, next_dfu_id: 0, sessions: HashMap::new() }
+    }
+
+    /// Registers the GATT client running the updates. Must be called once the profiles are
+    /// initialized.
+    pub fn init(&mut self, gatt: Arc<Mutex<Box<BluetoothGatt>>>) {
+        gatt.lock().unwrap().register_client(
+            BtUuid { uu: DFU_CLIENT_UUID },
+            Box::new(DfuGattCallback { tx: self.tx.clone() }),
+            false,
+        );
+        self.gatt = Some(gatt);
+    }
+
+    /// Stops the updates of a callback which disconnected.
+    pub(crate) fn callback_disconnected(&mut self, callback_id: u32) {
+        let ids: Vec<i32> =
+            self.sessions.values().filter(|s| s.callback_id == callback_id).map(|s| s.id).collect();
+        for id in ids {
+            if let Some(session) = self.sessions.remove(&id) {
+                self.release(session);
+            }
+        }
+    }
+
+    pub fn dispatch_dfu_actions(&mut self, action: DfuActions) {
+        match action {
+            DfuActions::GattClientRegistered(status, client_id) => {
+                if status != GattStatus::Success as i32 {
+                    warn!("Failed to register the DFU GATT client: {}", status);
+                    return;
+                }
+                self.client_id = Some(client_id);
+            }
+            DfuActions::GattConnectionState(address, connected) => {
+                let (id, state) = match self.session_of(&address) {
+                    Some(session) => (session.id, session.state),
+                    None => return,
+                };
+                match (state, connected) {
+                    (SessionState::Connecting, true) => self.configure_mtu(id),
+                    (SessionState::Connecting, false) => self.finish(
+                        id,
+                        DfuStatus::ConnectionFailed,
+                        String::from("Failed to connect"),
+                    ),
+                    (SessionState::Rebooting, false) => self.connect_bootloader(id),
+                    (_, false) => self.finish(
+                        id,
+                        DfuStatus::Disconnected,
+                        String::from("Device disconnected"),
+                    ),
+                    _ => (),
+                }
+            }
+            DfuActions::MtuConfigured(address, mtu, status) => {
+                let session = match self.session_of_mut(&address) {
+                    Some(session) if session.state == SessionState::ConfiguringMtu => session,
+                    _ => return,
+                };
+                // The firmware is split to the MTU obtained, so a refused MTU is not fatal.
+                if status == GattStatus::Success as i32 && mtu as usize > ATT_WRITE_HEADER_LEN {
+                    session.mtu = mtu as usize;
+                } else {
+                    warn!("[{}]: Failed to negotiate the DFU MTU: {}", address, status);
+                }
+                let id = session.id;
+                self.discover_services(id);
+            }
+            DfuActions::GattSearchComplete(address, services, status) => {
+                let id = match self.session_of(&address) {
+                    Some(session) if session.state == SessionState::Discovering => session.id,
+                    _ => return,
+                };
+                if status != GattStatus::Success as i32 {
+                    let message = format!("Service discovery failed with status {}", status);
+                    return self.finish(id, DfuStatus::OperationFailed, message);
+                }
+                self.subscribe(id, &services);
+            }
+            DfuActions::DescriptorWritten(address, status, _handle) => {
+                let session = match self.session_of_mut(&address) {
+                    Some(session) if session.state == SessionState::Subscribing => session,
+                    _ => return,
+                };
+                let id = session.id;
+                if status != GattStatus::Success as i32 {
+                    let message = format!("Subscription failed with status {}", status);
+                    return self.finish(id, DfuStatus::OperationFailed, message);
+                }
+                if session.buttonless.is_some() {
+                    return self.enter_bootloader(id);
+                }
+                let step = session.transfer.start(session.mtu - ATT_WRITE_HEADER_LEN);
+                self.run(id, step);
+            }
+            DfuActions::CharacteristicWritten(address, status, handle) => {
+                let session = match self.session_of(&address) {
+                    Some(session) => session,
+                    None => return,
+                };
+                let id = session.id;
+                let is_packet =
+                    Some(handle) == session.packet && session.state == SessionState::WritingPacket;
+                let is_buttonless = session.buttonless.map(|(handle, _)| handle) == Some(handle);
+                if !is_packet && !is_buttonless && Some(handle) != session.control_point {
+                    return;
+                }
+                if status != GattStatus::Success as i32 {
+                    let message = format!("Write failed with status {}", status);
+                    return self.finish(id, DfuStatus::OperationFailed, message);
+                }
+                // The response to a command is awaited in a notification.
+                if is_packet {
+                    self.report_progress(id);
+                    let step = match self.sessions.get_mut(&id) {
+                        Some(session) => session.transfer.on_packet_written(),
+                        None => return,
+                    };
+                    self.run(id, step);
+                }
+            }
+            DfuActions::GattNotification(address, handle, value) => {
+                let session = match self.session_of_mut(&address) {
+                    Some(session) => session,
+                    None => return,
+                };
+                let id = session.id;
+                match session.state {
+                    SessionState::AwaitingResponse if Some(handle) == session.control_point => {
+                        match session.transfer.on_response(&value) {
+                            Ok(step) => self.run(id, step),
+                            Err((status, message)) => self.finish(id, status, message),
+                        }
+                    }
+                    SessionState::EnteringBootloader
+                        if session.buttonless.map(|(handle, _)| handle) == Some(handle) =>
+                    {
+                        match check_bootloader_entered(&value) {
+                            Ok(()) => self.reboot(id),
+                            Err((status, message)) => self.finish(id, status, message),
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            DfuActions::OperationTimeout(id, operation) => {
+                let state = match self.sessions.get(&id) {
+                    Some(session) if session.operation == operation => session.state,
+                    _ => return,
+                };
+                self.finish(id, DfuStatus::TimedOut, format!("Timed out in state {:?}", state));
+            }
+        }
+    }
+
+    fn session_of(&self, address: &String) -> Option<&Session> {
+        let address = BtAddress::from_string(address)?;
+        self.sessions.values().find(|s| s.address == address)
+    }
+
+    fn session_of_mut(&mut self, address: &String) -> Option<&mut Session> {
+        let address = BtAddress::from_string(address)?;
+        self.sessions.values_mut().find(|s| s.address == address)
+    }
+
+    fn client(&self) -> Option<(Arc<Mutex<Box<BluetoothGatt>>>, i32)> {
+        match (&self.gatt, self.client_id) {
+            (Some(gatt), Some(client_id)) => Some((gatt.clone(), client_id)),
+            _ => None,
+        }
+    }
+
+    /// Moves an update to `state` and arms the timeout of its operation.
+    fn advance(&mut self, id: i32, state: SessionState) {
+        let tx = self.tx.clone();
+        let session = match self.sessions.get_mut(&id) {
+            Some(session) => session,
+            None => return,
+        };
+
+        session.state = state;
+        session.operation += 1;
+        if let Some(timeout) = session.timeout.take() {
+            timeout.abort();
+        }
+        let operation = session.operation;
+        session.timeout = Some(tokio::spawn(async move {
+            time::sleep(OPERATION_TIMEOUT).await;
+            let _ = tx.send(Message::Dfu(DfuActions::OperationTimeout(id, operation))).await;
+        }));
+    }
+
+    fn configure_mtu(&mut self, id: i32) {
+        let address = match self.sessions.get(&id) {
+            Some(session) => session.address,
+            None => return,
+        };
+        let (gatt, client_id) = match self.client() {
+            Some(client) => client,
+            None => return,
+        };
+
+        self.advance(id, SessionState::ConfiguringMtu);
+        let result = {
+            let mut gatt = gatt.lock().unwrap();
+            // Shorter connection intervals speed the transfer up.
+            if let Err(e) =
+                gatt.set_connection_priority(client_id, address, ConnectionPriority::High)
+            {
+                warn!("[{}]: Failed to raise the connection priority: {}", address, e);
+            }
+            gatt.configure_mtu(client_id, address, DFU_MTU)
+        };
+        if let Err(e) = result {
+            warn!("[{}]: Failed to request the DFU MTU: {}", address, e);
+            self.discover_services(id);
+        }
+    }
+
+    fn discover_services(&mut self, id: i32) {
+        let address = match self.sessions.get(&id) {
+            Some(session) => session.address,
+            None => return,
+        };
+        let (gatt, client_id) = match self.client() {
+            Some(client) => client,
+            None => return,
+        };
+
+        self.advance(id, SessionState::Discovering);
+        let result = gatt.lock().unwrap().discover_services(client_id, address);
+        if let Err(e) = result {
+            let message = format!("Failed to discover the services: {}", e);
+            self.finish(id, DfuStatus::OperationFailed, message);
+        }
+    }
+
+    /// Finds the attributes of the update service, and subscribes to the control point of the
+    /// bootloader or to the buttonless DFU characteristic of the application.
+    fn subscribe(&mut self, id: i32, services: &[BluetoothGattService]) {
+        let session = match self.sessions.get_mut(&id) {
+            Some(session) => session,
+            None => return,
+        };
+
+        let (handle, cccd_handle, cccd_value) = match find_attributes(services) {
+            Some(DfuAttributes::Bootloader { control_point, cccd, packet }) => {
+                session.control_point = Some(control_point);
+                session.packet = Some(packet);
+                (control_point, cccd, CCCD_ENABLE_NOTIFICATION)
+            }
+            // The bootloader is expected once the application rebooted.
+            Some(DfuAttributes::Buttonless { characteristic, cccd, bonded })
+                if !session.rebooted =>
+            {
+                session.buttonless = Some((characteristic, bonded));
+                (characteristic, cccd, CCCD_ENABLE_INDICATION)
+            }
+            _ => {
+                let message =
+                    format!("Service {} not found", BtUuid::from(NORDIC_DFU_SERVICE_UUID));
+                return self.finish(id, DfuStatus::ServiceNotFound, message);
+            }
+        };
+        let address = session.address;
+        let (gatt, client_id) = match self.client() {
+            Some(client) => client,
+            None => return,
+        };
+
+        self.advance(id, SessionState::Subscribing);
+        let result = {
+            let mut gatt = gatt.lock().unwrap();
+            gatt.register_for_notification(client_id, address, handle, true).and_then(|_| {
+                gatt.write_descriptor(client_id, address, cccd_handle, 0, cccd_value.to_vec())
+            })
+        };
+        if let Err(e) = result {
+            let message = format!("Failed to subscribe to the DFU service: {}", e);
+            self.finish(id, DfuStatus::OperationFailed, message);
+        }
+    }
+
+    /// Asks the application of a device to reboot into its bootloader.
+    fn enter_bootloader(&mut self, id: i32) {
+        let (address, handle) = match self.sessions.get(&id) {
+            Some(Session { address, buttonless: Some((handle, _)), .. }) => (*address, *handle),
+            _ => return,
+        };
+        let (gatt, client_id) = match self.client() {
+            Some(client) => client,
+            None => return,
+        };
+
+        self.advance(id, SessionState::EnteringBootloader);
+        let status = gatt.lock().unwrap().write_characteristic(
+            client_id,
+            address,
+            handle,
+            GattWriteType::Write,
+            0,
+            vec![BUTTONLESS_OP_ENTER_BOOTLOADER],
+        );
+        if let GattWriteRequestStatus::Success = status {
+            return;
+        }
+        self.finish(id, DfuStatus::OperationFailed, format!("Failed to write: {:?}", status));
+    }
+
+    /// Disconnects an application which accepted to reboot into its bootloader, the device
+    /// disconnecting by itself as well.
+    fn reboot(&mut self, id: i32) {
+        let address = match self.sessions.get(&id) {
+            Some(session) => session.address,
+            None => return,
+        };
+        let (gatt, client_id) = match self.client() {
+            Some(client) => client,
+            None => return,
+        };
+
+        self.advance(id, SessionState::Rebooting);
+        let _ = gatt.lock().unwrap().client_disconnect(client_id, address);
+    }
+
+    /// Connects the bootloader of a device whose application rebooted into it.
+    fn connect_bootloader(&mut self, id: i32) {
+        let session = match self.sessions.get_mut(&id) {
+            Some(session) => session,
+            None => return,
+        };
+        let bonded = match session.buttonless.take() {
+            Some((_, bonded)) => bonded,
+            None => return,
+        };
+        session.address = bootloader_address(session.address, bonded);
+        session.rebooted = true;
+        session.mtu = DEFAULT_ATT_MTU;
+        let (address, device) = (session.address, session.device);
+        let (gatt, client_id) = match self.client() {
+            Some(client) => client,
+            None => return,
+        };
+
+        debug!("[{}]: Connecting the bootloader of {}", address, device);
+        self.advance(id, SessionState::Connecting);
+        let result = gatt.lock().unwrap().client_connect(
+            client_id,
+            address,
+            true,
+            BtTransport::Le as i32,
+            false,
+            LePhy::Phy1m as i32,
+        );
+        if let Err(e) = result {
+            let message = format!("Failed to connect the bootloader: {}", e);
+            self.finish(id, DfuStatus::ConnectionFailed, message);
+        }
+    }
+
+    /// Runs the next operation of the transfer of an update.
+    fn run(&mut self, id: i32, step: TransferStep) {
+        let session = match self.sessions.get(&id) {
+            Some(session) => session,
+            None => return,
+        };
+        let (value, handle, write_type, state) = match step {
+            TransferStep::Command(value) => {
+                (value, session.control_point, GattWriteType::Write, SessionState::AwaitingResponse)
+            }
+            TransferStep::Packet(value) => {
+                (value, session.packet, GattWriteType::WriteNoRsp, SessionState::WritingPacket)
+            }
+            TransferStep::AwaitResponse => {
+                return self.advance(id, SessionState::AwaitingResponse);
+            }
+            TransferStep::Complete => return self.finish(id, DfuStatus::Success, String::new()),
+        };
+        let address = session.address;
+        let (gatt, client_id, handle) = match (self.client(), handle) {
+            (Some((gatt, client_id)), Some(handle)) => (gatt, client_id, handle),
+            _ => return,
+        };
+
+        self.advance(id, state);
+        let status = gatt
+            .lock()
+            .unwrap()
+            .write_characteristic(client_id, address, handle, write_type, 0, value);
+        if let GattWriteRequestStatus::Success = status {
+            return;
+        }
+        self.finish(id, DfuStatus::OperationFailed, format!("Failed to write: {:?}", status));
+    }
+
+    /// Reports the bytes of the firmware written, once per percent.
+    fn report_progress(&mut self, id: i32) {
+        let session = match self.sessions.get_mut(&id) {
+            Some(session) => session,
+            None => return,
+        };
+        let (sent, total) = (session.transfer.bytes_sent(), session.transfer.total_bytes());
+        let percent = sent * 100 / total.max(1);
+        if session.reported_percent == Some(percent) {
+            return;
+        }
+        session.reported_percent = Some(percent);
+        session.callback.on_dfu_progress(id, sent as i32, total as i32);
+    }
+
+    /// Ends an update and reports its outcome.
+    fn finish(&mut self, id: i32, status: DfuStatus, message: String) {
+        let mut session = match self.sessions.remove(&id) {
+            Some(session) => session,
+            None => return,
+        };
+
+        debug!("[{}]: Firmware update {} finished: {:?} {}", session.address, id, status, message);
+        session.callback.on_dfu_finished(id, session.device.to_string(), status, message);
+        let callback_id = session.callback_id;
+        session.callback.unregister(callback_id);
+        self.release(session);
+    }
+
+    /// Disconnects the device of an update.
+    fn release(&mut self, mut session: Session) {
+        if let Some(timeout) = session.timeout.take() {
+            timeout.abort();
+        }
+        if let Some((gatt, client_id)) = self.client() {
+            let _ = gatt.lock().unwrap().client_disconnect(client_id, session.address);
+        }
+    }
+}
+
+impl IDfu for DfuManager {
+    fn start_dfu(
+        &mut self,
+        addr: BtAddress,
+        init_packet: Vec<u8>,
+        firmware: Vec<u8>,
+        mut callback: Box<dyn IDfuCallback + Send>,
+    ) -> BtResult<i32> {
+        if firmware.is_empty() || firmware.len() > i32::MAX as usize {
+            return Err(BtError::invalid_argument(format!(
+                "Invalid firmware size {}",
+                firmware.len()
+            )));
+        }
+        if init_packet.is_empty() {
+            return Err(BtError::invalid_argument("Nordic Secure DFU needs an init packet"));
+        }
+        let transfer = NordicTransfer::new(init_packet, firmware);
+
+        let (gatt, client_id) = self.client().ok_or_else(|| {
+            BtError::new(BtErrorCategory::NotReady, "The DFU client is not registered")
+        })?;
+        if let Some(session) =
+            self.sessions.values().find(|s| s.device == addr || s.address == addr)
+        {
+            return Err(BtError::new(
+                BtErrorCategory::Busy,
+                format!("Firmware update {} of {} is running", session.id, addr),
+            ));
+        }
+
+        gatt.lock().unwrap().client_connect(
+            client_id,
+            addr,
+            true,
+            BtTransport::Le as i32,
+            false,
+            LePhy::Phy1m as i32,
+        )?;
+
+        let tx = self.tx.clone();
+        let callback_id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _ = tx.send(Message::DfuCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.next_dfu_id += 1;
+        let id = self.next_dfu_id;
+        self.sessions.insert(
+            id,
+            Session {
+                id,
+                device: addr,
+                address: addr,
+                transfer,
+                callback,
+                callback_id,
+                state: SessionState::Connecting,
+                mtu: DEFAULT_ATT_MTU,
+                control_point: None,
+                packet: None,
+                buttonless: None,
+                rebooted: false,
+                operation: 0,
+                reported_percent: None,
+                timeout: None,
+            },
+        );
+        self.advance(id, SessionState::Connecting);
+
+        Ok(id)
+    }
+
+    fn cancel_dfu(&mut self, dfu_id: i32) -> BtResult<()> {
+        if !self.sessions.contains_key(&dfu_id) {
+            return Err(BtError::not_found(format!("Firmware update {} is not running", dfu_id)));
+        }
+        self.finish(dfu_id, DfuStatus::Cancelled, String::from("Cancelled"));
+        Ok(())
+    }
+}
+
+fn send_dfu_action(tx: &Sender<Message>, action: DfuActions) {
+    let tx = tx.clone();
+    topstack::get_runtime().spawn(async move {
+        let _ = tx.send(Message::Dfu(action)).await;
+    });
+}
+
+/// Relays the events of the GATT client of the firmware update manager.
+struct DfuGattCallback {
+    tx: Sender<Message>,
+}
+
+impl IBluetoothGattCallback for DfuGattCallback {
+    fn on_client_registered(&self, status: i32, client_id: i32) {
+        send_dfu_action(&self.tx, DfuActions::GattClientRegistered(status, client_id));
+    }
+
+    fn on_client_connection_state(
+        &self,
+        status: i32,
+        _client_id: i32,
+        connected: bool,
+        addr: BtAddress,
+    ) {
+        let connected = connected && status == GattStatus::Success as i32;
+        send_dfu_action(&self.tx, DfuActions::GattConnectionState(addr.to_string(), connected));
+    }
+
+    fn on_phy_update(&self, _addr: BtAddress, _tx_phy: LePhy, _rx_phy: LePhy, _status: GattStatus) {
+    }
+
+    fn on_phy_read(&self, _addr: BtAddress, _tx_phy: LePhy, _rx_phy: LePhy, _status: GattStatus) {}
+
+    fn on_search_complete(
+        &self,
+        addr: BtAddress,
+        services: Vec<BluetoothGattService>,
+        status: i32,
+    ) {
+        send_dfu_action(
+            &self.tx,
+            DfuActions::GattSearchComplete(addr.to_string(), services, status),
+        );
+    }
+
+    fn on_service_read(
+        &self,
+        _addr: BtAddress,
+        _service_uuid: Uuid128Bit,
+        _results: Vec<CharacteristicReadResult>,
+    ) {
+    }
+
+    fn on_conformance_report(&self, _addr: BtAddress, _issues: Vec<ConformanceIssue>) {}
+
+    fn on_get_gatt_db(&self, _addr: BtAddress, _services: Vec<BluetoothGattService>) {}
+
+    fn on_characteristic_read(
+        &self,
+        _addr: BtAddress,
+        _status: i32,
+        _handle: i32,
+        _value: Vec<u8>,
+    ) {
+    }
+
+    fn on_characteristic_write(&self, addr: BtAddress, status: i32, handle: i32) {
+        send_dfu_action(
+            &self.tx,
+            DfuActions::CharacteristicWritten(addr.to_string(), status, handle),
+        );
+    }
+
+    fn on_characteristic_write_progress(
+        &self,
+        _addr: BtAddress,
+        _handle: i32,
+        _bytes_written: i32,
+        _total_bytes: i32,
+    ) {
+    }
+
+    fn on_execute_write(&self, _addr: BtAddress, _status: i32) {}
+
+    fn on_descriptor_read(&self, _addr: BtAddress, _status: i32, _handle: i32, _value: Vec<u8>) {}
+
+    fn on_descriptor_write(&self, addr: BtAddress, status: i32, handle: i32) {
+        send_dfu_action(&self.tx, DfuActions::DescriptorWritten(addr.to_string(), status, handle));
+    }
+
+    fn on_notify(&self, addr: BtAddress, handle: i32, value: Vec<u8>) {
+        send_dfu_action(&self.tx, DfuActions::GattNotification(addr.to_string(), handle, value));
+    }
+
+    fn on_notify_multiple(&self, addr: BtAddress, values: Vec<GattHandleValue>) {
+        for v in values {
+            self.on_notify(addr, v.handle, v.value);
+        }
+    }
+
+    fn on_notification_queue_overflow(&self, _addr: BtAddress, _handle: i32, _dropped: u32) {}
+
+    fn on_read_remote_rssi(&self, _addr: BtAddress, _rssi: i32, _status: i32) {}
+
+    fn on_rssi_threshold_crossed(&self, _addr: BtAddress, _rssi: i32, _threshold: i32) {}
+
+    fn on_configure_mtu(&self, addr: BtAddress, mtu: i32, status: i32) {
+        send_dfu_action(&self.tx, DfuActions::MtuConfigured(addr.to_string(), mtu, status));
+    }
+
+    fn on_connection_updated(
+        &self,
+        _addr: BtAddress,
+        _interval: i32,
+        _latency: i32,
+        _timeout: i32,
+        _status: i32,
+    ) {
+    }
+
+    fn on_service_changed(&self, _addr: BtAddress) {}
+
+    fn on_notification_pipe_active(&self, _addr: BtAddress, _handle: i32) {}
+
+    fn on_eatt_state_changed(&self, _addr: BtAddress, _count: i32, _mtus: Vec<i32>) {}
+}
+
+impl RPCProxy for DfuGattCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("DfuManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth_gatt::BluetoothGattDescriptor;
+
+    fn checksum(offset: usize, crc: u32) -> Vec<u8> {
+        let mut response = vec![NORDIC_OP_RESPONSE, NORDIC_OP_CALCULATE_CHECKSUM, 0x01];
+        response.extend_from_slice(&(offset as u32).to_le_bytes());
+        response.extend_from_slice(&crc.to_le_bytes());
+        response
+    }
+
+    fn select(max_object_size: u32) -> Vec<u8> {
+        let mut response = vec![NORDIC_OP_RESPONSE, NORDIC_OP_SELECT, 0x01];
+        response.extend_from_slice(&max_object_size.to_le_bytes());
+        response.extend_from_slice(&[0; 8]);
+        response
+    }
+
+    /// Writes the packets of an object until the transfer asks for something else.
+    fn write_packets(
+        transfer: &mut NordicTransfer,
+        mut step: TransferStep,
+    ) -> (usize, TransferStep) {
+        let mut written = 0;
+        while let TransferStep::Packet(packet) = step {
+            written += packet.len();
+            step = transfer.on_packet_written();
+        }
+        (written, step)
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0, crc32_update(0, &[]));
+        assert_eq!(0xCBF4_3926, crc32_update(0, b"123456789"));
+        assert_eq!(0xCBF4_3926, crc32_update(crc32_update(0, b"1234"), b"56789"));
+    }
+
+    #[test]
+    fn test_nordic_transfer() {
+        let init_packet = vec![0xAA; 10];
+        let firmware: Vec<u8> = (0..=255).cycle().take(700).collect();
+        let mut transfer = NordicTransfer::new(init_packet.clone(), firmware.clone());
+
+        assert_eq!(TransferStep::Command(vec![0x02, 12, 0]), transfer.start(20));
+        assert_eq!(
+            Ok(TransferStep::Command(vec![0x06, 0x01])),
+            transfer.on_response(&[0x60, 0x02, 0x01])
+        );
+        assert_eq!(
+            Ok(TransferStep::Command(vec![0x01, 0x01, 10, 0, 0, 0])),
+            transfer.on_response(&select(256))
+        );
+        assert_eq!(
+            Ok(TransferStep::Packet(init_packet.clone())),
+            transfer.on_response(&[0x60, 0x01, 0x01])
+        );
+        assert_eq!(TransferStep::Command(vec![0x03]), transfer.on_packet_written());
+        assert_eq!(
+            Ok(TransferStep::Command(vec![0x04])),
+            transfer.on_response(&checksum(10, crc32_update(0, &init_packet)))
+        );
+        assert_eq!(0, transfer.bytes_sent());
+
+        // The firmware is sent in objects of 512 bytes, with a receipt every 12 packets.
+        assert_eq!(
+            Ok(TransferStep::Command(vec![0x06, 0x02])),
+            transfer.on_response(&[0x60, 0x04, 0x01])
+        );
+        assert_eq!(
+            Ok(TransferStep::Command(vec![0x01, 0x02, 0x00, 0x02, 0, 0])),
+            transfer.on_response(&select(512))
+        );
+        let step = transfer.on_response(&[0x60, 0x01, 0x01]).unwrap();
+        assert_eq!((240, TransferStep::AwaitResponse), write_packets(&mut transfer, step));
+        assert_eq!(240, transfer.bytes_sent());
+        let step = transfer.on_response(&checksum(240, crc32_update(0, &firmware[..240])));
+        let (written, step) = write_packets(&mut transfer, step.unwrap());
+        assert_eq!((240, TransferStep::AwaitResponse), (written, step));
+        let step = transfer.on_response(&checksum(480, crc32_update(0, &firmware[..480])));
+        assert_eq!(
+            (32, TransferStep::Command(vec![0x03])),
+            write_packets(&mut transfer, step.unwrap())
+        );
+        assert_eq!(
+            Ok(TransferStep::Command(vec![0x04])),
+            transfer.on_response(&checksum(512, crc32_update(0, &firmware[..512])))
+        );
+        assert_eq!(
+            Ok(TransferStep::Command(vec![0x01, 0x02, 188, 0, 0, 0])),
+            transfer.on_response(&[0x60, 0x04, 0x01])
+        );
+        let step = transfer.on_response(&[0x60, 0x01, 0x01]).unwrap();
+        assert_eq!((188, TransferStep::Command(vec![0x03])), write_packets(&mut transfer, step));
+        assert_eq!(
+            Ok(TransferStep::Command(vec![0x04])),
+            transfer.on_response(&checksum(700, crc32_update(0, &firmware)))
+        );
+        assert_eq!(Ok(TransferStep::Complete), transfer.on_response(&[0x60, 0x04, 0x01]));
+        assert_eq!(700, transfer.bytes_sent());
+    }
+
+    #[test]
+    fn test_nordic_transfer_errors() {
+        let mut transfer = NordicTransfer::new(vec![0xAA; 10], vec![0xBB; 100]);
+        transfer.start(20);
+        // An unsupported PRN, then a response to another command.
+        assert_eq!(DfuStatus::Rejected, transfer.on_response(&[0x60, 0x02, 0x03]).unwrap_err().0);
+        assert_eq!(
+            DfuStatus::InvalidResponse,
+            transfer.on_response(&[0x60, 0x04, 0x01]).unwrap_err().0
+        );
+
+        // An init packet larger than the command objects.
+        transfer.on_response(&[0x60, 0x02, 0x01]).unwrap();
+        assert_eq!(DfuStatus::Rejected, transfer.on_response(&select(8)).unwrap_err().0);
+
+        // Bytes corrupted on the way.
+        transfer.on_response(&select(256)).unwrap();
+        let step = transfer.on_response(&[0x60, 0x01, 0x01]).unwrap();
+        write_packets(&mut transfer, step);
+        assert_eq!(
+            DfuStatus::VerificationFailed,
+            transfer.on_response(&checksum(10, 0x1234_5678)).unwrap_err().0
+        );
+        assert_eq!(
+            DfuStatus::InvalidResponse,
+            transfer.on_response(&[0x60, 0x03, 0x01, 0x0A]).unwrap_err().0
+        );
+    }
+
+    fn characteristic(uuid: Uuid128Bit, handle: i32, cccd: bool) -> BluetoothGattCharacteristic {
+        let mut characteristic = BluetoothGattCharacteristic::new(uuid, handle, 0, 0);
+        if cccd {
+            characteristic.descriptors.push(BluetoothGattDescriptor::new(CCCD_UUID, handle + 1, 0));
+        }
+        characteristic
+    }
+
+    fn dfu_service(characteristics: Vec<BluetoothGattCharacteristic>) -> BluetoothGattService {
+        let mut service = BluetoothGattService::new(
+            NORDIC_DFU_SERVICE_UUID,
+            1,
+            BluetoothGattService::SERVICE_TYPE_PRIMARY,
+        );
+        service.characteristics = characteristics;
+        service
+    }
+
+    #[test]
+    fn test_find_attributes() {
+        let bootloader = dfu_service(vec![
+            characteristic(NORDIC_DFU_CONTROL_POINT_UUID, 10, true),
+            characteristic(NORDIC_DFU_PACKET_UUID, 12, false),
+        ]);
+        assert_eq!(
+            Some(DfuAttributes::Bootloader { control_point: 10, cccd: 11, packet: 12 }),
+            find_attributes(&[bootloader])
+        );
+
+        let application = dfu_service(vec![characteristic(NORDIC_BUTTONLESS_UUID, 20, true)]);
+        assert_eq!(
+            Some(DfuAttributes::Buttonless { characteristic: 20, cccd: 21, bonded: false }),
+            find_attributes(&[application])
+        );
+        let application =
+            dfu_service(vec![characteristic(NORDIC_BUTTONLESS_BONDED_UUID, 20, true)]);
+        assert_eq!(
+            Some(DfuAttributes::Buttonless { characteristic: 20, cccd: 21, bonded: true }),
+            find_attributes(&[application])
+        );
+
+        // The responses cannot be received without a CCCD.
+        let bootloader = dfu_service(vec![
+            characteristic(NORDIC_DFU_CONTROL_POINT_UUID, 10, false),
+            characteristic(NORDIC_DFU_PACKET_UUID, 12, false),
+        ]);
+        assert_eq!(None, find_attributes(&[bootloader]));
+        let application = dfu_service(vec![characteristic(NORDIC_BUTTONLESS_UUID, 20, false)]);
+        assert_eq!(None, find_attributes(&[application]));
+
+        // The characteristics of other services are ignored.
+        let mut other = dfu_service(vec![characteristic(NORDIC_BUTTONLESS_UUID, 20, true)]);
+        other.uuid = DFU_CLIENT_UUID;
+        assert_eq!(None, find_attributes(&[other]));
+    }
+
+    #[test]
+    fn test_bootloader_address() {
+        let address = BtAddress::from_string("AA:BB:CC:DD:EE:10").unwrap();
+        assert_eq!("AA:BB:CC:DD:EE:11", bootloader_address(address, false).to_string());
+        assert_eq!(address, bootloader_address(address, true));
+
+        let address = BtAddress::from_string("AA:BB:CC:DD:EE:FF").unwrap();
+        assert_eq!("AA:BB:CC:DD:EE:00", bootloader_address(address, false).to_string());
+    }
+
+    #[test]
+    fn test_check_bootloader_entered() {
+        assert_eq!(Ok(()), check_bootloader_entered(&[0x20, 0x01, 0x01]));
+        // The bootloader refusing to start without a valid application, or without bonds.
+        assert_eq!(
+            DfuStatus::Rejected,
+            check_bootloader_entered(&[0x20, 0x01, 0x04]).unwrap_err().0
+        );
+        assert_eq!(
+            DfuStatus::InvalidResponse,
+            check_bootloader_entered(&[0x20, 0x02, 0x01]).unwrap_err().0
+        );
+        assert_eq!(DfuStatus::InvalidResponse, check_bootloader_entered(&[0x20]).unwrap_err().0);
+    }
+}
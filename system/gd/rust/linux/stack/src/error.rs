@@ -0,0 +1,241 @@
+//! Error type shared by the btstack public APIs.
+
+use bt_topshim::btif::BtStatus;
+
+use num_traits::cast::FromPrimitive;
+use std::fmt;
+
+/// Broad classification of a `BtError`.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum BtErrorCategory {
+    /// The operation failed for an unspecified reason.
+    Failed = 0,
+    /// A parameter of the request is invalid.
+    InvalidArgument,
+    /// The stack, adapter or profile is not ready for the request.
+    NotReady,
+    /// Another operation is in progress.
+    Busy,
+    /// The target of the request (device, client, connection, ...) is not known.
+    NotFound,
+    /// The request is not supported by the stack or controller.
+    Unsupported,
+    /// The controller reported an HCI error. The sub-code is the HCI error code.
+    Hci,
+    /// The remote device reported an ATT error. The sub-code is the ATT error code.
+    Att,
+    /// The pairing failed with an SMP error. The sub-code is the SMP reason code.
+    Smp,
+    /// The client reached a limit on the resources it may hold, such as advertising sets.
+    LimitExceeded,
+    /// The platform policy does not permit the client to make the request.
+    PermissionDenied,
+    /// The advertise data of the request is rejected. The sub-code is the
+    /// `AdvertiseDataProblem` found.
+    InvalidAdvertiseData,
+    /// The service to add to a GATT server is inconsistent. The sub-code is the first
+    /// `ServiceValidationProblem` found.
+    InvalidService,
+}
+
+/// Error returned by the btstack APIs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BtError {
+    pub category: BtErrorCategory,
+    /// Protocol specific error code for the `Hci`, `Att` and `Smp` categories, the problem found
+    /// for `InvalidAdvertiseData` and `InvalidService`, otherwise the `BtStatus` the error
+    /// originates from if any.
+    pub sub_code: u32,
+    pub message: String,
+}
+
+/// Result type of the btstack APIs.
+pub type BtResult<T> = Result<T, BtError>;
+
+// HCI error codes for which retrying later may succeed.
+const HCI_RETRIABLE_CODES: &[u32] = &[
+    0x07, // Memory Capacity Exceeded
+    0x08, // Connection Timeout
+    0x09, // Connection Limit Exceeded
+    0x0C, // Command Disallowed
+    0x0D, // Connection Rejected due to Limited Resources
+    0x22, // LMP/LL Response Timeout
+    0x2A, // Different Transaction Collision
+    0x3A, // Controller Busy
+    0x3E, // Connection Failed to be Established
+];
+
+// ATT error codes for which retrying later may succeed.
+const ATT_RETRIABLE_CODES: &[u32] = &[
+    0x11, // Insufficient Resources
+];
+
+// SMP reason codes for which retrying later may succeed.
+const SMP_RETRIABLE_CODES: &[u32] = &[
+    0x09, // Repeated Attempts
+];
+
+impl BtError {
+    pub fn new<T: Into<String>>(category: BtErrorCategory, message: T) -> BtError {
+        BtError { category, sub_code: 0, message: message.into() }
+    }
+
+    pub fn invalid_argument<T: Into<String>>(message: T) -> BtError {
+        BtError::new(BtErrorCategory::InvalidArgument, message)
+    }
+
+    pub fn not_found<T: Into<String>>(message: T) -> BtError {
+        BtError::new(BtErrorCategory::NotFound, message)
+    }
+
+    pub fn hci(code: u32) -> BtError {
+        BtError { category: BtErrorCategory::Hci, sub_code: code, message: String::from("") }
+    }
+
+    pub fn att(code: u32) -> BtError {
+        BtError { category: BtErrorCategory::Att, sub_code: code, message: String::from("") }
+    }
+
+    pub fn smp(code: u32) -> BtError {
+        BtError { category: BtErrorCategory::Smp, sub_code: code, message: String::from("") }
+    }
+
+    /// Returns whether the same request may succeed if retried later.
+    pub fn is_retriable(&self) -> bool {
+        match self.category {
+            BtErrorCategory::Busy | BtErrorCategory::NotReady => true,
+            BtErrorCategory::Hci => HCI_RETRIABLE_CODES.contains(&self.sub_code),
+            BtErrorCategory::Att => ATT_RETRIABLE_CODES.contains(&self.sub_code),
+            BtErrorCategory::Smp => SMP_RETRIABLE_CODES.contains(&self.sub_code),
+            _ => false,
+        }
+    }
+
+    /// Returns the error as named after its category with `prefix`, and a message carrying the
+    /// sub-code, for the RPC layers.
+    pub fn to_named_error(&self, prefix: &str) -> (String, String) {
+        (
+            format!("{}{:?}", prefix, self.category),
+            format!("{:#x}: {}", self.sub_code, self.message),
+        )
+    }
+
+    /// Builds the error back from its name and message given by `to_named_error`. Unknown names
+    /// are `Failed` errors.
+    pub fn from_named_error(prefix: &str, name: &str, message: &str) -> BtError {
+        let category = name
+            .strip_prefix(prefix)
+            .and_then(|name| {
+                // The categories are numbered from 0 without gaps.
+                (0..)
+                    .map(BtErrorCategory::from_u32)
+                    .take_while(Option::is_some)
+                    .flatten()
+                    .find(|c| name == format!("{:?}", c))
+            })
+            .unwrap_or(BtErrorCategory::Failed);
+
+        let (sub_code, message) = match message.find(": ") {
+            Some(pos) => (
+                u32::from_str_radix(message[..pos].trim_start_matches("0x"), 16).unwrap_or(0),
+                &message[pos + 2..],
+            ),
+            None => (0, message),
+        };
+
+        BtError { category, sub_code, message: String::from(message) }
+    }
+
+    /// Converts the integer status returned by the btif interface into a result.
+    pub fn from_status(status: i32) -> BtResult<()> {
+        match BtStatus::from_u32(status as u32).unwrap_or(BtStatus::Unknown) {
+            BtStatus::Success => Ok(()),
+            status => Err(BtError::from(status)),
+        }
+    }
+}
+
+impl From<BtStatus> for BtError {
+    fn from(status: BtStatus) -> Self {
+        let category = match status {
+            BtStatus::NotReady => BtErrorCategory::NotReady,
+            BtStatus::Busy => BtErrorCategory::Busy,
+            BtStatus::Unsupported => BtErrorCategory::Unsupported,
+            BtStatus::InvalidParam => BtErrorCategory::InvalidArgument,
+            BtStatus::RemoteDeviceDown => BtErrorCategory::NotFound,
+            _ => BtErrorCategory::Failed,
+        };
+
+        BtError { category, sub_code: status as u32, message: format!("{:?}", status) }
+    }
+}
+
+impl fmt::Display for BtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.category {
+            BtErrorCategory::Hci | BtErrorCategory::Att | BtErrorCategory::Smp => {
+                write!(f, "{:?} error {:#04x}", self.category, self.sub_code)?
+            }
+            _ => write!(f, "{:?}", self.category)?,
+        }
+
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for BtError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retriable() {
+        assert!(BtError::new(BtErrorCategory::Busy, "").is_retriable());
+        assert!(!BtError::invalid_argument("bad address").is_retriable());
+        assert!(BtError::hci(0x3E).is_retriable());
+        assert!(!BtError::hci(0x05).is_retriable());
+        assert!(BtError::att(0x11).is_retriable());
+        assert!(!BtError::att(0x05).is_retriable());
+        assert!(BtError::smp(0x09).is_retriable());
+    }
+
+    #[test]
+    fn test_from_status() {
+        assert_eq!(Ok(()), BtError::from_status(0));
+
+        let err = BtError::from_status(BtStatus::Busy as i32).unwrap_err();
+        assert_eq!(BtErrorCategory::Busy, err.category);
+        assert_eq!(BtStatus::Busy as u32, err.sub_code);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("Hci error 0x3e", BtError::hci(0x3E).to_string());
+        assert_eq!("NotFound: no client 3", BtError::not_found("no client 3").to_string());
+    }
+
+    #[test]
+    fn test_named_error() {
+        let prefix = "org.example.Error.";
+        let err =
+            BtError { category: BtErrorCategory::Att, sub_code: 0x0E, message: "a: b".into() };
+        let (name, message) = err.to_named_error(prefix);
+        assert_eq!("org.example.Error.Att", name);
+        assert_eq!(err, BtError::from_named_error(prefix, &name, &message));
+
+        let err = BtError::new(BtErrorCategory::InvalidService, "");
+        let (name, message) = err.to_named_error(prefix);
+        assert_eq!(err, BtError::from_named_error(prefix, &name, &message));
+
+        let err = BtError::from_named_error(prefix, "org.other.Error.Att", "no sub-code");
+        assert_eq!(BtErrorCategory::Failed, err.category);
+        assert_eq!(0, err.sub_code);
+        assert_eq!("no sub-code", err.message);
+    }
+}
@@ -0,0 +1,1518 @@
+//! Fast Pair provider, letting the phones of the users pair with the host in one tap, see
+//! `IFastPair`.
+//!
+//! The provider advertises the Fast Pair service data: the model ID of the host while the adapter
+//! is discoverable, so that nearby seekers offer to pair with it, and otherwise a filter of the
+//! account keys it holds, so that the seekers of the same account recognize it. It serves the
+//! key-based pairing characteristics on its own GATT server:
+//!
+//! 1. The seeker writes a key-based pairing request encrypted with a key shared with the
+//!    provider: either derived by ECDH from the seeker public key and the anti-spoofing private
+//!    key of the model, or one of the account keys. The provider answers with its public address.
+//! 2. The seeker starts pairing, and both sides exchange the passkey of the numeric comparison
+//!    encrypted with the shared key. The provider confirms the pairing itself if they match.
+//! 3. The seeker writes the account key of the user, which the provider stores so that the other
+//!    seekers of the account may pair without ECDH.
+//!
+//! The provider is configured by `FAST_PAIR_CONFIG_FILE`, holding the model ID and the
+//! anti-spoofing private key registered for the model of the host, and is disabled without it.
+//! The provider does not initiate the bonding itself, so the requests asking for it are answered
+//! the same way as the others and the seeker pairs on its own. The pairing is only confirmed if
+//! it comes from the address of the GATT connection of the seeker, or from the BR/EDR address
+//! named by its request.
+
+use bt_topshim::btif::{BtSspVariant, RawAddress, Uuid128Bit};
+use bt_topshim::profiles::gatt::GattStatus;
+use bt_topshim::topstack;
+
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::Sender;
+
+use crate::address::BtAddress;
+use crate::bluetooth::{Bluetooth, BluetoothDevice, IBluetooth, IBluetoothCallback, RadioActivity};
+use crate::bluetooth_adv::{
+    AdvertiseData, AdvertisingSetParameters, AdvertisingStatus, AdvertisingTerminationReason,
+    IAdvertisingSetCallback,
+};
+use crate::bluetooth_gatt::{
+    BluetoothGatt, BluetoothGattCharacteristic, BluetoothGattDescriptor, BluetoothGattService,
+    IBluetoothGatt, IBluetoothGattServerCallback, LePhy, BASE_UUID,
+};
+use crate::crypto::{
+    aes128_decrypt, aes128_encrypt, p256_ecdh, p256_public_key, random_bytes, sha256,
+};
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::gatt_service_builder::CCCD_UUID;
+use crate::storage::{from_hex, save_lines, to_hex, SECRET_FILE_MODE};
+use crate::uuid::BtUuid;
+use crate::{Message, RPCProxy};
+
+/// Configuration of the provider, see `FastPairConfig::parse`.
+pub const FAST_PAIR_CONFIG_FILE: &str = "/etc/bluetooth/fast_pair.conf";
+
+/// File holding the account keys, one per line from the oldest.
+pub const ACCOUNT_KEYS_FILE: &str = "/var/lib/bluetooth/fast_pair_account_keys";
+
+/// Application UUID of the server of the Fast Pair service.
+const FAST_PAIR_SERVER_UUID: Uuid128Bit = [
+    0x2A, 0x7C, 0x05, 0xE1, 0x6B, 0x38, 0x4F, 0x9D, 0xB2, 0x61, 0x0C, 0x84, 0x5F, 0xD3, 0x17, 0xA9,
+];
+
+const FAST_PAIR_SERVICE_UUID16: [u8; 2] = [0xFE, 0x2C];
+
+const MODEL_ID_UUID: Uuid128Bit = fast_pair_uuid(0x33);
+const KEY_BASED_PAIRING_UUID: Uuid128Bit = fast_pair_uuid(0x34);
+const PASSKEY_UUID: Uuid128Bit = fast_pair_uuid(0x35);
+const ACCOUNT_KEY_UUID: Uuid128Bit = fast_pair_uuid(0x36);
+
+/// Most account keys held, the oldest being dropped first.
+const MAX_ACCOUNT_KEYS: usize = 5;
+
+/// Requests which cannot be decrypted accepted until the provider is enabled again, so that a
+/// seeker cannot search the keys.
+const MAX_KEY_BASED_PAIRING_FAILURES: u32 = 10;
+
+/// Time after the key-based pairing request during which the pairing is confirmed by the
+/// provider.
+const PAIRING_WINDOW: Duration = Duration::from_secs(10);
+
+// Advertising intervals while discoverable and otherwise, in 0.625 ms units.
+const DISCOVERABLE_INTERVAL: i32 = 160;
+const NOT_DISCOVERABLE_INTERVAL: i32 = 400;
+
+// Message types of the characteristics.
+const KEY_BASED_PAIRING_REQUEST: u8 = 0x00;
+const KEY_BASED_PAIRING_RESPONSE: u8 = 0x01;
+const SEEKER_PASSKEY: u8 = 0x02;
+const PROVIDER_PASSKEY: u8 = 0x03;
+const ACCOUNT_KEY: u8 = 0x04;
+
+// Flag of the key-based pairing requests naming the BR/EDR address of the seeker.
+const FLAG_SEEKER_ADDRESS: u8 = 0x40;
+
+// Length of a key-based pairing request carrying the public key of the seeker.
+const PUBLIC_KEY_REQUEST_LEN: usize = 16 + 64;
+
+// Field types of the account key data.
+const FIELD_TYPE_FILTER_SHOW_UI: u8 = 0x0;
+const FIELD_TYPE_SALT: u8 = 0x1;
+
+/// Returns the UUID of a characteristic of the Fast Pair service, FE2C12xx-8366-4814-8EB0-
+/// 01DE32100BEA.
+const fn fast_pair_uuid(short: u8) -> Uuid128Bit {
+    [
+        0xFE, 0x2C, 0x12, short, 0x83, 0x66, 0x48, 0x14, 0x8E, 0xB0, 0x01, 0xDE, 0x32, 0x10, 0x0B,
+        0xEA,
+    ]
+}
+
+fn uuid16(short: [u8; 2]) -> Uuid128Bit {
+    let mut uuid = BASE_UUID;
+    uuid[2..4].copy_from_slice(&short);
+    uuid
+}
+
+/// Defines the Fast Pair provider API.
+pub trait IFastPair {
+    /// Adds an observer of the Fast Pair events.
+    ///
+    /// Returns the id of the callback.
+    fn register_fast_pair_callback(&mut self, callback: Box<dyn IFastPairCallback + Send>) -> u32;
+
+    /// Removes an observer of the Fast Pair events.
+    ///
+    /// Returns false if `callback_id` is not recognized.
+    fn unregister_fast_pair_callback(&mut self, callback_id: u32) -> bool;
+
+    /// Starts or stops the provider. The provider starts enabled if it is configured, and fails
+    /// to start otherwise.
+    fn set_provider_enabled(&mut self, enabled: bool) -> BtResult<()>;
+
+    /// Returns whether the provider is enabled.
+    fn is_provider_enabled(&self) -> bool;
+
+    /// Returns the number of account keys held by the provider.
+    fn get_account_key_count(&self) -> u32;
+
+    /// Forgets the account keys, so that the seekers of the accounts no longer recognize the
+    /// host until they pair again.
+    fn clear_account_keys(&mut self);
+}
+
+/// Fast Pair events.
+pub trait IFastPairCallback: RPCProxy {
+    /// When a seeker writes a key-based pairing request. `accepted` is false if the request could
+    /// not be decrypted with any of the keys of the provider.
+    fn on_key_based_pairing_request(&self, addr: String, accepted: bool);
+
+    /// When a seeker writes an account key. `account_key_count` is the number of account keys
+    /// held by the provider afterwards.
+    fn on_account_key_added(&self, addr: String, account_key_count: u32);
+}
+
+/// Configuration of the provider, registered with the Fast Pair service for the model of the
+/// host.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FastPairConfig {
+    pub model_id: [u8; 3],
+    pub anti_spoofing_private_key: [u8; 32],
+}
+
+impl FastPairConfig {
+    /// Parses the `key = value` lines of the configuration: `model_id`, in 6 hexadecimal digits,
+    /// and `anti_spoofing_private_key`, in 64. Lines starting with `#` are ignored.
+    pub fn parse(contents: &str) -> BtResult<FastPairConfig> {
+        let mut model_id = None;
+        let mut private_key = None;
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    return Err(BtError::invalid_argument(format!("Malformed line '{}'", line)))
+                }
+            };
+            match key {
+                "model_id" => model_id = Some(parse_hex_array::<3>(key, value)?),
+                "anti_spoofing_private_key" => {
+                    private_key = Some(parse_hex_array::<32>(key, value)?)
+                }
+                _ => return Err(BtError::invalid_argument(format!("Unknown key '{}'", key))),
+            }
+        }
+
+        let model_id = model_id.ok_or_else(|| BtError::invalid_argument("Missing model_id"))?;
+        let anti_spoofing_private_key = private_key
+            .ok_or_else(|| BtError::invalid_argument("Missing anti_spoofing_private_key"))?;
+        if p256_public_key(&anti_spoofing_private_key).is_none() {
+            return Err(BtError::invalid_argument("Invalid anti_spoofing_private_key"));
+        }
+        Ok(FastPairConfig { model_id, anti_spoofing_private_key })
+    }
+}
+
+fn parse_hex_array<const N: usize>(key: &str, value: &str) -> BtResult<[u8; N]> {
+    match from_hex(value) {
+        Some(bytes) if bytes.len() == N => {
+            let mut array = [0u8; N];
+            array.copy_from_slice(&bytes);
+            Ok(array)
+        }
+        _ => Err(BtError::invalid_argument(format!("{} is not {} hexadecimal bytes", key, N))),
+    }
+}
+
+/// Account keys written by the seekers, saved to a file after each change.
+pub(crate) struct AccountKeyStore {
+    path: PathBuf,
+    // From the oldest.
+    keys: Vec<[u8; 16]>,
+}
+
+impl AccountKeyStore {
+    /// Loads the keys saved in `path`. Malformed lines are skipped.
+    pub(crate) fn load<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
+        let keys = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter_map(|line| parse_hex_array::<16>("account key", line.trim()).ok())
+                .collect(),
+            Err(_) => vec![],
+        };
+
+        AccountKeyStore { path, keys }
+    }
+
+    pub(crate) fn keys(&self) -> &[[u8; 16]] {
+        &self.keys
+    }
+
+    /// Adds a key as the newest, dropping the oldest if the store is full.
+    pub(crate) fn add(&mut self, key: [u8; 16]) {
+        self.keys.retain(|k| *k != key);
+        self.keys.push(key);
+        if self.keys.len() > MAX_ACCOUNT_KEYS {
+            self.keys.remove(0);
+        }
+        self.save();
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.keys.clear();
+        self.save();
+    }
+
+    fn save(&self) {
+        let lines: Vec<String> = self.keys.iter().map(|key| to_hex(key)).collect();
+
+        if let Err(e) = save_lines(&self.path, &lines, SECRET_FILE_MODE) {
+            warn!("Failed to save the account keys to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Returns the Bloom filter of the account keys advertised with `salt`, which the seekers test
+/// their own account key against.
+pub(crate) fn account_key_filter(keys: &[[u8; 16]], salt: &[u8]) -> Vec<u8> {
+    let size = keys.len() * 6 / 5 + 3;
+    let mut filter = vec![0u8; size];
+    for key in keys {
+        let hash = sha256(&[&key[..], salt].concat());
+        for chunk in hash.chunks(4) {
+            let bit =
+                u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize % (size * 8);
+            filter[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+    filter
+}
+
+/// Returns the service data advertised while the adapter is not discoverable: the filter of the
+/// account keys and its salt, or an empty filter without any key.
+pub(crate) fn account_key_data(keys: &[[u8; 16]], salt: u8) -> Vec<u8> {
+    // The first byte holds flags, all reserved.
+    if keys.is_empty() {
+        return vec![0x00, 0x00];
+    }
+
+    let filter = account_key_filter(keys, &[salt]);
+    let mut data = vec![0x00, ((filter.len() as u8) << 4) | FIELD_TYPE_FILTER_SHOW_UI];
+    data.extend(filter);
+    data.extend(&[(1 << 4) | FIELD_TYPE_SALT, salt]);
+    data
+}
+
+/// Decrypts a key-based pairing request, with the key derived from the public key of the seeker
+/// if the request carries it and with each account key otherwise. The request must name one of
+/// `addresses` as the provider.
+///
+/// Returns the key shared with the seeker and the decrypted request.
+pub(crate) fn decrypt_key_based_pairing_request(
+    config: &FastPairConfig,
+    account_keys: &[[u8; 16]],
+    value: &[u8],
+    addresses: &[[u8; 6]],
+) -> Option<([u8; 16], [u8; 16])> {
+    let mut encrypted = [0u8; 16];
+    let keys = match value.len() {
+        16 => account_keys.to_vec(),
+        PUBLIC_KEY_REQUEST_LEN => {
+            let mut public_key = [0u8; 64];
+            public_key.copy_from_slice(&value[16..]);
+            let secret = p256_ecdh(&config.anti_spoofing_private_key, &public_key)?;
+            let mut key = [0u8; 16];
+            key.copy_from_slice(&sha256(&secret)[..16]);
+            vec![key]
+        }
+        _ => return None,
+    };
+    encrypted.copy_from_slice(&value[..16]);
+
+    keys.into_iter().find_map(|key| {
+        let request = aes128_decrypt(&key, &encrypted);
+        let is_valid = request[0] == KEY_BASED_PAIRING_REQUEST
+            && addresses.iter().any(|address| request[2..8] == address[..]);
+        if is_valid {
+            Some((key, request))
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the address the seeker pairs from: the BR/EDR address named by its request if any,
+/// otherwise the address of its GATT connection.
+fn seeker_address(request: &[u8; 16], connection_address: [u8; 6]) -> [u8; 6] {
+    if request[1] & FLAG_SEEKER_ADDRESS == 0 {
+        return connection_address;
+    }
+    let mut address = [0u8; 6];
+    address.copy_from_slice(&request[8..14]);
+    address
+}
+
+/// Returns the seeker whose key-based pairing request a pairing from `address` follows closely.
+fn pairing_seeker<'a>(
+    sessions: &'a HashMap<String, Session>,
+    address: &[u8; 6],
+) -> Option<&'a String> {
+    sessions
+        .iter()
+        .filter(|(_, session)| {
+            session.started.elapsed() < PAIRING_WINDOW && session.seeker_address == *address
+        })
+        .max_by_key(|(_, session)| session.started)
+        .map(|(seeker, _)| seeker)
+}
+
+/// Decrypts a message written by a seeker with the key of its session, checking its type.
+fn decrypt_message(key: &[u8; 16], value: &[u8], message_type: u8) -> Result<[u8; 16], GattStatus> {
+    if value.len() != 16 {
+        return Err(GattStatus::InvalidAttrLen);
+    }
+
+    let mut encrypted = [0u8; 16];
+    encrypted.copy_from_slice(value);
+    let message = aes128_decrypt(key, &encrypted);
+    if message[0] != message_type {
+        return Err(GattStatus::ValueNotAllowed);
+    }
+    Ok(message)
+}
+
+/// Encrypts a message of a characteristic, padding it to a block with random bytes.
+fn encrypt_message(key: &[u8; 16], message: &[u8]) -> Option<[u8; 16]> {
+    let mut block = [0u8; 16];
+    block[..message.len()].copy_from_slice(message);
+    if let Err(e) = random_bytes(&mut block[message.len()..]) {
+        warn!("Failed to salt a Fast Pair message: {}", e);
+        return None;
+    }
+    Some(aes128_encrypt(key, &block))
+}
+
+fn parse_address(address: &String) -> Option<[u8; 6]> {
+    RawAddress::from_string(address).map(|addr| addr.val)
+}
+
+/// Actions of the Fast Pair provider dispatched from the event loop, as the events of the GATT
+/// server, of the advertising set and of the adapter are delivered while their objects are
+/// locked.
+pub enum FastPairActions {
+    AdapterReady,
+    AdapterRestarting,
+    DiscoverableChanged(bool),
+    /// Params: Device, Variant, Passkey
+    SspRequest(BluetoothDevice, BtSspVariant, u32),
+    /// Params: Status, Server ID
+    ServerRegistered(i32, i32),
+    /// Params: Status, Service
+    ServiceAdded(i32, BluetoothGattService),
+    /// Params: Address, Connected
+    ServerConnectionState(String, bool),
+    /// Params: Address, Request ID, Handle
+    ReadRequest(String, i32, i32),
+    /// Params: Address, Request ID, Offset, Is prepared, Need response, Handle, Value
+    WriteRequest(String, i32, i32, bool, bool, i32, Vec<u8>),
+    /// Params: Advertiser ID, Status
+    AdvertisingSetStarted(i32, AdvertisingStatus),
+    /// Params: Advertiser ID, Address
+    OwnAddress(i32, String),
+}
+
+/// Handles of the characteristics of the service.
+#[derive(Default)]
+struct FastPairHandles {
+    model_id: i32,
+    key_based_pairing: i32,
+    passkey: i32,
+    account_key: i32,
+}
+
+/// Key-based pairing of a seeker, from the request until the account key is written.
+struct Session {
+    key: [u8; 16],
+    started: Instant,
+    // Address the seeker pairs from.
+    seeker_address: [u8; 6],
+    seeker_passkey: Option<u32>,
+    // Device and passkey of the pairing request awaiting the passkey of the seeker.
+    ssp_request: Option<(BluetoothDevice, u32)>,
+}
+
+/// Implementation of the Fast Pair provider API.
+pub struct FastPairManager {
+    tx: Sender<Message>,
+    adapter: Option<Arc<Mutex<Box<Bluetooth>>>>,
+    gatt: Option<Arc<Mutex<Box<BluetoothGatt>>>>,
+    config: Option<FastPairConfig>,
+    account_keys: AccountKeyStore,
+    callbacks: HashMap<u32, Box<dyn IFastPairCallback + Send>>,
+    enabled: bool,
+    discoverable: bool,
+    server_id: Option<i32>,
+    handles: Option<FastPairHandles>,
+    advertiser_id: Option<i32>,
+    // Address advertised by the set, which the seekers may name in their requests.
+    own_address: Option<[u8; 6]>,
+    key_based_pairing_failures: u32,
+    // By address of the seeker.
+    sessions: HashMap<String, Session>,
+}
+
+impl FastPairManager {
+    pub fn new(tx: Sender<Message>) -> FastPairManager {
+        let config = match std::fs::read_to_string(FAST_PAIR_CONFIG_FILE) {
+            Ok(contents) => match FastPairConfig::parse(&contents) {
+                Ok(config) => Some(config),
+                Err(e) => {
+                    warn!("Invalid Fast Pair configuration {}: {}", FAST_PAIR_CONFIG_FILE, e);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+
+        FastPairManager {
+            tx,
+            adapter: None,
+            gatt: None,
+            enabled: config.is_some(),
+            config,
+            account_keys: AccountKeyStore::load(ACCOUNT_KEYS_FILE),
+            callbacks: HashMap::new(),
+            discoverable: false,
+            server_id: None,
+            handles: None,
+            advertiser_id: None,
+            own_address: None,
+            key_based_pairing_failures: 0,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Starts watching the adapter, and starts the provider once the adapter is ready if it is
+    /// configured. Must be called once the profiles are initialized.
+    pub fn init(
+        &mut self,
+        adapter: Arc<Mutex<Box<Bluetooth>>>,
+        gatt: Arc<Mutex<Box<BluetoothGatt>>>,
+    ) {
+        let is_ready = {
+            let mut adapter = adapter.lock().unwrap();
+            adapter.register_callback(Box::new(FastPairAdapterCallback { tx: self.tx.clone() }));
+            self.discoverable = adapter.get_discoverable();
+            adapter.is_ready()
+        };
+
+        self.adapter = Some(adapter);
+        self.gatt = Some(gatt);
+        if is_ready {
+            self.start();
+        }
+    }
+
+    pub(crate) fn remove_callback(&mut self, id: u32) -> bool {
+        match self.callbacks.get_mut(&id) {
+            Some(callback) => {
+                callback.unregister(id);
+                self.callbacks.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn dispatch_fast_pair_actions(&mut self, action: FastPairActions) {
+        match action {
+            FastPairActions::AdapterReady => self.start(),
+            FastPairActions::AdapterRestarting => self.stop(),
+            FastPairActions::DiscoverableChanged(discoverable) => {
+                self.discoverable = discoverable;
+                self.update_advertising(true);
+            }
+            FastPairActions::SspRequest(device, variant, passkey) => {
+                self.on_ssp_request(device, variant, passkey)
+            }
+            FastPairActions::ServerRegistered(status, server_id) => {
+                if status != GattStatus::Success as i32 {
+                    warn!("Failed to register the Fast Pair GATT server: {}", status);
+                    return;
+                }
+                if !self.enabled {
+                    self.gatt.as_ref().unwrap().lock().unwrap().unregister_server(server_id);
+                    return;
+                }
+                self.server_id = Some(server_id);
+                let service = build_service();
+                if let Err(e) =
+                    self.gatt.as_ref().unwrap().lock().unwrap().add_service(server_id, service)
+                {
+                    warn!("Failed to add the Fast Pair service: {}", e);
+                }
+            }
+            FastPairActions::ServiceAdded(status, service) => {
+                if status != GattStatus::Success as i32 {
+                    warn!("Failed to add the Fast Pair service: {}", status);
+                    return;
+                }
+                self.handles = Some(find_handles(&service));
+                self.start_advertising();
+            }
+            FastPairActions::ServerConnectionState(address, connected) => {
+                if !connected {
+                    self.sessions.remove(&address);
+                }
+            }
+            FastPairActions::ReadRequest(address, request_id, handle) => {
+                self.on_read_request(address, request_id, handle)
+            }
+            FastPairActions::WriteRequest(
+                address,
+                request_id,
+                offset,
+                is_prep,
+                need_response,
+                handle,
+                value,
+            ) => {
+                let status = if is_prep {
+                    GattStatus::ReqNotSupported
+                } else if offset != 0 {
+                    GattStatus::InvalidOffset
+                } else {
+                    self.on_write_request(&address, handle, &value)
+                };
+                if need_response {
+                    self.send_response(address, request_id, status, vec![]);
+                }
+            }
+            FastPairActions::AdvertisingSetStarted(advertiser_id, status) => {
+                if self.advertiser_id != Some(advertiser_id) {
+                    return;
+                }
+                if status != AdvertisingStatus::Success {
+                    warn!("Failed to start the Fast Pair advertising: {:?}", status);
+                    self.advertiser_id = None;
+                    return;
+                }
+                let mut gatt = self.gatt.as_ref().unwrap().lock().unwrap();
+                if let Err(e) = gatt.get_own_address(advertiser_id) {
+                    warn!("Failed to read the Fast Pair advertising address: {}", e);
+                }
+            }
+            FastPairActions::OwnAddress(advertiser_id, address) => {
+                if self.advertiser_id != Some(advertiser_id) {
+                    return;
+                }
+                // The salt of the filter changes with the address, so that the advertisements
+                // cannot be linked across the rotations.
+                self.own_address = parse_address(&address);
+                self.update_advertising(false);
+            }
+        }
+    }
+
+    /// Registers the server of the service, which starts the advertising once the service is
+    /// added.
+    fn start(&mut self) {
+        if !self.enabled || self.server_id.is_some() || self.gatt.is_none() {
+            return;
+        }
+
+        self.key_based_pairing_failures = 0;
+        self.gatt.as_ref().unwrap().lock().unwrap().register_server(
+            BtUuid { uu: FAST_PAIR_SERVER_UUID },
+            Box::new(FastPairServerCallback { tx: self.tx.clone() }),
+            false,
+        );
+    }
+
+    fn stop(&mut self) {
+        let mut gatt = match self.gatt.as_ref() {
+            Some(gatt) => gatt.lock().unwrap(),
+            None => return,
+        };
+        if let Some(advertiser_id) = self.advertiser_id.take() {
+            let _ = gatt.stop_advertising_set(advertiser_id);
+        }
+        if let Some(server_id) = self.server_id.take() {
+            gatt.unregister_server(server_id);
+        }
+        self.handles = None;
+        self.own_address = None;
+        self.sessions.clear();
+    }
+
+    fn advertise_data(&self) -> AdvertiseData {
+        let payload = match self.config.as_ref() {
+            Some(config) if self.discoverable => config.model_id.to_vec(),
+            _ => {
+                let mut salt = [0u8];
+                if let Err(e) = random_bytes(&mut salt) {
+                    warn!("Failed to salt the account key filter: {}", e);
+                }
+                account_key_data(self.account_keys.keys(), salt[0])
+            }
+        };
+
+        let mut data =
+            AdvertiseData { include_tx_power_level: self.discoverable, ..Default::default() };
+        data.service_data.insert(BtUuid::from(uuid16(FAST_PAIR_SERVICE_UUID16)), payload);
+        data
+    }
+
+    fn advertise_parameters(&self) -> AdvertisingSetParameters {
+        AdvertisingSetParameters {
+            connectable: true,
+            scannable: true,
+            is_legacy: true,
+            primary_phy: LePhy::Phy1m,
+            secondary_phy: LePhy::Phy1m,
+            interval: if self.discoverable {
+                DISCOVERABLE_INTERVAL
+            } else {
+                NOT_DISCOVERABLE_INTERVAL
+            },
+            own_address_type: -1,
+            ..Default::default()
+        }
+    }
+
+    fn start_advertising(&mut self) {
+        if self.advertiser_id.is_some() {
+            return;
+        }
+
+        let result = self.gatt.as_ref().unwrap().lock().unwrap().start_advertising_set(
+            self.advertise_parameters(),
+            self.advertise_data(),
+            AdvertiseData::default(),
+            0,
+            0,
+            Box::new(FastPairAdvertisingCallback { tx: self.tx.clone() }),
+        );
+        match result {
+            Ok(advertiser_id) => self.advertiser_id = Some(advertiser_id),
+            Err(e) => warn!("Failed to start the Fast Pair advertising: {}", e),
+        }
+    }
+
+    /// Advertises the data for the current keys and discoverability, with a new salt.
+    fn update_advertising(&mut self, parameters_changed: bool) {
+        let advertiser_id = match self.advertiser_id {
+            Some(advertiser_id) => advertiser_id,
+            None => return,
+        };
+
+        let data = self.advertise_data();
+        let parameters = self.advertise_parameters();
+        let mut gatt = self.gatt.as_ref().unwrap().lock().unwrap();
+        if parameters_changed {
+            if let Err(e) = gatt.set_advertising_parameters(advertiser_id, parameters) {
+                warn!("Failed to update the Fast Pair advertising parameters: {}", e);
+            }
+        }
+        if let Err(e) = gatt.set_advertising_data(advertiser_id, data) {
+            warn!("Failed to update the Fast Pair advertising data: {}", e);
+        }
+    }
+
+    fn send_response(&self, address: String, request_id: i32, status: GattStatus, value: Vec<u8>) {
+        let (gatt, server_id, addr) =
+            match (self.gatt.as_ref(), self.server_id, BtAddress::from_string(&address)) {
+                (Some(gatt), Some(server_id), Some(addr)) => (gatt, server_id, addr),
+                _ => return,
+            };
+        let result =
+            gatt.lock().unwrap().send_response(server_id, addr, request_id, status, 0, value);
+        if let Err(e) = result {
+            warn!("Failed to answer a Fast Pair request: {}", e);
+        }
+    }
+
+    /// Notifies a seeker with a message encrypted with the key of its session.
+    fn notify(&self, address: &String, handle: i32, message: &[u8]) {
+        let (session, addr) = match (self.sessions.get(address), BtAddress::from_string(address)) {
+            (Some(session), Some(addr)) => (session, addr),
+            _ => return,
+        };
+        let value = match encrypt_message(&session.key, message) {
+            Some(value) => value.to_vec(),
+            None => return,
+        };
+        let result = self.gatt.as_ref().unwrap().lock().unwrap().send_notification(
+            self.server_id.unwrap_or(0),
+            addr,
+            handle,
+            false,
+            value,
+        );
+        if let Err(e) = result {
+            warn!("Failed to notify {} of a Fast Pair message: {}", address, e);
+        }
+    }
+
+    fn on_read_request(&self, address: String, request_id: i32, handle: i32) {
+        let is_model_id = self.handles.as_ref().map_or(false, |h| h.model_id == handle);
+        match self.config.as_ref() {
+            Some(config) if is_model_id => self.send_response(
+                address,
+                request_id,
+                GattStatus::Success,
+                config.model_id.to_vec(),
+            ),
+            // The configuration descriptors read as disabled, the notifications being sent to
+            // the seekers regardless.
+            _ => self.send_response(address, request_id, GattStatus::Success, vec![0, 0]),
+        }
+    }
+
+    /// Handles a write of a characteristic or descriptor. Returns the status to answer with.
+    fn on_write_request(&mut self, address: &String, handle: i32, value: &[u8]) -> GattStatus {
+        let (key_based_pairing, passkey, account_key) = match self.handles.as_ref() {
+            Some(h) => (h.key_based_pairing, h.passkey, h.account_key),
+            None => return GattStatus::WriteNotPermit,
+        };
+
+        if handle == key_based_pairing {
+            self.on_key_based_pairing_request(address, value)
+        } else if handle == passkey {
+            self.on_seeker_passkey(address, value)
+        } else if handle == account_key {
+            self.on_account_key(address, value)
+        } else {
+            // The configuration descriptors, see `on_read_request`.
+            GattStatus::Success
+        }
+    }
+
+    fn on_key_based_pairing_request(&mut self, address: &String, value: &[u8]) -> GattStatus {
+        if self.key_based_pairing_failures >= MAX_KEY_BASED_PAIRING_FAILURES {
+            return GattStatus::InsufAuthentication;
+        }
+        // The requests with the public key of the seeker are only accepted while discoverable,
+        // the others coming from seekers which already know an account key.
+        if value.len() == PUBLIC_KEY_REQUEST_LEN && !self.discoverable {
+            return self.reject_key_based_pairing(address);
+        }
+        let config = match self.config.as_ref() {
+            Some(config) => config,
+            None => return GattStatus::WriteNotPermit,
+        };
+        let public_address =
+            match parse_address(&self.adapter.as_ref().unwrap().lock().unwrap().get_address()) {
+                Some(address) => address,
+                None => return GattStatus::InternalError,
+            };
+
+        let connection_address = match parse_address(address) {
+            Some(connection_address) => connection_address,
+            None => return GattStatus::InternalError,
+        };
+
+        let addresses: Vec<[u8; 6]> =
+            self.own_address.iter().chain(std::iter::once(&public_address)).cloned().collect();
+        let account_keys = self.account_keys.keys();
+        let (key, request) =
+            match decrypt_key_based_pairing_request(config, account_keys, value, &addresses) {
+                Some(decrypted) => decrypted,
+                None => return self.reject_key_based_pairing(address),
+            };
+
+        debug!("Fast Pair key-based pairing request from {}", address);
+        self.sessions.insert(
+            address.clone(),
+            Session {
+                key,
+                started: Instant::now(),
+                seeker_address: seeker_address(&request, connection_address),
+                seeker_passkey: None,
+                ssp_request: None,
+            },
+        );
+        let mut response = vec![KEY_BASED_PAIRING_RESPONSE];
+        response.extend(&public_address);
+        let handle = self.handles.as_ref().unwrap().key_based_pairing;
+        self.notify(address, handle, &response);
+
+        for callback in self.callbacks.values() {
+            callback.on_key_based_pairing_request(address.clone(), true);
+        }
+        GattStatus::Success
+    }
+
+    fn reject_key_based_pairing(&mut self, address: &String) -> GattStatus {
+        self.key_based_pairing_failures += 1;
+        info!("Rejected a Fast Pair key-based pairing request from {}", address);
+        for callback in self.callbacks.values() {
+            callback.on_key_based_pairing_request(address.clone(), false);
+        }
+        GattStatus::InsufAuthentication
+    }
+
+    fn on_ssp_request(&mut self, device: BluetoothDevice, variant: BtSspVariant, passkey: u32) {
+        if variant != BtSspVariant::PasskeyConfirmation {
+            return;
+        }
+
+        let address = match parse_address(&device.address) {
+            Some(address) => address,
+            None => return,
+        };
+        if let Some(seeker) = pairing_seeker(&self.sessions, &address).cloned() {
+            self.sessions.get_mut(&seeker).unwrap().ssp_request = Some((device, passkey));
+            self.confirm_pairing(&seeker);
+            return;
+        }
+
+        // Another device pairing while a seeker is expected is rejected, rather than confirmed
+        // in its place.
+        let is_awaited =
+            self.sessions.values().any(|session| session.started.elapsed() < PAIRING_WINDOW);
+        if is_awaited {
+            warn!(
+                "Fast Pair pairing from {} is not the one of a seeker, rejecting it",
+                device.address
+            );
+            self.adapter.as_ref().unwrap().lock().unwrap().set_pairing_confirmation(device, false);
+        }
+    }
+
+    fn on_seeker_passkey(&mut self, address: &String, value: &[u8]) -> GattStatus {
+        let session = match self.sessions.get_mut(address) {
+            Some(session) => session,
+            None => return GattStatus::InsufAuthentication,
+        };
+        let message = match decrypt_message(&session.key, value, SEEKER_PASSKEY) {
+            Ok(message) => message,
+            Err(status) => return status,
+        };
+        session.seeker_passkey = Some(u32::from_be_bytes([0, message[1], message[2], message[3]]));
+        self.confirm_pairing(address);
+        GattStatus::Success
+    }
+
+    /// Confirms the pairing of a seeker once both its passkey and the pairing request are
+    /// received, and sends the passkey of the provider to the seeker.
+    fn confirm_pairing(&mut self, address: &String) {
+        let (device, seeker_passkey, passkey) = match self.sessions.get_mut(address) {
+            Some(Session { seeker_passkey: Some(seeker_passkey), ssp_request, .. }) => {
+                match ssp_request.take() {
+                    Some((device, passkey)) => (device, *seeker_passkey, passkey),
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+
+        let accept = seeker_passkey == passkey;
+        if !accept {
+            warn!("Fast Pair passkey of {} does not match, rejecting the pairing", address);
+        }
+        self.adapter.as_ref().unwrap().lock().unwrap().set_pairing_confirmation(device, accept);
+
+        let mut message = vec![PROVIDER_PASSKEY];
+        message.extend(&passkey.to_be_bytes()[1..]);
+        let handle = self.handles.as_ref().unwrap().passkey;
+        self.notify(address, handle, &message);
+    }
+
+    fn on_account_key(&mut self, address: &String, value: &[u8]) -> GattStatus {
+        let session = match self.sessions.get(address) {
+            Some(session) => session,
+            None => return GattStatus::InsufAuthentication,
+        };
+        let account_key = match decrypt_message(&session.key, value, ACCOUNT_KEY) {
+            Ok(account_key) => account_key,
+            Err(status) => return status,
+        };
+
+        self.sessions.remove(address);
+        self.account_keys.add(account_key);
+        let count = self.account_keys.keys().len() as u32;
+        info!("Fast Pair account key added by {}, now holding {}", address, count);
+        for callback in self.callbacks.values() {
+            callback.on_account_key_added(address.clone(), count);
+        }
+        self.update_advertising(false);
+        GattStatus::Success
+    }
+}
+
+/// Returns the Fast Pair service to add to the server.
+fn build_service() -> BluetoothGattService {
+    let mut service = BluetoothGattService::new(
+        uuid16(FAST_PAIR_SERVICE_UUID16),
+        0,
+        BluetoothGattService::SERVICE_TYPE_PRIMARY,
+    );
+
+    service.characteristics.push(BluetoothGattCharacteristic::new(
+        MODEL_ID_UUID,
+        0,
+        BluetoothGattCharacteristic::PROPERTY_READ,
+        BluetoothGattCharacteristic::PERMISSION_READ,
+    ));
+    for uuid in &[KEY_BASED_PAIRING_UUID, PASSKEY_UUID] {
+        let mut characteristic = BluetoothGattCharacteristic::new(
+            *uuid,
+            0,
+            BluetoothGattCharacteristic::PROPERTY_WRITE
+                | BluetoothGattCharacteristic::PROPERTY_NOTIFY,
+            BluetoothGattCharacteristic::PERMISSION_WRITE,
+        );
+        characteristic.descriptors.push(BluetoothGattDescriptor::new(
+            CCCD_UUID,
+            0,
+            BluetoothGattCharacteristic::PERMISSION_READ
+                | BluetoothGattCharacteristic::PERMISSION_WRITE,
+        ));
+        service.characteristics.push(characteristic);
+    }
+    service.characteristics.push(BluetoothGattCharacteristic::new(
+        ACCOUNT_KEY_UUID,
+        0,
+        BluetoothGattCharacteristic::PROPERTY_WRITE,
+        BluetoothGattCharacteristic::PERMISSION_WRITE,
+    ));
+    service
+}
+
+fn find_handles(service: &BluetoothGattService) -> FastPairHandles {
+    let mut handles = FastPairHandles::default();
+    for characteristic in &service.characteristics {
+        let handle = characteristic.instance_id;
+        match characteristic.uuid {
+            MODEL_ID_UUID => handles.model_id = handle,
+            KEY_BASED_PAIRING_UUID => handles.key_based_pairing = handle,
+            PASSKEY_UUID => handles.passkey = handle,
+            ACCOUNT_KEY_UUID => handles.account_key = handle,
+            _ => (),
+        }
+    }
+    handles
+}
+
+impl IFastPair for FastPairManager {
+    fn register_fast_pair_callback(
+        &mut self,
+        mut callback: Box<dyn IFastPairCallback + Send>,
+    ) -> u32 {
+        let tx = self.tx.clone();
+
+        let id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _result = tx.send(Message::FastPairCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.callbacks.insert(id, callback);
+        id
+    }
+
+    fn unregister_fast_pair_callback(&mut self, callback_id: u32) -> bool {
+        self.remove_callback(callback_id)
+    }
+
+    fn set_provider_enabled(&mut self, enabled: bool) -> BtResult<()> {
+        if enabled && self.config.is_none() {
+            return Err(BtError::new(
+                BtErrorCategory::NotReady,
+                format!("Fast Pair is not configured in {}", FAST_PAIR_CONFIG_FILE),
+            ));
+        }
+        if enabled == self.enabled {
+            return Ok(());
+        }
+
+        self.enabled = enabled;
+        if enabled {
+            let is_ready = self.adapter.as_ref().map_or(false, |a| a.lock().unwrap().is_ready());
+            if is_ready {
+                self.start();
+            }
+        } else {
+            self.stop();
+        }
+        Ok(())
+    }
+
+    fn is_provider_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn get_account_key_count(&self) -> u32 {
+        self.account_keys.keys().len() as u32
+    }
+
+    fn clear_account_keys(&mut self) {
+        self.account_keys.clear();
+        self.update_advertising(false);
+    }
+}
+
+fn send_fast_pair_action(tx: &Sender<Message>, action: FastPairActions) {
+    let tx = tx.clone();
+    topstack::get_runtime().spawn(async move {
+        let _ = tx.send(Message::FastPair(action)).await;
+    });
+}
+
+/// Relays the events of the adapter to the Fast Pair provider.
+struct FastPairAdapterCallback {
+    tx: Sender<Message>,
+}
+
+impl IBluetoothCallback for FastPairAdapterCallback {
+    fn on_address_changed(&self, _addr: String) {}
+
+    fn on_name_changed(&self, _name: String) {}
+
+    fn on_discoverable_changed(&self, discoverable: bool) {
+        send_fast_pair_action(&self.tx, FastPairActions::DiscoverableChanged(discoverable));
+    }
+
+    fn on_device_found(&self, _remote_device: BluetoothDevice) {}
+
+    fn on_device_cleared(&self, _remote_device: BluetoothDevice) {}
+
+    fn on_discovering_changed(&self, _discovering: bool) {}
+
+    fn on_ssp_request(
+        &self,
+        remote_device: BluetoothDevice,
+        _cod: u32,
+        variant: BtSspVariant,
+        passkey: u32,
+    ) {
+        send_fast_pair_action(
+            &self.tx,
+            FastPairActions::SspRequest(remote_device, variant, passkey),
+        );
+    }
+
+    fn on_bond_state_changed(&self, _status: u32, _device_address: String, _state: u32) {}
+
+    fn on_ready(&self) {
+        send_fast_pair_action(&self.tx, FastPairActions::AdapterReady);
+    }
+
+    fn on_pairing_locked_out(&self, _device_address: String, _global: bool, _lockout_secs: u32) {}
+
+    fn on_stack_restart_started(&self, _reason: String) {
+        send_fast_pair_action(&self.tx, FastPairActions::AdapterRestarting);
+    }
+
+    fn on_stack_restart_completed(&self, _reason: String, _success: bool) {}
+
+    fn on_radio_activity_changed(&self, _activity: RadioActivity) {}
+
+    fn on_device_forgotten(&self, _device_address: String) {}
+}
+
+impl RPCProxy for FastPairAdapterCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("FastPairManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+/// Relays the requests of the seekers to the Fast Pair provider.
+struct FastPairServerCallback {
+    tx: Sender<Message>,
+}
+
+impl IBluetoothGattServerCallback for FastPairServerCallback {
+    fn on_server_registered(&self, status: i32, server_id: i32) {
+        send_fast_pair_action(&self.tx, FastPairActions::ServerRegistered(status, server_id));
+    }
+
+    fn on_server_connection_state(&self, _server_id: i32, connected: bool, addr: BtAddress) {
+        send_fast_pair_action(
+            &self.tx,
+            FastPairActions::ServerConnectionState(addr.to_string(), connected),
+        );
+    }
+
+    fn on_service_added(&self, status: i32, service: BluetoothGattService) {
+        send_fast_pair_action(&self.tx, FastPairActions::ServiceAdded(status, service));
+    }
+
+    fn on_service_removed(&self, _status: i32, _handle: i32) {}
+
+    fn on_characteristic_read_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        _offset: i32,
+        _is_long: bool,
+        handle: i32,
+    ) {
+        send_fast_pair_action(
+            &self.tx,
+            FastPairActions::ReadRequest(addr.to_string(), request_id, handle),
+        );
+    }
+
+    fn on_descriptor_read_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        _offset: i32,
+        _is_long: bool,
+        handle: i32,
+    ) {
+        send_fast_pair_action(
+            &self.tx,
+            FastPairActions::ReadRequest(addr.to_string(), request_id, handle),
+        );
+    }
+
+    fn on_characteristic_write_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        _len: i32,
+        is_prep: bool,
+        need_response: bool,
+        handle: i32,
+        value: Vec<u8>,
+    ) {
+        send_fast_pair_action(
+            &self.tx,
+            FastPairActions::WriteRequest(
+                addr.to_string(),
+                request_id,
+                offset,
+                is_prep,
+                need_response,
+                handle,
+                value,
+            ),
+        );
+    }
+
+    fn on_descriptor_write_request(
+        &self,
+        addr: BtAddress,
+        request_id: i32,
+        offset: i32,
+        _len: i32,
+        is_prep: bool,
+        need_response: bool,
+        handle: i32,
+        value: Vec<u8>,
+    ) {
+        send_fast_pair_action(
+            &self.tx,
+            FastPairActions::WriteRequest(
+                addr.to_string(),
+                request_id,
+                offset,
+                is_prep,
+                need_response,
+                handle,
+                value,
+            ),
+        );
+    }
+
+    fn on_execute_write(&self, _addr: BtAddress, _request_id: i32, _execute_write: bool) {}
+
+    fn on_notification_sent(&self, _addr: BtAddress, _status: i32) {}
+
+    fn on_mtu_changed(&self, _addr: BtAddress, _mtu: i32) {}
+
+    fn on_user_description_changed(&self, _addr: BtAddress, _handle: i32, _description: String) {}
+
+    fn on_server_configuration_changed(&self, _addr: BtAddress, _handle: i32, _broadcast: bool) {}
+
+    fn on_phy_update(&self, _addr: BtAddress, _tx_phy: LePhy, _rx_phy: LePhy, _status: GattStatus) {
+    }
+
+    fn on_connection_updated(
+        &self,
+        _addr: BtAddress,
+        _interval: i32,
+        _latency: i32,
+        _timeout: i32,
+        _status: i32,
+    ) {
+    }
+}
+
+impl RPCProxy for FastPairServerCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("FastPairManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+/// Relays the events of the advertising set to the Fast Pair provider.
+struct FastPairAdvertisingCallback {
+    tx: Sender<Message>,
+}
+
+impl IAdvertisingSetCallback for FastPairAdvertisingCallback {
+    fn on_advertising_set_started(
+        &self,
+        _reg_id: i32,
+        advertiser_id: i32,
+        _tx_power: i32,
+        status: AdvertisingStatus,
+    ) {
+        send_fast_pair_action(
+            &self.tx,
+            FastPairActions::AdvertisingSetStarted(advertiser_id, status),
+        );
+    }
+
+    fn on_own_address_read(&self, advertiser_id: i32, _address_type: i32, address: String) {
+        send_fast_pair_action(&self.tx, FastPairActions::OwnAddress(advertiser_id, address));
+    }
+
+    fn on_own_address_changed(&self, advertiser_id: i32, _address_type: i32, address: String) {
+        send_fast_pair_action(&self.tx, FastPairActions::OwnAddress(advertiser_id, address));
+    }
+
+    fn on_advertising_set_stopped(&self, _advertiser_id: i32) {}
+
+    fn on_advertising_enabled(
+        &self,
+        _advertiser_id: i32,
+        _enable: bool,
+        _status: AdvertisingStatus,
+    ) {
+    }
+
+    fn on_advertising_set_terminated(
+        &self,
+        _advertiser_id: i32,
+        _reason: AdvertisingTerminationReason,
+    ) {
+    }
+
+    fn on_advertising_data_set(&self, _advertiser_id: i32, _status: AdvertisingStatus) {}
+
+    fn on_scan_response_data_set(&self, _advertiser_id: i32, _status: AdvertisingStatus) {}
+
+    fn on_advertising_parameters_updated(
+        &self,
+        _advertiser_id: i32,
+        _tx_power: i32,
+        _status: AdvertisingStatus,
+    ) {
+    }
+
+    fn on_advertising_set_suspended(&self, _advertiser_id: i32) {}
+
+    fn on_advertising_set_resumed(&self, _advertiser_id: i32) {}
+
+    fn on_advertising_set_restored(
+        &self,
+        _advertiser_id: i32,
+        _tx_power: i32,
+        _status: AdvertisingStatus,
+    ) {
+    }
+
+    fn on_tx_power_changed(&self, _advertiser_id: i32, _tx_power: i32) {}
+}
+
+impl RPCProxy for FastPairAdvertisingCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("FastPairManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(hex: &str) -> [u8; 16] {
+        parse_hex_array::<16>("key", hex).unwrap()
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = FastPairConfig::parse(
+            "# Test model\n\
+             model_id = 0a0b0c\n\
+             anti_spoofing_private_key = \
+             3f49f6d4a3c55f3874c9b3e3d2103f504aff607beb40b7995899b8a6cd3c1abd\n",
+        )
+        .unwrap();
+        assert_eq!([0x0A, 0x0B, 0x0C], config.model_id);
+
+        assert!(FastPairConfig::parse("model_id = 0a0b0c\n").is_err());
+        assert!(FastPairConfig::parse("model_id = 0a0b\n").is_err());
+        // The private key must be lower than the order of the curve.
+        assert!(FastPairConfig::parse(&format!(
+            "model_id = 0a0b0c\nanti_spoofing_private_key = {}\n",
+            "ff".repeat(32)
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_account_key_filter() {
+        // Sample data of the Fast Pair specification.
+        let first = key("11223344556677889900aabbccddeeff");
+        let second = key("11112222333344445555666677778888");
+        assert_eq!(vec![0x0A, 0x42, 0x88, 0x10], account_key_filter(&[first], &[0xC7]));
+        assert_eq!(
+            vec![0x2F, 0xBA, 0x06, 0x42, 0x00],
+            account_key_filter(&[first, second], &[0xC7])
+        );
+
+        assert_eq!(vec![0x00, 0x00], account_key_data(&[], 0xC7));
+        assert_eq!(
+            vec![0x00, 0x40, 0x0A, 0x42, 0x88, 0x10, 0x11, 0xC7],
+            account_key_data(&[first], 0xC7)
+        );
+    }
+
+    #[test]
+    fn test_decrypt_key_based_pairing_request() {
+        let config = FastPairConfig {
+            model_id: [0x0A, 0x0B, 0x0C],
+            anti_spoofing_private_key: parse_hex_array::<32>(
+                "key",
+                "3f49f6d4a3c55f3874c9b3e3d2103f504aff607beb40b7995899b8a6cd3c1abd",
+            )
+            .unwrap(),
+        };
+        let provider = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+        let mut request = [0u8; 16];
+        request[2..8].copy_from_slice(&provider);
+
+        // With the key derived from the public key of the seeker.
+        let seeker = parse_hex_array::<32>(
+            "key",
+            "55188b3d32f6bb9a900afcfbeed4e72a59cb9ac2f19d7cfb6b4fdd49f47fc5fd",
+        )
+        .unwrap();
+        let provider_public = p256_public_key(&config.anti_spoofing_private_key).unwrap();
+        let secret = p256_ecdh(&seeker, &provider_public).unwrap();
+        let mut shared = [0u8; 16];
+        shared.copy_from_slice(&sha256(&secret)[..16]);
+        let mut value = aes128_encrypt(&shared, &request).to_vec();
+        value.extend(&p256_public_key(&seeker).unwrap()[..]);
+        assert_eq!(
+            Some((shared, request)),
+            decrypt_key_based_pairing_request(&config, &[], &value, &[provider])
+        );
+        // The request must name the provider.
+        assert_eq!(None, decrypt_key_based_pairing_request(&config, &[], &value, &[[0; 6]]));
+
+        // With an account key.
+        let account_key = key("04112233445566778899aabbccddeeff");
+        let value = aes128_encrypt(&account_key, &request).to_vec();
+        assert_eq!(
+            Some((account_key, request)),
+            decrypt_key_based_pairing_request(
+                &config,
+                &[key("04000000000000000000000000000000"), account_key],
+                &value,
+                &[provider]
+            )
+        );
+        assert_eq!(None, decrypt_key_based_pairing_request(&config, &[], &value, &[provider]));
+
+        // The seeker pairs from its GATT connection unless the request names its address.
+        let connection = [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF];
+        assert_eq!(connection, seeker_address(&request, connection));
+        let seeker = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+        request[1] = FLAG_SEEKER_ADDRESS;
+        request[8..14].copy_from_slice(&seeker);
+        assert_eq!(seeker, seeker_address(&request, connection));
+    }
+
+    fn session(key: [u8; 16], started: Instant, seeker_address: [u8; 6]) -> Session {
+        Session { key, started, seeker_address, seeker_passkey: None, ssp_request: None }
+    }
+
+    #[test]
+    fn test_passkey_exchange() {
+        let key = key("04112233445566778899aabbccddeeff");
+        let seeker = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+        let other = [0x11, 0x12, 0x13, 0x14, 0x15, 0x16];
+        let expired = Instant::now().checked_sub(PAIRING_WINDOW).unwrap();
+        let mut sessions = HashMap::new();
+        sessions.insert(String::from("01:02:03:04:05:06"), session(key, expired, seeker));
+        sessions.insert(String::from("22:22:22:22:22:22"), session(key, expired, other));
+
+        // Only the pairings following closely a request from the same address are bound to it.
+        assert_eq!(None, pairing_seeker(&sessions, &seeker));
+        sessions.insert(String::from("01:02:03:04:05:06"), session(key, Instant::now(), seeker));
+        assert_eq!(Some(&String::from("01:02:03:04:05:06")), pairing_seeker(&sessions, &seeker));
+        assert_eq!(None, pairing_seeker(&sessions, &other));
+
+        // The passkey of the seeker is read from its encrypted message.
+        let mut message = [0u8; 16];
+        message[..4].copy_from_slice(&[SEEKER_PASSKEY, 0x01, 0xE2, 0x40]);
+        let value = aes128_encrypt(&key, &message);
+        assert_eq!(Ok(message), decrypt_message(&key, &value, SEEKER_PASSKEY));
+        assert_eq!(Err(GattStatus::ValueNotAllowed), decrypt_message(&key, &value, ACCOUNT_KEY));
+        assert_eq!(
+            Err(GattStatus::InvalidAttrLen),
+            decrypt_message(&key, &value[..15], SEEKER_PASSKEY)
+        );
+
+        // The passkey of the provider is salted but decrypts to the same message.
+        let value = encrypt_message(&key, &[PROVIDER_PASSKEY, 0x01, 0xE2, 0x40]).unwrap();
+        assert_ne!(value, encrypt_message(&key, &[PROVIDER_PASSKEY, 0x01, 0xE2, 0x40]).unwrap());
+        let message = decrypt_message(&key, &value, PROVIDER_PASSKEY).unwrap();
+        assert_eq!([PROVIDER_PASSKEY, 0x01, 0xE2, 0x40], message[..4]);
+    }
+
+    #[test]
+    fn test_account_key_store() {
+        let path = std::env::temp_dir()
+            .join(format!("fast_pair_account_keys_test_{}", std::process::id()));
+
+        let mut store = AccountKeyStore::load(&path);
+        for i in 0..=MAX_ACCOUNT_KEYS as u8 {
+            store.add([i; 16]);
+        }
+        // Adding a key again makes it the newest.
+        store.add([1; 16]);
+
+        let mut store = AccountKeyStore::load(&path);
+        assert_eq!(&[[2; 16], [3; 16], [4; 16], [5; 16], [1; 16]], store.keys());
+        // The keys are only readable by the daemon.
+        let mode = std::os::unix::fs::PermissionsExt::mode(
+            &std::fs::metadata(&path).unwrap().permissions(),
+        );
+        assert_eq!(SECRET_FILE_MODE, mode & 0o777);
+
+        // A key written by a seeker is stored as decrypted, its first byte being its type.
+        let session_key = key("0123456789abcdef0123456789abcdef");
+        let account_key = key("04aabbccddeeff00112233445566778a");
+        let value = aes128_encrypt(&session_key, &account_key);
+        store.add(decrypt_message(&session_key, &value, ACCOUNT_KEY).unwrap());
+        assert_eq!(Some(&account_key), AccountKeyStore::load(&path).keys().last());
+
+        store.clear();
+        assert!(AccountKeyStore::load(&path).keys().is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,368 @@
+//! Conformance checks of the attribute database of a remote GATT server, meant for the developers
+//! of peripheral firmware.
+//!
+//! The declarations of the discovered database are checked first, then every readable
+//! characteristic is read and its result checked. The problems found are reported together once
+//! all the reads are done.
+
+use bt_topshim::btif::Uuid128Bit;
+use bt_topshim::profiles::gatt::GattStatus;
+
+use num_traits::cast::FromPrimitive;
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::bluetooth_gatt::{
+    BluetoothGattCharacteristic, BluetoothGattService, CharacteristicReadResult,
+};
+use crate::gatt_service_builder::{CCCD_UUID, CEPD_UUID, SCCD_UUID};
+use crate::uuid::UuidHelper;
+
+// Longest attribute value allowed by the Core specification (Vol 3, Part F, 3.2.9).
+const ATT_MAX_VALUE_LEN: usize = 512;
+
+/// Problem found in the attribute database of a remote GATT server.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum ConformanceProblem {
+    /// The characteristic can notify or indicate but has no CCCD.
+    MissingCccd = 0,
+    /// The characteristic can be broadcast but has no SCCD.
+    MissingSccd,
+    /// The characteristic has extended properties but no Characteristic Extended Properties
+    /// descriptor.
+    MissingExtendedProperties,
+    /// The characteristic has the same descriptor twice.
+    DuplicateDescriptor,
+    /// The attribute is not declared after the previous attribute of the database.
+    HandleOutOfOrder,
+    /// The value read is longer than the 512 bytes an attribute can hold.
+    ValueTooLong,
+    /// The characteristic declares the read property but rejected the read.
+    ReadNotPermitted,
+    /// The read was rejected for insufficient security although the device is bonded.
+    SecurityMismatch,
+}
+
+impl Default for ConformanceProblem {
+    fn default() -> Self {
+        ConformanceProblem::MissingCccd
+    }
+}
+
+/// A problem found in a service of a remote GATT server, delivered with
+/// `IBluetoothGattCallback::on_conformance_report`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConformanceIssue {
+    pub problem: ConformanceProblem,
+    pub service_uuid: Uuid128Bit,
+    /// All zeros if the problem is on the service.
+    pub characteristic_uuid: Uuid128Bit,
+    /// All zeros if the problem is not on a descriptor.
+    pub descriptor_uuid: Uuid128Bit,
+    /// Handle of the attribute with the problem.
+    pub handle: i32,
+}
+
+impl fmt::Display for ConformanceIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} at handle {} in service {}",
+            self.problem,
+            self.handle,
+            UuidHelper::to_string(&self.service_uuid)
+        )?;
+        if self.characteristic_uuid != [0; 16] {
+            write!(f, " characteristic {}", UuidHelper::to_string(&self.characteristic_uuid))?;
+        }
+        if self.descriptor_uuid != [0; 16] {
+            write!(f, " descriptor {}", UuidHelper::to_string(&self.descriptor_uuid))?;
+        }
+        Ok(())
+    }
+}
+
+fn characteristic_issue(
+    problem: ConformanceProblem,
+    service: &BluetoothGattService,
+    characteristic: &BluetoothGattCharacteristic,
+) -> ConformanceIssue {
+    ConformanceIssue {
+        problem,
+        service_uuid: service.uuid,
+        characteristic_uuid: characteristic.uuid,
+        descriptor_uuid: [0; 16],
+        handle: characteristic.instance_id,
+    }
+}
+
+/// Checks the descriptors required by the properties of a characteristic.
+fn check_descriptors(
+    service: &BluetoothGattService,
+    characteristic: &BluetoothGattCharacteristic,
+    issues: &mut Vec<ConformanceIssue>,
+) {
+    use ConformanceProblem::*;
+
+    let properties = characteristic.properties;
+    let has_descriptor =
+        |uuid: &Uuid128Bit| characteristic.descriptors.iter().any(|d| d.uuid == *uuid);
+
+    let subscribable = BluetoothGattCharacteristic::PROPERTY_NOTIFY
+        | BluetoothGattCharacteristic::PROPERTY_INDICATE;
+    if properties & subscribable != 0 && !has_descriptor(&CCCD_UUID) {
+        issues.push(characteristic_issue(MissingCccd, service, characteristic));
+    }
+    if properties & BluetoothGattCharacteristic::PROPERTY_BROADCAST != 0
+        && !has_descriptor(&SCCD_UUID)
+    {
+        issues.push(characteristic_issue(MissingSccd, service, characteristic));
+    }
+    if properties & BluetoothGattCharacteristic::PROPERTY_EXTENDED_PROPS != 0
+        && !has_descriptor(&CEPD_UUID)
+    {
+        issues.push(characteristic_issue(MissingExtendedProperties, service, characteristic));
+    }
+
+    let mut seen = HashSet::new();
+    for descriptor in &characteristic.descriptors {
+        if !seen.insert(descriptor.uuid) {
+            issues.push(ConformanceIssue {
+                descriptor_uuid: descriptor.uuid,
+                handle: descriptor.instance_id,
+                ..characteristic_issue(DuplicateDescriptor, service, characteristic)
+            });
+        }
+    }
+}
+
+/// Returns the problems found in the declarations of a discovered database: the descriptors
+/// required by the characteristic properties, and the order of the attribute handles.
+pub fn check_database(services: &[BluetoothGattService]) -> Vec<ConformanceIssue> {
+    let mut issues = vec![];
+    // Last attribute handle declared so far.
+    let mut last_handle = 0;
+
+    let mut out_of_order = |handle: i32, last_handle: &mut i32, issue: ConformanceIssue| {
+        if handle <= *last_handle {
+            issues.push(issue);
+        }
+        *last_handle = std::cmp::max(*last_handle, handle);
+    };
+
+    for service in services {
+        let service_issue = ConformanceIssue {
+            problem: ConformanceProblem::HandleOutOfOrder,
+            service_uuid: service.uuid,
+            handle: service.instance_id,
+            ..Default::default()
+        };
+        out_of_order(service.instance_id, &mut last_handle, service_issue);
+
+        for characteristic in &service.characteristics {
+            // The value immediately follows the characteristic declaration.
+            let issue =
+                characteristic_issue(ConformanceProblem::HandleOutOfOrder, service, characteristic);
+            out_of_order(characteristic.instance_id - 1, &mut last_handle, issue);
+            last_handle = std::cmp::max(last_handle, characteristic.instance_id);
+
+            for descriptor in &characteristic.descriptors {
+                let issue = ConformanceIssue {
+                    descriptor_uuid: descriptor.uuid,
+                    handle: descriptor.instance_id,
+                    ..characteristic_issue(
+                        ConformanceProblem::HandleOutOfOrder,
+                        service,
+                        characteristic,
+                    )
+                };
+                out_of_order(descriptor.instance_id, &mut last_handle, issue);
+            }
+        }
+    }
+
+    for service in services {
+        for characteristic in &service.characteristics {
+            check_descriptors(service, characteristic, &mut issues);
+        }
+    }
+
+    issues
+}
+
+/// Returns the problem found in the result of reading a characteristic which declares the read
+/// property, if any.
+pub fn check_read_result(
+    service_uuid: Uuid128Bit,
+    result: &CharacteristicReadResult,
+    is_bonded: bool,
+) -> Option<ConformanceIssue> {
+    let problem = match GattStatus::from_i32(result.status) {
+        Some(GattStatus::Success) if result.value.len() > ATT_MAX_VALUE_LEN => {
+            ConformanceProblem::ValueTooLong
+        }
+        Some(GattStatus::ReadNotPermit) => ConformanceProblem::ReadNotPermitted,
+        Some(GattStatus::InsufAuthentication)
+        | Some(GattStatus::InsufAuthorization)
+        | Some(GattStatus::InsufEncryption)
+        | Some(GattStatus::InsufKeySize)
+            if is_bonded =>
+        {
+            ConformanceProblem::SecurityMismatch
+        }
+        _ => return None,
+    };
+
+    Some(ConformanceIssue {
+        problem,
+        service_uuid,
+        characteristic_uuid: result.uuid,
+        descriptor_uuid: [0; 16],
+        handle: result.handle,
+    })
+}
+
+/// Ongoing `IBluetoothGatt::check_conformance` on a connection.
+pub(crate) struct ConformanceCheck {
+    /// Waiting for the discovery of the database before starting.
+    pub awaiting_database: bool,
+    pub is_bonded: bool,
+    /// Readable characteristics left to read, as (service UUID, characteristic UUID, handle).
+    pub pending: Vec<(Uuid128Bit, Uuid128Bit, i32)>,
+    pub issues: Vec<ConformanceIssue>,
+}
+
+impl ConformanceCheck {
+    pub fn new(is_bonded: bool) -> ConformanceCheck {
+        ConformanceCheck { awaiting_database: true, is_bonded, pending: vec![], issues: vec![] }
+    }
+
+    /// Checks the declarations of the database and queues the reads of its readable
+    /// characteristics.
+    pub fn start(&mut self, services: &[BluetoothGattService]) {
+        self.awaiting_database = false;
+        self.issues = check_database(services);
+        self.pending = services
+            .iter()
+            .flat_map(|s| s.characteristics.iter().map(move |c| (s.uuid, c)))
+            .filter(|(_, c)| c.properties & BluetoothGattCharacteristic::PROPERTY_READ != 0)
+            .map(|(service_uuid, c)| (service_uuid, c.uuid, c.instance_id))
+            .collect();
+    }
+
+    pub fn current_handle(&self) -> Option<i32> {
+        self.pending.first().map(|(_, _, handle)| *handle)
+    }
+
+    /// Records the result of the current read.
+    pub fn on_read(&mut self, status: i32, value: Vec<u8>) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let (service_uuid, uuid, handle) = self.pending.remove(0);
+        let result = CharacteristicReadResult { uuid, handle, status, value };
+        if let Some(issue) = check_read_result(service_uuid, &result, self.is_bonded) {
+            self.issues.push(issue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth_gatt::BluetoothGattDescriptor;
+
+    const NOTIFY: i32 = BluetoothGattCharacteristic::PROPERTY_NOTIFY;
+    const READ: i32 = BluetoothGattCharacteristic::PROPERTY_READ;
+
+    fn service(
+        handle: i32,
+        characteristics: Vec<BluetoothGattCharacteristic>,
+    ) -> BluetoothGattService {
+        let mut service =
+            BluetoothGattService::new([1; 16], handle, BluetoothGattService::SERVICE_TYPE_PRIMARY);
+        service.characteristics = characteristics;
+        service
+    }
+
+    fn characteristic(
+        value_handle: i32,
+        properties: i32,
+        descriptors: Vec<(Uuid128Bit, i32)>,
+    ) -> BluetoothGattCharacteristic {
+        let mut characteristic =
+            BluetoothGattCharacteristic::new([2; 16], value_handle, properties, 0);
+        characteristic.descriptors = descriptors
+            .into_iter()
+            .map(|(uuid, handle)| BluetoothGattDescriptor::new(uuid, handle, 0))
+            .collect();
+        characteristic
+    }
+
+    #[test]
+    fn test_conformant_database() {
+        let services = vec![
+            service(1, vec![characteristic(3, READ | NOTIFY, vec![(CCCD_UUID, 4)])]),
+            service(5, vec![characteristic(7, READ, vec![])]),
+        ];
+        assert_eq!(Vec::<ConformanceIssue>::new(), check_database(&services));
+    }
+
+    #[test]
+    fn test_database_problems() {
+        let services = vec![
+            service(
+                1,
+                vec![
+                    characteristic(3, NOTIFY, vec![]),
+                    characteristic(5, READ, vec![(SCCD_UUID, 6), (SCCD_UUID, 7)]),
+                ],
+            ),
+            // Overlaps the previous service.
+            service(6, vec![characteristic(9, READ, vec![])]),
+        ];
+
+        let problems: Vec<(ConformanceProblem, i32)> =
+            check_database(&services).iter().map(|i| (i.problem, i.handle)).collect();
+        assert_eq!(
+            vec![
+                (ConformanceProblem::HandleOutOfOrder, 6),
+                (ConformanceProblem::MissingCccd, 3),
+                (ConformanceProblem::DuplicateDescriptor, 7),
+            ],
+            problems
+        );
+    }
+
+    #[test]
+    fn test_check_read_result() {
+        let result = |status: GattStatus, len: usize| CharacteristicReadResult {
+            uuid: [2; 16],
+            handle: 3,
+            status: status as i32,
+            value: vec![0; len],
+        };
+
+        assert_eq!(None, check_read_result([1; 16], &result(GattStatus::Success, 512), false));
+        assert_eq!(
+            Some(ConformanceProblem::ValueTooLong),
+            check_read_result([1; 16], &result(GattStatus::Success, 513), false).map(|i| i.problem)
+        );
+        assert_eq!(
+            Some(ConformanceProblem::ReadNotPermitted),
+            check_read_result([1; 16], &result(GattStatus::ReadNotPermit, 0), false)
+                .map(|i| i.problem)
+        );
+        assert_eq!(
+            None,
+            check_read_result([1; 16], &result(GattStatus::InsufEncryption, 0), false)
+        );
+        assert_eq!(
+            Some(ConformanceProblem::SecurityMismatch),
+            check_read_result([1; 16], &result(GattStatus::InsufEncryption, 0), true)
+                .map(|i| i.problem)
+        );
+    }
+}
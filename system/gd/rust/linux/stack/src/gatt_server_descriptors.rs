@@ -0,0 +1,274 @@
+//! Descriptors of the local GATT servers answered by the stack itself: the Characteristic User
+//! Description and the Server Characteristic Configuration.
+//!
+//! Both descriptors hold a single value shared by all the clients, as the core specification
+//! requires, so a client writing one changes it for every other client. The values written by
+//! bonded clients are persisted with the address of the writer, and restored each time the
+//! server adds the service again until the bond is removed.
+
+use bt_topshim::btif::Uuid128Bit;
+use bt_topshim::profiles::gatt::GattStatus;
+
+use log::warn;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::gatt_service_builder::{CUD_UUID, SCCD_UUID};
+use crate::storage::{from_hex, parse_uuid, save_lines, to_hex, PUBLIC_FILE_MODE};
+
+/// File holding the values written by the bonded clients, one descriptor per line.
+pub const SERVER_DESCRIPTORS_FILE: &str = "/var/lib/bluetooth/gatt_server_descriptors";
+
+/// Bit of the Server Characteristic Configuration enabling the broadcast of the characteristic
+/// value in the advertising data.
+pub(crate) const SCCD_BROADCAST: u16 = 0x0001;
+
+/// Longest user description, as long as an attribute value may be.
+pub(crate) const MAX_USER_DESCRIPTION_LEN: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ManagedDescriptorKind {
+    UserDescription,
+    ServerConfiguration,
+}
+
+impl ManagedDescriptorKind {
+    /// Returns the kind of the descriptor `uuid`, if the stack answers it.
+    pub(crate) fn from_uuid(uuid: &Uuid128Bit) -> Option<ManagedDescriptorKind> {
+        match *uuid {
+            CUD_UUID => Some(ManagedDescriptorKind::UserDescription),
+            SCCD_UUID => Some(ManagedDescriptorKind::ServerConfiguration),
+            _ => None,
+        }
+    }
+}
+
+/// Identifies a descriptor across the registrations of its server.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct DescriptorKey {
+    pub app_uuid: Uuid128Bit,
+    pub characteristic_uuid: Uuid128Bit,
+    pub descriptor_uuid: Uuid128Bit,
+}
+
+/// Descriptor of a service added by a server, answered by the stack.
+pub(crate) struct ManagedDescriptor {
+    pub kind: ManagedDescriptorKind,
+    pub key: DescriptorKey,
+    pub service_handle: i32,
+    pub characteristic_handle: i32,
+    pub writable: bool,
+    pub value: Vec<u8>,
+}
+
+impl ManagedDescriptor {
+    pub(crate) fn new(
+        kind: ManagedDescriptorKind,
+        key: DescriptorKey,
+        service_handle: i32,
+        characteristic_handle: i32,
+        writable: bool,
+    ) -> ManagedDescriptor {
+        let value = match kind {
+            ManagedDescriptorKind::UserDescription => vec![],
+            ManagedDescriptorKind::ServerConfiguration => vec![0, 0],
+        };
+        ManagedDescriptor { kind, key, service_handle, characteristic_handle, writable, value }
+    }
+
+    /// Returns whether the characteristic is broadcast, for a Server Characteristic
+    /// Configuration.
+    pub(crate) fn is_broadcast(&self) -> bool {
+        self.kind == ManagedDescriptorKind::ServerConfiguration
+            && self.value.first().map_or(false, |v| *v as u16 & SCCD_BROADCAST != 0)
+    }
+
+    /// Checks a value written by a client. Returns the value to store, or the status to answer
+    /// the client with.
+    pub(crate) fn check_write(
+        &self,
+        offset: i32,
+        is_prep: bool,
+        value: &[u8],
+    ) -> Result<Vec<u8>, GattStatus> {
+        if !self.writable {
+            return Err(GattStatus::WriteNotPermit);
+        }
+        // The value is replaced at once, the prepared writes are left to the servers.
+        if is_prep {
+            return Err(GattStatus::ReqNotSupported);
+        }
+        if offset != 0 {
+            return Err(GattStatus::InvalidOffset);
+        }
+
+        match self.kind {
+            ManagedDescriptorKind::UserDescription => {
+                if value.len() > MAX_USER_DESCRIPTION_LEN {
+                    return Err(GattStatus::InvalidAttrLen);
+                }
+                if std::str::from_utf8(value).is_err() {
+                    return Err(GattStatus::ValueNotAllowed);
+                }
+                Ok(value.to_vec())
+            }
+            ManagedDescriptorKind::ServerConfiguration => {
+                if value.len() != 2 {
+                    return Err(GattStatus::InvalidAttrLen);
+                }
+                // The other bits are reserved.
+                let config = u16::from_le_bytes([value[0], value[1]]) & SCCD_BROADCAST;
+                Ok(config.to_le_bytes().to_vec())
+            }
+        }
+    }
+}
+
+/// Values written by the bonded clients, saved to a file after each change.
+pub(crate) struct ServerDescriptorStore {
+    path: PathBuf,
+    // Value and address of the client which wrote it, by descriptor.
+    values: HashMap<DescriptorKey, (String, Vec<u8>)>,
+}
+
+impl ServerDescriptorStore {
+    /// Loads the values saved in `path`. Malformed lines are skipped.
+    pub(crate) fn load<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
+        let values = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().filter_map(parse_line).collect(),
+            Err(_) => HashMap::new(),
+        };
+
+        ServerDescriptorStore { path, values }
+    }
+
+    pub(crate) fn get(&self, key: &DescriptorKey) -> Option<&Vec<u8>> {
+        self.values.get(key).map(|(_, value)| value)
+    }
+
+    pub(crate) fn set(&mut self, key: DescriptorKey, address: &String, value: Vec<u8>) {
+        self.values.insert(key, (address.to_uppercase(), value));
+        self.save();
+    }
+
+    pub(crate) fn remove(&mut self, key: &DescriptorKey) {
+        if self.values.remove(key).is_some() {
+            self.save();
+        }
+    }
+
+    /// Drops the values written by a device whose bond is removed.
+    pub(crate) fn forget_device(&mut self, address: &String) {
+        let address = address.to_uppercase();
+        let len = self.values.len();
+        self.values.retain(|_, (writer, _)| *writer != address);
+        if self.values.len() != len {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let mut lines: Vec<String> = self
+            .values
+            .iter()
+            .map(|(key, (address, value))| {
+                format!(
+                    "{} {} {} {} {}",
+                    to_hex(&key.app_uuid),
+                    to_hex(&key.characteristic_uuid),
+                    to_hex(&key.descriptor_uuid),
+                    address,
+                    to_hex(value)
+                )
+            })
+            .collect();
+        lines.sort();
+
+        if let Err(e) = save_lines(&self.path, &lines, PUBLIC_FILE_MODE) {
+            warn!("Failed to save the server descriptors to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Parses a line made of the app, characteristic and descriptor UUIDs, the address of the writer
+/// and the value, which is left out when empty.
+fn parse_line(line: &str) -> Option<(DescriptorKey, (String, Vec<u8>))> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 4 && fields.len() != 5 {
+        return None;
+    }
+
+    let key = DescriptorKey {
+        app_uuid: parse_uuid(fields[0])?,
+        characteristic_uuid: parse_uuid(fields[1])?,
+        descriptor_uuid: parse_uuid(fields[2])?,
+    };
+    let value = match fields.get(4) {
+        Some(hex) => from_hex(hex)?,
+        None => vec![],
+    };
+    Some((key, (fields[3].to_uppercase(), value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(descriptor_uuid: Uuid128Bit) -> DescriptorKey {
+        DescriptorKey { app_uuid: [1; 16], characteristic_uuid: [2; 16], descriptor_uuid }
+    }
+
+    #[test]
+    fn test_check_write() {
+        let mut cud = ManagedDescriptor::new(
+            ManagedDescriptorKind::UserDescription,
+            key(CUD_UUID),
+            1,
+            2,
+            false,
+        );
+        assert_eq!(Err(GattStatus::WriteNotPermit), cud.check_write(0, false, b"Kitchen"));
+
+        cud.writable = true;
+        assert_eq!(Ok(b"Kitchen".to_vec()), cud.check_write(0, false, b"Kitchen"));
+        assert_eq!(Err(GattStatus::ValueNotAllowed), cud.check_write(0, false, &[0xff, 0xfe]));
+        assert_eq!(Err(GattStatus::InvalidOffset), cud.check_write(2, false, b"Kitchen"));
+        assert_eq!(Err(GattStatus::ReqNotSupported), cud.check_write(0, true, b"Kitchen"));
+
+        let mut sccd = ManagedDescriptor::new(
+            ManagedDescriptorKind::ServerConfiguration,
+            key(SCCD_UUID),
+            1,
+            2,
+            true,
+        );
+        assert!(!sccd.is_broadcast());
+        assert_eq!(Err(GattStatus::InvalidAttrLen), sccd.check_write(0, false, &[1]));
+        sccd.value = sccd.check_write(0, false, &[0x03, 0x80]).unwrap();
+        assert_eq!(vec![0x01, 0x00], sccd.value);
+        assert!(sccd.is_broadcast());
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let path = std::env::temp_dir()
+            .join(format!("gatt_server_descriptors_test_{}", std::process::id()));
+        let address = String::from("aa:bb:cc:dd:ee:ff");
+
+        let mut store = ServerDescriptorStore::load(&path);
+        store.set(key(CUD_UUID), &address, vec![]);
+        store.set(key(SCCD_UUID), &String::from("11:22:33:44:55:66"), vec![1, 0]);
+
+        let mut store = ServerDescriptorStore::load(&path);
+        assert_eq!(Some(&vec![]), store.get(&key(CUD_UUID)));
+        assert_eq!(Some(&vec![1, 0]), store.get(&key(SCCD_UUID)));
+
+        store.forget_device(&String::from("AA:BB:CC:DD:EE:FF"));
+        let store = ServerDescriptorStore::load(&path);
+        assert_eq!(None, store.get(&key(CUD_UUID)));
+        assert_eq!(Some(&vec![1, 0]), store.get(&key(SCCD_UUID)));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
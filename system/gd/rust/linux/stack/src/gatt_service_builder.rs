@@ -0,0 +1,358 @@
+//! Builder of the services added to the local GATT servers, checking that the properties,
+//! permissions and descriptors of their characteristics are consistent before the services reach
+//! the stack.
+
+use bt_topshim::btif::Uuid128Bit;
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::bluetooth_gatt::{
+    BluetoothGattCharacteristic, BluetoothGattDescriptor, BluetoothGattService,
+};
+use crate::error::{BtError, BtErrorCategory};
+use crate::uuid::UuidHelper;
+
+/// Characteristic Extended Properties descriptor.
+pub const CEPD_UUID: Uuid128Bit =
+    [0, 0, 0x29, 0x00, 0, 0, 0x10, 0, 0x80, 0, 0, 0x80, 0x5F, 0x9B, 0x34, 0xFB];
+/// Characteristic User Description descriptor.
+pub const CUD_UUID: Uuid128Bit =
+    [0, 0, 0x29, 0x01, 0, 0, 0x10, 0, 0x80, 0, 0, 0x80, 0x5F, 0x9B, 0x34, 0xFB];
+/// Client Characteristic Configuration descriptor.
+pub const CCCD_UUID: Uuid128Bit =
+    [0, 0, 0x29, 0x02, 0, 0, 0x10, 0, 0x80, 0, 0, 0x80, 0x5F, 0x9B, 0x34, 0xFB];
+/// Server Characteristic Configuration descriptor.
+pub const SCCD_UUID: Uuid128Bit =
+    [0, 0, 0x29, 0x03, 0, 0, 0x10, 0, 0x80, 0, 0, 0x80, 0x5F, 0x9B, 0x34, 0xFB];
+
+const READ_PERMISSIONS: i32 = BluetoothGattCharacteristic::PERMISSION_READ
+    | BluetoothGattCharacteristic::PERMISSION_READ_ENCRYPTED
+    | BluetoothGattCharacteristic::PERMISSION_READ_ENCRYPTED_MITM;
+pub(crate) const WRITE_PERMISSIONS: i32 = BluetoothGattCharacteristic::PERMISSION_WRITE
+    | BluetoothGattCharacteristic::PERMISSION_WRITE_ENCRYPTED
+    | BluetoothGattCharacteristic::PERMISSION_WRITE_ENCRYPTED_MITM;
+const SIGNED_WRITE_PERMISSIONS: i32 = BluetoothGattCharacteristic::PERMISSION_WRITE_SIGNED
+    | BluetoothGattCharacteristic::PERMISSION_WRITE_SIGNED_MITM;
+
+const WRITE_PROPERTIES: i32 = BluetoothGattCharacteristic::PROPERTY_WRITE
+    | BluetoothGattCharacteristic::PROPERTY_WRITE_NO_RESPONSE;
+
+// Range of the encryption key size, in bytes.
+const MIN_KEY_SIZE: i32 = 7;
+const MAX_KEY_SIZE: i32 = 16;
+
+/// Inconsistency found in the declaration of a characteristic.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum ServiceValidationProblem {
+    /// The characteristic can be read but has no read permission.
+    MissingReadPermission = 0,
+    /// The characteristic has a read permission but cannot be read.
+    ReadPermissionWithoutProperty,
+    /// The characteristic can be written but has no write permission.
+    MissingWritePermission,
+    /// The characteristic has a write permission but cannot be written.
+    WritePermissionWithoutProperty,
+    /// The characteristic supports signed writes but has no signed write permission.
+    MissingSignedWritePermission,
+    /// The characteristic can notify or indicate but has no CCCD.
+    MissingCccd,
+    /// The CCCD cannot be both read and written.
+    CccdNotReadWrite,
+    /// The characteristic can be broadcast but has no SCCD.
+    MissingSccd,
+    /// The characteristic has extended properties but no Characteristic Extended Properties
+    /// descriptor.
+    MissingExtendedProperties,
+    /// The characteristic has the same descriptor twice.
+    DuplicateDescriptor,
+    /// The encryption key size is out of range.
+    InvalidKeySize,
+}
+
+impl Default for ServiceValidationProblem {
+    fn default() -> Self {
+        ServiceValidationProblem::MissingReadPermission
+    }
+}
+
+/// A problem found in a characteristic, or one of its descriptors, of a service.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServiceValidationError {
+    pub problem: ServiceValidationProblem,
+    pub characteristic_uuid: Uuid128Bit,
+    /// The descriptor with the problem, unless the problem is on the characteristic.
+    pub descriptor_uuid: Option<Uuid128Bit>,
+}
+
+impl ServiceValidationError {
+    fn new(
+        problem: ServiceValidationProblem,
+        characteristic: &BluetoothGattCharacteristic,
+    ) -> ServiceValidationError {
+        ServiceValidationError {
+            problem,
+            characteristic_uuid: characteristic.uuid,
+            descriptor_uuid: None,
+        }
+    }
+}
+
+impl fmt::Display for ServiceValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} on {}", self.problem, UuidHelper::to_string(&self.characteristic_uuid))?;
+        if let Some(descriptor_uuid) = &self.descriptor_uuid {
+            write!(f, " descriptor {}", UuidHelper::to_string(descriptor_uuid))?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_characteristic(
+    characteristic: &BluetoothGattCharacteristic,
+    errors: &mut Vec<ServiceValidationError>,
+) {
+    use ServiceValidationProblem::*;
+
+    let properties = characteristic.properties;
+    let permissions = characteristic.permissions;
+    let mut problems = vec![];
+
+    let readable = properties & BluetoothGattCharacteristic::PROPERTY_READ != 0;
+    if readable && permissions & READ_PERMISSIONS == 0 {
+        problems.push(MissingReadPermission);
+    } else if !readable && permissions & READ_PERMISSIONS != 0 {
+        problems.push(ReadPermissionWithoutProperty);
+    }
+
+    let signed = properties & BluetoothGattCharacteristic::PROPERTY_SIGNED_WRITE != 0;
+    let writable = properties & WRITE_PROPERTIES != 0;
+    if writable && permissions & WRITE_PERMISSIONS == 0 {
+        problems.push(MissingWritePermission);
+    }
+    if signed && permissions & SIGNED_WRITE_PERMISSIONS == 0 {
+        problems.push(MissingSignedWritePermission);
+    }
+    if (!writable && permissions & WRITE_PERMISSIONS != 0)
+        || (!signed && permissions & SIGNED_WRITE_PERMISSIONS != 0)
+    {
+        problems.push(WritePermissionWithoutProperty);
+    }
+
+    if !(MIN_KEY_SIZE..=MAX_KEY_SIZE).contains(&characteristic.key_size) {
+        problems.push(InvalidKeySize);
+    }
+
+    let has_descriptor =
+        |uuid: &Uuid128Bit| characteristic.descriptors.iter().any(|d| d.uuid == *uuid);
+    let subscribable = BluetoothGattCharacteristic::PROPERTY_NOTIFY
+        | BluetoothGattCharacteristic::PROPERTY_INDICATE;
+    if properties & subscribable != 0 && !has_descriptor(&CCCD_UUID) {
+        problems.push(MissingCccd);
+    }
+    if properties & BluetoothGattCharacteristic::PROPERTY_BROADCAST != 0
+        && !has_descriptor(&SCCD_UUID)
+    {
+        problems.push(MissingSccd);
+    }
+    if properties & BluetoothGattCharacteristic::PROPERTY_EXTENDED_PROPS != 0
+        && !has_descriptor(&CEPD_UUID)
+    {
+        problems.push(MissingExtendedProperties);
+    }
+
+    errors.extend(problems.into_iter().map(|p| ServiceValidationError::new(p, characteristic)));
+
+    let mut seen = HashSet::new();
+    for descriptor in &characteristic.descriptors {
+        let mut problem = None;
+        if !seen.insert(descriptor.uuid) {
+            problem = Some(DuplicateDescriptor);
+        } else if descriptor.uuid == CCCD_UUID
+            && (descriptor.permissions & READ_PERMISSIONS == 0
+                || descriptor.permissions & WRITE_PERMISSIONS == 0)
+        {
+            problem = Some(CccdNotReadWrite);
+        }
+
+        if let Some(problem) = problem {
+            errors.push(ServiceValidationError {
+                problem,
+                characteristic_uuid: characteristic.uuid,
+                descriptor_uuid: Some(descriptor.uuid),
+            });
+        }
+    }
+}
+
+/// Returns the problems found in the characteristics of a service, which is empty if the service
+/// is consistent.
+pub fn validate_service(service: &BluetoothGattService) -> Vec<ServiceValidationError> {
+    let mut errors = vec![];
+    for characteristic in &service.characteristics {
+        validate_characteristic(characteristic, &mut errors);
+    }
+    errors
+}
+
+/// Returns the error rejecting a service with the problems found by `validate_service`. Its
+/// sub-code is the first problem.
+pub(crate) fn invalid_service_error(errors: &[ServiceValidationError]) -> BtError {
+    let problems: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    BtError {
+        category: BtErrorCategory::InvalidService,
+        sub_code: errors.first().map_or(0, |e| e.problem as u32),
+        message: problems.join(", "),
+    }
+}
+
+/// Declares a characteristic of a `ServiceBuilder`.
+pub struct CharacteristicBuilder {
+    characteristic: BluetoothGattCharacteristic,
+}
+
+impl CharacteristicBuilder {
+    pub fn new(uuid: Uuid128Bit, properties: i32, permissions: i32) -> CharacteristicBuilder {
+        CharacteristicBuilder {
+            characteristic: BluetoothGattCharacteristic::new(uuid, 0, properties, permissions),
+        }
+    }
+
+    /// Sets the encryption key size required to access the characteristic.
+    pub fn key_size(mut self, key_size: i32) -> CharacteristicBuilder {
+        self.characteristic.key_size = key_size;
+        self
+    }
+
+    pub fn descriptor(mut self, uuid: Uuid128Bit, permissions: i32) -> CharacteristicBuilder {
+        self.characteristic.descriptors.push(BluetoothGattDescriptor::new(uuid, 0, permissions));
+        self
+    }
+
+    /// Adds the CCCD required by the characteristics which notify or indicate.
+    pub fn cccd(self) -> CharacteristicBuilder {
+        let permissions = BluetoothGattCharacteristic::PERMISSION_READ
+            | BluetoothGattCharacteristic::PERMISSION_WRITE;
+        self.descriptor(CCCD_UUID, permissions)
+    }
+}
+
+/// Declares a service to add with `IBluetoothGatt::add_service`.
+///
+/// Example:
+///     ServiceBuilder::new(uuid)
+///         .characteristic(CharacteristicBuilder::new(char_uuid, properties, permissions).cccd())
+///         .build()
+pub struct ServiceBuilder {
+    service: BluetoothGattService,
+}
+
+impl ServiceBuilder {
+    pub fn new(uuid: Uuid128Bit) -> ServiceBuilder {
+        ServiceBuilder {
+            service: BluetoothGattService::new(uuid, 0, BluetoothGattService::SERVICE_TYPE_PRIMARY),
+        }
+    }
+
+    pub fn secondary(mut self) -> ServiceBuilder {
+        self.service.service_type = BluetoothGattService::SERVICE_TYPE_SECONDARY;
+        self
+    }
+
+    /// Includes a service already added to the server, identified by its handle.
+    pub fn include(mut self, uuid: Uuid128Bit, handle: i32) -> ServiceBuilder {
+        self.service.included_services.push(BluetoothGattService::new(
+            uuid,
+            handle,
+            BluetoothGattService::SERVICE_TYPE_PRIMARY,
+        ));
+        self
+    }
+
+    pub fn characteristic(mut self, characteristic: CharacteristicBuilder) -> ServiceBuilder {
+        self.service.characteristics.push(characteristic.characteristic);
+        self
+    }
+
+    /// Returns the service, or every problem found in its characteristics.
+    pub fn build(self) -> Result<BluetoothGattService, Vec<ServiceValidationError>> {
+        let errors = validate_service(&self.service);
+        if errors.is_empty() {
+            Ok(self.service)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const READ: i32 = BluetoothGattCharacteristic::PERMISSION_READ;
+    const WRITE: i32 = BluetoothGattCharacteristic::PERMISSION_WRITE;
+
+    #[test]
+    fn test_valid_service() {
+        let service = ServiceBuilder::new([1; 16])
+            .characteristic(
+                CharacteristicBuilder::new(
+                    [2; 16],
+                    BluetoothGattCharacteristic::PROPERTY_READ
+                        | BluetoothGattCharacteristic::PROPERTY_INDICATE,
+                    READ,
+                )
+                .cccd(),
+            )
+            .characteristic(CharacteristicBuilder::new(
+                [3; 16],
+                BluetoothGattCharacteristic::PROPERTY_WRITE,
+                BluetoothGattCharacteristic::PERMISSION_WRITE_ENCRYPTED_MITM,
+            ))
+            .build()
+            .unwrap();
+
+        assert_eq!(2, service.characteristics.len());
+        assert_eq!(CCCD_UUID, service.characteristics[0].descriptors[0].uuid);
+    }
+
+    #[test]
+    fn test_invalid_service() {
+        let errors = ServiceBuilder::new([1; 16])
+            .characteristic(CharacteristicBuilder::new(
+                [2; 16],
+                BluetoothGattCharacteristic::PROPERTY_NOTIFY,
+                READ,
+            ))
+            .characteristic(
+                CharacteristicBuilder::new([3; 16], BluetoothGattCharacteristic::PROPERTY_READ, 0)
+                    .descriptor(CCCD_UUID, READ)
+                    .descriptor([4; 16], READ)
+                    .descriptor([4; 16], WRITE)
+                    .key_size(20),
+            )
+            .build()
+            .unwrap_err();
+
+        let problems: Vec<(ServiceValidationProblem, Uuid128Bit)> =
+            errors.iter().map(|e| (e.problem, e.characteristic_uuid)).collect();
+        assert_eq!(
+            vec![
+                (ServiceValidationProblem::ReadPermissionWithoutProperty, [2; 16]),
+                (ServiceValidationProblem::MissingCccd, [2; 16]),
+                (ServiceValidationProblem::MissingReadPermission, [3; 16]),
+                (ServiceValidationProblem::InvalidKeySize, [3; 16]),
+                (ServiceValidationProblem::CccdNotReadWrite, [3; 16]),
+                (ServiceValidationProblem::DuplicateDescriptor, [3; 16]),
+            ],
+            problems
+        );
+        assert_eq!(None, errors[3].descriptor_uuid);
+        assert_eq!(Some([4; 16]), errors[5].descriptor_uuid);
+
+        let error = invalid_service_error(&errors);
+        assert_eq!(BtErrorCategory::InvalidService, error.category);
+        assert_eq!(ServiceValidationProblem::ReadPermissionWithoutProperty as u32, error.sub_code);
+    }
+}
@@ -0,0 +1,139 @@
+//! Latency of the HCI commands, from the command sent by the HCI layer to the event completing it,
+//! as reported by the topshim for every command whichever layer of the stack sent it. The
+//! latencies are counted in histograms per opcode group in the metrics, and the clients are
+//! alerted when the controller becomes sluggish, to tell controller firmware issues from host-side
+//! stalls.
+
+use std::time::Duration;
+
+/// Upper bounds of the buckets of the latency histograms, in milliseconds. The latencies above
+/// the last bound are counted in an overflow bucket.
+pub(crate) const LATENCY_BUCKETS_MS: [u64; 10] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000];
+
+/// Default latency above which the controller is considered sluggish.
+pub(crate) const DEFAULT_LATENCY_THRESHOLD: Duration = Duration::from_millis(500);
+
+/// Number of round trips in a row under the threshold for the controller to be considered
+/// responsive again, so that a sluggish controller does not flap.
+const RECOVERY_ROUND_TRIPS: u32 = 3;
+
+/// Returns the name of the opcode group (OGF) of `opcode`, as named in the core specification.
+pub(crate) fn opcode_group_name(opcode: u16) -> String {
+    match opcode >> 10 {
+        0x01 => String::from("link_control"),
+        0x02 => String::from("link_policy"),
+        0x03 => String::from("controller_baseband"),
+        0x04 => String::from("informational"),
+        0x05 => String::from("status"),
+        0x06 => String::from("testing"),
+        0x08 => String::from("le_controller"),
+        0x3f => String::from("vendor"),
+        ogf => format!("ogf_{:#04x}", ogf),
+    }
+}
+
+/// Returns the name of the histogram bucket of `latency`, such as `le_5ms`.
+pub(crate) fn bucket_name(latency: Duration) -> String {
+    let ms = latency.as_millis() as u64;
+    match LATENCY_BUCKETS_MS.iter().find(|bound| ms <= **bound) {
+        Some(bound) => format!("le_{}ms", bound),
+        None => format!("gt_{}ms", LATENCY_BUCKETS_MS[LATENCY_BUCKETS_MS.len() - 1]),
+    }
+}
+
+/// Change of the responsiveness of the controller.
+#[derive(Debug, PartialEq)]
+pub(crate) enum LatencyAlert {
+    Sluggish,
+    Recovered,
+}
+
+/// Watches the responsiveness of the controller from the latencies of the HCI commands.
+pub(crate) struct HciLatencyTracker {
+    // None when the alerts are disabled.
+    threshold: Option<Duration>,
+    sluggish: bool,
+    round_trips_under_threshold: u32,
+}
+
+impl HciLatencyTracker {
+    pub(crate) fn new() -> Self {
+        HciLatencyTracker {
+            threshold: Some(DEFAULT_LATENCY_THRESHOLD),
+            sluggish: false,
+            round_trips_under_threshold: 0,
+        }
+    }
+
+    /// Sets the latency above which the controller is considered sluggish, None to disable the
+    /// alerts.
+    pub(crate) fn set_threshold(&mut self, threshold: Option<Duration>) {
+        self.threshold = threshold;
+        self.sluggish = false;
+        self.round_trips_under_threshold = 0;
+    }
+
+    /// Compares the latency of a round trip with the threshold. Returns an alert when the
+    /// controller becomes sluggish or responsive again.
+    pub(crate) fn check_threshold(&mut self, latency: Duration) -> Option<LatencyAlert> {
+        let threshold = self.threshold?;
+
+        if latency > threshold {
+            self.round_trips_under_threshold = 0;
+            if !self.sluggish {
+                self.sluggish = true;
+                return Some(LatencyAlert::Sluggish);
+            }
+            return None;
+        }
+
+        if self.sluggish {
+            self.round_trips_under_threshold += 1;
+            if self.round_trips_under_threshold == RECOVERY_ROUND_TRIPS {
+                self.sluggish = false;
+                self.round_trips_under_threshold = 0;
+                return Some(LatencyAlert::Recovered);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert_eq!(opcode_group_name(0x201D), "le_controller");
+        assert_eq!(opcode_group_name(0xFD1E), "vendor");
+        assert_eq!(opcode_group_name(0x1C00), "ogf_0x07");
+
+        assert_eq!(bucket_name(Duration::from_micros(300)), "le_1ms");
+        assert_eq!(bucket_name(Duration::from_millis(5)), "le_5ms");
+        assert_eq!(bucket_name(Duration::from_millis(6)), "le_10ms");
+        assert_eq!(bucket_name(Duration::from_millis(1001)), "gt_1000ms");
+    }
+
+    #[test]
+    fn test_threshold() {
+        let mut tracker = HciLatencyTracker::new();
+        let slow = DEFAULT_LATENCY_THRESHOLD + Duration::from_millis(1);
+        let fast = Duration::from_millis(1);
+
+        assert_eq!(tracker.check_threshold(fast), None);
+        assert_eq!(tracker.check_threshold(slow), Some(LatencyAlert::Sluggish));
+        assert_eq!(tracker.check_threshold(slow), None);
+        assert_eq!(tracker.check_threshold(fast), None);
+        assert_eq!(tracker.check_threshold(fast), None);
+        // A slow round trip restarts the recovery.
+        assert_eq!(tracker.check_threshold(slow), None);
+        for _ in 0..RECOVERY_ROUND_TRIPS - 1 {
+            assert_eq!(tracker.check_threshold(fast), None);
+        }
+        assert_eq!(tracker.check_threshold(fast), Some(LatencyAlert::Recovered));
+
+        tracker.set_threshold(None);
+        assert_eq!(tracker.check_threshold(slow), None);
+    }
+}
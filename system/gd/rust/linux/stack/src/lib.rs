@@ -6,26 +6,80 @@
 #[macro_use]
 extern crate num_derive;
 
+pub mod address;
+pub mod address_resolution;
+pub mod advertising_policy;
+pub mod att_retry;
+pub mod att_trace;
+pub mod battery_manager;
 pub mod bluetooth;
+pub mod bluetooth_admin;
+pub mod bluetooth_adv;
+pub mod bluetooth_debug;
 pub mod bluetooth_gatt;
+pub mod bluetooth_hid;
+pub mod bluetooth_le_audio;
 pub mod bluetooth_media;
+pub mod bluetooth_qa;
+pub mod connection_priority;
+pub mod crypto;
+pub mod dfu;
+pub mod error;
+pub mod fast_pair;
+pub mod gatt_conformance;
+pub mod gatt_server_descriptors;
+pub mod gatt_service_builder;
+pub mod hci_latency;
+pub mod link_tuning;
+pub mod mesh;
+pub mod metrics;
+pub mod msft;
+pub mod notification_pacing;
+pub mod notification_queue;
+pub mod pairing_guard;
+pub mod phy_preferences;
+pub mod policy;
+pub mod privacy;
+pub mod provisioning;
+pub mod rssi_monitor;
+pub mod rssi_smoother;
+pub mod socket_manager;
+pub mod state_snapshot;
+pub mod storage;
 pub mod suspend;
+pub mod time_service;
 pub mod uuid;
+pub mod write_journal;
 
-use log::debug;
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::{Receiver, Sender};
 
-use crate::bluetooth::Bluetooth;
+use crate::battery_manager::{BatteryActions, BatteryManager};
+use crate::bluetooth::{Bluetooth, BluetoothDevice};
+use crate::bluetooth_admin::BluetoothAdmin;
+use crate::bluetooth_debug::BluetoothDebug;
 use crate::bluetooth_gatt::BluetoothGatt;
+use crate::bluetooth_hid::BluetoothHid;
+use crate::bluetooth_le_audio::BluetoothLeAudio;
 use crate::bluetooth_media::{BluetoothMedia, MediaActions};
-use crate::suspend::Suspend;
+use crate::bluetooth_qa::BluetoothQA;
+use crate::dfu::{DfuActions, DfuManager};
+use crate::fast_pair::{FastPairActions, FastPairManager};
+use crate::mesh::{MeshActions, MeshManager};
+use crate::privacy::{LocalIdentity, OwnAddressType};
+use crate::provisioning::{ProvisioningActions, ProvisioningManager};
+use crate::socket_manager::{BluetoothSocketManager, SocketActions};
+use crate::suspend::{Suspend, SuspendPreparation};
 use bt_topshim::{
     btif::BaseCallbacks,
+    controller::ControllerCallbacks,
+    l2cap::L2capCallbacks,
     profiles::{
-        a2dp::A2dpCallbacks, avrcp::AvrcpCallbacks, gatt::GattClientCallbacks,
-        gatt::GattServerCallbacks, hfp::HfpCallbacks, hid_host::HHCallbacks, sdp::SdpCallbacks,
+        a2dp::A2dpCallbacks, avrcp::AvrcpCallbacks, gatt::GattAdvCallbacks,
+        gatt::GattClientCallbacks, gatt::GattScannerCallbacks, gatt::GattScannerInbandCallbacks,
+        gatt::GattServerCallbacks, hfp::HfpCallbacks, hid_host::HHCallbacks,
+        le_audio::LeAudioCallbacks, sdp::SdpCallbacks,
     },
 };
 
@@ -43,12 +97,24 @@ pub enum Message {
     Base(BaseCallbacks),
     GattClient(GattClientCallbacks),
     GattServer(GattServerCallbacks),
+    LeScanner(GattScannerCallbacks),
+    LeScannerInband(GattScannerInbandCallbacks),
+    LeAdvertiser(GattAdvCallbacks),
     HidHost(HHCallbacks),
     Hfp(HfpCallbacks),
+    LeAudio(LeAudioCallbacks),
     Sdp(SdpCallbacks),
+    L2cap(L2capCallbacks),
+    Controller(ControllerCallbacks),
 
     // Actions within the stack
     Media(MediaActions),
+    SocketManager(SocketActions),
+    BatteryManager(BatteryActions),
+    Provisioning(ProvisioningActions),
+    FastPair(FastPairActions),
+    Mesh(MeshActions),
+    Dfu(DfuActions),
 
     // Client callback disconnections
     BluetoothCallbackDisconnected(u32, BluetoothCallbackType),
@@ -56,9 +122,113 @@ pub enum Message {
     // Update list of found devices and remove old instances.
     DeviceFreshnessCheck,
 
+    // Forget everything about a device, see `IBluetooth::remove_bond_cascade`.
+    ForgetDevice(String),
+
+    // Release the GATT registrations and disable the adapter for `IBluetooth::restart_stack`,
+    // and report the restart failed if it is not over in time.
+    StackRestart,
+    StackRestartTimeout,
+
+    // Give the controller advertiser slots to the suspended advertising sets.
+    AdvertisingSetRotation,
+
+    // Step to the next level of the TX power sweep of an advertising set.
+    AdvertisingTxPowerSweep(i32),
+
+    // Report the devices no longer matching the scans tracking their matches.
+    ScanMatchLostCheck,
+
+    // Hand the aggressive scan parameters over to the next competing scanner.
+    ScanGovernorRotate,
+
+    // Deliver the results a scanner held for its report delay.
+    ScanResultBatchFlush(i32),
+
+    // Reclaim a scanner which stopped pinging, see `IBluetoothGatt::set_scanner_liveness_interval`.
+    ScannerLivenessCheck(i32),
+    ScannerCallbackDisconnected(u32),
+    // Stop the advertising sets of a callback that disconnected.
+    AdvertiserCallbackDisconnected(u32),
+
+    // Fail the operations of a connection whose ATT requests were not answered in time.
+    GattTransactionTimeout(i32),
+
+    // Send again a read of a connection which failed with a transient error: connection ID and
+    // attribute handle.
+    GattRetryRead(i32, i32),
+    GattDeliverNotifications(i32),
+    // Read the RSSI of a connection monitored with `IBluetoothGatt::start_rssi_monitor`.
+    GattRssiPoll(i32),
+    // Reject a central the peripheral connection agent did not decide on in time.
+    GattPeripheralAgentTimeout(String),
+    // Tune the LE link of a device that connected, or forget its tuning once disconnected.
+    GattLeLinkStateChanged(String, bool),
+    // A local identity broadcast by an advertising set.
+    IdentityExposed(String, LocalIdentity, OwnAddressType),
+
+    // Register the built-in Current Time Service after the adapter is enabled.
+    TimeServiceStart,
+    // Forget the advertising sets lost by the controller once the adapter is disabled, and start
+    // again the persistent ones once it is enabled.
+    AdvertisingSetsLost,
+    AdvertisingSetsRestore,
+    // Register again the GATT clients, servers and scanners released by a restart of the stack.
+    GattRegistrationsRestore,
+    // Check whether the clock served by the Current Time Service was adjusted.
+    TimeServiceClockCheck,
+
+    // Log the counters of the activity of the stack.
+    MetricsLog,
+    // The latency of the HCI commands crossed the alert threshold: opcode, latency in
+    // milliseconds and whether the controller became sluggish.
+    HciLatencyAlert(u16, u32, bool),
+
+    // Debug related
+    DebugCallbackDisconnected(u32),
+
     // Suspend related
     SuspendCallbackRegistered(u32),
     SuspendCallbackDisconnected(u32),
+    SuspendReady(u32),
+    SuspendPreparationDone(SuspendPreparation),
+    SuspendPreparationTimeout(u32),
+    ResumeReady(u32),
+
+    // QA related
+    QACallbackDisconnected(u32),
+
+    // Admin policy related
+    AdminCallbackDisconnected(u32),
+    // Report the effect of the policy on a device whose services changed.
+    AdminRemoteUuidsChanged(BluetoothDevice),
+
+    // Battery manager related
+    BatteryManagerCallbackDisconnected(u32),
+
+    // Provisioning related
+    ProvisioningCallbackDisconnected(u32),
+
+    // Fast Pair related
+    FastPairCallbackDisconnected(u32),
+
+    // Mesh related
+    MeshCallbackDisconnected(u32),
+
+    // Firmware update related
+    DfuCallbackDisconnected(u32),
+
+    // HID host related
+    HidCallbackDisconnected(u32),
+
+    // LE Audio related
+    LeAudioCallbackDisconnected(u32),
+
+    // Telephony related
+    TelephonyCallbackDisconnected(u32),
+
+    // Socket manager related
+    SocketManagerCallbackDisconnected(u32),
 }
 
 /// Umbrella class for the Bluetooth stack.
@@ -77,6 +247,17 @@ impl Stack {
         bluetooth_gatt: Arc<Mutex<Box<BluetoothGatt>>>,
         bluetooth_media: Arc<Mutex<Box<BluetoothMedia>>>,
         suspend: Arc<Mutex<Box<Suspend>>>,
+        bluetooth_qa: Arc<Mutex<Box<BluetoothQA>>>,
+        bluetooth_le_audio: Arc<Mutex<Box<BluetoothLeAudio>>>,
+        bluetooth_socket_manager: Arc<Mutex<Box<BluetoothSocketManager>>>,
+        bluetooth_admin: Arc<Mutex<Box<BluetoothAdmin>>>,
+        battery_manager: Arc<Mutex<Box<BatteryManager>>>,
+        bluetooth_hid: Arc<Mutex<Box<BluetoothHid>>>,
+        bluetooth_debug: Arc<Mutex<Box<BluetoothDebug>>>,
+        provisioning: Arc<Mutex<Box<ProvisioningManager>>>,
+        fast_pair: Arc<Mutex<Box<FastPairManager>>>,
+        mesh: Arc<Mutex<Box<MeshManager>>>,
+        dfu: Arc<Mutex<Box<DfuManager>>>,
     ) {
         loop {
             let m = rx.recv().await;
@@ -95,36 +276,98 @@ impl Stack {
                     bluetooth_media.lock().unwrap().dispatch_avrcp_callbacks(av);
                 }
 
-                Message::Base(b) => {
-                    bluetooth.lock().unwrap().dispatch_base_callbacks(b);
-                }
+                Message::Base(b) => match b {
+                    BaseCallbacks::DutModeRecv(..) | BaseCallbacks::LeTestMode(..) => {
+                        bluetooth_qa.lock().unwrap().dispatch_base_callbacks(b);
+                    }
+                    _ => {
+                        bluetooth.lock().unwrap().dispatch_base_callbacks(b);
+                    }
+                },
 
                 Message::GattClient(m) => {
                     bluetooth_gatt.lock().unwrap().dispatch_gatt_client_callbacks(m);
                 }
 
                 Message::GattServer(m) => {
-                    // TODO(b/193685149): dispatch GATT server callbacks.
-                    debug!("Unhandled Message::GattServer: {:?}", m);
+                    bluetooth_gatt.lock().unwrap().dispatch_gatt_server_callbacks(m);
                 }
 
-                Message::Hfp(hf) => {
-                    bluetooth_media.lock().unwrap().dispatch_hfp_callbacks(hf);
+                Message::LeScanner(m) => {
+                    bluetooth_gatt.lock().unwrap().dispatch_le_scanner_callbacks(m);
                 }
 
-                Message::HidHost(_h) => {
-                    // TODO(abps) - Handle hid host callbacks
-                    debug!("Received HH callback");
+                Message::LeScannerInband(m) => {
+                    bluetooth_gatt.lock().unwrap().dispatch_le_scanner_inband_callbacks(m);
+                }
+
+                Message::LeAdvertiser(m) => {
+                    bluetooth_gatt.lock().unwrap().dispatch_le_adv_callbacks(m);
+                }
+
+                Message::Hfp(hf) => match hf {
+                    HfpCallbacks::BatteryLevel(..) => {
+                        battery_manager.lock().unwrap().dispatch_hfp_callbacks(hf);
+                    }
+                    _ => {
+                        bluetooth_media.lock().unwrap().dispatch_hfp_callbacks(hf);
+                    }
+                },
+
+                Message::LeAudio(la) => {
+                    bluetooth_le_audio.lock().unwrap().dispatch_le_audio_callbacks(la);
+                }
+
+                Message::HidHost(h) => {
+                    bluetooth_hid.lock().unwrap().dispatch_hid_host_callbacks(h);
                 }
 
                 Message::Sdp(s) => {
                     bluetooth.lock().unwrap().dispatch_sdp_callbacks(s);
                 }
 
+                Message::L2cap(l2cap) => {
+                    bluetooth_socket_manager.lock().unwrap().dispatch_l2cap_callbacks(l2cap);
+                }
+
+                Message::Controller(controller) => match controller {
+                    ControllerCallbacks::ControllerLists(..)
+                    | ControllerCallbacks::LeTestEnded(..) => {
+                        bluetooth_qa.lock().unwrap().dispatch_controller_callbacks(controller);
+                    }
+                    _ => {
+                        bluetooth_gatt.lock().unwrap().dispatch_controller_callbacks(controller);
+                    }
+                },
+
                 Message::Media(action) => {
                     bluetooth_media.lock().unwrap().dispatch_media_actions(action);
                 }
 
+                Message::SocketManager(action) => {
+                    bluetooth_socket_manager.lock().unwrap().dispatch_socket_actions(action);
+                }
+
+                Message::BatteryManager(action) => {
+                    battery_manager.lock().unwrap().dispatch_battery_actions(action);
+                }
+
+                Message::Provisioning(action) => {
+                    provisioning.lock().unwrap().dispatch_provisioning_actions(action);
+                }
+
+                Message::FastPair(action) => {
+                    fast_pair.lock().unwrap().dispatch_fast_pair_actions(action);
+                }
+
+                Message::Mesh(action) => {
+                    mesh.lock().unwrap().dispatch_mesh_actions(action);
+                }
+
+                Message::Dfu(action) => {
+                    dfu.lock().unwrap().dispatch_dfu_actions(action);
+                }
+
                 Message::BluetoothCallbackDisconnected(id, cb_type) => {
                     bluetooth.lock().unwrap().callback_disconnected(id, cb_type);
                 }
@@ -133,6 +376,123 @@ impl Stack {
                     bluetooth.lock().unwrap().trigger_freshness_check();
                 }
 
+                Message::StackRestart => {
+                    // Released first, while the stack can still take the IDs back.
+                    bluetooth_gatt.lock().unwrap().release_registrations();
+                    bluetooth.lock().unwrap().disable_for_restart();
+                }
+
+                Message::StackRestartTimeout => {
+                    bluetooth.lock().unwrap().check_stack_restart_timeout();
+                }
+
+                Message::ForgetDevice(address) => {
+                    // Handled in one go so that no subsystem acts on a partially forgotten device.
+                    bluetooth_gatt.lock().unwrap().forget_device(&address);
+                    bluetooth_media.lock().unwrap().forget_device(&address);
+                    bluetooth.lock().unwrap().forget_device(&address);
+                }
+
+                Message::AdvertisingSetRotation => {
+                    bluetooth_gatt.lock().unwrap().rotate_advertising_sets();
+                }
+
+                Message::AdvertisingTxPowerSweep(advertiser_id) => {
+                    bluetooth_gatt.lock().unwrap().step_tx_power_sweep(advertiser_id);
+                }
+
+                Message::ScanMatchLostCheck => {
+                    bluetooth_gatt.lock().unwrap().check_lost_matches();
+                }
+
+                Message::ScanGovernorRotate => {
+                    bluetooth_gatt.lock().unwrap().rotate_scan_holder();
+                }
+
+                Message::ScannerLivenessCheck(scanner_id) => {
+                    bluetooth_gatt.lock().unwrap().check_scanner_liveness(scanner_id);
+                }
+
+                Message::ScanResultBatchFlush(scanner_id) => {
+                    bluetooth_gatt.lock().unwrap().flush_scan_results(scanner_id);
+                }
+
+                Message::ScannerCallbackDisconnected(id) => {
+                    bluetooth_gatt.lock().unwrap().remove_scanner_callback(id);
+                }
+
+                Message::AdvertiserCallbackDisconnected(id) => {
+                    bluetooth_gatt.lock().unwrap().remove_advertiser_callback(id);
+                }
+
+                Message::GattRetryRead(conn_id, handle) => {
+                    bluetooth_gatt.lock().unwrap().retry_read(conn_id, handle);
+                }
+
+                Message::GattTransactionTimeout(conn_id) => {
+                    bluetooth_gatt.lock().unwrap().time_out_transactions(conn_id);
+                }
+
+                Message::GattDeliverNotifications(client_id) => {
+                    bluetooth_gatt.lock().unwrap().deliver_notifications(client_id);
+                }
+
+                Message::GattRssiPoll(conn_id) => {
+                    bluetooth_gatt.lock().unwrap().poll_rssi(conn_id);
+                }
+
+                Message::GattPeripheralAgentTimeout(address) => {
+                    bluetooth_gatt.lock().unwrap().time_out_peripheral_agent(address);
+                }
+
+                Message::GattLeLinkStateChanged(address, true) => {
+                    bluetooth_gatt.lock().unwrap().on_le_link_connected(address);
+                }
+
+                Message::GattLeLinkStateChanged(address, false) => {
+                    bluetooth_gatt.lock().unwrap().on_le_link_disconnected(address);
+                }
+
+                Message::IdentityExposed(address, identity, own_address_type) => {
+                    bluetooth.lock().unwrap().record_identity_exposure(
+                        &address,
+                        identity,
+                        own_address_type,
+                    );
+                }
+
+                Message::TimeServiceStart => {
+                    bluetooth_gatt.lock().unwrap().start_time_service();
+                }
+
+                Message::AdvertisingSetsLost => {
+                    bluetooth_gatt.lock().unwrap().forget_advertising_slots();
+                }
+
+                Message::AdvertisingSetsRestore => {
+                    bluetooth_gatt.lock().unwrap().restore_advertising_sets();
+                }
+
+                Message::GattRegistrationsRestore => {
+                    bluetooth_gatt.lock().unwrap().restore_registrations();
+                }
+
+                Message::TimeServiceClockCheck => {
+                    bluetooth_gatt.lock().unwrap().check_clock();
+                }
+
+                Message::MetricsLog => {
+                    bluetooth_gatt.lock().unwrap().log_metrics();
+                }
+
+                Message::HciLatencyAlert(opcode, latency_ms, sluggish) => {
+                    bluetooth_debug.lock().unwrap().hci_latency_alert(opcode, latency_ms, sluggish);
+                }
+
+                Message::DebugCallbackDisconnected(id) => {
+                    bluetooth_debug.lock().unwrap().remove_callback(id);
+                }
+
                 Message::SuspendCallbackRegistered(id) => {
                     suspend.lock().unwrap().callback_registered(id);
                 }
@@ -140,6 +500,70 @@ impl Stack {
                 Message::SuspendCallbackDisconnected(id) => {
                     suspend.lock().unwrap().remove_callback(id);
                 }
+
+                Message::SuspendReady(suspend_id) => {
+                    suspend.lock().unwrap().suspend_ready(suspend_id);
+                }
+
+                Message::SuspendPreparationDone(preparation) => {
+                    suspend.lock().unwrap().preparation_done(preparation);
+                }
+
+                Message::SuspendPreparationTimeout(suspend_id) => {
+                    suspend.lock().unwrap().preparation_timed_out(suspend_id);
+                }
+
+                Message::ResumeReady(suspend_id) => {
+                    suspend.lock().unwrap().resume_ready(suspend_id);
+                }
+
+                Message::QACallbackDisconnected(id) => {
+                    bluetooth_qa.lock().unwrap().remove_callback(id);
+                }
+
+                Message::AdminCallbackDisconnected(id) => {
+                    bluetooth_admin.lock().unwrap().remove_callback(id);
+                }
+
+                Message::AdminRemoteUuidsChanged(device) => {
+                    bluetooth_admin.lock().unwrap().update_device_policy_effect(device);
+                }
+
+                Message::BatteryManagerCallbackDisconnected(id) => {
+                    battery_manager.lock().unwrap().remove_callback(id);
+                }
+
+                Message::ProvisioningCallbackDisconnected(id) => {
+                    provisioning.lock().unwrap().callback_disconnected(id);
+                }
+
+                Message::FastPairCallbackDisconnected(id) => {
+                    fast_pair.lock().unwrap().remove_callback(id);
+                }
+
+                Message::MeshCallbackDisconnected(id) => {
+                    mesh.lock().unwrap().remove_callback(id);
+                }
+
+                Message::DfuCallbackDisconnected(id) => {
+                    dfu.lock().unwrap().callback_disconnected(id);
+                }
+
+                Message::HidCallbackDisconnected(id) => {
+                    bluetooth_hid.lock().unwrap().remove_callback(id);
+                }
+
+                Message::LeAudioCallbackDisconnected(id) => {
+                    bluetooth_le_audio.lock().unwrap().remove_callback(id);
+                }
+
+                Message::TelephonyCallbackDisconnected(id) => {
+                    bluetooth_media.lock().unwrap().remove_telephony_callback(id);
+                }
+
+                Message::SocketManagerCallbackDisconnected(id) => {
+                    bluetooth_socket_manager.lock().unwrap().remove_callback(id);
+                }
             }
         }
     }
@@ -157,6 +581,12 @@ pub trait RPCProxy {
     /// Returns the ID of the object. For example this would be an object path in D-Bus RPC.
     fn get_object_id(&self) -> String;
 
+    /// Returns the ID of the peer owning the object, the unique bus name of its connection in
+    /// D-Bus RPC. Empty for local objects.
+    fn get_remote_id(&self) -> String {
+        String::from("")
+    }
+
     /// Unregisters callback with this id.
     fn unregister(&mut self, id: u32) -> bool;
 
@@ -0,0 +1,230 @@
+//! Tuning of the LE links of GATT connections by class of peripheral.
+//!
+//! Bonded peripherals are classified from their services when their LE link connects, whichever
+//! client or profile connected it: input devices get short connection intervals and keep the
+//! default MTU, while devices receiving firmware updates or files get the largest MTU, which also
+//! raises the data length, and the 2M PHY. Clients may override the profile of a device with
+//! `IBluetoothGatt::set_link_tuning_profile`.
+
+use bt_topshim::btif::Uuid128Bit;
+use std::collections::HashMap;
+
+use crate::bluetooth_gatt::LePhy;
+use crate::uuid::UuidHelper;
+
+/// Link parameters applied to a connection.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum LinkTuningProfile {
+    /// The parameters of the stack are left untouched.
+    Default = 0,
+    /// Short connection intervals without peripheral latency, for input devices.
+    LowLatency = 1,
+    /// Largest MTU and data length on the 2M PHY, for bulk transfers.
+    HighThroughput = 2,
+}
+
+impl Default for LinkTuningProfile {
+    fn default() -> Self {
+        LinkTuningProfile::Default
+    }
+}
+
+// Services of the input devices.
+const LOW_LATENCY_SERVICES: &[&str] = &[crate::uuid::HID, crate::uuid::HOGP];
+
+// Services used to update the firmware of a device or to transfer files.
+const HIGH_THROUGHPUT_SERVICES: &[&str] = &[
+    // Object Transfer Service.
+    "00001825-0000-1000-8000-00805F9B34FB",
+    // Nordic Secure DFU.
+    "0000FE59-0000-1000-8000-00805F9B34FB",
+    // Nordic legacy DFU.
+    "00001530-1212-EFDE-1523-785FEABCD123",
+    // MCUmgr Simple Management Protocol.
+    "8D53DC1D-1DB7-4CD3-868B-8A527460AA84",
+];
+
+/// Parameters of the connections tuned by a profile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct LinkParameters {
+    /// MTU to request, which lets the stack raise the data length as well.
+    pub mtu: Option<i32>,
+    pub phy: Option<LePhy>,
+    /// Minimum and maximum connection intervals in 1.25 ms units.
+    pub interval: (i32, i32),
+    pub latency: i32,
+    /// Supervision timeout in 10 ms units.
+    pub timeout: i32,
+}
+
+/// Largest MTU supported by ATT.
+const MAX_MTU: i32 = 517;
+
+/// Returns the profile of a device from its services. Input devices take precedence, as their
+/// latency is noticed by the user.
+pub(crate) fn classify(uuids: &[Uuid128Bit]) -> LinkTuningProfile {
+    let provides = |services: &[&str]| {
+        services.iter().filter_map(|s| UuidHelper::from_string(*s)).any(|s| uuids.contains(&s))
+    };
+
+    if provides(LOW_LATENCY_SERVICES) {
+        LinkTuningProfile::LowLatency
+    } else if provides(HIGH_THROUGHPUT_SERVICES) {
+        LinkTuningProfile::HighThroughput
+    } else {
+        LinkTuningProfile::Default
+    }
+}
+
+/// Returns the profile applied to a device whose link connected: the profile overridden by a
+/// client, or else the profile of its class if it is bonded, with the services `bonded_uuids`.
+pub(crate) fn select(
+    overridden: Option<LinkTuningProfile>,
+    bonded_uuids: Option<&[Uuid128Bit]>,
+) -> LinkTuningProfile {
+    match (overridden, bonded_uuids) {
+        (Some(profile), _) => profile,
+        (None, Some(uuids)) => classify(uuids),
+        (None, None) => LinkTuningProfile::Default,
+    }
+}
+
+/// Profiles overridden by the clients, by device. The override set last applies to a device, the
+/// earlier ones of the other clients applying again once it is cleared.
+#[derive(Default)]
+pub(crate) struct LinkProfileOverrides {
+    // Overrides by address in upper case, oldest first, along with the ID of their client.
+    overrides: HashMap<String, Vec<(i32, LinkTuningProfile)>>,
+}
+
+impl LinkProfileOverrides {
+    pub(crate) fn get(&self, address: &str) -> Option<LinkTuningProfile> {
+        self.overrides.get(&address.to_uppercase())?.last().map(|(_, profile)| *profile)
+    }
+
+    pub(crate) fn set(&mut self, client_id: i32, address: &str, profile: LinkTuningProfile) {
+        let overrides = self.overrides.entry(address.to_uppercase()).or_default();
+        overrides.retain(|(id, _)| *id != client_id);
+        overrides.push((client_id, profile));
+    }
+
+    /// Removes the override of a client. Returns false if it has none for the device.
+    pub(crate) fn clear(&mut self, client_id: i32, address: &str) -> bool {
+        let address = address.to_uppercase();
+        let overrides = match self.overrides.get_mut(&address) {
+            Some(overrides) => overrides,
+            None => return false,
+        };
+
+        let count = overrides.len();
+        overrides.retain(|(id, _)| *id != client_id);
+        let cleared = overrides.len() != count;
+        if overrides.is_empty() {
+            self.overrides.remove(&address);
+        }
+        cleared
+    }
+
+    /// Removes the overrides of a client. Returns the addresses of the devices whose profile
+    /// changed.
+    pub(crate) fn remove_client(&mut self, client_id: i32) -> Vec<String> {
+        let mut changed = vec![];
+        self.overrides.retain(|address, overrides| {
+            let previous = overrides.last().cloned();
+            overrides.retain(|(id, _)| *id != client_id);
+            if overrides.last().map(|(_, profile)| *profile) != previous.map(|(_, profile)| profile)
+            {
+                changed.push(address.clone());
+            }
+            !overrides.is_empty()
+        });
+        changed
+    }
+}
+
+/// Returns the parameters applied by `profile`, None if the connection is left untouched.
+pub(crate) fn parameters(profile: LinkTuningProfile) -> Option<LinkParameters> {
+    match profile {
+        LinkTuningProfile::Default => None,
+        // 7.5 to 15 ms.
+        LinkTuningProfile::LowLatency => Some(LinkParameters {
+            mtu: None,
+            phy: None,
+            interval: (6, 12),
+            latency: 0,
+            timeout: 200,
+        }),
+        // 15 to 30 ms, leaving room for several packets per connection event.
+        LinkTuningProfile::HighThroughput => Some(LinkParameters {
+            mtu: Some(MAX_MTU),
+            phy: Some(LePhy::Phy2m),
+            interval: (12, 24),
+            latency: 0,
+            timeout: 500,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let uuid = |s: &str| UuidHelper::from_string(s).unwrap();
+        let hogp = uuid(crate::uuid::HOGP);
+        let dfu = uuid("0000FE59-0000-1000-8000-00805F9B34FB");
+        let battery = uuid("0000180F-0000-1000-8000-00805F9B34FB");
+
+        assert_eq!(LinkTuningProfile::Default, classify(&[]));
+        assert_eq!(LinkTuningProfile::Default, classify(&[battery]));
+        assert_eq!(LinkTuningProfile::HighThroughput, classify(&[battery, dfu]));
+        assert_eq!(LinkTuningProfile::LowLatency, classify(&[dfu, hogp]));
+
+        assert_eq!(None, parameters(LinkTuningProfile::Default));
+        assert_eq!(Some(MAX_MTU), parameters(LinkTuningProfile::HighThroughput).unwrap().mtu);
+    }
+
+    #[test]
+    fn test_select() {
+        let hid = UuidHelper::from_string(crate::uuid::HID).unwrap();
+
+        // Input devices are tuned whichever client connected them, once bonded.
+        assert_eq!(LinkTuningProfile::LowLatency, select(None, Some(&[hid])));
+        assert_eq!(LinkTuningProfile::Default, select(None, None));
+        assert_eq!(
+            LinkTuningProfile::HighThroughput,
+            select(Some(LinkTuningProfile::HighThroughput), Some(&[hid]))
+        );
+        assert_eq!(
+            LinkTuningProfile::Default,
+            select(Some(LinkTuningProfile::Default), Some(&[hid]))
+        );
+    }
+
+    #[test]
+    fn test_link_profile_overrides() {
+        let address = "aa:bb:cc:dd:ee:ff";
+        let mut overrides = LinkProfileOverrides::default();
+        assert_eq!(None, overrides.get(address));
+
+        overrides.set(1, address, LinkTuningProfile::LowLatency);
+        overrides.set(2, "AA:BB:CC:DD:EE:FF", LinkTuningProfile::HighThroughput);
+        assert_eq!(Some(LinkTuningProfile::HighThroughput), overrides.get(address));
+
+        // Setting again makes the override of the client the latest.
+        overrides.set(1, address, LinkTuningProfile::Default);
+        assert_eq!(Some(LinkTuningProfile::Default), overrides.get(address));
+
+        // The override of the other client applies again once cleared.
+        assert!(overrides.clear(1, address));
+        assert!(!overrides.clear(1, address));
+        assert_eq!(Some(LinkTuningProfile::HighThroughput), overrides.get(address));
+
+        overrides.set(1, address, LinkTuningProfile::LowLatency);
+        assert_eq!(Vec::<String>::new(), overrides.remove_client(2));
+        assert_eq!(vec![String::from("AA:BB:CC:DD:EE:FF")], overrides.remove_client(1));
+        assert_eq!(None, overrides.get(address));
+    }
+}
@@ -0,0 +1,1933 @@
+//! Bluetooth Mesh bearers, see `IBluetoothMesh`.
+//!
+//! The stack carries the PDUs of a mesh daemon, which runs the upper layers of the mesh itself
+//! (the network and transport layers, the provisioning protocol and the keys):
+//!
+//! * The advertising bearer sends and receives the Mesh Message and Mesh Beacon AD structures.
+//! * PB-ADV carries the provisioning PDUs over advertising, on a link opened to the UUID of an
+//!   unprovisioned device. Each PDU is split into a transaction, sent again until the device
+//!   acknowledges it.
+//! * PB-GATT and the proxy protocol carry the provisioning PDUs and the mesh messages over a
+//!   connection to the Mesh Provisioning or Mesh Proxy service of a device, split to the MTU.
+//!
+//! The stack is the provisioner and the proxy client: it opens the links to the devices, which do
+//! not open links to it.
+
+use bt_topshim::btif::{BtTransport, Uuid128Bit};
+use bt_topshim::profiles::gatt::GattStatus;
+use bt_topshim::topstack;
+
+use log::{debug, warn};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::address::BtAddress;
+use crate::bluetooth_adv::{
+    AdvertiseData, AdvertisingSetParameters, AdvertisingStatus, AdvertisingTerminationReason,
+    IAdvertisingSetCallback,
+};
+use crate::bluetooth_gatt::{
+    BatchScanResult, BluetoothGatt, BluetoothGattService, CharacteristicReadResult,
+    GattHandleValue, GattWriteRequestStatus, GattWriteType, IBluetoothGatt, IBluetoothGattCallback,
+    IScannerCallback, LePhy, RSSISettings, ScanCallbackType, ScanPriority, ScanRecordDelivery,
+    ScanResult, ScanSettings, ScanType, BASE_UUID, SCAN_PHY_LE_1M,
+};
+use crate::crypto::random_bytes;
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::gatt_conformance::ConformanceIssue;
+use crate::gatt_service_builder::CCCD_UUID;
+use crate::uuid::BtUuid;
+use crate::{Message, RPCProxy};
+
+/// Application UUID of the GATT client of the PB-GATT and proxy links.
+const MESH_CLIENT_UUID: Uuid128Bit = [
+    0x2F, 0x6C, 0x81, 0x3A, 0x5D, 0x07, 0x4C, 0x92, 0xB3, 0x1E, 0x94, 0x0A, 0x66, 0xD2, 0x18, 0x27,
+];
+
+// AD types of the advertising bearer.
+const AD_TYPE_PB_ADV: u8 = 0x29;
+const AD_TYPE_MESH_MESSAGE: u8 = 0x2A;
+const AD_TYPE_MESH_BEACON: u8 = 0x2B;
+
+const MESH_PROVISIONING_SERVICE_UUID16: [u8; 2] = [0x18, 0x27];
+const MESH_PROVISIONING_DATA_IN_UUID16: [u8; 2] = [0x2A, 0xDB];
+const MESH_PROVISIONING_DATA_OUT_UUID16: [u8; 2] = [0x2A, 0xDC];
+const MESH_PROXY_SERVICE_UUID16: [u8; 2] = [0x18, 0x28];
+const MESH_PROXY_DATA_IN_UUID16: [u8; 2] = [0x2A, 0xDD];
+const MESH_PROXY_DATA_OUT_UUID16: [u8; 2] = [0x2A, 0xDE];
+
+const CCCD_ENABLE_NOTIFICATION: [u8; 2] = [0x01, 0x00];
+
+/// MTU requested on the GATT links, so that most PDUs fit in a single segment.
+const MESH_GATT_MTU: i32 = 247;
+// Until the MTU is negotiated.
+const DEFAULT_ATT_MTU: usize = 23;
+
+// Longest data of an AD structure in a legacy advertisement.
+const MAX_AD_DATA_LEN: usize = 29;
+// Link ID and transaction number heading the Generic Provisioning PDUs.
+const PB_ADV_HEADER_LEN: usize = 5;
+// Segments of a transaction, counted by a 6-bit field.
+const MAX_TRANSACTION_SEGMENTS: usize = 64;
+const TRANSACTION_START_HEADER_LEN: usize = 4;
+const TRANSACTION_CONTINUATION_HEADER_LEN: usize = 1;
+
+// Generic Provisioning Control Format, in the 2 low bits of the first byte.
+const GPCF_TRANSACTION_START: u8 = 0b00;
+const GPCF_TRANSACTION_ACK: u8 = 0b01;
+const GPCF_TRANSACTION_CONTINUATION: u8 = 0b10;
+const GPCF_BEARER_CONTROL: u8 = 0b11;
+
+const BEARER_OPCODE_LINK_OPEN: u8 = 0x00;
+const BEARER_OPCODE_LINK_ACK: u8 = 0x01;
+const BEARER_OPCODE_LINK_CLOSE: u8 = 0x02;
+
+// Transaction numbers of the provisioner, the device using 0x80 to 0xFF.
+const PROVISIONER_TRANSACTIONS: u8 = 0x80;
+// Times a Link Close is advertised, as it is not acknowledged.
+const LINK_CLOSE_REPEAT: usize = 3;
+
+// Segmentation and Reassembly field of the proxy PDUs, in the 2 high bits of the first byte.
+const SAR_COMPLETE: u8 = 0b00;
+const SAR_FIRST: u8 = 0b01;
+const SAR_CONTINUATION: u8 = 0b10;
+const SAR_LAST: u8 = 0b11;
+// Longest PDU reassembled from the proxy PDUs, the Public Key PDU of the provisioning protocol.
+const MAX_PROXY_PDU_LEN: usize = 65;
+
+// Scan of the advertising bearer, continuous so that few of the short-lived mesh PDUs are missed,
+// in 0.625 ms units.
+const MESH_SCAN_INTERVAL: i32 = 96;
+
+/// Time each PDU is advertised, a few advertising events at the shortest interval.
+const ADVERTISING_SLOT: Duration = Duration::from_millis(60);
+const ADVERTISING_INTERVAL: i32 = 32;
+/// Interval between the retransmissions of the unacknowledged PB-ADV PDUs.
+const RETRANSMIT_INTERVAL: Duration = Duration::from_millis(500);
+/// Time for a link to open, and for a PB-ADV transaction to be acknowledged.
+const LINK_OPEN_TIMEOUT: Duration = Duration::from_secs(60);
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn uuid16(short: [u8; 2]) -> Uuid128Bit {
+    let mut uuid = BASE_UUID;
+    uuid[2..4].copy_from_slice(&short);
+    uuid
+}
+
+/// Defines the Bluetooth Mesh bearer API.
+pub trait IBluetoothMesh {
+    /// Adds an observer of the mesh bearers. Every observer is reported the PDUs received and the
+    /// events of every link.
+    ///
+    /// Returns the id of the callback.
+    fn register_mesh_callback(&mut self, callback: Box<dyn IBluetoothMeshCallback + Send>) -> u32;
+
+    /// Removes an observer of the mesh bearers.
+    ///
+    /// Returns false if `callback_id` is not recognized.
+    fn unregister_mesh_callback(&mut self, callback_id: u32) -> bool;
+
+    /// Starts scanning for the mesh messages and beacons advertised nearby, reported with
+    /// `IBluetoothMeshCallback::on_advertising_pdu`.
+    fn start_advertising_bearer(&mut self) -> BtResult<()>;
+
+    /// Stops reporting the advertised mesh messages and beacons.
+    fn stop_advertising_bearer(&mut self);
+
+    /// Advertises a Network PDU or a Mesh Beacon, after the PDUs queued before it.
+    fn send_advertising_pdu(&mut self, pdu_type: MeshPduType, pdu: Vec<u8>) -> BtResult<()>;
+
+    /// Opens a PB-ADV link to the unprovisioned device advertising `device_uuid` in its beacon.
+    /// The link is reported with `IBluetoothMeshCallback::on_link_opened` once the device
+    /// accepts it, or closed with `MeshLinkCloseReason::Timeout`.
+    ///
+    /// Returns the id of the link.
+    fn open_advertising_link(&mut self, device_uuid: Uuid128Bit) -> BtResult<i32>;
+
+    /// Connects to the Mesh Provisioning (PB-GATT) or the Mesh Proxy service of a device. The
+    /// link is reported with `IBluetoothMeshCallback::on_link_opened` once subscribed to the
+    /// service, or closed if it cannot be.
+    ///
+    /// Returns the id of the link.
+    fn open_gatt_link(&mut self, addr: BtAddress, bearer: MeshGattBearer) -> BtResult<i32>;
+
+    /// Closes a link, telling a PB-ADV device the `reason`. A GATT link disconnects.
+    fn close_link(&mut self, link_id: i32, reason: MeshLinkCloseReason) -> BtResult<()>;
+
+    /// Sends a PDU over an open link: provisioning PDUs over the PB-ADV and PB-GATT links, and
+    /// the other types over the proxy links.
+    fn send_link_pdu(&mut self, link_id: i32, pdu_type: MeshPduType, pdu: Vec<u8>) -> BtResult<()>;
+}
+
+/// Bluetooth Mesh bearer events.
+pub trait IBluetoothMeshCallback: RPCProxy {
+    /// When a Network PDU or a Mesh Beacon is advertised by `addr`, while the advertising bearer
+    /// is started.
+    fn on_advertising_pdu(&self, addr: String, rssi: i32, pdu_type: MeshPduType, pdu: Vec<u8>);
+
+    /// When a link opened with `open_advertising_link` or `open_gatt_link` is ready.
+    fn on_link_opened(&self, link_id: i32);
+
+    /// When a link closes, whichever side closed it.
+    fn on_link_closed(&self, link_id: i32, reason: MeshLinkCloseReason);
+
+    /// When a PDU is received over a link.
+    fn on_link_pdu(&self, link_id: i32, pdu_type: MeshPduType, pdu: Vec<u8>);
+}
+
+/// Type of a mesh PDU, numbered as the message types of the proxy protocol.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum MeshPduType {
+    NetworkPdu = 0,
+    MeshBeacon = 1,
+    ProxyConfiguration = 2,
+    ProvisioningPdu = 3,
+}
+
+/// Service of a GATT link.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum MeshGattBearer {
+    /// Mesh Provisioning Service, carrying the provisioning PDUs of an unprovisioned device.
+    Provisioning = 0,
+    /// Mesh Proxy Service, relaying the mesh messages of a node.
+    Proxy = 1,
+}
+
+/// Reason a link closed. The first three are those of the PB-ADV Link Close.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum MeshLinkCloseReason {
+    Success = 0,
+    Timeout = 1,
+    Fail = 2,
+    ConnectionFailed = 3,
+    Disconnected = 4,
+    /// The device does not have the service of the GATT link.
+    ServiceNotFound = 5,
+}
+
+impl MeshLinkCloseReason {
+    fn from_link_close(reason: u8) -> MeshLinkCloseReason {
+        match reason {
+            0 => MeshLinkCloseReason::Success,
+            1 => MeshLinkCloseReason::Timeout,
+            _ => MeshLinkCloseReason::Fail,
+        }
+    }
+}
+
+/// Returns the Frame Check Sequence of 3GPP TS 27.010 protecting the PB-ADV transactions.
+pub(crate) fn fcs(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0xFF;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x01 != 0 { (crc >> 1) ^ 0xE0 } else { crc >> 1 };
+        }
+    }
+    0xFF - crc
+}
+
+/// Generic Provisioning PDU carried by PB-ADV, after the link ID and the transaction number.
+#[derive(Debug, PartialEq)]
+pub(crate) enum GenericProvisioningPdu {
+    TransactionStart { last_segment: u8, total_length: u16, fcs: u8, data: Vec<u8> },
+    TransactionAck,
+    TransactionContinuation { segment: u8, data: Vec<u8> },
+    LinkOpen(Uuid128Bit),
+    LinkAck,
+    LinkClose(u8),
+}
+
+impl GenericProvisioningPdu {
+    pub(crate) fn parse(bytes: &[u8]) -> Option<GenericProvisioningPdu> {
+        let (first, rest) = bytes.split_first()?;
+        let field = first >> 2;
+        match first & 0b11 {
+            GPCF_TRANSACTION_START => {
+                if rest.len() < 3 {
+                    return None;
+                }
+                Some(GenericProvisioningPdu::TransactionStart {
+                    last_segment: field,
+                    total_length: u16::from_be_bytes([rest[0], rest[1]]),
+                    fcs: rest[2],
+                    data: rest[3..].to_vec(),
+                })
+            }
+            GPCF_TRANSACTION_ACK => Some(GenericProvisioningPdu::TransactionAck),
+            GPCF_TRANSACTION_CONTINUATION => {
+                Some(GenericProvisioningPdu::TransactionContinuation {
+                    segment: field,
+                    data: rest.to_vec(),
+                })
+            }
+            _ => match (field, rest.len()) {
+                (BEARER_OPCODE_LINK_OPEN, 16) => {
+                    let mut uuid = [0u8; 16];
+                    uuid.copy_from_slice(rest);
+                    Some(GenericProvisioningPdu::LinkOpen(uuid))
+                }
+                (BEARER_OPCODE_LINK_ACK, 0) => Some(GenericProvisioningPdu::LinkAck),
+                (BEARER_OPCODE_LINK_CLOSE, 1) => Some(GenericProvisioningPdu::LinkClose(rest[0])),
+                _ => None,
+            },
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        match self {
+            GenericProvisioningPdu::TransactionStart { last_segment, total_length, fcs, data } => {
+                let mut bytes = vec![last_segment << 2 | GPCF_TRANSACTION_START];
+                bytes.extend(&total_length.to_be_bytes());
+                bytes.push(*fcs);
+                bytes.extend(data);
+                bytes
+            }
+            GenericProvisioningPdu::TransactionAck => vec![GPCF_TRANSACTION_ACK],
+            GenericProvisioningPdu::TransactionContinuation { segment, data } => {
+                [&[segment << 2 | GPCF_TRANSACTION_CONTINUATION], &data[..]].concat()
+            }
+            GenericProvisioningPdu::LinkOpen(uuid) => {
+                [&[BEARER_OPCODE_LINK_OPEN << 2 | GPCF_BEARER_CONTROL], &uuid[..]].concat()
+            }
+            GenericProvisioningPdu::LinkAck => {
+                vec![BEARER_OPCODE_LINK_ACK << 2 | GPCF_BEARER_CONTROL]
+            }
+            GenericProvisioningPdu::LinkClose(reason) => {
+                vec![BEARER_OPCODE_LINK_CLOSE << 2 | GPCF_BEARER_CONTROL, *reason]
+            }
+        }
+    }
+}
+
+/// Splits a provisioning PDU into the segments of a PB-ADV transaction. Returns None if the PDU
+/// does not fit in a transaction.
+pub(crate) fn segment_transaction(pdu: &[u8]) -> Option<Vec<GenericProvisioningPdu>> {
+    let first_len = MAX_AD_DATA_LEN - PB_ADV_HEADER_LEN - TRANSACTION_START_HEADER_LEN;
+    let continuation_len =
+        MAX_AD_DATA_LEN - PB_ADV_HEADER_LEN - TRANSACTION_CONTINUATION_HEADER_LEN;
+
+    let split = pdu.len().min(first_len);
+    let continuations: Vec<&[u8]> = pdu[split..].chunks(continuation_len).collect();
+    if pdu.is_empty() || continuations.len() >= MAX_TRANSACTION_SEGMENTS {
+        return None;
+    }
+
+    let mut segments = vec![GenericProvisioningPdu::TransactionStart {
+        last_segment: continuations.len() as u8,
+        total_length: pdu.len() as u16,
+        fcs: fcs(pdu),
+        data: pdu[..split].to_vec(),
+    }];
+    segments.extend(continuations.iter().enumerate().map(|(i, data)| {
+        GenericProvisioningPdu::TransactionContinuation {
+            segment: i as u8 + 1,
+            data: data.to_vec(),
+        }
+    }));
+    Some(segments)
+}
+
+/// Reassembles the segments of an incoming PB-ADV transaction, received in any order.
+#[derive(Default)]
+pub(crate) struct TransactionReassembly {
+    transaction: Option<u8>,
+    total_length: usize,
+    fcs: u8,
+    segments: Vec<Option<Vec<u8>>>,
+}
+
+impl TransactionReassembly {
+    /// Adds a segment of `transaction`, dropping the segments of a previous transaction. Returns
+    /// the PDU once all its segments are received and its FCS matches.
+    pub(crate) fn add(
+        &mut self,
+        transaction: u8,
+        segment: &GenericProvisioningPdu,
+    ) -> Option<Vec<u8>> {
+        if self.transaction != Some(transaction) {
+            *self = TransactionReassembly { transaction: Some(transaction), ..Default::default() };
+        }
+
+        let (index, data) = match segment {
+            GenericProvisioningPdu::TransactionStart { last_segment, total_length, fcs, data } => {
+                self.total_length = *total_length as usize;
+                self.fcs = *fcs;
+                self.segments.resize(*last_segment as usize + 1, None);
+                (0, data)
+            }
+            GenericProvisioningPdu::TransactionContinuation { segment, data } => {
+                let index = *segment as usize;
+                if self.segments.len() <= index {
+                    self.segments.resize(index + 1, None);
+                }
+                (index, data)
+            }
+            _ => return None,
+        };
+        self.segments[index] = Some(data.clone());
+
+        // Until the start is received, it is missing from the segments.
+        if self.segments.iter().any(|s| s.is_none()) {
+            return None;
+        }
+        let pdu: Vec<u8> = self.segments.iter().flatten().flatten().cloned().collect();
+        let complete = pdu.len() == self.total_length && fcs(&pdu) == self.fcs;
+        *self = TransactionReassembly::default();
+        if complete {
+            Some(pdu)
+        } else {
+            warn!("Dropping a malformed PB-ADV transaction {}", transaction);
+            None
+        }
+    }
+}
+
+/// Splits a PDU into the proxy PDUs written to a GATT link with `mtu`.
+pub(crate) fn segment_proxy_pdu(pdu_type: MeshPduType, pdu: &[u8], mtu: usize) -> Vec<Vec<u8>> {
+    // The ATT header of the write and the proxy PDU header.
+    let max_len = mtu.saturating_sub(4).max(1);
+    let chunks: Vec<&[u8]> = pdu.chunks(max_len).collect();
+    let message_type = pdu_type as u8;
+    if chunks.len() <= 1 {
+        return vec![[&[SAR_COMPLETE << 6 | message_type], pdu].concat()];
+    }
+
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let sar = match i {
+                0 => SAR_FIRST,
+                _ if i == chunks.len() - 1 => SAR_LAST,
+                _ => SAR_CONTINUATION,
+            };
+            [&[sar << 6 | message_type], *chunk].concat()
+        })
+        .collect()
+}
+
+/// Reassembles the proxy PDUs notified by a GATT link.
+#[derive(Default)]
+pub(crate) struct ProxyReassembly {
+    // Type and data of the PDU being reassembled.
+    pending: Option<(u8, Vec<u8>)>,
+}
+
+impl ProxyReassembly {
+    /// Adds a proxy PDU. Returns the PDU completed by it, if any. A segment out of sequence, or
+    /// making the PDU longer than `MAX_PROXY_PDU_LEN`, drops the PDU being reassembled.
+    pub(crate) fn add(&mut self, segment: &[u8]) -> Option<(MeshPduType, Vec<u8>)> {
+        let (header, data) = segment.split_first()?;
+        let (sar, message_type) = (header >> 6, header & 0x3F);
+
+        let pending_len = self.pending.as_ref().map_or(0, |(_, pdu)| pdu.len());
+        if pending_len + data.len() > MAX_PROXY_PDU_LEN {
+            warn!("Dropping a proxy PDU longer than {} bytes", MAX_PROXY_PDU_LEN);
+            self.pending = None;
+            return None;
+        }
+
+        let complete = match (sar, self.pending.take()) {
+            (SAR_COMPLETE, None) => Some((message_type, data.to_vec())),
+            (SAR_FIRST, None) => {
+                self.pending = Some((message_type, data.to_vec()));
+                None
+            }
+            (SAR_CONTINUATION, Some((pending_type, mut pdu))) if pending_type == message_type => {
+                pdu.extend(data);
+                self.pending = Some((pending_type, pdu));
+                None
+            }
+            (SAR_LAST, Some((pending_type, mut pdu))) if pending_type == message_type => {
+                pdu.extend(data);
+                Some((pending_type, pdu))
+            }
+            _ => {
+                warn!("Dropping a proxy PDU segmented out of sequence");
+                None
+            }
+        };
+
+        let (message_type, pdu) = complete?;
+        match num_traits::FromPrimitive::from_u8(message_type) {
+            Some(pdu_type) => Some((pdu_type, pdu)),
+            None => {
+                warn!("Dropping a proxy PDU of unknown type {}", message_type);
+                None
+            }
+        }
+    }
+}
+
+/// Returns the settings of the scan of the advertising bearer. It is passive, the mesh PDUs being
+/// in the advertising data, and only delivers the raw data parsed by `mesh_ad_structures`.
+pub(crate) fn mesh_scan_settings() -> ScanSettings {
+    ScanSettings {
+        interval: MESH_SCAN_INTERVAL,
+        window: MESH_SCAN_INTERVAL,
+        scan_type: ScanType::Passive,
+        rssi_settings: RSSISettings { low_threshold: 0, high_threshold: 0 },
+        rssi_smoothing_window: 0,
+        allowed_addresses: vec![],
+        denied_addresses: vec![],
+        callback_type: ScanCallbackType::AllMatches,
+        match_lost_timeout_ms: 0,
+        match_sightings: 0,
+        match_sightings_window_ms: 0,
+        priority: ScanPriority::Normal,
+        record_delivery: ScanRecordDelivery::RawOnly,
+        phys: SCAN_PHY_LE_1M,
+        report_delay_ms: 0,
+    }
+}
+
+/// Returns the mesh AD structures of advertising data: their type and data.
+pub(crate) fn mesh_ad_structures(adv_data: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut structures = vec![];
+    let mut rest = adv_data;
+    while let Some((&len, tail)) = rest.split_first() {
+        let len = len as usize;
+        if len == 0 || len > tail.len() {
+            break;
+        }
+        let ad_type = tail[0];
+        if (AD_TYPE_PB_ADV..=AD_TYPE_MESH_BEACON).contains(&ad_type) {
+            structures.push((ad_type, tail[1..len].to_vec()));
+        }
+        rest = &tail[len..];
+    }
+    structures
+}
+
+fn ad_structure(ad_type: u8, data: &[u8]) -> Vec<u8> {
+    [&[data.len() as u8 + 1, ad_type], data].concat()
+}
+
+/// Actions of the mesh bearers dispatched from the event loop, as the events of the GATT client,
+/// of the scanner and of the advertising set are delivered while the GATT object is locked.
+pub enum MeshActions {
+    /// Params: Status, Client ID
+    GattClientRegistered(i32, i32),
+    /// Params: Status, Scanner ID
+    ScannerRegistered(i32, i32),
+    ScanResult(ScanResult),
+    /// Params: Advertiser ID, Status
+    AdvertisingSetStarted(i32, AdvertisingStatus),
+    AdvertisingSlotEnded,
+    /// Params: Address, Connected
+    GattConnectionState(String, bool),
+    /// Params: Address, MTU, Status
+    MtuConfigured(String, i32, i32),
+    /// Params: Address, Services, Status
+    GattSearchComplete(String, Vec<BluetoothGattService>, i32),
+    /// Params: Address, Status, Handle
+    DescriptorWritten(String, i32, i32),
+    /// Params: Address, Status, Handle
+    CharacteristicWritten(String, i32, i32),
+    /// Params: Address, Handle, Value
+    GattNotification(String, i32, Vec<u8>),
+    /// Params: Link ID
+    LinkRetransmit(i32),
+    /// Params: Link ID
+    LinkTimeout(i32),
+}
+
+/// PB-ADV link.
+struct AdvertisingLink {
+    link_id: u32,
+    device_uuid: Uuid128Bit,
+    // Provisioner transaction number of the next PDU.
+    next_transaction: u8,
+    // Transaction sent and not acknowledged yet, with its segments.
+    outgoing: Option<(u8, Vec<Vec<u8>>)>,
+    // PDUs waiting for the outgoing transaction to be acknowledged.
+    queued: VecDeque<Vec<u8>>,
+    incoming: TransactionReassembly,
+    // Device transaction last received, acknowledged again if the device sends it again.
+    last_received: Option<u8>,
+}
+
+impl AdvertisingLink {
+    /// Returns the AD structure carrying a Generic Provisioning PDU on the link.
+    fn ad_structure(&self, transaction: u8, pdu: &GenericProvisioningPdu) -> Vec<u8> {
+        let mut data = self.link_id.to_be_bytes().to_vec();
+        data.push(transaction);
+        data.extend(pdu.encode());
+        ad_structure(AD_TYPE_PB_ADV, &data)
+    }
+}
+
+/// Stage of a GATT link until it opens.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GattLinkState {
+    Connecting,
+    ConfiguringMtu,
+    Discovering,
+    Subscribing,
+    Open,
+}
+
+/// PB-GATT or proxy link.
+struct GattLink {
+    address: BtAddress,
+    bearer: MeshGattBearer,
+    state: GattLinkState,
+    mtu: usize,
+    data_in: Option<i32>,
+    data_out: Option<i32>,
+    // Proxy PDUs to write, the first one being written if `writing`.
+    outgoing: VecDeque<Vec<u8>>,
+    writing: bool,
+    incoming: ProxyReassembly,
+}
+
+enum LinkBearer {
+    Advertising(AdvertisingLink),
+    Gatt(GattLink),
+}
+
+struct Link {
+    id: i32,
+    bearer: LinkBearer,
+    opened: bool,
+    retransmit: Option<JoinHandle<()>>,
+    timeout: Option<JoinHandle<()>>,
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        for task in [self.retransmit.take(), self.timeout.take()].iter().flatten() {
+            task.abort();
+        }
+    }
+}
+
+/// Implementation of the Bluetooth Mesh bearer API.
+pub struct MeshManager {
+    tx: Sender<Message>,
+    gatt: Option<Arc<Mutex<Box<BluetoothGatt>>>>,
+    callbacks: HashMap<u32, Box<dyn IBluetoothMeshCallback + Send>>,
+    client_id: Option<i32>,
+    scanner_id: Option<i32>,
+    scanning: bool,
+    advertising_bearer_started: bool,
+    advertiser_id: Option<i32>,
+    advertiser_starting: bool,
+    advertising_enabled: bool,
+    // AD structures to advertise, in order.
+    advertising_queue: VecDeque<Vec<u8>>,
+    advertising_slot: Option<JoinHandle<()>>,
+    next_link_id: i32,
+    links: HashMap<i32, Link>,
+}
+
+impl MeshManager {
+    pub fn new(tx: Sender<Message>) -> MeshManager {
+        MeshManager {
+            tx,
+            gatt: None,
+            callbacks: HashMap::new(),
+            client_id: None,
+            scanner_id: None,
+            scanning: false,
+            advertising_bearer_started: false,
+            advertiser_id: None,
+            advertiser_starting: false,
+            advertising_enabled: false,
+            advertising_queue: VecDeque::new(),
+            advertising_slot: None,
+            next_link_id: 0,
+            links: HashMap::new(),
+        }
+    }
+
+    /// Registers the GATT client and the scanner of the bearers. Must be called once the
+    /// profiles are initialized.
+    pub fn init(&mut self, gatt: Arc<Mutex<Box<BluetoothGatt>>>) {
+        {
+            let mut gatt = gatt.lock().unwrap();
+            gatt.register_client(
+                BtUuid { uu: MESH_CLIENT_UUID },
+                Box::new(MeshGattCallback { tx: self.tx.clone() }),
+                false,
+            );
+            gatt.register_scanner(Box::new(MeshScannerCallback { tx: self.tx.clone() }));
+        }
+
+        self.gatt = Some(gatt);
+    }
+
+    pub(crate) fn remove_callback(&mut self, id: u32) -> bool {
+        match self.callbacks.get_mut(&id) {
+            Some(callback) => {
+                callback.unregister(id);
+                self.callbacks.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn dispatch_mesh_actions(&mut self, action: MeshActions) {
+        match action {
+            MeshActions::GattClientRegistered(status, client_id) => {
+                if status != GattStatus::Success as i32 {
+                    warn!("Failed to register the mesh GATT client: {}", status);
+                    return;
+                }
+                self.client_id = Some(client_id);
+            }
+            MeshActions::ScannerRegistered(status, scanner_id) => {
+                if status != GattStatus::Success as i32 {
+                    warn!("Failed to register the mesh scanner: {}", status);
+                    return;
+                }
+                self.scanner_id = Some(scanner_id);
+                self.update_scan();
+            }
+            MeshActions::ScanResult(result) => self.on_scan_result(result),
+            MeshActions::AdvertisingSetStarted(advertiser_id, status) => {
+                self.advertiser_starting = false;
+                if status != AdvertisingStatus::Success {
+                    warn!("Failed to start the mesh advertising: {:?}", status);
+                    self.advertising_queue.clear();
+                    return;
+                }
+                // The set starts enabled.
+                self.advertiser_id = Some(advertiser_id);
+                self.advertising_enabled = true;
+                self.advertise_next();
+            }
+            MeshActions::AdvertisingSlotEnded => {
+                self.advertising_slot = None;
+                self.advertise_next();
+            }
+            MeshActions::GattConnectionState(address, connected) => {
+                let id = match self.find_gatt_link(&address) {
+                    Some(id) => id,
+                    None => return,
+                };
+                match (self.gatt_link(id).map(|l| l.state), connected) {
+                    (Some(GattLinkState::Connecting), true) => self.configure_mtu(id),
+                    (Some(GattLinkState::Connecting), false) => {
+                        self.remove_link(id, MeshLinkCloseReason::ConnectionFailed)
+                    }
+                    (Some(_), false) => self.remove_link(id, MeshLinkCloseReason::Disconnected),
+                    _ => (),
+                }
+            }
+            MeshActions::MtuConfigured(address, mtu, status) => {
+                let id = match self.find_gatt_link(&address) {
+                    Some(id) => id,
+                    None => return,
+                };
+                if let Some(link) = self.gatt_link_mut(id) {
+                    if link.state != GattLinkState::ConfiguringMtu {
+                        return;
+                    }
+                    // The PDUs are split to the MTU obtained, so a refused MTU is not fatal.
+                    if status == GattStatus::Success as i32 {
+                        link.mtu = mtu as usize;
+                    }
+                }
+                self.discover_services(id);
+            }
+            MeshActions::GattSearchComplete(address, services, status) => {
+                let id = match self.find_gatt_link(&address) {
+                    Some(id) => id,
+                    None => return,
+                };
+                if self.gatt_link(id).map(|l| l.state) != Some(GattLinkState::Discovering) {
+                    return;
+                }
+                if status != GattStatus::Success as i32 {
+                    warn!("[{}]: Mesh service discovery failed: {}", address, status);
+                    return self.close_gatt_link(id, MeshLinkCloseReason::Fail);
+                }
+                self.subscribe(id, &services);
+            }
+            MeshActions::DescriptorWritten(address, status, _handle) => {
+                let id = match self.find_gatt_link(&address) {
+                    Some(id) => id,
+                    None => return,
+                };
+                if self.gatt_link(id).map(|l| l.state) != Some(GattLinkState::Subscribing) {
+                    return;
+                }
+                if status != GattStatus::Success as i32 {
+                    warn!("[{}]: Mesh subscription failed: {}", address, status);
+                    return self.close_gatt_link(id, MeshLinkCloseReason::Fail);
+                }
+                if let Some(link) = self.gatt_link_mut(id) {
+                    link.state = GattLinkState::Open;
+                }
+                self.link_opened(id);
+            }
+            MeshActions::CharacteristicWritten(address, status, handle) => {
+                let id = match self.find_gatt_link(&address) {
+                    Some(id) => id,
+                    None => return,
+                };
+                if let Some(link) = self.gatt_link_mut(id) {
+                    if !link.writing || link.data_in != Some(handle) {
+                        return;
+                    }
+                    link.writing = false;
+                    link.outgoing.pop_front();
+                }
+                if status != GattStatus::Success as i32 {
+                    warn!("[{}]: Failed to write a mesh PDU: {}", address, status);
+                }
+                self.write_next(id);
+            }
+            MeshActions::GattNotification(address, handle, value) => {
+                let id = match self.find_gatt_link(&address) {
+                    Some(id) => id,
+                    None => return,
+                };
+                let pdu = match self.gatt_link_mut(id) {
+                    Some(link) if link.data_out == Some(handle) => link.incoming.add(&value),
+                    _ => return,
+                };
+                if let Some((pdu_type, pdu)) = pdu {
+                    let bearer = self.gatt_link(id).map(|l| l.bearer);
+                    let expected = (bearer == Some(MeshGattBearer::Provisioning))
+                        == (pdu_type == MeshPduType::ProvisioningPdu);
+                    if !expected {
+                        warn!(
+                            "[{}]: Dropping a {:?} received on a {:?} link",
+                            address, pdu_type, bearer
+                        );
+                        return;
+                    }
+                    for callback in self.callbacks.values() {
+                        callback.on_link_pdu(id, pdu_type, pdu.clone());
+                    }
+                }
+            }
+            MeshActions::LinkRetransmit(id) => {
+                if let Some(link) = self.links.get_mut(&id) {
+                    link.retransmit = None;
+                }
+                self.retransmit(id);
+            }
+            MeshActions::LinkTimeout(id) => {
+                if let Some(link) = self.links.get_mut(&id) {
+                    link.timeout = None;
+                }
+                match self.links.get(&id).map(|l| &l.bearer) {
+                    Some(LinkBearer::Advertising(_)) => {
+                        self.close_advertising_link(id, MeshLinkCloseReason::Timeout)
+                    }
+                    Some(LinkBearer::Gatt(_)) => {
+                        self.close_gatt_link(id, MeshLinkCloseReason::Timeout)
+                    }
+                    None => (),
+                }
+            }
+        }
+    }
+
+    fn gatt_link(&self, id: i32) -> Option<&GattLink> {
+        match self.links.get(&id).map(|l| &l.bearer) {
+            Some(LinkBearer::Gatt(link)) => Some(link),
+            _ => None,
+        }
+    }
+
+    fn gatt_link_mut(&mut self, id: i32) -> Option<&mut GattLink> {
+        match self.links.get_mut(&id).map(|l| &mut l.bearer) {
+            Some(LinkBearer::Gatt(link)) => Some(link),
+            _ => None,
+        }
+    }
+
+    fn advertising_link_mut(&mut self, id: i32) -> Option<&mut AdvertisingLink> {
+        match self.links.get_mut(&id).map(|l| &mut l.bearer) {
+            Some(LinkBearer::Advertising(link)) => Some(link),
+            _ => None,
+        }
+    }
+
+    fn find_gatt_link(&self, address: &str) -> Option<i32> {
+        let address = BtAddress::from_string(address)?;
+        self.links.values().find_map(|link| match &link.bearer {
+            LinkBearer::Gatt(gatt_link) if gatt_link.address == address => Some(link.id),
+            _ => None,
+        })
+    }
+
+    /// Scans while the advertising bearer is started or a PB-ADV link is open.
+    fn update_scan(&mut self) {
+        let needed = self.advertising_bearer_started
+            || self.links.values().any(|l| matches!(l.bearer, LinkBearer::Advertising(_)));
+        let (gatt, scanner_id) = match (&self.gatt, self.scanner_id) {
+            (Some(gatt), Some(scanner_id)) if needed != self.scanning => (gatt, scanner_id),
+            _ => return,
+        };
+
+        let mut gatt = gatt.lock().unwrap();
+        if needed {
+            if let Err(e) = gatt.start_scan(scanner_id, mesh_scan_settings(), vec![]) {
+                warn!("Failed to start the mesh scan: {}", e);
+                return;
+            }
+        } else {
+            gatt.stop_scan(scanner_id);
+        }
+        self.scanning = needed;
+    }
+
+    fn on_scan_result(&mut self, result: ScanResult) {
+        for (ad_type, data) in mesh_ad_structures(&result.adv_data) {
+            let pdu_type = match ad_type {
+                AD_TYPE_PB_ADV => {
+                    self.on_pb_adv_pdu(&data);
+                    continue;
+                }
+                AD_TYPE_MESH_MESSAGE => MeshPduType::NetworkPdu,
+                _ => MeshPduType::MeshBeacon,
+            };
+            if self.advertising_bearer_started {
+                for callback in self.callbacks.values() {
+                    callback.on_advertising_pdu(
+                        result.address.to_string(),
+                        result.rssi,
+                        pdu_type,
+                        data.clone(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Queues an AD structure to advertise during the next free slot.
+    fn advertise(&mut self, ad_structure: Vec<u8>) {
+        self.advertising_queue.push_back(ad_structure);
+        self.advertise_next();
+    }
+
+    /// Advertises the next queued AD structure once the current slot ends, and stops advertising
+    /// once the queue is empty.
+    fn advertise_next(&mut self) {
+        if self.advertising_slot.is_some() || self.advertiser_starting {
+            return;
+        }
+        let gatt = match &self.gatt {
+            Some(gatt) => gatt.clone(),
+            None => return,
+        };
+
+        let advertiser_id = match self.advertiser_id {
+            Some(advertiser_id) => advertiser_id,
+            None if self.advertising_queue.is_empty() => return,
+            None => {
+                let parameters = AdvertisingSetParameters {
+                    connectable: false,
+                    scannable: false,
+                    is_legacy: true,
+                    primary_phy: LePhy::Phy1m,
+                    secondary_phy: LePhy::Phy1m,
+                    interval: ADVERTISING_INTERVAL,
+                    own_address_type: -1,
+                    ..Default::default()
+                };
+                let result = gatt.lock().unwrap().start_advertising_set(
+                    parameters,
+                    AdvertiseData::default(),
+                    AdvertiseData::default(),
+                    0,
+                    0,
+                    Box::new(MeshAdvertisingCallback { tx: self.tx.clone() }),
+                );
+                match result {
+                    Ok(_) => self.advertiser_starting = true,
+                    Err(e) => {
+                        warn!("Failed to start the mesh advertising: {}", e);
+                        self.advertising_queue.clear();
+                    }
+                }
+                return;
+            }
+        };
+
+        let mut gatt = gatt.lock().unwrap();
+        let ad_structure = match self.advertising_queue.pop_front() {
+            Some(ad_structure) => ad_structure,
+            None => {
+                if self.advertising_enabled {
+                    let _ = gatt.enable_advertising_set(advertiser_id, false, 0, 0);
+                    self.advertising_enabled = false;
+                }
+                return;
+            }
+        };
+
+        if let Err(e) = gatt.set_raw_advertising_data(advertiser_id, ad_structure) {
+            warn!("Failed to advertise a mesh PDU: {}", e);
+        }
+        if !self.advertising_enabled {
+            match gatt.enable_advertising_set(advertiser_id, true, 0, 0) {
+                Ok(()) => self.advertising_enabled = true,
+                Err(e) => warn!("Failed to enable the mesh advertising: {}", e),
+            }
+        }
+
+        let tx = self.tx.clone();
+        self.advertising_slot = Some(tokio::spawn(async move {
+            time::sleep(ADVERTISING_SLOT).await;
+            let _ = tx.send(Message::Mesh(MeshActions::AdvertisingSlotEnded)).await;
+        }));
+    }
+
+    fn schedule(&self, delay: Duration, action: fn(i32) -> MeshActions, id: i32) -> JoinHandle<()> {
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            time::sleep(delay).await;
+            let _ = tx.send(Message::Mesh(action(id))).await;
+        })
+    }
+
+    fn arm_timeout(&mut self, id: i32, timeout: Duration) {
+        let task = self.schedule(timeout, MeshActions::LinkTimeout, id);
+        if let Some(link) = self.links.get_mut(&id) {
+            if let Some(previous) = link.timeout.replace(task) {
+                previous.abort();
+            }
+        }
+    }
+
+    fn link_opened(&mut self, id: i32) {
+        if let Some(link) = self.links.get_mut(&id) {
+            link.opened = true;
+            if let Some(timeout) = link.timeout.take() {
+                timeout.abort();
+            }
+        }
+        debug!("Mesh link {} opened", id);
+        for callback in self.callbacks.values() {
+            callback.on_link_opened(id);
+        }
+    }
+
+    /// Forgets a link and reports it closed.
+    fn remove_link(&mut self, id: i32, reason: MeshLinkCloseReason) {
+        if self.links.remove(&id).is_none() {
+            return;
+        }
+        debug!("Mesh link {} closed: {:?}", id, reason);
+        for callback in self.callbacks.values() {
+            callback.on_link_closed(id, reason);
+        }
+        self.update_scan();
+    }
+
+    /// Advertises the Link Open of a PB-ADV link or the unacknowledged transaction, and arms the
+    /// next retransmission.
+    fn retransmit(&mut self, id: i32) {
+        let ad_structures = match self.links.get(&id) {
+            Some(Link { bearer: LinkBearer::Advertising(link), opened, .. }) => {
+                if !opened {
+                    let link_open = GenericProvisioningPdu::LinkOpen(link.device_uuid);
+                    vec![link.ad_structure(0, &link_open)]
+                } else if let Some((transaction, segments)) = &link.outgoing {
+                    segments
+                        .iter()
+                        .filter_map(|s| GenericProvisioningPdu::parse(s))
+                        .map(|s| link.ad_structure(*transaction, &s))
+                        .collect()
+                } else {
+                    return;
+                }
+            }
+            _ => return,
+        };
+
+        for ad_structure in ad_structures {
+            self.advertise(ad_structure);
+        }
+        let task = self.schedule(RETRANSMIT_INTERVAL, MeshActions::LinkRetransmit, id);
+        if let Some(previous) = self.links.get_mut(&id).and_then(|l| l.retransmit.replace(task)) {
+            previous.abort();
+        }
+    }
+
+    /// Starts the transaction of the next queued PDU of a PB-ADV link, if none is outgoing.
+    fn send_next_transaction(&mut self, id: i32) {
+        let link = match self.advertising_link_mut(id) {
+            Some(link) if link.outgoing.is_none() => link,
+            _ => return,
+        };
+        let pdu = match link.queued.pop_front() {
+            Some(pdu) => pdu,
+            None => return,
+        };
+
+        // The PDU length is checked when queued.
+        let segments = segment_transaction(&pdu).unwrap_or_default();
+        let transaction = link.next_transaction;
+        link.next_transaction = (link.next_transaction + 1) % PROVISIONER_TRANSACTIONS;
+        link.outgoing = Some((transaction, segments.iter().map(|s| s.encode()).collect()));
+
+        self.arm_timeout(id, TRANSACTION_TIMEOUT);
+        self.retransmit(id);
+    }
+
+    fn on_pb_adv_pdu(&mut self, data: &[u8]) {
+        if data.len() <= PB_ADV_HEADER_LEN {
+            return;
+        }
+        let link_id = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let transaction = data[4];
+        let id = match self.links.values().find(|l| match &l.bearer {
+            LinkBearer::Advertising(link) => link.link_id == link_id,
+            _ => false,
+        }) {
+            Some(link) => link.id,
+            None => return,
+        };
+        let opened = self.links.get(&id).map_or(false, |l| l.opened);
+
+        match GenericProvisioningPdu::parse(&data[PB_ADV_HEADER_LEN..]) {
+            Some(GenericProvisioningPdu::LinkAck) if !opened => {
+                if let Some(retransmit) = self.links.get_mut(&id).and_then(|l| l.retransmit.take())
+                {
+                    retransmit.abort();
+                }
+                self.link_opened(id);
+                self.send_next_transaction(id);
+            }
+            Some(GenericProvisioningPdu::LinkClose(reason)) => {
+                self.remove_link(id, MeshLinkCloseReason::from_link_close(reason))
+            }
+            Some(GenericProvisioningPdu::TransactionAck) if opened => {
+                let link = self.links.get_mut(&id).unwrap();
+                let acknowledged = match &link.bearer {
+                    LinkBearer::Advertising(l) => {
+                        l.outgoing.as_ref().map(|(t, _)| *t) == Some(transaction)
+                    }
+                    _ => false,
+                };
+                if !acknowledged {
+                    return;
+                }
+                for task in [link.retransmit.take(), link.timeout.take()].iter().flatten() {
+                    task.abort();
+                }
+                if let Some(link) = self.advertising_link_mut(id) {
+                    link.outgoing = None;
+                }
+                self.send_next_transaction(id);
+            }
+            Some(segment @ GenericProvisioningPdu::TransactionStart { .. })
+            | Some(segment @ GenericProvisioningPdu::TransactionContinuation { .. })
+                if opened && transaction >= PROVISIONER_TRANSACTIONS =>
+            {
+                let link = self.advertising_link_mut(id).unwrap();
+                let pdu = if link.last_received == Some(transaction) {
+                    // The device did not receive the acknowledgement.
+                    None
+                } else {
+                    match link.incoming.add(transaction, &segment) {
+                        Some(pdu) => Some(pdu),
+                        None => return,
+                    }
+                };
+                link.last_received = Some(transaction);
+                let ack = link.ad_structure(transaction, &GenericProvisioningPdu::TransactionAck);
+                self.advertise(ack);
+
+                if let Some(pdu) = pdu {
+                    for callback in self.callbacks.values() {
+                        callback.on_link_pdu(id, MeshPduType::ProvisioningPdu, pdu.clone());
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn close_advertising_link(&mut self, id: i32, reason: MeshLinkCloseReason) {
+        let link_close = match self.links.get(&id).map(|l| &l.bearer) {
+            Some(LinkBearer::Advertising(link)) => {
+                // Only the reasons of the Link Close are sent.
+                let reason = (reason as u32).min(MeshLinkCloseReason::Fail as u32) as u8;
+                link.ad_structure(0, &GenericProvisioningPdu::LinkClose(reason))
+            }
+            _ => return,
+        };
+        for _ in 0..LINK_CLOSE_REPEAT {
+            self.advertise(link_close.clone());
+        }
+        self.remove_link(id, reason);
+    }
+
+    fn close_gatt_link(&mut self, id: i32, reason: MeshLinkCloseReason) {
+        let address = match self.gatt_link(id) {
+            Some(link) => link.address,
+            None => return,
+        };
+        if let (Some(gatt), Some(client_id)) = (&self.gatt, self.client_id) {
+            let _ = gatt.lock().unwrap().client_disconnect(client_id, address);
+        }
+        self.remove_link(id, reason);
+    }
+
+    fn configure_mtu(&mut self, id: i32) {
+        let address = match self.gatt_link_mut(id) {
+            Some(link) => {
+                link.state = GattLinkState::ConfiguringMtu;
+                link.address
+            }
+            None => return,
+        };
+        let result = match (&self.gatt, self.client_id) {
+            (Some(gatt), Some(client_id)) => {
+                gatt.lock().unwrap().configure_mtu(client_id, address, MESH_GATT_MTU)
+            }
+            _ => return,
+        };
+        if let Err(e) = result {
+            warn!("[{}]: Failed to request the mesh MTU: {}", address, e);
+            self.discover_services(id);
+        }
+    }
+
+    fn discover_services(&mut self, id: i32) {
+        let address = match self.gatt_link_mut(id) {
+            Some(link) => {
+                link.state = GattLinkState::Discovering;
+                link.address
+            }
+            None => return,
+        };
+        let result = match (&self.gatt, self.client_id) {
+            (Some(gatt), Some(client_id)) => {
+                gatt.lock().unwrap().discover_services(client_id, address)
+            }
+            _ => return,
+        };
+        if let Err(e) = result {
+            warn!("[{}]: Failed to discover the mesh services: {}", address, e);
+            self.close_gatt_link(id, MeshLinkCloseReason::Fail);
+        }
+    }
+
+    /// Finds the characteristics of the service of a link and subscribes to its Data Out.
+    fn subscribe(&mut self, id: i32, services: &[BluetoothGattService]) {
+        let link = match self.gatt_link_mut(id) {
+            Some(link) => link,
+            None => return,
+        };
+        let (service_uuid, data_in_uuid, data_out_uuid) = match link.bearer {
+            MeshGattBearer::Provisioning => (
+                uuid16(MESH_PROVISIONING_SERVICE_UUID16),
+                uuid16(MESH_PROVISIONING_DATA_IN_UUID16),
+                uuid16(MESH_PROVISIONING_DATA_OUT_UUID16),
+            ),
+            MeshGattBearer::Proxy => (
+                uuid16(MESH_PROXY_SERVICE_UUID16),
+                uuid16(MESH_PROXY_DATA_IN_UUID16),
+                uuid16(MESH_PROXY_DATA_OUT_UUID16),
+            ),
+        };
+
+        let characteristics = services
+            .iter()
+            .filter(|s| s.uuid == service_uuid)
+            .flat_map(|s| s.characteristics.iter());
+        let mut cccd_handle = None;
+        for characteristic in characteristics {
+            if characteristic.uuid == data_in_uuid {
+                link.data_in = Some(characteristic.instance_id);
+            } else if characteristic.uuid == data_out_uuid {
+                link.data_out = Some(characteristic.instance_id);
+                cccd_handle = characteristic
+                    .descriptors
+                    .iter()
+                    .find(|d| d.uuid == CCCD_UUID)
+                    .map(|d| d.instance_id);
+            }
+        }
+
+        let (data_out, cccd_handle) = match (link.data_in, link.data_out, cccd_handle) {
+            (Some(_), Some(data_out), Some(cccd_handle)) => (data_out, cccd_handle),
+            _ => return self.close_gatt_link(id, MeshLinkCloseReason::ServiceNotFound),
+        };
+        link.state = GattLinkState::Subscribing;
+        let address = link.address;
+
+        let result = match (&self.gatt, self.client_id) {
+            (Some(gatt), Some(client_id)) => {
+                let mut gatt = gatt.lock().unwrap();
+                gatt.register_for_notification(client_id, address, data_out, true).and_then(|_| {
+                    gatt.write_descriptor(
+                        client_id,
+                        address,
+                        cccd_handle,
+                        0,
+                        CCCD_ENABLE_NOTIFICATION.to_vec(),
+                    )
+                })
+            }
+            _ => return,
+        };
+        if let Err(e) = result {
+            warn!("[{}]: Failed to subscribe to the mesh service: {}", address, e);
+            self.close_gatt_link(id, MeshLinkCloseReason::Fail);
+        }
+    }
+
+    /// Writes the next proxy PDU of a GATT link, once the previous one is written.
+    fn write_next(&mut self, id: i32) {
+        let (address, data_in, value) = match self.gatt_link_mut(id) {
+            Some(link) if !link.writing && link.state == GattLinkState::Open => {
+                match (link.data_in, link.outgoing.front()) {
+                    (Some(data_in), Some(value)) => (link.address, data_in, value.clone()),
+                    _ => return,
+                }
+            }
+            _ => return,
+        };
+        let status = match (&self.gatt, self.client_id) {
+            (Some(gatt), Some(client_id)) => gatt.lock().unwrap().write_characteristic(
+                client_id,
+                address,
+                data_in,
+                GattWriteType::WriteNoRsp,
+                0,
+                value,
+            ),
+            _ => return,
+        };
+
+        let link = self.gatt_link_mut(id).unwrap();
+        match status {
+            GattWriteRequestStatus::Success => link.writing = true,
+            _ => {
+                warn!("[{}]: Failed to write a mesh PDU: {:?}", address, status);
+                link.outgoing.pop_front();
+            }
+        }
+    }
+
+    fn open_link(&mut self, bearer: LinkBearer, timeout: Duration) -> i32 {
+        self.next_link_id += 1;
+        let id = self.next_link_id;
+        self.links.insert(id, Link { id, bearer, opened: false, retransmit: None, timeout: None });
+        self.arm_timeout(id, timeout);
+        id
+    }
+}
+
+impl IBluetoothMesh for MeshManager {
+    fn register_mesh_callback(
+        &mut self,
+        mut callback: Box<dyn IBluetoothMeshCallback + Send>,
+    ) -> u32 {
+        let tx = self.tx.clone();
+
+        let id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _result = tx.send(Message::MeshCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.callbacks.insert(id, callback);
+        id
+    }
+
+    fn unregister_mesh_callback(&mut self, callback_id: u32) -> bool {
+        self.remove_callback(callback_id)
+    }
+
+    fn start_advertising_bearer(&mut self) -> BtResult<()> {
+        if self.scanner_id.is_none() {
+            return Err(BtError::new(
+                BtErrorCategory::NotReady,
+                "The mesh scanner is not registered",
+            ));
+        }
+
+        self.advertising_bearer_started = true;
+        self.update_scan();
+        Ok(())
+    }
+
+    fn stop_advertising_bearer(&mut self) {
+        self.advertising_bearer_started = false;
+        self.update_scan();
+    }
+
+    fn send_advertising_pdu(&mut self, pdu_type: MeshPduType, pdu: Vec<u8>) -> BtResult<()> {
+        let ad_type = match pdu_type {
+            MeshPduType::NetworkPdu => AD_TYPE_MESH_MESSAGE,
+            MeshPduType::MeshBeacon => AD_TYPE_MESH_BEACON,
+            _ => {
+                return Err(BtError::invalid_argument(format!(
+                    "{:?} is not carried by the advertising bearer",
+                    pdu_type
+                )))
+            }
+        };
+        if pdu.is_empty() || pdu.len() > MAX_AD_DATA_LEN {
+            return Err(BtError::invalid_argument(format!(
+                "Advertised mesh PDUs hold 1 to {} bytes, not {}",
+                MAX_AD_DATA_LEN,
+                pdu.len()
+            )));
+        }
+
+        self.advertise(ad_structure(ad_type, &pdu));
+        Ok(())
+    }
+
+    fn open_advertising_link(&mut self, device_uuid: Uuid128Bit) -> BtResult<i32> {
+        if self.scanner_id.is_none() {
+            return Err(BtError::new(
+                BtErrorCategory::NotReady,
+                "The mesh scanner is not registered",
+            ));
+        }
+        let mut link_id = [0u8; 4];
+        random_bytes(&mut link_id)
+            .map_err(|e| BtError::new(BtErrorCategory::Failed, e.to_string()))?;
+
+        let id = self.open_link(
+            LinkBearer::Advertising(AdvertisingLink {
+                link_id: u32::from_be_bytes(link_id),
+                device_uuid,
+                next_transaction: 0,
+                outgoing: None,
+                queued: VecDeque::new(),
+                incoming: TransactionReassembly::default(),
+                last_received: None,
+            }),
+            LINK_OPEN_TIMEOUT,
+        );
+        self.update_scan();
+        self.retransmit(id);
+        Ok(id)
+    }
+
+    fn open_gatt_link(&mut self, addr: BtAddress, bearer: MeshGattBearer) -> BtResult<i32> {
+        let (gatt, client_id) = match (&self.gatt, self.client_id) {
+            (Some(gatt), Some(client_id)) => (gatt.clone(), client_id),
+            _ => {
+                return Err(BtError::new(
+                    BtErrorCategory::NotReady,
+                    "The mesh GATT client is not registered",
+                ))
+            }
+        };
+        if let Some(id) = self.find_gatt_link(&addr.to_string()) {
+            return Err(BtError::new(
+                BtErrorCategory::Busy,
+                format!("Mesh link {} is open to {}", id, addr),
+            ));
+        }
+
+        gatt.lock().unwrap().client_connect(
+            client_id,
+            addr,
+            true,
+            BtTransport::Le as i32,
+            false,
+            LePhy::Phy1m as i32,
+        )?;
+        Ok(self.open_link(
+            LinkBearer::Gatt(GattLink {
+                address: addr,
+                bearer,
+                state: GattLinkState::Connecting,
+                mtu: DEFAULT_ATT_MTU,
+                data_in: None,
+                data_out: None,
+                outgoing: VecDeque::new(),
+                writing: false,
+                incoming: ProxyReassembly::default(),
+            }),
+            LINK_OPEN_TIMEOUT,
+        ))
+    }
+
+    fn close_link(&mut self, link_id: i32, reason: MeshLinkCloseReason) -> BtResult<()> {
+        match self.links.get(&link_id).map(|l| &l.bearer) {
+            Some(LinkBearer::Advertising(_)) => self.close_advertising_link(link_id, reason),
+            Some(LinkBearer::Gatt(_)) => self.close_gatt_link(link_id, reason),
+            None => return Err(BtError::not_found(format!("No mesh link {}", link_id))),
+        }
+        Ok(())
+    }
+
+    fn send_link_pdu(&mut self, link_id: i32, pdu_type: MeshPduType, pdu: Vec<u8>) -> BtResult<()> {
+        let link = match self.links.get_mut(&link_id) {
+            Some(link) if link.opened => link,
+            Some(_) => {
+                return Err(BtError::new(
+                    BtErrorCategory::NotReady,
+                    format!("Mesh link {} is not open", link_id),
+                ))
+            }
+            None => return Err(BtError::not_found(format!("No mesh link {}", link_id))),
+        };
+
+        let is_provisioning = match &link.bearer {
+            LinkBearer::Advertising(_) => true,
+            LinkBearer::Gatt(gatt_link) => gatt_link.bearer == MeshGattBearer::Provisioning,
+        };
+        if is_provisioning != (pdu_type == MeshPduType::ProvisioningPdu) {
+            return Err(BtError::invalid_argument(format!(
+                "Mesh link {} does not carry {:?}",
+                link_id, pdu_type
+            )));
+        }
+
+        match &mut link.bearer {
+            LinkBearer::Advertising(adv_link) => {
+                if segment_transaction(&pdu).is_none() {
+                    return Err(BtError::invalid_argument(format!(
+                        "A provisioning PDU of {} bytes does not fit in a transaction",
+                        pdu.len()
+                    )));
+                }
+                adv_link.queued.push_back(pdu);
+                self.send_next_transaction(link_id);
+            }
+            LinkBearer::Gatt(gatt_link) => {
+                let segments = segment_proxy_pdu(pdu_type, &pdu, gatt_link.mtu);
+                gatt_link.outgoing.extend(segments);
+                self.write_next(link_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn send_mesh_action(tx: &Sender<Message>, action: MeshActions) {
+    let tx = tx.clone();
+    topstack::get_runtime().spawn(async move {
+        let _ = tx.send(Message::Mesh(action)).await;
+    });
+}
+
+/// Relays the events of the GATT client of the PB-GATT and proxy links.
+struct MeshGattCallback {
+    tx: Sender<Message>,
+}
+
+impl IBluetoothGattCallback for MeshGattCallback {
+    fn on_client_registered(&self, status: i32, client_id: i32) {
+        send_mesh_action(&self.tx, MeshActions::GattClientRegistered(status, client_id));
+    }
+
+    fn on_client_connection_state(
+        &self,
+        status: i32,
+        _client_id: i32,
+        connected: bool,
+        addr: BtAddress,
+    ) {
+        let connected = connected && status == GattStatus::Success as i32;
+        send_mesh_action(&self.tx, MeshActions::GattConnectionState(addr.to_string(), connected));
+    }
+
+    fn on_phy_update(&self, _addr: BtAddress, _tx_phy: LePhy, _rx_phy: LePhy, _status: GattStatus) {
+    }
+
+    fn on_phy_read(&self, _addr: BtAddress, _tx_phy: LePhy, _rx_phy: LePhy, _status: GattStatus) {}
+
+    fn on_search_complete(
+        &self,
+        addr: BtAddress,
+        services: Vec<BluetoothGattService>,
+        status: i32,
+    ) {
+        send_mesh_action(
+            &self.tx,
+            MeshActions::GattSearchComplete(addr.to_string(), services, status),
+        );
+    }
+
+    fn on_service_read(
+        &self,
+        _addr: BtAddress,
+        _service_uuid: Uuid128Bit,
+        _results: Vec<CharacteristicReadResult>,
+    ) {
+    }
+
+    fn on_conformance_report(&self, _addr: BtAddress, _issues: Vec<ConformanceIssue>) {}
+
+    fn on_get_gatt_db(&self, _addr: BtAddress, _services: Vec<BluetoothGattService>) {}
+
+    fn on_characteristic_read(
+        &self,
+        _addr: BtAddress,
+        _status: i32,
+        _handle: i32,
+        _value: Vec<u8>,
+    ) {
+    }
+
+    fn on_characteristic_write(&self, addr: BtAddress, status: i32, handle: i32) {
+        send_mesh_action(
+            &self.tx,
+            MeshActions::CharacteristicWritten(addr.to_string(), status, handle),
+        );
+    }
+
+    fn on_characteristic_write_progress(
+        &self,
+        _addr: BtAddress,
+        _handle: i32,
+        _bytes_written: i32,
+        _total_bytes: i32,
+    ) {
+    }
+
+    fn on_execute_write(&self, _addr: BtAddress, _status: i32) {}
+
+    fn on_descriptor_read(&self, _addr: BtAddress, _status: i32, _handle: i32, _value: Vec<u8>) {}
+
+    fn on_descriptor_write(&self, addr: BtAddress, status: i32, handle: i32) {
+        send_mesh_action(
+            &self.tx,
+            MeshActions::DescriptorWritten(addr.to_string(), status, handle),
+        );
+    }
+
+    fn on_notify(&self, addr: BtAddress, handle: i32, value: Vec<u8>) {
+        send_mesh_action(&self.tx, MeshActions::GattNotification(addr.to_string(), handle, value));
+    }
+
+    fn on_notify_multiple(&self, addr: BtAddress, values: Vec<GattHandleValue>) {
+        for v in values {
+            self.on_notify(addr, v.handle, v.value);
+        }
+    }
+
+    fn on_notification_queue_overflow(&self, _addr: BtAddress, _handle: i32, _dropped: u32) {}
+
+    fn on_read_remote_rssi(&self, _addr: BtAddress, _rssi: i32, _status: i32) {}
+
+    fn on_rssi_threshold_crossed(&self, _addr: BtAddress, _rssi: i32, _threshold: i32) {}
+
+    fn on_configure_mtu(&self, addr: BtAddress, mtu: i32, status: i32) {
+        send_mesh_action(&self.tx, MeshActions::MtuConfigured(addr.to_string(), mtu, status));
+    }
+
+    fn on_connection_updated(
+        &self,
+        _addr: BtAddress,
+        _interval: i32,
+        _latency: i32,
+        _timeout: i32,
+        _status: i32,
+    ) {
+    }
+
+    fn on_service_changed(&self, _addr: BtAddress) {}
+
+    fn on_notification_pipe_active(&self, _addr: BtAddress, _handle: i32) {}
+
+    fn on_eatt_state_changed(&self, _addr: BtAddress, _count: i32, _mtus: Vec<i32>) {}
+}
+
+impl RPCProxy for MeshGattCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("MeshManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+/// Relays the advertisements received by the scanner of the advertising bearer.
+struct MeshScannerCallback {
+    tx: Sender<Message>,
+}
+
+impl IScannerCallback for MeshScannerCallback {
+    fn on_scanner_registered(&self, status: i32, scanner_id: i32) {
+        send_mesh_action(&self.tx, MeshActions::ScannerRegistered(status, scanner_id));
+    }
+
+    fn on_scan_result(&self, scan_result: ScanResult) {
+        send_mesh_action(&self.tx, MeshActions::ScanResult(scan_result));
+    }
+
+    fn on_scan_result_batch(&self, scan_results: Vec<ScanResult>) {
+        for scan_result in scan_results {
+            self.on_scan_result(scan_result);
+        }
+    }
+
+    fn on_scan_result_lost(&self, _scan_result: ScanResult) {}
+
+    fn on_batch_scan_reports(
+        &self,
+        _scanner_id: i32,
+        _status: i32,
+        _results: Vec<BatchScanResult>,
+    ) {
+    }
+
+    fn on_batch_scan_threshold_crossed(&self, _scanner_id: i32) {}
+
+    fn on_scan_parameters_changed(&self, _scanner_id: i32, _interval: i32, _window: i32) {}
+
+    fn on_scan_duty_cycle_changed(&self, _scanner_id: i32, _requested: i32, _effective: i32) {}
+
+    fn on_manufacturer_data_found(
+        &self,
+        _scanner_id: i32,
+        _subscription_id: u32,
+        _addr: BtAddress,
+        _rssi: i32,
+        _data: Vec<u8>,
+    ) {
+    }
+}
+
+impl RPCProxy for MeshScannerCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("MeshManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+/// Relays the events of the advertising set of the advertising bearer.
+struct MeshAdvertisingCallback {
+    tx: Sender<Message>,
+}
+
+impl IAdvertisingSetCallback for MeshAdvertisingCallback {
+    fn on_advertising_set_started(
+        &self,
+        _reg_id: i32,
+        advertiser_id: i32,
+        _tx_power: i32,
+        status: AdvertisingStatus,
+    ) {
+        send_mesh_action(&self.tx, MeshActions::AdvertisingSetStarted(advertiser_id, status));
+    }
+
+    fn on_own_address_read(&self, _advertiser_id: i32, _address_type: i32, _address: String) {}
+
+    fn on_own_address_changed(&self, _advertiser_id: i32, _address_type: i32, _address: String) {}
+
+    fn on_advertising_set_stopped(&self, _advertiser_id: i32) {}
+
+    fn on_advertising_enabled(
+        &self,
+        _advertiser_id: i32,
+        _enable: bool,
+        _status: AdvertisingStatus,
+    ) {
+    }
+
+    fn on_advertising_set_terminated(
+        &self,
+        _advertiser_id: i32,
+        _reason: AdvertisingTerminationReason,
+    ) {
+    }
+
+    fn on_advertising_data_set(&self, _advertiser_id: i32, _status: AdvertisingStatus) {}
+
+    fn on_scan_response_data_set(&self, _advertiser_id: i32, _status: AdvertisingStatus) {}
+
+    fn on_advertising_parameters_updated(
+        &self,
+        _advertiser_id: i32,
+        _tx_power: i32,
+        _status: AdvertisingStatus,
+    ) {
+    }
+
+    fn on_advertising_set_suspended(&self, _advertiser_id: i32) {}
+
+    fn on_advertising_set_resumed(&self, _advertiser_id: i32) {}
+
+    fn on_advertising_set_restored(
+        &self,
+        _advertiser_id: i32,
+        _tx_power: i32,
+        _status: AdvertisingStatus,
+    ) {
+    }
+
+    fn on_tx_power_changed(&self, _advertiser_id: i32, _tx_power: i32) {}
+}
+
+impl RPCProxy for MeshAdvertisingCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("MeshManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fcs() {
+        // 3GPP TS 27.010, annex B.
+        assert_eq!(0x1C, fcs(&[0x03, 0x3F, 0x01]));
+        // The FCS of a message followed by its FCS is constant.
+        let message = [0x01, 0x02, 0x03, 0x04];
+        assert_eq!(0xCF, 0xFF - fcs(&[&message[..], &[fcs(&message)]].concat()));
+    }
+
+    #[test]
+    fn test_generic_provisioning_pdu() {
+        let pdus = vec![
+            GenericProvisioningPdu::TransactionStart {
+                last_segment: 2,
+                total_length: 50,
+                fcs: 0xA5,
+                data: vec![1, 2, 3],
+            },
+            GenericProvisioningPdu::TransactionAck,
+            GenericProvisioningPdu::TransactionContinuation { segment: 1, data: vec![4, 5] },
+            GenericProvisioningPdu::LinkOpen([7; 16]),
+            GenericProvisioningPdu::LinkAck,
+            GenericProvisioningPdu::LinkClose(2),
+        ];
+        for pdu in pdus {
+            assert_eq!(Some(&pdu), GenericProvisioningPdu::parse(&pdu.encode()).as_ref());
+        }
+
+        let start = GenericProvisioningPdu::TransactionStart {
+            last_segment: 2,
+            total_length: 50,
+            fcs: 0xA5,
+            data: vec![1],
+        };
+        assert_eq!(vec![0x08, 0x00, 0x32, 0xA5, 1], start.encode());
+        assert_eq!(0x03, GenericProvisioningPdu::LinkOpen([0; 16]).encode()[0]);
+        assert_eq!(vec![0x0B, 0x01], GenericProvisioningPdu::LinkClose(1).encode());
+        assert_eq!(None, GenericProvisioningPdu::parse(&[0x03, 1, 2]));
+        assert_eq!(None, GenericProvisioningPdu::parse(&[]));
+    }
+
+    #[test]
+    fn test_transaction() {
+        // The Public Key PDU of the provisioning protocol.
+        let pdu: Vec<u8> = (0..65).collect();
+        let segments = segment_transaction(&pdu).unwrap();
+        assert_eq!(3, segments.len());
+        for segment in segments.iter() {
+            assert!(PB_ADV_HEADER_LEN + segment.encode().len() <= MAX_AD_DATA_LEN);
+        }
+
+        // Received in any order, with a segment of a previous transaction first.
+        let mut reassembly = TransactionReassembly::default();
+        assert_eq!(None, reassembly.add(0x80, &segments[1]));
+        assert_eq!(None, reassembly.add(0x81, &segments[2]));
+        assert_eq!(None, reassembly.add(0x81, &segments[0]));
+        assert_eq!(Some(pdu.clone()), reassembly.add(0x81, &segments[1]));
+
+        // A corrupted segment fails the FCS.
+        let mut corrupted = segment_transaction(&pdu).unwrap();
+        corrupted[1] =
+            GenericProvisioningPdu::TransactionContinuation { segment: 1, data: vec![0; 23] };
+        let mut reassembly = TransactionReassembly::default();
+        for segment in corrupted.iter() {
+            assert_eq!(None, reassembly.add(0x82, segment));
+        }
+
+        assert_eq!(1, segment_transaction(&[1]).unwrap().len());
+        assert!(segment_transaction(&[]).is_none());
+        assert!(segment_transaction(&vec![0; 20 + 63 * 23]).is_some());
+        assert!(segment_transaction(&vec![0; 20 + 63 * 23 + 1]).is_none());
+    }
+
+    #[test]
+    fn test_proxy_pdu() {
+        assert_eq!(
+            vec![vec![0x03, 1, 2, 3]],
+            segment_proxy_pdu(MeshPduType::ProvisioningPdu, &[1, 2, 3], 23)
+        );
+
+        let pdu: Vec<u8> = (0..45).collect();
+        let segments = segment_proxy_pdu(MeshPduType::NetworkPdu, &pdu, 23);
+        assert_eq!(vec![0x40, 0x80, 0xC0], segments.iter().map(|s| s[0]).collect::<Vec<u8>>());
+        assert!(segments.iter().all(|s| s.len() <= 23 - 3));
+
+        let mut reassembly = ProxyReassembly::default();
+        assert_eq!(None, reassembly.add(&segments[0]));
+        assert_eq!(None, reassembly.add(&segments[1]));
+        assert_eq!(Some((MeshPduType::NetworkPdu, pdu)), reassembly.add(&segments[2]));
+
+        // A continuation without a first segment is dropped.
+        assert_eq!(None, reassembly.add(&segments[1]));
+        assert_eq!(Some((MeshPduType::MeshBeacon, vec![9])), reassembly.add(&[0x01, 9]));
+        assert_eq!(None, reassembly.add(&[0x3F, 9]));
+
+        // The PDUs longer than the Public Key PDU are dropped.
+        let pdu: Vec<u8> = (0..MAX_PROXY_PDU_LEN as u8).collect();
+        let segments = segment_proxy_pdu(MeshPduType::ProvisioningPdu, &pdu, 23);
+        for segment in &segments[..segments.len() - 1] {
+            assert_eq!(None, reassembly.add(segment));
+        }
+        assert_eq!(
+            Some((MeshPduType::ProvisioningPdu, pdu)),
+            reassembly.add(&segments[segments.len() - 1])
+        );
+        let segments = segment_proxy_pdu(MeshPduType::ProvisioningPdu, &[0; 80], 23);
+        assert!(segments.iter().all(|segment| reassembly.add(segment).is_none()));
+        assert_eq!(None, reassembly.add(&[&[0x03][..], &[0; MAX_PROXY_PDU_LEN + 1]].concat()));
+        assert_eq!(Some((MeshPduType::MeshBeacon, vec![9])), reassembly.add(&[0x01, 9]));
+    }
+
+    #[test]
+    fn test_mesh_ad_structures() {
+        let adv_data = [
+            [&[0x02, 0x01, 0x06][..], &ad_structure(AD_TYPE_MESH_BEACON, &[0, 1, 2])].concat(),
+            ad_structure(AD_TYPE_MESH_MESSAGE, &[3, 4]),
+            vec![0x05, AD_TYPE_PB_ADV, 5],
+        ]
+        .concat();
+        assert_eq!(
+            vec![(AD_TYPE_MESH_BEACON, vec![0, 1, 2]), (AD_TYPE_MESH_MESSAGE, vec![3, 4])],
+            mesh_ad_structures(&adv_data)
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Counters of the activity of the stack, to debug a fleet of devices without attaching HCI
+//! sniffers. The counters are read with `IBluetoothDebug::get_counters` and logged periodically.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+/// Period of the logging of the counters.
+pub const METRICS_LOG_PERIOD: Duration = Duration::from_secs(30 * 60);
+
+/// Counters by name, such as `gatt.operation.ReadCharacteristic`. The counters only grow, from
+/// the start of the daemon.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    counters: BTreeMap<String, u64>,
+}
+
+impl Metrics {
+    pub(crate) fn increment<T: Into<String>>(&mut self, name: T) {
+        self.add(name, 1);
+    }
+
+    pub(crate) fn add<T: Into<String>>(&mut self, name: T, value: u64) {
+        let counter = self.counters.entry(name.into()).or_insert(0);
+        *counter = counter.saturating_add(value);
+    }
+
+    /// Records a duration in the `<name>.count`, `<name>.total_ms` and `<name>.max_ms` counters.
+    pub(crate) fn record_duration(&mut self, name: &str, duration: Duration) {
+        let ms = duration.as_millis() as u64;
+        self.increment(format!("{}.count", name));
+        self.add(format!("{}.total_ms", name), ms);
+        let max = self.counters.entry(format!("{}.max_ms", name)).or_insert(0);
+        *max = (*max).max(ms);
+    }
+
+    pub(crate) fn counters(&self) -> HashMap<String, u64> {
+        self.counters.iter().map(|(name, value)| (name.clone(), *value)).collect()
+    }
+
+    /// Returns the counters on one line, as `name=value` sorted by name.
+    pub(crate) fn summary(&self) -> String {
+        self.counters
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let mut metrics = Metrics::default();
+        metrics.increment("scan.session");
+        metrics.increment("gatt.connection");
+        metrics.increment("scan.session");
+        metrics.add("gatt.connection", u64::MAX);
+
+        let counters = metrics.counters();
+        assert_eq!(counters.get("scan.session"), Some(&2));
+        assert_eq!(counters.get("gatt.connection"), Some(&u64::MAX));
+        assert_eq!(metrics.summary(), format!("gatt.connection={} scan.session=2", u64::MAX));
+    }
+
+    #[test]
+    fn test_record_duration() {
+        let mut metrics = Metrics::default();
+        metrics.record_duration("adv.set_lifetime", Duration::from_millis(300));
+        metrics.record_duration("adv.set_lifetime", Duration::from_millis(100));
+
+        let counters = metrics.counters();
+        assert_eq!(counters.get("adv.set_lifetime.count"), Some(&2));
+        assert_eq!(counters.get("adv.set_lifetime.total_ms"), Some(&400));
+        assert_eq!(counters.get("adv.set_lifetime.max_ms"), Some(&300));
+    }
+}
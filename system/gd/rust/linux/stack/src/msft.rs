@@ -0,0 +1,217 @@
+//! Microsoft HCI extension (MSFT) support, used to offload scan filters to the controllers
+//! implementing it instead of APCF.
+//!
+//! The extension monitors advertisements with vendor commands whose opcode depends on the
+//! manufacturer of the controller. A monitor matches on a single kind of condition and its
+//! patterns are alternatives, so only the scan filters setting a single condition can be
+//! offloaded this way.
+
+// Subcommands of the extension.
+pub(crate) const MSFT_READ_SUPPORTED_FEATURES: u8 = 0x00;
+pub(crate) const MSFT_LE_MONITOR_ADVERTISEMENT: u8 = 0x03;
+pub(crate) const MSFT_LE_CANCEL_MONITOR_ADVERTISEMENT: u8 = 0x04;
+pub(crate) const MSFT_LE_SET_ADVERTISEMENT_FILTER_ENABLE: u8 = 0x05;
+
+// Supported features bit of the LE advertisement monitoring.
+const MSFT_FEATURE_LE_ADV_MONITOR: u64 = 1 << 3;
+
+// Condition types of LE Monitor Advertisement.
+const CONDITION_PATTERNS: u8 = 0x01;
+const CONDITION_UUID: u8 = 0x02;
+const CONDITION_ADDRESS: u8 = 0x04;
+
+// UUID types of the UUID condition.
+const UUID_TYPE_16: u8 = 0x01;
+const UUID_TYPE_32: u8 = 0x02;
+const UUID_TYPE_128: u8 = 0x03;
+
+// RSSI thresholds accepted by the controller, in dBm.
+const RSSI_THRESHOLD_MIN: i32 = -127;
+const RSSI_THRESHOLD_MAX: i32 = 20;
+
+// Seconds under the low threshold before an advertiser is considered lost.
+const RSSI_LOW_INTERVAL: u8 = 60;
+// Report every advertisement received.
+const RSSI_SAMPLING_PERIOD: u8 = 0;
+
+// Longest pattern, which must fit in a legacy advertisement after its AD structure header.
+const PATTERN_LEN_MAX: usize = 29;
+
+/// Returns the vendor opcode of the extension on controllers from `manufacturer`, the company
+/// identifier read from the controller version.
+pub(crate) fn opcode_for_manufacturer(manufacturer: u16) -> Option<u16> {
+    match manufacturer {
+        0x0002 => Some(0xFC1E), // Intel
+        0x001D => Some(0xFD70), // Qualcomm
+        0x0046 => Some(0xFD30), // MediaTek
+        0x005D => Some(0xFCF0), // Realtek
+        _ => None,
+    }
+}
+
+/// Returns the parameters of Read Supported Features.
+pub(crate) fn read_supported_features() -> Vec<u8> {
+    vec![MSFT_READ_SUPPORTED_FEATURES]
+}
+
+/// Returns whether the return parameters of a successful Read Supported Features report the LE
+/// advertisement monitoring.
+pub(crate) fn supports_le_monitor(return_params: &[u8]) -> bool {
+    // Status, subcommand then the 8 bytes of features.
+    if return_params.len() < 10 || return_params[0] != 0 {
+        return false;
+    }
+
+    let mut features = [0; 8];
+    features.copy_from_slice(&return_params[2..10]);
+    u64::from_le_bytes(features) & MSFT_FEATURE_LE_ADV_MONITOR != 0
+}
+
+/// Returns the subcommand and whether the command succeeded from its return parameters.
+pub(crate) fn command_result(return_params: &[u8]) -> Option<(u8, bool)> {
+    match return_params {
+        [status, subcommand, ..] => Some((*subcommand, *status == 0)),
+        _ => None,
+    }
+}
+
+/// Returns the monitor handle from the return parameters of a successful LE Monitor
+/// Advertisement.
+pub(crate) fn monitor_handle(return_params: &[u8]) -> Option<u8> {
+    match return_params {
+        [0, MSFT_LE_MONITOR_ADVERTISEMENT, handle, ..] => Some(*handle),
+        _ => None,
+    }
+}
+
+/// Condition of an advertisement monitor.
+#[derive(Debug, PartialEq)]
+pub(crate) enum MonitorCondition {
+    /// Any of the AD structures of the given types starting with the given bytes.
+    Patterns(Vec<(u8, Vec<u8>)>),
+    /// A service UUID, in its shortest little-endian form.
+    Uuid(Vec<u8>),
+    /// The address of the advertiser with its type.
+    Address(u8, [u8; 6]),
+}
+
+impl MonitorCondition {
+    /// Returns the condition type and condition, or None if they do not fit in the command.
+    fn to_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            MonitorCondition::Patterns(patterns) => {
+                let mut bytes = vec![CONDITION_PATTERNS, patterns.len() as u8];
+                for (ad_type, prefix) in patterns {
+                    if prefix.len() > PATTERN_LEN_MAX {
+                        return None;
+                    }
+
+                    // The length covers the AD type and start byte as well.
+                    bytes.extend(&[prefix.len() as u8 + 2, *ad_type, 0]);
+                    bytes.extend(prefix);
+                }
+                Some(bytes)
+            }
+            MonitorCondition::Uuid(uuid) => {
+                let uuid_type = match uuid.len() {
+                    2 => UUID_TYPE_16,
+                    4 => UUID_TYPE_32,
+                    16 => UUID_TYPE_128,
+                    _ => return None,
+                };
+                let mut bytes = vec![CONDITION_UUID, uuid_type];
+                bytes.extend(uuid);
+                Some(bytes)
+            }
+            MonitorCondition::Address(addr_type, address) => {
+                let mut bytes = vec![CONDITION_ADDRESS, *addr_type];
+                bytes.extend(address.iter().rev());
+                Some(bytes)
+            }
+        }
+    }
+}
+
+/// Returns the parameters of LE Monitor Advertisement, or None if the condition cannot be
+/// monitored. Advertisers are reported once their RSSI reaches `rssi_high` and lost after
+/// staying under `rssi_low`.
+pub(crate) fn monitor_advertisement(
+    rssi_high: i32,
+    rssi_low: i32,
+    condition: &MonitorCondition,
+) -> Option<Vec<u8>> {
+    let clamp_rssi = |rssi: i32| rssi.clamp(RSSI_THRESHOLD_MIN, RSSI_THRESHOLD_MAX) as i8 as u8;
+
+    let mut params = vec![
+        MSFT_LE_MONITOR_ADVERTISEMENT,
+        clamp_rssi(rssi_high),
+        clamp_rssi(rssi_low.min(rssi_high)),
+        RSSI_LOW_INTERVAL,
+        RSSI_SAMPLING_PERIOD,
+    ];
+    params.extend(condition.to_bytes()?);
+    Some(params)
+}
+
+/// Returns the parameters of LE Cancel Monitor Advertisement.
+pub(crate) fn cancel_monitor_advertisement(handle: u8) -> Vec<u8> {
+    vec![MSFT_LE_CANCEL_MONITOR_ADVERTISEMENT, handle]
+}
+
+/// Returns the parameters of LE Set Advertisement Filter Enable. While enabled, only the
+/// advertisements matching a monitor are reported.
+pub(crate) fn set_advertisement_filter_enable(enable: bool) -> Vec<u8> {
+    vec![MSFT_LE_SET_ADVERTISEMENT_FILTER_ENABLE, enable as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supported_features() {
+        assert!(supports_le_monitor(&[0, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(!supports_le_monitor(&[0, 0, 0x07, 0, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(!supports_le_monitor(&[0x01, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(!supports_le_monitor(&[0, 0]));
+    }
+
+    #[test]
+    fn test_monitor_handle() {
+        assert_eq!(Some(7), monitor_handle(&[0, MSFT_LE_MONITOR_ADVERTISEMENT, 7]));
+        assert_eq!(None, monitor_handle(&[0x07, MSFT_LE_MONITOR_ADVERTISEMENT, 7]));
+        assert_eq!(None, monitor_handle(&[0, MSFT_LE_CANCEL_MONITOR_ADVERTISEMENT]));
+    }
+
+    #[test]
+    fn test_monitor_advertisement() {
+        let address = MonitorCondition::Address(1, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            Some(vec![0x03, (-60i8) as u8, (-127i8) as u8, 60, 0, 0x04, 1, 6, 5, 4, 3, 2, 1]),
+            monitor_advertisement(-60, -200, &address)
+        );
+
+        // The low threshold cannot exceed the high one.
+        let params = monitor_advertisement(-80, -40, &address).unwrap();
+        assert_eq!(params[1], params[2]);
+
+        let uuid = MonitorCondition::Uuid(vec![0x0F, 0x18]);
+        assert_eq!(&[0x02, 0x01, 0x0F, 0x18], &monitor_advertisement(0, 0, &uuid).unwrap()[5..]);
+
+        let patterns =
+            MonitorCondition::Patterns(vec![(0x09, b"ab".to_vec()), (0x08, b"ab".to_vec())]);
+        assert_eq!(
+            &[0x01, 2, 4, 0x09, 0, b'a', b'b', 4, 0x08, 0, b'a', b'b'],
+            &monitor_advertisement(0, 0, &patterns).unwrap()[5..]
+        );
+    }
+
+    #[test]
+    fn test_monitor_advertisement_too_long() {
+        let patterns = MonitorCondition::Patterns(vec![(0x09, vec![0; PATTERN_LEN_MAX + 1])]);
+        assert_eq!(None, monitor_advertisement(0, 0, &patterns));
+
+        let uuid = MonitorCondition::Uuid(vec![0; 3]);
+        assert_eq!(None, monitor_advertisement(0, 0, &uuid));
+    }
+}
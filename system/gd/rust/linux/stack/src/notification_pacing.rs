@@ -0,0 +1,94 @@
+//! Pacing of the notifications and indications sent by the servers, see
+//! `IBluetoothGatt::send_notification`. Each link is paced on its own: while a link is congested,
+//! its notifications wait in a queue of the connection and are sent one at a time once it clears,
+//! without holding back the notifications to the other devices.
+
+use crate::bluetooth_gatt::NotificationDropPolicy;
+
+use std::collections::VecDeque;
+
+/// Most notifications waiting for a congested link to clear, per connection.
+pub(crate) const MAX_PENDING_NOTIFICATIONS: usize = 64;
+
+/// Notification or indication waiting to be sent.
+pub(crate) struct PendingNotification {
+    pub handle: i32,
+    pub confirm: bool,
+    pub value: Vec<u8>,
+}
+
+/// Notifications of a server connection waiting for the link to be uncongested.
+#[derive(Default)]
+pub(crate) struct PendingNotifications {
+    queue: VecDeque<PendingNotification>,
+}
+
+impl PendingNotifications {
+    pub(crate) fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Queues a notification according to the drop policy of its characteristic. Returns false
+    /// if the queue is full.
+    pub(crate) fn push(
+        &mut self,
+        notification: PendingNotification,
+        policy: NotificationDropPolicy,
+    ) -> bool {
+        if policy == NotificationDropPolicy::LatestWins {
+            self.queue.retain(|n| n.handle != notification.handle);
+        }
+
+        if self.queue.len() >= MAX_PENDING_NOTIFICATIONS {
+            return false;
+        }
+
+        self.queue.push_back(notification);
+        true
+    }
+
+    /// Takes the next notification to send.
+    pub(crate) fn pop(&mut self) -> Option<PendingNotification> {
+        self.queue.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_notifications() {
+        let notification = |handle: i32, value: u8| PendingNotification {
+            handle,
+            confirm: false,
+            value: vec![value],
+        };
+        let mut pending = PendingNotifications::default();
+
+        assert!(pending.push(notification(10, 1), NotificationDropPolicy::Queue));
+        assert!(pending.push(notification(10, 2), NotificationDropPolicy::Queue));
+        assert_eq!(2, pending.len());
+
+        // The latest value replaces all the queued ones and is queued last.
+        assert!(pending.push(notification(20, 1), NotificationDropPolicy::LatestWins));
+        assert!(pending.push(notification(10, 3), NotificationDropPolicy::LatestWins));
+        assert!(pending.push(notification(20, 2), NotificationDropPolicy::LatestWins));
+        let queued: Vec<(i32, Vec<u8>)> =
+            pending.queue.iter().map(|n| (n.handle, n.value.clone())).collect();
+        assert_eq!(vec![(10, vec![3]), (20, vec![2])], queued);
+
+        while pending.len() < MAX_PENDING_NOTIFICATIONS {
+            assert!(pending.push(notification(30, 0), NotificationDropPolicy::Queue));
+        }
+        assert!(!pending.push(notification(30, 0), NotificationDropPolicy::Queue));
+        assert!(pending.push(notification(20, 3), NotificationDropPolicy::LatestWins));
+
+        assert_eq!(Some(10), pending.pop().map(|n| n.handle));
+        assert!(!pending.is_empty());
+    }
+}
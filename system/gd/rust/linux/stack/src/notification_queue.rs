@@ -0,0 +1,125 @@
+//! Queues of the notifications and indications received for the clients, see
+//! `IBluetoothGatt::set_notification_queue`. A client consuming `on_notify` slowly sets a queue so
+//! that a flooding peripheral does not overwhelm it: the values are delivered in batches, the
+//! oldest values are dropped when the queue is full, and the client is told how many were lost.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+/// Deepest queue a client may set.
+pub(crate) const MAX_NOTIFICATION_QUEUE_DEPTH: u32 = 1024;
+
+/// Wait between the first value queued and the delivery of the queue, during which values of the
+/// same handle may be coalesced.
+pub(crate) const NOTIFICATION_QUEUE_DELAY: Duration = Duration::from_millis(20);
+
+/// Notification queue settings of a client.
+#[derive(Clone, Debug, Default)]
+pub struct NotificationQueueConfig {
+    /// Most values waiting for their delivery. 0 disables the queue, each value being delivered
+    /// as it is received.
+    pub depth: u32,
+    /// Whether a value replaces the one of the same device and handle still waiting for its
+    /// delivery, instead of being queued after it.
+    pub coalesce: bool,
+}
+
+/// Value waiting for its delivery.
+#[derive(Debug, PartialEq)]
+pub(crate) struct QueuedNotification {
+    pub address: String,
+    pub handle: i32,
+    pub value: Vec<u8>,
+}
+
+/// Notifications waiting for their delivery to a client.
+pub(crate) struct NotificationQueue {
+    config: NotificationQueueConfig,
+    queued: VecDeque<QueuedNotification>,
+    // Values dropped since the last delivery, by device and handle.
+    dropped: BTreeMap<(String, i32), u32>,
+}
+
+impl NotificationQueue {
+    pub(crate) fn new(config: NotificationQueueConfig) -> Self {
+        NotificationQueue { config, queued: VecDeque::new(), dropped: BTreeMap::new() }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queued.is_empty() && self.dropped.is_empty()
+    }
+
+    /// Queues a value, dropping the oldest one if the queue is full.
+    pub(crate) fn push(&mut self, address: String, handle: i32, value: Vec<u8>) {
+        if self.config.coalesce {
+            if let Some(queued) =
+                self.queued.iter_mut().find(|n| n.handle == handle && n.address == address)
+            {
+                queued.value = value;
+                return;
+            }
+        }
+
+        if self.queued.len() >= self.config.depth as usize {
+            if let Some(oldest) = self.queued.pop_front() {
+                *self.dropped.entry((oldest.address, oldest.handle)).or_insert(0) += 1;
+            }
+        }
+        self.queued.push_back(QueuedNotification { address, handle, value });
+    }
+
+    /// Empties the queue. Returns the number of values dropped by device and handle, and the
+    /// values to deliver in the order they were received.
+    pub(crate) fn take(&mut self) -> (BTreeMap<(String, i32), u32>, Vec<QueuedNotification>) {
+        (std::mem::take(&mut self.dropped), self.queued.drain(..).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address() -> String {
+        String::from("11:22:33:44:55:66")
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut queue =
+            NotificationQueue::new(NotificationQueueConfig { depth: 2, coalesce: false });
+        assert!(queue.is_empty());
+        queue.push(address(), 1, vec![1]);
+        queue.push(address(), 1, vec![2]);
+        queue.push(address(), 2, vec![3]);
+        assert!(!queue.is_empty());
+
+        let (dropped, queued) = queue.take();
+        assert_eq!(dropped.get(&(address(), 1)), Some(&1));
+        assert_eq!(queued.iter().map(|n| n.value[0]).collect::<Vec<u8>>(), vec![2, 3]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let mut queue =
+            NotificationQueue::new(NotificationQueueConfig { depth: 2, coalesce: true });
+        queue.push(address(), 1, vec![1]);
+        queue.push(address(), 2, vec![2]);
+        queue.push(address(), 1, vec![3]);
+        queue.push(String::from("AA:BB:CC:DD:EE:FF"), 1, vec![4]);
+
+        let (dropped, queued) = queue.take();
+        assert_eq!(dropped.get(&(address(), 1)), Some(&1));
+        assert_eq!(
+            queued,
+            vec![
+                QueuedNotification { address: address(), handle: 2, value: vec![2] },
+                QueuedNotification {
+                    address: String::from("AA:BB:CC:DD:EE:FF"),
+                    handle: 1,
+                    value: vec![4]
+                },
+            ]
+        );
+    }
+}
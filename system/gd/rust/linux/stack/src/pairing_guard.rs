@@ -0,0 +1,223 @@
+//! Rate limiting of the pairing attempts initiated by remote devices, against pairing spam.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Window in which the pairing attempts are counted.
+const ATTEMPT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Maximum number of pairing attempts from a single device within `ATTEMPT_WINDOW`.
+const MAX_ATTEMPTS_PER_DEVICE: usize = 3;
+
+/// Maximum number of pairing attempts from all devices within `ATTEMPT_WINDOW`.
+const MAX_ATTEMPTS_GLOBAL: usize = 10;
+
+/// Duration of the first lockout. Each consecutive lockout doubles it, up to `MAX_LOCKOUT`.
+const INITIAL_LOCKOUT: Duration = Duration::from_secs(30);
+
+const MAX_LOCKOUT: Duration = Duration::from_secs(3600);
+
+/// Maximum number of devices with a tracked history. Devices with no recent attempt are dropped
+/// first.
+const MAX_TRACKED_DEVICES: usize = 256;
+
+/// Outcome of `PairingRateLimiter::check`.
+#[derive(Debug, PartialEq)]
+pub(crate) enum PairingDecision {
+    Allowed,
+    Rejected {
+        /// Remaining time of the lockout.
+        lockout: Duration,
+        /// Whether the lockout has just started.
+        new_lockout: bool,
+        /// Whether the lockout applies to all devices rather than to a single one.
+        global: bool,
+    },
+}
+
+/// Pairing attempts of a device, or of all devices.
+struct AttemptHistory {
+    attempts: VecDeque<Instant>,
+    locked_until: Option<Instant>,
+    // Number of consecutive lockouts, which sets the duration of the next one.
+    lockouts: u32,
+}
+
+impl AttemptHistory {
+    fn new() -> AttemptHistory {
+        AttemptHistory { attempts: VecDeque::new(), locked_until: None, lockouts: 0 }
+    }
+
+    fn last_activity(&self) -> Option<Instant> {
+        self.locked_until.max(self.attempts.back().cloned())
+    }
+
+    /// Records an attempt at `now`. Returns the remaining lockout and whether it has just started
+    /// if the attempt is rejected.
+    fn check(&mut self, now: Instant, max_attempts: usize) -> Option<(Duration, bool)> {
+        if let Some(locked_until) = self.locked_until {
+            if locked_until > now {
+                return Some((locked_until - now, false));
+            }
+
+            // Forget about the previous lockouts once the device has been quiet long enough.
+            if now - locked_until >= MAX_LOCKOUT {
+                self.lockouts = 0;
+            }
+            self.locked_until = None;
+        }
+
+        while self.attempts.front().map_or(false, |t| now - *t >= ATTEMPT_WINDOW) {
+            self.attempts.pop_front();
+        }
+
+        if self.attempts.len() < max_attempts {
+            self.attempts.push_back(now);
+            return None;
+        }
+
+        let lockout = INITIAL_LOCKOUT
+            .checked_mul(1 << self.lockouts.min(16))
+            .map_or(MAX_LOCKOUT, |d| d.min(MAX_LOCKOUT));
+        self.lockouts += 1;
+        self.locked_until = Some(now + lockout);
+        self.attempts.clear();
+        Some((lockout, true))
+    }
+}
+
+/// Limits the rate of incoming pairing attempts, per remote device and globally. Exceeding a
+/// limit locks the pairing out for a duration that grows exponentially with repeated abuse.
+pub(crate) struct PairingRateLimiter {
+    devices: HashMap<String, AttemptHistory>,
+    global: AttemptHistory,
+}
+
+impl PairingRateLimiter {
+    pub(crate) fn new() -> PairingRateLimiter {
+        PairingRateLimiter { devices: HashMap::new(), global: AttemptHistory::new() }
+    }
+
+    /// Records a pairing attempt initiated by `address` and decides whether it is allowed.
+    pub(crate) fn check(&mut self, address: &String) -> PairingDecision {
+        self.check_at(address, Instant::now())
+    }
+
+    fn check_at(&mut self, address: &String, now: Instant) -> PairingDecision {
+        if let Some(locked_until) = self.global.locked_until {
+            if locked_until > now {
+                return PairingDecision::Rejected {
+                    lockout: locked_until - now,
+                    new_lockout: false,
+                    global: true,
+                };
+            }
+        }
+
+        if !self.devices.contains_key(address) && self.devices.len() >= MAX_TRACKED_DEVICES {
+            let oldest =
+                self.devices.iter().min_by_key(|(_, h)| h.last_activity()).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.devices.remove(&oldest);
+            }
+        }
+
+        let device = self.devices.entry(address.clone()).or_insert_with(AttemptHistory::new);
+        if let Some((lockout, new_lockout)) = device.check(now, MAX_ATTEMPTS_PER_DEVICE) {
+            return PairingDecision::Rejected { lockout, new_lockout, global: false };
+        }
+
+        match self.global.check(now, MAX_ATTEMPTS_GLOBAL) {
+            Some((lockout, new_lockout)) => {
+                PairingDecision::Rejected { lockout, new_lockout, global: true }
+            }
+            None => PairingDecision::Allowed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_lockout_is_exponential() {
+        let peer = String::from("AA:BB:CC:DD:EE:FF");
+        let mut limiter = PairingRateLimiter::new();
+        let start = Instant::now();
+
+        for i in 0..MAX_ATTEMPTS_PER_DEVICE {
+            let now = start + Duration::from_secs(i as u64);
+            assert_eq!(PairingDecision::Allowed, limiter.check_at(&peer, now));
+        }
+
+        let now = start + Duration::from_secs(10);
+        assert_eq!(
+            PairingDecision::Rejected {
+                lockout: INITIAL_LOCKOUT,
+                new_lockout: true,
+                global: false
+            },
+            limiter.check_at(&peer, now)
+        );
+        assert_eq!(
+            PairingDecision::Rejected {
+                lockout: INITIAL_LOCKOUT - Duration::from_secs(10),
+                new_lockout: false,
+                global: false
+            },
+            limiter.check_at(&peer, now + Duration::from_secs(10))
+        );
+
+        // Other devices are not affected.
+        assert_eq!(PairingDecision::Allowed, limiter.check_at(&String::from("peer2"), now));
+
+        // The next lockout lasts twice as long.
+        let now = now + INITIAL_LOCKOUT;
+        for _ in 0..MAX_ATTEMPTS_PER_DEVICE {
+            assert_eq!(PairingDecision::Allowed, limiter.check_at(&peer, now));
+        }
+        assert_eq!(
+            PairingDecision::Rejected {
+                lockout: INITIAL_LOCKOUT * 2,
+                new_lockout: true,
+                global: false
+            },
+            limiter.check_at(&peer, now)
+        );
+
+        // Attempts outside of the window are forgotten.
+        let now = now + INITIAL_LOCKOUT * 2;
+        for i in 0..MAX_ATTEMPTS_PER_DEVICE * 2 {
+            let now = now + ATTEMPT_WINDOW * i as u32;
+            assert_eq!(PairingDecision::Allowed, limiter.check_at(&peer, now));
+        }
+    }
+
+    #[test]
+    fn test_global_lockout() {
+        let mut limiter = PairingRateLimiter::new();
+        let now = Instant::now();
+
+        for i in 0..MAX_ATTEMPTS_GLOBAL {
+            assert_eq!(PairingDecision::Allowed, limiter.check_at(&format!("peer{}", i), now));
+        }
+
+        assert_eq!(
+            PairingDecision::Rejected { lockout: INITIAL_LOCKOUT, new_lockout: true, global: true },
+            limiter.check_at(&String::from("peer"), now)
+        );
+        assert_eq!(
+            PairingDecision::Rejected {
+                lockout: INITIAL_LOCKOUT,
+                new_lockout: false,
+                global: true
+            },
+            limiter.check_at(&String::from("other"), now)
+        );
+        assert_eq!(
+            PairingDecision::Allowed,
+            limiter.check_at(&String::from("other"), now + INITIAL_LOCKOUT)
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Persistence of the PHY preferences of bonded devices, which are applied again whenever the
+//! devices reconnect.
+
+use log::warn;
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::bluetooth_gatt::LePhy;
+use crate::storage::{save_lines, PUBLIC_FILE_MODE};
+
+/// File holding the preferences, one device per line.
+pub const PHY_PREFERENCES_FILE: &str = "/var/lib/bluetooth/gatt_phy_preferences";
+
+/// PHY preference of a device, as set by `IBluetoothGatt::client_set_preferred_phy`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PhyPreference {
+    pub tx_phy: LePhy,
+    pub rx_phy: LePhy,
+    pub phy_options: i32,
+}
+
+/// Preferences by device address, saved to a file after each change.
+pub(crate) struct PhyPreferenceStore {
+    path: PathBuf,
+    preferences: HashMap<String, PhyPreference>,
+}
+
+impl PhyPreferenceStore {
+    /// Loads the preferences saved in `path`. Malformed lines are skipped.
+    pub(crate) fn load<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
+        let preferences = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().filter_map(parse_line).collect(),
+            Err(_) => HashMap::new(),
+        };
+
+        PhyPreferenceStore { path, preferences }
+    }
+
+    pub(crate) fn get(&self, address: &String) -> Option<PhyPreference> {
+        self.preferences.get(&address.to_uppercase()).copied()
+    }
+
+    pub(crate) fn set(&mut self, address: &String, preference: PhyPreference) {
+        if self.preferences.insert(address.to_uppercase(), preference) != Some(preference) {
+            self.save();
+        }
+    }
+
+    pub(crate) fn remove(&mut self, address: &String) {
+        if self.preferences.remove(&address.to_uppercase()).is_some() {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let mut lines: Vec<String> = self
+            .preferences
+            .iter()
+            .map(|(address, p)| {
+                format!(
+                    "{} {} {} {}",
+                    address,
+                    p.tx_phy.to_u8().unwrap_or_default(),
+                    p.rx_phy.to_u8().unwrap_or_default(),
+                    p.phy_options
+                )
+            })
+            .collect();
+        lines.sort();
+
+        if let Err(e) = save_lines(&self.path, &lines, PUBLIC_FILE_MODE) {
+            warn!("Failed to save the PHY preferences to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Parses a line made of the address, TX PHY, RX PHY and PHY options.
+fn parse_line(line: &str) -> Option<(String, PhyPreference)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 4 {
+        return None;
+    }
+
+    let tx_phy = LePhy::from_u8(fields[1].parse().ok()?)?;
+    let rx_phy = LePhy::from_u8(fields[2].parse().ok()?)?;
+    let phy_options = fields[3].parse().ok()?;
+    Some((fields[0].to_uppercase(), PhyPreference { tx_phy, rx_phy, phy_options }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            Some((
+                String::from("AA:BB:CC:DD:EE:FF"),
+                PhyPreference { tx_phy: LePhy::PhyCoded, rx_phy: LePhy::Phy1m, phy_options: 2 }
+            )),
+            parse_line("aa:bb:cc:dd:ee:ff 3 1 2")
+        );
+        assert_eq!(None, parse_line("AA:BB:CC:DD:EE:FF 3 1"));
+        assert_eq!(None, parse_line("AA:BB:CC:DD:EE:FF 7 1 2"));
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("gatt_phy_preferences_test_{}", std::process::id()));
+        let address = String::from("aa:bb:cc:dd:ee:ff");
+        let preference =
+            PhyPreference { tx_phy: LePhy::PhyCoded, rx_phy: LePhy::PhyCoded, phy_options: 1 };
+
+        let mut store = PhyPreferenceStore::load(&path);
+        assert_eq!(None, store.get(&address));
+        store.set(&address, preference);
+
+        let mut store = PhyPreferenceStore::load(&path);
+        assert_eq!(Some(preference), store.get(&String::from("AA:BB:CC:DD:EE:FF")));
+        store.remove(&address);
+        assert_eq!(None, PhyPreferenceStore::load(&path).get(&address));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
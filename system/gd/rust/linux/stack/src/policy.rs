@@ -0,0 +1,107 @@
+//! Policy of the daemon, read at its start from a file provided with the system image.
+//!
+//! The file holds one `key = value` setting per line, `#` starting a comment:
+//!
+//! ```text
+//! # Production images don't run the factory tests.
+//! disabled_interface = org.chromium.bluetooth.BluetoothQA
+//! # Regulatory limit of the advertising sets.
+//! advertising_min_interval_ms = 200
+//! ```
+//!
+//! A disabled interface is not exported at all, as if the daemon was built without it. The
+//! settings starting with `advertising_` limit the advertising sets, see
+//! `crate::advertising_policy`.
+
+use log::warn;
+use std::path::Path;
+
+use crate::advertising_policy::{AdvertisingPolicy, ADVERTISING_SETTING_PREFIX};
+
+/// File holding the policy.
+pub const POLICY_FILE: &str = "/etc/bluetooth/policy.conf";
+
+/// Settings of the policy file.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    disabled_interfaces: Vec<String>,
+    advertising: AdvertisingPolicy,
+}
+
+impl Policy {
+    /// Loads the policy in `path`, the default one if the file does not exist. Malformed lines
+    /// are skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Policy::parse(&contents),
+            Err(_) => Policy::default(),
+        }
+    }
+
+    fn parse(contents: &str) -> Self {
+        let mut policy = Policy::default();
+
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    warn!("Malformed policy line: {}", line);
+                    continue;
+                }
+            };
+            match key {
+                "disabled_interface" => match value {
+                    "" => warn!("Missing name of the disabled interface"),
+                    _ => policy.disabled_interfaces.push(value.to_string()),
+                },
+                key if key.starts_with(ADVERTISING_SETTING_PREFIX) => {
+                    if let Err(e) = policy.advertising.parse_setting(key, value) {
+                        warn!("{}", e);
+                    }
+                }
+                _ => warn!("Unknown policy setting: {}", key),
+            }
+        }
+
+        policy
+    }
+
+    /// Returns the names of the D-Bus interfaces not to export.
+    pub fn disabled_interfaces(&self) -> &[String] {
+        &self.disabled_interfaces
+    }
+
+    pub(crate) fn advertising(&self) -> &AdvertisingPolicy {
+        &self.advertising
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let policy = Policy::parse(
+            "# Kiosk\n\
+             disabled_interface = org.chromium.bluetooth.BluetoothQA\n\
+             disabled_interface=org.chromium.bluetooth.BluetoothDebug # Production\n\
+             disabled_interface =\n\
+             advertising_min_interval_ms = 200\n\
+             unknown = 1\n\
+             malformed\n",
+        );
+
+        assert_eq!(
+            policy.disabled_interfaces(),
+            ["org.chromium.bluetooth.BluetoothQA", "org.chromium.bluetooth.BluetoothDebug"]
+        );
+        assert!(policy.advertising().is_enabled());
+        assert!(!Policy::default().advertising().is_enabled());
+    }
+}
@@ -0,0 +1,238 @@
+//! Tracking of the local identities exposed to remote devices, for privacy auditing.
+
+use bt_topshim::btif::BtTransport;
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Maximum number of peers kept in the exposure history. The least recently exposed peer is
+/// dropped first.
+const MAX_EXPOSURE_PEERS: usize = 256;
+
+/// Local identity revealed to a peer.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum LocalIdentity {
+    /// The public BD_ADDR of the adapter, revealed by any BR/EDR connection.
+    PublicAddress = 0,
+    /// The local LE address, which is either the public or a random address depending on the
+    /// LE privacy configuration.
+    LeAddress = 1,
+}
+
+impl Default for LocalIdentity {
+    fn default() -> Self {
+        LocalIdentity::PublicAddress
+    }
+}
+
+impl LocalIdentity {
+    /// Returns the identity revealed by a link on the given transport.
+    pub(crate) fn from_transport(transport: &BtTransport) -> LocalIdentity {
+        match transport {
+            BtTransport::Le => LocalIdentity::LeAddress,
+            _ => LocalIdentity::PublicAddress,
+        }
+    }
+}
+
+/// Type of the local address a local identity was exposed with.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum OwnAddressType {
+    Public = 0,
+    RandomStatic = 1,
+    ResolvablePrivate = 2,
+    NonResolvablePrivate = 3,
+}
+
+impl Default for OwnAddressType {
+    fn default() -> Self {
+        OwnAddressType::Public
+    }
+}
+
+impl OwnAddressType {
+    /// Classifies a local LE address from its HCI address type and its two most significant
+    /// bits, see Core 5.3 Vol 6 Part B 1.3.
+    pub(crate) fn from_le_address(address_type: u8, address: &[u8; 6]) -> OwnAddressType {
+        if address_type & 1 == 0 {
+            return OwnAddressType::Public;
+        }
+
+        match address[0] >> 6 {
+            0b11 => OwnAddressType::RandomStatic,
+            0b01 => OwnAddressType::ResolvablePrivate,
+            _ => OwnAddressType::NonResolvablePrivate,
+        }
+    }
+
+    /// Whether the address stays the same across connections and advertisements, and thus
+    /// identifies the adapter.
+    pub fn is_identity(&self) -> bool {
+        matches!(self, OwnAddressType::Public | OwnAddressType::RandomStatic)
+    }
+}
+
+/// LE privacy mode of a bonded device, see `IBluetooth::set_device_privacy_mode`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum PrivacyMode {
+    /// The device is only accepted when using a resolvable private address once its identity
+    /// resolving key is known.
+    Network = 0,
+    /// The device is also accepted when using its identity address, as some devices do after
+    /// bonding.
+    Device = 1,
+}
+
+impl Default for PrivacyMode {
+    fn default() -> Self {
+        PrivacyMode::Device
+    }
+}
+
+/// Reports that a local identity has been exposed to a peer, returned by
+/// `IBluetooth::get_identity_exposure_report`.
+#[derive(Clone, Debug, Default)]
+pub struct IdentityExposure {
+    /// Address of the peer the identity was exposed to, empty if it was broadcast by an
+    /// advertising set.
+    pub peer_address: String,
+    pub identity: LocalIdentity,
+    pub own_address_type: OwnAddressType,
+    /// Time of the first exposure, in milliseconds since the UNIX epoch.
+    pub first_exposed_millis: u64,
+    /// Time of the most recent exposure, in milliseconds since the UNIX epoch.
+    pub last_exposed_millis: u64,
+    /// Number of times the identity was exposed to this peer.
+    pub count: u32,
+}
+
+/// History of local identity exposures, keyed by peer, identity and own address type.
+pub(crate) struct IdentityExposureLog {
+    exposures: HashMap<(String, LocalIdentity, OwnAddressType), IdentityExposure>,
+}
+
+impl IdentityExposureLog {
+    pub(crate) fn new() -> IdentityExposureLog {
+        IdentityExposureLog { exposures: HashMap::new() }
+    }
+
+    /// Records that `identity` has just been exposed to `peer_address` with an own address of
+    /// type `own_address_type`.
+    pub(crate) fn record(
+        &mut self,
+        peer_address: &String,
+        identity: LocalIdentity,
+        own_address_type: OwnAddressType,
+    ) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        self.record_at(peer_address, identity, own_address_type, now);
+    }
+
+    fn record_at(
+        &mut self,
+        peer_address: &String,
+        identity: LocalIdentity,
+        own_address_type: OwnAddressType,
+        now: u64,
+    ) {
+        let key = (peer_address.clone(), identity, own_address_type);
+
+        if !self.exposures.contains_key(&key) && self.exposures.len() >= MAX_EXPOSURE_PEERS {
+            let oldest = self
+                .exposures
+                .iter()
+                .min_by_key(|(_, e)| e.last_exposed_millis)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.exposures.remove(&oldest);
+            }
+        }
+
+        let exposure = self.exposures.entry(key).or_insert(IdentityExposure {
+            peer_address: peer_address.clone(),
+            identity,
+            own_address_type,
+            first_exposed_millis: now,
+            last_exposed_millis: now,
+            count: 0,
+        });
+        exposure.last_exposed_millis = now;
+        exposure.count += 1;
+    }
+
+    /// Returns the exposures sorted from the most recent one.
+    pub(crate) fn report(&self) -> Vec<IdentityExposure> {
+        let mut report: Vec<IdentityExposure> = self.exposures.values().cloned().collect();
+        report.sort_by(|a, b| b.last_exposed_millis.cmp(&a.last_exposed_millis));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_exposure_log() {
+        let peer1 = String::from("aa:bb:cc:dd:ee:ff");
+        let peer2 = String::from("11:22:33:44:55:66");
+        let mut log = IdentityExposureLog::new();
+
+        log.record_at(&peer1, LocalIdentity::PublicAddress, OwnAddressType::Public, 10);
+        log.record_at(&peer1, LocalIdentity::PublicAddress, OwnAddressType::Public, 20);
+        log.record_at(&peer1, LocalIdentity::LeAddress, OwnAddressType::RandomStatic, 30);
+        log.record_at(&peer2, LocalIdentity::LeAddress, OwnAddressType::ResolvablePrivate, 40);
+        log.record_at(&String::new(), LocalIdentity::LeAddress, OwnAddressType::Public, 50);
+
+        let report = log.report();
+        assert_eq!(4, report.len());
+        assert_eq!("", report[0].peer_address);
+        assert_eq!(OwnAddressType::Public, report[0].own_address_type);
+        assert_eq!(peer2, report[1].peer_address);
+        assert_eq!(OwnAddressType::RandomStatic, report[2].own_address_type);
+
+        let public = &report[3];
+        assert_eq!(peer1, public.peer_address);
+        assert_eq!(LocalIdentity::PublicAddress, public.identity);
+        assert_eq!(10, public.first_exposed_millis);
+        assert_eq!(20, public.last_exposed_millis);
+        assert_eq!(2, public.count);
+    }
+
+    #[test]
+    fn test_identity_exposure_log_drops_oldest() {
+        let mut log = IdentityExposureLog::new();
+        for i in 0..MAX_EXPOSURE_PEERS + 1 {
+            log.record_at(
+                &format!("peer{}", i),
+                LocalIdentity::PublicAddress,
+                OwnAddressType::Public,
+                i as u64,
+            );
+        }
+
+        let report = log.report();
+        assert_eq!(MAX_EXPOSURE_PEERS, report.len());
+        assert!(report.iter().all(|e| e.peer_address != "peer0"));
+    }
+
+    #[test]
+    fn test_own_address_type_from_le_address() {
+        let address = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc];
+        assert_eq!(OwnAddressType::Public, OwnAddressType::from_le_address(0, &address));
+        assert_eq!(OwnAddressType::Public, OwnAddressType::from_le_address(2, &[0xc0; 6]));
+
+        let random = |msb: u8| OwnAddressType::from_le_address(1, &[msb, 0, 0, 0, 0, 0]);
+        assert_eq!(OwnAddressType::RandomStatic, random(0xc1));
+        assert_eq!(OwnAddressType::ResolvablePrivate, random(0x41));
+        assert_eq!(OwnAddressType::NonResolvablePrivate, random(0x01));
+        assert!(OwnAddressType::RandomStatic.is_identity());
+        assert!(!OwnAddressType::ResolvablePrivate.is_identity());
+    }
+}
@@ -0,0 +1,1556 @@
+//! Provisioning sessions, see `IProvisioning::start_provisioning`.
+//!
+//! Setting up an LE device such as a thermostat mostly follows the same flow: find the device
+//! advertising a service, connect to it, negotiate the MTU, subscribe with an authenticated link to
+//! the characteristics reporting the progress, then write a sequence of characteristics, some of
+//! them answered with a notification. A session runs this flow as described by a JSON descriptor
+//! and reports each step, so that the clients do not orchestrate the GATT operations themselves.
+//!
+//! The descriptor is an object with the following members:
+//!
+//! * `service_uuid`: service advertised by the device and holding the characteristics.
+//! * `name_prefix`: prefix of the advertised name, optional.
+//! * `scan_timeout_ms`: time to find the device, 10 seconds if left out.
+//! * `operation_timeout_ms`: time to complete each other step, 10 seconds if left out.
+//! * `mtu`: MTU to request once connected, optional.
+//! * `subscribe`: characteristics to subscribe to, optional.
+//! * `steps`: characteristics to write, in order. Each step is an object with the
+//!   `characteristic` UUID, the `value` in hexadecimal, and optionally `with_response` (true if
+//!   left out) and `await_notification`, a subscribed characteristic whose notification completes
+//!   the step.
+//!
+//! Only one session runs at a time, as they would otherwise compete for the same devices.
+
+use bt_topshim::btif::{BtTransport, Uuid128Bit};
+use bt_topshim::profiles::gatt::GattStatus;
+use bt_topshim::topstack;
+
+use log::{debug, warn};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::address::BtAddress;
+use crate::bluetooth_gatt::{
+    BatchScanResult, BluetoothGatt, BluetoothGattCharacteristic, BluetoothGattService,
+    CharacteristicReadResult, GattHandleValue, GattWriteRequestStatus, GattWriteType,
+    IBluetoothGatt, IBluetoothGattCallback, IScannerCallback, LePhy, ScanFilter, ScanResult,
+    ScanSettings,
+};
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::gatt_conformance::ConformanceIssue;
+use crate::gatt_service_builder::CCCD_UUID;
+use crate::storage::{from_hex, to_hex};
+use crate::uuid::BtUuid;
+use crate::{Message, RPCProxy};
+
+/// Application UUID of the GATT client running the sessions.
+const PROVISIONING_CLIENT_UUID: Uuid128Bit = [
+    0x6D, 0x1A, 0x4E, 0x27, 0x93, 0xC5, 0x4B, 0x0E, 0xA1, 0x58, 0x2F, 0x70, 0xC4, 0x3D, 0x9B, 0x16,
+];
+
+const DEFAULT_SCAN_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_OPERATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Authentication requirement of the subscriptions and writes: encrypted with MITM protection.
+const AUTH_REQ_MITM: i32 = 2;
+
+// Values of the CCCD enabling the notifications or the indications.
+const CCCD_ENABLE_NOTIFICATION: [u8; 2] = [0x01, 0x00];
+const CCCD_ENABLE_INDICATION: [u8; 2] = [0x02, 0x00];
+
+/// Defines the provisioning API.
+pub trait IProvisioning {
+    /// Starts a provisioning session described by the JSON `descriptor`, see the module
+    /// documentation. The progress and the outcome of the session are reported to `callback`.
+    ///
+    /// Returns the id of the session. Fails if the descriptor is malformed or if another session
+    /// is running.
+    fn start_provisioning(
+        &mut self,
+        descriptor: String,
+        callback: Box<dyn IProvisioningCallback + Send>,
+    ) -> BtResult<i32>;
+
+    /// Stops a session, disconnecting the device. The session finishes with
+    /// `ProvisioningStatus::Cancelled`.
+    fn cancel_provisioning(&mut self, session_id: i32) -> BtResult<()>;
+}
+
+/// Provisioning session events.
+pub trait IProvisioningCallback: RPCProxy {
+    /// When a session starts a step. `step` counts from 1 to `total_steps`.
+    fn on_provisioning_progress(
+        &self,
+        session_id: i32,
+        step: i32,
+        total_steps: i32,
+        description: String,
+    );
+
+    /// When a session finishes. `addr` is the device provisioned, empty if none was found.
+    /// `message` explains the failures.
+    fn on_provisioning_finished(
+        &self,
+        session_id: i32,
+        addr: String,
+        status: ProvisioningStatus,
+        message: String,
+    );
+}
+
+/// Outcome of a provisioning session.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ProvisioningStatus {
+    Success = 0,
+    /// The session was cancelled with `IProvisioning::cancel_provisioning`.
+    Cancelled = 1,
+    /// No device advertising the service was found before the scan timeout.
+    DeviceNotFound = 2,
+    ConnectionFailed = 3,
+    /// The device disconnected before the end of the session.
+    Disconnected = 4,
+    /// The device does not have the service or one of the characteristics of the descriptor.
+    AttributeNotFound = 5,
+    /// A GATT operation failed, such as a subscription refused for lack of authentication.
+    OperationFailed = 6,
+    /// A step did not complete before the operation timeout.
+    TimedOut = 7,
+}
+
+/// Characteristic written by a session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProvisioningStep {
+    pub characteristic: Uuid128Bit,
+    pub value: Vec<u8>,
+    pub with_response: bool,
+    /// Subscribed characteristic whose notification completes the step.
+    pub await_notification: Option<Uuid128Bit>,
+}
+
+/// Provisioning session, as parsed from its JSON descriptor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProvisioningDescriptor {
+    pub service_uuid: Uuid128Bit,
+    pub name_prefix: String,
+    pub scan_timeout: Duration,
+    pub operation_timeout: Duration,
+    pub mtu: Option<i32>,
+    pub subscribe: Vec<Uuid128Bit>,
+    pub steps: Vec<ProvisioningStep>,
+}
+
+impl ProvisioningDescriptor {
+    /// Parses a JSON descriptor, see the module documentation.
+    pub fn parse(json: &str) -> BtResult<ProvisioningDescriptor> {
+        let value: Value = serde_json::from_str(json)
+            .map_err(|e| BtError::invalid_argument(format!("Malformed descriptor: {}", e)))?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| BtError::invalid_argument("Descriptor is not an object"))?;
+
+        let subscribe: Vec<Uuid128Bit> = match object.get("subscribe") {
+            Some(Value::Array(uuids)) => {
+                uuids.iter().map(|u| parse_uuid_value(u, "subscribe")).collect::<BtResult<_>>()?
+            }
+            Some(_) => return Err(BtError::invalid_argument("subscribe is not an array")),
+            None => vec![],
+        };
+
+        let steps = match object.get("steps") {
+            Some(Value::Array(steps)) => {
+                steps.iter().map(parse_step).collect::<BtResult<Vec<ProvisioningStep>>>()?
+            }
+            _ => return Err(BtError::invalid_argument("steps is missing or not an array")),
+        };
+        for uuid in steps.iter().filter_map(|s| s.await_notification.as_ref()) {
+            if !subscribe.contains(uuid) {
+                return Err(BtError::invalid_argument(format!(
+                    "Awaited characteristic {} is not subscribed to",
+                    BtUuid::from(*uuid)
+                )));
+            }
+        }
+
+        let mtu = match object.get("mtu") {
+            Some(mtu) => Some(parse_int(mtu, "mtu")? as i32),
+            None => None,
+        };
+
+        Ok(ProvisioningDescriptor {
+            service_uuid: parse_uuid_value(member(object, "service_uuid")?, "service_uuid")?,
+            name_prefix: match object.get("name_prefix") {
+                Some(prefix) => prefix
+                    .as_str()
+                    .map(String::from)
+                    .ok_or_else(|| BtError::invalid_argument("name_prefix is not a string"))?,
+                None => String::new(),
+            },
+            scan_timeout: parse_timeout(object, "scan_timeout_ms", DEFAULT_SCAN_TIMEOUT)?,
+            operation_timeout: parse_timeout(
+                object,
+                "operation_timeout_ms",
+                DEFAULT_OPERATION_TIMEOUT,
+            )?,
+            mtu,
+            subscribe,
+            steps,
+        })
+    }
+
+    /// Number of steps reported with `IProvisioningCallback::on_provisioning_progress`: the scan,
+    /// the connection, the MTU negotiation if requested, the discovery, each subscription and each
+    /// write.
+    pub fn total_steps(&self) -> i32 {
+        let mtu_steps = if self.mtu.is_some() { 1 } else { 0 };
+        (3 + mtu_steps + self.subscribe.len() + self.steps.len()) as i32
+    }
+}
+
+fn member<'a>(object: &'a Map<String, Value>, key: &str) -> BtResult<&'a Value> {
+    object.get(key).ok_or_else(|| BtError::invalid_argument(format!("{} is missing", key)))
+}
+
+fn parse_uuid_value(value: &Value, key: &str) -> BtResult<Uuid128Bit> {
+    value
+        .as_str()
+        .and_then(BtUuid::from_string)
+        .map(|uuid| uuid.uu)
+        .ok_or_else(|| BtError::invalid_argument(format!("{} is not a UUID: {}", key, value)))
+}
+
+fn parse_int(value: &Value, key: &str) -> BtResult<u32> {
+    value
+        .as_u64()
+        .filter(|v| *v <= i32::MAX as u64)
+        .map(|v| v as u32)
+        .ok_or_else(|| BtError::invalid_argument(format!("{} is not a valid number", key)))
+}
+
+fn parse_timeout(object: &Map<String, Value>, key: &str, default: Duration) -> BtResult<Duration> {
+    match object.get(key) {
+        Some(value) => match parse_int(value, key)? {
+            0 => Err(BtError::invalid_argument(format!("{} is zero", key))),
+            ms => Ok(Duration::from_millis(ms.into())),
+        },
+        None => Ok(default),
+    }
+}
+
+fn parse_step(value: &Value) -> BtResult<ProvisioningStep> {
+    let object =
+        value.as_object().ok_or_else(|| BtError::invalid_argument("Step is not an object"))?;
+
+    let value = member(object, "value")?;
+    let value = value
+        .as_str()
+        .and_then(from_hex)
+        .ok_or_else(|| BtError::invalid_argument(format!("value is not hexadecimal: {}", value)))?;
+
+    Ok(ProvisioningStep {
+        characteristic: parse_uuid_value(member(object, "characteristic")?, "characteristic")?,
+        value,
+        with_response: match object.get("with_response") {
+            Some(with_response) => with_response
+                .as_bool()
+                .ok_or_else(|| BtError::invalid_argument("with_response is not a boolean"))?,
+            None => true,
+        },
+        await_notification: match object.get("await_notification") {
+            Some(uuid) => Some(parse_uuid_value(uuid, "await_notification")?),
+            None => None,
+        },
+    })
+}
+
+/// Looks up the characteristics of a descriptor in the services of a device, by UUID.
+fn find_characteristics(
+    descriptor: &ProvisioningDescriptor,
+    services: &Vec<BluetoothGattService>,
+) -> Result<HashMap<Uuid128Bit, FoundCharacteristic>, String> {
+    let service = services
+        .iter()
+        .find(|s| s.uuid == descriptor.service_uuid)
+        .ok_or_else(|| format!("Service {} not found", BtUuid::from(descriptor.service_uuid)))?;
+
+    let uuids =
+        descriptor.subscribe.iter().chain(descriptor.steps.iter().map(|s| &s.characteristic));
+    let mut characteristics = HashMap::new();
+    for uuid in uuids {
+        let characteristic = service
+            .characteristics
+            .iter()
+            .find(|c| c.uuid == *uuid)
+            .ok_or_else(|| format!("Characteristic {} not found", BtUuid::from(*uuid)))?;
+        characteristics.insert(
+            *uuid,
+            FoundCharacteristic {
+                handle: characteristic.instance_id,
+                properties: characteristic.properties,
+                cccd_handle: characteristic
+                    .descriptors
+                    .iter()
+                    .find(|d| d.uuid == CCCD_UUID)
+                    .map(|d| d.instance_id),
+            },
+        );
+    }
+
+    Ok(characteristics)
+}
+
+/// Actions of the provisioning manager dispatched from the event loop, as the events of the GATT
+/// client and of the scanner are delivered while the GATT object is locked.
+pub enum ProvisioningActions {
+    /// Params: Status, Client ID
+    GattClientRegistered(i32, i32),
+    /// Params: Status, Scanner ID
+    ScannerRegistered(i32, i32),
+    ScanResult(ScanResult),
+    /// Params: Address, Connected
+    GattConnectionState(String, bool),
+    /// Params: Address, Status
+    MtuConfigured(String, i32),
+    /// Params: Address, Services, Status
+    GattSearchComplete(String, Vec<BluetoothGattService>, i32),
+    /// Params: Address, Status, Handle
+    DescriptorWritten(String, i32, i32),
+    /// Params: Address, Status, Handle
+    CharacteristicWritten(String, i32, i32),
+    /// Params: Address, Handle
+    GattNotification(String, i32),
+    /// Params: Session ID, Step
+    StepTimeout(i32, i32),
+}
+
+/// Characteristic of the descriptor found by the discovery.
+struct FoundCharacteristic {
+    handle: i32,
+    properties: i32,
+    cccd_handle: Option<i32>,
+}
+
+/// Stage of a session.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SessionState {
+    Scanning,
+    Connecting,
+    ConfiguringMtu,
+    Discovering,
+    /// Index of the characteristic of `subscribe` being subscribed to.
+    Subscribing(usize),
+    /// Index of the step being written, whether the write completed and whether the awaited
+    /// notification was received, as the device may notify before the write response.
+    Writing {
+        step: usize,
+        written: bool,
+        notified: bool,
+    },
+}
+
+struct Session {
+    id: i32,
+    descriptor: ProvisioningDescriptor,
+    callback: Box<dyn IProvisioningCallback + Send>,
+    callback_id: u32,
+    state: SessionState,
+    // Step last reported, so that the timeout of a completed step is ignored.
+    step: i32,
+    // None until a device is found.
+    address: Option<BtAddress>,
+    characteristics: HashMap<Uuid128Bit, FoundCharacteristic>,
+    timeout: Option<JoinHandle<()>>,
+}
+
+/// Implementation of the provisioning API.
+pub struct ProvisioningManager {
+    tx: Sender<Message>,
+    gatt: Option<Arc<Mutex<Box<BluetoothGatt>>>>,
+    client_id: Option<i32>,
+    scanner_id: Option<i32>,
+    next_session_id: i32,
+    session: Option<Session>,
+}
+
+impl ProvisioningManager {
+    pub fn new(tx: Sender<Message>) -> ProvisioningManager {
+        ProvisioningManager {
+            tx,
+            gatt: None,
+            client_id: None,
+            scanner_id: None,
+            next_session_id: 0,
+            session: None,
+        }
+    }
+
+    /// Registers the GATT client and the scanner running the sessions. Must be called once the
+    /// profiles are initialized.
+    pub fn init(&mut self, gatt: Arc<Mutex<Box<BluetoothGatt>>>) {
+        {
+            let mut gatt = gatt.lock().unwrap();
+            gatt.register_client(
+                BtUuid { uu: PROVISIONING_CLIENT_UUID },
+                Box::new(ProvisioningGattCallback { tx: self.tx.clone() }),
+                false,
+            );
+            gatt.register_scanner(Box::new(ProvisioningScannerCallback { tx: self.tx.clone() }));
+        }
+
+        self.gatt = Some(gatt);
+    }
+
+    /// Stops the session of a callback which disconnected.
+    pub(crate) fn callback_disconnected(&mut self, callback_id: u32) {
+        if self.session.as_ref().map(|s| s.callback_id) == Some(callback_id) {
+            if let Some(session) = self.session.take() {
+                self.release(session);
+            }
+        }
+    }
+
+    pub fn dispatch_provisioning_actions(&mut self, action: ProvisioningActions) {
+        match action {
+            ProvisioningActions::GattClientRegistered(status, client_id) => {
+                if status != GattStatus::Success as i32 {
+                    warn!("Failed to register the provisioning GATT client: {}", status);
+                    return;
+                }
+                self.client_id = Some(client_id);
+            }
+            ProvisioningActions::ScannerRegistered(status, scanner_id) => {
+                if status != GattStatus::Success as i32 {
+                    warn!("Failed to register the provisioning scanner: {}", status);
+                    return;
+                }
+                self.scanner_id = Some(scanner_id);
+            }
+            ProvisioningActions::ScanResult(result) => self.on_scan_result(result),
+            ProvisioningActions::GattConnectionState(address, connected) => {
+                if !self.is_session_device(&address) {
+                    return;
+                }
+                match (self.state(), connected) {
+                    (Some(SessionState::Connecting), true) => self.configure_mtu(),
+                    (Some(SessionState::Connecting), false) => self.finish(
+                        ProvisioningStatus::ConnectionFailed,
+                        String::from("Failed to connect"),
+                    ),
+                    (Some(_), false) => self.finish(
+                        ProvisioningStatus::Disconnected,
+                        String::from("Device disconnected"),
+                    ),
+                    _ => (),
+                }
+            }
+            ProvisioningActions::MtuConfigured(address, status) => {
+                if !self.is_session_device(&address)
+                    || self.state() != Some(SessionState::ConfiguringMtu)
+                {
+                    return;
+                }
+                // The writes are split to the MTU obtained, so a refused MTU is not fatal.
+                if status != GattStatus::Success as i32 {
+                    warn!("[{}]: Failed to negotiate the MTU: {}", address, status);
+                }
+                self.discover_services();
+            }
+            ProvisioningActions::GattSearchComplete(address, services, status) => {
+                if !self.is_session_device(&address)
+                    || self.state() != Some(SessionState::Discovering)
+                {
+                    return;
+                }
+                if status != GattStatus::Success as i32 {
+                    self.finish(
+                        ProvisioningStatus::OperationFailed,
+                        format!("Service discovery failed with status {}", status),
+                    );
+                    return;
+                }
+                self.find_characteristics(services);
+            }
+            ProvisioningActions::DescriptorWritten(address, status, handle) => {
+                let index = match self.state() {
+                    Some(SessionState::Subscribing(index)) => index,
+                    _ => return,
+                };
+                if !self.is_session_device(&address) || self.cccd_handle(index) != Some(handle) {
+                    return;
+                }
+                if status != GattStatus::Success as i32 {
+                    self.finish(
+                        ProvisioningStatus::OperationFailed,
+                        format!("Subscription failed with status {}", status),
+                    );
+                    return;
+                }
+                self.subscribe(index + 1);
+            }
+            ProvisioningActions::CharacteristicWritten(address, status, handle) => {
+                let (step, notified) = match self.state() {
+                    Some(SessionState::Writing { step, written: false, notified }) => {
+                        (step, notified)
+                    }
+                    _ => return,
+                };
+                if !self.is_session_device(&address) || self.step_handle(step) != Some(handle) {
+                    return;
+                }
+                if status != GattStatus::Success as i32 {
+                    self.finish(
+                        ProvisioningStatus::OperationFailed,
+                        format!("Write failed with status {}", status),
+                    );
+                    return;
+                }
+                self.complete_write(step, true, notified);
+            }
+            ProvisioningActions::GattNotification(address, handle) => {
+                let (step, written) = match self.state() {
+                    Some(SessionState::Writing { step, written, notified: false }) => {
+                        (step, written)
+                    }
+                    _ => return,
+                };
+                if !self.is_session_device(&address) || self.awaited_handle(step) != Some(handle) {
+                    return;
+                }
+                self.complete_write(step, written, true);
+            }
+            ProvisioningActions::StepTimeout(session_id, step) => {
+                let state = match &self.session {
+                    Some(s) if s.id == session_id && s.step == step => s.state,
+                    _ => return,
+                };
+                if state == SessionState::Scanning {
+                    self.finish(
+                        ProvisioningStatus::DeviceNotFound,
+                        String::from("No device found"),
+                    );
+                } else {
+                    self.finish(
+                        ProvisioningStatus::TimedOut,
+                        format!("Timed out at step {}", step),
+                    );
+                }
+            }
+        }
+    }
+
+    fn state(&self) -> Option<SessionState> {
+        self.session.as_ref().map(|s| s.state)
+    }
+
+    fn is_session_device(&self, address: &String) -> bool {
+        let address = BtAddress::from_string(address);
+        self.session.as_ref().map_or(false, |s| s.address.is_some() && s.address == address)
+    }
+
+    fn cccd_handle(&self, index: usize) -> Option<i32> {
+        let session = self.session.as_ref()?;
+        let uuid = session.descriptor.subscribe.get(index)?;
+        session.characteristics.get(uuid)?.cccd_handle
+    }
+
+    fn step_handle(&self, step: usize) -> Option<i32> {
+        let session = self.session.as_ref()?;
+        let uuid = session.descriptor.steps.get(step)?.characteristic;
+        session.characteristics.get(&uuid).map(|c| c.handle)
+    }
+
+    fn awaited_handle(&self, step: usize) -> Option<i32> {
+        let session = self.session.as_ref()?;
+        let uuid = session.descriptor.steps.get(step)?.await_notification?;
+        session.characteristics.get(&uuid).map(|c| c.handle)
+    }
+
+    /// Moves the session to `state`, reports the step and arms its timeout.
+    fn advance(&mut self, state: SessionState, timeout: Duration, description: String) {
+        let tx = self.tx.clone();
+        let session = match self.session.as_mut() {
+            Some(session) => session,
+            None => return,
+        };
+
+        session.state = state;
+        session.step += 1;
+        debug!("Provisioning session {}: {}", session.id, description);
+        session.callback.on_provisioning_progress(
+            session.id,
+            session.step,
+            session.descriptor.total_steps(),
+            description,
+        );
+
+        if let Some(timeout) = session.timeout.take() {
+            timeout.abort();
+        }
+        let (session_id, step) = (session.id, session.step);
+        session.timeout = Some(tokio::spawn(async move {
+            time::sleep(timeout).await;
+            let _ = tx
+                .send(Message::Provisioning(ProvisioningActions::StepTimeout(session_id, step)))
+                .await;
+        }));
+    }
+
+    fn on_scan_result(&mut self, result: ScanResult) {
+        let session = match self.session.as_mut() {
+            Some(session) if session.state == SessionState::Scanning => session,
+            _ => return,
+        };
+        if !result.scan_record.service_uuids.contains(&session.descriptor.service_uuid)
+            || !result.scan_record.name.starts_with(&session.descriptor.name_prefix)
+        {
+            return;
+        }
+
+        let address = result.address;
+        session.address = Some(address);
+        if let (Some(gatt), Some(scanner_id)) = (&self.gatt, self.scanner_id) {
+            gatt.lock().unwrap().stop_scan(scanner_id);
+        }
+
+        let timeout = session.descriptor.operation_timeout;
+        self.advance(SessionState::Connecting, timeout, format!("Connecting to {}", address));
+
+        let (gatt, client_id) = match (&self.gatt, self.client_id) {
+            (Some(gatt), Some(client_id)) => (gatt.clone(), client_id),
+            _ => return,
+        };
+        let result = gatt.lock().unwrap().client_connect(
+            client_id,
+            address,
+            true,
+            BtTransport::Le as i32,
+            false,
+            LePhy::Phy1m as i32,
+        );
+        if let Err(e) = result {
+            self.finish(ProvisioningStatus::ConnectionFailed, format!("Failed to connect: {}", e));
+        }
+    }
+
+    fn configure_mtu(&mut self) {
+        let (address, mtu, timeout) = match &self.session {
+            Some(s) => (s.address, s.descriptor.mtu, s.descriptor.operation_timeout),
+            None => return,
+        };
+        let mtu = match mtu {
+            Some(mtu) => mtu,
+            None => return self.discover_services(),
+        };
+
+        self.advance(
+            SessionState::ConfiguringMtu,
+            timeout,
+            format!("Requesting an MTU of {}", mtu),
+        );
+        let result = match (&self.gatt, self.client_id, address) {
+            (Some(gatt), Some(client_id), Some(address)) => {
+                gatt.lock().unwrap().configure_mtu(client_id, address, mtu)
+            }
+            _ => return,
+        };
+        if let Err(e) = result {
+            warn!("Failed to request an MTU of {}: {}", mtu, e);
+            self.discover_services();
+        }
+    }
+
+    fn discover_services(&mut self) {
+        let (address, timeout) = match &self.session {
+            Some(s) => (s.address, s.descriptor.operation_timeout),
+            None => return,
+        };
+
+        self.advance(SessionState::Discovering, timeout, String::from("Discovering the services"));
+        let result = match (&self.gatt, self.client_id, address) {
+            (Some(gatt), Some(client_id), Some(address)) => {
+                gatt.lock().unwrap().discover_services(client_id, address)
+            }
+            _ => return,
+        };
+        if let Err(e) = result {
+            self.finish(
+                ProvisioningStatus::OperationFailed,
+                format!("Failed to discover the services: {}", e),
+            );
+        }
+    }
+
+    /// Looks up the characteristics of the descriptor in the discovered services.
+    fn find_characteristics(&mut self, services: Vec<BluetoothGattService>) {
+        let found = match &self.session {
+            Some(session) => find_characteristics(&session.descriptor, &services),
+            None => return,
+        };
+
+        match found {
+            Ok(characteristics) => {
+                if let Some(session) = self.session.as_mut() {
+                    session.characteristics = characteristics;
+                }
+                self.subscribe(0);
+            }
+            Err(message) => self.finish(ProvisioningStatus::AttributeNotFound, message),
+        }
+    }
+
+    /// Subscribes to the characteristic `index` of `subscribe`, or starts the writes once all
+    /// the characteristics are subscribed to.
+    fn subscribe(&mut self, index: usize) {
+        let (uuid, address, timeout) = match &self.session {
+            Some(s) => match s.descriptor.subscribe.get(index) {
+                Some(uuid) => (*uuid, s.address, s.descriptor.operation_timeout),
+                None => return self.write(0),
+            },
+            None => return,
+        };
+        let (handle, properties, cccd_handle) = match self.session.as_ref().and_then(|s| {
+            s.characteristics.get(&uuid).map(|c| (c.handle, c.properties, c.cccd_handle))
+        }) {
+            Some((handle, properties, Some(cccd_handle))) => (handle, properties, cccd_handle),
+            _ => {
+                let message = format!("Characteristic {} cannot notify", BtUuid::from(uuid));
+                return self.finish(ProvisioningStatus::AttributeNotFound, message);
+            }
+        };
+
+        self.advance(
+            SessionState::Subscribing(index),
+            timeout,
+            format!("Subscribing to {}", BtUuid::from(uuid)),
+        );
+
+        let value = if properties & BluetoothGattCharacteristic::PROPERTY_NOTIFY != 0 {
+            CCCD_ENABLE_NOTIFICATION
+        } else {
+            CCCD_ENABLE_INDICATION
+        };
+        let result = match (&self.gatt, self.client_id, address) {
+            (Some(gatt), Some(client_id), Some(address)) => {
+                let mut gatt = gatt.lock().unwrap();
+                gatt.register_for_notification(client_id, address, handle, true).and_then(|_| {
+                    gatt.write_descriptor(
+                        client_id,
+                        address,
+                        cccd_handle,
+                        AUTH_REQ_MITM,
+                        value.to_vec(),
+                    )
+                })
+            }
+            _ => return,
+        };
+        if let Err(e) = result {
+            self.finish(
+                ProvisioningStatus::OperationFailed,
+                format!("Failed to subscribe to {}: {}", BtUuid::from(uuid), e),
+            );
+        }
+    }
+
+    /// Writes the step `step`, or completes the session once all the steps are written.
+    fn write(&mut self, step: usize) {
+        let (write, address, timeout) = match &self.session {
+            Some(s) => match s.descriptor.steps.get(step) {
+                Some(write) => (write.clone(), s.address, s.descriptor.operation_timeout),
+                None => return self.finish(ProvisioningStatus::Success, String::new()),
+            },
+            None => return,
+        };
+        let handle = match self.step_handle(step) {
+            Some(handle) => handle,
+            None => return,
+        };
+
+        self.advance(
+            SessionState::Writing {
+                step,
+                written: false,
+                notified: write.await_notification.is_none(),
+            },
+            timeout,
+            format!("Writing {}", BtUuid::from(write.characteristic)),
+        );
+
+        let write_type =
+            if write.with_response { GattWriteType::Write } else { GattWriteType::WriteNoRsp };
+        let status = match (&self.gatt, self.client_id, address) {
+            (Some(gatt), Some(client_id), Some(address)) => {
+                gatt.lock().unwrap().write_characteristic(
+                    client_id,
+                    address,
+                    handle,
+                    write_type,
+                    AUTH_REQ_MITM,
+                    write.value,
+                )
+            }
+            _ => return,
+        };
+        if let GattWriteRequestStatus::Success = status {
+            return;
+        }
+        self.finish(
+            ProvisioningStatus::OperationFailed,
+            format!("Failed to write {}: {:?}", BtUuid::from(write.characteristic), status),
+        );
+    }
+
+    /// Moves to the next step once the write completed and the awaited notification arrived.
+    fn complete_write(&mut self, step: usize, written: bool, notified: bool) {
+        if written && notified {
+            return self.write(step + 1);
+        }
+        if let Some(session) = self.session.as_mut() {
+            session.state = SessionState::Writing { step, written, notified };
+        }
+    }
+
+    /// Ends the session and reports its outcome.
+    fn finish(&mut self, status: ProvisioningStatus, message: String) {
+        let mut session = match self.session.take() {
+            Some(session) => session,
+            None => return,
+        };
+
+        debug!("Provisioning session {} finished: {:?} {}", session.id, status, message);
+        session.callback.on_provisioning_finished(
+            session.id,
+            session.address.map(|address| address.to_string()).unwrap_or_default(),
+            status,
+            message,
+        );
+        let callback_id = session.callback_id;
+        session.callback.unregister(callback_id);
+        self.release(session);
+    }
+
+    /// Stops the scan or disconnects the device of a session.
+    fn release(&mut self, mut session: Session) {
+        if let Some(timeout) = session.timeout.take() {
+            timeout.abort();
+        }
+
+        let gatt = match &self.gatt {
+            Some(gatt) => gatt,
+            None => return,
+        };
+        match (session.state, self.scanner_id, self.client_id, session.address) {
+            (SessionState::Scanning, Some(scanner_id), _, _) => {
+                gatt.lock().unwrap().stop_scan(scanner_id)
+            }
+            (SessionState::Scanning, None, _, _) => (),
+            (_, _, Some(client_id), Some(address)) => {
+                let _ = gatt.lock().unwrap().client_disconnect(client_id, address);
+            }
+            _ => (),
+        }
+    }
+}
+
+impl IProvisioning for ProvisioningManager {
+    fn start_provisioning(
+        &mut self,
+        descriptor: String,
+        mut callback: Box<dyn IProvisioningCallback + Send>,
+    ) -> BtResult<i32> {
+        let descriptor = ProvisioningDescriptor::parse(&descriptor)?;
+
+        let (gatt, scanner_id) = match (&self.gatt, self.client_id, self.scanner_id) {
+            (Some(gatt), Some(_), Some(scanner_id)) => (gatt.clone(), scanner_id),
+            _ => {
+                return Err(BtError::new(
+                    BtErrorCategory::NotReady,
+                    "The provisioning client is not registered",
+                ))
+            }
+        };
+        if let Some(session) = &self.session {
+            return Err(BtError::new(
+                BtErrorCategory::Busy,
+                format!("Provisioning session {} is running", session.id),
+            ));
+        }
+
+        let filter =
+            ScanFilter { service_uuid: to_hex(&descriptor.service_uuid), ..Default::default() };
+        gatt.lock().unwrap().start_scan(scanner_id, ScanSettings::default(), vec![filter])?;
+
+        let tx = self.tx.clone();
+        let callback_id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _ = tx.send(Message::ProvisioningCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.next_session_id += 1;
+        let id = self.next_session_id;
+        let scan_timeout = descriptor.scan_timeout;
+        let description = format!("Scanning for {}", BtUuid::from(descriptor.service_uuid));
+        self.session = Some(Session {
+            id,
+            descriptor,
+            callback,
+            callback_id,
+            state: SessionState::Scanning,
+            step: 0,
+            address: None,
+            characteristics: HashMap::new(),
+            timeout: None,
+        });
+        self.advance(SessionState::Scanning, scan_timeout, description);
+
+        Ok(id)
+    }
+
+    fn cancel_provisioning(&mut self, session_id: i32) -> BtResult<()> {
+        match &self.session {
+            Some(session) if session.id == session_id => {
+                self.finish(ProvisioningStatus::Cancelled, String::from("Cancelled"));
+                Ok(())
+            }
+            _ => Err(BtError::not_found(format!(
+                "Provisioning session {} is not running",
+                session_id
+            ))),
+        }
+    }
+}
+
+fn send_provisioning_action(tx: &Sender<Message>, action: ProvisioningActions) {
+    let tx = tx.clone();
+    topstack::get_runtime().spawn(async move {
+        let _ = tx.send(Message::Provisioning(action)).await;
+    });
+}
+
+/// Relays the events of the GATT client of the provisioning manager.
+struct ProvisioningGattCallback {
+    tx: Sender<Message>,
+}
+
+impl IBluetoothGattCallback for ProvisioningGattCallback {
+    fn on_client_registered(&self, status: i32, client_id: i32) {
+        send_provisioning_action(
+            &self.tx,
+            ProvisioningActions::GattClientRegistered(status, client_id),
+        );
+    }
+
+    fn on_client_connection_state(
+        &self,
+        status: i32,
+        _client_id: i32,
+        connected: bool,
+        addr: BtAddress,
+    ) {
+        let connected = connected && status == GattStatus::Success as i32;
+        send_provisioning_action(
+            &self.tx,
+            ProvisioningActions::GattConnectionState(addr.to_string(), connected),
+        );
+    }
+
+    fn on_phy_update(&self, _addr: BtAddress, _tx_phy: LePhy, _rx_phy: LePhy, _status: GattStatus) {
+    }
+
+    fn on_phy_read(&self, _addr: BtAddress, _tx_phy: LePhy, _rx_phy: LePhy, _status: GattStatus) {}
+
+    fn on_search_complete(
+        &self,
+        addr: BtAddress,
+        services: Vec<BluetoothGattService>,
+        status: i32,
+    ) {
+        send_provisioning_action(
+            &self.tx,
+            ProvisioningActions::GattSearchComplete(addr.to_string(), services, status),
+        );
+    }
+
+    fn on_service_read(
+        &self,
+        _addr: BtAddress,
+        _service_uuid: Uuid128Bit,
+        _results: Vec<CharacteristicReadResult>,
+    ) {
+    }
+
+    fn on_conformance_report(&self, _addr: BtAddress, _issues: Vec<ConformanceIssue>) {}
+
+    fn on_get_gatt_db(&self, _addr: BtAddress, _services: Vec<BluetoothGattService>) {}
+
+    fn on_characteristic_read(
+        &self,
+        _addr: BtAddress,
+        _status: i32,
+        _handle: i32,
+        _value: Vec<u8>,
+    ) {
+    }
+
+    fn on_characteristic_write(&self, addr: BtAddress, status: i32, handle: i32) {
+        send_provisioning_action(
+            &self.tx,
+            ProvisioningActions::CharacteristicWritten(addr.to_string(), status, handle),
+        );
+    }
+
+    fn on_characteristic_write_progress(
+        &self,
+        _addr: BtAddress,
+        _handle: i32,
+        _bytes_written: i32,
+        _total_bytes: i32,
+    ) {
+    }
+
+    fn on_execute_write(&self, _addr: BtAddress, _status: i32) {}
+
+    fn on_descriptor_read(&self, _addr: BtAddress, _status: i32, _handle: i32, _value: Vec<u8>) {}
+
+    fn on_descriptor_write(&self, addr: BtAddress, status: i32, handle: i32) {
+        send_provisioning_action(
+            &self.tx,
+            ProvisioningActions::DescriptorWritten(addr.to_string(), status, handle),
+        );
+    }
+
+    fn on_notify(&self, addr: BtAddress, handle: i32, _value: Vec<u8>) {
+        send_provisioning_action(
+            &self.tx,
+            ProvisioningActions::GattNotification(addr.to_string(), handle),
+        );
+    }
+
+    fn on_notify_multiple(&self, addr: BtAddress, values: Vec<GattHandleValue>) {
+        for v in values {
+            self.on_notify(addr, v.handle, v.value);
+        }
+    }
+
+    fn on_notification_queue_overflow(&self, _addr: BtAddress, _handle: i32, _dropped: u32) {}
+
+    fn on_read_remote_rssi(&self, _addr: BtAddress, _rssi: i32, _status: i32) {}
+
+    fn on_rssi_threshold_crossed(&self, _addr: BtAddress, _rssi: i32, _threshold: i32) {}
+
+    fn on_configure_mtu(&self, addr: BtAddress, _mtu: i32, status: i32) {
+        send_provisioning_action(
+            &self.tx,
+            ProvisioningActions::MtuConfigured(addr.to_string(), status),
+        );
+    }
+
+    fn on_connection_updated(
+        &self,
+        _addr: BtAddress,
+        _interval: i32,
+        _latency: i32,
+        _timeout: i32,
+        _status: i32,
+    ) {
+    }
+
+    fn on_service_changed(&self, _addr: BtAddress) {}
+
+    fn on_notification_pipe_active(&self, _addr: BtAddress, _handle: i32) {}
+
+    fn on_eatt_state_changed(&self, _addr: BtAddress, _count: i32, _mtus: Vec<i32>) {}
+}
+
+impl RPCProxy for ProvisioningGattCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("ProvisioningManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+/// Relays the results of the scanner of the provisioning manager.
+struct ProvisioningScannerCallback {
+    tx: Sender<Message>,
+}
+
+impl IScannerCallback for ProvisioningScannerCallback {
+    fn on_scanner_registered(&self, status: i32, scanner_id: i32) {
+        send_provisioning_action(
+            &self.tx,
+            ProvisioningActions::ScannerRegistered(status, scanner_id),
+        );
+    }
+
+    fn on_scan_result(&self, scan_result: ScanResult) {
+        send_provisioning_action(&self.tx, ProvisioningActions::ScanResult(scan_result));
+    }
+
+    fn on_scan_result_batch(&self, scan_results: Vec<ScanResult>) {
+        for scan_result in scan_results {
+            self.on_scan_result(scan_result);
+        }
+    }
+
+    fn on_scan_result_lost(&self, _scan_result: ScanResult) {}
+
+    fn on_batch_scan_reports(
+        &self,
+        _scanner_id: i32,
+        _status: i32,
+        _results: Vec<BatchScanResult>,
+    ) {
+    }
+
+    fn on_batch_scan_threshold_crossed(&self, _scanner_id: i32) {}
+
+    fn on_scan_parameters_changed(&self, _scanner_id: i32, _interval: i32, _window: i32) {}
+
+    fn on_scan_duty_cycle_changed(&self, _scanner_id: i32, _requested: i32, _effective: i32) {}
+
+    fn on_manufacturer_data_found(
+        &self,
+        _scanner_id: i32,
+        _subscription_id: u32,
+        _addr: BtAddress,
+        _rssi: i32,
+        _data: Vec<u8>,
+    ) {
+    }
+}
+
+impl RPCProxy for ProvisioningScannerCallback {
+    fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+        0
+    }
+
+    fn get_object_id(&self) -> String {
+        String::from("ProvisioningManager")
+    }
+
+    fn unregister(&mut self, _id: u32) -> bool {
+        false
+    }
+
+    fn export_for_rpc(self: Box<Self>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth_gatt::{BluetoothGattDescriptor, ScanRecord};
+
+    const DEVICE: &str = "00:11:22:33:44:55";
+
+    // Handles of the thermostat characteristics.
+    const CONFIG_HANDLE: i32 = 3;
+    const STATUS_HANDLE: i32 = 5;
+    const STATUS_CCCD_HANDLE: i32 = 6;
+
+    const THERMOSTAT: &str = r#"{
+        "service_uuid": "0000fe00-0000-1000-8000-00805f9b34fb",
+        "name_prefix": "Thermo",
+        "mtu": 247,
+        "subscribe": ["fe02"],
+        "steps": [
+            { "characteristic": "fe01", "value": "0a0b", "await_notification": "fe02" },
+            { "characteristic": "fe01", "value": "", "with_response": false }
+        ]
+    }"#;
+
+    fn uuid(short: &str) -> Uuid128Bit {
+        BtUuid::from_string(short).unwrap().uu
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        /// Params: Step, Total steps
+        Progress(i32, i32),
+        /// Params: Address, Status
+        Finished(String, ProvisioningStatus),
+    }
+
+    struct EventRecorder(Arc<Mutex<Vec<Event>>>);
+
+    impl RPCProxy for EventRecorder {
+        fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+            0
+        }
+
+        fn get_object_id(&self) -> String {
+            String::from("")
+        }
+
+        fn unregister(&mut self, _id: u32) -> bool {
+            true
+        }
+
+        fn export_for_rpc(self: Box<Self>) {}
+    }
+
+    impl IProvisioningCallback for EventRecorder {
+        fn on_provisioning_progress(
+            &self,
+            _session_id: i32,
+            step: i32,
+            total_steps: i32,
+            _description: String,
+        ) {
+            self.0.lock().unwrap().push(Event::Progress(step, total_steps));
+        }
+
+        fn on_provisioning_finished(
+            &self,
+            _session_id: i32,
+            addr: String,
+            status: ProvisioningStatus,
+            _message: String,
+        ) {
+            self.0.lock().unwrap().push(Event::Finished(addr, status));
+        }
+    }
+
+    /// Returns a manager running a session of `descriptor` which is scanning, without GATT
+    /// client so that only the state machine runs.
+    fn scanning(descriptor: &str) -> (ProvisioningManager, Arc<Mutex<Vec<Event>>>) {
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut manager = ProvisioningManager::new(tx);
+        let descriptor = ProvisioningDescriptor::parse(descriptor).unwrap();
+        let scan_timeout = descriptor.scan_timeout;
+        manager.next_session_id = 1;
+        manager.session = Some(Session {
+            id: 1,
+            descriptor,
+            callback: Box::new(EventRecorder(events.clone())),
+            callback_id: 0,
+            state: SessionState::Scanning,
+            step: 0,
+            address: None,
+            characteristics: HashMap::new(),
+            timeout: None,
+        });
+        manager.advance(SessionState::Scanning, scan_timeout, String::from("Scanning"));
+        (manager, events)
+    }
+
+    fn advertisement(address: &str, name: &str) -> ProvisioningActions {
+        ProvisioningActions::ScanResult(ScanResult {
+            address: BtAddress::from_string(address).unwrap(),
+            scan_record: ScanRecord {
+                name: String::from(name),
+                service_uuids: vec![uuid("fe00")],
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    fn thermostat_services() -> Vec<BluetoothGattService> {
+        let mut service = BluetoothGattService::new(uuid("fe00"), 1, 0);
+        service.characteristics.push(BluetoothGattCharacteristic::new(
+            uuid("fe01"),
+            CONFIG_HANDLE,
+            BluetoothGattCharacteristic::PROPERTY_WRITE,
+            0,
+        ));
+        let mut status = BluetoothGattCharacteristic::new(
+            uuid("fe02"),
+            STATUS_HANDLE,
+            BluetoothGattCharacteristic::PROPERTY_NOTIFY,
+            0,
+        );
+        status.descriptors.push(BluetoothGattDescriptor::new(CCCD_UUID, STATUS_CCCD_HANDLE, 0));
+        service.characteristics.push(status);
+        vec![service]
+    }
+
+    /// Runs a session of `THERMOSTAT` up to the subscription.
+    fn subscribing() -> (ProvisioningManager, Arc<Mutex<Vec<Event>>>) {
+        let (mut manager, events) = scanning(THERMOSTAT);
+        manager.dispatch_provisioning_actions(advertisement(DEVICE, "Thermostat"));
+        manager.dispatch_provisioning_actions(ProvisioningActions::GattConnectionState(
+            String::from(DEVICE),
+            true,
+        ));
+        manager.dispatch_provisioning_actions(ProvisioningActions::MtuConfigured(
+            String::from(DEVICE),
+            GattStatus::Success as i32,
+        ));
+        manager.dispatch_provisioning_actions(ProvisioningActions::GattSearchComplete(
+            String::from(DEVICE),
+            thermostat_services(),
+            GattStatus::Success as i32,
+        ));
+        assert_eq!(Some(SessionState::Subscribing(0)), manager.state());
+        (manager, events)
+    }
+
+    fn finished(events: &Arc<Mutex<Vec<Event>>>) -> Option<ProvisioningStatus> {
+        match events.lock().unwrap().last() {
+            Some(Event::Finished(_, status)) => Some(*status),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let descriptor = ProvisioningDescriptor::parse(THERMOSTAT).unwrap();
+        assert_eq!(uuid("fe00"), descriptor.service_uuid);
+        assert_eq!("Thermo", descriptor.name_prefix);
+        assert_eq!(DEFAULT_SCAN_TIMEOUT, descriptor.scan_timeout);
+        assert_eq!(Some(247), descriptor.mtu);
+        assert_eq!(vec![uuid("fe02")], descriptor.subscribe);
+        assert_eq!(
+            vec![
+                ProvisioningStep {
+                    characteristic: uuid("fe01"),
+                    value: vec![0x0a, 0x0b],
+                    with_response: true,
+                    await_notification: Some(uuid("fe02")),
+                },
+                ProvisioningStep {
+                    characteristic: uuid("fe01"),
+                    value: vec![],
+                    with_response: false,
+                    await_notification: None,
+                },
+            ],
+            descriptor.steps
+        );
+        // Scan, connection, MTU, discovery, one subscription and two writes.
+        assert_eq!(7, descriptor.total_steps());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ProvisioningDescriptor::parse("[]").is_err());
+        assert!(ProvisioningDescriptor::parse(r#"{ "steps": [] }"#).is_err());
+        assert!(ProvisioningDescriptor::parse(r#"{ "service_uuid": "fe00" }"#).is_err());
+        assert!(ProvisioningDescriptor::parse(
+            r#"{ "service_uuid": "fe00", "steps": [], "scan_timeout_ms": 0 }"#
+        )
+        .is_err());
+        assert!(ProvisioningDescriptor::parse(
+            r#"{ "service_uuid": "fe00", "steps": [{ "characteristic": "fe01", "value": "0" }] }"#
+        )
+        .is_err());
+        // The awaited notifications must be subscribed to.
+        assert!(ProvisioningDescriptor::parse(
+            r#"{ "service_uuid": "fe00", "steps": [
+                { "characteristic": "fe01", "value": "01", "await_notification": "fe02" }
+            ] }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_session() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut manager, events) = scanning(THERMOSTAT);
+
+            // Only the devices whose name has the prefix are connected to.
+            manager.dispatch_provisioning_actions(advertisement(DEVICE, "Lamp"));
+            assert_eq!(Some(SessionState::Scanning), manager.state());
+            manager.dispatch_provisioning_actions(advertisement(DEVICE, "Thermostat"));
+            assert_eq!(Some(SessionState::Connecting), manager.state());
+
+            manager.dispatch_provisioning_actions(ProvisioningActions::GattConnectionState(
+                String::from(DEVICE),
+                true,
+            ));
+            assert_eq!(Some(SessionState::ConfiguringMtu), manager.state());
+
+            // A refused MTU is not fatal.
+            manager.dispatch_provisioning_actions(ProvisioningActions::MtuConfigured(
+                String::from(DEVICE),
+                GattStatus::Error as i32,
+            ));
+            assert_eq!(Some(SessionState::Discovering), manager.state());
+
+            manager.dispatch_provisioning_actions(ProvisioningActions::GattSearchComplete(
+                String::from(DEVICE),
+                thermostat_services(),
+                GattStatus::Success as i32,
+            ));
+            assert_eq!(Some(SessionState::Subscribing(0)), manager.state());
+
+            manager.dispatch_provisioning_actions(ProvisioningActions::DescriptorWritten(
+                String::from(DEVICE),
+                GattStatus::Success as i32,
+                STATUS_CCCD_HANDLE,
+            ));
+            assert_eq!(
+                Some(SessionState::Writing { step: 0, written: false, notified: false }),
+                manager.state()
+            );
+
+            // The notification completing the step may arrive before the write response.
+            manager.dispatch_provisioning_actions(ProvisioningActions::GattNotification(
+                String::from(DEVICE),
+                STATUS_HANDLE,
+            ));
+            assert_eq!(
+                Some(SessionState::Writing { step: 0, written: false, notified: true }),
+                manager.state()
+            );
+            manager.dispatch_provisioning_actions(ProvisioningActions::CharacteristicWritten(
+                String::from(DEVICE),
+                GattStatus::Success as i32,
+                CONFIG_HANDLE,
+            ));
+            // The second step awaits no notification.
+            assert_eq!(
+                Some(SessionState::Writing { step: 1, written: false, notified: true }),
+                manager.state()
+            );
+
+            manager.dispatch_provisioning_actions(ProvisioningActions::CharacteristicWritten(
+                String::from(DEVICE),
+                GattStatus::Success as i32,
+                CONFIG_HANDLE,
+            ));
+            assert_eq!(None, manager.state());
+
+            let mut expected: Vec<Event> = (1..=7).map(|step| Event::Progress(step, 7)).collect();
+            expected.push(Event::Finished(String::from(DEVICE), ProvisioningStatus::Success));
+            assert_eq!(expected, *events.lock().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_events_of_other_operations_ignored() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut manager, events) = subscribing();
+
+            manager.dispatch_provisioning_actions(ProvisioningActions::DescriptorWritten(
+                String::from("00:11:22:33:44:66"),
+                GattStatus::Success as i32,
+                STATUS_CCCD_HANDLE,
+            ));
+            manager.dispatch_provisioning_actions(ProvisioningActions::DescriptorWritten(
+                String::from(DEVICE),
+                GattStatus::Success as i32,
+                CONFIG_HANDLE,
+            ));
+            manager.dispatch_provisioning_actions(ProvisioningActions::CharacteristicWritten(
+                String::from(DEVICE),
+                GattStatus::Success as i32,
+                CONFIG_HANDLE,
+            ));
+            manager.dispatch_provisioning_actions(ProvisioningActions::GattConnectionState(
+                String::from("00:11:22:33:44:66"),
+                false,
+            ));
+            assert_eq!(Some(SessionState::Subscribing(0)), manager.state());
+            assert_eq!(None, finished(&events));
+        });
+    }
+
+    #[test]
+    fn test_failures() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut manager, events) = scanning(THERMOSTAT);
+            manager.dispatch_provisioning_actions(advertisement(DEVICE, "Thermostat"));
+            manager.dispatch_provisioning_actions(ProvisioningActions::GattConnectionState(
+                String::from(DEVICE),
+                false,
+            ));
+            assert_eq!(Some(ProvisioningStatus::ConnectionFailed), finished(&events));
+
+            let (mut manager, events) = subscribing();
+            manager.dispatch_provisioning_actions(ProvisioningActions::DescriptorWritten(
+                String::from(DEVICE),
+                GattStatus::InsufAuthentication as i32,
+                STATUS_CCCD_HANDLE,
+            ));
+            assert_eq!(Some(ProvisioningStatus::OperationFailed), finished(&events));
+
+            let (mut manager, events) = subscribing();
+            manager.dispatch_provisioning_actions(ProvisioningActions::GattConnectionState(
+                String::from(DEVICE),
+                false,
+            ));
+            assert_eq!(Some(ProvisioningStatus::Disconnected), finished(&events));
+            assert_eq!(None, manager.state());
+
+            // The device lacks the status characteristic.
+            let (mut manager, events) = scanning(THERMOSTAT);
+            manager.dispatch_provisioning_actions(advertisement(DEVICE, "Thermostat"));
+            manager.dispatch_provisioning_actions(ProvisioningActions::GattConnectionState(
+                String::from(DEVICE),
+                true,
+            ));
+            manager.dispatch_provisioning_actions(ProvisioningActions::MtuConfigured(
+                String::from(DEVICE),
+                GattStatus::Success as i32,
+            ));
+            let mut services = thermostat_services();
+            services[0].characteristics.pop();
+            manager.dispatch_provisioning_actions(ProvisioningActions::GattSearchComplete(
+                String::from(DEVICE),
+                services,
+                GattStatus::Success as i32,
+            ));
+            assert_eq!(Some(ProvisioningStatus::AttributeNotFound), finished(&events));
+        });
+    }
+
+    #[test]
+    fn test_step_timeout() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut manager, events) = scanning(THERMOSTAT);
+            manager.dispatch_provisioning_actions(ProvisioningActions::StepTimeout(1, 1));
+            assert_eq!(Some(ProvisioningStatus::DeviceNotFound), finished(&events));
+
+            // The timeouts of the completed steps and of the other sessions are ignored.
+            let (mut manager, events) = subscribing();
+            manager.dispatch_provisioning_actions(ProvisioningActions::StepTimeout(1, 4));
+            manager.dispatch_provisioning_actions(ProvisioningActions::StepTimeout(2, 5));
+            assert_eq!(None, finished(&events));
+
+            manager.dispatch_provisioning_actions(ProvisioningActions::StepTimeout(1, 5));
+            assert_eq!(Some(ProvisioningStatus::TimedOut), finished(&events));
+        });
+    }
+
+    #[test]
+    fn test_cancel() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut manager, events) = scanning(THERMOSTAT);
+            assert!(manager.cancel_provisioning(2).is_err());
+            assert!(manager.cancel_provisioning(1).is_ok());
+            assert_eq!(
+                Some(&Event::Finished(String::new(), ProvisioningStatus::Cancelled)),
+                events.lock().unwrap().last()
+            );
+            assert!(manager.cancel_provisioning(1).is_err());
+        });
+    }
+
+    #[test]
+    fn test_start_without_client() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let mut manager = ProvisioningManager::new(tx);
+        let events = Arc::new(Mutex::new(vec![]));
+        let result =
+            manager.start_provisioning(String::from(THERMOSTAT), Box::new(EventRecorder(events)));
+        assert_eq!(BtErrorCategory::NotReady, result.unwrap_err().category);
+    }
+}
@@ -0,0 +1,121 @@
+//! Monitors of the RSSI of connected devices, see `IBluetoothGatt::start_rssi_monitor`. The RSSI
+//! of the connection is read periodically and compared with a low and a high threshold, so that
+//! the clients learn when a device comes close or walks away without reading the RSSI
+//! themselves.
+//!
+//! The thresholds act as a hysteresis: once above the high threshold, the device is only
+//! reported again when it falls to the low threshold, so that an RSSI wavering around a
+//! threshold is not reported at each sample.
+
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::error::{BtError, BtResult};
+
+/// Shortest period between the samples of a monitor.
+pub(crate) const MIN_RSSI_SAMPLING_PERIOD: Duration = Duration::from_millis(100);
+
+/// Range of the RSSI reported by the controller, in dBm.
+const RSSI_MIN: i32 = -127;
+const RSSI_MAX: i32 = 20;
+
+/// Side of the thresholds the RSSI was last reported on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RssiZone {
+    Low,
+    High,
+}
+
+/// RSSI monitor of a connection.
+pub(crate) struct RssiMonitor {
+    low: i32,
+    high: i32,
+    pub period: Duration,
+    // None until the RSSI first reaches a threshold.
+    zone: Option<RssiZone>,
+    /// Reads sent for the monitor whose result is not reported yet.
+    pub polls_pending: u32,
+    /// Pending sample, cancelled when the monitor is dropped.
+    pub poll: Option<JoinHandle<()>>,
+}
+
+impl RssiMonitor {
+    /// Monitors the RSSI between `low` and `high` dBm, sampled every `sampling_period_ms`.
+    pub(crate) fn new(low: i32, high: i32, sampling_period_ms: i32) -> BtResult<Self> {
+        if low < RSSI_MIN || high > RSSI_MAX || low >= high {
+            return Err(BtError::invalid_argument(format!(
+                "Invalid RSSI thresholds {} and {} dBm",
+                low, high
+            )));
+        }
+
+        let period = Duration::from_millis(sampling_period_ms.max(0) as u64);
+        if period < MIN_RSSI_SAMPLING_PERIOD {
+            return Err(BtError::invalid_argument(format!(
+                "RSSI sampling period of {} ms is shorter than {} ms",
+                sampling_period_ms,
+                MIN_RSSI_SAMPLING_PERIOD.as_millis()
+            )));
+        }
+
+        Ok(RssiMonitor { low, high, period, zone: None, polls_pending: 0, poll: None })
+    }
+
+    /// Compares a sample with the thresholds. Returns the threshold the RSSI reached, if it is
+    /// not on the side last reported.
+    pub(crate) fn sample(&mut self, rssi: i32) -> Option<i32> {
+        let (zone, threshold) = if rssi >= self.high {
+            (RssiZone::High, self.high)
+        } else if rssi <= self.low {
+            (RssiZone::Low, self.low)
+        } else {
+            return None;
+        };
+
+        if self.zone == Some(zone) {
+            return None;
+        }
+        self.zone = Some(zone);
+        Some(threshold)
+    }
+}
+
+impl Drop for RssiMonitor {
+    fn drop(&mut self) {
+        if let Some(poll) = self.poll.take() {
+            poll.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        assert!(RssiMonitor::new(-80, -50, 1000).is_ok());
+        assert!(RssiMonitor::new(-50, -80, 1000).is_err());
+        assert!(RssiMonitor::new(-50, -50, 1000).is_err());
+        assert!(RssiMonitor::new(-128, -50, 1000).is_err());
+        assert!(RssiMonitor::new(-80, -50, 99).is_err());
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut monitor = RssiMonitor::new(-80, -50, 1000).unwrap();
+        assert_eq!(monitor.sample(-60), None);
+        assert_eq!(monitor.sample(-50), Some(-50));
+        assert_eq!(monitor.sample(-45), None);
+        // Falling under the high threshold is not reported until the low one is reached.
+        assert_eq!(monitor.sample(-55), None);
+        assert_eq!(monitor.sample(-49), None);
+        assert_eq!(monitor.sample(-85), Some(-80));
+        assert_eq!(monitor.sample(-80), None);
+        assert_eq!(monitor.sample(-40), Some(-50));
+
+        // The first sample is reported if it is already past a threshold.
+        let mut monitor = RssiMonitor::new(-80, -50, 1000).unwrap();
+        assert_eq!(monitor.sample(-90), Some(-80));
+    }
+}
@@ -0,0 +1,123 @@
+//! Smoothing of the RSSI of the devices found by the scanners, see
+//! `ScanSettings::rssi_smoothing_window`.
+
+use crate::address::BtAddress;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Largest window of the RSSI smoothing, beyond which the average would lag behind a device moving
+/// for several seconds.
+pub const MAX_RSSI_SMOOTHING_WINDOW: i32 = 32;
+
+/// Time without a sample after which the average RSSI of a device is dropped, the device having
+/// likely moved or rotated its address.
+const RSSI_AVERAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of devices whose RSSI is smoothed by a scanner. The least recently seen device
+/// is dropped first.
+const MAX_RSSI_SMOOTHED_DEVICES: usize = 1024;
+
+/// Smooths the RSSI of found devices with an exponentially weighted moving average.
+pub(crate) struct RssiSmoother {
+    alpha: f64,
+    // Average and time of the last sample of each device.
+    averages: HashMap<BtAddress, (f64, Instant)>,
+}
+
+impl RssiSmoother {
+    pub(crate) fn new(window: i32) -> RssiSmoother {
+        let window = window.min(MAX_RSSI_SMOOTHING_WINDOW);
+        let alpha = if window <= 1 { 1.0 } else { 2.0 / (window as f64 + 1.0) };
+        RssiSmoother { alpha, averages: HashMap::new() }
+    }
+
+    /// Feeds a new calibrated RSSI sample for `address` and returns the smoothed value.
+    pub(crate) fn update(&mut self, address: &BtAddress, rssi: i32) -> i32 {
+        self.update_at(address, rssi, Instant::now())
+    }
+
+    fn update_at(&mut self, address: &BtAddress, rssi: i32, now: Instant) -> i32 {
+        // Nothing to remember when smoothing is disabled.
+        if self.alpha >= 1.0 {
+            return rssi;
+        }
+
+        if !self.averages.contains_key(address) && self.averages.len() >= MAX_RSSI_SMOOTHED_DEVICES
+        {
+            self.averages.retain(|_, (_, seen)| now.duration_since(*seen) < RSSI_AVERAGE_TIMEOUT);
+            if self.averages.len() >= MAX_RSSI_SMOOTHED_DEVICES {
+                let oldest =
+                    self.averages.iter().min_by_key(|(_, (_, seen))| *seen).map(|(addr, _)| *addr);
+                if let Some(oldest) = oldest {
+                    self.averages.remove(&oldest);
+                }
+            }
+        }
+
+        let alpha = self.alpha;
+        let (average, seen) = self.averages.entry(*address).or_insert((rssi as f64, now));
+        if now.duration_since(*seen) >= RSSI_AVERAGE_TIMEOUT {
+            *average = rssi as f64;
+        } else {
+            *average = alpha * rssi as f64 + (1.0 - alpha) * *average;
+        }
+        *seen = now;
+
+        average.round() as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bt_address(addr: &str) -> BtAddress {
+        BtAddress::from_string(addr).unwrap()
+    }
+
+    #[test]
+    fn test_rssi_smoother() {
+        let addr1 = bt_address("aa:bb:cc:dd:ee:ff");
+        let addr2 = bt_address("11:22:33:44:55:66");
+
+        // Smoothing disabled.
+        let mut smoother = RssiSmoother::new(0);
+        assert_eq!(-60, smoother.update(&addr1, -60));
+        assert_eq!(-80, smoother.update(&addr1, -80));
+
+        // A window of 3 gives half of the weight to the latest sample.
+        let mut smoother = RssiSmoother::new(3);
+        assert_eq!(-60, smoother.update(&addr1, -60));
+        assert_eq!(-70, smoother.update(&addr1, -80));
+        assert_eq!(-65, smoother.update(&addr1, -60));
+
+        // Devices are smoothed independently.
+        assert_eq!(-40, smoother.update(&addr2, -40));
+
+        // Larger windows are capped.
+        let smoother = RssiSmoother::new(i32::MAX);
+        assert_eq!(RssiSmoother::new(MAX_RSSI_SMOOTHING_WINDOW).alpha, smoother.alpha);
+    }
+
+    #[test]
+    fn test_rssi_smoother_forgets_devices() {
+        let addr = bt_address("aa:bb:cc:dd:ee:ff");
+        let now = Instant::now();
+
+        // The average restarts once the device has not been seen for a while.
+        let mut smoother = RssiSmoother::new(3);
+        assert_eq!(-60, smoother.update_at(&addr, -60, now));
+        assert_eq!(-80, smoother.update_at(&addr, -80, now + RSSI_AVERAGE_TIMEOUT));
+
+        // The least recently seen device is dropped when the smoother is full.
+        let mut smoother = RssiSmoother::new(3);
+        smoother.update_at(&addr, -60, now);
+        for i in 0..MAX_RSSI_SMOOTHED_DEVICES {
+            let other = bt_address(&format!("11:22:33:44:{:02x}:{:02x}", i >> 8, i & 0xff));
+            smoother.update_at(&other, -40, now + Duration::from_millis(1));
+        }
+        assert_eq!(MAX_RSSI_SMOOTHED_DEVICES, smoother.averages.len());
+        assert!(!smoother.averages.contains_key(&addr));
+    }
+}
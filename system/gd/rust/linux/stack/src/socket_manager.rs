@@ -0,0 +1,1135 @@
+//! Socket API (IBluetoothSocketManager), for RFCOMM and L2CAP connection-oriented channels whose
+//! data flows directly between the clients and libbluetooth through file descriptors.
+//!
+//! The data of the L2CAP Enhanced Credit Based Flow Control (ECFC) channels goes through the
+//! socket manager instead, which relays the SDUs between a socket pair per channel and libbluetooth.
+//! The relay of a channel is fed by the L2CAP callbacks directly, off the stack main dispatch loop.
+//! The SDUs received are acknowledged once delivered to the client, so that the peer is not given
+//! credits while the client does not read, and the client may only write a few SDUs ahead of L2CAP.
+
+use bt_topshim::btif::{BluetoothInterface, BtStatus, RawAddress, Uuid, Uuid128Bit};
+use bt_topshim::l2cap::{
+    EcfcChannelInfo, L2cap, L2capCallbacks, L2capCallbacksDispatcher, ECFC_RESULT_SUCCESS,
+};
+use bt_topshim::profiles::socket::{
+    self, BtSocket, ConnectionComplete, SocketFlags, SocketType, CHANNEL_SIZE,
+    CONNECTION_COMPLETE_SIZE,
+};
+use bt_topshim::topstack;
+
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+use tokio::io::unix::AsyncFd;
+use tokio::sync::mpsc::{self, error::TrySendError, Sender};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+
+use crate::error::{BtError, BtErrorCategory, BtResult};
+use crate::{Message, RPCProxy};
+
+/// Identifies a socket created by the socket manager.
+pub type SocketId = u64;
+
+// The sockets are not accounted per application.
+const SOCKET_CALLING_UID: i32 = 0;
+
+// Smallest MTU of the ECFC channels, see Core 5.3 Vol 3 Part A 4.25.
+const ECFC_MIN_MTU: i32 = 64;
+
+// Most channels opened in a single ECFC request.
+const ECFC_MAX_CHANNELS: i32 = 5;
+
+// Range of the dynamic LE PSMs, to which the ECFC connections are limited.
+const LE_DYNAMIC_PSM_START: i32 = 0x80;
+const LE_DYNAMIC_PSM_END: i32 = 0xFF;
+
+// Most SDUs written by the client of an ECFC channel that L2CAP has not taken yet.
+const ECFC_TX_QUEUE_SIZE: usize = 8;
+
+/// Defines the Socket API.
+pub trait IBluetoothSocketManager {
+    /// Adds an observer of the sockets created with its id.
+    ///
+    /// Returns the id of the callback.
+    fn register_callback(&mut self, callback: Box<dyn IBluetoothSocketCallback + Send>) -> u32;
+
+    /// Removes an observer and closes the sockets created with it.
+    ///
+    /// Returns false if `callback_id` is not recognized.
+    fn unregister_callback(&mut self, callback_id: u32) -> bool;
+
+    /// Listens for RFCOMM connections to the service `uuid`, advertised with a service record
+    /// named `name`. Connections are delivered with
+    /// `IBluetoothSocketCallback::on_incoming_connection`.
+    fn listen_using_rfcomm(
+        &mut self,
+        callback_id: u32,
+        name: String,
+        uuid: Uuid128Bit,
+        secure: bool,
+    ) -> BtResult<SocketId>;
+
+    /// Listens for LE L2CAP connection-oriented channels on a PSM allocated by the stack, which is
+    /// delivered with `IBluetoothSocketCallback::on_listening`.
+    fn listen_using_l2cap_channel(&mut self, callback_id: u32, secure: bool) -> BtResult<SocketId>;
+
+    /// Connects to the RFCOMM service `uuid` of a device. The result is delivered with
+    /// `IBluetoothSocketCallback::on_outgoing_connection` or `on_socket_closed`.
+    fn connect_rfcomm(
+        &mut self,
+        callback_id: u32,
+        addr: String,
+        uuid: Uuid128Bit,
+        secure: bool,
+    ) -> BtResult<SocketId>;
+
+    /// Connects to an LE L2CAP connection-oriented channel of a device. The result is delivered
+    /// with `IBluetoothSocketCallback::on_outgoing_connection` or `on_socket_closed`.
+    fn connect_l2cap_channel(
+        &mut self,
+        callback_id: u32,
+        addr: String,
+        psm: i32,
+        secure: bool,
+    ) -> BtResult<SocketId>;
+
+    /// Listens for LE L2CAP Enhanced Credit Based Flow Control channels, accepting SDUs of up to
+    /// `mtu` bytes, on a PSM allocated by the stack which is delivered with
+    /// `IBluetoothSocketCallback::on_listening`. Each channel opened by a device is delivered with
+    /// `on_incoming_connection`.
+    fn listen_using_l2cap_ecfc(
+        &mut self,
+        callback_id: u32,
+        mtu: i32,
+        secure: bool,
+    ) -> BtResult<SocketId>;
+
+    /// Opens `channel_count` (up to 5) ECFC channels to `psm` in a single request, the device
+    /// being connected over LE. Each established channel is delivered with
+    /// `IBluetoothSocketCallback::on_outgoing_connection`, and `on_socket_closed` is called if
+    /// none could be established.
+    fn connect_l2cap_ecfc(
+        &mut self,
+        callback_id: u32,
+        addr: String,
+        psm: i32,
+        channel_count: i32,
+        mtu: i32,
+        secure: bool,
+    ) -> BtResult<SocketId>;
+
+    /// Closes a listening socket, or cancels an outgoing connection. Established connections
+    /// belong to the client, which closes them by closing their file descriptor.
+    fn close(&mut self, socket_id: SocketId) -> BtResult<()>;
+}
+
+/// Socket events.
+pub trait IBluetoothSocketCallback: RPCProxy {
+    /// When a listening socket is ready, with the RFCOMM channel or L2CAP PSM it listens on.
+    fn on_listening(&self, socket_id: SocketId, channel: i32);
+
+    /// When a device connects to a listening socket. The data of the connection flows through
+    /// `fd`.
+    fn on_incoming_connection(
+        &self,
+        socket_id: SocketId,
+        connection: BluetoothSocketConnection,
+        fd: File,
+    );
+
+    /// When an outgoing connection is established. The data of the connection flows through
+    /// `fd`.
+    fn on_outgoing_connection(
+        &self,
+        socket_id: SocketId,
+        connection: BluetoothSocketConnection,
+        fd: File,
+    );
+
+    /// When the stack closes a listening socket, or an outgoing connection fails, in which case
+    /// `status` is not `Success`.
+    fn on_socket_closed(&self, socket_id: SocketId, status: BtStatus);
+}
+
+/// An established RFCOMM or L2CAP connection.
+#[derive(Clone, Debug, Default)]
+pub struct BluetoothSocketConnection {
+    pub addr: String,
+    /// RFCOMM channel or L2CAP PSM.
+    pub channel: i32,
+    /// Writes must not exceed this size to avoid losing data (L2CAP only).
+    pub max_tx_packet_size: u16,
+    /// Reads must use a buffer of at least this size to avoid losing data (L2CAP only).
+    pub max_rx_packet_size: u16,
+    /// Size of the PDUs the sent SDUs are segmented into (ECFC only).
+    pub max_tx_pdu_size: u16,
+    /// Size of the PDUs the received SDUs are segmented into (ECFC only).
+    pub max_rx_pdu_size: u16,
+}
+
+impl From<ConnectionComplete> for BluetoothSocketConnection {
+    fn from(item: ConnectionComplete) -> Self {
+        BluetoothSocketConnection {
+            addr: item.addr.to_string(),
+            channel: item.channel,
+            max_tx_packet_size: item.max_tx_packet_size,
+            max_rx_packet_size: item.max_rx_packet_size,
+            ..Default::default()
+        }
+    }
+}
+
+impl BluetoothSocketConnection {
+    fn from_ecfc(addr: &RawAddress, info: &EcfcChannelInfo) -> Self {
+        BluetoothSocketConnection {
+            addr: addr.to_string(),
+            channel: info.psm.into(),
+            max_tx_packet_size: info.peer_mtu,
+            max_rx_packet_size: info.local_mtu,
+            max_tx_pdu_size: info.peer_mps,
+            max_rx_pdu_size: info.local_mps,
+        }
+    }
+}
+
+/// Events read from the sockets, sent to the stack main dispatch loop.
+pub enum SocketActions {
+    /// The socket listens on the RFCOMM channel or L2CAP PSM.
+    Listening(SocketId, i32),
+    /// A listening socket accepted a connection, or an outgoing connection was established.
+    Connected(SocketId, ConnectionComplete, File),
+    /// The stack closed the socket.
+    Closed(SocketId, BtStatus),
+    /// The client closed its end of the ECFC channel with the local CID.
+    EcfcChannelClosed(u16),
+}
+
+struct Socket {
+    callback_id: u32,
+    listening: bool,
+    // Reads the events of the socket. Aborting it closes the socket.
+    task: JoinHandle<()>,
+}
+
+struct EcfcSocket {
+    callback_id: u32,
+    request_id: u32,
+    listening: bool,
+    // PSM listened on, once registered.
+    psm: Option<u16>,
+    // Channels whose result is awaited, once the request is sent (outgoing only).
+    pending: Option<u8>,
+    connected: u8,
+}
+
+/// Data path of an established ECFC channel, shared with the L2CAP callbacks.
+struct EcfcDataPath {
+    // SDUs received on the channel, which cannot outnumber the credits given to the peer.
+    received: mpsc::Sender<Vec<u8>>,
+    // Taken by the relay of the channel once it is delivered to the client.
+    receiver: Option<mpsc::Receiver<Vec<u8>>>,
+    // SDUs the client may still write ahead of L2CAP.
+    send_permits: Arc<Semaphore>,
+}
+
+/// Data paths of the ECFC channels by local CID.
+type EcfcDataPaths = Arc<Mutex<HashMap<u16, EcfcDataPath>>>;
+
+/// Handles the L2CAP callbacks of the ECFC data paths as they are called. Returns the message to
+/// send to the stack main dispatch loop, if any.
+fn dispatch_ecfc_data_path(paths: &EcfcDataPaths, cb: L2capCallbacks) -> Option<Message> {
+    let mut paths = paths.lock().unwrap();
+    match cb {
+        L2capCallbacks::EcfcConnected(_, _, result, info) if result == ECFC_RESULT_SUCCESS => {
+            // Set up now, the first SDUs may be received before the channel reaches the socket
+            // manager.
+            let (received, receiver) = mpsc::channel(usize::from(info.local_credits) + 1);
+            let send_permits = Arc::new(Semaphore::new(ECFC_TX_QUEUE_SIZE));
+            paths.insert(
+                info.lcid,
+                EcfcDataPath { received, receiver: Some(receiver), send_permits },
+            );
+            Some(Message::L2cap(cb))
+        }
+        L2capCallbacks::EcfcData(lcid, data) => {
+            let path = paths.get(&lcid)?;
+            match path.received.try_send(data) {
+                Ok(()) | Err(TrySendError::Closed(_)) => None,
+                Err(TrySendError::Full(_)) => {
+                    warn!("ECFC channel {:#06x} received more SDUs than its credits", lcid);
+                    Some(Message::SocketManager(SocketActions::EcfcChannelClosed(lcid)))
+                }
+            }
+        }
+        L2capCallbacks::EcfcSent(lcid, count) => {
+            if let Some(path) = paths.get(&lcid) {
+                path.send_permits.add_permits(count.into());
+            }
+            None
+        }
+        L2capCallbacks::EcfcDisconnected(lcid) => {
+            // The relay delivers the SDUs already received before closing the socket.
+            paths.remove(&lcid);
+            Some(Message::L2cap(cb))
+        }
+        cb => Some(Message::L2cap(cb)),
+    }
+}
+
+/// Returns a pair of connected sockets which keep the boundaries of the SDUs. The first one is
+/// non-blocking and kept by the stack.
+fn ecfc_socket_pair() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    let flags = libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC;
+    if unsafe { libc::socketpair(libc::AF_UNIX, flags, 0, fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let (local, remote) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    if unsafe { libc::fcntl(fds[0], libc::F_SETFL, libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((local, remote))
+}
+
+/// Receives an SDU written by the client. Returns its full size, which exceeds `buf.len()` if it
+/// was truncated.
+fn receive_sdu(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    let len = unsafe {
+        libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), libc::MSG_TRUNC)
+    };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(len as usize)
+}
+
+/// Relays the SDUs of an ECFC channel between the client on `fd` and L2CAP, until either closes
+/// the channel.
+async fn run_ecfc_channel(
+    info: EcfcChannelInfo,
+    fd: AsyncFd<File>,
+    mut received: mpsc::Receiver<Vec<u8>>,
+    send_permits: Arc<Semaphore>,
+    l2cap: Arc<Mutex<L2cap>>,
+    tx: Sender<Message>,
+) {
+    let lcid = info.lcid;
+    // Large enough to tell the SDUs exceeding the peer MTU, which are dropped.
+    let mut buf = vec![0u8; info.local_mtu.max(info.peer_mtu).into()];
+    // SDU received that the client has not read yet.
+    let mut delivering: Option<Vec<u8>> = None;
+    // Taken before reading an SDU written by the client, and released by L2CAP once sent.
+    let mut permit = None;
+
+    loop {
+        tokio::select! {
+            sdu = received.recv(), if delivering.is_none() => match sdu {
+                Some(sdu) => delivering = Some(sdu),
+                // Disconnected, closing the socket lets the client read the end of the stream.
+                None => return,
+            },
+            guard = fd.writable(), if delivering.is_some() => {
+                let mut guard = match guard {
+                    Ok(guard) => guard,
+                    Err(_) => break,
+                };
+                let sdu = delivering.as_deref().unwrap_or_default();
+                match guard.try_io(|inner| inner.get_ref().write(sdu)) {
+                    Ok(Ok(_)) => {
+                        delivering = None;
+                        l2cap.lock().unwrap().ack_ecfc(lcid);
+                    }
+                    Ok(Err(_)) => break,
+                    Err(_would_block) => (),
+                }
+            }
+            acquired = send_permits.acquire(), if permit.is_none() => match acquired {
+                Ok(acquired) => permit = Some(acquired),
+                Err(_) => break,
+            },
+            guard = fd.readable(), if permit.is_some() => {
+                let mut guard = match guard {
+                    Ok(guard) => guard,
+                    Err(_) => break,
+                };
+                match guard.try_io(|inner| receive_sdu(inner.as_raw_fd(), &mut buf)) {
+                    Ok(Ok(0)) | Ok(Err(_)) => break,
+                    Ok(Ok(len)) if len > usize::from(info.peer_mtu) => {
+                        warn!("Dropping an SDU of {} bytes of ECFC channel {:#06x}", len, lcid);
+                    }
+                    Ok(Ok(len)) => {
+                        // Given back with `EcfcSent`.
+                        if let Some(permit) = permit.take() {
+                            permit.forget();
+                        }
+                        l2cap.lock().unwrap().write_ecfc(lcid, buf[..len].to_vec());
+                    }
+                    Err(_would_block) => (),
+                }
+            }
+        }
+    }
+
+    let _ = tx.send(Message::SocketManager(SocketActions::EcfcChannelClosed(lcid))).await;
+}
+
+/// Reads `buf.len()` bytes written by the stack on a socket. Returns the file descriptor passed
+/// along with them, if any.
+async fn receive_exact(fd: &AsyncFd<File>, buf: &mut [u8]) -> io::Result<Option<File>> {
+    let mut received = 0;
+    let mut passed_fd = None;
+
+    while received < buf.len() {
+        let mut guard = fd.readable().await?;
+        match guard.try_io(|inner| socket::receive_with_fd(inner.as_raw_fd(), &mut buf[received..]))
+        {
+            Ok(Ok((0, _))) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(Ok((len, fd))) => {
+                received += len;
+                if let Some(fd) = fd {
+                    passed_fd = Some(unsafe { File::from_raw_fd(fd) });
+                }
+            }
+            Ok(Err(e)) => return Err(e),
+            Err(_would_block) => continue,
+        }
+    }
+
+    Ok(passed_fd)
+}
+
+/// Forwards the events of a socket to the stack until it is closed.
+async fn run_socket(id: SocketId, file: File, listening: bool, tx: Sender<Message>) {
+    let fd = match AsyncFd::new(file) {
+        Ok(fd) => fd,
+        Err(e) => {
+            warn!("Failed to watch socket {}: {}", id, e);
+            let _ =
+                tx.send(Message::SocketManager(SocketActions::Closed(id, BtStatus::Fail))).await;
+            return;
+        }
+    };
+
+    let mut channel = [0u8; CHANNEL_SIZE];
+    if receive_exact(&fd, &mut channel).await.is_err() {
+        let _ = tx.send(Message::SocketManager(SocketActions::Closed(id, BtStatus::Fail))).await;
+        return;
+    }
+    if listening {
+        let channel = i32::from_ne_bytes(channel);
+        let _ = tx.send(Message::SocketManager(SocketActions::Listening(id, channel))).await;
+    }
+
+    loop {
+        let mut signal = [0u8; CONNECTION_COMPLETE_SIZE];
+        let passed_fd = match receive_exact(&fd, &mut signal).await {
+            Ok(passed_fd) => passed_fd,
+            Err(_) => break,
+        };
+
+        let complete = match ConnectionComplete::from_bytes(&signal) {
+            Some(complete) => complete,
+            None => break,
+        };
+
+        if !listening {
+            let action = if complete.status == 0 {
+                SocketActions::Connected(id, complete, fd.into_inner())
+            } else {
+                SocketActions::Closed(id, BtStatus::Fail)
+            };
+            let _ = tx.send(Message::SocketManager(action)).await;
+            return;
+        }
+
+        match passed_fd {
+            Some(passed_fd) => {
+                let action = SocketActions::Connected(id, complete, passed_fd);
+                let _ = tx.send(Message::SocketManager(action)).await;
+            }
+            None => warn!("Socket {} accepted a connection without its fd", id),
+        }
+    }
+
+    let status = if listening { BtStatus::Success } else { BtStatus::Fail };
+    let _ = tx.send(Message::SocketManager(SocketActions::Closed(id, status))).await;
+}
+
+/// Implementation of the Socket API.
+pub struct BluetoothSocketManager {
+    intf: Arc<Mutex<BluetoothInterface>>,
+    tx: Sender<Message>,
+    sock: Option<BtSocket>,
+    l2cap: Option<Arc<Mutex<L2cap>>>,
+    callbacks: HashMap<u32, Box<dyn IBluetoothSocketCallback + Send>>,
+    sockets: HashMap<SocketId, Socket>,
+    ecfc_sockets: HashMap<SocketId, EcfcSocket>,
+    // Relays of the established ECFC channels by local CID.
+    ecfc_channels: HashMap<u16, JoinHandle<()>>,
+    ecfc_paths: EcfcDataPaths,
+    next_socket_id: SocketId,
+    next_ecfc_request_id: u32,
+}
+
+impl BluetoothSocketManager {
+    pub fn new(
+        tx: Sender<Message>,
+        intf: Arc<Mutex<BluetoothInterface>>,
+    ) -> BluetoothSocketManager {
+        BluetoothSocketManager {
+            intf,
+            tx,
+            sock: None,
+            l2cap: None,
+            callbacks: HashMap::new(),
+            sockets: HashMap::new(),
+            ecfc_sockets: HashMap::new(),
+            ecfc_channels: HashMap::new(),
+            ecfc_paths: Arc::new(Mutex::new(HashMap::new())),
+            next_socket_id: 1,
+            next_ecfc_request_id: 1,
+        }
+    }
+
+    pub fn initialize(&mut self) {
+        self.sock = Some(BtSocket::new(&self.intf.lock().unwrap()));
+
+        let tx = self.tx.clone();
+        let paths = self.ecfc_paths.clone();
+        let mut l2cap = L2cap::new();
+        l2cap.initialize(L2capCallbacksDispatcher {
+            dispatch: Box::new(move |cb| {
+                let message = match dispatch_ecfc_data_path(&paths, cb) {
+                    Some(message) => message,
+                    None => return,
+                };
+                let tx_clone = tx.clone();
+                topstack::get_runtime().spawn(async move {
+                    let _ = tx_clone.send(message).await;
+                });
+            }),
+        });
+        self.l2cap = Some(Arc::new(Mutex::new(l2cap)));
+    }
+
+    pub(crate) fn remove_callback(&mut self, id: u32) -> bool {
+        match self.callbacks.get_mut(&id) {
+            Some(callback) => {
+                callback.unregister(id);
+                self.callbacks.remove(&id);
+                self.sockets.retain(|_, socket| {
+                    if socket.callback_id == id {
+                        socket.task.abort();
+                    }
+                    socket.callback_id != id
+                });
+
+                let ecfc_ids: Vec<SocketId> = self
+                    .ecfc_sockets
+                    .iter()
+                    .filter(|(_, socket)| socket.callback_id == id)
+                    .map(|(socket_id, _)| *socket_id)
+                    .collect();
+                for socket_id in ecfc_ids {
+                    self.close_ecfc_socket(socket_id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn dispatch_socket_actions(&mut self, action: SocketActions) {
+        match action {
+            SocketActions::Listening(id, channel) => {
+                debug!("Socket {} listening on channel {}", id, channel);
+                if let Some(socket) = self.sockets.get(&id) {
+                    if let Some(callback) = self.callbacks.get(&socket.callback_id) {
+                        callback.on_listening(id, channel);
+                    }
+                }
+            }
+            SocketActions::Connected(id, complete, fd) => {
+                let socket = match self.sockets.get(&id) {
+                    Some(socket) => socket,
+                    None => return,
+                };
+
+                let callback_id = socket.callback_id;
+                let listening = socket.listening;
+                if !listening {
+                    // The connection now belongs to the client.
+                    self.sockets.remove(&id);
+                }
+
+                if let Some(callback) = self.callbacks.get(&callback_id) {
+                    let connection = BluetoothSocketConnection::from(complete);
+                    if listening {
+                        callback.on_incoming_connection(id, connection, fd);
+                    } else {
+                        callback.on_outgoing_connection(id, connection, fd);
+                    }
+                }
+            }
+            SocketActions::Closed(id, status) => {
+                if let Some(socket) = self.sockets.remove(&id) {
+                    if let Some(callback) = self.callbacks.get(&socket.callback_id) {
+                        callback.on_socket_closed(id, status);
+                    }
+                }
+            }
+            SocketActions::EcfcChannelClosed(lcid) => {
+                let path = self.ecfc_paths.lock().unwrap().remove(&lcid);
+                let relay = self.ecfc_channels.remove(&lcid);
+                if path.is_some() || relay.is_some() {
+                    debug!("ECFC channel {:#06x} closed", lcid);
+                    if let Some(relay) = relay {
+                        relay.abort();
+                    }
+                    self.disconnect_ecfc(lcid);
+                }
+            }
+        }
+    }
+
+    pub fn dispatch_l2cap_callbacks(&mut self, cb: L2capCallbacks) {
+        match cb {
+            L2capCallbacks::EcfcListening(request_id, psm) => {
+                let id = match self.find_ecfc_socket(request_id) {
+                    Some(id) => id,
+                    None => {
+                        // Closed while the PSM was being registered.
+                        if psm != 0 {
+                            self.stop_listening_ecfc(psm);
+                        }
+                        return;
+                    }
+                };
+
+                if psm == 0 {
+                    self.remove_ecfc_socket(id, BtStatus::Fail);
+                    return;
+                }
+
+                let socket = self.ecfc_sockets.get_mut(&id).unwrap();
+                socket.psm = Some(psm);
+                if let Some(callback) = self.callbacks.get(&socket.callback_id) {
+                    callback.on_listening(id, psm.into());
+                }
+            }
+            L2capCallbacks::EcfcConnecting(request_id, channel_count) => {
+                let id = match self.find_ecfc_socket(request_id) {
+                    Some(id) => id,
+                    None => return,
+                };
+
+                if channel_count == 0 {
+                    self.remove_ecfc_socket(id, BtStatus::Fail);
+                    return;
+                }
+                self.ecfc_sockets.get_mut(&id).unwrap().pending = Some(channel_count);
+            }
+            L2capCallbacks::EcfcConnected(request_id, addr, result, info) => {
+                self.on_ecfc_connected(request_id, addr, result, info);
+            }
+            // Handled as they are called, see `dispatch_ecfc_data_path`.
+            L2capCallbacks::EcfcData(..) | L2capCallbacks::EcfcSent(..) => (),
+            L2capCallbacks::EcfcDisconnected(lcid) => {
+                // The relay ends once the SDUs already received are delivered.
+                if self.ecfc_channels.remove(&lcid).is_some() {
+                    debug!("ECFC channel {:#06x} disconnected", lcid);
+                }
+            }
+        }
+    }
+
+    fn on_ecfc_connected(
+        &mut self,
+        request_id: u32,
+        addr: RawAddress,
+        result: u16,
+        info: EcfcChannelInfo,
+    ) {
+        let id = self.find_ecfc_socket(request_id);
+        let socket = match id.and_then(|id| self.ecfc_sockets.get_mut(&id)) {
+            Some(socket) => socket,
+            None => {
+                // The socket was closed in the meantime.
+                if result == ECFC_RESULT_SUCCESS {
+                    self.ecfc_paths.lock().unwrap().remove(&info.lcid);
+                    self.disconnect_ecfc(info.lcid);
+                }
+                return;
+            }
+        };
+        let id = id.unwrap();
+
+        if let Some(pending) = socket.pending.as_mut() {
+            *pending = pending.saturating_sub(1);
+        }
+        if result != ECFC_RESULT_SUCCESS {
+            debug!("ECFC channel of socket {} failed with result {:#06x}", id, result);
+            self.complete_ecfc_request(id);
+            return;
+        }
+
+        // Missing if the channel was disconnected or closed in the meantime.
+        let path = self.ecfc_paths.lock().unwrap().get_mut(&info.lcid).and_then(|path| {
+            path.receiver.take().map(|receiver| (receiver, path.send_permits.clone()))
+        });
+        let (received, send_permits) = match path {
+            Some(path) => path,
+            None => {
+                self.complete_ecfc_request(id);
+                return;
+            }
+        };
+
+        let socket = self.ecfc_sockets.get_mut(&id).unwrap();
+        let listening = socket.listening;
+        let callback_id = socket.callback_id;
+        socket.connected = socket.connected.saturating_add(1);
+
+        let (local, remote) = match ecfc_socket_pair() {
+            Ok(pair) => pair,
+            Err(e) => {
+                warn!("Failed to create the socket pair of ECFC channel {:#06x}: {}", info.lcid, e);
+                self.ecfc_paths.lock().unwrap().remove(&info.lcid);
+                self.disconnect_ecfc(info.lcid);
+                self.complete_ecfc_request(id);
+                return;
+            }
+        };
+        let fd = match AsyncFd::new(local) {
+            Ok(fd) => fd,
+            Err(e) => {
+                warn!("Failed to watch ECFC channel {:#06x}: {}", info.lcid, e);
+                self.ecfc_paths.lock().unwrap().remove(&info.lcid);
+                self.disconnect_ecfc(info.lcid);
+                self.complete_ecfc_request(id);
+                return;
+            }
+        };
+
+        let relay = topstack::get_runtime().spawn(run_ecfc_channel(
+            info,
+            fd,
+            received,
+            send_permits,
+            self.l2cap.as_ref().unwrap().clone(),
+            self.tx.clone(),
+        ));
+        self.ecfc_channels.insert(info.lcid, relay);
+
+        if let Some(callback) = self.callbacks.get(&callback_id) {
+            let connection = BluetoothSocketConnection::from_ecfc(&addr, &info);
+            if listening {
+                callback.on_incoming_connection(id, connection, remote);
+            } else {
+                callback.on_outgoing_connection(id, connection, remote);
+            }
+        }
+        self.complete_ecfc_request(id);
+    }
+
+    /// Forgets an outgoing ECFC socket once the result of all its channels is known.
+    fn complete_ecfc_request(&mut self, id: SocketId) {
+        let (pending, connected) = match self.ecfc_sockets.get(&id) {
+            Some(socket) if !socket.listening => (socket.pending, socket.connected),
+            _ => return,
+        };
+
+        match (pending, connected) {
+            (Some(0), 0) => self.remove_ecfc_socket(id, BtStatus::Fail),
+            (Some(0), _) => {
+                self.ecfc_sockets.remove(&id);
+            }
+            _ => (),
+        }
+    }
+
+    fn find_ecfc_socket(&self, request_id: u32) -> Option<SocketId> {
+        self.ecfc_sockets.iter().find(|(_, s)| s.request_id == request_id).map(|(id, _)| *id)
+    }
+
+    /// Forgets an ECFC socket that the stack closed, or could not open.
+    fn remove_ecfc_socket(&mut self, id: SocketId, status: BtStatus) {
+        if let Some(socket) = self.ecfc_sockets.remove(&id) {
+            if let Some(callback) = self.callbacks.get(&socket.callback_id) {
+                callback.on_socket_closed(id, status);
+            }
+        }
+    }
+
+    /// Closes an ECFC socket on behalf of the client. Established channels are not affected.
+    fn close_ecfc_socket(&mut self, id: SocketId) -> bool {
+        match self.ecfc_sockets.remove(&id) {
+            Some(socket) => {
+                if let Some(psm) = socket.psm {
+                    self.stop_listening_ecfc(psm);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn stop_listening_ecfc(&self, psm: u16) {
+        if let Some(l2cap) = self.l2cap.as_ref() {
+            l2cap.lock().unwrap().stop_listening_ecfc(psm);
+        }
+    }
+
+    fn disconnect_ecfc(&self, lcid: u16) {
+        if let Some(l2cap) = self.l2cap.as_ref() {
+            l2cap.lock().unwrap().disconnect_ecfc(lcid);
+        }
+    }
+
+    fn get_l2cap(&self) -> BtResult<Arc<Mutex<L2cap>>> {
+        self.l2cap
+            .clone()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "Sockets are not initialized"))
+    }
+
+    /// Tracks an ECFC socket created for `callback_id`. Returns its id and the id of its request.
+    fn add_ecfc_socket(&mut self, callback_id: u32, listening: bool) -> (SocketId, u32) {
+        let id = self.next_socket_id;
+        self.next_socket_id += 1;
+        let request_id = self.next_ecfc_request_id;
+        self.next_ecfc_request_id = self.next_ecfc_request_id.wrapping_add(1).max(1);
+
+        self.ecfc_sockets.insert(
+            id,
+            EcfcSocket {
+                callback_id,
+                request_id,
+                listening,
+                psm: None,
+                pending: None,
+                connected: 0,
+            },
+        );
+        (id, request_id)
+    }
+
+    fn get_sock(&self) -> BtResult<&BtSocket> {
+        self.sock
+            .as_ref()
+            .ok_or_else(|| BtError::new(BtErrorCategory::NotReady, "Sockets are not initialized"))
+    }
+
+    /// Tracks a socket created for `callback_id` and starts reading its events.
+    fn add_socket(
+        &mut self,
+        callback_id: u32,
+        listening: bool,
+        result: (BtStatus, Option<i32>),
+    ) -> BtResult<SocketId> {
+        let fd = match result {
+            (BtStatus::Success, Some(fd)) => fd,
+            (BtStatus::Success, None) => return Err(BtError::from(BtStatus::Fail)),
+            (status, _) => return Err(BtError::from(status)),
+        };
+
+        let id = self.next_socket_id;
+        self.next_socket_id += 1;
+
+        let file = unsafe { File::from_raw_fd(fd) };
+        let task = topstack::get_runtime().spawn(run_socket(id, file, listening, self.tx.clone()));
+        self.sockets.insert(id, Socket { callback_id, listening, task });
+        Ok(id)
+    }
+
+    fn check_callback(&self, callback_id: u32) -> BtResult<()> {
+        if self.callbacks.contains_key(&callback_id) {
+            Ok(())
+        } else {
+            Err(BtError::not_found(format!("No callback {}", callback_id)))
+        }
+    }
+}
+
+fn security_flags(secure: bool) -> SocketFlags {
+    if secure {
+        SocketFlags::ENCRYPT | SocketFlags::AUTH
+    } else {
+        SocketFlags::NONE
+    }
+}
+
+fn parse_address(addr: &String) -> BtResult<RawAddress> {
+    RawAddress::from_string(addr.clone())
+        .ok_or_else(|| BtError::invalid_argument(format!("Invalid address {}", addr)))
+}
+
+fn ecfc_mtu(mtu: i32) -> BtResult<u16> {
+    if mtu < ECFC_MIN_MTU || mtu > u16::MAX.into() {
+        return Err(BtError::invalid_argument(format!(
+            "ECFC MTU {} is not in [{}, {}]",
+            mtu,
+            ECFC_MIN_MTU,
+            u16::MAX
+        )));
+    }
+    Ok(mtu as u16)
+}
+
+impl IBluetoothSocketManager for BluetoothSocketManager {
+    fn register_callback(&mut self, mut callback: Box<dyn IBluetoothSocketCallback + Send>) -> u32 {
+        let tx = self.tx.clone();
+
+        let id = callback.register_disconnect(Box::new(move |cb_id| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _result = tx.send(Message::SocketManagerCallbackDisconnected(cb_id)).await;
+            });
+        }));
+
+        self.callbacks.insert(id, callback);
+        id
+    }
+
+    fn unregister_callback(&mut self, callback_id: u32) -> bool {
+        self.remove_callback(callback_id)
+    }
+
+    fn listen_using_rfcomm(
+        &mut self,
+        callback_id: u32,
+        name: String,
+        uuid: Uuid128Bit,
+        secure: bool,
+    ) -> BtResult<SocketId> {
+        self.check_callback(callback_id)?;
+        let result = self.get_sock()?.listen(
+            SocketType::Rfcomm,
+            name,
+            Some(Uuid { uu: uuid }),
+            0,
+            security_flags(secure),
+            SOCKET_CALLING_UID,
+        );
+        self.add_socket(callback_id, true, result)
+    }
+
+    fn listen_using_l2cap_channel(&mut self, callback_id: u32, secure: bool) -> BtResult<SocketId> {
+        self.check_callback(callback_id)?;
+        // Without a service record, the stack allocates the PSM.
+        let result = self.get_sock()?.listen(
+            SocketType::L2capLe,
+            String::from(""),
+            None,
+            0,
+            security_flags(secure) | SocketFlags::NO_SDP,
+            SOCKET_CALLING_UID,
+        );
+        self.add_socket(callback_id, true, result)
+    }
+
+    fn connect_rfcomm(
+        &mut self,
+        callback_id: u32,
+        addr: String,
+        uuid: Uuid128Bit,
+        secure: bool,
+    ) -> BtResult<SocketId> {
+        self.check_callback(callback_id)?;
+        let address = parse_address(&addr)?;
+        let result = self.get_sock()?.connect(
+            address,
+            SocketType::Rfcomm,
+            Some(Uuid { uu: uuid }),
+            0,
+            security_flags(secure),
+            SOCKET_CALLING_UID,
+        );
+        self.add_socket(callback_id, false, result)
+    }
+
+    fn connect_l2cap_channel(
+        &mut self,
+        callback_id: u32,
+        addr: String,
+        psm: i32,
+        secure: bool,
+    ) -> BtResult<SocketId> {
+        self.check_callback(callback_id)?;
+        let address = parse_address(&addr)?;
+        let result = self.get_sock()?.connect(
+            address,
+            SocketType::L2capLe,
+            None,
+            psm,
+            security_flags(secure),
+            SOCKET_CALLING_UID,
+        );
+        self.add_socket(callback_id, false, result)
+    }
+
+    fn listen_using_l2cap_ecfc(
+        &mut self,
+        callback_id: u32,
+        mtu: i32,
+        secure: bool,
+    ) -> BtResult<SocketId> {
+        self.check_callback(callback_id)?;
+        let mtu = ecfc_mtu(mtu)?;
+        let l2cap = self.get_l2cap()?;
+
+        let (id, request_id) = self.add_ecfc_socket(callback_id, true);
+        l2cap.lock().unwrap().listen_ecfc(request_id, mtu, secure);
+        Ok(id)
+    }
+
+    fn connect_l2cap_ecfc(
+        &mut self,
+        callback_id: u32,
+        addr: String,
+        psm: i32,
+        channel_count: i32,
+        mtu: i32,
+        secure: bool,
+    ) -> BtResult<SocketId> {
+        self.check_callback(callback_id)?;
+        let address = parse_address(&addr)?;
+        if psm < LE_DYNAMIC_PSM_START || psm > LE_DYNAMIC_PSM_END {
+            return Err(BtError::invalid_argument(format!("Invalid ECFC PSM {:#x}", psm)));
+        }
+        if channel_count < 1 || channel_count > ECFC_MAX_CHANNELS {
+            return Err(BtError::invalid_argument(format!(
+                "Invalid ECFC channel count {}",
+                channel_count
+            )));
+        }
+        let mtu = ecfc_mtu(mtu)?;
+        let l2cap = self.get_l2cap()?;
+
+        let (id, request_id) = self.add_ecfc_socket(callback_id, false);
+        l2cap.lock().unwrap().connect_ecfc(
+            request_id,
+            address,
+            psm as u16,
+            channel_count as u8,
+            mtu,
+            secure,
+        );
+        Ok(id)
+    }
+
+    fn close(&mut self, socket_id: SocketId) -> BtResult<()> {
+        if self.close_ecfc_socket(socket_id) {
+            return Ok(());
+        }
+
+        match self.sockets.remove(&socket_id) {
+            Some(socket) => {
+                socket.task.abort();
+                Ok(())
+            }
+            None => Err(BtError::not_found(format!("No socket {}", socket_id))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn ecfc_info(lcid: u16, local_credits: u16) -> EcfcChannelInfo {
+        EcfcChannelInfo { lcid, local_credits, ..Default::default() }
+    }
+
+    #[test]
+    fn test_ecfc_socket_pair_keeps_sdus() {
+        let (mut local, mut remote) = ecfc_socket_pair().unwrap();
+        remote.write_all(&[1, 2, 3]).unwrap();
+        remote.write_all(&[4, 5]).unwrap();
+
+        let mut buf = [0u8; 16];
+        assert_eq!(3, local.read(&mut buf).unwrap());
+        assert_eq!(2, local.read(&mut buf).unwrap());
+        assert_eq!(io::ErrorKind::WouldBlock, local.read(&mut buf).unwrap_err().kind());
+
+        drop(remote);
+        assert_eq!(0, local.read(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn test_receive_sdu_reports_truncation() {
+        let (local, mut remote) = ecfc_socket_pair().unwrap();
+        remote.write_all(&[1, 2, 3, 4]).unwrap();
+
+        let mut buf = [0u8; 2];
+        assert_eq!(4, receive_sdu(local.as_raw_fd(), &mut buf).unwrap());
+        assert_eq!([1, 2], buf);
+        assert_eq!(
+            io::ErrorKind::WouldBlock,
+            receive_sdu(local.as_raw_fd(), &mut buf).unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn test_ecfc_data_path() {
+        let paths: EcfcDataPaths = Arc::new(Mutex::new(HashMap::new()));
+        let connected = L2capCallbacks::EcfcConnected(
+            1,
+            RawAddress::default(),
+            ECFC_RESULT_SUCCESS,
+            ecfc_info(0x40, 1),
+        );
+        assert!(matches!(dispatch_ecfc_data_path(&paths, connected), Some(Message::L2cap(_))));
+        let mut receiver = paths.lock().unwrap().get_mut(&0x40).unwrap().receiver.take().unwrap();
+
+        // Not relayed to the stack main dispatch loop.
+        assert!(dispatch_ecfc_data_path(&paths, L2capCallbacks::EcfcData(0x40, vec![1])).is_none());
+        assert!(dispatch_ecfc_data_path(&paths, L2capCallbacks::EcfcData(0x40, vec![2])).is_none());
+        assert_eq!(vec![1], receiver.try_recv().unwrap());
+        assert_eq!(vec![2], receiver.try_recv().unwrap());
+
+        let permits = paths.lock().unwrap().get(&0x40).unwrap().send_permits.clone();
+        assert!(dispatch_ecfc_data_path(&paths, L2capCallbacks::EcfcSent(0x40, 2)).is_none());
+        assert_eq!(ECFC_TX_QUEUE_SIZE + 2, permits.available_permits());
+
+        // The SDUs received are delivered before the end of the stream.
+        dispatch_ecfc_data_path(&paths, L2capCallbacks::EcfcData(0x40, vec![3]));
+        let disconnected = L2capCallbacks::EcfcDisconnected(0x40);
+        assert!(matches!(dispatch_ecfc_data_path(&paths, disconnected), Some(Message::L2cap(_))));
+        assert!(paths.lock().unwrap().is_empty());
+        assert_eq!(vec![3], receiver.try_recv().unwrap());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_ecfc_data_path_closes_when_credits_exceeded() {
+        let paths: EcfcDataPaths = Arc::new(Mutex::new(HashMap::new()));
+        let connected = L2capCallbacks::EcfcConnected(
+            1,
+            RawAddress::default(),
+            ECFC_RESULT_SUCCESS,
+            ecfc_info(0x40, 1),
+        );
+        dispatch_ecfc_data_path(&paths, connected);
+
+        assert!(dispatch_ecfc_data_path(&paths, L2capCallbacks::EcfcData(0x40, vec![1])).is_none());
+        assert!(dispatch_ecfc_data_path(&paths, L2capCallbacks::EcfcData(0x40, vec![2])).is_none());
+        assert!(matches!(
+            dispatch_ecfc_data_path(&paths, L2capCallbacks::EcfcData(0x40, vec![3])),
+            Some(Message::SocketManager(SocketActions::EcfcChannelClosed(0x40)))
+        ));
+    }
+
+    #[test]
+    fn test_ecfc_mtu() {
+        assert_eq!(64, ecfc_mtu(64).unwrap());
+        assert_eq!(u16::MAX, ecfc_mtu(65535).unwrap());
+        assert!(ecfc_mtu(63).is_err());
+        assert!(ecfc_mtu(65536).is_err());
+    }
+}
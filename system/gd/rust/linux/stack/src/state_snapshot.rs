@@ -0,0 +1,348 @@
+//! Snapshot of the state of the stack, to attach to crash reports and support tickets.
+//!
+//! The snapshot is serialized as JSON. When redacted, the addresses are replaced by salted hashes
+//! and the device names are left out, so that the snapshot does not identify the devices while
+//! the entries of the same device can still be matched.
+
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of recent errors kept for the snapshots.
+pub(crate) const MAX_RECENT_ERRORS: usize = 32;
+
+/// Known device of the adapter, bonded or connected.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceSnapshot {
+    pub address: String,
+    pub name: String,
+    pub bonded: bool,
+    pub connected: bool,
+}
+
+/// State of the adapter.
+#[derive(Clone, Debug, Default)]
+pub struct AdapterSnapshot {
+    pub enabled: bool,
+    pub address: String,
+    pub name: String,
+    pub discoverable: bool,
+    pub connectable: bool,
+    pub discovering: bool,
+    pub le_scanning: bool,
+    pub le_advertising: bool,
+    pub devices: Vec<DeviceSnapshot>,
+}
+
+/// GATT connection, as a client or as a server.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionSnapshot {
+    pub conn_id: i32,
+    pub address: String,
+    /// ID of the client or of the server the connection belongs to.
+    pub app_id: i32,
+    pub is_server: bool,
+    pub mtu: usize,
+    /// Connection parameters last reported by the controller, in controller units. None until
+    /// the connection is updated.
+    pub interval: Option<u16>,
+    pub latency: Option<u16>,
+    pub timeout: Option<u16>,
+    pub congested: bool,
+    /// Operations waiting for their result, or notifications waiting to be sent on a server
+    /// connection.
+    pub queue_depth: usize,
+}
+
+/// LE scanner.
+#[derive(Clone, Debug, Default)]
+pub struct ScannerSnapshot {
+    pub scanner_id: Option<u8>,
+    pub scanning: bool,
+    pub priority: String,
+    pub interval: u16,
+    pub window: u16,
+    pub filters: usize,
+}
+
+/// Advertising set.
+#[derive(Clone, Debug, Default)]
+pub struct AdvertiserSnapshot {
+    pub reg_id: i32,
+    pub state: String,
+    pub enabled: bool,
+    pub adv_data_len: usize,
+    pub scan_rsp_len: usize,
+}
+
+/// Failed operation, kept for the snapshots.
+#[derive(Clone, Debug)]
+pub struct RecentError {
+    /// Seconds since the epoch.
+    pub time: u64,
+    pub address: String,
+    pub operation: String,
+    pub status: i32,
+}
+
+impl RecentError {
+    pub(crate) fn new(address: &str, operation: &str, status: i32) -> RecentError {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        RecentError { time, address: address.to_string(), operation: operation.to_string(), status }
+    }
+}
+
+/// Records `error`, dropping the oldest one once `MAX_RECENT_ERRORS` are kept.
+pub(crate) fn push_recent_error(errors: &mut VecDeque<RecentError>, error: RecentError) {
+    if errors.len() == MAX_RECENT_ERRORS {
+        errors.pop_front();
+    }
+    errors.push_back(error);
+}
+
+/// Depths of the queues of the stack, not tied to a connection.
+#[derive(Clone, Debug, Default)]
+pub struct QueueDepths {
+    /// Write results held back while the clients are congested.
+    pub congestion_queue: usize,
+    pub msft_requests: usize,
+    pub sync_transfers: usize,
+}
+
+/// State of the stack.
+#[derive(Clone, Debug, Default)]
+pub struct StateSnapshot {
+    /// Seconds since the epoch.
+    pub time: u64,
+    pub adapter: AdapterSnapshot,
+    pub connections: Vec<ConnectionSnapshot>,
+    pub scanners: Vec<ScannerSnapshot>,
+    pub advertisers: Vec<AdvertiserSnapshot>,
+    pub recent_errors: Vec<RecentError>,
+    pub queues: QueueDepths,
+}
+
+/// Replaces the personal information of a snapshot, see `StateSnapshot::to_json`.
+pub struct Redaction {
+    // None when the snapshot is not redacted.
+    salt: Option<u64>,
+}
+
+impl Redaction {
+    /// Keeps the addresses and names.
+    pub fn none() -> Redaction {
+        Redaction { salt: None }
+    }
+
+    /// Hashes the addresses with `salt` and leaves the names out.
+    pub fn hashed(salt: u64) -> Redaction {
+        Redaction { salt: Some(salt) }
+    }
+
+    fn address(&self, address: &str) -> String {
+        let salt = match self.salt {
+            Some(salt) => salt,
+            None => return address.to_string(),
+        };
+        if address.is_empty() {
+            return String::new();
+        }
+
+        let mut hasher = DefaultHasher::new();
+        salt.hash(&mut hasher);
+        address.to_uppercase().hash(&mut hasher);
+        // 48 bits, as many as an address.
+        format!("hash:{:012x}", hasher.finish() & 0xffff_ffff_ffff)
+    }
+
+    fn name(&self, name: &str) -> String {
+        match self.salt {
+            Some(_) => String::new(),
+            None => name.to_string(),
+        }
+    }
+}
+
+impl StateSnapshot {
+    /// Serializes the snapshot as a JSON object, with the personal information replaced as
+    /// selected by `redaction`.
+    pub fn to_json(&self, redaction: &Redaction) -> String {
+        let adapter = &self.adapter;
+        let devices: Vec<Value> = adapter
+            .devices
+            .iter()
+            .map(|d| {
+                json!({
+                    "address": redaction.address(&d.address),
+                    "name": redaction.name(&d.name),
+                    "bonded": d.bonded,
+                    "connected": d.connected,
+                })
+            })
+            .collect();
+
+        let connections: Vec<Value> = self
+            .connections
+            .iter()
+            .map(|c| {
+                json!({
+                    "conn_id": c.conn_id,
+                    "address": redaction.address(&c.address),
+                    "app_id": c.app_id,
+                    "is_server": c.is_server,
+                    "mtu": c.mtu,
+                    "interval": c.interval,
+                    "latency": c.latency,
+                    "timeout": c.timeout,
+                    "congested": c.congested,
+                    "queue_depth": c.queue_depth,
+                })
+            })
+            .collect();
+
+        let scanners: Vec<Value> = self
+            .scanners
+            .iter()
+            .map(|s| {
+                json!({
+                    "scanner_id": s.scanner_id,
+                    "scanning": s.scanning,
+                    "priority": s.priority,
+                    "interval": s.interval,
+                    "window": s.window,
+                    "filters": s.filters,
+                })
+            })
+            .collect();
+
+        let advertisers: Vec<Value> = self
+            .advertisers
+            .iter()
+            .map(|a| {
+                json!({
+                    "reg_id": a.reg_id,
+                    "state": a.state,
+                    "enabled": a.enabled,
+                    "adv_data_len": a.adv_data_len,
+                    "scan_rsp_len": a.scan_rsp_len,
+                })
+            })
+            .collect();
+
+        let recent_errors: Vec<Value> = self
+            .recent_errors
+            .iter()
+            .map(|e| {
+                json!({
+                    "time": e.time,
+                    "address": redaction.address(&e.address),
+                    "operation": e.operation,
+                    "status": e.status,
+                })
+            })
+            .collect();
+
+        json!({
+            "time": self.time,
+            "adapter": {
+                "enabled": adapter.enabled,
+                "address": redaction.address(&adapter.address),
+                "name": redaction.name(&adapter.name),
+                "discoverable": adapter.discoverable,
+                "connectable": adapter.connectable,
+                "discovering": adapter.discovering,
+                "le_scanning": adapter.le_scanning,
+                "le_advertising": adapter.le_advertising,
+                "devices": devices,
+            },
+            "connections": connections,
+            "scanners": scanners,
+            "advertisers": advertisers,
+            "recent_errors": recent_errors,
+            "queues": {
+                "congestion_queue": self.queues.congestion_queue,
+                "msft_requests": self.queues.msft_requests,
+                "sync_transfers": self.queues.sync_transfers,
+            },
+        })
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> StateSnapshot {
+        StateSnapshot {
+            adapter: AdapterSnapshot {
+                enabled: true,
+                address: String::from("00:11:22:33:44:55"),
+                name: String::from("My \"laptop\""),
+                devices: vec![DeviceSnapshot {
+                    address: String::from("AA:BB:CC:DD:EE:FF"),
+                    name: String::from("Headphones"),
+                    bonded: true,
+                    connected: true,
+                }],
+                ..Default::default()
+            },
+            connections: vec![ConnectionSnapshot {
+                conn_id: 3,
+                address: String::from("aa:bb:cc:dd:ee:ff"),
+                app_id: 1,
+                mtu: 247,
+                interval: Some(24),
+                ..Default::default()
+            }],
+            recent_errors: vec![RecentError {
+                time: 10,
+                address: String::from("AA:BB:CC:DD:EE:FF"),
+                operation: String::from("Connect"),
+                status: 133,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_to_json_unredacted() {
+        let json: Value = serde_json::from_str(&snapshot().to_json(&Redaction::none())).unwrap();
+
+        assert_eq!(json["time"], 0);
+        assert_eq!(json["adapter"]["enabled"], true);
+        assert_eq!(json["adapter"]["address"], "00:11:22:33:44:55");
+        assert_eq!(json["adapter"]["name"], "My \"laptop\"");
+        assert_eq!(json["connections"][0]["interval"], 24);
+        assert_eq!(json["connections"][0]["latency"], Value::Null);
+        assert_eq!(json["recent_errors"][0]["operation"], "Connect");
+        assert_eq!(json["recent_errors"][0]["status"], 133);
+    }
+
+    #[test]
+    fn test_to_json_redacted() {
+        let json = snapshot().to_json(&Redaction::hashed(42));
+
+        assert!(!json.contains("00:11:22:33:44:55"));
+        assert!(!json.to_uppercase().contains("AA:BB:CC:DD:EE:FF"));
+        assert!(!json.contains("Headphones"));
+
+        // The entries of a device are hashed the same regardless of the case of the address.
+        let hash = Redaction::hashed(42).address("AA:BB:CC:DD:EE:FF");
+        assert_eq!(json.matches(&hash).count(), 3);
+        assert_ne!(hash, Redaction::hashed(43).address("AA:BB:CC:DD:EE:FF"));
+    }
+
+    #[test]
+    fn test_recent_errors_capped() {
+        let mut errors = VecDeque::new();
+        for status in 0..MAX_RECENT_ERRORS as i32 + 2 {
+            push_recent_error(&mut errors, RecentError::new("", "Read", status));
+        }
+
+        assert_eq!(errors.len(), MAX_RECENT_ERRORS);
+        assert_eq!(errors.front().unwrap().status, 2);
+    }
+}
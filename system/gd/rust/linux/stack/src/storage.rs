@@ -0,0 +1,93 @@
+//! Files in which the stack keeps its state across restarts of the daemon.
+
+use bt_topshim::btif::Uuid128Bit;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+/// Mode of the files holding state which is not secret.
+pub(crate) const PUBLIC_FILE_MODE: u32 = 0o644;
+
+/// Mode of the files holding keys, only readable by the daemon.
+pub(crate) const SECRET_FILE_MODE: u32 = 0o600;
+
+/// Replaces the contents of `path` with `lines`. They are written aside then renamed over `path`,
+/// so that a crash never leaves a truncated file, and the file is created with `mode`.
+pub(crate) fn save_lines(path: &Path, lines: &[String], mode: u32) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+
+    // A file left by an interrupted save would keep its mode.
+    match std::fs::remove_file(&tmp) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+
+    let mut file = OpenOptions::new().write(true).create_new(true).mode(mode).open(&tmp)?;
+    for line in lines {
+        writeln!(file, "{}", line)?;
+    }
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Encodes bytes as lowercase hex, as the values are written in the state files.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes hex written by `to_hex`, None if it is malformed.
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// Decodes a UUID written by `to_hex`.
+pub(crate) fn parse_uuid(hex: &str) -> Option<Uuid128Bit> {
+    let bytes = from_hex(hex)?;
+    let mut uuid = [0; 16];
+    if bytes.len() != uuid.len() {
+        return None;
+    }
+    uuid.copy_from_slice(&bytes);
+    Some(uuid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_save_lines() {
+        let path = std::env::temp_dir().join(format!("btstack-storage-{}", std::process::id()));
+
+        save_lines(&path, &[String::from("a 1"), String::from("b 2")], SECRET_FILE_MODE).unwrap();
+        assert_eq!("a 1\nb 2\n", std::fs::read_to_string(&path).unwrap());
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(SECRET_FILE_MODE, mode & 0o777);
+        assert!(!path.with_extension("tmp").exists());
+
+        // A file left by an interrupted save does not get in the way.
+        std::fs::write(path.with_extension("tmp"), "stale").unwrap();
+        save_lines(&path, &[], PUBLIC_FILE_MODE).unwrap();
+        assert_eq!("", std::fs::read_to_string(&path).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!("00ff1a", to_hex(&[0x00, 0xff, 0x1a]));
+        assert_eq!(Some(vec![0x00, 0xff, 0x1a]), from_hex("00FF1a"));
+        assert_eq!(None, from_hex("0ff"));
+        assert_eq!(None, from_hex("zz"));
+
+        let uuid = [0x42; 16];
+        assert_eq!(Some(uuid), parse_uuid(&to_hex(&uuid)));
+        assert_eq!(None, parse_uuid("4242"));
+    }
+}
@@ -1,9 +1,19 @@
 //! Suspend/Resume API.
 
+use crate::bluetooth::Bluetooth;
+use crate::bluetooth_gatt::BluetoothGatt;
 use crate::{Message, RPCProxy};
-use log::warn;
-use std::collections::HashMap;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// Time after which the stack is ready for suspend even if the controller did not complete every
+/// preparation, so that a missing event never holds the suspend back.
+const SUSPEND_PREPARATION_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Defines the Suspend/Resume API.
 ///
@@ -26,12 +36,12 @@ pub trait ISuspend {
     ///
     /// Returns a positive number identifying the suspend if it can be started. If there is already
     /// a suspend, that active suspend id is returned.
-    fn suspend(&self, suspend_type: SuspendType) -> u32;
+    fn suspend(&mut self, suspend_type: SuspendType) -> u32;
 
     /// Undoes previous suspend preparation identified by `suspend_id`.
     ///
     /// Returns true if suspend can be resumed, and false if there is no suspend to resume.
-    fn resume(&self) -> bool;
+    fn resume(&mut self) -> bool;
 }
 
 /// Suspend events.
@@ -46,7 +56,7 @@ pub trait ISuspendCallback: RPCProxy {
     fn on_resumed(&self, suspend_id: u32);
 }
 
-#[derive(FromPrimitive, ToPrimitive)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
 #[repr(u32)]
 pub enum SuspendType {
     NoWakesAllowed,
@@ -54,15 +64,52 @@ pub enum SuspendType {
     Other,
 }
 
+/// Preparation of the controller for suspend, completed once the controller reports it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SuspendPreparation {
+    /// The adapter is no longer discoverable, and connectable only if there are wake devices.
+    ScanMode,
+    /// The LE scan is stopped.
+    LeScan,
+    /// The advertising sets are disarmed.
+    Advertising,
+}
+
 /// Implementation of the suspend API.
 pub struct Suspend {
     tx: Sender<Message>,
     callbacks: HashMap<u32, Box<dyn ISuspendCallback + Send>>,
+    adapter: Option<Arc<Mutex<Box<Bluetooth>>>>,
+    gatt: Option<Arc<Mutex<Box<BluetoothGatt>>>>,
+    // Id of the ongoing suspend, if any.
+    suspend_id: Option<u32>,
+    next_suspend_id: u32,
+    // Preparations the ongoing suspend waits for before being ready, and the timer ending the
+    // wait.
+    pending_preparations: HashSet<SuspendPreparation>,
+    preparation_timeout: Option<JoinHandle<()>>,
 }
 
 impl Suspend {
     pub fn new(tx: Sender<Message>) -> Suspend {
-        Self { tx, callbacks: HashMap::new() }
+        Self {
+            tx,
+            callbacks: HashMap::new(),
+            adapter: None,
+            gatt: None,
+            suspend_id: None,
+            next_suspend_id: 1,
+            pending_preparations: HashSet::new(),
+            preparation_timeout: None,
+        }
+    }
+
+    pub fn set_adapter(&mut self, adapter: Arc<Mutex<Box<Bluetooth>>>) {
+        self.adapter = Some(adapter);
+    }
+
+    pub fn set_gatt(&mut self, gatt: Arc<Mutex<Box<BluetoothGatt>>>) {
+        self.gatt = Some(gatt);
     }
 
     pub(crate) fn callback_registered(&mut self, id: u32) {
@@ -82,6 +129,48 @@ impl Suspend {
             None => false,
         }
     }
+
+    pub(crate) fn suspend_ready(&self, suspend_id: u32) {
+        for callback in self.callbacks.values() {
+            callback.on_suspend_ready(suspend_id);
+        }
+    }
+
+    pub(crate) fn resume_ready(&self, suspend_id: u32) {
+        for callback in self.callbacks.values() {
+            callback.on_resumed(suspend_id);
+        }
+    }
+
+    /// Notes that the controller completed `preparation`, the ongoing suspend being ready once it
+    /// completed all of them.
+    pub(crate) fn preparation_done(&mut self, preparation: SuspendPreparation) {
+        let suspend_id = match self.suspend_id {
+            Some(suspend_id) => suspend_id,
+            None => return,
+        };
+        if !self.pending_preparations.remove(&preparation) || !self.pending_preparations.is_empty()
+        {
+            return;
+        }
+
+        if let Some(timeout) = self.preparation_timeout.take() {
+            timeout.abort();
+        }
+        self.suspend_ready(suspend_id);
+    }
+
+    /// Makes the suspend `suspend_id` ready without the preparations still pending.
+    pub(crate) fn preparation_timed_out(&mut self, suspend_id: u32) {
+        if self.suspend_id != Some(suspend_id) || self.pending_preparations.is_empty() {
+            return;
+        }
+
+        warn!("Suspend {} ready without {:?}", suspend_id, self.pending_preparations);
+        self.pending_preparations.clear();
+        self.preparation_timeout = None;
+        self.suspend_ready(suspend_id);
+    }
 }
 
 impl ISuspend for Suspend {
@@ -108,11 +197,154 @@ impl ISuspend for Suspend {
         self.remove_callback(callback_id)
     }
 
-    fn suspend(&self, _suspend_type: SuspendType) -> u32 {
-        todo!()
+    fn suspend(&mut self, suspend_type: SuspendType) -> u32 {
+        if let Some(suspend_id) = self.suspend_id {
+            return suspend_id;
+        }
+
+        let suspend_id = self.next_suspend_id;
+        self.next_suspend_id = self.next_suspend_id.wrapping_add(1).max(1);
+        self.suspend_id = Some(suspend_id);
+        info!("Preparing suspend {} ({:?})", suspend_id, suspend_type);
+
+        let mut preparations = HashSet::new();
+        if let Some(adapter) = &self.adapter {
+            if adapter.lock().unwrap().suspend_classic(suspend_type) {
+                preparations.insert(SuspendPreparation::ScanMode);
+            }
+        }
+        if let Some(gatt) = &self.gatt {
+            preparations.extend(gatt.lock().unwrap().suspend_le(suspend_type));
+        }
+
+        let tx = self.tx.clone();
+        if preparations.is_empty() {
+            tokio::spawn(async move {
+                let _result = tx.send(Message::SuspendReady(suspend_id)).await;
+            });
+        } else {
+            self.pending_preparations = preparations;
+            self.preparation_timeout = Some(tokio::spawn(async move {
+                time::sleep(SUSPEND_PREPARATION_TIMEOUT).await;
+                let _result = tx.send(Message::SuspendPreparationTimeout(suspend_id)).await;
+            }));
+        }
+
+        suspend_id
     }
 
-    fn resume(&self) -> bool {
-        todo!()
+    fn resume(&mut self) -> bool {
+        let suspend_id = match self.suspend_id.take() {
+            Some(suspend_id) => suspend_id,
+            None => return false,
+        };
+        info!("Resuming from suspend {}", suspend_id);
+        self.pending_preparations.clear();
+        if let Some(timeout) = self.preparation_timeout.take() {
+            timeout.abort();
+        }
+
+        if let Some(adapter) = &self.adapter {
+            adapter.lock().unwrap().resume_classic();
+        }
+        if let Some(gatt) = &self.gatt {
+            gatt.lock().unwrap().resume_le();
+        }
+
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let _result = tx.send(Message::ResumeReady(suspend_id)).await;
+        });
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ReadyRecorder(Arc<Mutex<Vec<u32>>>);
+
+    impl RPCProxy for ReadyRecorder {
+        fn register_disconnect(&mut self, _f: Box<dyn Fn(u32) + Send>) -> u32 {
+            0
+        }
+
+        fn get_object_id(&self) -> String {
+            String::from("")
+        }
+
+        fn unregister(&mut self, _id: u32) -> bool {
+            true
+        }
+
+        fn export_for_rpc(self: Box<Self>) {}
+    }
+
+    impl ISuspendCallback for ReadyRecorder {
+        fn on_callback_registered(&self, _callback_id: u32) {}
+
+        fn on_suspend_ready(&self, suspend_id: u32) {
+            self.0.lock().unwrap().push(suspend_id);
+        }
+
+        fn on_resumed(&self, _suspend_id: u32) {}
+    }
+
+    fn suspending(preparations: &[SuspendPreparation]) -> (Suspend, Arc<Mutex<Vec<u32>>>) {
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let ready = Arc::new(Mutex::new(vec![]));
+        let mut suspend = Suspend::new(tx);
+        suspend.callbacks.insert(1, Box::new(ReadyRecorder(ready.clone())));
+        suspend.suspend_id = Some(7);
+        suspend.pending_preparations = preparations.iter().cloned().collect();
+        (suspend, ready)
+    }
+
+    #[test]
+    fn test_ready_once_preparations_done() {
+        let (mut suspend, ready) =
+            suspending(&[SuspendPreparation::ScanMode, SuspendPreparation::Advertising]);
+
+        suspend.preparation_done(SuspendPreparation::Advertising);
+        suspend.preparation_done(SuspendPreparation::Advertising);
+        suspend.preparation_done(SuspendPreparation::LeScan);
+        assert!(ready.lock().unwrap().is_empty());
+
+        suspend.preparation_done(SuspendPreparation::ScanMode);
+        assert_eq!(vec![7], *ready.lock().unwrap());
+
+        // Reported once only.
+        suspend.preparation_done(SuspendPreparation::ScanMode);
+        suspend.preparation_timed_out(7);
+        assert_eq!(vec![7], *ready.lock().unwrap());
+    }
+
+    #[test]
+    fn test_ready_on_timeout() {
+        let (mut suspend, ready) = suspending(&[SuspendPreparation::LeScan]);
+
+        // The timer of a previous suspend is ignored.
+        suspend.preparation_timed_out(6);
+        assert!(ready.lock().unwrap().is_empty());
+
+        suspend.preparation_timed_out(7);
+        assert_eq!(vec![7], *ready.lock().unwrap());
+
+        suspend.preparation_done(SuspendPreparation::LeScan);
+        assert_eq!(vec![7], *ready.lock().unwrap());
+    }
+
+    #[test]
+    fn test_not_ready_once_resumed() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut suspend, ready) = suspending(&[SuspendPreparation::ScanMode]);
+
+            assert!(suspend.resume());
+            suspend.preparation_done(SuspendPreparation::ScanMode);
+            suspend.preparation_timed_out(7);
+            assert!(ready.lock().unwrap().is_empty());
+        });
     }
 }
@@ -0,0 +1,286 @@
+//! Built-in Current Time Service, serving the time of the system clock to the centrals so that
+//! watches and trackers can sync their time with the host.
+//!
+//! The service is registered with its own GATT server once the adapter is enabled, if enabled
+//! with `BluetoothGatt::set_time_service_enabled`. The subscribers of the Current Time are
+//! notified when the clock is set, or when the time zone or DST changes.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::bluetooth_gatt::{
+    BluetoothGattCharacteristic, BluetoothGattDescriptor, BluetoothGattService, BASE_UUID,
+};
+use crate::gatt_service_builder::CCCD_UUID;
+use bt_topshim::btif::Uuid128Bit;
+
+/// Application UUID of the server of the service.
+pub(crate) const TIME_SERVER_UUID: Uuid128Bit = [
+    0x6E, 0x8A, 0x3D, 0x1C, 0x52, 0x0F, 0x4B, 0x7E, 0x9A, 0x40, 0xC1, 0x25, 0x7F, 0x0B, 0x18, 0x05,
+];
+
+/// Period of the checks for clock adjustments.
+pub(crate) const CLOCK_CHECK_PERIOD: Duration = Duration::from_secs(10);
+
+// Difference between the clock and its expected time above which the clock was set.
+const CLOCK_JUMP_TOLERANCE: Duration = Duration::from_secs(1);
+
+const CURRENT_TIME_SERVICE_UUID16: [u8; 2] = [0x18, 0x05];
+const CURRENT_TIME_UUID16: [u8; 2] = [0x2A, 0x2B];
+const LOCAL_TIME_INFORMATION_UUID16: [u8; 2] = [0x2A, 0x0F];
+
+// Bits of the Adjust Reason field of the Current Time.
+pub(crate) const ADJUST_MANUAL_TIME_UPDATE: u8 = 0x01;
+pub(crate) const ADJUST_CHANGE_OF_TIME_ZONE: u8 = 0x04;
+pub(crate) const ADJUST_CHANGE_OF_DST: u8 = 0x08;
+
+// DST offsets of the Local Time Information.
+const DST_STANDARD_TIME: u8 = 0;
+const DST_DAYLIGHT_TIME: u8 = 4;
+
+// Seconds in a 15 minute increment of the time zone.
+const TIME_ZONE_INCREMENT: i32 = 15 * 60;
+// Seconds added by the daylight time, which is an hour in most time zones.
+const DST_OFFSET: i32 = 60 * 60;
+
+fn uuid16(short: [u8; 2]) -> Uuid128Bit {
+    let mut uuid = BASE_UUID;
+    uuid[2..4].copy_from_slice(&short);
+    uuid
+}
+
+/// Returns the service to add to the server.
+pub(crate) fn build_service() -> BluetoothGattService {
+    let mut service = BluetoothGattService::new(
+        uuid16(CURRENT_TIME_SERVICE_UUID16),
+        0,
+        BluetoothGattService::SERVICE_TYPE_PRIMARY,
+    );
+
+    let mut current_time = BluetoothGattCharacteristic::new(
+        uuid16(CURRENT_TIME_UUID16),
+        0,
+        BluetoothGattCharacteristic::PROPERTY_READ | BluetoothGattCharacteristic::PROPERTY_NOTIFY,
+        BluetoothGattCharacteristic::PERMISSION_READ,
+    );
+    current_time.descriptors.push(BluetoothGattDescriptor::new(
+        CCCD_UUID,
+        0,
+        BluetoothGattCharacteristic::PERMISSION_READ
+            | BluetoothGattCharacteristic::PERMISSION_WRITE,
+    ));
+    service.characteristics.push(current_time);
+
+    service.characteristics.push(BluetoothGattCharacteristic::new(
+        uuid16(LOCAL_TIME_INFORMATION_UUID16),
+        0,
+        BluetoothGattCharacteristic::PROPERTY_READ,
+        BluetoothGattCharacteristic::PERMISSION_READ,
+    ));
+    service
+}
+
+/// Local time of the system clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct LocalTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    /// 1 for Monday to 7 for Sunday.
+    pub day_of_week: u8,
+    /// Fractions of the second, in 1/256 units.
+    pub fractions256: u8,
+    /// Offset from UTC, including the DST, in seconds.
+    pub utc_offset: i32,
+    pub is_dst: bool,
+}
+
+impl LocalTime {
+    /// Converts `time` to the local time zone. Returns None if the conversion fails.
+    pub(crate) fn from_system_time(time: SystemTime) -> Option<LocalTime> {
+        let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+        let secs = since_epoch.as_secs() as libc::time_t;
+
+        // SAFETY: `tm` is a plain C struct fully written by localtime_r on success.
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+            return None;
+        }
+
+        Some(LocalTime {
+            year: (tm.tm_year + 1900) as u16,
+            month: (tm.tm_mon + 1) as u8,
+            day: tm.tm_mday as u8,
+            hours: tm.tm_hour as u8,
+            minutes: tm.tm_min as u8,
+            seconds: tm.tm_sec as u8,
+            // tm_wday counts from Sunday.
+            day_of_week: if tm.tm_wday == 0 { 7 } else { tm.tm_wday as u8 },
+            fractions256: (since_epoch.subsec_nanos() as u64 * 256 / 1_000_000_000) as u8,
+            utc_offset: tm.tm_gmtoff as i32,
+            is_dst: tm.tm_isdst > 0,
+        })
+    }
+
+    /// Offset of the time zone from UTC without the DST, in seconds.
+    fn standard_offset(&self) -> i32 {
+        self.utc_offset - if self.is_dst { DST_OFFSET } else { 0 }
+    }
+}
+
+/// Returns the value of the Current Time characteristic.
+pub(crate) fn current_time(time: &LocalTime, adjust_reason: u8) -> Vec<u8> {
+    let mut value = time.year.to_le_bytes().to_vec();
+    value.extend(&[
+        time.month,
+        time.day,
+        time.hours,
+        time.minutes,
+        time.seconds,
+        time.day_of_week,
+        time.fractions256,
+        adjust_reason,
+    ]);
+    value
+}
+
+/// Returns the value of the Local Time Information characteristic.
+pub(crate) fn local_time_information(time: &LocalTime) -> Vec<u8> {
+    let time_zone = (time.standard_offset() / TIME_ZONE_INCREMENT) as i8;
+    let dst_offset = if time.is_dst { DST_DAYLIGHT_TIME } else { DST_STANDARD_TIME };
+    vec![time_zone as u8, dst_offset]
+}
+
+/// Detects the adjustments of the system clock by comparing it with the monotonic clock.
+pub(crate) struct ClockWatch {
+    wall: SystemTime,
+    monotonic: Instant,
+    time: LocalTime,
+}
+
+impl ClockWatch {
+    pub(crate) fn new(wall: SystemTime, monotonic: Instant, time: LocalTime) -> Self {
+        ClockWatch { wall, monotonic, time }
+    }
+
+    /// Records a new sample of the clocks. Returns the Adjust Reason of the changes since the
+    /// previous sample, 0 if the clock was not adjusted.
+    pub(crate) fn sample(&mut self, wall: SystemTime, monotonic: Instant, time: LocalTime) -> u8 {
+        let expected = self.wall + monotonic.saturating_duration_since(self.monotonic);
+        let drift = match wall.duration_since(expected) {
+            Ok(ahead) => ahead,
+            Err(behind) => behind.duration(),
+        };
+
+        let mut reason = 0;
+        if drift > CLOCK_JUMP_TOLERANCE {
+            reason |= ADJUST_MANUAL_TIME_UPDATE;
+        }
+        if time.standard_offset() != self.time.standard_offset() {
+            reason |= ADJUST_CHANGE_OF_TIME_ZONE;
+        }
+        if time.is_dst != self.time.is_dst {
+            reason |= ADJUST_CHANGE_OF_DST;
+        }
+
+        *self = ClockWatch::new(wall, monotonic, time);
+        reason
+    }
+}
+
+/// State of the server of the service.
+#[derive(Default)]
+pub(crate) struct TimeServer {
+    pub server_id: Option<i32>,
+    pub current_time_handle: Option<i32>,
+    pub cccd_handle: Option<i32>,
+    pub local_time_handle: Option<i32>,
+    // Connections subscribed to the Current Time, by connection ID.
+    pub subscribers: HashSet<i32>,
+    pub clock: Option<ClockWatch>,
+}
+
+impl TimeServer {
+    /// Records the handles assigned to the service once added.
+    pub(crate) fn set_handles(&mut self, service: &BluetoothGattService) {
+        for characteristic in service.characteristics.iter() {
+            if characteristic.uuid == uuid16(CURRENT_TIME_UUID16) {
+                self.current_time_handle = Some(characteristic.instance_id);
+                self.cccd_handle = characteristic
+                    .descriptors
+                    .iter()
+                    .find(|d| d.uuid == CCCD_UUID)
+                    .map(|d| d.instance_id);
+            } else if characteristic.uuid == uuid16(LOCAL_TIME_INFORMATION_UUID16) {
+                self.local_time_handle = Some(characteristic.instance_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gatt_service_builder::validate_service;
+
+    fn test_time() -> LocalTime {
+        LocalTime {
+            year: 2023,
+            month: 3,
+            day: 26,
+            hours: 14,
+            minutes: 5,
+            seconds: 9,
+            day_of_week: 7,
+            fractions256: 128,
+            utc_offset: 2 * 3600,
+            is_dst: true,
+        }
+    }
+
+    #[test]
+    fn test_service_is_valid() {
+        assert!(validate_service(&build_service()).is_empty());
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(
+            vec![0xE7, 0x07, 3, 26, 14, 5, 9, 7, 128, ADJUST_CHANGE_OF_DST],
+            current_time(&test_time(), ADJUST_CHANGE_OF_DST)
+        );
+        // UTC+1 in daylight time.
+        assert_eq!(vec![4, DST_DAYLIGHT_TIME], local_time_information(&test_time()));
+
+        let west = LocalTime { utc_offset: -5 * 3600, is_dst: false, ..test_time() };
+        assert_eq!(vec![(-20i8) as u8, DST_STANDARD_TIME], local_time_information(&west));
+    }
+
+    #[test]
+    fn test_clock_watch() {
+        let wall = SystemTime::now();
+        let monotonic = Instant::now();
+        let mut watch = ClockWatch::new(wall, monotonic, test_time());
+
+        let step = Duration::from_secs(10);
+        assert_eq!(0, watch.sample(wall + step, monotonic + step, test_time()));
+
+        // Set back by a minute.
+        let wall = wall + step + step - Duration::from_secs(60);
+        let monotonic = monotonic + step + step;
+        assert_eq!(ADJUST_MANUAL_TIME_UPDATE, watch.sample(wall, monotonic, test_time()));
+
+        // End of the daylight time, the time zone staying the same.
+        let winter = LocalTime { utc_offset: 3600, is_dst: false, ..test_time() };
+        assert_eq!(ADJUST_CHANGE_OF_DST, watch.sample(wall + step, monotonic + step, winter));
+
+        let moved = LocalTime { utc_offset: 0, ..winter };
+        assert_eq!(
+            ADJUST_CHANGE_OF_TIME_ZONE,
+            watch.sample(wall + step + step, monotonic + step + step, moved)
+        );
+    }
+}
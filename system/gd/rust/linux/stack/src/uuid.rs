@@ -1,6 +1,7 @@
 //! Collection of Profile UUIDs and helpers to use them.
 
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 use bt_topshim::btif::Uuid128Bit;
 
@@ -188,6 +189,69 @@ impl UuidHelper {
     }
 }
 
+/// A UUID passed to the GATT and advertising APIs.
+///
+/// It is represented in D-Bus as a string in its 16-bit (`180f`), 32-bit (`0000180f`) or 128-bit
+/// form, the latter with or without dashes, so that malformed UUIDs are rejected before reaching
+/// the stack.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BtUuid {
+    pub uu: Uuid128Bit,
+}
+
+impl BtUuid {
+    /// Parses a UUID in any of the forms accepted in D-Bus. The 16-bit and 32-bit forms are
+    /// expanded with the Bluetooth Base UUID.
+    pub fn from_string<S: AsRef<str>>(raw: S) -> Option<BtUuid> {
+        let raw = raw.as_ref();
+        let hex: String = if raw.len() == 36 {
+            let dashes = [8, 13, 18, 23];
+            if dashes.iter().any(|i| raw.as_bytes()[*i] != b'-') {
+                return None;
+            }
+            raw.split('-').collect()
+        } else {
+            String::from(raw)
+        };
+
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let bytes: Vec<u8> = (0..hex.len() / 2)
+            .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap())
+            .collect();
+        let mut uu = UuidHelper::from_string(BASE_UUID).unwrap();
+        match hex.len() {
+            4 => uu[2..4].copy_from_slice(&bytes),
+            8 => uu[0..4].copy_from_slice(&bytes),
+            32 => uu.copy_from_slice(&bytes),
+            _ => return None,
+        }
+
+        Some(BtUuid { uu })
+    }
+}
+
+impl From<Uuid128Bit> for BtUuid {
+    fn from(uu: Uuid128Bit) -> BtUuid {
+        BtUuid { uu }
+    }
+}
+
+/// Formats the UUID in its 128-bit form, with dashes.
+impl fmt::Display for BtUuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, byte) in self.uu.iter().enumerate() {
+            if [4, 6, 8, 10].contains(&i) {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -204,4 +268,20 @@ mod tests {
             });
         }
     }
+
+    #[test]
+    fn test_uuid_from_string() {
+        let battery = BtUuid::from_string("0000180f-0000-1000-8000-00805f9b34fb").unwrap();
+        assert_eq!(Some(battery), BtUuid::from_string("180f"));
+        assert_eq!(Some(battery), BtUuid::from_string("0000180F"));
+        assert_eq!(Some(battery), BtUuid::from_string("0000180f00001000800000805f9b34fb"));
+        assert_eq!("0000180f-0000-1000-8000-00805f9b34fb", battery.to_string());
+
+        assert_eq!(None, BtUuid::from_string(""));
+        assert_eq!(None, BtUuid::from_string("18f"));
+        assert_eq!(None, BtUuid::from_string("180g"));
+        assert_eq!(None, BtUuid::from_string("+180"));
+        assert_eq!(None, BtUuid::from_string("0000180f-0000-1000-8000_00805f9b34fb"));
+        assert_eq!(None, BtUuid::from_string("0000180f00001000800000805f9b34fb00"));
+    }
 }
@@ -0,0 +1,331 @@
+//! Journal of the characteristic writes a client issues while the device is disconnected. The
+//! writes are sent in order once the client connects to the device again, see
+//! `IBluetoothGatt::enable_write_journal`.
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bluetooth_gatt::GattWriteType;
+
+/// Most writes journaled by a client, over all its devices.
+pub(crate) const MAX_JOURNAL_ENTRIES: usize = 64;
+
+/// What to do with a write journaled for a characteristic that already has one pending.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum JournalConflictPolicy {
+    /// Every write is kept and sent.
+    KeepAll = 0,
+    /// The new write replaces the pending one, and is sent after the other pending writes.
+    LastWriteWins = 1,
+    /// The new write is rejected.
+    FirstWriteWins = 2,
+}
+
+/// Write waiting in the journal of a client.
+#[derive(Clone, Debug)]
+pub struct JournalEntry {
+    pub id: i32,
+    pub address: String,
+    pub handle: i32,
+    pub write_type: GattWriteType,
+    pub auth_req: i32,
+    pub value: Vec<u8>,
+    /// Milliseconds since the epoch the write was journaled at.
+    pub queued_at_ms: u64,
+}
+
+/// Writes journaled by a client, in the order they were issued.
+pub(crate) struct WriteJournal {
+    policy: JournalConflictPolicy,
+    entries: VecDeque<JournalEntry>,
+    next_id: i32,
+}
+
+impl WriteJournal {
+    pub(crate) fn new(policy: JournalConflictPolicy) -> Self {
+        WriteJournal { policy, entries: VecDeque::new(), next_id: 1 }
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: JournalConflictPolicy) {
+        self.policy = policy;
+    }
+
+    /// Journals a write. Returns the ID of the entry, or None if the write is rejected by the
+    /// conflict policy or the journal is full.
+    pub(crate) fn push(
+        &mut self,
+        address: &str,
+        handle: i32,
+        write_type: GattWriteType,
+        auth_req: i32,
+        value: Vec<u8>,
+    ) -> Option<i32> {
+        let pending = self
+            .entries
+            .iter()
+            .position(|e| e.handle == handle && e.address.eq_ignore_ascii_case(address));
+        match (self.policy, pending) {
+            (JournalConflictPolicy::FirstWriteWins, Some(_)) => return None,
+            (JournalConflictPolicy::LastWriteWins, Some(i)) => {
+                self.entries.remove(i);
+            }
+            _ => (),
+        }
+
+        if self.entries.len() >= MAX_JOURNAL_ENTRIES {
+            return None;
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let queued_at_ms =
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        self.entries.push_back(JournalEntry {
+            id,
+            address: address.to_string(),
+            handle,
+            write_type,
+            auth_req,
+            value,
+            queued_at_ms,
+        });
+        Some(id)
+    }
+
+    /// Returns the writes journaled for `address`, or for every device if it is empty.
+    pub(crate) fn entries(&self, address: &str) -> Vec<JournalEntry> {
+        self.entries
+            .iter()
+            .filter(|e| address.is_empty() || e.address.eq_ignore_ascii_case(address))
+            .cloned()
+            .collect()
+    }
+
+    /// Removes the write `id`. Returns false if it is not in the journal.
+    pub(crate) fn cancel(&mut self, id: i32) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|e| e.id != id);
+        self.entries.len() != len
+    }
+
+    /// Removes and returns the writes journaled for `address`, or for every device if it is
+    /// empty, in order.
+    pub(crate) fn take(&mut self, address: &str) -> VecDeque<JournalEntry> {
+        let (taken, kept) = self
+            .entries
+            .drain(..)
+            .partition(|e| address.is_empty() || e.address.eq_ignore_ascii_case(address));
+        self.entries = kept;
+        taken
+    }
+
+    /// Puts back writes taken but not sent, ahead of the writes journaled since.
+    pub(crate) fn restore(&mut self, entries: VecDeque<JournalEntry>) {
+        for entry in entries.into_iter().rev() {
+            self.entries.push_front(entry);
+        }
+    }
+}
+
+/// Write of a flush waiting for its result.
+struct InFlightWrite {
+    entry: JournalEntry,
+    // Results of the writes of the same handle sent earlier, still to come before its own.
+    ahead: usize,
+    // Long writes complete with the execute write rather than with a write result.
+    long: bool,
+}
+
+/// Journaled writes being sent on a connection, one at a time, in order.
+pub(crate) struct JournalFlush {
+    entries: VecDeque<JournalEntry>,
+    in_flight: Option<InFlightWrite>,
+}
+
+impl JournalFlush {
+    pub(crate) fn new(entries: VecDeque<JournalEntry>) -> Self {
+        JournalFlush { entries, in_flight: None }
+    }
+
+    /// Takes the next write to send, None while a write is in flight or once every write is
+    /// sent.
+    pub(crate) fn next(&mut self) -> Option<JournalEntry> {
+        match self.in_flight {
+            Some(_) => None,
+            None => self.entries.pop_front(),
+        }
+    }
+
+    /// Records the write taken with `next` as sent, behind `ahead` writes of its handle which
+    /// were sent before and have not completed yet.
+    pub(crate) fn sent(&mut self, entry: JournalEntry, ahead: usize, long: bool) {
+        self.in_flight = Some(InFlightWrite { entry, ahead, long });
+    }
+
+    /// Puts back the write taken with `next` which cannot be sent yet, to send it first.
+    pub(crate) fn defer(&mut self, entry: JournalEntry) {
+        self.entries.push_front(entry);
+    }
+
+    /// Records the result of a write of `handle`. Returns whether it completes the write in
+    /// flight, the next write being sent then.
+    pub(crate) fn on_result(&mut self, handle: i32, long: bool) -> bool {
+        match &mut self.in_flight {
+            Some(write) if write.entry.handle == handle && write.long == long => {
+                if write.ahead > 0 {
+                    write.ahead -= 1;
+                    return false;
+                }
+                self.in_flight = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes the write `id` if it is not sent yet. Returns false otherwise.
+    pub(crate) fn cancel(&mut self, id: i32) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|e| e.id != id);
+        self.entries.len() != len
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.in_flight.is_none() && self.entries.is_empty()
+    }
+
+    /// Returns the writes which have not completed, the one in flight first, to journal them
+    /// again.
+    pub(crate) fn into_unsent(self) -> VecDeque<JournalEntry> {
+        let mut entries = self.entries;
+        if let Some(write) = self.in_flight {
+            entries.push_front(write.entry);
+        }
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: &str = "AA:BB:CC:DD:EE:FF";
+
+    fn push(journal: &mut WriteJournal, address: &str, handle: i32, value: u8) -> Option<i32> {
+        journal.push(address, handle, GattWriteType::Write, 0, vec![value])
+    }
+
+    fn values(journal: &WriteJournal) -> Vec<u8> {
+        journal.entries("").iter().map(|e| e.value[0]).collect()
+    }
+
+    #[test]
+    fn test_keep_all() {
+        let mut journal = WriteJournal::new(JournalConflictPolicy::KeepAll);
+        assert_eq!(push(&mut journal, ADDR, 3, 1), Some(1));
+        assert_eq!(push(&mut journal, ADDR, 5, 2), Some(2));
+        assert_eq!(push(&mut journal, ADDR, 3, 3), Some(3));
+
+        assert_eq!(values(&journal), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_conflict_policies() {
+        let mut journal = WriteJournal::new(JournalConflictPolicy::LastWriteWins);
+        push(&mut journal, ADDR, 3, 1);
+        push(&mut journal, ADDR, 5, 2);
+        push(&mut journal, &ADDR.to_lowercase(), 3, 3);
+        assert_eq!(values(&journal), vec![2, 3]);
+
+        journal.set_policy(JournalConflictPolicy::FirstWriteWins);
+        assert_eq!(push(&mut journal, ADDR, 5, 4), None);
+        // The same handle on another device is not a conflict.
+        assert!(push(&mut journal, "11:22:33:44:55:66", 5, 5).is_some());
+        assert_eq!(values(&journal), vec![2, 3, 5]);
+    }
+
+    #[test]
+    fn test_full() {
+        let mut journal = WriteJournal::new(JournalConflictPolicy::KeepAll);
+        for i in 0..MAX_JOURNAL_ENTRIES {
+            assert!(push(&mut journal, ADDR, i as i32, 0).is_some());
+        }
+
+        assert_eq!(push(&mut journal, ADDR, 0, 0), None);
+    }
+
+    #[test]
+    fn test_cancel_take_restore() {
+        let mut journal = WriteJournal::new(JournalConflictPolicy::KeepAll);
+        let first = push(&mut journal, ADDR, 3, 1).unwrap();
+        push(&mut journal, "11:22:33:44:55:66", 3, 2);
+        push(&mut journal, ADDR, 5, 3);
+        push(&mut journal, ADDR, 7, 4);
+
+        assert!(journal.cancel(first));
+        assert!(!journal.cancel(first));
+
+        let mut taken = journal.take(ADDR);
+        assert_eq!(taken.iter().map(|e| e.value[0]).collect::<Vec<u8>>(), vec![3, 4]);
+        assert_eq!(values(&journal), vec![2]);
+
+        // The first write was sent, the other one goes back ahead of the newer writes.
+        taken.pop_front();
+        push(&mut journal, ADDR, 9, 5);
+        journal.restore(taken);
+        assert_eq!(values(&journal), vec![4, 2, 5]);
+    }
+
+    #[test]
+    fn test_flush_waits_for_its_write() {
+        let mut journal = WriteJournal::new(JournalConflictPolicy::KeepAll);
+        push(&mut journal, ADDR, 3, 1);
+        push(&mut journal, ADDR, 5, 2);
+        let mut flush = JournalFlush::new(journal.take(ADDR));
+
+        // Sent behind a write of the same handle issued by the client.
+        let entry = flush.next().unwrap();
+        assert_eq!(entry.value, vec![1]);
+        flush.sent(entry, 1, false);
+        assert!(flush.next().is_none());
+
+        // The results of the other writes do not complete it.
+        assert!(!flush.on_result(7, false));
+        assert!(!flush.on_result(3, true));
+        assert!(!flush.on_result(3, false));
+        assert!(flush.next().is_none());
+        assert!(flush.on_result(3, false));
+
+        let entry = flush.next().unwrap();
+        assert_eq!(entry.value, vec![2]);
+        flush.sent(entry, 0, true);
+        assert!(!flush.on_result(5, false));
+        assert!(flush.on_result(5, true));
+        assert!(flush.next().is_none());
+        assert!(flush.is_done());
+    }
+
+    #[test]
+    fn test_flush_defer_cancel_unsent() {
+        let mut journal = WriteJournal::new(JournalConflictPolicy::KeepAll);
+        push(&mut journal, ADDR, 3, 1);
+        push(&mut journal, ADDR, 5, 2);
+        let third = push(&mut journal, ADDR, 7, 3).unwrap();
+        let mut flush = JournalFlush::new(journal.take(ADDR));
+
+        // A write which cannot be sent yet is sent first later.
+        let entry = flush.next().unwrap();
+        flush.defer(entry);
+        let entry = flush.next().unwrap();
+        assert_eq!(entry.value, vec![1]);
+        flush.sent(entry, 0, false);
+
+        assert!(flush.cancel(third));
+        assert!(!flush.cancel(third));
+
+        // The write in flight is kept along with the writes not sent, as on a disconnection.
+        journal.restore(flush.into_unsent());
+        assert_eq!(values(&journal), vec![1, 2]);
+    }
+}
@@ -10,6 +10,7 @@ mod ffi {
         fn finite_att_timeout_is_enabled() -> bool;
         fn gatt_robust_caching_client_is_enabled() -> bool;
         fn gatt_robust_caching_server_is_enabled() -> bool;
+        fn gatt_service_changed_is_enabled() -> bool;
         fn gd_core_is_enabled() -> bool;
         fn gd_l2cap_is_enabled() -> bool;
         fn gd_link_policy_is_enabled() -> bool;
@@ -9,3 +9,4 @@
 #include "hardware/bt_gatt.h"
 #include "hardware/bt_hh.h"
 #include "hardware/bt_sdp.h"
+#include "hardware/bt_sock.h"
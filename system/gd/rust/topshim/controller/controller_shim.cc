@@ -16,11 +16,32 @@
 
 #include "gd/rust/topshim/controller/controller_shim.h"
 
+#include <base/bind.h>
+#include <base/callback_helpers.h>
+
+#include <algorithm>
+#include <chrono>
+#include <cstring>
+#include <future>
 #include <memory>
+#include <string>
+#include <vector>
 
+#include "btif/include/btif_storage.h"
+#include "gd/hci/controller.h"
+#include "gd/hci/hci_layer.h"
 #include "gd/rust/topshim/common/utils.h"
+#include "main/shim/acl_api.h"
+#include "main/shim/entry.h"
 #include "rust/cxx.h"
 #include "src/controller.rs.h"
+#include "stack/btm/ble_scanner_hci_interface.h"
+#include "stack/include/acl_api.h"
+#include "stack/include/btm_ble_api_types.h"
+#include "stack/include/btm_api.h"
+#include "stack/include/btu.h"
+#include "stack/include/hcidefs.h"
+#include "stack/include/hcimsgs.h"
 #include "types/raw_address.h"
 
 namespace bluetooth {
@@ -44,6 +65,286 @@ RustRawAddress ControllerIntf::read_local_addr() const {
   return CopyToRustAddress(*controller_->get_address());
 }
 
+uint16_t ControllerIntf::read_manufacturer() const {
+  if (!controller_) std::abort();
+  return controller_->get_bt_version()->manufacturer;
+}
+
+RustLocalVersion ControllerIntf::read_local_version() const {
+  if (!controller_) std::abort();
+  const bt_version_t* version = controller_->get_bt_version();
+  RustLocalVersion local_version = {};
+  local_version.hci_version = version->hci_version;
+  local_version.hci_revision = version->hci_revision;
+  local_version.lmp_version = version->lmp_version;
+  local_version.lmp_subversion = version->lmp_subversion;
+  local_version.manufacturer = version->manufacturer;
+  return local_version;
+}
+
+uint64_t ControllerIntf::read_le_local_features() const {
+  return bluetooth::shim::GetController()->GetControllerLeLocalSupportedFeatures();
+}
+
+bool ControllerIntf::supports_secure_connections() const {
+  if (!controller_) std::abort();
+  return controller_->supports_secure_connections();
+}
+
+bool ControllerIntf::supports_simultaneous_le_bredr() const {
+  if (!controller_) std::abort();
+  return controller_->supports_simultaneous_le_bredr();
+}
+
+static uint16_t ToScanType(bool interlaced) {
+  return interlaced ? BTM_SCAN_TYPE_INTERLACED : BTM_SCAN_TYPE_STANDARD;
+}
+
+// The scan parameters go through BTM, which keeps them for the next time the
+// scans are enabled.
+void ControllerIntf::write_page_scan_activity(uint16_t interval,
+                                              uint16_t window) const {
+  do_in_main_thread(FROM_HERE,
+                    base::BindOnce(base::IgnoreResult(&BTM_SetPageScanActivity),
+                                   interval, window));
+}
+
+void ControllerIntf::write_inquiry_scan_activity(uint16_t interval,
+                                                 uint16_t window) const {
+  do_in_main_thread(
+      FROM_HERE, base::BindOnce(base::IgnoreResult(&BTM_SetInquiryScanActivity),
+                                interval, window));
+}
+
+void ControllerIntf::write_page_scan_type(bool interlaced) const {
+  do_in_main_thread(FROM_HERE,
+                    base::BindOnce(base::IgnoreResult(&BTM_SetPageScanType),
+                                   ToScanType(interlaced)));
+}
+
+void ControllerIntf::write_inquiry_scan_type(bool interlaced) const {
+  do_in_main_thread(FROM_HERE,
+                    base::BindOnce(base::IgnoreResult(&BTM_SetInquiryScanType),
+                                   ToScanType(interlaced)));
+}
+
+static void SetConnectionSetupFilter(RawAddress address) {
+  // The filter condition is the address followed by the auto accept flag, so
+  // that the connection wakes the host once set up.
+  uint8_t condition[BD_ADDR_LEN + 1];
+  memcpy(condition, &address, BD_ADDR_LEN);
+  condition[BD_ADDR_LEN] = HCI_DO_AUTO_ACCEPT_CONNECT;
+  btsnd_hcic_set_event_filter(HCI_FILTER_CONNECTION_SETUP,
+                              HCI_FILTER_COND_BD_ADDR, condition,
+                              sizeof(condition));
+}
+
+void ControllerIntf::add_connection_setup_filter(RustRawAddress address) const {
+  do_in_main_thread(FROM_HERE, base::BindOnce(&SetConnectionSetupFilter,
+                                              CopyFromRustAddress(address)));
+}
+
+static ::rust::Vec<::rust::String> ToRustStrings(
+    const std::vector<std::string>& strings) {
+  ::rust::Vec<::rust::String> rust_strings;
+  for (const auto& s : strings) {
+    rust_strings.push_back(::rust::String(s));
+  }
+  return rust_strings;
+}
+
+static void ReportControllerLists(std::vector<std::string> acceptlist,
+                                  std::vector<std::string> resolving_list) {
+  BleScannerHciInterface* scanner = BleScannerHciInterface::Get();
+  // Without the interface, the controller does not support the list.
+  std::vector<std::string> periodic_advertiser_list =
+      scanner ? scanner->GetPeriodicAdvertiserList()
+              : std::vector<std::string>();
+  controller_on_controller_lists(ToRustStrings(acceptlist),
+                                 ToRustStrings(resolving_list),
+                                 ToRustStrings(periodic_advertiser_list));
+}
+
+void ControllerIntf::read_controller_lists() const {
+  // The periodic advertiser list is kept by the main thread, where the lists
+  // of the ACL thread are brought.
+  bluetooth::shim::ACL_GetLeLists([](std::vector<std::string> acceptlist,
+                                     std::vector<std::string> resolving_list) {
+    do_in_main_thread(FROM_HERE, base::BindOnce(&ReportControllerLists,
+                                                std::move(acceptlist),
+                                                std::move(resolving_list)));
+  });
+}
+
+void ControllerIntf::clear_filter_accept_list() const {
+  bluetooth::shim::ACL_ClearAcceptList();
+}
+
+void ControllerIntf::clear_resolving_list() const {
+  bluetooth::shim::ACL_ClearAddressResolution();
+}
+
+static void ClearPeriodicAdvertiserList() {
+  BleScannerHciInterface* scanner = BleScannerHciInterface::Get();
+  if (scanner) {
+    scanner->PeriodicAdvertiserListClear(base::DoNothing());
+  }
+}
+
+void ControllerIntf::clear_periodic_advertiser_list() const {
+  do_in_main_thread(FROM_HERE, base::BindOnce(&ClearPeriodicAdvertiserList));
+}
+
+void ControllerIntf::suspend_le_connections(
+    ::rust::Vec<RustRawAddress> wake_devices) const {
+  std::vector<RawAddress> addresses;
+  for (const auto& address : wake_devices) {
+    addresses.push_back(CopyFromRustAddress(address));
+  }
+  bluetooth::shim::ACL_SuspendLeConnections(addresses);
+}
+
+void ControllerIntf::resume_le_connections() const {
+  bluetooth::shim::ACL_ResumeLeConnections();
+}
+
+bool ControllerIntf::set_privacy_mode(RustRawAddress address,
+                                      bool device_privacy) const {
+  return bluetooth::shim::ACL_SetPrivacyMode(CopyFromRustAddress(address),
+                                             device_privacy);
+}
+
+static void ReadRemoteVersion(RawAddress address,
+                              std::promise<RustRemoteVersion> promise) {
+  RustRemoteVersion version = {};
+  version.valid =
+      BTM_ReadRemoteVersion(address, &version.lmp_version,
+                            &version.manufacturer, &version.lmp_subversion);
+  promise.set_value(version);
+}
+
+RustRemoteVersion ControllerIntf::read_remote_version(
+    RustRawAddress address) const {
+  // The ACL connections are owned by the main thread.
+  std::promise<RustRemoteVersion> promise;
+  auto future = promise.get_future();
+  do_in_main_thread(FROM_HERE,
+                    base::BindOnce(&ReadRemoteVersion,
+                                   CopyFromRustAddress(address),
+                                   std::move(promise)));
+  return future.get();
+}
+
+RustIdentityKey ControllerIntf::read_identity_key(
+    RustRawAddress address) const {
+  // The keys are those btif loads into the resolving list, and btif_config
+  // guards its own accesses.
+  RustIdentityKey key = {};
+  tBTM_LE_PID_KEYS pid_keys;
+  key.valid = btif_storage_get_ble_bonding_key(
+                  CopyFromRustAddress(address), BTM_LE_KEY_PID,
+                  reinterpret_cast<uint8_t*>(&pid_keys),
+                  sizeof(pid_keys)) == BT_STATUS_SUCCESS;
+  if (!key.valid) return key;
+
+  std::copy(pid_keys.irk.begin(), pid_keys.irk.end(), key.irk.begin());
+  key.identity_address = CopyToRustAddress(pid_keys.identity_addr);
+  return key;
+}
+
+static void ReadConnectionAddress(
+    RawAddress address, std::promise<RustConnectionAddress> promise) {
+  RawAddress local_address = RawAddress::kEmpty;
+  tBLE_ADDR_TYPE address_type = BLE_ADDR_PUBLIC;
+  BTM_ReadConnectionAddr(address, local_address, &address_type);
+
+  RustConnectionAddress connection_address = {};
+  connection_address.valid = !local_address.IsEmpty();
+  connection_address.address_type = address_type;
+  connection_address.address = CopyToRustAddress(local_address);
+  promise.set_value(connection_address);
+}
+
+RustConnectionAddress ControllerIntf::read_connection_address(
+    RustRawAddress address) const {
+  // The ACL connections are owned by the main thread.
+  std::promise<RustConnectionAddress> promise;
+  auto future = promise.get_future();
+  do_in_main_thread(FROM_HERE,
+                    base::BindOnce(&ReadConnectionAddress,
+                                   CopyFromRustAddress(address),
+                                   std::move(promise)));
+  return future.get();
+}
+
+static void ReadRemoteFeatures(RawAddress address,
+                               std::promise<std::vector<uint8_t>> promise) {
+  // The pages are read in order, so the first missing one ends the features.
+  std::vector<uint8_t> features;
+  for (uint8_t i = 0; i <= HCI_EXT_FEATURES_PAGE_MAX; ++i) {
+    const uint8_t* page = BTM_ReadRemoteFeaturesPage(address, i);
+    if (!page) break;
+    features.insert(features.end(), page, page + HCI_FEATURE_BYTES_PER_PAGE);
+  }
+  promise.set_value(features);
+}
+
+::rust::Vec<uint8_t> ControllerIntf::read_remote_features(
+    RustRawAddress address) const {
+  std::promise<std::vector<uint8_t>> promise;
+  auto future = promise.get_future();
+  do_in_main_thread(FROM_HERE,
+                    base::BindOnce(&ReadRemoteFeatures,
+                                   CopyFromRustAddress(address),
+                                   std::move(promise)));
+
+  ::rust::Vec<uint8_t> features;
+  for (uint8_t byte : future.get()) {
+    features.push_back(byte);
+  }
+  return features;
+}
+
+static void OnCommandLatency(hci::OpCode op_code,
+                             std::chrono::microseconds latency) {
+  controller_on_command_latency(static_cast<uint16_t>(op_code),
+                                static_cast<uint64_t>(latency.count()));
+}
+
+void ControllerIntf::start_command_latency_reports() const {
+  // Every command goes through the HCI layer, whichever layer of the stack
+  // sent it.
+  bluetooth::shim::GetHciLayer()->RegisterCommandLatencyHandler(
+      bluetooth::shim::GetGdShimHandler()->Bind(&OnCommandLatency));
+}
+
+// RSSI reported when the controller gives none, as for HCI_Read_RSSI.
+constexpr int8_t kRssiNotAvailable = 127;
+
+static void OnLeTestEnd(hci::CommandCompleteView view) {
+  // The report holds the status and the number of packets received, which
+  // some controllers follow with the average RSSI of these packets.
+  auto payload = view.GetPayload();
+  uint8_t status = payload.size() > 0 ? payload[0] : HCI_ERR_UNSPECIFIED;
+  uint16_t num_packets = 0;
+  int8_t rssi = kRssiNotAvailable;
+  if (status == HCI_SUCCESS && payload.size() >= 3) {
+    num_packets = payload[1] | (payload[2] << 8);
+  }
+  if (status == HCI_SUCCESS && payload.size() >= 4) {
+    rssi = static_cast<int8_t>(payload[3]);
+  }
+  controller_on_le_test_ended(status, num_packets, rssi);
+}
+
+void ControllerIntf::end_le_test() const {
+  // Sent to the HCI layer directly for its whole report, which the legacy
+  // stack truncates to the number of packets.
+  bluetooth::shim::GetHciLayer()->EnqueueCommand(
+      hci::LeTestEndBuilder::Create(),
+      bluetooth::shim::GetGdShimHandler()->BindOnce(&OnLeTestEnd));
+}
+
 }  // namespace rust
 }  // namespace topshim
 }  // namespace bluetooth
@@ -27,6 +27,10 @@ namespace topshim {
 namespace rust {
 
 struct RustRawAddress;
+struct RustRemoteVersion;
+struct RustLocalVersion;
+struct RustIdentityKey;
+struct RustConnectionAddress;
 
 class ControllerIntf {
  public:
@@ -34,6 +38,29 @@ class ControllerIntf {
   ~ControllerIntf();
 
   RustRawAddress read_local_addr() const;
+  uint16_t read_manufacturer() const;
+  RustLocalVersion read_local_version() const;
+  uint64_t read_le_local_features() const;
+  bool supports_secure_connections() const;
+  bool supports_simultaneous_le_bredr() const;
+  void write_page_scan_activity(uint16_t interval, uint16_t window) const;
+  void write_inquiry_scan_activity(uint16_t interval, uint16_t window) const;
+  void write_page_scan_type(bool interlaced) const;
+  void write_inquiry_scan_type(bool interlaced) const;
+  void add_connection_setup_filter(RustRawAddress address) const;
+  void read_controller_lists() const;
+  void clear_filter_accept_list() const;
+  void clear_resolving_list() const;
+  void clear_periodic_advertiser_list() const;
+  void suspend_le_connections(::rust::Vec<RustRawAddress> wake_devices) const;
+  void resume_le_connections() const;
+  bool set_privacy_mode(RustRawAddress address, bool device_privacy) const;
+  RustRemoteVersion read_remote_version(RustRawAddress address) const;
+  ::rust::Vec<uint8_t> read_remote_features(RustRawAddress address) const;
+  RustIdentityKey read_identity_key(RustRawAddress address) const;
+  RustConnectionAddress read_connection_address(RustRawAddress address) const;
+  void start_command_latency_reports() const;
+  void end_le_test() const;
 
  private:
   const controller_t* controller_;
@@ -96,6 +96,10 @@ void BleAdvertiserIntf::OnOwnAddressRead(uint8_t advertiser_id, uint8_t address_
   RustRawAddress converted = rusty::CopyToRustAddress(address);
   rusty::gdadv_on_own_address_read(advertiser_id, address_type, &converted);
 }
+void BleAdvertiserIntf::OnOwnAddressChanged(uint8_t advertiser_id, uint8_t address_type, RawAddress address) {
+  RustRawAddress converted = rusty::CopyToRustAddress(address);
+  rusty::gdadv_on_own_address_changed(advertiser_id, address_type, &converted);
+}
 
 // BleAdvertiserInterface implementations
 
@@ -112,6 +116,10 @@ void BleAdvertiserIntf::GetOwnAddress(uint8_t adv_id) {
       adv_id, base::Bind(&BleAdvertiserIntf::OnGetAddressCallback, base::Unretained(this), adv_id));
 }
 
+void BleAdvertiserIntf::SetAddressRotationInterval(uint8_t adv_id, uint32_t interval_ms) {
+  adv_intf_->SetAddressRotationInterval(adv_id, interval_ms);
+}
+
 void BleAdvertiserIntf::SetParameters(uint8_t adv_id, RustAdvertiseParameters params) {
   AdvertiseParameters converted = internal::ConvertRustAdvParams(params);
   adv_intf_->SetParameters(
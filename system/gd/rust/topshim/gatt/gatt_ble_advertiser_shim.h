@@ -50,6 +50,7 @@ class BleAdvertiserIntf : public AdvertisingCallbacks {
   void OnPeriodicAdvertisingDataSet(uint8_t advertiser_id, uint8_t status) override;
   void OnPeriodicAdvertisingEnabled(uint8_t advertiser_id, bool enable, uint8_t status) override;
   void OnOwnAddressRead(uint8_t advertiser_id, uint8_t address_type, RawAddress address) override;
+  void OnOwnAddressChanged(uint8_t advertiser_id, uint8_t address_type, RawAddress address) override;
 
   // BleAdvertiserInterface implementations
 
@@ -57,6 +58,7 @@ class BleAdvertiserIntf : public AdvertisingCallbacks {
   void Unregister(uint8_t adv_id);
 
   void GetOwnAddress(uint8_t adv_id);
+  void SetAddressRotationInterval(uint8_t adv_id, uint32_t interval_ms);
   void SetParameters(uint8_t adv_id, RustAdvertiseParameters params);
   void SetData(uint8_t adv_id, bool set_scan_rsp, ::rust::Vec<uint8_t> data);
   void Enable(uint8_t adv_id, bool enable, uint16_t duration, uint8_t max_ext_adv_events);
@@ -29,6 +29,8 @@
 #include "include/hardware/bt_common_types.h"
 #include "rust/cxx.h"
 #include "src/profiles/gatt.rs.h"
+#include "stack/include/btm_api.h"
+#include "stack/include/btu.h"
 #include "types/bluetooth/uuid.h"
 #include "types/raw_address.h"
 
@@ -150,8 +152,8 @@ void BleScannerIntf::OnTrackAdvFoundLost(AdvertisingTrackInfo ati) {
       // .scan_response is copied below
   };
 
-  std::copy(rust_info.adv_packet.begin(), rust_info.adv_packet.end(), std::back_inserter(ati.adv_packet));
-  std::copy(rust_info.scan_response.begin(), rust_info.scan_response.end(), std::back_inserter(ati.scan_response));
+  std::copy(ati.adv_packet.begin(), ati.adv_packet.end(), std::back_inserter(rust_info.adv_packet));
+  std::copy(ati.scan_response.begin(), ati.scan_response.end(), std::back_inserter(rust_info.scan_response));
 
   rusty::gdscan_on_track_adv_found_lost(rust_info);
 }
@@ -219,6 +221,11 @@ void BleScannerIntf::SetScanParameters(uint8_t scanner_id, uint16_t scan_interva
       base::Bind(&BleScannerIntf::OnStatusCallback, base::Unretained(this), scanner_id));
 }
 
+void BleScannerIntf::SetScanPhys(uint8_t scanner_id, uint8_t scan_phys) {
+  scanner_intf_->SetScanPhys(
+      scanner_id, scan_phys, base::Bind(&BleScannerIntf::OnStatusCallback, base::Unretained(this), scanner_id));
+}
+
 void BleScannerIntf::BatchscanConfigStorage(
     uint8_t scanner_id,
     int32_t batch_scan_full_max,
@@ -280,6 +287,21 @@ void BleScannerIntf::SyncTxParameters(RustRawAddress address, uint8_t mode, uint
   scanner_intf_->SyncTxParameters(converted, mode, skip, timeout, 0 /* place holder */);
 }
 
+void BleScannerIntf::MsftCommand(uint16_t opcode, ::rust::Vec<uint8_t> params) {
+  std::vector<uint8_t> converted;
+  std::copy(params.begin(), params.end(), std::back_inserter(converted));
+
+  do_in_main_thread(
+      FROM_HERE,
+      base::BindOnce(
+          [](uint16_t opcode, std::vector<uint8_t> params) {
+            BTM_VendorSpecificCommand(
+                opcode, params.size(), params.data(), &BleScannerIntf::OnMsftCommandCallback);
+          },
+          opcode,
+          std::move(converted)));
+}
+
 void BleScannerIntf::OnRegisterCallback(RustUuid uuid, uint8_t scanner_id, uint8_t btm_status) {
   rusty::gdscan_register_callback(uuid, scanner_id, btm_status);
 }
@@ -302,6 +324,10 @@ void BleScannerIntf::OnFilterConfigCallback(
   rusty::gdscan_filter_config_callback(filter_index, filt_type, avbl_space, action, btm_status);
 }
 
+void BleScannerIntf::OnMsftCommandCallback(tBTM_VSC_CMPL* p_result) {
+  rusty::gdscan_msft_command_callback(p_result->opcode, p_result->p_param_buf, p_result->param_len);
+}
+
 void BleScannerIntf::OnPeriodicSyncStarted(
     int,
     uint8_t status,
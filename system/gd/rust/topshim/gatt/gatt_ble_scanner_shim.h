@@ -21,6 +21,7 @@
 #include "include/hardware/ble_scanner.h"
 #include "include/hardware/bt_gatt.h"
 #include "rust/cxx.h"
+#include "stack/include/btm_api_types.h"
 
 namespace bluetooth {
 namespace topshim {
@@ -106,6 +107,10 @@ class BleScannerIntf : public ScanningCallbacks {
   // of this action is returned via |OnStatusCallback|.
   void SetScanParameters(uint8_t scanner_id, uint16_t scan_interval, uint16_t scan_window);
 
+  // Sets the PHYs to scan on, as a bitmask of LE 1M (0x01) and LE Coded (0x04).
+  // The result of this action is returned via |OnStatusCallback|.
+  void SetScanPhys(uint8_t scanner_id, uint8_t scan_phys);
+
   // Configure the batchscan storage and get a response via |OnStatusCallback|.
   void BatchscanConfigStorage(
       uint8_t scanner_id,
@@ -146,6 +151,10 @@ class BleScannerIntf : public ScanningCallbacks {
   // Sync tx parameters to target address. Gets responses via |OnStartSyncCb|.
   void SyncTxParameters(RustRawAddress address, uint8_t mode, uint16_t skip, uint16_t timeout);
 
+  // Send a command of the Microsoft HCI extension with the vendor |opcode| of
+  // the controller. Gets responses via |OnMsftCommandCallback|.
+  void MsftCommand(uint16_t opcode, ::rust::Vec<uint8_t> params);
+
   // Register scanning callbacks to be dispatched to the Rust layer via static
   // methods.
   void RegisterCallbacks();
@@ -162,6 +171,7 @@ class BleScannerIntf : public ScanningCallbacks {
   void OnFilterParamSetupCallback(uint8_t scanner_id, uint8_t avbl_space, uint8_t action_type, uint8_t btm_status);
   void OnFilterConfigCallback(
       uint8_t filt_index, uint8_t filt_type, uint8_t avbl_space, uint8_t action, uint8_t btm_status);
+  static void OnMsftCommandCallback(tBTM_VSC_CMPL* p_result);
 
   BleScannerInterface* scanner_intf_;
 };
@@ -16,11 +16,16 @@
 
 #include "gd/rust/topshim/gatt/gatt_shim.h"
 
+#include <vector>
+
 #include "base/bind.h"
 #include "base/callback.h"
+#include "bta/include/bta_gatt_api.h"
 #include "gd/rust/topshim/common/utils.h"
 #include "rust/cxx.h"
 #include "src/profiles/gatt.rs.h"
+#include "stack/eatt/eatt.h"
+#include "stack/include/btu.h"
 #include "types/raw_address.h"
 
 namespace bluetooth {
@@ -33,6 +38,25 @@ void ReadPhyCallback(int client_if, RawAddress address, uint8_t tx_phy, uint8_t
   bluetooth::topshim::rust::read_phy_callback(client_if, CopyToRustAddress(address), tx_phy, rx_phy, status);
 }
 
+// Btif has no way to set the offset of a prepared write, so long writes chunked by the Rust stack
+// go to BTA directly and complete here instead of in the btif write callback.
+void PrepareWriteCallback(
+    uint16_t conn_id, tGATT_STATUS status, uint16_t handle, uint16_t len, const uint8_t* value, void* data) {
+  bluetooth::topshim::rust::prepare_write_callback(conn_id, static_cast<int>(status), handle, len);
+}
+
+// Runs on the main thread, which owns the EATT state.
+void EattChannelsChanged(const RawAddress& address) {
+  auto mtus = bluetooth::eatt::EattExtension::GetInstance()->GetConnectedChannelsTxMtu(address);
+  bluetooth::topshim::rust::eatt_channels_callback(
+      CopyToRustAddress(address), mtus.data(), mtus.size());
+}
+
+void SetEattChannelsChangedCallback() {
+  bluetooth::eatt::EattExtension::GetInstance()->SetChannelsChangedCallback(
+      base::BindRepeating(&EattChannelsChanged));
+}
+
 }  // namespace internal
 
 int GattClientIntf::read_phy(int client_if, RustRawAddress addr) {
@@ -40,6 +64,24 @@ int GattClientIntf::read_phy(int client_if, RustRawAddress addr) {
   return client_intf_->read_phy(address, base::Bind(&internal::ReadPhyCallback, client_if, address));
 }
 
+int GattClientIntf::prepare_write(
+    int conn_id, uint16_t handle, uint16_t offset, ::rust::Slice<const uint8_t> value, int auth_req) {
+  std::vector<uint8_t> data(value.begin(), value.end());
+  BTA_GATTC_PrepareWrite(
+      conn_id,
+      handle,
+      offset,
+      std::move(data),
+      static_cast<tGATT_AUTH_REQ>(auth_req),
+      internal::PrepareWriteCallback,
+      nullptr);
+  return BT_STATUS_SUCCESS;
+}
+
+void GattClientIntf::register_eatt_callback() {
+  do_in_main_thread(FROM_HERE, base::BindOnce(&internal::SetEattChannelsChangedCallback));
+}
+
 std::unique_ptr<GattClientIntf> GetGattClientProfile(const unsigned char* gatt_intf) {
   return std::make_unique<GattClientIntf>(reinterpret_cast<const btgatt_interface_t*>(gatt_intf)->client);
 }
@@ -34,6 +34,8 @@ class GattClientIntf {
   ~GattClientIntf() = default;
 
   int read_phy(int client_if, RustRawAddress bt_addr);
+  int prepare_write(int conn_id, uint16_t handle, uint16_t offset, ::rust::Slice<const uint8_t> value, int auth_req);
+  void register_eatt_callback();
 
  private:
   const btgatt_client_interface_t* client_intf_;
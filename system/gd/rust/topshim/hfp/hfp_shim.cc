@@ -41,6 +41,26 @@ static void audio_state_cb(bluetooth::headset::bthf_audio_state_t state, RawAddr
   rusty::hfp_audio_state_callback(state, raddr);
 }
 
+static void volume_update_cb(uint8_t volume, RawAddress* addr) {
+  RustRawAddress raddr = rusty::CopyToRustAddress(*addr);
+  rusty::hfp_volume_update_callback(volume, raddr);
+}
+
+static void battery_level_cb(uint8_t level, RawAddress* addr) {
+  RustRawAddress raddr = rusty::CopyToRustAddress(*addr);
+  rusty::hfp_battery_level_callback(level, raddr);
+}
+
+static void answer_call_cb(RawAddress* addr) {
+  RustRawAddress raddr = rusty::CopyToRustAddress(*addr);
+  rusty::hfp_answer_call_callback(raddr);
+}
+
+static void hangup_call_cb(RawAddress* addr) {
+  RustRawAddress raddr = rusty::CopyToRustAddress(*addr);
+  rusty::hfp_hangup_call_callback(raddr);
+}
+
 }  // namespace internal
 
 class DBusHeadsetCallbacks : public headset::Callbacks {
@@ -84,14 +104,21 @@ class DBusHeadsetCallbacks : public headset::Callbacks {
   void VoiceRecognitionCallback(
       [[maybe_unused]] headset::bthf_vr_state_t state, [[maybe_unused]] RawAddress* bd_addr) override {}
 
-  void AnswerCallCallback([[maybe_unused]] RawAddress* bd_addr) override {}
+  void AnswerCallCallback(RawAddress* bd_addr) override {
+    LOG_INFO("AnswerCallCallback from %s", bd_addr->ToString().c_str());
+    topshim::rust::internal::answer_call_cb(bd_addr);
+  }
 
-  void HangupCallCallback([[maybe_unused]] RawAddress* bd_addr) override {}
+  void HangupCallCallback(RawAddress* bd_addr) override {
+    LOG_INFO("HangupCallCallback from %s", bd_addr->ToString().c_str());
+    topshim::rust::internal::hangup_call_cb(bd_addr);
+  }
 
-  void VolumeControlCallback(
-      [[maybe_unused]] headset::bthf_volume_type_t type,
-      [[maybe_unused]] int volume,
-      [[maybe_unused]] RawAddress* bd_addr) override {}
+  void VolumeControlCallback(headset::bthf_volume_type_t type, int volume, RawAddress* bd_addr) override {
+    // Only the speaker volume is synchronized with the audio server.
+    if (type != headset::bthf_volume_type_t::BTHF_VOLUME_TYPE_SPK) return;
+    topshim::rust::internal::volume_update_cb(volume, bd_addr);
+  }
 
   void DialCallCallback([[maybe_unused]] char* number, [[maybe_unused]] RawAddress* bd_addr) override {}
 
@@ -156,6 +183,15 @@ class DBusHeadsetCallbacks : public headset::Callbacks {
   }
 
   void AtBievCallback(headset::bthf_hf_ind_type_t ind_id, int ind_value, RawAddress* bd_addr) override {
+    if (ind_id == headset::bthf_hf_ind_type_t::BTHF_HF_IND_BATTERY_LEVEL_STATUS) {
+      if (ind_value < 0 || ind_value > 100) {
+        LOG_WARN("Invalid battery level %d from addr %s", ind_value, bd_addr->ToString().c_str());
+        return;
+      }
+      topshim::rust::internal::battery_level_cb(static_cast<uint8_t>(ind_value), bd_addr);
+      return;
+    }
+
     LOG_WARN(
         "AT+BIEV=%d,%d from addr %s: Bluetooth HF Indicators is not supported.",
         ind_id,
@@ -225,6 +261,25 @@ int HfpIntf::disconnect_audio(RustRawAddress bt_addr) {
   return intf_->DisconnectAudio(&addr);
 }
 
+int HfpIntf::set_volume(int8_t volume, RustRawAddress bt_addr) {
+  RawAddress addr = rusty::CopyFromRustAddress(bt_addr);
+  return intf_->VolumeControl(headset::bthf_volume_type_t::BTHF_VOLUME_TYPE_SPK, volume, &addr);
+}
+
+int HfpIntf::phone_state_change(
+    uint32_t num_active, uint32_t num_held, uint32_t call_setup_state, rust::Str number, RustRawAddress bt_addr) {
+  RawAddress addr = rusty::CopyFromRustAddress(bt_addr);
+  std::string number_str(number);
+  return intf_->PhoneStateChange(
+      num_active,
+      num_held,
+      (headset::bthf_call_state_t)call_setup_state,
+      number_str.c_str(),
+      headset::BTHF_CALL_ADDRTYPE_UNKNOWN,
+      /*name=*/"",
+      &addr);
+}
+
 void HfpIntf::cleanup() {}
 
 std::unique_ptr<HfpIntf> GetHfpProfile(const unsigned char* btif) {
@@ -20,6 +20,7 @@
 
 #include "btif/include/btif_hf.h"
 #include "include/hardware/bluetooth_headset_callbacks.h"
+#include "rust/cxx.h"
 #include "types/raw_address.h"
 
 namespace bluetooth {
@@ -37,6 +38,9 @@ class HfpIntf {
   int connect_audio(RustRawAddress bt_addr);
   int disconnect(RustRawAddress bt_addr);
   int disconnect_audio(RustRawAddress bt_addr);
+  int set_volume(int8_t volume, RustRawAddress bt_addr);
+  int phone_state_change(
+      uint32_t num_active, uint32_t num_held, uint32_t call_setup_state, rust::Str number, RustRawAddress bt_addr);
   void cleanup();
 
  private:
@@ -0,0 +1,359 @@
+/*
+ * Copyright 2023 The Android Open Source Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#include "gd/rust/topshim/l2cap/l2cap_shim.h"
+
+#include <base/bind.h>
+
+#include <algorithm>
+#include <deque>
+#include <map>
+#include <memory>
+#include <vector>
+
+#include "device/include/controller.h"
+#include "gd/rust/topshim/common/utils.h"
+#include "osi/include/allocator.h"
+#include "osi/include/log.h"
+#include "rust/cxx.h"
+#include "src/l2cap.rs.h"
+#include "stack/btm/btm_sec.h"
+#include "stack/include/bt_hdr.h"
+#include "stack/include/btm_api_types.h"
+#include "stack/include/btu.h"
+#include "stack/include/l2c_api.h"
+#include "types/raw_address.h"
+
+namespace bluetooth {
+namespace topshim {
+namespace rust {
+
+namespace rusty = ::bluetooth::topshim::rust;
+
+namespace internal {
+static L2capIntf* g_l2cap_intf;
+
+struct EcfcServer {
+  uint32_t request_id;
+  bool secure;
+  tL2CAP_LE_CFG_INFO cfg;
+};
+
+struct EcfcChannel {
+  uint32_t request_id;
+  RawAddress address;
+  uint16_t psm;
+  // PSM the channel is registered on, virtual for the outgoing channels.
+  uint16_t registered_psm;
+  tL2CAP_LE_CFG_INFO cfg;
+  // SDUs delivered to Rust and not acknowledged yet. The credits of the peer
+  // are held meanwhile.
+  uint16_t unacked_sdus = 0;
+  // SDUs written by Rust, waiting for the channel to be uncongested.
+  std::deque<std::vector<uint8_t>> pending_sdus;
+  bool congested = false;
+};
+
+// SDUs of the MTU the peer may send before the client reads them.
+constexpr uint32_t kCreditWindowSdus = 2;
+
+// Only accessed on the main thread.
+static std::map<uint16_t, EcfcServer> g_servers;
+static std::map<uint16_t, EcfcChannel> g_channels;
+
+static tL2CAP_LE_CFG_INFO MakeConfig(uint16_t mtu, uint8_t channel_count) {
+  tL2CAP_LE_CFG_INFO cfg;
+  cfg.mtu = mtu;
+  cfg.mps = std::min(mtu, controller_get_interface()->get_acl_data_size_ble());
+  cfg.number_of_channels = channel_count;
+  // The SDUs are split into PDUs of the MPS, the first one carrying the SDU
+  // length.
+  uint32_t pdus_per_sdu = (mtu + 2 + cfg.mps - 1) / cfg.mps;
+  cfg.credits = std::min<uint32_t>(kCreditWindowSdus * pdus_per_sdu,
+                                   L2CAP_LE_CREDIT_MAX);
+  return cfg;
+}
+
+// Forgets a channel, and releases the virtual PSM of its request once all its channels are gone.
+static void RemoveChannel(std::map<uint16_t, EcfcChannel>::iterator it) {
+  uint16_t registered_psm = it->second.registered_psm;
+  g_channels.erase(it);
+
+  if (g_servers.count(registered_psm)) return;
+  for (const auto& [lcid, channel] : g_channels) {
+    if (channel.registered_psm == registered_psm) return;
+  }
+  L2CA_DeregisterLECoc(registered_psm);
+  L2CA_FreeLePSM(registered_psm);
+}
+
+static void ReportConnected(uint16_t lcid, uint16_t result) {
+  auto it = g_channels.find(lcid);
+  if (it == g_channels.end()) return;
+
+  tL2CAP_LE_CFG_INFO peer_cfg = {};
+  if (result == L2CAP_LE_RESULT_CONN_OK && !L2CA_GetPeerLECocConfig(lcid, &peer_cfg)) {
+    result = L2CAP_LE_RESULT_NO_RESOURCES;
+  }
+
+  RustRawAddress address = rusty::CopyToRustAddress(it->second.address);
+  rusty::l2cap_on_ecfc_connected(
+      it->second.request_id,
+      &address,
+      it->second.psm,
+      lcid,
+      result,
+      it->second.cfg.mtu,
+      it->second.cfg.mps,
+      it->second.cfg.credits,
+      peer_cfg.mtu,
+      peer_cfg.mps);
+
+  if (result != L2CAP_LE_RESULT_CONN_OK) RemoveChannel(it);
+}
+
+static void OnConnectInd(
+    const RawAddress& address, std::vector<uint16_t>& lcids, uint16_t psm, uint16_t peer_mtu, uint8_t identifier) {
+  std::vector<uint16_t> rejected;
+  auto server = g_servers.find(psm);
+  if (server == g_servers.end()) {
+    L2CA_ConnectCreditBasedRsp(address, identifier, rejected, L2CAP_LE_RESULT_NO_PSM, nullptr);
+    return;
+  }
+
+  if (server->second.secure && !BTM_IsEncrypted(address, BT_TRANSPORT_LE)) {
+    LOG_WARN("Rejecting unencrypted ECFC channels to PSM 0x%04x", psm);
+    L2CA_ConnectCreditBasedRsp(address, identifier, rejected, L2CAP_LE_RESULT_INSUFFICIENT_ENCRYP, nullptr);
+    return;
+  }
+
+  tL2CAP_LE_CFG_INFO cfg = server->second.cfg;
+  if (!L2CA_ConnectCreditBasedRsp(address, identifier, lcids, L2CAP_LE_RESULT_CONN_OK, &cfg)) {
+    LOG_WARN("Failed to accept ECFC channels to PSM 0x%04x", psm);
+    return;
+  }
+
+  for (uint16_t lcid : lcids) {
+    g_channels[lcid] = {server->second.request_id, address, psm, psm, cfg};
+    ReportConnected(lcid, L2CAP_LE_RESULT_CONN_OK);
+  }
+}
+
+static void OnConnectCfm(const RawAddress& address, uint16_t lcid, uint16_t peer_mtu, uint16_t result) {
+  ReportConnected(lcid, result);
+}
+
+static void OnReconfigCompleted(const RawAddress& address, uint16_t lcid, bool is_local_cfg, tL2CAP_LE_CFG_INFO* cfg) {}
+
+static void OnCollisionInd(const RawAddress& address) {
+  LOG_INFO("ECFC connection collision with %s", address.ToString().c_str());
+}
+
+static void OnDisconnectInd(uint16_t lcid, bool please_confirm) {
+  auto it = g_channels.find(lcid);
+  if (it == g_channels.end()) return;
+
+  RemoveChannel(it);
+  rusty::l2cap_on_ecfc_disconnected(lcid);
+}
+
+static void OnError(uint16_t lcid, uint16_t reason) {
+  LOG_WARN("ECFC channel 0x%04x error 0x%04x", lcid, reason);
+}
+
+static void OnDataInd(uint16_t lcid, BT_HDR* p_buf) {
+  auto it = g_channels.find(lcid);
+  if (it != g_channels.end()) {
+    // The peer gets its credits back once all the SDUs are acknowledged, so
+    // that it stops sending while the client does not read.
+    if (it->second.unacked_sdus++ == 0) L2CA_HoldLeCocCredits(lcid, true);
+    const uint8_t* data = p_buf->data + p_buf->offset;
+    rusty::l2cap_on_ecfc_data(lcid, data, p_buf->len);
+  }
+  osi_free(p_buf);
+}
+
+// Writes the pending SDUs of a channel until it is congested, and lets Rust
+// write as many more.
+static void FlushEcfc(uint16_t lcid) {
+  auto it = g_channels.find(lcid);
+  if (it == g_channels.end()) return;
+
+  EcfcChannel& channel = it->second;
+  uint16_t written = 0;
+  while (!channel.congested && !channel.pending_sdus.empty()) {
+    std::vector<uint8_t> data = std::move(channel.pending_sdus.front());
+    channel.pending_sdus.pop_front();
+    written++;
+
+    BT_HDR* p_buf = (BT_HDR*)osi_malloc(sizeof(BT_HDR) + L2CAP_MIN_OFFSET + data.size());
+    p_buf->offset = L2CAP_MIN_OFFSET;
+    p_buf->len = data.size();
+    std::copy(data.begin(), data.end(), p_buf->data + p_buf->offset);
+
+    switch (L2CA_DataWrite(lcid, p_buf)) {
+      case L2CAP_DW_CONGESTED:
+        // Accepted, but no more until the channel is uncongested.
+        channel.congested = true;
+        break;
+      case L2CAP_DW_FAILED:
+        LOG_WARN("Failed to write %zu bytes to ECFC channel 0x%04x", data.size(), lcid);
+        break;
+      default:
+        break;
+    }
+  }
+
+  if (written) rusty::l2cap_on_ecfc_sent(lcid, written);
+}
+
+static void OnCongestionStatus(uint16_t lcid, bool congested) {
+  auto it = g_channels.find(lcid);
+  if (it == g_channels.end()) return;
+
+  it->second.congested = congested;
+  // Not written from the callback, as L2CAP may be sending.
+  if (!congested) do_in_main_thread(FROM_HERE, base::BindOnce(&FlushEcfc, lcid));
+}
+
+static tL2CAP_APPL_INFO MakeApplInfo() {
+  tL2CAP_APPL_INFO info = {};
+  info.pL2CA_CreditBasedConnectInd_Cb = OnConnectInd;
+  info.pL2CA_CreditBasedConnectCfm_Cb = OnConnectCfm;
+  info.pL2CA_CreditBasedReconfigCompleted_Cb = OnReconfigCompleted;
+  info.pL2CA_CreditBasedCollisionInd_Cb = OnCollisionInd;
+  info.pL2CA_DisconnectInd_Cb = OnDisconnectInd;
+  info.pL2CA_Error_Cb = OnError;
+  info.pL2CA_DataInd_Cb = OnDataInd;
+  info.pL2CA_CongestionStatus_Cb = OnCongestionStatus;
+  return info;
+}
+
+static uint16_t SecurityLevel(bool secure) {
+  return secure ? (BTM_SEC_IN_ENCRYPT | BTM_SEC_OUT_ENCRYPT) : BTM_SEC_NONE;
+}
+
+static void ListenEcfc(uint32_t request_id, uint16_t mtu, bool secure) {
+  uint16_t psm = L2CA_AllocateLePSM();
+  if (psm == 0) {
+    rusty::l2cap_on_ecfc_listening(request_id, 0);
+    return;
+  }
+
+  tL2CAP_LE_CFG_INFO cfg = MakeConfig(mtu, L2CAP_CREDIT_BASED_MAX_CIDS);
+  if (L2CA_RegisterLECoc(psm, MakeApplInfo(), SecurityLevel(secure), cfg) == 0) {
+    L2CA_FreeLePSM(psm);
+    rusty::l2cap_on_ecfc_listening(request_id, 0);
+    return;
+  }
+
+  g_servers[psm] = {request_id, secure, cfg};
+  rusty::l2cap_on_ecfc_listening(request_id, psm);
+}
+
+static void StopListeningEcfc(uint16_t psm) {
+  if (!g_servers.erase(psm)) return;
+  L2CA_DeregisterLECoc(psm);
+  L2CA_FreeLePSM(psm);
+}
+
+static void ConnectEcfc(
+    uint32_t request_id, RawAddress address, uint16_t psm, uint8_t channel_count, uint16_t mtu, bool secure) {
+  tL2CAP_LE_CFG_INFO cfg = MakeConfig(mtu, channel_count);
+
+  // Outgoing channels are registered on a virtual PSM, as they accept no connection.
+  tL2CAP_APPL_INFO info = MakeApplInfo();
+  info.pL2CA_CreditBasedConnectInd_Cb = nullptr;
+  uint16_t vpsm = L2CA_RegisterLECoc(psm, info, SecurityLevel(secure), cfg);
+  std::vector<uint16_t> lcids;
+  if (vpsm != 0) {
+    lcids = L2CA_ConnectCreditBasedReq(vpsm, address, &cfg);
+    if (lcids.empty()) {
+      L2CA_DeregisterLECoc(vpsm);
+      L2CA_FreeLePSM(vpsm);
+    }
+  }
+
+  rusty::l2cap_on_ecfc_connecting(request_id, static_cast<uint8_t>(lcids.size()));
+  for (uint16_t lcid : lcids) {
+    g_channels[lcid] = {request_id, address, psm, vpsm, cfg};
+  }
+}
+
+static void WriteEcfc(uint16_t lcid, std::vector<uint8_t> data) {
+  auto it = g_channels.find(lcid);
+  if (it == g_channels.end()) return;
+
+  it->second.pending_sdus.push_back(std::move(data));
+  FlushEcfc(lcid);
+}
+
+static void AckEcfc(uint16_t lcid) {
+  auto it = g_channels.find(lcid);
+  if (it == g_channels.end() || it->second.unacked_sdus == 0) return;
+
+  if (--it->second.unacked_sdus == 0) L2CA_HoldLeCocCredits(lcid, false);
+}
+
+static void DisconnectEcfc(uint16_t lcid) {
+  auto it = g_channels.find(lcid);
+  if (it == g_channels.end()) return;
+
+  L2CA_DisconnectReq(lcid);
+  RemoveChannel(it);
+}
+}  // namespace internal
+
+std::unique_ptr<L2capIntf> GetL2capInterface() {
+  if (internal::g_l2cap_intf) std::abort();
+  auto l2cap_intf = std::make_unique<L2capIntf>();
+  internal::g_l2cap_intf = l2cap_intf.get();
+  return l2cap_intf;
+}
+
+void L2capIntf::listen_ecfc(uint32_t request_id, uint16_t mtu, bool secure) const {
+  do_in_main_thread(FROM_HERE, base::BindOnce(&internal::ListenEcfc, request_id, mtu, secure));
+}
+
+void L2capIntf::stop_listening_ecfc(uint16_t psm) const {
+  do_in_main_thread(FROM_HERE, base::BindOnce(&internal::StopListeningEcfc, psm));
+}
+
+void L2capIntf::connect_ecfc(
+    uint32_t request_id, RustRawAddress address, uint16_t psm, uint8_t channel_count, uint16_t mtu, bool secure)
+    const {
+  do_in_main_thread(
+      FROM_HERE,
+      base::BindOnce(
+          &internal::ConnectEcfc, request_id, CopyFromRustAddress(address), psm, channel_count, mtu, secure));
+}
+
+void L2capIntf::write_ecfc(uint16_t lcid, ::rust::Vec<uint8_t> data) const {
+  std::vector<uint8_t> converted(data.begin(), data.end());
+  do_in_main_thread(FROM_HERE, base::BindOnce(&internal::WriteEcfc, lcid, std::move(converted)));
+}
+
+void L2capIntf::ack_ecfc(uint16_t lcid) const {
+  do_in_main_thread(FROM_HERE, base::BindOnce(&internal::AckEcfc, lcid));
+}
+
+void L2capIntf::disconnect_ecfc(uint16_t lcid) const {
+  do_in_main_thread(FROM_HERE, base::BindOnce(&internal::DisconnectEcfc, lcid));
+}
+
+}  // namespace rust
+}  // namespace topshim
+}  // namespace bluetooth
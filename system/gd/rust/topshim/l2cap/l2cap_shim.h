@@ -0,0 +1,66 @@
+/*
+ * Copyright 2023 The Android Open Source Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+#ifndef GD_RUST_TOPSHIM_L2CAP_L2CAP_SHIM_H
+#define GD_RUST_TOPSHIM_L2CAP_L2CAP_SHIM_H
+
+#include <memory>
+
+#include "rust/cxx.h"
+
+namespace bluetooth {
+namespace topshim {
+namespace rust {
+
+struct RustRawAddress;
+
+// L2CAP Enhanced Credit Based Flow Control channels, which libbluetooth does
+// not expose through the socket interface.
+//
+// All the calls are posted to the main thread. Their results, and the data
+// received on the channels, are dispatched to the Rust callbacks.
+//
+// The data is flow controlled both ways: the peer gets credits back once Rust
+// acknowledged the SDUs it received, and Rust is told how many SDUs it wrote
+// were handed over to L2CAP, the others waiting for the channel to be
+// uncongested.
+class L2capIntf {
+ public:
+  L2capIntf() = default;
+  ~L2capIntf() = default;
+
+  // Listens on a PSM allocated by the stack, accepting channels of up to
+  // |mtu| bytes.
+  void listen_ecfc(uint32_t request_id, uint16_t mtu, bool secure) const;
+  void stop_listening_ecfc(uint16_t psm) const;
+
+  // Requests |channel_count| channels to a PSM of a device connected over LE.
+  void connect_ecfc(
+      uint32_t request_id, RustRawAddress address, uint16_t psm, uint8_t channel_count, uint16_t mtu, bool secure)
+      const;
+
+  void write_ecfc(uint16_t lcid, ::rust::Vec<uint8_t> data) const;
+  // Acknowledges an SDU received on the channel, once delivered to the client.
+  void ack_ecfc(uint16_t lcid) const;
+  void disconnect_ecfc(uint16_t lcid) const;
+};
+
+std::unique_ptr<L2capIntf> GetL2capInterface();
+
+}  // namespace rust
+}  // namespace topshim
+}  // namespace bluetooth
+
+#endif  // GD_RUST_TOPSHIM_L2CAP_L2CAP_SHIM_H
@@ -0,0 +1,155 @@
+/*
+ * Copyright (C) 2022 The Android Open Source Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#include "gd/rust/topshim/le_audio/le_audio_shim.h"
+
+#include "gd/os/log.h"
+#include "gd/rust/topshim/common/utils.h"
+#include "include/hardware/bluetooth.h"
+#include "include/hardware/bt_le_audio.h"
+#include "src/profiles/le_audio.rs.h"
+#include "types/raw_address.h"
+
+namespace rusty = ::bluetooth::topshim::rust;
+
+namespace bluetooth {
+namespace topshim {
+namespace rust {
+namespace internal {
+static LeAudioClientIntf* g_le_audio_if;
+}  // namespace internal
+
+class DBusLeAudioClientCallbacks : public le_audio::LeAudioClientCallbacks {
+ public:
+  static le_audio::LeAudioClientCallbacks* GetInstance() {
+    static le_audio::LeAudioClientCallbacks* instance = new DBusLeAudioClientCallbacks();
+    return instance;
+  }
+
+  DBusLeAudioClientCallbacks(){};
+
+  // le_audio::LeAudioClientCallbacks
+  void OnInitialized(void) override {
+    LOG_INFO("LE Audio client initialized");
+  }
+
+  void OnConnectionState(le_audio::ConnectionState state, const RawAddress& address) override {
+    LOG_INFO("OnConnectionState %d from %s", static_cast<int>(state), address.ToString().c_str());
+    rusty::le_audio_connection_state_callback(
+        static_cast<uint32_t>(state), rusty::CopyToRustAddress(address));
+  }
+
+  void OnGroupStatus(int group_id, le_audio::GroupStatus group_status) override {
+    rusty::le_audio_group_status_callback(group_id, static_cast<uint32_t>(group_status));
+  }
+
+  void OnGroupNodeStatus(
+      const RawAddress& bd_addr, int group_id, le_audio::GroupNodeStatus node_status) override {
+    rusty::le_audio_group_node_status_callback(
+        rusty::CopyToRustAddress(bd_addr), group_id, static_cast<uint32_t>(node_status));
+  }
+
+  void OnAudioConf(
+      uint8_t direction,
+      int group_id,
+      uint32_t snk_audio_location,
+      uint32_t src_audio_location,
+      uint16_t avail_cont) override {
+    rusty::le_audio_audio_conf_callback(
+        direction, group_id, snk_audio_location, src_audio_location, avail_cont);
+  }
+
+  void OnSinkAudioLocationAvailable(
+      [[maybe_unused]] const RawAddress& address,
+      [[maybe_unused]] uint32_t snk_audio_locations) override {}
+
+  void OnAudioLocalCodecCapabilities(
+      [[maybe_unused]] std::vector<le_audio::btle_audio_codec_config_t> local_input_capa_codec_conf,
+      [[maybe_unused]] std::vector<le_audio::btle_audio_codec_config_t>
+          local_output_capa_codec_conf) override {}
+
+  void OnAudioGroupCodecConf(
+      int group_id,
+      le_audio::btle_audio_codec_config_t input_codec_conf,
+      le_audio::btle_audio_codec_config_t output_codec_conf,
+      [[maybe_unused]] std::vector<le_audio::btle_audio_codec_config_t> input_selectable_codec_conf,
+      [[maybe_unused]] std::vector<le_audio::btle_audio_codec_config_t>
+          output_selectable_codec_conf) override {
+    rusty::le_audio_group_codec_conf_callback(
+        group_id,
+        static_cast<uint32_t>(input_codec_conf.codec_type),
+        static_cast<uint32_t>(output_codec_conf.codec_type));
+  }
+};
+
+void LeAudioClientIntf::init() {
+  intf_->Initialize(DBusLeAudioClientCallbacks::GetInstance(), {});
+}
+
+void LeAudioClientIntf::connect(RustRawAddress bt_addr) {
+  intf_->Connect(rusty::CopyFromRustAddress(bt_addr));
+}
+
+void LeAudioClientIntf::disconnect(RustRawAddress bt_addr) {
+  intf_->Disconnect(rusty::CopyFromRustAddress(bt_addr));
+}
+
+void LeAudioClientIntf::group_add_node(int group_id, RustRawAddress bt_addr) {
+  intf_->GroupAddNode(group_id, rusty::CopyFromRustAddress(bt_addr));
+}
+
+void LeAudioClientIntf::group_remove_node(int group_id, RustRawAddress bt_addr) {
+  intf_->GroupRemoveNode(group_id, rusty::CopyFromRustAddress(bt_addr));
+}
+
+void LeAudioClientIntf::group_set_active(int group_id) {
+  intf_->GroupSetActive(group_id);
+}
+
+void LeAudioClientIntf::set_codec_config_preference(
+    int group_id, uint32_t input_codec_type, uint32_t output_codec_type) {
+  le_audio::btle_audio_codec_config_t input_config = {
+      .codec_type = static_cast<le_audio::btle_audio_codec_index_t>(input_codec_type)};
+  le_audio::btle_audio_codec_config_t output_config = {
+      .codec_type = static_cast<le_audio::btle_audio_codec_index_t>(output_codec_type)};
+  intf_->SetCodecConfigPreference(group_id, input_config, output_config);
+}
+
+void LeAudioClientIntf::set_in_call(bool in_call) {
+  intf_->SetInCall(in_call);
+}
+
+void LeAudioClientIntf::cleanup() {
+  intf_->Cleanup();
+}
+
+std::unique_ptr<LeAudioClientIntf> GetLeAudioClientProfile(const unsigned char* btif) {
+  if (internal::g_le_audio_if) std::abort();
+
+  const bt_interface_t* btif_ = reinterpret_cast<const bt_interface_t*>(btif);
+
+  auto le_audio_if = std::make_unique<LeAudioClientIntf>(
+      const_cast<le_audio::LeAudioClientInterface*>(
+          reinterpret_cast<const le_audio::LeAudioClientInterface*>(
+              btif_->get_profile_interface(BT_PROFILE_LE_AUDIO_ID))));
+  internal::g_le_audio_if = le_audio_if.get();
+
+  return le_audio_if;
+}
+
+}  // namespace rust
+}  // namespace topshim
+}  // namespace bluetooth
@@ -0,0 +1,53 @@
+/*
+ * Copyright (C) 2022 The Android Open Source Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#pragma once
+
+#include <memory>
+
+#include "include/hardware/bt_le_audio.h"
+#include "types/raw_address.h"
+
+namespace bluetooth {
+namespace topshim {
+namespace rust {
+
+struct RustRawAddress;
+
+class LeAudioClientIntf {
+ public:
+  LeAudioClientIntf(le_audio::LeAudioClientInterface* intf) : intf_(intf){};
+
+  void init();
+  void connect(RustRawAddress bt_addr);
+  void disconnect(RustRawAddress bt_addr);
+  void group_add_node(int group_id, RustRawAddress bt_addr);
+  void group_remove_node(int group_id, RustRawAddress bt_addr);
+  void group_set_active(int group_id);
+  void set_codec_config_preference(
+      int group_id, uint32_t input_codec_type, uint32_t output_codec_type);
+  void set_in_call(bool in_call);
+  void cleanup();
+
+ private:
+  le_audio::LeAudioClientInterface* intf_;
+};
+
+std::unique_ptr<LeAudioClientIntf> GetLeAudioClientProfile(const unsigned char* btif);
+
+}  // namespace rust
+}  // namespace topshim
+}  // namespace bluetooth
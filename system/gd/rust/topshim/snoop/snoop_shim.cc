@@ -0,0 +1,52 @@
+/*
+ * Copyright 2023 The Android Open Source Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#include "gd/rust/topshim/snoop/snoop_shim.h"
+
+#include <string>
+
+#include "gd/hal/snoop_logger.h"
+#include "gd/os/system_properties.h"
+#include "main/shim/entry.h"
+#include "rust/cxx.h"
+#include "src/snoop.rs.h"
+
+namespace bluetooth {
+namespace topshim {
+namespace rust {
+
+void SetSnoopLogMode(::rust::Str mode) {
+  std::string btsnoop_mode(mode);
+  // The snoop logger reads the mode from the property when the stack starts.
+  os::SetSystemProperty(hal::SnoopLogger::kBtSnoopLogModeProperty, btsnoop_mode);
+
+  auto snoop_logger = shim::GetSnoopLogger();
+  if (snoop_logger) {
+    snoop_logger->SetMode(btsnoop_mode);
+  }
+}
+
+::rust::String GetSnoopLogMode() {
+  auto snoop_logger = shim::GetSnoopLogger();
+  if (snoop_logger) {
+    return ::rust::String(snoop_logger->GetMode());
+  }
+  return ::rust::String(hal::SnoopLogger::GetBtSnoopMode());
+}
+
+}  // namespace rust
+}  // namespace topshim
+}  // namespace bluetooth
@@ -0,0 +1,37 @@
+/*
+ * Copyright 2023 The Android Open Source Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+#ifndef GD_RUST_TOPSHIM_SNOOP_SNOOP_SHIM_H
+#define GD_RUST_TOPSHIM_SNOOP_SNOOP_SHIM_H
+
+#include "rust/cxx.h"
+
+namespace bluetooth {
+namespace topshim {
+namespace rust {
+
+// Sets the mode of the btsnoop log. The mode takes effect right away if the
+// HCI layer is started, and is kept for the next starts of the stack.
+void SetSnoopLogMode(::rust::Str mode);
+
+// Returns the mode of the btsnoop log, or the mode of the next start of the
+// stack if the HCI layer is not started.
+::rust::String GetSnoopLogMode();
+
+}  // namespace rust
+}  // namespace topshim
+}  // namespace bluetooth
+
+#endif  // GD_RUST_TOPSHIM_SNOOP_SNOOP_SHIM_H
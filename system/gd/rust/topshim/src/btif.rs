@@ -553,6 +553,7 @@ pub enum SupportedProfiles {
     A2dp,
     Gatt,
     Sdp,
+    Socket,
 }
 
 impl From<SupportedProfiles> for Vec<u8> {
@@ -563,6 +564,7 @@ impl From<SupportedProfiles> for Vec<u8> {
             SupportedProfiles::A2dp => "a2dp",
             SupportedProfiles::Gatt => "gatt",
             SupportedProfiles::Sdp => "sdp",
+            SupportedProfiles::Socket => "socket",
         }
         .bytes()
         .chain("\0".bytes())
@@ -704,10 +706,10 @@ pub enum BaseCallbacks {
     AddressConsolidate(RawAddress, RawAddress),
     LeAddressAssociate(RawAddress, RawAddress),
     AclState(BtStatus, RawAddress, BtAclState, BtTransport, BtHciErrorCode),
+    DutModeRecv(u16, Vec<u8>, u8),
+    LeTestMode(BtStatus, u16),
     // Unimplemented so far:
     // thread_evt_cb
-    // dut_mode_recv_cb
-    // le_test_mode_cb
     // energy_info_cb
     // link_quality_report_cb
     // generate_local_oob_data_cb
@@ -769,6 +771,13 @@ u32 -> BtStatus, *mut FfiAddress, bindings::bt_acl_state_t -> BtAclState, i32 ->
     let _1 = unsafe { *(_1 as *const RawAddress) };
 });
 
+cb_variant!(BaseCb, dut_mode_recv_cb -> BaseCallbacks::DutModeRecv,
+u16, *mut u8, u8, {
+    let _1 = ptr_to_vec(_1, _2 as usize);
+});
+
+cb_variant!(BaseCb, le_test_mode_cb -> BaseCallbacks::LeTestMode, u32 -> BtStatus, u16);
+
 struct RawInterfaceWrapper {
     pub raw: *const bindings::bt_interface_t,
 }
@@ -881,8 +890,8 @@ impl BluetoothInterface {
             le_address_associate_cb: Some(le_address_associate_cb),
             acl_state_changed_cb: Some(acl_state_cb),
             thread_evt_cb: None,
-            dut_mode_recv_cb: None,
-            le_test_mode_cb: None,
+            dut_mode_recv_cb: Some(dut_mode_recv_cb),
+            le_test_mode_cb: Some(le_test_mode_cb),
             energy_info_cb: None,
             link_quality_report_cb: None,
             generate_local_oob_data_cb: None,
@@ -1021,6 +1030,18 @@ impl BluetoothInterface {
         ccall!(self, ssp_reply, ffi_addr, cvariant, accept, passkey)
     }
 
+    pub fn dut_mode_configure(&self, enable: bool) -> i32 {
+        ccall!(self, dut_mode_configure, enable as u8)
+    }
+
+    pub fn dut_mode_send(&self, opcode: u16, params: &mut Vec<u8>) -> i32 {
+        ccall!(self, dut_mode_send, opcode, params.as_mut_ptr(), params.len() as u8)
+    }
+
+    pub fn le_test_mode(&self, opcode: u16, params: &mut Vec<u8>) -> i32 {
+        ccall!(self, le_test_mode, opcode, params.as_mut_ptr(), params.len() as u8)
+    }
+
     pub fn clear_event_filter(&self) -> i32 {
         ccall!(self, clear_event_filter)
     }
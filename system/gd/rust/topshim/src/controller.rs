@@ -1,9 +1,42 @@
+use crate::topstack::get_dispatchers;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use topshim_macros::cb_variant;
+
 #[cxx::bridge(namespace = bluetooth::topshim::rust)]
 mod ffi {
     pub struct RustRawAddress {
         address: [u8; 6],
     }
 
+    pub struct RustRemoteVersion {
+        valid: bool,
+        lmp_version: u8,
+        manufacturer: u16,
+        lmp_subversion: u16,
+    }
+
+    pub struct RustIdentityKey {
+        valid: bool,
+        irk: [u8; 16],
+        identity_address: RustRawAddress,
+    }
+
+    pub struct RustConnectionAddress {
+        valid: bool,
+        address_type: u8,
+        address: RustRawAddress,
+    }
+
+    pub struct RustLocalVersion {
+        hci_version: u8,
+        hci_revision: u16,
+        lmp_version: u8,
+        lmp_subversion: u16,
+        manufacturer: u16,
+    }
+
     unsafe extern "C++" {
         include!("controller/controller_shim.h");
 
@@ -11,9 +44,120 @@ mod ffi {
 
         fn GetControllerInterface() -> UniquePtr<ControllerIntf>;
         fn read_local_addr(self: &ControllerIntf) -> RustRawAddress;
+        fn read_manufacturer(self: &ControllerIntf) -> u16;
+        fn read_local_version(self: &ControllerIntf) -> RustLocalVersion;
+        fn read_le_local_features(self: &ControllerIntf) -> u64;
+        fn supports_secure_connections(self: &ControllerIntf) -> bool;
+        fn supports_simultaneous_le_bredr(self: &ControllerIntf) -> bool;
+        fn write_page_scan_activity(self: &ControllerIntf, interval: u16, window: u16);
+        fn write_inquiry_scan_activity(self: &ControllerIntf, interval: u16, window: u16);
+        fn write_page_scan_type(self: &ControllerIntf, interlaced: bool);
+        fn write_inquiry_scan_type(self: &ControllerIntf, interlaced: bool);
+        fn add_connection_setup_filter(self: &ControllerIntf, address: RustRawAddress);
+        fn read_controller_lists(self: &ControllerIntf);
+        fn clear_filter_accept_list(self: &ControllerIntf);
+        fn clear_resolving_list(self: &ControllerIntf);
+        fn clear_periodic_advertiser_list(self: &ControllerIntf);
+        fn suspend_le_connections(self: &ControllerIntf, wake_devices: Vec<RustRawAddress>);
+        fn resume_le_connections(self: &ControllerIntf);
+        fn set_privacy_mode(
+            self: &ControllerIntf,
+            address: RustRawAddress,
+            device_privacy: bool,
+        ) -> bool;
+        fn read_remote_version(self: &ControllerIntf, address: RustRawAddress)
+            -> RustRemoteVersion;
+        fn read_remote_features(self: &ControllerIntf, address: RustRawAddress) -> Vec<u8>;
+        fn read_identity_key(self: &ControllerIntf, address: RustRawAddress) -> RustIdentityKey;
+        fn read_connection_address(
+            self: &ControllerIntf,
+            address: RustRawAddress,
+        ) -> RustConnectionAddress;
+        fn start_command_latency_reports(self: &ControllerIntf);
+        fn end_le_test(self: &ControllerIntf);
     }
+
+    extern "Rust" {
+        // All callbacks below are generated by cb_variant!.
+        fn controller_on_command_latency(opcode: u16, latency_us: u64);
+        fn controller_on_controller_lists(
+            filter_accept_list: Vec<String>,
+            resolving_list: Vec<String>,
+            periodic_advertiser_list: Vec<String>,
+        );
+        fn controller_on_le_test_ended(status: u8, num_packets: u16, rssi: i8);
+    }
+}
+
+/// Version information of a connected remote device.
+#[derive(Clone, Copy, Debug)]
+pub struct RemoteVersion {
+    /// LMP version for BR/EDR or LL version for LE, which share their numbering.
+    pub lmp_version: u8,
+    pub manufacturer: u16,
+    pub lmp_subversion: u16,
+}
+
+/// Identity Resolving Key (IRK) distributed by a bonded LE device.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IdentityKey {
+    /// IRK, least significant byte first as distributed.
+    pub irk: [u8; 16],
+    /// Identity address of the device, all zeros if it distributed none.
+    pub identity_address: [u8; 6],
+}
+
+/// Local address of an LE connection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConnectionAddress {
+    /// `BLE_ADDR_PUBLIC` or `BLE_ADDR_RANDOM`, or their identity variants.
+    pub address_type: u8,
+    pub address: [u8; 6],
+}
+
+/// Version information of the local controller.
+#[derive(Clone, Copy, Debug)]
+pub struct LocalVersion {
+    pub hci_version: u8,
+    pub hci_revision: u16,
+    pub lmp_version: u8,
+    pub lmp_subversion: u16,
+    pub manufacturer: u16,
 }
 
+#[derive(Debug)]
+pub enum ControllerCallbacks {
+    /// Params: Opcode, Latency from the command sent to the controller to the Command Complete or
+    /// Command Status event completing it
+    CommandLatency(u16, Duration),
+    /// Params: Entries of the LE filter accept list, of the LE resolving list and of the periodic
+    /// advertiser list, as mirrored by the host
+    ControllerLists(Vec<String>, Vec<String>, Vec<String>),
+    /// Params: HCI status, Number of packets received by the receiver test, Average RSSI of
+    /// these packets in dBm, 127 if the controller does not report it
+    LeTestEnded(u8, u16, i8),
+}
+
+pub struct ControllerCallbacksDispatcher {
+    pub dispatch: Box<dyn Fn(ControllerCallbacks) + Send>,
+}
+
+type ControllerCb = Arc<Mutex<ControllerCallbacksDispatcher>>;
+
+cb_variant!(ControllerCb,
+controller_on_command_latency -> ControllerCallbacks::CommandLatency,
+u16, u64, {
+    let _1 = Duration::from_micros(_1);
+});
+
+cb_variant!(ControllerCb,
+controller_on_controller_lists -> ControllerCallbacks::ControllerLists,
+Vec<String>, Vec<String>, Vec<String>);
+
+cb_variant!(ControllerCb,
+controller_on_le_test_ended -> ControllerCallbacks::LeTestEnded,
+u8, u16, i8);
+
 pub struct Controller {
     internal: cxx::UniquePtr<ffi::ControllerIntf>,
 }
@@ -26,7 +170,163 @@ impl Controller {
         Controller { internal: intf }
     }
 
+    pub fn initialize(&mut self, callbacks: ControllerCallbacksDispatcher) -> bool {
+        if get_dispatchers().lock().unwrap().set::<ControllerCb>(Arc::new(Mutex::new(callbacks))) {
+            panic!("Tried to set dispatcher for ControllerCallbacks but it already existed");
+        }
+        true
+    }
+
     pub fn read_local_addr(&mut self) -> [u8; 6] {
         self.internal.read_local_addr().address
     }
+
+    /// Returns the company identifier of the manufacturer of the controller.
+    pub fn read_manufacturer(&mut self) -> u16 {
+        self.internal.read_manufacturer()
+    }
+
+    pub fn read_local_version(&mut self) -> LocalVersion {
+        let version = self.internal.read_local_version();
+        LocalVersion {
+            hci_version: version.hci_version,
+            hci_revision: version.hci_revision,
+            lmp_version: version.lmp_version,
+            lmp_subversion: version.lmp_subversion,
+            manufacturer: version.manufacturer,
+        }
+    }
+
+    /// Returns the LE features supported by the controller, as the bit mask of the LE Read Local
+    /// Supported Features command.
+    pub fn read_le_local_features(&mut self) -> u64 {
+        self.internal.read_le_local_features()
+    }
+
+    pub fn supports_secure_connections(&mut self) -> bool {
+        self.internal.supports_secure_connections()
+    }
+
+    pub fn supports_simultaneous_le_bredr(&mut self) -> bool {
+        self.internal.supports_simultaneous_le_bredr()
+    }
+
+    /// Sets the page scan interval and window, in units of 0.625 ms.
+    pub fn write_page_scan_activity(&mut self, interval: u16, window: u16) {
+        self.internal.write_page_scan_activity(interval, window);
+    }
+
+    /// Sets the inquiry scan interval and window, in units of 0.625 ms.
+    pub fn write_inquiry_scan_activity(&mut self, interval: u16, window: u16) {
+        self.internal.write_inquiry_scan_activity(interval, window);
+    }
+
+    pub fn write_page_scan_type(&mut self, interlaced: bool) {
+        self.internal.write_page_scan_type(interlaced);
+    }
+
+    pub fn write_inquiry_scan_type(&mut self, interlaced: bool) {
+        self.internal.write_inquiry_scan_type(interlaced);
+    }
+
+    /// Adds an event filter letting the connection requests of `address` through. Once the first
+    /// connection setup filter is set, the controller drops the connection requests of the other
+    /// devices, until the filters are cleared with `BluetoothInterface::clear_event_filter`.
+    pub fn add_connection_setup_filter(&mut self, address: [u8; 6]) {
+        self.internal.add_connection_setup_filter(ffi::RustRawAddress { address });
+    }
+
+    /// Reads the entries of the LE lists, as mirrored by the host since the controller cannot
+    /// read them back. They are reported with `ControllerLists` once gathered from the threads
+    /// of the stack keeping them.
+    pub fn read_controller_lists(&mut self) {
+        self.internal.read_controller_lists();
+    }
+
+    pub fn clear_filter_accept_list(&mut self) {
+        self.internal.clear_filter_accept_list();
+    }
+
+    pub fn clear_resolving_list(&mut self) {
+        self.internal.clear_resolving_list();
+    }
+
+    pub fn clear_periodic_advertiser_list(&mut self) {
+        self.internal.clear_periodic_advertiser_list();
+    }
+
+    /// Removes the LE devices other than `wake_devices` from the filter accept list, so that
+    /// only the wake devices can connect and wake the system, until `resume_le_connections`.
+    pub fn suspend_le_connections(&mut self, wake_devices: Vec<[u8; 6]>) {
+        self.internal.suspend_le_connections(
+            wake_devices.into_iter().map(|address| ffi::RustRawAddress { address }).collect(),
+        );
+    }
+
+    /// Adds back the devices removed from the filter accept list by `suspend_le_connections`.
+    pub fn resume_le_connections(&mut self) {
+        self.internal.resume_le_connections();
+    }
+
+    /// Sets the LE privacy mode of a device of the resolving list, by its identity address: with
+    /// device privacy, the device is also accepted when using its identity address instead of a
+    /// resolvable private address. Returns false if the device is not in the resolving list.
+    pub fn set_privacy_mode(&mut self, address: [u8; 6], device_privacy: bool) -> bool {
+        self.internal.set_privacy_mode(ffi::RustRawAddress { address }, device_privacy)
+    }
+
+    /// Returns the version of the remote device, if connected and once read from the device.
+    pub fn read_remote_version(&mut self, address: [u8; 6]) -> Option<RemoteVersion> {
+        let version = self.internal.read_remote_version(ffi::RustRawAddress { address });
+        if !version.valid {
+            return None;
+        }
+
+        Some(RemoteVersion {
+            lmp_version: version.lmp_version,
+            manufacturer: version.manufacturer,
+            lmp_subversion: version.lmp_subversion,
+        })
+    }
+
+    /// Returns the identity key of a bonded device, as loaded into the resolving list, if the
+    /// device distributed one.
+    pub fn read_identity_key(&mut self, address: [u8; 6]) -> Option<IdentityKey> {
+        let key = self.internal.read_identity_key(ffi::RustRawAddress { address });
+        if !key.valid {
+            return None;
+        }
+
+        Some(IdentityKey { irk: key.irk, identity_address: key.identity_address.address })
+    }
+
+    /// Returns the local address of the LE connection to a device, None if not connected over LE.
+    pub fn read_connection_address(&mut self, address: [u8; 6]) -> Option<ConnectionAddress> {
+        let connection = self.internal.read_connection_address(ffi::RustRawAddress { address });
+        if !connection.valid {
+            return None;
+        }
+
+        Some(ConnectionAddress {
+            address_type: connection.address_type,
+            address: connection.address.address,
+        })
+    }
+
+    /// Returns the pages of the LMP features of the remote device read so far, one after the
+    /// other, empty if not connected over BR/EDR.
+    pub fn read_remote_features(&mut self, address: [u8; 6]) -> Vec<u8> {
+        self.internal.read_remote_features(ffi::RustRawAddress { address })
+    }
+
+    /// Reports the latency of every HCI command with `CommandLatency`, from now on and until the
+    /// stack stops. The HCI layer must be started.
+    pub fn start_command_latency_reports(&mut self) {
+        self.internal.start_command_latency_reports();
+    }
+
+    /// Ends the LE test running on the controller. Its report is delivered with `LeTestEnded`.
+    pub fn end_le_test(&mut self) {
+        self.internal.end_le_test();
+    }
 }
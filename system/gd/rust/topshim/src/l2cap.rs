@@ -0,0 +1,197 @@
+//! L2CAP Enhanced Credit Based Flow Control (ECFC) channels.
+//!
+//! Unlike the channels of `profiles::socket`, the data of these channels goes through the
+//! callbacks and `L2cap::write_ecfc`, the caller being in charge of delivering it to its clients.
+//! The data is flow controlled: each SDU received with `EcfcData` is acknowledged with
+//! `L2cap::ack_ecfc` once delivered, and `EcfcSent` tells how many SDUs written were taken by
+//! L2CAP.
+
+use crate::btif::{ptr_to_vec, RawAddress};
+use crate::deref_ffi_address;
+use crate::topstack::get_dispatchers;
+
+use std::sync::{Arc, Mutex};
+use topshim_macros::cb_variant;
+
+#[cxx::bridge(namespace = bluetooth::topshim::rust)]
+mod ffi {
+    pub struct RustRawAddress {
+        address: [u8; 6],
+    }
+
+    unsafe extern "C++" {
+        include!("l2cap/l2cap_shim.h");
+
+        type L2capIntf;
+
+        fn GetL2capInterface() -> UniquePtr<L2capIntf>;
+        fn listen_ecfc(self: &L2capIntf, request_id: u32, mtu: u16, secure: bool);
+        fn stop_listening_ecfc(self: &L2capIntf, psm: u16);
+        fn connect_ecfc(
+            self: &L2capIntf,
+            request_id: u32,
+            address: RustRawAddress,
+            psm: u16,
+            channel_count: u8,
+            mtu: u16,
+            secure: bool,
+        );
+        fn write_ecfc(self: &L2capIntf, lcid: u16, data: Vec<u8>);
+        fn ack_ecfc(self: &L2capIntf, lcid: u16);
+        fn disconnect_ecfc(self: &L2capIntf, lcid: u16);
+    }
+
+    extern "Rust" {
+        // All callbacks below are generated by cb_variant!.
+        fn l2cap_on_ecfc_listening(request_id: u32, psm: u16);
+        fn l2cap_on_ecfc_connecting(request_id: u32, channel_count: u8);
+        unsafe fn l2cap_on_ecfc_connected(
+            request_id: u32,
+            address: *const RustRawAddress,
+            psm: u16,
+            lcid: u16,
+            result: u16,
+            local_mtu: u16,
+            local_mps: u16,
+            local_credits: u16,
+            peer_mtu: u16,
+            peer_mps: u16,
+        );
+        unsafe fn l2cap_on_ecfc_data(lcid: u16, data: *const u8, len: usize);
+        fn l2cap_on_ecfc_sent(lcid: u16, count: u16);
+        fn l2cap_on_ecfc_disconnected(lcid: u16);
+    }
+}
+
+/// Result of a channel in `L2capCallbacks::EcfcConnected` once established.
+pub const ECFC_RESULT_SUCCESS: u16 = 0;
+
+/// Configuration of an ECFC channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EcfcChannelInfo {
+    pub psm: u16,
+    /// Local channel identifier, which identifies the channel in the other calls.
+    pub lcid: u16,
+    /// Largest SDU received on the channel.
+    pub local_mtu: u16,
+    /// Largest PDU received on the channel, SDUs being segmented into PDUs.
+    pub local_mps: u16,
+    /// PDUs the peer may send before the SDUs received are acknowledged.
+    pub local_credits: u16,
+    /// Largest SDU that can be sent on the channel.
+    pub peer_mtu: u16,
+    /// Largest PDU that can be sent on the channel.
+    pub peer_mps: u16,
+}
+
+#[derive(Debug)]
+pub enum L2capCallbacks {
+    /// Params: Request Id, PSM (0 if the PSM could not be registered)
+    EcfcListening(u32, u16),
+
+    /// Params: Request Id, Number of channels requested (0 if the request could not be sent)
+    EcfcConnecting(u32, u8),
+
+    /// Params: Request Id, Address, Result, Channel
+    EcfcConnected(u32, RawAddress, u16, EcfcChannelInfo),
+
+    /// Params: Local CID, SDU
+    EcfcData(u16, Vec<u8>),
+
+    /// Params: Local CID, Number of SDUs written taken by L2CAP
+    EcfcSent(u16, u16),
+
+    /// Params: Local CID
+    EcfcDisconnected(u16),
+}
+
+pub struct L2capCallbacksDispatcher {
+    pub dispatch: Box<dyn Fn(L2capCallbacks) + Send>,
+}
+
+type L2capCb = Arc<Mutex<L2capCallbacksDispatcher>>;
+
+cb_variant!(L2capCb, l2cap_on_ecfc_listening -> L2capCallbacks::EcfcListening, u32, u16);
+cb_variant!(L2capCb, l2cap_on_ecfc_connecting -> L2capCallbacks::EcfcConnecting, u32, u8);
+cb_variant!(L2capCb,
+l2cap_on_ecfc_connected -> L2capCallbacks::EcfcConnected,
+u32, *const ffi::RustRawAddress, u16 -> _, u16 -> _, u16, u16, u16 -> _, u16 -> _, u16 -> _,
+u16 -> _, {
+    let _1 = unsafe { deref_ffi_address!(_1) };
+    let _5 = EcfcChannelInfo {
+        psm: _2,
+        lcid: _3,
+        local_mtu: _5,
+        local_mps: _6,
+        local_credits: _7,
+        peer_mtu: _8,
+        peer_mps: _9,
+    };
+});
+cb_variant!(L2capCb,
+l2cap_on_ecfc_data -> L2capCallbacks::EcfcData,
+u16, *const u8, usize -> _, {
+    let _1 = ptr_to_vec(_1, _2);
+});
+cb_variant!(L2capCb, l2cap_on_ecfc_sent -> L2capCallbacks::EcfcSent, u16, u16);
+cb_variant!(L2capCb, l2cap_on_ecfc_disconnected -> L2capCallbacks::EcfcDisconnected, u16);
+
+pub struct L2cap {
+    internal: cxx::UniquePtr<ffi::L2capIntf>,
+}
+
+unsafe impl Send for L2cap {}
+
+impl L2cap {
+    pub fn new() -> L2cap {
+        L2cap { internal: ffi::GetL2capInterface() }
+    }
+
+    pub fn initialize(&mut self, callbacks: L2capCallbacksDispatcher) -> bool {
+        if get_dispatchers().lock().unwrap().set::<L2capCb>(Arc::new(Mutex::new(callbacks))) {
+            panic!("Tried to set dispatcher for L2capCallbacks but it already existed");
+        }
+        true
+    }
+
+    /// Listens on a PSM allocated by the stack, delivered with `EcfcListening`. Each channel
+    /// accepted is delivered with `EcfcConnected`.
+    pub fn listen_ecfc(&self, request_id: u32, mtu: u16, secure: bool) {
+        self.internal.listen_ecfc(request_id, mtu, secure);
+    }
+
+    pub fn stop_listening_ecfc(&self, psm: u16) {
+        self.internal.stop_listening_ecfc(psm);
+    }
+
+    /// Requests up to 5 channels to `psm` in a single request. The number of channels requested
+    /// is delivered with `EcfcConnecting`, then the result of each channel with `EcfcConnected`.
+    pub fn connect_ecfc(
+        &self,
+        request_id: u32,
+        address: RawAddress,
+        psm: u16,
+        channel_count: u8,
+        mtu: u16,
+        secure: bool,
+    ) {
+        let address = ffi::RustRawAddress { address: address.val };
+        self.internal.connect_ecfc(request_id, address, psm, channel_count, mtu, secure);
+    }
+
+    /// Sends an SDU of at most the peer MTU on a channel. The SDU is counted in `EcfcSent` once
+    /// taken by L2CAP.
+    pub fn write_ecfc(&self, lcid: u16, data: Vec<u8>) {
+        self.internal.write_ecfc(lcid, data);
+    }
+
+    /// Acknowledges an SDU received with `EcfcData`. The peer is given credits to send more once
+    /// all of them are acknowledged.
+    pub fn ack_ecfc(&self, lcid: u16) {
+        self.internal.ack_ecfc(lcid);
+    }
+
+    pub fn disconnect_ecfc(&self, lcid: u16) {
+        self.internal.disconnect_ecfc(lcid);
+    }
+}
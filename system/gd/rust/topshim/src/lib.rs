@@ -26,6 +26,10 @@ pub mod btif;
 /// Helper module for the topshim facade.
 pub mod controller;
 
+pub mod l2cap;
+
 pub mod profiles;
 
+pub mod snoop;
+
 pub mod topstack;
@@ -305,6 +305,10 @@ impl A2dp {
         self.internal.disconnect(addr.into());
     }
 
+    pub fn config_codec(&self, addr: RawAddress, codec_preferences: Vec<A2dpCodecConfig>) -> i32 {
+        self.internal.config_codec(addr.into(), codec_preferences)
+    }
+
     pub fn set_audio_config(&self, sample_rate: i32, bits_per_sample: i32, channel_mode: i32) {
         let config =
             A2dpCodecConfig { sample_rate, bits_per_sample, channel_mode, ..Default::default() };
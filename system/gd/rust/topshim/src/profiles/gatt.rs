@@ -20,6 +20,7 @@ pub type BtGattNotifyParams = bindings::btgatt_notify_params_t;
 pub type BtGattReadParams = bindings::btgatt_read_params_t;
 pub type BtGattDbElement = bindings::btgatt_db_element_t;
 pub type BtGattResponse = bindings::btgatt_response_t;
+pub type BtGattValue = bindings::btgatt_value_t;
 pub type BtGattTestParams = bindings::btgatt_test_params_t;
 
 #[cxx::bridge(namespace = bluetooth::topshim::rust)]
@@ -113,6 +114,17 @@ pub mod ffi {
 
         fn read_phy(self: Pin<&mut GattClientIntf>, client_if: i32, bt_addr: RustRawAddress)
             -> i32;
+
+        fn prepare_write(
+            self: Pin<&mut GattClientIntf>,
+            conn_id: i32,
+            handle: u16,
+            offset: u16,
+            value: &[u8],
+            auth_req: i32,
+        ) -> i32;
+
+        fn register_eatt_callback(self: Pin<&mut GattClientIntf>);
     }
 
     extern "Rust" {
@@ -124,6 +136,10 @@ pub mod ffi {
             rx_phy: u8,
             status: u8,
         );
+
+        fn prepare_write_callback(conn_id: i32, status: i32, handle: u16, len: u16);
+
+        unsafe fn eatt_channels_callback(addr: RustRawAddress, mtus: *const u16, count: usize);
     }
 
     unsafe extern "C++" {
@@ -156,6 +172,7 @@ pub mod ffi {
             scan_interval: u16,
             scan_window: u16,
         );
+        fn SetScanPhys(self: Pin<&mut BleScannerIntf>, scanner_id: u8, scan_phys: u8);
 
         fn BatchscanConfigStorage(
             self: Pin<&mut BleScannerIntf>,
@@ -204,6 +221,8 @@ pub mod ffi {
             timeout: u16,
         );
 
+        fn MsftCommand(self: Pin<&mut BleScannerIntf>, opcode: u16, params: Vec<u8>);
+
         /// Registers a C++ |ScanningCallbacks| implementation with the BleScanner.
         /// The shim implementation will call all the callbacks defined via |cb_variant!|.
         fn RegisterCallbacks(self: Pin<&mut BleScannerIntf>);
@@ -274,6 +293,7 @@ pub mod ffi {
         );
         unsafe fn gdscan_sync_lost_callback(sync_handle: u16);
         unsafe fn gdscan_sync_transfer_callback(status: u8, address: *const RustRawAddress);
+        unsafe fn gdscan_msft_command_callback(opcode: u16, data: *const u8, len: u16);
     }
 
     unsafe extern "C++" {
@@ -289,6 +309,11 @@ pub mod ffi {
         fn Unregister(self: Pin<&mut BleAdvertiserIntf>, adv_id: u8);
 
         fn GetOwnAddress(self: Pin<&mut BleAdvertiserIntf>, adv_id: u8);
+        fn SetAddressRotationInterval(
+            self: Pin<&mut BleAdvertiserIntf>,
+            adv_id: u8,
+            interval_ms: u32,
+        );
         fn SetParameters(
             self: Pin<&mut BleAdvertiserIntf>,
             adv_id: u8,
@@ -363,6 +388,11 @@ pub mod ffi {
             addr_type: u8,
             address: *const RustRawAddress,
         );
+        unsafe fn gdadv_on_own_address_changed(
+            adv_id: u8,
+            addr_type: u8,
+            address: *const RustRawAddress,
+        );
 
         // In-band callbacks also generated with cb_variant!.
         unsafe fn gdadv_idstatus_callback(adv_id: u8, status: u8);
@@ -468,6 +498,8 @@ pub enum GattClientCallbacks {
     ConnUpdated(i32, u16, u16, u16, u8),
     ServiceChanged(i32),
     ReadPhy(i32, RawAddress, u8, u8, u8),
+    PrepareWrite(i32, i32, u16, u16),
+    EattChannels(RawAddress, Vec<u16>),
 }
 
 #[derive(Debug)]
@@ -633,6 +665,21 @@ cb_variant!(
     }
 );
 
+cb_variant!(
+    GattClientCb,
+    prepare_write_callback -> GattClientCallbacks::PrepareWrite,
+    i32, i32, u16, u16, {}
+);
+
+cb_variant!(
+    GattClientCb,
+    eatt_channels_callback -> GattClientCallbacks::EattChannels,
+    ffi::RustRawAddress -> RawAddress, *const u16, usize -> _, {
+        let _0 = RawAddress { val: _0.address };
+        let _1 = ptr_to_vec(_1, _2);
+    }
+);
+
 cb_variant!(
     GattServerCb,
     gs_register_server_cb -> GattServerCallbacks::RegisterServer,
@@ -841,6 +888,9 @@ pub enum GattScannerInbandCallbacks {
 
     /// Params: Status, Address
     SyncTransferCallback(u8, RawAddress),
+
+    /// Params: Opcode, Return Parameters
+    MsftCommandCallback(u16, Vec<u8>),
 }
 
 pub struct GattScannerInbandCallbacksDispatcher {
@@ -875,6 +925,10 @@ cb_variant!(GDScannerInbandCb, gdscan_sync_transfer_callback -> GattScannerInban
 u8, *const ffi::RustRawAddress, {
     let _1 = unsafe { deref_ffi_address!(_1) };
 });
+cb_variant!(GDScannerInbandCb, gdscan_msft_command_callback -> GattScannerInbandCallbacks::MsftCommandCallback,
+u16, *const u8, u16 -> _, {
+    let _1 = ptr_to_vec(_1, _2 as usize);
+});
 
 /// Advertising callbacks used by the GD implementation of BleAdvertiserInterface.
 /// These callbacks should be registered using |RegisterCallbacks| on
@@ -907,6 +961,9 @@ pub enum GattAdvCallbacks {
 
     /// Params: Advertiser Id, Address Type, Address
     OnOwnAddressRead(u8, u8, RawAddress),
+
+    /// Params: Advertiser Id, Address Type, Address
+    OnOwnAddressChanged(u8, u8, RawAddress),
 }
 
 pub struct GattAdvCallbacksDispatcher {
@@ -944,6 +1001,11 @@ gdadv_on_own_address_read -> GattAdvCallbacks::OnOwnAddressRead, u8, u8,
 *const ffi::RustRawAddress, {
     let _2 = unsafe { deref_ffi_address!(_2) };
 });
+cb_variant!(GDAdvCb,
+gdadv_on_own_address_changed -> GattAdvCallbacks::OnOwnAddressChanged, u8, u8,
+*const ffi::RustRawAddress, {
+    let _2 = unsafe { deref_ffi_address!(_2) };
+});
 
 #[derive(Debug)]
 pub enum GattAdvInbandCallbacks {
@@ -1214,10 +1276,61 @@ impl GattClient {
         .unwrap()
     }
 
+    /// Queues a part of a long characteristic value on the remote device, to be committed with
+    /// `execute_write`.
+    pub fn prepare_write(
+        &mut self,
+        conn_id: i32,
+        handle: u16,
+        offset: u16,
+        auth_req: i32,
+        value: &[u8],
+    ) -> BtStatus {
+        BtStatus::from_i32(mutcxxcall!(
+            self,
+            prepare_write,
+            conn_id,
+            handle,
+            offset,
+            value,
+            auth_req
+        ))
+        .unwrap()
+    }
+
+    /// Has the transmit MTU of the connected EATT channels of a device reported in
+    /// `GattClientCallbacks::EattChannels` whenever a channel connects, is reconfigured or
+    /// disconnects.
+    pub fn register_eatt_callback(&mut self) {
+        mutcxxcall!(self, register_eatt_callback)
+    }
+
     pub fn test_command(&self, command: i32, params: &BtGattTestParams) -> BtStatus {
         BtStatus::from(ccall!(self, test_command, command, params))
     }
 
+    /// Runs a GATT test command of the stack with the device `addr`, the UUID `uuid` and the
+    /// numeric parameters `params`, whose meaning depends on the command.
+    pub fn run_test_command(
+        &self,
+        command: i32,
+        addr: &mut RawAddress,
+        uuid: &mut Uuid,
+        params: [u16; 5],
+    ) -> BtStatus {
+        // The stack only reads the address and the UUID during the call.
+        let test_params = BtGattTestParams {
+            bda1: cast_to_ffi_address!(addr as *mut RawAddress),
+            uuid1: uuid as *mut Uuid,
+            u1: params[0],
+            u2: params[1],
+            u3: params[2],
+            u4: params[3],
+            u5: params[4],
+        };
+        self.test_command(command, &test_params)
+    }
+
     pub fn get_gatt_db(&self, conn_id: i32) -> BtStatus {
         BtStatus::from(ccall!(self, get_gatt_db, conn_id))
     }
@@ -1284,6 +1397,27 @@ impl GattServer {
         ))
     }
 
+    pub fn send_multiple_notifications(
+        &self,
+        server_if: i32,
+        conn_id: i32,
+        values: &[(u16, Vec<u8>)],
+    ) -> BtStatus {
+        let handles: Vec<u16> = values.iter().map(|(handle, _)| *handle).collect();
+        let lengths: Vec<u16> = values.iter().map(|(_, value)| value.len() as u16).collect();
+        let concatenated: Vec<u8> = values.iter().flat_map(|(_, value)| value.clone()).collect();
+        BtStatus::from(ccall!(
+            self,
+            send_multiple_notifications,
+            server_if,
+            conn_id,
+            handles.as_ptr(),
+            lengths.as_ptr(),
+            values.len(),
+            concatenated.as_ptr()
+        ))
+    }
+
     pub fn send_response(
         &self,
         conn_id: i32,
@@ -1372,6 +1506,10 @@ impl BleScanner {
         mutcxxcall!(self, SetScanParameters, scanner_id, scan_interval, scan_window);
     }
 
+    pub fn set_scan_phys(&mut self, scanner_id: u8, scan_phys: u8) {
+        mutcxxcall!(self, SetScanPhys, scanner_id, scan_phys);
+    }
+
     pub fn batchscan_config_storage(
         &mut self,
         scanner_id: u8,
@@ -1444,6 +1582,12 @@ impl BleScanner {
         let addr = unsafe { *((&address as *const RawAddress) as *const ffi::RustRawAddress) };
         mutcxxcall!(self, SyncTxParameters, addr, mode, skip, timeout);
     }
+
+    /// Sends a command of the Microsoft HCI extension, whose vendor `opcode` depends on the
+    /// controller. The result comes back as `GattScannerInbandCallbacks::MsftCommandCallback`.
+    pub fn msft_command(&mut self, opcode: u16, params: Vec<u8>) {
+        mutcxxcall!(self, MsftCommand, opcode, params);
+    }
 }
 
 pub struct BleAdvertiser {
@@ -1476,6 +1620,10 @@ impl BleAdvertiser {
         mutcxxcall!(self, GetOwnAddress, adv_id);
     }
 
+    pub fn set_address_rotation_interval(&mut self, adv_id: u8, interval_ms: u32) {
+        mutcxxcall!(self, SetAddressRotationInterval, adv_id, interval_ms);
+    }
+
     pub fn set_parameters(&mut self, adv_id: u8, params: AdvertiseParameters) {
         mutcxxcall!(self, SetParameters, adv_id, params);
     }
@@ -1608,6 +1756,9 @@ impl Gatt {
         gatt_client_callbacks_dispatcher: GattClientCallbacksDispatcher,
         gatt_server_callbacks_dispatcher: GattServerCallbacksDispatcher,
         gatt_scanner_callbacks_dispatcher: GattScannerCallbacksDispatcher,
+        gatt_scanner_inband_callbacks_dispatcher: GattScannerInbandCallbacksDispatcher,
+        gatt_adv_callbacks_dispatcher: GattAdvCallbacksDispatcher,
+        gatt_adv_inband_callbacks_dispatcher: GattAdvInbandCallbacksDispatcher,
     ) -> bool {
         // Register dispatcher
         if get_dispatchers()
@@ -1634,6 +1785,28 @@ impl Gatt {
             panic!("Tried to set dispatcher for GattScannerCallbacks but it already existed");
         }
 
+        if get_dispatchers().lock().unwrap().set::<GDScannerInbandCb>(Arc::new(Mutex::new(
+            gatt_scanner_inband_callbacks_dispatcher,
+        ))) {
+            panic!("Tried to set dispatcher for GattScannerInbandCallbacks but it already existed");
+        }
+
+        if get_dispatchers()
+            .lock()
+            .unwrap()
+            .set::<GDAdvCb>(Arc::new(Mutex::new(gatt_adv_callbacks_dispatcher)))
+        {
+            panic!("Tried to set dispatcher for GattAdvCallbacks but it already existed");
+        }
+
+        if get_dispatchers()
+            .lock()
+            .unwrap()
+            .set::<GDAdvInbandCb>(Arc::new(Mutex::new(gatt_adv_inband_callbacks_dispatcher)))
+        {
+            panic!("Tried to set dispatcher for GattAdvInbandCallbacks but it already existed");
+        }
+
         let mut gatt_client_callbacks = Box::new(btgatt_client_callbacks_t {
             register_client_cb: Some(gc_register_client_cb),
             open_cb: Some(gc_open_cb),
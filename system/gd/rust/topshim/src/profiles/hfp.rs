@@ -1,7 +1,7 @@
 use crate::btif::{BluetoothInterface, RawAddress};
 use crate::topstack::get_dispatchers;
 
-use num_traits::cast::FromPrimitive;
+use num_traits::cast::{FromPrimitive, ToPrimitive};
 use std::convert::{TryFrom, TryInto};
 use std::sync::{Arc, Mutex};
 use topshim_macros::cb_variant;
@@ -37,6 +37,20 @@ impl From<u32> for BthfAudioState {
     }
 }
 
+/// State of the call being set up, as reported to the hands-free unit.
+#[derive(Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum BthfCallState {
+    Active = 0,
+    Held,
+    Dialing,
+    Alerting,
+    Incoming,
+    Waiting,
+    Idle,
+    Disconnected,
+}
+
 bitflags! {
     #[derive(Default)]
     pub struct HfpCodecCapability: i32 {
@@ -79,12 +93,25 @@ pub mod ffi {
         fn connect_audio(self: Pin<&mut HfpIntf>, bt_addr: RustRawAddress) -> i32;
         fn disconnect(self: Pin<&mut HfpIntf>, bt_addr: RustRawAddress) -> i32;
         fn disconnect_audio(self: Pin<&mut HfpIntf>, bt_addr: RustRawAddress) -> i32;
+        fn set_volume(self: Pin<&mut HfpIntf>, volume: i8, bt_addr: RustRawAddress) -> i32;
+        fn phone_state_change(
+            self: Pin<&mut HfpIntf>,
+            num_active: u32,
+            num_held: u32,
+            call_setup_state: u32,
+            number: &str,
+            bt_addr: RustRawAddress,
+        ) -> i32;
         fn cleanup(self: Pin<&mut HfpIntf>);
 
     }
     extern "Rust" {
         fn hfp_connection_state_callback(state: u32, addr: RustRawAddress);
         fn hfp_audio_state_callback(state: u32, addr: RustRawAddress);
+        fn hfp_volume_update_callback(volume: u8, addr: RustRawAddress);
+        fn hfp_battery_level_callback(level: u8, addr: RustRawAddress);
+        fn hfp_answer_call_callback(addr: RustRawAddress);
+        fn hfp_hangup_call_callback(addr: RustRawAddress);
     }
 }
 
@@ -104,6 +131,11 @@ impl Into<RawAddress> for ffi::RustRawAddress {
 pub enum HfpCallbacks {
     ConnectionState(BthfConnectionState, RawAddress),
     AudioState(BthfAudioState, RawAddress),
+    VolumeUpdate(u8, RawAddress),
+    /// Params: Battery level in percent reported with the HF indicator, Address
+    BatteryLevel(u8, RawAddress),
+    AnswerCall(RawAddress),
+    HangupCall(RawAddress),
 }
 
 pub struct HfpCallbacksDispatcher {
@@ -128,6 +160,38 @@ cb_variant!(
     }
 );
 
+cb_variant!(
+    HfpCb,
+    hfp_volume_update_callback -> HfpCallbacks::VolumeUpdate,
+    u8, ffi::RustRawAddress -> RawAddress, {
+        let _1 = _1.into();
+    }
+);
+
+cb_variant!(
+    HfpCb,
+    hfp_battery_level_callback -> HfpCallbacks::BatteryLevel,
+    u8, ffi::RustRawAddress -> RawAddress, {
+        let _1 = _1.into();
+    }
+);
+
+cb_variant!(
+    HfpCb,
+    hfp_answer_call_callback -> HfpCallbacks::AnswerCall,
+    ffi::RustRawAddress -> RawAddress, {
+        let _0 = _0.into();
+    }
+);
+
+cb_variant!(
+    HfpCb,
+    hfp_hangup_call_callback -> HfpCallbacks::HangupCall,
+    ffi::RustRawAddress -> RawAddress, {
+        let _0 = _0.into();
+    }
+);
+
 pub struct Hfp {
     internal: cxx::UniquePtr<ffi::HfpIntf>,
     _is_init: bool,
@@ -170,6 +234,27 @@ impl Hfp {
         self.internal.pin_mut().disconnect_audio(addr.into())
     }
 
+    pub fn set_volume(&mut self, volume: i8, addr: RawAddress) -> i32 {
+        self.internal.pin_mut().set_volume(volume, addr.into())
+    }
+
+    pub fn phone_state_change(
+        &mut self,
+        num_active: u32,
+        num_held: u32,
+        call_setup_state: BthfCallState,
+        number: &str,
+        addr: RawAddress,
+    ) -> i32 {
+        self.internal.pin_mut().phone_state_change(
+            num_active,
+            num_held,
+            call_setup_state.to_u32().unwrap(),
+            number,
+            addr.into(),
+        )
+    }
+
     pub fn cleanup(&mut self) -> bool {
         self.internal.pin_mut().cleanup();
         true
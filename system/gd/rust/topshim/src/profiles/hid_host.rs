@@ -24,7 +24,7 @@ impl From<bindings::bthh_connection_state_t> for BthhConnectionState {
     }
 }
 
-#[derive(Debug, FromPrimitive, PartialEq, PartialOrd)]
+#[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, PartialOrd)]
 #[repr(u32)]
 pub enum BthhStatus {
     Ok = 0,
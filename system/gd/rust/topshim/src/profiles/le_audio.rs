@@ -0,0 +1,250 @@
+use crate::btif::{BluetoothInterface, RawAddress};
+use crate::topstack::get_dispatchers;
+
+use num_traits::cast::FromPrimitive;
+use std::sync::{Arc, Mutex};
+use topshim_macros::cb_variant;
+
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, PartialOrd)]
+#[repr(u32)]
+pub enum LeAudioConnectionState {
+    Disconnected = 0,
+    Connecting,
+    Connected,
+    Disconnecting,
+}
+
+impl From<u32> for LeAudioConnectionState {
+    fn from(item: u32) -> Self {
+        LeAudioConnectionState::from_u32(item).unwrap_or(LeAudioConnectionState::Disconnected)
+    }
+}
+
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, PartialOrd)]
+#[repr(u32)]
+pub enum LeAudioGroupStatus {
+    Inactive = 0,
+    Active,
+    TurnedIdleDuringCall,
+}
+
+impl From<u32> for LeAudioGroupStatus {
+    fn from(item: u32) -> Self {
+        LeAudioGroupStatus::from_u32(item).unwrap_or(LeAudioGroupStatus::Inactive)
+    }
+}
+
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, PartialOrd)]
+#[repr(u32)]
+pub enum LeAudioGroupNodeStatus {
+    Added = 1,
+    Removed,
+}
+
+impl From<u32> for LeAudioGroupNodeStatus {
+    fn from(item: u32) -> Self {
+        LeAudioGroupNodeStatus::from_u32(item).unwrap_or(LeAudioGroupNodeStatus::Removed)
+    }
+}
+
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, PartialOrd)]
+#[repr(u32)]
+pub enum LeAudioCodecType {
+    Lc3 = 0,
+    Invalid,
+}
+
+impl From<u32> for LeAudioCodecType {
+    fn from(item: u32) -> Self {
+        LeAudioCodecType::from_u32(item).unwrap_or(LeAudioCodecType::Invalid)
+    }
+}
+
+#[cxx::bridge(namespace = bluetooth::topshim::rust)]
+pub mod ffi {
+    #[derive(Debug, Copy, Clone)]
+    pub struct RustRawAddress {
+        address: [u8; 6],
+    }
+
+    unsafe extern "C++" {
+        include!("le_audio/le_audio_shim.h");
+
+        type LeAudioClientIntf;
+
+        unsafe fn GetLeAudioClientProfile(btif: *const u8) -> UniquePtr<LeAudioClientIntf>;
+
+        fn init(self: Pin<&mut LeAudioClientIntf>);
+        fn connect(self: Pin<&mut LeAudioClientIntf>, bt_addr: RustRawAddress);
+        fn disconnect(self: Pin<&mut LeAudioClientIntf>, bt_addr: RustRawAddress);
+        fn group_add_node(
+            self: Pin<&mut LeAudioClientIntf>,
+            group_id: i32,
+            bt_addr: RustRawAddress,
+        );
+        fn group_remove_node(
+            self: Pin<&mut LeAudioClientIntf>,
+            group_id: i32,
+            bt_addr: RustRawAddress,
+        );
+        fn group_set_active(self: Pin<&mut LeAudioClientIntf>, group_id: i32);
+        fn set_codec_config_preference(
+            self: Pin<&mut LeAudioClientIntf>,
+            group_id: i32,
+            input_codec_type: u32,
+            output_codec_type: u32,
+        );
+        fn set_in_call(self: Pin<&mut LeAudioClientIntf>, in_call: bool);
+        fn cleanup(self: Pin<&mut LeAudioClientIntf>);
+    }
+    extern "Rust" {
+        fn le_audio_connection_state_callback(state: u32, addr: RustRawAddress);
+        fn le_audio_group_status_callback(group_id: i32, status: u32);
+        fn le_audio_group_node_status_callback(addr: RustRawAddress, group_id: i32, status: u32);
+        fn le_audio_audio_conf_callback(
+            direction: u8,
+            group_id: i32,
+            sink_audio_location: u32,
+            source_audio_location: u32,
+            available_contexts: u16,
+        );
+        fn le_audio_group_codec_conf_callback(
+            group_id: i32,
+            input_codec_type: u32,
+            output_codec_type: u32,
+        );
+    }
+}
+
+impl From<RawAddress> for ffi::RustRawAddress {
+    fn from(addr: RawAddress) -> Self {
+        ffi::RustRawAddress { address: addr.val }
+    }
+}
+
+impl Into<RawAddress> for ffi::RustRawAddress {
+    fn into(self) -> RawAddress {
+        RawAddress { val: self.address }
+    }
+}
+
+#[derive(Debug)]
+pub enum LeAudioCallbacks {
+    ConnectionState(LeAudioConnectionState, RawAddress),
+    GroupStatus(i32, LeAudioGroupStatus),
+    GroupNodeStatus(RawAddress, i32, LeAudioGroupNodeStatus),
+    /// Direction, group id, sink and source audio locations, available contexts.
+    AudioConf(u8, i32, u32, u32, u16),
+    /// Group id, input and output codec types.
+    GroupCodecConf(i32, LeAudioCodecType, LeAudioCodecType),
+}
+
+pub struct LeAudioCallbacksDispatcher {
+    pub dispatch: Box<dyn Fn(LeAudioCallbacks) + Send>,
+}
+
+type LeAudioCb = Arc<Mutex<LeAudioCallbacksDispatcher>>;
+
+cb_variant!(
+    LeAudioCb,
+    le_audio_connection_state_callback -> LeAudioCallbacks::ConnectionState,
+    u32 -> LeAudioConnectionState, ffi::RustRawAddress -> RawAddress, {
+        let _1 = _1.into();
+    }
+);
+
+cb_variant!(
+    LeAudioCb,
+    le_audio_group_status_callback -> LeAudioCallbacks::GroupStatus,
+    i32, u32 -> LeAudioGroupStatus, {}
+);
+
+cb_variant!(
+    LeAudioCb,
+    le_audio_group_node_status_callback -> LeAudioCallbacks::GroupNodeStatus,
+    ffi::RustRawAddress -> RawAddress, i32, u32 -> LeAudioGroupNodeStatus, {
+        let _0 = _0.into();
+    }
+);
+
+cb_variant!(
+    LeAudioCb,
+    le_audio_audio_conf_callback -> LeAudioCallbacks::AudioConf,
+    u8, i32, u32, u32, u16, {}
+);
+
+cb_variant!(
+    LeAudioCb,
+    le_audio_group_codec_conf_callback -> LeAudioCallbacks::GroupCodecConf,
+    i32, u32 -> LeAudioCodecType, u32 -> LeAudioCodecType, {}
+);
+
+pub struct LeAudioClient {
+    internal: cxx::UniquePtr<ffi::LeAudioClientIntf>,
+    _is_init: bool,
+}
+
+// For *const u8 opaque btif
+unsafe impl Send for LeAudioClient {}
+
+impl LeAudioClient {
+    pub fn new(intf: &BluetoothInterface) -> LeAudioClient {
+        let le_audio_if: cxx::UniquePtr<ffi::LeAudioClientIntf>;
+        unsafe {
+            le_audio_if = ffi::GetLeAudioClientProfile(intf.as_raw_ptr());
+        }
+
+        LeAudioClient { internal: le_audio_if, _is_init: false }
+    }
+
+    pub fn initialize(&mut self, callbacks: LeAudioCallbacksDispatcher) -> bool {
+        if get_dispatchers().lock().unwrap().set::<LeAudioCb>(Arc::new(Mutex::new(callbacks))) {
+            panic!("Tried to set dispatcher for LE Audio callbacks while it already exists");
+        }
+        self.internal.pin_mut().init();
+        self._is_init = true;
+        true
+    }
+
+    pub fn connect(&mut self, addr: RawAddress) {
+        self.internal.pin_mut().connect(addr.into());
+    }
+
+    pub fn disconnect(&mut self, addr: RawAddress) {
+        self.internal.pin_mut().disconnect(addr.into());
+    }
+
+    pub fn group_add_node(&mut self, group_id: i32, addr: RawAddress) {
+        self.internal.pin_mut().group_add_node(group_id, addr.into());
+    }
+
+    pub fn group_remove_node(&mut self, group_id: i32, addr: RawAddress) {
+        self.internal.pin_mut().group_remove_node(group_id, addr.into());
+    }
+
+    pub fn group_set_active(&mut self, group_id: i32) {
+        self.internal.pin_mut().group_set_active(group_id);
+    }
+
+    pub fn set_codec_config_preference(
+        &mut self,
+        group_id: i32,
+        input_codec: LeAudioCodecType,
+        output_codec: LeAudioCodecType,
+    ) {
+        self.internal.pin_mut().set_codec_config_preference(
+            group_id,
+            input_codec as u32,
+            output_codec as u32,
+        );
+    }
+
+    pub fn set_in_call(&mut self, in_call: bool) {
+        self.internal.pin_mut().set_in_call(in_call);
+    }
+
+    pub fn cleanup(&mut self) -> bool {
+        self.internal.pin_mut().cleanup();
+        true
+    }
+}
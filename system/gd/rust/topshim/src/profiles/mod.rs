@@ -5,4 +5,6 @@ pub mod avrcp;
 pub mod gatt;
 pub mod hfp;
 pub mod hid_host;
+pub mod le_audio;
 pub mod sdp;
+pub mod socket;
@@ -0,0 +1,211 @@
+//! RFCOMM and L2CAP sockets, see `hardware/bt_sock.h`.
+//!
+//! The data of a socket flows through a file descriptor shared with libbluetooth. The stack
+//! first writes the channel (or PSM) of the socket on it, then a `sock_connect_signal_t` once a
+//! connection is established. Connections accepted by a listening socket are passed along with
+//! their signal as ancillary data.
+
+use crate::bindings::root as bindings;
+use crate::btif::{BluetoothInterface, BtStatus, RawAddress, SupportedProfiles, Uuid};
+use crate::profiles::socket::bindings::btsock_interface_t;
+use crate::{cast_to_const_ffi_address, ccall};
+
+use nix::sys::socket::{recvmsg, ControlMessageOwned, MsgFlags};
+use num_traits::cast::ToPrimitive;
+use std::convert::TryInto;
+use std::ffi::CString;
+use std::io::IoSliceMut;
+use std::os::unix::io::RawFd;
+
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum SocketType {
+    Unknown = 0,
+    Rfcomm = 1,
+    Sco = 2,
+    L2cap = 3,
+    L2capLe = 4,
+}
+
+impl From<SocketType> for bindings::btsock_type_t {
+    fn from(item: SocketType) -> Self {
+        item.to_u32().unwrap()
+    }
+}
+
+bitflags! {
+    pub struct SocketFlags: i32 {
+        const NONE = 0;
+        const ENCRYPT = 1;
+        const AUTH = 1 << 1;
+        const NO_SDP = 1 << 2;
+        const AUTH_MITM = 1 << 3;
+        const AUTH_16_DIGIT = 1 << 4;
+        const LE_COC = 1 << 5;
+    }
+}
+
+/// Size of the channel written first on a socket.
+pub const CHANNEL_SIZE: usize = 4;
+
+/// Size of `sock_connect_signal_t`.
+pub const CONNECTION_COMPLETE_SIZE: usize = 20;
+
+/// Written by the stack on a socket once a connection is established (`sock_connect_signal_t`).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConnectionComplete {
+    pub size: u16,
+    pub addr: RawAddress,
+    pub channel: i32,
+    pub status: i32,
+    /// Writes must not exceed this size to avoid losing data (L2CAP only).
+    pub max_tx_packet_size: u16,
+    /// Reads must use a buffer of at least this size to avoid losing data (L2CAP only).
+    pub max_rx_packet_size: u16,
+}
+
+impl ConnectionComplete {
+    /// Parses a packed `sock_connect_signal_t`.
+    pub fn from_bytes(data: &[u8]) -> Option<ConnectionComplete> {
+        if data.len() != CONNECTION_COMPLETE_SIZE {
+            return None;
+        }
+
+        Some(ConnectionComplete {
+            size: u16::from_ne_bytes(data[0..2].try_into().ok()?),
+            addr: RawAddress::from_bytes(&data[2..8])?,
+            channel: i32::from_ne_bytes(data[8..12].try_into().ok()?),
+            status: i32::from_ne_bytes(data[12..16].try_into().ok()?),
+            max_tx_packet_size: u16::from_ne_bytes(data[16..18].try_into().ok()?),
+            max_rx_packet_size: u16::from_ne_bytes(data[18..20].try_into().ok()?),
+        })
+    }
+}
+
+/// Receives the data written by the stack on a socket without blocking, along with the file
+/// descriptor passed as ancillary data if any.
+///
+/// Returns the number of bytes received, which is 0 once the stack closed the socket.
+pub fn receive_with_fd(fd: RawFd, buf: &mut [u8]) -> std::io::Result<(usize, Option<RawFd>)> {
+    let mut iov = [IoSliceMut::new(buf)];
+    let mut cmsg_buffer = nix::cmsg_space!(RawFd);
+    let msg = recvmsg::<()>(fd, &mut iov, Some(&mut cmsg_buffer), MsgFlags::MSG_DONTWAIT)
+        .map_err(std::io::Error::from)?;
+
+    let mut passed_fd = None;
+    for cmsg in msg.cmsgs() {
+        if let ControlMessageOwned::ScmRights(fds) = cmsg {
+            passed_fd = fds.first().cloned();
+        }
+    }
+
+    Ok((msg.bytes, passed_fd))
+}
+
+// Export the raw address type directly from the bindings
+type FfiAddress = bindings::RawAddress;
+
+struct RawBtSockWrapper {
+    raw: *const btsock_interface_t,
+}
+
+// Pointers unsafe due to ownership but this is a static pointer so Send is ok
+unsafe impl Send for RawBtSockWrapper {}
+
+pub struct BtSocket {
+    internal: RawBtSockWrapper,
+}
+
+impl BtSocket {
+    pub fn new(intf: &BluetoothInterface) -> BtSocket {
+        let r = intf.get_profile_interface(SupportedProfiles::Socket);
+        BtSocket { internal: RawBtSockWrapper { raw: r as *const btsock_interface_t } }
+    }
+
+    /// Listens on a RFCOMM channel or L2CAP PSM. Returns the file descriptor of the socket on
+    /// success.
+    pub fn listen(
+        &self,
+        sock_type: SocketType,
+        service_name: String,
+        service_uuid: Option<Uuid>,
+        channel: i32,
+        flags: SocketFlags,
+        calling_uid: i32,
+    ) -> (BtStatus, Option<RawFd>) {
+        let mut sock_fd: i32 = -1;
+        let name = CString::new(service_name).unwrap_or_default();
+        let uuid_ptr = service_uuid.as_ref().map_or(std::ptr::null(), |uuid| uuid as *const Uuid);
+
+        let status = BtStatus::from(ccall!(
+            self,
+            listen,
+            sock_type.into(),
+            name.as_ptr(),
+            uuid_ptr,
+            channel,
+            &mut sock_fd,
+            flags.bits(),
+            calling_uid
+        ));
+
+        let fd = if status == BtStatus::Success && sock_fd >= 0 { Some(sock_fd) } else { None };
+        (status, fd)
+    }
+
+    /// Connects to a RFCOMM service or channel, or to a L2CAP PSM of a remote device. Returns the
+    /// file descriptor of the socket on success.
+    pub fn connect(
+        &self,
+        addr: RawAddress,
+        sock_type: SocketType,
+        uuid: Option<Uuid>,
+        channel: i32,
+        flags: SocketFlags,
+        calling_uid: i32,
+    ) -> (BtStatus, Option<RawFd>) {
+        let mut sock_fd: i32 = -1;
+        let ffi_addr = cast_to_const_ffi_address!(&addr as *const RawAddress);
+        let uuid_ptr = uuid.as_ref().map_or(std::ptr::null(), |uuid| uuid as *const Uuid);
+
+        let status = BtStatus::from(ccall!(
+            self,
+            connect,
+            ffi_addr,
+            sock_type.into(),
+            uuid_ptr,
+            channel,
+            &mut sock_fd,
+            flags.bits(),
+            calling_uid
+        ));
+
+        let fd = if status == BtStatus::Success && sock_fd >= 0 { Some(sock_fd) } else { None };
+        (status, fd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_complete_from_bytes() {
+        let mut data = vec![];
+        data.extend_from_slice(&(CONNECTION_COMPLETE_SIZE as u16).to_ne_bytes());
+        data.extend_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+        data.extend_from_slice(&5i32.to_ne_bytes());
+        data.extend_from_slice(&0i32.to_ne_bytes());
+        data.extend_from_slice(&512u16.to_ne_bytes());
+        data.extend_from_slice(&1024u16.to_ne_bytes());
+
+        let signal = ConnectionComplete::from_bytes(&data).unwrap();
+        assert_eq!("11:22:33:44:55:66", signal.addr.to_string());
+        assert_eq!(5, signal.channel);
+        assert_eq!(0, signal.status);
+        assert_eq!(512, signal.max_tx_packet_size);
+        assert_eq!(1024, signal.max_rx_packet_size);
+
+        assert_eq!(None, ConnectionComplete::from_bytes(&data[..CONNECTION_COMPLETE_SIZE - 1]));
+    }
+}
@@ -0,0 +1,53 @@
+//! Control of the btsnoop log, the capture of the HCI traffic.
+
+#[cxx::bridge(namespace = bluetooth::topshim::rust)]
+mod ffi {
+    unsafe extern "C++" {
+        include!("snoop/snoop_shim.h");
+
+        fn SetSnoopLogMode(mode: &str);
+        fn GetSnoopLogMode() -> String;
+    }
+}
+
+/// Mode of the btsnoop log.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum SnoopLogMode {
+    /// Only the recent packets are kept in memory, trimmed, to be dumped with the bug reports.
+    Disabled = 0,
+    /// The packets are logged without the payloads carrying the data of the user, such as the
+    /// audio or the values of the GATT attributes.
+    Filtered = 1,
+    /// The packets are logged in full.
+    Full = 2,
+}
+
+impl SnoopLogMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SnoopLogMode::Disabled => "disabled",
+            SnoopLogMode::Filtered => "filtered",
+            SnoopLogMode::Full => "full",
+        }
+    }
+
+    fn from_str(mode: &str) -> SnoopLogMode {
+        match mode {
+            "filtered" => SnoopLogMode::Filtered,
+            "full" => SnoopLogMode::Full,
+            _ => SnoopLogMode::Disabled,
+        }
+    }
+}
+
+/// Sets the mode of the btsnoop log. It takes effect right away, re-opening the log file, and
+/// is kept for the next starts of the stack within the process only, as the system properties on
+/// Linux.
+pub fn set_snoop_log_mode(mode: SnoopLogMode) {
+    ffi::SetSnoopLogMode(mode.as_str());
+}
+
+pub fn get_snoop_log_mode() -> SnoopLogMode {
+    SnoopLogMode::from_str(&ffi::GetSnoopLogMode())
+}
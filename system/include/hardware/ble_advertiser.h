@@ -69,6 +69,9 @@ class AdvertisingCallbacks {
                                             uint8_t status) = 0;
   virtual void OnOwnAddressRead(uint8_t advertiser_id, uint8_t address_type,
                                 RawAddress address) = 0;
+  /** Called when the random address of an advertising set is rotated. */
+  virtual void OnOwnAddressChanged(uint8_t advertiser_id, uint8_t address_type,
+                                   RawAddress address) {}
 };
 
 class BleAdvertiserInterface {
@@ -92,6 +95,12 @@ class BleAdvertiserInterface {
       base::Callback<void(uint8_t /* address_type*/, RawAddress /*address*/)>;
   virtual void GetOwnAddress(uint8_t advertiser_id, GetAddressCallback cb) = 0;
 
+  /** Sets the interval between the rotations of the random address of an
+   * advertising set. An interval of 0 restores the default interval. Not all
+   * implementations support it. */
+  virtual void SetAddressRotationInterval(uint8_t advertiser_id,
+                                          uint32_t interval_ms) {}
+
   /* Set the parameters as per spec, user manual specified values */
   virtual void SetParameters(uint8_t advertiser_id, AdvertiseParameters params,
                              ParametersCallback cb) = 0;
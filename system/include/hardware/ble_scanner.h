@@ -159,6 +159,10 @@ class BleScannerInterface {
   virtual void SetScanParameters(int scanner_id, int scan_interval,
                                  int scan_window, Callback cb) = 0;
 
+  /** Sets the PHYs to scan on, as the Scanning_PHYs bitmask of the LE Set
+   * Extended Scan Parameters command */
+  virtual void SetScanPhys(int scanner_id, uint8_t scan_phys, Callback cb) = 0;
+
   /* Configure the batchscan storage */
   virtual void BatchscanConfigStorage(int client_if, int batch_scan_full_max,
                                       int batch_scan_trunc_max,
@@ -61,6 +61,9 @@ typedef struct {
   uint16_t handle;
   uint16_t len;
   uint8_t is_notify;
+  /** Number of values of the multiple handle value notification this value
+   * was received in, 0 for a single value */
+  uint16_t multi_count;
 } btgatt_notify_params_t;
 
 typedef struct {
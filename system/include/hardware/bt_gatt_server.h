@@ -179,6 +179,15 @@ typedef struct {
       const RawAddress& bd_addr,
       base::Callback<void(uint8_t tx_phy, uint8_t rx_phy, uint8_t status)> cb);
 
+  /** Send the values of several attributes to a remote device in a single
+   * notification. The values are concatenated in |values|, the value of
+   * handles[i] being lengths[i] bytes long */
+  bt_status_t (*send_multiple_notifications)(int server_if, int conn_id,
+                                             const uint16_t* handles,
+                                             const uint16_t* lengths,
+                                             size_t count,
+                                             const uint8_t* values);
+
 } btgatt_server_interface_t;
 
 __END_DECLS
@@ -20,6 +20,7 @@
 #include <base/strings/stringprintf.h>
 #include <time.h>
 
+#include <algorithm>
 #include <chrono>
 #include <cstdint>
 #include <functional>
@@ -28,6 +29,7 @@
 #include <memory>
 #include <string>
 #include <unordered_set>
+#include <vector>
 
 #include "btif/include/btif_hh.h"
 #include "device/include/controller.h"
@@ -88,6 +90,15 @@ class ConnectAddressWithType {
     return ss.str();
   }
 
+  hci::Address GetAddress() const { return address_; }
+
+  hci::AddressWithType ToAddressWithType() const {
+    return hci::AddressWithType(
+        address_, type_ == hci::FilterAcceptListAddressType::RANDOM
+                      ? hci::AddressType::RANDOM_DEVICE_ADDRESS
+                      : hci::AddressType::PUBLIC_DEVICE_ADDRESS);
+  }
+
   bool operator==(const ConnectAddressWithType& rhs) const {
     return address_ == rhs.address_ && type_ == rhs.type_;
   }
@@ -819,6 +830,10 @@ struct shim::legacy::Acl::impl {
   ShadowAcceptlist shadow_acceptlist_;
   ShadowAddressResolutionList shadow_address_resolution_list_;
 
+  // Acceptlist entries removed while the system is suspended, restored on
+  // resume.
+  std::unordered_set<ConnectAddressWithType> suspended_acceptlist_;
+
   bool IsClassicAcl(HciHandle handle) {
     return handle_to_classic_connection_map_.find(handle) !=
            handle_to_classic_connection_map_.end();
@@ -981,6 +996,7 @@ struct shim::legacy::Acl::impl {
   void ignore_le_connection_from(
       const hci::AddressWithType& address_with_type) {
     shadow_acceptlist_.Remove(address_with_type);
+    suspended_acceptlist_.erase(ConnectAddressWithType(address_with_type));
     GetAclManager()->CancelLeConnectAndRemoveFromBackgroundList(
         address_with_type);
     LOG_DEBUG("Ignore Le connection from remote:%s",
@@ -994,9 +1010,54 @@ struct shim::legacy::Acl::impl {
     size_t count = shadow_acceptlist.size();
     GetAclManager()->ClearFilterAcceptList();
     shadow_acceptlist_.Clear();
+    suspended_acceptlist_.clear();
     LOG_DEBUG("Cleared entire Le address acceptlist count:%zu", count);
   }
 
+  void suspend_le_connections(std::vector<hci::Address> wake_devices) {
+    // The connected devices are not in the acceptlist, their links waking the
+    // host anyway.
+    for (const auto& entry : shadow_acceptlist_.GetCopy()) {
+      if (std::find(wake_devices.begin(), wake_devices.end(),
+                    entry.GetAddress()) != wake_devices.end()) {
+        continue;
+      }
+      auto address_with_type = entry.ToAddressWithType();
+      shadow_acceptlist_.Remove(address_with_type);
+      GetAclManager()->CancelLeConnectAndRemoveFromBackgroundList(
+          address_with_type);
+      suspended_acceptlist_.insert(entry);
+    }
+    LOG_INFO("Suspended Le connections from %zu devices",
+             suspended_acceptlist_.size());
+  }
+
+  void resume_le_connections() {
+    auto acceptlist = shadow_acceptlist_.GetCopy();
+    for (const auto& entry : suspended_acceptlist_) {
+      if (acceptlist.count(entry) != 0) continue;
+      auto address_with_type = entry.ToAddressWithType();
+      if (!shadow_acceptlist_.Add(address_with_type)) break;
+      GetAclManager()->CreateLeConnection(address_with_type,
+                                         /* is_direct */ false);
+    }
+    LOG_INFO("Resumed Le connections from %zu devices",
+             suspended_acceptlist_.size());
+    suspended_acceptlist_.clear();
+  }
+
+  void get_le_lists(LeListsCallback callback) {
+    std::vector<std::string> acceptlist;
+    for (const auto& entry : shadow_acceptlist_.GetCopy()) {
+      acceptlist.push_back(entry.ToString());
+    }
+    std::vector<std::string> address_resolution_list;
+    for (const auto& entry : shadow_address_resolution_list_.GetCopy()) {
+      address_resolution_list.push_back(entry.ToString());
+    }
+    callback(std::move(acceptlist), std::move(address_resolution_list));
+  }
+
   void AddToAddressResolution(const hci::AddressWithType& address_with_type,
                               const std::array<uint8_t, 16>& peer_irk,
                               const std::array<uint8_t, 16>& local_irk) {
@@ -1027,6 +1088,19 @@ struct shim::legacy::Acl::impl {
     shadow_address_resolution_list_.Clear();
   }
 
+  void SetPrivacyMode(const hci::Address& address, hci::PrivacyMode privacy_mode,
+                      std::promise<bool> promise) {
+    // The mode applies to the identity address of the device in the list.
+    for (const auto& entry : shadow_address_resolution_list_.GetCopy()) {
+      if (entry.GetAddress() == address) {
+        GetAclManager()->SetPrivacyMode(entry, privacy_mode);
+        promise.set_value(true);
+        return;
+      }
+    }
+    promise.set_value(false);
+  }
+
   void DumpConnectionHistory() const {
     std::vector<std::string> history =
         connection_history_.ReadElementsAsString();
@@ -1691,6 +1765,21 @@ void shim::legacy::Acl::ClearAcceptList() {
   handler_->CallOn(pimpl_.get(), &Acl::impl::clear_acceptlist);
 }
 
+void shim::legacy::Acl::GetLeLists(LeListsCallback callback) {
+  handler_->CallOn(pimpl_.get(), &Acl::impl::get_le_lists,
+                   std::move(callback));
+}
+
+void shim::legacy::Acl::SuspendLeConnections(
+    std::vector<hci::Address> wake_devices) {
+  handler_->CallOn(pimpl_.get(), &Acl::impl::suspend_le_connections,
+                   std::move(wake_devices));
+}
+
+void shim::legacy::Acl::ResumeLeConnections() {
+  handler_->CallOn(pimpl_.get(), &Acl::impl::resume_le_connections);
+}
+
 void shim::legacy::Acl::AddToAddressResolution(
     const hci::AddressWithType& address_with_type,
     const std::array<uint8_t, 16>& peer_irk,
@@ -1708,3 +1797,10 @@ void shim::legacy::Acl::RemoveFromAddressResolution(
 void shim::legacy::Acl::ClearAddressResolution() {
   handler_->CallOn(pimpl_.get(), &Acl::impl::ClearResolvingList);
 }
+
+void shim::legacy::Acl::SetPrivacyMode(const hci::Address& address,
+                                       hci::PrivacyMode privacy_mode,
+                                       std::promise<bool> promise) {
+  handler_->CallOn(pimpl_.get(), &Acl::impl::SetPrivacyMode, address,
+                   privacy_mode, std::move(promise));
+}
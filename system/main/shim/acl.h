@@ -16,8 +16,11 @@
 
 #pragma once
 
+#include <functional>
 #include <future>
 #include <memory>
+#include <string>
+#include <vector>
 
 #include "gd/hci/acl_manager/connection_callbacks.h"
 #include "gd/hci/acl_manager/le_connection_callbacks.h"
@@ -88,6 +91,9 @@ class Acl : public hci::acl_manager::ConnectionCallbacks,
   void RemoveFromAddressResolution(
       const hci::AddressWithType& address_with_type);
   void ClearAddressResolution();
+  void SetPrivacyMode(const hci::Address& address,
+                      hci::PrivacyMode privacy_mode,
+                      std::promise<bool> promise);
 
   // LinkPolicyInterface
   bool HoldMode(uint16_t hci_handle, uint16_t max_interval,
@@ -110,6 +116,15 @@ class Acl : public hci::acl_manager::ConnectionCallbacks,
   void FinalShutdown();
 
   void ClearAcceptList();
+  // Runs `callback` on the ACL thread with the acceptlist and the address
+  // resolution list, as mirrored by the host.
+  using LeListsCallback = std::function<void(std::vector<std::string>,
+                                             std::vector<std::string>)>;
+  void GetLeLists(LeListsCallback callback);
+  // Removes the devices other than `wake_devices` from the acceptlist, until
+  // ResumeLeConnections.
+  void SuspendLeConnections(std::vector<hci::Address> wake_devices);
+  void ResumeLeConnections();
 
  protected:
   void on_incoming_acl_credits(uint16_t handle, uint16_t credits);
@@ -128,6 +128,36 @@ void bluetooth::shim::ACL_ClearAddressResolution() {
   Stack::GetInstance()->GetAcl()->ClearAddressResolution();
 }
 
+bool bluetooth::shim::ACL_SetPrivacyMode(const RawAddress& identity_address,
+                                         bool device_privacy) {
+  std::promise<bool> promise;
+  auto future = promise.get_future();
+  Stack::GetInstance()->GetAcl()->SetPrivacyMode(
+      ToGdAddress(identity_address),
+      device_privacy ? hci::PrivacyMode::DEVICE : hci::PrivacyMode::NETWORK,
+      std::move(promise));
+  return future.get();
+}
+
 void bluetooth::shim::ACL_ClearAcceptList() {
   Stack::GetInstance()->GetAcl()->ClearAcceptList();
 }
+
+void bluetooth::shim::ACL_GetLeLists(
+    std::function<void(std::vector<std::string>, std::vector<std::string>)>
+        callback) {
+  Stack::GetInstance()->GetAcl()->GetLeLists(std::move(callback));
+}
+
+void bluetooth::shim::ACL_SuspendLeConnections(
+    const std::vector<RawAddress>& wake_devices) {
+  std::vector<hci::Address> addresses;
+  for (const auto& address : wake_devices) {
+    addresses.push_back(ToGdAddress(address));
+  }
+  Stack::GetInstance()->GetAcl()->SuspendLeConnections(std::move(addresses));
+}
+
+void bluetooth::shim::ACL_ResumeLeConnections() {
+  Stack::GetInstance()->GetAcl()->ResumeLeConnections();
+}
@@ -16,6 +16,10 @@
 
 #pragma once
 
+#include <functional>
+#include <string>
+#include <vector>
+
 #include "stack/include/bt_hdr.h"
 #include "stack/include/bt_types.h"
 #include "stack/include/hci_error_code.h"
@@ -48,7 +52,17 @@ void ACL_AddToAddressResolution(const tBLE_BD_ADDR& legacy_address_with_type,
 void ACL_RemoveFromAddressResolution(
     const tBLE_BD_ADDR& legacy_address_with_type);
 void ACL_ClearAddressResolution();
+// Returns false if the device is not in the address resolution list.
+bool ACL_SetPrivacyMode(const RawAddress& identity_address,
+                        bool device_privacy);
 void ACL_ClearAcceptList();
+// Runs `callback` on the ACL thread with the acceptlist and the address
+// resolution list, as mirrored by the host.
+void ACL_GetLeLists(
+    std::function<void(std::vector<std::string>, std::vector<std::string>)>
+        callback);
+void ACL_SuspendLeConnections(const std::vector<RawAddress>& wake_devices);
+void ACL_ResumeLeConnections();
 
 }  // namespace shim
 }  // namespace bluetooth
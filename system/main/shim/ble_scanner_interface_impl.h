@@ -54,6 +54,7 @@ class BleScannerInterfaceImpl : public ::BleScannerInterface,
   void ScanFilterEnable(bool enable, EnableCallback cb) override;
   void SetScanParameters(int scanner_id, int scan_interval, int scan_window,
                          Callback cb) override;
+  void SetScanPhys(int scanner_id, uint8_t scan_phys, Callback cb) override;
   void BatchscanConfigStorage(int client_if, int batch_scan_full_max,
                               int batch_scan_trunc_max,
                               int batch_scan_notify_threshold,
@@ -15,6 +15,7 @@
  */
 
 #include "gd/btaa/activity_attribution.h"
+#include "gd/hal/snoop_logger.h"
 #include "gd/hci/controller.h"
 #include "gd/hci/hci_layer.h"
 #include "gd/hci/le_advertising_manager.h"
@@ -145,5 +146,14 @@ metrics::CounterMetrics* GetCounterMetrics() {
       ->GetInstance<metrics::CounterMetrics>();
 }
 
+hal::SnoopLogger* GetSnoopLogger() {
+  auto stack = Stack::GetInstance();
+  if (!stack->IsRunning() ||
+      !stack->GetStackManager()->IsStarted<hal::SnoopLogger>()) {
+    return nullptr;
+  }
+  return stack->GetStackManager()->GetInstance<hal::SnoopLogger>();
+}
+
 }  // namespace shim
 }  // namespace bluetooth
@@ -45,6 +45,9 @@ class InquiryModule;
 class NameModule;
 class PageModule;
 }
+namespace hal {
+class SnoopLogger;
+}
 namespace hci {
 class Controller;
 class HciLayer;
@@ -98,6 +101,8 @@ hci::AclManager* GetAclManager();
 hci::VendorSpecificEventManager* GetVendorSpecificEventManager();
 activity_attribution::ActivityAttribution* GetActivityAttribution();
 metrics::CounterMetrics* GetCounterMetrics();
+/* This returns nullptr while the HCI layer is not started. */
+hal::SnoopLogger* GetSnoopLogger();
 
 }  // namespace shim
 }  // namespace bluetooth
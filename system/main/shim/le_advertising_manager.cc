@@ -77,6 +77,13 @@ class BleAdvertiserInterfaceImpl : public BleAdvertiserInterface,
     bluetooth::shim::GetAdvertising()->GetOwnAddress(advertiser_id);
   }
 
+  void SetAddressRotationInterval(uint8_t advertiser_id,
+                                  uint32_t interval_ms) override {
+    LOG(INFO) << __func__ << " in shim layer";
+    bluetooth::shim::GetAdvertising()->SetAddressRotationInterval(
+        advertiser_id, std::chrono::milliseconds(interval_ms));
+  }
+
   void SetParameters(uint8_t advertiser_id, AdvertiseParameters params,
                      ParametersCallback cb) override {
     LOG(INFO) << __func__ << " in shim layer";
@@ -378,6 +385,15 @@ class BleAdvertiserInterfaceImpl : public BleAdvertiserInterface,
                                 advertiser_id, address_type, raw_address));
   }
 
+  void OnOwnAddressChanged(uint8_t advertiser_id, uint8_t address_type,
+                           bluetooth::hci::Address address) {
+    do_in_jni_thread(FROM_HERE,
+                     base::Bind(&AdvertisingCallbacks::OnOwnAddressChanged,
+                                base::Unretained(advertising_callbacks_),
+                                advertiser_id, address_type,
+                                bluetooth::ToRawAddress(address)));
+  }
+
   AdvertisingCallbacks* advertising_callbacks_;
 
  private:
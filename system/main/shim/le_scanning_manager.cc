@@ -264,6 +264,13 @@ void BleScannerInterfaceImpl::SetScanParameters(int scanner_id,
                                                     scan_interval, scan_window);
 }
 
+/** Sets the PHYs to scan on */
+void BleScannerInterfaceImpl::SetScanPhys(int scanner_id, uint8_t scan_phys,
+                                          Callback cb) {
+  LOG(INFO) << __func__ << " in shim layer";
+  bluetooth::shim::GetScanning()->SetScanPhys(scanner_id, scan_phys);
+}
+
 /* Configure the batchscan storage */
 void BleScannerInterfaceImpl::BatchscanConfigStorage(
     int client_if, int batch_scan_full_max, int batch_scan_trunc_max,
@@ -55,6 +55,8 @@ class MockScannerHandler : public BleScannerInterface {
   MOCK_METHOD2(ScanFilterEnable, void(bool enable, EnableCallback cb));
   MOCK_METHOD4(SetScanParameters, void(int scanner_id, int scan_interval,
                                        int scan_window, Callback cb));
+  MOCK_METHOD3(SetScanPhys,
+               void(int scanner_id, uint8_t scan_phys, Callback cb));
 
   MOCK_METHOD5(BatchscanConfigStorage,
                void(int client_if, int batch_scan_full_max,
@@ -1599,6 +1599,23 @@ uint8_t* BTM_ReadRemoteFeatures(const RawAddress& addr) {
   return (p->peer_lmp_feature_pages[0]);
 }
 
+/*******************************************************************************
+ *
+ * Function         BTM_ReadRemoteFeaturesPage
+ *
+ * Returns          pointer to the remote supported features mask of the page
+ *                  (8 bytes), NULL if not read
+ *
+ ******************************************************************************/
+uint8_t* BTM_ReadRemoteFeaturesPage(const RawAddress& addr, uint8_t page) {
+  if (page > HCI_EXT_FEATURES_PAGE_MAX) return NULL;
+
+  tACL_CONN* p = internal_.btm_bda_to_acl(addr, BT_TRANSPORT_BR_EDR);
+  if (p == NULL || !p->peer_lmp_feature_valid[page]) return NULL;
+
+  return (p->peer_lmp_feature_pages[page]);
+}
+
 /*******************************************************************************
  *
  * Function         BTM_ReadRSSI
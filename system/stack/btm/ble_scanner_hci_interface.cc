@@ -20,6 +20,10 @@
 #include <base/bind.h>
 #include <base/logging.h>
 
+#include <set>
+#include <string>
+#include <tuple>
+
 #include "acl_api.h"
 #include "btm_api.h"
 #include "device/include/controller.h"
@@ -120,6 +124,7 @@ class BleScannerImplBase : public BleScannerHciInterface {
                                        RawAddress& adv_addr, uint8_t set_id,
                                        status_cb command_complete) override {
     VLOG(1) << __func__;
+    periodic_advertiser_list_.emplace(adv_addr_type, adv_addr, set_id);
     btsnd_hci_ble_add_device_to_periodic_advertiser_list(
         adv_addr_type, adv_addr, set_id,
         base::Bind(&status_callback, std::move(command_complete)));
@@ -129,6 +134,8 @@ class BleScannerImplBase : public BleScannerHciInterface {
                                           RawAddress& adv_addr, uint8_t set_id,
                                           status_cb command_complete) override {
     VLOG(1) << __func__;
+    periodic_advertiser_list_.erase(
+        std::make_tuple(adv_addr_type, adv_addr, set_id));
     btsnd_hci_ble_remove_device_from_periodic_advertiser_list(
         adv_addr_type, adv_addr, set_id,
         base::Bind(&status_callback, std::move(command_complete)));
@@ -136,6 +143,7 @@ class BleScannerImplBase : public BleScannerHciInterface {
 
   void PeriodicAdvertiserListClear(status_cb command_complete) override {
     VLOG(1) << __func__;
+    periodic_advertiser_list_.clear();
     btsnd_hci_ble_clear_periodic_advertiser_list(
         base::Bind(&status_callback, std::move(command_complete)));
   };
@@ -226,6 +234,22 @@ class BleScannerImplBase : public BleScannerHciInterface {
       scan_event_observer->OnPeriodicScanLost(sync_handle);
   }
 
+  std::vector<std::string> GetPeriodicAdvertiserList() override {
+    std::vector<std::string> entries;
+    for (const auto& [adv_addr_type, adv_addr, set_id] :
+         periodic_advertiser_list_) {
+      entries.push_back(adv_addr.ToString() +
+                        " type:" + std::to_string(adv_addr_type) +
+                        " sid:" + std::to_string(set_id));
+    }
+    return entries;
+  }
+
+ protected:
+  // Entries added to the Periodic Advertiser list of the controller, as
+  // address type, address and SID. Kept up to date when the commands are sent.
+  std::set<std::tuple<uint8_t, RawAddress, uint8_t>> periodic_advertiser_list_;
+
  private:
   ScanEventObserver* scan_event_observer = nullptr;
 };
@@ -235,6 +259,7 @@ class BleScannerListImpl : public virtual BleScannerImplBase {
                                        RawAddress& adv_addr, uint8_t set_id,
                                        status_cb command_complete) override {
     VLOG(1) << __func__;
+    periodic_advertiser_list_.emplace(adv_addr_type, adv_addr, set_id);
     btsnd_hci_ble_add_device_to_periodic_advertiser_list(
         adv_addr_type, adv_addr, set_id,
         base::Bind(&status_callback, std::move(command_complete)));
@@ -244,6 +269,8 @@ class BleScannerListImpl : public virtual BleScannerImplBase {
                                           RawAddress& adv_addr, uint8_t set_id,
                                           status_cb command_complete) override {
     VLOG(1) << __func__;
+    periodic_advertiser_list_.erase(
+        std::make_tuple(adv_addr_type, adv_addr, set_id));
     btsnd_hci_ble_remove_device_from_periodic_advertiser_list(
         adv_addr_type, adv_addr, set_id,
         base::Bind(&status_callback, std::move(command_complete)));
@@ -251,6 +278,7 @@ class BleScannerListImpl : public virtual BleScannerImplBase {
 
   void PeriodicAdvertiserListClear(status_cb command_complete) override {
     VLOG(1) << __func__;
+    periodic_advertiser_list_.clear();
     btsnd_hci_ble_clear_periodic_advertiser_list(
         base::Bind(&status_callback, std::move(command_complete)));
   };
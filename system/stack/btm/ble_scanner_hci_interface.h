@@ -20,6 +20,7 @@
 
 #include <base/callback.h>
 
+#include <string>
 #include <vector>
 
 #include "stack/include/bt_types.h"
@@ -158,6 +159,12 @@ class BleScannerHciInterface {
    */
   virtual void PeriodicAdvertiserListGetSize(list_size_cb cb) = 0;
 
+  /**
+   * Return the entries added to the Periodic Advertiser list by the host, as
+   * the address followed by the address type and the SID, for debugging.
+   */
+  virtual std::vector<std::string> GetPeriodicAdvertiserList() = 0;
+
   /**
    * Send synchronization information about the periodic advertising train
    * identified by the sync_handle parameter to a connected device.
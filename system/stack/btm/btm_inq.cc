@@ -86,6 +86,13 @@ using bluetooth::Uuid;
 static const LAP general_inq_lap = {0x9e, 0x8b, 0x33};
 static const LAP limited_inq_lap = {0x9e, 0x8b, 0x00};
 
+/* Page and inquiry scan activity written when the scans are enabled, which may
+ * be tuned with BTM_SetPageScanActivity and BTM_SetInquiryScanActivity */
+static uint16_t page_scan_window_cfg = BTM_DEFAULT_CONN_WINDOW;
+static uint16_t page_scan_interval_cfg = BTM_DEFAULT_CONN_INTERVAL;
+static uint16_t inq_scan_window_cfg = BTM_DEFAULT_DISC_WINDOW;
+static uint16_t inq_scan_interval_cfg = BTM_DEFAULT_DISC_INTERVAL;
+
 const uint16_t BTM_EIR_UUID_LKUP_TBL[BTM_EIR_MAX_SERVICES] = {
     UUID_SERVCLASS_SERVICE_DISCOVERY_SERVER,
     /*    UUID_SERVCLASS_BROWSE_GROUP_DESCRIPTOR,   */
@@ -179,7 +186,8 @@ void SendRemoteNameRequest(const RawAddress& raw_address) {
  ******************************************************************************/
 tBTM_STATUS BTM_SetDiscoverability(uint16_t inq_mode) {
   if (bluetooth::shim::is_gd_shim_enabled()) {
-    return bluetooth::shim::BTM_SetDiscoverability(inq_mode, 0, 0);
+    return bluetooth::shim::BTM_SetDiscoverability(
+        inq_mode, inq_scan_window_cfg, inq_scan_interval_cfg);
   }
 
   uint8_t scan_mode = 0;
@@ -190,8 +198,8 @@ tBTM_STATUS BTM_SetDiscoverability(uint16_t inq_mode) {
   LAP temp_lap[2];
   bool is_limited;
   bool cod_limited;
-  uint16_t window = BTM_DEFAULT_DISC_WINDOW;
-  uint16_t interval = BTM_DEFAULT_DISC_INTERVAL;
+  uint16_t window = inq_scan_window_cfg;
+  uint16_t interval = inq_scan_interval_cfg;
 
   BTM_TRACE_API("BTM_SetDiscoverability");
   if (controller_get_interface()->supports_ble()) {
@@ -350,12 +358,13 @@ tBTM_STATUS BTM_SetInquiryMode(uint8_t mode) {
  ******************************************************************************/
 tBTM_STATUS BTM_SetConnectability(uint16_t page_mode) {
   if (bluetooth::shim::is_gd_shim_enabled()) {
-    return bluetooth::shim::BTM_SetConnectability(page_mode, 0, 0);
+    return bluetooth::shim::BTM_SetConnectability(
+        page_mode, page_scan_window_cfg, page_scan_interval_cfg);
   }
 
   uint8_t scan_mode = 0;
-  uint16_t window = BTM_DEFAULT_CONN_WINDOW;
-  uint16_t interval = BTM_DEFAULT_CONN_INTERVAL;
+  uint16_t window = page_scan_window_cfg;
+  uint16_t interval = page_scan_interval_cfg;
   tBTM_INQUIRY_VAR_ST* p_inq = &btm_cb.btm_inq_vars;
 
   BTM_TRACE_API("BTM_SetConnectability");
@@ -401,6 +410,144 @@ tBTM_STATUS BTM_SetConnectability(uint16_t page_mode) {
   return (BTM_SUCCESS);
 }
 
+/*******************************************************************************
+ *
+ * Function         BTM_SetPageScanActivity
+ *
+ * Description      This function sets the page scan interval and window used
+ *                  whenever the device is connectable, and writes them to the
+ *                  controller if they changed.
+ *
+ * Returns          BTM_SUCCESS if successful
+ *                  BTM_ILLEGAL_VALUE if a bad parameter is detected
+ *
+ ******************************************************************************/
+tBTM_STATUS BTM_SetPageScanActivity(uint16_t interval, uint16_t window) {
+  tBTM_INQUIRY_VAR_ST* p_inq = &btm_cb.btm_inq_vars;
+
+  BTM_TRACE_API("BTM_SetPageScanActivity: interval %d window %d", interval,
+                window);
+  if (interval < HCI_MIN_PAGESCAN_INTERVAL ||
+      interval > HCI_MAX_PAGESCAN_INTERVAL ||
+      window < HCI_MIN_PAGESCAN_WINDOW || window > interval)
+    return (BTM_ILLEGAL_VALUE);
+
+  page_scan_window_cfg = window;
+  page_scan_interval_cfg = interval;
+
+  /* Otherwise written when the controller is set connectable */
+  if (!controller_get_interface()->get_is_ready()) return (BTM_SUCCESS);
+
+  if ((window != p_inq->page_scan_window) ||
+      (interval != p_inq->page_scan_period)) {
+    p_inq->page_scan_window = window;
+    p_inq->page_scan_period = interval;
+    btsnd_hcic_write_pagescan_cfg(interval, window);
+  }
+  return (BTM_SUCCESS);
+}
+
+/*******************************************************************************
+ *
+ * Function         BTM_SetInquiryScanActivity
+ *
+ * Description      This function sets the inquiry scan interval and window
+ *                  used whenever the device is discoverable, and writes them
+ *                  to the controller if they changed.
+ *
+ * Returns          BTM_SUCCESS if successful
+ *                  BTM_ILLEGAL_VALUE if a bad parameter is detected
+ *
+ ******************************************************************************/
+tBTM_STATUS BTM_SetInquiryScanActivity(uint16_t interval, uint16_t window) {
+  tBTM_INQUIRY_VAR_ST* p_inq = &btm_cb.btm_inq_vars;
+
+  BTM_TRACE_API("BTM_SetInquiryScanActivity: interval %d window %d", interval,
+                window);
+  if (interval < HCI_MIN_INQUIRYSCAN_INTERVAL ||
+      interval > HCI_MAX_INQUIRYSCAN_INTERVAL ||
+      window < HCI_MIN_INQUIRYSCAN_WINDOW || window > interval)
+    return (BTM_ILLEGAL_VALUE);
+
+  inq_scan_window_cfg = window;
+  inq_scan_interval_cfg = interval;
+
+  /* Otherwise written when the controller is set discoverable */
+  if (!controller_get_interface()->get_is_ready()) return (BTM_SUCCESS);
+
+  if ((window != p_inq->inq_scan_window) ||
+      (interval != p_inq->inq_scan_period)) {
+    p_inq->inq_scan_window = window;
+    p_inq->inq_scan_period = interval;
+    btsnd_hcic_write_inqscan_cfg(interval, window);
+  }
+  return (BTM_SUCCESS);
+}
+
+/*******************************************************************************
+ *
+ * Function         BTM_SetPageScanType
+ *
+ * Description      This function sets the page scan type, standard or
+ *                  interlaced.
+ *
+ * Returns          BTM_SUCCESS if successful
+ *                  BTM_ILLEGAL_VALUE if a bad parameter is detected
+ *                  BTM_MODE_UNSUPPORTED if interlaced scans are not supported
+ *                  BTM_WRONG_MODE if the device is not up.
+ *
+ ******************************************************************************/
+tBTM_STATUS BTM_SetPageScanType(uint16_t scan_type) {
+  BTM_TRACE_API("BTM_SetPageScanType: type %d", scan_type);
+  if (scan_type != BTM_SCAN_TYPE_STANDARD &&
+      scan_type != BTM_SCAN_TYPE_INTERLACED)
+    return (BTM_ILLEGAL_VALUE);
+
+  if (!controller_get_interface()->get_is_ready()) return (BTM_WRONG_MODE);
+
+  if (scan_type == BTM_SCAN_TYPE_INTERLACED &&
+      !controller_get_interface()->supports_interlaced_inquiry_scan())
+    return (BTM_MODE_UNSUPPORTED);
+
+  if (scan_type != btm_cb.btm_inq_vars.page_scan_type) {
+    btsnd_hcic_write_pagescan_type(scan_type);
+    btm_cb.btm_inq_vars.page_scan_type = scan_type;
+  }
+  return (BTM_SUCCESS);
+}
+
+/*******************************************************************************
+ *
+ * Function         BTM_SetInquiryScanType
+ *
+ * Description      This function sets the inquiry scan type, standard or
+ *                  interlaced.
+ *
+ * Returns          BTM_SUCCESS if successful
+ *                  BTM_ILLEGAL_VALUE if a bad parameter is detected
+ *                  BTM_MODE_UNSUPPORTED if interlaced scans are not supported
+ *                  BTM_WRONG_MODE if the device is not up.
+ *
+ ******************************************************************************/
+tBTM_STATUS BTM_SetInquiryScanType(uint16_t scan_type) {
+  BTM_TRACE_API("BTM_SetInquiryScanType: type %d", scan_type);
+  if (scan_type != BTM_SCAN_TYPE_STANDARD &&
+      scan_type != BTM_SCAN_TYPE_INTERLACED)
+    return (BTM_ILLEGAL_VALUE);
+
+  if (!controller_get_interface()->get_is_ready()) return (BTM_WRONG_MODE);
+
+  if (scan_type == BTM_SCAN_TYPE_INTERLACED &&
+      !controller_get_interface()->supports_interlaced_inquiry_scan())
+    return (BTM_MODE_UNSUPPORTED);
+
+  if (scan_type != btm_cb.btm_inq_vars.inq_scan_type) {
+    btsnd_hcic_write_inqscan_type(scan_type);
+    btm_cb.btm_inq_vars.inq_scan_type = scan_type;
+  }
+  return (BTM_SUCCESS);
+}
+
 /*******************************************************************************
  *
  * Function         BTM_IsInquiryActive
@@ -51,6 +51,7 @@ struct EattExtension::impl {
       LOG(ERROR) << __func__ << " cannot register EATT";
     } else {
       eatt_impl_ = std::make_unique<eatt_impl>();
+      eatt_impl_->channels_changed_cb_ = channels_changed_cb_;
     }
   }
 
@@ -118,6 +119,8 @@ struct EattExtension::impl {
 
   std::unique_ptr<eatt_impl> eatt_impl_;
   tL2CAP_APPL_INFO reg_info_;
+  /* Kept here to survive a restart of the module */
+  base::RepeatingCallback<void(const RawAddress&)> channels_changed_cb_;
 };
 
 void EattExtension::AddFromStorage(const RawAddress& bd_addr) {
@@ -185,6 +188,17 @@ EattChannel* EattExtension::GetChannelAvailableForClientRequest(
   return pimpl_->eatt_impl_->get_channel_available_for_client_request(bd_addr);
 }
 
+std::vector<uint16_t> EattExtension::GetConnectedChannelsTxMtu(
+    const RawAddress& bd_addr) {
+  return pimpl_->eatt_impl_->get_connected_channels_tx_mtu(bd_addr);
+}
+
+void EattExtension::SetChannelsChangedCallback(
+    base::RepeatingCallback<void(const RawAddress&)> cb) {
+  pimpl_->channels_changed_cb_ = cb;
+  if (pimpl_->eatt_impl_) pimpl_->eatt_impl_->channels_changed_cb_ = cb;
+}
+
 /* Start stop GATT indication timer per CID */
 void EattExtension::StartIndicationConfirmationTimer(const RawAddress& bd_addr,
                                                      uint16_t cid) {
@@ -17,7 +17,10 @@
 
 #pragma once
 
+#include <base/callback.h>
+
 #include <deque>
+#include <vector>
 
 #include "stack/gatt/gatt_int.h"
 #include "types/raw_address.h"
@@ -238,6 +241,25 @@ class EattExtension {
   virtual EattChannel* GetChannelAvailableForClientRequest(
       const RawAddress& bd_addr);
 
+  /**
+   * Get the transmit MTU of each connected EATT channel to peer device.
+   *
+   * @param bd_addr peer device address
+   *
+   * @return transmit MTUs of the channels, empty when none is connected.
+   */
+  virtual std::vector<uint16_t> GetConnectedChannelsTxMtu(
+      const RawAddress& bd_addr);
+
+  /**
+   * Set the callback run whenever an EATT channel to a peer device gets
+   * connected, reconfigured or disconnected. It replaces the previous one.
+   *
+   * @param cb callback receiving the peer device address
+   */
+  virtual void SetChannelsChangedCallback(
+      base::RepeatingCallback<void(const RawAddress&)> cb);
+
   /**
    * Start GATT indication timer per CID.
    *
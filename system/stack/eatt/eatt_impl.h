@@ -64,6 +64,7 @@ struct eatt_impl {
   uint16_t default_mtu_;
   uint16_t max_mps_;
   tL2CAP_APPL_INFO reg_info_;
+  base::RepeatingCallback<void(const RawAddress&)> channels_changed_cb_;
 
   eatt_impl() {
     default_mtu_ = EATT_DEFAULT_MTU;
@@ -110,6 +111,10 @@ struct eatt_impl {
     return (it == eatt_dev->eatt_channels.end()) ? nullptr : it->second.get();
   }
 
+  void notify_channels_changed(const RawAddress& bda) {
+    if (!channels_changed_cb_.is_null()) channels_changed_cb_.Run(bda);
+  }
+
   void remove_channel_by_cid(eatt_device* eatt_dev, uint16_t lcid) {
     auto channel = eatt_dev->eatt_channels[lcid];
     if (!channel->cl_cmd_q_.empty()) {
@@ -121,6 +126,9 @@ struct eatt_impl {
     eatt_dev->eatt_channels.erase(lcid);
 
     if (eatt_dev->eatt_channels.size() == 0) eatt_dev->eatt_tcb_ = NULL;
+
+    if (channel->state_ != EattChannelState::EATT_CHANNEL_PENDING)
+      notify_channels_changed(eatt_dev->bda_);
   }
 
   void remove_channel_by_cid(uint16_t lcid) {
@@ -179,6 +187,7 @@ struct eatt_impl {
       LOG(INFO) << __func__ << " Channel connected CID " << loghex(cid);
     }
 
+    notify_channels_changed(bda);
     return true;
   }
 
@@ -420,6 +429,7 @@ struct eatt_impl {
     eatt_dev->eatt_tcb_->eatt++;
 
     LOG_INFO("Channel connected CID 0x%04x", lcid);
+    notify_channels_changed(bda);
 
     if (stack_config_get_interface()->get_pts_l2cap_ecoc_upper_tester()) {
       upper_tester_l2cap_connect_cfm(eatt_dev);
@@ -450,6 +460,7 @@ struct eatt_impl {
 
     /* Go back to open state */
     channel->EattChannelSetState(EattChannelState::EATT_CHANNEL_OPENED);
+    if (!is_local_cfg) notify_channels_changed(bda);
 
     if (stack_config_get_interface()->get_pts_l2cap_ecoc_reconfigure()) {
       /* Upper tester for L2CAP - schedule sending data */
@@ -696,6 +707,19 @@ struct eatt_impl {
                                                    : iter->second.get();
   }
 
+  std::vector<uint16_t> get_connected_channels_tx_mtu(
+      const RawAddress& bd_addr) {
+    std::vector<uint16_t> mtus;
+    eatt_device* eatt_dev = find_device_by_address(bd_addr);
+    if (!eatt_dev) return mtus;
+
+    for (auto const& el : eatt_dev->eatt_channels) {
+      if (el.second->state_ != EattChannelState::EATT_CHANNEL_PENDING)
+        mtus.push_back(el.second->tx_mtu_);
+    }
+    return mtus;
+  }
+
   void free_gatt_resources(const RawAddress& bd_addr) {
     eatt_device* eatt_dev = find_device_by_address(bd_addr);
     if (!eatt_dev) return;
@@ -929,6 +953,7 @@ struct eatt_impl {
     eatt_dev->eatt_tcb_->eatt = 0;
     eatt_dev->eatt_tcb_ = nullptr;
     eatt_dev->collision = false;
+    notify_channels_changed(bd_addr);
   }
 
   void upper_tester_connect(const RawAddress& bd_addr, eatt_device* eatt_dev,
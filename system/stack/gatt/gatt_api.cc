@@ -483,16 +483,20 @@ tGATT_STATUS GATTS_HandleValueIndication(uint16_t conn_id, uint16_t attr_handle,
   return cmd_status;
 }
 
-#if (GATT_UPPER_TESTER_MULT_VARIABLE_LENGTH_NOTIF == TRUE)
-static tGATT_STATUS GATTS_HandleMultileValueNotification(
-    tGATT_TCB* p_tcb, std::vector<tGATT_VALUE> gatt_notif_vector) {
-  LOG(INFO) << __func__;
-
-  uint16_t cid = gatt_tcb_get_att_cid(*p_tcb, true /* eatt support */);
+static tGATT_STATUS gatt_send_multiple_value_notification(
+    tGATT_TCB* p_tcb, uint16_t cid,
+    const std::vector<tGATT_VALUE>& gatt_notif_vector) {
   uint16_t payload_size = gatt_tcb_get_payload_size_tx(*p_tcb, cid);
 
-  /* TODO Handle too big packet size here. Not needed now for testing. */
-  /* Just build the message. */
+  /* Opcode, then handle, length and value of each notification */
+  size_t total_len = 1;
+  for (const auto& notif : gatt_notif_vector) total_len += 4 + notif.len;
+  if (total_len > payload_size) {
+    LOG(WARNING) << __func__ << ": " << total_len
+                 << " bytes do not fit the MTU, payload size: " << payload_size;
+    return GATT_INVALID_ATTR_LEN;
+  }
+
   BT_HDR* p_buf =
       (BT_HDR*)osi_malloc(sizeof(BT_HDR) + payload_size + L2CAP_MIN_OFFSET);
 
@@ -500,9 +504,9 @@ static tGATT_STATUS GATTS_HandleMultileValueNotification(
   UINT8_TO_STREAM(p, GATT_HANDLE_MULTI_VALUE_NOTIF);
   p_buf->offset = L2CAP_MIN_OFFSET;
   p_buf->len = 1;
-  for (auto notif : gatt_notif_vector) {
-    LOG(INFO) << __func__ << "Adding handle: " << loghex(notif.handle)
-              << "val len: " << +notif.len;
+  for (const auto& notif : gatt_notif_vector) {
+    VLOG(1) << __func__ << " Adding handle: " << loghex(notif.handle)
+            << " val len: " << +notif.len;
     UINT16_TO_STREAM(p, notif.handle);
     p_buf->len += 2;
     UINT16_TO_STREAM(p, notif.len);
@@ -511,11 +515,50 @@ static tGATT_STATUS GATTS_HandleMultileValueNotification(
     p_buf->len += notif.len;
   }
 
-  LOG(INFO) << __func__ << "Total len: " << +p_buf->len;
-
   return attp_send_sr_msg(*p_tcb, cid, p_buf);
 }
-#endif
+
+/*******************************************************************************
+ *
+ * Function         GATTS_HandleMultipleValueNotification
+ *
+ * Description      This function sends the values of several attributes to a
+ *                  client in a single multiple handle value notification.
+ *
+ * Parameter        conn_id: connection identifier.
+ *                  values: handles and values of the notified attributes.
+ *
+ * Returns          GATT_SUCCESS if sucessfully sent; otherwise error code.
+ *
+ ******************************************************************************/
+tGATT_STATUS GATTS_HandleMultipleValueNotification(
+    uint16_t conn_id, const std::vector<tGATT_VALUE>& values) {
+  tGATT_IF gatt_if = GATT_GET_GATT_IF(conn_id);
+  uint8_t tcb_idx = GATT_GET_TCB_IDX(conn_id);
+  tGATT_REG* p_reg = gatt_get_regcb(gatt_if);
+  tGATT_TCB* p_tcb = gatt_get_tcb_by_idx(tcb_idx);
+
+  if ((p_reg == NULL) || (p_tcb == NULL)) {
+    LOG(ERROR) << __func__ << ": Unknown conn_id: " << conn_id;
+    return (tGATT_STATUS)GATT_INVALID_CONN_ID;
+  }
+
+  /* A single value is sent in a handle value notification */
+  if (values.size() < 2) return GATT_ILLEGAL_PARAMETER;
+  for (const auto& value : values) {
+    if (!GATT_HANDLE_IS_VALID(value.handle)) return GATT_ILLEGAL_PARAMETER;
+  }
+
+  if (!gatt_sr_is_cl_multi_variable_len_notif_supported(*p_tcb)) {
+    LOG(WARNING) << __func__
+                 << ": Client does not support multiple value notifications";
+    return GATT_REQ_NOT_SUPPORTED;
+  }
+
+  uint16_t cid = gatt_tcb_get_att_cid(*p_tcb, p_reg->eatt_support);
+  return gatt_send_multiple_value_notification(p_tcb, cid, values);
+}
+
 /*******************************************************************************
  *
  * Function         GATTS_HandleValueNotification
@@ -585,7 +628,9 @@ tGATT_STATUS GATTS_HandleValueNotification(uint16_t conn_id,
 
       notif.auth_req = GATT_AUTH_REQ_NONE;
 
-      return GATTS_HandleMultileValueNotification(p_tcb, gatt_notif_vector);
+      return gatt_send_multiple_value_notification(
+          p_tcb, gatt_tcb_get_att_cid(*p_tcb, true /* eatt support */),
+          gatt_notif_vector);
     }
 
     LOG(ERROR) << __func__ << "PTS Mode: Invalid tcb_idx: " << tcb_idx
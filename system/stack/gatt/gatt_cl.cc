@@ -724,6 +724,18 @@ void gatt_process_notification(tGATT_TCB& tcb, uint16_t cid, uint8_t op_code,
 
   STREAM_TO_ARRAY(value.value, p, value.len);
 
+  // Need a signed type to check if the value is below 0
+  // as uint16_t doesn't have negatives so the negatives register as a number
+  // thus anything less than zero won't trigger the conditional and it is not
+  // always 0
+  // when done looping as value.len is arbitrary.
+  int16_t rem_len = (int16_t)len - (4 /* octets */ + value.len);
+
+  // Tell the values of a multiple handle value notification followed by other
+  // values, so that the clients may deliver the values together
+  if (op_code == GATT_HANDLE_MULTI_VALUE_NOTIF && rem_len > 4 /* octets */)
+    event = GATTC_OPTYPE_MULTI_NOTIFICATION;
+
   tGATT_CL_COMPLETE gatt_cl_complete;
   gatt_cl_complete.att_value = value;
   gatt_cl_complete.cid = cid;
@@ -739,13 +751,6 @@ void gatt_process_notification(tGATT_TCB& tcb, uint16_t cid, uint8_t op_code,
   // If this is single value, then nothing is left to do
   if (op_code != GATT_HANDLE_MULTI_VALUE_NOTIF) return;
 
-  // Need a signed type to check if the value is below 0
-  // as uint16_t doesn't have negatives so the negatives register as a number
-  // thus anything less than zero won't trigger the conditional and it is not
-  // always 0
-  // when done looping as value.len is arbitrary.
-  int16_t rem_len = (int16_t)len - (4 /* octets */ + value.len);
-
   // Already streamed the first value and sent it, lets send the rest
   while (rem_len > 4 /* octets */) {
     // 2
@@ -761,6 +766,9 @@ void gatt_process_notification(tGATT_TCB& tcb, uint16_t cid, uint8_t op_code,
     // Accounting
     rem_len -= value.len;
 
+    event = (rem_len > 4 /* octets */) ? GATTC_OPTYPE_MULTI_NOTIFICATION
+                                       : GATTC_OPTYPE_NOTIFICATION;
+
     gatt_cl_complete.att_value = value;
     gatt_cl_complete.cid = cid;
 
@@ -913,6 +913,12 @@ void gatt_send_srv_chg_ind(const RawAddress& peer_bda) {
 
   if (!gatt_cb.handle_of_h_r) return;
 
+  // Only disabled for qualification testing, with INIT_gatt_service_changed=false.
+  if (!bluetooth::common::init_flags::gatt_service_changed_is_enabled()) {
+    LOG(INFO) << __func__ << ": Service Changed indications are disabled";
+    return;
+  }
+
   uint16_t conn_id = gatt_profile_find_conn_id_by_bd_addr(peer_bda);
   if (conn_id == GATT_INVALID_CONN_ID) {
     LOG(ERROR) << "Unable to find conn_id for " << peer_bda;
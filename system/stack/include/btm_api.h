@@ -333,6 +333,64 @@ void BTM_CancelInquiry(void);
  ******************************************************************************/
 tBTM_STATUS BTM_SetConnectability(uint16_t page_mode);
 
+/*******************************************************************************
+ *
+ * Function         BTM_SetPageScanActivity
+ *
+ * Description      This function sets the page scan interval and window used
+ *                  whenever the device is connectable, and writes them to the
+ *                  controller if they changed.
+ *
+ * Returns          BTM_SUCCESS if successful
+ *                  BTM_ILLEGAL_VALUE if a bad parameter is detected
+ *
+ ******************************************************************************/
+tBTM_STATUS BTM_SetPageScanActivity(uint16_t interval, uint16_t window);
+
+/*******************************************************************************
+ *
+ * Function         BTM_SetInquiryScanActivity
+ *
+ * Description      This function sets the inquiry scan interval and window
+ *                  used whenever the device is discoverable, and writes them
+ *                  to the controller if they changed.
+ *
+ * Returns          BTM_SUCCESS if successful
+ *                  BTM_ILLEGAL_VALUE if a bad parameter is detected
+ *
+ ******************************************************************************/
+tBTM_STATUS BTM_SetInquiryScanActivity(uint16_t interval, uint16_t window);
+
+/*******************************************************************************
+ *
+ * Function         BTM_SetPageScanType
+ *
+ * Description      This function sets the page scan type, standard or
+ *                  interlaced.
+ *
+ * Returns          BTM_SUCCESS if successful
+ *                  BTM_ILLEGAL_VALUE if a bad parameter is detected
+ *                  BTM_MODE_UNSUPPORTED if interlaced scans are not supported
+ *                  BTM_WRONG_MODE if the device is not up.
+ *
+ ******************************************************************************/
+tBTM_STATUS BTM_SetPageScanType(uint16_t scan_type);
+
+/*******************************************************************************
+ *
+ * Function         BTM_SetInquiryScanType
+ *
+ * Description      This function sets the inquiry scan type, standard or
+ *                  interlaced.
+ *
+ * Returns          BTM_SUCCESS if successful
+ *                  BTM_ILLEGAL_VALUE if a bad parameter is detected
+ *                  BTM_MODE_UNSUPPORTED if interlaced scans are not supported
+ *                  BTM_WRONG_MODE if the device is not up.
+ *
+ ******************************************************************************/
+tBTM_STATUS BTM_SetInquiryScanType(uint16_t scan_type);
+
 /*******************************************************************************
  *
  * Function         BTM_SetInquiryMode
@@ -444,6 +502,21 @@ bool BTM_ReadRemoteVersion(const RawAddress& addr, uint8_t* lmp_version,
  ******************************************************************************/
 uint8_t* BTM_ReadRemoteFeatures(const RawAddress& addr);
 
+/*******************************************************************************
+ *
+ * Function         BTM_ReadRemoteFeaturesPage
+ *
+ * Description      This function is called to read a page of a remote device's
+ *                  supported features mask
+ *
+ * Returns          pointer to the features mask of the page, NULL if the page
+ *                  was not read from the device.
+ *                  The size of device features mask page is
+ *                  HCI_FEATURE_BYTES_PER_PAGE bytes.
+ *
+ ******************************************************************************/
+uint8_t* BTM_ReadRemoteFeaturesPage(const RawAddress& addr, uint8_t page);
+
 /*******************************************************************************
  *
  * Function         BTM_InqDbRead
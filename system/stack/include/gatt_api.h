@@ -22,6 +22,7 @@
 
 #include <cstdint>
 #include <string>
+#include <vector>
 
 #include "bt_target.h"
 #include "btm_ble_api.h"
@@ -595,6 +596,9 @@ typedef enum : uint8_t {
   GATTC_OPTYPE_CONFIG = 5,
   GATTC_OPTYPE_NOTIFICATION = 6,
   GATTC_OPTYPE_INDICATION = 7,
+  /* Value of a multiple handle value notification followed by other values,
+   * the last value being reported as a GATTC_OPTYPE_NOTIFICATION */
+  GATTC_OPTYPE_MULTI_NOTIFICATION = 8,
 } tGATTC_OPTYPE;
 
 /* characteristic declaration
@@ -869,6 +873,22 @@ extern tGATT_STATUS GATTS_HandleValueNotification(uint16_t conn_id,
                                                   uint16_t val_len,
                                                   uint8_t* p_val);
 
+/*******************************************************************************
+ *
+ * Function         GATTS_HandleMultipleValueNotification
+ *
+ * Description      This function sends the values of several attributes to a
+ *                  client in a single multiple handle value notification.
+ *
+ * Parameter        conn_id: connection identifier.
+ *                  values: handles and values of the notified attributes.
+ *
+ * Returns          GATT_SUCCESS if sucessfully sent; otherwise error code.
+ *
+ ******************************************************************************/
+extern tGATT_STATUS GATTS_HandleMultipleValueNotification(
+    uint16_t conn_id, const std::vector<tGATT_VALUE>& values);
+
 /*******************************************************************************
  *
  * Function         GATTS_SendRsp
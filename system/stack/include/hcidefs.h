@@ -710,6 +710,7 @@ typedef enum : uint16_t {
 
 /* Page and inquiry scan types */
 #define HCI_SCAN_TYPE_STANDARD 0x00
+#define HCI_SCAN_TYPE_INTERLACED 0x01
 #define HCI_DEF_SCAN_TYPE HCI_SCAN_TYPE_STANDARD
 
 /* Definitions for Extended Inquiry Response */
@@ -760,15 +761,21 @@ typedef enum : uint16_t {
 
 /* Pagescan timer definitions in 0.625 ms */
 #define HCI_DEF_PAGESCAN_INTERVAL 0x0800 /* 1.28 sec */
+#define HCI_MIN_PAGESCAN_INTERVAL 0x0012 /* 11.25 ms */
+#define HCI_MAX_PAGESCAN_INTERVAL 0x1000 /* 2.56 sec */
 
 /* Parameter for pagescan window is passed to LC and is kept in slots */
 #define HCI_DEF_PAGESCAN_WINDOW 0x12   /* 11.25 ms  */
+#define HCI_MIN_PAGESCAN_WINDOW 0x11   /* 10.625 ms */
 
 /* Inquiryscan timer definitions in 0.625 ms */
 #define HCI_DEF_INQUIRYSCAN_INTERVAL 0x1000 /* 2.56 sec */
+#define HCI_MIN_INQUIRYSCAN_INTERVAL 0x0012 /* 11.25 ms */
+#define HCI_MAX_INQUIRYSCAN_INTERVAL 0x1000 /* 2.56 sec */
 
 /* Parameter for inquiryscan window is passed to LC and is kept in slots */
 #define HCI_DEF_INQUIRYSCAN_WINDOW 0x12   /* 11.25 ms */
+#define HCI_MIN_INQUIRYSCAN_WINDOW 0x11   /* 10.625 ms */
 
 /* Encryption modes */
 typedef enum : uint8_t {
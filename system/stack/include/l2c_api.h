@@ -511,6 +511,21 @@ extern bool L2CA_GetPeerLECocConfig(uint16_t lcid,
  ******************************************************************************/
 uint16_t L2CA_GetPeerLECocCredit(const RawAddress& bd_addr, uint16_t lcid);
 
+/*******************************************************************************
+ *
+ *  Function         L2CA_HoldLeCocCredits
+ *
+ *  Description      Holds or releases the credits consumed by the peer on an
+ *                   LE Connection Oriented Channel. While held, the credits
+ *                   are not returned, so that the peer stops sending once the
+ *                   upper layer cannot take more data. Releasing them returns
+ *                   the credits consumed meanwhile.
+ *
+ *  Return value:    true if the channel was found
+ *
+ ******************************************************************************/
+bool L2CA_HoldLeCocCredits(uint16_t lcid, bool hold);
+
 /*******************************************************************************
  *
  *  Function         L2CA_ReconfigCreditBasedConnsReq
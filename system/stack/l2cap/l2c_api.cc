@@ -658,6 +658,29 @@ uint16_t L2CA_GetPeerLECocCredit(const RawAddress& bd_addr, uint16_t lcid) {
   return p_ccb->peer_conn_cfg.credits;
 }
 
+/*******************************************************************************
+ *
+ *  Function         L2CA_HoldLeCocCredits
+ *
+ *  Description      Holds or releases the credits consumed by the peer on an
+ *                   LE Connection Oriented Channel.
+ *
+ *  Return value:    true if the channel was found
+ *
+ ******************************************************************************/
+bool L2CA_HoldLeCocCredits(uint16_t lcid, bool hold) {
+  tL2C_CCB* p_ccb = l2cu_find_ccb_by_cid(NULL, lcid);
+  if (p_ccb == NULL || p_ccb->p_lcb == NULL ||
+      p_ccb->p_lcb->transport != BT_TRANSPORT_LE) {
+    L2CAP_TRACE_WARNING("%s No LE CCB for CID:0x%04x", __func__, lcid);
+    return false;
+  }
+
+  p_ccb->credits_held = hold;
+  if (!hold) l2cble_return_credits(p_ccb);
+  return true;
+}
+
 /*******************************************************************************
  *
  * Function         L2CA_ConnectCreditBasedRsp
@@ -28,6 +28,8 @@
 #include <base/strings/stringprintf.h>
 #include <log/log.h>
 
+#include <algorithm>
+
 #include "bt_target.h"
 #include "bta/include/bta_hearing_aid_api.h"
 #include "device/include/controller.h"
@@ -1464,6 +1466,31 @@ void l2cble_send_flow_control_credit(tL2C_CCB* p_ccb, uint16_t credit_value) {
   return;
 }
 
+/*******************************************************************************
+ *
+ * Function         l2cble_return_credits
+ *
+ * Description      This function gives the remote back the credits it
+ *                  consumed on an LE connection oriented channel, once they
+ *                  get low and unless the upper layer holds them.
+ *
+ * Returns          void
+ *
+ ******************************************************************************/
+void l2cble_return_credits(tL2C_CCB* p_ccb) {
+  if (p_ccb->credits_held) return;
+
+  // The credits are returned up to the ones given at connection.
+  uint16_t window = p_ccb->local_conn_cfg.credits;
+  if (window == 0) window = L2CAP_LE_CREDIT_DEFAULT;
+  uint16_t threshold = std::min<uint16_t>(L2CAP_LE_CREDIT_THRESHOLD, window / 2);
+  if (p_ccb->remote_credit_count > threshold) return;
+
+  uint16_t credits = window - p_ccb->remote_credit_count;
+  p_ccb->remote_credit_count = window;
+  l2c_csm_execute(p_ccb, L2CEVT_L2CA_SEND_FLOW_CONTROL_CREDIT, &credits);
+}
+
 /*******************************************************************************
  *
  * Function         l2cble_send_peer_disc_req
@@ -353,6 +353,10 @@ typedef struct t_l2c_ccb {
    * remote). Valid only for LE CoC */
   uint16_t remote_credit_count;
 
+  /* Credits consumed by the remote are not returned while held by the upper
+   * layer. Valid only for LE CoC */
+  bool credits_held{false};
+
   /* used to indicate that ECOC is used */
   bool ecoc{false};
   bool reconfig_started;
@@ -865,6 +869,7 @@ extern void l2cble_credit_based_conn_res(tL2C_CCB* p_ccb, uint16_t result);
 extern void l2cble_send_peer_disc_req(tL2C_CCB* p_ccb);
 extern void l2cble_send_flow_control_credit(tL2C_CCB* p_ccb,
                                             uint16_t credit_value);
+extern void l2cble_return_credits(tL2C_CCB* p_ccb);
 extern tL2CAP_LE_RESULT_CODE l2ble_sec_access_req(const RawAddress& bd_addr,
                                                   uint16_t psm,
                                                   bool is_originator,
@@ -96,6 +96,16 @@ EattChannel* EattExtension::GetChannelAvailableForClientRequest(
   return pimpl_->GetChannelAvailableForClientRequest(bd_addr);
 }
 
+std::vector<uint16_t> EattExtension::GetConnectedChannelsTxMtu(
+    const RawAddress& bd_addr) {
+  return pimpl_->GetConnectedChannelsTxMtu(bd_addr);
+}
+
+void EattExtension::SetChannelsChangedCallback(
+    base::RepeatingCallback<void(const RawAddress&)> cb) {
+  pimpl_->SetChannelsChangedCallback(cb);
+}
+
 /* Start stop GATT indication timer per CID */
 void EattExtension::StartIndicationConfirmationTimer(const RawAddress& bd_addr,
                                                      uint16_t cid) {
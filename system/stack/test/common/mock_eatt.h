@@ -56,6 +56,10 @@ class MockEattExtension : public EattExtension {
               (const RawAddress& bd_addr));
   MOCK_METHOD((EattChannel*), GetChannelAvailableForClientRequest,
               (const RawAddress& bd_addr));
+  MOCK_METHOD((std::vector<uint16_t>), GetConnectedChannelsTxMtu,
+              (const RawAddress& bd_addr));
+  MOCK_METHOD((void), SetChannelsChangedCallback,
+              (base::RepeatingCallback<void(const RawAddress&)> cb));
   MOCK_METHOD((void), StartIndicationConfirmationTimer,
               (const RawAddress& bd_addr, uint16_t cid));
   MOCK_METHOD((void), StopIndicationConfirmationTimer,
@@ -523,6 +523,31 @@ TEST_F(EattTest, DoubleDisconnect) {
   eatt_instance_->Disconnect(test_address);
 }
 
+TEST_F(EattTest, ChannelsChangedReported) {
+  MockFunction<void(const RawAddress&)> channels_changed;
+  eatt_instance_->SetChannelsChangedCallback(
+      base::BindRepeating(&MockFunction<void(const RawAddress&)>::Call,
+                          base::Unretained(&channels_changed)));
+
+  /* Rejected channels were never connected */
+  EXPECT_CALL(channels_changed, Call(test_address)).Times(2);
+  ConnectDeviceEattSupported(2);
+  ASSERT_EQ(eatt_instance_->GetConnectedChannelsTxMtu(test_address),
+            std::vector<uint16_t>({EATT_MIN_MTU_MPS, EATT_MIN_MTU_MPS}));
+
+  EXPECT_CALL(channels_changed, Call(test_address)).Times(1);
+  l2cap_app_info_.pL2CA_DisconnectInd_Cb(connected_cids_[0], true);
+  ASSERT_EQ(eatt_instance_->GetConnectedChannelsTxMtu(test_address).size(),
+            1u);
+
+  EXPECT_CALL(channels_changed, Call(test_address)).Times(1);
+  DisconnectEattDevice({connected_cids_[1]});
+  ASSERT_TRUE(eatt_instance_->GetConnectedChannelsTxMtu(test_address).empty());
+
+  eatt_instance_->SetChannelsChangedCallback(
+      base::RepeatingCallback<void(const RawAddress&)>());
+}
+
 TEST_F(EattTest, TestCollisionHandling) {
   ConnectDeviceEattSupported(0, true /* collision*/);
   ConnectDeviceEattSupported(5, true /* collision*/);
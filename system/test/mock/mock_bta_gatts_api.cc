@@ -69,6 +69,10 @@ void BTA_GATTS_HandleValueIndication(uint16_t conn_id, uint16_t attr_id,
                                      bool need_confirm) {
   mock_function_count_map[__func__]++;
 }
+void BTA_GATTS_HandleMultipleValueNotification(
+    uint16_t conn_id, std::vector<tGATT_VALUE> values) {
+  mock_function_count_map[__func__]++;
+}
 void BTA_GATTS_Open(tGATT_IF server_if, const RawAddress& remote_bda,
                     bool is_direct, tBT_TRANSPORT transport) {
   mock_function_count_map[__func__]++;
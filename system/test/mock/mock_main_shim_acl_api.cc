@@ -86,3 +86,15 @@ void bluetooth::shim::ACL_RemoveFromAddressResolution(
 void bluetooth::shim::ACL_ClearAddressResolution() {
   mock_function_count_map[__func__]++;
 }
+void bluetooth::shim::ACL_GetLeLists(
+    std::function<void(std::vector<std::string>, std::vector<std::string>)>
+        callback) {
+  mock_function_count_map[__func__]++;
+}
+void bluetooth::shim::ACL_SuspendLeConnections(
+    const std::vector<RawAddress>& wake_devices) {
+  mock_function_count_map[__func__]++;
+}
+void bluetooth::shim::ACL_ResumeLeConnections() {
+  mock_function_count_map[__func__]++;
+}
@@ -96,6 +96,7 @@ struct BTM_SetTraceLevel BTM_SetTraceLevel;
 struct acl_link_role_from_handle acl_link_role_from_handle;
 struct btm_handle_to_acl_index btm_handle_to_acl_index;
 struct BTM_ReadRemoteFeatures BTM_ReadRemoteFeatures;
+struct BTM_ReadRemoteFeaturesPage BTM_ReadRemoteFeaturesPage;
 struct ACL_RegisterClient ACL_RegisterClient;
 struct ACL_UnregisterClient ACL_UnregisterClient;
 struct BTM_ReadConnectionAddr BTM_ReadConnectionAddr;
@@ -395,6 +396,10 @@ uint8_t* BTM_ReadRemoteFeatures(const RawAddress& addr) {
   mock_function_count_map[__func__]++;
   return test::mock::stack_acl::BTM_ReadRemoteFeatures(addr);
 }
+uint8_t* BTM_ReadRemoteFeaturesPage(const RawAddress& addr, uint8_t page) {
+  mock_function_count_map[__func__]++;
+  return test::mock::stack_acl::BTM_ReadRemoteFeaturesPage(addr, page);
+}
 void ACL_RegisterClient(struct acl_client_callback_s* callbacks) {
   mock_function_count_map[__func__]++;
   test::mock::stack_acl::ACL_RegisterClient(callbacks);
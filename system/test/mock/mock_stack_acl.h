@@ -570,6 +570,17 @@ struct BTM_ReadRemoteFeatures {
   uint8_t* operator()(const RawAddress& addr) { return body(addr); };
 };
 extern struct BTM_ReadRemoteFeatures BTM_ReadRemoteFeatures;
+// Name: BTM_ReadRemoteFeaturesPage
+// Params: const RawAddress& addr, uint8_t page
+// Returns: uint8_t*
+struct BTM_ReadRemoteFeaturesPage {
+  std::function<uint8_t*(const RawAddress& addr, uint8_t page)> body{
+      [](const RawAddress& addr, uint8_t page) { return nullptr; }};
+  uint8_t* operator()(const RawAddress& addr, uint8_t page) {
+    return body(addr, page);
+  };
+};
+extern struct BTM_ReadRemoteFeaturesPage BTM_ReadRemoteFeaturesPage;
 // Name: ACL_RegisterClient
 // Params: struct acl_client_callback_s* callbacks
 // Returns: void
@@ -100,6 +100,22 @@ tBTM_STATUS BTM_SetDiscoverability(uint16_t inq_mode) {
   mock_function_count_map[__func__]++;
   return BTM_SUCCESS;
 }
+tBTM_STATUS BTM_SetInquiryScanActivity(uint16_t interval, uint16_t window) {
+  mock_function_count_map[__func__]++;
+  return BTM_SUCCESS;
+}
+tBTM_STATUS BTM_SetInquiryScanType(uint16_t scan_type) {
+  mock_function_count_map[__func__]++;
+  return BTM_SUCCESS;
+}
+tBTM_STATUS BTM_SetPageScanActivity(uint16_t interval, uint16_t window) {
+  mock_function_count_map[__func__]++;
+  return BTM_SUCCESS;
+}
+tBTM_STATUS BTM_SetPageScanType(uint16_t scan_type) {
+  mock_function_count_map[__func__]++;
+  return BTM_SUCCESS;
+}
 tBTM_STATUS BTM_SetInquiryMode(uint8_t mode) {
   mock_function_count_map[__func__]++;
   return BTM_SUCCESS;
@@ -26,6 +26,7 @@
 #include <functional>
 #include <map>
 #include <string>
+#include <vector>
 
 extern std::map<std::string, int> mock_function_count_map;
 
@@ -218,6 +219,24 @@ struct GATTS_HandleValueNotification {
 };
 extern struct GATTS_HandleValueNotification GATTS_HandleValueNotification;
 
+// Name: GATTS_HandleMultipleValueNotification
+// Params: uint16_t conn_id, const std::vector<tGATT_VALUE>& values
+// Return: tGATT_STATUS
+struct GATTS_HandleMultipleValueNotification {
+  static tGATT_STATUS return_value;
+  std::function<tGATT_STATUS(uint16_t conn_id,
+                             const std::vector<tGATT_VALUE>& values)>
+      body{[](uint16_t conn_id, const std::vector<tGATT_VALUE>& values) {
+        return return_value;
+      }};
+  tGATT_STATUS operator()(uint16_t conn_id,
+                          const std::vector<tGATT_VALUE>& values) {
+    return body(conn_id, values);
+  };
+};
+extern struct GATTS_HandleMultipleValueNotification
+    GATTS_HandleMultipleValueNotification;
+
 // Name: GATTS_NVRegister
 // Params: tGATT_APPL_INFO* p_cb_info
 // Return: bool
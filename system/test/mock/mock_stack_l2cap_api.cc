@@ -95,6 +95,7 @@ struct L2CA_RemoveFixedChnl L2CA_RemoveFixedChnl;
 struct L2CA_SetLeGattTimeout L2CA_SetLeGattTimeout;
 struct L2CA_MarkLeLinkAsActive L2CA_MarkLeLinkAsActive;
 struct L2CA_DataWrite L2CA_DataWrite;
+struct L2CA_HoldLeCocCredits L2CA_HoldLeCocCredits;
 struct L2CA_LECocDataWrite L2CA_LECocDataWrite;
 struct L2CA_SetChnlFlushability L2CA_SetChnlFlushability;
 struct L2CA_FlushChannel L2CA_FlushChannel;
@@ -268,6 +269,10 @@ uint8_t L2CA_DataWrite(uint16_t cid, BT_HDR* p_data) {
   mock_function_count_map[__func__]++;
   return test::mock::stack_l2cap_api::L2CA_DataWrite(cid, p_data);
 }
+bool L2CA_HoldLeCocCredits(uint16_t lcid, bool hold) {
+  mock_function_count_map[__func__]++;
+  return test::mock::stack_l2cap_api::L2CA_HoldLeCocCredits(lcid, hold);
+}
 uint8_t L2CA_LECocDataWrite(uint16_t cid, BT_HDR* p_data) {
   mock_function_count_map[__func__]++;
   return test::mock::stack_l2cap_api::L2CA_LECocDataWrite(cid, p_data);
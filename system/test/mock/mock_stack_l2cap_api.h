@@ -441,6 +441,15 @@ struct L2CA_DataWrite {
   };
 };
 extern struct L2CA_DataWrite L2CA_DataWrite;
+// Name: L2CA_HoldLeCocCredits
+// Params: uint16_t lcid, bool hold
+// Returns: bool
+struct L2CA_HoldLeCocCredits {
+  std::function<bool(uint16_t lcid, bool hold)> body{
+      [](uint16_t lcid, bool hold) { return false; }};
+  bool operator()(uint16_t lcid, bool hold) { return body(lcid, hold); };
+};
+extern struct L2CA_HoldLeCocCredits L2CA_HoldLeCocCredits;
 // Name: L2CA_LECocDataWrite
 // Params: uint16_t cid, BT_HDR* p_data
 // Returns: uint8_t
@@ -68,6 +68,7 @@ struct l2cble_process_data_length_change_event
 struct l2cble_credit_based_conn_req l2cble_credit_based_conn_req;
 struct l2cble_credit_based_conn_res l2cble_credit_based_conn_res;
 struct l2cble_send_flow_control_credit l2cble_send_flow_control_credit;
+struct l2cble_return_credits l2cble_return_credits;
 struct l2cble_send_peer_disc_req l2cble_send_peer_disc_req;
 struct l2cble_sec_comp l2cble_sec_comp;
 struct l2ble_sec_access_req l2ble_sec_access_req;
@@ -169,6 +170,10 @@ void l2cble_send_flow_control_credit(tL2C_CCB* p_ccb, uint16_t credit_value) {
   test::mock::stack_l2cap_ble::l2cble_send_flow_control_credit(p_ccb,
                                                                credit_value);
 }
+void l2cble_return_credits(tL2C_CCB* p_ccb) {
+  mock_function_count_map[__func__]++;
+  test::mock::stack_l2cap_ble::l2cble_return_credits(p_ccb);
+}
 void l2cble_send_peer_disc_req(tL2C_CCB* p_ccb) {
   mock_function_count_map[__func__]++;
   test::mock::stack_l2cap_ble::l2cble_send_peer_disc_req(p_ccb);
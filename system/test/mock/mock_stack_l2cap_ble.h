@@ -251,6 +251,14 @@ struct l2cble_send_flow_control_credit {
   };
 };
 extern struct l2cble_send_flow_control_credit l2cble_send_flow_control_credit;
+// Name: l2cble_return_credits
+// Params: tL2C_CCB* p_ccb
+// Returns: void
+struct l2cble_return_credits {
+  std::function<void(tL2C_CCB* p_ccb)> body{[](tL2C_CCB* p_ccb) {}};
+  void operator()(tL2C_CCB* p_ccb) { body(p_ccb); };
+};
+extern struct l2cble_return_credits l2cble_return_credits;
 // Name: l2cble_send_peer_disc_req
 // Params: tL2C_CCB* p_ccb
 // Returns: void